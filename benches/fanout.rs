@@ -0,0 +1,123 @@
+//! WebSocket fan-out benchmark: N subscribers of one symbol receiving M book updates, each
+//! socket serializing every message itself (the old path) vs the fan-out feed serializing it
+//! once. Run with `cargo bench --bench fanout [-- <subscribers> <updates>]`.
+
+use rust_exchange::api::fanout::{Delivery, Fanout};
+use rust_exchange::api::routes::WsMessage;
+use rust_exchange::api::ws::SymbolSubscription;
+use std::hint::black_box;
+use std::time::Instant;
+use tokio::sync::broadcast;
+use uuid::Uuid;
+
+const DEFAULT_SUBSCRIBERS: usize = 100;
+const DEFAULT_UPDATES: usize = 1_000;
+const ROUNDS: usize = 5;
+/// Levels per side of every book update.
+const DEPTH: i64 = 50;
+
+fn book_update(i: usize) -> WsMessage {
+    let mid = 10_000 + (i % 100) as i64;
+    WsMessage::OrderBookUpdate {
+        symbol: "BTCUSDT".to_string(),
+        bids: (1..=DEPTH)
+            .map(|level| (mid - level, 1 + level as u64))
+            .collect(),
+        asks: (1..=DEPTH)
+            .map(|level| (mid + level, 1 + level as u64))
+            .collect(),
+        diff: None,
+    }
+}
+
+/// Every subscriber receives from the broadcast channel and serializes each message.
+async fn per_socket(subscribers: usize, updates: &[WsMessage]) -> usize {
+    let (ws_tx, _) = broadcast::channel(updates.len());
+    let mut receivers: Vec<_> = (0..subscribers).map(|_| ws_tx.subscribe()).collect();
+    for update in updates {
+        ws_tx.send(update.clone()).unwrap();
+    }
+    let mut bytes = 0;
+    for receiver in &mut receivers {
+        for _ in updates {
+            let message = receiver.recv().await.unwrap();
+            bytes += black_box(serde_json::to_string(&message).unwrap()).len();
+        }
+    }
+    bytes
+}
+
+/// One feed serializes each message and queues the shared frame for every subscriber.
+async fn fanned_out(subscribers: usize, updates: &[WsMessage]) -> usize {
+    let (ws_tx, _) = broadcast::channel(updates.len());
+    let fanout = Fanout::default();
+    let subscribers: Vec<_> = (0..subscribers)
+        .map(|_| {
+            let subscriber = fanout.connect(&ws_tx, Uuid::new_v4(), None, updates.len());
+            fanout.subscribe(
+                &ws_tx,
+                &subscriber,
+                "BTCUSDT",
+                SymbolSubscription::default(),
+            );
+            subscriber
+        })
+        .collect();
+    for update in updates {
+        ws_tx.send(update.clone()).unwrap();
+    }
+    let mut bytes = 0;
+    for subscriber in &subscribers {
+        for _ in updates {
+            let Delivery::Frame(frame) = subscriber.next().await else {
+                panic!("outboxes hold every update");
+            };
+            bytes += black_box(frame).len();
+        }
+    }
+    for subscriber in &subscribers {
+        fanout.disconnect(subscriber);
+    }
+    bytes
+}
+
+fn time(
+    runtime: &tokio::runtime::Runtime,
+    label: &str,
+    subscribers: usize,
+    updates: &[WsMessage],
+    run: impl AsyncFn(usize, &[WsMessage]) -> usize,
+) {
+    let mut best = f64::INFINITY;
+    for _ in 0..ROUNDS {
+        let started = Instant::now();
+        let bytes = runtime.block_on(run(subscribers, updates));
+        best = best.min(started.elapsed().as_secs_f64());
+        assert!(bytes > 0);
+    }
+    let frames = (subscribers * updates.len()) as f64;
+    println!(
+        "{:<12} {:>6} subscribers x {:>6} updates  best of {}: {:>9.2} ms  ({:.0} frames/s)",
+        label,
+        subscribers,
+        updates.len(),
+        ROUNDS,
+        best * 1000.0,
+        frames / best
+    );
+}
+
+fn main() {
+    let mut args = std::env::args().skip(1).filter_map(|arg| arg.parse().ok());
+    let subscribers = args.next().unwrap_or(DEFAULT_SUBSCRIBERS);
+    let updates: Vec<WsMessage> = (0..args.next().unwrap_or(DEFAULT_UPDATES))
+        .map(book_update)
+        .collect();
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+
+    time(&runtime, "per-socket", subscribers, &updates, per_socket);
+    time(&runtime, "fan-out", subscribers, &updates, fanned_out);
+}
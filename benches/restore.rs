@@ -0,0 +1,85 @@
+//! Warm-start benchmark: restoring resting orders one `restore_order` call at a time vs the
+//! bulk `restore_orders` path. Run with `cargo bench --bench restore [-- <order count>]`.
+
+use chrono::{Duration, TimeZone, Utc};
+use rust_exchange::orderbook::orderbook::OrderBook;
+use rust_exchange::types::order::{
+    Order, OrderSide, OrderSource, OrderStatus, OrderTags, OrderType,
+};
+use std::hint::black_box;
+use std::time::Instant;
+use uuid::Uuid;
+
+const DEFAULT_ORDERS: usize = 100_000;
+const ROUNDS: usize = 5;
+
+/// Non-crossing resting orders spread over 1000 levels per side, oldest first (the order
+/// hydration reads them in).
+fn resting_orders(count: usize) -> Vec<Order> {
+    let start = Utc.with_ymd_and_hms(2025, 1, 2, 0, 0, 0).unwrap();
+    (0..count)
+        .map(|i| {
+            let side = if i % 2 == 0 {
+                OrderSide::Buy
+            } else {
+                OrderSide::Sell
+            };
+            let offset = ((i * 7919) % 1000) as i64;
+            Order {
+                id: Uuid::new_v4(),
+                user_id: Uuid::new_v4(),
+                side,
+                order_type: OrderType::Limit,
+                price: match side {
+                    OrderSide::Buy => 10_000 - offset,
+                    OrderSide::Sell => 10_001 + offset,
+                },
+                quantity: 1 + (i % 10) as u64,
+                status: OrderStatus::Pending,
+                timestamp: start + Duration::milliseconds(i as i64),
+                tags: OrderTags::new(),
+                source: OrderSource::Api,
+            }
+        })
+        .collect()
+}
+
+fn time(label: &str, orders: &[Order], restore: impl Fn(Vec<Order>) -> OrderBook) {
+    let mut best = f64::INFINITY;
+    for _ in 0..ROUNDS {
+        let input = orders.to_vec();
+        let started = Instant::now();
+        let book = black_box(restore(input));
+        best = best.min(started.elapsed().as_secs_f64());
+        assert_eq!(book.iter_orders().count(), orders.len());
+    }
+    println!(
+        "{:<12} {:>8} orders  best of {}: {:>8.2} ms  ({:.0} orders/s)",
+        label,
+        orders.len(),
+        ROUNDS,
+        best * 1000.0,
+        orders.len() as f64 / best
+    );
+}
+
+fn main() {
+    let count = std::env::args()
+        .skip(1)
+        .find_map(|arg| arg.parse().ok())
+        .unwrap_or(DEFAULT_ORDERS);
+    let orders = resting_orders(count);
+
+    time("incremental", &orders, |orders| {
+        let mut book = OrderBook::new();
+        for order in orders {
+            book.restore_order(order);
+        }
+        book
+    });
+    time("bulk", &orders, |orders| {
+        let mut book = OrderBook::new();
+        book.restore_orders(orders).unwrap();
+        book
+    });
+}
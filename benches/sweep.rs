@@ -0,0 +1,74 @@
+//! Sweep benchmark: one market order taking every ask of a book with thousands of levels, with
+//! one and with several makers per level. Run with `cargo bench --bench sweep [-- <levels>]`.
+
+use chrono::{Duration, TimeZone, Utc};
+use rust_exchange::orderbook::orderbook::OrderBook;
+use rust_exchange::types::order::{
+    Order, OrderSide, OrderSource, OrderStatus, OrderTags, OrderType,
+};
+use std::hint::black_box;
+use std::time::Instant;
+use uuid::Uuid;
+
+const DEFAULT_LEVELS: usize = 5_000;
+const ROUNDS: usize = 5;
+
+/// `per_level` asks of quantity 1..=3 at each of `levels` consecutive prices, oldest first.
+fn asks(levels: usize, per_level: usize) -> Vec<Order> {
+    let start = Utc.with_ymd_and_hms(2025, 1, 2, 0, 0, 0).unwrap();
+    (0..levels * per_level)
+        .map(|i| Order {
+            id: Uuid::new_v4(),
+            user_id: Uuid::new_v4(),
+            side: OrderSide::Sell,
+            order_type: OrderType::Limit,
+            price: 10_000 + (i / per_level) as i64,
+            quantity: 1 + (i % 3) as u64,
+            status: OrderStatus::Pending,
+            timestamp: start + Duration::milliseconds(i as i64),
+            tags: OrderTags::new(),
+            source: OrderSource::Api,
+        })
+        .collect()
+}
+
+fn time(label: &str, levels: usize, per_level: usize) {
+    let orders = asks(levels, per_level);
+    let quantity = orders.iter().map(|o| o.quantity).sum();
+    let mut best = f64::INFINITY;
+    for _ in 0..ROUNDS {
+        let mut book = OrderBook::new();
+        book.restore_orders(orders.clone()).unwrap();
+        let started = Instant::now();
+        let report = black_box(book.add_order(
+            Uuid::new_v4(),
+            0,
+            quantity,
+            OrderSide::Buy,
+            OrderType::Market,
+            None,
+            None,
+        ));
+        best = best.min(started.elapsed().as_secs_f64());
+        assert_eq!(report.trades.len(), orders.len());
+        assert!(book.get_asks().is_empty());
+    }
+    println!(
+        "{:<16} {:>6} levels  {:>7} fills  best of {}: {:>8.2} ms  ({:.0} fills/s)",
+        label,
+        levels,
+        orders.len(),
+        ROUNDS,
+        best * 1000.0,
+        orders.len() as f64 / best
+    );
+}
+
+fn main() {
+    let levels = std::env::args()
+        .skip(1)
+        .find_map(|arg| arg.parse().ok())
+        .unwrap_or(DEFAULT_LEVELS);
+    time("1 per level", levels, 1);
+    time("10 per level", levels, 10);
+}
@@ -0,0 +1,17 @@
+fn main() {
+    #[cfg(feature = "grpc")]
+    compile_protos();
+}
+
+/// Generate the `grpc::proto` messages, server and client from `proto/exchange.proto`, with
+/// the vendored `protoc` unless `PROTOC` names another.
+#[cfg(feature = "grpc")]
+fn compile_protos() {
+    if std::env::var_os("PROTOC").is_none() {
+        let protoc = protoc_bin_vendored::protoc_bin_path().expect("no vendored protoc");
+        // SAFETY: the build script is single-threaded
+        unsafe { std::env::set_var("PROTOC", protoc) };
+    }
+    println!("cargo:rerun-if-changed=proto/exchange.proto");
+    tonic_prost_build::compile_protos("proto/exchange.proto").expect("compiling exchange.proto");
+}
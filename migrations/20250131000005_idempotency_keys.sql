@@ -0,0 +1,11 @@
+CREATE TABLE idempotency_keys (
+    user_id UUID NOT NULL,
+    key TEXT NOT NULL,
+    request_body TEXT NOT NULL,
+    status_code INTEGER NOT NULL,
+    response_body TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (user_id, key)
+);
+
+CREATE INDEX idx_idempotency_keys_created_at ON idempotency_keys (created_at);
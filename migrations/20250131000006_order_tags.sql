@@ -0,0 +1,3 @@
+ALTER TABLE orders ADD COLUMN tags JSONB NOT NULL DEFAULT '{}'::jsonb;
+
+CREATE INDEX idx_orders_tags ON orders USING GIN (tags);
@@ -0,0 +1,12 @@
+ALTER TABLE trades ADD COLUMN trade_seq BIGINT;
+
+UPDATE trades t SET trade_seq = numbered.seq
+FROM (
+    SELECT id, ROW_NUMBER() OVER (PARTITION BY symbol ORDER BY created_at, id) AS seq
+    FROM trades
+) numbered
+WHERE t.id = numbered.id;
+
+ALTER TABLE trades ALTER COLUMN trade_seq SET NOT NULL;
+
+CREATE UNIQUE INDEX idx_trades_symbol_trade_seq ON trades (symbol, trade_seq);
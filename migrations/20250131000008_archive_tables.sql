@@ -0,0 +1,35 @@
+CREATE TABLE orders_archive (
+    id UUID PRIMARY KEY,
+    user_id UUID NOT NULL,
+    symbol TEXT NOT NULL,
+    side TEXT NOT NULL,
+    order_type TEXT NOT NULL,
+    price BIGINT NOT NULL,
+    quantity BIGINT NOT NULL,
+    status TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL,
+    tags JSONB NOT NULL DEFAULT '{}'::jsonb,
+    archived_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_orders_archive_user_id ON orders_archive (user_id);
+
+CREATE TABLE trades_archive (
+    id UUID PRIMARY KEY,
+    trade_seq BIGINT NOT NULL,
+    maker_order_id UUID NOT NULL,
+    taker_order_id UUID NOT NULL,
+    maker_user_id UUID NOT NULL,
+    taker_user_id UUID NOT NULL,
+    symbol TEXT NOT NULL,
+    price BIGINT NOT NULL,
+    quantity BIGINT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL,
+    archived_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE UNIQUE INDEX idx_trades_archive_symbol_trade_seq ON trades_archive (symbol, trade_seq);
+CREATE INDEX idx_trades_archive_maker_user_id ON trades_archive (maker_user_id);
+CREATE INDEX idx_trades_archive_taker_user_id ON trades_archive (taker_user_id);
+CREATE INDEX idx_orders_status_created_at ON orders (status, created_at);
+CREATE INDEX idx_trades_created_at ON trades (created_at);
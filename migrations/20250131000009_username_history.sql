@@ -0,0 +1,8 @@
+CREATE TABLE username_history (
+    id BIGSERIAL PRIMARY KEY,
+    username TEXT NOT NULL,
+    user_id UUID NOT NULL REFERENCES users (id),
+    released_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_username_history_username ON username_history (username, released_at DESC);
@@ -0,0 +1,2 @@
+ALTER TABLE orders ADD COLUMN close_reason TEXT;
+ALTER TABLE orders_archive ADD COLUMN close_reason TEXT;
@@ -0,0 +1,4 @@
+ALTER TABLE orders ADD COLUMN source TEXT NOT NULL DEFAULT 'Api';
+ALTER TABLE orders_archive ADD COLUMN source TEXT NOT NULL DEFAULT 'Api';
+
+CREATE INDEX idx_orders_user_source ON orders (user_id, source);
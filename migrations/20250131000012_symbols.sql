@@ -0,0 +1,21 @@
+CREATE TABLE symbols (
+    symbol TEXT PRIMARY KEY,
+    tick_size BIGINT NOT NULL,
+    lot_size BIGINT NOT NULL,
+    price_band_bps INTEGER,
+    maker_fee_bps INTEGER NOT NULL,
+    taker_fee_bps INTEGER NOT NULL,
+    halted BOOLEAN NOT NULL DEFAULT FALSE,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE TABLE symbol_config_audit (
+    id BIGSERIAL PRIMARY KEY,
+    symbol TEXT NOT NULL,
+    admin_user_id UUID NOT NULL,
+    changes JSONB NOT NULL,
+    previous JSONB NOT NULL,
+    changed_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_symbol_config_audit_symbol ON symbol_config_audit (symbol, changed_at DESC);
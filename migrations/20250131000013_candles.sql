@@ -0,0 +1,11 @@
+CREATE TABLE candles (
+    symbol TEXT NOT NULL,
+    open_time TIMESTAMPTZ NOT NULL,
+    open BIGINT NOT NULL,
+    high BIGINT NOT NULL,
+    low BIGINT NOT NULL,
+    close BIGINT NOT NULL,
+    volume BIGINT NOT NULL,
+    trade_count BIGINT NOT NULL,
+    PRIMARY KEY (symbol, open_time)
+);
@@ -0,0 +1,13 @@
+CREATE TABLE reconcile_audit (
+    id BIGSERIAL PRIMARY KEY,
+    symbol TEXT NOT NULL,
+    admin_user_id UUID NOT NULL,
+    entity TEXT NOT NULL,
+    entity_id TEXT NOT NULL,
+    action TEXT NOT NULL,
+    previous JSONB,
+    applied JSONB,
+    repaired_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_reconcile_audit_symbol ON reconcile_audit (symbol, repaired_at DESC);
@@ -0,0 +1 @@
+ALTER TABLE symbols ADD COLUMN pricing_policy TEXT NOT NULL DEFAULT 'MakerPrice';
@@ -0,0 +1,20 @@
+ALTER TABLE trades
+    ADD COLUMN taker_side TEXT,
+    ADD COLUMN maker_fee BIGINT NOT NULL DEFAULT 0,
+    ADD COLUMN taker_fee BIGINT NOT NULL DEFAULT 0,
+    ADD COLUMN realized_pnl_maker BIGINT NOT NULL DEFAULT 0,
+    ADD COLUMN realized_pnl_taker BIGINT NOT NULL DEFAULT 0;
+
+ALTER TABLE trades_archive
+    ADD COLUMN taker_side TEXT,
+    ADD COLUMN maker_fee BIGINT NOT NULL DEFAULT 0,
+    ADD COLUMN taker_fee BIGINT NOT NULL DEFAULT 0,
+    ADD COLUMN realized_pnl_maker BIGINT NOT NULL DEFAULT 0,
+    ADD COLUMN realized_pnl_taker BIGINT NOT NULL DEFAULT 0;
+
+-- Earlier trades: the taker side is the taker order's side. Fees and realized P&L were never
+-- recorded for them and stay 0.
+UPDATE trades t SET taker_side = o.side FROM orders o WHERE o.id = t.taker_order_id;
+UPDATE trades_archive t SET taker_side = o.side FROM orders o WHERE o.id = t.taker_order_id;
+UPDATE trades_archive t SET taker_side = o.side FROM orders_archive o
+    WHERE o.id = t.taker_order_id AND t.taker_side IS NULL;
@@ -0,0 +1,5 @@
+-- Existing users registered before this migration get the time it ran.
+ALTER TABLE users
+    ADD COLUMN created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    ADD COLUMN last_login_at TIMESTAMPTZ,
+    ADD COLUMN login_count BIGINT NOT NULL DEFAULT 0;
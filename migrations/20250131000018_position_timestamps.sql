@@ -0,0 +1,4 @@
+-- Positions open before this migration get the time it ran.
+ALTER TABLE positions
+    ADD COLUMN opened_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    ADD COLUMN updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW();
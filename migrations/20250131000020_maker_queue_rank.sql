@@ -0,0 +1,4 @@
+-- The maker's place in its price level's queue when each trade executed (0 = front).
+-- NULL for trades stored before it was recorded.
+ALTER TABLE trades ADD COLUMN maker_queue_rank INTEGER;
+ALTER TABLE trades_archive ADD COLUMN maker_queue_rank INTEGER;
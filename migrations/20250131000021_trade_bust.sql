@@ -0,0 +1,17 @@
+-- Busted trades stay stored, marked with when and why; public listings leave them out.
+ALTER TABLE trades ADD COLUMN busted_at TIMESTAMPTZ, ADD COLUMN bust_reason TEXT;
+ALTER TABLE trades_archive ADD COLUMN busted_at TIMESTAMPTZ, ADD COLUMN bust_reason TEXT;
+
+-- One row per bust: the trade as it was and the positions its reversal left.
+CREATE TABLE trade_bust_audit (
+    id BIGSERIAL PRIMARY KEY,
+    trade_id UUID NOT NULL,
+    symbol TEXT NOT NULL,
+    admin_user_id UUID NOT NULL,
+    reason TEXT NOT NULL,
+    trade JSONB NOT NULL,
+    positions JSONB NOT NULL,
+    busted_at TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX idx_trade_bust_audit_symbol ON trade_bust_audit (symbol, busted_at DESC);
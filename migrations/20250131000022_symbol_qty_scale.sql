@@ -0,0 +1,3 @@
+-- Quantities are in lots of 1 / qty_scale of the base asset; 1 keeps whole units.
+ALTER TABLE symbols ADD COLUMN min_qty BIGINT NOT NULL DEFAULT 1;
+ALTER TABLE symbols ADD COLUMN qty_scale BIGINT NOT NULL DEFAULT 1;
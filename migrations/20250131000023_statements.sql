@@ -0,0 +1,14 @@
+-- One end-of-day statement per user and UTC day. `details` holds the statement as JSON
+-- (positions, trades, balance movements); `csv` the same rendered for download.
+CREATE TABLE statements (
+    user_id UUID NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    statement_date DATE NOT NULL,
+    trade_count INTEGER NOT NULL,
+    fees BIGINT NOT NULL,
+    realized_pnl BIGINT NOT NULL,
+    unrealized_pnl BIGINT NOT NULL,
+    details JSONB NOT NULL,
+    csv TEXT NOT NULL,
+    generated_at TIMESTAMPTZ NOT NULL,
+    PRIMARY KEY (user_id, statement_date)
+);
@@ -0,0 +1,12 @@
+-- One row per operator push to WebSocket clients: a book rebroadcast (`rebroadcast`, with the
+-- symbols and whether a resync was requested) or an announcement (`announcement`).
+CREATE TABLE ws_broadcast_audit (
+    id BIGSERIAL PRIMARY KEY,
+    admin_user_id UUID NOT NULL,
+    action TEXT NOT NULL,
+    symbol TEXT,
+    details JSONB NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_ws_broadcast_audit_created_at ON ws_broadcast_audit (created_at DESC);
@@ -0,0 +1,3 @@
+-- The asset a symbol trades and the one it is priced in. NULL on rows saved before symbols had
+-- assets; those are read off the symbol's name on load.
+ALTER TABLE symbols ADD COLUMN base_asset TEXT, ADD COLUMN quote_asset TEXT;
@@ -0,0 +1,6 @@
+-- Delisting: a symbol moves from Trading to CancelOnly until delist_at, then to Delisted, when
+-- its positions are closed or frozen per delist_positions. Delisted symbols are not hydrated.
+ALTER TABLE symbols
+    ADD COLUMN status TEXT NOT NULL DEFAULT 'Trading',
+    ADD COLUMN delist_at TIMESTAMPTZ,
+    ADD COLUMN delist_positions TEXT;
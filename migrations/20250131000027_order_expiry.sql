@@ -0,0 +1,9 @@
+-- Refresh TTL orders: the deadline an open order expires at unless refreshed, updated on every
+-- refresh, and the TTL a refresh extends it by. A restart expires the orders whose deadline
+-- passed while it was down.
+ALTER TABLE orders
+    ADD COLUMN expires_at TIMESTAMPTZ,
+    ADD COLUMN refresh_ttl_ms BIGINT;
+
+CREATE INDEX idx_orders_open_expires_at ON orders (expires_at)
+    WHERE expires_at IS NOT NULL AND status IN ('Pending', 'PartiallyFilled');
@@ -0,0 +1,13 @@
+-- The account kill switch: set by the user to refuse their new orders until they clear it.
+ALTER TABLE users ADD COLUMN trading_disabled BOOLEAN NOT NULL DEFAULT FALSE;
+
+-- One row per engage (`engaged`, with the orders it cancelled) or disable (`disabled`).
+CREATE TABLE kill_switch_audit (
+    id BIGSERIAL PRIMARY KEY,
+    user_id UUID NOT NULL,
+    action TEXT NOT NULL,
+    cancelled_orders INTEGER NOT NULL DEFAULT 0,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_kill_switch_audit_user_id ON kill_switch_audit (user_id, created_at DESC);
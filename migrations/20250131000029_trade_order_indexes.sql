@@ -0,0 +1,6 @@
+-- An order's fills (GET /orders/{id}/fills) are looked up by the order on either side of the
+-- trade, in the hot table and the archive.
+CREATE INDEX idx_trades_maker_order_id ON trades (maker_order_id);
+CREATE INDEX idx_trades_taker_order_id ON trades (taker_order_id);
+CREATE INDEX idx_trades_archive_maker_order_id ON trades_archive (maker_order_id);
+CREATE INDEX idx_trades_archive_taker_order_id ON trades_archive (taker_order_id);
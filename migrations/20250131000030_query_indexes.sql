@@ -0,0 +1,21 @@
+-- GET /trades/me reads a user's trades newest first as two branches, one per side of the trade
+-- (see list_trades_for_user). Each branch walks one of these in keyset order and stops at the
+-- page limit, where the maker_user_id/taker_user_id indexes had to collect and sort every match.
+CREATE INDEX idx_trades_maker_user_created_at
+    ON trades (maker_user_id, created_at DESC, trade_seq DESC, id DESC);
+CREATE INDEX idx_trades_taker_user_created_at
+    ON trades (taker_user_id, created_at DESC, trade_seq DESC, id DESC);
+CREATE INDEX idx_trades_archive_maker_user_created_at
+    ON trades_archive (maker_user_id, created_at DESC, trade_seq DESC, id DESC);
+CREATE INDEX idx_trades_archive_taker_user_created_at
+    ON trades_archive (taker_user_id, created_at DESC, trade_seq DESC, id DESC);
+
+-- Hydration and reconciliation load a symbol's open orders oldest first; terminal orders,
+-- the bulk of the table, are left out of the index.
+CREATE INDEX idx_orders_open_symbol_created_at
+    ON orders (symbol, created_at)
+    WHERE status IN ('Pending', 'PartiallyFilled');
+
+-- The hot table has had (symbol, created_at DESC) since it was created; history reads that
+-- include archived trades need it on the archive too.
+CREATE INDEX idx_trades_archive_symbol_created_at ON trades_archive (symbol, created_at DESC);
@@ -0,0 +1,18 @@
+-- Versions of the exchange's published state; `rules_version` is GET /exchangeInfo's, bumped
+-- with every stored change to a symbol's rules.
+CREATE TABLE exchange_metadata (
+    key TEXT PRIMARY KEY,
+    value BIGINT NOT NULL
+);
+INSERT INTO exchange_metadata (key, value) VALUES ('rules_version', 0);
+
+-- One row per rules version: the fields of one symbol's config that changed, before and after
+-- (GET /exchangeInfo/changes). Written in the transaction that saves the config.
+CREATE TABLE symbol_rule_changes (
+    version BIGINT PRIMARY KEY,
+    symbol TEXT NOT NULL,
+    source TEXT NOT NULL,
+    changed_by UUID,
+    changed_at TIMESTAMPTZ NOT NULL,
+    changes JSONB NOT NULL
+);
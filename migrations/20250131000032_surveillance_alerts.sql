@@ -0,0 +1,17 @@
+-- Alerts raised by market abuse surveillance (see `api::surveillance`), kept until reviewed:
+-- an admin acknowledging one sets acknowledged_by/acknowledged_at.
+CREATE TABLE surveillance_alerts (
+    id UUID PRIMARY KEY,
+    user_id UUID NOT NULL,
+    symbol TEXT NOT NULL,
+    kind TEXT NOT NULL,
+    metrics JSONB NOT NULL,
+    raised_at TIMESTAMPTZ NOT NULL,
+    acknowledged_by UUID,
+    acknowledged_at TIMESTAMPTZ
+);
+
+CREATE INDEX idx_surveillance_alerts_raised_at ON surveillance_alerts (raised_at DESC, id);
+CREATE INDEX idx_surveillance_alerts_unacknowledged
+    ON surveillance_alerts (raised_at DESC, id)
+    WHERE acknowledged_at IS NULL;
@@ -0,0 +1,22 @@
+-- Trading environments (see types::environment): a paper book's symbol carries an '@paper'
+-- suffix, so every row keyed by symbol already belongs to one environment. The column names it,
+-- derived from the symbol so it can never disagree, for reads across symbols (a user's orders,
+-- trades and positions; daily statements) to stay within one environment.
+ALTER TABLE orders ADD COLUMN environment TEXT NOT NULL GENERATED ALWAYS AS (
+    CASE WHEN strpos(symbol, '@') > 0 THEN split_part(symbol, '@', 2) ELSE 'live' END
+) STORED;
+ALTER TABLE orders_archive ADD COLUMN environment TEXT NOT NULL GENERATED ALWAYS AS (
+    CASE WHEN strpos(symbol, '@') > 0 THEN split_part(symbol, '@', 2) ELSE 'live' END
+) STORED;
+ALTER TABLE trades ADD COLUMN environment TEXT NOT NULL GENERATED ALWAYS AS (
+    CASE WHEN strpos(symbol, '@') > 0 THEN split_part(symbol, '@', 2) ELSE 'live' END
+) STORED;
+ALTER TABLE trades_archive ADD COLUMN environment TEXT NOT NULL GENERATED ALWAYS AS (
+    CASE WHEN strpos(symbol, '@') > 0 THEN split_part(symbol, '@', 2) ELSE 'live' END
+) STORED;
+ALTER TABLE positions ADD COLUMN environment TEXT NOT NULL GENERATED ALWAYS AS (
+    CASE WHEN strpos(symbol, '@') > 0 THEN split_part(symbol, '@', 2) ELSE 'live' END
+) STORED;
+ALTER TABLE candles ADD COLUMN environment TEXT NOT NULL GENERATED ALWAYS AS (
+    CASE WHEN strpos(symbol, '@') > 0 THEN split_part(symbol, '@', 2) ELSE 'live' END
+) STORED;
@@ -0,0 +1,273 @@
+//! Exchange-wide recent activity for dashboards: a bounded ring of public events (trades
+//! without their parties, halts and resumes, auctions) that order entry and admin actions
+//! append to as they happen. `GET /activity` reads the ring only, never the database.
+//! Sandbox, paper and self-test symbols are never recorded.
+
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::Json,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, VecDeque};
+use std::str::FromStr;
+use std::sync::Mutex;
+
+use crate::api::routes::{AppState, ErrorResponse};
+use crate::sandbox::is_sandbox_symbol;
+use crate::selftest::SELFTEST_SYMBOL;
+use crate::types::environment::Environment;
+use crate::types::order::{Price, Qty};
+use crate::types::trade::Trade;
+
+/// Events kept when `ACTIVITY_FEED_SIZE` is unset.
+pub const DEFAULT_CAPACITY: usize = 1000;
+/// Events returned when `limit` is not given.
+const DEFAULT_LIMIT: usize = 50;
+
+/// What happened. Serialized with a `type` tag next to the event's `seq` and `timestamp`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "type")]
+pub enum ActivityKind {
+    /// A fill; the counterparties are left out.
+    Trade {
+        symbol: String,
+        trade_seq: u64,
+        price: Price,
+        quantity: Qty,
+    },
+    Halted {
+        symbol: String,
+    },
+    Resumed {
+        symbol: String,
+    },
+    AuctionStarted {
+        symbol: String,
+    },
+    /// An auction ended; its trades are recorded just before.
+    AuctionResult {
+        symbol: String,
+        clearing_price: Option<Price>,
+        volume: Qty,
+    },
+}
+
+/// The `type` of an `ActivityKind`, as named in `types=`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ActivityType {
+    Trade,
+    Halted,
+    Resumed,
+    AuctionStarted,
+    AuctionResult,
+}
+
+impl ActivityType {
+    pub const ALL: [ActivityType; 5] = [
+        Self::Trade,
+        Self::Halted,
+        Self::Resumed,
+        Self::AuctionStarted,
+        Self::AuctionResult,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Trade => "Trade",
+            Self::Halted => "Halted",
+            Self::Resumed => "Resumed",
+            Self::AuctionStarted => "AuctionStarted",
+            Self::AuctionResult => "AuctionResult",
+        }
+    }
+}
+
+impl FromStr for ActivityType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|t| t.as_str() == s)
+            .ok_or_else(|| {
+                let names: Vec<&str> = Self::ALL.iter().map(|t| t.as_str()).collect();
+                format!(
+                    "Unknown activity type '{}'; expected one of {}",
+                    s,
+                    names.join(", ")
+                )
+            })
+    }
+}
+
+impl ActivityKind {
+    pub fn activity_type(&self) -> ActivityType {
+        match self {
+            Self::Trade { .. } => ActivityType::Trade,
+            Self::Halted { .. } => ActivityType::Halted,
+            Self::Resumed { .. } => ActivityType::Resumed,
+            Self::AuctionStarted { .. } => ActivityType::AuctionStarted,
+            Self::AuctionResult { .. } => ActivityType::AuctionResult,
+        }
+    }
+
+    pub fn symbol(&self) -> &str {
+        match self {
+            Self::Trade { symbol, .. }
+            | Self::Halted { symbol }
+            | Self::Resumed { symbol }
+            | Self::AuctionStarted { symbol }
+            | Self::AuctionResult { symbol, .. } => symbol,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ActivityEvent {
+    /// Order the feed recorded events in, across every symbol; increases by one per event.
+    pub seq: u64,
+    pub timestamp: DateTime<Utc>,
+    #[serde(flatten)]
+    pub kind: ActivityKind,
+}
+
+struct Ring {
+    events: VecDeque<ActivityEvent>,
+    next_seq: u64,
+}
+
+/// The ring of recent events; the oldest are dropped past `capacity`.
+pub struct ActivityFeed {
+    ring: Mutex<Ring>,
+    capacity: usize,
+}
+
+impl Default for ActivityFeed {
+    fn default() -> Self {
+        Self::new(DEFAULT_CAPACITY)
+    }
+}
+
+fn is_public(symbol: &str) -> bool {
+    symbol != SELFTEST_SYMBOL
+        && !is_sandbox_symbol(symbol)
+        && Environment::of(symbol) == Environment::Live
+}
+
+impl ActivityFeed {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            ring: Mutex::new(Ring {
+                events: VecDeque::with_capacity(capacity),
+                next_seq: 1,
+            }),
+            capacity,
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Append events under one lock, in order. Events of non-public symbols are skipped.
+    pub fn record_all(&self, events: impl IntoIterator<Item = (DateTime<Utc>, ActivityKind)>) {
+        let mut ring = self.ring.lock().unwrap_or_else(|e| e.into_inner());
+        for (timestamp, kind) in events {
+            if !is_public(kind.symbol()) || self.capacity == 0 {
+                continue;
+            }
+            if ring.events.len() == self.capacity {
+                ring.events.pop_front();
+            }
+            let seq = ring.next_seq;
+            ring.next_seq += 1;
+            ring.events.push_back(ActivityEvent {
+                seq,
+                timestamp,
+                kind,
+            });
+        }
+    }
+
+    /// Append one event happening now.
+    pub fn record(&self, kind: ActivityKind) {
+        self.record_all([(Utc::now(), kind)]);
+    }
+
+    /// Append `symbol`'s trades, at their match time.
+    pub fn record_trades(&self, symbol: &str, trades: &[Trade]) {
+        if !is_public(symbol) {
+            return;
+        }
+        self.record_all(trades.iter().map(|trade| {
+            (
+                trade.timestamp,
+                ActivityKind::Trade {
+                    symbol: symbol.to_string(),
+                    trade_seq: trade.trade_seq,
+                    price: trade.price,
+                    quantity: trade.quantity,
+                },
+            )
+        }));
+    }
+
+    /// Up to `limit` most recent events, newest first, of the given `types` (all when None).
+    pub fn recent(
+        &self,
+        limit: usize,
+        types: Option<&BTreeSet<ActivityType>>,
+    ) -> Vec<ActivityEvent> {
+        let ring = self.ring.lock().unwrap_or_else(|e| e.into_inner());
+        ring.events
+            .iter()
+            .rev()
+            .filter(|event| types.is_none_or(|types| types.contains(&event.kind.activity_type())))
+            .take(limit)
+            .cloned()
+            .collect()
+    }
+}
+
+/// Parse a comma-separated `types=` list. Names are trimmed and empty entries ignored; an
+/// unknown name, or a list naming none, is an error.
+pub fn parse_types(raw: &str) -> Result<BTreeSet<ActivityType>, String> {
+    let types = raw
+        .split(',')
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .map(str::parse)
+        .collect::<Result<BTreeSet<_>, _>>()?;
+    if types.is_empty() {
+        return Err("types names no activity type".to_string());
+    }
+    Ok(types)
+}
+
+#[derive(Deserialize)]
+pub struct ActivityQuery {
+    limit: Option<usize>,
+    /// Comma-separated event types to return (see `ActivityType`); all when absent.
+    types: Option<String>,
+}
+
+/// GET /activity?limit=&types=: the most recent public events across all symbols, newest
+/// first. `limit` defaults to 50 and is capped at the feed's size.
+pub async fn get_activity(
+    State(state): State<AppState>,
+    Query(params): Query<ActivityQuery>,
+) -> Result<Json<Vec<ActivityEvent>>, (StatusCode, Json<ErrorResponse>)> {
+    let types = params
+        .types
+        .as_deref()
+        .map(parse_types)
+        .transpose()
+        .map_err(|msg| ErrorResponse::new(msg, StatusCode::BAD_REQUEST))?;
+    let limit = params
+        .limit
+        .unwrap_or(DEFAULT_LIMIT)
+        .min(state.activity.capacity());
+    Ok(Json(state.activity.recent(limit, types.as_ref())))
+}
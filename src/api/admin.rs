@@ -0,0 +1,1524 @@
+//! Admin-only endpoints (caller must be in `AppState::admin_user_ids`).
+
+use axum::{
+    extract::{Path, Query, State},
+    http::{StatusCode, header},
+    response::{IntoResponse, Json},
+};
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use uuid::Uuid;
+
+use crate::api::activity::ActivityKind;
+use crate::api::auth::{Account, AdminUser};
+use crate::api::deadline::{RequestDeadline, lock_book};
+use crate::api::dto::{CancelledOrderDto, PositionDto, TradeDto};
+use crate::api::exchange_info::RuleChangeSource;
+use crate::api::incidents;
+use crate::api::routes::{
+    AppState, AssetBalance, ErrorResponse, SymbolConfigResponse, WsMessage, balances_disabled,
+    filled_states, get_orderbook, mark_price, persist_fills, settle_trades,
+};
+use crate::api::service;
+use crate::api::ws::{self, AnnouncementSeverity};
+use crate::api::ws_connections::{ConnectionCounts, ConnectionInfo, WsConfig, WsTotals};
+use crate::hydration::HydrationReport;
+use crate::market_data::CandleCorrection;
+use crate::orderbook::book_stats::BookStats;
+use crate::orderbook::matching_stats::{MatchingStatsReport, render_prometheus};
+use crate::orderbook::order_limits::{CapOverride, RestingOrderCaps};
+use crate::orderbook::orderbook::{OrderBook, TradingPhase};
+use crate::persistence::{
+    self, PositionRow, QueryHistogram, SchemaStatus, TradePersistenceSnapshot,
+};
+use crate::positions;
+use crate::reconcile::{self, ReconcileReport, RepairTarget};
+use crate::retention::{RetentionError, RetentionProgress};
+use crate::selftest::{self, SelfTestReport};
+use crate::statements::{self, StatementRun};
+use crate::tasks::TaskStatus;
+use crate::types::environment::Environment;
+use crate::types::order::{
+    CloseReason, OrderId, OrderSide, OrderSource, Price, Qty, RejectReason, total_quantity,
+};
+use crate::types::position::Position;
+use crate::types::symbol::{Symbol, SymbolConfig, SymbolConfigPatch, optional_symbol};
+use crate::types::trade::{Trade, TradeAttribution, TradeBust};
+
+#[derive(Deserialize)]
+pub struct SymbolRequest {
+    symbol: Symbol,
+}
+
+#[derive(Serialize)]
+pub struct AuctionStartResponse {
+    symbol: Symbol,
+    phase: TradingPhase,
+}
+
+#[derive(Serialize)]
+pub struct AuctionEndResponse {
+    symbol: Symbol,
+    clearing_price: Option<i64>,
+    volume: u64,
+    trades: Vec<TradeDto>,
+}
+
+/// POST /admin/auction/start: stop continuous matching for a symbol; orders accumulate.
+pub async fn start_auction(
+    _admin: AdminUser,
+    State(state): State<AppState>,
+    Json(body): Json<SymbolRequest>,
+) -> Result<Json<AuctionStartResponse>, (StatusCode, Json<ErrorResponse>)> {
+    let normalized_symbol = body.symbol;
+    let orderbook = get_orderbook(&state, &normalized_symbol).await?;
+    let mut book = orderbook.write().await;
+    if book.phase() == TradingPhase::Auction {
+        return Err(ErrorResponse::new(
+            format!("Symbol '{}' is already in an auction", normalized_symbol),
+            StatusCode::CONFLICT,
+        ));
+    }
+    book.start_auction();
+    state.activity.record(ActivityKind::AuctionStarted {
+        symbol: normalized_symbol.to_string(),
+    });
+    Ok(Json(AuctionStartResponse {
+        symbol: normalized_symbol,
+        phase: book.phase(),
+    }))
+}
+
+/// POST /admin/auction/end: uncross the book at the clearing price and resume continuous trading.
+pub async fn end_auction(
+    _admin: AdminUser,
+    deadline: RequestDeadline,
+    State(state): State<AppState>,
+    Json(body): Json<SymbolRequest>,
+) -> Result<Json<AuctionEndResponse>, (StatusCode, Json<ErrorResponse>)> {
+    let normalized_symbol = body.symbol;
+    let orderbook = get_orderbook(&state, &normalized_symbol).await?;
+    // Taken in arrival order like an order, and held through persistence so the database sees
+    // fills in book order (see create_order)
+    let ticket = state.ingress.admit(&normalized_symbol);
+    let mut book = lock_book(&state, &normalized_symbol, &orderbook, ticket, &deadline).await?;
+    if book.phase() != TradingPhase::Auction {
+        return Err(ErrorResponse::new(
+            format!("Symbol '{}' is not in an auction", normalized_symbol),
+            StatusCode::CONFLICT,
+        ));
+    }
+    let result = match incidents::catch_panic(|| {
+        book.run_auction(Some(&state.ws_channel), Some(&normalized_symbol))
+    }) {
+        Ok(result) => result,
+        Err(message) => {
+            return Err(
+                incidents::quarantine(&state, &normalized_symbol, &mut book, message).await,
+            );
+        }
+    };
+    if let Some(mut ledger) = service::symbol_ledger(&state, &normalized_symbol).await {
+        let config = service::symbol_config(&state, &normalized_symbol).await;
+        ledger.settle(&config, OrderSide::Buy, &result.trades);
+    }
+
+    // Auction convention: maker = sell order, taker = buy order
+    let mut deltas = Vec::with_capacity(result.trades.len() * 2);
+    let mut attributions = Vec::with_capacity(result.trades.len());
+    for trade in &result.trades {
+        let (trade_deltas, trade_attributions) = settle_trades(
+            &state,
+            &normalized_symbol,
+            &mut book,
+            trade.taker_user_id,
+            OrderSide::Buy,
+            std::slice::from_ref(trade),
+        )
+        .await;
+        deltas.extend(trade_deltas);
+        attributions.extend(trade_attributions);
+    }
+    state.activity.record(ActivityKind::AuctionResult {
+        symbol: normalized_symbol.to_string(),
+        clearing_price: result.clearing_price,
+        volume: result.volume,
+    });
+
+    if !deltas.is_empty() {
+        let mark = mark_price(&state, &normalized_symbol, &book).await;
+        let qty_scale = service::symbol_config(&state, &normalized_symbol)
+            .await
+            .qty_scale;
+        ws::broadcast_position_updates(&state.ws_channel, &deltas, mark, qty_scale);
+    }
+
+    if let Some(ref db) = state.db {
+        // Both sides of an auction trade were resting
+        let filled = filled_states(
+            &book,
+            result
+                .trades
+                .iter()
+                .flat_map(|t| [t.maker_order_id, t.taker_order_id]),
+        );
+        let _ = persist_fills(
+            db,
+            None,
+            None,
+            None,
+            &filled,
+            &normalized_symbol,
+            &result.trades,
+            &attributions,
+            &deltas,
+            &state.trade_metrics,
+        )
+        .await;
+    }
+    drop(book);
+
+    Ok(Json(AuctionEndResponse {
+        symbol: normalized_symbol,
+        clearing_price: result.clearing_price,
+        volume: result.volume,
+        trades: result.trades.into_iter().map(TradeDto::from).collect(),
+    }))
+}
+
+const BOOK_ORDERS_DEFAULT_LIMIT: usize = 100;
+const BOOK_ORDERS_MAX_LIMIT: usize = 1000;
+
+#[derive(Deserialize)]
+pub struct BookOrdersQuery {
+    symbol: Symbol,
+    side: Option<OrderSide>,
+    min_qty: Option<Qty>,
+    user_id: Option<Uuid>,
+    source: Option<OrderSource>,
+    limit: Option<usize>,
+    offset: Option<usize>,
+}
+
+#[derive(Serialize)]
+pub struct BookOrderEntry {
+    order_id: OrderId,
+    user_id: Uuid,
+    username: Option<String>,
+    side: OrderSide,
+    source: OrderSource,
+    price: Price,
+    remaining_quantity: Qty,
+    queue_position: usize,
+    timestamp: DateTime<Utc>,
+    age_ms: i64,
+}
+
+#[derive(Serialize)]
+pub struct BookOrdersResponse {
+    symbol: Symbol,
+    total: usize,
+    offset: usize,
+    limit: usize,
+    orders: Vec<BookOrderEntry>,
+}
+
+/// DELETE /admin/orders/{id}?symbol=: cancel any user's resting order. The owner is told with
+/// an `OrderClosed` message (reason `ADMIN_CANCELLED`). An order another cancel closed first is
+/// answered with that closure, as for the owner's own cancel.
+pub async fn cancel_order(
+    admin: AdminUser,
+    deadline: RequestDeadline,
+    State(state): State<AppState>,
+    Path(order_id): Path<OrderId>,
+    Query(params): Query<SymbolRequest>,
+) -> Result<Json<CancelledOrderDto>, (StatusCode, Json<ErrorResponse>)> {
+    let orderbook = get_orderbook(&state, &params.symbol).await?;
+    let ticket = state.ingress.admit(&params.symbol);
+    let mut book = lock_book(&state, &params.symbol, &orderbook, ticket, &deadline).await?;
+    let cancelled = service::close_orders(
+        &state,
+        &params.symbol,
+        &mut book,
+        [order_id],
+        CloseReason::AdminCancelled,
+    )
+    .await
+    .pop()
+    .or_else(|| service::already_closed(&book, order_id, None))
+    .ok_or_else(|| {
+        ErrorResponse::new(
+            format!("Order '{}' not found", order_id),
+            StatusCode::NOT_FOUND,
+        )
+    })?;
+    eprintln!("admin {} cancelled order {}", admin.user_id, order_id);
+    Ok(Json(cancelled.into()))
+}
+
+/// GET /admin/book/orders: every resting order of a symbol with owner attribution, sorted by
+/// price then time priority (bids first). Filters: side, min_qty, user_id, source; paginated.
+pub async fn list_book_orders(
+    _admin: AdminUser,
+    State(state): State<AppState>,
+    Query(params): Query<BookOrdersQuery>,
+) -> Result<Json<BookOrdersResponse>, (StatusCode, Json<ErrorResponse>)> {
+    let normalized_symbol = params.symbol;
+    let orderbook = get_orderbook(&state, &normalized_symbol).await?;
+    let limit = params
+        .limit
+        .unwrap_or(BOOK_ORDERS_DEFAULT_LIMIT)
+        .min(BOOK_ORDERS_MAX_LIMIT);
+    let offset = params.offset.unwrap_or(0);
+    let now = Utc::now();
+
+    // Collect under the book lock; usernames are resolved after it is released
+    let (total, mut orders) = {
+        let book = orderbook.read().await;
+        let matching: Vec<_> = book
+            .iter_orders()
+            .filter(|(_, o)| {
+                params.side.is_none_or(|side| o.side == side)
+                    && params.min_qty.is_none_or(|min| o.quantity >= min)
+                    && params.user_id.is_none_or(|id| o.user_id == id)
+                    && params.source.is_none_or(|source| o.source == source)
+            })
+            .collect();
+        let page: Vec<BookOrderEntry> = matching
+            .iter()
+            .skip(offset)
+            .take(limit)
+            .map(|&(queue_position, o)| BookOrderEntry {
+                order_id: o.id,
+                user_id: o.user_id,
+                username: None,
+                side: o.side,
+                source: o.source,
+                price: o.price,
+                remaining_quantity: o.quantity,
+                queue_position,
+                timestamp: o.timestamp,
+                age_ms: (now - o.timestamp).num_milliseconds(),
+            })
+            .collect();
+        (matching.len(), page)
+    };
+
+    let owners: HashSet<Uuid> = orders.iter().map(|o| o.user_id).collect();
+    let usernames: HashMap<Uuid, String> = state
+        .user_store
+        .read()
+        .await
+        .values()
+        .filter(|cred| owners.contains(&cred.user_id))
+        .map(|cred| (cred.user_id, cred.username.clone()))
+        .collect();
+    for order in &mut orders {
+        order.username = usernames.get(&order.user_id).cloned();
+    }
+
+    Ok(Json(BookOrdersResponse {
+        symbol: normalized_symbol,
+        total,
+        offset,
+        limit,
+        orders,
+    }))
+}
+
+const POSITIONS_DEFAULT_LIMIT: usize = 100;
+const POSITIONS_MAX_LIMIT: usize = 1000;
+
+#[derive(Deserialize)]
+pub struct SymbolPositionsQuery {
+    symbol: Symbol,
+    min_abs_qty: Option<u64>,
+    limit: Option<usize>,
+    offset: Option<usize>,
+}
+
+#[derive(Serialize)]
+pub struct SymbolPositionEntry {
+    user_id: Uuid,
+    quantity: i64,
+    average_price: Price,
+    /// At `mark_price`; None when the symbol has no mark price or the P&L overflows.
+    #[serde(with = "crate::types::string_i64::option")]
+    unrealized_pnl: Option<i64>,
+    opened_at: DateTime<Utc>,
+    updated_at: DateTime<Utc>,
+}
+
+#[derive(Serialize)]
+pub struct SymbolPositionsResponse {
+    symbol: Symbol,
+    /// Fresh index price, else the last trade price.
+    mark_price: Option<Price>,
+    /// Sum of long / short quantities over every position in the symbol (ignores `min_abs_qty`).
+    long_open_interest: u64,
+    short_open_interest: u64,
+    total: usize,
+    offset: usize,
+    limit: usize,
+    positions: Vec<SymbolPositionEntry>,
+}
+
+/// GET /admin/positions?symbol=&min_abs_qty=: every user's position in a symbol, largest
+/// absolute quantity first, with unrealized P&L at the mark price; paginated.
+pub async fn list_symbol_positions(
+    _admin: AdminUser,
+    State(state): State<AppState>,
+    Query(params): Query<SymbolPositionsQuery>,
+) -> Result<Json<SymbolPositionsResponse>, (StatusCode, Json<ErrorResponse>)> {
+    let normalized_symbol = params.symbol;
+    let orderbook = get_orderbook(&state, &normalized_symbol).await?;
+    let limit = params
+        .limit
+        .unwrap_or(POSITIONS_DEFAULT_LIMIT)
+        .min(POSITIONS_MAX_LIMIT);
+    let offset = params.offset.unwrap_or(0);
+
+    let all = if let Some(ref db) = state.db {
+        persistence::list_positions_by_symbol(db, &normalized_symbol)
+            .await
+            .map_err(|e| ErrorResponse::from_db("Failed to load positions", e))?
+            .iter()
+            .map(PositionRow::to_position)
+            .collect()
+    } else {
+        positions::get_positions_by_symbol(&state.positions, &normalized_symbol).await
+    };
+
+    let mark_price = mark_price(&state, &normalized_symbol, &*orderbook.read().await).await;
+    let qty_scale = service::symbol_config(&state, &normalized_symbol)
+        .await
+        .qty_scale
+        .max(1);
+    let long_open_interest = total_quantity(
+        all.iter()
+            .filter(|p| p.quantity > 0)
+            .map(|p| p.quantity.unsigned_abs()),
+    );
+    let short_open_interest = total_quantity(
+        all.iter()
+            .filter(|p| p.quantity < 0)
+            .map(|p| p.quantity.unsigned_abs()),
+    );
+
+    let matching: Vec<&Position> = all
+        .iter()
+        .filter(|p| {
+            params
+                .min_abs_qty
+                .is_none_or(|min| p.quantity.unsigned_abs() >= min)
+        })
+        .collect();
+    let page = matching
+        .iter()
+        .skip(offset)
+        .take(limit)
+        .map(|p| SymbolPositionEntry {
+            user_id: p.user_id,
+            quantity: p.quantity,
+            average_price: p.average_price,
+            unrealized_pnl: mark_price
+                .and_then(|mark| positions::scaled_unrealized_pnl(p, mark, qty_scale).ok()),
+            opened_at: p.opened_at,
+            updated_at: p.updated_at,
+        })
+        .collect();
+
+    Ok(Json(SymbolPositionsResponse {
+        symbol: normalized_symbol,
+        mark_price,
+        long_open_interest,
+        short_open_interest,
+        total: matching.len(),
+        offset,
+        limit,
+        positions: page,
+    }))
+}
+
+/// GET /admin/retention: progress of the current or most recent retention run, plus totals.
+pub async fn get_retention(
+    _admin: AdminUser,
+    State(state): State<AppState>,
+) -> Json<RetentionProgress> {
+    Json(state.retention.progress.read().await.clone())
+}
+
+/// POST /admin/retention/run: start a retention run in the background; poll
+/// GET /admin/retention for progress.
+pub async fn run_retention(
+    _admin: AdminUser,
+    State(state): State<AppState>,
+) -> Result<(StatusCode, Json<RetentionProgress>), (StatusCode, Json<ErrorResponse>)> {
+    let Some(db) = state.db.clone() else {
+        return Err(ErrorResponse::new(
+            "Retention requires a database".to_string(),
+            StatusCode::SERVICE_UNAVAILABLE,
+        ));
+    };
+    if !state.retention.try_claim().await {
+        return Err(ErrorResponse::new(
+            RetentionError::AlreadyRunning.to_string(),
+            StatusCode::CONFLICT,
+        ));
+    }
+    let retention = state.retention.clone();
+    tokio::spawn(async move {
+        let _ = retention.run_claimed(&db).await;
+    });
+    let progress = state.retention.progress.read().await.clone();
+    Ok((StatusCode::ACCEPTED, Json(progress)))
+}
+
+#[derive(Deserialize)]
+pub struct StatementDateQuery {
+    /// UTC day to generate; yesterday when absent.
+    date: Option<NaiveDate>,
+}
+
+/// POST /admin/statements/generate?date=: generate (or regenerate) every user's statement
+/// for one UTC day, as the midnight job does, and return what was stored.
+pub async fn generate_statements(
+    _admin: AdminUser,
+    State(state): State<AppState>,
+    Query(params): Query<StatementDateQuery>,
+) -> Result<Json<StatementRun>, (StatusCode, Json<ErrorResponse>)> {
+    let Some(ref db) = state.db else {
+        return Err(ErrorResponse::new(
+            "Statements require a database".to_string(),
+            StatusCode::SERVICE_UNAVAILABLE,
+        ));
+    };
+    let date = params
+        .date
+        .or_else(|| Utc::now().date_naive().pred_opt())
+        .ok_or_else(|| {
+            ErrorResponse::new("No previous day".to_string(), StatusCode::BAD_REQUEST)
+        })?;
+    let configs = state.symbol_configs.read().await.clone();
+    let run = statements::generate(db, &configs, date, statements::chunk_size_from_env())
+        .await
+        .map_err(|e| ErrorResponse::from_db("Failed to generate statements", e))?;
+    Ok(Json(run))
+}
+
+/// POST /admin/self-test: trade one lot between two ephemeral users on the isolated self-test
+/// book and check every layer, then clean up. 200 if every step passed, 409 if the run was
+/// refused (the book has resting orders or another run is in progress), else 500.
+pub async fn run_self_test(
+    _admin: AdminUser,
+    State(state): State<AppState>,
+) -> (StatusCode, Json<SelfTestReport>) {
+    let report = selftest::run(&state).await;
+    let status = if report.passed {
+        StatusCode::OK
+    } else if report.refused {
+        StatusCode::CONFLICT
+    } else {
+        StatusCode::INTERNAL_SERVER_ERROR
+    };
+    (status, Json(report))
+}
+
+#[derive(Serialize)]
+pub struct OrderLimitsResponse {
+    configured: RestingOrderCaps,
+    #[serde(rename = "override")]
+    cap_override: Option<CapOverride>,
+    /// Caps in force now.
+    effective: RestingOrderCaps,
+    resting_orders: usize,
+    resting_orders_by_symbol: BTreeMap<String, usize>,
+    book_full_rejections: u64,
+}
+
+async fn order_limits_response(state: &AppState) -> OrderLimitsResponse {
+    let mut by_symbol = BTreeMap::new();
+    for (symbol, orderbook) in &state.orderbooks {
+        by_symbol.insert(symbol.clone(), orderbook.read().await.resting_count());
+    }
+    let limits = &state.order_limits;
+    let now = Utc::now();
+    OrderLimitsResponse {
+        configured: limits.configured(),
+        cap_override: limits.active_override(now),
+        effective: limits.effective(now),
+        resting_orders: limits.resting(),
+        resting_orders_by_symbol: by_symbol,
+        book_full_rejections: limits.book_full_rejections(),
+    }
+}
+
+/// GET /admin/order-limits: resting order caps (configured, override, in force) and counts.
+pub async fn get_order_limits(
+    _admin: AdminUser,
+    State(state): State<AppState>,
+) -> Json<OrderLimitsResponse> {
+    Json(order_limits_response(&state).await)
+}
+
+#[derive(Deserialize)]
+pub struct OrderLimitsOverrideRequest {
+    #[serde(flatten)]
+    caps: RestingOrderCaps,
+    duration_secs: u64,
+}
+
+/// Longest a cap override may last.
+const MAX_OVERRIDE_SECS: u64 = 7 * 24 * 60 * 60;
+
+/// PUT /admin/order-limits/override: raise the global and/or per-symbol cap for
+/// `duration_secs`, replacing any earlier override.
+pub async fn set_order_limits_override(
+    _admin: AdminUser,
+    State(state): State<AppState>,
+    Json(body): Json<OrderLimitsOverrideRequest>,
+) -> Result<Json<OrderLimitsResponse>, (StatusCode, Json<ErrorResponse>)> {
+    if body.duration_secs == 0 || body.duration_secs > MAX_OVERRIDE_SECS {
+        return Err(ErrorResponse::new(
+            format!("duration_secs must be between 1 and {}", MAX_OVERRIDE_SECS),
+            StatusCode::BAD_REQUEST,
+        ));
+    }
+    let expires_at = Utc::now() + chrono::Duration::seconds(body.duration_secs as i64);
+    state
+        .order_limits
+        .set_override(CapOverride {
+            caps: body.caps,
+            expires_at,
+        })
+        .map_err(|msg| ErrorResponse::new(msg, StatusCode::BAD_REQUEST))?;
+    Ok(Json(order_limits_response(&state).await))
+}
+
+/// DELETE /admin/order-limits/override: return to the configured caps now.
+pub async fn clear_order_limits_override(
+    _admin: AdminUser,
+    State(state): State<AppState>,
+) -> StatusCode {
+    state.order_limits.clear_override();
+    StatusCode::NO_CONTENT
+}
+
+#[derive(Deserialize)]
+pub struct CreditBalanceRequest {
+    user_id: Uuid,
+    asset: String,
+    /// Negative to withdraw; available may not go below zero.
+    amount: i64,
+    /// The balances credited; live unless given.
+    #[serde(default)]
+    environment: Environment,
+}
+
+/// POST /admin/balances/credit: add to (or withdraw from) a user's available balance.
+pub async fn credit_balance(
+    _admin: AdminUser,
+    State(state): State<AppState>,
+    Json(body): Json<CreditBalanceRequest>,
+) -> Result<Json<AssetBalance>, (StatusCode, Json<ErrorResponse>)> {
+    let mut ledger = service::environment_balances(&state, body.environment)
+        .ledger()
+        .await
+        .ok_or_else(balances_disabled)?;
+    let balance = ledger
+        .credit(body.user_id, &body.asset, body.amount)
+        .map_err(|e| ErrorResponse::new(e.to_string(), StatusCode::BAD_REQUEST))?;
+    Ok(Json(AssetBalance {
+        asset: body.asset,
+        balance,
+    }))
+}
+
+/// GET /admin/hydration: what startup restored from the database, skipped, or failed to load.
+pub async fn get_hydration_report(
+    _admin: AdminUser,
+    State(state): State<AppState>,
+) -> Json<HydrationReport> {
+    Json((*state.hydration_report).clone())
+}
+
+/// GET /admin/tasks: every background task with its state, restarts and last panic.
+pub async fn list_tasks(_admin: AdminUser, State(state): State<AppState>) -> Json<Vec<TaskStatus>> {
+    Json(state.tasks.statuses())
+}
+
+/// GET /admin/schema: migrations the database has applied and those this binary still
+/// expects (`pending`), plus any applied by a newer binary (`unknown`).
+pub async fn get_schema_status(
+    _admin: AdminUser,
+    State(state): State<AppState>,
+) -> Result<Json<SchemaStatus>, (StatusCode, Json<ErrorResponse>)> {
+    let Some(ref db) = state.db else {
+        return Err(ErrorResponse::new(
+            "Schema status requires a database".to_string(),
+            StatusCode::SERVICE_UNAVAILABLE,
+        ));
+    };
+    persistence::schema_status(db)
+        .await
+        .map(Json)
+        .map_err(|e| ErrorResponse::from_db("Failed to read schema status", e))
+}
+
+/// GET /admin/persistence: trade persistence counters.
+pub async fn get_persistence_metrics(
+    _admin: AdminUser,
+    State(state): State<AppState>,
+) -> Json<TradePersistenceSnapshot> {
+    Json(state.trade_metrics.snapshot())
+}
+
+#[derive(Deserialize)]
+pub struct WsConnectionsQuery {
+    #[serde(default, deserialize_with = "optional_symbol")]
+    pub symbol: Option<Symbol>,
+    pub user_id: Option<Uuid>,
+}
+
+#[derive(Serialize)]
+pub struct WsConnectionsResponse {
+    /// Totals over all open connections, regardless of the filters.
+    pub counts: ConnectionCounts,
+    /// Overflow counters since startup, including closed connections.
+    pub totals: WsTotals,
+    pub config: WsConfig,
+    pub connections: Vec<ConnectionInfo>,
+}
+
+/// GET /admin/ws/connections: open WebSocket connections, optionally only those subscribed to
+/// `symbol` and/or authenticated as `user_id`.
+pub async fn list_ws_connections(
+    _admin: AdminUser,
+    State(state): State<AppState>,
+    Query(params): Query<WsConnectionsQuery>,
+) -> Json<WsConnectionsResponse> {
+    let symbol = params.symbol;
+    Json(WsConnectionsResponse {
+        counts: state.ws_connections.counts(),
+        totals: state.ws_connections.totals(),
+        config: *state.ws_connections.config(),
+        connections: state.ws_connections.list(symbol.as_deref(), params.user_id),
+    })
+}
+
+/// DELETE /admin/ws/connections/{id}: send the connection a close frame and drop it.
+pub async fn close_ws_connection(
+    _admin: AdminUser,
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+) -> Result<StatusCode, (StatusCode, Json<ErrorResponse>)> {
+    if state.ws_connections.close(id) {
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Err(ErrorResponse::new(
+            format!("Connection {} not found", id),
+            StatusCode::NOT_FOUND,
+        ))
+    }
+}
+
+#[derive(Deserialize)]
+pub struct RebroadcastQuery {
+    #[serde(default, deserialize_with = "optional_symbol")]
+    symbol: Option<Symbol>,
+    /// Precede each snapshot with a `Resync` message.
+    #[serde(default)]
+    resync: bool,
+}
+
+#[derive(Serialize)]
+pub struct RebroadcastResponse {
+    symbols: Vec<String>,
+    resync: bool,
+}
+
+/// Audit an operator push when there is a database; the push is refused if that fails.
+async fn audit_ws_push(
+    state: &AppState,
+    admin: &AdminUser,
+    action: &str,
+    symbol: Option<&str>,
+    details: serde_json::Value,
+) -> Result<(), (StatusCode, Json<ErrorResponse>)> {
+    let Some(ref db) = state.db else {
+        return Ok(());
+    };
+    persistence::insert_ws_broadcast_audit(db, admin.user_id, action, symbol, &details)
+        .await
+        .map_err(|e| ErrorResponse::from_db("Failed to audit the broadcast", e))
+}
+
+/// POST /admin/ws/rebroadcast?symbol=&resync=: publish a fresh `OrderBookUpdate` snapshot of
+/// `symbol`, or of every symbol when omitted, to its subscribers now. With `resync=true` each
+/// snapshot follows a `Resync` message telling clients to drop their book first.
+pub async fn rebroadcast_books(
+    admin: AdminUser,
+    State(state): State<AppState>,
+    Query(params): Query<RebroadcastQuery>,
+) -> Result<Json<RebroadcastResponse>, (StatusCode, Json<ErrorResponse>)> {
+    let symbols: Vec<String> = match params.symbol {
+        Some(ref symbol) => {
+            get_orderbook(&state, symbol).await?;
+            vec![symbol.to_string()]
+        }
+        None => {
+            let mut symbols: Vec<String> = state.orderbooks.keys().cloned().collect();
+            symbols.sort();
+            symbols
+        }
+    };
+    audit_ws_push(
+        &state,
+        &admin,
+        "rebroadcast",
+        params.symbol.as_deref(),
+        serde_json::json!({ "symbols": symbols, "resync": params.resync }),
+    )
+    .await?;
+    for symbol in &symbols {
+        // Under the read lock, so no update for the symbol lands between the two
+        let book = state.orderbooks[symbol].read().await;
+        if params.resync {
+            let _ = state.ws_channel.send(WsMessage::Resync {
+                symbol: symbol.clone(),
+            });
+        }
+        let _ = state.ws_channel.send(WsMessage::OrderBookUpdate {
+            symbol: symbol.clone(),
+            bids: book.get_bids(),
+            asks: book.get_asks(),
+            diff: None,
+        });
+    }
+    Ok(Json(RebroadcastResponse {
+        symbols,
+        resync: params.resync,
+    }))
+}
+
+/// Longest announcement accepted, in characters.
+const MAX_ANNOUNCEMENT_LEN: usize = 1000;
+
+#[derive(Deserialize)]
+pub struct AnnouncementRequest {
+    message: String,
+    #[serde(default)]
+    severity: AnnouncementSeverity,
+}
+
+#[derive(Serialize)]
+pub struct AnnouncementResponse {
+    /// Connections it was queued for.
+    delivered: usize,
+}
+
+/// POST /admin/ws/broadcast: send an `Announcement` to every connected WebSocket client.
+pub async fn broadcast_announcement(
+    admin: AdminUser,
+    State(state): State<AppState>,
+    Json(body): Json<AnnouncementRequest>,
+) -> Result<Json<AnnouncementResponse>, (StatusCode, Json<ErrorResponse>)> {
+    let length = body.message.trim().chars().count();
+    if length == 0 || length > MAX_ANNOUNCEMENT_LEN {
+        return Err(ErrorResponse::new(
+            format!(
+                "Announcement must be 1 to {} characters",
+                MAX_ANNOUNCEMENT_LEN
+            ),
+            StatusCode::BAD_REQUEST,
+        ));
+    }
+    audit_ws_push(
+        &state,
+        &admin,
+        "announcement",
+        None,
+        serde_json::json!({ "message": body.message, "severity": body.severity }),
+    )
+    .await?;
+    let delivered = state.ws_fanout.announce(&WsMessage::Announcement {
+        message: body.message,
+        severity: body.severity,
+    });
+    Ok(Json(AnnouncementResponse { delivered }))
+}
+
+const USERS_DEFAULT_LIMIT: usize = 100;
+const USERS_MAX_LIMIT: usize = 1000;
+
+/// Order of `GET /admin/users`.
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UserSort {
+    /// Most recent login first; users who never logged in last, newest first.
+    #[default]
+    LastLogin,
+    /// Newest registration first.
+    CreatedAt,
+    Username,
+}
+
+#[derive(Deserialize)]
+pub struct UsersQuery {
+    #[serde(default)]
+    sort: UserSort,
+    limit: Option<usize>,
+    offset: Option<usize>,
+}
+
+#[derive(Serialize)]
+pub struct UsersResponse {
+    total: usize,
+    offset: usize,
+    limit: usize,
+    users: Vec<Account>,
+}
+
+/// GET /admin/users?sort=last_login|created_at|username: every user's account metadata,
+/// paginated.
+pub async fn list_users(
+    _admin: AdminUser,
+    State(state): State<AppState>,
+    Query(params): Query<UsersQuery>,
+) -> Json<UsersResponse> {
+    let limit = params
+        .limit
+        .unwrap_or(USERS_DEFAULT_LIMIT)
+        .min(USERS_MAX_LIMIT);
+    let offset = params.offset.unwrap_or(0);
+    let mut users: Vec<Account> = state
+        .user_store
+        .read()
+        .await
+        .values()
+        .map(|credential| credential.account())
+        .collect();
+    match params.sort {
+        UserSort::LastLogin => users.sort_by(|a, b| {
+            b.last_login_at
+                .cmp(&a.last_login_at)
+                .then(b.created_at.cmp(&a.created_at))
+        }),
+        UserSort::CreatedAt => users.sort_by_key(|user| Reverse(user.created_at)),
+        UserSort::Username => users.sort_by_key(|user| user.username.to_lowercase()),
+    }
+    Json(UsersResponse {
+        total: users.len(),
+        offset,
+        limit,
+        users: users.into_iter().skip(offset).take(limit).collect(),
+    })
+}
+
+/// GET /admin/queries: latency histogram per persistence function (buckets in
+/// `QUERY_LATENCY_BUCKETS_MS`).
+pub async fn get_query_latencies(_admin: AdminUser) -> Json<BTreeMap<String, QueryHistogram>> {
+    Json(persistence::query_histograms())
+}
+
+#[derive(Serialize)]
+pub struct MatchingStatsResponse {
+    symbol: Symbol,
+    /// Orders resting in the book now.
+    resting_orders: usize,
+    #[serde(flatten)]
+    stats: MatchingStatsReport,
+}
+
+/// GET /admin/stats/matching?symbol=: time-to-fill percentiles, fill ratio and maker share over
+/// the book's recent window.
+pub async fn get_matching_stats(
+    _admin: AdminUser,
+    State(state): State<AppState>,
+    Query(params): Query<SymbolRequest>,
+) -> Result<Json<MatchingStatsResponse>, (StatusCode, Json<ErrorResponse>)> {
+    let normalized_symbol = params.symbol;
+    let orderbook = get_orderbook(&state, &normalized_symbol).await?;
+    let book = orderbook.read().await;
+    let (resting_orders, stats) = (book.resting_count(), book.matching_stats().report());
+    drop(book);
+    Ok(Json(MatchingStatsResponse {
+        symbol: normalized_symbol,
+        resting_orders,
+        stats,
+    }))
+}
+
+#[derive(Serialize)]
+pub struct BookInfoResponse {
+    symbol: Symbol,
+    #[serde(flatten)]
+    stats: BookStats,
+}
+
+/// GET /admin/book/info?symbol=: level, order and trade counts, estimated memory, sequence
+/// and timing of the book, read live under its lock.
+pub async fn get_book_info(
+    _admin: AdminUser,
+    State(state): State<AppState>,
+    Query(params): Query<SymbolRequest>,
+) -> Result<Json<BookInfoResponse>, (StatusCode, Json<ErrorResponse>)> {
+    let orderbook = get_orderbook(&state, &params.symbol).await?;
+    let stats = orderbook.read().await.stats();
+    Ok(Json(BookInfoResponse {
+        symbol: params.symbol,
+        stats,
+    }))
+}
+
+/// GET /admin/metrics: cumulative matching metrics for every book in Prometheus text format,
+/// plus the book gauges as last sampled by the stats refresher.
+pub async fn get_prometheus_metrics(
+    _admin: AdminUser,
+    State(state): State<AppState>,
+) -> impl IntoResponse {
+    let mut symbols: Vec<&String> = state.orderbooks.keys().collect();
+    symbols.sort();
+    let mut books = Vec::with_capacity(symbols.len());
+    let mut resting = Vec::with_capacity(symbols.len());
+    for symbol in symbols {
+        let book = state.orderbooks[symbol].read().await;
+        books.push((symbol.clone(), book.matching_stats().metrics()));
+        resting.push((symbol.clone(), book.resting_count()));
+    }
+    let mut body = render_prometheus(&books);
+    body.push_str(&state.order_limits.render_prometheus(&resting, Utc::now()));
+    body.push_str(&state.lock_waits.render_prometheus());
+    body.push_str(&state.phase_timings.render_prometheus());
+    body.push_str(&state.ws_connections.render_prometheus());
+    body.push_str(&state.book_stats.render_prometheus());
+    body.push_str(&state.incidents.render_prometheus());
+    body.push_str(&state.surveillance.render_prometheus());
+    ([(header::CONTENT_TYPE, "text/plain; version=0.0.4")], body)
+}
+
+#[derive(Deserialize)]
+pub struct ReconcileQuery {
+    symbol: Symbol,
+    repair: Option<RepairTarget>,
+}
+
+/// GET /admin/reconcile?symbol=: diff the book's resting orders and the positions map against
+/// the database without changing anything. `repair=orders|positions` writes the in-memory
+/// side back, with an audit entry per fix; the report is the diff found before repairing.
+pub async fn reconcile_state(
+    admin: AdminUser,
+    State(state): State<AppState>,
+    Query(params): Query<ReconcileQuery>,
+) -> Result<Json<ReconcileReport>, (StatusCode, Json<ErrorResponse>)> {
+    let Some(ref db) = state.db else {
+        return Err(ErrorResponse::new(
+            "Reconciliation requires a database".to_string(),
+            StatusCode::SERVICE_UNAVAILABLE,
+        ));
+    };
+    let orderbook = get_orderbook(&state, &params.symbol).await?;
+    let report = reconcile::run(
+        &state,
+        db,
+        &params.symbol,
+        &orderbook,
+        params.repair,
+        admin.user_id,
+    )
+    .await
+    .map_err(|e| ErrorResponse::from_db("Failed to reconcile", e))?;
+    Ok(Json(report))
+}
+
+#[derive(Serialize)]
+pub struct ReconcileResponse {
+    symbol: Symbol,
+    checked: usize,
+    missing: Vec<Uuid>,
+    reinserted: u64,
+}
+
+/// POST /admin/trades/reconcile: check the trades the book still retains against the
+/// database and re-insert any that are missing (e.g. after a failed persist).
+pub async fn reconcile_trades(
+    _admin: AdminUser,
+    State(state): State<AppState>,
+    Json(body): Json<SymbolRequest>,
+) -> Result<Json<ReconcileResponse>, (StatusCode, Json<ErrorResponse>)> {
+    let Some(ref db) = state.db else {
+        return Err(ErrorResponse::new(
+            "Reconciliation requires a database".to_string(),
+            StatusCode::SERVICE_UNAVAILABLE,
+        ));
+    };
+    let normalized_symbol = body.symbol;
+    let orderbook = get_orderbook(&state, &normalized_symbol).await?;
+    let (trades, attributions): (Vec<Trade>, Vec<TradeAttribution>) = {
+        let book = orderbook.read().await;
+        let trades = book.get_all_trades();
+        let attributions = trades
+            .iter()
+            .filter_map(|t| book.attribution(t.id).copied())
+            .collect();
+        (trades, attributions)
+    };
+    let ids: Vec<Uuid> = trades.iter().map(|t| t.id).collect();
+
+    let db_error = |e| ErrorResponse::from_db("Failed to reconcile trades", e);
+    let missing = persistence::reconcile_trades(db, &normalized_symbol, &ids)
+        .await
+        .map_err(db_error)?;
+    state.trade_metrics.record_missing(missing.len());
+    let missing_set: HashSet<Uuid> = missing.iter().copied().collect();
+    let to_insert: Vec<Trade> = trades
+        .into_iter()
+        .filter(|t| missing_set.contains(&t.id))
+        .collect();
+    let reinserted =
+        persistence::insert_trades_bulk(db, &normalized_symbol, &to_insert, &attributions)
+            .await
+            .map_err(db_error)?;
+    state
+        .trade_metrics
+        .record_insert(to_insert.len(), reinserted);
+
+    Ok(Json(ReconcileResponse {
+        symbol: normalized_symbol,
+        checked: ids.len(),
+        missing,
+        reinserted,
+    }))
+}
+
+#[derive(Deserialize)]
+pub struct BustTradeRequest {
+    reason: String,
+}
+
+#[derive(Serialize)]
+pub struct BustTradeResponse {
+    symbol: Symbol,
+    trade: TradeDto,
+    busted_at: DateTime<Utc>,
+    reason: String,
+    /// Each party's position after the reversal (quantity 0 when it went flat).
+    positions: Vec<PositionDto>,
+}
+
+/// POST /admin/trades/{id}/bust: cancel an executed trade. It stays stored, marked busted,
+/// and drops out of public trades and candles; both parties' positions and balances are
+/// taken back as if it had not happened (`positions::reverse_position`), and each is told
+/// with a `TradeBusted` message. With a database the bust, the positions and an audit entry
+/// are written in one transaction, which also claims the bust: 409 if it was already busted.
+pub async fn bust_trade(
+    admin: AdminUser,
+    State(state): State<AppState>,
+    Path(trade_id): Path<Uuid>,
+    Json(body): Json<BustTradeRequest>,
+) -> Result<Json<BustTradeResponse>, (StatusCode, Json<ErrorResponse>)> {
+    let reason = body.reason.trim();
+    if reason.is_empty() {
+        return Err(ErrorResponse::new(
+            "A reason is required to bust a trade".to_string(),
+            StatusCode::BAD_REQUEST,
+        ));
+    }
+    let not_found = || {
+        ErrorResponse::new(
+            format!("Trade '{}' not found", trade_id),
+            StatusCode::NOT_FOUND,
+        )
+    };
+    let db_error = |e| ErrorResponse::from_db("Failed to bust trade", e);
+
+    let stored = match state.db {
+        Some(ref db) => persistence::find_trade(db, trade_id)
+            .await
+            .map_err(db_error)?,
+        None => None,
+    };
+    let mut retained_by = None;
+    for (symbol, orderbook) in &state.orderbooks {
+        if orderbook.read().await.trade(trade_id).is_some() {
+            retained_by = Some(symbol.clone());
+            break;
+        }
+    }
+    let symbol = retained_by
+        .or_else(|| stored.as_ref().map(|row| row.trade.symbol.clone()))
+        .ok_or_else(not_found)?;
+    let symbol = Symbol::parse(&symbol).map_err(|_| not_found())?;
+    let orderbook = get_orderbook(&state, &symbol).await?;
+    // Held throughout, so no fill moves these positions while they are reversed
+    let mut book = orderbook.write().await;
+
+    let (trade, attribution, already_busted) = match (book.trade(trade_id), &stored) {
+        (Some(trade), _) => (
+            trade.clone(),
+            book.attribution(trade_id).copied(),
+            book.bust(trade_id).is_some() || stored.as_ref().is_some_and(|r| r.busted_at.is_some()),
+        ),
+        (None, Some(row)) => (row.to_trade(), row.attribution(), row.busted_at.is_some()),
+        (None, None) => return Err(not_found()),
+    };
+    if already_busted {
+        return Err(ErrorResponse::new(
+            format!("Trade '{}' was already busted", trade_id),
+            StatusCode::CONFLICT,
+        ));
+    }
+    let Some(attribution) = attribution else {
+        return Err(ErrorResponse::new(
+            format!(
+                "Trade '{}' has no recorded taker side, so it cannot be reversed",
+                trade_id
+            ),
+            StatusCode::CONFLICT,
+        ));
+    };
+    let bust = TradeBust {
+        busted_at: Utc::now(),
+        reason: reason.to_string(),
+    };
+
+    let tx = match state.db {
+        Some(ref db) => {
+            let mut tx = db.begin().await.map_err(db_error)?;
+            if !persistence::mark_trade_busted(&mut *tx, trade_id, &bust)
+                .await
+                .map_err(db_error)?
+            {
+                return Err(ErrorResponse::new(
+                    format!("Trade '{}' was already busted", trade_id),
+                    StatusCode::CONFLICT,
+                ));
+            }
+            Some(tx)
+        }
+        None => None,
+    };
+
+    // Legs in the reverse of the order they were applied (maker, then taker), which matters
+    // for a self-trade
+    let taker_side = attribution.taker_side;
+    let mut deltas = Vec::with_capacity(2);
+    for (user_id, side) in [
+        (trade.taker_user_id, taker_side),
+        (trade.maker_user_id, taker_side.opposite()),
+    ] {
+        deltas.push(
+            positions::reverse_position(
+                &state.positions,
+                user_id,
+                &symbol,
+                side,
+                trade.price,
+                trade.quantity,
+                bust.busted_at,
+            )
+            .await,
+        );
+    }
+    let config = service::symbol_config(&state, &symbol).await;
+    let qty_scale = config.qty_scale;
+    if let Some(mut ledger) = service::symbol_ledger(&state, &symbol).await {
+        ledger.reverse(&config, taker_side, &trade);
+    }
+    book.record_bust(trade_id, bust.clone());
+
+    // The last delta per user is where each position ended up
+    let mut positions: Vec<Position> = Vec::with_capacity(2);
+    for delta in deltas.iter().rev() {
+        if !positions.iter().any(|p| p.user_id == delta.user_id) {
+            positions.insert(0, delta.position());
+        }
+    }
+    if let Some(mut tx) = tx {
+        // Memory already moved on; a failure here leaves positions for /admin/reconcile
+        let saved: Result<(), sqlx::Error> = async {
+            for position in &positions {
+                if position.quantity == 0 {
+                    persistence::delete_position(&mut *tx, position.user_id, &symbol).await?;
+                } else {
+                    persistence::upsert_position(
+                        &mut *tx,
+                        position.user_id,
+                        &symbol,
+                        position.quantity,
+                        position.average_price,
+                        position.opened_at,
+                        position.updated_at,
+                    )
+                    .await?;
+                }
+            }
+            let positions = serde_json::to_value(&positions).unwrap_or_default();
+            persistence::insert_trade_bust_audit(
+                &mut *tx,
+                &symbol,
+                admin.user_id,
+                &trade,
+                &bust,
+                &positions,
+            )
+            .await?;
+            tx.commit().await
+        }
+        .await;
+        saved.map_err(db_error)?;
+    }
+    eprintln!(
+        "admin {} busted trade {} on {}: {}",
+        admin.user_id, trade_id, symbol, bust.reason
+    );
+
+    if let Some(correction) = state.market_data.remove_trade(&symbol, &trade)
+        && let Some(ref db) = state.db
+    {
+        let corrected = match correction {
+            CandleCorrection::Updated(candle) => {
+                persistence::upsert_candle(db, &symbol, &candle).await
+            }
+            CandleCorrection::Removed { open_time } => {
+                persistence::delete_candle(db, &symbol, open_time).await
+            }
+        };
+        if let Err(e) = corrected {
+            eprintln!("failed to correct {} candle after bust: {}", symbol, e);
+        }
+    }
+    ws::broadcast_trade_busted(&state.ws_channel, &symbol, &trade, &bust);
+    let mark = mark_price(&state, &symbol, &book).await;
+    ws::broadcast_position_updates(&state.ws_channel, &deltas, mark, qty_scale);
+    drop(book);
+
+    Ok(Json(BustTradeResponse {
+        symbol,
+        trade: trade.into(),
+        busted_at: bust.busted_at,
+        reason: bust.reason,
+        positions: positions.into_iter().map(PositionDto::from).collect(),
+    }))
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct PatchSymbolConfigQuery {
+    /// Cancel resting orders that break the new rules instead of refusing the change.
+    #[serde(default)]
+    pub cancel_violating: bool,
+}
+
+#[derive(Serialize)]
+pub struct PatchSymbolConfigResponse {
+    #[serde(flatten)]
+    pub config: SymbolConfigResponse,
+    /// Orders cancelled with `cancel_violating`.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub cancelled: Vec<CancelledOrderDto>,
+}
+
+/// PATCH /admin/symbols/{symbol}: change trading rules without a restart. Order entry is held
+/// off (book lock) while the change is checked against resting orders, saved with an audit
+/// entry, and applied; subscribers then get a `SymbolConfigUpdate`. A tick or lot size that
+/// resting orders do not fit is rejected with 409 and must wait until they are cancelled,
+/// unless `?cancel_violating=true` asks for them (and any others breaking the new rules) to be
+/// cancelled as by `enforce_symbol_config`.
+pub async fn patch_symbol_config(
+    admin: AdminUser,
+    State(state): State<AppState>,
+    Path(normalized_symbol): Path<Symbol>,
+    Query(params): Query<PatchSymbolConfigQuery>,
+    Json(patch): Json<SymbolConfigPatch>,
+) -> Result<Json<PatchSymbolConfigResponse>, (StatusCode, Json<ErrorResponse>)> {
+    if patch.is_empty() {
+        return Err(ErrorResponse::new(
+            "No config fields supplied".to_string(),
+            StatusCode::BAD_REQUEST,
+        ));
+    }
+    let orderbook = get_orderbook(&state, &normalized_symbol).await?;
+    let mut book = orderbook.write().await;
+    let mut configs = state.symbol_configs.write().await;
+    let previous = configs
+        .get(normalized_symbol.as_str())
+        .copied()
+        .unwrap_or_default();
+    let config = previous
+        .apply(&patch)
+        .map_err(|msg| ErrorResponse::new(msg, StatusCode::BAD_REQUEST))?;
+
+    let mut violating = config.violating_orders(&previous, book.iter_orders().map(|(_, o)| o));
+    if let Some(first) = violating.first()
+        && !params.cancel_violating
+    {
+        return Err(ErrorResponse::new(
+            format!(
+                "{} resting order(s) do not fit the new tick or lot size (e.g. {}); cancel them first",
+                violating.len(),
+                first
+            ),
+            StatusCode::CONFLICT,
+        ));
+    }
+    if params.cancel_violating {
+        for found in config_violations(&config, &book) {
+            if !violating.contains(&found.order_id) {
+                violating.push(found.order_id);
+            }
+        }
+    }
+
+    let change = state.rules.prepare(
+        &normalized_symbol,
+        &previous,
+        &config,
+        RuleChangeSource::AdminPatch,
+        Some(admin.user_id),
+        state.clock.now(),
+    );
+    if let Some(ref db) = state.db {
+        let saved: Result<(), sqlx::Error> = async {
+            let mut tx = db.begin().await?;
+            persistence::upsert_symbol_config(&mut *tx, &normalized_symbol, &config).await?;
+            persistence::insert_symbol_config_audit(
+                &mut *tx,
+                &normalized_symbol,
+                admin.user_id,
+                &patch,
+                &previous,
+            )
+            .await?;
+            if let Some(ref change) = change {
+                persistence::insert_rule_change(&mut *tx, &change.to_row()).await?;
+            }
+            tx.commit().await
+        }
+        .await;
+        saved.map_err(|e| ErrorResponse::from_db("Failed to save symbol config", e))?;
+    }
+    if let Some(change) = change {
+        state.rules.commit(change);
+    }
+    eprintln!(
+        "symbol config {} changed by {}: {:?} -> {:?}",
+        normalized_symbol, admin.user_id, previous, config
+    );
+    configs.insert(normalized_symbol.to_string(), config);
+    drop(configs);
+    if config.halted != previous.halted {
+        let symbol = normalized_symbol.to_string();
+        state.activity.record(if config.halted {
+            ActivityKind::Halted { symbol }
+        } else {
+            ActivityKind::Resumed { symbol }
+        });
+    }
+    book.set_pricing_policy(config.pricing_policy, config.tick_size);
+    if config.meta() != previous.meta() {
+        // `/book` bodies and ETags carry the scales
+        book.touch();
+    }
+    let cancelled = service::close_orders(
+        &state,
+        &normalized_symbol,
+        &mut book,
+        violating,
+        CloseReason::ConfigEnforcement,
+    )
+    .await;
+    drop(book);
+
+    crate::api::ws::broadcast_symbol_config_update(&state.ws_channel, &normalized_symbol, &config);
+    Ok(Json(PatchSymbolConfigResponse {
+        config: SymbolConfigResponse::new(normalized_symbol, config),
+        cancelled: cancelled.into_iter().map(CancelledOrderDto::from).collect(),
+    }))
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EnforceConfigRequest {
+    /// Cancel the violating orders; without it they are only reported.
+    #[serde(default)]
+    pub apply: bool,
+}
+
+/// A resting order that breaks its symbol's current rules.
+#[derive(Debug, Serialize)]
+pub struct ConfigViolation {
+    pub order_id: OrderId,
+    pub user_id: Uuid,
+    pub side: OrderSide,
+    #[serde(with = "crate::types::string_i64")]
+    pub price: Price,
+    #[serde(with = "crate::types::string_i64")]
+    pub quantity: Qty,
+    pub reasons: Vec<RejectReason>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct EnforceConfigResponse {
+    pub symbol: Symbol,
+    /// Whether `violations` were cancelled or only found.
+    pub applied: bool,
+    pub violations: Vec<ConfigViolation>,
+}
+
+/// Resting orders on `book` breaking `config`, with the rules each breaks; the price band is
+/// anchored at the book's last trade.
+fn config_violations(config: &SymbolConfig, book: &OrderBook) -> Vec<ConfigViolation> {
+    let last_price = book.last_trade_price();
+    book.find_orders(|order| !config.resting_violations(order, last_price).is_empty())
+        .into_iter()
+        .map(|order| ConfigViolation {
+            order_id: order.id,
+            user_id: order.user_id,
+            side: order.side,
+            price: order.price,
+            quantity: order.quantity,
+            reasons: config.resting_violations(order, last_price),
+        })
+        .collect()
+}
+
+/// POST /admin/symbols/{symbol}/enforce-config: find the resting orders that break the
+/// symbol's current rules (off tick, outside the price band, off lot or below the minimum
+/// quantity), e.g. after a `PATCH` loosened nothing but tightened the band. Reports them; with
+/// `apply` also cancels them with reason `CONFIG_ENFORCEMENT`, notifying the owners.
+pub async fn enforce_symbol_config(
+    admin: AdminUser,
+    State(state): State<AppState>,
+    Path(normalized_symbol): Path<Symbol>,
+    Json(request): Json<EnforceConfigRequest>,
+) -> Result<Json<EnforceConfigResponse>, (StatusCode, Json<ErrorResponse>)> {
+    let orderbook = get_orderbook(&state, &normalized_symbol).await?;
+    let mut book = orderbook.write().await;
+    let config = service::symbol_config(&state, &normalized_symbol).await;
+    let violations = config_violations(&config, &book);
+    if request.apply && !violations.is_empty() {
+        let cancelled = service::close_orders(
+            &state,
+            &normalized_symbol,
+            &mut book,
+            violations.iter().map(|v| v.order_id),
+            CloseReason::ConfigEnforcement,
+        )
+        .await;
+        eprintln!(
+            "symbol config of {} enforced by {}: {} order(s) cancelled",
+            normalized_symbol,
+            admin.user_id,
+            cancelled.len()
+        );
+    }
+    drop(book);
+
+    Ok(Json(EnforceConfigResponse {
+        symbol: normalized_symbol,
+        applied: request.apply,
+        violations,
+    }))
+}
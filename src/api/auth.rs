@@ -1,7 +1,11 @@
 use argon2::password_hash::{rand_core::OsRng, PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
 use argon2::Argon2;
 use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::time::Duration;
+use tokio::sync::RwLock;
 use uuid::Uuid;
 
 /// JWT claims: `sub` = user id (Uuid as string), `exp` (expiry), `iat` (issued at).
@@ -18,19 +22,121 @@ pub struct AuthUser {
     pub user_id: Uuid,
 }
 
+/// Authenticated user whose id is in the configured admin set.
+#[derive(Debug, Clone)]
+pub struct AdminUser {
+    pub user_id: Uuid,
+}
+
 /// User credential for login validation (from DB or in-memory). Holds only password hash.
 #[derive(Clone)]
 pub struct AuthUserCredential {
     pub user_id: Uuid,
     pub username: String,
     pub password_hash: String,
+    pub created_at: DateTime<Utc>,
+    /// Last successful login; None until the first.
+    pub last_login_at: Option<DateTime<Utc>>,
+    pub login_count: u64,
+}
+
+impl AuthUserCredential {
+    /// A user registered at `created_at` who has not logged in yet.
+    pub fn new(
+        user_id: Uuid,
+        username: String,
+        password_hash: String,
+        created_at: DateTime<Utc>,
+    ) -> Self {
+        Self {
+            user_id,
+            username,
+            password_hash,
+            created_at,
+            last_login_at: None,
+            login_count: 0,
+        }
+    }
+
+    pub fn account(&self) -> Account {
+        Account {
+            user_id: self.user_id,
+            username: self.username.clone(),
+            created_at: self.created_at,
+            last_login_at: self.last_login_at,
+            login_count: self.login_count,
+        }
+    }
+}
+
+/// Account metadata as shown to its owner (`GET /auth/me`) and admins (`GET /admin/users`).
+#[derive(Debug, Clone, Serialize)]
+pub struct Account {
+    pub user_id: Uuid,
+    pub username: String,
+    pub created_at: DateTime<Utc>,
+    pub last_login_at: Option<DateTime<Utc>>,
+    pub login_count: u64,
 }
 
 const JWT_EXPIRY_HOURS: i64 = 24;
 
+/// Default time a released username stays reserved for its previous owner.
+pub const DEFAULT_USERNAME_COOLDOWN: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+/// Usernames released by a rename. Another user cannot take a released name until `cooldown`
+/// has passed, so an old name cannot be used to impersonate its previous owner. The in-memory
+/// map is used when there is no DB; with a DB, `username_history` is the source of truth.
+pub struct UsernameHistory {
+    pub cooldown: Duration,
+    released: RwLock<HashMap<String, (Uuid, DateTime<Utc>)>>,
+}
+
+impl UsernameHistory {
+    pub fn new(cooldown: Duration) -> Self {
+        Self {
+            cooldown,
+            released: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// Start of the cooldown window: names released after this are still reserved.
+    pub fn cutoff(&self) -> DateTime<Utc> {
+        chrono::Duration::from_std(self.cooldown)
+            .ok()
+            .and_then(|cooldown| Utc::now().checked_sub_signed(cooldown))
+            .unwrap_or(DateTime::<Utc>::MIN_UTC)
+    }
+
+    /// Record that `user_id` released `username` (lowercase) just now.
+    pub async fn release(&self, username: &str, user_id: Uuid) {
+        self.released
+            .write()
+            .await
+            .insert(username.to_string(), (user_id, Utc::now()));
+    }
+
+    /// The user still holding `username` (lowercase) within the cooldown, if any.
+    pub async fn held_by(&self, username: &str) -> Option<Uuid> {
+        let cutoff = self.cutoff();
+        self.released
+            .read()
+            .await
+            .get(username)
+            .filter(|(_, released_at)| *released_at > cutoff)
+            .map(|(user_id, _)| *user_id)
+    }
+}
+
+impl Default for UsernameHistory {
+    fn default() -> Self {
+        Self::new(DEFAULT_USERNAME_COOLDOWN)
+    }
+}
+
 impl Claims {
-    pub fn new(user_id: Uuid) -> Self {
-        let now = chrono::Utc::now();
+    /// Claims for a token issued to `user_id` at `now`.
+    pub fn new(user_id: Uuid, now: DateTime<Utc>) -> Self {
         let exp = (now + chrono::Duration::hours(JWT_EXPIRY_HOURS)).timestamp();
         Self {
             sub: user_id.to_string(),
@@ -40,8 +146,12 @@ impl Claims {
     }
 }
 
-pub fn create_token(secret: &[u8], user_id: Uuid) -> Result<String, jsonwebtoken::errors::Error> {
-    let claims = Claims::new(user_id);
+pub fn create_token(
+    secret: &[u8],
+    user_id: Uuid,
+    now: DateTime<Utc>,
+) -> Result<String, jsonwebtoken::errors::Error> {
+    let claims = Claims::new(user_id, now);
     encode(
         &Header::default(),
         &claims,
@@ -49,10 +159,20 @@ pub fn create_token(secret: &[u8], user_id: Uuid) -> Result<String, jsonwebtoken
     )
 }
 
-pub fn decode_token(secret: &[u8], token: &str) -> Result<Claims, jsonwebtoken::errors::Error> {
+/// The claims of `token` if it is signed with `secret` and not expired at `now` (with the
+/// library's default leeway).
+pub fn decode_token(
+    secret: &[u8],
+    token: &str,
+    now: DateTime<Utc>,
+) -> Result<Claims, jsonwebtoken::errors::Error> {
+    // Expiry is checked against `now` rather than the system time the library would use
     let mut validation = Validation::default();
-    validation.validate_exp = true;
+    validation.validate_exp = false;
     let token_data = decode::<Claims>(token, &DecodingKey::from_secret(secret), &validation)?;
+    if token_data.claims.exp.saturating_add(validation.leeway as i64) < now.timestamp() {
+        return Err(jsonwebtoken::errors::ErrorKind::ExpiredSignature.into());
+    }
     Ok(token_data.claims)
 }
 
@@ -0,0 +1,94 @@
+//! Serialized `GET /book` responses, cached per (symbol, depth) together with the book
+//! sequence they were rendered at. Every book mutation bumps the sequence, so a stale entry is
+//! never served: a lookup at a newer sequence misses and the fresh body replaces it.
+//!
+//! Sequences restart when the process does, so ETags also carry a per-process epoch.
+
+use axum::body::Bytes;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use uuid::Uuid;
+
+/// Most (symbol, depth) combinations kept; further combinations are rendered uncached.
+const DEFAULT_CAPACITY: usize = 256;
+
+type CacheKey = (String, Option<usize>);
+
+pub struct BookCache {
+    epoch: Uuid,
+    entries: Mutex<HashMap<CacheKey, (u64, Bytes)>>,
+    capacity: usize,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct BookCacheStats {
+    pub hits: u64,
+    pub misses: u64,
+}
+
+impl Default for BookCache {
+    fn default() -> Self {
+        Self::new(DEFAULT_CAPACITY)
+    }
+}
+
+impl BookCache {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            epoch: Uuid::new_v4(),
+            entries: Mutex::new(HashMap::new()),
+            capacity,
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    /// Strong ETag for one view of a book at `seq`.
+    pub fn etag(&self, symbol: &str, depth: Option<usize>, seq: u64) -> String {
+        let depth = depth.map_or_else(|| "all".to_string(), |d| d.to_string());
+        format!("\"{}-{}-{}-{}\"", self.epoch.simple(), symbol, depth, seq)
+    }
+
+    /// Body rendered for `symbol`/`depth` at exactly `seq`, if cached.
+    pub fn get(&self, symbol: &str, depth: Option<usize>, seq: u64) -> Option<Bytes> {
+        let entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        let body = entries
+            .get(&(symbol.to_string(), depth))
+            .filter(|(cached_seq, _)| *cached_seq == seq)
+            .map(|(_, body)| body.clone());
+        let counter = if body.is_some() {
+            &self.hits
+        } else {
+            &self.misses
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+        body
+    }
+
+    /// Store the body rendered at `seq`, replacing any older rendering of the same view.
+    pub fn insert(&self, symbol: &str, depth: Option<usize>, seq: u64, body: Bytes) {
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        let key = (symbol.to_string(), depth);
+        if entries.len() >= self.capacity && !entries.contains_key(&key) {
+            return;
+        }
+        match entries.get(&key) {
+            // A slower request may finish after a newer rendering was stored
+            Some((cached_seq, _)) if *cached_seq > seq => {}
+            _ => {
+                entries.insert(key, (seq, body));
+            }
+        }
+    }
+
+    pub fn stats(&self) -> BookCacheStats {
+        BookCacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+        }
+    }
+}
@@ -0,0 +1,123 @@
+//! Gap recovery for the order book diff stream (see `orderbook::diff_buffer`). A client that
+//! applied diffs up to some sequence and then missed some asks for the ones after it, through
+//! `GET /book/diffs` or the WebSocket `get_diffs` action, and gets them in order while the
+//! book's buffer still reaches back that far. Otherwise the answer is `snapshot_required` with
+//! the oldest sequence the buffer can replay from: the client rebuilds from `GET /books` and
+//! follows the diffs after that snapshot's `seq`.
+//!
+//! REST replies are cut at `limit`; `next_cursor` is a signed cursor (see `pagination`) for
+//! the diffs after the last one returned, accepted for the same symbol only.
+
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::Json,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::api::dto::BookDiffDto;
+use crate::api::pagination;
+use crate::api::routes::{AppState, ErrorResponse, get_orderbook};
+use crate::orderbook::orderbook::SharedOrderBook;
+use crate::types::symbol::Symbol;
+
+/// Diffs per `GET /book/diffs` reply when `limit` is not given.
+const DEFAULT_LIMIT: usize = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DiffsStatus {
+    /// `diffs` bring a book at `from_seq` up to `seq` (or as far as `limit` allowed).
+    Replayed,
+    /// The buffer no longer reaches back to `from_seq`; take a snapshot instead.
+    SnapshotRequired,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BookDiffsResponse {
+    pub symbol: String,
+    pub status: DiffsStatus,
+    pub diffs: Vec<BookDiffDto>,
+    /// Sequence of the last published diff.
+    pub seq: u64,
+    /// Lowest `from_seq` the buffer can replay.
+    pub oldest_seq: u64,
+    /// Pass back as `cursor` for the diffs after the last one here, when `limit` cut them short.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_cursor: Option<String>,
+}
+
+/// Cursors of one symbol's diffs are not accepted for another's.
+fn cursor_scope(symbol: &Symbol) -> String {
+    format!("book/diffs:{}", symbol)
+}
+
+/// The buffered diffs of `orderbook` after `from_seq`, at most `limit` of them.
+pub async fn replay(
+    state: &AppState,
+    symbol: &Symbol,
+    orderbook: &SharedOrderBook,
+    from_seq: u64,
+    limit: Option<usize>,
+) -> BookDiffsResponse {
+    let (replayed, seq, oldest_seq) = {
+        let book = orderbook.read().await;
+        let buffer = book.diff_buffer();
+        (buffer.since(from_seq), buffer.seq(), buffer.oldest_seq())
+    };
+    let (status, mut diffs) = match replayed {
+        Ok(diffs) => (DiffsStatus::Replayed, diffs),
+        Err(_) => (DiffsStatus::SnapshotRequired, Vec::new()),
+    };
+    let mut next_cursor = None;
+    if let Some(limit) = limit.filter(|&limit| diffs.len() > limit) {
+        diffs.truncate(limit);
+        next_cursor = diffs.last().map(|last| {
+            pagination::encode_cursor(&state.jwt_secret, &cursor_scope(symbol), &last.seq)
+        });
+    }
+    BookDiffsResponse {
+        symbol: symbol.to_string(),
+        status,
+        diffs: diffs.into_iter().map(BookDiffDto::from).collect(),
+        seq,
+        oldest_seq,
+        next_cursor,
+    }
+}
+
+#[derive(Deserialize)]
+pub struct BookDiffsQuery {
+    symbol: Symbol,
+    /// Sequence of the last diff (or snapshot) the client applied.
+    from_seq: Option<u64>,
+    /// `next_cursor` of the previous reply, in place of `from_seq`.
+    cursor: Option<String>,
+    limit: Option<usize>,
+}
+
+/// GET /book/diffs?symbol=&from_seq=: the diffs a client at `from_seq` missed, or
+/// `snapshot_required` when they are no longer buffered.
+pub async fn get_book_diffs(
+    State(state): State<AppState>,
+    Query(params): Query<BookDiffsQuery>,
+) -> Result<Json<BookDiffsResponse>, (StatusCode, Json<ErrorResponse>)> {
+    let from_seq = match (params.cursor.as_deref(), params.from_seq) {
+        (Some(cursor), _) => {
+            pagination::decode_cursor(&state.jwt_secret, &cursor_scope(&params.symbol), cursor)
+                .map_err(ErrorResponse::invalid_cursor)?
+        }
+        (None, Some(from_seq)) => from_seq,
+        (None, None) => {
+            return Err(ErrorResponse::new(
+                "from_seq or cursor is required".to_string(),
+                StatusCode::BAD_REQUEST,
+            ));
+        }
+    };
+    let orderbook = get_orderbook(&state, &params.symbol).await?;
+    let limit = params.limit.unwrap_or(DEFAULT_LIMIT).max(1);
+    Ok(Json(
+        replay(&state, &params.symbol, &orderbook, from_seq, Some(limit)).await,
+    ))
+}
@@ -0,0 +1,207 @@
+//! Per-request deadline for order entry.
+//!
+//! Every order request gets a budget: `AppState::request_timeout`, or less if the client sends
+//! `X-Request-Timeout-Ms` (the header can only shorten it). Book write locks are acquired
+//! against that deadline, so a request queued behind a wedged book fails with 503
+//! `ENGINE_BUSY` instead of running long after its client gave up. Lock waits are recorded
+//! per symbol and exported with `/admin/metrics`.
+
+use axum::{Json, extract::FromRequestParts, http::StatusCode, http::request::Parts};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tokio::sync::{RwLock, RwLockWriteGuard};
+
+use crate::api::ingress::Ticket;
+use crate::api::routes::{AppState, ErrorResponse};
+use crate::orderbook::orderbook::OrderBook;
+
+pub const REQUEST_TIMEOUT_HEADER: &str = "x-request-timeout-ms";
+
+/// Budget used when `REQUEST_TIMEOUT_MS` is not configured; also the cap on the header.
+pub const DEFAULT_REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Upper bounds (µs) of the lock wait histogram buckets; a final bucket counts everything slower.
+pub const LOCK_WAIT_BUCKETS_US: [u64; 8] = [
+    100, 1_000, 5_000, 10_000, 50_000, 100_000, 500_000, 1_000_000,
+];
+
+#[derive(Debug, Clone, Copy)]
+pub struct RequestDeadline {
+    at: Instant,
+}
+
+impl RequestDeadline {
+    pub fn after(timeout: Duration) -> Self {
+        Self {
+            at: Instant::now() + timeout,
+        }
+    }
+
+    pub fn remaining(&self) -> Duration {
+        self.at.saturating_duration_since(Instant::now())
+    }
+
+    /// 503 `ENGINE_BUSY` once the deadline has passed.
+    pub fn check(&self) -> Result<(), (StatusCode, Json<ErrorResponse>)> {
+        if self.remaining().is_zero() {
+            return Err(ErrorResponse::engine_busy());
+        }
+        Ok(())
+    }
+}
+
+impl FromRequestParts<AppState> for RequestDeadline {
+    type Rejection = (StatusCode, Json<ErrorResponse>);
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &AppState,
+    ) -> Result<Self, Self::Rejection> {
+        let Some(value) = parts.headers.get(REQUEST_TIMEOUT_HEADER) else {
+            return Ok(Self::after(state.request_timeout));
+        };
+        let ms = value
+            .to_str()
+            .ok()
+            .and_then(|v| v.trim().parse::<u64>().ok())
+            .filter(|&ms| ms > 0)
+            .ok_or_else(|| {
+                ErrorResponse::new(
+                    "X-Request-Timeout-Ms must be a positive number of milliseconds".to_string(),
+                    StatusCode::BAD_REQUEST,
+                )
+            })?;
+        Ok(Self::after(
+            Duration::from_millis(ms).min(state.request_timeout),
+        ))
+    }
+}
+
+/// Take `book`'s write lock after every request admitted before `ticket`, or 503 `ENGINE_BUSY`
+/// if that does not happen before `deadline`.
+pub async fn lock_book<'a>(
+    state: &AppState,
+    symbol: &str,
+    book: &'a RwLock<OrderBook>,
+    ticket: Ticket,
+    deadline: &RequestDeadline,
+) -> Result<RwLockWriteGuard<'a, OrderBook>, (StatusCode, Json<ErrorResponse>)> {
+    let start = Instant::now();
+    // The ticket is released once the lock is held (or the wait gives up), so the next
+    // request queues for the lock behind this one
+    let guard = tokio::time::timeout(deadline.remaining(), async move {
+        ticket.turn().await;
+        book.write().await
+    })
+    .await;
+    state
+        .lock_waits
+        .record(symbol, start.elapsed(), guard.is_ok());
+    guard.map_err(|_| {
+        eprintln!(
+            "engine busy: {} book lock not acquired within {}ms",
+            symbol,
+            start.elapsed().as_millis()
+        );
+        ErrorResponse::engine_busy()
+    })
+}
+
+/// Cumulative lock waits for one book; timed-out waits count in both.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LockWaitHistogram {
+    pub count: u64,
+    pub sum_us: u64,
+    /// Counts per `LOCK_WAIT_BUCKETS_US` bucket (not cumulative), plus one overflow bucket.
+    pub buckets: Vec<u64>,
+    pub timeouts: u64,
+}
+
+#[derive(Debug, Default)]
+pub struct LockWaitMetrics {
+    books: Mutex<BTreeMap<String, LockWaitHistogram>>,
+}
+
+impl LockWaitMetrics {
+    pub fn record(&self, symbol: &str, waited: Duration, acquired: bool) {
+        let us = waited.as_micros() as u64;
+        let mut books = self.books.lock().unwrap_or_else(|e| e.into_inner());
+        let histogram = books.entry(symbol.to_string()).or_default();
+        if histogram.buckets.is_empty() {
+            histogram.buckets = vec![0; LOCK_WAIT_BUCKETS_US.len() + 1];
+        }
+        let bucket = LOCK_WAIT_BUCKETS_US
+            .iter()
+            .position(|&bound| us < bound)
+            .unwrap_or(LOCK_WAIT_BUCKETS_US.len());
+        histogram.buckets[bucket] += 1;
+        histogram.count += 1;
+        histogram.sum_us += us;
+        if !acquired {
+            histogram.timeouts += 1;
+        }
+    }
+
+    pub fn snapshot(&self) -> BTreeMap<String, LockWaitHistogram> {
+        self.books.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    /// Prometheus text exposition of the lock wait histograms and timeout counters.
+    pub fn render_prometheus(&self) -> String {
+        let books = self.snapshot();
+        let mut out = String::new();
+        let name = "exchange_book_lock_wait_seconds";
+        let _ = writeln!(
+            out,
+            "# HELP {} Time order requests waited for the book write lock.\n# TYPE {} histogram",
+            name, name
+        );
+        for (symbol, histogram) in &books {
+            let mut cumulative = 0;
+            for (i, bound) in LOCK_WAIT_BUCKETS_US.iter().enumerate() {
+                cumulative += histogram.buckets.get(i).copied().unwrap_or(0);
+                let _ = writeln!(
+                    out,
+                    "{}_bucket{{symbol=\"{}\",le=\"{}\"}} {}",
+                    name,
+                    symbol,
+                    *bound as f64 / 1_000_000.0,
+                    cumulative
+                );
+            }
+            let _ = writeln!(
+                out,
+                "{}_bucket{{symbol=\"{}\",le=\"+Inf\"}} {}",
+                name, symbol, histogram.count
+            );
+            let _ = writeln!(
+                out,
+                "{}_sum{{symbol=\"{}\"}} {}",
+                name,
+                symbol,
+                histogram.sum_us as f64 / 1_000_000.0
+            );
+            let _ = writeln!(
+                out,
+                "{}_count{{symbol=\"{}\"}} {}",
+                name, symbol, histogram.count
+            );
+        }
+
+        let _ = writeln!(
+            out,
+            "# HELP exchange_engine_busy_total Order requests rejected because the book lock was not acquired before their deadline.\n\
+             # TYPE exchange_engine_busy_total counter"
+        );
+        for (symbol, histogram) in &books {
+            let _ = writeln!(
+                out,
+                "exchange_engine_busy_total{{symbol=\"{}\"}} {}",
+                symbol, histogram.timeouts
+            );
+        }
+        out
+    }
+}
@@ -0,0 +1,393 @@
+//! Symbol delisting: `POST /admin/symbols/{symbol}/delist` moves a symbol to `CancelOnly`
+//! for a grace period, during which its resting orders can still be cancelled but no new
+//! ones are accepted. Subscribers get a `SymbolStatus` message, and users with resting orders
+//! or a position in the symbol a `SymbolStatus` webhook event.
+//!
+//! At the deadline the finalizer cancels every order left on the book with reason
+//! `SYMBOL_DELISTED` (owners get `OrderClosed`), closes open positions at the last trade
+//! price or leaves them frozen, per the policy given when the delisting started, and marks the
+//! symbol `Delisted`. The stored status keeps it out of hydration from then on; until the next
+//! restart its (empty) book stays in `AppState::orderbooks` but every lookup treats the symbol
+//! as unknown. Deadlines of pending delistings are rebuilt from the stored configs on startup,
+//! so a delisting whose deadline passed while the server was down completes right away.
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::Json,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::Notify;
+use uuid::Uuid;
+
+use crate::api::auth::AdminUser;
+use crate::api::exchange_info::RuleChangeSource;
+use crate::api::lazy_books;
+use crate::api::routes::{AppState, ErrorResponse, SymbolConfigResponse, get_orderbook};
+use crate::api::service;
+use crate::api::ws;
+use crate::persistence;
+use crate::positions::{self, PositionDelta};
+use crate::tasks::{RestartPolicy, ShutdownStage};
+use crate::types::order::{CloseReason, OrderSide};
+use crate::types::symbol::{Delisting, PositionSettlement, Symbol, SymbolConfig, SymbolStatus};
+use crate::webhooks::{SymbolStatusEvent, WebhookEvent};
+
+/// Grace period when the request does not give one: a day.
+pub const DEFAULT_GRACE: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// The finalizer checks for new deadlines at least this often, even with none pending.
+const MAX_FINALIZE_WAIT: Duration = Duration::from_secs(1);
+
+pub type SharedDelistings = Arc<Delistings>;
+
+#[derive(Debug, Default)]
+struct Registry {
+    /// Deadline per symbol in `CancelOnly`.
+    pending: HashMap<String, DateTime<Utc>>,
+    delisted: HashSet<String>,
+}
+
+/// Pending and completed delistings since startup.
+#[derive(Debug, Default)]
+pub struct Delistings {
+    registry: Mutex<Registry>,
+    /// Wakes the finalizer when a delisting is scheduled.
+    scheduled: Notify,
+}
+
+impl Delistings {
+    /// Pick up the delistings recorded in `configs`: pending ones are scheduled again,
+    /// completed ones stay delisted.
+    pub fn from_configs(configs: &HashMap<String, SymbolConfig>) -> Self {
+        let delistings = Self::default();
+        for (symbol, config) in configs {
+            match (config.status, config.delisting) {
+                (SymbolStatus::CancelOnly, Some(delisting)) => {
+                    delistings.schedule(symbol, delisting.at)
+                }
+                (SymbolStatus::Delisted, _) => delistings.mark_delisted(symbol),
+                _ => {}
+            }
+        }
+        delistings
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Registry> {
+        self.registry.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Delist `symbol` at `at`.
+    pub fn schedule(&self, symbol: &str, at: DateTime<Utc>) {
+        self.lock().pending.insert(symbol.to_string(), at);
+        self.scheduled.notify_one();
+    }
+
+    /// Whether `symbol` was delisted; it is then unknown to every endpoint.
+    pub fn is_delisted(&self, symbol: &str) -> bool {
+        self.lock().delisted.contains(symbol)
+    }
+
+    fn mark_delisted(&self, symbol: &str) {
+        let mut registry = self.lock();
+        registry.pending.remove(symbol);
+        registry.delisted.insert(symbol.to_string());
+    }
+
+    /// Earliest pending deadline.
+    pub fn next_deadline(&self) -> Option<DateTime<Utc>> {
+        self.lock().pending.values().min().copied()
+    }
+
+    /// Unschedule and return the symbols whose deadline is at or before `now`.
+    fn take_due(&self, now: DateTime<Utc>) -> BTreeSet<String> {
+        let mut registry = self.lock();
+        let due: BTreeSet<String> = registry
+            .pending
+            .iter()
+            .filter(|(_, at)| **at <= now)
+            .map(|(symbol, _)| symbol.clone())
+            .collect();
+        for symbol in &due {
+            registry.pending.remove(symbol);
+        }
+        due
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DelistRequest {
+    /// Seconds in `CancelOnly` before the delisting completes; `DEFAULT_GRACE` when omitted.
+    #[serde(default)]
+    pub grace_secs: Option<u64>,
+    #[serde(default)]
+    pub positions: PositionSettlement,
+}
+
+/// POST /admin/symbols/{symbol}/delist: stop accepting orders on the symbol and delist it once
+/// the grace period is over. 409 if a delisting already started.
+pub async fn delist_symbol(
+    admin: AdminUser,
+    State(state): State<AppState>,
+    Path(normalized_symbol): Path<Symbol>,
+    Json(request): Json<DelistRequest>,
+) -> Result<Json<SymbolConfigResponse>, (StatusCode, Json<ErrorResponse>)> {
+    let grace = request
+        .grace_secs
+        .map_or(DEFAULT_GRACE, Duration::from_secs);
+    let at = state.clock.now() + grace;
+    let orderbook = get_orderbook(&state, &normalized_symbol).await?;
+    // Book first: no order gets in between the check and the status change
+    let book = orderbook.write().await;
+    let mut configs = state.symbol_configs.write().await;
+    let previous = configs
+        .get(normalized_symbol.as_str())
+        .copied()
+        .unwrap_or_else(|| SymbolConfig::for_symbol(&normalized_symbol));
+    if previous.status != SymbolStatus::Trading {
+        return Err(ErrorResponse::new(
+            format!("Symbol '{}' is already being delisted", normalized_symbol),
+            StatusCode::CONFLICT,
+        ));
+    }
+    let config = SymbolConfig {
+        status: SymbolStatus::CancelOnly,
+        delisting: Some(Delisting {
+            at,
+            positions: request.positions,
+        }),
+        ..previous
+    };
+    let change = state.rules.prepare(
+        &normalized_symbol,
+        &previous,
+        &config,
+        RuleChangeSource::DelistingStarted,
+        Some(admin.user_id),
+        state.clock.now(),
+    );
+    if let Some(ref db) = state.db {
+        let saved: Result<(), sqlx::Error> = async {
+            let mut tx = db.begin().await?;
+            persistence::upsert_symbol_config(&mut *tx, &normalized_symbol, &config).await?;
+            if let Some(ref change) = change {
+                persistence::insert_rule_change(&mut *tx, &change.to_row()).await?;
+            }
+            tx.commit().await
+        }
+        .await;
+        saved.map_err(|e| ErrorResponse::from_db("Failed to save symbol config", e))?;
+    }
+    if let Some(change) = change {
+        state.rules.commit(change);
+    }
+    eprintln!(
+        "symbol {} delisting by {} at {} (positions: {})",
+        normalized_symbol,
+        admin.user_id,
+        at,
+        request.positions.as_str()
+    );
+    configs.insert(normalized_symbol.to_string(), config);
+    drop(configs);
+    let mut affected: BTreeSet<Uuid> = book.iter_orders().map(|(_, order)| order.user_id).collect();
+    drop(book);
+    affected.extend(
+        positions::get_positions_by_symbol(&state.positions, &normalized_symbol)
+            .await
+            .into_iter()
+            .map(|position| position.user_id),
+    );
+
+    notify_status(&state, &normalized_symbol, &config, affected);
+    state.delistings.schedule(&normalized_symbol, at);
+    Ok(Json(SymbolConfigResponse::new(normalized_symbol, config)))
+}
+
+/// `symbol`'s new status to its subscribers, and to the webhooks of `users`.
+fn notify_status(
+    state: &AppState,
+    symbol: &str,
+    config: &SymbolConfig,
+    users: impl IntoIterator<Item = Uuid>,
+) {
+    ws::broadcast_symbol_status(&state.ws_channel, symbol, config);
+    let event = SymbolStatusEvent {
+        symbol: symbol.to_string(),
+        status: config.status,
+        delist_at: config.delisting.map(|delisting| delisting.at),
+    };
+    for user_id in users {
+        state
+            .webhooks
+            .notify(user_id, WebhookEvent::SymbolStatus(event.clone()));
+    }
+}
+
+/// What completing a delisting did.
+#[derive(Debug, Clone, Serialize)]
+pub struct DelistedSymbol {
+    pub symbol: String,
+    pub orders_cancelled: usize,
+    /// Closed positions' owners and the PnL each realized; empty when positions are frozen.
+    pub positions_closed: BTreeMap<Uuid, i64>,
+    pub positions_frozen: usize,
+}
+
+/// Complete every delisting whose deadline is at or before `now`.
+pub async fn finalize_due(state: &AppState, now: DateTime<Utc>) -> Vec<DelistedSymbol> {
+    let mut delisted = Vec::new();
+    for symbol in state.delistings.take_due(now) {
+        if let Some(outcome) = finalize(state, &symbol, now).await {
+            delisted.push(outcome);
+        }
+    }
+    delisted
+}
+
+/// Cancel `symbol`'s orders, settle its positions and mark it delisted. None if it is not in
+/// `CancelOnly`, or is lazy and its book could not be loaded.
+async fn finalize(state: &AppState, symbol: &str, now: DateTime<Utc>) -> Option<DelistedSymbol> {
+    let orderbook = state.orderbooks.get(symbol)?.clone();
+    let normalized_symbol = Symbol::parse(symbol).ok()?;
+    // Every resting order is cancelled, so a lazy book's must be restored first
+    if !lazy_books::ensure_loaded(state, symbol).await {
+        return None;
+    }
+    let mut book = orderbook.write().await;
+    let previous = service::symbol_config(state, symbol).await;
+    let (SymbolStatus::CancelOnly, Some(delisting)) = (previous.status, previous.delisting) else {
+        return None;
+    };
+
+    let order_ids: Vec<_> = book.iter_orders().map(|(_, order)| order.id).collect();
+    let cancelled = service::close_orders(
+        state,
+        symbol,
+        &mut book,
+        order_ids,
+        CloseReason::SymbolDelisted,
+    )
+    .await;
+    let mut affected: BTreeSet<Uuid> = cancelled.iter().map(|c| c.order.user_id).collect();
+
+    let open = positions::get_positions_by_symbol(&state.positions, &normalized_symbol).await;
+    affected.extend(open.iter().map(|position| position.user_id));
+    let last_price = book.last_trade_price();
+    let mut positions_closed = BTreeMap::new();
+    let mut deltas: Vec<PositionDelta> = Vec::new();
+    if delisting.positions == PositionSettlement::Close {
+        for position in &open {
+            let price = last_price.unwrap_or(position.average_price);
+            let side = if position.quantity > 0 {
+                OrderSide::Sell
+            } else {
+                OrderSide::Buy
+            };
+            let quantity = position.quantity.unsigned_abs();
+            let realized = positions::realized_pnl(Some(position), side, price, quantity);
+            positions_closed.insert(position.user_id, realized);
+            deltas.push(
+                positions::update_position(
+                    &state.positions,
+                    position.user_id,
+                    &normalized_symbol,
+                    side,
+                    price,
+                    quantity,
+                    now,
+                )
+                .await,
+            );
+        }
+    }
+
+    let config = SymbolConfig {
+        status: SymbolStatus::Delisted,
+        ..previous
+    };
+    let mut configs = state.symbol_configs.write().await;
+    let change = state.rules.prepare(
+        symbol,
+        &previous,
+        &config,
+        RuleChangeSource::Delisted,
+        None,
+        now,
+    );
+    if let Some(ref db) = state.db {
+        // Memory moves on regardless; a failure here leaves positions for /admin/reconcile
+        let saved: Result<(), sqlx::Error> = async {
+            let mut tx = db.begin().await?;
+            for delta in &deltas {
+                persistence::delete_position(&mut *tx, delta.user_id, symbol).await?;
+            }
+            persistence::upsert_symbol_config(&mut *tx, symbol, &config).await?;
+            if let Some(ref change) = change {
+                persistence::insert_rule_change(&mut *tx, &change.to_row()).await?;
+            }
+            tx.commit().await
+        }
+        .await;
+        if let Err(e) = saved {
+            eprintln!("failed to save delisting of {}: {}", symbol, e);
+        }
+    }
+    if let Some(change) = change {
+        state.rules.commit(change);
+    }
+    configs.insert(symbol.to_string(), config);
+    drop(configs);
+    state.delistings.mark_delisted(symbol);
+    drop(book);
+
+    ws::broadcast_position_updates(&state.ws_channel, &deltas, last_price, config.qty_scale);
+    notify_status(state, symbol, &config, affected);
+    let outcome = DelistedSymbol {
+        symbol: symbol.to_string(),
+        orders_cancelled: cancelled.len(),
+        positions_frozen: open.len() - positions_closed.len(),
+        positions_closed,
+    };
+    eprintln!(
+        "symbol {} delisted: {} orders cancelled, {} positions closed, {} frozen",
+        symbol,
+        outcome.orders_cancelled,
+        outcome.positions_closed.len(),
+        outcome.positions_frozen
+    );
+    Some(outcome)
+}
+
+/// Complete delistings as their deadlines pass on `state.clock`.
+pub fn spawn_finalizer(state: AppState) {
+    let tasks = state.tasks.clone();
+    tasks.spawn(
+        "delisting_finalizer",
+        ShutdownStage::Worker,
+        RestartPolicy::default(),
+        move |token| {
+            let state = state.clone();
+            async move {
+                loop {
+                    let latest = state.clock.now() + MAX_FINALIZE_WAIT;
+                    let wake_at = state
+                        .delistings
+                        .next_deadline()
+                        .map_or(latest, |deadline| deadline.min(latest));
+                    tokio::select! {
+                        _ = token.cancelled() => return,
+                        _ = state.clock.sleep_until(wake_at) => {}
+                        _ = state.delistings.scheduled.notified() => {}
+                    }
+                    finalize_due(&state, state.clock.now()).await;
+                }
+            }
+        },
+    );
+}
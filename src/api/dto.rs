@@ -0,0 +1,336 @@
+//! Wire shapes of the engine types. Handlers and WebSocket messages serialize these, never
+//! `Order`, `Trade` or `Position` themselves, so the API can change how a field is written
+//! without touching matching or storage; the engine types' own serde derives are only the
+//! snapshot and audit-record format. Each DTO converts `From` its engine type and keeps the
+//! field names it has always had on the wire.
+//!
+//! Integers a JavaScript client cannot hold exactly are written with `string_i64` here.
+//! Responses about one symbol carry its `ScaleMeta` as `meta`, so their prices and quantities
+//! can be read without another request.
+//! Input goes the other way: request bodies (`CreateOrderRequest` and friends) are validated
+//! into `service::NewOrder` before anything reaches the book.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize, Serializer};
+use uuid::Uuid;
+
+use crate::api::routes::ReplaceOrderResponse;
+use crate::api::service::CancelledOrder;
+use crate::orderbook::diff_buffer::BookDiff;
+use crate::orderbook::orderbook::{BookSnapshot, ExecutionReport};
+use crate::types::order::{
+    CloseReason, Order, OrderSide, OrderSource, OrderStatus, OrderTags, OrderType, Price, Qty,
+    RejectReason,
+};
+use crate::types::position::Position;
+use crate::types::symbol::{ScaleMeta, SymbolConfig};
+use crate::types::trade::{OrderFill, Trade, TradeRole, UserTrade};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OrderDto {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub side: OrderSide,
+    pub order_type: OrderType,
+    #[serde(with = "crate::types::string_i64")]
+    pub price: Price,
+    #[serde(with = "crate::types::string_i64")]
+    pub quantity: Qty,
+    pub status: OrderStatus,
+    pub timestamp: DateTime<Utc>,
+    #[serde(default)]
+    pub tags: OrderTags,
+    #[serde(default)]
+    pub source: OrderSource,
+}
+
+impl From<Order> for OrderDto {
+    fn from(order: Order) -> Self {
+        Self {
+            id: order.id,
+            user_id: order.user_id,
+            side: order.side,
+            order_type: order.order_type,
+            price: order.price,
+            quantity: order.quantity,
+            status: order.status,
+            timestamp: order.timestamp,
+            tags: order.tags,
+            source: order.source,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TradeDto {
+    pub id: Uuid,
+    pub trade_seq: u64,
+    pub maker_order_id: Uuid,
+    pub taker_order_id: Uuid,
+    pub maker_user_id: Uuid,
+    pub taker_user_id: Uuid,
+    #[serde(with = "crate::types::string_i64")]
+    pub price: Price,
+    #[serde(with = "crate::types::string_i64")]
+    pub quantity: Qty,
+    pub timestamp: DateTime<Utc>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub maker_queue_rank: Option<u32>,
+}
+
+impl From<Trade> for TradeDto {
+    fn from(trade: Trade) -> Self {
+        Self {
+            id: trade.id,
+            trade_seq: trade.trade_seq,
+            maker_order_id: trade.maker_order_id,
+            taker_order_id: trade.taker_order_id,
+            maker_user_id: trade.maker_user_id,
+            taker_user_id: trade.taker_user_id,
+            price: trade.price,
+            quantity: trade.quantity,
+            timestamp: trade.timestamp,
+            maker_queue_rank: trade.maker_queue_rank,
+        }
+    }
+}
+
+/// A trade as one of its counterparties sees it (GET /trades/me).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UserTradeDto {
+    #[serde(flatten)]
+    pub trade: TradeDto,
+    pub role: TradeRole,
+    pub side: Option<OrderSide>,
+    #[serde(with = "crate::types::string_i64")]
+    pub fee: i64,
+    #[serde(with = "crate::types::string_i64")]
+    pub realized_pnl: i64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub busted_at: Option<DateTime<Utc>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bust_reason: Option<String>,
+}
+
+impl From<UserTrade> for UserTradeDto {
+    fn from(trade: UserTrade) -> Self {
+        Self {
+            trade: trade.trade.into(),
+            role: trade.role,
+            side: trade.side,
+            fee: trade.fee,
+            realized_pnl: trade.realized_pnl,
+            busted_at: trade.busted_at,
+            bust_reason: trade.bust_reason,
+        }
+    }
+}
+
+/// One fill of an order (GET /orders/{id}/fills): a trade seen from that order's side.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OrderFillDto {
+    pub trade_id: Uuid,
+    pub trade_seq: u64,
+    #[serde(with = "crate::types::string_i64")]
+    pub price: Price,
+    #[serde(with = "crate::types::string_i64")]
+    pub quantity: Qty,
+    #[serde(with = "crate::types::string_i64")]
+    pub fee: i64,
+    pub role: TradeRole,
+    pub timestamp: DateTime<Utc>,
+}
+
+impl From<OrderFill> for OrderFillDto {
+    fn from(fill: OrderFill) -> Self {
+        Self {
+            trade_id: fill.trade.id,
+            trade_seq: fill.trade.trade_seq,
+            price: fill.trade.price,
+            quantity: fill.trade.quantity,
+            fee: fill.fee,
+            role: fill.role,
+            timestamp: fill.trade.timestamp,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PositionDto {
+    pub user_id: Uuid,
+    pub symbol: String,
+    #[serde(with = "crate::types::string_i64")]
+    pub quantity: i64,
+    #[serde(with = "crate::types::string_i64")]
+    pub average_price: Price,
+    pub opened_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl From<Position> for PositionDto {
+    fn from(position: Position) -> Self {
+        Self {
+            user_id: position.user_id,
+            symbol: position.symbol,
+            quantity: position.quantity,
+            average_price: position.average_price,
+            opened_at: position.opened_at,
+            updated_at: position.updated_at,
+        }
+    }
+}
+
+/// One aggregated price level, written as a `[price, quantity]` pair.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BookLevelDto(pub Price, pub Qty);
+
+impl From<(Price, Qty)> for BookLevelDto {
+    fn from((price, quantity): (Price, Qty)) -> Self {
+        Self(price, quantity)
+    }
+}
+
+/// `levels` as DTOs, in the same order.
+pub fn book_levels(levels: Vec<(Price, Qty)>) -> Vec<BookLevelDto> {
+    levels.into_iter().map(BookLevelDto::from).collect()
+}
+
+/// Aggregated levels at a book sequence (`GET /books`, sandbox books).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BookSnapshotDto {
+    pub seq: u64,
+    pub captured_at: DateTime<Utc>,
+    pub bids: Vec<BookLevelDto>,
+    pub asks: Vec<BookLevelDto>,
+    pub is_empty: bool,
+    /// `book_checksum` of every level, whatever the depth.
+    pub checksum: u32,
+}
+
+impl From<BookSnapshot> for BookSnapshotDto {
+    fn from(snapshot: BookSnapshot) -> Self {
+        Self {
+            seq: snapshot.seq,
+            captured_at: snapshot.captured_at,
+            bids: book_levels(snapshot.bids),
+            asks: book_levels(snapshot.asks),
+            is_empty: snapshot.is_empty,
+            checksum: snapshot.checksum,
+        }
+    }
+}
+
+/// A change to a book's levels (`OrderBookDiff` messages, `GET /book/diffs`): the new quantity
+/// of each changed level, 0 for a level that is gone.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BookDiffDto {
+    pub seq: u64,
+    pub prev_seq: u64,
+    pub bids: Vec<BookLevelDto>,
+    pub asks: Vec<BookLevelDto>,
+    pub checksum: u32,
+}
+
+impl From<BookDiff> for BookDiffDto {
+    fn from(diff: BookDiff) -> Self {
+        Self {
+            seq: diff.seq,
+            prev_seq: diff.prev_seq,
+            bids: book_levels(diff.bids),
+            asks: book_levels(diff.asks),
+            checksum: diff.checksum,
+        }
+    }
+}
+
+/// `#[serde(serialize_with)]` for `(price, quantity)` levels inside internal messages.
+pub fn serialize_levels<S: Serializer>(
+    levels: &[(Price, Qty)],
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.collect_seq(levels.iter().copied().map(BookLevelDto::from))
+}
+
+/// `#[serde(serialize_with = "crate::api::dto::serialize_as::<TradeDto, _, _>")]` for an
+/// engine type inside an internal message (`WsMessage` is also the in-process event bus, so
+/// it carries engine types): the field is written as its DTO.
+pub fn serialize_as<D, T, S>(value: &T, serializer: S) -> Result<S::Ok, S::Error>
+where
+    T: Clone + Into<D>,
+    D: Serialize,
+    S: Serializer,
+{
+    Into::<D>::into(value.clone()).serialize(serializer)
+}
+
+/// The outcome of an order (`OrderUpdate` messages, `PUT /orders/{id}`).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExecutionReportDto {
+    pub order: OrderDto,
+    pub trades: Vec<TradeDto>,
+    pub rejection: Option<RejectReason>,
+}
+
+impl From<ExecutionReport> for ExecutionReportDto {
+    fn from(report: ExecutionReport) -> Self {
+        Self {
+            order: report.order.into(),
+            trades: report.trades.into_iter().map(TradeDto::from).collect(),
+            rejection: report.rejection,
+        }
+    }
+}
+
+/// An order as it left the book on cancel (see `CancelledOrder`).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CancelledOrderDto {
+    #[serde(flatten)]
+    pub order: OrderDto,
+    pub remaining_quantity: u64,
+    pub closed_at: DateTime<Utc>,
+    pub close_reason: CloseReason,
+}
+
+impl From<CancelledOrder> for CancelledOrderDto {
+    fn from(cancelled: CancelledOrder) -> Self {
+        Self {
+            order: cancelled.order.into(),
+            remaining_quantity: cancelled.remaining_quantity,
+            closed_at: cancelled.closed_at,
+            close_reason: cancelled.close_reason,
+        }
+    }
+}
+
+/// `body` with the meta block of the symbol it is about (see `ScaleMeta`).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct WithMeta<T> {
+    #[serde(flatten)]
+    pub body: T,
+    pub meta: ScaleMeta,
+}
+
+impl<T> WithMeta<T> {
+    pub fn new(body: T, config: &SymbolConfig) -> Self {
+        Self {
+            body,
+            meta: config.meta(),
+        }
+    }
+}
+
+/// `PUT /orders/{id}`: the cancelled order and its replacement's outcome.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReplacedOrderDto {
+    pub cancelled: OrderDto,
+    pub report: ExecutionReportDto,
+}
+
+impl From<ReplaceOrderResponse> for ReplacedOrderDto {
+    fn from(replaced: ReplaceOrderResponse) -> Self {
+        Self {
+            cancelled: replaced.cancelled.into(),
+            report: replaced.report.into(),
+        }
+    }
+}
@@ -0,0 +1,337 @@
+//! A versioned view of every symbol's trading rules for bots that cache them. `GET
+//! /exchangeInfo` returns all rules with a `rules_version`; every change to a symbol's config
+//! (an admin patch, a delisting starting or completing, an incident halt) bumps the version by
+//! one and is recorded as the fields that changed, before and after, which `GET
+//! /exchangeInfo/changes?since_version=` lists. A client that saw version N needs only the
+//! changes after it.
+//!
+//! With a database each change is stored in the transaction that saves the config, and the
+//! version in `exchange_metadata`; the log loads both at startup. Memory keeps the most recent
+//! `DEFAULT_CAPACITY` changes; older ones are read from the database, or are gone without one.
+
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::Json,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::PgPool;
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::{Arc, Mutex, MutexGuard};
+use uuid::Uuid;
+
+use crate::api::routes::{AppState, ErrorResponse, SymbolConfigResponse, listed_orderbooks};
+use crate::persistence::{self, RuleChangeRow};
+use crate::types::symbol::{Symbol, SymbolConfig};
+
+/// Changes kept in memory.
+pub const DEFAULT_CAPACITY: usize = 1000;
+/// Changes returned by one `GET /exchangeInfo/changes`.
+const MAX_CHANGES: usize = 1000;
+
+/// What changed a symbol's rules.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RuleChangeSource {
+    /// `PATCH /admin/symbols/{symbol}`.
+    AdminPatch,
+    /// `POST /admin/symbols/{symbol}/delist` moved it to `CancelOnly`.
+    DelistingStarted,
+    /// The grace period ended and the symbol was delisted.
+    Delisted,
+    /// Matching panicked and the symbol was halted (see `api::incidents`).
+    IncidentHalt,
+}
+
+impl RuleChangeSource {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::AdminPatch => "admin_patch",
+            Self::DelistingStarted => "delisting_started",
+            Self::Delisted => "delisted",
+            Self::IncidentHalt => "incident_halt",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        [
+            Self::AdminPatch,
+            Self::DelistingStarted,
+            Self::Delisted,
+            Self::IncidentHalt,
+        ]
+        .into_iter()
+        .find(|source| source.as_str() == s)
+    }
+}
+
+/// One config field's value before and after a change, as `/symbols` writes it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FieldChange {
+    pub from: Value,
+    pub to: Value,
+}
+
+/// One rules version: the fields of one symbol's config that changed.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RuleChange {
+    pub version: u64,
+    pub symbol: String,
+    pub source: RuleChangeSource,
+    /// The admin who made it; None for changes the exchange made itself.
+    pub changed_by: Option<Uuid>,
+    pub changed_at: DateTime<Utc>,
+    /// By field name.
+    pub changes: BTreeMap<String, FieldChange>,
+}
+
+impl RuleChange {
+    pub fn to_row(&self) -> RuleChangeRow {
+        RuleChangeRow {
+            version: self.version as i64,
+            symbol: self.symbol.clone(),
+            source: self.source.as_str().to_string(),
+            changed_by: self.changed_by,
+            changed_at: self.changed_at,
+            changes: sqlx::types::Json(serde_json::to_value(&self.changes).unwrap_or(Value::Null)),
+        }
+    }
+
+    pub fn from_row(row: RuleChangeRow) -> Option<Self> {
+        Some(Self {
+            version: u64::try_from(row.version).ok()?,
+            source: RuleChangeSource::parse(&row.source)?,
+            changes: serde_json::from_value(row.changes.0).ok()?,
+            symbol: row.symbol,
+            changed_by: row.changed_by,
+            changed_at: row.changed_at,
+        })
+    }
+}
+
+/// The fields that differ between `previous` and `config`, by their serialized names.
+pub fn diff(previous: &SymbolConfig, config: &SymbolConfig) -> BTreeMap<String, FieldChange> {
+    let fields = |config: &SymbolConfig| match serde_json::to_value(config) {
+        Ok(Value::Object(fields)) => fields,
+        _ => Default::default(),
+    };
+    let (before, mut after) = (fields(previous), fields(config));
+    let mut changes = BTreeMap::new();
+    for (name, from) in before {
+        let to = after.remove(&name).unwrap_or(Value::Null);
+        if from != to {
+            changes.insert(name, FieldChange { from, to });
+        }
+    }
+    for (name, to) in after {
+        changes.insert(
+            name,
+            FieldChange {
+                from: Value::Null,
+                to,
+            },
+        );
+    }
+    changes
+}
+
+/// Changes before this version are no longer kept; refetch `/exchangeInfo`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChangesEvicted {
+    pub oldest_available_version: u64,
+}
+
+#[derive(Default)]
+struct Log {
+    version: u64,
+    changes: VecDeque<RuleChange>,
+}
+
+pub type SharedRulesLog = Arc<RulesLog>;
+
+/// The current rules version and the recent changes.
+pub struct RulesLog {
+    log: Mutex<Log>,
+    capacity: usize,
+}
+
+impl Default for RulesLog {
+    fn default() -> Self {
+        Self::new(DEFAULT_CAPACITY)
+    }
+}
+
+impl RulesLog {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            log: Mutex::new(Log::default()),
+            capacity,
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Log> {
+        self.log.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn version(&self) -> u64 {
+        self.lock().version
+    }
+
+    /// The change from `previous` to `config` as the next version, or None when no field
+    /// differs. Nothing is recorded until `commit`; call both with `symbol_configs`
+    /// write-locked, so versions are handed out and recorded in order.
+    pub fn prepare(
+        &self,
+        symbol: &str,
+        previous: &SymbolConfig,
+        config: &SymbolConfig,
+        source: RuleChangeSource,
+        changed_by: Option<Uuid>,
+        changed_at: DateTime<Utc>,
+    ) -> Option<RuleChange> {
+        let changes = diff(previous, config);
+        if changes.is_empty() {
+            return None;
+        }
+        Some(RuleChange {
+            version: self.version() + 1,
+            symbol: symbol.to_string(),
+            source,
+            changed_by,
+            changed_at,
+            changes,
+        })
+    }
+
+    /// Record a prepared change and move to its version.
+    pub fn commit(&self, change: RuleChange) {
+        let mut log = self.lock();
+        log.version = log.version.max(change.version);
+        if self.capacity == 0 {
+            return;
+        }
+        if log.changes.len() == self.capacity {
+            log.changes.pop_front();
+        }
+        log.changes.push_back(change);
+    }
+
+    /// The changes after `version`, oldest first, or where memory starts if it no longer
+    /// holds all of them.
+    pub fn since(&self, version: u64) -> Result<Vec<RuleChange>, ChangesEvicted> {
+        let log = self.lock();
+        let oldest = log
+            .changes
+            .front()
+            .map_or(log.version + 1, |change| change.version);
+        if version + 1 < oldest && version < log.version {
+            return Err(ChangesEvicted {
+                oldest_available_version: oldest,
+            });
+        }
+        Ok(log
+            .changes
+            .iter()
+            .filter(|change| change.version > version)
+            .cloned()
+            .collect())
+    }
+
+    /// Pick up the stored version and the most recent stored changes. Returns how many
+    /// changes were loaded.
+    pub async fn load(&self, pool: &PgPool) -> Result<usize, sqlx::Error> {
+        let version = persistence::get_rules_version(pool).await?;
+        let rows = persistence::list_recent_rule_changes(pool, self.capacity).await?;
+        let mut log = self.lock();
+        log.version = version.max(0) as u64;
+        log.changes = rows.into_iter().filter_map(RuleChange::from_row).collect();
+        Ok(log.changes.len())
+    }
+}
+
+/// GET /exchangeInfo body.
+#[derive(Serialize)]
+pub struct ExchangeInfo {
+    pub rules_version: u64,
+    pub server_time: DateTime<Utc>,
+    /// Every public symbol with its full rules (status, halt and fees included), sorted.
+    pub symbols: Vec<SymbolConfigResponse>,
+}
+
+/// GET /exchangeInfo: every public symbol's rules and the version they are at.
+pub async fn get_exchange_info(State(state): State<AppState>) -> Json<ExchangeInfo> {
+    // Read under the configs lock, so the version matches the rules returned
+    let configs = state.symbol_configs.read().await;
+    let mut symbols: Vec<&String> = listed_orderbooks(&state)
+        .map(|(symbol, _)| symbol)
+        .collect();
+    symbols.sort();
+    let symbols = symbols
+        .into_iter()
+        .filter_map(|symbol| {
+            Some(SymbolConfigResponse::new(
+                Symbol::parse(symbol).ok()?,
+                configs
+                    .get(symbol.as_str())
+                    .copied()
+                    .unwrap_or_else(|| SymbolConfig::for_symbol(symbol)),
+            ))
+        })
+        .collect();
+    Json(ExchangeInfo {
+        rules_version: state.rules.version(),
+        server_time: state.clock.now(),
+        symbols,
+    })
+}
+
+#[derive(Deserialize)]
+pub struct ChangesQuery {
+    since_version: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RuleChangesResponse {
+    pub rules_version: u64,
+    /// Oldest first, at most 1000; ask again from the last one's version for more.
+    pub changes: Vec<RuleChange>,
+}
+
+/// GET /exchangeInfo/changes?since_version=: the rules changes after `since_version`. 410
+/// when they are no longer kept, in which case the client refetches `/exchangeInfo`.
+pub async fn list_changes(
+    State(state): State<AppState>,
+    Query(params): Query<ChangesQuery>,
+) -> Result<Json<RuleChangesResponse>, (StatusCode, Json<ErrorResponse>)> {
+    let rules_version = state.rules.version();
+    let mut changes = match state.rules.since(params.since_version) {
+        Ok(changes) => changes,
+        Err(evicted) => match state.db {
+            Some(ref db) => {
+                persistence::list_rule_changes_since(db, params.since_version as i64, MAX_CHANGES)
+                    .await
+                    .map_err(|e| ErrorResponse::from_db("Failed to list rules changes", e))?
+                    .into_iter()
+                    .filter_map(RuleChange::from_row)
+                    .collect()
+            }
+            None => {
+                return Err(ErrorResponse::new(
+                    format!(
+                        "Rules changes before version {} are no longer available; refetch \
+                         /exchangeInfo",
+                        evicted.oldest_available_version
+                    ),
+                    StatusCode::GONE,
+                ));
+            }
+        },
+    };
+    changes.truncate(MAX_CHANGES);
+    Ok(Json(RuleChangesResponse {
+        rules_version,
+        changes,
+    }))
+}
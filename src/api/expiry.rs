@@ -0,0 +1,449 @@
+//! Order expiry: limit orders placed with `refresh_ttl_ms` stay on the book only while their
+//! owner keeps refreshing them, through `POST /orders/{id}/heartbeat` or the WS `refresh`
+//! action. Each refresh moves the order's deadline to `now + ttl`; the sweeper cancels orders
+//! whose deadline lapses with reason `TTL_EXPIRED`, which the owner learns of from
+//! `OrderClosed` like any other cancel they did not ask for.
+//!
+//! Deadlines are kept in a min-heap keyed by expiry, so the sweeper sleeps until the earliest
+//! one and each placement, refresh or expiry costs O(log n), however many orders rest. A
+//! refresh pushes a new entry rather than finding the old one; entries that no longer match an
+//! order's deadline are dropped when they come up. Orders that leave the book some other way
+//! (filled, cancelled) are dropped the same way: cancelling an order that is gone is a no-op.
+//! A replace carries the TTL over to the new order.
+//!
+//! With a database each deadline is also stored on the order row, on placement and on every
+//! refresh. At startup `catch_up` cancels the open orders whose deadline passed while the
+//! server was down and tracks the rest again; orders restored from a snapshot rest without
+//! one.
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::Json,
+};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BinaryHeap, HashMap};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::Notify;
+use uuid::Uuid;
+
+use crate::api::auth::AuthUser;
+use crate::api::routes::{AppState, ErrorResponse, find_orderbook};
+use crate::api::service::{self, CancelledOrder};
+use crate::persistence::{self, PgPool};
+use crate::sandbox::is_sandbox_symbol;
+use crate::tasks::{RestartPolicy, ShutdownStage};
+use crate::types::order::{CloseReason, OrderId, OrderType};
+
+/// Longest `refresh_ttl_ms` accepted: one hour.
+pub const MAX_REFRESH_TTL_MS: u64 = 3_600_000;
+
+/// The sweeper checks for new deadlines at least this often, even with none pending.
+const MAX_SWEEP_WAIT: Duration = Duration::from_secs(1);
+
+pub type SharedOrderExpiry = Arc<OrderExpiry>;
+
+/// A tracked order's owner, book and current deadline.
+#[derive(Debug, Clone)]
+struct Tracked {
+    symbol: String,
+    user_id: Uuid,
+    ttl: Duration,
+    deadline: DateTime<Utc>,
+}
+
+#[derive(Debug, Default)]
+struct Deadlines {
+    /// Earliest first. An entry whose deadline differs from its order's in `orders` is stale.
+    heap: BinaryHeap<Reverse<(DateTime<Utc>, OrderId)>>,
+    orders: HashMap<OrderId, Tracked>,
+}
+
+/// Deadlines of the orders placed with a refresh TTL.
+#[derive(Debug, Default)]
+pub struct OrderExpiry {
+    deadlines: Mutex<Deadlines>,
+    /// Wakes the sweeper when a deadline earlier than every other is added.
+    earlier: Notify,
+}
+
+/// Why a refresh was refused.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RefreshError {
+    /// Not resting, placed without `refresh_ttl_ms`, or already expired.
+    NotFound,
+    NotOwner,
+}
+
+impl OrderExpiry {
+    fn lock(&self) -> std::sync::MutexGuard<'_, Deadlines> {
+        self.deadlines.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Start tracking `order_id`, resting on `symbol` for `user_id`; it expires `ttl` after
+    /// `now` unless refreshed. Returns the deadline.
+    pub fn track(
+        &self,
+        order_id: OrderId,
+        symbol: &str,
+        user_id: Uuid,
+        ttl: Duration,
+        now: DateTime<Utc>,
+    ) -> DateTime<Utc> {
+        let deadline = now + ttl;
+        self.restore(order_id, symbol, user_id, ttl, deadline);
+        deadline
+    }
+
+    /// Track `order_id` with a known `deadline` (e.g. one stored before a restart).
+    pub fn restore(
+        &self,
+        order_id: OrderId,
+        symbol: &str,
+        user_id: Uuid,
+        ttl: Duration,
+        deadline: DateTime<Utc>,
+    ) {
+        let mut deadlines = self.lock();
+        let earliest = deadlines
+            .heap
+            .peek()
+            .is_none_or(|Reverse((first, _))| deadline < *first);
+        deadlines.heap.push(Reverse((deadline, order_id)));
+        deadlines.orders.insert(
+            order_id,
+            Tracked {
+                symbol: symbol.to_string(),
+                user_id,
+                ttl,
+                deadline,
+            },
+        );
+        drop(deadlines);
+        if earliest {
+            self.earlier.notify_one();
+        }
+    }
+
+    /// `order_id`'s current deadline and TTL, if it is tracked.
+    pub fn deadline_of(&self, order_id: OrderId) -> Option<(DateTime<Utc>, Duration)> {
+        self.lock()
+            .orders
+            .get(&order_id)
+            .map(|tracked| (tracked.deadline, tracked.ttl))
+    }
+
+    /// The symbol `user_id`'s tracked order `order_id` rests on.
+    fn symbol_of(&self, order_id: OrderId, user_id: Uuid) -> Result<String, RefreshError> {
+        match self.lock().orders.get(&order_id) {
+            None => Err(RefreshError::NotFound),
+            Some(tracked) if tracked.user_id != user_id => Err(RefreshError::NotOwner),
+            Some(tracked) => Ok(tracked.symbol.clone()),
+        }
+    }
+
+    /// Move `order_id`'s deadline to its TTL after `now`. Returns the new deadline.
+    pub fn refresh(
+        &self,
+        order_id: OrderId,
+        user_id: Uuid,
+        now: DateTime<Utc>,
+    ) -> Result<DateTime<Utc>, RefreshError> {
+        let mut deadlines = self.lock();
+        let tracked = deadlines
+            .orders
+            .get_mut(&order_id)
+            .ok_or(RefreshError::NotFound)?;
+        if tracked.user_id != user_id {
+            return Err(RefreshError::NotOwner);
+        }
+        let deadline = now + tracked.ttl;
+        tracked.deadline = deadline;
+        deadlines.heap.push(Reverse((deadline, order_id)));
+        Ok(deadline)
+    }
+
+    /// Move `from`'s TTL to its replacement `to` (None if the replacement does not rest),
+    /// which expires a TTL after `now`. Nothing happens when `from` was not tracked.
+    pub fn transfer(&self, from: OrderId, to: Option<OrderId>, now: DateTime<Utc>) {
+        let Some(tracked) = self.lock().orders.remove(&from) else {
+            return;
+        };
+        if let Some(to) = to {
+            self.track(to, &tracked.symbol, tracked.user_id, tracked.ttl, now);
+        }
+    }
+
+    /// Stop tracking `order_id`.
+    pub fn forget(&self, order_id: OrderId) {
+        self.lock().orders.remove(&order_id);
+    }
+
+    /// Untrack and return (symbol, owner, order id) of every order whose deadline is at or
+    /// before `now`, earliest first.
+    pub fn take_due(&self, now: DateTime<Utc>) -> Vec<(String, Uuid, OrderId)> {
+        let mut deadlines = self.lock();
+        let mut due = Vec::new();
+        while let Some(&Reverse((deadline, order_id))) = deadlines.heap.peek() {
+            if deadline > now {
+                break;
+            }
+            deadlines.heap.pop();
+            if deadlines
+                .orders
+                .get(&order_id)
+                .is_some_and(|tracked| tracked.deadline == deadline)
+                && let Some(tracked) = deadlines.orders.remove(&order_id)
+            {
+                due.push((tracked.symbol, tracked.user_id, order_id));
+            }
+        }
+        due
+    }
+
+    /// Earliest pending deadline (possibly of an entry that turns out stale).
+    pub fn next_deadline(&self) -> Option<DateTime<Utc>> {
+        self.lock()
+            .heap
+            .peek()
+            .map(|Reverse((deadline, _))| *deadline)
+    }
+}
+
+/// Whether an order of `order_type` may carry `refresh_ttl_ms`: limit orders only, with a
+/// TTL between 1 ms and `MAX_REFRESH_TTL_MS`.
+pub(crate) fn ttl_violation(
+    order_type: OrderType,
+    refresh_ttl_ms: Option<u64>,
+) -> Option<(StatusCode, Json<ErrorResponse>)> {
+    let ttl = refresh_ttl_ms?;
+    let message = if order_type != OrderType::Limit {
+        "refresh_ttl_ms is only accepted on limit orders".to_string()
+    } else if !(1..=MAX_REFRESH_TTL_MS).contains(&ttl) {
+        format!(
+            "refresh_ttl_ms must be between 1 and {}",
+            MAX_REFRESH_TTL_MS
+        )
+    } else {
+        return None;
+    };
+    Some(ErrorResponse::new(message, StatusCode::BAD_REQUEST))
+}
+
+#[derive(Debug, Serialize)]
+pub struct HeartbeatResponse {
+    pub order_id: OrderId,
+    pub symbol: String,
+    pub expires_at: DateTime<Utc>,
+}
+
+/// Refresh `user_id`'s order `order_id` as of now. 404 unless it is still resting with a
+/// refresh TTL, 403 if it belongs to someone else.
+pub async fn refresh_order(
+    state: &AppState,
+    user_id: Uuid,
+    order_id: OrderId,
+) -> Result<HeartbeatResponse, (StatusCode, Json<ErrorResponse>)> {
+    let refresh_error = |e| match e {
+        RefreshError::NotFound => ErrorResponse::new(
+            format!("Order '{}' is not resting with a refresh TTL", order_id),
+            StatusCode::NOT_FOUND,
+        ),
+        RefreshError::NotOwner => ErrorResponse::new(
+            "Forbidden: order does not belong to you".to_string(),
+            StatusCode::FORBIDDEN,
+        ),
+    };
+    let symbol = state
+        .order_expiry
+        .symbol_of(order_id, user_id)
+        .map_err(refresh_error)?;
+    // A filled or cancelled order stays tracked until its deadline; do not extend it
+    let resting = match find_orderbook(state, &symbol, Some(user_id)).await {
+        Some(orderbook) => orderbook.read().await.get_order_by_id(order_id).is_some(),
+        None => false,
+    };
+    if !resting {
+        state.order_expiry.forget(order_id);
+        return Err(refresh_error(RefreshError::NotFound));
+    }
+    let expires_at = state
+        .order_expiry
+        .refresh(order_id, user_id, state.clock.now())
+        .map_err(refresh_error)?;
+    persist_deadline(state, &symbol, order_id).await;
+    Ok(HeartbeatResponse {
+        order_id,
+        symbol,
+        expires_at,
+    })
+}
+
+/// POST /orders/{id}/heartbeat: keep one of the caller's TTL orders alive for another TTL.
+pub async fn heartbeat(
+    auth: AuthUser,
+    State(state): State<AppState>,
+    Path(order_id): Path<OrderId>,
+) -> Result<Json<HeartbeatResponse>, (StatusCode, Json<ErrorResponse>)> {
+    refresh_order(&state, auth.user_id, order_id)
+        .await
+        .map(Json)
+}
+
+/// Cancel every tracked order whose deadline is at or before `now`, one book update per
+/// symbol. Returns the orders cancelled; ones that had already left the book are skipped.
+pub async fn expire_due(state: &AppState, now: DateTime<Utc>) -> Vec<CancelledOrder> {
+    let mut by_symbol: BTreeMap<String, (Uuid, Vec<OrderId>)> = BTreeMap::new();
+    for (symbol, user_id, order_id) in state.order_expiry.take_due(now) {
+        by_symbol
+            .entry(symbol)
+            .or_insert_with(|| (user_id, Vec::new()))
+            .1
+            .push(order_id);
+    }
+    let mut expired = Vec::new();
+    for (symbol, (user_id, order_ids)) in by_symbol {
+        // The owner reaches their sandbox books too
+        let Some(orderbook) = find_orderbook(state, &symbol, Some(user_id)).await else {
+            continue;
+        };
+        let mut book = orderbook.write().await;
+        expired.extend(
+            service::close_orders(
+                state,
+                &symbol,
+                &mut book,
+                order_ids,
+                CloseReason::TtlExpired,
+            )
+            .await,
+        );
+    }
+    expired
+}
+
+/// Store `order_id`'s current deadline on its row, when it is tracked and `symbol` is
+/// persisted. Call after the order itself was inserted.
+pub(crate) async fn persist_deadline(state: &AppState, symbol: &str, order_id: OrderId) {
+    let Some(ref db) = state.db else {
+        return;
+    };
+    if is_sandbox_symbol(symbol) {
+        return;
+    }
+    if let Some((deadline, ttl)) = state.order_expiry.deadline_of(order_id)
+        && let Err(e) =
+            persistence::set_order_expiry(db, order_id, deadline, ttl.as_millis() as u64).await
+    {
+        eprintln!("failed to store the deadline of order {}: {}", order_id, e);
+    }
+}
+
+/// Startup catch-up against the stored deadlines: cancel the open orders whose deadline is at
+/// or before `now` with reason `TTL_EXPIRED`, then track the surviving resting ones again.
+/// Expired orders resting in a book are closed like a sweep would; rows that never made it
+/// into a book (skipped by hydration, or on a symbol not loaded) are only marked cancelled.
+/// Returns how many orders were expired.
+pub async fn catch_up(
+    state: &AppState,
+    db: &PgPool,
+    now: DateTime<Utc>,
+) -> Result<usize, sqlx::Error> {
+    let mut resting: BTreeMap<String, Vec<OrderId>> = BTreeMap::new();
+    let mut expired = 0;
+    for row in persistence::list_expired_open_orders(db, now).await? {
+        if rests(state, &row.symbol, row.id).await {
+            resting.entry(row.symbol).or_default().push(row.id);
+        } else {
+            persistence::cancel_order(
+                db,
+                row.id,
+                row.quantity.max(0) as u64,
+                CloseReason::TtlExpired.code(),
+            )
+            .await?;
+            expired += 1;
+        }
+    }
+    for (symbol, order_ids) in resting {
+        let mut book = state.orderbooks[&symbol].write().await;
+        expired += service::close_orders(
+            state,
+            &symbol,
+            &mut book,
+            order_ids,
+            CloseReason::TtlExpired,
+        )
+        .await
+        .len();
+    }
+    for row in persistence::list_open_order_deadlines(db).await? {
+        if row.expires_at > now && rests(state, &row.symbol, row.id).await {
+            let ttl = Duration::from_millis(row.refresh_ttl_ms.max(0) as u64);
+            state
+                .order_expiry
+                .restore(row.id, &row.symbol, row.user_id, ttl, row.expires_at);
+        }
+    }
+    Ok(expired)
+}
+
+/// Track the stored deadlines of the orders resting in `symbol`'s book again, for a book
+/// loaded after startup (see `api::lazy_books`). Ones that lapsed meanwhile are cancelled by
+/// the sweeper's next pass. Returns how many are tracked.
+pub async fn track_stored(
+    state: &AppState,
+    db: &PgPool,
+    symbol: &str,
+) -> Result<usize, sqlx::Error> {
+    let mut tracked = 0;
+    for row in persistence::list_open_order_deadlines(db).await? {
+        if row.symbol == symbol && rests(state, &row.symbol, row.id).await {
+            let ttl = Duration::from_millis(row.refresh_ttl_ms.max(0) as u64);
+            state
+                .order_expiry
+                .restore(row.id, &row.symbol, row.user_id, ttl, row.expires_at);
+            tracked += 1;
+        }
+    }
+    Ok(tracked)
+}
+
+/// Whether `order_id` rests in `symbol`'s public book.
+async fn rests(state: &AppState, symbol: &str, order_id: OrderId) -> bool {
+    match state.orderbooks.get(symbol) {
+        Some(orderbook) => orderbook.read().await.get_order_by_id(order_id).is_some(),
+        None => false,
+    }
+}
+
+/// Cancel orders as their deadlines lapse on `state.clock`.
+pub fn spawn_sweeper(state: AppState) {
+    let tasks = state.tasks.clone();
+    tasks.spawn(
+        "expiry_sweeper",
+        ShutdownStage::Worker,
+        RestartPolicy::default(),
+        move |token| {
+            let state = state.clone();
+            async move {
+                loop {
+                    let latest = state.clock.now() + MAX_SWEEP_WAIT;
+                    let wake_at = state
+                        .order_expiry
+                        .next_deadline()
+                        .map_or(latest, |deadline| deadline.min(latest));
+                    tokio::select! {
+                        _ = token.cancelled() => return,
+                        _ = state.clock.sleep_until(wake_at) => {}
+                        _ = state.order_expiry.earlier.notified() => {}
+                    }
+                    expire_due(&state, state.clock.now()).await;
+                }
+            }
+        },
+    );
+}
@@ -0,0 +1,264 @@
+//! Exposure across books in one currency. Each of the caller's positions is valued at its
+//! symbol's mark price in the symbol's quote asset, then converted into the requested currency
+//! through the other books' mark prices (see `crate::conversion`). Read-only: positions come
+//! from memory and prices from the books and the index feed. Only live positions and books
+//! count; the paper environment is not the caller's money.
+
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::Json,
+};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+
+use crate::api::auth::AuthUser;
+use crate::api::routes::{AppState, ErrorResponse, find_orderbook};
+use crate::api::service;
+use crate::conversion::{ConversionGraph, ConversionPath, Hop, SymbolMark};
+use crate::orderbook::orderbook::OrderBook;
+use crate::positions::{self, scaled_unrealized_pnl};
+use crate::pricefeed;
+use crate::selftest::SELFTEST_SYMBOL;
+use crate::types::asset::Asset;
+use crate::types::environment::Environment;
+use crate::types::money::{MoneyError, Notional, descale, signed_notional};
+use crate::types::order::Price;
+use crate::types::position::Position;
+
+#[derive(Deserialize)]
+pub struct ExposureQuery {
+    currency: String,
+}
+
+/// Where a mark price came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MarkSource {
+    /// The index feed, fresh.
+    Index,
+    /// The book's last trade: no index price, or a stale one.
+    LastTrade,
+}
+
+/// Why a position is left out of the totals.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Unconvertible {
+    /// The symbol is not a pair of registered assets.
+    UnknownAssets,
+    /// No fresh index price and the book has never traded.
+    NoMarkPrice,
+    /// No chain of marked symbols links the quote asset to the currency.
+    NoConversionPath,
+    Overflow,
+}
+
+#[derive(Serialize)]
+pub struct PositionExposure {
+    symbol: String,
+    #[serde(with = "crate::types::string_i64")]
+    quantity: i64,
+    quote_asset: Option<Asset>,
+    #[serde(with = "crate::types::string_i64::option")]
+    mark_price: Option<Price>,
+    mark_source: Option<MarkSource>,
+    /// `mark_price * quantity` (negative when short) and the P&L at `mark_price`, in
+    /// `quote_asset`.
+    #[serde(with = "crate::types::string_i64::option")]
+    notional: Option<i64>,
+    #[serde(with = "crate::types::string_i64::option")]
+    unrealized_pnl: Option<i64>,
+    /// The same two amounts in the requested currency; None when `unconvertible` is set.
+    #[serde(with = "crate::types::string_i64::option")]
+    converted_notional: Option<i64>,
+    #[serde(with = "crate::types::string_i64::option")]
+    converted_unrealized_pnl: Option<i64>,
+    /// Conversion steps from `quote_asset` to the currency; empty when they are the same.
+    path: Vec<Hop>,
+    unconvertible: Option<Unconvertible>,
+}
+
+#[derive(Serialize)]
+pub struct ExposureResponse {
+    currency: Asset,
+    /// Sums over the convertible positions: signed notional, absolute notional and P&L.
+    #[serde(with = "crate::types::string_i64")]
+    net_notional: i64,
+    #[serde(with = "crate::types::string_i64")]
+    gross_notional: i64,
+    #[serde(with = "crate::types::string_i64")]
+    unrealized_pnl: i64,
+    /// Symbols (positions' or conversion hops') whose index price is stale, so their last
+    /// trade price was used.
+    stale_index_symbols: Vec<String>,
+    positions: Vec<PositionExposure>,
+}
+
+/// GET /account/exposure?currency=USDT: the caller's positions valued at mark and converted
+/// into `currency`, with totals over the ones that could be. 400 for an unknown currency.
+pub async fn get_exposure(
+    auth: AuthUser,
+    State(state): State<AppState>,
+    Query(params): Query<ExposureQuery>,
+) -> Result<Json<ExposureResponse>, (StatusCode, Json<ErrorResponse>)> {
+    let currency = Asset::parse(&params.currency)
+        .ok()
+        .filter(|asset| asset.info().is_some())
+        .ok_or_else(|| {
+            ErrorResponse::new(
+                format!("Unknown currency: {}", params.currency.trim()),
+                StatusCode::BAD_REQUEST,
+            )
+        })?;
+
+    let mut stale_index_symbols = Vec::new();
+    let mut marks = Vec::new();
+    let mut symbols: Vec<&String> = state.orderbooks.keys().collect();
+    symbols.sort();
+    for symbol in symbols {
+        if symbol == SELFTEST_SYMBOL
+            || Environment::of(symbol) != Environment::Live
+            || state.delistings.is_delisted(symbol)
+        {
+            continue;
+        }
+        let Some((base, quote)) = service::symbol_config(&state, symbol).await.assets() else {
+            continue;
+        };
+        let (mark, stale) =
+            symbol_mark(&state, symbol, &*state.orderbooks[symbol].read().await).await;
+        if stale {
+            stale_index_symbols.push(symbol.clone());
+        }
+        if let Some((price, _)) = mark {
+            marks.push(SymbolMark {
+                symbol: symbol.clone(),
+                base,
+                quote,
+                price,
+            });
+        }
+    }
+    let graph = ConversionGraph::new(marks);
+
+    let mut held = positions::get_positions(&state.positions, auth.user_id, None).await;
+    held.retain(|position| {
+        position.quantity != 0 && Environment::of(&position.symbol) == Environment::Live
+    });
+    held.sort_by(|a, b| a.symbol.cmp(&b.symbol));
+    let (mut net, mut gross, mut pnl) = (Notional::ZERO, Notional::ZERO, Notional::ZERO);
+    let mut entries = Vec::with_capacity(held.len());
+    for position in held {
+        let config = service::symbol_config(&state, &position.symbol).await;
+        let quote_asset = config.quote_asset;
+        let mut mark = None;
+        if let Some(orderbook) = find_orderbook(&state, &position.symbol, Some(auth.user_id)).await
+        {
+            let (found, stale) =
+                symbol_mark(&state, &position.symbol, &*orderbook.read().await).await;
+            if stale && !stale_index_symbols.contains(&position.symbol) {
+                stale_index_symbols.push(position.symbol.clone());
+            }
+            mark = found;
+        }
+        let mut entry = PositionExposure {
+            symbol: position.symbol.clone(),
+            quantity: position.quantity,
+            quote_asset,
+            mark_price: mark.map(|(price, _)| price),
+            mark_source: mark.map(|(_, source)| source),
+            notional: None,
+            unrealized_pnl: None,
+            converted_notional: None,
+            converted_unrealized_pnl: None,
+            path: Vec::new(),
+            unconvertible: None,
+        };
+        let valued = match (config.assets(), mark) {
+            (None, _) => Err(Unconvertible::UnknownAssets),
+            (_, None) => Err(Unconvertible::NoMarkPrice),
+            (Some((_, quote)), Some((price, _))) => value(
+                &mut entry,
+                &position,
+                price,
+                config.qty_scale,
+                graph.path(quote, currency),
+            ),
+        };
+        match valued {
+            Ok((notional, position_pnl)) => {
+                entry.converted_notional = Some(notional);
+                entry.converted_unrealized_pnl = Some(position_pnl);
+                let notional = Notional::from_raw(notional as i128);
+                net = net.checked_add(notional).unwrap_or(net);
+                gross = gross
+                    .checked_add(Notional::from_raw(notional.raw().abs()))
+                    .unwrap_or(gross);
+                pnl = pnl
+                    .checked_add(Notional::from_raw(position_pnl as i128))
+                    .unwrap_or(pnl);
+            }
+            Err(reason) => entry.unconvertible = Some(reason),
+        }
+        entries.push(entry);
+    }
+
+    stale_index_symbols.sort();
+    Ok(Json(ExposureResponse {
+        currency,
+        net_notional: net.to_i64_saturating(),
+        gross_notional: gross.to_i64_saturating(),
+        unrealized_pnl: pnl.to_i64_saturating(),
+        stale_index_symbols,
+        positions: entries,
+    }))
+}
+
+/// Fill in `entry`'s amounts for `position` at `price`, converted along `path`; the converted
+/// notional and P&L.
+fn value(
+    entry: &mut PositionExposure,
+    position: &Position,
+    price: Price,
+    qty_scale: u64,
+    path: Option<ConversionPath>,
+) -> Result<(i64, i64), Unconvertible> {
+    let qty_scale = qty_scale.max(1);
+    let notional = descale(signed_notional(price, position.quantity), qty_scale)
+        .to_i64()
+        .map_err(overflow)?;
+    let pnl = scaled_unrealized_pnl(position, price, qty_scale).map_err(overflow)?;
+    entry.notional = Some(notional);
+    entry.unrealized_pnl = Some(pnl);
+    let path = path.ok_or(Unconvertible::NoConversionPath)?;
+    let converted = (
+        path.convert(notional).map_err(overflow)?,
+        path.convert(pnl).map_err(overflow)?,
+    );
+    entry.path = path.hops;
+    Ok(converted)
+}
+
+fn overflow(_: MoneyError) -> Unconvertible {
+    Unconvertible::Overflow
+}
+
+/// `symbol`'s mark price as `routes::mark_price` picks it, with its source, and whether an
+/// index price was passed over for being stale.
+async fn symbol_mark(
+    state: &AppState,
+    symbol: &str,
+    book: &OrderBook,
+) -> (Option<(Price, MarkSource)>, bool) {
+    let index = state.index_prices.read().await.get(symbol).copied();
+    let stale =
+        index.is_some_and(|p| pricefeed::is_stale(&p, state.index_price_max_age, Utc::now()));
+    let mark = match index {
+        Some(index) if !stale => Some((index.price, MarkSource::Index)),
+        _ => book
+            .last_trade_price()
+            .map(|price| (price, MarkSource::LastTrade)),
+    };
+    (mark, stale)
+}
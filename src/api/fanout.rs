@@ -0,0 +1,355 @@
+//! WebSocket fan-out: the stage between the engine's broadcast channel and the sockets.
+//!
+//! The engine publishes each event once into `AppState::ws_channel`. One feed task per
+//! subscribed symbol, plus one for private messages, receives it, serializes each outbound
+//! frame once and hands the shared frame to the outbox of every connection that should see
+//! it. Socket tasks only write frames out, so serialization no longer grows with the number of
+//! subscribers and a slow socket fills its own outbox without holding up anyone else.
+//!
+//! An outbox holds `WsConfig::queue_capacity` frames and drops the oldest when full; the
+//! socket then learns how many it lost and gets the overflow policy's treatment (see
+//! `ws_connections`). A feed that itself falls behind the broadcast channel charges what it
+//! lost to each of its connections the same way. A feed stops once its last connection leaves.
+//!
+//! Announcements skip the channel and the feeds: `announce` queues one frame on every
+//! connection, including those that follow nothing.
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use tokio::select;
+use tokio::sync::Notify;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio_util::sync::CancellationToken;
+use uuid::Uuid;
+
+use crate::api::routes::WsMessage;
+use crate::api::ws::{Audience, Rendering, SymbolSubscription, audience};
+
+/// A serialized text frame, shared by every connection it goes to.
+pub type Frame = Arc<str>;
+
+pub type SharedFanout = Arc<Fanout>;
+
+/// What a socket takes out of its outbox next.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Delivery {
+    /// The outbox overflowed (or its feed fell behind) and this many frames were lost; sent
+    /// before anything still queued.
+    Lagged(u64),
+    Frame(Frame),
+}
+
+/// A connection's bounded queue of frames waiting to be written to its socket.
+#[derive(Debug)]
+struct Outbox {
+    queue: Mutex<Queue>,
+    ready: Notify,
+    capacity: usize,
+}
+
+#[derive(Debug, Default)]
+struct Queue {
+    frames: VecDeque<Frame>,
+    skipped: u64,
+}
+
+impl Outbox {
+    fn queue(&self) -> std::sync::MutexGuard<'_, Queue> {
+        self.queue.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn push(&self, frame: Frame) {
+        {
+            let mut queue = self.queue();
+            if queue.frames.len() >= self.capacity {
+                queue.frames.pop_front();
+                queue.skipped += 1;
+            }
+            queue.frames.push_back(frame);
+        }
+        self.ready.notify_one();
+    }
+
+    fn charge(&self, skipped: u64) {
+        self.queue().skipped += skipped;
+        self.ready.notify_one();
+    }
+}
+
+/// One connection as the fan-out sees it: who it is authenticated as and its outbox.
+#[derive(Debug)]
+pub struct Subscriber {
+    id: Uuid,
+    user_id: Option<Uuid>,
+    positions: AtomicBool,
+    outbox: Outbox,
+}
+
+impl Subscriber {
+    /// Wait for the next delivery. Cancel-safe: nothing is taken until it is returned.
+    pub async fn next(&self) -> Delivery {
+        loop {
+            {
+                let mut queue = self.outbox.queue();
+                if queue.skipped > 0 {
+                    return Delivery::Lagged(std::mem::take(&mut queue.skipped));
+                }
+                if let Some(frame) = queue.frames.pop_front() {
+                    return Delivery::Frame(frame);
+                }
+            }
+            self.outbox.ready.notified().await;
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum FeedKey {
+    Symbol(String),
+    /// Order, close and position updates, for authenticated connections.
+    Private,
+}
+
+#[derive(Clone)]
+struct Member {
+    subscriber: Arc<Subscriber>,
+    /// What the connection follows of the symbol (default on the private feed).
+    subscription: SymbolSubscription,
+}
+
+type Members = Arc<RwLock<HashMap<Uuid, Member>>>;
+
+struct Feed {
+    members: Members,
+    stop: CancellationToken,
+}
+
+/// The running feeds and their connections.
+#[derive(Default)]
+pub struct Fanout {
+    feeds: Mutex<HashMap<FeedKey, Feed>>,
+    /// Every connected subscriber, feeds or not, for announcements.
+    connected: Mutex<HashMap<Uuid, Arc<Subscriber>>>,
+}
+
+impl Fanout {
+    /// A subscriber for connection `id` with an outbox of `capacity` frames. Authenticated
+    /// connections join the private feed straight away.
+    pub fn connect(
+        &self,
+        ws_channel: &broadcast::Sender<WsMessage>,
+        id: Uuid,
+        user_id: Option<Uuid>,
+        capacity: usize,
+    ) -> Arc<Subscriber> {
+        let subscriber = Arc::new(Subscriber {
+            id,
+            user_id,
+            positions: AtomicBool::new(false),
+            outbox: Outbox {
+                queue: Mutex::new(Queue::default()),
+                ready: Notify::new(),
+                capacity: capacity.max(1),
+            },
+        });
+        self.connected
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(id, subscriber.clone());
+        if user_id.is_some() {
+            self.join(
+                ws_channel,
+                FeedKey::Private,
+                &subscriber,
+                SymbolSubscription::default(),
+            );
+        }
+        subscriber
+    }
+
+    /// Deliver `symbol`'s market data to `subscriber` as `subscription` renders it, replacing
+    /// any earlier subscription to the symbol.
+    pub fn subscribe(
+        &self,
+        ws_channel: &broadcast::Sender<WsMessage>,
+        subscriber: &Arc<Subscriber>,
+        symbol: &str,
+        subscription: SymbolSubscription,
+    ) {
+        self.join(
+            ws_channel,
+            FeedKey::Symbol(symbol.to_string()),
+            subscriber,
+            subscription,
+        );
+    }
+
+    pub fn unsubscribe(&self, subscriber: &Subscriber, symbol: &str) {
+        self.leave(
+            &mut self.feeds(),
+            &FeedKey::Symbol(symbol.to_string()),
+            subscriber.id,
+        );
+    }
+
+    /// Whether `subscriber` receives its own position updates.
+    pub fn set_positions(&self, subscriber: &Subscriber, enabled: bool) {
+        subscriber.positions.store(enabled, Ordering::Relaxed);
+    }
+
+    /// Queue `message` for every connected subscriber, serialized once. Returns how many it
+    /// was queued for.
+    pub fn announce(&self, message: &WsMessage) -> usize {
+        let Some(frame) = Rendering::Shared.render(message).map(Frame::from) else {
+            return 0;
+        };
+        let connected = self.connected.lock().unwrap_or_else(|e| e.into_inner());
+        for subscriber in connected.values() {
+            subscriber.outbox.push(frame.clone());
+        }
+        connected.len()
+    }
+
+    /// Remove `subscriber` from every feed.
+    pub fn disconnect(&self, subscriber: &Subscriber) {
+        self.connected
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(&subscriber.id);
+        let mut feeds = self.feeds();
+        let keys: Vec<FeedKey> = feeds.keys().cloned().collect();
+        for key in keys {
+            self.leave(&mut feeds, &key, subscriber.id);
+        }
+    }
+
+    /// Feeds running now, private feed included.
+    pub fn feed_count(&self) -> usize {
+        self.feeds().len()
+    }
+
+    fn feeds(&self) -> std::sync::MutexGuard<'_, HashMap<FeedKey, Feed>> {
+        self.feeds.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn join(
+        &self,
+        ws_channel: &broadcast::Sender<WsMessage>,
+        key: FeedKey,
+        subscriber: &Arc<Subscriber>,
+        subscription: SymbolSubscription,
+    ) {
+        let mut feeds = self.feeds();
+        let feed = feeds.entry(key.clone()).or_insert_with(|| {
+            // Subscribed before the caller acks, so nothing published after that is missed
+            let receiver = ws_channel.subscribe();
+            let feed = Feed {
+                members: Members::default(),
+                stop: CancellationToken::new(),
+            };
+            tokio::spawn(run_feed(
+                key,
+                receiver,
+                feed.members.clone(),
+                feed.stop.clone(),
+            ));
+            feed
+        });
+        feed.members
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(
+                subscriber.id,
+                Member {
+                    subscriber: subscriber.clone(),
+                    subscription,
+                },
+            );
+    }
+
+    fn leave(&self, feeds: &mut HashMap<FeedKey, Feed>, key: &FeedKey, id: Uuid) {
+        let Some(feed) = feeds.get(key) else {
+            return;
+        };
+        let mut members = feed.members.write().unwrap_or_else(|e| e.into_inner());
+        members.remove(&id);
+        if members.is_empty() {
+            drop(members);
+            if let Some(feed) = feeds.remove(key) {
+                feed.stop.cancel();
+            }
+        }
+    }
+}
+
+async fn run_feed(
+    key: FeedKey,
+    mut receiver: broadcast::Receiver<WsMessage>,
+    members: Members,
+    stop: CancellationToken,
+) {
+    loop {
+        let result = select! {
+            _ = stop.cancelled() => return,
+            result = receiver.recv() => result,
+        };
+        let members = members.read().unwrap_or_else(|e| e.into_inner());
+        match result {
+            Ok(message) => deliver(&key, &message, &members),
+            Err(RecvError::Lagged(skipped)) => {
+                for member in members.values() {
+                    member.subscriber.outbox.charge(skipped);
+                }
+            }
+            Err(RecvError::Closed) => return,
+        }
+    }
+}
+
+/// Queue `message` for the members of feed `key` that should see it, serializing each
+/// distinct rendering once.
+fn deliver(key: &FeedKey, message: &WsMessage, members: &HashMap<Uuid, Member>) {
+    let mut rendered: Vec<(Rendering, Option<Frame>)> = Vec::new();
+    let mut frame = |rendering: Rendering| -> Option<Frame> {
+        if let Some((_, frame)) = rendered.iter().find(|(r, _)| *r == rendering) {
+            return frame.clone();
+        }
+        let frame = rendering.render(message).map(Frame::from);
+        rendered.push((rendering, frame.clone()));
+        frame
+    };
+    match (key, audience(message)) {
+        (FeedKey::Symbol(symbol), Audience::Symbol(target)) if symbol == target => {
+            for member in members.values() {
+                let subscriber = &member.subscriber;
+                if let Some(rendering) = member.subscription.rendering(message, subscriber.user_id)
+                    && let Some(frame) = frame(rendering)
+                {
+                    subscriber.outbox.push(frame);
+                }
+            }
+        }
+        (FeedKey::Private, Audience::Owner(owner)) => {
+            for member in members.values() {
+                if member.subscriber.user_id == Some(owner)
+                    && let Some(frame) = frame(Rendering::Shared)
+                {
+                    member.subscriber.outbox.push(frame);
+                }
+            }
+        }
+        (FeedKey::Private, Audience::PositionsOwner(owner)) => {
+            for member in members.values() {
+                let subscriber = &member.subscriber;
+                if subscriber.user_id == Some(owner)
+                    && subscriber.positions.load(Ordering::Relaxed)
+                    && let Some(frame) = frame(Rendering::Shared)
+                {
+                    subscriber.outbox.push(frame);
+                }
+            }
+        }
+        _ => {}
+    }
+}
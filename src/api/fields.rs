@@ -0,0 +1,126 @@
+//! Partial responses: `?fields=id,price,quantity` on list endpoints keeps only those top-level
+//! fields of each returned object. Items are serialized to `serde_json::Value` and pruned, so
+//! any `Selectable` type works; envelopes around the list are left untouched.
+
+use axum::{
+    Json,
+    http::{StatusCode, Uri},
+    response::{IntoResponse, Response},
+};
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::BTreeSet;
+
+use crate::api::dto::{OrderDto, PositionDto, TradeDto};
+use crate::api::pagination::Page;
+use crate::api::routes::ErrorResponse;
+
+/// A type whose top-level serialized fields can be selected.
+pub trait Selectable: Serialize {
+    /// Every top-level field name the type serializes.
+    const FIELDS: &'static [&'static str];
+}
+
+impl Selectable for TradeDto {
+    const FIELDS: &'static [&'static str] = &[
+        "id",
+        "trade_seq",
+        "maker_order_id",
+        "taker_order_id",
+        "maker_user_id",
+        "taker_user_id",
+        "price",
+        "quantity",
+        "timestamp",
+        "maker_queue_rank",
+    ];
+}
+
+impl Selectable for OrderDto {
+    const FIELDS: &'static [&'static str] = &[
+        "id",
+        "user_id",
+        "side",
+        "order_type",
+        "price",
+        "quantity",
+        "status",
+        "timestamp",
+        "tags",
+        "source",
+    ];
+}
+
+impl Selectable for PositionDto {
+    const FIELDS: &'static [&'static str] = &[
+        "user_id",
+        "symbol",
+        "quantity",
+        "average_price",
+        "opened_at",
+        "updated_at",
+    ];
+}
+
+/// Parsed `fields=` parameter; `None` keeps every field.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FieldSelection(Option<BTreeSet<String>>);
+
+impl FieldSelection {
+    /// Parse a comma-separated list of `T`'s fields. Names are trimmed and empty entries
+    /// ignored; an unknown name, or a parameter naming no field at all, is a 400.
+    pub fn parse<T: Selectable>(
+        raw: Option<&str>,
+    ) -> Result<Self, (StatusCode, Json<ErrorResponse>)> {
+        let Some(raw) = raw else {
+            return Ok(Self(None));
+        };
+        let mut fields = BTreeSet::new();
+        for name in raw.split(',').map(str::trim).filter(|n| !n.is_empty()) {
+            if !T::FIELDS.contains(&name) {
+                return Err(ErrorResponse::new(
+                    format!(
+                        "Unknown field '{}'; available fields: {}",
+                        name,
+                        T::FIELDS.join(", ")
+                    ),
+                    StatusCode::BAD_REQUEST,
+                ));
+            }
+            fields.insert(name.to_string());
+        }
+        if fields.is_empty() {
+            return Err(ErrorResponse::new(
+                "fields must name at least one field".to_string(),
+                StatusCode::BAD_REQUEST,
+            ));
+        }
+        Ok(Self(Some(fields)))
+    }
+
+    /// `item` with only the selected top-level fields.
+    pub fn prune<T: Selectable>(&self, item: &T) -> Value {
+        let mut value = serde_json::to_value(item).unwrap_or(Value::Null);
+        if let (Some(fields), Value::Object(map)) = (&self.0, &mut value) {
+            map.retain(|key, _| fields.contains(key));
+        }
+        value
+    }
+
+    /// JSON array of `items`, pruned when a selection was given.
+    pub fn respond<T: Selectable>(&self, items: Vec<T>) -> Response {
+        if self.0.is_none() {
+            return Json(items).into_response();
+        }
+        let pruned: Vec<Value> = items.iter().map(|item| self.prune(item)).collect();
+        Json(pruned).into_response()
+    }
+
+    /// `page` (see `Page::respond`), its items pruned when a selection was given.
+    pub fn respond_page<T: Selectable>(&self, page: Page<T>, uri: &Uri) -> Response {
+        if self.0.is_none() {
+            return page.respond(uri);
+        }
+        page.map(|item| self.prune(&item)).respond(uri)
+    }
+}
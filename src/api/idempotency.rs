@@ -0,0 +1,298 @@
+//! `Idempotency-Key` support for order submission.
+//!
+//! The first request for a (user, key) runs normally and its response is cached (bounded,
+//! in memory, plus the `idempotency_keys` table when a database is configured). Replays within
+//! the TTL get the stored response with `Idempotent-Replay: true`; a replay with a different body
+//! gets 422; a replay while the first request is still running gets 409, so the matching engine
+//! never sees the same key twice.
+
+use axum::{
+    body::{Body, Bytes, to_bytes},
+    extract::{FromRequestParts, Request, State},
+    http::{HeaderValue, StatusCode, header},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use chrono::{DateTime, Utc};
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use uuid::Uuid;
+
+use crate::api::auth::AuthUser;
+use crate::api::routes::{AppState, ErrorResponse};
+use crate::persistence;
+
+pub const IDEMPOTENCY_KEY_HEADER: &str = "idempotency-key";
+pub const IDEMPOTENT_REPLAY_HEADER: &str = "idempotent-replay";
+
+const MAX_KEY_LEN: usize = 255;
+const MAX_BODY_BYTES: usize = 64 * 1024;
+const DEFAULT_CAPACITY: usize = 10_000;
+const DEFAULT_TTL_HOURS: i64 = 24;
+
+pub type SharedIdempotencyCache = Arc<Mutex<IdempotencyCache>>;
+
+type CacheKey = (Uuid, String);
+
+#[derive(Debug, Clone)]
+pub struct StoredResponse {
+    pub request_body: Bytes,
+    pub status: StatusCode,
+    pub body: Bytes,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone)]
+enum Entry {
+    InFlight { request_body: Bytes },
+    Completed(StoredResponse),
+}
+
+/// Bounded cache of idempotency entries; oldest keys are evicted first once full.
+pub struct IdempotencyCache {
+    entries: HashMap<CacheKey, Entry>,
+    order: VecDeque<CacheKey>,
+    capacity: usize,
+    ttl: chrono::Duration,
+}
+
+impl Default for IdempotencyCache {
+    fn default() -> Self {
+        Self::new(DEFAULT_CAPACITY, chrono::Duration::hours(DEFAULT_TTL_HOURS))
+    }
+}
+
+impl IdempotencyCache {
+    pub fn new(capacity: usize, ttl: chrono::Duration) -> Self {
+        Self {
+            entries: HashMap::new(),
+            order: VecDeque::new(),
+            capacity,
+            ttl,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    fn is_expired(&self, created_at: DateTime<Utc>) -> bool {
+        Utc::now() - created_at > self.ttl
+    }
+
+    fn insert(&mut self, key: CacheKey, entry: Entry) {
+        if self.entries.insert(key.clone(), entry).is_none() {
+            self.order.push_back(key);
+        }
+        while self.entries.len() > self.capacity {
+            let Some(oldest) = self.order.pop_front() else {
+                break;
+            };
+            self.entries.remove(&oldest);
+        }
+    }
+
+    fn remove(&mut self, key: &CacheKey) {
+        if self.entries.remove(key).is_some() {
+            self.order.retain(|k| k != key);
+        }
+    }
+}
+
+/// Outcome of looking up a key before running the handler.
+enum Lookup {
+    Execute,
+    Replay(StoredResponse),
+    Mismatch,
+    InProgress,
+}
+
+fn replay_or_mismatch(stored: StoredResponse, body: &Bytes) -> Lookup {
+    if stored.request_body != *body {
+        Lookup::Mismatch
+    } else {
+        Lookup::Replay(stored)
+    }
+}
+
+/// Middleware for mutating endpoints. Requests without the header pass straight through, as do
+/// unauthenticated ones (the handler's `AuthUser` extractor rejects those).
+pub async fn idempotency_middleware(
+    State(state): State<AppState>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let Some(key) = request
+        .headers()
+        .get(IDEMPOTENCY_KEY_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.trim().to_string())
+    else {
+        return next.run(request).await;
+    };
+    if key.is_empty() || key.len() > MAX_KEY_LEN {
+        return ErrorResponse::new(
+            format!("Idempotency-Key must be 1-{} characters", MAX_KEY_LEN),
+            StatusCode::BAD_REQUEST,
+        )
+        .into_response();
+    }
+
+    let (mut parts, body) = request.into_parts();
+    let user = match AuthUser::from_request_parts(&mut parts, &state).await {
+        Ok(user) => user,
+        Err(_) => return next.run(Request::from_parts(parts, body)).await,
+    };
+    let Ok(request_body) = to_bytes(body, MAX_BODY_BYTES).await else {
+        return ErrorResponse::new(
+            "Request body too large".to_string(),
+            StatusCode::PAYLOAD_TOO_LARGE,
+        )
+        .into_response();
+    };
+    let cache_key = (user.user_id, key.clone());
+
+    // Reserve the key under the cache lock so concurrent duplicates cannot both execute. The
+    // lock is not held over the database lookup below; the reservation keeps duplicates out.
+    let lookup = {
+        let mut cache = state.idempotency.lock().await;
+        let cached = cache.entries.get(&cache_key).cloned();
+        let lookup = match cached {
+            Some(Entry::InFlight {
+                request_body: stored,
+            }) if stored != request_body => Lookup::Mismatch,
+            Some(Entry::InFlight { .. }) => Lookup::InProgress,
+            Some(Entry::Completed(stored)) if !cache.is_expired(stored.created_at) => {
+                replay_or_mismatch(stored, &request_body)
+            }
+            _ => Lookup::Execute,
+        };
+        if matches!(lookup, Lookup::Execute) {
+            cache.insert(
+                cache_key.clone(),
+                Entry::InFlight {
+                    request_body: request_body.clone(),
+                },
+            );
+        }
+        lookup
+    };
+    // A key evicted from memory may still be stored; executing it again because the lookup
+    // failed would be the duplicate this exists to prevent.
+    let lookup = match lookup {
+        Lookup::Execute => match load_stored(&state, user.user_id, &key).await {
+            Ok(stored) => {
+                let mut cache = state.idempotency.lock().await;
+                match stored {
+                    Some(stored) if !cache.is_expired(stored.created_at) => {
+                        cache.insert(cache_key.clone(), Entry::Completed(stored.clone()));
+                        replay_or_mismatch(stored, &request_body)
+                    }
+                    _ => Lookup::Execute,
+                }
+            }
+            Err(e) => {
+                eprintln!("Failed to look up Idempotency-Key: {}", e);
+                state.idempotency.lock().await.remove(&cache_key);
+                return ErrorResponse::new(
+                    "Could not check the Idempotency-Key; retry the request".to_string(),
+                    StatusCode::SERVICE_UNAVAILABLE,
+                )
+                .into_response();
+            }
+        },
+        lookup => lookup,
+    };
+
+    match lookup {
+        Lookup::Replay(stored) => replay_response(&stored),
+        Lookup::Mismatch => ErrorResponse::new(
+            "Idempotency-Key was already used with a different request body".to_string(),
+            StatusCode::UNPROCESSABLE_ENTITY,
+        )
+        .into_response(),
+        Lookup::InProgress => ErrorResponse::new(
+            "A request with this Idempotency-Key is still in progress".to_string(),
+            StatusCode::CONFLICT,
+        )
+        .into_response(),
+        Lookup::Execute => {
+            let response = next
+                .run(Request::from_parts(parts, Body::from(request_body.clone())))
+                .await;
+            let (response_parts, response_body) = response.into_parts();
+            let Ok(body) = to_bytes(response_body, usize::MAX).await else {
+                state.idempotency.lock().await.remove(&cache_key);
+                return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+            };
+
+            // Server errors are not cached so the client can retry them.
+            if response_parts.status.is_server_error() {
+                state.idempotency.lock().await.remove(&cache_key);
+            } else {
+                let stored = StoredResponse {
+                    request_body,
+                    status: response_parts.status,
+                    body: body.clone(),
+                    created_at: Utc::now(),
+                };
+                if let Some(ref db) = state.db {
+                    let _ = persistence::upsert_idempotency_key(
+                        db,
+                        user.user_id,
+                        &key,
+                        &String::from_utf8_lossy(&stored.request_body),
+                        stored.status.as_u16(),
+                        &String::from_utf8_lossy(&stored.body),
+                        stored.created_at,
+                    )
+                    .await;
+                }
+                state
+                    .idempotency
+                    .lock()
+                    .await
+                    .insert(cache_key, Entry::Completed(stored));
+            }
+            Response::from_parts(response_parts, Body::from(body))
+        }
+    }
+}
+
+/// The response stored for (`user_id`, `key`), if any; always None without a database.
+async fn load_stored(
+    state: &AppState,
+    user_id: Uuid,
+    key: &str,
+) -> Result<Option<StoredResponse>, sqlx::Error> {
+    let Some(ref db) = state.db else {
+        return Ok(None);
+    };
+    let Some(row) = persistence::get_idempotency_key(db, user_id, key).await? else {
+        return Ok(None);
+    };
+    Ok(StatusCode::from_u16(row.status_code as u16)
+        .ok()
+        .map(|status| StoredResponse {
+            request_body: Bytes::from(row.request_body),
+            status,
+            body: Bytes::from(row.response_body),
+            created_at: row.created_at,
+        }))
+}
+
+fn replay_response(stored: &StoredResponse) -> Response {
+    let mut response = (stored.status, stored.body.clone()).into_response();
+    let headers = response.headers_mut();
+    headers.insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static("application/json"),
+    );
+    headers.insert(IDEMPOTENT_REPLAY_HEADER, HeaderValue::from_static("true"));
+    response
+}
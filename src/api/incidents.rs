@@ -0,0 +1,257 @@
+//! Matching incidents. A panic inside matching (a bug, e.g. an arithmetic overflow) is caught
+//! around the book call (placing, replacing or reducing an order, or uncrossing an auction)
+//! instead of unwinding through the handler, and is contained to its symbol: the request gets
+//! a 500 with an incident code, the symbol is halted and the book is quarantined, while every
+//! other symbol keeps trading.
+//!
+//! The panic may have left the book half-mutated. Without a database the book is swapped for
+//! its copy in the latest snapshot file, when there is one; with a database (which is
+//! authoritative) or without a snapshot the book is left as it is. Either way the symbol stays
+//! halted: new orders are refused until an operator has checked the book and resumes trading
+//! with `PATCH /admin/symbols/{symbol}`. Each incident is logged with its code, counted in
+//! `exchange_matching_panics_total`, announced to every WebSocket client and listed by
+//! `GET /admin/incidents`.
+
+use axum::{extract::State, http::StatusCode, response::Json};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::any::Any;
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt::Write;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use uuid::Uuid;
+
+use crate::api::activity::ActivityKind;
+use crate::api::auth::AdminUser;
+use crate::api::exchange_info::RuleChangeSource;
+use crate::api::routes::{AppState, ErrorResponse, WsMessage};
+use crate::api::ws::{self, AnnouncementSeverity};
+use crate::orderbook::orderbook::OrderBook;
+use crate::persistence;
+use crate::snapshot;
+use crate::types::symbol::SymbolConfig;
+
+/// Incidents kept for `Incidents::recent`.
+const RECENT_INCIDENTS: usize = 100;
+
+/// Tag key that makes matching panic once the tagged order has traded, to exercise incident
+/// handling in tests; a tagged order can rest and panic later, when replaced across the
+/// spread. Only honored with the `fault-injection` feature, so no client can halt a symbol in
+/// production.
+#[cfg(feature = "fault-injection")]
+pub const PANIC_TAG: &str = "test_util_panic";
+
+pub type SharedIncidents = Arc<Incidents>;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Incident {
+    /// Returned to the client whose order hit the panic, and logged.
+    pub code: String,
+    pub symbol: String,
+    /// The panic message.
+    pub message: String,
+    /// Whether the book was swapped for its snapshot copy.
+    pub restored_from_snapshot: bool,
+    pub at: DateTime<Utc>,
+}
+
+#[derive(Debug, Default)]
+struct Log {
+    recent: VecDeque<Incident>,
+    /// Panics by symbol, for the metric.
+    panics: BTreeMap<String, u64>,
+}
+
+/// Matching panics caught so far.
+#[derive(Debug, Default)]
+pub struct Incidents {
+    /// Snapshot file a quarantined book is restored from; None with a database.
+    snapshot_path: Option<PathBuf>,
+    log: Mutex<Log>,
+}
+
+impl Incidents {
+    pub fn new(snapshot_path: Option<PathBuf>) -> Self {
+        Self {
+            snapshot_path,
+            log: Mutex::default(),
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Log> {
+        self.log.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn record(&self, incident: Incident) {
+        let mut log = self.lock();
+        *log.panics.entry(incident.symbol.clone()).or_default() += 1;
+        if log.recent.len() == RECENT_INCIDENTS {
+            log.recent.pop_front();
+        }
+        log.recent.push_back(incident);
+    }
+
+    /// The latest incidents, oldest first.
+    pub fn recent(&self) -> Vec<Incident> {
+        self.lock().recent.iter().cloned().collect()
+    }
+
+    /// Prometheus text exposition of the panic counters.
+    pub fn render_prometheus(&self) -> String {
+        let mut out = String::from(
+            "# HELP exchange_matching_panics_total Panics caught inside matching; each halts its symbol.\n\
+             # TYPE exchange_matching_panics_total counter\n",
+        );
+        for (symbol, count) in &self.lock().panics {
+            let _ = writeln!(
+                out,
+                "exchange_matching_panics_total{{symbol=\"{}\"}} {}",
+                symbol, count
+            );
+        }
+        out
+    }
+
+    /// `symbol`'s book as of the snapshot file, if there is one that has it.
+    fn snapshot_book(&self, symbol: &str) -> Option<OrderBook> {
+        let path = self.snapshot_path.as_ref()?;
+        match snapshot::read_file(path) {
+            Ok(Some(snapshot)) => snapshot::restore(snapshot, &[symbol])
+                .orderbooks
+                .remove(symbol),
+            Ok(None) => None,
+            Err(e) => {
+                eprintln!(
+                    "cannot restore {} from snapshot {}: {}",
+                    symbol,
+                    path.display(),
+                    e
+                );
+                None
+            }
+        }
+    }
+}
+
+/// GET /admin/incidents: the latest matching incidents, newest first.
+pub async fn list_incidents(
+    _admin: AdminUser,
+    State(state): State<AppState>,
+) -> Json<Vec<Incident>> {
+    let mut incidents = state.incidents.recent();
+    incidents.reverse();
+    Json(incidents)
+}
+
+/// Run `matching`, catching a panic; the error is the panic message.
+pub(crate) fn catch_panic<T>(matching: impl FnOnce() -> T) -> Result<T, String> {
+    std::panic::catch_unwind(std::panic::AssertUnwindSafe(matching)).map_err(panic_message)
+}
+
+fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "non-string panic payload".to_string()
+    }
+}
+
+/// Handle a panic in `symbol`'s matching: quarantine `book` (whose write lock the caller
+/// holds), halt the symbol, record and announce the incident. Returns the 500 for the order.
+pub(crate) async fn quarantine(
+    state: &AppState,
+    symbol: &str,
+    book: &mut OrderBook,
+    message: String,
+) -> (StatusCode, Json<ErrorResponse>) {
+    let simple = Uuid::new_v4().simple().to_string();
+    let code = format!("INC-{}", simple[..12].to_uppercase());
+    eprintln!(
+        "incident {}: matching panicked on {}: {}; halting the symbol",
+        code, symbol, message
+    );
+
+    let mut configs = state.symbol_configs.write().await;
+    let previous = configs
+        .get(symbol)
+        .copied()
+        .unwrap_or_else(|| SymbolConfig::for_symbol(symbol));
+    let config = SymbolConfig {
+        halted: true,
+        ..previous
+    };
+    let change = state.rules.prepare(
+        symbol,
+        &previous,
+        &config,
+        RuleChangeSource::IncidentHalt,
+        None,
+        state.clock.now(),
+    );
+    if let Some(ref change) = change {
+        state.rules.commit(change.clone());
+    }
+    configs.insert(symbol.to_string(), config);
+    drop(configs);
+
+    let restored = match state.incidents.snapshot_book(symbol) {
+        Some(mut restored) => {
+            restored.set_order_limits(book.order_limits().clone());
+            restored.set_trade_archive_capacity(book.trade_archive().capacity());
+            restored.set_pricing_policy(config.pricing_policy, config.tick_size);
+            *book = restored;
+            true
+        }
+        None => false,
+    };
+    if let Some(ref db) = state.db {
+        let saved: Result<(), sqlx::Error> = async {
+            let mut tx = db.begin().await?;
+            persistence::upsert_symbol_config(&mut *tx, symbol, &config).await?;
+            if let Some(ref change) = change {
+                persistence::insert_rule_change(&mut *tx, &change.to_row()).await?;
+            }
+            tx.commit().await
+        }
+        .await;
+        if let Err(e) = saved {
+            eprintln!(
+                "incident {}: failed to persist the halt of {}: {}",
+                code, symbol, e
+            );
+        }
+    }
+    if !previous.halted {
+        state.activity.record(ActivityKind::Halted {
+            symbol: symbol.to_string(),
+        });
+    }
+    ws::broadcast_symbol_config_update(&state.ws_channel, symbol, &config);
+    state.ws_fanout.announce(&WsMessage::Announcement {
+        message: format!(
+            "Trading in {} is halted after an internal error (incident {})",
+            symbol, code
+        ),
+        severity: AnnouncementSeverity::Critical,
+    });
+    state.incidents.record(Incident {
+        code: code.clone(),
+        symbol: symbol.to_string(),
+        message,
+        restored_from_snapshot: restored,
+        at: Utc::now(),
+    });
+
+    let (status, Json(mut response)) = ErrorResponse::new(
+        format!(
+            "Internal error while matching on {}; trading in the symbol is halted (incident {})",
+            symbol, code
+        ),
+        StatusCode::INTERNAL_SERVER_ERROR,
+    );
+    response.error_code = Some("MATCHING_INCIDENT".to_string());
+    (status, Json(response))
+}
@@ -0,0 +1,172 @@
+//! Arrival order for order entry.
+//!
+//! Each placement, cancel and replace takes a ticket for its symbol as soon as the handler
+//! knows the symbol, before any await, and `lock_book` hands out the book write lock in ticket
+//! order. A cancel admitted before a crossing order is therefore applied before it, even if the
+//! order's request reached the book lock first. The ticket is returned as `X-Ingress-Seq`, a
+//! per-symbol sequence, on success and error alike; only a request for an unknown symbol, which
+//! is turned away before admission, has none. A symbol's queue is dropped whenever no ticket of
+//! it is out, and its sequence resumes where it stopped.
+
+use axum::{
+    http::HeaderValue,
+    response::{IntoResponse, Response},
+};
+use std::collections::{BTreeSet, HashMap};
+use std::sync::{Arc, Mutex, Weak};
+use tokio::sync::watch;
+
+pub const INGRESS_SEQ_HEADER: &str = "x-ingress-seq";
+
+pub type SharedIngress = Arc<Ingress>;
+
+/// Ticket queues of the symbols with order entry in flight.
+#[derive(Debug, Default)]
+pub struct Ingress {
+    queues: Mutex<Queues>,
+}
+
+#[derive(Debug, Default)]
+struct Queues {
+    active: HashMap<String, Arc<SymbolQueue>>,
+    /// Next ticket of each symbol whose queue went idle and was dropped, so its sequence
+    /// carries on where it stopped.
+    idle: HashMap<String, u64>,
+}
+
+#[derive(Debug)]
+struct SymbolQueue {
+    symbol: String,
+    issued: Mutex<Issued>,
+    /// Lowest ticket not yet through; every lower ticket has locked the book or given up.
+    serving: watch::Sender<u64>,
+}
+
+#[derive(Debug, Default)]
+struct Issued {
+    next: u64,
+    /// Tickets at or above `serving` that are already through, out of turn (requests that
+    /// failed or timed out before their turn).
+    finished: BTreeSet<u64>,
+}
+
+impl SymbolQueue {
+    fn new(symbol: &str, next: u64) -> Self {
+        Self {
+            symbol: symbol.to_string(),
+            issued: Mutex::new(Issued {
+                next,
+                finished: BTreeSet::new(),
+            }),
+            serving: watch::Sender::new(next),
+        }
+    }
+
+    /// Every ticket issued is through.
+    fn is_idle(&self, issued: &Issued) -> bool {
+        *self.serving.borrow() == issued.next
+    }
+}
+
+impl Ingress {
+    /// Admit a request for `symbol`: its place in the symbol's queue. Callers check the
+    /// symbol first (see `service::admit`); the queue lives while it has tickets out.
+    pub fn admit(self: &Arc<Self>, symbol: &str) -> Ticket {
+        let mut queues = self.queues.lock().unwrap();
+        let queue = match queues.active.get(symbol) {
+            Some(queue) => queue.clone(),
+            None => {
+                let next = queues.idle.remove(symbol).unwrap_or(0);
+                let queue = Arc::new(SymbolQueue::new(symbol, next));
+                queues.active.insert(symbol.to_string(), queue.clone());
+                queue
+            }
+        };
+        let seq = {
+            let mut issued = queue.issued.lock().unwrap();
+            issued.next += 1;
+            issued.next - 1
+        };
+        Ticket {
+            seq,
+            queue,
+            ingress: Arc::downgrade(self),
+        }
+    }
+
+    /// Symbols with a queue in memory.
+    pub fn queued_symbols(&self) -> usize {
+        self.queues.lock().unwrap().active.len()
+    }
+
+    /// Drop `queue` if it is still `symbol`'s and no ticket is out.
+    fn release(&self, queue: &Arc<SymbolQueue>) {
+        let mut queues = self.queues.lock().unwrap();
+        let Some(current) = queues.active.get(&queue.symbol) else {
+            return;
+        };
+        if !Arc::ptr_eq(current, queue) {
+            return;
+        }
+        let issued = queue.issued.lock().unwrap();
+        if !queue.is_idle(&issued) {
+            return;
+        }
+        let next = issued.next;
+        drop(issued);
+        queues.active.remove(&queue.symbol);
+        queues.idle.insert(queue.symbol.clone(), next);
+    }
+}
+
+/// A request's place in its symbol's queue. Dropping it lets the next ticket through, whether
+/// the request got the book or gave up.
+#[derive(Debug)]
+pub struct Ticket {
+    seq: u64,
+    queue: Arc<SymbolQueue>,
+    ingress: Weak<Ingress>,
+}
+
+impl Ticket {
+    pub fn seq(&self) -> u64 {
+        self.seq
+    }
+
+    /// Wait until every earlier ticket is through.
+    pub async fn turn(&self) {
+        let mut serving = self.queue.serving.subscribe();
+        // The sender lives in `self.queue`, so this cannot fail
+        let _ = serving.wait_for(|serving| *serving >= self.seq).await;
+    }
+}
+
+impl Drop for Ticket {
+    fn drop(&mut self) {
+        let idle = {
+            let mut issued = self.queue.issued.lock().unwrap();
+            issued.finished.insert(self.seq);
+            self.queue.serving.send_if_modified(|serving| {
+                let before = *serving;
+                while issued.finished.remove(serving) {
+                    *serving += 1;
+                }
+                *serving != before
+            });
+            self.queue.is_idle(&issued)
+        };
+        // Re-checked under the queues lock: a ticket may be admitted in between
+        if idle && let Some(ingress) = self.ingress.upgrade() {
+            ingress.release(&self.queue);
+        }
+    }
+}
+
+/// `response` with the request's ticket in `X-Ingress-Seq`.
+pub fn with_seq(seq: u64, response: impl IntoResponse) -> Response {
+    let mut response = response.into_response();
+    response
+        .headers_mut()
+        .insert(INGRESS_SEQ_HEADER, HeaderValue::from(seq));
+    response
+}
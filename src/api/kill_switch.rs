@@ -0,0 +1,208 @@
+//! The account kill switch, a user's own emergency stop. `POST /account/kill-switch` cancels
+//! every resting order of the caller, on the public books and the sandboxes they use, with
+//! reason `KILL_SWITCH`, and from then on their new orders and replacements are refused with
+//! `TRADING_DISABLED`. Cancels and reads keep working. Only the user can clear it, with
+//! `POST /account/kill-switch/disable` and their password.
+//!
+//! Each change reaches the user's authenticated sockets as a `KillSwitch` message (the
+//! cancelled orders also come as `OrderClosed`), is logged, and with a database is recorded in
+//! `kill_switch_audit`. The flag is `users.trading_disabled` (or a field of the snapshot user),
+//! so it survives a restart.
+
+use axum::{extract::State, http::StatusCode, response::Json};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::sync::{Arc, RwLock};
+use uuid::Uuid;
+
+use crate::api::auth::{self, AuthUser};
+use crate::api::dto::CancelledOrderDto;
+use crate::api::lazy_books;
+use crate::api::routes::{AppState, ErrorResponse, WsMessage};
+use crate::api::service;
+use crate::persistence;
+use crate::types::order::{CloseReason, OrderId};
+
+pub type SharedKillSwitches = Arc<KillSwitches>;
+
+/// Users whose kill switch is engaged.
+#[derive(Debug, Default)]
+pub struct KillSwitches {
+    engaged: RwLock<HashSet<Uuid>>,
+}
+
+impl KillSwitches {
+    pub fn new(engaged: HashSet<Uuid>) -> Self {
+        Self {
+            engaged: RwLock::new(engaged),
+        }
+    }
+
+    pub fn is_engaged(&self, user_id: Uuid) -> bool {
+        self.read().contains(&user_id)
+    }
+
+    /// Every user with the switch engaged.
+    pub fn engaged(&self) -> HashSet<Uuid> {
+        self.read().clone()
+    }
+
+    /// Engage or clear `user_id`'s switch; false if it already was so.
+    pub fn set(&self, user_id: Uuid, engaged: bool) -> bool {
+        let mut users = self.engaged.write().unwrap_or_else(|e| e.into_inner());
+        if engaged {
+            users.insert(user_id)
+        } else {
+            users.remove(&user_id)
+        }
+    }
+
+    /// 403 `TRADING_DISABLED` while `user_id`'s switch is engaged. Callers placing an order
+    /// check under the book write lock, so none can rest after the engage pass went by.
+    pub fn check(&self, user_id: Uuid) -> Result<(), (StatusCode, Json<ErrorResponse>)> {
+        if self.is_engaged(user_id) {
+            return Err(ErrorResponse::trading_disabled());
+        }
+        Ok(())
+    }
+
+    fn read(&self) -> std::sync::RwLockReadGuard<'_, HashSet<Uuid>> {
+        self.engaged.read().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[derive(Serialize)]
+pub struct KillSwitchCancel {
+    symbol: String,
+    #[serde(flatten)]
+    order: CancelledOrderDto,
+}
+
+#[derive(Serialize)]
+pub struct KillSwitchResponse {
+    trading_disabled: bool,
+    /// Orders the engage cancelled, by symbol; empty when disabling.
+    cancelled: Vec<KillSwitchCancel>,
+}
+
+#[derive(Deserialize)]
+pub struct DisableKillSwitchRequest {
+    password: String,
+}
+
+/// POST /account/kill-switch: refuse the caller's new orders and cancel every order of theirs
+/// still resting. Engaging it again cancels whatever rests by then.
+pub async fn engage(
+    auth: AuthUser,
+    State(state): State<AppState>,
+) -> Result<Json<KillSwitchResponse>, (StatusCode, Json<ErrorResponse>)> {
+    if let Some(ref db) = state.db {
+        persistence::set_trading_disabled(db, auth.user_id, true)
+            .await
+            .map_err(|e| ErrorResponse::from_db("Failed to engage the kill switch", e))?;
+    }
+    // Set before the cancel pass: an order placed after it reaches a book is refused there
+    state.kill_switches.set(auth.user_id, true);
+    let cancelled = cancel_all_orders(&state, auth.user_id).await;
+    announce(&state, auth.user_id, true, cancelled.len()).await;
+    Ok(Json(KillSwitchResponse {
+        trading_disabled: true,
+        cancelled,
+    }))
+}
+
+/// POST /account/kill-switch/disable: accept the caller's orders again, once they confirm
+/// with their password. Disabling a switch that is not engaged is not an error.
+pub async fn disable(
+    auth: AuthUser,
+    State(state): State<AppState>,
+    Json(body): Json<DisableKillSwitchRequest>,
+) -> Result<Json<KillSwitchResponse>, (StatusCode, Json<ErrorResponse>)> {
+    let password_hash = state
+        .user_store
+        .read()
+        .await
+        .values()
+        .find(|cred| cred.user_id == auth.user_id)
+        .map(|cred| cred.password_hash.clone())
+        .ok_or_else(|| ErrorResponse::new("User not found".to_string(), StatusCode::NOT_FOUND))?;
+    if !auth::verify_password(body.password.trim(), &password_hash) {
+        return Err(ErrorResponse::new(
+            "Invalid password".to_string(),
+            StatusCode::UNAUTHORIZED,
+        ));
+    }
+    if let Some(ref db) = state.db {
+        persistence::set_trading_disabled(db, auth.user_id, false)
+            .await
+            .map_err(|e| ErrorResponse::from_db("Failed to disable the kill switch", e))?;
+    }
+    if state.kill_switches.set(auth.user_id, false) {
+        announce(&state, auth.user_id, false, 0).await;
+    }
+    Ok(Json(KillSwitchResponse {
+        trading_disabled: false,
+        cancelled: Vec::new(),
+    }))
+}
+
+/// Cancel `user_id`'s resting orders on every public book and every sandbox they use, in
+/// symbol order. Lazy books are loaded first, or their stored orders would come back live
+/// with the load; one that cannot be loaded is skipped.
+async fn cancel_all_orders(state: &AppState, user_id: Uuid) -> Vec<KillSwitchCancel> {
+    let mut books: Vec<_> = state
+        .orderbooks
+        .iter()
+        .map(|(symbol, orderbook)| (symbol.clone(), orderbook.clone()))
+        .chain(state.sandboxes.books_of(user_id).await)
+        .collect();
+    books.sort_by(|(a, _), (b, _)| a.cmp(b));
+    let mut cancelled = Vec::new();
+    for (symbol, orderbook) in books {
+        if !lazy_books::ensure_loaded(state, &symbol).await {
+            continue;
+        }
+        let mut book = orderbook.write().await;
+        let order_ids: Vec<OrderId> = book
+            .iter_orders()
+            .map(|(_, order)| order)
+            .filter(|order| order.user_id == user_id)
+            .map(|order| order.id)
+            .collect();
+        let closed = service::close_orders(
+            state,
+            &symbol,
+            &mut book,
+            order_ids,
+            CloseReason::KillSwitch,
+        )
+        .await;
+        cancelled.extend(closed.into_iter().map(|order| KillSwitchCancel {
+            symbol: symbol.clone(),
+            order: order.into(),
+        }));
+    }
+    cancelled
+}
+
+/// Tell the user's sockets, log and audit a change of their switch. The switch has already
+/// changed, so a failed audit insert is only logged.
+async fn announce(state: &AppState, user_id: Uuid, engaged: bool, cancelled_orders: usize) {
+    let action = if engaged { "engaged" } else { "disabled" };
+    eprintln!(
+        "kill switch {} by user {}: {} order(s) cancelled",
+        action, user_id, cancelled_orders
+    );
+    let _ = state.ws_channel.send(WsMessage::KillSwitch {
+        user_id,
+        trading_disabled: engaged,
+        cancelled_orders,
+        at: state.clock.now(),
+    });
+    if let Some(ref db) = state.db
+        && let Err(e) =
+            persistence::insert_kill_switch_audit(db, user_id, action, cancelled_orders).await
+    {
+        eprintln!("failed to audit the kill switch of user {}: {}", user_id, e);
+    }
+}
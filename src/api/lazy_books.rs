@@ -0,0 +1,102 @@
+//! Books hydrated on first use. A symbol in `LAZY_SYMBOLS` is served from startup with its
+//! config and an empty book, and the first request that looks the book up (an order, a
+//! subscription, a read) restores its open orders, trade sequence and order deadlines from the
+//! database before it goes on. Each book has its own `OnceCell`, so concurrent first requests
+//! wait for one load instead of restoring the orders twice; other books are not held up.
+//!
+//! A load that fails in strict hydration is not kept: the request finds the book unavailable
+//! and the next one tries again. Otherwise, like at startup, what failed is logged and the book
+//! is served with what was restored.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use tokio::sync::OnceCell;
+
+use crate::api::expiry;
+use crate::api::routes::AppState;
+use crate::hydration::{HydrationReport, StoredBook};
+use crate::skew::SkewWindow;
+
+pub type SharedLazyBooks = Arc<LazyBooks>;
+
+/// The books still to load on first use, and how to load them.
+#[derive(Debug, Default)]
+pub struct LazyBooks {
+    books: HashMap<String, OnceCell<()>>,
+    strict: bool,
+    skew: SkewWindow,
+    loads: AtomicU64,
+}
+
+impl LazyBooks {
+    pub fn new(symbols: impl IntoIterator<Item = String>, strict: bool, skew: SkewWindow) -> Self {
+        Self {
+            books: symbols
+                .into_iter()
+                .map(|symbol| (symbol, OnceCell::new()))
+                .collect(),
+            strict,
+            skew,
+            loads: AtomicU64::new(0),
+        }
+    }
+
+    /// Whether `symbol`'s book is served lazily and not loaded yet.
+    pub fn is_pending(&self, symbol: &str) -> bool {
+        self.books
+            .get(symbol)
+            .is_some_and(|loaded| !loaded.initialized())
+    }
+
+    /// Loads run so far, failed ones included.
+    pub fn loads(&self) -> u64 {
+        self.loads.load(Ordering::Relaxed)
+    }
+}
+
+/// Load `symbol`'s book unless it is loaded already (or not lazy). False if the load failed
+/// in strict hydration; the book must not be used until a later call loads it.
+pub async fn ensure_loaded(state: &AppState, symbol: &str) -> bool {
+    let lazy = &state.lazy_books;
+    let Some(loaded) = lazy.books.get(symbol) else {
+        return true;
+    };
+    if loaded.initialized() {
+        return true;
+    }
+    let result = loaded
+        .get_or_try_init(|| async {
+            lazy.loads.fetch_add(1, Ordering::Relaxed);
+            load(state, symbol).await
+        })
+        .await;
+    match result {
+        Ok(()) => true,
+        Err(report) => {
+            eprintln!("lazy hydration of {} failed: {}", symbol, report);
+            false
+        }
+    }
+}
+
+async fn load(state: &AppState, symbol: &str) -> Result<(), HydrationReport> {
+    let (Some(db), Some(orderbook)) = (&state.db, state.orderbooks.get(symbol)) else {
+        return Ok(());
+    };
+    let lazy = &state.lazy_books;
+    let mut report = HydrationReport::default();
+    // Read before taking the lock; requests for the book wait on the cell meanwhile
+    let stored = StoredBook::read(db, symbol, &lazy.skew, state.clock.now(), &mut report).await;
+    if lazy.strict && !report.is_clean() {
+        return Err(report);
+    }
+    stored.restore(&mut *orderbook.write().await, &mut report);
+    match expiry::track_stored(state, db, symbol).await {
+        Ok(tracked) if tracked > 0 => eprintln!("{}: {} order deadlines tracked", symbol, tracked),
+        Ok(_) => {}
+        Err(e) => report.errors.push(format!("track order deadlines: {}", e)),
+    }
+    eprintln!("lazy hydration: {}", report);
+    Ok(())
+}
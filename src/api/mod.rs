@@ -1,3 +1,31 @@
+pub mod activity;
+pub mod admin;
 pub mod auth;
+pub mod book_cache;
+pub mod book_diffs;
+pub mod deadline;
+pub mod delisting;
+pub mod dto;
+pub mod exchange_info;
+pub mod expiry;
+pub mod exposure;
+pub mod fanout;
+pub mod fields;
+pub mod idempotency;
+pub mod incidents;
+pub mod ingress;
+pub mod kill_switch;
+pub mod lazy_books;
+pub mod pagination;
+pub mod phase_timer;
 pub mod routes;
+pub mod sandbox;
+pub mod service;
+pub mod spreads;
+pub mod sse;
+pub mod statements;
+pub mod surveillance;
+pub mod validation;
+pub mod webhooks;
 pub mod ws;
+pub mod ws_connections;
@@ -0,0 +1,169 @@
+//! Keyset pagination shared by list endpoints: a `Page<T>` envelope, opaque cursors and the
+//! `Link: <...>; rel="next"` header.
+//!
+//! A cursor is the base64 (URL-safe, unpadded) JSON of the last item's sort key, followed by
+//! `.` and a truncated HMAC-SHA256 of it under the server's secret. The MAC also covers the
+//! endpoint's scope, so a cursor is only accepted by the listing that issued it and clients
+//! cannot craft one to jump to arbitrary positions. Endpoints fetch `limit + 1` rows after the
+//! decoded key; the extra row only tells whether there is a next page.
+
+use axum::{
+    Json,
+    http::{HeaderValue, Uri, header},
+    response::{IntoResponse, Response},
+};
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use sha2::Sha256;
+use std::fmt;
+
+use crate::types::symbol::ScaleMeta;
+
+/// Bytes of the HMAC kept in a cursor.
+const MAC_LEN: usize = 16;
+
+/// One page of a listing. `next_cursor` is set exactly when `has_more` is; pass it back as
+/// `cursor` (with the same filters) for the following page.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    pub next_cursor: Option<String>,
+    pub has_more: bool,
+    /// Matching items across all pages, for listings that can count them cheaply.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub total: Option<u64>,
+    /// Scales of the items' numbers, for listings confined to one symbol.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub meta: Option<ScaleMeta>,
+}
+
+impl<T> Page<T> {
+    /// The page for `items` fetched with a limit of `limit + 1`: at most `limit` are kept, and
+    /// if one was dropped the next cursor is `cursor(last kept item)`.
+    pub fn from_overfetch(
+        mut items: Vec<T>,
+        limit: usize,
+        cursor: impl FnOnce(&T) -> String,
+    ) -> Self {
+        let has_more = items.len() > limit;
+        items.truncate(limit);
+        let next_cursor = if has_more {
+            items.last().map(cursor)
+        } else {
+            None
+        };
+        Self {
+            has_more: next_cursor.is_some(),
+            items,
+            next_cursor,
+            total: None,
+            meta: None,
+        }
+    }
+
+    pub fn with_meta(self, meta: ScaleMeta) -> Self {
+        Self {
+            meta: Some(meta),
+            ..self
+        }
+    }
+
+    pub fn map<U>(self, f: impl FnMut(T) -> U) -> Page<U> {
+        Page {
+            items: self.items.into_iter().map(f).collect(),
+            next_cursor: self.next_cursor,
+            has_more: self.has_more,
+            total: self.total,
+            meta: self.meta,
+        }
+    }
+}
+
+impl<T: Serialize> Page<T> {
+    /// JSON body, with a `Link` header to the next page when there is one. The link is `uri`
+    /// with its `cursor` parameter replaced.
+    pub fn respond(self, uri: &Uri) -> Response {
+        let link = self
+            .next_cursor
+            .as_deref()
+            .and_then(|cursor| HeaderValue::from_str(&next_link(uri, cursor)).ok());
+        let mut response = Json(self).into_response();
+        if let Some(link) = link {
+            response.headers_mut().insert(header::LINK, link);
+        }
+        response
+    }
+}
+
+/// `Link` header value pointing at the page after `cursor`.
+pub fn next_link(uri: &Uri, cursor: &str) -> String {
+    let mut query: Vec<&str> = uri
+        .query()
+        .unwrap_or_default()
+        .split('&')
+        .filter(|pair| !pair.is_empty() && *pair != "cursor" && !pair.starts_with("cursor="))
+        .collect();
+    let cursor_pair = format!("cursor={}", cursor);
+    query.push(&cursor_pair);
+    format!("<{}?{}>; rel=\"next\"", uri.path(), query.join("&"))
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CursorError {
+    /// Not a cursor this server could have issued.
+    Malformed,
+    /// Well-formed, but altered or issued for another listing.
+    Forged,
+}
+
+impl fmt::Display for CursorError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Malformed => write!(f, "Invalid cursor: malformed"),
+            Self::Forged => write!(f, "Invalid cursor: not issued for this listing"),
+        }
+    }
+}
+
+type HmacSha256 = Hmac<Sha256>;
+
+fn mac(secret: &[u8], scope: &str, payload: &str) -> HmacSha256 {
+    let mut mac = HmacSha256::new_from_slice(secret).expect("HMAC accepts keys of any length");
+    mac.update(b"cursor:");
+    mac.update(scope.as_bytes());
+    mac.update(b":");
+    mac.update(payload.as_bytes());
+    mac
+}
+
+/// Opaque cursor for `key` on the listing named `scope`, signed with `secret`.
+pub fn encode_cursor<K: Serialize>(secret: &[u8], scope: &str, key: &K) -> String {
+    let json = serde_json::to_vec(key).expect("cursor keys serialize");
+    let payload = URL_SAFE_NO_PAD.encode(json);
+    let tag = mac(secret, scope, &payload).finalize().into_bytes();
+    format!("{}.{}", payload, URL_SAFE_NO_PAD.encode(&tag[..MAC_LEN]))
+}
+
+/// The key `encode_cursor` put in `cursor`, if it was issued for `scope` under `secret`.
+pub fn decode_cursor<K: DeserializeOwned>(
+    secret: &[u8],
+    scope: &str,
+    cursor: &str,
+) -> Result<K, CursorError> {
+    let (payload, tag) = cursor.split_once('.').ok_or(CursorError::Malformed)?;
+    let tag = URL_SAFE_NO_PAD
+        .decode(tag)
+        .map_err(|_| CursorError::Malformed)?;
+    if tag.len() != MAC_LEN {
+        return Err(CursorError::Malformed);
+    }
+    mac(secret, scope, payload)
+        .verify_truncated_left(&tag)
+        .map_err(|_| CursorError::Forged)?;
+    let json = URL_SAFE_NO_PAD
+        .decode(payload)
+        .map_err(|_| CursorError::Malformed)?;
+    serde_json::from_slice(&json).map_err(|_| CursorError::Malformed)
+}
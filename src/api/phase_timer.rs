@@ -0,0 +1,177 @@
+//! Where the time of a request goes. A `PhaseTimer` splits one request into named phases as
+//! it runs; `PhaseTimings` keeps a histogram per (operation, phase) across requests, exported
+//! with `/admin/metrics`.
+//!
+//! Order placement is timed in `lock_wait`, `match` (validation, matching and balance locks;
+//! book updates are broadcast from inside matching), `positions`, `broadcast` (position
+//! updates) and `persist`. A request that sends `X-Debug-Timings` gets its own breakdown back
+//! in that header when `DEBUG_TIMINGS` is set or the caller is an admin.
+
+use axum::http::{HeaderMap, HeaderValue};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+pub const DEBUG_TIMINGS_HEADER: &str = "x-debug-timings";
+
+/// Upper bounds (µs) of the phase histogram buckets; a final bucket counts everything slower.
+pub const PHASE_BUCKETS_US: [u64; 8] = [10, 100, 1_000, 5_000, 10_000, 50_000, 100_000, 1_000_000];
+
+/// Durations of the phases of one request, in the order they first ran.
+#[derive(Debug, Clone)]
+pub struct PhaseTimer {
+    last: Instant,
+    phases: Vec<(&'static str, Duration)>,
+}
+
+impl Default for PhaseTimer {
+    fn default() -> Self {
+        Self::start()
+    }
+}
+
+impl PhaseTimer {
+    pub fn start() -> Self {
+        Self {
+            last: Instant::now(),
+            phases: Vec::new(),
+        }
+    }
+
+    /// End `phase` now: it took the time since the previous phase ended (or since the timer
+    /// started or skipped). A phase ended more than once adds up.
+    pub fn end(&mut self, phase: &'static str) {
+        let now = Instant::now();
+        let elapsed = now - self.last;
+        self.last = now;
+        match self.phases.iter_mut().find(|(name, _)| *name == phase) {
+            Some((_, total)) => *total += elapsed,
+            None => self.phases.push((phase, elapsed)),
+        }
+    }
+
+    /// Leave the time since the previous phase out of every phase.
+    pub fn skip(&mut self) {
+        self.last = Instant::now();
+    }
+
+    pub fn phases(&self) -> &[(&'static str, Duration)] {
+        &self.phases
+    }
+
+    /// `phase;dur=<ms>` for each phase, comma-separated (the `Server-Timing` syntax).
+    pub fn header_value(&self) -> String {
+        let mut out = String::new();
+        for (i, (phase, elapsed)) in self.phases.iter().enumerate() {
+            if i > 0 {
+                out.push_str(", ");
+            }
+            let _ = write!(out, "{};dur={:.3}", phase, elapsed.as_secs_f64() * 1_000.0);
+        }
+        out
+    }
+}
+
+/// Cumulative durations of one phase of one operation.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PhaseHistogram {
+    pub count: u64,
+    pub sum_us: u64,
+    /// Counts per `PHASE_BUCKETS_US` bucket (not cumulative), plus one overflow bucket.
+    pub buckets: Vec<u64>,
+}
+
+#[derive(Debug, Default)]
+pub struct PhaseTimings {
+    debug_header: bool,
+    histograms: Mutex<BTreeMap<(&'static str, &'static str), PhaseHistogram>>,
+}
+
+impl PhaseTimings {
+    /// With `debug_header`, anyone may ask for `X-Debug-Timings`; otherwise only admins.
+    pub fn new(debug_header: bool) -> Self {
+        Self {
+            debug_header,
+            histograms: Mutex::default(),
+        }
+    }
+
+    /// Whether a request with `headers` from a caller (`admin` or not) gets the header back.
+    pub fn wants_header(&self, headers: &HeaderMap, admin: bool) -> bool {
+        (self.debug_header || admin) && headers.contains_key(DEBUG_TIMINGS_HEADER)
+    }
+
+    /// `timer`'s breakdown as an `X-Debug-Timings` value.
+    pub fn header(timer: &PhaseTimer) -> (&'static str, HeaderValue) {
+        let value = HeaderValue::from_str(&timer.header_value())
+            .unwrap_or_else(|_| HeaderValue::from_static(""));
+        (DEBUG_TIMINGS_HEADER, value)
+    }
+
+    /// Add each phase of `timer` to `operation`'s histograms.
+    pub fn record(&self, operation: &'static str, timer: &PhaseTimer) {
+        let mut histograms = self.histograms.lock().unwrap_or_else(|e| e.into_inner());
+        for &(phase, elapsed) in timer.phases() {
+            let histogram = histograms.entry((operation, phase)).or_default();
+            if histogram.buckets.is_empty() {
+                histogram.buckets = vec![0; PHASE_BUCKETS_US.len() + 1];
+            }
+            let us = elapsed.as_micros() as u64;
+            let bucket = PHASE_BUCKETS_US
+                .iter()
+                .position(|&bound| us < bound)
+                .unwrap_or(PHASE_BUCKETS_US.len());
+            histogram.buckets[bucket] += 1;
+            histogram.count += 1;
+            histogram.sum_us += us;
+        }
+    }
+
+    pub fn snapshot(&self) -> BTreeMap<(&'static str, &'static str), PhaseHistogram> {
+        self.histograms
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    /// Prometheus text exposition of the phase histograms.
+    pub fn render_prometheus(&self) -> String {
+        let mut out = String::new();
+        let name = "exchange_request_phase_seconds";
+        let _ = writeln!(
+            out,
+            "# HELP {} Time requests spent in each phase of an operation.\n# TYPE {} histogram",
+            name, name
+        );
+        for ((operation, phase), histogram) in &self.snapshot() {
+            let labels = format!("operation=\"{}\",phase=\"{}\"", operation, phase);
+            let mut cumulative = 0;
+            for (i, bound) in PHASE_BUCKETS_US.iter().enumerate() {
+                cumulative += histogram.buckets.get(i).copied().unwrap_or(0);
+                let _ = writeln!(
+                    out,
+                    "{}_bucket{{{},le=\"{}\"}} {}",
+                    name,
+                    labels,
+                    *bound as f64 / 1_000_000.0,
+                    cumulative
+                );
+            }
+            let _ = writeln!(
+                out,
+                "{}_bucket{{{},le=\"+Inf\"}} {}",
+                name, labels, histogram.count
+            );
+            let _ = writeln!(
+                out,
+                "{}_sum{{{}}} {}",
+                name,
+                labels,
+                histogram.sum_us as f64 / 1_000_000.0
+            );
+            let _ = writeln!(out, "{}_count{{{}}} {}", name, labels, histogram.count);
+        }
+        out
+    }
+}
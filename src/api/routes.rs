@@ -1,25 +1,91 @@
 use axum::{
     Router,
-    extract::{FromRequestParts, Path, Query, State},
+    body::Bytes,
+    extract::{FromRequestParts, OriginalUri, Path, Query, State},
     http::StatusCode,
     http::request::Parts,
-    response::Json,
-    routing::{delete, get, post},
+    http::{HeaderMap, header},
+    response::{IntoResponse, Json, Response},
+    middleware,
+    routing::{delete, get, patch, post, put},
 };
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::sync::{broadcast, RwLock};
+use tower_http::compression::CompressionLayer;
 use uuid::Uuid;
 
-use crate::api::auth::{self, AuthUser, AuthUserCredential};
-use crate::api::ws::ws_handler;
-use crate::orderbook::orderbook::SharedOrderBook;
-use crate::persistence;
-use crate::positions::{self, SharedPositions};
-use crate::types::order::{Order, OrderSide, OrderStatus, OrderType};
+use crate::api::admin;
+use crate::api::activity::{self, ActivityFeed};
+use crate::api::book_cache::BookCache;
+use crate::api::book_diffs;
+use crate::api::deadline::{LockWaitMetrics, RequestDeadline, lock_book};
+use crate::api::delisting::{self, SharedDelistings};
+use crate::api::exchange_info::{self, SharedRulesLog};
+use crate::api::dto::{
+    self, BookLevelDto, BookSnapshotDto, CancelledOrderDto, ExecutionReportDto, OrderDto, OrderFillDto, PositionDto,
+    ReplacedOrderDto, TradeDto, UserTradeDto, WithMeta,
+};
+use crate::api::expiry::{self, SharedOrderExpiry};
+use crate::api::exposure;
+use crate::api::fanout::SharedFanout;
+use crate::api::fields::FieldSelection;
+use crate::api::auth::{self, Account, AdminUser, AuthUser, AuthUserCredential, UsernameHistory};
+use crate::api::idempotency::{SharedIdempotencyCache, idempotency_middleware};
+use crate::api::incidents::{self, SharedIncidents};
+use crate::api::ingress::{SharedIngress, Ticket, with_seq};
+use crate::api::kill_switch::{self, SharedKillSwitches};
+use crate::api::lazy_books::{self, SharedLazyBooks};
+use crate::api::pagination::{self, CursorError, Page};
+use crate::api::phase_timer::{PhaseTimer, PhaseTimings};
+use crate::api::sandbox;
+use crate::api::service;
+use crate::api::spreads::{self, SharedSpreads};
+use crate::api::sse;
+use crate::api::statements;
+use crate::api::surveillance::{self, SharedSurveillance};
+use crate::api::validation;
+use crate::api::webhooks;
+use crate::api::ws::{self, AnnouncementSeverity, ws_handler};
+use crate::api::ws_connections::SharedWsConnections;
+use crate::balances::{Balance, SharedBalances};
+use crate::engine::{Exchange, PlaceOrder};
+use crate::faults::SharedFaultPlan;
+use crate::hydration::HydrationReport;
+use crate::market_data::{
+    CANDLE_INTERVAL_SECS, Candle, RollingStats, SharedMarketData, TickerPoint,
+};
+use crate::orderbook::book_stats::SharedBookStatsGauges;
+use crate::orderbook::clock::SharedClock;
+use crate::orderbook::closed_orders::ClosedOrder;
+use crate::orderbook::diff_buffer::BookDiff;
+use crate::orderbook::orderbook::{ExecutionReport, OrderBook, SharedOrderBook};
+use crate::persistence::{self, PersistenceError, TradePersistenceMetrics};
+use crate::orderbook::order_limits::SharedOrderLimits;
+use crate::orderbook::trade_archive::TradesEvicted;
+use crate::positions::{self, AppliedTrades, PositionDelta, SharedPositions};
+use crate::pricefeed::{self, SharedIndexPrices};
+use crate::replica::{self, ServerRole};
+use crate::retention::SharedRetention;
+use crate::sandbox::{SharedSandboxes, is_sandbox_symbol};
+use crate::tasks::SharedSupervisor;
+use crate::types::asset::{ASSETS, AssetInfo};
+use crate::types::decimal::{self, DecimalError, QuantityInput};
+use crate::types::environment::Environment;
+use crate::types::order::{
+    CloseReason, Order, OrderId, OrderSide, OrderSource, OrderStatus, OrderTags, OrderType, Price,
+    Qty, RejectReason, total_quantity,
+};
+use crate::types::money::{self, Notional, apply_bps, notional};
 use crate::types::position::Position;
-use crate::types::trade::Trade;
+use crate::types::symbol::{
+    ScaleMeta, Symbol, SymbolConfig, SymbolError, SymbolHint, SymbolStatus, optional_symbol,
+};
+use crate::types::trade::{OrderFill, Trade, TradeAttribution, TradeRole, UserTrade};
+use crate::webhooks::SharedWebhooks;
 
 // WebSocket message type for broadcasting
 #[derive(Debug, Clone, Serialize)]
@@ -27,13 +93,104 @@ use crate::types::trade::Trade;
 pub enum WsMessage {
     OrderBookUpdate {
         symbol: String,
+        #[serde(serialize_with = "dto::serialize_levels")]
         bids: Vec<(i64, u64)>,
+        #[serde(serialize_with = "dto::serialize_levels")]
         asks: Vec<(i64, u64)>,
+        /// The change since the last update, for `diffs` subscriptions, which get it as an
+        /// `OrderBookDiff` instead of the levels. None for snapshots sent outside the stream.
+        #[serde(skip)]
+        diff: Option<BookDiff>,
     },
     Trade {
         symbol: String,
+        #[serde(serialize_with = "dto::serialize_as::<TradeDto, _, _>")]
         trade: Trade,
     },
+    AuctionResult {
+        symbol: String,
+        #[serde(with = "crate::types::string_i64::option")]
+        clearing_price: Option<i64>,
+        #[serde(with = "crate::types::string_i64")]
+        volume: u64,
+    },
+    IndexPrice {
+        symbol: String,
+        price: i64,
+        timestamp: DateTime<Utc>,
+    },
+    /// Private: delivered only to sockets authenticated as the order's owner.
+    OrderUpdate {
+        symbol: String,
+        #[serde(serialize_with = "dto::serialize_as::<ExecutionReportDto, _, _>")]
+        report: ExecutionReport,
+    },
+    /// Sent once to a socket when it subscribes to the symbol: how to read the prices and
+    /// quantities of its messages.
+    ExchangeInfo {
+        symbol: String,
+        meta: ScaleMeta,
+    },
+    /// The symbol's trading rules changed; clients should re-fetch them.
+    SymbolConfigUpdate {
+        symbol: String,
+        config: SymbolConfig,
+    },
+    /// A delisting started (`CancelOnly`, taking cancels only until `delist_at`) or completed
+    /// (`Delisted`: the symbol is gone).
+    SymbolStatus {
+        symbol: String,
+        status: SymbolStatus,
+        delist_at: Option<DateTime<Utc>>,
+    },
+    /// Private: one of the owner's orders was cancelled by someone else (see
+    /// `CloseReason::notifies_owner`), delivered to the owner's authenticated sockets.
+    OrderClosed {
+        symbol: String,
+        order_id: OrderId,
+        user_id: Uuid,
+        status: OrderStatus,
+        reason: CloseReason,
+        remaining_qty: u64,
+    },
+    /// Private: an admin busted a trade `user_id` was a party to; delivered to each party's
+    /// authenticated sockets. Their positions follow as `PositionUpdate`s.
+    TradeBusted {
+        symbol: String,
+        trade_id: Uuid,
+        user_id: Uuid,
+        role: TradeRole,
+        reason: String,
+        busted_at: DateTime<Utc>,
+    },
+    /// Private: the owner's position after a fill (quantity 0 once closed), delivered to the
+    /// owner's sockets subscribed to positions.
+    PositionUpdate {
+        #[serde(serialize_with = "dto::serialize_as::<PositionDto, _, _>")]
+        position: Position,
+        mark_price: Option<Price>,
+        #[serde(with = "crate::types::string_i64::option")]
+        unrealized_pnl: Option<i64>,
+    },
+    /// An admin asked the symbol's subscribers to drop their book and rebuild it from the
+    /// `OrderBookUpdate` snapshot that follows.
+    Resync {
+        symbol: String,
+    },
+    /// An operator notice for every connected socket, subscribed or not. Sent with
+    /// `Fanout::announce`, not through the channel.
+    Announcement {
+        message: String,
+        severity: AnnouncementSeverity,
+    },
+    /// Private: the user engaged (cancelling `cancelled_orders`) or disabled their account
+    /// kill switch, delivered to their authenticated sockets.
+    KillSwitch {
+        user_id: Uuid,
+        trading_disabled: bool,
+        cancelled_orders: usize,
+        at: DateTime<Utc>,
+    },
 }
 
 /// In-memory user store keyed by lowercase username.
@@ -48,6 +205,92 @@ pub struct AppState {
     pub jwt_secret: Vec<u8>,
     pub user_store: UserStore,
     pub db: Option<sqlx::PgPool>,
+    /// User ids allowed to call /admin endpoints.
+    pub admin_user_ids: HashSet<Uuid>,
+    pub index_prices: SharedIndexPrices,
+    /// Index prices older than this are reported stale and not used as a reference.
+    pub index_price_max_age: Duration,
+    pub idempotency: SharedIdempotencyCache,
+    /// WS session id for orders placed with `session_scope` (cancel-on-disconnect scoping).
+    pub order_sessions: SharedOrderSessions,
+    /// Retention settings and progress (runs need `db`).
+    pub retention: SharedRetention,
+    /// Outcome of startup hydration (served at /admin/hydration).
+    pub hydration_report: Arc<HydrationReport>,
+    pub trade_metrics: Arc<TradePersistenceMetrics>,
+    /// Usernames released by renames, reserved from other users during the cooldown.
+    pub username_history: Arc<UsernameHistory>,
+    /// Serialized `GET /book` bodies keyed by book sequence.
+    pub book_cache: Arc<BookCache>,
+    /// Open WebSocket connections (served at /admin/ws/connections).
+    pub ws_connections: SharedWsConnections,
+    /// Trading rules per symbol. Taken after the symbol's book lock when both are held.
+    pub symbol_configs: SharedSymbolConfigs,
+    /// Budget for order requests, and the cap on `X-Request-Timeout-Ms`.
+    pub request_timeout: Duration,
+    /// Book write lock waits of order requests (exported with /admin/metrics).
+    pub lock_waits: Arc<LockWaitMetrics>,
+    /// Per-phase durations of order placement (exported with /admin/metrics).
+    pub phase_timings: Arc<PhaseTimings>,
+    /// Last sampled per-book stats (exported with /admin/metrics).
+    pub book_stats: SharedBookStatsGauges,
+    /// Spread orders with both legs linked.
+    pub spreads: SharedSpreads,
+    /// Deadlines of orders placed with `refresh_ttl_ms`.
+    pub order_expiry: SharedOrderExpiry,
+    /// Panics caught inside matching, each of which halted its symbol.
+    pub incidents: SharedIncidents,
+    /// Recent candles and ticker points, written only by the market data aggregator.
+    pub market_data: SharedMarketData,
+    /// Resting order caps; every book in `orderbooks` and every sandbox counts against these.
+    pub order_limits: SharedOrderLimits,
+    /// Private in-memory books, reachable only by their owners and invited users.
+    pub sandboxes: SharedSandboxes,
+    /// Available and locked balances per asset; only tracked when enabled.
+    pub balances: SharedBalances,
+    /// The paper environment's balances, apart from the live ones in `balances`.
+    pub paper_balances: SharedBalances,
+    /// Arrival order of order entry per symbol; the book lock is handed out in this order.
+    pub ingress: SharedIngress,
+    /// Per-symbol feeds serializing `ws_channel` messages once for every WebSocket.
+    pub ws_fanout: SharedFanout,
+    /// Recent public events for `GET /activity`.
+    pub activity: Arc<ActivityFeed>,
+    /// Registered webhooks and their delivery tasks.
+    pub webhooks: SharedWebhooks,
+    /// A `MarketDataReplica` rejects every mutating request.
+    pub role: ServerRole,
+    /// Failures tests inject into persistence and WebSocket delivery; empty outside tests.
+    pub faults: SharedFaultPlan,
+    /// Time for handlers and background tasks (expiry, candles, tokens). The system clock
+    /// outside tests; books keep their own, which should be the same one.
+    pub clock: SharedClock,
+    /// Symbols being delisted or delisted since startup; the latter are hidden everywhere.
+    pub delistings: SharedDelistings,
+    /// Users who engaged their account kill switch; their order entry is refused.
+    pub kill_switches: SharedKillSwitches,
+    /// Background tasks, to list them and stop them in order on shutdown.
+    pub tasks: SharedSupervisor,
+    /// The version of the symbols' trading rules and its recent changes (`/exchangeInfo`).
+    pub rules: SharedRulesLog,
+    /// Rolling place/cancel/fill metrics per user and symbol, and the alerts they raised.
+    pub surveillance: SharedSurveillance,
+    /// Books in `orderbooks` whose open orders are restored on first use.
+    pub lazy_books: SharedLazyBooks,
+}
+
+/// Order id -> WS session id that owns it for cancel-on-disconnect.
+pub type SharedOrderSessions = Arc<RwLock<HashMap<OrderId, Uuid>>>;
+
+pub type SharedSymbolConfigs = Arc<RwLock<HashMap<String, SymbolConfig>>>;
+
+/// Reference price for valuing positions: the fresh index price, else the book's last trade.
+pub(crate) async fn mark_price(state: &AppState, symbol: &Symbol, book: &OrderBook) -> Option<Price> {
+    match pricefeed::fresh_index_price(&state.index_prices, symbol, state.index_price_max_age).await
+    {
+        Some(price) => Some(price),
+        None => book.last_trade_price(),
+    }
 }
 
 // Error response structure
@@ -55,6 +298,12 @@ pub struct AppState {
 pub struct ErrorResponse {
     pub error: String,
     pub code: u16,
+    /// Machine-readable error code, e.g. `QUERY_TIMEOUT` for a database statement timeout.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error_code: Option<String>,
+    /// The symbols that do exist, on `UNKNOWN_SYMBOL` errors.
+    #[serde(flatten, skip_serializing_if = "Option::is_none")]
+    pub symbols: Option<SymbolHint>,
 }
 
 impl ErrorResponse {
@@ -64,9 +313,191 @@ impl ErrorResponse {
             Json(Self {
                 error: message,
                 code: status_code.as_u16(),
+                error_code: None,
+                symbols: None,
+            }),
+        )
+    }
+
+    /// Order rejection, with the reason as `error_code`. Rejections the client can fix by
+    /// changing the request are 400s; an unknown symbol is a 404 like on every other route.
+    pub fn rejected(reason: RejectReason) -> (StatusCode, Json<Self>) {
+        let status_code = match reason {
+            RejectReason::UnknownSymbol => StatusCode::NOT_FOUND,
+            RejectReason::MissingSymbol
+            | RejectReason::MarketOrderInAuction
+            | RejectReason::NoLiquidity
+            | RejectReason::SymbolHalted
+            | RejectReason::SymbolCancelOnly
+            | RejectReason::InvalidTickSize
+            | RejectReason::InvalidLotSize
+            | RejectReason::PriceOutsideBand
+            | RejectReason::InvalidQuantity
+            | RejectReason::BelowMinQuantity
+            | RejectReason::InsufficientBalance => StatusCode::BAD_REQUEST,
+            // Capacity, not the request: the same order may rest once others leave
+            RejectReason::BookFull => StatusCode::SERVICE_UNAVAILABLE,
+        };
+        (
+            status_code,
+            Json(Self {
+                error: reason.to_string(),
+                code: status_code.as_u16(),
+                error_code: Some(reason.code().to_string()),
+                symbols: None,
+            }),
+        )
+    }
+
+    /// 404 `UNKNOWN_SYMBOL` for `symbol`, with `hint` on the symbols that do exist.
+    pub fn unknown_symbol(symbol: &str, hint: SymbolHint) -> (StatusCode, Json<Self>) {
+        let (status_code, Json(mut response)) = Self::rejected(RejectReason::UnknownSymbol);
+        response.error = format!("Symbol '{}' not found", symbol);
+        response.symbols = Some(hint);
+        (status_code, Json(response))
+    }
+
+    /// A symbol that failed `Symbol::parse`: a missing one is the `MISSING_SYMBOL` rejection,
+    /// a malformed one `INVALID_SYMBOL` naming the problem.
+    pub fn invalid_symbol(e: SymbolError) -> (StatusCode, Json<Self>) {
+        if e == SymbolError::Empty {
+            return Self::rejected(RejectReason::MissingSymbol);
+        }
+        let status_code = StatusCode::BAD_REQUEST;
+        (
+            status_code,
+            Json(Self {
+                error: e.to_string(),
+                code: status_code.as_u16(),
+                error_code: Some("INVALID_SYMBOL".to_string()),
+                symbols: None,
+            }),
+        )
+    }
+
+    /// The request's deadline passed before it could reach the matching engine.
+    pub fn engine_busy() -> (StatusCode, Json<Self>) {
+        let status_code = StatusCode::SERVICE_UNAVAILABLE;
+        (
+            status_code,
+            Json(Self {
+                error: "Matching engine busy, request deadline exceeded".to_string(),
+                code: status_code.as_u16(),
+                error_code: Some("ENGINE_BUSY".to_string()),
+                symbols: None,
+            }),
+        )
+    }
+
+    /// 403 `READ_ONLY_REPLICA`: this instance only serves market data.
+    pub fn read_only_replica() -> (StatusCode, Json<Self>) {
+        let status_code = StatusCode::FORBIDDEN;
+        (
+            status_code,
+            Json(Self {
+                error: "This instance is a read-only market data replica".to_string(),
+                code: status_code.as_u16(),
+                error_code: Some("READ_ONLY_REPLICA".to_string()),
+                symbols: None,
+            }),
+        )
+    }
+
+    /// 403 `TRADING_DISABLED`: the user's account kill switch is engaged.
+    pub fn trading_disabled() -> (StatusCode, Json<Self>) {
+        let status_code = StatusCode::FORBIDDEN;
+        (
+            status_code,
+            Json(Self {
+                error: "Trading is disabled by the account kill switch; disable it with \
+                        POST /account/kill-switch/disable"
+                    .to_string(),
+                code: status_code.as_u16(),
+                error_code: Some("TRADING_DISABLED".to_string()),
+                symbols: None,
+            }),
+        )
+    }
+
+    /// 400 `INVALID_CURSOR`: a `cursor` parameter this server did not issue for the listing.
+    pub fn invalid_cursor(e: CursorError) -> (StatusCode, Json<Self>) {
+        let status_code = StatusCode::BAD_REQUEST;
+        (
+            status_code,
+            Json(Self {
+                error: e.to_string(),
+                code: status_code.as_u16(),
+                error_code: Some("INVALID_CURSOR".to_string()),
+                symbols: None,
+            }),
+        )
+    }
+
+    /// 410 `TRADES_EVICTED`: trades asked for by sequence that the in-memory archive no longer
+    /// holds; the message names the oldest sequence it can still serve.
+    pub fn trades_evicted(e: TradesEvicted) -> (StatusCode, Json<Self>) {
+        let status_code = StatusCode::GONE;
+        (
+            status_code,
+            Json(Self {
+                error: e.to_string(),
+                code: status_code.as_u16(),
+                error_code: Some("TRADES_EVICTED".to_string()),
+                symbols: None,
+            }),
+        )
+    }
+
+    /// 400 `INVALID_QUANTITY` for a decimal quantity that cannot be converted to lots exactly.
+    pub fn invalid_quantity(e: DecimalError) -> (StatusCode, Json<Self>) {
+        let status_code = StatusCode::BAD_REQUEST;
+        (
+            status_code,
+            Json(Self {
+                error: format!("Invalid quantity: {}", e),
+                code: status_code.as_u16(),
+                error_code: Some(RejectReason::InvalidQuantity.code().to_string()),
+                symbols: None,
+            }),
+        )
+    }
+
+    /// 400 `SPREAD_LEG_WOULD_TRADE`: spread legs must rest on entry, so a failed second leg
+    /// can be undone by cancelling the first.
+    pub fn spread_leg_would_trade(symbol: &str) -> (StatusCode, Json<Self>) {
+        let status_code = StatusCode::BAD_REQUEST;
+        (
+            status_code,
+            Json(Self {
+                error: format!("Spread leg on {} would trade on entry", symbol),
+                code: status_code.as_u16(),
+                error_code: Some("SPREAD_LEG_WOULD_TRADE".to_string()),
+                symbols: None,
             }),
         )
     }
+
+    /// Map a database error: timeouts become 503 `QUERY_TIMEOUT`, anything else a 500 with `message`.
+    pub fn from_db(message: &str, e: sqlx::Error) -> (StatusCode, Json<Self>) {
+        match PersistenceError::from(e) {
+            PersistenceError::Timeout(e) => {
+                eprintln!("{}: {}", message, e);
+                let status_code = StatusCode::SERVICE_UNAVAILABLE;
+                (
+                    status_code,
+                    Json(Self {
+                        error: "Database query timed out".to_string(),
+                        code: status_code.as_u16(),
+                        error_code: Some("QUERY_TIMEOUT".to_string()),
+                        symbols: None,
+                    }),
+                )
+            }
+            PersistenceError::Db(_) => {
+                Self::new(message.to_string(), StatusCode::INTERNAL_SERVER_ERROR)
+            }
+        }
+    }
 }
 
 impl FromRequestParts<AppState> for AuthUser {
@@ -92,7 +523,7 @@ impl FromRequestParts<AppState> for AuthUser {
                 StatusCode::UNAUTHORIZED,
             )
         })?;
-        let claims = auth::decode_token(&state.jwt_secret, token).map_err(|_| {
+        let claims = auth::decode_token(&state.jwt_secret, token, state.clock.now()).map_err(|_| {
             ErrorResponse::new(
                 "Invalid or expired token".to_string(),
                 StatusCode::UNAUTHORIZED,
@@ -105,22 +536,139 @@ impl FromRequestParts<AppState> for AuthUser {
     }
 }
 
+impl FromRequestParts<AppState> for AdminUser {
+    type Rejection = (StatusCode, Json<ErrorResponse>);
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &AppState,
+    ) -> Result<Self, Self::Rejection> {
+        let user = AuthUser::from_request_parts(parts, state).await?;
+        if !state.admin_user_ids.contains(&user.user_id) {
+            return Err(ErrorResponse::new(
+                "Forbidden: admin only".to_string(),
+                StatusCode::FORBIDDEN,
+            ));
+        }
+        Ok(AdminUser {
+            user_id: user.user_id,
+        })
+    }
+}
+
 // Helper function to get orderbook by symbol
-fn get_orderbook(
+/// Public book for `symbol`, loaded if it is lazy. Sandbox books are never returned here;
+/// endpoints that serve them look them up for the caller with `find_orderbook`.
+pub(crate) async fn get_orderbook(
     state: &AppState,
-    symbol: &str,
+    symbol: &Symbol,
 ) -> Result<SharedOrderBook, (StatusCode, Json<ErrorResponse>)> {
-    let normalized_symbol = symbol.to_uppercase();
-    state
+    let orderbook = state
         .orderbooks
-        .get(&normalized_symbol)
+        .get(symbol.as_str())
+        .filter(|_| !state.delistings.is_delisted(symbol))
         .cloned()
-        .ok_or_else(|| {
-            ErrorResponse::new(
-                format!("Symbol '{}' not found", normalized_symbol),
-                StatusCode::NOT_FOUND,
-            )
-        })
+        .ok_or_else(|| unknown_symbol(state, symbol))?;
+    if !lazy_books::ensure_loaded(state, symbol).await {
+        return Err(ErrorResponse::new(
+            format!("Order book for '{}' could not be loaded", symbol),
+            StatusCode::SERVICE_UNAVAILABLE,
+        ));
+    }
+    Ok(orderbook)
+}
+
+/// 404 `UNKNOWN_SYMBOL` listing the public symbols (sandboxes stay private) and the closest
+/// one to `symbol`.
+pub(crate) fn unknown_symbol(state: &AppState, symbol: &str) -> (StatusCode, Json<ErrorResponse>) {
+    ErrorResponse::unknown_symbol(symbol, symbol_hint(state, symbol))
+}
+
+/// The public symbols as a hint for the unknown `symbol`.
+pub(crate) fn symbol_hint(state: &AppState, symbol: &str) -> SymbolHint {
+    SymbolHint::new(symbol, listed_orderbooks(state).map(|(symbol, _)| symbol.as_str()))
+}
+
+/// The public live books of symbols that have not been delisted.
+pub(crate) fn listed_orderbooks(
+    state: &AppState,
+) -> impl Iterator<Item = (&String, &SharedOrderBook)> {
+    environment_orderbooks(state, Environment::Live)
+}
+
+/// The public books of `environment` whose symbols have not been delisted.
+pub(crate) fn environment_orderbooks(
+    state: &AppState,
+    environment: Environment,
+) -> impl Iterator<Item = (&String, &SharedOrderBook)> {
+    state.orderbooks.iter().filter(move |(symbol, _)| {
+        Environment::of(symbol) == environment && !state.delistings.is_delisted(symbol)
+    })
+}
+
+/// Book for `symbol` as seen by `user_id`: a public book (loaded if it is lazy), or a sandbox
+/// the user owns or was invited to. Anyone else's sandbox, and a lazy book that could not be
+/// loaded, look like an unknown symbol.
+pub(crate) async fn find_orderbook(
+    state: &AppState,
+    symbol: &str,
+    user_id: Option<Uuid>,
+) -> Option<SharedOrderBook> {
+    if let Some(orderbook) = state.orderbooks.get(symbol) {
+        let served = !state.delistings.is_delisted(symbol)
+            && lazy_books::ensure_loaded(state, symbol).await;
+        return served.then(|| orderbook.clone());
+    }
+    state
+        .sandboxes
+        .book_for(symbol, user_id?, Utc::now())
+        .await
+}
+
+/// Books a user's own orders and trades are read from: `symbol`'s (as `find_orderbook` sees
+/// it), or every public book of `environment` plus, for live, the user's sandboxes.
+async fn user_orderbooks(
+    state: &AppState,
+    symbol: Option<&Symbol>,
+    environment: Environment,
+    user_id: Uuid,
+) -> Result<Vec<SharedOrderBook>, (StatusCode, Json<ErrorResponse>)> {
+    let Some(symbol) = symbol else {
+        let mut books: Vec<SharedOrderBook> = environment_orderbooks(state, environment)
+            .map(|(_, book)| book.clone())
+            .collect();
+        if environment == Environment::Live {
+            let sandboxes = state.sandboxes.books_of(user_id).await;
+            books.extend(sandboxes.into_iter().map(|(_, book)| book));
+        }
+        return Ok(books);
+    };
+    find_orderbook(state, symbol, Some(user_id))
+        .await
+        .map(|book| vec![book])
+        .ok_or_else(|| unknown_symbol(state, symbol))
+}
+
+/// What a listing of the caller's own orders, trades, positions or balances covers: `symbol`
+/// moved into `environment` when both are given, and the environment listed (the symbol's,
+/// else `environment`, else live).
+fn listing_scope(
+    symbol: Option<Symbol>,
+    environment: Option<Environment>,
+) -> Result<(Option<Symbol>, Environment), (StatusCode, Json<ErrorResponse>)> {
+    match (symbol, environment) {
+        (Some(symbol), Some(environment)) => {
+            let symbol = symbol
+                .in_environment(environment)
+                .map_err(ErrorResponse::invalid_symbol)?;
+            Ok((Some(symbol), environment))
+        }
+        (Some(symbol), None) => {
+            let environment = symbol.environment();
+            Ok((Some(symbol), environment))
+        }
+        (None, environment) => Ok((None, environment.unwrap_or_default())),
+    }
 }
 
 async fn health() -> &'static str {
@@ -153,12 +701,7 @@ async fn register(
     }
     let key = username.to_lowercase();
     let mut store = state.user_store.write().await;
-    if store.get(&key).is_some() {
-        return Err(ErrorResponse::new(
-            "Username already taken".to_string(),
-            StatusCode::BAD_REQUEST,
-        ));
-    }
+    ensure_username_available(&state, &store, &key, None).await?;
     let password_hash = auth::hash_password(password).map_err(|_| {
         ErrorResponse::new(
             "Failed to hash password".to_string(),
@@ -166,21 +709,14 @@ async fn register(
         )
     })?;
     let user_id = Uuid::new_v4();
+    let created_at = Utc::now();
     if let Some(ref db) = state.db {
-        persistence::insert_user(db, user_id, &key, &password_hash)
+        persistence::insert_user(db, user_id, &key, &password_hash, created_at)
             .await
-            .map_err(|_| {
-                ErrorResponse::new(
-                    "Failed to create user".to_string(),
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                )
-            })?;
+            .map_err(|e| ErrorResponse::from_db("Failed to create user", e))?;
     }
-    let credential = AuthUserCredential {
-        user_id,
-        username: username.to_string(),
-        password_hash,
-    };
+    let credential =
+        AuthUserCredential::new(user_id, username.to_string(), password_hash, created_at);
     store.insert(key, credential);
     Ok((
         StatusCode::CREATED,
@@ -191,18 +727,110 @@ async fn register(
     ))
 }
 
+/// Reject `key` if it belongs to a user, or was released by someone other than `user_id`
+/// within the username cooldown.
+async fn ensure_username_available(
+    state: &AppState,
+    store: &HashMap<String, AuthUserCredential>,
+    key: &str,
+    user_id: Option<Uuid>,
+) -> Result<(), (StatusCode, Json<ErrorResponse>)> {
+    if store.contains_key(key) {
+        return Err(ErrorResponse::new(
+            "Username already taken".to_string(),
+            StatusCode::BAD_REQUEST,
+        ));
+    }
+    let released_by = if let Some(ref db) = state.db {
+        persistence::username_released_by(db, key, state.username_history.cutoff())
+            .await
+            .map_err(|e| ErrorResponse::from_db("Failed to look up username history", e))?
+    } else {
+        state.username_history.held_by(key).await
+    };
+    if released_by.is_some() && released_by != user_id {
+        return Err(ErrorResponse::new(
+            "Username was recently released and is not yet available".to_string(),
+            StatusCode::BAD_REQUEST,
+        ));
+    }
+    Ok(())
+}
+
+#[derive(Deserialize)]
+struct ChangeUsernameRequest {
+    new_username: String,
+    password: String,
+}
+
+#[derive(Serialize)]
+struct ChangeUsernameResponse {
+    user_id: Uuid,
+    username: String,
+    previous_username: String,
+}
+
+/// Rename the caller. The store entry is re-keyed under the write lock, so the old and new
+/// names are never both (or neither) registered; tokens are keyed by user id and stay valid.
+async fn change_username(
+    auth: AuthUser,
+    State(state): State<AppState>,
+    Json(body): Json<ChangeUsernameRequest>,
+) -> Result<Json<ChangeUsernameResponse>, (StatusCode, Json<ErrorResponse>)> {
+    let username = body.new_username.trim();
+    if username.is_empty() {
+        return Err(ErrorResponse::new(
+            "Username is required".to_string(),
+            StatusCode::BAD_REQUEST,
+        ));
+    }
+    let key = username.to_lowercase();
+    let mut store = state.user_store.write().await;
+    let (old_key, credential) = store
+        .iter()
+        .find(|(_, cred)| cred.user_id == auth.user_id)
+        .map(|(k, cred)| (k.clone(), cred.clone()))
+        .ok_or_else(|| ErrorResponse::new("User not found".to_string(), StatusCode::NOT_FOUND))?;
+    if !auth::verify_password(body.password.trim(), &credential.password_hash) {
+        return Err(ErrorResponse::new(
+            "Invalid password".to_string(),
+            StatusCode::UNAUTHORIZED,
+        ));
+    }
+    // A case-only change keeps the same key and releases nothing
+    if key != old_key {
+        ensure_username_available(&state, &store, &key, Some(auth.user_id)).await?;
+        if let Some(ref db) = state.db {
+            persistence::change_username(db, auth.user_id, &old_key, &key)
+                .await
+                .map_err(|e| ErrorResponse::from_db("Failed to change username", e))?;
+        }
+        state.username_history.release(&old_key, auth.user_id).await;
+        store.remove(&old_key);
+    }
+    store.insert(
+        key,
+        AuthUserCredential {
+            username: username.to_string(),
+            ..credential.clone()
+        },
+    );
+    Ok(Json(ChangeUsernameResponse {
+        user_id: auth.user_id,
+        username: username.to_string(),
+        previous_username: credential.username,
+    }))
+}
+
 async fn login(
     State(state): State<AppState>,
     Json(body): Json<LoginRequest>,
 ) -> Result<Json<LoginResponse>, (StatusCode, Json<ErrorResponse>)> {
     let key = body.username.trim().to_lowercase();
     let user_id = if let Some(ref db) = state.db {
-        let user_row = persistence::get_user_by_username(db, &key).await.map_err(|_| {
-            ErrorResponse::new(
-                "Failed to look up user".to_string(),
-                StatusCode::INTERNAL_SERVER_ERROR,
-            )
-        })?;
+        let user_row = persistence::get_user_by_username(db, &key)
+            .await
+            .map_err(|e| ErrorResponse::from_db("Failed to look up user", e))?;
         let user_row = user_row.ok_or_else(|| {
             ErrorResponse::new(
                 "Invalid username or password".to_string(),
@@ -232,18 +860,60 @@ async fn login(
         }
         cred.user_id
     };
-    let token = auth::create_token(&state.jwt_secret, user_id).map_err(|_| {
+    let token = auth::create_token(&state.jwt_secret, user_id, state.clock.now()).map_err(|_| {
         ErrorResponse::new(
             "Failed to create token".to_string(),
             StatusCode::INTERNAL_SERVER_ERROR,
         )
     })?;
+    record_login(&state, &key, user_id).await?;
     Ok(Json(LoginResponse {
         token,
         user_id,
     }))
 }
 
+/// Stamp a successful login on the user's row and their store entry, keeping the two in step.
+async fn record_login(
+    state: &AppState,
+    key: &str,
+    user_id: Uuid,
+) -> Result<(), (StatusCode, Json<ErrorResponse>)> {
+    let at = Utc::now();
+    let mut store = state.user_store.write().await;
+    let login_count = match state.db {
+        Some(ref db) => Some(
+            persistence::touch_last_login(db, user_id, at)
+                .await
+                .map_err(|e| ErrorResponse::from_db("Failed to record login", e))?
+                .max(0) as u64,
+        ),
+        None => None,
+    };
+    if let Some(credential) = store.get_mut(key)
+        && credential.user_id == user_id
+    {
+        credential.last_login_at = Some(at);
+        credential.login_count = login_count.unwrap_or(credential.login_count + 1);
+    }
+    Ok(())
+}
+
+/// GET /auth/me: the caller's account metadata.
+async fn get_me(
+    auth: AuthUser,
+    State(state): State<AppState>,
+) -> Result<Json<Account>, (StatusCode, Json<ErrorResponse>)> {
+    state
+        .user_store
+        .read()
+        .await
+        .values()
+        .find(|credential| credential.user_id == auth.user_id)
+        .map(|credential| Json(credential.account()))
+        .ok_or_else(|| ErrorResponse::new("User not found".to_string(), StatusCode::NOT_FOUND))
+}
+
 #[derive(Deserialize)]
 struct LoginRequest {
     username: String,
@@ -256,196 +926,723 @@ struct LoginResponse {
     user_id: Uuid,
 }
 
-#[derive(Deserialize)]
-struct CreateOrderRequest {
-    symbol: String,
-    price: i64,
-    quantity: u64,
-    side: OrderSide,
+#[derive(Debug, Deserialize)]
+pub(crate) struct CreateOrderRequest {
+    pub(crate) symbol: String,
+    pub(crate) price: i64,
+    /// Lots, or a decimal string of the base asset (see `QuantityInput`).
+    pub(crate) quantity: QuantityInput,
+    pub(crate) side: OrderSide,
+    #[serde(default)]
+    pub(crate) order_type: OrderType,
+    /// WS session id (from the cancel_on_disconnect ack); the order is then cancelled only
+    /// when that session disconnects.
+    #[serde(default)]
+    pub(crate) session_scope: Option<Uuid>,
+    /// Client labels (max 5 keys, values up to 64 characters), returned with the order.
+    #[serde(default)]
+    pub(crate) tags: OrderTags,
+    /// Limit orders only: cancel the order unless it is refreshed (see `api::expiry`) at
+    /// least this often.
     #[serde(default)]
-    order_type: OrderType,
+    pub(crate) refresh_ttl_ms: Option<u64>,
+    /// Order source is assigned by the route; present only so a client-supplied value can be
+    /// rejected rather than silently ignored.
+    #[serde(default)]
+    pub(crate) source: Option<serde::de::IgnoredAny>,
+    /// Place the order on the symbol's book in this environment; same as an `@paper` symbol.
+    #[serde(default)]
+    pub(crate) environment: Option<Environment>,
+}
+
+impl CreateOrderRequest {
+    /// The book the order is for: `symbol`, in `environment` when one is given.
+    pub(crate) fn book_symbol(&self) -> Result<Symbol, SymbolError> {
+        let symbol = Symbol::parse(&self.symbol)?;
+        match self.environment {
+            Some(environment) => symbol.in_environment(environment),
+            None => Ok(symbol),
+        }
+    }
 }
 
 async fn create_order(
     auth: AuthUser,
-    State(state): State<AppState>,
+    deadline: RequestDeadline,
+    State(exchange): State<Exchange>,
+    headers: HeaderMap,
     Json(body): Json<CreateOrderRequest>,
-) -> Result<Json<Order>, (StatusCode, Json<ErrorResponse>)> {
-    if body.symbol.is_empty() {
-        return Err(ErrorResponse::new(
-            "Symbol parameter is required".to_string(),
-            StatusCode::BAD_REQUEST,
-        ));
+) -> Response {
+    let state = exchange.state();
+    let mut timer = PhaseTimer::start();
+    let normalized_symbol = match body.book_symbol() {
+        Ok(symbol) => symbol,
+        Err(e) => return ErrorResponse::invalid_symbol(e).into_response(),
+    };
+    let ticket = match service::admit(state, &normalized_symbol, auth.user_id).await {
+        Ok(ticket) => ticket,
+        Err(e) => return e.into_response(),
+    };
+    let seq = ticket.seq();
+    let placed = submit_order_request(
+        &exchange,
+        auth.user_id,
+        normalized_symbol,
+        body,
+        ticket,
+        &deadline,
+        &mut timer,
+    )
+    .await;
+    let mut response = with_seq(seq, placed.map(Json));
+    let admin = state.admin_user_ids.contains(&auth.user_id);
+    if state.phase_timings.wants_header(&headers, admin) {
+        let (name, value) = PhaseTimings::header(&timer);
+        response.headers_mut().insert(name, value);
     }
+    response
+}
+
+/// Validate `body` (whose symbol parsed as `symbol`) and place it for `user_id` in `ticket`'s
+/// turn: `POST /orders` and the WebSocket `place_order` message.
+pub(crate) async fn submit_order_request(
+    exchange: &Exchange,
+    user_id: Uuid,
+    symbol: Symbol,
+    body: CreateOrderRequest,
+    ticket: Ticket,
+    deadline: &RequestDeadline,
+    timer: &mut PhaseTimer,
+) -> Result<OrderResponse, (StatusCode, Json<ErrorResponse>)> {
+    let (_, violations) = validation::request_violations(&body);
+    validation::first_violation(violations)?;
+    if let Some(violation) = expiry::ttl_violation(body.order_type, body.refresh_ttl_ms) {
+        return Err(violation);
+    }
+    let config = service::symbol_config(exchange.state(), &symbol).await;
+    let quantity = body
+        .quantity
+        .to_lots(config.qty_scale)
+        .map_err(ErrorResponse::invalid_quantity)?;
+
+    let order = PlaceOrder {
+        order_type: body.order_type,
+        tags: body.tags,
+        refresh_ttl: body.refresh_ttl_ms.map(Duration::from_millis),
+        session_scope: body.session_scope,
+        ..PlaceOrder::limit(symbol, body.side, body.price, quantity)
+    };
+    let report = exchange
+        .submit(user_id, order, ticket, deadline, timer)
+        .await?;
+    Ok(OrderResponse::new(report.order, &config))
+}
+
+/// An order as returned to its owner, with its open quantity also as a decimal of the base
+/// asset (per the symbol's `qty_scale`) and the symbol's scales.
+#[derive(Serialize)]
+pub(crate) struct OrderResponse {
+    #[serde(flatten)]
+    order: OrderDto,
+    quantity_decimal: String,
+    meta: ScaleMeta,
+}
+
+impl OrderResponse {
+    pub(crate) fn new(order: Order, config: &SymbolConfig) -> Self {
+        Self {
+            quantity_decimal: decimal::format_scaled(order.quantity.into(), config.qty_scale),
+            order: order.into(),
+            meta: config.meta(),
+        }
+    }
+}
 
-    let normalized_symbol = body.symbol.to_uppercase();
-    let orderbook = get_orderbook(&state, &normalized_symbol)?;
-    let (order, trades) = {
-        let mut book = orderbook.write().await;
-        book.add_order(
+/// An order's fills, oldest first, with the quantity they filled and its volume-weighted
+/// average price (None before the first fill).
+#[derive(Serialize)]
+struct OrderFillsResponse {
+    order_id: OrderId,
+    fills: Vec<OrderFillDto>,
+    #[serde(with = "crate::types::string_i64")]
+    filled_quantity: Qty,
+    #[serde(with = "crate::types::string_i64::option")]
+    average_price: Option<Price>,
+    meta: ScaleMeta,
+}
+
+impl OrderFillsResponse {
+    fn new(order_id: OrderId, fills: Vec<OrderFill>, config: &SymbolConfig) -> Self {
+        let filled_quantity = total_quantity(fills.iter().map(|f| f.trade.quantity));
+        let average_price = fills
+            .iter()
+            .try_fold(Notional::ZERO, |total, f| {
+                total.checked_add(notional(f.trade.price, f.trade.quantity))
+            })
+            .and_then(|total| money::average_price(total, filled_quantity))
+            .ok();
+        Self {
+            order_id,
+            fills: fills.into_iter().map(OrderFillDto::from).collect(),
+            filled_quantity,
+            average_price,
+            meta: config.meta(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct OrderPreviewResponse {
+    trades: Vec<TradeDto>,
+    filled_quantity: u64,
+    remaining_quantity: u64,
+    average_price: Option<i64>,
+    status: OrderStatus,
+    position_change: i64,
+    resulting_position: Option<PositionDto>,
+}
+
+/// Dry-run of POST /orders: same validation and matching walk, but nothing is mutated,
+/// persisted, or broadcast.
+async fn preview_order(
+    auth: AuthUser,
+    State(state): State<AppState>,
+    Json(body): Json<CreateOrderRequest>,
+) -> Result<Json<OrderPreviewResponse>, (StatusCode, Json<ErrorResponse>)> {
+    let (symbol, violations) = validation::request_violations(&body);
+    validation::first_violation(violations)?;
+    if let Some(violation) = expiry::ttl_violation(body.order_type, body.refresh_ttl_ms) {
+        return Err(violation);
+    }
+    let normalized_symbol = symbol.expect("a symbol that failed to parse is a violation");
+    let quantity = body
+        .quantity
+        .to_lots(service::symbol_config(&state, &normalized_symbol).await.qty_scale)
+        .map_err(ErrorResponse::invalid_quantity)?;
+
+    let orderbook = find_orderbook(&state, &normalized_symbol, Some(auth.user_id))
+        .await
+        .ok_or_else(|| unknown_symbol(&state, &normalized_symbol))?;
+    let ExecutionReport {
+        order,
+        trades,
+        rejection,
+    } = {
+        let book = orderbook.read().await;
+        let (violations, _) = validation::book_violations(
+            &state,
+            &normalized_symbol,
+            &book,
+            None,
+            auth.user_id,
+            (body.side, body.order_type, body.price, quantity),
+            None,
+        )
+        .await;
+        validation::first_violation(violations)?;
+        book.simulate_order(
             auth.user_id,
             body.price,
-            body.quantity,
+            quantity,
             body.side,
             body.order_type,
-            Some(&state.ws_channel),
-            Some(&normalized_symbol),
         )
     };
 
-    if body.order_type == OrderType::Market && trades.is_empty() {
-        return Err(ErrorResponse::new(
-            "Market order could not be filled: no liquidity".to_string(),
-            StatusCode::BAD_REQUEST,
-        ));
+    if let Some(reason) = rejection {
+        return Err(ErrorResponse::rejected(reason));
     }
 
-    // Update positions for each trade (taker = order.side, maker = opposite)
-    let maker_side = match order.side {
-        OrderSide::Buy => OrderSide::Sell,
-        OrderSide::Sell => OrderSide::Buy,
+    let filled_quantity: u64 = trades.iter().map(|t| t.quantity).sum();
+    let average_price = trades
+        .iter()
+        .try_fold(Notional::ZERO, |total, t| {
+            total.checked_add(notional(t.price, t.quantity))
+        })
+        .and_then(|total| money::average_price(total, filled_quantity))
+        .ok();
+    let position_change = match order.side {
+        OrderSide::Buy => filled_quantity as i64,
+        OrderSide::Sell => -(filled_quantity as i64),
     };
+
+    let mut resulting_position =
+        positions::get_positions(&state.positions, auth.user_id, Some(&normalized_symbol))
+            .await
+            .into_iter()
+            .next();
     for trade in &trades {
-        positions::update_position(
-            &state.positions,
-            trade.maker_user_id,
-            &normalized_symbol,
-            maker_side,
-            trade.price,
-            trade.quantity,
-        )
-        .await;
-        positions::update_position(
-            &state.positions,
-            trade.taker_user_id,
+        resulting_position = positions::apply_fill(
+            resulting_position.as_ref(),
+            auth.user_id,
             &normalized_symbol,
             order.side,
             trade.price,
             trade.quantity,
+            trade.timestamp,
+        );
+    }
+
+    Ok(Json(OrderPreviewResponse {
+        trades: trades.into_iter().map(TradeDto::from).collect(),
+        filled_quantity,
+        remaining_quantity: order.quantity,
+        average_price,
+        status: order.status,
+        position_change,
+        resulting_position: resulting_position.map(PositionDto::from),
+    }))
+}
+
+/// Apply `trades`, all taken by `taker_user_id` on `taker_side`, to positions and attribute
+/// to each its fees (from `symbol`'s config) and the P&L it realized for both sides. The
+/// attributions are kept on `book` next to its trades and returned for persistence; the
+/// trades also go to the activity feed.
+pub(crate) async fn settle_trades(
+    state: &AppState,
+    symbol: &Symbol,
+    book: &mut OrderBook,
+    taker_user_id: Uuid,
+    taker_side: OrderSide,
+    trades: &[Trade],
+) -> (Vec<PositionDelta>, Vec<TradeAttribution>) {
+    let AppliedTrades { deltas, realized } =
+        positions::apply_trades(&state.positions, taker_user_id, taker_side, symbol, trades)
+            .await;
+    if trades.is_empty() {
+        return (deltas, Vec::new());
+    }
+    state.activity.record_trades(symbol, trades);
+    let config = service::symbol_config(state, symbol).await;
+    // Fees and P&L are quote amounts: price times quantity, over the quantity scale
+    let fee = |trade: &Trade, bps| {
+        apply_bps(
+            money::scaled_notional(trade.price, trade.quantity, config.qty_scale),
+            bps,
         )
-        .await;
+        .map_or(i64::MAX, Notional::to_i64_saturating)
+    };
+    let quote = |pnl: i64| pnl / config.qty_scale.max(1) as i64;
+    let attributions: Vec<TradeAttribution> = trades
+        .iter()
+        .zip(realized)
+        .map(|(trade, pnl)| TradeAttribution {
+            trade_id: trade.id,
+            taker_side,
+            maker_fee: fee(trade, config.maker_fee_bps),
+            taker_fee: fee(trade, config.taker_fee_bps),
+            realized_pnl_maker: quote(pnl.maker),
+            realized_pnl_taker: quote(pnl.taker),
+        })
+        .collect();
+    book.record_attributions(attributions.iter().copied());
+    surveillance::on_fills(state, symbol, taker_user_id, taker_side, trades);
+    spreads::on_fills(state, trades).await;
+    // Orders a trade took out of the book no longer rest in any session
+    let filled: Vec<OrderId> = trades
+        .iter()
+        .flat_map(|t| [t.maker_order_id, t.taker_order_id])
+        .filter(|&order_id| book.get_order_by_id(order_id).is_none())
+        .collect();
+    let mut order_sessions = state.order_sessions.write().await;
+    for order_id in filled {
+        order_sessions.remove(&order_id);
     }
+    drop(order_sessions);
+    (deltas, attributions)
+}
 
-    if let Some(ref db) = state.db {
-        let _ = persistence::insert_order(
-            db,
+/// Status and open quantity of `order_ids`, resting orders that just traded, as `book` now
+/// has them: an order no longer in the book was filled completely.
+pub(crate) fn filled_states(
+    book: &OrderBook,
+    order_ids: impl IntoIterator<Item = OrderId>,
+) -> Vec<(OrderId, OrderStatus, Qty)> {
+    let order_ids: BTreeSet<OrderId> = order_ids.into_iter().collect();
+    order_ids
+        .into_iter()
+        .map(|order_id| match book.get_order_by_id(order_id) {
+            Some(order) => (order_id, order.status, order.quantity),
+            None => (order_id, OrderStatus::Filled, 0),
+        })
+        .collect()
+}
+
+/// Persist one matching step in a single transaction: the order it replaced (if any, closed
+/// with its open quantity and reason like a cancel), the new order (if any, with `close_reason` when it was rejected), the state
+/// of the resting orders its trades filled (`filled`, see `filled_states`), its trades, and
+/// the resulting positions exactly as returned by the in-memory update (closed positions are
+/// deleted). Trades already stored (e.g. on a retry) are skipped and counted in `metrics`.
+#[allow(clippy::too_many_arguments)]
+pub(crate) async fn persist_fills(
+    db: &sqlx::PgPool,
+    replaced: Option<&ClosedOrder>,
+    order: Option<&Order>,
+    close_reason: Option<RejectReason>,
+    filled: &[(OrderId, OrderStatus, Qty)],
+    symbol: &str,
+    trades: &[Trade],
+    attributions: &[TradeAttribution],
+    deltas: &[PositionDelta],
+    metrics: &TradePersistenceMetrics,
+) -> Result<(), sqlx::Error> {
+    let mut tx = db.begin().await?;
+    if let Some(replaced) = replaced {
+        persistence::cancel_order(
+            &mut *tx,
+            replaced.order.id,
+            replaced.order.quantity,
+            replaced.reason.code(),
+        )
+        .await?;
+    }
+    if let Some(order) = order {
+        persistence::insert_order(
+            &mut *tx,
             order.id,
             order.user_id,
-            &normalized_symbol,
+            symbol,
             order.side,
             order.order_type,
             order.price,
             order.quantity,
             order.status,
             order.timestamp,
+            &order.tags,
+            order.source,
+            close_reason.map(RejectReason::code),
         )
-        .await;
-        for trade in &trades {
-            let _ = persistence::insert_trade(
-                db,
-                trade.id,
-                trade.maker_order_id,
-                trade.taker_order_id,
-                trade.maker_user_id,
-                trade.taker_user_id,
-                &normalized_symbol,
-                trade.price,
-                trade.quantity,
-                trade.timestamp,
+        .await?;
+    }
+    for &(order_id, status, quantity) in filled {
+        persistence::update_order_state(&mut *tx, order_id, status, quantity).await?;
+    }
+    let inserted = persistence::insert_trades_bulk(&mut *tx, symbol, trades, attributions).await?;
+    for delta in deltas {
+        if delta.closed {
+            persistence::delete_position(&mut *tx, delta.user_id, &delta.symbol).await?;
+        } else {
+            persistence::upsert_position(
+                &mut *tx,
+                delta.user_id,
+                &delta.symbol,
+                delta.new_qty,
+                delta.new_avg,
+                delta.opened_at,
+                delta.updated_at,
             )
-            .await;
-        }
-        let mut keys = std::collections::HashSet::new();
-        keys.insert((order.user_id, normalized_symbol.clone()));
-        for t in &trades {
-            keys.insert((t.maker_user_id, normalized_symbol.clone()));
-            keys.insert((t.taker_user_id, normalized_symbol.clone()));
-        }
-        for (uid, sym) in keys {
-            let pos_list =
-                positions::get_positions(&state.positions, uid, Some(&sym)).await;
-            if let Some(pos) = pos_list.into_iter().next() {
-                let _ = persistence::upsert_position(
-                    db,
-                    uid,
-                    &sym,
-                    pos.quantity,
-                    pos.average_price,
-                )
-                .await;
-            }
+            .await?;
         }
     }
-
-    Ok(Json(order))
+    tx.commit().await?;
+    metrics.record_insert(trades.len(), inserted);
+    Ok(())
 }
 
 #[derive(Deserialize)]
 struct OrderQuery {
-    symbol: String,
+    symbol: Symbol,
 }
 
+/// Cancel a resting order; 200 with the order as it left the book, so the client learns how
+/// much was still open. Also 200 when another cancel (a retry, the expiry sweeper, an admin)
+/// took it off first, with the reason it closed with.
 async fn cancel_order(
     auth: AuthUser,
+    deadline: RequestDeadline,
+    State(exchange): State<Exchange>,
+    Path(order_id): Path<Uuid>,
+    Query(params): Query<OrderQuery>,
+) -> Response {
+    let ticket = match service::admit(exchange.state(), &params.symbol, auth.user_id).await {
+        Ok(ticket) => ticket,
+        Err(e) => return e.into_response(),
+    };
+    let seq = ticket.seq();
+    let cancelled = exchange
+        .submit_cancel(auth.user_id, &params.symbol, order_id, ticket, &deadline)
+        .await;
+    with_seq(seq, cancelled.map(|c| Json(CancelledOrderDto::from(c))))
+}
+
+#[derive(Deserialize)]
+struct ReplaceOrderRequest {
+    price: i64,
+    /// Lots, or a decimal string of the base asset (see `QuantityInput`).
+    quantity: QuantityInput,
+}
+
+/// The cancelled order and its replacement's outcome; written as `ReplacedOrderDto`.
+pub(crate) struct ReplaceOrderResponse {
+    pub(crate) cancelled: Order,
+    pub(crate) report: ExecutionReport,
+}
+
+/// Cancel a resting order and submit its replacement under one book write lock, so no other
+/// order can trade against the book in between. 409 if the order is no longer resting.
+async fn replace_order(
+    auth: AuthUser,
+    deadline: RequestDeadline,
     State(state): State<AppState>,
     Path(order_id): Path<Uuid>,
     Query(params): Query<OrderQuery>,
-) -> Result<StatusCode, (StatusCode, Json<ErrorResponse>)> {
-    if params.symbol.is_empty() {
+    Json(body): Json<ReplaceOrderRequest>,
+) -> Response {
+    let ticket = match service::admit(&state, &params.symbol, auth.user_id).await {
+        Ok(ticket) => ticket,
+        Err(e) => return e.into_response(),
+    };
+    let seq = ticket.seq();
+    let config = service::symbol_config(&state, &params.symbol).await;
+    let quantity = match body.quantity.to_lots(config.qty_scale) {
+        Ok(quantity) => quantity,
+        Err(e) => return with_seq(seq, ErrorResponse::invalid_quantity(e)),
+    };
+    let replaced = replace_in_turn(
+        &state,
+        auth.user_id,
+        order_id,
+        params.symbol,
+        (body.price, quantity),
+        ticket,
+        &deadline,
+    )
+    .await;
+    with_seq(
+        seq,
+        replaced.map(|r| Json(WithMeta::new(ReplacedOrderDto::from(r), &config))),
+    )
+}
+
+/// `replace_order` once admitted, with the replacement's `(price, quantity)` in lots; `ticket`
+/// is its place in the symbol's queue.
+async fn replace_in_turn(
+    state: &AppState,
+    user_id: Uuid,
+    order_id: Uuid,
+    normalized_symbol: Symbol,
+    (price, quantity): (Price, Qty),
+    ticket: Ticket,
+    deadline: &RequestDeadline,
+) -> Result<ReplaceOrderResponse, (StatusCode, Json<ErrorResponse>)> {
+    if quantity == 0 {
         return Err(ErrorResponse::new(
-            "Symbol parameter is required".to_string(),
+            "Replacement quantity must be positive".to_string(),
             StatusCode::BAD_REQUEST,
         ));
     }
 
-    let normalized_symbol = params.symbol.to_uppercase();
-    let orderbook = get_orderbook(&state, &normalized_symbol)?;
-    {
-        let book = orderbook.read().await;
-        if let Some(order) = book.get_order_by_id(order_id)
-            && order.user_id != auth.user_id
-        {
+    let orderbook = find_orderbook(state, &normalized_symbol, Some(user_id))
+        .await
+        .ok_or_else(|| unknown_symbol(state, &normalized_symbol))?;
+    let mut book = lock_book(state, &normalized_symbol, &orderbook, ticket, deadline).await?;
+    let side = match book.get_order_by_id(order_id) {
+        Some(order) if order.user_id != user_id => {
             return Err(ErrorResponse::new(
                 "Forbidden: order does not belong to you".to_string(),
                 StatusCode::FORBIDDEN,
             ));
         }
+        Some(order) => order.side,
+        None => {
+            drop(book);
+            return Err(service::not_resting(state, user_id, order_id).await);
+        }
+    };
+    state.kill_switches.check(user_id)?;
+    replace_locked(
+        state,
+        user_id,
+        (order_id, side),
+        &normalized_symbol,
+        (price, quantity),
+        &mut book,
+        deadline,
+    )
+    .await
+}
+
+/// Replace `user_id`'s order `order_id`, resting on `side` of `book` (whose write lock the
+/// caller holds), with `(price, quantity)`.
+pub(crate) async fn replace_locked(
+    state: &AppState,
+    user_id: Uuid,
+    (order_id, side): (OrderId, OrderSide),
+    normalized_symbol: &Symbol,
+    (price, quantity): (Price, Qty),
+    book: &mut OrderBook,
+    deadline: &RequestDeadline,
+) -> Result<ReplaceOrderResponse, (StatusCode, Json<ErrorResponse>)> {
+    // The replacement may use what the cancelled order still has locked
+    let mut ledger = service::symbol_ledger(state, normalized_symbol).await;
+    let (violations, lock) = validation::book_violations(
+        state,
+        normalized_symbol,
+        book,
+        ledger.as_deref(),
+        user_id,
+        (side, OrderType::Limit, price, quantity),
+        Some(order_id),
+    )
+    .await;
+    validation::first_violation(violations)?;
+
+    deadline.check()?;
+    let replaced = incidents::catch_panic(|| {
+        book.replace_order(
+            order_id,
+            price,
+            quantity,
+            Some(&state.ws_channel),
+            Some(normalized_symbol),
+        )
+    });
+    let (cancelled, report) = match replaced {
+        Ok(Some(replaced)) => replaced,
+        Ok(None) => unreachable!("order was resting under the same write lock"),
+        Err(message) => {
+            drop(ledger);
+            return Err(incidents::quarantine(state, normalized_symbol, book, message).await);
+        }
+    };
+    // A replace closes the old order like a cancel, and to surveillance is a cancel and a new
+    // order
+    let closed = ClosedOrder {
+        order: cancelled.clone(),
+        closed_at: report.order.timestamp,
+        reason: CloseReason::UserCancelled,
+    };
+    book.record_closed(closed.clone());
+    surveillance::on_cancels(
+        state,
+        normalized_symbol,
+        [&cancelled],
+        closed.reason,
+        closed.closed_at,
+    )
+    .await;
+    surveillance::on_placed(state, normalized_symbol, &report.order);
+    if let Some(ref mut ledger) = ledger {
+        ledger.release_order(order_id);
+        let config = service::symbol_config(state, normalized_symbol).await;
+        service::apply_balances(
+            ledger,
+            book,
+            &config,
+            lock,
+            &report.order,
+            quantity,
+            &report.trades,
+        );
     }
-    let mut book = orderbook.write().await;
-    match book.remove_order(order_id, Some(&state.ws_channel), Some(&normalized_symbol)) {
-        Some(_) => {
-            if let Some(ref db) = state.db {
-                let _ = persistence::update_order_status(db, order_id, OrderStatus::Cancelled).await;
-            }
-            Ok(StatusCode::NO_CONTENT)
+    drop(ledger);
+
+    {
+        let mut sessions = state.order_sessions.write().await;
+        if let Some(session_id) = sessions.remove(&order_id)
+            && book.get_order_by_id(report.order.id).is_some()
+        {
+            sessions.insert(report.order.id, session_id);
         }
-        None => Err(ErrorResponse::new(
-            format!("Order '{}' not found", order_id),
-            StatusCode::NOT_FOUND,
-        )),
     }
+    // The replacement keeps the TTL, with a fresh deadline
+    state.order_expiry.transfer(
+        order_id,
+        book.get_order_by_id(report.order.id).map(|o| o.id),
+        state.clock.now(),
+    );
+
+    let (deltas, attributions) = settle_trades(
+        state,
+        normalized_symbol,
+        book,
+        report.order.user_id,
+        report.order.side,
+        &report.trades,
+    )
+    .await;
+    if !deltas.is_empty() {
+        let mark = mark_price(state, normalized_symbol, book).await;
+        let qty_scale = service::symbol_config(state, normalized_symbol)
+            .await
+            .qty_scale;
+        ws::broadcast_position_updates(&state.ws_channel, &deltas, mark, qty_scale);
+    }
+
+    if let Some(ref db) = state.db
+        && !is_sandbox_symbol(normalized_symbol)
+    {
+        let filled = filled_states(book, report.trades.iter().map(|t| t.maker_order_id));
+        let _ = persist_fills(
+            db,
+            Some(&closed),
+            Some(&report.order),
+            report.rejection,
+            &filled,
+            normalized_symbol,
+            &report.trades,
+            &attributions,
+            &deltas,
+            &state.trade_metrics,
+        )
+        .await;
+    }
+    expiry::persist_deadline(state, normalized_symbol, report.order.id).await;
+
+    Ok(ReplaceOrderResponse { cancelled, report })
 }
 
-async fn get_order(
+#[derive(Deserialize)]
+struct ReduceOrderRequest {
+    /// New open quantity: lots, or a decimal string of the base asset (see `QuantityInput`).
+    quantity: QuantityInput,
+}
+
+/// Trim a resting order's open quantity in place; unlike a replace it keeps its id and time
+/// priority. 400 unless the new quantity is positive and at most what is still open, 409 if
+/// the order no longer rests.
+async fn reduce_order(
     auth: AuthUser,
+    deadline: RequestDeadline,
     State(state): State<AppState>,
     Path(order_id): Path<Uuid>,
     Query(params): Query<OrderQuery>,
-) -> Result<Json<Order>, (StatusCode, Json<ErrorResponse>)> {
-    if params.symbol.is_empty() {
-        return Err(ErrorResponse::new(
-            "Symbol parameter is required".to_string(),
-            StatusCode::BAD_REQUEST,
-        ));
-    }
+    Json(body): Json<ReduceOrderRequest>,
+) -> Response {
+    let ticket = match service::admit(&state, &params.symbol, auth.user_id).await {
+        Ok(ticket) => ticket,
+        Err(e) => return e.into_response(),
+    };
+    let seq = ticket.seq();
+    let config = service::symbol_config(&state, &params.symbol).await;
+    let quantity = match body.quantity.to_lots(config.qty_scale) {
+        Ok(quantity) => quantity,
+        Err(e) => return with_seq(seq, ErrorResponse::invalid_quantity(e)),
+    };
+    let reduced = service::reduce_order(
+        &state,
+        auth.user_id,
+        &params.symbol,
+        order_id,
+        quantity,
+        ticket,
+        &deadline,
+    )
+    .await;
+    with_seq(
+        seq,
+        reduced.map(|order| Json(OrderResponse::new(order, &config))),
+    )
+}
 
+async fn get_order(
+    auth: AuthUser,
+    State(state): State<AppState>,
+    Path(order_id): Path<Uuid>,
+    Query(params): Query<OrderQuery>,
+) -> Result<Json<OrderResponse>, (StatusCode, Json<ErrorResponse>)> {
     if let Some(ref db) = state.db {
-        let row = persistence::get_order_by_id(db, order_id).await.map_err(|_| {
-            ErrorResponse::new(
-                "Failed to look up order".to_string(),
-                StatusCode::INTERNAL_SERVER_ERROR,
-            )
-        })?;
+        let row = persistence::get_order_by_id(db, order_id).await.map_err(|e| ErrorResponse::from_db("Failed to look up order", e))?;
         let row = row.ok_or_else(|| {
             ErrorResponse::new(
                 format!("Order '{}' not found", order_id),
@@ -464,10 +1661,11 @@ async fn get_order(
                 StatusCode::INTERNAL_SERVER_ERROR,
             )
         })?;
-        return Ok(Json(order));
+        let config = service::symbol_config(&state, &row.symbol).await;
+        return Ok(Json(OrderResponse::new(order, &config)));
     }
 
-    let orderbook = get_orderbook(&state, &params.symbol)?;
+    let orderbook = get_orderbook(&state, &params.symbol).await?;
     let book = orderbook.read().await;
     match book.get_order_by_id(order_id) {
         Some(order) => {
@@ -477,7 +1675,8 @@ async fn get_order(
                     StatusCode::FORBIDDEN,
                 ));
             }
-            Ok(Json(order))
+            let config = service::symbol_config(&state, &params.symbol).await;
+            Ok(Json(OrderResponse::new(order, &config)))
         }
         None => Err(ErrorResponse::new(
             format!("Order '{}' not found", order_id),
@@ -486,178 +1685,972 @@ async fn get_order(
     }
 }
 
+/// GET /orders/{id}/fills: every trade the caller's order took part in, as maker or taker,
+/// busted ones left out. Without a database only fills still in the book's trade archive are
+/// known, and an order that neither rests nor has any of them is not found.
+async fn get_order_fills(
+    auth: AuthUser,
+    State(state): State<AppState>,
+    Path(order_id): Path<Uuid>,
+    Query(params): Query<OrderQuery>,
+) -> Result<Json<OrderFillsResponse>, (StatusCode, Json<ErrorResponse>)> {
+    let not_found = || {
+        ErrorResponse::new(
+            format!("Order '{}' not found", order_id),
+            StatusCode::NOT_FOUND,
+        )
+    };
+    let forbidden = || {
+        ErrorResponse::new(
+            "Forbidden: order does not belong to you".to_string(),
+            StatusCode::FORBIDDEN,
+        )
+    };
+
+    if let Some(ref db) = state.db {
+        let row = persistence::get_order_by_id(db, order_id)
+            .await
+            .map_err(|e| ErrorResponse::from_db("Failed to look up order", e))?
+            .ok_or_else(not_found)?;
+        if row.user_id != auth.user_id {
+            return Err(forbidden());
+        }
+        let fills = persistence::list_trades_for_order(db, order_id)
+            .await
+            .map_err(|e| ErrorResponse::from_db("Failed to load fills", e))?
+            .iter()
+            .map(|t| OrderFill::new(t.to_trade(), t.attribution().as_ref(), order_id))
+            .collect();
+        let config = service::symbol_config(&state, &row.symbol).await;
+        return Ok(Json(OrderFillsResponse::new(order_id, fills, &config)));
+    }
+
+    let orderbook = get_orderbook(&state, &params.symbol).await?;
+    let book = orderbook.read().await;
+    let fills = book.trade_archive().fills_of(order_id);
+    let owner = match book.get_order_by_id(order_id) {
+        Some(order) => order.user_id,
+        None => fills.first().map(OrderFill::user_id).ok_or_else(not_found)?,
+    };
+    if owner != auth.user_id {
+        return Err(forbidden());
+    }
+    let config = service::symbol_config(&state, &params.symbol).await;
+    Ok(Json(OrderFillsResponse::new(order_id, fills, &config)))
+}
+
+#[derive(Deserialize)]
+struct OrdersMeQuery {
+    #[serde(default, deserialize_with = "optional_symbol")]
+    symbol: Option<Symbol>,
+    /// `key:value`; only orders carrying that tag are returned.
+    tag: Option<String>,
+    source: Option<OrderSource>,
+    /// Orders in this environment; live unless the symbol names another.
+    environment: Option<Environment>,
+    limit: Option<usize>,
+    /// `next_cursor` of the previous page.
+    cursor: Option<String>,
+    /// Comma-separated top-level fields to return (see `FieldSelection`).
+    fields: Option<String>,
+}
+
+/// Keyset of `/orders/me` pages: the last order's (created_at, id).
+type OrdersMeCursor = (DateTime<Utc>, OrderId);
+const ORDERS_ME_CURSOR_SCOPE: &str = "orders/me";
+
+/// GET /orders/me: a page of the caller's orders, newest first. With a database this is the
+/// full order history; without one only orders still resting in the book are known.
+async fn get_orders_me(
+    auth: AuthUser,
+    State(state): State<AppState>,
+    OriginalUri(uri): OriginalUri,
+    Query(params): Query<OrdersMeQuery>,
+) -> Result<Response, (StatusCode, Json<ErrorResponse>)> {
+    let fields = FieldSelection::parse::<OrderDto>(params.fields.as_deref())?;
+    let limit = params.limit.unwrap_or(100);
+    let user_id = auth.user_id;
+    let before: Option<OrdersMeCursor> = params
+        .cursor
+        .as_deref()
+        .map(|cursor| {
+            pagination::decode_cursor(&state.jwt_secret, ORDERS_ME_CURSOR_SCOPE, cursor)
+        })
+        .transpose()
+        .map_err(ErrorResponse::invalid_cursor)?;
+    let next_cursor = |order: &Order| {
+        pagination::encode_cursor(
+            &state.jwt_secret,
+            ORDERS_ME_CURSOR_SCOPE,
+            &(order.timestamp, order.id),
+        )
+    };
+
+    let (symbol_opt, environment) = listing_scope(params.symbol, params.environment)?;
+
+    let mut tag_filter = OrderTags::new();
+    if let Some(ref tag) = params.tag {
+        let (key, value) = tag.split_once(':').ok_or_else(|| {
+            ErrorResponse::new(
+                format!("Invalid tag filter '{}': expected key:value", tag),
+                StatusCode::BAD_REQUEST,
+            )
+        })?;
+        tag_filter.insert(key.to_string(), value.to_string());
+    }
+
+    // Sandbox orders are never stored, so they are always read from the book
+    if let Some(ref db) = state.db
+        && !symbol_opt.as_ref().is_some_and(|s| is_sandbox_symbol(s))
+    {
+        let rows = persistence::list_orders_for_user(
+            db,
+            user_id,
+            symbol_opt.as_deref(),
+            environment,
+            &tag_filter,
+            params.source,
+            before,
+            limit.saturating_add(1),
+        )
+        .await
+        .map_err(|e| ErrorResponse::from_db("Failed to load orders", e))?;
+        let orders = rows
+            .iter()
+            .filter_map(persistence::order_row_to_order_display)
+            .collect();
+        let page = Page::from_overfetch(orders, limit, next_cursor).map(OrderDto::from);
+        return Ok(fields.respond_page(with_symbol_meta(&state, page, symbol_opt.as_ref()).await, &uri));
+    }
+
+    let orderbooks = user_orderbooks(&state, symbol_opt.as_ref(), environment, user_id).await?;
+    let mut orders = Vec::new();
+    for orderbook in orderbooks {
+        let book = orderbook.read().await;
+        orders.extend(
+            book.iter_orders()
+                .map(|(_, o)| o)
+                .filter(|o| {
+                    o.user_id == user_id
+                        && params.source.is_none_or(|source| o.source == source)
+                        && tag_filter.iter().all(|(k, v)| o.tags.get(k) == Some(v))
+                        && before.is_none_or(|before| (o.timestamp, o.id) < before)
+                })
+                .cloned(),
+        );
+    }
+    orders.sort_by_key(|o| std::cmp::Reverse((o.timestamp, o.id)));
+    orders.truncate(limit.saturating_add(1));
+    let page = Page::from_overfetch(orders, limit, next_cursor).map(OrderDto::from);
+    Ok(fields.respond_page(with_symbol_meta(&state, page, symbol_opt.as_ref()).await, &uri))
+}
+
+/// `page` with the meta block of `symbol`, when the listing is confined to one.
+async fn with_symbol_meta<T>(state: &AppState, page: Page<T>, symbol: Option<&Symbol>) -> Page<T> {
+    match symbol {
+        Some(symbol) => page.with_meta(service::symbol_config(state, symbol).await.meta()),
+        None => page,
+    }
+}
+
 #[derive(Serialize)]
 struct OrderBookResponse {
-    bids: Vec<(i64, u64)>,
-    asks: Vec<(i64, u64)>,
+    bids: Vec<BookLevelDto>,
+    asks: Vec<BookLevelDto>,
+    /// Nothing rests on either side, whatever `depth` was asked for.
+    is_empty: bool,
+    meta: ScaleMeta,
 }
 
 #[derive(Deserialize)]
 struct OrderBookQuery {
-    symbol: String,
+    symbol: Symbol,
+    /// Levels per side; the whole book when omitted.
+    depth: Option<usize>,
 }
 
+/// True if an `If-None-Match` header lists `etag` (or `*`). Weak validators match too.
+fn etag_matches(headers: &HeaderMap, etag: &str) -> bool {
+    headers
+        .get_all(header::IF_NONE_MATCH)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(|tag| tag.trim())
+        .any(|tag| tag == "*" || tag.trim_start_matches("W/") == etag)
+}
+
+/// Book levels, with an ETag from the book sequence: `If-None-Match` with the current tag gets
+/// 304, and the serialized body is reused from the book cache while the sequence is unchanged.
 async fn get_order_book(
     State(state): State<AppState>,
     Query(params): Query<OrderBookQuery>,
-) -> Result<Json<OrderBookResponse>, (StatusCode, Json<ErrorResponse>)> {
-    if params.symbol.is_empty() {
+    headers: HeaderMap,
+) -> Result<Response, (StatusCode, Json<ErrorResponse>)> {
+    let symbol = params.symbol;
+    let orderbook = get_orderbook(&state, &symbol).await?;
+    let book = orderbook.read().await;
+    let seq = book.book_seq();
+    let etag = state.book_cache.etag(&symbol, params.depth, seq);
+    if etag_matches(&headers, &etag) {
+        drop(book);
+        return Ok((StatusCode::NOT_MODIFIED, [(header::ETAG, etag)]).into_response());
+    }
+
+    let body = match state.book_cache.get(&symbol, params.depth, seq) {
+        Some(body) => {
+            drop(book);
+            body
+        }
+        None => {
+            let mut bids = book.get_bids();
+            let mut asks = book.get_asks();
+            // Read under the book lock: a config change bumps the sequence under it too
+            let meta = service::symbol_config(&state, &symbol).await.meta();
+            drop(book);
+            let is_empty = bids.is_empty() && asks.is_empty();
+            if let Some(depth) = params.depth {
+                bids.truncate(depth);
+                asks.truncate(depth);
+            }
+            let body = Bytes::from(
+                serde_json::to_vec(&OrderBookResponse {
+                    bids: dto::book_levels(bids),
+                    asks: dto::book_levels(asks),
+                    is_empty,
+                    meta,
+                })
+                    .expect("order book serializes"),
+            );
+            state
+                .book_cache
+                .insert(&symbol, params.depth, seq, body.clone());
+            body
+        }
+    };
+    Ok((
+        [
+            (header::CONTENT_TYPE, "application/json".to_string()),
+            (header::ETAG, etag),
+        ],
+        body,
+    )
+        .into_response())
+}
+
+const BOOKS_MAX_SYMBOLS: usize = 10;
+const BOOKS_DEFAULT_DEPTH: usize = 20;
+const BOOKS_MAX_DEPTH: usize = 500;
+
+#[derive(Deserialize)]
+struct BooksQuery {
+    /// Comma-separated symbols, e.g. `BTCUSDT,ETHUSDT`.
+    symbols: String,
+    depth: Option<usize>,
+    /// Fail the whole request (404) if any symbol is unknown instead of reporting it in `errors`.
+    #[serde(default)]
+    all_or_nothing: bool,
+}
+
+#[derive(Serialize)]
+struct BooksResponse {
+    books: BTreeMap<String, BookSnapshotDto>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    errors: BTreeMap<String, String>,
+}
+
+/// Snapshots of several books taken together: read locks on all requested books are held at
+/// once (acquired in symbol order, so concurrent callers cannot deadlock) while each book is
+/// copied out, then released before the response is serialized.
+async fn get_order_books(
+    State(state): State<AppState>,
+    Query(params): Query<BooksQuery>,
+) -> Result<Json<BooksResponse>, (StatusCode, Json<ErrorResponse>)> {
+    let symbols: BTreeSet<Symbol> = params
+        .symbols
+        .split(',')
+        .filter(|s| !s.trim().is_empty())
+        .map(Symbol::parse)
+        .collect::<Result<_, _>>()
+        .map_err(ErrorResponse::invalid_symbol)?;
+    if symbols.is_empty() {
+        return Err(ErrorResponse::new(
+            "Symbols parameter is required".to_string(),
+            StatusCode::BAD_REQUEST,
+        ));
+    }
+    if symbols.len() > BOOKS_MAX_SYMBOLS {
         return Err(ErrorResponse::new(
-            "Symbol parameter is required".to_string(),
+            format!(
+                "Too many symbols: {} (max {})",
+                symbols.len(),
+                BOOKS_MAX_SYMBOLS
+            ),
             StatusCode::BAD_REQUEST,
         ));
     }
+    let depth = params
+        .depth
+        .unwrap_or(BOOKS_DEFAULT_DEPTH)
+        .min(BOOKS_MAX_DEPTH);
 
-    let orderbook = get_orderbook(&state, &params.symbol)?;
-    let book = orderbook.read().await;
-    Ok(Json(OrderBookResponse {
-        bids: book.get_bids(),
-        asks: book.get_asks(),
-    }))
+    let mut errors = BTreeMap::new();
+    let mut orderbooks = Vec::new();
+    for symbol in symbols {
+        match state.orderbooks.get(symbol.as_str()) {
+            Some(orderbook) if lazy_books::ensure_loaded(&state, &symbol).await => {
+                orderbooks.push((symbol, orderbook.clone()))
+            }
+            Some(_) => {
+                errors.insert(symbol.to_string(), format!("Symbol '{}' could not be loaded", symbol));
+            }
+            None => {
+                errors.insert(symbol.to_string(), format!("Symbol '{}' not found", symbol));
+            }
+        }
+    }
+    if params.all_or_nothing && !errors.is_empty() {
+        let unknown: Vec<String> = errors.into_keys().collect();
+        return Err(ErrorResponse::new(
+            format!("Unknown symbols: {}", unknown.join(",")),
+            StatusCode::NOT_FOUND,
+        ));
+    }
+
+    // BTreeSet iteration order is the canonical lock order
+    let mut guards = Vec::with_capacity(orderbooks.len());
+    for (symbol, orderbook) in &orderbooks {
+        guards.push((symbol, orderbook.read().await));
+    }
+    let books = guards
+        .iter()
+        .map(|(symbol, book)| (symbol.to_string(), book.snapshot(depth).into()))
+        .collect();
+    drop(guards);
+
+    Ok(Json(BooksResponse { books, errors }))
 }
 
 #[derive(Deserialize)]
 struct TradesQuery {
-    symbol: String,
+    symbol: Symbol,
     limit: Option<usize>,
+    /// Return trades with `trade_seq >= from_seq`, oldest first.
+    from_seq: Option<u64>,
+    /// `next_cursor` of the previous page; continues in that page's order over `from_seq`.
+    cursor: Option<String>,
+    /// Also search trades moved to `trades_archive` by retention (DB only).
+    #[serde(default)]
+    include_archived: bool,
+    /// Comma-separated top-level fields to return (see `FieldSelection`).
+    fields: Option<String>,
 }
 
 #[derive(Deserialize)]
 struct TradesMeQuery {
-    symbol: Option<String>,
+    #[serde(default, deserialize_with = "optional_symbol")]
+    symbol: Option<Symbol>,
+    /// Trades in this environment; live unless the symbol names another.
+    environment: Option<Environment>,
     limit: Option<usize>,
+    /// `next_cursor` of the previous page.
+    cursor: Option<String>,
+    #[serde(default)]
+    include_archived: bool,
+}
+
+/// Keyset of `/trades/me` pages: the last trade's (timestamp, trade_seq, id).
+type TradesMeCursor = (DateTime<Utc>, u64, Uuid);
+const TRADES_ME_CURSOR_SCOPE: &str = "trades/me";
+
+/// Keyset of `/trades` pages, which run newest first unless they started at `from_seq`.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+enum TradesCursor {
+    /// Trades with `trade_seq` below this, newest first.
+    Before(u64),
+    /// Trades from this `trade_seq` on, oldest first.
+    From(u64),
 }
+const TRADES_CURSOR_SCOPE: &str = "trades";
 
+/// GET /trades/me: a page of the caller's trades, newest first.
 async fn get_trades_me(
     auth: AuthUser,
     State(state): State<AppState>,
+    OriginalUri(uri): OriginalUri,
     Query(params): Query<TradesMeQuery>,
-) -> Result<Json<Vec<Trade>>, (StatusCode, Json<ErrorResponse>)> {
+) -> Result<Response, (StatusCode, Json<ErrorResponse>)> {
     let limit = params.limit.unwrap_or(100);
     let user_id = auth.user_id;
-
-    let symbol_opt = params
-        .symbol
+    let before: Option<TradesMeCursor> = params
+        .cursor
         .as_deref()
-        .map(|s| s.trim())
-        .filter(|s| !s.is_empty());
+        .map(|cursor| {
+            pagination::decode_cursor(&state.jwt_secret, TRADES_ME_CURSOR_SCOPE, cursor)
+        })
+        .transpose()
+        .map_err(ErrorResponse::invalid_cursor)?;
+    let next_cursor = |t: &UserTrade| {
+        pagination::encode_cursor(
+            &state.jwt_secret,
+            TRADES_ME_CURSOR_SCOPE,
+            &(t.trade.timestamp, t.trade.trade_seq, t.trade.id),
+        )
+    };
 
-    if let Some(ref db) = state.db {
-        let trades = persistence::list_trades_for_user(db, user_id, symbol_opt, limit)
-            .await
-            .map_err(|_| {
-                ErrorResponse::new(
-                    "Failed to load trades".to_string(),
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                )
-            })?;
-        return Ok(Json(trades));
+    let (symbol_opt, environment) = listing_scope(params.symbol, params.environment)?;
+    let symbol_opt = symbol_opt.as_ref();
+
+    if let Some(ref db) = state.db
+        && !symbol_opt.is_some_and(|s| is_sandbox_symbol(s))
+    {
+        let trades = persistence::list_trades_for_user(
+            db,
+            user_id,
+            symbol_opt.map(Symbol::as_str),
+            environment,
+            before,
+            limit.saturating_add(1),
+            params.include_archived,
+        )
+        .await
+        .map_err(|e| ErrorResponse::from_db("Failed to load trades", e))?;
+        let page = Page::from_overfetch(trades, limit, next_cursor).map(UserTradeDto::from);
+        return Ok(with_symbol_meta(&state, page, symbol_opt).await.respond(&uri));
     }
 
-    let trades: Vec<Trade> = if let Some(symbol) = symbol_opt {
-        let orderbook = get_orderbook(&state, symbol)?;
+    let mut filtered = Vec::new();
+    for orderbook in user_orderbooks(&state, symbol_opt, environment, user_id).await? {
         let book = orderbook.read().await;
-        book.get_recent_trades(limit)
-    } else {
-        let mut all = Vec::new();
-        for orderbook in state.orderbooks.values() {
-            let book = orderbook.read().await;
-            all.extend(book.get_recent_trades(limit));
-        }
-        all
-    };
-
-    let mut filtered: Vec<Trade> = trades
-        .into_iter()
-        .filter(|t| t.maker_user_id == user_id || t.taker_user_id == user_id)
-        .collect();
-    filtered.sort_by(|a, b| b.timestamp.cmp(&a.timestamp));
-    filtered.truncate(limit);
-    Ok(Json(filtered))
+        filtered.extend(
+            book.get_all_trades()
+                .into_iter()
+                .filter(|t| t.maker_user_id == user_id || t.taker_user_id == user_id)
+                .filter(|t| before.is_none_or(|before| (t.timestamp, t.trade_seq, t.id) < before))
+                .map(|t| {
+                    let (attribution, bust) = (book.attribution(t.id), book.bust(t.id));
+                    UserTrade::new(t, attribution, user_id).with_bust(bust)
+                }),
+        );
+    }
+    // Same order as the DB path: trades of one match share a timestamp
+    filtered.sort_by_key(|t| std::cmp::Reverse((t.trade.timestamp, t.trade.trade_seq, t.trade.id)));
+    filtered.truncate(limit.saturating_add(1));
+    let page = Page::from_overfetch(filtered, limit, next_cursor).map(UserTradeDto::from);
+    Ok(with_symbol_meta(&state, page, symbol_opt).await.respond(&uri))
 }
 
+/// GET /trades: a page of the symbol's trades, newest first, or oldest first from `from_seq`.
 async fn get_trades(
     auth: AuthUser,
     State(state): State<AppState>,
+    OriginalUri(uri): OriginalUri,
     Query(params): Query<TradesQuery>,
-) -> Result<Json<Vec<Trade>>, (StatusCode, Json<ErrorResponse>)> {
+) -> Result<Response, (StatusCode, Json<ErrorResponse>)> {
+    let fields = FieldSelection::parse::<TradeDto>(params.fields.as_deref())?;
     let _ = auth; // require auth; trades are market-wide for symbol
-    if params.symbol.is_empty() {
-        return Err(ErrorResponse::new(
-            "Symbol parameter is required".to_string(),
-            StatusCode::BAD_REQUEST,
-        ));
-    }
 
     let limit = params.limit.unwrap_or(100);
+    let fetch = limit.saturating_add(1);
+    let start = match params.cursor.as_deref() {
+        Some(cursor) => Some(
+            pagination::decode_cursor(&state.jwt_secret, TRADES_CURSOR_SCOPE, cursor)
+                .map_err(ErrorResponse::invalid_cursor)?,
+        ),
+        None => params.from_seq.map(TradesCursor::From),
+    };
 
-    if let Some(ref db) = state.db {
-        let trades = persistence::list_trades(db, &params.symbol, limit).await.map_err(|_| {
+    let trades = match (start, &state.db) {
+        (Some(TradesCursor::From(from_seq)), Some(db)) => persistence::list_trades_from_seq(
+            db,
+            &params.symbol,
+            from_seq,
+            fetch,
+            params.include_archived,
+        )
+        .await
+        .map_err(|e| ErrorResponse::from_db("Failed to load trades", e))?,
+        (before, Some(db)) => {
+            let before_seq = match before {
+                Some(TradesCursor::Before(seq)) => Some(seq),
+                _ => None,
+            };
+            persistence::list_trades_before_seq(
+                db,
+                &params.symbol,
+                before_seq,
+                fetch,
+                params.include_archived,
+            )
+            .await
+            .map_err(|e| ErrorResponse::from_db("Failed to load trades", e))?
+        }
+        (start, None) => {
+            let orderbook = get_orderbook(&state, &params.symbol).await?;
+            let book = orderbook.read().await;
+            match start {
+                // Without a database the archive goes back further than the book's own trades
+                Some(TradesCursor::From(from_seq)) => book
+                    .trade_archive()
+                    .since(from_seq, fetch)
+                    .map_err(ErrorResponse::trades_evicted)?,
+                Some(TradesCursor::Before(seq)) => book.get_trades_before_seq(Some(seq), fetch),
+                None => book.get_trades_before_seq(None, fetch),
+            }
+        }
+    };
+    let ascending = matches!(start, Some(TradesCursor::From(_)));
+    let page = Page::from_overfetch(trades, limit, |last| {
+        let next = if ascending {
+            TradesCursor::From(last.trade_seq + 1)
+        } else {
+            TradesCursor::Before(last.trade_seq)
+        };
+        pagination::encode_cursor(&state.jwt_secret, TRADES_CURSOR_SCOPE, &next)
+    })
+    .map(TradeDto::from)
+    .with_meta(service::symbol_config(&state, &params.symbol).await.meta());
+    Ok(fields.respond_page(page, &uri))
+}
+
+#[derive(Deserialize)]
+struct IndexPriceQuery {
+    symbol: Symbol,
+}
+
+#[derive(Serialize)]
+struct IndexPriceResponse {
+    symbol: String,
+    price: i64,
+    updated_at: DateTime<Utc>,
+    stale: bool,
+}
+
+#[derive(Serialize)]
+pub struct SymbolConfigResponse {
+    pub symbol: Symbol,
+    #[serde(flatten)]
+    pub config: SymbolConfig,
+    pub meta: ScaleMeta,
+}
+
+impl SymbolConfigResponse {
+    pub fn new(symbol: Symbol, config: SymbolConfig) -> Self {
+        Self {
+            symbol,
+            meta: config.meta(),
+            config,
+        }
+    }
+}
+
+/// Current trading rules for a symbol (re-fetched by clients on `SymbolConfigUpdate`).
+async fn get_symbol_config(
+    State(state): State<AppState>,
+    Path(normalized_symbol): Path<Symbol>,
+) -> Result<Json<SymbolConfigResponse>, (StatusCode, Json<ErrorResponse>)> {
+    get_orderbook(&state, &normalized_symbol).await?;
+    let config = service::symbol_config(&state, &normalized_symbol).await;
+    Ok(Json(SymbolConfigResponse::new(normalized_symbol, config)))
+}
+
+/// Trading rules of every public symbol, sorted by symbol.
+async fn get_symbol_configs(State(state): State<AppState>) -> Json<Vec<SymbolConfigResponse>> {
+    let configs = state.symbol_configs.read().await;
+    let mut symbols: Vec<&String> = listed_orderbooks(&state).map(|(symbol, _)| symbol).collect();
+    symbols.sort();
+    Json(
+        symbols
+            .into_iter()
+            .filter_map(|symbol| {
+                Some(SymbolConfigResponse::new(
+                    Symbol::parse(symbol).ok()?,
+                    configs
+                        .get(symbol.as_str())
+                        .copied()
+                        .unwrap_or_else(|| SymbolConfig::for_symbol(symbol)),
+                ))
+            })
+            .collect(),
+    )
+}
+
+/// The assets symbols may trade.
+async fn get_assets() -> Json<&'static [AssetInfo]> {
+    Json(ASSETS)
+}
+
+async fn get_index_price(
+    State(state): State<AppState>,
+    Query(params): Query<IndexPriceQuery>,
+) -> Result<Json<IndexPriceResponse>, (StatusCode, Json<ErrorResponse>)> {
+    let normalized_symbol = params.symbol;
+    let index_price = state
+        .index_prices
+        .read()
+        .await
+        .get(normalized_symbol.as_str())
+        .copied()
+        .ok_or_else(|| {
             ErrorResponse::new(
-                "Failed to load trades".to_string(),
-                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("No index price for '{}'", normalized_symbol),
+                StatusCode::NOT_FOUND,
             )
         })?;
-        return Ok(Json(trades));
-    }
+    Ok(Json(IndexPriceResponse {
+        symbol: normalized_symbol.into_string(),
+        price: index_price.price,
+        updated_at: index_price.updated_at,
+        stale: pricefeed::is_stale(&index_price, state.index_price_max_age, Utc::now()),
+    }))
+}
 
-    let orderbook = get_orderbook(&state, &params.symbol)?;
-    let book = orderbook.read().await;
-    Ok(Json(book.get_recent_trades(limit)))
+const HISTORY_DEFAULT_LIMIT: usize = 100;
+const HISTORY_MAX_LIMIT: usize = 1000;
+
+#[derive(Deserialize)]
+struct RecentKlinesQuery {
+    symbol: Symbol,
+    limit: Option<usize>,
+    offset: Option<usize>,
+}
+
+#[derive(Serialize)]
+struct RecentKlinesResponse {
+    symbol: Symbol,
+    interval_secs: i64,
+    total: usize,
+    offset: usize,
+    limit: usize,
+    candles: Vec<Candle>,
+}
+
+/// GET /klines/recent?symbol=: one-minute candles from the retained history, oldest first;
+/// the last one is still open unless `closed` is set. Paginated with limit/offset.
+async fn get_recent_klines(
+    State(state): State<AppState>,
+    Query(params): Query<RecentKlinesQuery>,
+) -> Result<Json<RecentKlinesResponse>, (StatusCode, Json<ErrorResponse>)> {
+    let normalized_symbol = params.symbol;
+    get_orderbook(&state, &normalized_symbol).await?;
+    let now = state.clock.now();
+    let limit = params
+        .limit
+        .unwrap_or(HISTORY_DEFAULT_LIMIT)
+        .min(HISTORY_MAX_LIMIT);
+    let offset = params.offset.unwrap_or(0);
+    let candles = state.market_data.recent_candles(&normalized_symbol, now);
+    Ok(Json(RecentKlinesResponse {
+        symbol: normalized_symbol,
+        interval_secs: CANDLE_INTERVAL_SECS,
+        total: candles.len(),
+        offset,
+        limit,
+        candles: candles.into_iter().skip(offset).take(limit).collect(),
+    }))
+}
+
+#[derive(Deserialize)]
+struct TickerQuery {
+    symbol: Symbol,
+}
+
+#[derive(Serialize)]
+struct TickerResponse {
+    symbol: Symbol,
+    as_of: DateTime<Utc>,
+    #[serde(flatten)]
+    stats: RollingStats,
+    meta: ScaleMeta,
+}
+
+/// GET /ticker?symbol=: high, low, volume and trade count over the last 24 hours, to the
+/// minute.
+async fn get_ticker(
+    State(state): State<AppState>,
+    Query(params): Query<TickerQuery>,
+) -> Result<Json<TickerResponse>, (StatusCode, Json<ErrorResponse>)> {
+    let normalized_symbol = params.symbol;
+    get_orderbook(&state, &normalized_symbol).await?;
+    let now = state.clock.now();
+    let stats = state.market_data.rolling_stats(&normalized_symbol, now);
+    let meta = service::symbol_config(&state, &normalized_symbol)
+        .await
+        .meta();
+    Ok(Json(TickerResponse {
+        symbol: normalized_symbol,
+        as_of: now,
+        stats,
+        meta,
+    }))
+}
+
+#[derive(Deserialize)]
+struct TickerHistoryQuery {
+    symbol: Symbol,
+    minutes: Option<u64>,
+    limit: Option<usize>,
+    offset: Option<usize>,
+}
+
+#[derive(Serialize)]
+struct TickerHistoryResponse {
+    symbol: Symbol,
+    since: DateTime<Utc>,
+    total: usize,
+    offset: usize,
+    limit: usize,
+    points: Vec<TickerPoint>,
+}
+
+/// GET /ticker/history?symbol=&minutes=: one point per trade over the last `minutes`
+/// (default and maximum: the retained history), oldest first. Paginated with limit/offset.
+async fn get_ticker_history(
+    State(state): State<AppState>,
+    Query(params): Query<TickerHistoryQuery>,
+) -> Result<Json<TickerHistoryResponse>, (StatusCode, Json<ErrorResponse>)> {
+    let normalized_symbol = params.symbol;
+    get_orderbook(&state, &normalized_symbol).await?;
+    let now = state.clock.now();
+    let max_minutes = state.market_data.history().as_secs() / 60;
+    let minutes = params.minutes.unwrap_or(max_minutes);
+    if minutes == 0 || minutes > max_minutes {
+        return Err(ErrorResponse::new(
+            format!("minutes must be between 1 and {}", max_minutes),
+            StatusCode::BAD_REQUEST,
+        ));
+    }
+    let limit = params
+        .limit
+        .unwrap_or(HISTORY_DEFAULT_LIMIT)
+        .min(HISTORY_MAX_LIMIT);
+    let offset = params.offset.unwrap_or(0);
+    let since = now - chrono::Duration::minutes(minutes as i64);
+    let points = state.market_data.ticker_history(&normalized_symbol, since);
+    Ok(Json(TickerHistoryResponse {
+        symbol: normalized_symbol,
+        since,
+        total: points.len(),
+        offset,
+        limit,
+        points: points.into_iter().skip(offset).take(limit).collect(),
+    }))
 }
 
 #[derive(Deserialize)]
 struct PositionsQuery {
-    symbol: Option<String>,
+    #[serde(default, deserialize_with = "optional_symbol")]
+    symbol: Option<Symbol>,
+    /// Positions in this environment; live unless the symbol names another.
+    environment: Option<Environment>,
+    /// Comma-separated top-level fields to return (see `FieldSelection`).
+    fields: Option<String>,
 }
 
 async fn get_positions(
     auth: AuthUser,
     State(state): State<AppState>,
     Query(params): Query<PositionsQuery>,
-) -> Result<Json<Vec<Position>>, (StatusCode, Json<ErrorResponse>)> {
-    if let Some(ref db) = state.db {
+) -> Result<Response, (StatusCode, Json<ErrorResponse>)> {
+    let fields = FieldSelection::parse::<PositionDto>(params.fields.as_deref())?;
+    let (symbol, environment) = listing_scope(params.symbol, params.environment)?;
+    // Sandbox positions are never stored, so they are always read from memory
+    if let Some(ref db) = state.db
+        && !symbol.as_ref().is_some_and(|s| is_sandbox_symbol(s))
+    {
         let rows = persistence::list_positions_for_user(
             db,
             auth.user_id,
-            params.symbol.as_deref(),
+            symbol.as_deref(),
+            environment,
         )
         .await
-        .map_err(|_| {
-            ErrorResponse::new(
-                "Failed to load positions".to_string(),
-                StatusCode::INTERNAL_SERVER_ERROR,
-            )
-        })?;
+        .map_err(|e| ErrorResponse::from_db("Failed to load positions", e))?;
         let positions = rows
-            .into_iter()
-            .map(|r| Position {
-                user_id: r.user_id,
-                symbol: r.symbol,
-                quantity: r.quantity,
-                average_price: r.average_price,
-            })
+            .iter()
+            .map(|row| PositionDto::from(row.to_position()))
             .collect();
-        return Ok(Json(positions));
+        return Ok(fields.respond(positions));
     }
 
-    let positions =
-        positions::get_positions(&state.positions, auth.user_id, params.symbol.as_deref()).await;
-    Ok(Json(positions))
+    let mut positions =
+        positions::get_positions(&state.positions, auth.user_id, symbol.as_ref()).await;
+    positions.retain(|position| Environment::of(&position.symbol) == environment);
+    Ok(fields.respond(positions.into_iter().map(PositionDto::from).collect()))
+}
+
+#[derive(Serialize)]
+pub struct AssetBalance {
+    pub asset: String,
+    #[serde(flatten)]
+    pub balance: Balance,
+}
+
+/// 503 when balances are not tracked (`BALANCE_LOCKING` unset).
+pub(crate) fn balances_disabled() -> (StatusCode, Json<ErrorResponse>) {
+    ErrorResponse::new(
+        "Balance tracking is not enabled".to_string(),
+        StatusCode::SERVICE_UNAVAILABLE,
+    )
+}
+
+#[derive(Deserialize)]
+struct BalancesQuery {
+    #[serde(default)]
+    environment: Environment,
+}
+
+/// The caller's available and locked balance per asset, live or in `environment`.
+async fn get_balances(
+    auth: AuthUser,
+    State(state): State<AppState>,
+    Query(params): Query<BalancesQuery>,
+) -> Result<Json<Vec<AssetBalance>>, (StatusCode, Json<ErrorResponse>)> {
+    let ledger = service::environment_balances(&state, params.environment)
+        .ledger()
+        .await
+        .ok_or_else(balances_disabled)?;
+    let balances = ledger
+        .balances_of(auth.user_id)
+        .into_iter()
+        .map(|(asset, balance)| AssetBalance { asset, balance })
+        .collect();
+    Ok(Json(balances))
 }
 
 pub fn app_router(state: AppState) -> Router {
-    Router::new()
+    let role = state.role;
+    let router = Router::new()
         .route("/health", get(health))
+        .route("/activity", get(activity::get_activity))
         .route("/auth/register", post(register))
         .route("/auth/login", post(login))
-        .route("/orders", post(create_order))
+        .route("/auth/change-username", post(change_username))
+        .route("/auth/me", get(get_me))
+        .route(
+            "/orders",
+            post(create_order).route_layer(middleware::from_fn_with_state(
+                state.clone(),
+                idempotency_middleware,
+            )),
+        )
+        .route("/orders/preview", post(preview_order))
+        .route("/orders/spread", post(spreads::create_spread))
+        .route("/orders/validate", post(validation::validate_order))
+        .route("/orders/me", get(get_orders_me))
         .route("/orders/{id}", delete(cancel_order))
         .route("/orders/{id}", get(get_order))
+        .route("/orders/{id}/fills", get(get_order_fills))
+        .route("/orders/{id}/replace", post(replace_order))
+        .route("/orders/{id}/reduce", post(reduce_order))
+        .route("/orders/{id}/heartbeat", post(expiry::heartbeat))
         .route("/book", get(get_order_book))
+        .route("/book/diffs", get(book_diffs::get_book_diffs))
+        .route("/books", get(get_order_books))
         .route("/trades/me", get(get_trades_me))
         .route("/trades", get(get_trades))
         .route("/positions", get(get_positions))
+        .route("/balances", get(get_balances))
+        .route("/account/statements", get(statements::download_statement))
+        .route("/account/exposure", get(exposure::get_exposure))
+        .route("/account/kill-switch", post(kill_switch::engage))
+        .route("/account/kill-switch/disable", post(kill_switch::disable))
+        .route("/index-price", get(get_index_price))
+        .route("/klines/recent", get(get_recent_klines))
+        .route("/ticker", get(get_ticker))
+        .route("/ticker/history", get(get_ticker_history))
+        .route("/assets", get(get_assets))
+        .route("/symbols", get(get_symbol_configs))
+        .route("/exchangeInfo", get(exchange_info::get_exchange_info))
+        .route("/exchangeInfo/changes", get(exchange_info::list_changes))
+        .route("/symbols/{symbol}", get(get_symbol_config))
         .route("/ws", get(ws_handler))
-        .with_state(state)
+        .route("/sse/market", get(sse::market_stream))
+        .route(
+            "/sandbox/symbols",
+            post(sandbox::create_sandbox).get(sandbox::list_sandboxes),
+        )
+        .route(
+            "/sandbox/symbols/{symbol}",
+            delete(sandbox::delete_sandbox),
+        )
+        .route(
+            "/sandbox/symbols/{symbol}/book",
+            get(sandbox::get_sandbox_book),
+        )
+        .route(
+            "/sandbox/symbols/{symbol}/members",
+            post(sandbox::invite_member),
+        )
+        .route(
+            "/webhooks",
+            post(webhooks::create_webhook).get(webhooks::list_webhooks),
+        )
+        .route("/webhooks/{id}", delete(webhooks::delete_webhook))
+        .route("/webhooks/{id}/deliveries", get(webhooks::list_deliveries))
+        .route("/admin/auction/start", post(admin::start_auction))
+        .route("/admin/auction/end", post(admin::end_auction))
+        .route("/admin/balances/credit", post(admin::credit_balance))
+        .route("/admin/book/info", get(admin::get_book_info))
+        .route("/admin/book/orders", get(admin::list_book_orders))
+        .route("/admin/hydration", get(admin::get_hydration_report))
+        .route("/admin/incidents", get(incidents::list_incidents))
+        .route("/admin/metrics", get(admin::get_prometheus_metrics))
+        .route("/admin/order-limits", get(admin::get_order_limits))
+        .route(
+            "/admin/order-limits/override",
+            put(admin::set_order_limits_override).delete(admin::clear_order_limits_override),
+        )
+        .route("/admin/orders/{id}", delete(admin::cancel_order))
+        .route("/admin/persistence", get(admin::get_persistence_metrics))
+        .route("/admin/positions", get(admin::list_symbol_positions))
+        .route("/admin/queries", get(admin::get_query_latencies))
+        .route("/admin/reconcile", get(admin::reconcile_state))
+        .route("/admin/retention", get(admin::get_retention))
+        .route("/admin/retention/run", post(admin::run_retention))
+        .route("/admin/schema", get(admin::get_schema_status))
+        .route("/admin/self-test", post(admin::run_self_test))
+        .route(
+            "/admin/statements/generate",
+            post(admin::generate_statements),
+        )
+        .route("/admin/stats/matching", get(admin::get_matching_stats))
+        .route("/admin/symbols/{symbol}", patch(admin::patch_symbol_config))
+        .route(
+            "/admin/symbols/{symbol}/enforce-config",
+            post(admin::enforce_symbol_config),
+        )
+        .route(
+            "/admin/symbols/{symbol}/delist",
+            post(delisting::delist_symbol),
+        )
+        .route(
+            "/admin/surveillance/alerts",
+            get(surveillance::list_alerts),
+        )
+        .route(
+            "/admin/surveillance/alerts/{id}/ack",
+            post(surveillance::acknowledge_alert),
+        )
+        .route("/admin/tasks", get(admin::list_tasks))
+        .route("/admin/trades/reconcile", post(admin::reconcile_trades))
+        .route("/admin/trades/{id}/bust", post(admin::bust_trade))
+        .route("/admin/users", get(admin::list_users))
+        .route("/admin/ws/broadcast", post(admin::broadcast_announcement))
+        .route("/admin/ws/connections", get(admin::list_ws_connections))
+        .route(
+            "/admin/ws/connections/{id}",
+            delete(admin::close_ws_connection),
+        )
+        .route("/admin/ws/rebroadcast", post(admin::rebroadcast_books));
+    let router = match role {
+        ServerRole::Primary => router,
+        ServerRole::MarketDataReplica => router.layer(middleware::from_fn(replica::reject_writes)),
+    };
+    #[cfg(feature = "fault-injection")]
+    let router = router.layer(middleware::from_fn_with_state(
+        state.faults.clone(),
+        crate::faults::scope_requests,
+    ));
+    router.layer(CompressionLayer::new()).with_state(state)
 }
@@ -0,0 +1,127 @@
+//! Sandbox endpoints: create, list, share and close private in-memory books. Orders on a
+//! sandbox symbol go through the regular `/orders` endpoints.
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::Json,
+};
+use chrono::Utc;
+use serde::Deserialize;
+
+use crate::api::auth::AuthUser;
+use crate::api::dto::BookSnapshotDto;
+use crate::api::routes::{AppState, ErrorResponse, find_orderbook};
+use crate::sandbox::{self, SandboxError, SandboxInfo};
+use crate::types::symbol::Symbol;
+
+/// Levels per side in a sandbox book snapshot.
+const SANDBOX_BOOK_DEPTH: usize = 50;
+
+fn sandbox_error(e: SandboxError) -> (StatusCode, Json<ErrorResponse>) {
+    let status = match e {
+        SandboxError::InvalidName(_) => StatusCode::BAD_REQUEST,
+        SandboxError::Taken(_) | SandboxError::LimitReached(_) => StatusCode::CONFLICT,
+        SandboxError::NotFound(_) => StatusCode::NOT_FOUND,
+        SandboxError::NotOwner => StatusCode::FORBIDDEN,
+    };
+    ErrorResponse::new(e.to_string(), status)
+}
+
+#[derive(Deserialize)]
+pub struct CreateSandboxRequest {
+    /// Becomes the symbol `SBX<NAME>`.
+    name: String,
+}
+
+/// POST /sandbox/symbols: open a private book owned by the caller.
+pub async fn create_sandbox(
+    auth: AuthUser,
+    State(state): State<AppState>,
+    Json(body): Json<CreateSandboxRequest>,
+) -> Result<(StatusCode, Json<SandboxInfo>), (StatusCode, Json<ErrorResponse>)> {
+    let info = state
+        .sandboxes
+        .create(
+            auth.user_id,
+            &body.name,
+            |symbol| state.orderbooks.contains_key(symbol),
+            state.order_limits.clone(),
+            Utc::now(),
+        )
+        .await
+        .map_err(sandbox_error)?;
+    eprintln!("sandbox {} opened by {}", info.symbol, auth.user_id);
+    Ok((StatusCode::CREATED, Json(info)))
+}
+
+/// GET /sandbox/symbols: sandboxes the caller owns or was invited to.
+pub async fn list_sandboxes(
+    auth: AuthUser,
+    State(state): State<AppState>,
+) -> Json<Vec<SandboxInfo>> {
+    Json(state.sandboxes.list(auth.user_id).await)
+}
+
+#[derive(Deserialize)]
+pub struct InviteRequest {
+    username: String,
+}
+
+/// POST /sandbox/symbols/{symbol}/members: let another user trade on the caller's sandbox.
+pub async fn invite_member(
+    auth: AuthUser,
+    State(state): State<AppState>,
+    Path(symbol): Path<Symbol>,
+    Json(body): Json<InviteRequest>,
+) -> Result<Json<SandboxInfo>, (StatusCode, Json<ErrorResponse>)> {
+    let member = state
+        .user_store
+        .read()
+        .await
+        .get(&body.username.trim().to_lowercase())
+        .map(|user| user.user_id)
+        .ok_or_else(|| {
+            ErrorResponse::new(
+                format!("User '{}' not found", body.username),
+                StatusCode::NOT_FOUND,
+            )
+        })?;
+    let info = state
+        .sandboxes
+        .invite(&symbol, auth.user_id, member, Utc::now())
+        .await
+        .map_err(sandbox_error)?;
+    Ok(Json(info))
+}
+
+/// DELETE /sandbox/symbols/{symbol}: close the caller's sandbox, dropping its orders and
+/// positions.
+pub async fn delete_sandbox(
+    auth: AuthUser,
+    State(state): State<AppState>,
+    Path(symbol): Path<Symbol>,
+) -> Result<StatusCode, (StatusCode, Json<ErrorResponse>)> {
+    let sandbox = state
+        .sandboxes
+        .remove(&symbol, auth.user_id)
+        .await
+        .map_err(sandbox_error)?;
+    sandbox::discard(&state, &symbol, sandbox).await;
+    eprintln!("sandbox {} closed by {}", symbol, auth.user_id);
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// GET /sandbox/symbols/{symbol}/book: aggregated levels of a sandbox the caller can use.
+pub async fn get_sandbox_book(
+    auth: AuthUser,
+    State(state): State<AppState>,
+    Path(symbol): Path<Symbol>,
+) -> Result<Json<BookSnapshotDto>, (StatusCode, Json<ErrorResponse>)> {
+    let orderbook = match find_orderbook(&state, &symbol, Some(auth.user_id)).await {
+        Some(orderbook) if sandbox::is_sandbox_symbol(&symbol) => orderbook,
+        _ => return Err(sandbox_error(SandboxError::NotFound(symbol.into_string()))),
+    };
+    let snapshot = orderbook.read().await.snapshot(SANDBOX_BOOK_DEPTH);
+    Ok(Json(snapshot.into()))
+}
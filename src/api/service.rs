@@ -0,0 +1,587 @@
+//! Order entry shared by every transport. The HTTP handlers parse and authenticate the
+//! request, then call in here, so matching, position updates, persistence and broadcasts
+//! follow one path whichever protocol the order arrived on. Sandbox books go through the same
+//! path but are never persisted.
+
+use axum::{http::StatusCode, response::Json};
+use chrono::{DateTime, Utc};
+use std::time::Duration;
+use tokio::sync::MutexGuard;
+use uuid::Uuid;
+
+use crate::api::expiry;
+use crate::api::deadline::{RequestDeadline, lock_book};
+use crate::api::incidents;
+use crate::api::ingress::Ticket;
+use crate::api::phase_timer::PhaseTimer;
+use crate::api::routes::{
+    AppState, ErrorResponse, filled_states, find_orderbook, mark_price, persist_fills,
+    settle_trades, unknown_symbol,
+};
+use crate::api::surveillance;
+use crate::api::validation;
+use crate::api::ws;
+use crate::balances::{BalanceError, Balances, Ledger, LockSpec};
+use crate::orderbook::closed_orders::ClosedOrder;
+use crate::orderbook::orderbook::{ExecutionReport, OrderBook, ReduceError};
+use crate::persistence;
+use crate::sandbox::is_sandbox_symbol;
+use crate::types::environment::Environment;
+use crate::types::money::{Notional, scaled_notional};
+use crate::types::order::{
+    CloseReason, Order, OrderId, OrderSide, OrderSource, OrderStatus, OrderTags, OrderType,
+    RejectReason,
+};
+use crate::types::symbol::{Symbol, SymbolConfig};
+use crate::types::trade::Trade;
+
+/// A validated order ready for the book.
+#[derive(Debug, Clone)]
+pub struct NewOrder {
+    pub symbol: Symbol,
+    pub price: i64,
+    pub quantity: u64,
+    pub side: OrderSide,
+    pub order_type: OrderType,
+    pub tags: OrderTags,
+    pub source: OrderSource,
+    /// WS session the order is scoped to; it is cancelled when that session disconnects.
+    pub session_scope: Option<Uuid>,
+    /// Cancel the order unless refreshed this often (`api::expiry`); limit orders only.
+    pub refresh_ttl: Option<Duration>,
+}
+
+/// A ticket in `symbol`'s ingress queue, once the symbol names a book `user_id` can reach (see
+/// `find_orderbook`). Requests for any other symbol are turned away without one, so they never
+/// open a queue.
+pub(crate) async fn admit(
+    state: &AppState,
+    symbol: &str,
+    user_id: Uuid,
+) -> Result<Ticket, (StatusCode, Json<ErrorResponse>)> {
+    if find_orderbook(state, symbol, Some(user_id)).await.is_none() {
+        return Err(unknown_symbol(state, symbol));
+    }
+    Ok(state.ingress.admit(symbol))
+}
+
+/// Match `new` for `user_id`, apply the fills to positions, persist and broadcast them.
+/// A rejected order is still persisted and its fills stand; the rejection is the error.
+/// The book is taken in `ticket` order (see `admit`). Each phase is timed into
+/// `timer` and recorded as `place_order` (see `api::phase_timer`).
+pub async fn place_order(
+    state: &AppState,
+    user_id: Uuid,
+    new: NewOrder,
+    ticket: Ticket,
+    deadline: &RequestDeadline,
+    timer: &mut PhaseTimer,
+) -> Result<ExecutionReport, (StatusCode, Json<ErrorResponse>)> {
+    let orderbook = find_orderbook(state, &new.symbol, Some(user_id))
+        .await
+        .ok_or_else(|| unknown_symbol(state, &new.symbol))?;
+    // Matching, position updates and persistence all run under the book write lock, so the
+    // database sees fills for this symbol in the same order as the in-memory state.
+    timer.skip();
+    let book = lock_book(state, &new.symbol, &orderbook, ticket, deadline).await;
+    timer.end("lock_wait");
+    let placed = match book {
+        Ok(mut book) => place_locked(state, user_id, new, &mut book, deadline, timer).await,
+        Err(e) => Err(e),
+    };
+    state.phase_timings.record("place_order", timer);
+    placed
+}
+
+/// `place_order` on `new.symbol`'s book, which the caller holds the write lock of.
+pub(crate) async fn place_locked(
+    state: &AppState,
+    user_id: Uuid,
+    new: NewOrder,
+    book: &mut OrderBook,
+    deadline: &RequestDeadline,
+    timer: &mut PhaseTimer,
+) -> Result<ExecutionReport, (StatusCode, Json<ErrorResponse>)> {
+    // Checked under the book lock, so nothing rests once a kill switch engage has passed the
+    // book. It stops the user's own orders, not those placed on their behalf.
+    if new.source != OrderSource::Admin {
+        state.kill_switches.check(user_id)?;
+    }
+    if let Some(session_id) = new.session_scope
+        && !state.ws_connections.is_live_session(user_id, session_id)
+    {
+        return Err(ErrorResponse::new(
+            "session_scope is not one of your open WebSocket sessions".to_string(),
+            StatusCode::BAD_REQUEST,
+        ));
+    }
+    let symbol = new.symbol;
+    timer.skip();
+    // Funds are checked before the order reaches the book and locked once it has matched,
+    // under one ledger guard
+    let mut ledger = symbol_ledger(state, &symbol).await;
+    let (violations, lock) = validation::book_violations(
+        state,
+        &symbol,
+        book,
+        ledger.as_deref(),
+        user_id,
+        (new.side, new.order_type, new.price, new.quantity),
+        None,
+    )
+    .await;
+    validation::first_violation(violations)?;
+    // Last point the request can give up: once the order reaches the book, its fills must be
+    // persisted whatever the deadline says.
+    deadline.check()?;
+    // A panic in matching halts this symbol only (see `api::incidents`)
+    let matched = incidents::catch_panic(|| {
+        book.add_order_with_tags(
+            user_id,
+            new.price,
+            new.quantity,
+            new.side,
+            new.order_type,
+            new.tags,
+            new.source,
+            Some(&state.ws_channel),
+            Some(&symbol),
+        )
+    });
+    let ExecutionReport {
+        order,
+        trades,
+        rejection,
+    } = match matched {
+        Ok(report) => report,
+        Err(message) => {
+            drop(ledger);
+            return Err(incidents::quarantine(state, &symbol, book, message).await);
+        }
+    };
+    if let Some(ref mut ledger) = ledger {
+        let config = symbol_config(state, &symbol).await;
+        apply_balances(
+            ledger,
+            book,
+            &config,
+            lock,
+            &order,
+            new.quantity,
+            &trades,
+        );
+    }
+    drop(ledger);
+    surveillance::on_placed(state, &symbol, &order);
+    timer.end("match");
+
+    if let Some(session_id) = new.session_scope
+        && book.get_order_by_id(order.id).is_some()
+    {
+        state
+            .order_sessions
+            .write()
+            .await
+            .insert(order.id, session_id);
+    }
+    if let Some(ttl) = new.refresh_ttl
+        && book.get_order_by_id(order.id).is_some()
+    {
+        state
+            .order_expiry
+            .track(order.id, &symbol, user_id, ttl, state.clock.now());
+    }
+
+    // Update positions for all fills (taker = order.side, maker = opposite) under one lock
+    timer.skip();
+    let (deltas, attributions) =
+        settle_trades(state, &symbol, book, order.user_id, order.side, &trades).await;
+    timer.end("positions");
+    if !deltas.is_empty() {
+        let mark = mark_price(state, &symbol, book).await;
+        let qty_scale = symbol_config(state, &symbol).await.qty_scale;
+        ws::broadcast_position_updates(&state.ws_channel, &deltas, mark, qty_scale);
+    }
+    timer.end("broadcast");
+
+    if let Some(ref db) = state.db
+        && !is_sandbox_symbol(&symbol)
+    {
+        let filled = filled_states(book, trades.iter().map(|t| t.maker_order_id));
+        let _ = persist_fills(
+            db,
+            None,
+            Some(&order),
+            rejection,
+            &filled,
+            &symbol,
+            &trades,
+            &attributions,
+            &deltas,
+            &state.trade_metrics,
+        )
+        .await;
+    }
+    if new.refresh_ttl.is_some() {
+        expiry::persist_deadline(state, &symbol, order.id).await;
+    }
+    timer.end("persist");
+
+    // Rejected orders are still persisted (Cancelled, with the reason as close_reason)
+    if let Some(reason) = rejection {
+        return Err(ErrorResponse::rejected(reason));
+    }
+    Ok(ExecutionReport {
+        order,
+        trades,
+        rejection: None,
+    })
+}
+
+/// `symbol`'s trading rules, or the defaults for a new symbol of that name if it has none
+/// configured.
+pub(crate) async fn symbol_config(state: &AppState, symbol: &str) -> SymbolConfig {
+    state
+        .symbol_configs
+        .read()
+        .await
+        .get(symbol)
+        .copied()
+        .unwrap_or_else(|| SymbolConfig::for_symbol(symbol))
+}
+
+/// The balance ledger when balances are tracked for `symbol` (never for sandboxes): its
+/// environment's. Take it while holding the book write lock.
+pub(crate) async fn symbol_ledger<'a>(
+    state: &'a AppState,
+    symbol: &str,
+) -> Option<MutexGuard<'a, Ledger>> {
+    if is_sandbox_symbol(symbol) {
+        return None;
+    }
+    environment_balances(state, Environment::of(symbol))
+        .ledger()
+        .await
+}
+
+/// The balances orders in `environment` trade.
+pub(crate) fn environment_balances(state: &AppState, environment: Environment) -> &Balances {
+    match environment {
+        Environment::Live => &state.balances,
+        Environment::Paper => &state.paper_balances,
+    }
+}
+
+/// The lock `user_id` needs for an order `(side, type, price, quantity)` on `symbol` (trading
+/// under `config`), after checking it is available (counting what the `replaced` order would
+/// release). None when the symbol has no assets. A market buy locks what it would spend on
+/// `book` now.
+pub(crate) fn balance_lock(
+    ledger: &Ledger,
+    book: &OrderBook,
+    (symbol, config): (&str, &SymbolConfig),
+    user_id: Uuid,
+    (side, order_type, price, quantity): (OrderSide, OrderType, i64, u64),
+    replaced: Option<OrderId>,
+) -> Result<Option<LockSpec>, (StatusCode, Json<ErrorResponse>)> {
+    let market_cost = if order_type == OrderType::Market && side == OrderSide::Buy {
+        let preview = book.simulate_order(user_id, price, quantity, side, order_type);
+        preview.trades.iter().try_fold(Notional::ZERO, |total, t| {
+            total.checked_add(scaled_notional(t.price, t.quantity, config.qty_scale))
+        })
+    } else {
+        Ok(Notional::ZERO)
+    };
+    let limit = (order_type == OrderType::Limit).then_some(price);
+    let spec = match market_cost
+        .map_err(|_| BalanceError::Overflow)
+        .and_then(|cost| LockSpec::for_order(symbol, config, side, limit, quantity, cost))
+    {
+        Ok(spec) => spec,
+        Err(BalanceError::UnknownAssets(_)) => return Ok(None),
+        Err(_) => return Err(ErrorResponse::rejected(RejectReason::InsufficientBalance)),
+    };
+    ledger
+        .check_freeing(user_id, &spec.asset, spec.amount, replaced)
+        .map_err(|_| ErrorResponse::rejected(RejectReason::InsufficientBalance))?;
+    Ok(Some(spec))
+}
+
+/// After `order` (of `quantity` on entry) matched: lock its funds, settle its fills for both
+/// sides and, unless it now rests, release whatever its fills did not spend.
+pub(crate) fn apply_balances(
+    ledger: &mut Ledger,
+    book: &OrderBook,
+    config: &SymbolConfig,
+    lock: Option<LockSpec>,
+    order: &Order,
+    quantity: u64,
+    trades: &[Trade],
+) {
+    if let Some(spec) = lock
+        && let Err(e) = ledger.lock_order(order.id, order.user_id, spec, quantity)
+    {
+        // Checked before matching under the same ledger guard
+        eprintln!("failed to lock funds for order {}: {}", order.id, e);
+    }
+    ledger.settle(config, order.side, trades);
+    if book.get_order_by_id(order.id).is_none() {
+        ledger.release_order(order.id);
+    }
+}
+
+/// An order as it left the book on cancel: status `Cancelled`, `quantity` is what was still
+/// open (also given as `remaining_quantity`). Written as `CancelledOrderDto`.
+#[derive(Debug, Clone)]
+pub struct CancelledOrder {
+    pub order: Order,
+    pub remaining_quantity: u64,
+    pub closed_at: DateTime<Utc>,
+    pub close_reason: CloseReason,
+}
+
+impl From<&ClosedOrder> for CancelledOrder {
+    fn from(closed: &ClosedOrder) -> Self {
+        Self {
+            order: closed.order.clone(),
+            remaining_quantity: closed.order.quantity,
+            closed_at: closed.closed_at,
+            close_reason: closed.reason,
+        }
+    }
+}
+
+/// How `order_id` left `book`, when a cancel took it off recently and `user_id` (if given)
+/// owns it. A cancel that lost the race to another one answers with this, so it reports the
+/// reason the order actually closed with rather than a 404.
+pub(crate) fn already_closed(
+    book: &OrderBook,
+    order_id: OrderId,
+    user_id: Option<Uuid>,
+) -> Option<CancelledOrder> {
+    book.closed_order(order_id)
+        .filter(|closed| user_id.is_none_or(|user_id| closed.order.user_id == user_id))
+        .map(CancelledOrder::from)
+}
+
+/// Cancel `user_id`'s resting order `order_id` on `symbol`, persisting the quantity that was
+/// still open. Cancelling an order that a cancel already took off the book (the user's own
+/// retry, or the expiry sweeper getting there first) succeeds with that closure and its reason.
+pub async fn cancel_order(
+    state: &AppState,
+    user_id: Uuid,
+    symbol: &Symbol,
+    order_id: Uuid,
+    ticket: Ticket,
+    deadline: &RequestDeadline,
+) -> Result<CancelledOrder, (StatusCode, Json<ErrorResponse>)> {
+    let orderbook = find_orderbook(state, symbol, Some(user_id))
+        .await
+        .ok_or_else(|| unknown_symbol(state, symbol))?;
+    let mut book = lock_book(state, symbol, &orderbook, ticket, deadline).await?;
+    if let Some(order) = book.get_order_by_id(order_id)
+        && order.user_id != user_id
+    {
+        return Err(ErrorResponse::new(
+            "Forbidden: order does not belong to you".to_string(),
+            StatusCode::FORBIDDEN,
+        ));
+    }
+    deadline.check()?;
+    close_orders(
+        state,
+        symbol,
+        &mut book,
+        [order_id],
+        CloseReason::UserCancelled,
+    )
+    .await
+    .pop()
+    .or_else(|| already_closed(&book, order_id, Some(user_id)))
+    .ok_or_else(|| {
+        ErrorResponse::new(
+            format!("Order '{}' not found", order_id),
+            StatusCode::NOT_FOUND,
+        )
+    })
+}
+
+/// Reduce `user_id`'s resting order `order_id` on `symbol` to `quantity` open, keeping its
+/// place in the queue: releases the lock share of the trimmed quantity and persists the new
+/// remainder. 409 if the order no longer rests.
+pub async fn reduce_order(
+    state: &AppState,
+    user_id: Uuid,
+    symbol: &Symbol,
+    order_id: OrderId,
+    quantity: u64,
+    ticket: Ticket,
+    deadline: &RequestDeadline,
+) -> Result<Order, (StatusCode, Json<ErrorResponse>)> {
+    let orderbook = find_orderbook(state, symbol, Some(user_id))
+        .await
+        .ok_or_else(|| unknown_symbol(state, symbol))?;
+    let mut book = lock_book(state, symbol, &orderbook, ticket, deadline).await?;
+    match book.get_order_by_id(order_id) {
+        Some(order) if order.user_id != user_id => {
+            return Err(ErrorResponse::new(
+                "Forbidden: order does not belong to you".to_string(),
+                StatusCode::FORBIDDEN,
+            ));
+        }
+        Some(_) => {}
+        None => {
+            drop(book);
+            return Err(not_resting(state, user_id, order_id).await);
+        }
+    }
+    deadline.check()?;
+    let reduced = incidents::catch_panic(|| {
+        book.reduce_order(order_id, quantity, Some(&state.ws_channel), Some(symbol))
+    });
+    let order = match reduced {
+        Ok(reduced) => reduced,
+        Err(message) => return Err(incidents::quarantine(state, symbol, &mut book, message).await),
+    }
+    .map_err(|e| match e {
+        ReduceError::InvalidQuantity { remaining } => ErrorResponse::new(
+            format!(
+                "Quantity must be positive and at most the {} still open",
+                remaining
+            ),
+            StatusCode::BAD_REQUEST,
+        ),
+        ReduceError::NotResting => unreachable!("order was resting under the same write lock"),
+    })?;
+    if let Some(mut ledger) = symbol_ledger(state, symbol).await {
+        ledger.reduce_order(order_id, order.quantity);
+    }
+
+    if let Some(ref db) = state.db
+        && !is_sandbox_symbol(symbol)
+    {
+        let _ = persistence::update_order_state(db, order_id, order.status, order.quantity).await;
+    }
+    Ok(order)
+}
+
+/// The error for `user_id`'s order `order_id` not resting on the book it was looked up in:
+/// 409 if it exists (it was filled or cancelled), 404 if the database has no such order of
+/// the user's. Without a database every such order is reported as 409.
+pub(crate) async fn not_resting(
+    state: &AppState,
+    user_id: Uuid,
+    order_id: OrderId,
+) -> (StatusCode, Json<ErrorResponse>) {
+    if let Some(ref db) = state.db {
+        let row = match persistence::get_order_by_id(db, order_id).await {
+            Ok(row) => row,
+            Err(e) => return ErrorResponse::from_db("Failed to look up order", e),
+        };
+        if row.is_none_or(|row| row.user_id != user_id) {
+            return ErrorResponse::new(
+                format!("Order '{}' not found", order_id),
+                StatusCode::NOT_FOUND,
+            );
+        }
+    }
+    ErrorResponse::new(
+        format!("Order '{}' is no longer open", order_id),
+        StatusCode::CONFLICT,
+    )
+}
+
+/// Take `order_ids` off `book` (ids no longer resting are skipped) with one book update,
+/// persist each with its open quantity and `reason`, and, when someone other than the owner
+/// asked, notify the owner with `OrderClosed` and log it. Every cancel goes through here;
+/// callers hold the book write lock. Whichever caller takes an order off the book first closes
+/// it: the book remembers the closure (see `already_closed`) and the stored status only moves
+/// from open to cancelled once, so a later cancel neither repeats nor rewrites it.
+pub async fn close_orders(
+    state: &AppState,
+    symbol: &str,
+    book: &mut OrderBook,
+    order_ids: impl IntoIterator<Item = OrderId>,
+    reason: CloseReason,
+) -> Vec<CancelledOrder> {
+    let closed_at = book.now();
+    let closed: Vec<CancelledOrder> = order_ids
+        .into_iter()
+        .filter_map(|order_id| book.remove_order(order_id, None, None))
+        .map(|mut order| {
+            order.status = OrderStatus::Cancelled;
+            CancelledOrder {
+                remaining_quantity: order.quantity,
+                order,
+                closed_at,
+                close_reason: reason,
+            }
+        })
+        .collect();
+    if closed.is_empty() {
+        return closed;
+    }
+    for cancelled in &closed {
+        book.record_closed(ClosedOrder {
+            order: cancelled.order.clone(),
+            closed_at,
+            reason,
+        });
+    }
+    surveillance::on_cancels(
+        state,
+        symbol,
+        closed.iter().map(|cancelled| &cancelled.order),
+        reason,
+        closed_at,
+    )
+    .await;
+    // Fills already spent their share of each lock; only the open remainder is released
+    if let Some(mut ledger) = symbol_ledger(state, symbol).await {
+        for cancelled in &closed {
+            ledger.release_order(cancelled.order.id);
+        }
+    }
+    ws::broadcast_orderbook_update(&state.ws_channel, symbol, book);
+
+    {
+        let mut order_sessions = state.order_sessions.write().await;
+        for cancelled in &closed {
+            order_sessions.remove(&cancelled.order.id);
+        }
+    }
+    if let Some(ref db) = state.db
+        && !is_sandbox_symbol(symbol)
+    {
+        for cancelled in &closed {
+            if let Ok(false) = persistence::cancel_order(
+                db,
+                cancelled.order.id,
+                cancelled.remaining_quantity,
+                reason.code(),
+            )
+            .await
+            {
+                eprintln!(
+                    "order {} was already closed in the database; kept its stored reason over {}",
+                    cancelled.order.id,
+                    reason.code()
+                );
+            }
+        }
+    }
+    if reason.notifies_owner() {
+        for cancelled in &closed {
+            eprintln!(
+                "order {} of user {} on {} cancelled ({}), {} open",
+                cancelled.order.id,
+                cancelled.order.user_id,
+                symbol,
+                reason.code(),
+                cancelled.remaining_quantity
+            );
+        }
+        ws::broadcast_orders_closed(&state.ws_channel, symbol, &closed);
+    }
+    closed
+}
@@ -0,0 +1,434 @@
+//! Spread orders: two limit orders on different symbols, typically a buy on one and a sell on
+//! the other, linked so that a fill on either leg adjusts the other (`POST /orders/spread`).
+//!
+//! Both legs are placed under both books' write locks. The tickets for the two symbols are
+//! taken together and the locks in symbol order, so two spreads over the same pair cannot
+//! deadlock. Both legs must rest on entry: a leg that would trade at once is rejected with
+//! `SPREAD_LEG_WOULD_TRADE`, so when the second leg fails (e.g. `BOOK_FULL`) cancelling the
+//! first undoes the spread completely.
+//!
+//! A fill on a leg is noticed in `settle_trades`, under that leg's book lock, and the policy
+//! then runs in a task of its own, one spread action at a time:
+//! - `cancel_other` cancels the other leg on the first fill;
+//! - `reduce_other` replaces the other leg (a new order id, at the back of its level) with
+//!   the quantity that keeps the legs' original ratio, and cancels it once the filled leg
+//!   is gone.
+//!
+//! A leg that is no longer resting unlinks the spread.
+
+use axum::{extract::State, http::StatusCode, response::Json};
+use futures_util::future::BoxFuture;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{Mutex, RwLock};
+use uuid::Uuid;
+
+use crate::api::auth::AuthUser;
+use crate::api::deadline::{RequestDeadline, lock_book};
+use crate::api::expiry;
+use crate::api::ingress::{SharedIngress, Ticket};
+use crate::api::phase_timer::PhaseTimer;
+use crate::api::routes::{
+    AppState, CreateOrderRequest, ErrorResponse, OrderResponse, find_orderbook, replace_locked,
+    unknown_symbol,
+};
+use crate::api::service::{self, NewOrder};
+use crate::api::validation;
+use crate::orderbook::orderbook::OrderBook;
+use crate::types::order::{CloseReason, Order, OrderId, OrderSource, OrderType, Qty};
+use crate::types::symbol::Symbol;
+use crate::types::trade::Trade;
+
+pub type SharedSpreads = Arc<Spreads>;
+
+/// What a fill on one leg does to the other.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SpreadPolicy {
+    CancelOther,
+    ReduceOther,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SpreadLeg {
+    pub symbol: Symbol,
+    /// The leg's current order; a `reduce_other` adjustment replaces it.
+    pub order_id: OrderId,
+    /// Quantity the leg was placed with, for the legs' ratio.
+    pub quantity: Qty,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Spread {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub policy: SpreadPolicy,
+    pub legs: [SpreadLeg; 2],
+}
+
+/// Spreads with both legs still linked.
+#[derive(Debug, Default)]
+pub struct Spreads {
+    linked: RwLock<Linked>,
+    /// Held while the two tickets of a placement are taken, so every spread queues for both
+    /// symbols in the same relative order.
+    admission: std::sync::Mutex<()>,
+    /// Held while a policy runs, so adjustments of one spread never interleave.
+    actions: Mutex<()>,
+}
+
+#[derive(Debug, Default)]
+struct Linked {
+    spreads: HashMap<Uuid, Spread>,
+    /// Order id of each leg -> its spread.
+    legs: HashMap<OrderId, Uuid>,
+}
+
+impl Spreads {
+    pub async fn get(&self, spread_id: Uuid) -> Option<Spread> {
+        self.linked.read().await.spreads.get(&spread_id).cloned()
+    }
+
+    /// The spread `order_id` is a leg of.
+    pub async fn of_order(&self, order_id: OrderId) -> Option<Spread> {
+        let linked = self.linked.read().await;
+        let spread_id = linked.legs.get(&order_id)?;
+        linked.spreads.get(spread_id).cloned()
+    }
+
+    /// Tickets for `first` and `second`, in that order.
+    fn admit(&self, ingress: &SharedIngress, first: &str, second: &str) -> (Ticket, Ticket) {
+        let _admission = self.admission.lock().unwrap();
+        (ingress.admit(first), ingress.admit(second))
+    }
+
+    async fn link(&self, spread: Spread) {
+        let mut linked = self.linked.write().await;
+        for leg in &spread.legs {
+            linked.legs.insert(leg.order_id, spread.id);
+        }
+        linked.spreads.insert(spread.id, spread);
+    }
+
+    async fn unlink(&self, spread_id: Uuid) {
+        let mut linked = self.linked.write().await;
+        if let Some(spread) = linked.spreads.remove(&spread_id) {
+            for leg in &spread.legs {
+                linked.legs.remove(&leg.order_id);
+            }
+        }
+    }
+
+    /// Point leg `leg` of `spread_id` at its replacement `order_id`.
+    async fn relink(&self, spread_id: Uuid, leg: usize, order_id: OrderId) {
+        let mut linked = self.linked.write().await;
+        let Some(spread) = linked.spreads.get_mut(&spread_id) else {
+            return;
+        };
+        let replaced = std::mem::replace(&mut spread.legs[leg].order_id, order_id);
+        linked.legs.remove(&replaced);
+        linked.legs.insert(order_id, spread_id);
+    }
+}
+
+#[derive(Deserialize)]
+pub struct CreateSpreadRequest {
+    legs: [CreateOrderRequest; 2],
+    policy: SpreadPolicy,
+}
+
+#[derive(Serialize)]
+pub struct SpreadResponse {
+    #[serde(flatten)]
+    spread: Spread,
+    orders: Vec<OrderResponse>,
+}
+
+/// POST /orders/spread: place both legs or neither.
+pub async fn create_spread(
+    auth: AuthUser,
+    deadline: RequestDeadline,
+    State(state): State<AppState>,
+    Json(body): Json<CreateSpreadRequest>,
+) -> Result<Json<SpreadResponse>, (StatusCode, Json<ErrorResponse>)> {
+    let mut legs = Vec::with_capacity(2);
+    for leg in body.legs {
+        let (symbol, violations) = validation::request_violations(&leg);
+        validation::first_violation(violations)?;
+        let symbol = symbol.expect("a symbol that failed to parse is a violation");
+        if leg.order_type != OrderType::Limit {
+            return Err(ErrorResponse::new(
+                "Spread legs must be limit orders".to_string(),
+                StatusCode::BAD_REQUEST,
+            ));
+        }
+        if let Some(violation) = expiry::ttl_violation(leg.order_type, leg.refresh_ttl_ms) {
+            return Err(violation);
+        }
+        let config = service::symbol_config(&state, &symbol).await;
+        let quantity = leg
+            .quantity
+            .to_lots(config.qty_scale)
+            .map_err(ErrorResponse::invalid_quantity)?;
+        legs.push((
+            NewOrder {
+                symbol,
+                price: leg.price,
+                quantity,
+                side: leg.side,
+                order_type: leg.order_type,
+                tags: leg.tags,
+                source: OrderSource::Spread,
+                session_scope: leg.session_scope,
+                refresh_ttl: leg.refresh_ttl_ms.map(Duration::from_millis),
+            },
+            config,
+        ));
+    }
+    if legs[0].0.symbol == legs[1].0.symbol {
+        return Err(ErrorResponse::new(
+            "Spread legs must be on different symbols".to_string(),
+            StatusCode::BAD_REQUEST,
+        ));
+    }
+    if legs[0].0.symbol.environment() != legs[1].0.symbol.environment() {
+        return Err(ErrorResponse::new(
+            "Spread legs must be in the same environment".to_string(),
+            StatusCode::BAD_REQUEST,
+        ));
+    }
+    let mut orderbooks = Vec::with_capacity(2);
+    for (new, _) in &legs {
+        let orderbook = find_orderbook(&state, &new.symbol, Some(auth.user_id))
+            .await
+            .ok_or_else(|| unknown_symbol(&state, &new.symbol))?;
+        orderbooks.push(orderbook);
+    }
+
+    // Locks in symbol order; `books[i]` is leg i's book either way
+    let (low, high) = if legs[0].0.symbol < legs[1].0.symbol {
+        (0, 1)
+    } else {
+        (1, 0)
+    };
+    let (low_ticket, high_ticket) =
+        state
+            .spreads
+            .admit(&state.ingress, &legs[low].0.symbol, &legs[high].0.symbol);
+    let mut low_book = lock_book(
+        &state,
+        &legs[low].0.symbol,
+        &orderbooks[low],
+        low_ticket,
+        &deadline,
+    )
+    .await?;
+    let mut high_book = lock_book(
+        &state,
+        &legs[high].0.symbol,
+        &orderbooks[high],
+        high_ticket,
+        &deadline,
+    )
+    .await?;
+    let books: [&mut OrderBook; 2] = if low == 0 {
+        [&mut low_book, &mut high_book]
+    } else {
+        [&mut high_book, &mut low_book]
+    };
+
+    for ((new, _), book) in legs.iter().zip(&books) {
+        let (violations, _) = validation::book_violations(
+            &state,
+            &new.symbol,
+            book,
+            None,
+            auth.user_id,
+            (new.side, new.order_type, new.price, new.quantity),
+            None,
+        )
+        .await;
+        validation::first_violation(violations)?;
+        let preview = book.simulate_order(
+            auth.user_id,
+            new.price,
+            new.quantity,
+            new.side,
+            new.order_type,
+        );
+        if !preview.trades.is_empty() {
+            return Err(ErrorResponse::spread_leg_would_trade(&new.symbol));
+        }
+    }
+
+    let [first_book, second_book] = books;
+    let mut orders: Vec<Order> = Vec::with_capacity(2);
+    let first = service::place_locked(
+        &state,
+        auth.user_id,
+        legs[0].0.clone(),
+        first_book,
+        &deadline,
+        &mut PhaseTimer::start(),
+    )
+    .await?
+    .order;
+    match service::place_locked(
+        &state,
+        auth.user_id,
+        legs[1].0.clone(),
+        second_book,
+        &deadline,
+        &mut PhaseTimer::start(),
+    )
+    .await
+    {
+        Ok(second) => orders.extend([first, second.order]),
+        Err(e) => {
+            service::close_orders(
+                &state,
+                &legs[0].0.symbol,
+                first_book,
+                [first.id],
+                CloseReason::SpreadRolledBack,
+            )
+            .await;
+            return Err(e);
+        }
+    }
+
+    let spread = Spread {
+        id: Uuid::new_v4(),
+        user_id: auth.user_id,
+        policy: body.policy,
+        legs: [0, 1].map(|i| SpreadLeg {
+            symbol: legs[i].0.symbol.clone(),
+            order_id: orders[i].id,
+            quantity: orders[i].quantity,
+        }),
+    };
+    // Linked before either book is released, so no fill on a leg goes unnoticed
+    state.spreads.link(spread.clone()).await;
+    drop(low_book);
+    drop(high_book);
+
+    let orders = orders
+        .into_iter()
+        .zip(&legs)
+        .map(|(order, (_, config))| OrderResponse::new(order, config))
+        .collect();
+    Ok(Json(SpreadResponse { spread, orders }))
+}
+
+/// Run the policy of every spread with a leg among `trades`' orders. Called under the book
+/// lock of `trades`' symbol, so the policies run later, in tasks of their own.
+pub(crate) async fn on_fills(state: &AppState, trades: &[Trade]) {
+    let mut filled: Vec<(Uuid, OrderId)> = {
+        let linked = state.spreads.linked.read().await;
+        if linked.legs.is_empty() {
+            return;
+        }
+        trades
+            .iter()
+            .flat_map(|t| [t.maker_order_id, t.taker_order_id])
+            .filter_map(|order_id| Some((*linked.legs.get(&order_id)?, order_id)))
+            .collect()
+    };
+    filled.sort();
+    filled.dedup();
+    for (spread_id, order_id) in filled {
+        let state = state.clone();
+        tokio::spawn(apply_policy(state, spread_id, order_id));
+    }
+}
+
+/// `leg`'s order, if it still rests.
+async fn resting(state: &AppState, user_id: Uuid, leg: &SpreadLeg) -> Option<Order> {
+    let orderbook = find_orderbook(state, &leg.symbol, Some(user_id)).await?;
+    orderbook.read().await.get_order_by_id(leg.order_id)
+}
+
+/// After a fill on order `filled_order`, bring the other leg of spread `spread_id` in line.
+/// Boxed because it settles fills of its own, which may in turn start it again.
+fn apply_policy(state: AppState, spread_id: Uuid, filled_order: OrderId) -> BoxFuture<'static, ()> {
+    Box::pin(async move {
+        let state = &state;
+        let _turn = state.spreads.actions.lock().await;
+        let Some(spread) = state.spreads.get(spread_id).await else {
+            return;
+        };
+        // A leg replaced since the fill was handled already
+        let Some(filled) = spread.legs.iter().position(|l| l.order_id == filled_order) else {
+            return;
+        };
+        let (leg, other) = (&spread.legs[filled], &spread.legs[1 - filled]);
+        let filled_open = resting(state, spread.user_id, leg)
+            .await
+            .map_or(0, |o| o.quantity);
+        let target = match spread.policy {
+            SpreadPolicy::CancelOther => 0,
+            SpreadPolicy::ReduceOther => {
+                (other.quantity as u128 * filled_open as u128 / leg.quantity.max(1) as u128) as Qty
+            }
+        };
+
+        let Some(orderbook) = find_orderbook(state, &other.symbol, Some(spread.user_id)).await
+        else {
+            state.spreads.unlink(spread_id).await;
+            return;
+        };
+        let ticket = state.ingress.admit(&other.symbol);
+        let deadline = RequestDeadline::after(state.request_timeout);
+        let mut book = match lock_book(state, &other.symbol, &orderbook, ticket, &deadline).await {
+            Ok(book) => book,
+            Err((_, Json(e))) => {
+                eprintln!("spread {}: {}", spread_id, e.error);
+                return;
+            }
+        };
+        let Some(other_order) = book.get_order_by_id(other.order_id) else {
+            drop(book);
+            state.spreads.unlink(spread_id).await;
+            return;
+        };
+        if target >= other_order.quantity {
+            return;
+        }
+        if target == 0 {
+            service::close_orders(
+                state,
+                &other.symbol,
+                &mut book,
+                [other.order_id],
+                CloseReason::SpreadLegFilled,
+            )
+            .await;
+            state.spreads.unlink(spread_id).await;
+            return;
+        }
+        match replace_locked(
+            state,
+            spread.user_id,
+            (other.order_id, other_order.side),
+            &other.symbol,
+            (other_order.price, target),
+            &mut book,
+            &deadline,
+        )
+        .await
+        {
+            // Relinked before the book is released, so a fill on the replacement is noticed
+            Ok(replaced) => {
+                state
+                    .spreads
+                    .relink(spread_id, 1 - filled, replaced.report.order.id)
+                    .await
+            }
+            Err((_, Json(e))) => {
+                eprintln!("spread {}: reducing leg failed: {}", spread_id, e.error)
+            }
+        }
+    })
+}
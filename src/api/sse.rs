@@ -0,0 +1,171 @@
+//! Market data over Server-Sent Events, for web clients that cannot hold a WebSocket (some
+//! proxies). `GET /sse/market?symbol=&channels=book,trades` bridges the same broadcast
+//! messages the WebSocket delivers, filtered the same way.
+//!
+//! Each event is named after its channel, carries the message JSON as data and a per-stream
+//! id counting up from 1. Missed events are not replayed: a client reconnecting with
+//! `Last-Event-ID` (or falling behind the broadcast channel) gets a fresh book snapshot, and
+//! ids carry on from the one it last saw.
+
+use axum::{
+    extract::{Query, State},
+    http::{HeaderMap, StatusCode},
+    response::{
+        Json,
+        sse::{Event, KeepAlive, Sse},
+    },
+};
+use futures_util::stream::{self, Stream};
+use serde::Deserialize;
+use std::collections::VecDeque;
+use std::time::Duration;
+use tokio::sync::broadcast::{self, error::RecvError};
+
+use crate::api::routes::{AppState, ErrorResponse, WsMessage, get_orderbook};
+use crate::api::ws::{Audience, audience};
+use crate::orderbook::orderbook::SharedOrderBook;
+use crate::types::symbol::Symbol;
+
+/// Interval of the keep-alive comments that stop idle proxies from closing the stream.
+pub const SSE_KEEP_ALIVE: Duration = Duration::from_secs(15);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Channel {
+    Book,
+    Trades,
+}
+
+impl Channel {
+    fn parse(name: &str) -> Option<Self> {
+        match name.trim() {
+            "book" => Some(Channel::Book),
+            "trades" => Some(Channel::Trades),
+            _ => None,
+        }
+    }
+
+    fn of(message: &WsMessage) -> Option<Self> {
+        match message {
+            WsMessage::OrderBookUpdate { .. } => Some(Channel::Book),
+            WsMessage::Trade { .. } => Some(Channel::Trades),
+            _ => None,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Channel::Book => "book",
+            Channel::Trades => "trades",
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct MarketStreamQuery {
+    symbol: Symbol,
+    /// Comma-separated channels; `book,trades` when omitted.
+    channels: Option<String>,
+}
+
+struct MarketStream {
+    symbol: Symbol,
+    orderbook: SharedOrderBook,
+    channels: Vec<Channel>,
+    receiver: broadcast::Receiver<WsMessage>,
+    next_id: u64,
+    pending: VecDeque<WsMessage>,
+}
+
+impl MarketStream {
+    fn wants(&self, message: &WsMessage) -> bool {
+        matches!(audience(message), Audience::Symbol(symbol) if symbol == self.symbol.as_str())
+            && Channel::of(message).is_some_and(|channel| self.channels.contains(&channel))
+    }
+
+    /// Queue a book snapshot if the stream carries the book.
+    async fn queue_snapshot(&mut self) {
+        if !self.channels.contains(&Channel::Book) {
+            return;
+        }
+        let book = self.orderbook.read().await;
+        self.pending.push_back(WsMessage::OrderBookUpdate {
+            symbol: self.symbol.to_string(),
+            bids: book.get_bids(),
+            asks: book.get_asks(),
+            diff: None,
+        });
+    }
+
+    fn event(&mut self, message: &WsMessage) -> Result<Event, axum::Error> {
+        let id = self.next_id;
+        self.next_id += 1;
+        Event::default()
+            .event(Channel::of(message).map_or("message", Channel::name))
+            .id(id.to_string())
+            .json_data(message)
+    }
+
+    async fn next_event(mut self) -> Option<(Result<Event, axum::Error>, Self)> {
+        loop {
+            if let Some(message) = self.pending.pop_front() {
+                return Some((self.event(&message), self));
+            }
+            match self.receiver.recv().await {
+                Ok(message) if self.wants(&message) => {
+                    return Some((self.event(&message), self));
+                }
+                Ok(_) => {}
+                Err(RecvError::Lagged(_)) => self.queue_snapshot().await,
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    }
+}
+
+/// GET /sse/market: book and trade events for one symbol as `text/event-stream`.
+pub async fn market_stream(
+    State(state): State<AppState>,
+    Query(params): Query<MarketStreamQuery>,
+    headers: HeaderMap,
+) -> Result<Sse<impl Stream<Item = Result<Event, axum::Error>>>, (StatusCode, Json<ErrorResponse>)>
+{
+    let orderbook = get_orderbook(&state, &params.symbol).await?;
+    let mut channels = Vec::new();
+    for name in params
+        .channels
+        .as_deref()
+        .unwrap_or("book,trades")
+        .split(',')
+    {
+        let channel = Channel::parse(name).ok_or_else(|| {
+            ErrorResponse::new(
+                format!(
+                    "Unknown channel '{}' (expected book or trades)",
+                    name.trim()
+                ),
+                StatusCode::BAD_REQUEST,
+            )
+        })?;
+        if !channels.contains(&channel) {
+            channels.push(channel);
+        }
+    }
+    let last_event_id = headers
+        .get("last-event-id")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.trim().parse::<u64>().ok());
+
+    let mut market = MarketStream {
+        symbol: params.symbol,
+        orderbook,
+        channels,
+        receiver: state.ws_channel.subscribe(),
+        next_id: last_event_id.map_or(1, |id| id.saturating_add(1)),
+        pending: VecDeque::new(),
+    };
+    if last_event_id.is_some() {
+        market.queue_snapshot().await;
+    }
+    let events = stream::unfold(market, MarketStream::next_event);
+    Ok(Sse::new(events).keep_alive(KeepAlive::new().interval(SSE_KEEP_ALIVE)))
+}
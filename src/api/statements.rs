@@ -0,0 +1,51 @@
+//! Statement downloads: the caller's daily statement as CSV. Statements are generated by
+//! `crate::statements`, at UTC midnight or through POST /admin/statements/generate.
+
+use axum::{
+    extract::{Query, State},
+    http::{StatusCode, header},
+    response::{IntoResponse, Json},
+};
+use chrono::NaiveDate;
+use serde::Deserialize;
+
+use crate::api::auth::AuthUser;
+use crate::api::routes::{AppState, ErrorResponse};
+use crate::persistence;
+
+#[derive(Deserialize)]
+pub struct StatementQuery {
+    date: NaiveDate,
+}
+
+/// GET /account/statements?date=YYYY-MM-DD: the caller's statement for that UTC day as a CSV
+/// attachment; 404 if none was generated (no activity, or the day has not been run yet).
+pub async fn download_statement(
+    auth: AuthUser,
+    State(state): State<AppState>,
+    Query(params): Query<StatementQuery>,
+) -> Result<impl IntoResponse, (StatusCode, Json<ErrorResponse>)> {
+    let Some(ref db) = state.db else {
+        return Err(ErrorResponse::new(
+            "Statements require a database".to_string(),
+            StatusCode::SERVICE_UNAVAILABLE,
+        ));
+    };
+    let row = persistence::get_statement(db, auth.user_id, params.date)
+        .await
+        .map_err(|e| ErrorResponse::from_db("Failed to load statement", e))?
+        .ok_or_else(|| {
+            ErrorResponse::new(
+                format!("No statement for {}", params.date),
+                StatusCode::NOT_FOUND,
+            )
+        })?;
+    let disposition = format!("attachment; filename=\"statement-{}.csv\"", params.date);
+    Ok((
+        [
+            (header::CONTENT_TYPE, "text/csv".to_string()),
+            (header::CONTENT_DISPOSITION, disposition),
+        ],
+        row.csv,
+    ))
+}
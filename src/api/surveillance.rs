@@ -0,0 +1,642 @@
+//! Market abuse surveillance. Every placement, owner-arranged cancel and fill on a public book
+//! is fed to `Surveillance`, which keeps rolling metrics per (user, symbol) over
+//! `SurveillanceConfig::window`:
+//! - the cancel-to-fill ratio, cancels per fill;
+//! - the average lifetime of the orders cancelled;
+//! - layering: orders at several price levels of one side cancelled together (within
+//!   `layer_window` of each other) after the user traded on the other side while they rested,
+//!   the shape of an order book spoof.
+//!
+//! Each event updates running totals and drops what left the window, so nothing rescans
+//! history, and each (user, symbol) keeps at most `max_events` events. A breached threshold
+//! raises a `SurveillanceAlert`, at most one of each kind per (user, symbol) per `cooldown`:
+//! it is logged, counted in `exchange_surveillance_alerts_total`, stored in
+//! `surveillance_alerts` with a database, and listed by `GET /admin/surveillance/alerts` until
+//! an admin acknowledges it with `POST /admin/surveillance/alerts/{id}/ack`.
+
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::Json,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
+use std::env;
+use std::fmt::Write;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+use uuid::Uuid;
+
+use crate::api::auth::AdminUser;
+use crate::api::routes::{AppState, ErrorResponse};
+use crate::persistence::{self, SurveillanceAlertRow};
+use crate::sandbox::is_sandbox_symbol;
+use crate::types::order::{CloseReason, Order, OrderSide, Price};
+use crate::types::trade::Trade;
+
+/// Alerts kept in memory for `GET /admin/surveillance/alerts` without a database.
+const RECENT_ALERTS: usize = 1000;
+const DEFAULT_ALERTS_LIMIT: usize = 100;
+const MAX_ALERTS_LIMIT: usize = 1000;
+
+pub type SharedSurveillance = Arc<Surveillance>;
+
+/// Thresholds of the surveillance checks.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SurveillanceConfig {
+    /// How far back the cancel-to-fill ratio and the average lifetime look.
+    pub window: Duration,
+    /// Cancels in the window before the ratio and the lifetime are judged at all.
+    pub min_cancels: u32,
+    /// Alert when there are more cancels than this per fill (a window without fills counts
+    /// as one).
+    pub max_cancel_to_fill: f64,
+    /// Alert when the orders cancelled in the window lived less than this on average.
+    pub min_avg_lifetime: Duration,
+    /// Cancels on one side at most this far apart are cancelled together.
+    pub layer_window: Duration,
+    /// Distinct price levels cancelled together that make a layering alert.
+    pub layer_min_levels: usize,
+    /// A (user, symbol) is not alerted for the same kind again for this long.
+    pub cooldown: Duration,
+    /// Events kept per (user, symbol); the oldest leave the window early beyond it.
+    pub max_events: usize,
+}
+
+impl Default for SurveillanceConfig {
+    fn default() -> Self {
+        Self {
+            window: Duration::from_secs(60),
+            min_cancels: 50,
+            max_cancel_to_fill: 25.0,
+            min_avg_lifetime: Duration::from_millis(500),
+            layer_window: Duration::from_secs(1),
+            layer_min_levels: 4,
+            cooldown: Duration::from_secs(5 * 60),
+            max_events: 2000,
+        }
+    }
+}
+
+impl SurveillanceConfig {
+    /// Read `SURVEILLANCE_*` variables, falling back to the defaults.
+    pub fn from_env() -> Self {
+        fn var<T: std::str::FromStr>(name: &str) -> Option<T> {
+            env::var(name).ok().and_then(|v| v.parse().ok())
+        }
+        let defaults = Self::default();
+        Self {
+            window: var::<u64>("SURVEILLANCE_WINDOW_SECS")
+                .filter(|&secs| secs > 0)
+                .map_or(defaults.window, Duration::from_secs),
+            min_cancels: var("SURVEILLANCE_MIN_CANCELS").unwrap_or(defaults.min_cancels),
+            max_cancel_to_fill: var("SURVEILLANCE_MAX_CANCEL_TO_FILL")
+                .unwrap_or(defaults.max_cancel_to_fill),
+            min_avg_lifetime: var("SURVEILLANCE_MIN_AVG_LIFETIME_MS")
+                .map_or(defaults.min_avg_lifetime, Duration::from_millis),
+            layer_window: var("SURVEILLANCE_LAYER_WINDOW_MS")
+                .map_or(defaults.layer_window, Duration::from_millis),
+            layer_min_levels: var::<usize>("SURVEILLANCE_LAYER_MIN_LEVELS")
+                .filter(|&levels| levels > 1)
+                .unwrap_or(defaults.layer_min_levels),
+            cooldown: var("SURVEILLANCE_COOLDOWN_SECS")
+                .map_or(defaults.cooldown, Duration::from_secs),
+            max_events: var::<usize>("SURVEILLANCE_MAX_EVENTS")
+                .filter(|&n| n > 0)
+                .unwrap_or(defaults.max_events),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AlertKind {
+    CancelToFill,
+    ShortLifetime,
+    Layering,
+}
+
+impl AlertKind {
+    const ALL: [AlertKind; 3] = [Self::CancelToFill, Self::ShortLifetime, Self::Layering];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::CancelToFill => "cancel_to_fill",
+            Self::ShortLifetime => "short_lifetime",
+            Self::Layering => "layering",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|kind| kind.as_str() == s)
+    }
+
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+/// The (user, symbol)'s metrics when an alert was raised.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AlertMetrics {
+    /// Orders placed, cancelled and fills in the window.
+    pub placed: u32,
+    pub cancelled: u32,
+    pub fills: u32,
+    pub cancel_to_fill_ratio: f64,
+    /// Average lifetime of the orders cancelled in the window.
+    pub avg_lifetime_ms: Option<i64>,
+    /// For layering: the side cancelled and its distinct price levels.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub layered_side: Option<OrderSide>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub layered_prices: Vec<Price>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SurveillanceAlert {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub symbol: String,
+    pub kind: AlertKind,
+    pub metrics: AlertMetrics,
+    pub raised_at: DateTime<Utc>,
+    pub acknowledged_by: Option<Uuid>,
+    pub acknowledged_at: Option<DateTime<Utc>>,
+}
+
+impl SurveillanceAlert {
+    pub fn to_row(&self) -> SurveillanceAlertRow {
+        SurveillanceAlertRow {
+            id: self.id,
+            user_id: self.user_id,
+            symbol: self.symbol.clone(),
+            kind: self.kind.as_str().to_string(),
+            metrics: sqlx::types::Json(
+                serde_json::to_value(&self.metrics).unwrap_or(serde_json::Value::Null),
+            ),
+            raised_at: self.raised_at,
+            acknowledged_by: self.acknowledged_by,
+            acknowledged_at: self.acknowledged_at,
+        }
+    }
+
+    pub fn from_row(row: SurveillanceAlertRow) -> Option<Self> {
+        Some(Self {
+            id: row.id,
+            user_id: row.user_id,
+            kind: AlertKind::parse(&row.kind)?,
+            metrics: serde_json::from_value(row.metrics.0).ok()?,
+            symbol: row.symbol,
+            raised_at: row.raised_at,
+            acknowledged_by: row.acknowledged_by,
+            acknowledged_at: row.acknowledged_at,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Event {
+    Placed,
+    Cancelled { lifetime_ms: i64 },
+    Filled,
+}
+
+/// Orders of one side cancelled together.
+#[derive(Debug)]
+struct Burst {
+    side: OrderSide,
+    started_at: DateTime<Utc>,
+    /// Stops growing at `layer_min_levels`.
+    prices: BTreeSet<Price>,
+    /// When the oldest of the cancelled orders was placed.
+    oldest_placed: DateTime<Utc>,
+    alerted: bool,
+}
+
+/// One (user, symbol)'s events in the window and their running totals.
+#[derive(Debug, Default)]
+struct Activity {
+    events: VecDeque<(DateTime<Utc>, Event)>,
+    placed: u32,
+    cancelled: u32,
+    fills: u32,
+    lifetime_ms: i64,
+    /// Latest fill per side (`Buy`, `Sell`), kept past the window.
+    last_fill: [Option<DateTime<Utc>>; 2],
+    burst: Option<Burst>,
+    last_alert: [Option<DateTime<Utc>>; 3],
+}
+
+fn side_index(side: OrderSide) -> usize {
+    match side {
+        OrderSide::Buy => 0,
+        OrderSide::Sell => 1,
+    }
+}
+
+impl Activity {
+    fn push(&mut self, at: DateTime<Utc>, event: Event, config: &SurveillanceConfig) {
+        if self.events.len() >= config.max_events {
+            self.pop();
+        }
+        match event {
+            Event::Placed => self.placed += 1,
+            Event::Cancelled { lifetime_ms } => {
+                self.cancelled += 1;
+                self.lifetime_ms += lifetime_ms;
+            }
+            Event::Filled => self.fills += 1,
+        }
+        self.events.push_back((at, event));
+        let Ok(window) = chrono::Duration::from_std(config.window) else {
+            return;
+        };
+        while self.events.front().is_some_and(|(t, _)| *t + window < at) {
+            self.pop();
+        }
+    }
+
+    fn pop(&mut self) {
+        match self.events.pop_front() {
+            Some((_, Event::Placed)) => self.placed -= 1,
+            Some((_, Event::Cancelled { lifetime_ms })) => {
+                self.cancelled -= 1;
+                self.lifetime_ms -= lifetime_ms;
+            }
+            Some((_, Event::Filled)) => self.fills -= 1,
+            None => {}
+        }
+    }
+
+    fn metrics(&self) -> AlertMetrics {
+        AlertMetrics {
+            placed: self.placed,
+            cancelled: self.cancelled,
+            fills: self.fills,
+            cancel_to_fill_ratio: self.cancelled as f64 / self.fills.max(1) as f64,
+            avg_lifetime_ms: (self.cancelled > 0)
+                .then(|| self.lifetime_ms / i64::from(self.cancelled)),
+            layered_side: None,
+            layered_prices: Vec::new(),
+        }
+    }
+
+    /// Start the cooldown of `kind` at `at`, unless one is running.
+    fn cooled_down(&mut self, kind: AlertKind, at: DateTime<Utc>, cooldown: Duration) -> bool {
+        let last = &mut self.last_alert[kind.index()];
+        let cooldown = chrono::Duration::from_std(cooldown).unwrap_or(chrono::Duration::MAX);
+        if last.is_some_and(|last| at < last + cooldown) {
+            return false;
+        }
+        *last = Some(at);
+        true
+    }
+}
+
+#[derive(Debug, Default)]
+struct AlertLog {
+    recent: VecDeque<SurveillanceAlert>,
+    raised: BTreeMap<AlertKind, u64>,
+}
+
+/// Rolling metrics per (user, symbol) and the alerts they raised.
+#[derive(Debug, Default)]
+pub struct Surveillance {
+    config: SurveillanceConfig,
+    activity: Mutex<HashMap<(Uuid, String), Activity>>,
+    alerts: Mutex<AlertLog>,
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+impl Surveillance {
+    pub fn new(config: SurveillanceConfig) -> Self {
+        Self {
+            config,
+            ..Self::default()
+        }
+    }
+
+    pub fn config(&self) -> &SurveillanceConfig {
+        &self.config
+    }
+
+    fn observe<T>(
+        &self,
+        user_id: Uuid,
+        symbol: &str,
+        observe: impl FnOnce(&mut Activity, &SurveillanceConfig) -> T,
+    ) -> T {
+        let mut activity = lock(&self.activity);
+        let entry = activity.entry((user_id, symbol.to_string())).or_default();
+        observe(entry, &self.config)
+    }
+
+    /// `order` reached `symbol`'s book.
+    pub fn observe_placed(&self, symbol: &str, order: &Order) {
+        self.observe(order.user_id, symbol, |activity, config| {
+            activity.push(order.timestamp, Event::Placed, config)
+        });
+    }
+
+    /// `trades`, taken by `taker_user_id` on `taker_side`, filled orders of both sides.
+    pub fn observe_fills(
+        &self,
+        symbol: &str,
+        taker_user_id: Uuid,
+        taker_side: OrderSide,
+        trades: &[Trade],
+    ) {
+        for trade in trades {
+            for (user_id, side) in [
+                (taker_user_id, taker_side),
+                (trade.maker_user_id, taker_side.opposite()),
+            ] {
+                self.observe(user_id, symbol, |activity, config| {
+                    activity.push(trade.timestamp, Event::Filled, config);
+                    activity.last_fill[side_index(side)] = Some(trade.timestamp);
+                });
+            }
+        }
+    }
+
+    /// Its owner had `order` cancelled at `at`. Returns the alerts this raised.
+    pub fn observe_cancel(
+        &self,
+        symbol: &str,
+        order: &Order,
+        at: DateTime<Utc>,
+    ) -> Vec<SurveillanceAlert> {
+        self.observe(order.user_id, symbol, |activity, config| {
+            let lifetime_ms = (at - order.timestamp).num_milliseconds().max(0);
+            activity.push(at, Event::Cancelled { lifetime_ms }, config);
+            let mut raised = Vec::new();
+            let metrics = activity.metrics();
+            if metrics.cancelled >= config.min_cancels {
+                if metrics.cancel_to_fill_ratio > config.max_cancel_to_fill
+                    && activity.cooled_down(AlertKind::CancelToFill, at, config.cooldown)
+                {
+                    raised.push((AlertKind::CancelToFill, metrics.clone()));
+                }
+                if metrics
+                    .avg_lifetime_ms
+                    .is_some_and(|ms| ms < config.min_avg_lifetime.as_millis() as i64)
+                    && activity.cooled_down(AlertKind::ShortLifetime, at, config.cooldown)
+                {
+                    raised.push((AlertKind::ShortLifetime, metrics.clone()));
+                }
+            }
+            if let Some(burst) = layered(activity, order, at, config)
+                && activity.cooled_down(AlertKind::Layering, at, config.cooldown)
+            {
+                raised.push((
+                    AlertKind::Layering,
+                    AlertMetrics {
+                        layered_side: Some(burst.0),
+                        layered_prices: burst.1,
+                        ..metrics
+                    },
+                ));
+            }
+            raised
+        })
+        .into_iter()
+        .map(|(kind, metrics)| {
+            self.raise(SurveillanceAlert {
+                id: Uuid::new_v4(),
+                user_id: order.user_id,
+                symbol: symbol.to_string(),
+                kind,
+                metrics,
+                raised_at: at,
+                acknowledged_by: None,
+                acknowledged_at: None,
+            })
+        })
+        .collect()
+    }
+
+    fn raise(&self, alert: SurveillanceAlert) -> SurveillanceAlert {
+        let mut log = lock(&self.alerts);
+        *log.raised.entry(alert.kind).or_default() += 1;
+        if log.recent.len() == RECENT_ALERTS {
+            log.recent.pop_front();
+        }
+        log.recent.push_back(alert.clone());
+        alert
+    }
+
+    /// The alerts raised since startup, oldest first.
+    pub fn recent(&self) -> Vec<SurveillanceAlert> {
+        lock(&self.alerts).recent.iter().cloned().collect()
+    }
+
+    /// Mark alert `id` acknowledged by `admin_id`, unless it already is. None if not kept.
+    pub fn acknowledge(
+        &self,
+        id: Uuid,
+        admin_id: Uuid,
+        at: DateTime<Utc>,
+    ) -> Option<SurveillanceAlert> {
+        let mut log = lock(&self.alerts);
+        let alert = log.recent.iter_mut().find(|alert| alert.id == id)?;
+        if alert.acknowledged_at.is_none() {
+            alert.acknowledged_by = Some(admin_id);
+            alert.acknowledged_at = Some(at);
+        }
+        Some(alert.clone())
+    }
+
+    /// Prometheus text exposition of the alert counters.
+    pub fn render_prometheus(&self) -> String {
+        let mut out = String::from(
+            "# HELP exchange_surveillance_alerts_total Surveillance alerts raised, by kind.\n\
+             # TYPE exchange_surveillance_alerts_total counter\n",
+        );
+        let log = lock(&self.alerts);
+        for kind in AlertKind::ALL {
+            let _ = writeln!(
+                out,
+                "exchange_surveillance_alerts_total{{kind=\"{}\"}} {}",
+                kind.as_str(),
+                log.raised.get(&kind).copied().unwrap_or(0)
+            );
+        }
+        out
+    }
+}
+
+/// Add the cancel of `order` at `at` to the burst of cancels on its side, starting a new one
+/// when the side changed or the last one is over. The burst's side and levels once it spans
+/// `layer_min_levels` levels and the user traded on the other side after its oldest order
+/// was placed.
+fn layered(
+    activity: &mut Activity,
+    order: &Order,
+    at: DateTime<Utc>,
+    config: &SurveillanceConfig,
+) -> Option<(OrderSide, Vec<Price>)> {
+    let window = chrono::Duration::from_std(config.layer_window).unwrap_or(chrono::Duration::MAX);
+    let burst = match activity.burst {
+        Some(ref mut burst) if burst.side == order.side && at <= burst.started_at + window => burst,
+        _ => activity.burst.insert(Burst {
+            side: order.side,
+            started_at: at,
+            prices: BTreeSet::new(),
+            oldest_placed: order.timestamp,
+            alerted: false,
+        }),
+    };
+    if burst.prices.len() < config.layer_min_levels {
+        burst.prices.insert(order.price);
+    }
+    burst.oldest_placed = burst.oldest_placed.min(order.timestamp);
+    let traded_against = activity.last_fill[side_index(order.side.opposite())]
+        .is_some_and(|filled| filled >= burst.oldest_placed);
+    if burst.alerted || burst.prices.len() < config.layer_min_levels || !traded_against {
+        return None;
+    }
+    burst.alerted = true;
+    Some((burst.side, burst.prices.iter().copied().collect()))
+}
+
+/// Whether a cancel for `reason` was the owner's doing: their own cancel, a TTL they set, or
+/// a socket they placed with cancel-on-disconnect dropping.
+pub fn owner_arranged(reason: CloseReason) -> bool {
+    matches!(
+        reason,
+        CloseReason::UserCancelled | CloseReason::TtlExpired | CloseReason::CancelOnDisconnect
+    )
+}
+
+/// Feed `order`, which just reached `symbol`'s book, to surveillance.
+pub(crate) fn on_placed(state: &AppState, symbol: &str, order: &Order) {
+    if !is_sandbox_symbol(symbol) {
+        state.surveillance.observe_placed(symbol, order);
+    }
+}
+
+/// Feed `trades`, taken by `taker_user_id` on `taker_side`, to surveillance.
+pub(crate) fn on_fills(
+    state: &AppState,
+    symbol: &str,
+    taker_user_id: Uuid,
+    taker_side: OrderSide,
+    trades: &[Trade],
+) {
+    if !is_sandbox_symbol(symbol) {
+        state
+            .surveillance
+            .observe_fills(symbol, taker_user_id, taker_side, trades);
+    }
+}
+
+/// Feed the owner-arranged cancels of `orders` on `symbol`, closed at `at`, to surveillance
+/// and record the alerts they raise. Sandbox books are not watched.
+pub(crate) async fn on_cancels<'a>(
+    state: &AppState,
+    symbol: &str,
+    orders: impl IntoIterator<Item = &'a Order>,
+    reason: CloseReason,
+    at: DateTime<Utc>,
+) {
+    if !owner_arranged(reason) || is_sandbox_symbol(symbol) {
+        return;
+    }
+    let alerts: Vec<SurveillanceAlert> = orders
+        .into_iter()
+        .flat_map(|order| state.surveillance.observe_cancel(symbol, order, at))
+        .collect();
+    for alert in alerts {
+        eprintln!(
+            "surveillance alert {} ({}) for user {} on {}: {:?}",
+            alert.id,
+            alert.kind.as_str(),
+            alert.user_id,
+            alert.symbol,
+            alert.metrics
+        );
+        if let Some(ref db) = state.db
+            && let Err(e) = persistence::insert_surveillance_alert(db, &alert.to_row()).await
+        {
+            eprintln!("failed to store surveillance alert {}: {}", alert.id, e);
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct AlertsQuery {
+    /// `false` for the alerts still waiting for an acknowledgment.
+    acknowledged: Option<bool>,
+    user_id: Option<Uuid>,
+    limit: Option<usize>,
+}
+
+/// GET /admin/surveillance/alerts?acknowledged=&user_id=&limit=: surveillance alerts, newest
+/// first. Read from the database when there is one, else from the alerts since startup.
+pub async fn list_alerts(
+    _admin: AdminUser,
+    State(state): State<AppState>,
+    Query(params): Query<AlertsQuery>,
+) -> Result<Json<Vec<SurveillanceAlert>>, (StatusCode, Json<ErrorResponse>)> {
+    let limit = params
+        .limit
+        .unwrap_or(DEFAULT_ALERTS_LIMIT)
+        .min(MAX_ALERTS_LIMIT);
+    let Some(ref db) = state.db else {
+        let mut alerts = state.surveillance.recent();
+        alerts.reverse();
+        alerts.retain(|alert| {
+            params
+                .acknowledged
+                .is_none_or(|acked| alert.acknowledged_at.is_some() == acked)
+                && params
+                    .user_id
+                    .is_none_or(|user_id| alert.user_id == user_id)
+        });
+        alerts.truncate(limit);
+        return Ok(Json(alerts));
+    };
+    let rows =
+        persistence::list_surveillance_alerts(db, params.acknowledged, params.user_id, limit)
+            .await
+            .map_err(|e| ErrorResponse::from_db("Failed to list surveillance alerts", e))?;
+    Ok(Json(
+        rows.into_iter()
+            .filter_map(SurveillanceAlert::from_row)
+            .collect(),
+    ))
+}
+
+/// POST /admin/surveillance/alerts/{id}/ack: mark an alert reviewed. Acknowledging it again
+/// keeps the first acknowledgment. 404 for an unknown alert.
+pub async fn acknowledge_alert(
+    admin: AdminUser,
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<SurveillanceAlert>, (StatusCode, Json<ErrorResponse>)> {
+    let now = state.clock.now();
+    let acknowledged = state.surveillance.acknowledge(id, admin.user_id, now);
+    let acknowledged = match state.db {
+        Some(ref db) => persistence::acknowledge_surveillance_alert(db, id, admin.user_id, now)
+            .await
+            .map_err(|e| ErrorResponse::from_db("Failed to acknowledge surveillance alert", e))?
+            .and_then(SurveillanceAlert::from_row),
+        None => acknowledged,
+    };
+    let alert = acknowledged.ok_or_else(|| {
+        ErrorResponse::new(
+            format!("Surveillance alert '{}' not found", id),
+            StatusCode::NOT_FOUND,
+        )
+    })?;
+    eprintln!(
+        "surveillance alert {} acknowledged by {}",
+        id, admin.user_id
+    );
+    Ok(Json(alert))
+}
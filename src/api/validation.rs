@@ -0,0 +1,216 @@
+//! Checks an order goes through before it reaches matching. Order entry (`POST /orders`,
+//! replace, preview) fails with the first violation; `POST /orders/validate` runs the same
+//! checks under a read lock and reports every violation, so the two cannot drift.
+
+use axum::{extract::State, http::StatusCode, response::Json};
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::api::auth::AuthUser;
+use crate::api::expiry;
+use crate::api::routes::{
+    AppState, CreateOrderRequest, ErrorResponse, find_orderbook, unknown_symbol,
+};
+use crate::api::service::{self, balance_lock};
+use crate::balances::{Ledger, LockSpec};
+use crate::orderbook::orderbook::{OrderBook, TradingPhase};
+use crate::types::order::{OrderId, OrderSide, OrderType, RejectReason, validate_order_tags};
+use crate::types::symbol::Symbol;
+
+/// One broken rule, as order entry would report it.
+pub(crate) type Violation = (StatusCode, Json<ErrorResponse>);
+
+/// `Err` with the first of `violations`, the one order entry reports.
+pub(crate) fn first_violation(violations: Vec<Violation>) -> Result<(), Violation> {
+    match violations.into_iter().next() {
+        Some(violation) => Err(violation),
+        None => Ok(()),
+    }
+}
+
+/// Checks on the request alone: the symbol parses (in the requested environment), the tags
+/// are within bounds and no source was supplied. The symbol is None when it did not parse.
+pub(crate) fn request_violations(body: &CreateOrderRequest) -> (Option<Symbol>, Vec<Violation>) {
+    let mut violations = Vec::new();
+    let symbol = body
+        .book_symbol()
+        .map_err(|e| violations.push(ErrorResponse::invalid_symbol(e)))
+        .ok();
+    if let Err(msg) = validate_order_tags(&body.tags) {
+        violations.push(ErrorResponse::new(msg, StatusCode::BAD_REQUEST));
+    }
+    if body.source.is_some() {
+        violations.push(ErrorResponse::new(
+            "Order source is set by the server and cannot be supplied".to_string(),
+            StatusCode::BAD_REQUEST,
+        ));
+    }
+    (symbol, violations)
+}
+
+/// Checks against the book and the caller's funds: `symbol`'s trading rules, then the balance
+/// the order needs locked when balances are tracked (`ledger`). `replaced` is an order being
+/// replaced, whose lock the new one may reuse. Also returns the lock the order takes once it
+/// has matched.
+pub(crate) async fn book_violations(
+    state: &AppState,
+    symbol: &Symbol,
+    book: &OrderBook,
+    ledger: Option<&Ledger>,
+    user_id: Uuid,
+    (side, order_type, price, quantity): (OrderSide, OrderType, i64, u64),
+    replaced: Option<OrderId>,
+) -> (Vec<Violation>, Option<LockSpec>) {
+    let config = service::symbol_config(state, symbol).await;
+    let mut violations: Vec<Violation> = config
+        .order_violations(price, quantity, order_type, book.last_trade_price())
+        .into_iter()
+        .map(ErrorResponse::rejected)
+        .collect();
+    let lock = match ledger {
+        Some(ledger) => balance_lock(
+            ledger,
+            book,
+            (symbol, &config),
+            user_id,
+            (side, order_type, price, quantity),
+            replaced,
+        )
+        .unwrap_or_else(|violation| {
+            violations.push(violation);
+            None
+        }),
+        None => None,
+    };
+    (violations, lock)
+}
+
+/// What matching may do with a valid order, given the book as it stands now.
+#[derive(Debug, Serialize)]
+pub struct ValidationWarning {
+    pub code: &'static str,
+    pub message: String,
+}
+
+fn matching_warnings(
+    book: &OrderBook,
+    side: OrderSide,
+    order_type: OrderType,
+    price: i64,
+) -> Vec<ValidationWarning> {
+    let opposite = match side {
+        OrderSide::Buy => book.best_ask(),
+        OrderSide::Sell => book.best_bid(),
+    };
+    let mut warnings = Vec::new();
+    if order_type == OrderType::Market {
+        if book.phase() == TradingPhase::Auction {
+            warnings.push(rejection_warning(RejectReason::MarketOrderInAuction));
+        } else if opposite.is_none() {
+            warnings.push(rejection_warning(RejectReason::NoLiquidity));
+        }
+    } else if book.phase() == TradingPhase::Continuous
+        && opposite.is_some_and(|best| match side {
+            OrderSide::Buy => price >= best,
+            OrderSide::Sell => price <= best,
+        })
+    {
+        warnings.push(ValidationWarning {
+            code: "CROSSES_BOOK",
+            message: "Order crosses the book and would trade immediately as taker".to_string(),
+        });
+    }
+    warnings
+}
+
+fn rejection_warning(reason: RejectReason) -> ValidationWarning {
+    ValidationWarning {
+        code: reason.code(),
+        message: reason.to_string(),
+    }
+}
+
+#[derive(Serialize)]
+pub struct ValidationPassed {
+    valid: bool,
+    warnings: Vec<ValidationWarning>,
+}
+
+#[derive(Serialize)]
+pub struct ValidationFailed {
+    valid: bool,
+    error: String,
+    code: u16,
+    error_code: &'static str,
+    /// Every broken rule, each as order entry would report it; it reports the first.
+    violations: Vec<ErrorResponse>,
+}
+
+fn validation_failed(violations: Vec<Violation>) -> (StatusCode, Json<ValidationFailed>) {
+    let status_code = StatusCode::BAD_REQUEST;
+    (
+        status_code,
+        Json(ValidationFailed {
+            valid: false,
+            error: "Order failed validation".to_string(),
+            code: status_code.as_u16(),
+            error_code: "VALIDATION_FAILED",
+            violations: violations
+                .into_iter()
+                .map(|(_, Json(violation))| violation)
+                .collect(),
+        }),
+    )
+}
+
+/// POST /orders/validate: run order entry's checks up to matching without placing anything,
+/// under a read lock. 400 `VALIDATION_FAILED` lists every violation; otherwise 200 with
+/// warnings about what matching may do with the order as the book stands.
+pub(crate) async fn validate_order(
+    auth: AuthUser,
+    State(state): State<AppState>,
+    Json(body): Json<CreateOrderRequest>,
+) -> Result<Json<ValidationPassed>, (StatusCode, Json<ValidationFailed>)> {
+    let (symbol, mut violations) = request_violations(&body);
+    violations.extend(expiry::ttl_violation(body.order_type, body.refresh_ttl_ms));
+    let Some(symbol) = symbol else {
+        return Err(validation_failed(violations));
+    };
+    let Some(orderbook) = find_orderbook(&state, &symbol, Some(auth.user_id)).await else {
+        violations.push(unknown_symbol(&state, &symbol));
+        return Err(validation_failed(violations));
+    };
+
+    let qty_scale = service::symbol_config(&state, &symbol).await.qty_scale;
+    let quantity = match body.quantity.to_lots(qty_scale) {
+        Ok(quantity) => quantity,
+        Err(e) => {
+            violations.push(ErrorResponse::invalid_quantity(e));
+            return Err(validation_failed(violations));
+        }
+    };
+
+    let book = orderbook.read().await;
+    let ledger = service::symbol_ledger(&state, &symbol).await;
+    let order = (body.side, body.order_type, body.price, quantity);
+    let (book_violations, _) = book_violations(
+        &state,
+        &symbol,
+        &book,
+        ledger.as_deref(),
+        auth.user_id,
+        order,
+        None,
+    )
+    .await;
+    drop(ledger);
+    violations.extend(book_violations);
+    if !violations.is_empty() {
+        return Err(validation_failed(violations));
+    }
+    let warnings = matching_warnings(&book, body.side, body.order_type, body.price);
+    Ok(Json(ValidationPassed {
+        valid: true,
+        warnings,
+    }))
+}
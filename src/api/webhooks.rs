@@ -0,0 +1,139 @@
+//! Webhook endpoints: register, list and remove the caller's webhooks and read their
+//! delivery logs. Delivery itself runs in `crate::webhooks`.
+
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::Json,
+};
+use chrono::Utc;
+use serde::Deserialize;
+use uuid::Uuid;
+
+use crate::api::auth::AuthUser;
+use crate::api::routes::{AppState, ErrorResponse};
+use crate::persistence;
+use crate::webhooks::{DeliveryRecord, Webhook, WebhookError, WebhookEventType, WebhookInfo};
+
+/// Deliveries returned when `limit` is not given.
+const DEFAULT_DELIVERIES_LIMIT: usize = 50;
+const MAX_DELIVERIES_LIMIT: usize = 500;
+
+fn webhook_error(e: WebhookError) -> (StatusCode, Json<ErrorResponse>) {
+    let status = match e {
+        WebhookError::InvalidUrl(_) | WebhookError::InvalidSecret | WebhookError::NoEvents => {
+            StatusCode::BAD_REQUEST
+        }
+        WebhookError::LimitReached(_) => StatusCode::CONFLICT,
+        WebhookError::NotFound(_) => StatusCode::NOT_FOUND,
+    };
+    ErrorResponse::new(e.to_string(), status)
+}
+
+#[derive(Deserialize)]
+pub struct CreateWebhookRequest {
+    url: String,
+    /// Key for the `X-Webhook-Signature` HMAC; never returned.
+    secret: String,
+    events: Vec<WebhookEventType>,
+}
+
+/// POST /webhooks: register a URL to receive the caller's events.
+pub async fn create_webhook(
+    auth: AuthUser,
+    State(state): State<AppState>,
+    Json(body): Json<CreateWebhookRequest>,
+) -> Result<(StatusCode, Json<WebhookInfo>), (StatusCode, Json<ErrorResponse>)> {
+    let webhook = Webhook::new(
+        auth.user_id,
+        &body.url,
+        body.secret,
+        body.events,
+        Utc::now(),
+    )
+    .map_err(webhook_error)?;
+    // Stored first, so deliveries logged as soon as it is added have their webhook row
+    let id = webhook.id;
+    if let Some(ref db) = state.db {
+        persistence::insert_webhook(db, &webhook.to_row())
+            .await
+            .map_err(|e| ErrorResponse::from_db("Failed to save webhook", e))?;
+    }
+    let info = match state.webhooks.add(webhook, state.db.clone()) {
+        Ok(info) => info,
+        Err(e) => {
+            if let Some(ref db) = state.db
+                && let Err(e) = persistence::delete_webhook(db, id).await
+            {
+                eprintln!("failed to delete rejected webhook {}: {}", id, e);
+            }
+            return Err(webhook_error(e));
+        }
+    };
+    eprintln!("webhook {} registered by {}", info.id, auth.user_id);
+    Ok((StatusCode::CREATED, Json(info)))
+}
+
+/// GET /webhooks: the caller's webhooks, oldest first.
+pub async fn list_webhooks(
+    auth: AuthUser,
+    State(state): State<AppState>,
+) -> Json<Vec<WebhookInfo>> {
+    Json(state.webhooks.list(auth.user_id))
+}
+
+/// DELETE /webhooks/{id}: stop delivering to one of the caller's webhooks and drop its log.
+pub async fn delete_webhook(
+    auth: AuthUser,
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+) -> Result<StatusCode, (StatusCode, Json<ErrorResponse>)> {
+    state
+        .webhooks
+        .get(id, auth.user_id)
+        .map_err(webhook_error)?;
+    if let Some(ref db) = state.db {
+        persistence::delete_webhook(db, id)
+            .await
+            .map_err(|e| ErrorResponse::from_db("Failed to delete webhook", e))?;
+    }
+    state
+        .webhooks
+        .remove(id, auth.user_id)
+        .map_err(webhook_error)?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[derive(Deserialize)]
+pub struct DeliveriesQuery {
+    limit: Option<usize>,
+}
+
+/// GET /webhooks/{id}/deliveries?limit=: the webhook's finished deliveries, newest first.
+/// Read from the database when there is one, else from the in-memory log.
+pub async fn list_deliveries(
+    auth: AuthUser,
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+    Query(params): Query<DeliveriesQuery>,
+) -> Result<Json<Vec<DeliveryRecord>>, (StatusCode, Json<ErrorResponse>)> {
+    state
+        .webhooks
+        .get(id, auth.user_id)
+        .map_err(webhook_error)?;
+    let limit = params
+        .limit
+        .unwrap_or(DEFAULT_DELIVERIES_LIMIT)
+        .min(MAX_DELIVERIES_LIMIT);
+    let Some(ref db) = state.db else {
+        return Ok(Json(state.webhooks.recent_deliveries(id, limit)));
+    };
+    let rows = persistence::list_webhook_deliveries(db, id, limit as i64)
+        .await
+        .map_err(|e| ErrorResponse::from_db("Failed to load webhook deliveries", e))?;
+    Ok(Json(
+        rows.into_iter()
+            .filter_map(DeliveryRecord::from_row)
+            .collect(),
+    ))
+}
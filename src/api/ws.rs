@@ -1,31 +1,216 @@
 use axum::{
     extract::{
-        State,
-        ws::{Message, WebSocket, WebSocketUpgrade},
+        ConnectInfo, Query, State,
+        ws::{CloseFrame, Message, WebSocket, WebSocketUpgrade, close_code},
     },
-    response::Response,
+    http::{Extensions, HeaderMap, StatusCode},
+    response::{IntoResponse, Json, Response},
 };
 use serde::{Deserialize, Serialize};
-use serde_json;
-use std::collections::HashSet;
+use serde_json::{self, Value};
+use std::collections::{HashMap, HashSet};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Instant;
 use tokio::{select, sync::broadcast};
+use uuid::Uuid;
 
-use crate::api::routes::{AppState, WsMessage};
-use crate::types::trade::Trade;
+use crate::api::auth;
+use crate::api::deadline::{RequestDeadline, lock_book};
+use crate::api::book_diffs::{self, BookDiffsResponse};
+use crate::api::dto::{BookDiffDto, CancelledOrderDto, TradeDto};
+use crate::api::expiry;
+use crate::api::fanout::{Delivery, Subscriber};
+use crate::api::lazy_books;
+use crate::api::phase_timer::PhaseTimer;
+use crate::api::routes::{
+    self, AppState, CreateOrderRequest, ErrorResponse, OrderResponse, WsMessage, find_orderbook,
+    symbol_hint,
+};
+use crate::api::service::{self, CancelledOrder};
+use crate::api::ws_connections::{self, ConnectionHandle, LagAction};
+use crate::engine::Exchange;
+use crate::orderbook::orderbook::SharedOrderBook;
+use crate::orderbook::trade_archive::TradesEvicted;
+use crate::positions::{self, PositionDelta};
+use crate::types::order::{CloseReason, OrderId, Price, RejectReason};
+use crate::types::symbol::{Symbol, SymbolHint};
+use crate::types::trade::{Trade, TradeBust, TradeRole};
 
-// Subscription action enum
+// Messages from client, tagged by "action"
+#[derive(Debug, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+enum ClientMessage {
+    Subscribe {
+        symbol: String,
+        /// Only this channel of the symbol; everything when absent.
+        #[serde(default)]
+        channel: Option<Channel>,
+        /// Narrows the channel; `own` trades need an authenticated socket.
+        #[serde(default)]
+        filter: Option<TradeFilter>,
+        /// Send the archived trades from this `trade_seq` on before going live.
+        #[serde(default)]
+        replay_from_seq: Option<u64>,
+    },
+    Unsubscribe {
+        symbol: String,
+    },
+    /// Own position updates; authenticated sockets only.
+    SubscribePositions,
+    UnsubscribePositions,
+    CancelOnDisconnect {
+        enabled: bool,
+    },
+    /// Keep one of the socket user's TTL orders alive, as `POST /orders/{id}/heartbeat`.
+    Refresh {
+        order_id: Uuid,
+    },
+    /// Place an order for the socket's user, as `POST /orders` with the same body. The reply
+    /// echoes `id`.
+    PlaceOrder {
+        #[serde(default)]
+        id: Value,
+        #[serde(flatten)]
+        order: CreateOrderRequest,
+    },
+    /// Cancel one of the socket user's orders, as `DELETE /orders/{id}`; the book is looked up
+    /// when `symbol` is absent. The reply echoes `id`.
+    CancelOrder {
+        #[serde(default)]
+        id: Value,
+        order_id: OrderId,
+        #[serde(default)]
+        symbol: Option<String>,
+    },
+    /// The buffered diffs of the book after `from_seq`, as `GET /book/diffs` without a limit.
+    GetDiffs {
+        symbol: String,
+        from_seq: u64,
+    },
+}
+
+/// Market data channels a subscription can be narrowed to.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
-#[serde(rename_all = "lowercase")]
-pub enum SubscriptionAction {
-    Subscribe,
-    Unsubscribe,
+#[serde(rename_all = "snake_case")]
+enum Channel {
+    Trades,
+    /// The book as `OrderBookDiff`s instead of `OrderBookUpdate` snapshots; nothing else.
+    Diffs,
 }
 
-// Subscription message from client
-#[derive(Debug, Deserialize)]
-struct SubscriptionMessage {
-    action: SubscriptionAction,
-    symbol: String,
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum TradeFilter {
+    /// Only trades the socket's user was maker or taker in.
+    Own,
+}
+
+/// What a socket receives for one subscribed symbol; everything by default.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SymbolSubscription {
+    channel: Option<Channel>,
+    own_trades: bool,
+}
+
+/// A trade on an `own`-filtered subscription, sent only to its maker and taker: the public
+/// `Trade` message plus the receiving user's role.
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename = "Trade")]
+struct OwnTrade<'a> {
+    symbol: &'a str,
+    trade: TradeDto,
+    role: TradeRole,
+}
+
+/// A book update on a `diffs` subscription: the change since the previous one.
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename = "OrderBookDiff")]
+struct DiffFrame<'a> {
+    symbol: &'a str,
+    #[serde(flatten)]
+    diff: BookDiffDto,
+}
+
+/// How a message is rendered for a socket: the frame every subscriber shares, a trade with
+/// the receiving user's role, or a book update as its diff.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Rendering {
+    Shared,
+    Own(TradeRole),
+    Diff,
+}
+
+impl Rendering {
+    pub(crate) fn render(self, message: &WsMessage) -> Option<String> {
+        match (self, message) {
+            (Rendering::Own(role), WsMessage::Trade { symbol, trade }) => {
+                serde_json::to_string(&OwnTrade {
+                    symbol,
+                    trade: trade.clone().into(),
+                    role,
+                })
+                .ok()
+            }
+            (Rendering::Diff, WsMessage::OrderBookUpdate { symbol, diff, .. }) => {
+                serde_json::to_string(&DiffFrame {
+                    symbol,
+                    diff: diff.clone()?.into(),
+                })
+                .ok()
+            }
+            _ => serde_json::to_string(message).ok(),
+        }
+    }
+}
+
+impl SymbolSubscription {
+    /// How `message` (market data of the subscribed symbol) is rendered for `user_id`'s
+    /// socket, or None if this subscription does not deliver it.
+    pub(crate) fn rendering(
+        &self,
+        message: &WsMessage,
+        user_id: Option<Uuid>,
+    ) -> Option<Rendering> {
+        match message {
+            WsMessage::OrderBookUpdate { diff, .. } if self.channel == Some(Channel::Diffs) => {
+                diff.as_ref().map(|_| Rendering::Diff)
+            }
+            _ if self.channel == Some(Channel::Diffs) => None,
+            WsMessage::Trade { trade, .. } if self.own_trades => {
+                let user_id = user_id?;
+                if trade.taker_user_id == user_id {
+                    Some(Rendering::Own(TradeRole::Taker))
+                } else if trade.maker_user_id == user_id {
+                    Some(Rendering::Own(TradeRole::Maker))
+                } else {
+                    None
+                }
+            }
+            WsMessage::Trade { .. } => Some(Rendering::Shared),
+            _ if self.channel == Some(Channel::Trades) => None,
+            _ => Some(Rendering::Shared),
+        }
+    }
+
+    fn describe(&self) -> &'static str {
+        match (self.channel, self.own_trades) {
+            (None, _) => "",
+            (Some(Channel::Trades), false) => " trades",
+            (Some(Channel::Trades), true) => " trades (own)",
+            (Some(Channel::Diffs), _) => " diffs",
+        }
+    }
+}
+
+/// How prominently clients should show an `Announcement`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AnnouncementSeverity {
+    #[default]
+    Info,
+    Warning,
+    Critical,
 }
 
 // Subscription status enum
@@ -42,40 +227,238 @@ struct SubscriptionAck {
     status: SubscriptionStatus,
     message: String,
     symbol: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    session_id: Option<Uuid>,
+    /// Machine-readable reason of an error ack, e.g. `UNKNOWN_SYMBOL`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    code: Option<&'static str>,
+    /// The symbols that do exist, when the subscribed one does not.
+    #[serde(flatten, skip_serializing_if = "Option::is_none")]
+    symbols: Option<SymbolHint>,
+    /// Trades replayed right after a `replay_from_seq` subscribe's `ExchangeInfo`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    replayed: Option<usize>,
+    /// Where the trade archive starts, when `replay_from_seq` is older than that.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    oldest_available_seq: Option<u64>,
+}
+
+impl SubscriptionAck {
+    fn new(status: SubscriptionStatus, message: String, symbol: Option<String>) -> Self {
+        Self {
+            status,
+            message,
+            symbol,
+            session_id: None,
+            code: None,
+            symbols: None,
+            replayed: None,
+            oldest_available_seq: None,
+        }
+    }
+}
+
+/// Reply to `place_order` and `cancel_order`: the client's `id` with the order as the REST
+/// endpoint returns it, or the error body it would answer with (`code` being the HTTP status).
+#[derive(Serialize)]
+struct OrderEntryReply {
+    id: Value,
+    action: &'static str,
+    status: SubscriptionStatus,
+    /// The symbol's ingress sequence, as `X-Ingress-Seq`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ingress_seq: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    order: Option<Value>,
+    #[serde(flatten, skip_serializing_if = "Option::is_none")]
+    error: Option<ErrorResponse>,
+}
+
+impl OrderEntryReply {
+    fn new<T: Serialize>(
+        id: Value,
+        action: &'static str,
+        ingress_seq: Option<u64>,
+        result: Result<T, (StatusCode, Json<ErrorResponse>)>,
+    ) -> Self {
+        let (status, order, error) = match result {
+            Ok(order) => (
+                SubscriptionStatus::Success,
+                serde_json::to_value(order).ok(),
+                None,
+            ),
+            Err((_, Json(error))) => (SubscriptionStatus::Error, None, Some(error)),
+        };
+        Self {
+            id,
+            action,
+            status,
+            ingress_seq,
+            order,
+            error,
+        }
+    }
+}
+
+/// Reply to `get_diffs`: the `GET /book/diffs` body, with every buffered diff.
+#[derive(Serialize)]
+struct DiffsReply {
+    action: &'static str,
+    #[serde(flatten)]
+    diffs: BookDiffsResponse,
+}
+
+/// Sent to a socket that fell behind its fan-out queue, before fresh book snapshots for
+/// its subscriptions.
+#[derive(Debug, Serialize)]
+#[serde(tag = "type")]
+enum LagNotice {
+    Lagged { skipped: u64 },
+}
+
+#[derive(Debug, Deserialize)]
+pub struct WsQuery {
+    token: Option<String>,
+}
+
+/// Authenticated user bound to a socket, with its session id and cancel-on-disconnect flag.
+struct WsSession {
+    user_id: Uuid,
+    session_id: Uuid,
+    cancel_on_disconnect: bool,
 }
 
-// WebSocket handler - accepts upgrade and handles the connection
-pub async fn ws_handler(ws: WebSocketUpgrade, State(state): State<AppState>) -> Response {
-    ws.on_upgrade(move |socket| handle_socket(socket, state))
+// WebSocket handler - accepts upgrade and handles the connection.
+// Authentication is optional (Bearer header or `?token=`); market data needs none.
+pub async fn ws_handler(
+    ws: WebSocketUpgrade,
+    State(state): State<AppState>,
+    Query(query): Query<WsQuery>,
+    headers: HeaderMap,
+    extensions: Extensions,
+) -> Response {
+    let token = headers
+        .get(axum::http::header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .map(str::to_string)
+        .or(query.token);
+    let session = match token {
+        Some(token) => {
+            let user_id = auth::decode_token(&state.jwt_secret, &token, state.clock.now())
+                .ok()
+                .and_then(|claims| Uuid::parse_str(&claims.sub).ok());
+            match user_id {
+                Some(user_id) => Some(WsSession {
+                    user_id,
+                    session_id: Uuid::new_v4(),
+                    cancel_on_disconnect: false,
+                }),
+                None => {
+                    return ErrorResponse::new(
+                        "Invalid or expired token".to_string(),
+                        StatusCode::UNAUTHORIZED,
+                    )
+                    .into_response();
+                }
+            }
+        }
+        None => None,
+    };
+    // Present when the server is run with connect info (as `main` does)
+    let remote_addr = extensions
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| *addr);
+    ws.on_upgrade(move |socket| handle_socket(socket, state, session, remote_addr))
 }
 
 // Handle individual WebSocket connection
-async fn handle_socket(mut socket: WebSocket, state: AppState) {
-    let mut broadcast_receiver = state.ws_channel.subscribe();
-    let mut subscribed_symbols: HashSet<String> = HashSet::new();
+async fn handle_socket(
+    mut socket: WebSocket,
+    state: AppState,
+    mut session: Option<WsSession>,
+    remote_addr: Option<SocketAddr>,
+) {
+    let user_id = session.as_ref().map(|s| s.user_id);
+    let session_id = session.as_ref().map(|s| s.session_id);
+    let connection = state
+        .ws_connections
+        .register(user_id, session_id, remote_addr);
+    let subscriber = state.ws_fanout.connect(
+        &state.ws_channel,
+        connection.id,
+        user_id,
+        state.ws_connections.config().queue_capacity,
+    );
+    run_socket(&mut socket, &state, &mut session, &connection, &subscriber).await;
+    state.ws_fanout.disconnect(&subscriber);
+    state.ws_connections.unregister(connection.id);
+
+    // Every exit path (close, error, dropped connection) ends up here.
+    if let Some(session) = session
+        && session.cancel_on_disconnect
+    {
+        cancel_session_orders(&state, &session).await;
+    }
+}
+
+async fn run_socket(
+    socket: &mut WebSocket,
+    state: &AppState,
+    session: &mut Option<WsSession>,
+    connection: &ConnectionHandle,
+    subscriber: &Arc<Subscriber>,
+) {
+    let mut subscribed_symbols: HashMap<String, SymbolSubscription> = HashMap::new();
 
     loop {
         select! {
-            // Handle incoming broadcast messages and send to client (if subscribed)
-            result = broadcast_receiver.recv() => {
-                match result {
-                    Ok(ws_msg) => {
-                        // Check if client is subscribed to this symbol
-                        let symbol = match &ws_msg {
-                            WsMessage::OrderBookUpdate { symbol, .. } => symbol,
-                            WsMessage::Trade { symbol, .. } => symbol,
-                        };
-
-                        // Only send if client is subscribed to this symbol
-                        if subscribed_symbols.contains(symbol)
-                            && let Ok(json) = serde_json::to_string(&ws_msg)
-                                && socket.send(Message::Text(json.into())).await.is_err() {
-                                    return;
-                                }
+            // Force-closed by an admin
+            _ = connection.cancel.cancelled() => {
+                let _ = socket
+                    .send(Message::Close(Some(CloseFrame {
+                        code: close_code::POLICY,
+                        reason: "Connection closed by admin".into(),
+                    })))
+                    .await;
+                return;
+            }
+            // Frames the fan-out queued for this socket (see `fanout`)
+            delivery = subscriber.next() => {
+                // An injected lag (see `faults`) stands in for the frame, which is lost
+                let delivery = state.faults.take_ws_lag().map_or(delivery, Delivery::Lagged);
+                match delivery {
+                    Delivery::Frame(frame) => {
+                        if socket.send(Message::Text(frame.as_ref().into())).await.is_err() {
+                            return;
+                        }
+                        connection.counters.record_sent();
                     }
-                    Err(_) => {
-                        // Broadcast channel closed
-                        return;
+                    Delivery::Lagged(skipped) => {
+                        // Fell behind and lost the oldest messages
+                        let action = state
+                            .ws_connections
+                            .record_lag(connection, skipped, Instant::now());
+                        if action == LagAction::Disconnect {
+                            eprintln!(
+                                "closing slow WebSocket consumer {} after {} dropped messages",
+                                connection.id, skipped
+                            );
+                            let _ = socket
+                                .send(Message::Close(Some(CloseFrame {
+                                    code: close_code::POLICY,
+                                    reason: "Slow consumer".into(),
+                                })))
+                                .await;
+                            return;
+                        }
+                        let user_id = session.as_ref().map(|s| s.user_id);
+                        if resync(socket, state, user_id, &subscribed_symbols, skipped, connection)
+                            .await
+                            .is_err()
+                        {
+                            return;
+                        }
                     }
                 }
             }
@@ -83,58 +466,274 @@ async fn handle_socket(mut socket: WebSocket, state: AppState) {
             result = socket.recv() => {
                 match result {
                     Some(Ok(Message::Text(text))) => {
-                        // Parse subscription message
-                        match serde_json::from_str::<SubscriptionMessage>(&text) {
-                            Ok(sub_msg) => {
-                                let normalized_symbol = sub_msg.symbol.to_uppercase();
-
-                                // Validate symbol exists
-                                let symbol_exists = state.orderbooks.contains_key(&normalized_symbol);
-
-                                let ack = match sub_msg.action {
-                                    SubscriptionAction::Subscribe => {
-                                        if symbol_exists {
-                                            subscribed_symbols.insert(normalized_symbol.clone());
-                                            SubscriptionAck {
-                                                status: SubscriptionStatus::Success,
-                                                message: format!("Subscribed to {}", normalized_symbol),
-                                                symbol: Some(normalized_symbol),
-                                            }
-                                        } else {
-                                            SubscriptionAck {
-                                                status: SubscriptionStatus::Error,
-                                                message: format!("Symbol '{}' not found", normalized_symbol),
-                                                symbol: None,
+                        // Follow a successful subscribe's ack
+                        let mut exchange_info = None;
+                        let mut replay = Vec::new();
+                        let ack = match serde_json::from_str::<ClientMessage>(&text) {
+                            Ok(ClientMessage::Subscribe { symbol, channel, filter, replay_from_seq }) => match Symbol::parse(&symbol) {
+                                Ok(_) if filter.is_some() && channel != Some(Channel::Trades) => {
+                                    SubscriptionAck::new(
+                                        SubscriptionStatus::Error,
+                                        "A filter needs channel \"trades\"".to_string(),
+                                        None,
+                                    )
+                                }
+                                Ok(_) if filter == Some(TradeFilter::Own) && session.is_none() => {
+                                    SubscriptionAck::new(
+                                        SubscriptionStatus::Error,
+                                        "Authentication required for own trades".to_string(),
+                                        None,
+                                    )
+                                }
+                                // Validate symbol exists (sandboxes only for their members)
+                                Ok(normalized_symbol) => match find_orderbook(
+                                    state,
+                                    &normalized_symbol,
+                                    session.as_ref().map(|s| s.user_id),
+                                )
+                                .await
+                                {
+                                    Some(orderbook) => {
+                                        let subscription = SymbolSubscription {
+                                            channel,
+                                            own_trades: filter == Some(TradeFilter::Own),
+                                        };
+                                        match subscribe_symbol(
+                                            state,
+                                            subscriber,
+                                            connection,
+                                            &orderbook,
+                                            &normalized_symbol,
+                                            subscription,
+                                            replay_from_seq,
+                                        )
+                                        .await
+                                        {
+                                            Ok(trades) => {
+                                                subscribed_symbols.insert(normalized_symbol.to_string(), subscription);
+                                                let user_id = session.as_ref().map(|s| s.user_id);
+                                                replay = trades
+                                                    .into_iter()
+                                                    .filter_map(|trade| {
+                                                        let message = WsMessage::Trade {
+                                                            symbol: normalized_symbol.to_string(),
+                                                            trade,
+                                                        };
+                                                        subscription.rendering(&message, user_id)?.render(&message)
+                                                    })
+                                                    .collect();
+                                                exchange_info = Some(WsMessage::ExchangeInfo {
+                                                    symbol: normalized_symbol.to_string(),
+                                                    meta: service::symbol_config(state, &normalized_symbol).await.meta(),
+                                                });
+                                                SubscriptionAck {
+                                                    replayed: replay_from_seq.map(|_| replay.len()),
+                                                    ..SubscriptionAck::new(
+                                                        SubscriptionStatus::Success,
+                                                        format!(
+                                                            "Subscribed to {}{}",
+                                                            normalized_symbol,
+                                                            subscription.describe()
+                                                        ),
+                                                        Some(normalized_symbol.into_string()),
+                                                    )
+                                                }
                                             }
+                                            Err(evicted) => SubscriptionAck {
+                                                code: Some("TRADES_EVICTED"),
+                                                oldest_available_seq: Some(evicted.oldest_available_seq),
+                                                ..SubscriptionAck::new(
+                                                    SubscriptionStatus::Error,
+                                                    evicted.to_string(),
+                                                    None,
+                                                )
+                                            },
                                         }
                                     }
-                                    SubscriptionAction::Unsubscribe => {
-                                        subscribed_symbols.remove(&normalized_symbol);
-                                        SubscriptionAck {
-                                            status: SubscriptionStatus::Success,
-                                            message: format!("Unsubscribed from {}", normalized_symbol),
-                                            symbol: Some(normalized_symbol),
-                                        }
+                                    None => SubscriptionAck {
+                                        code: Some(RejectReason::UnknownSymbol.code()),
+                                        symbols: Some(symbol_hint(state, &normalized_symbol)),
+                                        ..SubscriptionAck::new(
+                                            SubscriptionStatus::Error,
+                                            format!("Symbol '{}' not found", normalized_symbol),
+                                            None,
+                                        )
+                                    },
+                                },
+                                Err(e) => {
+                                    SubscriptionAck::new(SubscriptionStatus::Error, e.to_string(), None)
+                                }
+                            },
+                            Ok(ClientMessage::Unsubscribe { symbol }) => match Symbol::parse(&symbol) {
+                                Ok(normalized_symbol) => {
+                                    subscribed_symbols.remove(normalized_symbol.as_str());
+                                    state.ws_fanout.unsubscribe(subscriber, &normalized_symbol);
+                                    state.ws_connections.unsubscribe(connection.id, &normalized_symbol);
+                                    SubscriptionAck::new(
+                                        SubscriptionStatus::Success,
+                                        format!("Unsubscribed from {}", normalized_symbol),
+                                        Some(normalized_symbol.into_string()),
+                                    )
+                                }
+                                Err(e) => {
+                                    SubscriptionAck::new(SubscriptionStatus::Error, e.to_string(), None)
+                                }
+                            },
+                            Ok(ClientMessage::SubscribePositions) => {
+                                if session.is_some() {
+                                    state.ws_fanout.set_positions(subscriber, true);
+                                    SubscriptionAck::new(
+                                        SubscriptionStatus::Success,
+                                        "Subscribed to positions".to_string(),
+                                        None,
+                                    )
+                                } else {
+                                    SubscriptionAck::new(
+                                        SubscriptionStatus::Error,
+                                        "Authentication required for positions".to_string(),
+                                        None,
+                                    )
+                                }
+                            }
+                            Ok(ClientMessage::UnsubscribePositions) => {
+                                state.ws_fanout.set_positions(subscriber, false);
+                                SubscriptionAck::new(
+                                    SubscriptionStatus::Success,
+                                    "Unsubscribed from positions".to_string(),
+                                    None,
+                                )
+                            }
+                            Ok(ClientMessage::CancelOnDisconnect { enabled }) => match session {
+                                Some(session) => {
+                                    session.cancel_on_disconnect = enabled;
+                                    SubscriptionAck {
+                                        session_id: Some(session.session_id),
+                                        ..SubscriptionAck::new(
+                                            SubscriptionStatus::Success,
+                                            format!(
+                                                "Cancel on disconnect {}",
+                                                if enabled { "enabled" } else { "disabled" }
+                                            ),
+                                            None,
+                                        )
                                     }
-                                };
-
-                                // Send acknowledgment back to client
-                                if let Ok(ack_json) = serde_json::to_string(&ack)
-                                    && socket.send(Message::Text(ack_json.into())).await.is_err() {
-                                        return;
+                                }
+                                None => SubscriptionAck::new(
+                                    SubscriptionStatus::Error,
+                                    "Authentication required for cancel_on_disconnect".to_string(),
+                                    None,
+                                ),
+                            },
+                            Ok(ClientMessage::Refresh { order_id }) => match session {
+                                Some(session) => match expiry::refresh_order(state, session.user_id, order_id).await {
+                                    Ok(refreshed) => SubscriptionAck::new(
+                                        SubscriptionStatus::Success,
+                                        format!("Order {} refreshed until {}", order_id, refreshed.expires_at.to_rfc3339()),
+                                        Some(refreshed.symbol),
+                                    ),
+                                    Err((_, e)) => SubscriptionAck::new(SubscriptionStatus::Error, e.0.error, None),
+                                },
+                                None => SubscriptionAck::new(
+                                    SubscriptionStatus::Error,
+                                    "Authentication required for refresh".to_string(),
+                                    None,
+                                ),
+                            },
+                            // Order entry answers with its own reply, not an ack
+                            Ok(ClientMessage::PlaceOrder { id, order }) => {
+                                let (seq, placed) = match session {
+                                    Some(session) => {
+                                        place_order(state, session.user_id, order).await
                                     }
+                                    None => (None, Err(order_entry_unauthenticated())),
+                                };
+                                let reply = OrderEntryReply::new(id, "place_order", seq, placed);
+                                if send_reply(socket, connection, &reply).await.is_err() {
+                                    return;
+                                }
+                                continue;
                             }
-                            Err(_) => {
-                                // Invalid JSON - send error acknowledgment
-                                let error_ack = SubscriptionAck {
-                                    status: SubscriptionStatus::Error,
-                                    message: "Invalid message format. Expected: {\"action\": \"subscribe\", \"symbol\": \"BTCUSDT\"}".to_string(),
-                                    symbol: None,
+                            Ok(ClientMessage::CancelOrder { id, order_id, symbol }) => {
+                                let (seq, cancelled) = match session {
+                                    Some(session) => {
+                                        cancel_order(state, session.user_id, order_id, symbol).await
+                                    }
+                                    None => (None, Err(order_entry_unauthenticated())),
                                 };
-                                if let Ok(ack_json) = serde_json::to_string(&error_ack) {
-                                    let _ = socket.send(Message::Text(ack_json.into())).await;
+                                let reply =
+                                    OrderEntryReply::new(id, "cancel_order", seq, cancelled);
+                                if send_reply(socket, connection, &reply).await.is_err() {
+                                    return;
                                 }
+                                continue;
                             }
+                            Ok(ClientMessage::GetDiffs { symbol, from_seq }) => match Symbol::parse(&symbol) {
+                                Ok(normalized_symbol) => match find_orderbook(
+                                    state,
+                                    &normalized_symbol,
+                                    session.as_ref().map(|s| s.user_id),
+                                )
+                                .await
+                                {
+                                    // Answered with its own reply, not an ack
+                                    Some(orderbook) => {
+                                        let reply = DiffsReply {
+                                            action: "get_diffs",
+                                            diffs: book_diffs::replay(
+                                                state,
+                                                &normalized_symbol,
+                                                &orderbook,
+                                                from_seq,
+                                                None,
+                                            )
+                                            .await,
+                                        };
+                                        if send_reply(socket, connection, &reply).await.is_err() {
+                                            return;
+                                        }
+                                        continue;
+                                    }
+                                    None => SubscriptionAck {
+                                        code: Some(RejectReason::UnknownSymbol.code()),
+                                        symbols: Some(symbol_hint(state, &normalized_symbol)),
+                                        ..SubscriptionAck::new(
+                                            SubscriptionStatus::Error,
+                                            format!("Symbol '{}' not found", normalized_symbol),
+                                            None,
+                                        )
+                                    },
+                                },
+                                Err(e) => {
+                                    SubscriptionAck::new(SubscriptionStatus::Error, e.to_string(), None)
+                                }
+                            },
+                            Err(_) => SubscriptionAck::new(
+                                SubscriptionStatus::Error,
+                                "Invalid message format. Expected: {\"action\": \"subscribe\", \"symbol\": \"BTCUSDT\"}".to_string(),
+                                None,
+                            ),
+                        };
+
+                        // Send acknowledgment back to client
+                        if let Ok(ack_json) = serde_json::to_string(&ack) {
+                            if socket.send(Message::Text(ack_json.into())).await.is_err() {
+                                return;
+                            }
+                            connection.counters.record_sent();
+                        }
+                        if let Some(Ok(info_json)) = exchange_info.as_ref().map(serde_json::to_string) {
+                            if socket.send(Message::Text(info_json.into())).await.is_err() {
+                                return;
+                            }
+                            connection.counters.record_sent();
+                        }
+                        // Written straight out, each send waiting on the client; live frames
+                        // wait in the outbox meanwhile and lag it as usual if they overflow
+                        for frame in replay {
+                            if socket.send(Message::Text(frame.into())).await.is_err() {
+                                return;
+                            }
+                            connection.counters.record_sent();
                         }
                     }
                     Some(Ok(Message::Close(_))) => {
@@ -154,8 +753,251 @@ async fn handle_socket(mut socket: WebSocket, state: AppState) {
     }
 }
 
+fn order_entry_unauthenticated() -> (StatusCode, Json<ErrorResponse>) {
+    ErrorResponse::new(
+        "Authentication required for order entry".to_string(),
+        StatusCode::UNAUTHORIZED,
+    )
+}
+
+/// Place `body` for `user_id` through the same path as `POST /orders` (validation, kill switch,
+/// order caps, matching, persistence, notifications), in the symbol's ingress turn.
+async fn place_order(
+    state: &AppState,
+    user_id: Uuid,
+    body: CreateOrderRequest,
+) -> (Option<u64>, Result<OrderResponse, (StatusCode, Json<ErrorResponse>)>) {
+    let symbol = match body.book_symbol() {
+        Ok(symbol) => symbol,
+        Err(e) => return (None, Err(ErrorResponse::invalid_symbol(e))),
+    };
+    let ticket = match service::admit(state, &symbol, user_id).await {
+        Ok(ticket) => ticket,
+        Err(e) => return (None, Err(e)),
+    };
+    let seq = ticket.seq();
+    let deadline = RequestDeadline::after(state.request_timeout);
+    let placed = routes::submit_order_request(
+        &Exchange::new(state.clone()),
+        user_id,
+        symbol,
+        body,
+        ticket,
+        &deadline,
+        &mut PhaseTimer::start(),
+    )
+    .await;
+    (Some(seq), placed)
+}
+
+/// Cancel `user_id`'s order `order_id` as `DELETE /orders/{id}` does, on `symbol`'s book or, when
+/// it is not given, whichever of the user's books the order rests on.
+async fn cancel_order(
+    state: &AppState,
+    user_id: Uuid,
+    order_id: OrderId,
+    symbol: Option<String>,
+) -> (Option<u64>, Result<CancelledOrderDto, (StatusCode, Json<ErrorResponse>)>) {
+    let exchange = Exchange::new(state.clone());
+    let Some(symbol) = symbol else {
+        let cancelled = exchange.cancel(user_id, order_id).await;
+        return (None, cancelled.map(CancelledOrderDto::from).map_err(Into::into));
+    };
+    let symbol = match Symbol::parse(&symbol) {
+        Ok(symbol) => symbol,
+        Err(e) => return (None, Err(ErrorResponse::invalid_symbol(e))),
+    };
+    let ticket = match service::admit(state, &symbol, user_id).await {
+        Ok(ticket) => ticket,
+        Err(e) => return (None, Err(e)),
+    };
+    let seq = ticket.seq();
+    let deadline = RequestDeadline::after(state.request_timeout);
+    let cancelled = exchange
+        .submit_cancel(user_id, &symbol, order_id, ticket, &deadline)
+        .await;
+    (Some(seq), cancelled.map(CancelledOrderDto::from).map_err(Into::into))
+}
+
+async fn send_reply(
+    socket: &mut WebSocket,
+    connection: &ConnectionHandle,
+    reply: &impl Serialize,
+) -> Result<(), axum::Error> {
+    if let Ok(json) = serde_json::to_string(reply) {
+        socket.send(Message::Text(json.into())).await?;
+        connection.counters.record_sent();
+    }
+    Ok(())
+}
+
+/// Who a broadcast message is for; every stream (WebSocket, SSE) filters with this.
+pub(crate) enum Audience<'a> {
+    /// Market data: anyone following the symbol.
+    Symbol(&'a str),
+    /// Private: the user's authenticated streams.
+    Owner(Uuid),
+    /// Private: the user's authenticated streams that asked for positions.
+    PositionsOwner(Uuid),
+    /// Every WebSocket connection (see `Fanout::announce`).
+    Everyone,
+}
+
+pub(crate) fn audience(message: &WsMessage) -> Audience<'_> {
+    match message {
+        WsMessage::OrderBookUpdate { symbol, .. }
+        | WsMessage::Trade { symbol, .. }
+        | WsMessage::AuctionResult { symbol, .. }
+        | WsMessage::IndexPrice { symbol, .. }
+        | WsMessage::ExchangeInfo { symbol, .. }
+        | WsMessage::SymbolConfigUpdate { symbol, .. }
+        | WsMessage::SymbolStatus { symbol, .. }
+        | WsMessage::Resync { symbol } => Audience::Symbol(symbol),
+        WsMessage::OrderUpdate { report, .. } => Audience::Owner(report.order.user_id),
+        WsMessage::OrderClosed { user_id, .. } => Audience::Owner(*user_id),
+        WsMessage::TradeBusted { user_id, .. } => Audience::Owner(*user_id),
+        WsMessage::KillSwitch { user_id, .. } => Audience::Owner(*user_id),
+        WsMessage::PositionUpdate { position, .. } => Audience::PositionsOwner(position.user_id),
+        WsMessage::Announcement { .. } => Audience::Everyone,
+    }
+}
+
+/// Subscribe the socket to `symbol` and, with `replay_from_seq`, read the archived trades from
+/// that sequence on. The book's read lock is held from the read until the subscription is in
+/// place, so every later trade reaches the socket live and none falls in between; a trade
+/// still being fanned out can come once more live right after the replay, so clients skip any
+/// `trade_seq` they have seen. When the archive no longer reaches back to `replay_from_seq`,
+/// nothing is subscribed.
+async fn subscribe_symbol(
+    state: &AppState,
+    subscriber: &Arc<Subscriber>,
+    connection: &ConnectionHandle,
+    orderbook: &SharedOrderBook,
+    symbol: &Symbol,
+    subscription: SymbolSubscription,
+    replay_from_seq: Option<u64>,
+) -> Result<Vec<Trade>, TradesEvicted> {
+    let trades = match replay_from_seq {
+        Some(from_seq) => {
+            let book = orderbook.read().await;
+            let trades = book.trade_archive().since(from_seq, usize::MAX)?;
+            Some((book, trades))
+        }
+        None => None,
+    };
+    state
+        .ws_fanout
+        .subscribe(&state.ws_channel, subscriber, symbol, subscription);
+    state.ws_connections.subscribe(connection.id, symbol);
+    Ok(trades.map(|(_book, trades)| trades).unwrap_or_default())
+}
+
+/// Tell a lagging socket how many messages it lost, then send a fresh book snapshot for each
+/// symbol it follows the book of so it can rebuild its view. Trades and order updates are not
+/// replayed; `diffs` subscriptions recover the diffs they lost with `get_diffs`.
+async fn resync(
+    socket: &mut WebSocket,
+    state: &AppState,
+    user_id: Option<Uuid>,
+    subscribed_symbols: &HashMap<String, SymbolSubscription>,
+    skipped: u64,
+    connection: &ConnectionHandle,
+) -> Result<(), axum::Error> {
+    let notice = serde_json::to_string(&LagNotice::Lagged { skipped }).unwrap_or_default();
+    socket.send(Message::Text(notice.into())).await?;
+    connection.counters.record_sent();
+    for (symbol, subscription) in subscribed_symbols {
+        if subscription.channel.is_some() {
+            continue;
+        }
+        let Some(book) = find_orderbook(state, symbol, user_id).await else {
+            continue;
+        };
+        let snapshot = {
+            let book = book.read().await;
+            WsMessage::OrderBookUpdate {
+                symbol: symbol.clone(),
+                bids: book.get_bids(),
+                asks: book.get_asks(),
+                diff: None,
+            }
+        };
+        if let Ok(json) = serde_json::to_string(&snapshot) {
+            socket.send(Message::Text(json.into())).await?;
+            connection.counters.record_sent();
+        }
+    }
+    Ok(())
+}
+
+/// Cancel the user's resting orders on every book: untagged orders plus orders scoped to this
+/// session. Orders scoped to another session of the same user are left alone. Lazy books are
+/// loaded first, as for the kill switch.
+async fn cancel_session_orders(state: &AppState, session: &WsSession) {
+    let scopes = state.order_sessions.read().await.clone();
+    let public = state
+        .orderbooks
+        .iter()
+        .map(|(symbol, orderbook)| (symbol.clone(), orderbook.clone()));
+    let sandboxes = state.sandboxes.books_of(session.user_id).await;
+    for (symbol, orderbook) in public.chain(sandboxes) {
+        if !lazy_books::ensure_loaded(state, &symbol).await {
+            continue;
+        }
+        let ticket = state.ingress.admit(&symbol);
+        let deadline = RequestDeadline::after(state.request_timeout);
+        let mut book = match lock_book(state, &symbol, &orderbook, ticket, &deadline).await {
+            Ok(book) => book,
+            Err((_, Json(e))) => {
+                eprintln!(
+                    "cancel on disconnect of session {} skipped {}: {}",
+                    session.session_id, symbol, e.error
+                );
+                continue;
+            }
+        };
+        let order_ids: Vec<OrderId> = book
+            .iter_orders()
+            .map(|(_, order)| order)
+            .filter(|order| {
+                order.user_id == session.user_id
+                    && scopes
+                        .get(&order.id)
+                        .is_none_or(|scope| *scope == session.session_id)
+            })
+            .map(|order| order.id)
+            .collect();
+        service::close_orders(
+            state,
+            &symbol,
+            &mut book,
+            order_ids,
+            CloseReason::CancelOnDisconnect,
+        )
+        .await;
+    }
+    state
+        .order_sessions
+        .write()
+        .await
+        .retain(|_, scope| *scope != session.session_id);
+}
+
+/// Whether any socket is listening; when none is, the caller should skip building the
+/// message, and the skip is counted.
+pub fn has_receivers(ws_channel: &broadcast::Sender<WsMessage>) -> bool {
+    if ws_channel.receiver_count() == 0 {
+        ws_connections::record_skipped_broadcast();
+        return false;
+    }
+    true
+}
+
 // Helper function to broadcast trades
 pub fn broadcast_trades(ws_channel: &broadcast::Sender<WsMessage>, symbol: &str, trades: &[Trade]) {
+    if trades.is_empty() || !has_receivers(ws_channel) {
+        return;
+    }
     for trade in trades {
         let _ = ws_channel.send(WsMessage::Trade {
             symbol: symbol.to_string(),
@@ -168,13 +1010,159 @@ pub fn broadcast_trades(ws_channel: &broadcast::Sender<WsMessage>, symbol: &str,
 pub fn broadcast_orderbook_update(
     ws_channel: &broadcast::Sender<WsMessage>,
     symbol: &str,
-    book: &crate::orderbook::orderbook::OrderBook,
+    book: &mut crate::orderbook::orderbook::OrderBook,
 ) {
-    let bids = book.get_bids();
-    let asks = book.get_asks();
+    // Published even with no one listening, so the diff buffer has no gaps
+    let diff = book.publish_diff();
+    if !has_receivers(ws_channel) {
+        return;
+    }
+    // The levels just published are the book's current ones
+    let published = book.diff_buffer();
     let _ = ws_channel.send(WsMessage::OrderBookUpdate {
         symbol: symbol.to_string(),
-        bids,
-        asks,
+        bids: published.bids().to_vec(),
+        asks: published.asks().to_vec(),
+        diff,
+    });
+}
+
+/// Tell each owner that their order was cancelled on `symbol`.
+pub fn broadcast_orders_closed(
+    ws_channel: &broadcast::Sender<WsMessage>,
+    symbol: &str,
+    closed: &[CancelledOrder],
+) {
+    if closed.is_empty() || !has_receivers(ws_channel) {
+        return;
+    }
+    for cancelled in closed {
+        let _ = ws_channel.send(WsMessage::OrderClosed {
+            symbol: symbol.to_string(),
+            order_id: cancelled.order.id,
+            user_id: cancelled.order.user_id,
+            status: cancelled.order.status,
+            reason: cancelled.close_reason,
+            remaining_qty: cancelled.remaining_quantity,
+        });
+    }
+}
+
+/// Tell both parties (once for a self-trade) that `trade` was busted.
+pub fn broadcast_trade_busted(
+    ws_channel: &broadcast::Sender<WsMessage>,
+    symbol: &str,
+    trade: &Trade,
+    bust: &TradeBust,
+) {
+    if !has_receivers(ws_channel) {
+        return;
+    }
+    let mut parties = vec![(trade.maker_user_id, TradeRole::Maker)];
+    if trade.taker_user_id != trade.maker_user_id {
+        parties.push((trade.taker_user_id, TradeRole::Taker));
+    }
+    for (user_id, role) in parties {
+        let _ = ws_channel.send(WsMessage::TradeBusted {
+            symbol: symbol.to_string(),
+            trade_id: trade.id,
+            user_id,
+            role,
+            reason: bust.reason.clone(),
+            busted_at: bust.busted_at,
+        });
+    }
+}
+
+// Helper function to send an order's execution report to its owner
+pub fn broadcast_order_update(
+    ws_channel: &broadcast::Sender<WsMessage>,
+    symbol: &str,
+    report: &crate::orderbook::orderbook::ExecutionReport,
+) {
+    if !has_receivers(ws_channel) {
+        return;
+    }
+    let _ = ws_channel.send(WsMessage::OrderUpdate {
+        symbol: symbol.to_string(),
+        report: report.clone(),
+    });
+}
+
+// Helper function to broadcast a change to a symbol's trading rules
+pub fn broadcast_symbol_config_update(
+    ws_channel: &broadcast::Sender<WsMessage>,
+    symbol: &str,
+    config: &crate::types::symbol::SymbolConfig,
+) {
+    if !has_receivers(ws_channel) {
+        return;
+    }
+    let _ = ws_channel.send(WsMessage::SymbolConfigUpdate {
+        symbol: symbol.to_string(),
+        config: *config,
+    });
+}
+
+// Helper function to broadcast a symbol's status change (see `api::delisting`)
+pub fn broadcast_symbol_status(
+    ws_channel: &broadcast::Sender<WsMessage>,
+    symbol: &str,
+    config: &crate::types::symbol::SymbolConfig,
+) {
+    if !has_receivers(ws_channel) {
+        return;
+    }
+    let _ = ws_channel.send(WsMessage::SymbolStatus {
+        symbol: symbol.to_string(),
+        status: config.status,
+        delist_at: config.delisting.map(|delisting| delisting.at),
+    });
+}
+
+/// Send each affected user's resulting position (the last delta per user and symbol, so one
+/// matching step yields one update per position), valued at `mark_price`. Quantities are in
+/// lots of `1 / qty_scale`.
+pub fn broadcast_position_updates(
+    ws_channel: &broadcast::Sender<WsMessage>,
+    deltas: &[PositionDelta],
+    mark_price: Option<Price>,
+    qty_scale: u64,
+) {
+    if !has_receivers(ws_channel) {
+        return;
+    }
+    let mut seen = HashSet::new();
+    let mut latest: Vec<&PositionDelta> = deltas
+        .iter()
+        .rev()
+        .filter(|d| seen.insert((d.user_id, d.symbol.as_str())))
+        .collect();
+    latest.reverse();
+    for delta in latest {
+        let position = delta.position();
+        let unrealized_pnl = mark_price
+            .and_then(|mark| positions::scaled_unrealized_pnl(&position, mark, qty_scale).ok());
+        let _ = ws_channel.send(WsMessage::PositionUpdate {
+            position,
+            mark_price,
+            unrealized_pnl,
+        });
+    }
+}
+
+// Helper function to broadcast the outcome of a call auction
+pub fn broadcast_auction_result(
+    ws_channel: &broadcast::Sender<WsMessage>,
+    symbol: &str,
+    result: &crate::orderbook::orderbook::AuctionResult,
+) {
+    if !has_receivers(ws_channel) {
+        return;
+    }
+    let _ = ws_channel.send(WsMessage::AuctionResult {
+        symbol: symbol.to_string(),
+        clearing_price: result.clearing_price,
+        volume: result.volume,
     });
 }
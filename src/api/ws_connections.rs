@@ -0,0 +1,414 @@
+//! Registry of open WebSocket connections, for the `/admin/ws/connections` endpoints. The
+//! socket task registers on connect, records subscription changes and message counts, and
+//! unregisters on exit; admins can force-close a connection through its cancellation token.
+//!
+//! The fan-out feeds (see `fanout`) read one bounded broadcast channel
+//! (`WsConfig::channel_capacity`) and queue frames for each socket in an outbox of
+//! `WsConfig::queue_capacity`. A socket that falls more than that many frames behind loses the
+//! oldest ones (`Lagged`), as does every socket of a feed that falls behind the channel. What
+//! happens next is the `OverflowPolicy`: with `drop-oldest` the socket is told how many it
+//! missed and sent fresh book snapshots to resync; with `disconnect-slow-consumers` the same
+//! happens until it lags more than `max_lags` times within `lag_window`, and then it is closed.
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
+use std::env;
+use std::fmt::Write;
+use std::net::SocketAddr;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+use tokio_util::sync::CancellationToken;
+use uuid::Uuid;
+
+/// Broadcast sends skipped because no socket was listening (counted across all channels).
+static BROADCASTS_SKIPPED: AtomicU64 = AtomicU64::new(0);
+
+pub fn record_skipped_broadcast() {
+    BROADCASTS_SKIPPED.fetch_add(1, Ordering::Relaxed);
+}
+
+pub fn skipped_broadcasts() -> u64 {
+    BROADCASTS_SKIPPED.load(Ordering::Relaxed)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum OverflowPolicy {
+    #[default]
+    DropOldest,
+    DisconnectSlowConsumers,
+}
+
+impl FromStr for OverflowPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "drop-oldest" => Ok(Self::DropOldest),
+            "disconnect-slow-consumers" => Ok(Self::DisconnectSlowConsumers),
+            other => Err(format!(
+                "unknown overflow policy '{}' (expected drop-oldest or disconnect-slow-consumers)",
+                other
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct WsConfig {
+    /// Messages the broadcast channel buffers for the slowest fan-out feed.
+    pub channel_capacity: usize,
+    /// Frames each socket's outbox holds before it drops the oldest.
+    pub queue_capacity: usize,
+    pub overflow_policy: OverflowPolicy,
+    /// With `disconnect-slow-consumers`: lags tolerated within `lag_window`.
+    pub max_lags: usize,
+    #[serde(rename = "lag_window_secs", serialize_with = "serialize_secs")]
+    pub lag_window: Duration,
+}
+
+fn serialize_secs<S: serde::Serializer>(d: &Duration, s: S) -> Result<S::Ok, S::Error> {
+    s.serialize_u64(d.as_secs())
+}
+
+impl Default for WsConfig {
+    fn default() -> Self {
+        Self {
+            channel_capacity: 1000,
+            queue_capacity: 1000,
+            overflow_policy: OverflowPolicy::DropOldest,
+            max_lags: 3,
+            lag_window: Duration::from_secs(60),
+        }
+    }
+}
+
+impl WsConfig {
+    /// Read `WS_CHANNEL_CAPACITY`, `WS_QUEUE_CAPACITY`, `WS_OVERFLOW_POLICY`, `WS_MAX_LAGS`
+    /// and `WS_LAG_WINDOW_SECS`, falling back to the defaults.
+    pub fn from_env() -> Self {
+        fn var<T: FromStr>(name: &str) -> Option<T> {
+            env::var(name).ok().and_then(|v| v.parse().ok())
+        }
+        let defaults = Self::default();
+        let overflow_policy = match env::var("WS_OVERFLOW_POLICY") {
+            Ok(policy) => policy.parse().unwrap_or_else(|e| {
+                eprintln!("WS_OVERFLOW_POLICY: {}; using drop-oldest", e);
+                defaults.overflow_policy
+            }),
+            Err(_) => defaults.overflow_policy,
+        };
+        Self {
+            channel_capacity: var("WS_CHANNEL_CAPACITY")
+                .filter(|&n| n > 0)
+                .unwrap_or(defaults.channel_capacity),
+            queue_capacity: var("WS_QUEUE_CAPACITY")
+                .filter(|&n| n > 0)
+                .unwrap_or(defaults.queue_capacity),
+            overflow_policy,
+            max_lags: var("WS_MAX_LAGS").unwrap_or(defaults.max_lags),
+            lag_window: var::<u64>("WS_LAG_WINDOW_SECS")
+                .filter(|&secs| secs > 0)
+                .map_or(defaults.lag_window, Duration::from_secs),
+        }
+    }
+}
+
+/// What the socket task should do after falling behind.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LagAction {
+    /// Tell the client what it missed and send fresh snapshots.
+    Resync,
+    /// Close the connection (`disconnect-slow-consumers` and too many recent lags).
+    Disconnect,
+}
+
+/// Per-connection message counters, bumped by the socket task without taking the registry lock.
+#[derive(Debug, Default)]
+pub struct ConnectionCounters {
+    sent: AtomicU64,
+    dropped: AtomicU64,
+    lag_events: AtomicU64,
+    recent_lags: Mutex<VecDeque<Instant>>,
+}
+
+impl ConnectionCounters {
+    pub fn record_sent(&self) {
+        self.sent.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Totals since startup, including connections that have since closed.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct WsTotals {
+    pub lag_events: u64,
+    pub messages_dropped: u64,
+    pub slow_consumers_disconnected: u64,
+    pub broadcasts_skipped: u64,
+}
+
+struct Connection {
+    user_id: Option<Uuid>,
+    /// Session id of an authenticated socket (what `session_scope` names).
+    session_id: Option<Uuid>,
+    remote_addr: Option<SocketAddr>,
+    subscriptions: BTreeSet<String>,
+    connected_at: DateTime<Utc>,
+    counters: Arc<ConnectionCounters>,
+    cancel: CancellationToken,
+}
+
+/// What the socket task keeps for its own registry entry.
+pub struct ConnectionHandle {
+    pub id: Uuid,
+    pub counters: Arc<ConnectionCounters>,
+    /// Cancelled when an admin force-closes the connection.
+    pub cancel: CancellationToken,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ConnectionInfo {
+    pub id: Uuid,
+    pub user_id: Option<Uuid>,
+    pub remote_addr: Option<SocketAddr>,
+    pub subscriptions: BTreeSet<String>,
+    pub connected_at: DateTime<Utc>,
+    pub messages_sent: u64,
+    pub messages_dropped: u64,
+    /// Times the connection fell behind and lost messages.
+    pub lag_events: u64,
+}
+
+/// Totals across all open connections.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct ConnectionCounts {
+    pub connections: usize,
+    pub authenticated: usize,
+    /// Open connections subscribed to each symbol.
+    pub subscribers: BTreeMap<String, usize>,
+    pub messages_sent: u64,
+    pub messages_dropped: u64,
+    pub lag_events: u64,
+}
+
+#[derive(Default)]
+pub struct WsConnections {
+    connections: RwLock<HashMap<Uuid, Connection>>,
+    config: WsConfig,
+    lag_events: AtomicU64,
+    messages_dropped: AtomicU64,
+    slow_consumers_disconnected: AtomicU64,
+}
+
+pub type SharedWsConnections = Arc<WsConnections>;
+
+impl WsConnections {
+    pub fn new(config: WsConfig) -> Self {
+        Self {
+            config,
+            ..Self::default()
+        }
+    }
+
+    pub fn config(&self) -> &WsConfig {
+        &self.config
+    }
+
+    /// Record that `handle`'s connection fell behind and lost `skipped` messages, and decide
+    /// what to do about it under the overflow policy.
+    pub fn record_lag(&self, handle: &ConnectionHandle, skipped: u64, now: Instant) -> LagAction {
+        let counters = &handle.counters;
+        counters.dropped.fetch_add(skipped, Ordering::Relaxed);
+        counters.lag_events.fetch_add(1, Ordering::Relaxed);
+        self.messages_dropped.fetch_add(skipped, Ordering::Relaxed);
+        self.lag_events.fetch_add(1, Ordering::Relaxed);
+        if self.config.overflow_policy == OverflowPolicy::DropOldest {
+            return LagAction::Resync;
+        }
+
+        let mut recent = counters
+            .recent_lags
+            .lock()
+            .unwrap_or_else(|e| e.into_inner());
+        recent.push_back(now);
+        while recent
+            .front()
+            .is_some_and(|&at| now.duration_since(at) > self.config.lag_window)
+        {
+            recent.pop_front();
+        }
+        if recent.len() > self.config.max_lags {
+            self.slow_consumers_disconnected
+                .fetch_add(1, Ordering::Relaxed);
+            LagAction::Disconnect
+        } else {
+            LagAction::Resync
+        }
+    }
+
+    pub fn totals(&self) -> WsTotals {
+        WsTotals {
+            lag_events: self.lag_events.load(Ordering::Relaxed),
+            messages_dropped: self.messages_dropped.load(Ordering::Relaxed),
+            slow_consumers_disconnected: self.slow_consumers_disconnected.load(Ordering::Relaxed),
+            broadcasts_skipped: skipped_broadcasts(),
+        }
+    }
+
+    /// Prometheus text exposition of the connection gauge and the overflow counters.
+    pub fn render_prometheus(&self) -> String {
+        let counts = self.counts();
+        let totals = self.totals();
+        let mut out = String::new();
+        let _ = writeln!(
+            out,
+            "# HELP exchange_ws_connections Open WebSocket connections.\n\
+             # TYPE exchange_ws_connections gauge\n\
+             exchange_ws_connections {}",
+            counts.connections
+        );
+        for (name, help, value) in [
+            (
+                "exchange_ws_lag_events_total",
+                "Times a WebSocket connection fell behind the broadcast channel.",
+                totals.lag_events,
+            ),
+            (
+                "exchange_ws_messages_dropped_total",
+                "Broadcast messages lost by lagging WebSocket connections.",
+                totals.messages_dropped,
+            ),
+            (
+                "exchange_ws_slow_consumers_disconnected_total",
+                "WebSocket connections closed for lagging too often.",
+                totals.slow_consumers_disconnected,
+            ),
+            (
+                "exchange_ws_broadcasts_skipped_total",
+                "Broadcasts not built because no WebSocket connection was listening.",
+                totals.broadcasts_skipped,
+            ),
+        ] {
+            let _ = writeln!(
+                out,
+                "# HELP {} {}\n# TYPE {} counter\n{} {}",
+                name, help, name, name, value
+            );
+        }
+        out
+    }
+
+    pub fn register(
+        &self,
+        user_id: Option<Uuid>,
+        session_id: Option<Uuid>,
+        remote_addr: Option<SocketAddr>,
+    ) -> ConnectionHandle {
+        let handle = ConnectionHandle {
+            id: Uuid::new_v4(),
+            counters: Arc::new(ConnectionCounters::default()),
+            cancel: CancellationToken::new(),
+        };
+        self.write().insert(
+            handle.id,
+            Connection {
+                user_id,
+                session_id,
+                remote_addr,
+                subscriptions: BTreeSet::new(),
+                connected_at: Utc::now(),
+                counters: handle.counters.clone(),
+                cancel: handle.cancel.clone(),
+            },
+        );
+        handle
+    }
+
+    pub fn unregister(&self, id: Uuid) {
+        self.write().remove(&id);
+    }
+
+    /// Whether `session_id` is the session of one of `user_id`'s open connections.
+    pub fn is_live_session(&self, user_id: Uuid, session_id: Uuid) -> bool {
+        self.read()
+            .values()
+            .any(|c| c.user_id == Some(user_id) && c.session_id == Some(session_id))
+    }
+
+    pub fn subscribe(&self, id: Uuid, symbol: &str) {
+        if let Some(connection) = self.write().get_mut(&id) {
+            connection.subscriptions.insert(symbol.to_string());
+        }
+    }
+
+    pub fn unsubscribe(&self, id: Uuid, symbol: &str) {
+        if let Some(connection) = self.write().get_mut(&id) {
+            connection.subscriptions.remove(symbol);
+        }
+    }
+
+    /// Ask the connection's socket task to send a close frame and exit. False if not open.
+    pub fn close(&self, id: Uuid) -> bool {
+        match self.read().get(&id) {
+            Some(connection) => {
+                connection.cancel.cancel();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Open connections, oldest first, optionally only those subscribed to `symbol` and/or
+    /// authenticated as `user_id`.
+    pub fn list(&self, symbol: Option<&str>, user_id: Option<Uuid>) -> Vec<ConnectionInfo> {
+        let mut connections: Vec<ConnectionInfo> = self
+            .read()
+            .iter()
+            .filter(|(_, c)| symbol.is_none_or(|symbol| c.subscriptions.contains(symbol)))
+            .filter(|(_, c)| user_id.is_none_or(|user_id| c.user_id == Some(user_id)))
+            .map(|(&id, c)| ConnectionInfo {
+                id,
+                user_id: c.user_id,
+                remote_addr: c.remote_addr,
+                subscriptions: c.subscriptions.clone(),
+                connected_at: c.connected_at,
+                messages_sent: c.counters.sent.load(Ordering::Relaxed),
+                messages_dropped: c.counters.dropped.load(Ordering::Relaxed),
+                lag_events: c.counters.lag_events.load(Ordering::Relaxed),
+            })
+            .collect();
+        connections.sort_by_key(|c| (c.connected_at, c.id));
+        connections
+    }
+
+    pub fn counts(&self) -> ConnectionCounts {
+        let connections = self.read();
+        let mut counts = ConnectionCounts {
+            connections: connections.len(),
+            ..ConnectionCounts::default()
+        };
+        for connection in connections.values() {
+            if connection.user_id.is_some() {
+                counts.authenticated += 1;
+            }
+            for symbol in &connection.subscriptions {
+                *counts.subscribers.entry(symbol.clone()).or_default() += 1;
+            }
+            counts.messages_sent += connection.counters.sent.load(Ordering::Relaxed);
+            counts.messages_dropped += connection.counters.dropped.load(Ordering::Relaxed);
+            counts.lag_events += connection.counters.lag_events.load(Ordering::Relaxed);
+        }
+        counts
+    }
+
+    fn read(&self) -> std::sync::RwLockReadGuard<'_, HashMap<Uuid, Connection>> {
+        self.connections.read().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn write(&self) -> std::sync::RwLockWriteGuard<'_, HashMap<Uuid, Connection>> {
+        self.connections.write().unwrap_or_else(|e| e.into_inner())
+    }
+}
@@ -0,0 +1,427 @@
+//! Balance locking: each user's available and locked amount per asset, and the lock each open
+//! order holds. An order locks what it could spend: a buy `price * quantity` of the quote
+//! asset, a sell `quantity` of the base asset. Fills spend from the lock (a buy filled below
+//! its limit gets the difference back) and credit the other asset; when an order leaves the
+//! book any other way only the part of its lock that fills have not spent is released.
+//!
+//! Quote amounts are `price * quantity / qty_scale` for symbols that count quantity in lots.
+//! Which assets those are comes from the symbol's config (`base_asset`, `quote_asset`).
+//!
+//! Off unless `BALANCE_LOCKING` is set, in which case every order on a public symbol needs
+//! funds. Balances are kept in memory only (credited through the admin API).
+//!
+//! The paper environment (see `types::environment`) has a ledger of its own, tracked only
+//! when `PAPER_FUNDING` is set: every user starts with the amounts it lists.
+
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+use tokio::sync::{Mutex, MutexGuard};
+use uuid::Uuid;
+
+use crate::types::asset::Asset;
+use crate::types::money::{Notional, scaled_notional};
+use crate::types::order::{OrderId, OrderSide, Price, Qty};
+use crate::types::symbol::SymbolConfig;
+use crate::types::trade::Trade;
+
+pub type SharedBalances = Arc<Balances>;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct Balance {
+    pub available: i64,
+    /// Held by open orders.
+    pub locked: i64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BalanceError {
+    /// The symbol has no recognizable base and quote asset.
+    UnknownAssets(String),
+    Insufficient {
+        asset: String,
+        needed: i64,
+        available: i64,
+    },
+    /// The amount does not fit an i64.
+    Overflow,
+}
+
+impl fmt::Display for BalanceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnknownAssets(symbol) => write!(f, "No assets known for symbol '{}'", symbol),
+            Self::Insufficient {
+                asset,
+                needed,
+                available,
+            } => write!(
+                f,
+                "Insufficient {} balance: {} needed, {} available",
+                asset, needed, available
+            ),
+            Self::Overflow => write!(f, "Amount out of range"),
+        }
+    }
+}
+
+/// What an order needs locked: `amount` of `asset`, `per_unit` of it for each `qty_scale`
+/// lots of quantity (None for a market buy, which locks its expected cost).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LockSpec {
+    pub asset: String,
+    pub per_unit: Option<i64>,
+    pub qty_scale: u64,
+    pub amount: i64,
+}
+
+impl LockSpec {
+    /// The lock an order on `symbol` (trading under `config`) needs. `price` is None for a
+    /// market order; a market buy locks `market_cost`, what it is expected to spend.
+    pub fn for_order(
+        symbol: &str,
+        config: &SymbolConfig,
+        side: OrderSide,
+        price: Option<Price>,
+        quantity: Qty,
+        market_cost: Notional,
+    ) -> Result<Self, BalanceError> {
+        let (base, quote) = config
+            .assets()
+            .ok_or_else(|| BalanceError::UnknownAssets(symbol.to_string()))?;
+        let qty_scale = config.qty_scale;
+        // The base asset is held in lots, so a sell locks its quantity as is
+        let (asset, per_unit, qty_scale, amount) = match (side, price) {
+            (OrderSide::Sell, _) => (base, Some(1), 1, i64::try_from(quantity).ok()),
+            (OrderSide::Buy, Some(price)) => (
+                quote,
+                Some(price),
+                qty_scale,
+                scaled_notional(price, quantity, qty_scale).to_i64().ok(),
+            ),
+            (OrderSide::Buy, None) => (quote, None, qty_scale, market_cost.to_i64().ok()),
+        };
+        Ok(Self {
+            asset: asset.to_string(),
+            per_unit,
+            qty_scale,
+            amount: amount.ok_or(BalanceError::Overflow)?,
+        })
+    }
+}
+
+/// A lock held by an open order; `locked` is what its fills have not spent yet.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct OrderLock {
+    user_id: Uuid,
+    asset: String,
+    per_unit: Option<i64>,
+    qty_scale: u64,
+    quantity: Qty,
+    locked: i64,
+}
+
+/// Balances and order locks. Callers take it through `Balances::ledger` while holding the
+/// book write lock, so checking, matching and locking an order happen as one step.
+#[derive(Debug, Default)]
+pub struct Ledger {
+    accounts: HashMap<(Uuid, String), Balance>,
+    locks: HashMap<OrderId, OrderLock>,
+    /// Available amount per asset of an account not used yet.
+    funding: HashMap<String, i64>,
+}
+
+impl Ledger {
+    pub fn balance(&self, user_id: Uuid, asset: &str) -> Balance {
+        self.accounts
+            .get(&(user_id, asset.to_string()))
+            .copied()
+            .unwrap_or_else(|| self.funded(asset))
+    }
+
+    /// `user_id`'s balances by asset name, funded assets included.
+    pub fn balances_of(&self, user_id: Uuid) -> Vec<(String, Balance)> {
+        let mut balances: Vec<(String, Balance)> = self
+            .accounts
+            .iter()
+            .filter(|((user, _), _)| *user == user_id)
+            .map(|((_, asset), balance)| (asset.clone(), *balance))
+            .collect();
+        for asset in self.funding.keys() {
+            if !balances.iter().any(|(held, _)| held == asset) {
+                balances.push((asset.clone(), self.funded(asset)));
+            }
+        }
+        balances.sort_by(|a, b| a.0.cmp(&b.0));
+        balances
+    }
+
+    /// Add `amount` (negative to withdraw, down to zero available) to `user_id`'s `asset`.
+    pub fn credit(
+        &mut self,
+        user_id: Uuid,
+        asset: &str,
+        amount: i64,
+    ) -> Result<Balance, BalanceError> {
+        let account = self.account(user_id, asset);
+        let available = account
+            .available
+            .checked_add(amount)
+            .ok_or(BalanceError::Overflow)?;
+        if available < 0 {
+            return Err(BalanceError::Insufficient {
+                asset: asset.to_string(),
+                needed: -amount,
+                available: account.available,
+            });
+        }
+        account.available = available;
+        Ok(*account)
+    }
+
+    /// Whether `user_id` has `amount` of `asset` available.
+    pub fn check(&self, user_id: Uuid, asset: &str, amount: i64) -> Result<(), BalanceError> {
+        self.check_freeing(user_id, asset, amount, None)
+    }
+
+    /// Like `check`, counting what `replaced` (an open order being replaced) would release.
+    pub fn check_freeing(
+        &self,
+        user_id: Uuid,
+        asset: &str,
+        amount: i64,
+        replaced: Option<OrderId>,
+    ) -> Result<(), BalanceError> {
+        let freed = replaced
+            .and_then(|id| self.locks.get(&id))
+            .filter(|lock| lock.asset == asset)
+            .map_or(0, |lock| lock.locked);
+        let available = self.balance(user_id, asset).available.saturating_add(freed);
+        if available < amount {
+            return Err(BalanceError::Insufficient {
+                asset: asset.to_string(),
+                needed: amount,
+                available,
+            });
+        }
+        Ok(())
+    }
+
+    /// Move `spec.amount` from available to locked for `order_id`, which has `quantity` open.
+    /// Fails without changes when not enough is available.
+    pub fn lock_order(
+        &mut self,
+        order_id: OrderId,
+        user_id: Uuid,
+        spec: LockSpec,
+        quantity: Qty,
+    ) -> Result<(), BalanceError> {
+        self.check(user_id, &spec.asset, spec.amount)?;
+        let account = self.account(user_id, &spec.asset);
+        account.available -= spec.amount;
+        account.locked = account.locked.saturating_add(spec.amount);
+        self.locks.insert(
+            order_id,
+            OrderLock {
+                user_id,
+                asset: spec.asset,
+                per_unit: spec.per_unit,
+                qty_scale: spec.qty_scale,
+                quantity,
+                locked: spec.amount,
+            },
+        );
+        Ok(())
+    }
+
+    /// Apply fills to both sides' balances: the buyer spends quote from its lock and receives
+    /// base, the seller spends base and receives quote. Orders without a lock (placed while
+    /// balances were not tracked) pay from their available balance.
+    pub fn settle(&mut self, config: &SymbolConfig, taker_side: OrderSide, trades: &[Trade]) {
+        let Some((base, quote)) = config.assets() else {
+            return;
+        };
+        let (base, quote, qty_scale) = (base.as_str(), quote.as_str(), config.qty_scale);
+        for trade in trades {
+            let (buyer, buy_order, seller, sell_order) = match taker_side {
+                OrderSide::Buy => (
+                    trade.taker_user_id,
+                    trade.taker_order_id,
+                    trade.maker_user_id,
+                    trade.maker_order_id,
+                ),
+                OrderSide::Sell => (
+                    trade.maker_user_id,
+                    trade.maker_order_id,
+                    trade.taker_user_id,
+                    trade.taker_order_id,
+                ),
+            };
+            let cost = scaled_notional(trade.price, trade.quantity, qty_scale).to_i64_saturating();
+            let quantity = i64::try_from(trade.quantity).unwrap_or(i64::MAX);
+            self.spend(buy_order, buyer, quote, trade.quantity, cost);
+            self.add_available(buyer, base, quantity);
+            self.spend(sell_order, seller, base, trade.quantity, quantity);
+            self.add_available(seller, quote, cost);
+        }
+    }
+
+    /// Undo `trade`'s settlement (e.g. when it is busted): the buyer gives back the base it
+    /// received and gets its quote back, the seller the other way round. Both move through
+    /// available balances, which go negative if the asset has since been spent or locked.
+    pub fn reverse(&mut self, config: &SymbolConfig, taker_side: OrderSide, trade: &Trade) {
+        let Some((base, quote)) = config.assets() else {
+            return;
+        };
+        let (base, quote, qty_scale) = (base.as_str(), quote.as_str(), config.qty_scale);
+        let (buyer, seller) = match taker_side {
+            OrderSide::Buy => (trade.taker_user_id, trade.maker_user_id),
+            OrderSide::Sell => (trade.maker_user_id, trade.taker_user_id),
+        };
+        let cost = scaled_notional(trade.price, trade.quantity, qty_scale).to_i64_saturating();
+        let quantity = i64::try_from(trade.quantity).unwrap_or(i64::MAX);
+        self.add_available(buyer, base, quantity.saturating_neg());
+        self.add_available(buyer, quote, cost);
+        self.add_available(seller, quote, cost.saturating_neg());
+        self.add_available(seller, base, quantity);
+    }
+
+    /// Release what `order_id` still has locked, now that it left the book with the rest of
+    /// its quantity unfilled. Returns the asset and amount released.
+    pub fn release_order(&mut self, order_id: OrderId) -> Option<(String, i64)> {
+        let lock = self.locks.remove(&order_id)?;
+        self.release_lock(lock.user_id, &lock.asset, lock.locked);
+        Some((lock.asset, lock.locked))
+    }
+
+    /// Release the share of `order_id`'s lock held for quantity it no longer has open, now
+    /// that it was reduced in place to `quantity`. Returns the asset and amount released.
+    pub fn reduce_order(&mut self, order_id: OrderId, quantity: Qty) -> Option<(String, i64)> {
+        let lock = self.locks.get_mut(&order_id)?;
+        let removed = lock.quantity.saturating_sub(quantity);
+        let amount = match lock.per_unit {
+            Some(per_unit) => {
+                scaled_notional(per_unit, removed, lock.qty_scale).to_i64_saturating()
+            }
+            // Only market buys lock without a per-unit amount, and those never rest
+            None => 0,
+        }
+        .min(lock.locked);
+        lock.locked -= amount;
+        lock.quantity = lock.quantity.min(quantity);
+        let (user_id, asset) = (lock.user_id, lock.asset.clone());
+        self.release_lock(user_id, &asset, amount);
+        Some((asset, amount))
+    }
+
+    /// Move `amount` of `asset` from locked back to available.
+    pub fn release_lock(&mut self, user_id: Uuid, asset: &str, amount: i64) {
+        let account = self.account(user_id, asset);
+        let amount = amount.min(account.locked);
+        account.locked -= amount;
+        account.available = account.available.saturating_add(amount);
+    }
+
+    /// Amount `order_id` still has locked, if it holds a lock.
+    pub fn locked_by(&self, order_id: OrderId) -> Option<i64> {
+        self.locks.get(&order_id).map(|lock| lock.locked)
+    }
+
+    // Spend `cost` for `quantity` of `order_id`'s fill: from its lock when it has one (a buy
+    // below its limit gets the rest of the per-unit lock back), else from available
+    fn spend(&mut self, order_id: OrderId, user_id: Uuid, asset: &str, quantity: Qty, cost: i64) {
+        let Some(lock) = self.locks.get_mut(&order_id) else {
+            let account = self.account(user_id, asset);
+            account.available = account.available.saturating_sub(cost);
+            return;
+        };
+        let held = match lock.per_unit {
+            Some(per_unit) => {
+                scaled_notional(per_unit, quantity, lock.qty_scale).to_i64_saturating()
+            }
+            None => cost,
+        }
+        .min(lock.locked);
+        lock.locked -= held;
+        lock.quantity = lock.quantity.saturating_sub(quantity);
+        let done = lock.quantity == 0;
+        let account = self.account(user_id, asset);
+        account.locked -= held.min(account.locked);
+        account.available = account.available.saturating_add(held - cost);
+        if done {
+            self.release_order(order_id);
+        }
+    }
+
+    fn add_available(&mut self, user_id: Uuid, asset: &str, amount: i64) {
+        let account = self.account(user_id, asset);
+        account.available = account.available.saturating_add(amount);
+    }
+
+    fn account(&mut self, user_id: Uuid, asset: &str) -> &mut Balance {
+        let funded = self.funded(asset);
+        self.accounts
+            .entry((user_id, asset.to_string()))
+            .or_insert(funded)
+    }
+
+    fn funded(&self, asset: &str) -> Balance {
+        Balance {
+            available: self.funding.get(asset).copied().unwrap_or_default(),
+            locked: 0,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Balances {
+    enabled: bool,
+    ledger: Mutex<Ledger>,
+}
+
+impl Balances {
+    pub fn new(enabled: bool) -> Self {
+        Self {
+            enabled,
+            ledger: Mutex::new(Ledger::default()),
+        }
+    }
+
+    /// Balances every user starts with `funding` of (amount per asset), tracked unless
+    /// `funding` is empty.
+    pub fn funded(funding: HashMap<String, i64>) -> Self {
+        Self {
+            enabled: !funding.is_empty(),
+            ledger: Mutex::new(Ledger {
+                funding,
+                ..Ledger::default()
+            }),
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// The ledger, or None when balances are not tracked. Take it after the book lock.
+    pub async fn ledger(&self) -> Option<MutexGuard<'_, Ledger>> {
+        if !self.enabled {
+            return None;
+        }
+        Some(self.ledger.lock().await)
+    }
+}
+
+/// Parse `ASSET=amount` entries separated by commas (e.g. `PAPER_FUNDING`). Malformed entries
+/// are skipped.
+pub fn parse_funding(spec: &str) -> HashMap<String, i64> {
+    spec.split(',')
+        .filter_map(|entry| {
+            let (asset, amount) = entry.trim().split_once('=')?;
+            let asset = Asset::parse(asset).ok()?;
+            let amount: i64 = amount.trim().parse().ok().filter(|&amount| amount > 0)?;
+            Some((asset.to_string(), amount))
+        })
+        .collect()
+}
@@ -0,0 +1,93 @@
+//! Apply or inspect database migrations, for servers run with `MIGRATE_ON_START=false`.
+//!
+//! ```text
+//! migrate [status|run]
+//! ```
+//!
+//! Connects to `DATABASE_URL`. `status` (the default) lists applied and pending migrations and
+//! exits 1 if any are pending; `run` applies the pending ones. A run that is interrupted can be
+//! started again: it resumes from the first migration not applied. Exits 2 on bad arguments or
+//! database errors.
+
+use rust_exchange::persistence::{self, SchemaStatus};
+use std::process::ExitCode;
+
+const USAGE: &str = "usage: migrate [status|run]";
+
+fn print_status(status: &SchemaStatus) {
+    for migration in &status.applied {
+        println!(
+            "applied  {} {} ({})",
+            migration.version,
+            migration.description,
+            migration
+                .installed_on
+                .map(|at| at.to_rfc3339())
+                .unwrap_or_default()
+        );
+    }
+    for migration in &status.unknown {
+        println!("unknown  {} {}", migration.version, migration.description);
+    }
+    for migration in &status.pending {
+        println!("pending  {} {}", migration.version, migration.description);
+    }
+    for version in &status.modified {
+        println!("modified {}", version);
+    }
+    for version in &status.failed {
+        println!("failed   {}", version);
+    }
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    dotenvy::dotenv().ok();
+    let run = match std::env::args().nth(1).as_deref() {
+        None | Some("status") => false,
+        Some("run") => true,
+        Some(other) => {
+            eprintln!("unknown command '{}'\n{}", other, USAGE);
+            return ExitCode::from(2);
+        }
+    };
+    let Ok(url) = std::env::var("DATABASE_URL") else {
+        eprintln!("DATABASE_URL must be set");
+        return ExitCode::from(2);
+    };
+    let pool = match persistence::pool_options(None).connect(&url).await {
+        Ok(pool) => pool,
+        Err(e) => {
+            eprintln!("cannot connect: {}", e);
+            return ExitCode::from(2);
+        }
+    };
+
+    if run {
+        match persistence::ensure_schema(&pool, true).await {
+            Ok(status) => {
+                print_status(&status);
+                ExitCode::SUCCESS
+            }
+            Err(e) => {
+                eprintln!("{}", e);
+                ExitCode::from(2)
+            }
+        }
+    } else {
+        match persistence::schema_status(&pool).await {
+            Ok(status) => {
+                print_status(&status);
+                if status.is_current() {
+                    ExitCode::SUCCESS
+                } else {
+                    ExitCode::from(1)
+                }
+            }
+            Err(e) => {
+                eprintln!("cannot read schema status: {}", e);
+                ExitCode::from(2)
+            }
+        }
+    }
+}
@@ -0,0 +1,183 @@
+//! Replay a recorded session through the matching engine (input format: `rust_exchange::replay`).
+//!
+//! ```text
+//! replay <input> [--format ndjson|csv] [--trades-out PATH] [--book-out PATH]
+//!                [--expected PATH] [--progress-every N]
+//!                [--skew-policy reject|clamp|accept-with-warning] [--max-age-days N]
+//! ```
+//!
+//! Trades go to `--trades-out` (stdout by default) as NDJSON, the final book to `--book-out`
+//! as JSON, progress and the summary to stderr. Either skew option checks event timestamps
+//! against a window around the current time (`rust_exchange::skew`; a year old to five
+//! minutes ahead unless `--max-age-days` says otherwise). Exits 1 if the trades diverge from
+//! `--expected`, 2 on bad arguments or input.
+
+use rust_exchange::replay::{EventReader, ExpectedTrades, InputFormat, Replay, ReplayError};
+use rust_exchange::skew::{SkewPolicy, SkewWindow};
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Write};
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::time::Instant;
+
+const USAGE: &str = "usage: replay <input> [--format ndjson|csv] [--trades-out PATH] \
+                     [--book-out PATH] [--expected PATH] [--progress-every N] \
+                     [--skew-policy reject|clamp|accept-with-warning] [--max-age-days N]";
+
+struct Args {
+    input: PathBuf,
+    format: Option<InputFormat>,
+    trades_out: Option<PathBuf>,
+    book_out: Option<PathBuf>,
+    expected: Option<PathBuf>,
+    progress_every: u64,
+    skew: Option<SkewWindow>,
+}
+
+fn parse_args() -> Result<Args, String> {
+    let mut input = None;
+    let mut format = None;
+    let mut trades_out = None;
+    let mut book_out = None;
+    let mut expected = None;
+    let mut progress_every = 100_000;
+    let mut skew: Option<SkewWindow> = None;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| format!("{} needs a value", arg));
+        match arg.as_str() {
+            "--format" => {
+                let v = value()?;
+                format =
+                    Some(InputFormat::parse(&v).ok_or_else(|| format!("unknown format '{}'", v))?);
+            }
+            "--trades-out" => trades_out = Some(PathBuf::from(value()?)),
+            "--book-out" => book_out = Some(PathBuf::from(value()?)),
+            "--expected" => expected = Some(PathBuf::from(value()?)),
+            "--progress-every" => {
+                let v = value()?;
+                progress_every = v
+                    .parse()
+                    .map_err(|_| format!("invalid --progress-every '{}'", v))?;
+            }
+            "--skew-policy" => {
+                let v = value()?;
+                let policy =
+                    SkewPolicy::parse(&v).ok_or_else(|| format!("unknown skew policy '{}'", v))?;
+                skew.get_or_insert_default().policy = policy;
+            }
+            "--max-age-days" => {
+                let v = value()?;
+                let days = v
+                    .parse()
+                    .ok()
+                    .filter(|&days: &i64| days > 0)
+                    .ok_or_else(|| format!("invalid --max-age-days '{}'", v))?;
+                skew.get_or_insert_default().max_age = chrono::Duration::days(days);
+            }
+            _ if arg.starts_with("--") => return Err(format!("unknown option '{}'", arg)),
+            _ if input.is_none() => input = Some(PathBuf::from(arg)),
+            _ => return Err(format!("unexpected argument '{}'", arg)),
+        }
+    }
+    Ok(Args {
+        input: input.ok_or("missing input file")?,
+        format,
+        trades_out,
+        book_out,
+        expected,
+        progress_every,
+        skew,
+    })
+}
+
+fn main() -> ExitCode {
+    let args = match parse_args() {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("{}\n{}", e, USAGE);
+            return ExitCode::from(2);
+        }
+    };
+    match run(&args) {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::from(1),
+        Err(e) => {
+            eprintln!("replay failed: {}", e);
+            ExitCode::from(2)
+        }
+    }
+}
+
+/// Replay the input; Ok(false) if the trades diverged from the expected file.
+fn run(args: &Args) -> Result<bool, ReplayError> {
+    let format = args
+        .format
+        .unwrap_or_else(|| InputFormat::from_path(&args.input));
+    let events = EventReader::new(BufReader::new(File::open(&args.input)?), format);
+    let mut trades_out: Box<dyn Write> = match &args.trades_out {
+        Some(path) => Box::new(BufWriter::new(File::create(path)?)),
+        None => Box::new(BufWriter::new(io::stdout().lock())),
+    };
+    let mut expected = match &args.expected {
+        Some(path) => Some(ExpectedTrades::new(BufReader::new(File::open(path)?))),
+        None => None,
+    };
+
+    let mut replay = Replay::new();
+    if let Some(window) = args.skew {
+        replay = replay.with_skew_window(window, chrono::Utc::now());
+    }
+    let started = Instant::now();
+    for event in events {
+        let (line, event) = event?;
+        let trades = replay
+            .apply(&event)
+            .map_err(|message| ReplayError::Event { line, message })?;
+        for trade in &trades {
+            serde_json::to_writer(&mut trades_out, trade).map_err(io::Error::from)?;
+            trades_out.write_all(b"\n")?;
+            if let Some(expected) = expected.as_mut() {
+                expected.check(trade)?;
+            }
+        }
+        let processed = replay.stats().events;
+        if args.progress_every > 0 && processed.is_multiple_of(args.progress_every) {
+            eprintln!(
+                "replay: {} events, {} trades, {:.0} events/s",
+                processed,
+                replay.stats().trades,
+                processed as f64 / started.elapsed().as_secs_f64()
+            );
+        }
+    }
+    trades_out.flush()?;
+    let elapsed = started.elapsed();
+
+    if let Some(path) = &args.book_out {
+        let mut out = BufWriter::new(File::create(path)?);
+        serde_json::to_writer_pretty(&mut out, &replay.final_book()).map_err(io::Error::from)?;
+        out.flush()?;
+    }
+
+    let stats = replay.stats();
+    eprintln!(
+        "replay: {} in {:.3}s ({:.0} events/s)",
+        stats,
+        elapsed.as_secs_f64(),
+        stats.events as f64 / elapsed.as_secs_f64().max(f64::EPSILON)
+    );
+
+    let Some(expected) = expected else {
+        return Ok(true);
+    };
+    let comparison = expected.finish()?;
+    for sample in &comparison.samples {
+        eprintln!("replay: divergence: {}", sample);
+    }
+    eprintln!(
+        "replay: {} of {} trades diverged from the expected file",
+        comparison.divergences, comparison.compared
+    );
+    Ok(comparison.matches())
+}
@@ -0,0 +1,812 @@
+//! Server startup. `Config` gathers every setting from the environment, `build_state` turns it
+//! into an `AppState` and `spawn_background` starts the tasks that keep that state current;
+//! `main` is those calls plus serving, and tests boot through the same path.
+//!
+//! `PERSISTENCE_MODE` picks where state lives. `postgres` (needs `DATABASE_URL`) hydrates from
+//! the database and writes every change back. `memory` never opens a pool: it restores
+//! `SNAPSHOT_PATH` if that is set and starts with empty books otherwise, with the users listed
+//! in `SEED_USERS` (`name:password[:user_id]`, comma-separated). Unset, the mode is `postgres`
+//! when `DATABASE_URL` is set and `memory` when only `SNAPSHOT_PATH` is.
+//!
+//! `SYMBOLS` (comma-separated) declares the symbols an instance serves, so symbols can be
+//! sharded across instances; unset, it serves the defaults and every symbol stored in the
+//! `symbols` table. Symbols in `LAZY_SYMBOLS` are served too but hydrated on first use (see
+//! `api::lazy_books`). Open orders on symbols an instance does not serve are left stored and
+//! listed in the hydration report.
+
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::fmt;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{Mutex, RwLock, broadcast};
+use uuid::Uuid;
+
+use crate::api::activity::{self, ActivityFeed};
+use crate::api::auth::{self, AuthUserCredential, DEFAULT_USERNAME_COOLDOWN, UsernameHistory};
+use crate::api::book_cache::BookCache;
+use crate::api::deadline::{DEFAULT_REQUEST_TIMEOUT, LockWaitMetrics};
+use crate::api::delisting::{self, Delistings};
+use crate::api::exchange_info::RulesLog;
+use crate::api::expiry::{self, OrderExpiry};
+use crate::api::fanout::Fanout;
+use crate::api::idempotency::IdempotencyCache;
+use crate::api::incidents::Incidents;
+use crate::api::ingress::Ingress;
+use crate::api::kill_switch::KillSwitches;
+use crate::api::lazy_books::LazyBooks;
+use crate::api::phase_timer::PhaseTimings;
+use crate::api::routes::{AppState, UserStore, WsMessage};
+use crate::api::spreads::Spreads;
+use crate::api::surveillance::{Surveillance, SurveillanceConfig};
+use crate::api::ws_connections::{WsConfig, WsConnections};
+use crate::balances::{self, Balances};
+use crate::faults::FaultPlan;
+use crate::hydration::{self, Hydrated, HydrationReport};
+use crate::market_data::{self, DEFAULT_HISTORY, MarketDataStore, SharedMarketData};
+use crate::orderbook::book_stats::{self, BookStatsGauges};
+use crate::orderbook::clock::{SharedClock, SystemClock};
+use crate::orderbook::diff_buffer;
+use crate::orderbook::order_limits::{OrderLimits, RestingOrderCaps, SharedOrderLimits};
+use crate::orderbook::orderbook::SharedOrderBook;
+use crate::orderbook::trade_archive;
+use crate::persistence::{self, PgPool, TradePersistenceMetrics};
+use crate::positions::SharedPositions;
+use crate::pricefeed::{self, FeedConfig, HttpPriceSource, SharedIndexPrices};
+use crate::replica::{self, ReplicaConfig, ServerRole};
+use crate::retention::{self, Retention, RetentionConfig, SharedRetention};
+use crate::sandbox::{self, SandboxConfig, Sandboxes};
+use crate::selftest::SELFTEST_SYMBOL;
+use crate::skew::SkewWindow;
+use crate::snapshot::{self, ExchangeSnapshot};
+use crate::statements;
+use crate::tasks::Supervisor;
+use crate::types::environment::Environment;
+use crate::types::money::PRICE_SCALE;
+use crate::types::symbol::Symbol;
+use crate::webhooks::{self, WebhookConfig, Webhooks};
+
+/// Books every server starts with.
+pub const DEFAULT_SYMBOLS: [&str; 3] = ["BTCUSDT", "ETHUSDT", SELFTEST_SYMBOL];
+
+/// Signing secret used when `JWT_SECRET` is not set.
+const DEV_JWT_SECRET: &str = "dev-secret-change-in-production";
+
+/// Port the gRPC service listens on when `GRPC_PORT` is not set.
+pub const DEFAULT_GRPC_PORT: u16 = 50051;
+
+/// Where the server keeps its state, from `PERSISTENCE_MODE`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PersistenceMode {
+    /// Hydrate from and persist to the database at `DATABASE_URL`.
+    Postgres,
+    /// No database: state lives in memory (and in the snapshot file, if configured).
+    Memory,
+}
+
+impl FromStr for PersistenceMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "postgres" => Ok(Self::Postgres),
+            "memory" => Ok(Self::Memory),
+            other => Err(format!(
+                "unknown persistence mode '{}' (expected postgres or memory)",
+                other
+            )),
+        }
+    }
+}
+
+impl fmt::Display for PersistenceMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Postgres => write!(f, "postgres"),
+            Self::Memory => write!(f, "memory"),
+        }
+    }
+}
+
+/// A user a memory-mode server starts with.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SeedUser {
+    pub username: String,
+    pub password: String,
+    /// Fixed so the user can be listed in `ADMIN_USER_IDS`; random when not given.
+    pub user_id: Uuid,
+}
+
+/// Parse `name:password[:user_id]` entries separated by commas (e.g. `SEED_USERS`).
+pub fn parse_seed_users(spec: &str) -> Result<Vec<SeedUser>, String> {
+    spec.split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| {
+            let mut parts = entry.splitn(3, ':');
+            let username = parts.next().unwrap_or_default().trim();
+            let password = parts.next().unwrap_or_default().trim();
+            if username.is_empty() || password.is_empty() {
+                return Err(format!(
+                    "seed user '{}' needs a username and a password",
+                    entry
+                ));
+            }
+            let user_id = match parts.next() {
+                Some(id) => Uuid::parse_str(id.trim())
+                    .map_err(|e| format!("seed user '{}': bad user id: {}", username, e))?,
+                None => Uuid::new_v4(),
+            };
+            Ok(SeedUser {
+                username: username.to_string(),
+                password: password.to_string(),
+                user_id,
+            })
+        })
+        .collect()
+}
+
+/// Everything the server is configured with.
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub persistence: PersistenceMode,
+    /// Required in `Postgres` mode; ignored in `Memory` mode.
+    pub database_url: Option<String>,
+    /// Restored at startup in `Memory` mode and written while running in either mode.
+    pub snapshot_path: Option<PathBuf>,
+    pub role: ServerRole,
+    /// Per-statement database timeout; None disables it.
+    pub statement_timeout: Option<Duration>,
+    /// Apply pending migrations at startup; otherwise refuse to start until they are applied.
+    pub migrate_on_start: bool,
+    /// Refuse to start when hydration skipped anything.
+    pub strict_hydration: bool,
+    pub skew: SkewWindow,
+    /// Symbols served and hydrated at startup.
+    pub symbols: Vec<String>,
+    /// Symbols served but hydrated on first use. Without a database, or on a replica, they
+    /// are hydrated at startup like the others.
+    pub lazy_symbols: Vec<String>,
+    /// Also serve every symbol stored in the `symbols` table (unless `SYMBOLS` is set).
+    pub serve_stored_symbols: bool,
+    /// Users a `Memory` server starts with.
+    pub seed_users: Vec<SeedUser>,
+    pub jwt_secret: Vec<u8>,
+    pub admin_user_ids: HashSet<Uuid>,
+    pub balance_locking: bool,
+    /// Open a paper book (`SYMBOL@paper`) beside every symbol but `SELFTEST`.
+    pub paper_environment: bool,
+    /// What every user starts with in the paper environment, per asset; paper balances are
+    /// not tracked when this is empty.
+    pub paper_funding: HashMap<String, i64>,
+    /// Let any client ask for `X-Debug-Timings` (admins always can).
+    pub debug_timings: bool,
+    pub index_price_max_age: Duration,
+    pub index_price_feeds: HashMap<String, FeedConfig>,
+    pub index_price_poll: Duration,
+    pub username_cooldown: Duration,
+    pub request_timeout: Duration,
+    pub activity_feed_size: usize,
+    /// Trades each book archives for replay by sequence.
+    pub trade_archive_size: usize,
+    /// Diffs each book keeps for clients recovering a gap in the diff stream.
+    pub book_diff_buffer_size: usize,
+    pub market_data_history: Duration,
+    pub ticker_refresh: Duration,
+    pub book_stats_interval: Duration,
+    pub order_caps: RestingOrderCaps,
+    pub ws: WsConfig,
+    pub sandbox: SandboxConfig,
+    pub webhooks: WebhookConfig,
+    pub retention: RetentionConfig,
+    pub replica: ReplicaConfig,
+    pub surveillance: SurveillanceConfig,
+    /// Port of the gRPC service, served with the `grpc` feature; None serves none.
+    pub grpc_port: Option<u16>,
+    /// How long shutdown waits for each stage of background tasks before aborting them.
+    pub shutdown_timeout: Duration,
+}
+
+impl Config {
+    /// An in-memory primary with every other setting at its default.
+    pub fn memory() -> Self {
+        Self {
+            persistence: PersistenceMode::Memory,
+            database_url: None,
+            snapshot_path: None,
+            role: ServerRole::Primary,
+            statement_timeout: Some(Duration::from_secs(5)),
+            migrate_on_start: true,
+            strict_hydration: false,
+            skew: SkewWindow::default(),
+            symbols: DEFAULT_SYMBOLS.iter().map(|s| s.to_string()).collect(),
+            lazy_symbols: Vec::new(),
+            serve_stored_symbols: true,
+            seed_users: Vec::new(),
+            jwt_secret: DEV_JWT_SECRET.as_bytes().to_vec(),
+            admin_user_ids: HashSet::new(),
+            balance_locking: false,
+            paper_environment: false,
+            paper_funding: HashMap::new(),
+            debug_timings: false,
+            index_price_max_age: Duration::from_secs(30),
+            index_price_feeds: HashMap::new(),
+            index_price_poll: Duration::from_secs(5),
+            username_cooldown: DEFAULT_USERNAME_COOLDOWN,
+            request_timeout: DEFAULT_REQUEST_TIMEOUT,
+            activity_feed_size: activity::DEFAULT_CAPACITY,
+            trade_archive_size: trade_archive::DEFAULT_CAPACITY,
+            book_diff_buffer_size: diff_buffer::DEFAULT_CAPACITY,
+            market_data_history: DEFAULT_HISTORY,
+            ticker_refresh: market_data::DEFAULT_ROLLING_REFRESH,
+            book_stats_interval: Duration::from_secs(15),
+            order_caps: RestingOrderCaps::default(),
+            ws: WsConfig::default(),
+            sandbox: SandboxConfig::default(),
+            webhooks: WebhookConfig::default(),
+            retention: RetentionConfig::default(),
+            replica: ReplicaConfig::default(),
+            surveillance: SurveillanceConfig::default(),
+            grpc_port: Some(DEFAULT_GRPC_PORT),
+            shutdown_timeout: Duration::from_secs(10),
+        }
+    }
+
+    /// Keys of the books to open for `symbols`: each symbol, and its paper book when the paper
+    /// environment is on.
+    pub fn book_keys(&self, symbols: &[String]) -> Vec<String> {
+        let mut keys = symbols.to_vec();
+        if self.paper_environment {
+            keys.extend(
+                symbols
+                    .iter()
+                    .filter(|symbol| *symbol != SELFTEST_SYMBOL)
+                    .map(|symbol| Environment::Paper.book_key(symbol)),
+            );
+        }
+        keys
+    }
+
+    /// Read the configuration from the environment (see the module docs and each setting's
+    /// variable), failing on settings that contradict each other.
+    pub fn from_env() -> Result<Self, String> {
+        fn var<T: FromStr>(name: &str) -> Option<T> {
+            env::var(name).ok().and_then(|v| v.parse().ok())
+        }
+        let defaults = Self::memory();
+        let database_url = env::var("DATABASE_URL").ok();
+        let snapshot_path = env::var("SNAPSHOT_PATH").ok().map(PathBuf::from);
+        let persistence = match env::var("PERSISTENCE_MODE") {
+            Ok(mode) => mode
+                .parse()
+                .map_err(|e| format!("PERSISTENCE_MODE: {}", e))?,
+            Err(_) if database_url.is_some() => PersistenceMode::Postgres,
+            Err(_) if snapshot_path.is_some() => PersistenceMode::Memory,
+            Err(_) => {
+                return Err(
+                    "DATABASE_URL must be set (or PERSISTENCE_MODE=memory for an in-memory \
+                     development server)"
+                        .to_string(),
+                );
+            }
+        };
+        if persistence == PersistenceMode::Postgres && database_url.is_none() {
+            return Err("PERSISTENCE_MODE=postgres needs DATABASE_URL".to_string());
+        }
+        // A market data replica reads the primary's database and never writes to it
+        let role: ServerRole = match env::var("SERVER_ROLE") {
+            Ok(role) => role.parse().map_err(|e| format!("SERVER_ROLE: {}", e))?,
+            Err(_) => ServerRole::default(),
+        };
+        if role.is_replica() && persistence != PersistenceMode::Postgres {
+            return Err("SERVER_ROLE=market_data_replica needs PERSISTENCE_MODE=postgres".into());
+        }
+        // 0 disables the per-statement timeout
+        let statement_timeout_ms: u64 = var("DB_STATEMENT_TIMEOUT_MS").unwrap_or(5000);
+        let seed_users = parse_seed_users(&env::var("SEED_USERS").unwrap_or_default())
+            .map_err(|e| format!("SEED_USERS: {}", e))?;
+        let symbols = env::var("SYMBOLS")
+            .ok()
+            .map(|spec| parse_symbols(&spec).map_err(|e| format!("SYMBOLS: {}", e)))
+            .transpose()?;
+        let lazy_symbols = parse_symbols(&env::var("LAZY_SYMBOLS").unwrap_or_default())
+            .map_err(|e| format!("LAZY_SYMBOLS: {}", e))?;
+        Ok(Self {
+            persistence,
+            database_url,
+            snapshot_path,
+            role,
+            statement_timeout: (statement_timeout_ms > 0)
+                .then(|| Duration::from_millis(statement_timeout_ms)),
+            // With MIGRATE_ON_START=false, pending migrations are applied with the `migrate`
+            // binary and the server refuses to start until they are
+            migrate_on_start: var("MIGRATE_ON_START").unwrap_or(defaults.migrate_on_start),
+            strict_hydration: var("STRICT_HYDRATION").unwrap_or(defaults.strict_hydration),
+            skew: SkewWindow::from_env(),
+            serve_stored_symbols: symbols.is_none(),
+            symbols: symbols.unwrap_or(defaults.symbols),
+            lazy_symbols,
+            seed_users,
+            jwt_secret: env::var("JWT_SECRET")
+                .map(String::into_bytes)
+                .unwrap_or(defaults.jwt_secret),
+            admin_user_ids: env::var("ADMIN_USER_IDS")
+                .unwrap_or_default()
+                .split(',')
+                .filter_map(|id| Uuid::parse_str(id.trim()).ok())
+                .collect(),
+            balance_locking: var("BALANCE_LOCKING").unwrap_or(defaults.balance_locking),
+            paper_environment: var("PAPER_ENVIRONMENT").unwrap_or(defaults.paper_environment),
+            paper_funding: balances::parse_funding(&env::var("PAPER_FUNDING").unwrap_or_default()),
+            debug_timings: var("DEBUG_TIMINGS").unwrap_or(defaults.debug_timings),
+            index_price_max_age: var("INDEX_PRICE_MAX_AGE_SECS")
+                .map_or(defaults.index_price_max_age, Duration::from_secs),
+            index_price_feeds: pricefeed::parse_feed_config(
+                &env::var("INDEX_PRICE_FEEDS").unwrap_or_default(),
+            ),
+            index_price_poll: var("INDEX_PRICE_POLL_SECS")
+                .map_or(defaults.index_price_poll, Duration::from_secs),
+            username_cooldown: var::<u64>("USERNAME_COOLDOWN_DAYS")
+                .map_or(defaults.username_cooldown, |days| {
+                    Duration::from_secs(days * 24 * 60 * 60)
+                }),
+            request_timeout: var::<u64>("REQUEST_TIMEOUT_MS")
+                .filter(|&ms| ms > 0)
+                .map_or(defaults.request_timeout, Duration::from_millis),
+            activity_feed_size: var("ACTIVITY_FEED_SIZE").unwrap_or(defaults.activity_feed_size),
+            trade_archive_size: var("TRADE_ARCHIVE_SIZE").unwrap_or(defaults.trade_archive_size),
+            book_diff_buffer_size: var("BOOK_DIFF_BUFFER_SIZE")
+                .unwrap_or(defaults.book_diff_buffer_size),
+            market_data_history: var::<u64>("MARKET_DATA_HISTORY_MINUTES")
+                .filter(|&minutes| minutes > 0)
+                .map_or(defaults.market_data_history, |minutes| {
+                    Duration::from_secs(minutes * 60)
+                }),
+            ticker_refresh: var::<u64>("TICKER_REFRESH_SECS")
+                .filter(|&secs| secs > 0)
+                .map_or(defaults.ticker_refresh, Duration::from_secs),
+            book_stats_interval: var::<u64>("BOOK_STATS_INTERVAL_SECS")
+                .filter(|&secs| secs > 0)
+                .map_or(defaults.book_stats_interval, Duration::from_secs),
+            order_caps: RestingOrderCaps::from_env(),
+            ws: WsConfig::from_env(),
+            sandbox: SandboxConfig::from_env(),
+            webhooks: WebhookConfig::from_env(),
+            retention: RetentionConfig::from_env(),
+            replica: ReplicaConfig::from_env(),
+            surveillance: SurveillanceConfig::from_env(),
+            // GRPC_PORT=0 serves no gRPC
+            grpc_port: var::<u16>("GRPC_PORT")
+                .map_or(defaults.grpc_port, |port| (port > 0).then_some(port)),
+            shutdown_timeout: var("SHUTDOWN_TIMEOUT_SECS")
+                .map_or(defaults.shutdown_timeout, Duration::from_secs),
+        })
+    }
+}
+
+/// Parse a comma-separated list of symbols, normalized. Paper books come with
+/// `PAPER_ENVIRONMENT`, so a symbol naming an environment is refused.
+pub fn parse_symbols(spec: &str) -> Result<Vec<String>, String> {
+    let mut symbols: Vec<String> = Vec::new();
+    for name in spec
+        .split(',')
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+    {
+        let symbol = Symbol::parse(name).map_err(|e| e.to_string())?;
+        if symbol.environment() != Environment::Live {
+            return Err(format!("'{}' names an environment", name));
+        }
+        if !symbols.iter().any(|served| served == symbol.as_str()) {
+            symbols.push(symbol.to_string());
+        }
+    }
+    Ok(symbols)
+}
+
+/// The keys of the books hydrated at startup and of those hydrated on first use. Lazy books
+/// load from the database, and a replica refreshes every book by itself, so without one or on
+/// a replica every book is hydrated at startup.
+async fn served_book_keys(
+    config: &Config,
+    pool: Option<&PgPool>,
+) -> Result<(Vec<String>, Vec<String>), String> {
+    let mut symbols = config.symbols.clone();
+    if let Some(pool) = pool
+        && config.serve_stored_symbols
+    {
+        match persistence::list_stored_symbols(pool).await {
+            Ok(stored) => symbols.extend(
+                stored
+                    .into_iter()
+                    .filter(|symbol| Environment::of(symbol) == Environment::Live),
+            ),
+            Err(e) if config.strict_hydration => {
+                return Err(format!(
+                    "strict hydration failed: load stored symbols: {}",
+                    e
+                ));
+            }
+            Err(e) => eprintln!("failed to load stored symbols: {}", e),
+        }
+    }
+    symbols.extend(config.lazy_symbols.iter().cloned());
+    let lazy_allowed = pool.is_some() && !config.role.is_replica();
+    let (mut eager, mut lazy) = (Vec::new(), Vec::new());
+    for symbol in symbols {
+        let served = if lazy_allowed && config.lazy_symbols.contains(&symbol) {
+            &mut lazy
+        } else {
+            &mut eager
+        };
+        if !served.contains(&symbol) {
+            served.push(symbol);
+        }
+    }
+    Ok((config.book_keys(&eager), config.book_keys(&lazy)))
+}
+
+/// Connect (in `Postgres` mode), hydrate and assemble the server state. Starts no background
+/// tasks; see `spawn_background`.
+pub async fn build_state(config: &Config) -> Result<AppState, String> {
+    let clock: SharedClock = Arc::new(SystemClock);
+    let pool = match config.persistence {
+        PersistenceMode::Postgres => Some(connect(config).await?),
+        PersistenceMode::Memory => {
+            warn_not_durable(config);
+            None
+        }
+    };
+    let (book_keys, lazy_keys) = served_book_keys(config, pool.as_ref()).await?;
+    let symbols: Vec<&str> = book_keys.iter().map(String::as_str).collect();
+    let lazy: Vec<&str> = lazy_keys.iter().map(String::as_str).collect();
+    // The database is authoritative when there is one; the snapshot file is then only written
+    let mut hydrated = match &pool {
+        Some(pool) => hydration::hydrate_served(
+            pool,
+            &symbols,
+            &lazy,
+            config.strict_hydration,
+            &config.skew,
+            clock.now(),
+        )
+        .await
+        .map_err(|report| format!("strict hydration failed: {}", report))?,
+        None => restore_snapshot(config, &symbols)?,
+    };
+    eprintln!("hydration: {}", hydrated.report);
+    if pool.is_none() {
+        seed_users(&mut hydrated, &config.seed_users, clock.now())?;
+    } else if !config.seed_users.is_empty() {
+        eprintln!("SEED_USERS ignored: users come from the database in postgres mode");
+    }
+
+    let user_store: UserStore = Arc::new(RwLock::new(hydrated.users));
+    let kill_switches = Arc::new(KillSwitches::new(hydrated.trading_disabled));
+    let order_limits: SharedOrderLimits = Arc::new(OrderLimits::new(config.order_caps));
+    eprintln!("resting order caps: {:?}", order_limits.configured());
+    let orderbooks: HashMap<String, SharedOrderBook> = hydrated
+        .orderbooks
+        .into_iter()
+        .map(|(symbol, mut book)| {
+            book.set_order_limits(order_limits.clone());
+            book.set_trade_archive_capacity(config.trade_archive_size);
+            book.set_diff_buffer_capacity(config.book_diff_buffer_size);
+            (symbol, Arc::new(RwLock::new(book)))
+        })
+        .collect();
+    eprintln!(
+        "websocket: channel capacity {}, queue capacity {}, overflow policy {:?}",
+        config.ws.channel_capacity, config.ws.queue_capacity, config.ws.overflow_policy
+    );
+    let (ws_tx, _) = broadcast::channel::<WsMessage>(config.ws.channel_capacity);
+    let positions: SharedPositions = Arc::new(RwLock::new(hydrated.positions));
+    let index_prices: SharedIndexPrices = Arc::new(RwLock::new(HashMap::new()));
+
+    let market_data: SharedMarketData = Arc::new(MarketDataStore::new(config.market_data_history));
+    if let Some(ref pool) = pool {
+        let since = clock.now()
+            - chrono::Duration::from_std(config.market_data_history)
+                .unwrap_or(chrono::Duration::MAX);
+        match persistence::list_candles_since(pool, since).await {
+            Ok(rows) => {
+                let mut by_symbol: HashMap<String, Vec<_>> = HashMap::new();
+                for row in &rows {
+                    by_symbol
+                        .entry(row.symbol.clone())
+                        .or_default()
+                        .push(row.to_candle());
+                }
+                for (symbol, candles) in by_symbol {
+                    market_data.load_candles(&symbol, candles);
+                }
+            }
+            Err(e) => eprintln!("failed to load recent candles: {}", e),
+        }
+        // The rolling 24h ticker is read from the trades themselves, then kept up to date in
+        // memory and re-read every TICKER_REFRESH_SECS (see `spawn_background`)
+        let ticker_symbols = ticker_symbols(&orderbooks);
+        if let Err(e) =
+            market_data::load_rolling_stats(&market_data, pool, &ticker_symbols, clock.now(), None)
+                .await
+        {
+            eprintln!("failed to load rolling ticker statistics: {}", e);
+        }
+    }
+
+    let webhooks = Arc::new(Webhooks::new(config.webhooks.clone()));
+    if let Some(ref pool) = pool
+        && !config.role.is_replica()
+    {
+        match webhooks.load(pool).await {
+            Ok(loaded) => eprintln!("webhooks: {} loaded", loaded),
+            Err(e) => eprintln!("failed to load webhooks: {}", e),
+        }
+    }
+
+    let rules = Arc::new(RulesLog::default());
+    if let Some(ref pool) = pool {
+        match rules.load(pool).await {
+            Ok(loaded) => eprintln!(
+                "exchange rules: version {}, {} changes loaded",
+                rules.version(),
+                loaded
+            ),
+            Err(e) => eprintln!("failed to load exchange rules changes: {}", e),
+        }
+    }
+
+    // With a database a quarantined book is not restored from the snapshot file
+    let incidents = Arc::new(Incidents::new(
+        config.snapshot_path.clone().filter(|_| pool.is_none()),
+    ));
+    // Pending delistings pick up where they left off
+    let delistings = Arc::new(Delistings::from_configs(&hydrated.symbol_configs));
+    let lazy_books = Arc::new(LazyBooks::new(
+        hydrated.report.symbols_lazy.clone(),
+        config.strict_hydration,
+        config.skew,
+    ));
+    let mut state = AppState {
+        orderbooks,
+        ws_channel: ws_tx,
+        positions,
+        jwt_secret: config.jwt_secret.clone(),
+        user_store,
+        db: pool,
+        admin_user_ids: config.admin_user_ids.clone(),
+        index_prices,
+        index_price_max_age: config.index_price_max_age,
+        idempotency: Arc::new(Mutex::new(IdempotencyCache::default())),
+        order_sessions: Arc::new(RwLock::new(HashMap::new())),
+        retention: Arc::new(Retention::new(config.retention.clone())) as SharedRetention,
+        hydration_report: Arc::new(hydrated.report),
+        trade_metrics: Arc::new(TradePersistenceMetrics::default()),
+        username_history: Arc::new(UsernameHistory::new(config.username_cooldown)),
+        book_cache: Arc::new(BookCache::default()),
+        ws_connections: Arc::new(WsConnections::new(config.ws)),
+        symbol_configs: Arc::new(RwLock::new(hydrated.symbol_configs)),
+        request_timeout: config.request_timeout,
+        lock_waits: Arc::new(LockWaitMetrics::default()),
+        phase_timings: Arc::new(PhaseTimings::new(config.debug_timings)),
+        book_stats: Arc::new(BookStatsGauges::default()),
+        spreads: Arc::new(Spreads::default()),
+        order_expiry: Arc::new(OrderExpiry::default()),
+        incidents,
+        market_data,
+        order_limits,
+        sandboxes: Arc::new(Sandboxes::new(config.sandbox.clone())),
+        balances: Arc::new(Balances::new(config.balance_locking)),
+        paper_balances: Arc::new(Balances::funded(config.paper_funding.clone())),
+        ingress: Arc::new(Ingress::default()),
+        ws_fanout: Arc::new(Fanout::default()),
+        activity: Arc::new(ActivityFeed::new(config.activity_feed_size)),
+        webhooks,
+        role: config.role,
+        faults: Arc::new(FaultPlan::default()),
+        clock,
+        delistings,
+        kill_switches,
+        tasks: Arc::new(Supervisor::default()),
+        rules,
+        surveillance: Arc::new(Surveillance::new(config.surveillance)),
+        lazy_books,
+    };
+    if let Some(pool) = state.db.clone()
+        && !config.role.is_replica()
+    {
+        expire_lapsed_orders(&mut state, &pool, config.strict_hydration).await?;
+    }
+    Ok(state)
+}
+
+/// Start the tasks that keep `state` current: index price polling, candles and tickers,
+/// book stats, and either the replica refresher or the primary's sweepers, retention,
+/// statements and webhook delivery.
+pub fn spawn_background(state: &AppState, config: &Config) {
+    if !config.index_price_feeds.is_empty() {
+        let source = HttpPriceSource::new(config.index_price_feeds.clone(), PRICE_SCALE);
+        let symbols = source.symbols();
+        pricefeed::spawn_poller(
+            &state.tasks,
+            source,
+            symbols,
+            state.index_prices.clone(),
+            state.ws_channel.clone(),
+            config.index_price_poll,
+        );
+    }
+    if let Some(ref pool) = state.db {
+        market_data::spawn_rolling_refresher(
+            &state.tasks,
+            state.market_data.clone(),
+            pool.clone(),
+            ticker_symbols(&state.orderbooks),
+            config.ticker_refresh,
+            state.clock.clone(),
+        );
+    }
+    // Candles are persisted by the primary only
+    let candle_pool = state.db.clone().filter(|_| !state.role.is_replica());
+    market_data::spawn_aggregator(
+        &state.tasks,
+        state.market_data.clone(),
+        &state.ws_channel,
+        candle_pool,
+    );
+    // Retention and statements run on the primary only
+    if let Some(ref pool) = state.db
+        && !state.role.is_replica()
+    {
+        retention::spawn_retention_task(&state.tasks, pool.clone(), state.retention.clone());
+        statements::spawn_daily(
+            &state.tasks,
+            pool.clone(),
+            state.symbol_configs.clone(),
+            statements::chunk_size_from_env(),
+        );
+    }
+    book_stats::spawn_refresher(
+        &state.tasks,
+        state.book_stats.clone(),
+        state.orderbooks.clone(),
+        config.book_stats_interval,
+    );
+    match (state.role, &state.db) {
+        (ServerRole::MarketDataReplica, Some(pool)) => {
+            eprintln!(
+                "replica: refreshing books every {:?}",
+                config.replica.refresh_interval
+            );
+            replica::spawn_refresher(state.clone(), pool.clone(), config.replica.clone());
+        }
+        _ => {
+            sandbox::spawn_sweeper(state.clone());
+            expiry::spawn_sweeper(state.clone());
+            delisting::spawn_finalizer(state.clone());
+            webhooks::spawn_dispatcher(state.clone());
+        }
+    }
+}
+
+/// Cancel the orders whose refresh deadline passed while the server was down and track the
+/// rest again, recording the count in the hydration report.
+async fn expire_lapsed_orders(
+    state: &mut AppState,
+    pool: &PgPool,
+    strict: bool,
+) -> Result<(), String> {
+    let mut report = HydrationReport::clone(&state.hydration_report);
+    match expiry::catch_up(state, pool, state.clock.now()).await {
+        Ok(expired) => {
+            eprintln!("expiry catch-up: {} lapsed orders expired", expired);
+            report.orders_expired = expired;
+        }
+        Err(e) => report.errors.push(format!("expire lapsed orders: {}", e)),
+    }
+    if strict && !report.is_clean() {
+        return Err(format!("strict hydration failed: {}", report));
+    }
+    state.hydration_report = Arc::new(report);
+    Ok(())
+}
+
+async fn connect(config: &Config) -> Result<PgPool, String> {
+    let url = config
+        .database_url
+        .as_deref()
+        .ok_or("PERSISTENCE_MODE=postgres needs DATABASE_URL")?;
+    let pool = persistence::pool_options(config.statement_timeout)
+        .connect(url)
+        .await
+        .map_err(|e| format!("connect to DATABASE_URL: {}", e))?;
+    let migrate = config.migrate_on_start && !config.role.is_replica();
+    let status = persistence::ensure_schema(&pool, migrate)
+        .await
+        .map_err(|e| e.to_string())?;
+    eprintln!(
+        "database schema at migration {}",
+        status.current_version.unwrap_or_default()
+    );
+    Ok(pool)
+}
+
+/// `SNAPSHOT_PATH`'s state, or empty books when it is unset or not written yet.
+fn restore_snapshot(config: &Config, symbols: &[&str]) -> Result<Hydrated, String> {
+    let snapshot = match &config.snapshot_path {
+        Some(path) => match snapshot::read_file(path) {
+            Ok(Some(snapshot)) => {
+                eprintln!(
+                    "restoring snapshot {} taken at {}",
+                    path.display(),
+                    snapshot.taken_at
+                );
+                snapshot
+            }
+            Ok(None) => {
+                eprintln!("no snapshot at {}; starting empty", path.display());
+                ExchangeSnapshot::default()
+            }
+            Err(e) => return Err(format!("cannot restore snapshot {}: {}", path.display(), e)),
+        },
+        None => ExchangeSnapshot::default(),
+    };
+    let hydrated = snapshot::restore(snapshot, symbols);
+    if config.strict_hydration && !hydrated.report.is_clean() {
+        return Err(format!("strict hydration failed: {}", hydrated.report));
+    }
+    Ok(hydrated)
+}
+
+/// Add `seeds` to the hydrated users; a username already restored keeps its stored user.
+fn seed_users(
+    hydrated: &mut Hydrated,
+    seeds: &[SeedUser],
+    now: chrono::DateTime<chrono::Utc>,
+) -> Result<(), String> {
+    for seed in seeds {
+        let key = seed.username.to_lowercase();
+        if hydrated.users.contains_key(&key) {
+            continue;
+        }
+        let password_hash = auth::hash_password(&seed.password)
+            .map_err(|e| format!("seed user '{}': {}", seed.username, e))?;
+        hydrated.users.insert(
+            key,
+            AuthUserCredential::new(seed.user_id, seed.username.clone(), password_hash, now),
+        );
+        eprintln!("seeded user {} ({})", seed.username, seed.user_id);
+    }
+    Ok(())
+}
+
+fn warn_not_durable(config: &Config) {
+    let banner = "=".repeat(72);
+    eprintln!("{}", banner);
+    match &config.snapshot_path {
+        Some(path) => eprintln!(
+            "WARNING: PERSISTENCE_MODE=memory: no database. State survives a restart only \
+             as of the last snapshot written to {}.",
+            path.display()
+        ),
+        None => eprintln!(
+            "WARNING: PERSISTENCE_MODE=memory: no database and no SNAPSHOT_PATH. Nothing is \
+             durable: every user, order, trade and position is lost on restart."
+        ),
+    }
+    if config.database_url.is_some() {
+        eprintln!("WARNING: DATABASE_URL is set but ignored in memory mode.");
+    }
+    eprintln!("{}", banner);
+}
+
+/// Public symbols the rolling ticker covers.
+fn ticker_symbols(orderbooks: &HashMap<String, SharedOrderBook>) -> Vec<String> {
+    orderbooks
+        .keys()
+        .filter(|symbol| *symbol != SELFTEST_SYMBOL && Environment::of(symbol) == Environment::Live)
+        .cloned()
+        .collect()
+}
@@ -0,0 +1,141 @@
+//! Converting amounts between assets at the books' mark prices. Every symbol with a base and a
+//! quote asset (see `types::asset`) and a mark price links the two both ways: base to quote
+//! multiplies by the price, quote to base divides by it. `ConversionGraph::path` finds the
+//! fewest hops between two assets, e.g. ETH to USDT through ETHBTC then BTCUSDT when there is
+//! no ETHUSDT book; among equally short paths the one through the alphabetically first symbols
+//! wins, so the same prices always give the same path.
+//!
+//! Amounts are in price units (1e8-scaled, see `types::money`) whatever the asset.
+
+use serde::Serialize;
+use std::collections::{BTreeMap, HashSet, VecDeque};
+
+use crate::types::asset::Asset;
+use crate::types::money::{MoneyError, Notional, PRICE_SCALE};
+use crate::types::order::Price;
+
+/// A symbol's assets and the price to convert between them at.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SymbolMark {
+    pub symbol: String,
+    pub base: Asset,
+    pub quote: Asset,
+    pub price: Price,
+}
+
+/// One conversion step through `symbol`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Hop {
+    pub symbol: String,
+    pub from: Asset,
+    pub to: Asset,
+    pub price: Price,
+    /// `from` is the symbol's quote asset: the amount is divided by `price`.
+    pub inverse: bool,
+}
+
+impl Hop {
+    /// `amount` of `from` in `to`, rounded toward zero.
+    pub fn convert(&self, amount: Notional) -> Result<Notional, MoneyError> {
+        let (numerator, denominator) = match self.inverse {
+            false => (self.price as i128, PRICE_SCALE as i128),
+            true => (PRICE_SCALE as i128, self.price as i128),
+        };
+        amount
+            .raw()
+            .checked_mul(numerator)
+            .map(|product| Notional::from_raw(product / denominator))
+            .ok_or(MoneyError::Overflow)
+    }
+}
+
+/// The hops from one asset to another; empty when they are the same asset.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ConversionPath {
+    pub from: Asset,
+    pub to: Asset,
+    pub hops: Vec<Hop>,
+}
+
+impl ConversionPath {
+    /// `amount` of `from` in `to`, rounded toward zero at every hop.
+    pub fn convert(&self, amount: i64) -> Result<i64, MoneyError> {
+        self.hops
+            .iter()
+            .try_fold(Notional::from_raw(amount as i128), |amount, hop| {
+                hop.convert(amount)
+            })?
+            .to_i64()
+    }
+
+    pub fn symbols(&self) -> Vec<&str> {
+        self.hops.iter().map(|hop| hop.symbol.as_str()).collect()
+    }
+}
+
+/// The assets linked by the symbols that have a mark price.
+#[derive(Debug, Clone, Default)]
+pub struct ConversionGraph {
+    /// Hops out of each asset, by symbol.
+    edges: BTreeMap<Asset, Vec<Hop>>,
+}
+
+impl ConversionGraph {
+    /// Links for `marks`; a symbol without a positive price or trading an asset against
+    /// itself links nothing.
+    pub fn new(marks: impl IntoIterator<Item = SymbolMark>) -> Self {
+        let mut edges: BTreeMap<Asset, Vec<Hop>> = BTreeMap::new();
+        for mark in marks {
+            if mark.price <= 0 || mark.base == mark.quote {
+                continue;
+            }
+            edges.entry(mark.base).or_default().push(Hop {
+                symbol: mark.symbol.clone(),
+                from: mark.base,
+                to: mark.quote,
+                price: mark.price,
+                inverse: false,
+            });
+            edges.entry(mark.quote).or_default().push(Hop {
+                symbol: mark.symbol,
+                from: mark.quote,
+                to: mark.base,
+                price: mark.price,
+                inverse: true,
+            });
+        }
+        for hops in edges.values_mut() {
+            hops.sort_by(|a, b| a.symbol.cmp(&b.symbol));
+        }
+        Self { edges }
+    }
+
+    /// The shortest way from `from` to `to`, or None if no chain of symbols links them.
+    pub fn path(&self, from: Asset, to: Asset) -> Option<ConversionPath> {
+        let mut reached: BTreeMap<Asset, &Hop> = BTreeMap::new();
+        let mut visited = HashSet::from([from]);
+        let mut queue = VecDeque::from([from]);
+        while let Some(asset) = queue.pop_front() {
+            if asset == to {
+                break;
+            }
+            for hop in self.edges.get(&asset).into_iter().flatten() {
+                if visited.insert(hop.to) {
+                    reached.insert(hop.to, hop);
+                    queue.push_back(hop.to);
+                }
+            }
+        }
+        if !visited.contains(&to) {
+            return None;
+        }
+        let mut hops = Vec::new();
+        let mut asset = to;
+        while let Some(hop) = reached.get(&asset) {
+            hops.push((*hop).clone());
+            asset = hop.from;
+        }
+        hops.reverse();
+        Some(ConversionPath { from, to, hops })
+    }
+}
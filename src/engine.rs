@@ -0,0 +1,437 @@
+//! The exchange as a library: `Exchange` places and cancels orders and reads books and
+//! positions through the same service path the HTTP API uses (matching, position updates,
+//! persistence, broadcasts), with typed commands and errors and no server or request in
+//! between. The order handlers are thin wrappers over it; simulators and research tools can
+//! build one with `Exchange::in_memory` or over the `AppState` of a running server.
+//!
+//! Commands take the book in arrival order like any other request (see `Ingress::admit`) and
+//! give up with `EngineError::Busy` after `AppState::request_timeout`.
+
+use axum::{
+    extract::FromRef,
+    http::StatusCode,
+    response::{IntoResponse, Json, Response},
+};
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{RwLock, broadcast};
+use uuid::Uuid;
+
+use crate::api::activity::ActivityFeed;
+use crate::api::auth::UsernameHistory;
+use crate::api::book_cache::BookCache;
+use crate::api::deadline::{DEFAULT_REQUEST_TIMEOUT, LockWaitMetrics, RequestDeadline};
+use crate::api::delisting::Delistings;
+use crate::api::exchange_info::RulesLog;
+use crate::api::expiry::{self, OrderExpiry};
+use crate::api::fanout::Fanout;
+use crate::api::idempotency::IdempotencyCache;
+use crate::api::incidents::Incidents;
+use crate::api::ingress::{Ingress, Ticket};
+use crate::api::kill_switch::KillSwitches;
+use crate::api::lazy_books::LazyBooks;
+use crate::api::phase_timer::{PhaseTimer, PhaseTimings};
+use crate::api::routes::{
+    AppState, ErrorResponse, environment_orderbooks, find_orderbook, symbol_hint,
+};
+use crate::api::service::{self, CancelledOrder, NewOrder};
+use crate::api::spreads::Spreads;
+use crate::api::surveillance::Surveillance;
+use crate::api::ws_connections::WsConnections;
+use crate::balances::Balances;
+use crate::faults::FaultPlan;
+use crate::hydration::HydrationReport;
+use crate::market_data::MarketDataStore;
+use crate::orderbook::book_stats::BookStatsGauges;
+use crate::orderbook::clock::SystemClock;
+use crate::orderbook::order_limits::OrderLimits;
+use crate::orderbook::orderbook::{BookSnapshot, ExecutionReport, OrderBook};
+use crate::persistence::TradePersistenceMetrics;
+use crate::positions;
+use crate::replica::ServerRole;
+use crate::retention::Retention;
+use crate::sandbox::Sandboxes;
+use crate::tasks::Supervisor;
+use crate::types::environment::Environment;
+use crate::types::order::{
+    OrderId, OrderSide, OrderSource, OrderTags, OrderType, Price, Qty, RejectReason,
+    validate_order_tags,
+};
+use crate::types::position::Position;
+use crate::types::symbol::{Symbol, SymbolHint};
+use crate::webhooks::Webhooks;
+
+/// An order to place: `PlaceOrder::limit` or `PlaceOrder::market`, then set the optional
+/// fields directly.
+#[derive(Debug, Clone)]
+pub struct PlaceOrder {
+    pub symbol: Symbol,
+    pub side: OrderSide,
+    pub order_type: OrderType,
+    /// Limit price; ignored for market orders.
+    pub price: Price,
+    /// Lots of the base asset.
+    pub quantity: Qty,
+    pub tags: OrderTags,
+    pub source: OrderSource,
+    /// Cancel the order unless refreshed this often (`api::expiry`); limit orders only.
+    pub refresh_ttl: Option<Duration>,
+    /// WS session the order is scoped to; it is cancelled when that session disconnects.
+    pub session_scope: Option<Uuid>,
+}
+
+impl PlaceOrder {
+    pub fn limit(symbol: Symbol, side: OrderSide, price: Price, quantity: Qty) -> Self {
+        Self {
+            symbol,
+            side,
+            order_type: OrderType::Limit,
+            price,
+            quantity,
+            tags: OrderTags::new(),
+            source: OrderSource::Api,
+            refresh_ttl: None,
+            session_scope: None,
+        }
+    }
+
+    pub fn market(symbol: Symbol, side: OrderSide, quantity: Qty) -> Self {
+        Self {
+            order_type: OrderType::Market,
+            ..Self::limit(symbol, side, 0, quantity)
+        }
+    }
+}
+
+impl From<PlaceOrder> for NewOrder {
+    fn from(order: PlaceOrder) -> Self {
+        NewOrder {
+            symbol: order.symbol,
+            price: order.price,
+            quantity: order.quantity,
+            side: order.side,
+            order_type: order.order_type,
+            tags: order.tags,
+            source: order.source,
+            session_scope: order.session_scope,
+            refresh_ttl: order.refresh_ttl,
+        }
+    }
+}
+
+/// Why a command failed. Each maps to the response the HTTP API gives for it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EngineError {
+    /// No listed book (or sandbox the user can reach) by that name.
+    UnknownSymbol { symbol: String, hint: SymbolHint },
+    /// The order was refused. With `BookFull` the fills before the rejection stand.
+    Rejected(RejectReason),
+    /// The order is not resting on any book the user can reach.
+    OrderNotFound(OrderId),
+    /// The order rests but belongs to someone else.
+    NotOwner(OrderId),
+    /// The book lock was not granted before the request deadline.
+    Busy,
+    /// The command itself is malformed (tags out of bounds, a bad TTL).
+    Invalid(String),
+    /// Anything else, as the HTTP API reports it (a quarantined symbol, a database error).
+    Other {
+        status: u16,
+        error_code: Option<String>,
+        message: String,
+    },
+}
+
+impl EngineError {
+    /// The HTTP status the error is reported with.
+    pub fn status(&self) -> StatusCode {
+        self.to_response().0
+    }
+
+    fn to_response(&self) -> (StatusCode, Json<ErrorResponse>) {
+        match self {
+            EngineError::UnknownSymbol { symbol, hint } => {
+                ErrorResponse::unknown_symbol(symbol, hint.clone())
+            }
+            EngineError::Rejected(reason) => ErrorResponse::rejected(*reason),
+            EngineError::OrderNotFound(order_id) => ErrorResponse::new(
+                format!("Order '{}' not found", order_id),
+                StatusCode::NOT_FOUND,
+            ),
+            EngineError::NotOwner(_) => ErrorResponse::new(
+                "Forbidden: order does not belong to you".to_string(),
+                StatusCode::FORBIDDEN,
+            ),
+            EngineError::Busy => ErrorResponse::engine_busy(),
+            EngineError::Invalid(message) => {
+                ErrorResponse::new(message.clone(), StatusCode::BAD_REQUEST)
+            }
+            EngineError::Other {
+                status,
+                error_code,
+                message,
+            } => {
+                let status =
+                    StatusCode::from_u16(*status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+                (
+                    status,
+                    Json(ErrorResponse {
+                        error: message.clone(),
+                        code: status.as_u16(),
+                        error_code: error_code.clone(),
+                        symbols: None,
+                    }),
+                )
+            }
+        }
+    }
+
+    /// Type an error from the service layer, raised for a command on `symbol` (and
+    /// `order_id`, for cancels). Anything that would not read back as the same response stays
+    /// `Other`, so the HTTP API answers exactly as the service did.
+    fn from_service(
+        (status, Json(body)): (StatusCode, Json<ErrorResponse>),
+        symbol: &str,
+        order_id: Option<OrderId>,
+    ) -> Self {
+        let typed = match (&body.symbols, body.error_code.as_deref(), status) {
+            (Some(hint), _, _) => Some(EngineError::UnknownSymbol {
+                symbol: symbol.to_string(),
+                hint: hint.clone(),
+            }),
+            (None, Some("ENGINE_BUSY"), _) => Some(EngineError::Busy),
+            (None, Some(code), _) => serde_json::from_value(Value::String(code.to_string()))
+                .ok()
+                .map(EngineError::Rejected),
+            (None, None, StatusCode::NOT_FOUND) => order_id.map(EngineError::OrderNotFound),
+            (None, None, StatusCode::FORBIDDEN) => order_id.map(EngineError::NotOwner),
+            (None, None, StatusCode::BAD_REQUEST) => Some(EngineError::Invalid(body.error.clone())),
+            (None, None, _) => None,
+        };
+        if let Some(typed) = typed {
+            let (typed_status, Json(typed_body)) = typed.to_response();
+            if typed_status == status
+                && typed_body.error == body.error
+                && typed_body.error_code == body.error_code
+            {
+                return typed;
+            }
+        }
+        EngineError::Other {
+            status: status.as_u16(),
+            error_code: body.error_code,
+            message: body.error,
+        }
+    }
+}
+
+impl fmt::Display for EngineError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.to_response().1.error)
+    }
+}
+
+impl std::error::Error for EngineError {}
+
+impl From<EngineError> for (StatusCode, Json<ErrorResponse>) {
+    fn from(e: EngineError) -> Self {
+        e.to_response()
+    }
+}
+
+impl IntoResponse for EngineError {
+    fn into_response(self) -> Response {
+        self.to_response().into_response()
+    }
+}
+
+/// Order entry, books and positions over one `AppState`. Cloning is cheap and clones share
+/// the exchange.
+#[derive(Clone)]
+pub struct Exchange {
+    state: AppState,
+}
+
+impl FromRef<AppState> for Exchange {
+    fn from_ref(state: &AppState) -> Self {
+        Exchange::new(state.clone())
+    }
+}
+
+impl Exchange {
+    pub fn new(state: AppState) -> Self {
+        Self { state }
+    }
+
+    /// A standalone exchange with an empty book for each of `symbols`: nothing is persisted,
+    /// balances are not tracked and the wall clock is used.
+    pub fn in_memory(symbols: &[&str]) -> Self {
+        let orderbooks = symbols
+            .iter()
+            .map(|symbol| (symbol.to_string(), Arc::new(RwLock::new(OrderBook::new()))))
+            .collect();
+        let (ws_tx, _) = broadcast::channel(1000);
+        Self::new(AppState {
+            orderbooks,
+            ws_channel: ws_tx,
+            positions: Arc::new(RwLock::new(HashMap::new())),
+            // Nothing signs tokens for an embedded exchange; a random secret keeps any served
+            // router closed
+            jwt_secret: Uuid::new_v4().as_bytes().to_vec(),
+            user_store: Arc::new(RwLock::new(HashMap::new())),
+            db: None,
+            admin_user_ids: HashSet::new(),
+            index_prices: Arc::new(RwLock::new(HashMap::new())),
+            index_price_max_age: Duration::from_secs(30),
+            idempotency: Arc::new(tokio::sync::Mutex::new(IdempotencyCache::default())),
+            order_sessions: Arc::new(RwLock::new(HashMap::new())),
+            retention: Arc::new(Retention::default()),
+            hydration_report: Arc::new(HydrationReport::default()),
+            trade_metrics: Arc::new(TradePersistenceMetrics::default()),
+            username_history: Arc::new(UsernameHistory::default()),
+            book_cache: Arc::new(BookCache::default()),
+            ws_connections: Arc::new(WsConnections::default()),
+            symbol_configs: Arc::new(RwLock::new(HashMap::new())),
+            request_timeout: DEFAULT_REQUEST_TIMEOUT,
+            lock_waits: Arc::new(LockWaitMetrics::default()),
+            phase_timings: Arc::new(PhaseTimings::default()),
+            book_stats: Arc::new(BookStatsGauges::default()),
+            spreads: Arc::new(Spreads::default()),
+            order_expiry: Arc::new(OrderExpiry::default()),
+            incidents: Arc::new(Incidents::default()),
+            market_data: Arc::new(MarketDataStore::default()),
+            order_limits: Arc::new(OrderLimits::default()),
+            sandboxes: Arc::new(Sandboxes::default()),
+            balances: Arc::new(Balances::default()),
+            paper_balances: Arc::new(Balances::default()),
+            ingress: Arc::new(Ingress::default()),
+            ws_fanout: Arc::new(Fanout::default()),
+            activity: Arc::new(ActivityFeed::default()),
+            webhooks: Arc::new(Webhooks::default()),
+            role: ServerRole::Primary,
+            faults: Arc::new(FaultPlan::default()),
+            clock: Arc::new(SystemClock),
+            delistings: Arc::new(Delistings::default()),
+            kill_switches: Arc::new(KillSwitches::default()),
+            tasks: Arc::new(Supervisor::default()),
+            rules: Arc::new(RulesLog::default()),
+            surveillance: Arc::new(Surveillance::default()),
+            lazy_books: Arc::new(LazyBooks::default()),
+        })
+    }
+
+    pub fn state(&self) -> &AppState {
+        &self.state
+    }
+
+    pub fn into_state(self) -> AppState {
+        self.state
+    }
+
+    /// Match `order` for `user_id` and apply its fills to positions, persisting and
+    /// broadcasting them. A rejected order is still persisted and its fills stand; the
+    /// rejection is the error.
+    pub async fn place_order(
+        &self,
+        user_id: Uuid,
+        order: PlaceOrder,
+    ) -> Result<ExecutionReport, EngineError> {
+        let ticket = service::admit(&self.state, &order.symbol, user_id)
+            .await
+            .map_err(|e| EngineError::from_service(e, &order.symbol, None))?;
+        let deadline = RequestDeadline::after(self.state.request_timeout);
+        self.submit(user_id, order, ticket, &deadline, &mut PhaseTimer::start())
+            .await
+    }
+
+    /// `place_order` with the caller's ticket and deadline (those of an HTTP request), timing
+    /// its phases into `timer`.
+    pub(crate) async fn submit(
+        &self,
+        user_id: Uuid,
+        order: PlaceOrder,
+        ticket: Ticket,
+        deadline: &RequestDeadline,
+        timer: &mut PhaseTimer,
+    ) -> Result<ExecutionReport, EngineError> {
+        let symbol = order.symbol.clone();
+        validate_order_tags(&order.tags).map_err(EngineError::Invalid)?;
+        let ttl_ms = order
+            .refresh_ttl
+            .map(|ttl| u64::try_from(ttl.as_millis()).unwrap_or(u64::MAX));
+        if let Some(violation) = expiry::ttl_violation(order.order_type, ttl_ms) {
+            return Err(EngineError::from_service(violation, &symbol, None));
+        }
+        service::place_order(&self.state, user_id, order.into(), ticket, deadline, timer)
+            .await
+            .map_err(|e| EngineError::from_service(e, &symbol, None))
+    }
+
+    /// Cancel `user_id`'s resting order `order_id`, on whichever of the user's books it rests.
+    pub async fn cancel(
+        &self,
+        user_id: Uuid,
+        order_id: OrderId,
+    ) -> Result<CancelledOrder, EngineError> {
+        let symbol = self
+            .order_symbol(user_id, order_id)
+            .await
+            .ok_or(EngineError::OrderNotFound(order_id))?;
+        let ticket = self.state.ingress.admit(&symbol);
+        let deadline = RequestDeadline::after(self.state.request_timeout);
+        self.submit_cancel(user_id, &symbol, order_id, ticket, &deadline)
+            .await
+    }
+
+    /// `cancel` on a known `symbol`, with the caller's ticket and deadline.
+    pub(crate) async fn submit_cancel(
+        &self,
+        user_id: Uuid,
+        symbol: &Symbol,
+        order_id: OrderId,
+        ticket: Ticket,
+        deadline: &RequestDeadline,
+    ) -> Result<CancelledOrder, EngineError> {
+        service::cancel_order(&self.state, user_id, symbol, order_id, ticket, deadline)
+            .await
+            .map_err(|e| EngineError::from_service(e, symbol, Some(order_id)))
+    }
+
+    /// Every level of the public book for `symbol`, best first.
+    pub async fn book(&self, symbol: &str) -> Result<BookSnapshot, EngineError> {
+        let orderbook = find_orderbook(&self.state, symbol, None)
+            .await
+            .ok_or_else(|| EngineError::UnknownSymbol {
+                symbol: symbol.to_string(),
+                hint: symbol_hint(&self.state, symbol),
+            })?;
+        let book = orderbook.read().await;
+        Ok(book.snapshot(usize::MAX))
+    }
+
+    /// `user_id`'s open positions, sandbox ones included.
+    pub async fn positions(&self, user_id: Uuid) -> Vec<Position> {
+        positions::get_positions(&self.state.positions, user_id, None).await
+    }
+
+    /// The symbol of the book `order_id` rests on (or was recently cancelled off), among the
+    /// public books of every environment and the user's sandboxes.
+    async fn order_symbol(&self, user_id: Uuid, order_id: OrderId) -> Option<Symbol> {
+        let mut books: Vec<(String, _)> = Environment::ALL
+            .into_iter()
+            .flat_map(|environment| environment_orderbooks(&self.state, environment))
+            .map(|(symbol, book)| (symbol.clone(), book.clone()))
+            .collect();
+        books.extend(self.state.sandboxes.books_of(user_id).await);
+        for (symbol, book) in books {
+            let book = book.read().await;
+            if book.get_order_by_id(order_id).is_some() || book.closed_order(order_id).is_some() {
+                return Symbol::parse(&symbol).ok();
+            }
+        }
+        None
+    }
+}
@@ -0,0 +1,156 @@
+//! Fault injection for tests (`fault-injection` feature). Test code programs the `FaultPlan`
+//! on `AppState` to make persistence calls fail or stall, to drop the database after a given
+//! call, or to make a WebSocket consumer see its feed lag; the failure paths then run as
+//! they would in production.
+//!
+//! Persistence calls are named as in the query latency histograms (`insert_trades_bulk`,
+//! `upsert_position`, ...) and consult the plan of the HTTP request they are made for, which
+//! the router scopes around every request. Calls made outside a request (startup, background
+//! tasks) never fault. Without the feature the plan is empty and every hook is a no-op.
+
+use std::sync::Arc;
+
+#[cfg(feature = "fault-injection")]
+use std::collections::HashMap;
+#[cfg(feature = "fault-injection")]
+use std::sync::Mutex;
+#[cfg(feature = "fault-injection")]
+use std::time::Duration;
+
+/// Faults to inject, programmed by tests.
+#[derive(Debug, Default)]
+pub struct FaultPlan {
+    #[cfg(feature = "fault-injection")]
+    rules: Mutex<Rules>,
+}
+
+pub type SharedFaultPlan = Arc<FaultPlan>;
+
+#[cfg(feature = "fault-injection")]
+#[derive(Debug, Default)]
+struct Rules {
+    /// Calls seen per persistence function since the rule for it was set.
+    calls: HashMap<&'static str, u64>,
+    /// Call number (1-based) that fails, per function.
+    fail_on: HashMap<&'static str, u64>,
+    delays: HashMap<&'static str, Duration>,
+    /// Function and call number after which the database is gone.
+    disconnect_after: Option<(&'static str, u64)>,
+    disconnected: bool,
+    /// Frames the next socket to take a delivery is told it lost.
+    ws_lag: Option<u64>,
+}
+
+#[cfg(feature = "fault-injection")]
+impl FaultPlan {
+    fn rules(&self) -> std::sync::MutexGuard<'_, Rules> {
+        self.rules.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Fail the `nth` call (1 for the next) of the persistence function `query`.
+    pub fn fail_call(&self, query: &'static str, nth: u64) {
+        let mut rules = self.rules();
+        rules.calls.remove(query);
+        rules.fail_on.insert(query, nth);
+    }
+
+    /// Hold every call of `query` for `delay` before it runs.
+    pub fn delay(&self, query: &'static str, delay: Duration) {
+        self.rules().delays.insert(query, delay);
+    }
+
+    /// Let the `nth` call of `query` through, then fail every persistence call as if the
+    /// pool had been closed, until `reconnect`.
+    pub fn disconnect_after(&self, query: &'static str, nth: u64) {
+        let mut rules = self.rules();
+        rules.calls.remove(query);
+        rules.disconnect_after = Some((query, nth));
+    }
+
+    pub fn reconnect(&self) {
+        let mut rules = self.rules();
+        rules.disconnect_after = None;
+        rules.disconnected = false;
+    }
+
+    /// Make the next socket to take a frame off its outbox see `skipped` lost frames instead.
+    pub fn lag_ws(&self, skipped: u64) {
+        self.rules().ws_lag = Some(skipped);
+    }
+
+    /// Drop every fault.
+    pub fn clear(&self) {
+        *self.rules() = Rules::default();
+    }
+
+    /// Count a call of `query`: its delay, and the error it fails with if it should.
+    fn on_query(&self, query: &'static str) -> (Option<Duration>, Option<sqlx::Error>) {
+        let mut rules = self.rules();
+        let delay = rules.delays.get(query).copied();
+        if rules.disconnected {
+            return (delay, Some(sqlx::Error::PoolClosed));
+        }
+        let calls = rules.calls.entry(query).or_default();
+        *calls += 1;
+        let calls = *calls;
+        if rules.disconnect_after == Some((query, calls)) {
+            rules.disconnected = true;
+        }
+        if rules.fail_on.get(query) == Some(&calls) {
+            rules.fail_on.remove(query);
+            let error = sqlx::Error::Protocol(format!("injected fault in {}", query));
+            return (delay, Some(error));
+        }
+        (delay, None)
+    }
+
+    pub(crate) fn take_ws_lag(&self) -> Option<u64> {
+        self.rules().ws_lag.take()
+    }
+}
+
+#[cfg(not(feature = "fault-injection"))]
+impl FaultPlan {
+    pub(crate) fn take_ws_lag(&self) -> Option<u64> {
+        None
+    }
+}
+
+#[cfg(feature = "fault-injection")]
+tokio::task_local! {
+    static CURRENT: SharedFaultPlan;
+}
+
+/// Run `future` with `plan` consulted by the persistence calls it makes.
+#[cfg(feature = "fault-injection")]
+pub async fn scope<F: std::future::Future>(plan: SharedFaultPlan, future: F) -> F::Output {
+    CURRENT.scope(plan, future).await
+}
+
+/// Router layer scoping `state.faults` around each request.
+#[cfg(feature = "fault-injection")]
+pub(crate) async fn scope_requests(
+    axum::extract::State(plan): axum::extract::State<SharedFaultPlan>,
+    request: axum::extract::Request,
+    next: axum::middleware::Next,
+) -> axum::response::Response {
+    scope(plan, next.run(request)).await
+}
+
+/// Entry point of every persistence call: wait out its delay, then fail it if the plan says
+/// so.
+#[cfg(feature = "fault-injection")]
+pub(crate) async fn before_query(query: &'static str) -> Result<(), sqlx::Error> {
+    let Ok((delay, error)) = CURRENT.try_with(|plan| plan.on_query(query)) else {
+        return Ok(());
+    };
+    if let Some(delay) = delay {
+        tokio::time::sleep(delay).await;
+    }
+    error.map_or(Ok(()), Err)
+}
+
+#[cfg(not(feature = "fault-injection"))]
+pub(crate) async fn before_query(_query: &'static str) -> Result<(), sqlx::Error> {
+    Ok(())
+}
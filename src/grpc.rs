@@ -0,0 +1,350 @@
+//! Order entry and market data over gRPC (`proto/exchange.proto`), for low-latency clients
+//! that want a binary protocol. The service adapts `engine::Exchange`, so orders go through
+//! the same service path as the HTTP API's and fail the same way; the HTTP status of an error
+//! becomes the nearest gRPC code, with any error code in the `error-code` metadata.
+//!
+//! `PlaceOrder` and `CancelOrder` need the HTTP API's JWT in `authorization: Bearer` metadata.
+//! The streams bridge the broadcast messages the WebSocket delivers: `SubscribeTrades` the
+//! symbol's trades, `SubscribeBook` its book now and after each change. Like `api::sse`,
+//! nothing missed is replayed; a book stream that falls behind gets the whole book again.
+//!
+//! The server runs on its own port (`Config::grpc_port`) in the same process.
+
+use axum::{http::StatusCode, response::Json};
+use futures_util::stream::{self, BoxStream, StreamExt};
+use std::collections::VecDeque;
+use std::future::Future;
+use tokio::net::TcpListener;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tonic::metadata::MetadataValue;
+use tonic::transport::Server;
+use tonic::transport::server::TcpIncoming;
+use tonic::{Code, Request, Response, Status};
+use uuid::Uuid;
+
+use crate::api::auth;
+use crate::api::routes::{AppState, ErrorResponse, WsMessage, find_orderbook, symbol_hint};
+use crate::api::service::CancelledOrder;
+use crate::engine::{EngineError, Exchange, PlaceOrder};
+use crate::orderbook::diff_buffer::book_checksum;
+use crate::orderbook::orderbook::{BookSnapshot, ExecutionReport, SharedOrderBook};
+use crate::types::order::{Order, OrderSide, OrderStatus, OrderType, Price, Qty};
+use crate::types::symbol::Symbol;
+use crate::types::trade::Trade;
+
+/// Messages and stubs generated from `proto/exchange.proto`.
+pub mod proto {
+    tonic::include_proto!("exchange.v1");
+}
+
+use proto::exchange_server::{Exchange as ExchangeRpc, ExchangeServer};
+
+/// The gRPC service over `state`.
+pub fn service(state: AppState) -> ExchangeServer<GrpcExchange> {
+    ExchangeServer::new(GrpcExchange {
+        exchange: Exchange::new(state),
+    })
+}
+
+/// Serve the gRPC service over `state` on `listener` until `shutdown` resolves.
+pub async fn serve(
+    state: AppState,
+    listener: TcpListener,
+    shutdown: impl Future<Output = ()>,
+) -> Result<(), tonic::transport::Error> {
+    Server::builder()
+        .add_service(service(state))
+        .serve_with_incoming_shutdown(TcpIncoming::from(listener), shutdown)
+        .await
+}
+
+pub struct GrpcExchange {
+    exchange: Exchange,
+}
+
+impl GrpcExchange {
+    fn state(&self) -> &AppState {
+        self.exchange.state()
+    }
+
+    /// The caller, from the `authorization: Bearer` metadata, checked as `AuthUser` checks the
+    /// header.
+    fn authenticate<T>(&self, request: &Request<T>) -> Result<Uuid, Status> {
+        let header = request
+            .metadata()
+            .get("authorization")
+            .and_then(|value| value.to_str().ok())
+            .ok_or_else(|| Status::unauthenticated("Missing authorization metadata"))?;
+        let token = header
+            .strip_prefix("Bearer ")
+            .ok_or_else(|| Status::unauthenticated("Invalid authorization format"))?;
+        let state = self.state();
+        let claims = auth::decode_token(&state.jwt_secret, token, state.clock.now())
+            .map_err(|_| Status::unauthenticated("Invalid or expired token"))?;
+        Uuid::parse_str(&claims.sub).map_err(|_| Status::unauthenticated("Invalid token claims"))
+    }
+
+    /// The listed book of `symbol` and its normalized name.
+    async fn orderbook(&self, symbol: &str) -> Result<(Symbol, SharedOrderBook), Status> {
+        let symbol = Symbol::parse(symbol).map_err(|e| Status::invalid_argument(e.to_string()))?;
+        let orderbook = find_orderbook(self.state(), &symbol, None)
+            .await
+            .ok_or_else(|| {
+                status(EngineError::UnknownSymbol {
+                    symbol: symbol.to_string(),
+                    hint: symbol_hint(self.state(), &symbol),
+                })
+            })?;
+        Ok((symbol, orderbook))
+    }
+}
+
+#[tonic::async_trait]
+impl ExchangeRpc for GrpcExchange {
+    async fn place_order(
+        &self,
+        request: Request<proto::PlaceOrderRequest>,
+    ) -> Result<Response<proto::ExecutionReport>, Status> {
+        let user_id = self.authenticate(&request)?;
+        let request = request.into_inner();
+        let symbol =
+            Symbol::parse(&request.symbol).map_err(|e| Status::invalid_argument(e.to_string()))?;
+        let side = match request.side() {
+            proto::Side::Buy => OrderSide::Buy,
+            proto::Side::Sell => OrderSide::Sell,
+            proto::Side::Unspecified => return Err(Status::invalid_argument("side is required")),
+        };
+        let mut order = match request.order_type() {
+            proto::OrderType::Limit => {
+                PlaceOrder::limit(symbol.clone(), side, request.price, request.quantity)
+            }
+            proto::OrderType::Market => PlaceOrder::market(symbol.clone(), side, request.quantity),
+        };
+        order.tags = request.tags;
+        let report = self
+            .exchange
+            .place_order(user_id, order)
+            .await
+            .map_err(status)?;
+        Ok(Response::new(execution_report(&symbol, report)))
+    }
+
+    async fn cancel_order(
+        &self,
+        request: Request<proto::CancelOrderRequest>,
+    ) -> Result<Response<proto::CancelledOrder>, Status> {
+        let user_id = self.authenticate(&request)?;
+        let order_id = Uuid::parse_str(&request.get_ref().order_id)
+            .map_err(|_| Status::invalid_argument("order_id is not a UUID"))?;
+        let cancelled = self
+            .exchange
+            .cancel(user_id, order_id)
+            .await
+            .map_err(status)?;
+        Ok(Response::new(cancelled_order(cancelled)))
+    }
+
+    async fn get_book(
+        &self,
+        request: Request<proto::GetBookRequest>,
+    ) -> Result<Response<proto::Book>, Status> {
+        let (symbol, orderbook) = self.orderbook(&request.get_ref().symbol).await?;
+        let snapshot = orderbook.read().await.snapshot(usize::MAX);
+        Ok(Response::new(book(&symbol, snapshot)))
+    }
+
+    type SubscribeTradesStream = BoxStream<'static, Result<proto::Trade, Status>>;
+
+    async fn subscribe_trades(
+        &self,
+        request: Request<proto::SubscribeRequest>,
+    ) -> Result<Response<Self::SubscribeTradesStream>, Status> {
+        let (symbol, _) = self.orderbook(&request.get_ref().symbol).await?;
+        let receiver = self.state().ws_channel.subscribe();
+        let trades = stream::unfold((symbol, receiver), |(symbol, mut receiver)| async move {
+            loop {
+                match receiver.recv().await {
+                    Ok(WsMessage::Trade { symbol: of, trade }) if of == symbol.as_str() => {
+                        let trade = self::trade(&symbol, trade);
+                        return Some((Ok(trade), (symbol, receiver)));
+                    }
+                    Ok(_) | Err(RecvError::Lagged(_)) => {}
+                    Err(RecvError::Closed) => return None,
+                }
+            }
+        });
+        Ok(Response::new(trades.boxed()))
+    }
+
+    type SubscribeBookStream = BoxStream<'static, Result<proto::Book, Status>>;
+
+    async fn subscribe_book(
+        &self,
+        request: Request<proto::SubscribeRequest>,
+    ) -> Result<Response<Self::SubscribeBookStream>, Status> {
+        let (symbol, orderbook) = self.orderbook(&request.get_ref().symbol).await?;
+        let mut stream = BookStream {
+            symbol,
+            orderbook,
+            receiver: self.state().ws_channel.subscribe(),
+            pending: VecDeque::new(),
+        };
+        stream.queue_snapshot().await;
+        Ok(Response::new(
+            stream::unfold(stream, BookStream::next_book).boxed(),
+        ))
+    }
+}
+
+struct BookStream {
+    symbol: Symbol,
+    orderbook: SharedOrderBook,
+    receiver: broadcast::Receiver<WsMessage>,
+    pending: VecDeque<proto::Book>,
+}
+
+impl BookStream {
+    async fn queue_snapshot(&mut self) {
+        let snapshot = self.orderbook.read().await.snapshot(usize::MAX);
+        self.pending.push_back(book(&self.symbol, snapshot));
+    }
+
+    async fn next_book(mut self) -> Option<(Result<proto::Book, Status>, Self)> {
+        loop {
+            if let Some(book) = self.pending.pop_front() {
+                return Some((Ok(book), self));
+            }
+            match self.receiver.recv().await {
+                Ok(WsMessage::OrderBookUpdate {
+                    symbol,
+                    bids,
+                    asks,
+                    diff,
+                }) if symbol == self.symbol.as_str() => match diff {
+                    Some(diff) => {
+                        let book = proto::Book {
+                            symbol,
+                            seq: diff.seq,
+                            checksum: book_checksum(&bids, &asks),
+                            bids: levels(bids),
+                            asks: levels(asks),
+                        };
+                        return Some((Ok(book), self));
+                    }
+                    // A snapshot sent outside the diff stream carries no sequence; read it
+                    None => self.queue_snapshot().await,
+                },
+                Ok(_) => {}
+                Err(RecvError::Lagged(_)) => self.queue_snapshot().await,
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    }
+}
+
+/// `e` as a gRPC status: the code nearest its HTTP status, its message, and its error code
+/// (a rejection reason, `ENGINE_BUSY`) in the `error-code` metadata.
+fn status(e: EngineError) -> Status {
+    let (http, Json(body)): (StatusCode, Json<ErrorResponse>) = e.into();
+    let code = match http {
+        StatusCode::BAD_REQUEST | StatusCode::UNPROCESSABLE_ENTITY => Code::InvalidArgument,
+        StatusCode::UNAUTHORIZED => Code::Unauthenticated,
+        StatusCode::FORBIDDEN => Code::PermissionDenied,
+        StatusCode::NOT_FOUND | StatusCode::GONE => Code::NotFound,
+        StatusCode::CONFLICT => Code::FailedPrecondition,
+        StatusCode::TOO_MANY_REQUESTS => Code::ResourceExhausted,
+        StatusCode::SERVICE_UNAVAILABLE | StatusCode::GATEWAY_TIMEOUT => Code::Unavailable,
+        _ => Code::Internal,
+    };
+    let mut status = Status::new(code, body.error);
+    if let Some(value) = body
+        .error_code
+        .and_then(|error_code| MetadataValue::try_from(error_code).ok())
+    {
+        status.metadata_mut().insert("error-code", value);
+    }
+    status
+}
+
+fn side(side: OrderSide) -> proto::Side {
+    match side {
+        OrderSide::Buy => proto::Side::Buy,
+        OrderSide::Sell => proto::Side::Sell,
+    }
+}
+
+fn order(order: Order) -> proto::Order {
+    let mut message = proto::Order {
+        id: order.id.to_string(),
+        user_id: order.user_id.to_string(),
+        price: order.price,
+        quantity: order.quantity,
+        timestamp_ms: order.timestamp.timestamp_millis(),
+        tags: order.tags,
+        ..Default::default()
+    };
+    message.set_side(side(order.side));
+    message.set_order_type(match order.order_type {
+        OrderType::Limit => proto::OrderType::Limit,
+        OrderType::Market => proto::OrderType::Market,
+    });
+    message.set_status(match order.status {
+        OrderStatus::Pending => proto::OrderStatus::Pending,
+        OrderStatus::PartiallyFilled => proto::OrderStatus::PartiallyFilled,
+        OrderStatus::Filled => proto::OrderStatus::Filled,
+        OrderStatus::Cancelled => proto::OrderStatus::Cancelled,
+    });
+    message
+}
+
+fn trade(symbol: &str, trade: Trade) -> proto::Trade {
+    proto::Trade {
+        id: trade.id.to_string(),
+        symbol: symbol.to_string(),
+        trade_seq: trade.trade_seq,
+        maker_order_id: trade.maker_order_id.to_string(),
+        taker_order_id: trade.taker_order_id.to_string(),
+        maker_user_id: trade.maker_user_id.to_string(),
+        taker_user_id: trade.taker_user_id.to_string(),
+        price: trade.price,
+        quantity: trade.quantity,
+        timestamp_ms: trade.timestamp.timestamp_millis(),
+    }
+}
+
+fn execution_report(symbol: &str, report: ExecutionReport) -> proto::ExecutionReport {
+    proto::ExecutionReport {
+        symbol: symbol.to_string(),
+        order: Some(order(report.order)),
+        trades: report
+            .trades
+            .into_iter()
+            .map(|t| trade(symbol, t))
+            .collect(),
+    }
+}
+
+fn cancelled_order(cancelled: CancelledOrder) -> proto::CancelledOrder {
+    proto::CancelledOrder {
+        order: Some(order(cancelled.order)),
+        remaining_quantity: cancelled.remaining_quantity,
+        closed_at_ms: cancelled.closed_at.timestamp_millis(),
+    }
+}
+
+fn levels(levels: Vec<(Price, Qty)>) -> Vec<proto::Level> {
+    levels
+        .into_iter()
+        .map(|(price, quantity)| proto::Level { price, quantity })
+        .collect()
+}
+
+fn book(symbol: &str, snapshot: BookSnapshot) -> proto::Book {
+    proto::Book {
+        symbol: symbol.to_string(),
+        seq: snapshot.seq,
+        bids: levels(snapshot.bids),
+        asks: levels(snapshot.asks),
+        checksum: snapshot.checksum,
+    }
+}
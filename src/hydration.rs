@@ -0,0 +1,389 @@
+//! Startup hydration: rebuild order books, positions and the user store from the database,
+//! recording what was restored, skipped and failed in a `HydrationReport`. In strict mode
+//! any error or skipped row fails hydration instead of starting with a partial state.
+//!
+//! Books listed as lazy are opened empty with their configs, and a `StoredBook` fills them in
+//! on first use (see `api::lazy_books`).
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+use uuid::Uuid;
+
+use crate::api::auth::AuthUserCredential;
+use crate::orderbook::orderbook::OrderBook;
+use crate::persistence::{self, OrderRow, PgPool};
+use crate::skew::SkewWindow;
+use crate::types::order::Order;
+use crate::types::position::Position;
+use crate::types::symbol::{SymbolConfig, SymbolStatus};
+
+/// A database row that was not restored, and why.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SkippedRow {
+    pub table: String,
+    pub id: Uuid,
+    pub reason: String,
+}
+
+/// A restored row whose timestamp was outside the sanity window (accepted or clamped).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SkewedRow {
+    pub table: String,
+    pub id: Uuid,
+    pub warning: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct HydrationReport {
+    /// Open orders restored into each symbol's book.
+    pub per_symbol_restored: BTreeMap<String, usize>,
+    pub rows_skipped: Vec<SkippedRow>,
+    /// Rows restored despite a skewed timestamp; ones the policy rejects are skipped instead.
+    pub timestamps_skewed: Vec<SkewedRow>,
+    /// Queries that failed; the affected state was left empty.
+    pub errors: Vec<String>,
+    pub positions_restored: usize,
+    pub users_restored: usize,
+    /// Symbols left out because they were delisted.
+    pub symbols_delisted: Vec<String>,
+    /// Symbols served but left to hydrate on first use.
+    pub symbols_lazy: Vec<String>,
+    /// Symbols with open orders in the database that this instance does not serve; their
+    /// orders stay stored for the instance that does.
+    pub symbols_not_served: Vec<String>,
+    /// Open orders whose refresh deadline passed while the server was down, cancelled at
+    /// startup (see `api::expiry::catch_up`).
+    pub orders_expired: usize,
+}
+
+impl HydrationReport {
+    /// True if nothing was skipped and no query failed. Skewed timestamps that were let
+    /// through do not count.
+    pub fn is_clean(&self) -> bool {
+        self.rows_skipped.is_empty() && self.errors.is_empty()
+    }
+}
+
+impl fmt::Display for HydrationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let orders: Vec<String> = self
+            .per_symbol_restored
+            .iter()
+            .map(|(symbol, n)| format!("{}={}", symbol, n))
+            .collect();
+        write!(
+            f,
+            "orders restored [{}], {} positions, {} users, {} rows skipped, {} timestamps skewed, \
+             {} orders expired, {} errors",
+            orders.join(", "),
+            self.positions_restored,
+            self.users_restored,
+            self.rows_skipped.len(),
+            self.timestamps_skewed.len(),
+            self.orders_expired,
+            self.errors.len()
+        )?;
+        for row in &self.rows_skipped {
+            write!(f, "\n  skipped {} {}: {}", row.table, row.id, row.reason)?;
+        }
+        for row in &self.timestamps_skewed {
+            write!(f, "\n  skewed {} {}: {}", row.table, row.id, row.warning)?;
+        }
+        for symbol in &self.symbols_delisted {
+            write!(f, "\n  delisted {}: not restored", symbol)?;
+        }
+        for symbol in &self.symbols_lazy {
+            write!(f, "\n  lazy {}: restored on first use", symbol)?;
+        }
+        for symbol in &self.symbols_not_served {
+            write!(f, "\n  not served {}: open orders left stored", symbol)?;
+        }
+        for error in &self.errors {
+            write!(f, "\n  error: {}", error)?;
+        }
+        Ok(())
+    }
+}
+
+/// State rebuilt from the database, keyed as in `AppState`.
+pub struct Hydrated {
+    pub orderbooks: HashMap<String, OrderBook>,
+    pub positions: HashMap<(Uuid, String), Position>,
+    pub users: HashMap<String, AuthUserCredential>,
+    /// Users with the account kill switch engaged.
+    pub trading_disabled: HashSet<Uuid>,
+    /// Stored config per hydrated symbol; defaults for symbols without a row.
+    pub symbol_configs: HashMap<String, SymbolConfig>,
+    pub report: HydrationReport,
+}
+
+/// Restore open order rows into `book` in one bulk pass, recording skipped rows and creation
+/// times outside `skew` around `now`. Returns the number restored; an inconsistent resulting
+/// book is recorded as an error.
+pub fn restore_orders(
+    book: &mut OrderBook,
+    rows: &[OrderRow],
+    skew: &SkewWindow,
+    now: DateTime<Utc>,
+    report: &mut HydrationReport,
+) -> usize {
+    let orders = orders_from_rows(rows, skew, now, report);
+    apply_orders(book, orders, report)
+}
+
+/// The orders of `rows`, recording the skipped ones and creation times outside `skew`.
+fn orders_from_rows(
+    rows: &[OrderRow],
+    skew: &SkewWindow,
+    now: DateTime<Utc>,
+    report: &mut HydrationReport,
+) -> Vec<Order> {
+    let mut orders = Vec::with_capacity(rows.len());
+    for row in rows {
+        match persistence::order_row_to_order(row, skew, now) {
+            Ok((order, warning)) => {
+                if let Some(warning) = warning {
+                    report.timestamps_skewed.push(SkewedRow {
+                        table: "orders".to_string(),
+                        id: row.id,
+                        warning,
+                    });
+                }
+                orders.push(order);
+            }
+            Err(reason) => report.rows_skipped.push(SkippedRow {
+                table: "orders".to_string(),
+                id: row.id,
+                reason,
+            }),
+        }
+    }
+    orders
+}
+
+fn apply_orders(book: &mut OrderBook, orders: Vec<Order>, report: &mut HydrationReport) -> usize {
+    let count = orders.len();
+    match book.restore_orders(orders) {
+        Ok(restored) => restored,
+        Err(e) => {
+            report
+                .errors
+                .push(format!("restored book is inconsistent: {}", e));
+            count
+        }
+    }
+}
+
+/// Leave out the books and configs of symbols whose config says they were delisted.
+pub fn drop_delisted(
+    orderbooks: &mut HashMap<String, OrderBook>,
+    symbol_configs: &mut HashMap<String, SymbolConfig>,
+    report: &mut HydrationReport,
+) {
+    let mut delisted: Vec<String> = symbol_configs
+        .iter()
+        .filter(|(_, config)| config.status == SymbolStatus::Delisted)
+        .map(|(symbol, _)| symbol.clone())
+        .collect();
+    delisted.sort();
+    for symbol in &delisted {
+        orderbooks.remove(symbol);
+        symbol_configs.remove(symbol);
+        report.per_symbol_restored.remove(symbol);
+    }
+    report.symbols_delisted = delisted;
+}
+
+/// Load users, open orders (and trade sequence) for `symbols`, and positions. With `strict`,
+/// returns the report as the error if anything was skipped or failed.
+pub async fn hydrate(
+    pool: &PgPool,
+    symbols: &[&str],
+    strict: bool,
+) -> Result<Hydrated, HydrationReport> {
+    hydrate_with(pool, symbols, strict, &SkewWindow::default(), Utc::now()).await
+}
+
+/// `hydrate`, checking order creation times against `skew` around `now`.
+pub async fn hydrate_with(
+    pool: &PgPool,
+    symbols: &[&str],
+    strict: bool,
+    skew: &SkewWindow,
+    now: DateTime<Utc>,
+) -> Result<Hydrated, HydrationReport> {
+    hydrate_served(pool, symbols, &[], strict, skew, now).await
+}
+
+/// A book's open orders and last trade sequence as read from the database, not yet restored.
+pub struct StoredBook {
+    symbol: String,
+    orders: Option<Vec<Order>>,
+    last_trade_seq: Option<u64>,
+}
+
+impl StoredBook {
+    /// Read `symbol`'s book, recording skipped rows and failed queries in `report`.
+    pub async fn read(
+        pool: &PgPool,
+        symbol: &str,
+        skew: &SkewWindow,
+        now: DateTime<Utc>,
+        report: &mut HydrationReport,
+    ) -> Self {
+        let orders = match persistence::list_open_orders_by_symbol(pool, symbol).await {
+            Ok(rows) => Some(orders_from_rows(&rows, skew, now, report)),
+            Err(e) => {
+                report
+                    .errors
+                    .push(format!("load open orders for {}: {}", symbol, e));
+                None
+            }
+        };
+        let last_trade_seq = match persistence::max_trade_seq(pool, symbol).await {
+            Ok(seq) => Some(seq),
+            Err(e) => {
+                report
+                    .errors
+                    .push(format!("load last trade sequence for {}: {}", symbol, e));
+                None
+            }
+        };
+        Self {
+            symbol: symbol.to_string(),
+            orders,
+            last_trade_seq,
+        }
+    }
+
+    /// Restore what was read into `book`.
+    pub fn restore(self, book: &mut OrderBook, report: &mut HydrationReport) {
+        if let Some(orders) = self.orders {
+            let restored = apply_orders(book, orders, report);
+            report.per_symbol_restored.insert(self.symbol, restored);
+        }
+        if let Some(seq) = self.last_trade_seq {
+            book.set_last_trade_seq(seq);
+        }
+    }
+}
+
+/// `hydrate_with` for the books of `symbols` and the empty books of `lazy` ones, whose orders
+/// are restored later. Symbols with open orders that are in neither are reported.
+pub async fn hydrate_served(
+    pool: &PgPool,
+    symbols: &[&str],
+    lazy: &[&str],
+    strict: bool,
+    skew: &SkewWindow,
+    now: DateTime<Utc>,
+) -> Result<Hydrated, HydrationReport> {
+    let mut report = HydrationReport::default();
+
+    let mut users = HashMap::new();
+    let mut trading_disabled = HashSet::new();
+    match persistence::list_users(pool).await {
+        Ok(rows) => {
+            for r in rows {
+                if r.trading_disabled {
+                    trading_disabled.insert(r.id);
+                }
+                users.insert(
+                    r.username.clone(),
+                    AuthUserCredential {
+                        user_id: r.id,
+                        username: r.username,
+                        password_hash: r.password_hash,
+                        created_at: r.created_at,
+                        last_login_at: r.last_login_at,
+                        login_count: r.login_count.max(0) as u64,
+                    },
+                );
+            }
+        }
+        Err(e) => report.errors.push(format!("load users: {}", e)),
+    }
+    report.users_restored = users.len();
+
+    let mut orderbooks = HashMap::new();
+    for symbol in symbols {
+        let mut book = OrderBook::new();
+        StoredBook::read(pool, symbol, skew, now, &mut report)
+            .await
+            .restore(&mut book, &mut report);
+        orderbooks.insert((*symbol).to_string(), book);
+    }
+    for symbol in lazy {
+        orderbooks.insert((*symbol).to_string(), OrderBook::new());
+    }
+    match persistence::list_open_order_symbols(pool).await {
+        Ok(stored) => {
+            report.symbols_not_served = stored
+                .into_iter()
+                .filter(|symbol| !orderbooks.contains_key(symbol))
+                .collect();
+        }
+        Err(e) => report
+            .errors
+            .push(format!("load symbols with open orders: {}", e)),
+    }
+
+    let mut positions = HashMap::new();
+    match persistence::list_positions(pool).await {
+        Ok(rows) => {
+            for r in rows {
+                positions.insert((r.user_id, r.symbol.clone()), r.to_position());
+            }
+        }
+        Err(e) => report.errors.push(format!("load positions: {}", e)),
+    }
+    report.positions_restored = positions.len();
+
+    let mut symbol_configs: HashMap<String, SymbolConfig> = symbols
+        .iter()
+        .chain(lazy)
+        .map(|symbol| (symbol.to_string(), SymbolConfig::for_symbol(symbol)))
+        .collect();
+    match persistence::list_symbol_configs(pool).await {
+        Ok(rows) => {
+            for row in rows {
+                let Some(config) = symbol_configs.get_mut(&row.symbol) else {
+                    continue;
+                };
+                match persistence::symbol_config_row_to_config(&row) {
+                    Ok(stored) => *config = stored,
+                    Err(e) => report
+                        .errors
+                        .push(format!("invalid config for {}: {}", row.symbol, e)),
+                }
+            }
+        }
+        Err(e) => report.errors.push(format!("load symbol configs: {}", e)),
+    }
+    drop_delisted(&mut orderbooks, &mut symbol_configs, &mut report);
+    report.symbols_lazy = lazy
+        .iter()
+        .filter(|symbol| orderbooks.contains_key(**symbol))
+        .map(|symbol| symbol.to_string())
+        .collect();
+    report.symbols_lazy.sort();
+    for (symbol, book) in orderbooks.iter_mut() {
+        if let Some(config) = symbol_configs.get(symbol) {
+            book.set_pricing_policy(config.pricing_policy, config.tick_size);
+        }
+    }
+
+    if strict && !report.is_clean() {
+        return Err(report);
+    }
+    Ok(Hydrated {
+        orderbooks,
+        positions,
+        users,
+        trading_disabled,
+        symbol_configs,
+        report,
+    })
+}
@@ -1,5 +1,28 @@
 pub mod api;
+pub mod balances;
+pub mod bootstrap;
+pub mod conversion;
+pub mod engine;
+pub mod faults;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+pub mod hydration;
+pub mod market_data;
 pub mod orderbook;
 pub mod persistence;
 pub mod positions;
+pub mod pricefeed;
+pub mod reconcile;
+pub mod replay;
+pub mod replica;
+pub mod retention;
+pub mod sandbox;
+pub mod selftest;
+pub mod skew;
+pub mod snapshot;
+pub mod statements;
+pub mod tasks;
+#[cfg(feature = "test-util")]
+pub mod testing;
 pub mod types;
+pub mod webhooks;
@@ -1,85 +1,153 @@
-use rust_exchange::api::auth::AuthUserCredential;
-use rust_exchange::api::routes::{AppState, UserStore, app_router};
-use rust_exchange::orderbook::orderbook::{OrderBook, SharedOrderBook};
-use rust_exchange::persistence::{self, PgPool};
-use rust_exchange::positions::SharedPositions;
-use std::collections::HashMap;
+use futures_util::future;
+use rust_exchange::api::routes::{AppState, app_router};
+use rust_exchange::bootstrap::{self, Config};
+#[cfg(feature = "grpc")]
+use rust_exchange::grpc;
+use rust_exchange::persistence;
+use rust_exchange::selftest;
+use rust_exchange::snapshot;
+use rust_exchange::tasks::{RestartPolicy, ShutdownStage, TaskHandle};
 use std::env;
-use std::sync::Arc;
-use tokio::sync::{RwLock, broadcast};
+use std::future::IntoFuture;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
 
 #[tokio::main]
 async fn main() {
     dotenvy::dotenv().ok();
-    let database_url = env::var("DATABASE_URL").expect("DATABASE_URL must be set");
-    let pool: PgPool = persistence::create_pool_and_migrate(&database_url)
+    let config = Config::from_env().unwrap_or_else(|e| panic!("{}", e));
+    eprintln!(
+        "server role: {}, persistence: {}",
+        config.role, config.persistence
+    );
+    if let Some(threshold) = env::var("SLOW_QUERY_THRESHOLD_MS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+    {
+        persistence::set_slow_query_threshold(Duration::from_millis(threshold));
+    }
+    let app_state = bootstrap::build_state(&config)
         .await
-        .expect("create pool and run migrations");
+        .unwrap_or_else(|e| panic!("{}", e));
+    bootstrap::spawn_background(&app_state, &config);
 
-    let user_rows = persistence::list_users(&pool).await.expect("load users from DB");
-    let user_store: UserStore = Arc::new(RwLock::new(
-        user_rows
-            .into_iter()
-            .map(|r| {
-                (
-                    r.username.clone(),
-                    AuthUserCredential {
-                        user_id: r.id,
-                        username: r.username,
-                        password_hash: r.password_hash,
-                    },
-                )
-            })
-            .collect(),
-    ));
+    // `--self-test`: run the self-test against the hydrated state, print the report and exit
+    if env::args().any(|arg| arg == "--self-test") {
+        let report = selftest::run(&app_state).await;
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&report).unwrap_or_default()
+        );
+        std::process::exit(if report.passed { 0 } else { 1 });
+    }
 
-    let mut orderbooks: HashMap<String, SharedOrderBook> = HashMap::new();
-    for symbol in &["BTCUSDT", "ETHUSDT"] {
-        let mut book = OrderBook::new();
-        if let Ok(rows) = persistence::list_open_orders_by_symbol(&pool, symbol).await {
-            for row in &rows {
-                if let Some(order) = persistence::order_row_to_order(row) {
-                    book.restore_order(order);
-                }
-            }
-        }
-        orderbooks.insert((*symbol).to_string(), Arc::new(RwLock::new(book)));
+    if let Some(snapshot_path) = config
+        .snapshot_path
+        .clone()
+        .filter(|_| !config.role.is_replica())
+    {
+        let interval = Duration::from_secs(
+            env::var("SNAPSHOT_INTERVAL_SECS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .filter(|&secs| secs > 0)
+                .unwrap_or(60),
+        );
+        // Writes a last snapshot when it is stopped, after every other task
+        snapshot::spawn_writer(app_state.clone(), snapshot_path, interval);
+    }
+    let server = spawn_server(app_state.clone());
+    #[cfg(feature = "grpc")]
+    let grpc_server = config
+        .grpc_port
+        .map(|port| spawn_grpc_server(app_state.clone(), port));
+    #[cfg(not(feature = "grpc"))]
+    let grpc_server: Option<TaskHandle> = None;
+
+    // Stop on SIGINT/SIGTERM (or if a server fails) without waiting for open connections:
+    // the listeners first, then the workers, then the writers
+    let failed = tokio::select! {
+        _ = future::select_all(
+            std::iter::once(&server)
+                .chain(&grpc_server)
+                .map(|server| Box::pin(server.stopped()))
+        ) => true,
+        _ = shutdown_requested() => false,
+    };
+    let report = app_state.tasks.shutdown(config.shutdown_timeout).await;
+    eprintln!(
+        "shutdown: {} task(s) stopped, {} aborted",
+        report.stopped.len(),
+        report.timed_out.len()
+    );
+    if failed {
+        std::process::exit(1);
     }
+}
 
-    let (ws_tx, _) = broadcast::channel::<rust_exchange::api::routes::WsMessage>(1000);
-    let positions: SharedPositions = Arc::new(RwLock::new({
-        let mut map = HashMap::new();
-        if let Ok(rows) = persistence::list_positions(&pool).await {
-            use rust_exchange::types::position::Position;
-            for r in rows {
-                map.insert(
-                    (r.user_id, r.symbol.clone()),
-                    Position {
-                        user_id: r.user_id,
-                        symbol: r.symbol,
-                        quantity: r.quantity,
-                        average_price: r.average_price,
-                    },
+/// Serve the API until the supervisor stops it; it is not restarted.
+fn spawn_server(app_state: AppState) -> TaskHandle {
+    let tasks = app_state.tasks.clone();
+    let app = app_router(app_state);
+    tasks.spawn(
+        "http",
+        ShutdownStage::Ingress,
+        RestartPolicy::Never,
+        move |token| {
+            let app = app.clone();
+            async move {
+                let listener = tokio::net::TcpListener::bind("0.0.0.0:3000").await.unwrap();
+                let server = axum::serve(
+                    listener,
+                    app.into_make_service_with_connect_info::<SocketAddr>(),
                 );
+                if let Some(result) = token.run_until_cancelled(server.into_future()).await {
+                    result.unwrap();
+                }
             }
-        }
-        map
-    }));
+        },
+    )
+}
 
-    let jwt_secret = env::var("JWT_SECRET")
-        .unwrap_or_else(|_| "dev-secret-change-in-production".to_string())
-        .into_bytes();
+/// Serve the gRPC service on `port` until the supervisor stops it; it is not restarted.
+#[cfg(feature = "grpc")]
+fn spawn_grpc_server(app_state: AppState, port: u16) -> TaskHandle {
+    let tasks = app_state.tasks.clone();
+    tasks.spawn(
+        "grpc",
+        ShutdownStage::Ingress,
+        RestartPolicy::Never,
+        move |token| {
+            let app_state = app_state.clone();
+            async move {
+                let listener = tokio::net::TcpListener::bind(("0.0.0.0", port))
+                    .await
+                    .unwrap();
+                grpc::serve(app_state, listener, token.cancelled_owned())
+                    .await
+                    .unwrap();
+            }
+        },
+    )
+}
 
-    let app_state = AppState {
-        orderbooks,
-        ws_channel: ws_tx,
-        positions,
-        jwt_secret,
-        user_store,
-        db: Some(pool),
-    };
+static SHUTDOWN_REQUESTED: AtomicBool = AtomicBool::new(false);
 
-    let app = app_router(app_state);
-    let listener = tokio::net::TcpListener::bind("0.0.0.0:3000").await.unwrap();
-    axum::serve(listener, app).await.unwrap();
+extern "C" fn request_shutdown(_signal: libc::c_int) {
+    SHUTDOWN_REQUESTED.store(true, Ordering::SeqCst);
+}
+
+/// Resolves once SIGINT or SIGTERM arrives. The handler only sets a flag (all a signal
+/// handler may safely do), which is polled here.
+async fn shutdown_requested() {
+    let handler = request_shutdown as extern "C" fn(libc::c_int) as libc::sighandler_t;
+    // SAFETY: `request_shutdown` is async-signal-safe: it only stores to an atomic
+    unsafe {
+        libc::signal(libc::SIGINT, handler);
+        libc::signal(libc::SIGTERM, handler);
+    }
+    while !SHUTDOWN_REQUESTED.load(Ordering::SeqCst) {
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
 }
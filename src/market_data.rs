@@ -0,0 +1,548 @@
+//! Recent market data history for charting clients that join late: one-minute candles and a
+//! ticker point per trade, kept in memory per symbol for `MarketDataStore::history` (an hour
+//! by default) and served by `GET /klines/recent` and `GET /ticker/history`.
+//!
+//! The candle aggregator task is the only writer of new trades. It folds the `Trade` messages
+//! every book already broadcasts, so it sees exactly what WebSocket clients see, and stores
+//! each candle it closes in the `candles` table when a database is configured. Busting a trade
+//! takes it back out (`MarketDataStore::remove_trade`). Entries older than the
+//! history window (measured from the newest trade) are pruned as new trades arrive. Trades on
+//! the self-test symbol are ignored.
+//!
+//! The same trades also feed each symbol's rolling 24-hour statistics (`GET /ticker`), kept as
+//! one bucket per candle interval so the window can roll forward. With a database the buckets
+//! are seeded from the `trades` table at startup, so a restart does not zero the ticker, and
+//! re-read periodically (`spawn_rolling_refresher`) to correct drift: a busted trade, for
+//! instance, only has its volume taken out of memory, its bucket's high and low stay until the
+//! next refresh.
+
+use chrono::{DateTime, Duration as ChronoDuration, DurationRound, Utc};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio_util::sync::CancellationToken;
+
+use crate::api::routes::WsMessage;
+use crate::orderbook::clock::SharedClock;
+use crate::persistence::{self, PgPool};
+use crate::sandbox::is_sandbox_symbol;
+use crate::selftest::SELFTEST_SYMBOL;
+use crate::tasks::{RestartPolicy, ShutdownStage, Supervisor, TaskHandle};
+use crate::types::order::{Price, Qty, total_quantity};
+use crate::types::trade::Trade;
+
+pub const CANDLE_INTERVAL_SECS: i64 = 60;
+
+/// How far back history is kept when `MARKET_DATA_HISTORY_MINUTES` is not configured.
+pub const DEFAULT_HISTORY: Duration = Duration::from_secs(60 * 60);
+
+/// Ticker points kept per symbol regardless of age, so a burst cannot grow memory unbounded.
+const MAX_TICKER_POINTS: usize = 10_000;
+
+/// Span of the rolling ticker statistics.
+pub const ROLLING_WINDOW: ChronoDuration = ChronoDuration::hours(24);
+
+/// How often the rolling statistics are re-read from the database when
+/// `TICKER_REFRESH_SECS` is not configured.
+pub const DEFAULT_ROLLING_REFRESH: Duration = Duration::from_secs(5 * 60);
+
+/// A refresh leaves the buckets of the last minute or so alone: their trades may not have
+/// reached both the database and the aggregator yet.
+const ROLLING_SETTLE: ChronoDuration = ChronoDuration::seconds(CANDLE_INTERVAL_SECS);
+
+pub type SharedMarketData = Arc<MarketDataStore>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Candle {
+    pub open_time: DateTime<Utc>,
+    #[serde(with = "crate::types::string_i64")]
+    pub open: Price,
+    #[serde(with = "crate::types::string_i64")]
+    pub high: Price,
+    #[serde(with = "crate::types::string_i64")]
+    pub low: Price,
+    #[serde(with = "crate::types::string_i64")]
+    pub close: Price,
+    #[serde(with = "crate::types::string_i64")]
+    pub volume: Qty,
+    pub trade_count: u64,
+    /// False for the candle still being built.
+    pub closed: bool,
+}
+
+impl Candle {
+    fn open(open_time: DateTime<Utc>, trade: &Trade) -> Self {
+        Self {
+            open_time,
+            open: trade.price,
+            high: trade.price,
+            low: trade.price,
+            close: trade.price,
+            volume: trade.quantity,
+            trade_count: 1,
+            closed: false,
+        }
+    }
+
+    /// Rebuild from `points`, the ticker points of this candle's interval, oldest first.
+    fn from_points(open_time: DateTime<Utc>, points: &[TickerPoint], closed: bool) -> Self {
+        let prices = points.iter().map(|p| p.price);
+        Self {
+            open_time,
+            open: points[0].price,
+            high: prices.clone().max().unwrap_or(points[0].price),
+            low: prices.min().unwrap_or(points[0].price),
+            close: points[points.len() - 1].price,
+            volume: total_quantity(points.iter().map(|p| p.quantity)),
+            trade_count: points.len() as u64,
+            closed,
+        }
+    }
+
+    fn add(&mut self, trade: &Trade) {
+        self.high = self.high.max(trade.price);
+        self.low = self.low.min(trade.price);
+        self.close = trade.price;
+        self.volume = self.volume.saturating_add(trade.quantity);
+        self.trade_count += 1;
+    }
+}
+
+/// How taking a trade back out changed a closed candle, for the copy in the database.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CandleCorrection {
+    Updated(Candle),
+    /// It was the candle's only trade.
+    Removed {
+        open_time: DateTime<Utc>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct TickerPoint {
+    pub timestamp: DateTime<Utc>,
+    #[serde(with = "crate::types::string_i64")]
+    pub price: Price,
+    #[serde(with = "crate::types::string_i64")]
+    pub quantity: Qty,
+}
+
+/// The trades of one candle interval, for the rolling statistics.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StatsBucket {
+    pub open_time: DateTime<Utc>,
+    pub high: Price,
+    pub low: Price,
+    pub volume: Qty,
+    pub trade_count: u64,
+}
+
+impl StatsBucket {
+    fn add(&mut self, trade: &Trade) {
+        self.high = self.high.max(trade.price);
+        self.low = self.low.min(trade.price);
+        self.volume = self.volume.saturating_add(trade.quantity);
+        self.trade_count += 1;
+    }
+}
+
+/// A symbol's trading over the rolling window, to the candle interval.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct RollingStats {
+    /// Open time of the oldest interval counted.
+    pub window_start: DateTime<Utc>,
+    /// None without trades in the window.
+    #[serde(with = "crate::types::string_i64::option")]
+    pub high: Option<Price>,
+    #[serde(with = "crate::types::string_i64::option")]
+    pub low: Option<Price>,
+    #[serde(with = "crate::types::string_i64")]
+    pub volume: Qty,
+    pub trade_count: u64,
+}
+
+#[derive(Debug, Default)]
+struct SymbolHistory {
+    /// Closed candles, oldest first.
+    candles: VecDeque<Candle>,
+    current: Option<Candle>,
+    ticker: VecDeque<TickerPoint>,
+    /// Rolling statistics by interval open time, over `ROLLING_WINDOW`.
+    rolling: BTreeMap<DateTime<Utc>, StatsBucket>,
+}
+
+impl SymbolHistory {
+    fn prune(&mut self, cutoff: DateTime<Utc>) {
+        while self.candles.front().is_some_and(|c| c.open_time < cutoff) {
+            self.candles.pop_front();
+        }
+        while self.ticker.front().is_some_and(|p| p.timestamp < cutoff)
+            || self.ticker.len() > MAX_TICKER_POINTS
+        {
+            self.ticker.pop_front();
+        }
+    }
+
+    fn prune_rolling(&mut self, now: DateTime<Utc>) {
+        let start = rolling_window_start(now);
+        self.rolling = self.rolling.split_off(&start);
+    }
+}
+
+/// Open time of the oldest candle interval in the rolling window ending at `now`.
+pub fn rolling_window_start(now: DateTime<Utc>) -> DateTime<Utc> {
+    candle_open_time(now - ROLLING_WINDOW)
+}
+
+/// Start of the candle `timestamp` falls in.
+pub fn candle_open_time(timestamp: DateTime<Utc>) -> DateTime<Utc> {
+    timestamp
+        .duration_trunc(ChronoDuration::seconds(CANDLE_INTERVAL_SECS))
+        .unwrap_or(timestamp)
+}
+
+#[derive(Debug)]
+pub struct MarketDataStore {
+    history: Duration,
+    symbols: Mutex<HashMap<String, SymbolHistory>>,
+}
+
+impl Default for MarketDataStore {
+    fn default() -> Self {
+        Self::new(DEFAULT_HISTORY)
+    }
+}
+
+impl MarketDataStore {
+    pub fn new(history: Duration) -> Self {
+        Self {
+            history,
+            symbols: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn history(&self) -> Duration {
+        self.history
+    }
+
+    fn cutoff(&self, now: DateTime<Utc>) -> DateTime<Utc> {
+        now - ChronoDuration::from_std(self.history).unwrap_or(ChronoDuration::MAX)
+    }
+
+    /// Fold one trade in; returns the candle it closed, if it was the first of a new minute.
+    /// Trades older than the current candle (out of order) only add a ticker point, in time
+    /// order, and count in their interval's rolling bucket.
+    pub fn record_trade(&self, symbol: &str, trade: &Trade) -> Option<Candle> {
+        let open_time = candle_open_time(trade.timestamp);
+        let mut symbols = self.symbols.lock().unwrap_or_else(|e| e.into_inner());
+        let history = symbols.entry(symbol.to_string()).or_default();
+        // Nearly always at the back; skewed timestamps go in behind newer points
+        let at = history
+            .ticker
+            .iter()
+            .rposition(|p| p.timestamp <= trade.timestamp)
+            .map_or(0, |i| i + 1);
+        history.ticker.insert(
+            at,
+            TickerPoint {
+                timestamp: trade.timestamp,
+                price: trade.price,
+                quantity: trade.quantity,
+            },
+        );
+        history
+            .rolling
+            .entry(open_time)
+            .and_modify(|bucket| bucket.add(trade))
+            .or_insert(StatsBucket {
+                open_time,
+                high: trade.price,
+                low: trade.price,
+                volume: trade.quantity,
+                trade_count: 1,
+            });
+        // Measured from the newest interval, so a late trade from before the window goes at once
+        let newest = history
+            .rolling
+            .keys()
+            .next_back()
+            .copied()
+            .unwrap_or(open_time);
+        history.prune_rolling(newest);
+
+        let mut closed = None;
+        match history.current.as_mut() {
+            Some(current) if current.open_time == open_time => current.add(trade),
+            Some(current) if current.open_time > open_time => {}
+            _ => {
+                if let Some(mut previous) = history.current.replace(Candle::open(open_time, trade))
+                {
+                    previous.closed = true;
+                    history.candles.push_back(previous);
+                    closed = Some(previous);
+                }
+            }
+        }
+        let newest = history
+            .ticker
+            .back()
+            .map_or(trade.timestamp, |p| p.timestamp);
+        history.prune(self.cutoff(newest));
+        closed
+    }
+
+    /// Take a busted trade back out: drop its ticker point and rebuild its candle from the
+    /// ticker points left in that interval (or, if some have already been pruned, just take
+    /// its volume out). Returns the correction when the candle was already closed.
+    pub fn remove_trade(&self, symbol: &str, trade: &Trade) -> Option<CandleCorrection> {
+        let open_time = candle_open_time(trade.timestamp);
+        let mut symbols = self.symbols.lock().unwrap_or_else(|e| e.into_inner());
+        let history = symbols.get_mut(symbol)?;
+        if let Some(bucket) = history.rolling.get_mut(&open_time) {
+            bucket.volume = bucket.volume.saturating_sub(trade.quantity);
+            bucket.trade_count = bucket.trade_count.saturating_sub(1);
+            if bucket.trade_count == 0 {
+                history.rolling.remove(&open_time);
+            }
+        }
+        if let Some(i) = history.ticker.iter().position(|p| {
+            (p.timestamp, p.price, p.quantity) == (trade.timestamp, trade.price, trade.quantity)
+        }) {
+            history.ticker.remove(i);
+        }
+        let points: Vec<TickerPoint> = history
+            .ticker
+            .iter()
+            .filter(|p| candle_open_time(p.timestamp) == open_time)
+            .copied()
+            .collect();
+
+        let in_current = history.current.is_some_and(|c| c.open_time == open_time);
+        let candle = if in_current {
+            history.current.as_mut()?
+        } else {
+            history
+                .candles
+                .iter_mut()
+                .find(|c| c.open_time == open_time)?
+        };
+        let remaining = candle.trade_count.saturating_sub(1);
+        if remaining == 0 {
+            if in_current {
+                history.current = None;
+                return None;
+            }
+            history.candles.retain(|c| c.open_time != open_time);
+            return Some(CandleCorrection::Removed { open_time });
+        }
+        if points.len() as u64 == remaining {
+            *candle = Candle::from_points(open_time, &points, candle.closed);
+        } else {
+            candle.volume = candle.volume.saturating_sub(trade.quantity);
+            candle.trade_count = remaining;
+        }
+        (!in_current).then_some(CandleCorrection::Updated(*candle))
+    }
+
+    /// Seed closed candles (e.g. loaded from the database at startup), oldest first.
+    pub fn load_candles(&self, symbol: &str, candles: Vec<Candle>) {
+        let mut symbols = self.symbols.lock().unwrap_or_else(|e| e.into_inner());
+        let history = symbols.entry(symbol.to_string()).or_default();
+        history.candles = candles
+            .into_iter()
+            .map(|candle| Candle {
+                closed: true,
+                ..candle
+            })
+            .collect();
+    }
+
+    /// Replace `symbol`'s rolling buckets opened before `settled` with `buckets` (read from
+    /// the database), keeping newer ones. None replaces every bucket, as at startup.
+    pub fn load_rolling(
+        &self,
+        symbol: &str,
+        buckets: Vec<StatsBucket>,
+        settled: Option<DateTime<Utc>>,
+    ) {
+        let mut symbols = self.symbols.lock().unwrap_or_else(|e| e.into_inner());
+        let history = symbols.entry(symbol.to_string()).or_default();
+        let newer = match settled {
+            Some(settled) => history.rolling.split_off(&settled),
+            None => BTreeMap::new(),
+        };
+        history.rolling = buckets
+            .into_iter()
+            .filter(|bucket| settled.is_none_or(|settled| bucket.open_time < settled))
+            .map(|bucket| (bucket.open_time, bucket))
+            .collect();
+        history.rolling.extend(newer);
+    }
+
+    /// `symbol`'s statistics over the rolling window ending at `now`.
+    pub fn rolling_stats(&self, symbol: &str, now: DateTime<Utc>) -> RollingStats {
+        let window_start = rolling_window_start(now);
+        let mut stats = RollingStats {
+            window_start,
+            high: None,
+            low: None,
+            volume: 0,
+            trade_count: 0,
+        };
+        let symbols = self.symbols.lock().unwrap_or_else(|e| e.into_inner());
+        let Some(history) = symbols.get(symbol) else {
+            return stats;
+        };
+        for bucket in history.rolling.range(window_start..).map(|(_, b)| b) {
+            stats.high = Some(stats.high.map_or(bucket.high, |h| h.max(bucket.high)));
+            stats.low = Some(stats.low.map_or(bucket.low, |l| l.min(bucket.low)));
+            stats.volume = stats.volume.saturating_add(bucket.volume);
+            stats.trade_count += bucket.trade_count;
+        }
+        stats
+    }
+
+    /// Candles opened within the history window before `now`, oldest first; the candle
+    /// still being built comes last.
+    pub fn recent_candles(&self, symbol: &str, now: DateTime<Utc>) -> Vec<Candle> {
+        let cutoff = self.cutoff(now);
+        let symbols = self.symbols.lock().unwrap_or_else(|e| e.into_inner());
+        let Some(history) = symbols.get(symbol) else {
+            return Vec::new();
+        };
+        history
+            .candles
+            .iter()
+            .chain(history.current.iter())
+            .filter(|c| c.open_time >= cutoff)
+            .copied()
+            .collect()
+    }
+
+    /// Ticker points at or after `since`, oldest first.
+    pub fn ticker_history(&self, symbol: &str, since: DateTime<Utc>) -> Vec<TickerPoint> {
+        let symbols = self.symbols.lock().unwrap_or_else(|e| e.into_inner());
+        symbols
+            .get(symbol)
+            .map(|history| {
+                history
+                    .ticker
+                    .iter()
+                    .filter(|p| p.timestamp >= since)
+                    .copied()
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+}
+
+/// Fold trades broadcast on `ws_channel` into `store` until the channel closes or `tasks`
+/// stops it, persisting closed candles to `db` when given. Trades sent once this returns are
+/// counted.
+pub fn spawn_aggregator(
+    tasks: &Supervisor,
+    store: SharedMarketData,
+    ws_channel: &broadcast::Sender<WsMessage>,
+    db: Option<PgPool>,
+) -> TaskHandle {
+    let ws_channel = ws_channel.clone();
+    tasks.spawn(
+        "candle_aggregator",
+        ShutdownStage::Worker,
+        RestartPolicy::default(),
+        move |token| {
+            let (store, db) = (store.clone(), db.clone());
+            let receiver = ws_channel.subscribe();
+            aggregate(store, receiver, db, token)
+        },
+    )
+}
+
+async fn aggregate(
+    store: SharedMarketData,
+    mut receiver: broadcast::Receiver<WsMessage>,
+    db: Option<PgPool>,
+    token: CancellationToken,
+) {
+    while let Some(received) = token.run_until_cancelled(receiver.recv()).await {
+        match received {
+            Ok(WsMessage::Trade { symbol, trade })
+                if symbol != SELFTEST_SYMBOL && !is_sandbox_symbol(&symbol) =>
+            {
+                let Some(candle) = store.record_trade(&symbol, &trade) else {
+                    continue;
+                };
+                if let Some(ref db) = db
+                    && let Err(e) = persistence::upsert_candle(db, &symbol, &candle).await
+                {
+                    eprintln!(
+                        "failed to persist {} candle at {}: {}",
+                        symbol, candle.open_time, e
+                    );
+                }
+            }
+            Ok(_) => {}
+            Err(RecvError::Lagged(skipped)) => {
+                eprintln!(
+                    "candle aggregator fell behind; {} market data messages lost",
+                    skipped
+                );
+            }
+            Err(RecvError::Closed) => return,
+        }
+    }
+}
+
+/// Seed `store`'s rolling statistics for `symbols` from the `trades` table, as of `now`.
+/// `settled` as for `MarketDataStore::load_rolling`.
+pub async fn load_rolling_stats(
+    store: &MarketDataStore,
+    db: &PgPool,
+    symbols: &[String],
+    now: DateTime<Utc>,
+    settled: Option<DateTime<Utc>>,
+) -> Result<(), sqlx::Error> {
+    let since = rolling_window_start(now);
+    for symbol in symbols {
+        let rows = persistence::list_trade_stats_buckets(db, symbol, since).await?;
+        let buckets = rows.iter().map(|row| row.to_bucket()).collect();
+        store.load_rolling(symbol, buckets, settled);
+    }
+    Ok(())
+}
+
+/// Re-read the rolling statistics of `symbols` from `db` every `interval`, leaving the
+/// buckets of the last `ROLLING_SETTLE` to the aggregator.
+pub fn spawn_rolling_refresher(
+    tasks: &Supervisor,
+    store: SharedMarketData,
+    db: PgPool,
+    symbols: Vec<String>,
+    interval: Duration,
+    clock: SharedClock,
+) {
+    tasks.spawn(
+        "rolling_ticker_refresher",
+        ShutdownStage::Worker,
+        RestartPolicy::default(),
+        move |token| {
+            let (store, db, symbols, clock) =
+                (store.clone(), db.clone(), symbols.clone(), clock.clone());
+            async move {
+                let mut ticker =
+                    tokio::time::interval_at(tokio::time::Instant::now() + interval, interval);
+                while token.run_until_cancelled(ticker.tick()).await.is_some() {
+                    let now = clock.now();
+                    let settled = candle_open_time(now - ROLLING_SETTLE);
+                    if let Err(e) =
+                        load_rolling_stats(&store, &db, &symbols, now, Some(settled)).await
+                    {
+                        eprintln!("failed to refresh rolling ticker statistics: {}", e);
+                    }
+                }
+            }
+        },
+    );
+}
@@ -0,0 +1,223 @@
+//! Introspection of a book's internals: price levels, resting orders, retained and archived
+//! trades, buffered diffs, an estimate of the memory they take, and the age of the oldest
+//! resting order. Served live at /admin/book/info; a background task samples every book into
+//! `BookStatsGauges` for /admin/metrics, so scrapes never wait on a book lock.
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::orderbook::orderbook::SharedOrderBook;
+use crate::tasks::{RestartPolicy, ShutdownStage, Supervisor};
+use crate::types::order::Price;
+
+/// What `OrderBook::stats` reports.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct BookStats {
+    pub bid_levels: usize,
+    pub ask_levels: usize,
+    pub resting_orders: usize,
+    /// Trades kept in memory for `/trades` and `/trades/me`, busted ones included.
+    pub retained_trades: usize,
+    /// Trades in the replay archive (see `trade_archive`), at most `archive_capacity`.
+    pub archived_trades: usize,
+    pub archive_capacity: usize,
+    /// Lowest `from_seq` the archive can replay without a gap.
+    pub archive_oldest_seq: u64,
+    /// Trades dropped from the archive since the book was created.
+    pub archive_evicted: u64,
+    /// Diffs kept for gap recovery (see `diff_buffer`), at most `diff_buffer_capacity`.
+    pub buffered_diffs: usize,
+    pub diff_buffer_capacity: usize,
+    /// Diffs dropped from the buffer since the book was created.
+    pub diffs_evicted: u64,
+    /// Bytes held by the buffered diffs, included in `estimated_bytes`.
+    pub diff_buffer_bytes: usize,
+    /// Bytes held by orders, levels, retained and archived trades and buffered diffs, from
+    /// entry counts times struct sizes. Ignores allocator slack and spare map capacity, so it
+    /// is a lower bound.
+    pub estimated_bytes: usize,
+    pub book_seq: u64,
+    pub last_trade_seq: u64,
+    /// Most recent retained trade that was not busted.
+    #[serde(with = "crate::types::string_i64::option")]
+    pub last_trade_price: Option<Price>,
+    pub last_trade_at: Option<DateTime<Utc>>,
+    pub oldest_resting_at: Option<DateTime<Utc>>,
+}
+
+pub type SharedBookStatsGauges = Arc<BookStatsGauges>;
+
+/// The latest sample of every book's stats, exported as Prometheus gauges.
+#[derive(Debug, Default)]
+pub struct BookStatsGauges {
+    sample: Mutex<Option<BookStatsSample>>,
+}
+
+#[derive(Debug)]
+struct BookStatsSample {
+    taken_at: DateTime<Utc>,
+    books: BTreeMap<String, BookStats>,
+}
+
+impl BookStatsGauges {
+    /// Sample every book, holding each read lock only while its stats are computed.
+    pub async fn refresh(&self, orderbooks: &HashMap<String, SharedOrderBook>) {
+        let mut books = BTreeMap::new();
+        for (symbol, book) in orderbooks {
+            let stats = book.read().await.stats();
+            books.insert(symbol.clone(), stats);
+        }
+        *self.sample.lock().unwrap() = Some(BookStatsSample {
+            taken_at: Utc::now(),
+            books,
+        });
+    }
+
+    /// When the latest sample was taken (None before the first refresh).
+    pub fn refreshed_at(&self) -> Option<DateTime<Utc>> {
+        self.sample.lock().unwrap().as_ref().map(|s| s.taken_at)
+    }
+
+    /// Prometheus text for the latest sample; empty before the first refresh.
+    pub fn render_prometheus(&self) -> String {
+        let sample = self.sample.lock().unwrap();
+        let Some(sample) = sample.as_ref() else {
+            return String::new();
+        };
+        let books = &sample.books;
+        let mut out = String::new();
+        out.push_str("# HELP exchange_book_price_levels Price levels with resting orders.\n");
+        out.push_str("# TYPE exchange_book_price_levels gauge\n");
+        for (symbol, stats) in books {
+            for (side, levels) in [("bid", stats.bid_levels), ("ask", stats.ask_levels)] {
+                let _ = writeln!(
+                    out,
+                    "exchange_book_price_levels{{symbol=\"{}\",side=\"{}\"}} {}",
+                    symbol, side, levels
+                );
+            }
+        }
+        write_gauge(
+            &mut out,
+            "exchange_book_retained_trades",
+            "Trades retained in memory by the book.",
+            books,
+            |s| Some(s.retained_trades as i64),
+        );
+        write_gauge(
+            &mut out,
+            "exchange_book_archived_trades",
+            "Trades in the book's replay archive.",
+            books,
+            |s| Some(s.archived_trades as i64),
+        );
+        write_gauge(
+            &mut out,
+            "exchange_book_archive_evicted_trades",
+            "Trades dropped from the book's replay archive since startup.",
+            books,
+            |s| Some(s.archive_evicted as i64),
+        );
+        write_gauge(
+            &mut out,
+            "exchange_book_buffered_diffs",
+            "Diffs in the book's gap recovery buffer.",
+            books,
+            |s| Some(s.buffered_diffs as i64),
+        );
+        write_gauge(
+            &mut out,
+            "exchange_book_diff_buffer_bytes",
+            "Estimated memory held by the book's gap recovery buffer.",
+            books,
+            |s| Some(s.diff_buffer_bytes as i64),
+        );
+        write_gauge(
+            &mut out,
+            "exchange_book_estimated_bytes",
+            "Estimated memory held by the book's orders, levels, trades and buffered diffs.",
+            books,
+            |s| Some(s.estimated_bytes as i64),
+        );
+        write_gauge(
+            &mut out,
+            "exchange_book_seq",
+            "Sequence of the book state.",
+            books,
+            |s| Some(s.book_seq as i64),
+        );
+        write_gauge(
+            &mut out,
+            "exchange_book_last_trade_price",
+            "Price of the most recent trade (absent before the first).",
+            books,
+            |s| s.last_trade_price,
+        );
+        write_gauge(
+            &mut out,
+            "exchange_book_last_trade_timestamp_seconds",
+            "Time of the most recent trade (absent before the first).",
+            books,
+            |s| s.last_trade_at.map(|t| t.timestamp()),
+        );
+        write_gauge(
+            &mut out,
+            "exchange_book_oldest_resting_order_timestamp_seconds",
+            "Placement time of the oldest resting order (absent when the book is empty).",
+            books,
+            |s| s.oldest_resting_at.map(|t| t.timestamp()),
+        );
+        out.push_str(
+            "# HELP exchange_book_stats_refreshed_timestamp_seconds When the book gauges were last sampled.\n",
+        );
+        out.push_str("# TYPE exchange_book_stats_refreshed_timestamp_seconds gauge\n");
+        let _ = writeln!(
+            out,
+            "exchange_book_stats_refreshed_timestamp_seconds {}",
+            sample.taken_at.timestamp()
+        );
+        out
+    }
+}
+
+fn write_gauge(
+    out: &mut String,
+    name: &str,
+    help: &str,
+    books: &BTreeMap<String, BookStats>,
+    value: impl Fn(&BookStats) -> Option<i64>,
+) {
+    let _ = writeln!(out, "# HELP {} {}\n# TYPE {} gauge", name, help, name);
+    for (symbol, stats) in books {
+        if let Some(value) = value(stats) {
+            let _ = writeln!(out, "{}{{symbol=\"{}\"}} {}", name, symbol, value);
+        }
+    }
+}
+
+/// Sample `orderbooks` into `gauges` now and then every `interval`.
+pub fn spawn_refresher(
+    tasks: &Supervisor,
+    gauges: SharedBookStatsGauges,
+    orderbooks: HashMap<String, SharedOrderBook>,
+    interval: Duration,
+) {
+    tasks.spawn(
+        "book_stats_refresher",
+        ShutdownStage::Worker,
+        RestartPolicy::default(),
+        move |token| {
+            let (gauges, orderbooks) = (gauges.clone(), orderbooks.clone());
+            async move {
+                let mut ticker = tokio::time::interval(interval);
+                while token.run_until_cancelled(ticker.tick()).await.is_some() {
+                    gauges.refresh(&orderbooks).await;
+                }
+            }
+        },
+    );
+}
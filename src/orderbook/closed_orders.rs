@@ -0,0 +1,72 @@
+//! How recently cancelled orders left a book: the order as it left, when, and why. A cancel
+//! that finds its order gone looks here to tell "someone else closed it first" (answered with
+//! the closure, whoever won) from "no such order". Bounded to the most recent
+//! `DEFAULT_CAPACITY` closures; older ones are looked up in the database, when there is one.
+
+use chrono::{DateTime, Utc};
+use std::collections::{HashMap, VecDeque};
+
+use crate::types::order::{CloseReason, Order, OrderId};
+
+/// Closures remembered per book.
+pub const DEFAULT_CAPACITY: usize = 10_000;
+
+/// An order as a cancel took it off the book: status `Cancelled`, `quantity` still open.
+#[derive(Debug, Clone)]
+pub struct ClosedOrder {
+    pub order: Order,
+    pub closed_at: DateTime<Utc>,
+    pub reason: CloseReason,
+}
+
+#[derive(Debug, Clone)]
+pub struct ClosedOrders {
+    by_id: HashMap<OrderId, ClosedOrder>,
+    /// Ids in closing order, oldest first, for eviction.
+    order: VecDeque<OrderId>,
+    capacity: usize,
+}
+
+impl Default for ClosedOrders {
+    fn default() -> Self {
+        Self::new(DEFAULT_CAPACITY)
+    }
+}
+
+impl ClosedOrders {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            by_id: HashMap::new(),
+            order: VecDeque::new(),
+            capacity,
+        }
+    }
+
+    /// Remember `closed`, forgetting the oldest closure beyond the capacity. An order closes
+    /// once, so a second record for the same id is ignored.
+    pub fn record(&mut self, closed: ClosedOrder) {
+        let id = closed.order.id;
+        if self.capacity == 0 || self.by_id.contains_key(&id) {
+            return;
+        }
+        self.by_id.insert(id, closed);
+        self.order.push_back(id);
+        while self.order.len() > self.capacity {
+            if let Some(oldest) = self.order.pop_front() {
+                self.by_id.remove(&oldest);
+            }
+        }
+    }
+
+    pub fn get(&self, order_id: OrderId) -> Option<&ClosedOrder> {
+        self.by_id.get(&order_id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.order.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.order.is_empty()
+    }
+}
@@ -0,0 +1,256 @@
+//! The book's diff stream and a short memory of it: every published change to the price levels
+//! becomes a `BookDiff` (the new quantity of each level that changed, 0 once a level is gone),
+//! sent to `diffs` subscribers and kept in a ring of the most recent `BOOK_DIFF_BUFFER_SIZE`.
+//! A client that missed some (a dropped connection, a lagging socket) asks for the diffs after
+//! the last sequence it applied instead of fetching the whole book again; when the ring no
+//! longer reaches back that far it says where it starts, and the client takes a snapshot.
+//!
+//! Diffs are taken against the levels last published, not against the previous book sequence:
+//! changes that were never broadcast (a restore, a `touch`) are folded into the next diff, so
+//! the diffs chain by `prev_seq` and sequences may skip. Each diff carries the `book_checksum`
+//! of the levels once applied, so a client can tell that its copy is whole.
+
+use std::cmp::Ordering;
+use std::collections::VecDeque;
+
+use crate::types::order::{Price, Qty};
+
+/// Diffs buffered per book when `BOOK_DIFF_BUFFER_SIZE` is unset.
+pub const DEFAULT_CAPACITY: usize = 1_000;
+
+/// The change to a book's levels between two published states.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BookDiff {
+    /// Book sequence after the change.
+    pub seq: u64,
+    /// Sequence of the state the diff applies to: the previous diff's `seq`.
+    pub prev_seq: u64,
+    /// Changed levels, best first, with their new quantity (0: the level is gone).
+    pub bids: Vec<(Price, Qty)>,
+    pub asks: Vec<(Price, Qty)>,
+    /// `book_checksum` of every level once the diff is applied.
+    pub checksum: u32,
+}
+
+/// The requested diffs are no longer (or were never) buffered; the buffer can replay from
+/// `oldest_seq` on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DiffsEvicted {
+    pub oldest_seq: u64,
+}
+
+impl std::fmt::Display for DiffsEvicted {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Diffs before seq {} are no longer available",
+            self.oldest_seq
+        )
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct DiffBuffer {
+    diffs: VecDeque<BookDiff>,
+    capacity: usize,
+    /// Levels as last published; the next diff is taken against them.
+    bids: Vec<(Price, Qty)>,
+    asks: Vec<(Price, Qty)>,
+    /// Sequence of the last published state.
+    seq: u64,
+    /// Diffs dropped to stay within `capacity`, ever.
+    evicted: u64,
+}
+
+impl Default for DiffBuffer {
+    fn default() -> Self {
+        Self::new(DEFAULT_CAPACITY)
+    }
+}
+
+impl DiffBuffer {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            diffs: VecDeque::new(),
+            capacity,
+            bids: Vec::new(),
+            asks: Vec::new(),
+            seq: 0,
+            evicted: 0,
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Keep at most `capacity` diffs from now on, evicting the oldest beyond it.
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        self.evict();
+    }
+
+    pub fn len(&self) -> usize {
+        self.diffs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.diffs.is_empty()
+    }
+
+    /// Diffs dropped from the buffer since it was created.
+    pub fn evicted(&self) -> u64 {
+        self.evicted
+    }
+
+    /// Sequence of the last published state.
+    pub fn seq(&self) -> u64 {
+        self.seq
+    }
+
+    /// The lowest `from_seq` that `since` can serve without a gap.
+    pub fn oldest_seq(&self) -> u64 {
+        self.diffs.front().map_or(self.seq, |d| d.prev_seq)
+    }
+
+    /// Bid levels as last published, best first.
+    pub fn bids(&self) -> &[(Price, Qty)] {
+        &self.bids
+    }
+
+    /// Ask levels as last published, best first.
+    pub fn asks(&self) -> &[(Price, Qty)] {
+        &self.asks
+    }
+
+    /// Bytes held by the buffered diffs and the published levels, as
+    /// `BookStats::estimated_bytes` counts them.
+    pub fn estimated_bytes(&self) -> usize {
+        let levels: usize = self
+            .diffs
+            .iter()
+            .map(|d| d.bids.len() + d.asks.len())
+            .sum::<usize>()
+            + self.bids.len()
+            + self.asks.len();
+        self.diffs.len() * size_of::<BookDiff>() + levels * size_of::<(Price, Qty)>()
+    }
+
+    /// Publish the book's levels at `seq`: the diff from the last published ones, buffered
+    /// (evicting the oldest when full), or None when no level changed.
+    pub fn publish(
+        &mut self,
+        seq: u64,
+        bids: Vec<(Price, Qty)>,
+        asks: Vec<(Price, Qty)>,
+    ) -> Option<BookDiff> {
+        let bid_changes = changed_levels(&self.bids, &bids, |a, b| b.cmp(a));
+        let ask_changes = changed_levels(&self.asks, &asks, |a, b| a.cmp(b));
+        if bid_changes.is_empty() && ask_changes.is_empty() {
+            return None;
+        }
+        let diff = BookDiff {
+            seq,
+            prev_seq: self.seq,
+            bids: bid_changes,
+            asks: ask_changes,
+            checksum: book_checksum(&bids, &asks),
+        };
+        self.seq = seq;
+        self.bids = bids;
+        self.asks = asks;
+        self.diffs.push_back(diff.clone());
+        self.evict();
+        Some(diff)
+    }
+
+    /// Buffered diffs that bring a book at `from_seq` (a diff's `seq`, or a snapshot's) up to
+    /// the last published state, oldest first; empty when it is there already.
+    pub fn since(&self, from_seq: u64) -> Result<Vec<BookDiff>, DiffsEvicted> {
+        if from_seq >= self.seq {
+            return Ok(Vec::new());
+        }
+        if from_seq < self.oldest_seq() {
+            return Err(DiffsEvicted {
+                oldest_seq: self.oldest_seq(),
+            });
+        }
+        Ok(self
+            .diffs
+            .iter()
+            .filter(|d| d.seq > from_seq)
+            .cloned()
+            .collect())
+    }
+
+    fn evict(&mut self) {
+        while self.diffs.len() > self.capacity {
+            self.diffs.pop_front();
+            self.evicted += 1;
+        }
+    }
+}
+
+/// Levels of `new` that differ from `old`, with quantity 0 for the ones gone. Both sides are
+/// sorted by `order` (best first), and so is the result.
+fn changed_levels(
+    old: &[(Price, Qty)],
+    new: &[(Price, Qty)],
+    order: impl Fn(&Price, &Price) -> Ordering,
+) -> Vec<(Price, Qty)> {
+    let mut changes = Vec::new();
+    let (mut old, mut new) = (old.iter().peekable(), new.iter().peekable());
+    loop {
+        match (old.peek(), new.peek()) {
+            (Some(&&(old_price, old_qty)), Some(&&(new_price, new_qty))) => {
+                match order(&old_price, &new_price) {
+                    Ordering::Less => {
+                        changes.push((old_price, 0));
+                        old.next();
+                    }
+                    Ordering::Greater => {
+                        changes.push((new_price, new_qty));
+                        new.next();
+                    }
+                    Ordering::Equal => {
+                        if old_qty != new_qty {
+                            changes.push((new_price, new_qty));
+                        }
+                        old.next();
+                        new.next();
+                    }
+                }
+            }
+            (Some(&&(old_price, _)), None) => {
+                changes.push((old_price, 0));
+                old.next();
+            }
+            (None, Some(&&level)) => {
+                changes.push(level);
+                new.next();
+            }
+            (None, None) => return changes,
+        }
+    }
+}
+
+/// FNV-1a (32-bit) over the bids and then the asks, best first: each side as its level count
+/// and then every level's price and quantity, all in little-endian bytes. Clients compute it
+/// over their own copy of the book to check it against a diff or a snapshot.
+pub fn book_checksum(bids: &[(Price, Qty)], asks: &[(Price, Qty)]) -> u32 {
+    let mut hash: u32 = 0x811c_9dc5;
+    let mut write = |bytes: [u8; 8]| {
+        for byte in bytes {
+            hash ^= u32::from(byte);
+            hash = hash.wrapping_mul(0x0100_0193);
+        }
+    };
+    for levels in [bids, asks] {
+        write((levels.len() as u64).to_le_bytes());
+        for &(price, qty) in levels {
+            write(price.to_le_bytes());
+            write(qty.to_le_bytes());
+        }
+    }
+    hash
+}
@@ -0,0 +1,364 @@
+//! Matching-quality statistics kept per book: time from placement to first and full fill,
+//! the share of limit orders that trade before they leave the book, and fill volume by
+//! liquidity role. Percentiles and ratios cover a window of the most recent samples; the
+//! Prometheus histograms and counters are cumulative. Served at /admin/stats/matching and
+//! /admin/metrics.
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::fmt::Write;
+
+use crate::types::order::{Order, OrderStatus, OrderType, Qty};
+
+/// Samples kept per window when the book does not set its own.
+pub const DEFAULT_MATCHING_WINDOW: usize = 1000;
+
+/// Upper bounds (ms) of the time-to-fill histogram buckets; a final bucket counts the rest.
+pub const FILL_LATENCY_BUCKETS_MS: [i64; 9] = [
+    1, 10, 100, 1_000, 10_000, 60_000, 300_000, 3_600_000, 86_400_000,
+];
+
+/// Which side of a fill an order was on. Both orders in an auction fill rest in the book,
+/// so neither is maker or taker.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Liquidity {
+    Maker,
+    Taker,
+    Auction,
+}
+
+/// Filled quantity by liquidity role; only market orders are ever `market_taker`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct FillVolume {
+    #[serde(with = "crate::types::string_i64")]
+    pub limit_maker: u64,
+    #[serde(with = "crate::types::string_i64")]
+    pub limit_taker: u64,
+    #[serde(with = "crate::types::string_i64")]
+    pub market_taker: u64,
+    #[serde(with = "crate::types::string_i64")]
+    pub auction: u64,
+}
+
+impl FillVolume {
+    fn slot(&mut self, liquidity: Liquidity, order_type: OrderType) -> &mut u64 {
+        match (liquidity, order_type) {
+            (Liquidity::Maker, _) => &mut self.limit_maker,
+            (Liquidity::Taker, OrderType::Limit) => &mut self.limit_taker,
+            (Liquidity::Taker, OrderType::Market) => &mut self.market_taker,
+            (Liquidity::Auction, _) => &mut self.auction,
+        }
+    }
+}
+
+/// Time-to-fill percentiles (ms, nearest rank) over the window; None when it is empty.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct LatencySummary {
+    pub samples: usize,
+    pub p50_ms: Option<i64>,
+    pub p90_ms: Option<i64>,
+    pub p99_ms: Option<i64>,
+    pub max_ms: Option<i64>,
+}
+
+/// Windowed view served at /admin/stats/matching.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct MatchingStatsReport {
+    pub window: usize,
+    pub time_to_first_fill: LatencySummary,
+    pub time_to_full_fill: LatencySummary,
+    /// Limit orders that left the book (filled or cancelled) within the window.
+    pub limit_orders_completed: usize,
+    /// Of those, the ones that traded at least once.
+    pub limit_orders_traded: usize,
+    pub limit_fill_ratio: Option<f64>,
+    /// Volume of the most recent fills in the window.
+    pub volume: FillVolume,
+    /// Share of limit-order fill volume that was resting (maker) rather than aggressing.
+    pub maker_ratio: Option<f64>,
+}
+
+/// Cumulative time-to-fill histogram in `FILL_LATENCY_BUCKETS_MS` buckets.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct FillLatencyHistogram {
+    pub count: u64,
+    pub sum_ms: i64,
+    /// Counts per bucket (not cumulative), plus one overflow bucket.
+    pub buckets: Vec<u64>,
+}
+
+impl FillLatencyHistogram {
+    fn record(&mut self, elapsed_ms: i64) {
+        if self.buckets.is_empty() {
+            self.buckets = vec![0; FILL_LATENCY_BUCKETS_MS.len() + 1];
+        }
+        let bucket = FILL_LATENCY_BUCKETS_MS
+            .iter()
+            .position(|&bound| elapsed_ms < bound)
+            .unwrap_or(FILL_LATENCY_BUCKETS_MS.len());
+        self.buckets[bucket] += 1;
+        self.count += 1;
+        self.sum_ms += elapsed_ms;
+    }
+}
+
+/// Cumulative counters for the Prometheus exposition.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MatchingMetrics {
+    pub time_to_first_fill: FillLatencyHistogram,
+    pub time_to_full_fill: FillLatencyHistogram,
+    pub limit_orders_completed: u64,
+    pub limit_orders_traded: u64,
+    pub volume: FillVolume,
+}
+
+#[derive(Debug, Clone, Default)]
+struct LatencyWindow {
+    recent: VecDeque<i64>,
+    histogram: FillLatencyHistogram,
+}
+
+impl LatencyWindow {
+    fn record(&mut self, elapsed_ms: i64, window: usize) {
+        if self.recent.len() == window {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(elapsed_ms);
+        self.histogram.record(elapsed_ms);
+    }
+
+    fn summary(&self) -> LatencySummary {
+        let mut sorted: Vec<i64> = self.recent.iter().copied().collect();
+        sorted.sort_unstable();
+        let rank = |p: usize| {
+            (!sorted.is_empty()).then(|| sorted[(p * sorted.len()).div_ceil(100).max(1) - 1])
+        };
+        LatencySummary {
+            samples: sorted.len(),
+            p50_ms: rank(50),
+            p90_ms: rank(90),
+            p99_ms: rank(99),
+            max_ms: sorted.last().copied(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct MatchingStats {
+    window: usize,
+    first_fill: LatencyWindow,
+    full_fill: LatencyWindow,
+    /// Whether each recently completed limit order traded.
+    limit_outcomes: VecDeque<bool>,
+    recent_fills: VecDeque<(Liquidity, OrderType, Qty)>,
+    recent_volume: FillVolume,
+    totals: MatchingMetrics,
+}
+
+impl Default for MatchingStats {
+    fn default() -> Self {
+        Self::new(DEFAULT_MATCHING_WINDOW)
+    }
+}
+
+impl MatchingStats {
+    /// Stats keeping the `window` (at least 1) most recent samples of each kind.
+    pub fn new(window: usize) -> Self {
+        Self {
+            window: window.max(1),
+            first_fill: LatencyWindow::default(),
+            full_fill: LatencyWindow::default(),
+            limit_outcomes: VecDeque::new(),
+            recent_fills: VecDeque::new(),
+            recent_volume: FillVolume::default(),
+            totals: MatchingMetrics::default(),
+        }
+    }
+
+    /// Record `qty` of `order` filling at `now`; `order` is as it was before this fill, so a
+    /// `Pending` status marks its first fill and `qty == quantity` its last. Time to fill is
+    /// tracked for limit orders only (market orders fill on arrival or not at all).
+    pub(crate) fn record_fill(
+        &mut self,
+        order: &Order,
+        qty: Qty,
+        liquidity: Liquidity,
+        now: DateTime<Utc>,
+    ) {
+        if self.recent_fills.len() == self.window
+            && let Some((liquidity, order_type, qty)) = self.recent_fills.pop_front()
+        {
+            let slot = self.recent_volume.slot(liquidity, order_type);
+            *slot = slot.saturating_sub(qty);
+        }
+        self.recent_fills
+            .push_back((liquidity, order.order_type, qty));
+        // Volumes saturate like every other quantity aggregate
+        for slot in [
+            self.recent_volume.slot(liquidity, order.order_type),
+            self.totals.volume.slot(liquidity, order.order_type),
+        ] {
+            *slot = slot.saturating_add(qty);
+        }
+
+        if order.order_type != OrderType::Limit {
+            return;
+        }
+        let elapsed_ms = (now - order.timestamp).num_milliseconds().max(0);
+        if order.status == OrderStatus::Pending {
+            self.first_fill.record(elapsed_ms, self.window);
+        }
+        if qty == order.quantity {
+            self.full_fill.record(elapsed_ms, self.window);
+            self.record_limit_outcome(true);
+        }
+    }
+
+    /// Record `order` leaving the book without filling completely.
+    pub(crate) fn record_cancel(&mut self, order: &Order) {
+        if order.order_type == OrderType::Limit {
+            self.record_limit_outcome(order.status != OrderStatus::Pending);
+        }
+    }
+
+    fn record_limit_outcome(&mut self, traded: bool) {
+        if self.limit_outcomes.len() == self.window {
+            self.limit_outcomes.pop_front();
+        }
+        self.limit_outcomes.push_back(traded);
+        self.totals.limit_orders_completed += 1;
+        if traded {
+            self.totals.limit_orders_traded += 1;
+        }
+    }
+
+    pub fn report(&self) -> MatchingStatsReport {
+        let completed = self.limit_outcomes.len();
+        let traded = self.limit_outcomes.iter().filter(|&&t| t).count();
+        let volume = self.recent_volume;
+        let limit_volume = volume.limit_maker.saturating_add(volume.limit_taker);
+        MatchingStatsReport {
+            window: self.window,
+            time_to_first_fill: self.first_fill.summary(),
+            time_to_full_fill: self.full_fill.summary(),
+            limit_orders_completed: completed,
+            limit_orders_traded: traded,
+            limit_fill_ratio: (completed > 0).then(|| traded as f64 / completed as f64),
+            volume,
+            maker_ratio: (limit_volume > 0)
+                .then(|| volume.limit_maker as f64 / limit_volume as f64),
+        }
+    }
+
+    pub fn metrics(&self) -> MatchingMetrics {
+        let mut metrics = self.totals.clone();
+        metrics.time_to_first_fill = self.first_fill.histogram.clone();
+        metrics.time_to_full_fill = self.full_fill.histogram.clone();
+        metrics
+    }
+}
+
+/// Prometheus text exposition (format 0.0.4) of per-symbol matching metrics.
+pub fn render_prometheus(books: &[(String, MatchingMetrics)]) -> String {
+    let mut out = String::new();
+    write_histogram(
+        &mut out,
+        "exchange_time_to_first_fill_seconds",
+        "Time from limit order placement to its first fill.",
+        books,
+        |m| &m.time_to_first_fill,
+    );
+    write_histogram(
+        &mut out,
+        "exchange_time_to_full_fill_seconds",
+        "Time from limit order placement to its complete fill.",
+        books,
+        |m| &m.time_to_full_fill,
+    );
+
+    let _ = writeln!(
+        out,
+        "# HELP exchange_limit_orders_completed_total Limit orders that left the book, by whether they traded.\n\
+         # TYPE exchange_limit_orders_completed_total counter"
+    );
+    for (symbol, metrics) in books {
+        for (traded, count) in [
+            ("true", metrics.limit_orders_traded),
+            (
+                "false",
+                metrics.limit_orders_completed - metrics.limit_orders_traded,
+            ),
+        ] {
+            let _ = writeln!(
+                out,
+                "exchange_limit_orders_completed_total{{symbol=\"{}\",traded=\"{}\"}} {}",
+                symbol, traded, count
+            );
+        }
+    }
+
+    let _ = writeln!(
+        out,
+        "# HELP exchange_fill_volume_total Filled quantity by liquidity role and order type.\n\
+         # TYPE exchange_fill_volume_total counter"
+    );
+    for (symbol, metrics) in books {
+        let volume = metrics.volume;
+        for (liquidity, order_type, qty) in [
+            ("maker", "limit", volume.limit_maker),
+            ("taker", "limit", volume.limit_taker),
+            ("taker", "market", volume.market_taker),
+            ("auction", "limit", volume.auction),
+        ] {
+            let _ = writeln!(
+                out,
+                "exchange_fill_volume_total{{symbol=\"{}\",liquidity=\"{}\",order_type=\"{}\"}} {}",
+                symbol, liquidity, order_type, qty
+            );
+        }
+    }
+    out
+}
+
+fn write_histogram(
+    out: &mut String,
+    name: &str,
+    help: &str,
+    books: &[(String, MatchingMetrics)],
+    histogram: impl Fn(&MatchingMetrics) -> &FillLatencyHistogram,
+) {
+    let _ = writeln!(out, "# HELP {} {}\n# TYPE {} histogram", name, help, name);
+    for (symbol, metrics) in books {
+        let histogram = histogram(metrics);
+        let mut cumulative = 0;
+        for (i, bound) in FILL_LATENCY_BUCKETS_MS.iter().enumerate() {
+            cumulative += histogram.buckets.get(i).copied().unwrap_or(0);
+            let _ = writeln!(
+                out,
+                "{}_bucket{{symbol=\"{}\",le=\"{}\"}} {}",
+                name,
+                symbol,
+                *bound as f64 / 1000.0,
+                cumulative
+            );
+        }
+        let _ = writeln!(
+            out,
+            "{}_bucket{{symbol=\"{}\",le=\"+Inf\"}} {}",
+            name, symbol, histogram.count
+        );
+        let _ = writeln!(
+            out,
+            "{}_sum{{symbol=\"{}\"}} {}",
+            name,
+            symbol,
+            histogram.sum_ms as f64 / 1000.0
+        );
+        let _ = writeln!(
+            out,
+            "{}_count{{symbol=\"{}\"}} {}",
+            name, symbol, histogram.count
+        );
+    }
+}
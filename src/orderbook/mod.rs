@@ -1,2 +1,9 @@
+pub mod book_stats;
+pub mod clock;
+pub mod closed_orders;
+pub mod diff_buffer;
+pub mod matching_stats;
+pub mod order_limits;
 #[allow(clippy::module_inception)]
 pub mod orderbook;
+pub mod trade_archive;
@@ -0,0 +1,221 @@
+//! Caps on how many orders may rest, globally and in any one book, so a hostile user or a bug
+//! cannot grow the books until the process runs out of memory.
+//!
+//! Every book shares one `OrderLimits` and keeps the global count up to date as orders rest
+//! and leave. Only resting is capped: an order may always take liquidity, and if the remainder
+//! that would rest does not fit, the remainder is cancelled and the order is rejected with
+//! `BOOK_FULL` (its fills stand). Restoring persisted orders at startup is never capped.
+//!
+//! Admins can raise the caps for a while (`PUT /admin/order-limits/override`); the override
+//! lapses on its own at `expires_at`.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+pub type SharedOrderLimits = Arc<OrderLimits>;
+
+/// Resting order caps; None is unlimited.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct RestingOrderCaps {
+    /// Across every book.
+    pub global: Option<usize>,
+    /// In each book.
+    pub per_symbol: Option<usize>,
+}
+
+impl RestingOrderCaps {
+    /// Read `MAX_RESTING_ORDERS` and `MAX_RESTING_ORDERS_PER_SYMBOL`; unset or 0 is unlimited.
+    pub fn from_env() -> Self {
+        fn cap(name: &str) -> Option<usize> {
+            env::var(name)
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .filter(|&n| n > 0)
+        }
+        Self {
+            global: cap("MAX_RESTING_ORDERS"),
+            per_symbol: cap("MAX_RESTING_ORDERS_PER_SYMBOL"),
+        }
+    }
+}
+
+/// Temporarily raised caps. A field left None keeps the configured cap.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct CapOverride {
+    pub caps: RestingOrderCaps,
+    pub expires_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Default)]
+pub struct OrderLimits {
+    configured: RestingOrderCaps,
+    cap_override: Mutex<Option<CapOverride>>,
+    /// Orders resting in every book sharing these limits.
+    resting: AtomicUsize,
+    book_full_rejections: AtomicU64,
+}
+
+impl OrderLimits {
+    pub fn new(configured: RestingOrderCaps) -> Self {
+        Self {
+            configured,
+            ..Self::default()
+        }
+    }
+
+    pub fn configured(&self) -> RestingOrderCaps {
+        self.configured
+    }
+
+    /// The override in force at `now`, if any; an expired one is dropped.
+    pub fn active_override(&self, now: DateTime<Utc>) -> Option<CapOverride> {
+        let mut cap_override = self.cap_override.lock().unwrap_or_else(|e| e.into_inner());
+        if cap_override.is_some_and(|o| o.expires_at <= now) {
+            *cap_override = None;
+        }
+        *cap_override
+    }
+
+    /// Raise the caps until `expires_at`, replacing any earlier override. Each given cap must
+    /// be at least the configured one; an override can only raise a cap, and an unlimited
+    /// cap cannot be raised.
+    pub fn set_override(&self, cap_override: CapOverride) -> Result<(), String> {
+        let pairs = [
+            ("global", cap_override.caps.global, self.configured.global),
+            (
+                "per_symbol",
+                cap_override.caps.per_symbol,
+                self.configured.per_symbol,
+            ),
+        ];
+        if pairs.iter().all(|(_, raised, _)| raised.is_none()) {
+            return Err("override must raise at least one cap".to_string());
+        }
+        for (name, raised, configured) in pairs {
+            match (raised, configured) {
+                (Some(_), None) => {
+                    return Err(format!("{} cap is unlimited and cannot be raised", name));
+                }
+                (Some(raised), Some(configured)) if raised < configured => {
+                    return Err(format!(
+                        "{} override {} is below the configured cap {}",
+                        name, raised, configured
+                    ));
+                }
+                _ => {}
+            }
+        }
+        *self.cap_override.lock().unwrap_or_else(|e| e.into_inner()) = Some(cap_override);
+        Ok(())
+    }
+
+    /// Drop the override; returns whether one was set.
+    pub fn clear_override(&self) -> bool {
+        self.cap_override
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .take()
+            .is_some()
+    }
+
+    /// Caps in force at `now`: the override's where it sets one, else the configured ones.
+    pub fn effective(&self, now: DateTime<Utc>) -> RestingOrderCaps {
+        let raised = self
+            .active_override(now)
+            .map(|o| o.caps)
+            .unwrap_or_default();
+        RestingOrderCaps {
+            global: raised.global.or(self.configured.global),
+            per_symbol: raised.per_symbol.or(self.configured.per_symbol),
+        }
+    }
+
+    /// Orders resting across every book.
+    pub fn resting(&self) -> usize {
+        self.resting.load(Ordering::Acquire)
+    }
+
+    pub fn book_full_rejections(&self) -> u64 {
+        self.book_full_rejections.load(Ordering::Relaxed)
+    }
+
+    /// Whether one more order may rest in a book holding `book_resting` once `freed` of those
+    /// have left (e.g. makers a match would fill), without claiming a slot.
+    pub fn admits(&self, book_resting: usize, freed: usize, now: DateTime<Utc>) -> bool {
+        let caps = self.effective(now);
+        caps.per_symbol
+            .is_none_or(|cap| book_resting.saturating_sub(freed) < cap)
+            && caps
+                .global
+                .is_none_or(|cap| self.resting().saturating_sub(freed) < cap)
+    }
+
+    /// Claim a global slot for one more order in a book holding `book_resting`. The caller
+    /// must rest the order (the count is already taken); on refusal the rejection is counted.
+    pub(crate) fn try_reserve(&self, book_resting: usize, now: DateTime<Utc>) -> bool {
+        let caps = self.effective(now);
+        let reserved = caps.per_symbol.is_none_or(|cap| book_resting < cap)
+            && self
+                .resting
+                .fetch_update(Ordering::AcqRel, Ordering::Acquire, |n| {
+                    caps.global.is_none_or(|cap| n < cap).then_some(n + 1)
+                })
+                .is_ok();
+        if !reserved {
+            self.book_full_rejections.fetch_add(1, Ordering::Relaxed);
+        }
+        reserved
+    }
+
+    /// Move the global count from a book's old resting count to its new one.
+    pub(crate) fn adjust(&self, before: usize, after: usize) {
+        if after > before {
+            self.resting.fetch_add(after - before, Ordering::AcqRel);
+        } else {
+            self.resting.fetch_sub(before - after, Ordering::AcqRel);
+        }
+    }
+
+    /// Prometheus text for the resting order count of each `(symbol, count)` book, the caps
+    /// in force (absent when unlimited) and the `BOOK_FULL` rejections.
+    pub fn render_prometheus(&self, books: &[(String, usize)], now: DateTime<Utc>) -> String {
+        let mut out = String::new();
+        out.push_str("# HELP exchange_resting_orders Orders resting in the book.\n");
+        out.push_str("# TYPE exchange_resting_orders gauge\n");
+        for (symbol, count) in books {
+            let _ = writeln!(
+                out,
+                "exchange_resting_orders{{symbol=\"{}\"}} {}",
+                symbol, count
+            );
+        }
+        let caps = self.effective(now);
+        out.push_str(
+            "# HELP exchange_resting_orders_limit Cap on resting orders in force (absent when unlimited).\n",
+        );
+        out.push_str("# TYPE exchange_resting_orders_limit gauge\n");
+        for (scope, cap) in [("global", caps.global), ("per_symbol", caps.per_symbol)] {
+            if let Some(cap) = cap {
+                let _ = writeln!(
+                    out,
+                    "exchange_resting_orders_limit{{scope=\"{}\"}} {}",
+                    scope, cap
+                );
+            }
+        }
+        out.push_str(
+            "# HELP exchange_book_full_rejections_total Orders whose remainder could not rest because a cap was reached.\n",
+        );
+        out.push_str("# TYPE exchange_book_full_rejections_total counter\n");
+        let _ = writeln!(
+            out,
+            "exchange_book_full_rejections_total {}",
+            self.book_full_rejections()
+        );
+        out
+    }
+}
@@ -1,23 +1,192 @@
-use chrono::Utc;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
 use std::collections::btree_map::Entry;
-use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
 use std::sync::Arc;
 use tokio::sync::{RwLock, broadcast};
 use uuid::Uuid;
 
-use crate::types::order::{Order, OrderId, OrderSide, OrderStatus, OrderType, Price, Qty};
-use crate::types::trade::Trade;
+use crate::orderbook::book_stats::BookStats;
+use crate::orderbook::clock::{SharedClock, SystemClock};
+use crate::orderbook::closed_orders::{ClosedOrder, ClosedOrders};
+use crate::orderbook::diff_buffer::{BookDiff, DiffBuffer, book_checksum};
+use crate::orderbook::matching_stats::{Liquidity, MatchingStats};
+use crate::orderbook::order_limits::{OrderLimits, SharedOrderLimits};
+use crate::orderbook::trade_archive::TradeArchive;
+use crate::types::order::{
+    Order, OrderId, OrderSide, OrderSource, OrderStatus, OrderTags, OrderType, Price, Qty,
+    RejectReason, total_quantity,
+};
+use crate::types::trade::{Trade, TradeAttribution, TradeBust};
 
-type PriceLevel = VecDeque<OrderId>;
+/// Resting orders at one price in time priority, with their open quantity cached so depth
+/// reads and whole-level sweeps need not walk the queue.
+#[derive(Debug, Clone, Default)]
+struct PriceLevel {
+    queue: VecDeque<OrderId>,
+    /// Sum of the queued orders' open quantities; wider than `Qty`, so it never saturates.
+    total: u128,
+}
+
+impl PriceLevel {
+    fn push_back(&mut self, order_id: OrderId, qty: Qty) {
+        self.queue.push_back(order_id);
+        self.total += u128::from(qty);
+    }
+
+    /// Move `other`'s orders behind this level's.
+    fn append(&mut self, mut other: PriceLevel) {
+        self.queue.append(&mut other.queue);
+        self.total += other.total;
+    }
+
+    /// `qty` of a queued order left the book (filled, cancelled or reduced).
+    fn release(&mut self, qty: Qty) {
+        self.total -= u128::from(qty);
+    }
+
+    /// Total open quantity, saturating at `Qty::MAX` like every quantity aggregate.
+    fn quantity(&self) -> Qty {
+        Qty::try_from(self.total).unwrap_or(Qty::MAX)
+    }
+
+    fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+}
 
 // Type alias for shared OrderBook state
 pub type SharedOrderBook = Arc<RwLock<OrderBook>>;
 
+/// Trading phase of a book. During `Auction`, limit orders rest without matching until
+/// `run_auction` uncrosses the book at a single clearing price.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub enum TradingPhase {
+    #[default]
+    Continuous,
+    Auction,
+}
+
+/// Price a crossing limit order trades at, set per book from its symbol config. Market orders
+/// have no limit price and always trade at the maker's price; auctions use their clearing price.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub enum PricingPolicy {
+    /// The resting order's price (price improvement goes to the taker). The default.
+    #[default]
+    MakerPrice,
+    /// The incoming order's limit price (price improvement goes to the maker).
+    TakerPrice,
+    /// Halfway between the two, rounded toward the maker's price onto the tick grid.
+    Midpoint,
+}
+
+impl PricingPolicy {
+    /// Execution price for a maker resting at `maker` crossed by a taker limited at `taker`
+    /// (None for market orders). The result always lies between the two prices.
+    pub fn execution_price(self, maker: Price, taker: Option<Price>, tick_size: Price) -> Price {
+        let Some(taker) = taker else {
+            return maker;
+        };
+        match self {
+            PricingPolicy::MakerPrice => maker,
+            PricingPolicy::TakerPrice => taker,
+            PricingPolicy::Midpoint => {
+                let (maker, taker) = (maker as i128, taker as i128);
+                let tick = tick_size.max(1) as i128;
+                // Halving truncates toward zero, i.e. toward the maker's side of the spread
+                let mid = maker + (taker - maker) / 2;
+                let price = if taker >= maker {
+                    (mid.div_euclid(tick) * tick).max(maker)
+                } else {
+                    (-(-mid).div_euclid(tick) * tick).min(maker)
+                };
+                price as Price
+            }
+        }
+    }
+}
+
+/// Outcome of a call auction: the clearing price (None if nothing crossed), the executed
+/// volume, and the trades created at that price.
+#[derive(Debug, Clone)]
+pub struct AuctionResult {
+    pub clearing_price: Option<Price>,
+    pub volume: Qty,
+    pub trades: Vec<Trade>,
+}
+
+/// Outcome of submitting an order: the order after matching, the trades it created, and why
+/// it was rejected, if it was. A rejected order never rests and ends `Cancelled`; with
+/// `BookFull` its trades still stand and only the remainder was cancelled.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExecutionReport {
+    pub order: Order,
+    pub trades: Vec<Trade>,
+    pub rejection: Option<RejectReason>,
+}
+
+/// Why `OrderBook::reduce_order` left the book unchanged.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReduceError {
+    /// The order is not resting (filled, cancelled or never placed).
+    NotResting,
+    /// The new quantity is zero or above the `remaining` open quantity.
+    InvalidQuantity { remaining: Qty },
+}
+
+/// Aggregated price levels (best first, at most `depth` per side) at book sequence `seq`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BookSnapshot {
+    pub seq: u64,
+    pub captured_at: DateTime<Utc>,
+    pub bids: Vec<(Price, Qty)>,
+    pub asks: Vec<(Price, Qty)>,
+    /// Nothing rests on either side, whatever the depth.
+    pub is_empty: bool,
+    /// `book_checksum` of every level, whatever the depth.
+    pub checksum: u32,
+}
+
 pub struct OrderBook {
     bids: BTreeMap<Price, PriceLevel>,
     asks: BTreeMap<Price, PriceLevel>,
     orders: HashMap<OrderId, Order>,
     trades: VecDeque<Trade>,
+    /// Fees and realized P&L of retained trades, by trade id; dropped with the trade.
+    attributions: HashMap<Uuid, TradeAttribution>,
+    /// Busted retained trades, by trade id; dropped with the trade. Busted trades stay
+    /// retained (for `/trades/me`) but are left out of public listings and the last price.
+    busts: HashMap<Uuid, TradeBust>,
+    /// A longer run of recent trades than `trades`, for replay by sequence.
+    archive: TradeArchive,
+    /// Level changes published to the diff stream, recent ones kept for clients that missed
+    /// some.
+    diffs: DiffBuffer,
+    /// How recently cancelled orders left, for a cancel that arrives after another one.
+    closed: ClosedOrders,
+    phase: TradingPhase,
+    /// Sequence of the last trade created; the next trade gets `last_trade_seq + 1`.
+    last_trade_seq: u64,
+    /// Bumped once per call that changes resting orders (add, fill, cancel, auction, restore),
+    /// and by `touch`.
+    book_seq: u64,
+    /// Source of order, trade and snapshot timestamps.
+    clock: SharedClock,
+    /// Time-to-fill, fill-ratio and liquidity-role statistics, updated on every fill and cancel.
+    matching_stats: MatchingStats,
+    /// Resting order caps, shared with every other book.
+    limits: SharedOrderLimits,
+    /// Resting orders this book has counted into `limits`.
+    counted_resting: usize,
+    /// How crossing limit orders are priced, and the tick grid `Midpoint` rounds onto.
+    pricing_policy: PricingPolicy,
+    tick_size: Price,
+}
+
+impl Drop for OrderBook {
+    fn drop(&mut self) {
+        self.limits.adjust(self.counted_resting, 0);
+    }
 }
 
 impl Default for OrderBook {
@@ -28,14 +197,219 @@ impl Default for OrderBook {
 
 impl OrderBook {
     pub fn new() -> Self {
+        Self::with_clock(Arc::new(SystemClock))
+    }
+
+    /// A book that reads timestamps from `clock` (e.g. a `ManualClock` in replays).
+    pub fn with_clock(clock: SharedClock) -> Self {
         Self {
             bids: BTreeMap::new(),
             asks: BTreeMap::new(),
             orders: HashMap::new(),
             trades: VecDeque::new(),
+            attributions: HashMap::new(),
+            busts: HashMap::new(),
+            archive: TradeArchive::default(),
+            diffs: DiffBuffer::default(),
+            closed: ClosedOrders::default(),
+            phase: TradingPhase::Continuous,
+            last_trade_seq: 0,
+            book_seq: 0,
+            clock,
+            matching_stats: MatchingStats::default(),
+            limits: Arc::new(OrderLimits::default()),
+            counted_resting: 0,
+            pricing_policy: PricingPolicy::default(),
+            tick_size: 1,
         }
     }
 
+    /// Read timestamps from `clock` from now on.
+    pub fn set_clock(&mut self, clock: SharedClock) {
+        self.clock = clock;
+    }
+
+    /// Count this book's resting orders against `limits` (shared with the other books)
+    /// instead of its own unlimited ones.
+    pub fn set_order_limits(&mut self, limits: SharedOrderLimits) {
+        self.limits.adjust(self.counted_resting, 0);
+        limits.adjust(0, self.counted_resting);
+        self.limits = limits;
+    }
+
+    pub fn order_limits(&self) -> &SharedOrderLimits {
+        &self.limits
+    }
+
+    /// Orders resting in the book.
+    pub fn resting_count(&self) -> usize {
+        self.orders.len()
+    }
+
+    /// Bring the shared resting count in line after orders rested or left.
+    fn sync_resting_count(&mut self) {
+        self.limits.adjust(self.counted_resting, self.orders.len());
+        self.counted_resting = self.orders.len();
+    }
+
+    /// Price later crossing limit orders with `policy`; `tick_size` is the symbol's tick.
+    pub fn set_pricing_policy(&mut self, policy: PricingPolicy, tick_size: Price) {
+        self.pricing_policy = policy;
+        self.tick_size = tick_size;
+    }
+
+    /// Count a change outside the orders (e.g. to the symbol's scales, which `/book` responses
+    /// carry) as a book change, so nothing rendered at the old sequence is served again.
+    pub fn touch(&mut self) {
+        self.book_seq += 1;
+    }
+
+    pub fn pricing_policy(&self) -> PricingPolicy {
+        self.pricing_policy
+    }
+
+    /// Price of a trade between a maker resting at `maker_price` and `taker`.
+    fn execution_price(&self, maker_price: Price, taker: &Order) -> Price {
+        let limit = (taker.order_type == OrderType::Limit).then_some(taker.price);
+        self.pricing_policy
+            .execution_price(maker_price, limit, self.tick_size)
+    }
+
+    pub fn matching_stats(&self) -> &MatchingStats {
+        &self.matching_stats
+    }
+
+    pub fn trade_archive(&self) -> &TradeArchive {
+        &self.archive
+    }
+
+    /// Archive the `capacity` most recent trades (see `trade_archive`).
+    pub fn set_trade_archive_capacity(&mut self, capacity: usize) {
+        self.archive.set_capacity(capacity);
+    }
+
+    pub fn diff_buffer(&self) -> &DiffBuffer {
+        &self.diffs
+    }
+
+    /// Buffer the `capacity` most recent diffs (see `diff_buffer`).
+    pub fn set_diff_buffer_capacity(&mut self, capacity: usize) {
+        self.diffs.set_capacity(capacity);
+    }
+
+    /// Publish the current levels to the diff stream: the change since they were last
+    /// published, buffered, or None if no level changed. Called wherever book updates are
+    /// broadcast, whether anyone listens or not, so the buffer has no gaps.
+    pub fn publish_diff(&mut self) -> Option<BookDiff> {
+        let (bids, asks) = (self.get_bids(), self.get_asks());
+        self.diffs.publish(self.book_seq, bids, asks)
+    }
+
+    /// Remember that `closed` was cancelled off this book (see `closed_order`).
+    pub fn record_closed(&mut self, closed: ClosedOrder) {
+        self.closed.record(closed);
+    }
+
+    /// How `order_id` left this book, if a cancel took it off recently.
+    pub fn closed_order(&self, order_id: OrderId) -> Option<&ClosedOrder> {
+        self.closed.get(order_id)
+    }
+
+    /// Keep the `window` most recent samples for matching statistics (resets them).
+    pub fn set_matching_window(&mut self, window: usize) {
+        self.matching_stats = MatchingStats::new(window);
+    }
+
+    /// Sequence number of the most recent trade (0 if none yet).
+    pub fn last_trade_seq(&self) -> u64 {
+        self.last_trade_seq
+    }
+
+    /// Current time on the book's clock.
+    pub fn now(&self) -> DateTime<Utc> {
+        self.clock.now()
+    }
+
+    /// Price of the most recent retained trade that was not busted.
+    pub fn last_trade_price(&self) -> Option<Price> {
+        self.public_trades().next_back().map(|t| t.price)
+    }
+
+    /// Sequence of the book state: two snapshots with the same value show the same orders.
+    pub fn book_seq(&self) -> u64 {
+        self.book_seq
+    }
+
+    /// Level, order and trade counts, estimated memory and timing of the book, for
+    /// introspection. Walks every resting order, so it costs O(n) under the caller's lock.
+    pub fn stats(&self) -> BookStats {
+        let levels = self.bids.len() + self.asks.len();
+        // A resting order is a map entry plus its id in a level's queue
+        let estimated_bytes = self.orders.len()
+            * (size_of::<OrderId>() + size_of::<Order>() + size_of::<OrderId>())
+            + levels * (size_of::<Price>() + size_of::<PriceLevel>())
+            + self.trades.len() * size_of::<Trade>()
+            + self.attributions.len() * (size_of::<Uuid>() + size_of::<TradeAttribution>())
+            + self.busts.len() * (size_of::<Uuid>() + size_of::<TradeBust>())
+            + self.archive.estimated_bytes()
+            + self.diffs.estimated_bytes();
+        let last_trade = self.public_trades().next_back();
+        BookStats {
+            bid_levels: self.bids.len(),
+            ask_levels: self.asks.len(),
+            resting_orders: self.orders.len(),
+            retained_trades: self.trades.len(),
+            archived_trades: self.archive.len(),
+            archive_capacity: self.archive.capacity(),
+            archive_oldest_seq: self.archive.oldest_available_seq(),
+            archive_evicted: self.archive.evicted(),
+            buffered_diffs: self.diffs.len(),
+            diff_buffer_capacity: self.diffs.capacity(),
+            diffs_evicted: self.diffs.evicted(),
+            diff_buffer_bytes: self.diffs.estimated_bytes(),
+            estimated_bytes,
+            book_seq: self.book_seq,
+            last_trade_seq: self.last_trade_seq,
+            last_trade_price: last_trade.map(|t| t.price),
+            last_trade_at: last_trade.map(|t| t.timestamp),
+            oldest_resting_at: self.orders.values().map(|o| o.timestamp).min(),
+        }
+    }
+
+    /// Top `depth` levels per side, tagged with the current book sequence.
+    pub fn snapshot(&self, depth: usize) -> BookSnapshot {
+        let mut bids = self.get_bids();
+        let mut asks = self.get_asks();
+        let is_empty = bids.is_empty() && asks.is_empty();
+        let checksum = book_checksum(&bids, &asks);
+        bids.truncate(depth);
+        asks.truncate(depth);
+        BookSnapshot {
+            seq: self.book_seq,
+            captured_at: self.clock.now(),
+            bids,
+            asks,
+            is_empty,
+            checksum,
+        }
+    }
+
+    /// Drop every retained and archived trade. Sequencing continues from `last_trade_seq`, so
+    /// later trades never reuse a sequence.
+    pub fn clear_trades(&mut self) {
+        self.trades.clear();
+        self.archive.clear();
+        self.attributions.clear();
+        self.busts.clear();
+    }
+
+    /// Resume trade sequencing after `seq` (e.g. `MAX(trade_seq)` from the DB at startup),
+    /// so sequences never repeat across restarts.
+    pub fn set_last_trade_seq(&mut self, seq: u64) {
+        self.last_trade_seq = seq;
+        self.archive.resume_after(seq);
+    }
+
     #[allow(clippy::too_many_arguments)]
     pub fn add_order(
         &mut self,
@@ -46,9 +420,37 @@ impl OrderBook {
         order_type: OrderType,
         ws_channel: Option<&broadcast::Sender<crate::api::routes::WsMessage>>,
         symbol: Option<&str>,
-    ) -> (Order, Vec<Trade>) {
+    ) -> ExecutionReport {
+        self.add_order_with_tags(
+            user_id,
+            price,
+            qty,
+            side,
+            order_type,
+            OrderTags::new(),
+            OrderSource::Api,
+            ws_channel,
+            symbol,
+        )
+    }
+
+    /// `add_order` with client tags and the entry point stored on the order (neither affects
+    /// matching).
+    #[allow(clippy::too_many_arguments)]
+    pub fn add_order_with_tags(
+        &mut self,
+        user_id: Uuid,
+        price: Price,
+        qty: Qty,
+        side: OrderSide,
+        order_type: OrderType,
+        tags: OrderTags,
+        source: OrderSource,
+        ws_channel: Option<&broadcast::Sender<crate::api::routes::WsMessage>>,
+        symbol: Option<&str>,
+    ) -> ExecutionReport {
         // Create the order
-        let order = Order {
+        let mut order = Order {
             id: Uuid::new_v4(),
             user_id,
             side,
@@ -56,11 +458,58 @@ impl OrderBook {
             price,
             quantity: qty,
             status: OrderStatus::Pending,
-            timestamp: Utc::now(),
+            timestamp: self.clock.now(),
+            tags,
+            source,
         };
 
-        // Try to match the order first
-        let (trades, matched_order) = self.match_order(order);
+        if order.order_type == OrderType::Market && self.phase == TradingPhase::Auction {
+            order.status = OrderStatus::Cancelled;
+            let report = ExecutionReport {
+                order,
+                trades: Vec::new(),
+                rejection: Some(RejectReason::MarketOrderInAuction),
+            };
+            if let (Some(channel), Some(sym)) = (ws_channel, symbol) {
+                crate::api::ws::broadcast_order_update(channel, sym, &report);
+            }
+            return report;
+        }
+
+        // Try to match the order first (orders only accumulate during an auction)
+        let (trades, mut matched_order) = if self.phase == TradingPhase::Auction {
+            (Vec::new(), order)
+        } else {
+            self.match_order(order)
+        };
+        #[cfg(feature = "fault-injection")]
+        if !trades.is_empty()
+            && matched_order
+                .tags
+                .contains_key(crate::api::incidents::PANIC_TAG)
+        {
+            panic!(
+                "deliberate matching panic ({})",
+                crate::api::incidents::PANIC_TAG
+            );
+        }
+        let mut rejection = (matched_order.order_type == OrderType::Market && trades.is_empty())
+            .then_some(RejectReason::NoLiquidity);
+        if rejection.is_some() {
+            matched_order.status = OrderStatus::Cancelled;
+        }
+
+        // A remainder that would rest past a cap is cancelled; the fills stand
+        let mut rests = matched_order.quantity > 0 && matched_order.order_type == OrderType::Limit;
+        if rests {
+            if self.limits.try_reserve(self.orders.len(), self.clock.now()) {
+                self.counted_resting += 1;
+            } else {
+                rests = false;
+                matched_order.status = OrderStatus::Cancelled;
+                rejection = Some(RejectReason::BookFull);
+            }
+        }
 
         // Store all trades
         self.store_trades(trades.clone());
@@ -70,37 +519,300 @@ impl OrderBook {
             crate::api::ws::broadcast_trades(channel, sym, &trades);
         }
 
+        if rests || !trades.is_empty() {
+            self.book_seq += 1;
+        }
+
         // If there's remaining quantity, add it to the book (limit orders only; market orders do not rest)
-        if matched_order.quantity > 0 && matched_order.order_type == OrderType::Limit {
+        if rests {
             let order_id = matched_order.id;
 
             // Store order in lookup map
             self.orders.insert(order_id, matched_order.clone());
 
             // Add only OrderId to price level (FIFO queue)
-            match matched_order.side {
-                OrderSide::Buy => {
-                    self.bids
-                        .entry(matched_order.price)
-                        .or_default()
-                        .push_back(order_id);
+            let levels = match matched_order.side {
+                OrderSide::Buy => &mut self.bids,
+                OrderSide::Sell => &mut self.asks,
+            };
+            levels
+                .entry(matched_order.price)
+                .or_default()
+                .push_back(order_id, matched_order.quantity);
+        }
+        // If quantity is 0, order is fully filled and already has correct status
+
+        let report = ExecutionReport {
+            order: matched_order,
+            trades,
+            rejection,
+        };
+
+        // Broadcast orderbook update and the owner's execution report if channel is provided
+        if let (Some(channel), Some(sym)) = (ws_channel, symbol) {
+            crate::api::ws::broadcast_orderbook_update(channel, sym, self);
+            crate::api::ws::broadcast_order_update(channel, sym, &report);
+        }
+
+        report
+    }
+
+    /// Dry-run `add_order`: walk the opposite side exactly as matching would, without mutating
+    /// the book, storing trades, or broadcasting. The report has the order as it would look
+    /// after matching (remaining quantity and status), the trades that would be created, and
+    /// the rejection `add_order` would give.
+    pub fn simulate_order(
+        &self,
+        user_id: Uuid,
+        price: Price,
+        qty: Qty,
+        side: OrderSide,
+        order_type: OrderType,
+    ) -> ExecutionReport {
+        let mut order = Order {
+            id: Uuid::new_v4(),
+            user_id,
+            side,
+            order_type,
+            price,
+            quantity: qty,
+            status: OrderStatus::Pending,
+            timestamp: self.clock.now(),
+            tags: OrderTags::new(),
+            source: OrderSource::Api,
+        };
+        let mut trades = Vec::new();
+        if self.phase == TradingPhase::Auction {
+            let rejection =
+                (order_type == OrderType::Market).then_some(RejectReason::MarketOrderInAuction);
+            if rejection.is_some() {
+                order.status = OrderStatus::Cancelled;
+            }
+            return ExecutionReport {
+                order,
+                trades,
+                rejection,
+            };
+        }
+
+        // Best price first: lowest asks for a buy, highest bids for a sell
+        let levels: Box<dyn Iterator<Item = (&Price, &PriceLevel)>> = match side {
+            OrderSide::Buy => Box::new(self.asks.iter()),
+            OrderSide::Sell => Box::new(self.bids.iter().rev()),
+        };
+
+        let mut makers_filled = 0;
+        'levels: for (&level_price, queue) in levels {
+            if order.quantity == 0 {
+                break;
+            }
+            // Same price check as match_buy_order / match_sell_order
+            let crosses = match side {
+                OrderSide::Buy => order.price >= level_price,
+                OrderSide::Sell => order.price <= level_price,
+            };
+            if order.order_type != OrderType::Market && !crosses {
+                break;
+            }
+            // Makers ahead are consumed before the next one fills, so each is at the front
+            for maker_order_id in &queue.queue {
+                if order.quantity == 0 {
+                    break 'levels;
                 }
-                OrderSide::Sell => {
-                    self.asks
-                        .entry(matched_order.price)
-                        .or_default()
-                        .push_back(order_id);
+                let Some(maker_order) = self.orders.get(maker_order_id) else {
+                    continue;
+                };
+                let match_qty = order.quantity.min(maker_order.quantity);
+                if match_qty == maker_order.quantity {
+                    makers_filled += 1;
                 }
+                trades.push(Self::create_trade(
+                    self.last_trade_seq + trades.len() as u64 + 1,
+                    *maker_order_id,
+                    order.id,
+                    maker_order.user_id,
+                    order.user_id,
+                    self.execution_price(level_price, &order),
+                    match_qty,
+                    order.timestamp,
+                    0,
+                ));
+                order.quantity -= match_qty;
             }
         }
-        // If quantity is 0, order is fully filled and already has correct status
 
-        // Broadcast orderbook update if channel is provided
+        order.status = Self::update_order_status(qty, order.quantity);
+        let mut rejection = (order_type == OrderType::Market && trades.is_empty())
+            .then_some(RejectReason::NoLiquidity);
+        let rests = order.quantity > 0 && order_type == OrderType::Limit;
+        if rests
+            && !self
+                .limits
+                .admits(self.orders.len(), makers_filled, self.clock.now())
+        {
+            rejection = Some(RejectReason::BookFull);
+        }
+        if rejection.is_some() {
+            order.status = OrderStatus::Cancelled;
+        }
+        ExecutionReport {
+            order,
+            trades,
+            rejection,
+        }
+    }
+
+    pub fn phase(&self) -> TradingPhase {
+        self.phase
+    }
+
+    /// Switch the book into auction mode: incoming limit orders rest without matching.
+    pub fn start_auction(&mut self) {
+        self.phase = TradingPhase::Auction;
+    }
+
+    /// Price that maximizes executable volume over the cumulative bid/ask curves, with that
+    /// volume. Ties go to the smallest imbalance, then to the lowest price. None if nothing crosses.
+    pub fn auction_clearing_price(&self) -> Option<(Price, Qty)> {
+        let candidates: BTreeSet<Price> =
+            self.bids.keys().chain(self.asks.keys()).copied().collect();
+        let mut best: Option<(Price, Qty, Qty)> = None;
+        for price in candidates {
+            let demand =
+                total_quantity(self.bids.range(price..).map(|(_, level)| level.quantity()));
+            let supply =
+                total_quantity(self.asks.range(..=price).map(|(_, level)| level.quantity()));
+            let volume = demand.min(supply);
+            let imbalance = demand.abs_diff(supply);
+            let better = match best {
+                None => volume > 0,
+                Some((_, best_volume, best_imbalance)) => {
+                    volume > best_volume || (volume == best_volume && imbalance < best_imbalance)
+                }
+            };
+            if better {
+                best = Some((price, volume, imbalance));
+            }
+        }
+        best.map(|(price, volume, _)| (price, volume))
+    }
+
+    /// End the auction: execute every crossing order at the single clearing price in
+    /// price-time priority and return the book to continuous trading. Auction fills have no
+    /// aggressor; by convention the sell order is recorded as maker and the buy order as taker.
+    pub fn run_auction(
+        &mut self,
+        ws_channel: Option<&broadcast::Sender<crate::api::routes::WsMessage>>,
+        symbol: Option<&str>,
+    ) -> AuctionResult {
+        self.phase = TradingPhase::Continuous;
+        let now = self.clock.now();
+        let clearing = self.auction_clearing_price();
+        let mut trades = Vec::new();
+
+        if let Some((clearing_price, _)) = clearing {
+            while let (Some(bid_price), Some(ask_price)) = (self.best_bid(), self.best_ask()) {
+                if bid_price < clearing_price || ask_price > clearing_price {
+                    break;
+                }
+                let (Some(bid), Some(ask)) = (
+                    self.front_order(OrderSide::Buy, bid_price),
+                    self.front_order(OrderSide::Sell, ask_price),
+                ) else {
+                    continue;
+                };
+                let match_qty = bid.quantity.min(ask.quantity);
+                self.last_trade_seq += 1;
+                trades.push(Self::create_trade(
+                    self.last_trade_seq,
+                    ask.id,
+                    bid.id,
+                    ask.user_id,
+                    bid.user_id,
+                    clearing_price,
+                    match_qty,
+                    now,
+                    0,
+                ));
+                self.matching_stats
+                    .record_fill(&bid, match_qty, Liquidity::Auction, now);
+                self.matching_stats
+                    .record_fill(&ask, match_qty, Liquidity::Auction, now);
+                self.fill_front_order(OrderSide::Buy, bid_price, match_qty);
+                self.fill_front_order(OrderSide::Sell, ask_price, match_qty);
+            }
+        }
+
+        if !trades.is_empty() {
+            self.book_seq += 1;
+        }
+        self.sync_resting_count();
+        self.store_trades(trades.clone());
+        let result = AuctionResult {
+            clearing_price: clearing.map(|(price, _)| price),
+            volume: total_quantity(trades.iter().map(|t| t.quantity)),
+            trades,
+        };
+
         if let (Some(channel), Some(sym)) = (ws_channel, symbol) {
+            crate::api::ws::broadcast_trades(channel, sym, &result.trades);
+            crate::api::ws::broadcast_auction_result(channel, sym, &result);
             crate::api::ws::broadcast_orderbook_update(channel, sym, self);
         }
 
-        (matched_order, trades)
+        result
+    }
+
+    // Helper: first live order at a price level; drops dangling ids (None if one was dropped)
+    fn front_order(&mut self, side: OrderSide, price: Price) -> Option<Order> {
+        let price_levels = match side {
+            OrderSide::Buy => &mut self.bids,
+            OrderSide::Sell => &mut self.asks,
+        };
+        let Entry::Occupied(mut entry) = price_levels.entry(price) else {
+            return None;
+        };
+        let level = entry.get_mut();
+        let order_id = level.queue.front().copied()?;
+        match self.orders.get(&order_id) {
+            Some(order) => Some(order.clone()),
+            None => {
+                level.queue.pop_front();
+                if level.is_empty() {
+                    entry.remove();
+                }
+                None
+            }
+        }
+    }
+
+    // Helper: reduce the first order at a price level by qty, removing it (and the level) when filled
+    fn fill_front_order(&mut self, side: OrderSide, price: Price, qty: Qty) {
+        let price_levels = match side {
+            OrderSide::Buy => &mut self.bids,
+            OrderSide::Sell => &mut self.asks,
+        };
+        let Entry::Occupied(mut entry) = price_levels.entry(price) else {
+            return;
+        };
+        let level = entry.get_mut();
+        let Some(order_id) = level.queue.front().copied() else {
+            return;
+        };
+        let Some(order) = self.orders.get_mut(&order_id) else {
+            return;
+        };
+        let original_qty = order.quantity;
+        order.quantity -= qty;
+        order.status = Self::update_order_status(original_qty, order.quantity);
+        level.release(qty);
+        if order.quantity == 0 {
+            level.queue.pop_front();
+            self.orders.remove(&order_id);
+            if level.is_empty() {
+                entry.remove();
+            }
+        }
     }
 
     pub fn best_bid(&self) -> Option<Price> {
@@ -130,17 +842,23 @@ impl OrderBook {
 
         // Remove order ID from the price level's queue
         if let Entry::Occupied(mut entry) = price_levels.entry(price) {
-            let queue = entry.get_mut();
-            queue.retain(|&oid| oid != order_id);
+            let level = entry.get_mut();
+            level.queue.retain(|&oid| oid != order_id);
+            level.release(order.quantity);
 
             // If the queue is now empty, remove this price level completely
-            if queue.is_empty() {
+            if level.is_empty() {
                 entry.remove();
             }
         }
 
         // Remove the order from the global order map
         let removed_order = self.orders.remove(&order_id);
+        self.book_seq += 1;
+        self.sync_resting_count();
+        if let Some(ref order) = removed_order {
+            self.matching_stats.record_cancel(order);
+        }
 
         // Broadcast orderbook update if channel is provided
         if let (Some(channel), Some(sym)) = (ws_channel, symbol) {
@@ -150,12 +868,271 @@ impl OrderBook {
         removed_order
     }
 
+    /// Remove every resting order of `user_id` accepted by `filter`, broadcasting one book update.
+    /// Returns the removed orders.
+    pub fn remove_orders_for_user(
+        &mut self,
+        user_id: Uuid,
+        filter: impl Fn(&Order) -> bool,
+        ws_channel: Option<&broadcast::Sender<crate::api::routes::WsMessage>>,
+        symbol: Option<&str>,
+    ) -> Vec<Order> {
+        let order_ids: Vec<OrderId> = self
+            .orders
+            .values()
+            .filter(|o| o.user_id == user_id && filter(o))
+            .map(|o| o.id)
+            .collect();
+        let removed: Vec<Order> = order_ids
+            .into_iter()
+            .filter_map(|order_id| self.remove_order(order_id, None, None))
+            .collect();
+
+        if !removed.is_empty()
+            && let (Some(channel), Some(sym)) = (ws_channel, symbol)
+        {
+            crate::api::ws::broadcast_orderbook_update(channel, sym, self);
+        }
+
+        removed
+    }
+
+    /// Cancel resting order `order_id` and submit a limit order for `price`/`qty` in its place,
+    /// inheriting owner, side, tags and source. The replacement gets a new id and joins the back
+    /// of its level; it matches like any new order. Only the replacement's submission
+    /// broadcasts, so subscribers see one book update. Returns the cancelled order (status
+    /// `Cancelled`) and the replacement's report, or None if `order_id` is not resting.
+    pub fn replace_order(
+        &mut self,
+        order_id: OrderId,
+        price: Price,
+        qty: Qty,
+        ws_channel: Option<&broadcast::Sender<crate::api::routes::WsMessage>>,
+        symbol: Option<&str>,
+    ) -> Option<(Order, ExecutionReport)> {
+        let mut cancelled = self.remove_order(order_id, None, None)?;
+        cancelled.status = OrderStatus::Cancelled;
+        let report = self.add_order_with_tags(
+            cancelled.user_id,
+            price,
+            qty,
+            cancelled.side,
+            OrderType::Limit,
+            cancelled.tags.clone(),
+            cancelled.source,
+            ws_channel,
+            symbol,
+        );
+        Some((cancelled, report))
+    }
+
+    /// Trim resting order `order_id` to `new_qty` in place. Unlike `replace_order` the order
+    /// keeps its id and its place in the level's queue. `new_qty` must be positive and at most
+    /// what is still open; reducing to the open quantity changes nothing. Broadcasts one book
+    /// update when the quantity changed. Returns the order as it now rests.
+    pub fn reduce_order(
+        &mut self,
+        order_id: OrderId,
+        new_qty: Qty,
+        ws_channel: Option<&broadcast::Sender<crate::api::routes::WsMessage>>,
+        symbol: Option<&str>,
+    ) -> Result<Order, ReduceError> {
+        let order = self
+            .orders
+            .get_mut(&order_id)
+            .ok_or(ReduceError::NotResting)?;
+        if new_qty == 0 || new_qty > order.quantity {
+            return Err(ReduceError::InvalidQuantity {
+                remaining: order.quantity,
+            });
+        }
+        if new_qty == order.quantity {
+            return Ok(order.clone());
+        }
+        let price_levels = match order.side {
+            OrderSide::Buy => &mut self.bids,
+            OrderSide::Sell => &mut self.asks,
+        };
+        if let Some(level) = price_levels.get_mut(&order.price) {
+            level.release(order.quantity - new_qty);
+        }
+        order.quantity = new_qty;
+        let reduced = order.clone();
+        self.book_seq += 1;
+
+        if let (Some(channel), Some(sym)) = (ws_channel, symbol) {
+            crate::api::ws::broadcast_orderbook_update(channel, sym, self);
+        }
+        Ok(reduced)
+    }
+
     pub fn get_order_by_id(&self, order_id: OrderId) -> Option<Order> {
         self.orders.get(&order_id).cloned()
     }
 
+    /// Iterate resting orders in priority order: bids best (highest) price first, then asks
+    /// best (lowest) price first, each level in time priority. Yields the 0-based queue
+    /// position within the order's price level alongside the order.
+    pub fn iter_orders(&self) -> impl Iterator<Item = (usize, &Order)> {
+        self.bids
+            .values()
+            .rev()
+            .chain(self.asks.values())
+            .flat_map(|level| {
+                level
+                    .queue
+                    .iter()
+                    .filter_map(|order_id| self.orders.get(order_id))
+                    .enumerate()
+            })
+    }
+
+    /// Resting orders matching `predicate`, in `iter_orders` order.
+    pub fn find_orders(&self, mut predicate: impl FnMut(&Order) -> bool) -> Vec<&Order> {
+        self.iter_orders()
+            .map(|(_, order)| order)
+            .filter(|order| predicate(order))
+            .collect()
+    }
+
+    /// Swap every resting order for `orders` (restored as by `restore_orders`), keeping
+    /// retained trades and sequencing; how a replica mirrors the primary's book. Counts as a
+    /// book change. Returns the number restored.
+    pub fn replace_resting_orders(&mut self, orders: Vec<Order>) -> Result<usize, String> {
+        self.orders.clear();
+        self.bids.clear();
+        self.asks.clear();
+        self.book_seq += 1;
+        self.restore_orders(orders)
+    }
+
+    /// Bulk `restore_order` for warm starts: sorts each side once by (price, timestamp, id) and
+    /// builds every price level in a single pass instead of one entry lookup per order. Orders
+    /// `restore_order` would ignore (zero quantity, non-limit) are skipped, as are ids already
+    /// in the book, which make this an error once the rest are restored. Counts as one book
+    /// change. Resting order caps do not apply. Returns the number restored.
+    pub fn restore_orders(&mut self, orders: Vec<Order>) -> Result<usize, String> {
+        // Sort small (price, timestamp, id, quantity) keys rather than whole orders
+        let mut bid_keys = Vec::new();
+        let mut ask_keys = Vec::new();
+        let mut duplicates = 0;
+        self.orders.reserve(orders.len());
+        for order in orders {
+            if order.quantity == 0 || order.order_type != OrderType::Limit {
+                continue;
+            }
+            let key = (order.price, order.timestamp, order.id, order.quantity);
+            let side = order.side;
+            if let Some(existing) = self.orders.insert(order.id, order) {
+                self.orders.insert(existing.id, existing);
+                duplicates += 1;
+                continue;
+            }
+            match side {
+                OrderSide::Buy => bid_keys.push(key),
+                OrderSide::Sell => ask_keys.push(key),
+            }
+        }
+        let restored = bid_keys.len() + ask_keys.len();
+        for (keys, levels) in [
+            (&mut bid_keys, &mut self.bids),
+            (&mut ask_keys, &mut self.asks),
+        ] {
+            keys.sort_unstable();
+            let built = keys.chunk_by(|a, b| a.0 == b.0).map(|level| {
+                let queue = level.iter().map(|&(_, _, id, _)| id).collect();
+                let total = level.iter().map(|&(_, _, _, qty)| u128::from(qty)).sum();
+                (level[0].0, PriceLevel { queue, total })
+            });
+            if levels.is_empty() {
+                *levels = built.collect();
+            } else {
+                for (price, level) in built {
+                    levels.entry(price).or_default().append(level);
+                }
+            }
+        }
+        if restored > 0 {
+            self.book_seq += 1;
+        }
+        self.sync_resting_count();
+
+        // Every resting order queued exactly once; the full per-order scan is debug-only
+        let queued: usize = self
+            .bids
+            .values()
+            .chain(self.asks.values())
+            .map(|level| level.queue.len())
+            .sum();
+        if queued != self.orders.len() {
+            return Err(format!(
+                "{} orders in the book but {} queued",
+                self.orders.len(),
+                queued
+            ));
+        }
+        debug_assert_eq!(self.check_invariants(), Ok(()));
+        if duplicates > 0 {
+            return Err(format!(
+                "{} orders skipped: id already in the book",
+                duplicates
+            ));
+        }
+        Ok(restored)
+    }
+
+    /// Check that price levels and the order map agree: every queued id is a resting order of
+    /// that side and price with quantity left, listed once, every resting order is queued, no
+    /// level is empty, and each level's cached total is the sum of its orders.
+    pub fn check_invariants(&self) -> Result<(), String> {
+        let mut queued = 0;
+        let mut seen = HashSet::with_capacity(self.orders.len());
+        for (side, levels) in [(OrderSide::Buy, &self.bids), (OrderSide::Sell, &self.asks)] {
+            for (&price, level) in levels {
+                if level.is_empty() {
+                    return Err(format!("empty {:?} level at {}", side, price));
+                }
+                let mut total = 0;
+                for order_id in &level.queue {
+                    let order = self
+                        .orders
+                        .get(order_id)
+                        .ok_or_else(|| format!("queued order {} is not in the book", order_id))?;
+                    if order.side != side || order.price != price {
+                        return Err(format!(
+                            "order {} ({:?} at {}) is queued at {:?} {}",
+                            order_id, order.side, order.price, side, price
+                        ));
+                    }
+                    if order.quantity == 0 {
+                        return Err(format!("order {} rests with no quantity", order_id));
+                    }
+                    if !seen.insert(*order_id) {
+                        return Err(format!("order {} is queued more than once", order_id));
+                    }
+                    total += u128::from(order.quantity);
+                    queued += 1;
+                }
+                if level.total != total {
+                    return Err(format!(
+                        "{:?} level at {} caches {} but holds {}",
+                        side, price, level.total, total
+                    ));
+                }
+            }
+        }
+        if queued != self.orders.len() {
+            return Err(format!(
+                "{} orders in the book but {} queued",
+                self.orders.len(),
+                queued
+            ));
+        }
+        Ok(())
+    }
+
     /// Restore an open order into the book without matching (for hydration from DB).
-    /// Call only for Pending/PartiallyFilled Limit orders.
+    /// Call only for Pending/PartiallyFilled Limit orders. Resting order caps do not apply.
     pub fn restore_order(&mut self, order: Order) {
         if order.quantity == 0 {
             return;
@@ -165,188 +1142,159 @@ impl OrderBook {
         }
         let order_id = order.id;
         self.orders.insert(order_id, order.clone());
-        match order.side {
-            OrderSide::Buy => self
-                .bids
-                .entry(order.price)
-                .or_default()
-                .push_back(order_id),
-            OrderSide::Sell => self
-                .asks
-                .entry(order.price)
-                .or_default()
-                .push_back(order_id),
-        }
+        self.book_seq += 1;
+        self.sync_resting_count();
+        let levels = match order.side {
+            OrderSide::Buy => &mut self.bids,
+            OrderSide::Sell => &mut self.asks,
+        };
+        levels
+            .entry(order.price)
+            .or_default()
+            .push_back(order_id, order.quantity);
     }
 
     // Match a buy order against asks
     // Iterate through asks from lowest price, match until order filled or no more matches
     pub fn match_buy_order(&mut self, order: &mut Order) -> Vec<Trade> {
-        let mut trades = Vec::new();
-        let original_qty = order.quantity;
-
-        // Continue matching while there are asks and buy price >= ask price
-        while order.quantity > 0 {
-            // Get best ask price
-            let ask_price = match self.best_ask() {
-                Some(price) => price,
-                None => break, // No more asks to match
-            };
-
-            // Check if buy order can match (limit: buy price must be >= ask price; market: no price check)
-            if order.order_type != OrderType::Market && order.price < ask_price {
-                break; // Can't match, price too low
-            }
-
-            // Get the price level queue for this ask price
-            if let Entry::Occupied(mut entry) = self.asks.entry(ask_price) {
-                let queue = entry.get_mut();
-
-                // Get first order from queue (FIFO)
-                if let Some(maker_order_id) = queue.front().copied() {
-                    // Lookup full maker order (clone to avoid borrow issues)
-                    if let Some(maker_order) = self.orders.get(&maker_order_id).cloned() {
-                        // Calculate match quantity (min of both)
-                        let match_qty = order.quantity.min(maker_order.quantity);
-
-                        // Create trade (maker price = ask price)
-                        let trade = Self::create_trade(
-                            maker_order_id,
-                            order.id,
-                            maker_order.user_id,
-                            order.user_id,
-                            ask_price,
-                            match_qty,
-                        );
-                        trades.push(trade);
-
-                        // Update incoming order quantity
-                        order.quantity -= match_qty;
-                        order.status = Self::update_order_status(original_qty, order.quantity);
-
-                        // Update maker order
-                        let mut updated_maker = maker_order;
-                        updated_maker.quantity -= match_qty;
-                        let maker_original_qty = updated_maker.quantity + match_qty;
-                        updated_maker.status =
-                            Self::update_order_status(maker_original_qty, updated_maker.quantity);
-
-                        // If maker order is fully filled, remove it
-                        if updated_maker.quantity == 0 {
-                            queue.pop_front(); // Remove from queue (FIFO)
-                            self.orders.remove(&maker_order_id); // Remove from HashMap
-
-                            // If price level is now empty, remove it
-                            if queue.is_empty() {
-                                entry.remove();
-                            }
-                        } else {
-                            // Update maker order in HashMap
-                            self.orders.insert(maker_order_id, updated_maker);
-                        }
-                    } else {
-                        // Order not found in HashMap (shouldn't happen, but handle gracefully)
-                        queue.pop_front(); // Remove invalid reference
-                        if queue.is_empty() {
-                            entry.remove();
-                        }
-                    }
-                } else {
-                    // Queue is empty, remove price level
-                    entry.remove();
-                }
-            } else {
-                break; // Price level doesn't exist (shouldn't happen after best_ask check)
-            }
-        }
-
-        trades
+        self.sweep(order, OrderSide::Sell)
     }
 
     // Match a sell order against bids
     // Iterate through bids from highest price, match until order filled or no more matches
     pub fn match_sell_order(&mut self, order: &mut Order) -> Vec<Trade> {
+        self.sweep(order, OrderSide::Buy)
+    }
+
+    /// Fill `order` against the `maker_side` levels, best price first and FIFO within a level,
+    /// until it is filled or the best level no longer crosses (market orders cross any level).
+    /// Each step takes the best level straight from its map, and a level the order outsizes is
+    /// consumed whole: its makers are filled in queue order and the level dropped at once.
+    fn sweep(&mut self, order: &mut Order, maker_side: OrderSide) -> Vec<Trade> {
+        let now = self.clock.now();
         let mut trades = Vec::new();
         let original_qty = order.quantity;
+        let limit = (order.order_type == OrderType::Limit).then_some(order.price);
 
-        // Continue matching while there are bids and sell price <= bid price
         while order.quantity > 0 {
-            // Get best bid price
-            let bid_price = match self.best_bid() {
-                Some(price) => price,
-                None => break, // No more bids to match
+            let best = match maker_side {
+                OrderSide::Buy => self.bids.last_entry(),
+                OrderSide::Sell => self.asks.first_entry(),
             };
+            let Some(mut entry) = best else {
+                break; // No more makers to match
+            };
+            let level_price = *entry.key();
+            let crosses = match maker_side {
+                OrderSide::Buy => order.price <= level_price,
+                OrderSide::Sell => order.price >= level_price,
+            };
+            if order.order_type != OrderType::Market && !crosses {
+                break;
+            }
 
-            // Check if sell order can match (limit: sell price must be <= bid price; market: no price check)
-            if order.order_type != OrderType::Market && order.price > bid_price {
-                break; // Can't match, price too low
+            // Trades at this level execute at the book's policy price (by default the maker's)
+            let price = self
+                .pricing_policy
+                .execution_price(level_price, limit, self.tick_size);
+
+            // Whole level: every maker fills completely, in queue order
+            if u128::from(order.quantity) >= entry.get().total {
+                for maker_order_id in entry.remove().queue {
+                    // Skip ids with no order behind them (shouldn't happen)
+                    let Some(maker_order) = self.orders.remove(&maker_order_id) else {
+                        continue;
+                    };
+                    let match_qty = maker_order.quantity;
+                    trades.push(Self::execute(
+                        &mut self.last_trade_seq,
+                        &mut self.matching_stats,
+                        order,
+                        &maker_order,
+                        price,
+                        match_qty,
+                        now,
+                    ));
+                    order.quantity -= match_qty;
+                    order.status = Self::update_order_status(original_qty, order.quantity);
+                }
+                continue;
             }
 
-            // Get the price level queue for this bid price
-            if let Entry::Occupied(mut entry) = self.bids.entry(bid_price) {
-                let queue = entry.get_mut();
-
-                // Get first order from queue (FIFO)
-                if let Some(maker_order_id) = queue.front().copied() {
-                    // Lookup full maker order (clone to avoid borrow issues)
-                    if let Some(maker_order) = self.orders.get(&maker_order_id).cloned() {
-                        // Calculate match quantity (min of both)
-                        let match_qty = order.quantity.min(maker_order.quantity);
-
-                        // Create trade (maker price = bid price)
-                        let trade = Self::create_trade(
-                            maker_order_id,
-                            order.id,
-                            maker_order.user_id,
-                            order.user_id,
-                            bid_price,
-                            match_qty,
-                        );
-                        trades.push(trade);
-
-                        // Update incoming order quantity
-                        order.quantity -= match_qty;
-                        order.status = Self::update_order_status(original_qty, order.quantity);
-
-                        // Update maker order
-                        let mut updated_maker = maker_order;
-                        updated_maker.quantity -= match_qty;
-                        let maker_original_qty = updated_maker.quantity + match_qty;
-                        updated_maker.status =
-                            Self::update_order_status(maker_original_qty, updated_maker.quantity);
-
-                        // If maker order is fully filled, remove it
-                        if updated_maker.quantity == 0 {
-                            queue.pop_front(); // Remove from queue (FIFO)
-                            self.orders.remove(&maker_order_id); // Remove from HashMap
-
-                            // If price level is now empty, remove it
-                            if queue.is_empty() {
-                                entry.remove();
-                            }
-                        } else {
-                            // Update maker order in HashMap
-                            self.orders.insert(maker_order_id, updated_maker);
-                        }
-                    } else {
-                        // Order not found in HashMap (shouldn't happen, but handle gracefully)
-                        queue.pop_front(); // Remove invalid reference
-                        if queue.is_empty() {
-                            entry.remove();
-                        }
-                    }
-                } else {
-                    // Queue is empty, remove price level
+            // Otherwise the order fills before the level runs out: take makers off the front
+            let level = entry.get_mut();
+            let Some(maker_order_id) = level.queue.front().copied() else {
+                entry.remove();
+                continue;
+            };
+            let Some(maker_order) = self.orders.get_mut(&maker_order_id) else {
+                // Order not found in HashMap (shouldn't happen, but handle gracefully)
+                level.queue.pop_front();
+                if level.is_empty() {
+                    entry.remove();
+                }
+                continue;
+            };
+            let match_qty = order.quantity.min(maker_order.quantity);
+            trades.push(Self::execute(
+                &mut self.last_trade_seq,
+                &mut self.matching_stats,
+                order,
+                maker_order,
+                price,
+                match_qty,
+                now,
+            ));
+            order.quantity -= match_qty;
+            order.status = Self::update_order_status(original_qty, order.quantity);
+
+            let maker_original_qty = maker_order.quantity;
+            maker_order.quantity -= match_qty;
+            maker_order.status =
+                Self::update_order_status(maker_original_qty, maker_order.quantity);
+            level.release(match_qty);
+            // If maker order is fully filled, remove it (and the level once empty)
+            if maker_order.quantity == 0 {
+                level.queue.pop_front();
+                self.orders.remove(&maker_order_id);
+                if level.is_empty() {
                     entry.remove();
                 }
-            } else {
-                break; // Price level doesn't exist (shouldn't happen after best_bid check)
             }
         }
 
         trades
     }
 
+    /// Trade `qty` between `taker` and the front `maker` at `price`, counting the fill for
+    /// both in the matching stats. Both orders are as they were before the fill; the caller
+    /// updates their quantities.
+    fn execute(
+        last_trade_seq: &mut u64,
+        matching_stats: &mut MatchingStats,
+        taker: &Order,
+        maker: &Order,
+        price: Price,
+        qty: Qty,
+        now: DateTime<Utc>,
+    ) -> Trade {
+        *last_trade_seq += 1;
+        let trade = Self::create_trade(
+            *last_trade_seq,
+            maker.id,
+            taker.id,
+            maker.user_id,
+            taker.user_id,
+            price,
+            qty,
+            now,
+            0, // the FIFO front
+        );
+        matching_stats.record_fill(taker, qty, Liquidity::Taker, now);
+        matching_stats.record_fill(maker, qty, Liquidity::Maker, now);
+        trade
+    }
+
     // Main matching function - processes incoming order and matches with opposite side
     // Returns vector of trades created and the order (with updated quantity/status)
     pub fn match_order(&mut self, mut order: Order) -> (Vec<Trade>, Order) {
@@ -354,6 +1302,7 @@ impl OrderBook {
             OrderSide::Buy => self.match_buy_order(&mut order),
             OrderSide::Sell => self.match_sell_order(&mut order),
         };
+        self.sync_resting_count();
 
         // Always return the order (even if fully filled, quantity will be 0)
         (trades, order)
@@ -363,19 +1312,100 @@ impl OrderBook {
     fn store_trades(&mut self, trades: Vec<Trade>) {
         // Add all new trades
         for trade in trades {
+            self.archive.push(trade.clone());
             self.trades.push_back(trade);
         }
 
         // Keep only recent trades (limit to last 1000)
         const MAX_TRADES: usize = 1000;
         while self.trades.len() > MAX_TRADES {
-            self.trades.pop_front();
+            if let Some(trade) = self.trades.pop_front() {
+                self.attributions.remove(&trade.id);
+                self.busts.remove(&trade.id);
+            }
+        }
+    }
+
+    /// Keep fees and realized P&L for trades this book still retains or archives (others are
+    /// ignored), so `/trades/me` and `/orders/{id}/fills` can show them without a database.
+    pub fn record_attributions(
+        &mut self,
+        attributions: impl IntoIterator<Item = TradeAttribution>,
+    ) {
+        let attributions: Vec<TradeAttribution> = attributions.into_iter().collect();
+        self.archive
+            .record_attributions(attributions.iter().copied());
+        for attribution in attributions {
+            self.attributions.insert(attribution.trade_id, attribution);
+        }
+        // Only when some trades were evicted before being attributed (or never stored here)
+        if self.attributions.len() > self.trades.len() {
+            let retained: HashSet<Uuid> = self.trades.iter().map(|t| t.id).collect();
+            self.attributions.retain(|id, _| retained.contains(id));
         }
     }
 
-    // Get recent trades (most recent first)
+    pub fn attribution(&self, trade_id: Uuid) -> Option<&TradeAttribution> {
+        self.attributions.get(&trade_id)
+    }
+
+    /// A retained trade by id, busted or not.
+    pub fn trade(&self, trade_id: Uuid) -> Option<&Trade> {
+        self.trades.iter().find(|t| t.id == trade_id)
+    }
+
+    /// Mark a retained or archived trade busted; trades this book no longer holds are ignored.
+    pub fn record_bust(&mut self, trade_id: Uuid, bust: TradeBust) {
+        self.archive.mark_busted(trade_id);
+        if self.trade(trade_id).is_some() {
+            self.busts.insert(trade_id, bust);
+        }
+    }
+
+    pub fn bust(&self, trade_id: Uuid) -> Option<&TradeBust> {
+        self.busts.get(&trade_id)
+    }
+
+    // Retained trades that were not busted, oldest first
+    fn public_trades(&self) -> impl DoubleEndedIterator<Item = &Trade> {
+        self.trades
+            .iter()
+            .filter(|t| !self.busts.contains_key(&t.id))
+    }
+
+    /// Put back retained trades (oldest first), e.g. from a state snapshot. Sequencing resumes
+    /// after the highest restored sequence unless it is already further along.
+    pub fn restore_trades(&mut self, trades: Vec<Trade>) {
+        if let Some(last) = trades.iter().map(|t| t.trade_seq).max() {
+            self.last_trade_seq = self.last_trade_seq.max(last);
+        }
+        self.store_trades(trades);
+    }
+
+    // Get recent trades (most recent first), busted ones left out
     pub fn get_recent_trades(&self, limit: usize) -> Vec<Trade> {
-        self.trades.iter().rev().take(limit).cloned().collect()
+        self.public_trades().rev().take(limit).cloned().collect()
+    }
+
+    /// Retained trades with `trade_seq < before_seq` (all of them when None), newest first (at
+    /// most `limit`). Busted trades are left out.
+    pub fn get_trades_before_seq(&self, before_seq: Option<u64>, limit: usize) -> Vec<Trade> {
+        self.public_trades()
+            .rev()
+            .filter(|t| before_seq.is_none_or(|before| t.trade_seq < before))
+            .take(limit)
+            .cloned()
+            .collect()
+    }
+
+    /// Retained trades with `trade_seq >= from_seq`, oldest first (at most `limit`). Busted
+    /// trades are left out.
+    pub fn get_trades_from_seq(&self, from_seq: u64, limit: usize) -> Vec<Trade> {
+        self.public_trades()
+            .filter(|t| t.trade_seq >= from_seq)
+            .take(limit)
+            .cloned()
+            .collect()
     }
 
     // Get all trades (for debugging/testing)
@@ -383,58 +1413,53 @@ impl OrderBook {
         self.trades.iter().cloned().collect()
     }
 
-    // Get bids as Vec of (price, total_quantity) pairs
+    // Get bids as Vec of (price, total_quantity) pairs; a level total saturates at Qty::MAX
     // Returns highest bid prices first
     pub fn get_bids(&self) -> Vec<(Price, Qty)> {
         self.bids
             .iter()
             .rev()
-            .map(|(&price, level)| {
-                let total_qty: Qty = level
-                    .iter()
-                    .filter_map(|&order_id| self.orders.get(&order_id))
-                    .map(|order| order.quantity)
-                    .sum();
-                (price, total_qty)
-            })
+            .map(|(&price, level)| (price, level.quantity()))
             .collect()
     }
 
-    // Get asks as Vec of (price, total_quantity) pairs
+    // Get asks as Vec of (price, total_quantity) pairs; a level total saturates at Qty::MAX
     // Returns lowest ask prices first
     pub fn get_asks(&self) -> Vec<(Price, Qty)> {
         self.asks
             .iter()
-            .map(|(&price, level)| {
-                let total_qty: Qty = level
-                    .iter()
-                    .filter_map(|&order_id| self.orders.get(&order_id))
-                    .map(|order| order.quantity)
-                    .sum();
-                (price, total_qty)
-            })
+            .map(|(&price, level)| (price, level.quantity()))
             .collect()
     }
 
     // Helper: Create a Trade object from matched orders
     // maker = resting order, taker = incoming order, qty = matched quantity
+    // trade_seq = per-book sequence number (callers advance last_trade_seq)
+    // timestamp = book clock reading, taken once per matching call
+    // maker_queue_rank = maker's position in its level's queue when it executed (0 = front)
+    #[allow(clippy::too_many_arguments)]
     fn create_trade(
+        trade_seq: u64,
         maker_order_id: OrderId,
         taker_order_id: OrderId,
         maker_user_id: Uuid,
         taker_user_id: Uuid,
         price: Price,
         qty: Qty,
+        timestamp: DateTime<Utc>,
+        maker_queue_rank: u32,
     ) -> Trade {
         Trade {
             id: Uuid::new_v4(),
+            trade_seq,
             maker_order_id,
             taker_order_id,
             maker_user_id,
             taker_user_id,
             price,
             quantity: qty,
-            timestamp: Utc::now(),
+            timestamp,
+            maker_queue_rank: Some(maker_queue_rank),
         }
     }
 
@@ -0,0 +1,196 @@
+//! A longer memory of a book's trades than the 1000 it retains for listings: a ring of the
+//! most recent `TRADE_ARCHIVE_SIZE` trades, looked up by trade sequence. It serves
+//! `/trades?from_seq=` on servers without a database and the `replay_from_seq` of a WebSocket
+//! subscribe, and when asked for trades it no longer holds it says where it starts instead of
+//! skipping ahead. It also keeps the archived trades' fees, so an order's fills
+//! (`/orders/{id}/fills`) can be listed from it.
+//!
+//! Trades arrive in sequence, so the oldest is always at the front and eviction is one pop. The
+//! archive only knows trades created (or restored) since the process started; after a restart
+//! with a database it starts at the next sequence.
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use uuid::Uuid;
+
+use crate::types::order::OrderId;
+use crate::types::trade::{OrderFill, Trade, TradeAttribution};
+
+/// Trades archived per book when `TRADE_ARCHIVE_SIZE` is unset.
+pub const DEFAULT_CAPACITY: usize = 10_000;
+
+/// The requested trades are no longer (or were never) in the archive, which starts at
+/// `oldest_available_seq`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TradesEvicted {
+    pub oldest_available_seq: u64,
+}
+
+impl std::fmt::Display for TradesEvicted {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Trades before seq {} are no longer available",
+            self.oldest_available_seq
+        )
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct TradeArchive {
+    trades: VecDeque<Trade>,
+    /// Ids of busted archived trades; dropped with the trade.
+    busted: HashSet<Uuid>,
+    /// Fees and realized P&L of archived trades, by trade id; dropped with the trade.
+    attributions: HashMap<Uuid, TradeAttribution>,
+    capacity: usize,
+    /// Sequence the next archived trade will have; where the archive starts while empty.
+    next_seq: u64,
+    /// Trades dropped from the archive (to stay within `capacity`, or by `clear`), ever.
+    evicted: u64,
+}
+
+impl Default for TradeArchive {
+    fn default() -> Self {
+        Self::new(DEFAULT_CAPACITY)
+    }
+}
+
+impl TradeArchive {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            trades: VecDeque::new(),
+            busted: HashSet::new(),
+            attributions: HashMap::new(),
+            capacity,
+            next_seq: 1,
+            evicted: 0,
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Keep at most `capacity` trades from now on, evicting the oldest beyond it.
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        self.evict();
+    }
+
+    pub fn len(&self) -> usize {
+        self.trades.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.trades.is_empty()
+    }
+
+    /// Trades dropped from the archive since it was created.
+    pub fn evicted(&self) -> u64 {
+        self.evicted
+    }
+
+    /// The lowest `from_seq` that `since` can serve without a gap.
+    pub fn oldest_available_seq(&self) -> u64 {
+        self.trades.front().map_or(self.next_seq, |t| t.trade_seq)
+    }
+
+    /// Bytes held by the archived trades, as `BookStats::estimated_bytes` counts them.
+    pub fn estimated_bytes(&self) -> usize {
+        self.trades.len() * size_of::<Trade>()
+            + self.busted.len() * size_of::<Uuid>()
+            + self.attributions.len() * size_of::<TradeAttribution>()
+    }
+
+    /// Archive `trade`, evicting the oldest when full. Trades older than the newest archived
+    /// one are already here (or fell before a gap) and are ignored.
+    pub fn push(&mut self, trade: Trade) {
+        if trade.trade_seq < self.next_seq {
+            return;
+        }
+        self.next_seq = trade.trade_seq + 1;
+        self.trades.push_back(trade);
+        self.evict();
+    }
+
+    /// Sequencing resumes after `seq`: anything archived before a jump past the next expected
+    /// sequence would leave a gap, so the archive starts over there.
+    pub fn resume_after(&mut self, seq: u64) {
+        if seq >= self.next_seq {
+            self.clear();
+            self.next_seq = seq + 1;
+        }
+    }
+
+    /// Drop every archived trade; the archive then starts at the next sequence.
+    pub fn clear(&mut self) {
+        self.evicted += self.trades.len() as u64;
+        self.trades.clear();
+        self.busted.clear();
+        self.attributions.clear();
+    }
+
+    /// Leave an archived trade out of `since` from now on; others are ignored.
+    pub fn mark_busted(&mut self, trade_id: Uuid) {
+        if self.trades.iter().any(|t| t.id == trade_id) {
+            self.busted.insert(trade_id);
+        }
+    }
+
+    /// Keep the fees and realized P&L of archived trades; others are ignored.
+    pub fn record_attributions(
+        &mut self,
+        attributions: impl IntoIterator<Item = TradeAttribution>,
+    ) {
+        for attribution in attributions {
+            self.attributions.insert(attribution.trade_id, attribution);
+        }
+        // Only when some were for trades never archived here
+        if self.attributions.len() > self.trades.len() {
+            let archived: HashSet<Uuid> = self.trades.iter().map(|t| t.id).collect();
+            self.attributions.retain(|id, _| archived.contains(id));
+        }
+    }
+
+    /// Archived trades of order `order_id`, as maker or taker, oldest first, busted ones left
+    /// out.
+    pub fn fills_of(&self, order_id: OrderId) -> Vec<OrderFill> {
+        self.trades
+            .iter()
+            .filter(|t| t.maker_order_id == order_id || t.taker_order_id == order_id)
+            .filter(|t| !self.busted.contains(&t.id))
+            .map(|t| OrderFill::new(t.clone(), self.attributions.get(&t.id), order_id))
+            .collect()
+    }
+
+    /// Trades with `trade_seq >= from_seq`, oldest first (at most `limit`), busted ones left
+    /// out. An error when trades from `from_seq` on were evicted, so a reader never skips
+    /// some without knowing.
+    pub fn since(&self, from_seq: u64, limit: usize) -> Result<Vec<Trade>, TradesEvicted> {
+        let oldest_available_seq = self.oldest_available_seq();
+        // Sequences start at 1, so nothing is missing below that
+        if from_seq.max(1) < oldest_available_seq {
+            return Err(TradesEvicted {
+                oldest_available_seq,
+            });
+        }
+        let start = self.trades.partition_point(|t| t.trade_seq < from_seq);
+        Ok(self
+            .trades
+            .range(start..)
+            .filter(|t| !self.busted.contains(&t.id))
+            .take(limit)
+            .cloned()
+            .collect())
+    }
+
+    fn evict(&mut self) {
+        while self.trades.len() > self.capacity {
+            if let Some(trade) = self.trades.pop_front() {
+                self.busted.remove(&trade.id);
+                self.attributions.remove(&trade.id);
+                self.evicted += 1;
+            }
+        }
+    }
+}
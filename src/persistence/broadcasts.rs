@@ -0,0 +1,56 @@
+//! Audit trail of operator pushes to WebSocket clients (`/admin/ws/rebroadcast`,
+//! `/admin/ws/broadcast`).
+
+use chrono::{DateTime, Utc};
+use serde_json::Value;
+use sqlx::{FromRow, PgExecutor, PgPool};
+use uuid::Uuid;
+
+use super::timing::timed;
+
+#[derive(Debug, FromRow)]
+pub struct WsBroadcastAuditRow {
+    pub admin_user_id: Uuid,
+    /// `rebroadcast` or `announcement`.
+    pub action: String,
+    /// The one symbol rebroadcast; None for every symbol and for announcements.
+    pub symbol: Option<String>,
+    pub details: sqlx::types::Json<Value>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Record one push by `admin_user_id`.
+pub async fn insert_ws_broadcast_audit(
+    executor: impl PgExecutor<'_>,
+    admin_user_id: Uuid,
+    action: &str,
+    symbol: Option<&str>,
+    details: &Value,
+) -> Result<(), sqlx::Error> {
+    let query = sqlx::query(
+        "INSERT INTO ws_broadcast_audit (admin_user_id, action, symbol, details) \
+         VALUES ($1, $2, $3, $4)",
+    )
+    .bind(admin_user_id)
+    .bind(action)
+    .bind(symbol)
+    .bind(sqlx::types::Json(details))
+    .execute(executor);
+    timed("insert_ws_broadcast_audit", query).await?;
+    Ok(())
+}
+
+/// The latest `limit` pushes, newest first.
+pub async fn list_ws_broadcast_audit(
+    pool: &PgPool,
+    limit: usize,
+) -> Result<Vec<WsBroadcastAuditRow>, sqlx::Error> {
+    let query = sqlx::query_as::<_, WsBroadcastAuditRow>(
+        "SELECT admin_user_id, action, symbol, details, created_at FROM ws_broadcast_audit \
+         ORDER BY created_at DESC, id DESC LIMIT $1",
+    )
+    .bind(limit as i64)
+    .fetch_all(pool);
+    let rows = timed("list_ws_broadcast_audit", query).await?;
+    Ok(rows)
+}
@@ -0,0 +1,89 @@
+//! Closed one-minute candles written by the market data aggregator, reloaded at startup so
+//! the recent history survives a restart.
+
+use chrono::{DateTime, Utc};
+use sqlx::{FromRow, PgExecutor, PgPool};
+
+use super::timing::timed;
+use crate::market_data::Candle;
+
+#[derive(Debug, FromRow)]
+pub struct CandleRow {
+    pub symbol: String,
+    pub open_time: DateTime<Utc>,
+    pub open: i64,
+    pub high: i64,
+    pub low: i64,
+    pub close: i64,
+    pub volume: i64,
+    pub trade_count: i64,
+}
+
+impl CandleRow {
+    pub fn to_candle(&self) -> Candle {
+        Candle {
+            open_time: self.open_time,
+            open: self.open,
+            high: self.high,
+            low: self.low,
+            close: self.close,
+            volume: self.volume.max(0) as u64,
+            trade_count: self.trade_count.max(0) as u64,
+            closed: true,
+        }
+    }
+}
+
+/// Insert or replace the candle for (`symbol`, `candle.open_time`).
+pub async fn upsert_candle(
+    executor: impl PgExecutor<'_>,
+    symbol: &str,
+    candle: &Candle,
+) -> Result<(), sqlx::Error> {
+    let query = sqlx::query(
+        "INSERT INTO candles (symbol, open_time, open, high, low, close, volume, trade_count) \
+         VALUES ($1, $2, $3, $4, $5, $6, $7, $8) \
+         ON CONFLICT (symbol, open_time) DO UPDATE SET open = $3, high = $4, low = $5, \
+         close = $6, volume = $7, trade_count = $8",
+    )
+    .bind(symbol)
+    .bind(candle.open_time)
+    .bind(candle.open)
+    .bind(candle.high)
+    .bind(candle.low)
+    .bind(candle.close)
+    .bind(i64::try_from(candle.volume).unwrap_or(i64::MAX))
+    .bind(candle.trade_count as i64)
+    .execute(executor);
+    timed("upsert_candle", query).await?;
+    Ok(())
+}
+
+/// Delete the candle for (`symbol`, `open_time`), e.g. when its only trade was busted.
+pub async fn delete_candle(
+    executor: impl PgExecutor<'_>,
+    symbol: &str,
+    open_time: DateTime<Utc>,
+) -> Result<(), sqlx::Error> {
+    let query = sqlx::query("DELETE FROM candles WHERE symbol = $1 AND open_time = $2")
+        .bind(symbol)
+        .bind(open_time)
+        .execute(executor);
+    timed("delete_candle", query).await?;
+    Ok(())
+}
+
+/// Candles of every symbol opened at or after `since`, oldest first per symbol.
+pub async fn list_candles_since(
+    pool: &PgPool,
+    since: DateTime<Utc>,
+) -> Result<Vec<CandleRow>, sqlx::Error> {
+    let query = sqlx::query_as::<_, CandleRow>(
+        "SELECT symbol, open_time, open, high, low, close, volume, trade_count FROM candles \
+         WHERE open_time >= $1 ORDER BY symbol, open_time",
+    )
+    .bind(since)
+    .fetch_all(pool);
+    let rows = timed("list_candles_since", query).await?;
+    Ok(rows)
+}
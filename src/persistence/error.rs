@@ -0,0 +1,37 @@
+//! Typed persistence errors: distinguishes timeouts (statement timeout or no free
+//! connection) from other database failures so the API can report them as 503.
+
+use std::fmt;
+
+/// SQLSTATE for a statement cancelled by `statement_timeout` (query_canceled).
+const QUERY_CANCELED: &str = "57014";
+
+#[derive(Debug)]
+pub enum PersistenceError {
+    Timeout(sqlx::Error),
+    Db(sqlx::Error),
+}
+
+impl From<sqlx::Error> for PersistenceError {
+    fn from(e: sqlx::Error) -> Self {
+        let timed_out = match &e {
+            sqlx::Error::Database(db) => db.code().as_deref() == Some(QUERY_CANCELED),
+            sqlx::Error::PoolTimedOut => true,
+            _ => false,
+        };
+        if timed_out {
+            Self::Timeout(e)
+        } else {
+            Self::Db(e)
+        }
+    }
+}
+
+impl fmt::Display for PersistenceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Timeout(e) => write!(f, "query timed out: {}", e),
+            Self::Db(e) => write!(f, "database error: {}", e),
+        }
+    }
+}
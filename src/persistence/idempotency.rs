@@ -0,0 +1,61 @@
+//! Idempotency key persistence: store completed responses, look them up on replay.
+
+use chrono::{DateTime, Utc};
+use sqlx::{FromRow, PgPool};
+use uuid::Uuid;
+
+use super::timing::timed;
+
+#[derive(Debug, FromRow)]
+pub struct IdempotencyRow {
+    pub user_id: Uuid,
+    pub key: String,
+    pub request_body: String,
+    pub status_code: i32,
+    pub response_body: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Get the stored response for (user, key), if any.
+pub async fn get_idempotency_key(
+    pool: &PgPool,
+    user_id: Uuid,
+    key: &str,
+) -> Result<Option<IdempotencyRow>, sqlx::Error> {
+    let query = sqlx::query_as::<_, IdempotencyRow>(
+        "SELECT user_id, key, request_body, status_code, response_body, created_at \
+         FROM idempotency_keys WHERE user_id = $1 AND key = $2",
+    )
+    .bind(user_id)
+    .bind(key)
+    .fetch_optional(pool);
+    let row = timed("get_idempotency_key", query).await?;
+    Ok(row)
+}
+
+/// Store a completed response (overwrites an expired entry for the same key).
+pub async fn upsert_idempotency_key(
+    pool: &PgPool,
+    user_id: Uuid,
+    key: &str,
+    request_body: &str,
+    status_code: u16,
+    response_body: &str,
+    created_at: DateTime<Utc>,
+) -> Result<(), sqlx::Error> {
+    let query = sqlx::query(
+        "INSERT INTO idempotency_keys (user_id, key, request_body, status_code, response_body, created_at) \
+         VALUES ($1, $2, $3, $4, $5, $6) \
+         ON CONFLICT (user_id, key) DO UPDATE SET request_body = $3, status_code = $4, \
+         response_body = $5, created_at = $6",
+    )
+    .bind(user_id)
+    .bind(key)
+    .bind(request_body)
+    .bind(status_code as i32)
+    .bind(response_body)
+    .bind(created_at)
+    .execute(pool);
+    timed("upsert_idempotency_key", query).await?;
+    Ok(())
+}
@@ -0,0 +1,44 @@
+//! Trade persistence counters (duplicate inserts skipped, missing trades found by
+//! reconciliation), served at /admin/persistence.
+
+use serde::Serialize;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+#[derive(Debug, Default)]
+pub struct TradePersistenceMetrics {
+    inserted: AtomicU64,
+    duplicates_skipped: AtomicU64,
+    missing_found: AtomicU64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct TradePersistenceSnapshot {
+    pub inserted: u64,
+    pub duplicates_skipped: u64,
+    pub missing_found: u64,
+}
+
+impl TradePersistenceMetrics {
+    /// Record a (bulk) insert of `attempted` trades of which `inserted` were new.
+    pub fn record_insert(&self, attempted: usize, inserted: u64) {
+        self.inserted.fetch_add(inserted, Ordering::Relaxed);
+        self.duplicates_skipped.fetch_add(
+            (attempted as u64).saturating_sub(inserted),
+            Ordering::Relaxed,
+        );
+    }
+
+    /// Record trades that reconciliation found missing from the database.
+    pub fn record_missing(&self, missing: usize) {
+        self.missing_found
+            .fetch_add(missing as u64, Ordering::Relaxed);
+    }
+
+    pub fn snapshot(&self) -> TradePersistenceSnapshot {
+        TradePersistenceSnapshot {
+            inserted: self.inserted.load(Ordering::Relaxed),
+            duplicates_skipped: self.duplicates_skipped.load(Ordering::Relaxed),
+            missing_found: self.missing_found.load(Ordering::Relaxed),
+        }
+    }
+}
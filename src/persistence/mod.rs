@@ -1,17 +1,85 @@
-//! Database layer: pool, migrations, and access for users, orders, trades, positions.
+//! Database layer: pool, migrations and schema checks, and access for users, orders, trades, positions, symbols,
+//! rules changes, candles, reconciliation repairs, webhooks, daily statements, operator
+//! WebSocket pushes and surveillance alerts, and the plans of the hot reads.
 
+mod broadcasts;
+mod candles;
+mod error;
+mod idempotency;
+mod metrics;
 mod orders;
+mod plans;
 mod pool;
 mod positions;
+mod reconcile;
+mod retention;
+mod schema;
+mod selftest;
+mod statements;
+mod surveillance;
+mod symbols;
+mod timing;
 mod trades;
 mod users;
+mod webhooks;
 
+pub use broadcasts::{
+    insert_ws_broadcast_audit, list_ws_broadcast_audit, WsBroadcastAuditRow,
+};
+pub use candles::{delete_candle, list_candles_since, upsert_candle, CandleRow};
+pub use error::PersistenceError;
+pub use idempotency::{get_idempotency_key, upsert_idempotency_key, IdempotencyRow};
+pub use metrics::{TradePersistenceMetrics, TradePersistenceSnapshot};
 pub use orders::{
-    get_order_by_id, insert_order, list_open_orders_by_symbol, order_row_to_order,
-    order_row_to_order_display, update_order_status, OrderRow,
+    get_order_by_id, insert_order, list_expired_open_orders, list_open_order_deadlines,
+    list_open_order_symbols, list_open_orders_by_symbol, list_orders_for_user, cancel_order,
+    order_row_to_order, order_row_to_order_display, set_order_expiry, update_order_status,
+    OrderDeadlineRow, OrderRow,
 };
-pub use pool::{create_pool_and_migrate, run_migrations};
+pub use plans::{explain, PlannedQuery};
+pub use pool::{create_pool_and_migrate, pool_options, run_migrations};
 pub use sqlx::PgPool;
-pub use users::{get_user_by_username, insert_user, list_users};
-pub use positions::{list_positions, list_positions_for_user, upsert_position, PositionRow};
-pub use trades::{insert_trade, list_trades, list_trades_for_user};
\ No newline at end of file
+pub use users::{
+    change_username, get_user_by_username, insert_kill_switch_audit, insert_user,
+    list_kill_switch_audit, list_users, set_trading_disabled, touch_last_login,
+    username_released_by, KillSwitchAuditRow,
+};
+pub use positions::{
+    delete_position, list_positions, list_positions_by_symbol, list_positions_for_user,
+    upsert_position, PositionRow,
+};
+pub use reconcile::{
+    insert_reconcile_audit, list_reconcile_audit, update_order_state, ReconcileAuditRow,
+};
+pub use retention::{archive_orders_batch, archive_trades_batch};
+pub use schema::{
+    ensure_schema, run_pending, schema_status, MigrationInfo, SchemaError, SchemaStatus, MIGRATOR,
+};
+pub use selftest::purge_selftest_data;
+pub use statements::{
+    get_statement, last_trade_prices_before, list_trades_for_users_before, list_user_ids_after,
+    upsert_statements, StatementRow,
+};
+pub use surveillance::{
+    acknowledge_surveillance_alert, insert_surveillance_alert, list_surveillance_alerts,
+    SurveillanceAlertRow,
+};
+pub use symbols::{
+    get_rules_version, insert_rule_change, insert_symbol_config_audit, list_recent_rule_changes,
+    list_rule_changes_since, list_stored_symbols, list_symbol_config_audit, list_symbol_configs,
+    symbol_config_row_to_config, upsert_symbol_config, RuleChangeRow, SymbolConfigAuditRow,
+    SymbolConfigRow,
+};
+pub use timing::{
+    query_histograms, set_slow_query_threshold, QueryHistogram, QUERY_LATENCY_BUCKETS_MS,
+};
+pub use trades::{
+    find_trade, insert_trade, insert_trade_bust_audit, insert_trades_bulk, list_trade_bust_audit,
+    list_trade_stats_buckets, list_trades, list_trades_before_seq, list_trades_for_order,
+    list_trades_for_user, list_trades_from_seq, mark_trade_busted, max_trade_seq,
+    reconcile_trades, TradeBustAuditRow, TradeStatsBucketRow, UserTradeRow,
+};
+pub use webhooks::{
+    delete_webhook, insert_webhook, insert_webhook_delivery, list_webhook_deliveries, list_webhooks,
+    WebhookDeliveryRow, WebhookRow,
+};
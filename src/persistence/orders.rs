@@ -1,10 +1,16 @@
 //! Order persistence: insert, update status, list open by symbol.
 
 use chrono::{DateTime, Utc};
-use sqlx::PgPool;
+use sqlx::types::Json;
+use sqlx::{PgExecutor, PgPool};
 use uuid::Uuid;
 
-fn side_to_str(side: crate::types::order::OrderSide) -> &'static str {
+use super::timing::timed;
+use crate::skew::SkewWindow;
+use crate::types::environment::Environment;
+use crate::types::order::{OrderSource, OrderTags};
+
+pub(super) fn side_to_str(side: crate::types::order::OrderSide) -> &'static str {
     match side {
         crate::types::order::OrderSide::Buy => "Buy",
         crate::types::order::OrderSide::Sell => "Sell",
@@ -18,7 +24,15 @@ fn order_type_to_str(ot: crate::types::order::OrderType) -> &'static str {
     }
 }
 
-fn status_to_str(s: crate::types::order::OrderStatus) -> &'static str {
+fn source_to_str(source: OrderSource) -> &'static str {
+    match source {
+        OrderSource::Api => "Api",
+        OrderSource::Admin => "Admin",
+        OrderSource::Spread => "Spread",
+    }
+}
+
+pub(super) fn status_to_str(s: crate::types::order::OrderStatus) -> &'static str {
     match s {
         crate::types::order::OrderStatus::Pending => "Pending",
         crate::types::order::OrderStatus::PartiallyFilled => "PartiallyFilled",
@@ -27,10 +41,10 @@ fn status_to_str(s: crate::types::order::OrderStatus) -> &'static str {
     }
 }
 
-/// Insert an order (after create or match).
+/// Insert an order (after create or match). `close_reason` is the rejection code, if any.
 #[allow(clippy::too_many_arguments)]
 pub async fn insert_order(
-    pool: &PgPool,
+    executor: impl PgExecutor<'_>,
     id: Uuid,
     user_id: Uuid,
     symbol: &str,
@@ -40,10 +54,13 @@ pub async fn insert_order(
     quantity: u64,
     status: crate::types::order::OrderStatus,
     created_at: DateTime<Utc>,
+    tags: &OrderTags,
+    source: OrderSource,
+    close_reason: Option<&str>,
 ) -> Result<(), sqlx::Error> {
-    sqlx::query(
-        "INSERT INTO orders (id, user_id, symbol, side, order_type, price, quantity, status, created_at) \
-         VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)",
+    let query = sqlx::query(
+        "INSERT INTO orders (id, user_id, symbol, side, order_type, price, quantity, status, created_at, tags, source, close_reason) \
+         VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12)",
     )
     .bind(id)
     .bind(user_id)
@@ -54,25 +71,50 @@ pub async fn insert_order(
     .bind(quantity as i64)
     .bind(status_to_str(status))
     .bind(created_at)
-    .execute(pool)
-    .await?;
+    .bind(Json(tags))
+    .bind(source_to_str(source))
+    .bind(close_reason)
+    .execute(executor);
+    timed("insert_order", query).await?;
     Ok(())
 }
 
 /// Update order status (e.g. on cancel or fill).
 pub async fn update_order_status(
-    pool: &PgPool,
+    executor: impl PgExecutor<'_>,
     id: Uuid,
     status: crate::types::order::OrderStatus,
 ) -> Result<(), sqlx::Error> {
-    sqlx::query("UPDATE orders SET status = $1 WHERE id = $2")
+    let query = sqlx::query("UPDATE orders SET status = $1 WHERE id = $2")
         .bind(status_to_str(status))
         .bind(id)
-        .execute(pool)
-        .await?;
+        .execute(executor);
+    timed("update_order_status", query).await?;
     Ok(())
 }
 
+/// Mark an open order cancelled with the quantity still open when it left the book. Only an
+/// order still `Pending` or `PartiallyFilled` changes, so the first close recorded keeps its
+/// reason; returns whether this call was it.
+pub async fn cancel_order(
+    executor: impl PgExecutor<'_>,
+    id: Uuid,
+    remaining_quantity: u64,
+    close_reason: &str,
+) -> Result<bool, sqlx::Error> {
+    let query = sqlx::query(
+        "UPDATE orders SET status = $1, quantity = $2, close_reason = $3 \
+         WHERE id = $4 AND status IN ('Pending', 'PartiallyFilled')",
+    )
+    .bind(status_to_str(crate::types::order::OrderStatus::Cancelled))
+    .bind(remaining_quantity as i64)
+    .bind(close_reason)
+    .bind(id)
+    .execute(executor);
+    let result = timed("cancel_order", query).await?;
+    Ok(result.rows_affected() > 0)
+}
+
 #[derive(Debug, sqlx::FromRow)]
 pub struct OrderRow {
     pub id: Uuid,
@@ -84,6 +126,10 @@ pub struct OrderRow {
     pub quantity: i64,
     pub status: String,
     pub created_at: DateTime<Utc>,
+    pub tags: Json<OrderTags>,
+    pub source: String,
+    /// Rejection code for orders closed by the engine (e.g. `NO_LIQUIDITY`).
+    pub close_reason: Option<String>,
 }
 
 /// Get a single order by id (for GET /orders/{id}).
@@ -91,32 +137,137 @@ pub async fn get_order_by_id(
     pool: &PgPool,
     order_id: Uuid,
 ) -> Result<Option<OrderRow>, sqlx::Error> {
-    let row = sqlx::query_as::<_, OrderRow>(
-        "SELECT id, user_id, symbol, side, order_type, price, quantity, status, created_at \
+    let query = sqlx::query_as::<_, OrderRow>(
+        "SELECT id, user_id, symbol, side, order_type, price, quantity, status, created_at, tags, source, close_reason \
          FROM orders WHERE id = $1",
     )
     .bind(order_id)
-    .fetch_optional(pool)
-    .await?;
+    .fetch_optional(pool);
+    let row = timed("get_order_by_id", query).await?;
     Ok(row)
 }
 
+/// The query of `list_open_orders_by_symbol`. Its status list is the predicate of the partial
+/// `idx_orders_open_symbol_created_at`, which only matches when the two are written the same.
+pub(super) const OPEN_ORDERS_BY_SYMBOL_SQL: &str = "SELECT id, user_id, symbol, side, order_type, price, quantity, status, created_at, tags, source, close_reason \
+     FROM orders WHERE symbol = $1 AND status IN ('Pending', 'PartiallyFilled') ORDER BY created_at";
+
 /// List open orders (Pending or PartiallyFilled) for a symbol, for hydration.
 pub async fn list_open_orders_by_symbol(
     pool: &PgPool,
     symbol: &str,
 ) -> Result<Vec<OrderRow>, sqlx::Error> {
-    let rows = sqlx::query_as::<_, OrderRow>(
-        "SELECT id, user_id, symbol, side, order_type, price, quantity, status, created_at \
-         FROM orders WHERE symbol = $1 AND status IN ('Pending', 'PartiallyFilled') ORDER BY created_at",
+    let query = sqlx::query_as::<_, OrderRow>(OPEN_ORDERS_BY_SYMBOL_SQL)
+        .bind(symbol)
+        .fetch_all(pool);
+    let rows = timed("list_open_orders_by_symbol", query).await?;
+    Ok(rows)
+}
+
+/// Symbols with open orders, to report the ones a server does not serve.
+pub async fn list_open_order_symbols(pool: &PgPool) -> Result<Vec<String>, sqlx::Error> {
+    let query = sqlx::query_scalar::<_, String>(
+        "SELECT DISTINCT symbol FROM orders WHERE status IN ('Pending', 'PartiallyFilled') \
+         ORDER BY symbol",
     )
-    .bind(symbol)
-    .fetch_all(pool)
-    .await?;
+    .fetch_all(pool);
+    let symbols = timed("list_open_order_symbols", query).await?;
+    Ok(symbols)
+}
+
+/// An open order placed with a refresh TTL, and when it expires unless refreshed.
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct OrderDeadlineRow {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub symbol: String,
+    pub quantity: i64,
+    pub expires_at: DateTime<Utc>,
+    pub refresh_ttl_ms: i64,
+}
+
+/// Store the deadline of a resting TTL order (on placement and every refresh).
+pub async fn set_order_expiry(
+    executor: impl PgExecutor<'_>,
+    id: Uuid,
+    expires_at: DateTime<Utc>,
+    refresh_ttl_ms: u64,
+) -> Result<(), sqlx::Error> {
+    let query = sqlx::query("UPDATE orders SET expires_at = $1, refresh_ttl_ms = $2 WHERE id = $3")
+        .bind(expires_at)
+        .bind(refresh_ttl_ms as i64)
+        .bind(id)
+        .execute(executor);
+    timed("set_order_expiry", query).await?;
+    Ok(())
+}
+
+/// Open orders whose deadline is at or before `now`, earliest first.
+pub async fn list_expired_open_orders(
+    executor: impl PgExecutor<'_>,
+    now: DateTime<Utc>,
+) -> Result<Vec<OrderDeadlineRow>, sqlx::Error> {
+    let query = sqlx::query_as::<_, OrderDeadlineRow>(
+        "SELECT id, user_id, symbol, quantity, expires_at, refresh_ttl_ms FROM orders \
+         WHERE status IN ('Pending', 'PartiallyFilled') AND expires_at <= $1 \
+         AND refresh_ttl_ms IS NOT NULL ORDER BY expires_at, id",
+    )
+    .bind(now)
+    .fetch_all(executor);
+    let rows = timed("list_expired_open_orders", query).await?;
+    Ok(rows)
+}
+
+/// Every open order with a deadline, earliest first (to track them again after a restart).
+pub async fn list_open_order_deadlines(
+    executor: impl PgExecutor<'_>,
+) -> Result<Vec<OrderDeadlineRow>, sqlx::Error> {
+    let query = sqlx::query_as::<_, OrderDeadlineRow>(
+        "SELECT id, user_id, symbol, quantity, expires_at, refresh_ttl_ms FROM orders \
+         WHERE status IN ('Pending', 'PartiallyFilled') AND expires_at IS NOT NULL \
+         AND refresh_ttl_ms IS NOT NULL ORDER BY expires_at, id",
+    )
+    .fetch_all(executor);
+    let rows = timed("list_open_order_deadlines", query).await?;
+    Ok(rows)
+}
+
+/// List a user's orders in `environment`, newest first (for GET /orders/me). Optional symbol
+/// and source; `tags` must be contained in the order's tags (JSONB `@>`), so an empty map
+/// matches every order. `before` is the (created_at, id) keyset to continue after.
+#[allow(clippy::too_many_arguments)]
+pub async fn list_orders_for_user(
+    pool: &PgPool,
+    user_id: Uuid,
+    symbol_opt: Option<&str>,
+    environment: Environment,
+    tags: &OrderTags,
+    source: Option<OrderSource>,
+    before: Option<(DateTime<Utc>, Uuid)>,
+    limit: usize,
+) -> Result<Vec<OrderRow>, sqlx::Error> {
+    let query = sqlx::query_as::<_, OrderRow>(
+        "SELECT id, user_id, symbol, side, order_type, price, quantity, status, created_at, tags, source, close_reason \
+         FROM orders WHERE user_id = $1 AND ($2::TEXT IS NULL OR symbol = $2) AND tags @> $3 \
+         AND ($4::TEXT IS NULL OR source = $4) \
+         AND ($5::TIMESTAMPTZ IS NULL OR (created_at, id) < ($5, $6)) \
+         AND environment = $8 \
+         ORDER BY created_at DESC, id DESC LIMIT $7",
+    )
+    .bind(user_id)
+    .bind(symbol_opt)
+    .bind(Json(tags))
+    .bind(source.map(source_to_str))
+    .bind(before.map(|(created_at, _)| created_at))
+    .bind(before.map(|(_, id)| id))
+    .bind(limit as i64)
+    .bind(environment.as_str())
+    .fetch_all(pool);
+    let rows = timed("list_orders_for_user", query).await?;
     Ok(rows)
 }
 
-fn str_to_side(s: &str) -> Option<crate::types::order::OrderSide> {
+pub(super) fn str_to_side(s: &str) -> Option<crate::types::order::OrderSide> {
     match s {
         "Buy" => Some(crate::types::order::OrderSide::Buy),
         "Sell" => Some(crate::types::order::OrderSide::Sell),
@@ -132,6 +283,15 @@ fn str_to_order_type(s: &str) -> Option<crate::types::order::OrderType> {
     }
 }
 
+fn str_to_source(s: &str) -> Option<OrderSource> {
+    match s {
+        "Api" => Some(OrderSource::Api),
+        "Admin" => Some(OrderSource::Admin),
+        "Spread" => Some(OrderSource::Spread),
+        _ => None,
+    }
+}
+
 fn str_to_status(s: &str) -> Option<crate::types::order::OrderStatus> {
     match s {
         "Pending" => Some(crate::types::order::OrderStatus::Pending),
@@ -142,13 +302,37 @@ fn str_to_status(s: &str) -> Option<crate::types::order::OrderStatus> {
     }
 }
 
-/// Convert OrderRow to Order for hydration. Skips invalid rows (quantity > 0).
-pub fn order_row_to_order(row: &OrderRow) -> Option<crate::types::order::Order> {
-    let side = str_to_side(&row.side)?;
-    let order_type = str_to_order_type(&row.order_type)?;
-    let status = str_to_status(&row.status)?;
-    let quantity = row.quantity.try_into().ok().filter(|&q: &u64| q > 0)?;
-    Some(crate::types::order::Order {
+/// Convert OrderRow to a restorable open Order for hydration. Errors name why the row was
+/// rejected (unknown side/type/status, not open, non-positive quantity, market order, or a
+/// creation time outside `window` around `now` that its policy rejects). A skewed creation
+/// time the policy lets through comes back as the warning.
+pub fn order_row_to_order(
+    row: &OrderRow,
+    window: &SkewWindow,
+    now: DateTime<Utc>,
+) -> Result<(crate::types::order::Order, Option<String>), String> {
+    use crate::types::order::{OrderStatus, OrderType};
+    let side = str_to_side(&row.side).ok_or_else(|| format!("invalid side '{}'", row.side))?;
+    let order_type = str_to_order_type(&row.order_type)
+        .ok_or_else(|| format!("invalid order type '{}'", row.order_type))?;
+    let status =
+        str_to_status(&row.status).ok_or_else(|| format!("invalid status '{}'", row.status))?;
+    let source =
+        str_to_source(&row.source).ok_or_else(|| format!("invalid source '{}'", row.source))?;
+    if !matches!(status, OrderStatus::Pending | OrderStatus::PartiallyFilled) {
+        return Err(format!("status '{}' is not open", row.status));
+    }
+    if order_type != OrderType::Limit {
+        return Err("market orders cannot rest in the book".to_string());
+    }
+    let quantity = row
+        .quantity
+        .try_into()
+        .ok()
+        .filter(|&q: &u64| q > 0)
+        .ok_or_else(|| format!("invalid quantity {}", row.quantity))?;
+    let created_at = window.check(row.created_at, now)?;
+    let order = crate::types::order::Order {
         id: row.id,
         user_id: row.user_id,
         side,
@@ -156,8 +340,11 @@ pub fn order_row_to_order(row: &OrderRow) -> Option<crate::types::order::Order>
         price: row.price,
         quantity,
         status,
-        timestamp: row.created_at,
-    })
+        timestamp: created_at.timestamp,
+        tags: row.tags.0.clone(),
+        source,
+    };
+    Ok((order, created_at.warning))
 }
 
 /// Convert OrderRow to Order for display (GET /orders/{id}). Allows quantity >= 0 (filled orders).
@@ -165,6 +352,7 @@ pub fn order_row_to_order_display(row: &OrderRow) -> Option<crate::types::order:
     let side = str_to_side(&row.side)?;
     let order_type = str_to_order_type(&row.order_type)?;
     let status = str_to_status(&row.status)?;
+    let source = str_to_source(&row.source)?;
     let quantity = row.quantity.max(0) as u64;
     Some(crate::types::order::Order {
         id: row.id,
@@ -175,5 +363,7 @@ pub fn order_row_to_order_display(row: &OrderRow) -> Option<crate::types::order:
         quantity,
         status,
         timestamp: row.created_at,
+        tags: row.tags.0.clone(),
+        source,
     })
 }
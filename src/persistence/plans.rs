@@ -0,0 +1,53 @@
+//! Query plans of the hot reads, to check them against the schema's indexes: `explain` runs
+//! `EXPLAIN` (not `ANALYZE`, so nothing executes) on the exact query a read function sends.
+
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use super::orders::OPEN_ORDERS_BY_SYMBOL_SQL;
+use crate::types::environment::Environment;
+use super::trades::user_trades_sql;
+
+/// A read whose plan can be inspected, with the arguments to plan it for.
+#[derive(Debug, Clone)]
+pub enum PlannedQuery {
+    /// `list_trades_for_user`'s first page of `user_id`'s trades, all symbols.
+    TradesForUser {
+        user_id: Uuid,
+        limit: usize,
+        include_archived: bool,
+    },
+    /// `list_open_orders_by_symbol`.
+    OpenOrdersBySymbol { symbol: String },
+}
+
+/// The plan Postgres picks for `query`, as `EXPLAIN` prints it (one node per line).
+pub async fn explain(pool: &PgPool, query: &PlannedQuery) -> Result<String, sqlx::Error> {
+    let lines: Vec<String> = match query {
+        PlannedQuery::TradesForUser {
+            user_id,
+            limit,
+            include_archived,
+        } => {
+            let sql = format!("EXPLAIN {}", user_trades_sql(*include_archived));
+            sqlx::query_scalar(&sql)
+                .bind(user_id)
+                .bind(None::<&str>)
+                .bind(None::<chrono::DateTime<chrono::Utc>>)
+                .bind(None::<i64>)
+                .bind(None::<Uuid>)
+                .bind(*limit as i64)
+                .bind(Environment::Live.as_str())
+                .fetch_all(pool)
+                .await?
+        }
+        PlannedQuery::OpenOrdersBySymbol { symbol } => {
+            let sql = format!("EXPLAIN {}", OPEN_ORDERS_BY_SYMBOL_SQL);
+            sqlx::query_scalar(&sql)
+                .bind(symbol)
+                .fetch_all(pool)
+                .await?
+        }
+    };
+    Ok(lines.join("\n"))
+}
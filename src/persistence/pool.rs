@@ -1,12 +1,34 @@
 //! Database pool and migrations.
 
-use sqlx::postgres::PgPoolOptions;
 use sqlx::PgPool;
+use sqlx::postgres::PgPoolOptions;
+use std::time::Duration;
+
+use super::schema::MIGRATOR;
+
+/// Pool options shared by the server and tests. With `statement_timeout`, every connection
+/// sets it on connect so no single query can hold a worker indefinitely.
+pub fn pool_options(statement_timeout: Option<Duration>) -> PgPoolOptions {
+    let options = PgPoolOptions::new().max_connections(5);
+    match statement_timeout {
+        Some(timeout) => options.after_connect(move |conn, _meta| {
+            Box::pin(async move {
+                sqlx::query(&format!("SET statement_timeout = {}", timeout.as_millis()))
+                    .execute(conn)
+                    .await?;
+                Ok(())
+            })
+        }),
+        None => options,
+    }
+}
 
 /// Create a pool from `DATABASE_URL` and run migrations.
-pub async fn create_pool_and_migrate(database_url: &str) -> Result<PgPool, sqlx::Error> {
-    let pool = PgPoolOptions::new()
-        .max_connections(5)
+pub async fn create_pool_and_migrate(
+    database_url: &str,
+    statement_timeout: Option<Duration>,
+) -> Result<PgPool, sqlx::Error> {
+    let pool = pool_options(statement_timeout)
         .connect(database_url)
         .await?;
     run_migrations(&pool).await?;
@@ -15,6 +37,6 @@ pub async fn create_pool_and_migrate(database_url: &str) -> Result<PgPool, sqlx:
 
 /// Run embedded migrations.
 pub async fn run_migrations(pool: &PgPool) -> Result<(), sqlx::Error> {
-    sqlx::migrate!("./migrations").run(pool).await?;
+    MIGRATOR.run(pool).await?;
     Ok(())
 }
@@ -1,27 +1,54 @@
-//! Position persistence: upsert and list for hydration.
+//! Position persistence: upsert, delete, and list for hydration.
 
-use sqlx::PgPool;
+use chrono::{DateTime, Utc};
+use sqlx::{PgExecutor, PgPool};
 use uuid::Uuid;
 
+use super::timing::timed;
+use crate::types::environment::Environment;
+use crate::types::position::Position;
+
+const POSITION_COLUMNS: &str = "user_id, symbol, quantity, average_price, opened_at, updated_at";
+
 /// Upsert a position (insert or update on conflict).
 pub async fn upsert_position(
-    pool: &PgPool,
+    executor: impl PgExecutor<'_>,
     user_id: Uuid,
     symbol: &str,
     quantity: i64,
     average_price: i64,
+    opened_at: DateTime<Utc>,
+    updated_at: DateTime<Utc>,
 ) -> Result<(), sqlx::Error> {
-    sqlx::query(
-        "INSERT INTO positions (user_id, symbol, quantity, average_price) \
-         VALUES ($1, $2, $3, $4) \
-         ON CONFLICT (user_id, symbol) DO UPDATE SET quantity = $3, average_price = $4",
+    let query = sqlx::query(
+        "INSERT INTO positions \
+         (user_id, symbol, quantity, average_price, opened_at, updated_at) \
+         VALUES ($1, $2, $3, $4, $5, $6) \
+         ON CONFLICT (user_id, symbol) DO UPDATE \
+         SET quantity = $3, average_price = $4, opened_at = $5, updated_at = $6",
     )
     .bind(user_id)
     .bind(symbol)
     .bind(quantity)
     .bind(average_price)
-    .execute(pool)
-    .await?;
+    .bind(opened_at)
+    .bind(updated_at)
+    .execute(executor);
+    timed("upsert_position", query).await?;
+    Ok(())
+}
+
+/// Delete a position row (position closed to zero).
+pub async fn delete_position(
+    executor: impl PgExecutor<'_>,
+    user_id: Uuid,
+    symbol: &str,
+) -> Result<(), sqlx::Error> {
+    let query = sqlx::query("DELETE FROM positions WHERE user_id = $1 AND symbol = $2")
+        .bind(user_id)
+        .bind(symbol)
+        .execute(executor);
+    timed("delete_position", query).await?;
     Ok(())
 }
 
@@ -31,39 +58,76 @@ pub struct PositionRow {
     pub symbol: String,
     pub quantity: i64,
     pub average_price: i64,
+    pub opened_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl PositionRow {
+    pub fn to_position(&self) -> Position {
+        Position {
+            user_id: self.user_id,
+            symbol: self.symbol.clone(),
+            quantity: self.quantity,
+            average_price: self.average_price,
+            opened_at: self.opened_at,
+            updated_at: self.updated_at,
+        }
+    }
 }
 
 /// List all positions for hydration.
 pub async fn list_positions(pool: &PgPool) -> Result<Vec<PositionRow>, sqlx::Error> {
-    let rows = sqlx::query_as::<_, PositionRow>(
-        "SELECT user_id, symbol, quantity, average_price FROM positions",
-    )
-    .fetch_all(pool)
-    .await?;
+    let sql = format!("SELECT {} FROM positions", POSITION_COLUMNS);
+    let query = sqlx::query_as::<_, PositionRow>(&sql).fetch_all(pool);
+    let rows = timed("list_positions", query).await?;
     Ok(rows)
 }
 
-/// List positions for a user, optional symbol filter (for GET /positions).
+/// List positions for a user in `environment`, optional symbol filter (for GET /positions).
 pub async fn list_positions_for_user(
     pool: &PgPool,
     user_id: Uuid,
     symbol_filter: Option<&str>,
+    environment: Environment,
 ) -> Result<Vec<PositionRow>, sqlx::Error> {
     let rows = if let Some(symbol) = symbol_filter {
-        sqlx::query_as::<_, PositionRow>(
-            "SELECT user_id, symbol, quantity, average_price FROM positions WHERE user_id = $1 AND symbol = $2",
-        )
-        .bind(user_id)
-        .bind(symbol)
-        .fetch_all(pool)
-        .await?
+        let sql = format!(
+            "SELECT {} FROM positions WHERE user_id = $1 AND symbol = $2 AND environment = $3",
+            POSITION_COLUMNS
+        );
+        let query = sqlx::query_as::<_, PositionRow>(&sql)
+            .bind(user_id)
+            .bind(symbol)
+            .bind(environment.as_str())
+            .fetch_all(pool);
+        timed("list_positions_for_user", query).await?
     } else {
-        sqlx::query_as::<_, PositionRow>(
-            "SELECT user_id, symbol, quantity, average_price FROM positions WHERE user_id = $1",
-        )
-        .bind(user_id)
-        .fetch_all(pool)
-        .await?
+        let sql = format!(
+            "SELECT {} FROM positions WHERE user_id = $1 AND environment = $2",
+            POSITION_COLUMNS
+        );
+        let query = sqlx::query_as::<_, PositionRow>(&sql)
+            .bind(user_id)
+            .bind(environment.as_str())
+            .fetch_all(pool);
+        timed("list_positions_for_user", query).await?
     };
     Ok(rows)
 }
+
+/// List every user's position in a symbol, largest absolute quantity first (for the admin
+/// risk view; same order as `positions::get_positions_by_symbol`).
+pub async fn list_positions_by_symbol(
+    pool: &PgPool,
+    symbol: &str,
+) -> Result<Vec<PositionRow>, sqlx::Error> {
+    let sql = format!(
+        "SELECT {} FROM positions WHERE symbol = $1 ORDER BY ABS(quantity) DESC, user_id",
+        POSITION_COLUMNS
+    );
+    let query = sqlx::query_as::<_, PositionRow>(&sql)
+        .bind(symbol)
+        .fetch_all(pool);
+    let rows = timed("list_positions_by_symbol", query).await?;
+    Ok(rows)
+}
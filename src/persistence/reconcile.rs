@@ -0,0 +1,85 @@
+//! Writes for memory/database reconciliation repairs and their audit trail.
+
+use chrono::{DateTime, Utc};
+use serde_json::Value;
+use sqlx::{FromRow, PgExecutor, PgPool};
+use uuid::Uuid;
+
+use super::orders::status_to_str;
+use super::timing::timed;
+use crate::types::order::OrderStatus;
+
+/// Overwrite an order's status and remaining quantity.
+pub async fn update_order_state(
+    executor: impl PgExecutor<'_>,
+    id: Uuid,
+    status: OrderStatus,
+    quantity: u64,
+) -> Result<(), sqlx::Error> {
+    let query = sqlx::query("UPDATE orders SET status = $1, quantity = $2 WHERE id = $3")
+        .bind(status_to_str(status))
+        .bind(quantity as i64)
+        .bind(id)
+        .execute(executor);
+    timed("update_order_state", query).await?;
+    Ok(())
+}
+
+#[derive(Debug, FromRow)]
+pub struct ReconcileAuditRow {
+    pub symbol: String,
+    pub admin_user_id: Uuid,
+    /// `order` or `position`.
+    pub entity: String,
+    /// Order id, or the owner's user id for a position.
+    pub entity_id: String,
+    pub action: String,
+    /// Database state before the repair (None when the row was missing).
+    pub previous: Option<sqlx::types::Json<Value>>,
+    /// State written (None when the row was closed or deleted).
+    pub applied: Option<sqlx::types::Json<Value>>,
+    pub repaired_at: DateTime<Utc>,
+}
+
+/// Record one repair an admin applied to `symbol`.
+#[allow(clippy::too_many_arguments)]
+pub async fn insert_reconcile_audit(
+    executor: impl PgExecutor<'_>,
+    symbol: &str,
+    admin_user_id: Uuid,
+    entity: &str,
+    entity_id: &str,
+    action: &str,
+    previous: Option<&Value>,
+    applied: Option<&Value>,
+) -> Result<(), sqlx::Error> {
+    let query = sqlx::query(
+        "INSERT INTO reconcile_audit (symbol, admin_user_id, entity, entity_id, action, previous, applied) \
+         VALUES ($1, $2, $3, $4, $5, $6, $7)",
+    )
+    .bind(symbol)
+    .bind(admin_user_id)
+    .bind(entity)
+    .bind(entity_id)
+    .bind(action)
+    .bind(previous.map(sqlx::types::Json))
+    .bind(applied.map(sqlx::types::Json))
+    .execute(executor);
+    timed("insert_reconcile_audit", query).await?;
+    Ok(())
+}
+
+/// Repairs applied to `symbol`, newest first.
+pub async fn list_reconcile_audit(
+    pool: &PgPool,
+    symbol: &str,
+) -> Result<Vec<ReconcileAuditRow>, sqlx::Error> {
+    let query = sqlx::query_as::<_, ReconcileAuditRow>(
+        "SELECT symbol, admin_user_id, entity, entity_id, action, previous, applied, repaired_at \
+         FROM reconcile_audit WHERE symbol = $1 ORDER BY repaired_at DESC, id DESC",
+    )
+    .bind(symbol)
+    .fetch_all(pool);
+    let rows = timed("list_reconcile_audit", query).await?;
+    Ok(rows)
+}
@@ -0,0 +1,101 @@
+//! Retention: move (or delete) old terminal orders and trades in bounded batches.
+//! Each batch is a single statement, so locks on the hot tables are held only briefly.
+//! A row already in the archive (left by an earlier, interrupted move) is overwritten with the
+//! one being moved, never dropped, and every batch counts the rows it took off the hot table.
+
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+
+use super::timing::timed;
+
+const ORDER_COLUMNS: &str = "id, user_id, symbol, side, order_type, price, quantity, status, created_at, tags, source, \
+     close_reason";
+const TRADE_COLUMNS: &str = "id, trade_seq, maker_order_id, taker_order_id, maker_user_id, \
+     taker_user_id, symbol, price, quantity, created_at, maker_queue_rank, taker_side, maker_fee, taker_fee, \
+     realized_pnl_maker, realized_pnl_taker, busted_at, bust_reason";
+
+/// `col = EXCLUDED.col` for each of `columns` but `id`, for an `ON CONFLICT (id) DO UPDATE`.
+fn overwrite(columns: &str) -> String {
+    columns
+        .split(',')
+        .map(str::trim)
+        .filter(|col| *col != "id")
+        .map(|col| format!("{col} = EXCLUDED.{col}"))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Move up to `batch_size` Filled/Cancelled orders created before `cutoff` into
+/// `orders_archive` (or delete them when `archive` is false). Returns the rows processed.
+pub async fn archive_orders_batch(
+    pool: &PgPool,
+    cutoff: DateTime<Utc>,
+    batch_size: usize,
+    archive: bool,
+) -> Result<u64, sqlx::Error> {
+    let batch = "SELECT id FROM orders \
+         WHERE status IN ('Filled', 'Cancelled') AND created_at < $1 \
+         ORDER BY created_at LIMIT $2 FOR UPDATE SKIP LOCKED";
+    let sql = if archive {
+        format!(
+            "WITH batch AS ({batch}), \
+             moved AS (DELETE FROM orders o USING batch WHERE o.id = batch.id RETURNING o.*), \
+             archived AS (INSERT INTO orders_archive ({cols}) SELECT {cols} FROM moved \
+             ON CONFLICT (id) DO UPDATE SET {updates}) \
+             SELECT COUNT(*) FROM moved",
+            batch = batch,
+            cols = ORDER_COLUMNS,
+            updates = overwrite(ORDER_COLUMNS),
+        )
+    } else {
+        format!(
+            "WITH batch AS ({}), \
+             moved AS (DELETE FROM orders o USING batch WHERE o.id = batch.id RETURNING 1) \
+             SELECT COUNT(*) FROM moved",
+            batch
+        )
+    };
+    let query = sqlx::query_scalar::<_, i64>(&sql)
+        .bind(cutoff)
+        .bind(batch_size as i64)
+        .fetch_one(pool);
+    let moved = timed("archive_orders_batch", query).await?;
+    Ok(moved as u64)
+}
+
+/// Move up to `batch_size` trades created before `cutoff` into `trades_archive` (or delete
+/// them when `archive` is false). Returns the rows processed.
+pub async fn archive_trades_batch(
+    pool: &PgPool,
+    cutoff: DateTime<Utc>,
+    batch_size: usize,
+    archive: bool,
+) -> Result<u64, sqlx::Error> {
+    let batch = "SELECT id FROM trades WHERE created_at < $1 \
+         ORDER BY created_at LIMIT $2 FOR UPDATE SKIP LOCKED";
+    let sql = if archive {
+        format!(
+            "WITH batch AS ({batch}), \
+             moved AS (DELETE FROM trades t USING batch WHERE t.id = batch.id RETURNING t.*), \
+             archived AS (INSERT INTO trades_archive ({cols}) SELECT {cols} FROM moved \
+             ON CONFLICT (id) DO UPDATE SET {updates}) \
+             SELECT COUNT(*) FROM moved",
+            batch = batch,
+            cols = TRADE_COLUMNS,
+            updates = overwrite(TRADE_COLUMNS),
+        )
+    } else {
+        format!(
+            "WITH batch AS ({}), \
+             moved AS (DELETE FROM trades t USING batch WHERE t.id = batch.id RETURNING 1) \
+             SELECT COUNT(*) FROM moved",
+            batch
+        )
+    };
+    let query = sqlx::query_scalar::<_, i64>(&sql)
+        .bind(cutoff)
+        .bind(batch_size as i64)
+        .fetch_one(pool);
+    let moved = timed("archive_trades_batch", query).await?;
+    Ok(moved as u64)
+}
@@ -0,0 +1,221 @@
+//! Schema version checks: which of the binary's embedded migrations the database has applied,
+//! and the startup decision to migrate or refuse to run against a schema that is behind.
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::migrate::{MigrateError, Migrator};
+use sqlx::{FromRow, PgPool};
+use std::collections::HashMap;
+use std::fmt;
+
+use super::timing::timed;
+
+/// The migrations this binary expects, embedded at build time.
+pub static MIGRATOR: Migrator = sqlx::migrate!("./migrations");
+
+/// One migration, as embedded in the binary and/or recorded in `_sqlx_migrations`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct MigrationInfo {
+    pub version: i64,
+    pub description: String,
+    /// When it was applied; None for pending migrations.
+    pub installed_on: Option<DateTime<Utc>>,
+}
+
+/// How the database schema compares with the binary's migrations.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SchemaStatus {
+    /// Latest migration the binary embeds.
+    pub expected_version: Option<i64>,
+    /// Latest migration applied successfully.
+    pub current_version: Option<i64>,
+    pub applied: Vec<MigrationInfo>,
+    /// Embedded but not yet applied, oldest first.
+    pub pending: Vec<MigrationInfo>,
+    /// Applied but unknown to this binary: a newer binary migrated the database.
+    pub unknown: Vec<MigrationInfo>,
+    /// Applied, but the embedded file has changed since.
+    pub modified: Vec<i64>,
+    /// Started and never finished; the database needs manual repair.
+    pub failed: Vec<i64>,
+}
+
+impl SchemaStatus {
+    /// Every embedded migration is applied, unchanged, and none failed.
+    pub fn is_current(&self) -> bool {
+        self.pending.is_empty() && self.modified.is_empty() && self.failed.is_empty()
+    }
+}
+
+#[derive(FromRow)]
+struct AppliedRow {
+    version: i64,
+    description: String,
+    installed_on: DateTime<Utc>,
+    success: bool,
+    checksum: Vec<u8>,
+}
+
+/// Compare `_sqlx_migrations` with the embedded migrations. A database that was never migrated
+/// has everything pending.
+pub async fn schema_status(pool: &PgPool) -> Result<SchemaStatus, sqlx::Error> {
+    let query = sqlx::query_scalar::<_, bool>("SELECT to_regclass('_sqlx_migrations') IS NOT NULL")
+        .fetch_one(pool);
+    let rows = if timed("schema_exists", query).await? {
+        let query = sqlx::query_as::<_, AppliedRow>(
+            "SELECT version, description, installed_on, success, checksum \
+             FROM _sqlx_migrations ORDER BY version",
+        )
+        .fetch_all(pool);
+        timed("schema_status", query).await?
+    } else {
+        Vec::new()
+    };
+    let mut recorded: HashMap<i64, AppliedRow> =
+        rows.into_iter().map(|row| (row.version, row)).collect();
+
+    let mut status = SchemaStatus {
+        expected_version: None,
+        current_version: None,
+        applied: Vec::new(),
+        pending: Vec::new(),
+        unknown: Vec::new(),
+        modified: Vec::new(),
+        failed: Vec::new(),
+    };
+    for migration in MIGRATOR
+        .iter()
+        .filter(|m| !m.migration_type.is_down_migration())
+    {
+        status.expected_version = Some(migration.version);
+        match recorded.remove(&migration.version) {
+            Some(row) if !row.success => {
+                status.failed.push(row.version);
+                status.pending.push(MigrationInfo {
+                    version: migration.version,
+                    description: migration.description.to_string(),
+                    installed_on: None,
+                });
+            }
+            Some(row) => {
+                if row.checksum != *migration.checksum {
+                    status.modified.push(row.version);
+                }
+                status.current_version = Some(row.version);
+                status.applied.push(MigrationInfo {
+                    version: row.version,
+                    description: row.description,
+                    installed_on: Some(row.installed_on),
+                });
+            }
+            None => status.pending.push(MigrationInfo {
+                version: migration.version,
+                description: migration.description.to_string(),
+                installed_on: None,
+            }),
+        }
+    }
+    let mut unknown: Vec<AppliedRow> = recorded.into_values().collect();
+    unknown.sort_by_key(|row| row.version);
+    for row in unknown {
+        if !row.success {
+            status.failed.push(row.version);
+            continue;
+        }
+        status.current_version = status.current_version.max(Some(row.version));
+        status.unknown.push(MigrationInfo {
+            version: row.version,
+            description: row.description,
+            installed_on: Some(row.installed_on),
+        });
+    }
+    Ok(status)
+}
+
+#[derive(Debug)]
+pub enum SchemaError {
+    Db(sqlx::Error),
+    Migrate(MigrateError),
+    /// Auto-migration is off and these migrations (oldest first) are not applied.
+    Behind(Vec<MigrationInfo>),
+    /// A migration started and never finished.
+    Failed(i64),
+    /// An applied migration's file has changed since.
+    Modified(i64),
+}
+
+impl fmt::Display for SchemaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Db(e) => write!(f, "cannot read schema version: {}", e),
+            Self::Migrate(e) => write!(f, "migration failed: {}", e),
+            Self::Behind(pending) => {
+                let first = &pending[0];
+                write!(
+                    f,
+                    "database schema is behind: migration {} ({}) is pending",
+                    first.version, first.description
+                )?;
+                if pending.len() > 1 {
+                    write!(f, ", with {} more after it", pending.len() - 1)?;
+                }
+                write!(
+                    f,
+                    "; apply them with the `migrate` binary or set MIGRATE_ON_START=true"
+                )
+            }
+            Self::Failed(version) => write!(
+                f,
+                "migration {} did not complete; repair the database before starting",
+                version
+            ),
+            Self::Modified(version) => write!(
+                f,
+                "migration {} was changed after it was applied to this database",
+                version
+            ),
+        }
+    }
+}
+
+impl std::error::Error for SchemaError {}
+
+/// Check the schema at startup. Pending migrations are applied when `migrate` is set and are
+/// an error otherwise; a schema ahead of the binary (a newer binary migrated it) is accepted.
+/// Returns the status the server starts with.
+pub async fn ensure_schema(pool: &PgPool, migrate: bool) -> Result<SchemaStatus, SchemaError> {
+    let status = schema_status(pool).await.map_err(SchemaError::Db)?;
+    if let Some(&version) = status.failed.first() {
+        return Err(SchemaError::Failed(version));
+    }
+    if let Some(&version) = status.modified.first() {
+        return Err(SchemaError::Modified(version));
+    }
+    if !status.unknown.is_empty() {
+        eprintln!(
+            "database schema is ahead of this binary: {} unknown migration(s), latest {}",
+            status.unknown.len(),
+            status.current_version.unwrap_or_default()
+        );
+    }
+    if status.pending.is_empty() {
+        return Ok(status);
+    }
+    if !migrate {
+        return Err(SchemaError::Behind(status.pending));
+    }
+    run_pending(pool).await.map_err(SchemaError::Migrate)?;
+    schema_status(pool).await.map_err(SchemaError::Db)
+}
+
+/// Apply every pending migration, each in its own transaction, so an interrupted run resumes
+/// from the first one not applied. Migrations unknown to this binary are left alone.
+pub async fn run_pending(pool: &PgPool) -> Result<(), MigrateError> {
+    let migrator = Migrator {
+        migrations: MIGRATOR.migrations.clone(),
+        ignore_missing: true,
+        locking: MIGRATOR.locking,
+        no_tx: MIGRATOR.no_tx,
+    };
+    migrator.run(pool).await
+}
@@ -0,0 +1,27 @@
+//! Removal of the rows a self-test run leaves behind: everything stored for its isolated
+//! symbol, and its ephemeral users.
+
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use super::timing::timed;
+
+/// Delete every order, trade, position and candle of `symbol`, and the users in `user_ids`,
+/// in one transaction.
+pub async fn purge_selftest_data(
+    pool: &PgPool,
+    symbol: &str,
+    user_ids: &[Uuid],
+) -> Result<(), sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    for table in ["trades", "orders", "positions", "candles"] {
+        let sql = format!("DELETE FROM {} WHERE symbol = $1", table);
+        let query = sqlx::query(&sql).bind(symbol).execute(&mut *tx);
+        timed("purge_selftest_data", query).await?;
+    }
+    let query = sqlx::query("DELETE FROM users WHERE id = ANY($1)")
+        .bind(user_ids)
+        .execute(&mut *tx);
+    timed("purge_selftest_data", query).await?;
+    tx.commit().await
+}
@@ -0,0 +1,129 @@
+//! Daily statements: the reads they are computed from and the rows they are stored as.
+
+use chrono::{DateTime, NaiveDate, Utc};
+use sqlx::types::Json;
+use sqlx::{FromRow, PgPool};
+use std::collections::HashMap;
+use uuid::Uuid;
+
+use super::timing::timed;
+use super::trades::{UserTradeRow, trades_source};
+use crate::statements::Statement;
+
+#[derive(Debug, FromRow)]
+pub struct StatementRow {
+    pub user_id: Uuid,
+    pub statement_date: NaiveDate,
+    pub trade_count: i32,
+    pub fees: i64,
+    pub realized_pnl: i64,
+    pub unrealized_pnl: i64,
+    pub details: Json<Statement>,
+    pub csv: String,
+    pub generated_at: DateTime<Utc>,
+}
+
+/// Up to `limit` user ids after `after`, in id order: one chunk of a statement run.
+pub async fn list_user_ids_after(
+    pool: &PgPool,
+    after: Option<Uuid>,
+    limit: usize,
+) -> Result<Vec<Uuid>, sqlx::Error> {
+    let query = sqlx::query_scalar(
+        "SELECT id FROM users WHERE ($1::UUID IS NULL OR id > $1) ORDER BY id LIMIT $2",
+    )
+    .bind(after)
+    .bind(limit as i64)
+    .fetch_all(pool);
+    let ids = timed("list_user_ids_after", query).await?;
+    Ok(ids)
+}
+
+/// Every live trade, hot or archived, that `user_ids` took part in before `before`, oldest
+/// first. Busted trades are left out: they no longer count towards positions or P&L. Paper
+/// trades are not the user's money and never reach a statement.
+pub async fn list_trades_for_users_before(
+    pool: &PgPool,
+    user_ids: &[Uuid],
+    before: DateTime<Utc>,
+) -> Result<Vec<UserTradeRow>, sqlx::Error> {
+    let sql = format!(
+        "SELECT id, trade_seq, maker_order_id, taker_order_id, maker_user_id, taker_user_id, symbol, price, quantity, created_at, \
+         maker_queue_rank, taker_side, maker_fee, taker_fee, realized_pnl_maker, realized_pnl_taker, busted_at, bust_reason \
+         FROM {} WHERE (maker_user_id = ANY($1) OR taker_user_id = ANY($1)) AND created_at < $2 AND busted_at IS NULL \
+         AND environment = 'live' ORDER BY created_at, trade_seq, id",
+        trades_source(true)
+    );
+    let query = sqlx::query_as::<_, UserTradeRow>(&sql)
+        .bind(user_ids)
+        .bind(before)
+        .fetch_all(pool);
+    let rows = timed("list_trades_for_users_before", query).await?;
+    Ok(rows)
+}
+
+/// Price of the last trade of each of `symbols` before `before` (busted trades aside);
+/// symbols that had none are absent.
+pub async fn last_trade_prices_before(
+    pool: &PgPool,
+    symbols: &[String],
+    before: DateTime<Utc>,
+) -> Result<HashMap<String, i64>, sqlx::Error> {
+    let sql = format!(
+        "SELECT DISTINCT ON (symbol) symbol, price FROM {} \
+         WHERE symbol = ANY($1) AND created_at < $2 AND busted_at IS NULL \
+         ORDER BY symbol, created_at DESC, trade_seq DESC",
+        trades_source(true)
+    );
+    let query = sqlx::query_as::<_, (String, i64)>(&sql)
+        .bind(symbols)
+        .bind(before)
+        .fetch_all(pool);
+    let rows = timed("last_trade_prices_before", query).await?;
+    Ok(rows.into_iter().collect())
+}
+
+/// Store `rows` in one transaction, replacing any statement already stored for the same user
+/// and day.
+pub async fn upsert_statements(pool: &PgPool, rows: &[StatementRow]) -> Result<(), sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    for row in rows {
+        let query = sqlx::query(
+            "INSERT INTO statements \
+             (user_id, statement_date, trade_count, fees, realized_pnl, unrealized_pnl, details, csv, generated_at) \
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9) \
+             ON CONFLICT (user_id, statement_date) DO UPDATE \
+             SET trade_count = $3, fees = $4, realized_pnl = $5, unrealized_pnl = $6, details = $7, \
+             csv = $8, generated_at = $9",
+        )
+        .bind(row.user_id)
+        .bind(row.statement_date)
+        .bind(row.trade_count)
+        .bind(row.fees)
+        .bind(row.realized_pnl)
+        .bind(row.unrealized_pnl)
+        .bind(&row.details)
+        .bind(&row.csv)
+        .bind(row.generated_at)
+        .execute(&mut *tx);
+        timed("upsert_statement", query).await?;
+    }
+    tx.commit().await
+}
+
+/// `user_id`'s statement for `date`, if one was generated.
+pub async fn get_statement(
+    pool: &PgPool,
+    user_id: Uuid,
+    date: NaiveDate,
+) -> Result<Option<StatementRow>, sqlx::Error> {
+    let query = sqlx::query_as::<_, StatementRow>(
+        "SELECT user_id, statement_date, trade_count, fees, realized_pnl, unrealized_pnl, details, csv, generated_at \
+         FROM statements WHERE user_id = $1 AND statement_date = $2",
+    )
+    .bind(user_id)
+    .bind(date)
+    .fetch_optional(pool);
+    let row = timed("get_statement", query).await?;
+    Ok(row)
+}
@@ -0,0 +1,89 @@
+//! Surveillance alerts (see `api::surveillance`).
+
+use chrono::{DateTime, Utc};
+use sqlx::types::Json;
+use sqlx::{FromRow, PgPool};
+use uuid::Uuid;
+
+use super::timing::timed;
+
+#[derive(Debug, FromRow)]
+pub struct SurveillanceAlertRow {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub symbol: String,
+    /// `api::surveillance::AlertKind` name.
+    pub kind: String,
+    pub metrics: Json<serde_json::Value>,
+    pub raised_at: DateTime<Utc>,
+    pub acknowledged_by: Option<Uuid>,
+    pub acknowledged_at: Option<DateTime<Utc>>,
+}
+
+pub async fn insert_surveillance_alert(
+    pool: &PgPool,
+    row: &SurveillanceAlertRow,
+) -> Result<(), sqlx::Error> {
+    let query = sqlx::query(
+        "INSERT INTO surveillance_alerts \
+         (id, user_id, symbol, kind, metrics, raised_at, acknowledged_by, acknowledged_at) \
+         VALUES ($1, $2, $3, $4, $5, $6, $7, $8)",
+    )
+    .bind(row.id)
+    .bind(row.user_id)
+    .bind(&row.symbol)
+    .bind(&row.kind)
+    .bind(&row.metrics)
+    .bind(row.raised_at)
+    .bind(row.acknowledged_by)
+    .bind(row.acknowledged_at)
+    .execute(pool);
+    timed("insert_surveillance_alert", query).await?;
+    Ok(())
+}
+
+/// The latest `limit` alerts, newest first, only those (not) acknowledged and of `user_id`
+/// when given.
+pub async fn list_surveillance_alerts(
+    pool: &PgPool,
+    acknowledged: Option<bool>,
+    user_id: Option<Uuid>,
+    limit: usize,
+) -> Result<Vec<SurveillanceAlertRow>, sqlx::Error> {
+    let query = sqlx::query_as::<_, SurveillanceAlertRow>(
+        "SELECT id, user_id, symbol, kind, metrics, raised_at, acknowledged_by, acknowledged_at \
+         FROM surveillance_alerts \
+         WHERE ($1::BOOLEAN IS NULL OR (acknowledged_at IS NOT NULL) = $1) \
+           AND ($2::UUID IS NULL OR user_id = $2) \
+         ORDER BY raised_at DESC, id LIMIT $3",
+    )
+    .bind(acknowledged)
+    .bind(user_id)
+    .bind(limit as i64)
+    .fetch_all(pool);
+    let rows = timed("list_surveillance_alerts", query).await?;
+    Ok(rows)
+}
+
+/// Acknowledge alert `id` for `admin_id` at `at` unless it already is, and return it as
+/// stored; None if there is no such alert.
+pub async fn acknowledge_surveillance_alert(
+    pool: &PgPool,
+    id: Uuid,
+    admin_id: Uuid,
+    at: DateTime<Utc>,
+) -> Result<Option<SurveillanceAlertRow>, sqlx::Error> {
+    let query = sqlx::query_as::<_, SurveillanceAlertRow>(
+        "UPDATE surveillance_alerts SET \
+             acknowledged_by = COALESCE(acknowledged_by, $2), \
+             acknowledged_at = COALESCE(acknowledged_at, $3) \
+         WHERE id = $1 \
+         RETURNING id, user_id, symbol, kind, metrics, raised_at, acknowledged_by, acknowledged_at",
+    )
+    .bind(id)
+    .bind(admin_id)
+    .bind(at)
+    .fetch_optional(pool);
+    let row = timed("acknowledge_surveillance_alert", query).await?;
+    Ok(row)
+}
@@ -0,0 +1,280 @@
+//! Symbol configuration persistence: load for hydration, save changes with an audit entry, and
+//! the versioned rules changes `GET /exchangeInfo/changes` lists.
+
+use chrono::{DateTime, Utc};
+use sqlx::types::Json;
+use sqlx::{FromRow, PgExecutor, PgPool};
+use uuid::Uuid;
+
+use super::timing::timed;
+use crate::orderbook::orderbook::PricingPolicy;
+use crate::types::asset::{self, Asset};
+use crate::types::symbol::{
+    Delisting, PositionSettlement, SymbolConfig, SymbolConfigPatch, SymbolStatus,
+};
+
+#[derive(Debug, FromRow)]
+pub struct SymbolConfigRow {
+    pub symbol: String,
+    pub tick_size: i64,
+    pub lot_size: i64,
+    pub min_qty: i64,
+    pub qty_scale: i64,
+    pub price_band_bps: Option<i32>,
+    pub maker_fee_bps: i32,
+    pub taker_fee_bps: i32,
+    pub halted: bool,
+    pub pricing_policy: String,
+    pub base_asset: Option<String>,
+    pub quote_asset: Option<String>,
+    pub status: String,
+    pub delist_at: Option<DateTime<Utc>>,
+    pub delist_positions: Option<String>,
+}
+
+fn pricing_policy_to_str(policy: PricingPolicy) -> &'static str {
+    match policy {
+        PricingPolicy::MakerPrice => "MakerPrice",
+        PricingPolicy::TakerPrice => "TakerPrice",
+        PricingPolicy::Midpoint => "Midpoint",
+    }
+}
+
+fn str_to_pricing_policy(s: &str) -> Option<PricingPolicy> {
+    match s {
+        "MakerPrice" => Some(PricingPolicy::MakerPrice),
+        "TakerPrice" => Some(PricingPolicy::TakerPrice),
+        "Midpoint" => Some(PricingPolicy::Midpoint),
+        _ => None,
+    }
+}
+
+/// Convert a row, rejecting values `SymbolConfig::apply` would not accept.
+pub fn symbol_config_row_to_config(row: &SymbolConfigRow) -> Result<SymbolConfig, String> {
+    let lot_size =
+        u64::try_from(row.lot_size).map_err(|_| format!("invalid lot_size {}", row.lot_size))?;
+    let min_qty =
+        u64::try_from(row.min_qty).map_err(|_| format!("invalid min_qty {}", row.min_qty))?;
+    let qty_scale = u64::try_from(row.qty_scale)
+        .map_err(|_| format!("invalid qty_scale {}", row.qty_scale))?;
+    let price_band_bps = row
+        .price_band_bps
+        .map(|band| u32::try_from(band).map_err(|_| format!("invalid price_band_bps {}", band)))
+        .transpose()?;
+    let pricing_policy = str_to_pricing_policy(&row.pricing_policy)
+        .ok_or_else(|| format!("invalid pricing_policy {}", row.pricing_policy))?;
+    // Rows saved before symbols had assets take the ones in the name
+    let assets = match (&row.base_asset, &row.quote_asset) {
+        (Some(base), Some(quote)) => Some((Asset::parse(base)?, Asset::parse(quote)?)),
+        (None, None) => asset::split_symbol(&row.symbol),
+        _ => return Err("base_asset and quote_asset must both be set".to_string()),
+    };
+    let status = SymbolStatus::parse(&row.status)
+        .ok_or_else(|| format!("invalid status {}", row.status))?;
+    let delisting = match (row.delist_at, &row.delist_positions) {
+        (Some(at), Some(positions)) => Some(Delisting {
+            at,
+            positions: PositionSettlement::parse(positions)
+                .ok_or_else(|| format!("invalid delist_positions {}", positions))?,
+        }),
+        (None, None) => None,
+        _ => return Err("delist_at and delist_positions must both be set".to_string()),
+    };
+    let config = SymbolConfig {
+        status,
+        delisting,
+        ..SymbolConfig::default()
+    }
+    .apply(&SymbolConfigPatch {
+        tick_size: Some(row.tick_size),
+        lot_size: Some(lot_size),
+        min_qty: Some(min_qty),
+        qty_scale: Some(qty_scale),
+        price_band_bps: Some(price_band_bps),
+        maker_fee_bps: Some(row.maker_fee_bps),
+        taker_fee_bps: Some(row.taker_fee_bps),
+        halted: Some(row.halted),
+        pricing_policy: Some(pricing_policy),
+    })?;
+    match assets {
+        Some((base, quote)) => config.with_assets(base, quote),
+        None => Ok(config),
+    }
+}
+
+/// List all stored symbol configs for hydration.
+pub async fn list_symbol_configs(pool: &PgPool) -> Result<Vec<SymbolConfigRow>, sqlx::Error> {
+    let query = sqlx::query_as::<_, SymbolConfigRow>(
+        "SELECT symbol, tick_size, lot_size, min_qty, qty_scale, price_band_bps, maker_fee_bps, \
+         taker_fee_bps, halted, pricing_policy, base_asset, quote_asset, status, delist_at, \
+         delist_positions FROM symbols",
+    )
+    .fetch_all(pool);
+    let rows = timed("list_symbol_configs", query).await?;
+    Ok(rows)
+}
+
+/// Every symbol with a stored config, which a server serves unless told which ones to.
+pub async fn list_stored_symbols(pool: &PgPool) -> Result<Vec<String>, sqlx::Error> {
+    let query = sqlx::query_scalar::<_, String>("SELECT symbol FROM symbols ORDER BY symbol")
+        .fetch_all(pool);
+    let symbols = timed("list_stored_symbols", query).await?;
+    Ok(symbols)
+}
+
+/// Insert or replace the stored config for `symbol`.
+pub async fn upsert_symbol_config(
+    executor: impl PgExecutor<'_>,
+    symbol: &str,
+    config: &SymbolConfig,
+) -> Result<(), sqlx::Error> {
+    let query = sqlx::query(
+        "INSERT INTO symbols (symbol, tick_size, lot_size, price_band_bps, maker_fee_bps, taker_fee_bps, halted, pricing_policy, min_qty, qty_scale, base_asset, quote_asset, status, delist_at, delist_positions, updated_at) \
+         VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, NOW()) \
+         ON CONFLICT (symbol) DO UPDATE SET tick_size = $2, lot_size = $3, price_band_bps = $4, \
+         maker_fee_bps = $5, taker_fee_bps = $6, halted = $7, pricing_policy = $8, min_qty = $9, \
+         qty_scale = $10, base_asset = $11, quote_asset = $12, status = $13, delist_at = $14, \
+         delist_positions = $15, updated_at = NOW()",
+    )
+    .bind(symbol)
+    .bind(config.tick_size)
+    .bind(config.lot_size as i64)
+    .bind(config.price_band_bps.map(|band| band as i32))
+    .bind(config.maker_fee_bps)
+    .bind(config.taker_fee_bps)
+    .bind(config.halted)
+    .bind(pricing_policy_to_str(config.pricing_policy))
+    .bind(config.min_qty as i64)
+    .bind(config.qty_scale as i64)
+    .bind(config.base_asset.map(|a| a.to_string()))
+    .bind(config.quote_asset.map(|a| a.to_string()))
+    .bind(config.status.as_str())
+    .bind(config.delisting.map(|d| d.at))
+    .bind(config.delisting.map(|d| d.positions.as_str()))
+    .execute(executor);
+    timed("upsert_symbol_config", query).await?;
+    Ok(())
+}
+
+#[derive(Debug, FromRow)]
+pub struct SymbolConfigAuditRow {
+    pub symbol: String,
+    pub admin_user_id: Uuid,
+    pub changes: Json<SymbolConfigPatch>,
+    pub previous: Json<SymbolConfig>,
+    pub changed_at: DateTime<Utc>,
+}
+
+/// Record who changed `symbol`'s config, the fields they sent and the config it replaced.
+pub async fn insert_symbol_config_audit(
+    executor: impl PgExecutor<'_>,
+    symbol: &str,
+    admin_user_id: Uuid,
+    changes: &SymbolConfigPatch,
+    previous: &SymbolConfig,
+) -> Result<(), sqlx::Error> {
+    let query = sqlx::query(
+        "INSERT INTO symbol_config_audit (symbol, admin_user_id, changes, previous) \
+         VALUES ($1, $2, $3, $4)",
+    )
+    .bind(symbol)
+    .bind(admin_user_id)
+    .bind(Json(changes))
+    .bind(Json(previous))
+    .execute(executor);
+    timed("insert_symbol_config_audit", query).await?;
+    Ok(())
+}
+
+/// Audit entries for `symbol`, newest first.
+pub async fn list_symbol_config_audit(
+    pool: &PgPool,
+    symbol: &str,
+) -> Result<Vec<SymbolConfigAuditRow>, sqlx::Error> {
+    let query = sqlx::query_as::<_, SymbolConfigAuditRow>(
+        "SELECT symbol, admin_user_id, changes, previous, changed_at FROM symbol_config_audit \
+         WHERE symbol = $1 ORDER BY changed_at DESC, id DESC",
+    )
+    .bind(symbol)
+    .fetch_all(pool);
+    let rows = timed("list_symbol_config_audit", query).await?;
+    Ok(rows)
+}
+
+/// One stored rules change (see `api::exchange_info`).
+#[derive(Debug, FromRow)]
+pub struct RuleChangeRow {
+    pub version: i64,
+    pub symbol: String,
+    pub source: String,
+    pub changed_by: Option<Uuid>,
+    pub changed_at: DateTime<Utc>,
+    /// Field name to `{from, to}`.
+    pub changes: Json<serde_json::Value>,
+}
+
+/// Store a rules change and move `rules_version` to its version, in one statement; run it in
+/// the transaction that saves the config it describes.
+pub async fn insert_rule_change(
+    executor: impl PgExecutor<'_>,
+    row: &RuleChangeRow,
+) -> Result<(), sqlx::Error> {
+    let query = sqlx::query(
+        "WITH bumped AS ( \
+             UPDATE exchange_metadata SET value = GREATEST(value, $1) WHERE key = 'rules_version') \
+         INSERT INTO symbol_rule_changes (version, symbol, source, changed_by, changed_at, changes) \
+         VALUES ($1, $2, $3, $4, $5, $6)",
+    )
+    .bind(row.version)
+    .bind(&row.symbol)
+    .bind(&row.source)
+    .bind(row.changed_by)
+    .bind(row.changed_at)
+    .bind(&row.changes)
+    .execute(executor);
+    timed("insert_rule_change", query).await?;
+    Ok(())
+}
+
+/// The stored `rules_version` (0 before any change).
+pub async fn get_rules_version(pool: &PgPool) -> Result<i64, sqlx::Error> {
+    let query = sqlx::query_scalar(
+        "SELECT COALESCE((SELECT value FROM exchange_metadata WHERE key = 'rules_version'), 0)",
+    )
+    .fetch_one(pool);
+    let version: i64 = timed("get_rules_version", query).await?;
+    Ok(version)
+}
+
+/// The `limit` most recent rules changes, oldest first.
+pub async fn list_recent_rule_changes(
+    pool: &PgPool,
+    limit: usize,
+) -> Result<Vec<RuleChangeRow>, sqlx::Error> {
+    let query = sqlx::query_as::<_, RuleChangeRow>(
+        "SELECT version, symbol, source, changed_by, changed_at, changes FROM ( \
+             SELECT * FROM symbol_rule_changes ORDER BY version DESC LIMIT $1) AS recent \
+         ORDER BY version",
+    )
+    .bind(limit as i64)
+    .fetch_all(pool);
+    let rows = timed("list_recent_rule_changes", query).await?;
+    Ok(rows)
+}
+
+/// Up to `limit` rules changes after `version`, oldest first.
+pub async fn list_rule_changes_since(
+    pool: &PgPool,
+    version: i64,
+    limit: usize,
+) -> Result<Vec<RuleChangeRow>, sqlx::Error> {
+    let query = sqlx::query_as::<_, RuleChangeRow>(
+        "SELECT version, symbol, source, changed_by, changed_at, changes FROM symbol_rule_changes \
+         WHERE version > $1 ORDER BY version LIMIT $2",
+    )
+    .bind(version)
+    .bind(limit as i64)
+    .fetch_all(pool);
+    let rows = timed("list_rule_changes_since", query).await?;
+    Ok(rows)
+}
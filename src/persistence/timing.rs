@@ -0,0 +1,84 @@
+//! Query timing: every persistence call is measured; calls slower than the configured
+//! threshold are logged, and all calls feed a per-query latency histogram.
+
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use crate::faults;
+
+/// Upper bounds (ms) of the histogram buckets; a final bucket counts everything slower.
+pub const QUERY_LATENCY_BUCKETS_MS: [u64; 8] = [1, 5, 10, 50, 100, 500, 1000, 5000];
+
+static SLOW_QUERY_THRESHOLD_MS: AtomicU64 = AtomicU64::new(500);
+static QUERY_HISTOGRAMS: Mutex<BTreeMap<&'static str, QueryHistogram>> =
+    Mutex::new(BTreeMap::new());
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct QueryHistogram {
+    pub count: u64,
+    pub total_us: u64,
+    pub max_us: u64,
+    /// Counts per `QUERY_LATENCY_BUCKETS_MS` bucket, plus one overflow bucket.
+    pub buckets: Vec<u64>,
+}
+
+impl QueryHistogram {
+    fn record(&mut self, elapsed: Duration) {
+        if self.buckets.is_empty() {
+            self.buckets = vec![0; QUERY_LATENCY_BUCKETS_MS.len() + 1];
+        }
+        let us = elapsed.as_micros() as u64;
+        let ms = elapsed.as_millis() as u64;
+        let bucket = QUERY_LATENCY_BUCKETS_MS
+            .iter()
+            .position(|&bound| ms < bound)
+            .unwrap_or(QUERY_LATENCY_BUCKETS_MS.len());
+        self.buckets[bucket] += 1;
+        self.count += 1;
+        self.total_us += us;
+        self.max_us = self.max_us.max(us);
+    }
+}
+
+/// Log queries slower than `threshold` (default 500ms).
+pub fn set_slow_query_threshold(threshold: Duration) {
+    SLOW_QUERY_THRESHOLD_MS.store(threshold.as_millis() as u64, Ordering::Relaxed);
+}
+
+/// Latency histograms per persistence function, keyed by function name.
+pub fn query_histograms() -> BTreeMap<String, QueryHistogram> {
+    QUERY_HISTOGRAMS
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .iter()
+        .map(|(name, h)| (name.to_string(), h.clone()))
+        .collect()
+}
+
+/// Await `query`, recording its duration under `name` and logging it if slow. Injected
+/// faults (see `faults`) apply here, delays counting towards the duration.
+pub(crate) async fn timed<T, F>(name: &'static str, query: F) -> Result<T, sqlx::Error>
+where
+    F: Future<Output = Result<T, sqlx::Error>>,
+{
+    let start = Instant::now();
+    let output = match faults::before_query(name).await {
+        Ok(()) => query.await,
+        Err(e) => Err(e),
+    };
+    let elapsed = start.elapsed();
+    if elapsed.as_millis() as u64 >= SLOW_QUERY_THRESHOLD_MS.load(Ordering::Relaxed) {
+        eprintln!("slow query {}: {}ms", name, elapsed.as_millis());
+    }
+    QUERY_HISTOGRAMS
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .entry(name)
+        .or_default()
+        .record(elapsed);
+    output
+}
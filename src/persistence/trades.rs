@@ -1,14 +1,21 @@
-//! Trade persistence: insert on match, list for API.
+//! Trade persistence: insert on match, list for API, mark busted.
 
 use chrono::{DateTime, Utc};
-use sqlx::{FromRow, PgPool};
+use serde_json::Value;
+use sqlx::{FromRow, PgExecutor, PgPool};
+use std::collections::HashMap;
 use uuid::Uuid;
 
-use crate::types::trade::Trade;
+use super::orders::{side_to_str, str_to_side};
+use super::timing::timed;
+use crate::market_data::StatsBucket;
+use crate::types::environment::Environment;
+use crate::types::trade::{Trade, TradeAttribution, TradeBust, UserTrade};
 
 #[derive(Debug, FromRow)]
 pub struct TradeRow {
     pub id: Uuid,
+    pub trade_seq: i64,
     pub maker_order_id: Uuid,
     pub taker_order_id: Uuid,
     pub maker_user_id: Uuid,
@@ -18,11 +25,58 @@ pub struct TradeRow {
     pub price: i64,
     pub quantity: i64,
     pub created_at: DateTime<Utc>,
+    pub maker_queue_rank: Option<i32>,
+}
+
+/// A trade with the fee and realized P&L columns, for per-user listings.
+#[derive(Debug, FromRow)]
+pub struct UserTradeRow {
+    #[sqlx(flatten)]
+    pub trade: TradeRow,
+    /// NULL for trades stored before attribution whose taker order was gone.
+    pub taker_side: Option<String>,
+    pub maker_fee: i64,
+    pub taker_fee: i64,
+    pub realized_pnl_maker: i64,
+    pub realized_pnl_taker: i64,
+    pub busted_at: Option<DateTime<Utc>>,
+    pub bust_reason: Option<String>,
+}
+
+impl UserTradeRow {
+    pub fn to_trade(&self) -> Trade {
+        trade_row_to_trade(&self.trade)
+    }
+
+    pub fn attribution(&self) -> Option<TradeAttribution> {
+        let taker_side = self.taker_side.as_deref().and_then(str_to_side)?;
+        Some(TradeAttribution {
+            trade_id: self.trade.id,
+            taker_side,
+            maker_fee: self.maker_fee,
+            taker_fee: self.taker_fee,
+            realized_pnl_maker: self.realized_pnl_maker,
+            realized_pnl_taker: self.realized_pnl_taker,
+        })
+    }
+
+    pub fn bust(&self) -> Option<TradeBust> {
+        Some(TradeBust {
+            busted_at: self.busted_at?,
+            reason: self.bust_reason.clone().unwrap_or_default(),
+        })
+    }
+}
+
+fn user_trade_row_to_user_trade(row: &UserTradeRow, user_id: Uuid) -> UserTrade {
+    UserTrade::new(row.to_trade(), row.attribution().as_ref(), user_id)
+        .with_bust(row.bust().as_ref())
 }
 
 fn trade_row_to_trade(row: &TradeRow) -> Trade {
     Trade {
         id: row.id,
+        trade_seq: row.trade_seq as u64,
         maker_order_id: row.maker_order_id,
         taker_order_id: row.taker_order_id,
         maker_user_id: row.maker_user_id,
@@ -30,6 +84,20 @@ fn trade_row_to_trade(row: &TradeRow) -> Trade {
         price: row.price,
         quantity: row.quantity as u64,
         timestamp: row.created_at,
+        maker_queue_rank: row.maker_queue_rank.map(|rank| rank as u32),
+    }
+}
+
+/// Table expression for trade history: the hot table, or the hot table plus `trades_archive`.
+pub(super) fn trades_source(include_archived: bool) -> &'static str {
+    if include_archived {
+        "(SELECT id, trade_seq, maker_order_id, taker_order_id, maker_user_id, taker_user_id, symbol, price, quantity, created_at, maker_queue_rank, \
+         taker_side, maker_fee, taker_fee, realized_pnl_maker, realized_pnl_taker, busted_at, bust_reason, environment FROM trades \
+         UNION ALL \
+         SELECT id, trade_seq, maker_order_id, taker_order_id, maker_user_id, taker_user_id, symbol, price, quantity, created_at, maker_queue_rank, \
+         taker_side, maker_fee, taker_fee, realized_pnl_maker, realized_pnl_taker, busted_at, bust_reason, environment FROM trades_archive) AS trades"
+    } else {
+        "trades"
     }
 }
 
@@ -38,53 +106,284 @@ pub async fn list_trades(
     pool: &PgPool,
     symbol: &str,
     limit: usize,
+    include_archived: bool,
+) -> Result<Vec<Trade>, sqlx::Error> {
+    list_trades_before_seq(pool, symbol, None, limit, include_archived).await
+}
+
+/// List trades for a symbol newest first, only those with `trade_seq < before_seq` when it is
+/// given (the keyset of GET /trades pages). Busted trades are left out.
+pub async fn list_trades_before_seq(
+    pool: &PgPool,
+    symbol: &str,
+    before_seq: Option<u64>,
+    limit: usize,
+    include_archived: bool,
+) -> Result<Vec<Trade>, sqlx::Error> {
+    let sql = format!(
+        "SELECT id, trade_seq, maker_order_id, taker_order_id, maker_user_id, taker_user_id, symbol, price, quantity, created_at, maker_queue_rank \
+         FROM {} WHERE symbol = $1 AND ($2::BIGINT IS NULL OR trade_seq < $2) AND busted_at IS NULL \
+         ORDER BY trade_seq DESC LIMIT $3",
+        trades_source(include_archived)
+    );
+    let query = sqlx::query_as::<_, TradeRow>(&sql)
+        .bind(symbol)
+        .bind(before_seq.map(|seq| seq as i64))
+        .bind(limit as i64)
+        .fetch_all(pool);
+    let rows = timed("list_trades", query).await?;
+    Ok(rows.iter().map(trade_row_to_trade).collect())
+}
+
+/// List trades for a symbol with `trade_seq >= from_seq`, oldest first (for GET /trades?from_seq=).
+/// Busted trades are left out.
+pub async fn list_trades_from_seq(
+    pool: &PgPool,
+    symbol: &str,
+    from_seq: u64,
+    limit: usize,
+    include_archived: bool,
 ) -> Result<Vec<Trade>, sqlx::Error> {
-    let rows = sqlx::query_as::<_, TradeRow>(
-        "SELECT id, maker_order_id, taker_order_id, maker_user_id, taker_user_id, symbol, price, quantity, created_at \
-         FROM trades WHERE symbol = $1 ORDER BY created_at DESC LIMIT $2",
+    let sql = format!(
+        "SELECT id, trade_seq, maker_order_id, taker_order_id, maker_user_id, taker_user_id, symbol, price, quantity, created_at, maker_queue_rank \
+         FROM {} WHERE symbol = $1 AND trade_seq >= $2 AND busted_at IS NULL ORDER BY trade_seq LIMIT $3",
+        trades_source(include_archived)
+    );
+    let query = sqlx::query_as::<_, TradeRow>(&sql)
+        .bind(symbol)
+        .bind(from_seq as i64)
+        .bind(limit as i64)
+        .fetch_all(pool);
+    let rows = timed("list_trades_from_seq", query).await?;
+    Ok(rows.iter().map(trade_row_to_trade).collect())
+}
+
+/// Highest persisted trade sequence for a symbol (0 if none), to seed the book at startup.
+/// Archived trades count too, so sequences never repeat after a retention run.
+pub async fn max_trade_seq(pool: &PgPool, symbol: &str) -> Result<u64, sqlx::Error> {
+    let query = sqlx::query_scalar(
+        "SELECT GREATEST( \
+             (SELECT MAX(trade_seq) FROM trades WHERE symbol = $1), \
+             (SELECT MAX(trade_seq) FROM trades_archive WHERE symbol = $1))",
     )
     .bind(symbol)
-    .bind(limit as i64)
-    .fetch_all(pool)
-    .await?;
-    Ok(rows.iter().map(trade_row_to_trade).collect())
+    .fetch_one(pool);
+    let max: Option<i64> = timed("max_trade_seq", query).await?;
+    Ok(max.unwrap_or(0) as u64)
 }
 
-/// List trades for a user (maker or taker), optional symbol (for GET /trades/me).
+/// A symbol's unbusted trades of one candle interval, aggregated.
+#[derive(Debug, FromRow)]
+pub struct TradeStatsBucketRow {
+    pub open_time: DateTime<Utc>,
+    pub high: i64,
+    pub low: i64,
+    pub volume: i64,
+    pub trade_count: i64,
+}
+
+impl TradeStatsBucketRow {
+    pub fn to_bucket(&self) -> StatsBucket {
+        StatsBucket {
+            open_time: self.open_time,
+            high: self.high,
+            low: self.low,
+            volume: self.volume.max(0) as u64,
+            trade_count: self.trade_count.max(0) as u64,
+        }
+    }
+}
+
+/// `symbol`'s unbusted trades since `since`, aggregated per minute (the candle interval),
+/// oldest first; seeds the rolling ticker statistics.
+pub async fn list_trade_stats_buckets(
+    pool: &PgPool,
+    symbol: &str,
+    since: DateTime<Utc>,
+) -> Result<Vec<TradeStatsBucketRow>, sqlx::Error> {
+    let query = sqlx::query_as::<_, TradeStatsBucketRow>(
+        "SELECT date_trunc('minute', created_at) AS open_time, MAX(price) AS high,          MIN(price) AS low, SUM(quantity)::BIGINT AS volume, COUNT(*) AS trade_count          FROM trades WHERE symbol = $1 AND created_at >= $2 AND busted_at IS NULL          GROUP BY 1 ORDER BY 1",
+    )
+    .bind(symbol)
+    .bind(since)
+    .fetch_all(pool);
+    let rows = timed("list_trade_stats_buckets", query).await?;
+    Ok(rows)
+}
+
+/// List trades for a user (maker or taker) in `environment`, optional symbol (for GET
+/// /trades/me), newest first, each with the user's role, side, fee and realized P&L (busted trades included and
+/// marked). Trades of one match share
+/// `created_at`, so `trade_seq` and then the id break ties; `before` is the (created_at,
+/// trade_seq, id) keyset to continue after.
 pub async fn list_trades_for_user(
     pool: &PgPool,
     user_id: Uuid,
     symbol_opt: Option<&str>,
+    environment: Environment,
+    before: Option<(DateTime<Utc>, u64, Uuid)>,
     limit: usize,
-) -> Result<Vec<Trade>, sqlx::Error> {
-    let rows = if let Some(symbol) = symbol_opt {
-        sqlx::query_as::<_, TradeRow>(
-            "SELECT id, maker_order_id, taker_order_id, maker_user_id, taker_user_id, symbol, price, quantity, created_at \
-             FROM trades WHERE (maker_user_id = $1 OR taker_user_id = $1) AND symbol = $2 ORDER BY created_at DESC LIMIT $3",
-        )
-        .bind(user_id)
-        .bind(symbol)
-        .bind(limit as i64)
-        .fetch_all(pool)
-        .await?
-    } else {
-        sqlx::query_as::<_, TradeRow>(
-            "SELECT id, maker_order_id, taker_order_id, maker_user_id, taker_user_id, symbol, price, quantity, created_at \
-             FROM trades WHERE maker_user_id = $1 OR taker_user_id = $1 ORDER BY created_at DESC LIMIT $2",
-        )
+    include_archived: bool,
+) -> Result<Vec<UserTrade>, sqlx::Error> {
+    let sql = user_trades_sql(include_archived);
+    let query = sqlx::query_as::<_, UserTradeRow>(&sql)
         .bind(user_id)
+        .bind(symbol_opt)
+        .bind(before.map(|(created_at, _, _)| created_at))
+        .bind(before.map(|(_, seq, _)| seq as i64))
+        .bind(before.map(|(_, _, id)| id))
         .bind(limit as i64)
-        .fetch_all(pool)
-        .await?
-    };
-    Ok(rows.iter().map(trade_row_to_trade).collect())
+        .bind(environment.as_str())
+        .fetch_all(pool);
+    let rows = timed("list_trades_for_user", query).await?;
+    Ok(rows
+        .iter()
+        .map(|row| user_trade_row_to_user_trade(row, user_id))
+        .collect())
+}
+
+/// The query of `list_trades_for_user`. `maker_user_id = $1 OR taker_user_id = $1` cannot be
+/// answered from one index, so it is two branches, each a limited walk of its side's
+/// (user, created_at, trade_seq, id) index, merged and limited again. A self-trade matches
+/// both; the taker branch leaves it to the maker one.
+pub(super) fn user_trades_sql(include_archived: bool) -> String {
+    const COLUMNS: &str = "id, trade_seq, maker_order_id, taker_order_id, maker_user_id, taker_user_id, symbol, price, quantity, created_at, \
+         maker_queue_rank, taker_side, maker_fee, taker_fee, realized_pnl_maker, realized_pnl_taker, busted_at, bust_reason";
+    const FILTER: &str = "($2::TEXT IS NULL OR symbol = $2) AND environment = $7 \
+         AND ($3::TIMESTAMPTZ IS NULL OR (created_at, trade_seq, id) < ($3, $4, $5))";
+    const ORDER: &str = "ORDER BY created_at DESC, trade_seq DESC, id DESC LIMIT $6";
+    let source = trades_source(include_archived);
+    format!(
+        "SELECT {columns} FROM ( \
+         (SELECT {columns} FROM {source} WHERE maker_user_id = $1 AND {filter} {order}) \
+         UNION ALL \
+         (SELECT {columns} FROM {source} WHERE taker_user_id = $1 AND maker_user_id <> $1 AND {filter} {order}) \
+         ) AS mine {order}",
+        columns = COLUMNS,
+        source = source,
+        filter = FILTER,
+        order = ORDER,
+    )
+}
+
+/// A stored trade by id, hot or archived, with its attribution and bust (see `UserTradeRow`).
+pub async fn find_trade(pool: &PgPool, id: Uuid) -> Result<Option<UserTradeRow>, sqlx::Error> {
+    let sql = format!(
+        "SELECT id, trade_seq, maker_order_id, taker_order_id, maker_user_id, taker_user_id, symbol, price, quantity, created_at, \
+         maker_queue_rank, taker_side, maker_fee, taker_fee, realized_pnl_maker, realized_pnl_taker, busted_at, bust_reason \
+         FROM {} WHERE id = $1",
+        trades_source(true)
+    );
+    let query = sqlx::query_as::<_, UserTradeRow>(&sql)
+        .bind(id)
+        .fetch_optional(pool);
+    let row = timed("find_trade", query).await?;
+    Ok(row)
+}
+
+/// Trades order `order_id` took part in as maker or taker, hot or archived, oldest first, with
+/// their attribution (for GET /orders/{id}/fills). Busted trades are left out; they no longer
+/// fill anything.
+pub async fn list_trades_for_order(
+    pool: &PgPool,
+    order_id: Uuid,
+) -> Result<Vec<UserTradeRow>, sqlx::Error> {
+    let sql = format!(
+        "SELECT id, trade_seq, maker_order_id, taker_order_id, maker_user_id, taker_user_id, symbol, price, quantity, created_at, \
+         maker_queue_rank, taker_side, maker_fee, taker_fee, realized_pnl_maker, realized_pnl_taker, busted_at, bust_reason \
+         FROM {} WHERE (maker_order_id = $1 OR taker_order_id = $1) AND busted_at IS NULL \
+         ORDER BY trade_seq",
+        trades_source(true)
+    );
+    let query = sqlx::query_as::<_, UserTradeRow>(&sql)
+        .bind(order_id)
+        .fetch_all(pool);
+    let rows = timed("list_trades_for_order", query).await?;
+    Ok(rows)
+}
+
+/// Mark a trade busted, wherever it is stored. Returns false when it is missing or was
+/// already busted, so two concurrent busts cannot both succeed.
+pub async fn mark_trade_busted(
+    executor: impl PgExecutor<'_>,
+    id: Uuid,
+    bust: &TradeBust,
+) -> Result<bool, sqlx::Error> {
+    let query = sqlx::query_scalar(
+        "WITH hot AS (UPDATE trades SET busted_at = $2, bust_reason = $3 \
+             WHERE id = $1 AND busted_at IS NULL RETURNING id), \
+         cold AS (UPDATE trades_archive SET busted_at = $2, bust_reason = $3 \
+             WHERE id = $1 AND busted_at IS NULL RETURNING id) \
+         SELECT (SELECT COUNT(*) FROM hot) + (SELECT COUNT(*) FROM cold)",
+    )
+    .bind(id)
+    .bind(bust.busted_at)
+    .bind(&bust.reason)
+    .fetch_one(executor);
+    let marked: i64 = timed("mark_trade_busted", query).await?;
+    Ok(marked > 0)
 }
 
-/// Insert a single trade (call after each match).
+#[derive(Debug, FromRow)]
+pub struct TradeBustAuditRow {
+    pub trade_id: Uuid,
+    pub symbol: String,
+    pub admin_user_id: Uuid,
+    pub reason: String,
+    /// The trade as it was stored before the bust.
+    pub trade: sqlx::types::Json<Value>,
+    /// Both parties' positions after the reversal.
+    pub positions: sqlx::types::Json<Value>,
+    pub busted_at: DateTime<Utc>,
+}
+
+/// Record that `admin_user_id` busted `trade` on `symbol`, leaving `positions`.
+pub async fn insert_trade_bust_audit(
+    executor: impl PgExecutor<'_>,
+    symbol: &str,
+    admin_user_id: Uuid,
+    trade: &Trade,
+    bust: &TradeBust,
+    positions: &Value,
+) -> Result<(), sqlx::Error> {
+    let query = sqlx::query(
+        "INSERT INTO trade_bust_audit (trade_id, symbol, admin_user_id, reason, trade, positions, busted_at) \
+         VALUES ($1, $2, $3, $4, $5, $6, $7)",
+    )
+    .bind(trade.id)
+    .bind(symbol)
+    .bind(admin_user_id)
+    .bind(&bust.reason)
+    .bind(sqlx::types::Json(trade))
+    .bind(sqlx::types::Json(positions))
+    .bind(bust.busted_at)
+    .execute(executor);
+    timed("insert_trade_bust_audit", query).await?;
+    Ok(())
+}
+
+/// Busts on `symbol`, newest first.
+pub async fn list_trade_bust_audit(
+    pool: &PgPool,
+    symbol: &str,
+) -> Result<Vec<TradeBustAuditRow>, sqlx::Error> {
+    let query = sqlx::query_as::<_, TradeBustAuditRow>(
+        "SELECT trade_id, symbol, admin_user_id, reason, trade, positions, busted_at \
+         FROM trade_bust_audit WHERE symbol = $1 ORDER BY busted_at DESC, id DESC",
+    )
+    .bind(symbol)
+    .fetch_all(pool);
+    let rows = timed("list_trade_bust_audit", query).await?;
+    Ok(rows)
+}
+
+/// Insert a single trade (call after each match). Idempotent: a trade whose id is already
+/// stored is skipped. Returns the number of rows inserted (0 or 1).
 #[allow(clippy::too_many_arguments)]
 pub async fn insert_trade(
-    pool: &PgPool,
+    executor: impl PgExecutor<'_>,
     id: Uuid,
+    trade_seq: u64,
     maker_order_id: Uuid,
     taker_order_id: Uuid,
     maker_user_id: Uuid,
@@ -93,12 +392,14 @@ pub async fn insert_trade(
     price: i64,
     quantity: u64,
     created_at: DateTime<Utc>,
-) -> Result<(), sqlx::Error> {
-    sqlx::query(
-        "INSERT INTO trades (id, maker_order_id, taker_order_id, maker_user_id, taker_user_id, symbol, price, quantity, created_at) \
-         VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)",
+) -> Result<u64, sqlx::Error> {
+    let query = sqlx::query(
+        "INSERT INTO trades (id, trade_seq, maker_order_id, taker_order_id, maker_user_id, taker_user_id, symbol, price, quantity, created_at) \
+         VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10) \
+         ON CONFLICT (id) DO NOTHING",
     )
     .bind(id)
+    .bind(trade_seq as i64)
     .bind(maker_order_id)
     .bind(taker_order_id)
     .bind(maker_user_id)
@@ -107,7 +408,108 @@ pub async fn insert_trade(
     .bind(price)
     .bind(quantity as i64)
     .bind(created_at)
-    .execute(pool)
-    .await?;
-    Ok(())
+    .execute(executor);
+    let result = timed("insert_trade", query).await?;
+    Ok(result.rows_affected())
+}
+
+/// Insert a batch of trades for one symbol in a single statement, skipping ids already stored.
+/// Each trade's fees and realized P&L come from its entry in `attributions` (zero and no
+/// taker side when it has none). Returns the number of rows inserted; `trades.len()` minus
+/// that is the duplicates skipped.
+pub async fn insert_trades_bulk(
+    executor: impl PgExecutor<'_>,
+    symbol: &str,
+    trades: &[Trade],
+    attributions: &[TradeAttribution],
+) -> Result<u64, sqlx::Error> {
+    if trades.is_empty() {
+        return Ok(0);
+    }
+    let by_id: HashMap<Uuid, &TradeAttribution> =
+        attributions.iter().map(|a| (a.trade_id, a)).collect();
+    let attribution = |t: &Trade| by_id.get(&t.id).copied();
+    let query = sqlx::query(
+        "INSERT INTO trades (id, trade_seq, maker_order_id, taker_order_id, maker_user_id, taker_user_id, symbol, price, quantity, created_at, maker_queue_rank, \
+         taker_side, maker_fee, taker_fee, realized_pnl_maker, realized_pnl_taker) \
+         SELECT t.id, t.trade_seq, t.maker_order_id, t.taker_order_id, t.maker_user_id, t.taker_user_id, $1, t.price, t.quantity, t.created_at, \
+         t.maker_queue_rank, t.taker_side, t.maker_fee, t.taker_fee, t.realized_pnl_maker, t.realized_pnl_taker \
+         FROM UNNEST($2::UUID[], $3::BIGINT[], $4::UUID[], $5::UUID[], $6::UUID[], $7::UUID[], $8::BIGINT[], $9::BIGINT[], $10::TIMESTAMPTZ[], \
+         $11::INTEGER[], $12::TEXT[], $13::BIGINT[], $14::BIGINT[], $15::BIGINT[], $16::BIGINT[]) \
+         AS t(id, trade_seq, maker_order_id, taker_order_id, maker_user_id, taker_user_id, price, quantity, created_at, \
+         maker_queue_rank, taker_side, maker_fee, taker_fee, realized_pnl_maker, realized_pnl_taker) \
+         ON CONFLICT (id) DO NOTHING",
+    )
+    .bind(symbol)
+    .bind(trades.iter().map(|t| t.id).collect::<Vec<_>>())
+    .bind(trades.iter().map(|t| t.trade_seq as i64).collect::<Vec<_>>())
+    .bind(trades.iter().map(|t| t.maker_order_id).collect::<Vec<_>>())
+    .bind(trades.iter().map(|t| t.taker_order_id).collect::<Vec<_>>())
+    .bind(trades.iter().map(|t| t.maker_user_id).collect::<Vec<_>>())
+    .bind(trades.iter().map(|t| t.taker_user_id).collect::<Vec<_>>())
+    .bind(trades.iter().map(|t| t.price).collect::<Vec<_>>())
+    .bind(trades.iter().map(|t| t.quantity as i64).collect::<Vec<_>>())
+    .bind(trades.iter().map(|t| t.timestamp).collect::<Vec<_>>())
+    .bind(
+        trades
+            .iter()
+            .map(|t| t.maker_queue_rank.map(|rank| rank as i32))
+            .collect::<Vec<_>>(),
+    )
+    .bind(
+        trades
+            .iter()
+            .map(|t| attribution(t).map(|a| side_to_str(a.taker_side)))
+            .collect::<Vec<_>>(),
+    )
+    .bind(
+        trades
+            .iter()
+            .map(|t| attribution(t).map_or(0, |a| a.maker_fee))
+            .collect::<Vec<_>>(),
+    )
+    .bind(
+        trades
+            .iter()
+            .map(|t| attribution(t).map_or(0, |a| a.taker_fee))
+            .collect::<Vec<_>>(),
+    )
+    .bind(
+        trades
+            .iter()
+            .map(|t| attribution(t).map_or(0, |a| a.realized_pnl_maker))
+            .collect::<Vec<_>>(),
+    )
+    .bind(
+        trades
+            .iter()
+            .map(|t| attribution(t).map_or(0, |a| a.realized_pnl_taker))
+            .collect::<Vec<_>>(),
+    )
+    .execute(executor);
+    let result = timed("insert_trades_bulk", query).await?;
+    Ok(result.rows_affected())
+}
+
+/// Ids from `expected_ids` with no row for `symbol` in `trades` or `trades_archive`, in input
+/// order, so a writer can re-insert only the missing trades.
+pub async fn reconcile_trades(
+    pool: &PgPool,
+    symbol: &str,
+    expected_ids: &[Uuid],
+) -> Result<Vec<Uuid>, sqlx::Error> {
+    if expected_ids.is_empty() {
+        return Ok(Vec::new());
+    }
+    let query = sqlx::query_scalar(
+        "SELECT e.id FROM UNNEST($2::UUID[]) WITH ORDINALITY AS e(id, ord) \
+         WHERE NOT EXISTS (SELECT 1 FROM trades t WHERE t.id = e.id AND t.symbol = $1) \
+           AND NOT EXISTS (SELECT 1 FROM trades_archive a WHERE a.id = e.id AND a.symbol = $1) \
+         ORDER BY e.ord",
+    )
+    .bind(symbol)
+    .bind(expected_ids)
+    .fetch_all(pool);
+    let missing: Vec<Uuid> = timed("reconcile_trades", query).await?;
+    Ok(missing)
 }
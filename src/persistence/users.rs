@@ -1,21 +1,33 @@
-//! User persistence: list and insert.
+//! User persistence: list, insert, login activity, rename with username history, and the
+//! account kill switch with its audit trail.
 
-use sqlx::{FromRow, PgPool};
+use chrono::{DateTime, Utc};
+use sqlx::{FromRow, PgExecutor, PgPool};
 use uuid::Uuid;
 
+use super::timing::timed;
+
 /// Row returned from DB (username is stored lowercase).
 #[derive(FromRow)]
 pub struct UserRow {
     pub id: Uuid,
     pub username: String,
     pub password_hash: String,
+    pub created_at: DateTime<Utc>,
+    pub last_login_at: Option<DateTime<Utc>>,
+    pub login_count: i64,
+    /// The user engaged the account kill switch (see `api::kill_switch`).
+    pub trading_disabled: bool,
 }
 
+const USER_COLUMNS: &str =
+    "id, username, password_hash, created_at, last_login_at, login_count, trading_disabled";
+
 /// List all users (username is lowercase in DB).
 pub async fn list_users(pool: &PgPool) -> Result<Vec<UserRow>, sqlx::Error> {
-    let rows = sqlx::query_as::<_, UserRow>("SELECT id, username, password_hash FROM users")
-        .fetch_all(pool)
-        .await?;
+    let sql = format!("SELECT {} FROM users", USER_COLUMNS);
+    let query = sqlx::query_as::<_, UserRow>(&sql).fetch_all(pool);
+    let rows = timed("list_users", query).await?;
     Ok(rows)
 }
 
@@ -24,27 +36,145 @@ pub async fn get_user_by_username(
     pool: &PgPool,
     username_lowercase: &str,
 ) -> Result<Option<UserRow>, sqlx::Error> {
-    let row = sqlx::query_as::<_, UserRow>(
-        "SELECT id, username, password_hash FROM users WHERE username = $1",
-    )
-    .bind(username_lowercase)
-    .fetch_optional(pool)
-    .await?;
+    let sql = format!("SELECT {} FROM users WHERE username = $1", USER_COLUMNS);
+    let query = sqlx::query_as::<_, UserRow>(&sql)
+        .bind(username_lowercase)
+        .fetch_optional(pool);
+    let row = timed("get_user_by_username", query).await?;
     Ok(row)
 }
 
-/// Insert a user. Username must already be lowercase.
+/// Insert a user registered at `created_at`. Username must already be lowercase.
 pub async fn insert_user(
     pool: &PgPool,
     id: Uuid,
     username: &str,
     password_hash: &str,
+    created_at: DateTime<Utc>,
+) -> Result<(), sqlx::Error> {
+    let query = sqlx::query(
+        "INSERT INTO users (id, username, password_hash, created_at) VALUES ($1, $2, $3, $4)",
+    )
+    .bind(id)
+    .bind(username)
+    .bind(password_hash)
+    .bind(created_at)
+    .execute(pool);
+    timed("insert_user", query).await?;
+    Ok(())
+}
+
+/// Record a login by `id` at `at`; returns the user's login count including this one.
+pub async fn touch_last_login(
+    pool: &PgPool,
+    id: Uuid,
+    at: DateTime<Utc>,
+) -> Result<i64, sqlx::Error> {
+    let query = sqlx::query_scalar(
+        "UPDATE users SET last_login_at = $2, login_count = login_count + 1 WHERE id = $1 \
+         RETURNING login_count",
+    )
+    .bind(id)
+    .bind(at)
+    .fetch_one(pool);
+    let login_count = timed("touch_last_login", query).await?;
+    Ok(login_count)
+}
+
+/// Rename a user and record the old name in `username_history`, in one transaction.
+/// Both names must already be lowercase.
+pub async fn change_username(
+    pool: &PgPool,
+    id: Uuid,
+    old_username: &str,
+    new_username: &str,
+) -> Result<(), sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    let query = sqlx::query("UPDATE users SET username = $1 WHERE id = $2")
+        .bind(new_username)
+        .bind(id)
+        .execute(&mut *tx);
+    timed("change_username", query).await?;
+    let query = sqlx::query("INSERT INTO username_history (username, user_id) VALUES ($1, $2)")
+        .bind(old_username)
+        .bind(id)
+        .execute(&mut *tx);
+    timed("insert_username_history", query).await?;
+    tx.commit().await?;
+    Ok(())
+}
+
+/// User who released `username` (lowercase) most recently after `since`, if any.
+pub async fn username_released_by(
+    pool: &PgPool,
+    username: &str,
+    since: DateTime<Utc>,
+) -> Result<Option<Uuid>, sqlx::Error> {
+    let query = sqlx::query_scalar(
+        "SELECT user_id FROM username_history WHERE username = $1 AND released_at > $2 \
+         ORDER BY released_at DESC LIMIT 1",
+    )
+    .bind(username)
+    .bind(since)
+    .fetch_optional(pool);
+    let user_id = timed("username_released_by", query).await?;
+    Ok(user_id)
+}
+
+/// Set the kill switch flag of user `id`.
+pub async fn set_trading_disabled(
+    pool: &PgPool,
+    id: Uuid,
+    disabled: bool,
 ) -> Result<(), sqlx::Error> {
-    sqlx::query("INSERT INTO users (id, username, password_hash) VALUES ($1, $2, $3)")
+    let query = sqlx::query("UPDATE users SET trading_disabled = $2 WHERE id = $1")
         .bind(id)
-        .bind(username)
-        .bind(password_hash)
-        .execute(pool)
-        .await?;
+        .bind(disabled)
+        .execute(pool);
+    timed("set_trading_disabled", query).await?;
+    Ok(())
+}
+
+#[derive(Debug, FromRow)]
+pub struct KillSwitchAuditRow {
+    pub user_id: Uuid,
+    /// `engaged` or `disabled`.
+    pub action: String,
+    pub cancelled_orders: i32,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Record that `user_id` engaged (with the orders it cancelled) or disabled the kill switch.
+pub async fn insert_kill_switch_audit(
+    executor: impl PgExecutor<'_>,
+    user_id: Uuid,
+    action: &str,
+    cancelled_orders: usize,
+) -> Result<(), sqlx::Error> {
+    let query = sqlx::query(
+        "INSERT INTO kill_switch_audit (user_id, action, cancelled_orders) VALUES ($1, $2, $3)",
+    )
+    .bind(user_id)
+    .bind(action)
+    .bind(i32::try_from(cancelled_orders).unwrap_or(i32::MAX))
+    .execute(executor);
+    timed("insert_kill_switch_audit", query).await?;
     Ok(())
 }
+
+/// The latest `limit` kill switch changes of `user_id`, newest first.
+pub async fn list_kill_switch_audit(
+    pool: &PgPool,
+    user_id: Uuid,
+    limit: usize,
+) -> Result<Vec<KillSwitchAuditRow>, sqlx::Error> {
+    let query = sqlx::query_as::<_, KillSwitchAuditRow>(
+        "SELECT user_id, action, cancelled_orders, created_at FROM kill_switch_audit \
+         WHERE user_id = $1 ORDER BY created_at DESC, id DESC LIMIT $2",
+    )
+    .bind(user_id)
+    .bind(limit as i64)
+    .fetch_all(pool);
+    let rows = timed("list_kill_switch_audit", query).await?;
+    Ok(rows)
+}
@@ -0,0 +1,106 @@
+//! Webhook persistence: registrations (loaded at startup) and the delivery log.
+
+use chrono::{DateTime, Utc};
+use sqlx::{FromRow, PgPool};
+use uuid::Uuid;
+
+use super::timing::timed;
+
+#[derive(Debug, FromRow)]
+pub struct WebhookRow {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub url: String,
+    pub secret: String,
+    /// Event type names (see `webhooks::WebhookEventType`).
+    pub events: Vec<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, FromRow)]
+pub struct WebhookDeliveryRow {
+    pub id: Uuid,
+    pub webhook_id: Uuid,
+    pub event: String,
+    pub status: String,
+    pub attempts: i32,
+    pub response_status: Option<i32>,
+    pub error: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub finished_at: DateTime<Utc>,
+}
+
+/// Every registered webhook, oldest first.
+pub async fn list_webhooks(pool: &PgPool) -> Result<Vec<WebhookRow>, sqlx::Error> {
+    let query = sqlx::query_as::<_, WebhookRow>(
+        "SELECT id, user_id, url, secret, events, created_at FROM webhooks ORDER BY created_at, id",
+    )
+    .fetch_all(pool);
+    let rows = timed("list_webhooks", query).await?;
+    Ok(rows)
+}
+
+pub async fn insert_webhook(pool: &PgPool, row: &WebhookRow) -> Result<(), sqlx::Error> {
+    let query = sqlx::query(
+        "INSERT INTO webhooks (id, user_id, url, secret, events, created_at) \
+         VALUES ($1, $2, $3, $4, $5, $6)",
+    )
+    .bind(row.id)
+    .bind(row.user_id)
+    .bind(&row.url)
+    .bind(&row.secret)
+    .bind(&row.events)
+    .bind(row.created_at)
+    .execute(pool);
+    timed("insert_webhook", query).await?;
+    Ok(())
+}
+
+/// Delete a webhook and its delivery log.
+pub async fn delete_webhook(pool: &PgPool, id: Uuid) -> Result<(), sqlx::Error> {
+    let query = sqlx::query("DELETE FROM webhooks WHERE id = $1")
+        .bind(id)
+        .execute(pool);
+    timed("delete_webhook", query).await?;
+    Ok(())
+}
+
+pub async fn insert_webhook_delivery(
+    pool: &PgPool,
+    row: &WebhookDeliveryRow,
+) -> Result<(), sqlx::Error> {
+    let query = sqlx::query(
+        "INSERT INTO webhook_deliveries \
+         (id, webhook_id, event, status, attempts, response_status, error, created_at, finished_at) \
+         VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)",
+    )
+    .bind(row.id)
+    .bind(row.webhook_id)
+    .bind(&row.event)
+    .bind(&row.status)
+    .bind(row.attempts)
+    .bind(row.response_status)
+    .bind(&row.error)
+    .bind(row.created_at)
+    .bind(row.finished_at)
+    .execute(pool);
+    timed("insert_webhook_delivery", query).await?;
+    Ok(())
+}
+
+/// The last `limit` deliveries to `webhook_id`, newest first.
+pub async fn list_webhook_deliveries(
+    pool: &PgPool,
+    webhook_id: Uuid,
+    limit: i64,
+) -> Result<Vec<WebhookDeliveryRow>, sqlx::Error> {
+    let query = sqlx::query_as::<_, WebhookDeliveryRow>(
+        "SELECT id, webhook_id, event, status, attempts, response_status, error, created_at, finished_at \
+         FROM webhook_deliveries WHERE webhook_id = $1 ORDER BY finished_at DESC, id DESC LIMIT $2",
+    )
+    .bind(webhook_id)
+    .bind(limit)
+    .fetch_all(pool);
+    let rows = timed("list_webhook_deliveries", query).await?;
+    Ok(rows)
+}
@@ -1,82 +1,441 @@
-//! Position tracking: update_position, get_positions, unrealized_pnl.
+//! Position tracking: update_position, apply_trades, apply_fill, reverse_position,
+//! reverse_fill, realized_pnl, get_positions, get_positions_by_symbol, unrealized_pnl,
+//! scaled_unrealized_pnl.
 //! Testable without HTTP.
 
+use chrono::{DateTime, Utc};
 use std::collections::HashMap;
 use std::sync::Arc;
 use tokio::sync::RwLock;
 use uuid::Uuid;
 
+use crate::types::money::{MoneyError, Notional, average_price, descale, signed_notional};
 use crate::types::order::{OrderSide, Price, Qty};
 use crate::types::position::Position;
+use crate::types::symbol::Symbol;
+use crate::types::trade::Trade;
 
 pub type SharedPositions = Arc<RwLock<HashMap<(Uuid, String), Position>>>;
 
+/// Resulting state of one (user, symbol) position after applying fills. `closed` means the
+/// position went flat and was removed (`new_qty` is 0, `opened_at` is when the closed position
+/// opened).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PositionDelta {
+    pub user_id: Uuid,
+    pub symbol: String,
+    pub new_qty: i64,
+    pub new_avg: Price,
+    pub opened_at: DateTime<Utc>,
+    /// Time of the last fill applied.
+    pub updated_at: DateTime<Utc>,
+    pub closed: bool,
+}
+
+impl PositionDelta {
+    /// `closed_opened_at` is used when `position` is None, `at` is the time of the last fill.
+    fn from_result(
+        user_id: Uuid,
+        symbol: &str,
+        position: Option<&Position>,
+        closed_opened_at: DateTime<Utc>,
+        at: DateTime<Utc>,
+    ) -> Self {
+        match position {
+            Some(pos) => Self {
+                user_id,
+                symbol: symbol.to_string(),
+                new_qty: pos.quantity,
+                new_avg: pos.average_price,
+                opened_at: pos.opened_at,
+                updated_at: pos.updated_at,
+                closed: false,
+            },
+            None => Self {
+                user_id,
+                symbol: symbol.to_string(),
+                new_qty: 0,
+                new_avg: 0,
+                opened_at: closed_opened_at,
+                updated_at: at,
+                closed: true,
+            },
+        }
+    }
+
+    /// The resulting position (quantity 0 when closed).
+    pub fn position(&self) -> Position {
+        Position {
+            user_id: self.user_id,
+            symbol: self.symbol.clone(),
+            quantity: self.new_qty,
+            average_price: self.new_avg,
+            opened_at: self.opened_at,
+            updated_at: self.updated_at,
+        }
+    }
+}
+
 /// Apply one trade leg: update or create position. Buy adds to position, Sell reduces.
-/// Weighted average when adding; remove position when quantity becomes 0.
+/// Weighted average when adding; remove position when quantity becomes 0. `at` is the time of
+/// the fill. Returns the resulting state so callers can persist it without reading the store
+/// back.
 pub async fn update_position(
     store: &SharedPositions,
     user_id: Uuid,
-    symbol: &str,
+    symbol: &Symbol,
+    side: OrderSide,
+    trade_price: Price,
+    trade_qty: Qty,
+    at: DateTime<Utc>,
+) -> PositionDelta {
+    let mut guard = store.write().await;
+    let key = (user_id, symbol.to_string());
+    let previous = guard.get(&key);
+    let opened_at = previous.map_or(at, |pos| pos.opened_at);
+    let position = apply_fill(previous, user_id, symbol, side, trade_price, trade_qty, at);
+    let delta = PositionDelta::from_result(user_id, &key.1, position.as_ref(), opened_at, at);
+    match position {
+        Some(pos) => {
+            guard.insert(key, pos);
+        }
+        None => {
+            guard.remove(&key);
+        }
+    }
+    delta
+}
+
+/// Undo one trade leg (`side` is the side the user traded on) at time `at`, e.g. when the
+/// trade is busted, and return the resulting state like `update_position`.
+#[allow(clippy::too_many_arguments)]
+pub async fn reverse_position(
+    store: &SharedPositions,
+    user_id: Uuid,
+    symbol: &Symbol,
     side: OrderSide,
     trade_price: Price,
     trade_qty: Qty,
-) {
+    at: DateTime<Utc>,
+) -> PositionDelta {
     let mut guard = store.write().await;
-    let key = (user_id, symbol.to_uppercase());
+    let key = (user_id, symbol.to_string());
+    let previous = guard.get(&key);
+    let opened_at = previous.map_or(at, |pos| pos.opened_at);
+    let position = reverse_fill(previous, user_id, symbol, side, trade_price, trade_qty, at);
+    let delta = PositionDelta::from_result(user_id, &key.1, position.as_ref(), opened_at, at);
+    match position {
+        Some(pos) => {
+            guard.insert(key, pos);
+        }
+        None => {
+            guard.remove(&key);
+        }
+    }
+    delta
+}
+
+/// P&L realized by each trade passed to `apply_trades`, per counterparty.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RealizedPnl {
+    pub maker: i64,
+    pub taker: i64,
+}
+
+/// Outcome of `apply_trades`: one delta per affected user, and the P&L each trade realized
+/// (same order as the trades).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AppliedTrades {
+    pub deltas: Vec<PositionDelta>,
+    pub realized: Vec<RealizedPnl>,
+}
+
+/// Apply every leg of a taker order's fills under a single write lock: each trade's maker leg
+/// (opposite side) and the taker leg. Average price is path-dependent (integer division, flips),
+/// so each user's legs are folded in trade order rather than summed; the result is identical to
+/// calling `update_position` for maker then taker per trade, each at the trade's timestamp.
+/// Each leg's realized P&L is taken against the position it is applied to, so it is what that
+/// fill realized at the time.
+pub async fn apply_trades(
+    store: &SharedPositions,
+    taker_user_id: Uuid,
+    taker_side: OrderSide,
+    symbol: &Symbol,
+    trades: &[Trade],
+) -> AppliedTrades {
+    if trades.is_empty() {
+        return AppliedTrades::default();
+    }
+    let maker_side = taker_side.opposite();
+
+    // Legs per user, in the order sequential application would see them: (trade index,
+    // is maker, side, price, qty, time)
+    type Leg = (usize, bool, OrderSide, Price, Qty, DateTime<Utc>);
+    let mut legs: HashMap<Uuid, Vec<Leg>> = HashMap::new();
+    for (i, trade) in trades.iter().enumerate() {
+        legs.entry(trade.maker_user_id).or_default().push((
+            i,
+            true,
+            maker_side,
+            trade.price,
+            trade.quantity,
+            trade.timestamp,
+        ));
+        legs.entry(taker_user_id).or_default().push((
+            i,
+            false,
+            taker_side,
+            trade.price,
+            trade.quantity,
+            trade.timestamp,
+        ));
+    }
+
+    let mut deltas = Vec::with_capacity(legs.len());
+    let mut realized = vec![RealizedPnl::default(); trades.len()];
+    let mut guard = store.write().await;
+    for (user_id, user_legs) in legs {
+        let key = (user_id, symbol.to_string());
+        let mut position = guard.get(&key).cloned();
+        let mut last_fill = trades[0].timestamp;
+        let mut opened_at = last_fill;
+        for (i, is_maker, side, price, qty, at) in user_legs {
+            if let Some(pos) = &position {
+                opened_at = pos.opened_at;
+            }
+            let pnl = realized_pnl(position.as_ref(), side, price, qty);
+            if is_maker {
+                realized[i].maker = pnl;
+            } else {
+                realized[i].taker = pnl;
+            }
+            position = apply_fill(position.as_ref(), user_id, symbol, side, price, qty, at);
+            last_fill = at;
+        }
+        deltas.push(PositionDelta::from_result(
+            user_id,
+            symbol,
+            position.as_ref(),
+            opened_at,
+            last_fill,
+        ));
+        match position {
+            Some(pos) => {
+                guard.insert(key, pos);
+            }
+            None => {
+                guard.remove(&key);
+            }
+        }
+    }
+    AppliedTrades { deltas, realized }
+}
+
+/// P&L one trade leg realizes against `current`: the part of the fill that reduces the
+/// position, valued against its average price. Adding to (or opening) a position realizes
+/// nothing; a flip realizes only the closed quantity. Saturates at the i64 limits.
+pub fn realized_pnl(
+    current: Option<&Position>,
+    side: OrderSide,
+    trade_price: Price,
+    trade_qty: Qty,
+) -> i64 {
+    let Some(pos) = current else {
+        return 0;
+    };
+    let reducing = match side {
+        OrderSide::Buy => pos.quantity < 0,
+        OrderSide::Sell => pos.quantity > 0,
+    };
+    if !reducing {
+        return 0;
+    }
+    let closed = pos.quantity.unsigned_abs().min(trade_qty);
+    // Signed like the position: a long gains when sold higher, a short when bought lower
+    let closed = i64::try_from(closed).unwrap_or(i64::MAX) * pos.quantity.signum();
+    signed_notional(trade_price, closed)
+        .checked_sub(signed_notional(pos.average_price, closed))
+        .map_or(0, Notional::to_i64_saturating)
+}
+
+/// Pure position math for one trade leg at time `at`: returns the resulting position, or None
+/// when flat. Used by `update_position` and by order previews that must not touch the store.
+/// `opened_at` carries over while the position keeps its direction and restarts at `at` when
+/// the fill opens a position or flips it through zero.
+pub fn apply_fill(
+    current: Option<&Position>,
+    user_id: Uuid,
+    symbol: &Symbol,
+    side: OrderSide,
+    trade_price: Price,
+    trade_qty: Qty,
+    at: DateTime<Utc>,
+) -> Option<Position> {
+    // Fills are at most MAX_ORDER_QUANTITY, so the conversion is exact for every order the
+    // API accepts; position quantities saturate at the i64 limits rather than wrap
+    let trade_qty = i64::try_from(trade_qty).unwrap_or(i64::MAX);
     let signed_qty = match side {
-        OrderSide::Buy => trade_qty as i64,
-        OrderSide::Sell => -(trade_qty as i64),
+        OrderSide::Buy => trade_qty,
+        OrderSide::Sell => -trade_qty,
     };
 
-    let (new_qty, new_avg) = match guard.get(&key) {
+    let (new_qty, new_avg, opened_at) = match current {
         Some(pos) => {
             let old_qty = pos.quantity;
-            let new_qty = old_qty + signed_qty;
+            let new_qty = old_qty.saturating_add(signed_qty);
 
             if new_qty == 0 {
-                guard.remove(&key);
-                return;
+                return None;
             }
 
             // Same sign: same direction (adding to position) -> weighted average
             if (old_qty > 0 && signed_qty > 0) || (old_qty < 0 && signed_qty < 0) {
-                let new_avg = (pos.average_price * old_qty + trade_price * signed_qty) / new_qty;
-                (new_qty, new_avg)
+                // A weighted average of two prices always fits a price
+                let new_avg = signed_notional(pos.average_price, old_qty)
+                    .checked_add(signed_notional(trade_price, signed_qty))
+                    .and_then(|total| average_price(total, new_qty))
+                    .unwrap_or(trade_price);
+                (new_qty, new_avg, pos.opened_at)
+            } else if new_qty.signum() != old_qty.signum() {
+                // Flipped through zero: what remains was opened by this fill, at its price
+                (new_qty, trade_price, at)
             } else {
                 // Reducing position: no change to average for remaining open quantity
-                (new_qty, pos.average_price)
+                (new_qty, pos.average_price, pos.opened_at)
             }
         }
-        None => (signed_qty, trade_price),
+        None => (signed_qty, trade_price, at),
     };
 
-    guard.insert(
-        key,
-        Position {
+    Some(Position {
+        user_id,
+        symbol: symbol.to_string(),
+        quantity: new_qty,
+        average_price: new_avg,
+        opened_at,
+        updated_at: at,
+    })
+}
+
+/// Pure position math for taking back one trade leg (`side`, `trade_price`, `trade_qty` as it
+/// was filled) from `current`, at time `at`. The quantity always moves back by exactly the
+/// fill. When the fill had added to what is still a position in the same direction, its
+/// notional is taken out of the weighted average, so the average is what it would be without
+/// the fill even if the user has added more since (if they have also reduced it since and the
+/// result would not be a positive price, the current average is kept). A fill that had
+/// reduced the position gave back quantity at the average, so restoring it keeps the average.
+/// Anything the reversal opens (the fill had closed the position, or later fills have since
+/// reduced it below the fill) is valued at the trade price.
+pub fn reverse_fill(
+    current: Option<&Position>,
+    user_id: Uuid,
+    symbol: &Symbol,
+    side: OrderSide,
+    trade_price: Price,
+    trade_qty: Qty,
+    at: DateTime<Utc>,
+) -> Option<Position> {
+    let Some(pos) = current else {
+        return apply_fill(
+            None,
             user_id,
-            symbol: symbol.to_uppercase(),
-            quantity: new_qty,
-            average_price: new_avg,
-        },
-    );
+            symbol,
+            side.opposite(),
+            trade_price,
+            trade_qty,
+            at,
+        );
+    };
+    let trade_qty = i64::try_from(trade_qty).unwrap_or(i64::MAX);
+    // Signed like the reversal: undoing a buy sells
+    let reversal = match side {
+        OrderSide::Buy => -trade_qty,
+        OrderSide::Sell => trade_qty,
+    };
+    let old_qty = pos.quantity;
+    let new_qty = old_qty.saturating_add(reversal);
+    if new_qty == 0 {
+        return None;
+    }
+
+    let (new_avg, opened_at) = if new_qty.signum() != old_qty.signum() {
+        // Through zero: what remains is the reversal's own
+        (trade_price, at)
+    } else if reversal.signum() == old_qty.signum() {
+        // The fill had reduced the position at its average
+        (pos.average_price, pos.opened_at)
+    } else {
+        // The fill had added: remove its notional from the weighted average. Reductions since
+        // were valued at an average that included it, and that average was truncated, so the
+        // result can fall below the smallest price; it is clamped there
+        let avg = signed_notional(pos.average_price, old_qty)
+            .checked_add(signed_notional(trade_price, reversal))
+            .and_then(|total| average_price(total, new_qty))
+            .map_or(pos.average_price, |avg| avg.max(1));
+        (avg, pos.opened_at)
+    };
+
+    Some(Position {
+        user_id,
+        symbol: symbol.to_string(),
+        quantity: new_qty,
+        average_price: new_avg,
+        opened_at,
+        updated_at: at,
+    })
 }
 
 /// Returns positions for a user, optionally filtered by symbol.
 pub async fn get_positions(
     store: &SharedPositions,
     user_id: Uuid,
-    symbol_filter: Option<&str>,
+    symbol_filter: Option<&Symbol>,
 ) -> Vec<Position> {
     let guard = store.read().await;
-    let symbol_upper = symbol_filter.map(|s| s.to_uppercase());
     guard
         .iter()
-        .filter(|((uid, sym), _)| *uid == user_id && symbol_upper.as_ref().is_none_or(|s| sym == s))
+        .filter(|((uid, sym), _)| {
+            *uid == user_id && symbol_filter.is_none_or(|s| sym == s.as_str())
+        })
         .map(|(_, pos)| pos.clone())
         .collect()
 }
 
-/// Unrealized P&L: (current_price - average_price) * quantity. Works for long and short.
-pub fn unrealized_pnl(position: &Position, current_price: Price) -> i64 {
-    (current_price - position.average_price) * position.quantity
+/// Returns every user's position in a symbol, largest absolute quantity first (ties by user id,
+/// so pages are stable).
+pub async fn get_positions_by_symbol(store: &SharedPositions, symbol: &Symbol) -> Vec<Position> {
+    let guard = store.read().await;
+    let mut positions: Vec<Position> = guard
+        .iter()
+        .filter(|((_, sym), _)| sym == symbol.as_str())
+        .map(|(_, pos)| pos.clone())
+        .collect();
+    positions.sort_by(|a, b| {
+        b.quantity
+            .unsigned_abs()
+            .cmp(&a.quantity.unsigned_abs())
+            .then(a.user_id.cmp(&b.user_id))
+    });
+    positions
+}
+
+/// Unrealized P&L: (current_price - average_price) * quantity. Works for long and short;
+/// `Overflow` if the result does not fit an i64.
+pub fn unrealized_pnl(position: &Position, current_price: Price) -> Result<i64, MoneyError> {
+    unrealized_notional(position, current_price)?.to_i64()
+}
+
+/// `unrealized_pnl` of a position counted in lots of `1 / qty_scale`: the quote amount,
+/// rounded toward zero, descaled before it is narrowed to an i64.
+pub fn scaled_unrealized_pnl(
+    position: &Position,
+    current_price: Price,
+    qty_scale: u64,
+) -> Result<i64, MoneyError> {
+    descale(unrealized_notional(position, current_price)?, qty_scale).to_i64()
+}
+
+fn unrealized_notional(position: &Position, current_price: Price) -> Result<Notional, MoneyError> {
+    signed_notional(current_price, position.quantity)
+        .checked_sub(signed_notional(position.average_price, position.quantity))
 }
@@ -0,0 +1,227 @@
+//! External index price ingestion: `PriceSource` trait, HTTP polling source, shared store.
+//! The poller is testable without HTTP by plugging in any `PriceSource`.
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fmt;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{RwLock, broadcast};
+
+use crate::api::routes::WsMessage;
+use crate::api::ws;
+use crate::tasks::{RestartPolicy, ShutdownStage, Supervisor, TaskHandle};
+use crate::types::order::Price;
+use crate::types::symbol::Symbol;
+
+/// Latest index price per symbol (uppercase).
+pub type SharedIndexPrices = Arc<RwLock<HashMap<String, IndexPrice>>>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct IndexPrice {
+    pub price: Price,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug)]
+pub enum PriceFeedError {
+    UnknownSymbol(String),
+    Http(reqwest::Error),
+    MissingValue(String),
+    InvalidPrice(String),
+}
+
+impl fmt::Display for PriceFeedError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnknownSymbol(symbol) => write!(f, "no feed configured for {}", symbol),
+            Self::Http(e) => write!(f, "fetch failed: {}", e),
+            Self::MissingValue(pointer) => write!(f, "no value at JSON pointer '{}'", pointer),
+            Self::InvalidPrice(value) => write!(f, "invalid price value: {}", value),
+        }
+    }
+}
+
+/// A source of reference prices, one fetch per symbol.
+pub trait PriceSource: Send + Sync {
+    fn fetch(&self, symbol: &str) -> impl Future<Output = Result<Price, PriceFeedError>> + Send;
+}
+
+/// Where to fetch one symbol's price: a URL and a JSON pointer (RFC 6901) into the response.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FeedConfig {
+    pub url: String,
+    pub pointer: String,
+}
+
+/// Parse `SYMBOL=url|/json/pointer` entries separated by commas (e.g. `INDEX_PRICE_FEEDS`).
+/// Malformed entries are skipped.
+pub fn parse_feed_config(spec: &str) -> HashMap<String, FeedConfig> {
+    spec.split(',')
+        .filter_map(|entry| {
+            let (symbol, rest) = entry.trim().split_once('=')?;
+            let (url, pointer) = rest.split_once('|')?;
+            let symbol = Symbol::parse(symbol).ok()?;
+            if url.trim().is_empty() {
+                return None;
+            }
+            Some((
+                symbol.into_string(),
+                FeedConfig {
+                    url: url.trim().to_string(),
+                    pointer: pointer.trim().to_string(),
+                },
+            ))
+        })
+        .collect()
+}
+
+/// Read a decimal price (JSON number or numeric string) at `pointer` and scale it to integer ticks.
+pub fn extract_price(
+    json: &serde_json::Value,
+    pointer: &str,
+    price_scale: i64,
+) -> Result<Price, PriceFeedError> {
+    let value = json
+        .pointer(pointer)
+        .ok_or_else(|| PriceFeedError::MissingValue(pointer.to_string()))?;
+    let decimal = match value {
+        serde_json::Value::Number(n) => n.as_f64(),
+        serde_json::Value::String(s) => s.trim().parse::<f64>().ok(),
+        _ => None,
+    }
+    .filter(|v| v.is_finite() && *v > 0.0)
+    .ok_or_else(|| PriceFeedError::InvalidPrice(value.to_string()))?;
+    Ok((decimal * price_scale as f64).round() as Price)
+}
+
+/// Polls a JSON HTTP endpoint per symbol.
+pub struct HttpPriceSource {
+    client: reqwest::Client,
+    feeds: HashMap<String, FeedConfig>,
+    price_scale: i64,
+}
+
+impl HttpPriceSource {
+    pub fn new(feeds: HashMap<String, FeedConfig>, price_scale: i64) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            feeds,
+            price_scale,
+        }
+    }
+
+    pub fn symbols(&self) -> Vec<String> {
+        self.feeds.keys().cloned().collect()
+    }
+}
+
+impl PriceSource for HttpPriceSource {
+    async fn fetch(&self, symbol: &str) -> Result<Price, PriceFeedError> {
+        let feed = self
+            .feeds
+            .get(symbol)
+            .ok_or_else(|| PriceFeedError::UnknownSymbol(symbol.to_string()))?;
+        let json: serde_json::Value = self
+            .client
+            .get(&feed.url)
+            .send()
+            .await
+            .and_then(|res| res.error_for_status())
+            .map_err(PriceFeedError::Http)?
+            .json()
+            .await
+            .map_err(PriceFeedError::Http)?;
+        extract_price(&json, &feed.pointer, self.price_scale)
+    }
+}
+
+/// Fetch every symbol once. On success the store is updated (and `IndexPrice` broadcast when the
+/// price changed); on failure the last value is kept and will go stale by age.
+pub async fn poll_once<S: PriceSource>(
+    source: &S,
+    symbols: &[String],
+    store: &SharedIndexPrices,
+    ws_channel: Option<&broadcast::Sender<WsMessage>>,
+) {
+    for symbol in symbols {
+        match source.fetch(symbol).await {
+            Ok(price) => {
+                let now = Utc::now();
+                let changed = {
+                    let mut guard = store.write().await;
+                    let changed = guard.get(symbol).is_none_or(|p| p.price != price);
+                    guard.insert(
+                        symbol.clone(),
+                        IndexPrice {
+                            price,
+                            updated_at: now,
+                        },
+                    );
+                    changed
+                };
+                if changed
+                    && let Some(channel) = ws_channel
+                    && ws::has_receivers(channel)
+                {
+                    let _ = channel.send(WsMessage::IndexPrice {
+                        symbol: symbol.clone(),
+                        price,
+                        timestamp: now,
+                    });
+                }
+            }
+            Err(e) => eprintln!("index price poll for {} failed: {}", symbol, e),
+        }
+    }
+}
+
+/// Run `poll_once` on a fixed interval under `tasks` until it is stopped.
+pub fn spawn_poller<S: PriceSource + 'static>(
+    tasks: &Supervisor,
+    source: S,
+    symbols: Vec<String>,
+    store: SharedIndexPrices,
+    ws_channel: broadcast::Sender<WsMessage>,
+    interval: Duration,
+) -> TaskHandle {
+    let source = Arc::new(source);
+    tasks.spawn(
+        "index_price_poller",
+        ShutdownStage::Worker,
+        RestartPolicy::default(),
+        move |token| {
+            let (source, symbols) = (source.clone(), symbols.clone());
+            let (store, ws_channel) = (store.clone(), ws_channel.clone());
+            async move {
+                let mut ticker = tokio::time::interval(interval);
+                while token.run_until_cancelled(ticker.tick()).await.is_some() {
+                    poll_once(source.as_ref(), &symbols, &store, Some(&ws_channel)).await;
+                }
+            }
+        },
+    )
+}
+
+/// True if the price is older than `max_age` at `now`.
+pub fn is_stale(price: &IndexPrice, max_age: Duration, now: DateTime<Utc>) -> bool {
+    now.signed_duration_since(price.updated_at)
+        .to_std()
+        .is_ok_and(|age| age > max_age)
+}
+
+/// Index price for a symbol if present and not stale. Preferred reference price for
+/// price-sensitive logic; callers fall back to book prices when this is None.
+pub async fn fresh_index_price(
+    store: &SharedIndexPrices,
+    symbol: &Symbol,
+    max_age: Duration,
+) -> Option<Price> {
+    let guard = store.read().await;
+    guard
+        .get(symbol.as_str())
+        .filter(|p| !is_stale(p, max_age, Utc::now()))
+        .map(|p| p.price)
+}
@@ -0,0 +1,471 @@
+//! Memory vs database consistency check for one symbol (`GET /admin/reconcile`): the book's
+//! resting orders against the open order rows, and the positions map against the position
+//! rows. Nothing is changed unless a repair is asked for, which writes the in-memory state
+//! to the database (memory is authoritative) with one audit entry per fix.
+//!
+//! A check snapshots memory under brief read locks and reads the database afterwards, so
+//! orders that trade in between can show up as transient differences; run it again before
+//! acting on one. A repair holds the book write lock from snapshot to commit instead, so
+//! nothing it writes can be stale (order entry for the symbol waits meanwhile).
+
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::HashMap;
+use uuid::Uuid;
+
+use crate::api::routes::AppState;
+use crate::orderbook::orderbook::{OrderBook, SharedOrderBook};
+use crate::persistence::{self, OrderRow, PgPool, PositionRow};
+use crate::types::order::{CloseReason, Order, OrderId, OrderStatus, Price};
+use crate::types::position::Position;
+use crate::types::symbol::Symbol;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RepairTarget {
+    Orders,
+    Positions,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct OrderState {
+    pub status: OrderStatus,
+    pub quantity: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct OrderMismatch {
+    pub order_id: OrderId,
+    pub user_id: Uuid,
+    pub memory: OrderState,
+    pub database: OrderState,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct OrdersDiff {
+    /// Orders resting in the book.
+    pub checked: usize,
+    /// Resting in the book but not open in the database.
+    pub missing: Vec<OrderId>,
+    /// Open in the database but not resting in the book.
+    pub extra: Vec<OrderId>,
+    pub mismatched: Vec<OrderMismatch>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct PositionState {
+    pub quantity: i64,
+    pub average_price: Price,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PositionMismatch {
+    pub user_id: Uuid,
+    pub memory: PositionState,
+    pub database: PositionState,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct PositionsDiff {
+    /// Positions held in memory.
+    pub checked: usize,
+    /// Owners with a position in memory but no row.
+    pub missing: Vec<Uuid>,
+    /// Owners with a row but no position in memory.
+    pub extra: Vec<Uuid>,
+    pub mismatched: Vec<PositionMismatch>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ReconcileReport {
+    pub symbol: Symbol,
+    /// No differences were found (before any repair).
+    pub consistent: bool,
+    pub orders: OrdersDiff,
+    pub positions: PositionsDiff,
+    /// What was written back, if a repair was asked for.
+    pub repaired: Option<RepairTarget>,
+}
+
+/// Memory state of one symbol.
+struct Snapshot {
+    orders: Vec<Order>,
+    positions: Vec<Position>,
+}
+
+async fn snapshot(state: &AppState, symbol: &Symbol, book: &OrderBook) -> Snapshot {
+    let orders = book.iter_orders().map(|(_, order)| order.clone()).collect();
+    let positions = state
+        .positions
+        .read()
+        .await
+        .values()
+        .filter(|p| p.symbol == symbol.as_str())
+        .cloned()
+        .collect();
+    Snapshot { orders, positions }
+}
+
+/// Open order rows and position rows of `symbol`.
+async fn load(
+    db: &PgPool,
+    symbol: &Symbol,
+) -> Result<(Vec<OrderRow>, Vec<PositionRow>), sqlx::Error> {
+    let orders = persistence::list_open_orders_by_symbol(db, symbol).await?;
+    let positions = persistence::list_positions_by_symbol(db, symbol).await?;
+    Ok((orders, positions))
+}
+
+fn diff_orders(memory: &[Order], rows: &[OrderRow]) -> OrdersDiff {
+    let mut database: HashMap<OrderId, OrderState> = rows
+        .iter()
+        .filter_map(persistence::order_row_to_order_display)
+        .map(|o| {
+            (
+                o.id,
+                OrderState {
+                    status: o.status,
+                    quantity: o.quantity,
+                },
+            )
+        })
+        .collect();
+    let mut diff = OrdersDiff {
+        checked: memory.len(),
+        ..OrdersDiff::default()
+    };
+    for order in memory {
+        let state = OrderState {
+            status: order.status,
+            quantity: order.quantity,
+        };
+        match database.remove(&order.id) {
+            None => diff.missing.push(order.id),
+            Some(stored) if stored != state => diff.mismatched.push(OrderMismatch {
+                order_id: order.id,
+                user_id: order.user_id,
+                memory: state,
+                database: stored,
+            }),
+            Some(_) => {}
+        }
+    }
+    diff.extra = database.into_keys().collect();
+    diff.extra.sort();
+    diff
+}
+
+fn diff_positions(memory: &[Position], rows: &[PositionRow]) -> PositionsDiff {
+    let mut database: HashMap<Uuid, PositionState> = rows
+        .iter()
+        .map(|r| {
+            (
+                r.user_id,
+                PositionState {
+                    quantity: r.quantity,
+                    average_price: r.average_price,
+                },
+            )
+        })
+        .collect();
+    let mut diff = PositionsDiff {
+        checked: memory.len(),
+        ..PositionsDiff::default()
+    };
+    for position in memory {
+        let state = PositionState {
+            quantity: position.quantity,
+            average_price: position.average_price,
+        };
+        match database.remove(&position.user_id) {
+            None => diff.missing.push(position.user_id),
+            Some(stored) if stored != state => diff.mismatched.push(PositionMismatch {
+                user_id: position.user_id,
+                memory: state,
+                database: stored,
+            }),
+            Some(_) => {}
+        }
+    }
+    diff.extra = database.into_keys().collect();
+    diff.extra.sort();
+    diff
+}
+
+/// Check `symbol`, and with `repair` write the in-memory side of that diff to the database,
+/// audited under `admin_user_id`. Requires a database.
+pub async fn run(
+    state: &AppState,
+    db: &PgPool,
+    symbol: &Symbol,
+    orderbook: &SharedOrderBook,
+    repair: Option<RepairTarget>,
+    admin_user_id: Uuid,
+) -> Result<ReconcileReport, sqlx::Error> {
+    let Some(target) = repair else {
+        let memory = {
+            let book = orderbook.read().await;
+            snapshot(state, symbol, &book).await
+        };
+        let (order_rows, position_rows) = load(db, symbol).await?;
+        return Ok(report(symbol, &memory, &order_rows, &position_rows, None));
+    };
+
+    let book = orderbook.write().await;
+    let memory = snapshot(state, symbol, &book).await;
+    let (order_rows, position_rows) = load(db, symbol).await?;
+    let report = report(symbol, &memory, &order_rows, &position_rows, Some(target));
+    let mut tx = db.begin().await?;
+    match target {
+        RepairTarget::Orders => {
+            repair_orders(
+                &mut tx,
+                symbol,
+                admin_user_id,
+                &memory,
+                &order_rows,
+                &report.orders,
+            )
+            .await?
+        }
+        RepairTarget::Positions => {
+            repair_positions(
+                &mut tx,
+                symbol,
+                admin_user_id,
+                &memory,
+                &position_rows,
+                &report.positions,
+            )
+            .await?
+        }
+    }
+    tx.commit().await?;
+    drop(book);
+    Ok(report)
+}
+
+fn report(
+    symbol: &Symbol,
+    memory: &Snapshot,
+    order_rows: &[OrderRow],
+    position_rows: &[PositionRow],
+    repaired: Option<RepairTarget>,
+) -> ReconcileReport {
+    let orders = diff_orders(&memory.orders, order_rows);
+    let positions = diff_positions(&memory.positions, position_rows);
+    let consistent = [
+        orders.missing.len(),
+        orders.extra.len(),
+        orders.mismatched.len(),
+    ]
+    .into_iter()
+    .chain([
+        positions.missing.len(),
+        positions.extra.len(),
+        positions.mismatched.len(),
+    ])
+    .all(|n| n == 0);
+    ReconcileReport {
+        symbol: symbol.clone(),
+        consistent,
+        orders,
+        positions,
+        repaired,
+    }
+}
+
+async fn repair_orders(
+    tx: &mut sqlx::PgConnection,
+    symbol: &Symbol,
+    admin_user_id: Uuid,
+    memory: &Snapshot,
+    rows: &[OrderRow],
+    diff: &OrdersDiff,
+) -> Result<(), sqlx::Error> {
+    let resting: HashMap<OrderId, &Order> = memory.orders.iter().map(|o| (o.id, o)).collect();
+    let stored: HashMap<OrderId, &OrderRow> = rows.iter().map(|r| (r.id, r)).collect();
+    for order_id in &diff.missing {
+        let order = resting[order_id];
+        persistence::insert_order(
+            &mut *tx,
+            order.id,
+            order.user_id,
+            symbol,
+            order.side,
+            order.order_type,
+            order.price,
+            order.quantity,
+            order.status,
+            order.timestamp,
+            &order.tags,
+            order.source,
+            None,
+        )
+        .await?;
+        let applied = json!({ "status": order.status, "quantity": order.quantity });
+        audit(
+            tx,
+            symbol,
+            admin_user_id,
+            "order",
+            order_id,
+            "inserted",
+            None,
+            Some(&applied),
+        )
+        .await?;
+    }
+    for order_id in &diff.extra {
+        let row = stored[order_id];
+        let quantity = row.quantity.max(0) as u64;
+        persistence::cancel_order(
+            &mut *tx,
+            *order_id,
+            quantity,
+            CloseReason::Reconciled.code(),
+        )
+        .await?;
+        let previous = json!({ "status": row.status, "quantity": row.quantity });
+        let applied = json!({ "status": OrderStatus::Cancelled, "quantity": quantity });
+        audit(
+            tx,
+            symbol,
+            admin_user_id,
+            "order",
+            order_id,
+            "closed",
+            Some(&previous),
+            Some(&applied),
+        )
+        .await?;
+    }
+    for mismatch in &diff.mismatched {
+        persistence::update_order_state(
+            &mut *tx,
+            mismatch.order_id,
+            mismatch.memory.status,
+            mismatch.memory.quantity,
+        )
+        .await?;
+        let previous = json!(mismatch.database);
+        let applied = json!(mismatch.memory);
+        audit(
+            tx,
+            symbol,
+            admin_user_id,
+            "order",
+            &mismatch.order_id,
+            "updated",
+            Some(&previous),
+            Some(&applied),
+        )
+        .await?;
+    }
+    Ok(())
+}
+
+async fn repair_positions(
+    tx: &mut sqlx::PgConnection,
+    symbol: &Symbol,
+    admin_user_id: Uuid,
+    memory: &Snapshot,
+    rows: &[PositionRow],
+    diff: &PositionsDiff,
+) -> Result<(), sqlx::Error> {
+    let held: HashMap<Uuid, &Position> = memory.positions.iter().map(|p| (p.user_id, p)).collect();
+    let stored: HashMap<Uuid, &PositionRow> = rows.iter().map(|r| (r.user_id, r)).collect();
+    for user_id in &diff.missing {
+        let position = held[user_id];
+        persistence::upsert_position(
+            &mut *tx,
+            position.user_id,
+            symbol,
+            position.quantity,
+            position.average_price,
+            position.opened_at,
+            position.updated_at,
+        )
+        .await?;
+        let applied =
+            json!({ "quantity": position.quantity, "average_price": position.average_price });
+        audit(
+            tx,
+            symbol,
+            admin_user_id,
+            "position",
+            user_id,
+            "inserted",
+            None,
+            Some(&applied),
+        )
+        .await?;
+    }
+    for user_id in &diff.extra {
+        persistence::delete_position(&mut *tx, *user_id, symbol).await?;
+        let row = stored[user_id];
+        let previous = json!({ "quantity": row.quantity, "average_price": row.average_price });
+        audit(
+            tx,
+            symbol,
+            admin_user_id,
+            "position",
+            user_id,
+            "deleted",
+            Some(&previous),
+            None,
+        )
+        .await?;
+    }
+    for mismatch in &diff.mismatched {
+        let position = held[&mismatch.user_id];
+        persistence::upsert_position(
+            &mut *tx,
+            mismatch.user_id,
+            symbol,
+            mismatch.memory.quantity,
+            mismatch.memory.average_price,
+            position.opened_at,
+            position.updated_at,
+        )
+        .await?;
+        let previous = json!(mismatch.database);
+        let applied = json!(mismatch.memory);
+        audit(
+            tx,
+            symbol,
+            admin_user_id,
+            "position",
+            &mismatch.user_id,
+            "updated",
+            Some(&previous),
+            Some(&applied),
+        )
+        .await?;
+    }
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn audit(
+    tx: &mut sqlx::PgConnection,
+    symbol: &Symbol,
+    admin_user_id: Uuid,
+    entity: &str,
+    entity_id: &Uuid,
+    action: &str,
+    previous: Option<&serde_json::Value>,
+    applied: Option<&serde_json::Value>,
+) -> Result<(), sqlx::Error> {
+    persistence::insert_reconcile_audit(
+        tx,
+        symbol,
+        admin_user_id,
+        entity,
+        &entity_id.to_string(),
+        action,
+        previous,
+        applied,
+    )
+    .await
+}
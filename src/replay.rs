@@ -0,0 +1,573 @@
+//! Offline replay of a recorded session through the matching engine, for validating strategy
+//! logic and as a regression harness for matching changes (see `src/bin/replay.rs`).
+//!
+//! # Input format
+//!
+//! One event per line, read as a stream (the file is never loaded whole). Blank lines and lines
+//! starting with `#` are skipped. Timestamps are RFC 3339 and must not go backwards; the book's
+//! clock is set to each event's timestamp, so order and trade timestamps come from the file.
+//! With a skew window (`Replay::with_skew_window`), timestamps outside it are rejected, clamped
+//! or counted as skewed according to its policy before that check.
+//!
+//! NDJSON (default):
+//!
+//! ```text
+//! {"ts":"2025-01-02T09:30:00Z","action":"submit","ref":"o1","user":"alice","side":"Buy","type":"Limit","price":100,"quantity":5}
+//! {"ts":"2025-01-02T09:30:01Z","action":"submit","ref":"o2","user":"bob","side":"Sell","type":"Market","quantity":2}
+//! {"ts":"2025-01-02T09:30:02Z","action":"cancel","ref":"o1"}
+//! ```
+//!
+//! CSV (`.csv` files): a header line `ts,action,ref,user,side,type,price,quantity`, then one
+//! row per event. Fields are not quoted; cancel rows leave everything after `ref` empty.
+//!
+//! ```text
+//! ts,action,ref,user,side,type,price,quantity
+//! 2025-01-02T09:30:00Z,submit,o1,alice,Buy,Limit,100,5
+//! 2025-01-02T09:30:02Z,cancel,o1,,,,,
+//! ```
+//!
+//! `ref` is the recorder's order reference: cancels and trades refer to orders by it, and it
+//! must be unique among resting orders. `user` is a free-form label. `type` defaults to
+//! `Limit`; `price` is ignored for market orders. A cancel for an order that is no longer
+//! resting is counted as a miss, not an error, since recorded cancels often race fills.
+//!
+//! # Output
+//!
+//! Trades are [`ReplayTrade`] records, one JSON object per line; an expected-trades file uses
+//! the same format, so the output of one run can be the expectation of the next.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{BufRead, Lines};
+use std::path::Path;
+use std::sync::Arc;
+use uuid::Uuid;
+
+use crate::orderbook::clock::ManualClock;
+use crate::orderbook::orderbook::OrderBook;
+use crate::skew::SkewWindow;
+use crate::types::order::{OrderId, OrderSide, OrderStatus, OrderType, Price, Qty};
+
+/// Columns of the CSV header, in order.
+pub const CSV_HEADER: &str = "ts,action,ref,user,side,type,price,quantity";
+
+/// Divergences described in full by [`Comparison`]; the rest are only counted.
+const MAX_DIVERGENCE_SAMPLES: usize = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputFormat {
+    Ndjson,
+    Csv,
+}
+
+impl InputFormat {
+    /// CSV for `.csv` files, NDJSON otherwise.
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some(ext) if ext.eq_ignore_ascii_case("csv") => InputFormat::Csv,
+            _ => InputFormat::Ndjson,
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "ndjson" | "jsonl" => Some(InputFormat::Ndjson),
+            "csv" => Some(InputFormat::Csv),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(tag = "action", rename_all = "lowercase")]
+pub enum ReplayAction {
+    Submit {
+        #[serde(rename = "ref")]
+        order_ref: String,
+        user: String,
+        side: OrderSide,
+        #[serde(rename = "type", default)]
+        order_type: OrderType,
+        #[serde(default)]
+        price: Price,
+        quantity: Qty,
+    },
+    Cancel {
+        #[serde(rename = "ref")]
+        order_ref: String,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct ReplayEvent {
+    pub ts: DateTime<Utc>,
+    #[serde(flatten)]
+    pub action: ReplayAction,
+}
+
+/// A trade as produced by a replay, with orders and users named as in the input file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReplayTrade {
+    pub trade_seq: u64,
+    pub ts: DateTime<Utc>,
+    pub maker_ref: String,
+    pub taker_ref: String,
+    pub maker_user: String,
+    pub taker_user: String,
+    pub price: Price,
+    pub quantity: Qty,
+}
+
+#[derive(Debug)]
+pub enum ReplayError {
+    Io(std::io::Error),
+    /// A line that is not a valid event (bad JSON/CSV, unknown action, missing field).
+    Parse {
+        line: usize,
+        message: String,
+    },
+    /// A well-formed event the engine cannot apply (timestamp going backwards, duplicate ref).
+    Event {
+        line: usize,
+        message: String,
+    },
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReplayError::Io(e) => write!(f, "I/O error: {}", e),
+            ReplayError::Parse { line, message } => write!(f, "line {}: {}", line, message),
+            ReplayError::Event { line, message } => write!(f, "line {}: {}", line, message),
+        }
+    }
+}
+
+impl std::error::Error for ReplayError {}
+
+impl From<std::io::Error> for ReplayError {
+    fn from(e: std::io::Error) -> Self {
+        ReplayError::Io(e)
+    }
+}
+
+/// Streaming reader of replay events: yields `(line number, event)` one line at a time.
+pub struct EventReader<R> {
+    lines: Lines<R>,
+    format: InputFormat,
+    line: usize,
+    header_seen: bool,
+}
+
+impl<R: BufRead> EventReader<R> {
+    pub fn new(reader: R, format: InputFormat) -> Self {
+        Self {
+            lines: reader.lines(),
+            format,
+            line: 0,
+            header_seen: false,
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for EventReader<R> {
+    type Item = Result<(usize, ReplayEvent), ReplayError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let text = match self.lines.next()? {
+                Ok(text) => text,
+                Err(e) => return Some(Err(e.into())),
+            };
+            self.line += 1;
+            let text = text.trim();
+            if text.is_empty() || text.starts_with('#') {
+                continue;
+            }
+            let parsed = match self.format {
+                InputFormat::Ndjson => serde_json::from_str(text).map_err(|e| e.to_string()),
+                InputFormat::Csv if !self.header_seen => {
+                    self.header_seen = true;
+                    if text == CSV_HEADER {
+                        continue;
+                    }
+                    Err(format!("expected CSV header '{}'", CSV_HEADER))
+                }
+                InputFormat::Csv => parse_csv_event(text),
+            };
+            let line = self.line;
+            return Some(
+                parsed
+                    .map(|event| (line, event))
+                    .map_err(|message| ReplayError::Parse { line, message }),
+            );
+        }
+    }
+}
+
+fn parse_csv_event(text: &str) -> Result<ReplayEvent, String> {
+    let fields: Vec<&str> = text.split(',').map(str::trim).collect();
+    let [
+        ts,
+        action,
+        order_ref,
+        user,
+        side,
+        order_type,
+        price,
+        quantity,
+    ] = fields[..]
+    else {
+        return Err(format!("expected 8 fields, got {}", fields.len()));
+    };
+    let ts = DateTime::parse_from_rfc3339(ts)
+        .map_err(|e| format!("invalid ts '{}': {}", ts, e))?
+        .with_timezone(&Utc);
+    if order_ref.is_empty() {
+        return Err("missing ref".to_string());
+    }
+    let action = match action {
+        "submit" => ReplayAction::Submit {
+            order_ref: order_ref.to_string(),
+            user: user.to_string(),
+            side: match side {
+                "Buy" => OrderSide::Buy,
+                "Sell" => OrderSide::Sell,
+                _ => return Err(format!("invalid side '{}'", side)),
+            },
+            order_type: match order_type {
+                "" | "Limit" => OrderType::Limit,
+                "Market" => OrderType::Market,
+                _ => return Err(format!("invalid type '{}'", order_type)),
+            },
+            price: match price {
+                "" => 0,
+                _ => price
+                    .parse()
+                    .map_err(|_| format!("invalid price '{}'", price))?,
+            },
+            quantity: quantity
+                .parse()
+                .map_err(|_| format!("invalid quantity '{}'", quantity))?,
+        },
+        "cancel" => ReplayAction::Cancel {
+            order_ref: order_ref.to_string(),
+        },
+        _ => return Err(format!("unknown action '{}'", action)),
+    };
+    Ok(ReplayEvent { ts, action })
+}
+
+/// Counters for a replay run.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct ReplayStats {
+    pub events: u64,
+    pub submits: u64,
+    pub cancels: u64,
+    /// Cancels for refs that were not resting (already filled, cancelled or never seen).
+    pub cancel_misses: u64,
+    /// Submissions the engine rejected (e.g. market orders with no liquidity).
+    pub rejected: u64,
+    pub trades: u64,
+    pub volume: Qty,
+    /// Events whose timestamp was outside the skew window but accepted or clamped.
+    pub timestamps_skewed: u64,
+}
+
+impl fmt::Display for ReplayStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} events ({} submits, {} rejected, {} cancels, {} cancel misses), {} trades, volume {}",
+            self.events,
+            self.submits,
+            self.rejected,
+            self.cancels,
+            self.cancel_misses,
+            self.trades,
+            self.volume
+        )?;
+        if self.timestamps_skewed > 0 {
+            write!(f, ", {} skewed timestamps", self.timestamps_skewed)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RestingOrder {
+    #[serde(rename = "ref")]
+    pub order_ref: String,
+    pub user: String,
+    pub side: OrderSide,
+    pub price: Price,
+    pub quantity: Qty,
+}
+
+/// Book state at the end of a replay.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FinalBook {
+    pub book_seq: u64,
+    pub last_trade_seq: u64,
+    pub bids: Vec<(Price, Qty)>,
+    pub asks: Vec<(Price, Qty)>,
+    /// Resting orders in priority order (bids best first, then asks best first).
+    pub orders: Vec<RestingOrder>,
+}
+
+/// One book driven by replay events under a manual clock.
+pub struct Replay {
+    book: OrderBook,
+    clock: Arc<ManualClock>,
+    last_ts: Option<DateTime<Utc>>,
+    /// Window event timestamps are checked against, and the time it is centred on.
+    skew: Option<(SkewWindow, DateTime<Utc>)>,
+    users: HashMap<String, Uuid>,
+    user_labels: HashMap<Uuid, String>,
+    /// Resting orders only, both ways; entries go when the order fills or is cancelled.
+    resting: HashMap<String, OrderId>,
+    refs: HashMap<OrderId, String>,
+    stats: ReplayStats,
+}
+
+impl Default for Replay {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Replay {
+    pub fn new() -> Self {
+        let clock = Arc::new(ManualClock::new(DateTime::UNIX_EPOCH));
+        Self {
+            book: OrderBook::with_clock(clock.clone()),
+            clock,
+            last_ts: None,
+            skew: None,
+            users: HashMap::new(),
+            user_labels: HashMap::new(),
+            resting: HashMap::new(),
+            refs: HashMap::new(),
+            stats: ReplayStats::default(),
+        }
+    }
+
+    /// Check every event's timestamp against `window` around `now`.
+    pub fn with_skew_window(mut self, window: SkewWindow, now: DateTime<Utc>) -> Self {
+        self.skew = Some((window, now));
+        self
+    }
+
+    pub fn stats(&self) -> &ReplayStats {
+        &self.stats
+    }
+
+    /// Apply one event, returning the trades it created. Errors leave the book untouched.
+    pub fn apply(&mut self, event: &ReplayEvent) -> Result<Vec<ReplayTrade>, String> {
+        let (ts, skewed) = match &self.skew {
+            Some((window, now)) => {
+                let checked = window.check(event.ts, *now)?;
+                (checked.timestamp, checked.warning.is_some())
+            }
+            None => (event.ts, false),
+        };
+        if let Some(last) = self.last_ts
+            && ts < last
+        {
+            return Err(format!(
+                "timestamp {} is earlier than the previous event ({})",
+                ts.to_rfc3339(),
+                last.to_rfc3339()
+            ));
+        }
+        if let ReplayAction::Submit { order_ref, .. } = &event.action
+            && self.resting.contains_key(order_ref)
+        {
+            return Err(format!("ref '{}' is already resting", order_ref));
+        }
+        self.last_ts = Some(ts);
+        self.clock.set(ts);
+        self.stats.events += 1;
+        if skewed {
+            self.stats.timestamps_skewed += 1;
+        }
+
+        match &event.action {
+            ReplayAction::Submit {
+                order_ref,
+                user,
+                side,
+                order_type,
+                price,
+                quantity,
+            } => {
+                self.stats.submits += 1;
+                let user_id = self.user_id(user);
+                let report =
+                    self.book
+                        .add_order(user_id, *price, *quantity, *side, *order_type, None, None);
+                if report.rejection.is_some() {
+                    self.stats.rejected += 1;
+                }
+                let trades = report
+                    .trades
+                    .iter()
+                    .map(|trade| {
+                        let maker_ref = self.refs[&trade.maker_order_id].clone();
+                        if self.book.get_order_by_id(trade.maker_order_id).is_none() {
+                            self.refs.remove(&trade.maker_order_id);
+                            self.resting.remove(&maker_ref);
+                        }
+                        ReplayTrade {
+                            trade_seq: trade.trade_seq,
+                            ts: trade.timestamp,
+                            maker_ref,
+                            taker_ref: order_ref.clone(),
+                            maker_user: self.user_labels[&trade.maker_user_id].clone(),
+                            taker_user: user.clone(),
+                            price: trade.price,
+                            quantity: trade.quantity,
+                        }
+                    })
+                    .collect::<Vec<_>>();
+                if report.order.status != OrderStatus::Cancelled
+                    && self.book.get_order_by_id(report.order.id).is_some()
+                {
+                    self.resting.insert(order_ref.clone(), report.order.id);
+                    self.refs.insert(report.order.id, order_ref.clone());
+                }
+                self.stats.trades += trades.len() as u64;
+                self.stats.volume += trades.iter().map(|t| t.quantity).sum::<Qty>();
+                Ok(trades)
+            }
+            ReplayAction::Cancel { order_ref } => {
+                self.stats.cancels += 1;
+                match self.resting.remove(order_ref) {
+                    Some(order_id) => {
+                        self.refs.remove(&order_id);
+                        self.book.remove_order(order_id, None, None);
+                    }
+                    None => self.stats.cancel_misses += 1,
+                }
+                Ok(Vec::new())
+            }
+        }
+    }
+
+    pub fn final_book(&self) -> FinalBook {
+        FinalBook {
+            book_seq: self.book.book_seq(),
+            last_trade_seq: self.book.last_trade_seq(),
+            bids: self.book.get_bids(),
+            asks: self.book.get_asks(),
+            orders: self
+                .book
+                .iter_orders()
+                .map(|(_, order)| RestingOrder {
+                    order_ref: self.refs[&order.id].clone(),
+                    user: self.user_labels[&order.user_id].clone(),
+                    side: order.side,
+                    price: order.price,
+                    quantity: order.quantity,
+                })
+                .collect(),
+        }
+    }
+
+    fn user_id(&mut self, label: &str) -> Uuid {
+        if let Some(&id) = self.users.get(label) {
+            return id;
+        }
+        let id = Uuid::new_v4();
+        self.users.insert(label.to_string(), id);
+        self.user_labels.insert(id, label.to_string());
+        id
+    }
+}
+
+/// Result of comparing replayed trades with an expected-trades file.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct Comparison {
+    pub compared: u64,
+    pub divergences: u64,
+    /// The first few divergences, described.
+    pub samples: Vec<String>,
+}
+
+impl Comparison {
+    pub fn matches(&self) -> bool {
+        self.divergences == 0
+    }
+
+    fn diverged(&mut self, description: String) {
+        self.divergences += 1;
+        if self.samples.len() < MAX_DIVERGENCE_SAMPLES {
+            self.samples.push(description);
+        }
+    }
+}
+
+/// Expected trades (NDJSON [`ReplayTrade`] lines), read one at a time as actual trades arrive.
+pub struct ExpectedTrades<R> {
+    lines: Lines<R>,
+    line: usize,
+    comparison: Comparison,
+}
+
+impl<R: BufRead> ExpectedTrades<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            lines: reader.lines(),
+            line: 0,
+            comparison: Comparison::default(),
+        }
+    }
+
+    fn next_expected(&mut self) -> Result<Option<ReplayTrade>, ReplayError> {
+        for text in self.lines.by_ref() {
+            let text = text?;
+            self.line += 1;
+            if text.trim().is_empty() {
+                continue;
+            }
+            return serde_json::from_str(&text)
+                .map(Some)
+                .map_err(|e| ReplayError::Parse {
+                    line: self.line,
+                    message: format!("expected trades: {}", e),
+                });
+        }
+        Ok(None)
+    }
+
+    /// Compare the next actual trade with the next expected one.
+    pub fn check(&mut self, actual: &ReplayTrade) -> Result<(), ReplayError> {
+        self.comparison.compared += 1;
+        match self.next_expected()? {
+            Some(expected) if expected == *actual => {}
+            Some(expected) => self.comparison.diverged(format!(
+                "trade {}: expected {:?}, got {:?}",
+                self.comparison.compared, expected, actual
+            )),
+            None => self.comparison.diverged(format!(
+                "trade {}: unexpected {:?}",
+                self.comparison.compared, actual
+            )),
+        }
+        Ok(())
+    }
+
+    /// Count expected trades the replay never produced and return the comparison.
+    pub fn finish(mut self) -> Result<Comparison, ReplayError> {
+        while let Some(expected) = self.next_expected()? {
+            self.comparison.compared += 1;
+            self.comparison.diverged(format!(
+                "trade {}: missing {:?}",
+                self.comparison.compared, expected
+            ));
+        }
+        Ok(self.comparison)
+    }
+}
@@ -0,0 +1,221 @@
+//! Market-data replicas: extra instances that serve books, trades, klines, tickers and the
+//! public WebSocket feed from the primary's database but accept no writes. A replica rejects
+//! every mutating request with 403 `READ_ONLY_REPLICA`, polls the database for each public
+//! book's open orders and new trades, and runs none of the primary's background tasks
+//! (retention, sandbox expiry, webhook delivery, candle persistence).
+
+use axum::{
+    extract::Request, http::Method, middleware::Next, response::IntoResponse, response::Response,
+};
+use serde::Serialize;
+use std::env;
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+
+use crate::api::routes::{AppState, ErrorResponse};
+use crate::api::ws;
+use crate::persistence::{self, PgPool};
+use crate::skew::SkewWindow;
+use crate::tasks::{RestartPolicy, ShutdownStage};
+use crate::types::order::{Order, OrderId, OrderType, Qty};
+use crate::types::trade::Trade;
+
+/// Trades read per symbol and refresh; a replica further behind catches up over several.
+const MAX_TRADES_PER_REFRESH: usize = 1000;
+
+/// What this instance does, from `SERVER_ROLE`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ServerRole {
+    /// Accepts orders and runs matching; the only writer of the database.
+    #[default]
+    Primary,
+    /// Serves market data read from the primary's database; rejects writes.
+    MarketDataReplica,
+}
+
+impl ServerRole {
+    pub fn is_replica(self) -> bool {
+        self == Self::MarketDataReplica
+    }
+}
+
+impl FromStr for ServerRole {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "primary" => Ok(Self::Primary),
+            "market_data_replica" | "replica" => Ok(Self::MarketDataReplica),
+            other => Err(format!(
+                "unknown server role '{}' (expected primary or market_data_replica)",
+                other
+            )),
+        }
+    }
+}
+
+impl fmt::Display for ServerRole {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Primary => write!(f, "primary"),
+            Self::MarketDataReplica => write!(f, "market_data_replica"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ReplicaConfig {
+    /// How often books and trades are re-read from the database.
+    pub refresh_interval: Duration,
+}
+
+impl Default for ReplicaConfig {
+    fn default() -> Self {
+        Self {
+            refresh_interval: Duration::from_secs(1),
+        }
+    }
+}
+
+impl ReplicaConfig {
+    /// Read `REPLICA_*` variables, falling back to the defaults.
+    pub fn from_env() -> Self {
+        fn var<T: std::str::FromStr>(name: &str) -> Option<T> {
+            env::var(name).ok().and_then(|v| v.parse().ok())
+        }
+        let defaults = Self::default();
+        Self {
+            refresh_interval: var::<u64>("REPLICA_REFRESH_MS")
+                .filter(|&ms| ms > 0)
+                .map_or(defaults.refresh_interval, Duration::from_millis),
+        }
+    }
+}
+
+/// Middleware for replicas: anything but a read is refused with 403 `READ_ONLY_REPLICA`.
+pub async fn reject_writes(request: Request, next: Next) -> Response {
+    if matches!(
+        *request.method(),
+        Method::GET | Method::HEAD | Method::OPTIONS
+    ) {
+        return next.run(request).await;
+    }
+    ErrorResponse::read_only_replica().into_response()
+}
+
+/// What one refresh changed in a symbol's book.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Refreshed {
+    pub book_changed: bool,
+    pub new_trades: usize,
+}
+
+/// Bring `symbol`'s book in line with the primary: `orders` are all of its open orders and
+/// `trades` its recent trades, oldest first (those already seen are ignored). New trades and
+/// a changed book are published on `ws_channel` as the primary would, which also feeds
+/// klines, tickers and the activity feed.
+pub async fn apply(
+    state: &AppState,
+    symbol: &str,
+    orders: Vec<Order>,
+    trades: Vec<Trade>,
+) -> Result<Refreshed, String> {
+    let Some(orderbook) = state.orderbooks.get(symbol) else {
+        return Ok(Refreshed::default());
+    };
+    let mut book = orderbook.write().await;
+    let new_trades: Vec<Trade> = trades
+        .into_iter()
+        .filter(|t| t.trade_seq > book.last_trade_seq())
+        .collect();
+    book.restore_trades(new_trades.clone());
+
+    let mut current: Vec<(OrderId, Qty)> = book
+        .iter_orders()
+        .map(|(_, order)| (order.id, order.quantity))
+        .collect();
+    current.sort_unstable();
+    let mut incoming: Vec<(OrderId, Qty)> = orders
+        .iter()
+        .filter(|o| o.quantity > 0 && o.order_type == OrderType::Limit)
+        .map(|o| (o.id, o.quantity))
+        .collect();
+    incoming.sort_unstable();
+    let book_changed = current != incoming;
+    if book_changed {
+        book.replace_resting_orders(orders)?;
+    }
+
+    if !new_trades.is_empty() {
+        state.activity.record_trades(symbol, &new_trades);
+        ws::broadcast_trades(&state.ws_channel, symbol, &new_trades);
+    }
+    if book_changed {
+        ws::broadcast_orderbook_update(&state.ws_channel, symbol, &mut book);
+    }
+    Ok(Refreshed {
+        book_changed,
+        new_trades: new_trades.len(),
+    })
+}
+
+/// Re-read every public book's open orders and the trades after its last sequence.
+pub async fn refresh(state: &AppState, pool: &PgPool) -> Result<(), sqlx::Error> {
+    let skew = SkewWindow::default();
+    let now = state.clock.now();
+    for symbol in state.orderbooks.keys() {
+        let rows = persistence::list_open_orders_by_symbol(pool, symbol).await?;
+        let orders = rows
+            .iter()
+            // The primary reports skewed timestamps when it hydrates; a replica only mirrors
+            .filter_map(
+                |row| match persistence::order_row_to_order(row, &skew, now) {
+                    Ok((order, _)) => Some(order),
+                    Err(e) => {
+                        eprintln!("replica: skipping order {}: {}", row.id, e);
+                        None
+                    }
+                },
+            )
+            .collect();
+        let last_seq = state.orderbooks[symbol].read().await.last_trade_seq();
+        let trades = persistence::list_trades_from_seq(
+            pool,
+            symbol,
+            last_seq + 1,
+            MAX_TRADES_PER_REFRESH,
+            false,
+        )
+        .await?;
+        if let Err(e) = apply(state, symbol, orders, trades).await {
+            eprintln!("replica: refreshed {} book is inconsistent: {}", symbol, e);
+        }
+    }
+    Ok(())
+}
+
+/// Refresh from `pool` every `config.refresh_interval`.
+pub fn spawn_refresher(state: AppState, pool: PgPool, config: ReplicaConfig) {
+    let interval = config.refresh_interval;
+    let tasks = state.tasks.clone();
+    tasks.spawn(
+        "replica_refresher",
+        ShutdownStage::Worker,
+        RestartPolicy::default(),
+        move |token| {
+            let state = state.clone();
+            let pool = pool.clone();
+            async move {
+                let mut ticker =
+                    tokio::time::interval_at(tokio::time::Instant::now() + interval, interval);
+                while token.run_until_cancelled(ticker.tick()).await.is_some() {
+                    if let Err(e) = refresh(&state, &pool).await {
+                        eprintln!("replica refresh failed: {}", e);
+                    }
+                }
+            }
+        },
+    );
+}
@@ -0,0 +1,245 @@
+//! Data retention: periodically move old terminal orders and trades out of the hot tables
+//! into `orders_archive`/`trades_archive` (or delete them), in bounded batches with pauses.
+//! Progress is kept in `Retention` and served by the admin endpoints.
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::env;
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::RwLock;
+
+use crate::persistence::{self, PgPool};
+use crate::tasks::{RestartPolicy, ShutdownStage, Supervisor};
+
+pub type SharedRetention = Arc<Retention>;
+
+#[derive(Debug, Clone)]
+pub struct RetentionConfig {
+    /// Period of the background task; None runs retention only on demand.
+    pub interval: Option<Duration>,
+    /// Filled/Cancelled orders older than this are moved.
+    pub order_max_age: Duration,
+    /// Trades older than this are moved.
+    pub trade_max_age: Duration,
+    pub batch_size: usize,
+    /// Sleep between batches so concurrent writers are not starved.
+    pub batch_pause: Duration,
+    /// Move rows into the archive tables; when false they are deleted.
+    pub archive: bool,
+}
+
+impl Default for RetentionConfig {
+    fn default() -> Self {
+        Self {
+            interval: None,
+            order_max_age: Duration::from_secs(90 * 24 * 3600),
+            trade_max_age: Duration::from_secs(90 * 24 * 3600),
+            batch_size: 1000,
+            batch_pause: Duration::from_millis(100),
+            archive: true,
+        }
+    }
+}
+
+impl RetentionConfig {
+    /// Read `RETENTION_*` variables, falling back to the defaults.
+    pub fn from_env() -> Self {
+        fn var<T: std::str::FromStr>(name: &str) -> Option<T> {
+            env::var(name).ok().and_then(|v| v.parse().ok())
+        }
+        let defaults = Self::default();
+        Self {
+            interval: var::<u64>("RETENTION_INTERVAL_SECS")
+                .filter(|&secs| secs > 0)
+                .map(Duration::from_secs),
+            order_max_age: var::<u64>("RETENTION_ORDER_DAYS")
+                .map_or(defaults.order_max_age, |days| {
+                    Duration::from_secs(days * 24 * 3600)
+                }),
+            trade_max_age: var::<u64>("RETENTION_TRADE_DAYS")
+                .map_or(defaults.trade_max_age, |days| {
+                    Duration::from_secs(days * 24 * 3600)
+                }),
+            batch_size: var("RETENTION_BATCH_SIZE")
+                .filter(|&n| n > 0)
+                .unwrap_or(defaults.batch_size),
+            batch_pause: var("RETENTION_BATCH_PAUSE_MS")
+                .map_or(defaults.batch_pause, Duration::from_millis),
+            archive: var("RETENTION_ARCHIVE").unwrap_or(defaults.archive),
+        }
+    }
+}
+
+/// Rows processed by one retention run (updated after every batch while running).
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct RetentionRun {
+    pub started_at: Option<DateTime<Utc>>,
+    pub finished_at: Option<DateTime<Utc>>,
+    pub orders_moved: u64,
+    pub trades_moved: u64,
+    pub batches: u64,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct RetentionProgress {
+    pub running: bool,
+    pub runs_completed: u64,
+    pub orders_moved_total: u64,
+    pub trades_moved_total: u64,
+    /// The run in progress, or the most recent one.
+    pub last_run: Option<RetentionRun>,
+}
+
+#[derive(Debug)]
+pub enum RetentionError {
+    AlreadyRunning,
+    Db(sqlx::Error),
+}
+
+impl fmt::Display for RetentionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::AlreadyRunning => write!(f, "a retention run is already in progress"),
+            Self::Db(e) => write!(f, "retention failed: {}", e),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Retention {
+    pub config: RetentionConfig,
+    pub progress: RwLock<RetentionProgress>,
+}
+
+#[derive(Clone, Copy)]
+enum Table {
+    Orders,
+    Trades,
+}
+
+impl Retention {
+    pub fn new(config: RetentionConfig) -> Self {
+        Self {
+            config,
+            progress: RwLock::new(RetentionProgress::default()),
+        }
+    }
+
+    /// Claim the run slot. Returns false if a run is already in progress; on true the caller
+    /// must follow with `run_claimed`.
+    pub async fn try_claim(&self) -> bool {
+        let mut progress = self.progress.write().await;
+        if progress.running {
+            return false;
+        }
+        progress.running = true;
+        progress.last_run = Some(RetentionRun {
+            started_at: Some(Utc::now()),
+            ..RetentionRun::default()
+        });
+        true
+    }
+
+    /// Run retention once now (claims the run slot first).
+    pub async fn run_once(&self, pool: &PgPool) -> Result<RetentionRun, RetentionError> {
+        if !self.try_claim().await {
+            return Err(RetentionError::AlreadyRunning);
+        }
+        self.run_claimed(pool).await.map_err(RetentionError::Db)
+    }
+
+    /// Body of a run after `try_claim` succeeded; always releases the slot.
+    pub async fn run_claimed(&self, pool: &PgPool) -> Result<RetentionRun, sqlx::Error> {
+        let result = async {
+            self.drain(pool, Table::Orders).await?;
+            self.drain(pool, Table::Trades).await
+        }
+        .await;
+
+        let mut progress = self.progress.write().await;
+        progress.running = false;
+        progress.runs_completed += 1;
+        let run = progress.last_run.get_or_insert_with(RetentionRun::default);
+        run.finished_at = Some(Utc::now());
+        if let Err(ref e) = result {
+            run.error = Some(e.to_string());
+        }
+        let run = run.clone();
+        result.map(|_| run)
+    }
+
+    /// Process batches for one table until a batch comes back short.
+    async fn drain(&self, pool: &PgPool, table: Table) -> Result<(), sqlx::Error> {
+        let max_age = match table {
+            Table::Orders => self.config.order_max_age,
+            Table::Trades => self.config.trade_max_age,
+        };
+        let cutoff =
+            Utc::now() - chrono::Duration::from_std(max_age).unwrap_or(chrono::Duration::MAX);
+        loop {
+            let moved = match table {
+                Table::Orders => {
+                    persistence::archive_orders_batch(
+                        pool,
+                        cutoff,
+                        self.config.batch_size,
+                        self.config.archive,
+                    )
+                    .await?
+                }
+                Table::Trades => {
+                    persistence::archive_trades_batch(
+                        pool,
+                        cutoff,
+                        self.config.batch_size,
+                        self.config.archive,
+                    )
+                    .await?
+                }
+            };
+            {
+                let mut progress = self.progress.write().await;
+                match table {
+                    Table::Orders => progress.orders_moved_total += moved,
+                    Table::Trades => progress.trades_moved_total += moved,
+                }
+                if let Some(run) = progress.last_run.as_mut() {
+                    run.batches += 1;
+                    match table {
+                        Table::Orders => run.orders_moved += moved,
+                        Table::Trades => run.trades_moved += moved,
+                    }
+                }
+            }
+            if (moved as usize) < self.config.batch_size {
+                return Ok(());
+            }
+            tokio::time::sleep(self.config.batch_pause).await;
+        }
+    }
+}
+
+/// Run retention every `config.interval` (no-op if the interval is unset).
+pub fn spawn_retention_task(tasks: &Supervisor, pool: PgPool, retention: SharedRetention) {
+    let Some(interval) = retention.config.interval else {
+        return;
+    };
+    tasks.spawn(
+        "retention",
+        ShutdownStage::Worker,
+        RestartPolicy::default(),
+        move |token| {
+            let (pool, retention) = (pool.clone(), retention.clone());
+            async move {
+                let mut ticker =
+                    tokio::time::interval_at(tokio::time::Instant::now() + interval, interval);
+                while token.run_until_cancelled(ticker.tick()).await.is_some() {
+                    let _ = retention.run_once(&pool).await;
+                }
+            }
+        },
+    );
+}
@@ -0,0 +1,329 @@
+//! Private sandbox markets: in-memory books a user creates for testing bots against the real
+//! API. Only the owner and the users they invite can find a sandbox book; it is never listed,
+//! persisted or aggregated into market data, and it is dropped after a period of inactivity.
+//! Sandbox symbols carry `SANDBOX_PREFIX`, so every component can tell them apart by name.
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::RwLock;
+use uuid::Uuid;
+
+use crate::api::routes::AppState;
+use crate::orderbook::order_limits::SharedOrderLimits;
+use crate::orderbook::orderbook::{OrderBook, SharedOrderBook};
+use crate::tasks::{RestartPolicy, ShutdownStage};
+use crate::types::environment::Environment;
+use crate::types::symbol::{MAX_SYMBOL_LEN, Symbol};
+
+/// Every sandbox symbol starts with this; public symbols must not.
+pub const SANDBOX_PREFIX: &str = "SBX";
+
+pub type SharedSandboxes = Arc<Sandboxes>;
+
+/// Whether `symbol` names a sandbox book (which is then never persisted or aggregated).
+pub fn is_sandbox_symbol(symbol: &str) -> bool {
+    symbol.starts_with(SANDBOX_PREFIX)
+}
+
+#[derive(Debug, Clone)]
+pub struct SandboxConfig {
+    /// Sandboxes one user may own at a time.
+    pub max_per_user: usize,
+    /// A sandbox nobody has traded on or looked at for this long is dropped.
+    pub idle_ttl: Duration,
+    /// Period of the cleanup task.
+    pub sweep_interval: Duration,
+}
+
+impl Default for SandboxConfig {
+    fn default() -> Self {
+        Self {
+            max_per_user: 3,
+            idle_ttl: Duration::from_secs(30 * 60),
+            sweep_interval: Duration::from_secs(60),
+        }
+    }
+}
+
+impl SandboxConfig {
+    /// Read `SANDBOX_*` variables, falling back to the defaults.
+    pub fn from_env() -> Self {
+        fn var<T: std::str::FromStr>(name: &str) -> Option<T> {
+            env::var(name).ok().and_then(|v| v.parse().ok())
+        }
+        let defaults = Self::default();
+        Self {
+            max_per_user: var("SANDBOX_MAX_PER_USER").unwrap_or(defaults.max_per_user),
+            idle_ttl: var::<u64>("SANDBOX_IDLE_TTL_SECS")
+                .filter(|&secs| secs > 0)
+                .map_or(defaults.idle_ttl, Duration::from_secs),
+            sweep_interval: var::<u64>("SANDBOX_SWEEP_INTERVAL_SECS")
+                .filter(|&secs| secs > 0)
+                .map_or(defaults.sweep_interval, Duration::from_secs),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SandboxError {
+    InvalidName(String),
+    /// The symbol is public or another sandbox already uses it.
+    Taken(String),
+    LimitReached(usize),
+    /// No sandbox the caller can see has this symbol.
+    NotFound(String),
+    NotOwner,
+}
+
+impl fmt::Display for SandboxError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidName(msg) => write!(f, "{}", msg),
+            Self::Taken(symbol) => write!(f, "Symbol '{}' is already in use", symbol),
+            Self::LimitReached(max) => write!(f, "At most {} sandbox symbols per user", max),
+            Self::NotFound(symbol) => write!(f, "Symbol '{}' not found", symbol),
+            Self::NotOwner => write!(f, "Only the sandbox owner can do this"),
+        }
+    }
+}
+
+/// A sandbox book and who may use it.
+pub struct Sandbox {
+    pub owner: Uuid,
+    /// Invited users; the owner is not listed.
+    pub members: HashSet<Uuid>,
+    pub book: SharedOrderBook,
+    pub created_at: DateTime<Utc>,
+    /// Last time a member reached the book.
+    pub last_active: DateTime<Utc>,
+}
+
+impl Sandbox {
+    pub fn can_access(&self, user_id: Uuid) -> bool {
+        self.owner == user_id || self.members.contains(&user_id)
+    }
+
+    pub fn info(&self, symbol: &str) -> SandboxInfo {
+        let mut members: Vec<Uuid> = self.members.iter().copied().collect();
+        members.sort();
+        SandboxInfo {
+            symbol: symbol.to_string(),
+            owner: self.owner,
+            members,
+            created_at: self.created_at,
+            last_active: self.last_active,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SandboxInfo {
+    pub symbol: String,
+    pub owner: Uuid,
+    pub members: Vec<Uuid>,
+    pub created_at: DateTime<Utc>,
+    pub last_active: DateTime<Utc>,
+}
+
+/// All live sandboxes, keyed by symbol.
+#[derive(Default)]
+pub struct Sandboxes {
+    pub config: SandboxConfig,
+    books: RwLock<HashMap<String, Sandbox>>,
+}
+
+impl Sandboxes {
+    pub fn new(config: SandboxConfig) -> Self {
+        Self {
+            config,
+            books: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// Open a sandbox `SANDBOX_PREFIX + name` owned by `owner`. Its resting orders count
+    /// against `limits` like any other book's.
+    pub async fn create(
+        &self,
+        owner: Uuid,
+        name: &str,
+        public: impl Fn(&str) -> bool,
+        limits: SharedOrderLimits,
+        now: DateTime<Utc>,
+    ) -> Result<SandboxInfo, SandboxError> {
+        let name = Symbol::parse(name).map_err(|e| SandboxError::InvalidName(e.to_string()))?;
+        if name.environment() != Environment::Live {
+            return Err(SandboxError::InvalidName(
+                "Sandbox names cannot name an environment".to_string(),
+            ));
+        }
+        let symbol = Symbol::parse(&format!("{}{}", SANDBOX_PREFIX, name)).map_err(|_| {
+            SandboxError::InvalidName(format!(
+                "Sandbox name must be at most {} characters",
+                MAX_SYMBOL_LEN - SANDBOX_PREFIX.len()
+            ))
+        })?;
+        let mut books = self.books.write().await;
+        if public(&symbol) || books.contains_key(symbol.as_str()) {
+            return Err(SandboxError::Taken(symbol.into_string()));
+        }
+        if books.values().filter(|s| s.owner == owner).count() >= self.config.max_per_user {
+            return Err(SandboxError::LimitReached(self.config.max_per_user));
+        }
+        let mut book = OrderBook::new();
+        book.set_order_limits(limits);
+        let sandbox = Sandbox {
+            owner,
+            members: HashSet::new(),
+            book: Arc::new(RwLock::new(book)),
+            created_at: now,
+            last_active: now,
+        };
+        let info = sandbox.info(&symbol);
+        books.insert(symbol.into_string(), sandbox);
+        Ok(info)
+    }
+
+    /// `symbol`'s book if `user_id` may use it, marking the sandbox active.
+    pub async fn book_for(
+        &self,
+        symbol: &str,
+        user_id: Uuid,
+        now: DateTime<Utc>,
+    ) -> Option<SharedOrderBook> {
+        let mut books = self.books.write().await;
+        let sandbox = books.get_mut(symbol).filter(|s| s.can_access(user_id))?;
+        sandbox.last_active = now;
+        Some(sandbox.book.clone())
+    }
+
+    /// Books of every sandbox `user_id` may use.
+    pub async fn books_of(&self, user_id: Uuid) -> Vec<(String, SharedOrderBook)> {
+        self.books
+            .read()
+            .await
+            .iter()
+            .filter(|(_, s)| s.can_access(user_id))
+            .map(|(symbol, s)| (symbol.clone(), s.book.clone()))
+            .collect()
+    }
+
+    /// Sandboxes `user_id` owns or was invited to, by symbol.
+    pub async fn list(&self, user_id: Uuid) -> Vec<SandboxInfo> {
+        let mut infos: Vec<SandboxInfo> = self
+            .books
+            .read()
+            .await
+            .iter()
+            .filter(|(_, s)| s.can_access(user_id))
+            .map(|(symbol, s)| s.info(symbol))
+            .collect();
+        infos.sort_by(|a, b| a.symbol.cmp(&b.symbol));
+        infos
+    }
+
+    /// Let `member` use the owner's sandbox `symbol`.
+    pub async fn invite(
+        &self,
+        symbol: &str,
+        owner: Uuid,
+        member: Uuid,
+        now: DateTime<Utc>,
+    ) -> Result<SandboxInfo, SandboxError> {
+        let mut books = self.books.write().await;
+        let sandbox = Self::owned(&mut books, symbol, owner)?;
+        if member != owner {
+            sandbox.members.insert(member);
+        }
+        sandbox.last_active = now;
+        Ok(sandbox.info(symbol))
+    }
+
+    /// Close the owner's sandbox `symbol`, handing it back for cleanup.
+    pub async fn remove(&self, symbol: &str, owner: Uuid) -> Result<Sandbox, SandboxError> {
+        let mut books = self.books.write().await;
+        Self::owned(&mut books, symbol, owner)?;
+        Ok(books.remove(symbol).expect("sandbox checked above"))
+    }
+
+    /// Take out every sandbox idle for longer than the configured TTL.
+    pub async fn take_idle(&self, now: DateTime<Utc>) -> Vec<(String, Sandbox)> {
+        let ttl = chrono::Duration::from_std(self.config.idle_ttl).unwrap_or(chrono::Duration::MAX);
+        let mut books = self.books.write().await;
+        let idle: Vec<String> = books
+            .iter()
+            .filter(|(_, s)| now - s.last_active > ttl)
+            .map(|(symbol, _)| symbol.clone())
+            .collect();
+        idle.into_iter()
+            .filter_map(|symbol| books.remove(&symbol).map(|s| (symbol, s)))
+            .collect()
+    }
+
+    /// A sandbox only its owner can manage; others get `NotFound` unless they are members.
+    fn owned<'a>(
+        books: &'a mut HashMap<String, Sandbox>,
+        symbol: &str,
+        owner: Uuid,
+    ) -> Result<&'a mut Sandbox, SandboxError> {
+        match books.get_mut(symbol) {
+            Some(sandbox) if sandbox.owner == owner => Ok(sandbox),
+            Some(sandbox) if sandbox.can_access(owner) => Err(SandboxError::NotOwner),
+            _ => Err(SandboxError::NotFound(symbol.to_string())),
+        }
+    }
+}
+
+/// Drop what a closed sandbox left behind in shared state: session scopes of its resting
+/// orders and every position on its symbol.
+pub async fn discard(state: &AppState, symbol: &str, sandbox: Sandbox) {
+    let order_ids: Vec<_> = sandbox
+        .book
+        .read()
+        .await
+        .iter_orders()
+        .map(|(_, order)| order.id)
+        .collect();
+    {
+        let mut order_sessions = state.order_sessions.write().await;
+        for id in &order_ids {
+            order_sessions.remove(id);
+        }
+    }
+    state
+        .positions
+        .write()
+        .await
+        .retain(|(_, position_symbol), _| position_symbol != symbol);
+}
+
+/// Drop idle sandboxes every `config.sweep_interval`.
+pub fn spawn_sweeper(state: AppState) {
+    let interval = state.sandboxes.config.sweep_interval;
+    let tasks = state.tasks.clone();
+    tasks.spawn(
+        "sandbox_sweeper",
+        ShutdownStage::Worker,
+        RestartPolicy::default(),
+        move |token| {
+            let state = state.clone();
+            async move {
+                let mut ticker =
+                    tokio::time::interval_at(tokio::time::Instant::now() + interval, interval);
+                while token.run_until_cancelled(ticker.tick()).await.is_some() {
+                    for (symbol, sandbox) in state.sandboxes.take_idle(Utc::now()).await {
+                        eprintln!(
+                            "sandbox {} of user {} expired after inactivity",
+                            symbol, sandbox.owner
+                        );
+                        discard(&state, &symbol, sandbox).await;
+                    }
+                }
+            }
+        },
+    );
+}
@@ -0,0 +1,555 @@
+//! End-to-end smoke test of a running exchange, for deployment pipelines (`--self-test`) and
+//! admins (`POST /admin/self-test`).
+//!
+//! Everything happens on the `SELFTEST` book, which carries no real trading: two ephemeral
+//! users are registered, cross one lot at one tick through the HTTP routes, and the trade,
+//! positions, stored rows and WebSocket broadcasts are checked. The users and everything
+//! recorded for the symbol are removed afterwards, whether or not the checks passed. A run is
+//! refused if the book holds any resting order, or if another run is in progress.
+
+use axum::{
+    Router,
+    body::Body,
+    http::{Method, Request, StatusCode, header},
+};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use serde_json::Value;
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Instant;
+use tokio::sync::broadcast::{self, error::TryRecvError};
+use tower::ServiceExt;
+use uuid::Uuid;
+
+use crate::api::routes::{AppState, WsMessage, app_router};
+use crate::persistence;
+use crate::types::environment::Environment;
+use crate::types::order::{OrderId, Price, Qty};
+
+/// The isolated book self-tests trade on; it must be provisioned like any other symbol.
+pub const SELFTEST_SYMBOL: &str = "SELFTEST";
+
+/// Largest response body read from a route.
+const MAX_BODY_BYTES: usize = 1 << 20;
+
+static RUNNING: AtomicBool = AtomicBool::new(false);
+
+/// Clears `RUNNING` when the run ends, however it ends.
+struct RunGuard;
+
+impl Drop for RunGuard {
+    fn drop(&mut self) {
+        RUNNING.store(false, Ordering::Release);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StepStatus {
+    Passed,
+    Failed,
+    /// Not run: an earlier step failed, or it does not apply (no database).
+    Skipped,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SelfTestStep {
+    pub name: &'static str,
+    pub status: StepStatus,
+    pub detail: Option<String>,
+    pub duration_ms: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SelfTestReport {
+    pub symbol: &'static str,
+    pub passed: bool,
+    /// The run did not start because the isolation check failed; nothing was touched.
+    pub refused: bool,
+    pub started_at: DateTime<Utc>,
+    pub duration_ms: u64,
+    pub steps: Vec<SelfTestStep>,
+}
+
+#[derive(Default)]
+struct Steps {
+    steps: Vec<SelfTestStep>,
+    failed: bool,
+}
+
+impl Steps {
+    fn record(&mut self, name: &'static str, start: Instant, result: Result<String, String>) {
+        let (status, detail) = match result {
+            Ok(detail) => (StepStatus::Passed, detail),
+            Err(detail) => {
+                self.failed = true;
+                (StepStatus::Failed, detail)
+            }
+        };
+        self.steps.push(SelfTestStep {
+            name,
+            status,
+            detail: Some(detail),
+            duration_ms: start.elapsed().as_millis() as u64,
+        });
+    }
+
+    fn skip(&mut self, name: &'static str, detail: &str) {
+        self.steps.push(SelfTestStep {
+            name,
+            status: StepStatus::Skipped,
+            detail: Some(detail.to_string()),
+            duration_ms: 0,
+        });
+    }
+
+    /// Run `step` unless an earlier step failed.
+    async fn run(
+        &mut self,
+        name: &'static str,
+        step: impl Future<Output = Result<String, String>>,
+    ) {
+        if self.failed {
+            self.skip(name, "an earlier step failed");
+            return;
+        }
+        let start = Instant::now();
+        let result = step.await;
+        self.record(name, start, result);
+    }
+}
+
+/// An ephemeral user: store key (lowercase username), id and bearer token.
+struct TestUser {
+    key: String,
+    user_id: Uuid,
+    token: String,
+}
+
+#[derive(Default)]
+struct Fixture {
+    users: Vec<TestUser>,
+    /// (maker sell, taker buy)
+    orders: Option<(OrderId, OrderId)>,
+    price: Price,
+    quantity: Qty,
+}
+
+/// Run the self-test against `state` and report every step.
+pub async fn run(state: &AppState) -> SelfTestReport {
+    let started_at = Utc::now();
+    let start = Instant::now();
+    let mut steps = Steps::default();
+
+    let isolation_start = Instant::now();
+    let guard = RUNNING
+        .compare_exchange(false, true, Ordering::AcqRel, Ordering::Acquire)
+        .is_ok()
+        .then_some(RunGuard);
+    let isolation = match guard {
+        Some(_) => check_isolation(state).await,
+        None => Err("another self-test is running".to_string()),
+    };
+    steps.record("isolation", isolation_start, isolation);
+    if steps.failed {
+        return finish(steps, started_at, start, true);
+    }
+
+    let router = app_router(state.clone());
+    let mut ws = state.ws_channel.subscribe();
+    let mut fixture = Fixture::default();
+    {
+        let config = state
+            .symbol_configs
+            .read()
+            .await
+            .get(SELFTEST_SYMBOL)
+            .copied()
+            .unwrap_or_default();
+        fixture.price = config.tick_size;
+        fixture.quantity = config.lot_size;
+    }
+
+    steps
+        .run("register_users", register_users(&router, &mut fixture))
+        .await;
+    steps
+        .run("place_orders", place_orders(&router, &mut fixture))
+        .await;
+    steps.run("trades", check_trades(&router, &fixture)).await;
+    steps
+        .run("positions", check_positions(&router, &fixture))
+        .await;
+    if state.db.is_some() {
+        steps
+            .run("persistence", check_persistence(state, &fixture))
+            .await;
+    } else {
+        steps.skip("persistence", "no database configured");
+    }
+    steps
+        .run("ws_broadcasts", async {
+            check_broadcasts(&mut ws, &fixture)
+        })
+        .await;
+
+    // Always clean up, even after a failure
+    let cleanup_start = Instant::now();
+    let cleanup_result = cleanup(state, &fixture).await;
+    steps.record("cleanup", cleanup_start, cleanup_result);
+    drop(guard);
+    finish(steps, started_at, start, false)
+}
+
+fn finish(
+    steps: Steps,
+    started_at: DateTime<Utc>,
+    start: Instant,
+    refused: bool,
+) -> SelfTestReport {
+    SelfTestReport {
+        symbol: SELFTEST_SYMBOL,
+        passed: !steps.failed,
+        refused,
+        started_at,
+        duration_ms: start.elapsed().as_millis() as u64,
+        steps: steps.steps,
+    }
+}
+
+/// The book must exist and hold no resting orders (in memory or, with a database, stored).
+async fn check_isolation(state: &AppState) -> Result<String, String> {
+    let orderbook = state
+        .orderbooks
+        .get(SELFTEST_SYMBOL)
+        .ok_or_else(|| format!("no {} book is configured", SELFTEST_SYMBOL))?;
+    let resting = orderbook.read().await.iter_orders().count();
+    if resting > 0 {
+        return Err(format!(
+            "{} has {} resting orders; refusing to run",
+            SELFTEST_SYMBOL, resting
+        ));
+    }
+    if let Some(ref db) = state.db {
+        let open = persistence::list_open_orders_by_symbol(db, SELFTEST_SYMBOL)
+            .await
+            .map_err(|e| format!("failed to check stored orders: {}", e))?;
+        if !open.is_empty() {
+            return Err(format!(
+                "{} has {} open orders stored; refusing to run",
+                SELFTEST_SYMBOL,
+                open.len()
+            ));
+        }
+    }
+    Ok(format!("{} book is empty", SELFTEST_SYMBOL))
+}
+
+/// Send one request through the router; returns the status and JSON body.
+async fn call(
+    router: &Router,
+    method: Method,
+    uri: &str,
+    token: Option<&str>,
+    body: Option<Value>,
+) -> Result<(StatusCode, Value), String> {
+    let mut request = Request::builder().method(method).uri(uri);
+    if let Some(token) = token {
+        request = request.header(header::AUTHORIZATION, format!("Bearer {}", token));
+    }
+    let request = match body {
+        Some(body) => request
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(body.to_string())),
+        None => request.body(Body::empty()),
+    }
+    .map_err(|e| format!("{}: invalid request: {}", uri, e))?;
+    let response = router
+        .clone()
+        .oneshot(request)
+        .await
+        .map_err(|e| format!("{}: {}", uri, e))?;
+    let status = response.status();
+    let bytes = axum::body::to_bytes(response.into_body(), MAX_BODY_BYTES)
+        .await
+        .map_err(|e| format!("{}: failed to read body: {}", uri, e))?;
+    let json = serde_json::from_slice(&bytes).unwrap_or(Value::Null);
+    Ok((status, json))
+}
+
+/// `call`, failing unless the response has `expected` status.
+async fn expect(
+    router: &Router,
+    method: Method,
+    uri: &str,
+    token: Option<&str>,
+    body: Option<Value>,
+    expected: StatusCode,
+) -> Result<Value, String> {
+    let (status, json) = call(router, method, uri, token, body).await?;
+    if status != expected {
+        return Err(format!(
+            "{} returned {} (expected {}): {}",
+            uri, status, expected, json
+        ));
+    }
+    Ok(json)
+}
+
+async fn register_users(router: &Router, fixture: &mut Fixture) -> Result<String, String> {
+    for role in ["maker", "taker"] {
+        let username = format!(
+            "selftest-{}-{}",
+            role,
+            &Uuid::new_v4().simple().to_string()[..12]
+        );
+        let password = Uuid::new_v4().to_string();
+        let credentials = serde_json::json!({ "username": username, "password": password });
+        let registered = expect(
+            router,
+            Method::POST,
+            "/auth/register",
+            None,
+            Some(credentials.clone()),
+            StatusCode::CREATED,
+        )
+        .await?;
+        let user_id = registered["user_id"]
+            .as_str()
+            .and_then(|id| Uuid::parse_str(id).ok())
+            .ok_or("register returned no user_id")?;
+        // Registered users are cleaned up even if logging in fails
+        fixture.users.push(TestUser {
+            key: username.to_lowercase(),
+            user_id,
+            token: String::new(),
+        });
+        let login = expect(
+            router,
+            Method::POST,
+            "/auth/login",
+            None,
+            Some(credentials),
+            StatusCode::OK,
+        )
+        .await?;
+        let token = login["token"].as_str().ok_or("login returned no token")?;
+        if let Some(user) = fixture.users.last_mut() {
+            user.token = token.to_string();
+        }
+    }
+    Ok(format!("registered {} users", fixture.users.len()))
+}
+
+async fn place_orders(router: &Router, fixture: &mut Fixture) -> Result<String, String> {
+    let mut ids = Vec::new();
+    for (user, side, status) in [
+        (&fixture.users[0], "Sell", "Pending"),
+        (&fixture.users[1], "Buy", "Filled"),
+    ] {
+        let order = expect(
+            router,
+            Method::POST,
+            "/orders",
+            Some(&user.token),
+            Some(serde_json::json!({
+                "symbol": SELFTEST_SYMBOL,
+                "price": fixture.price,
+                "quantity": fixture.quantity,
+                "side": side,
+                "order_type": "Limit",
+            })),
+            StatusCode::OK,
+        )
+        .await?;
+        if order["status"] != status {
+            return Err(format!(
+                "{} order has status {} (expected {})",
+                side, order["status"], status
+            ));
+        }
+        let id = order["id"]
+            .as_str()
+            .and_then(|id| Uuid::parse_str(id).ok())
+            .ok_or("order response has no id")?;
+        ids.push(id);
+    }
+    fixture.orders = Some((ids[0], ids[1]));
+    Ok(format!("crossed {} at {}", fixture.quantity, fixture.price))
+}
+
+async fn check_trades(router: &Router, fixture: &Fixture) -> Result<String, String> {
+    let (sell_id, buy_id) = fixture.orders.ok_or("no orders were placed")?;
+    let uri = format!("/trades?symbol={}&limit=10", SELFTEST_SYMBOL);
+    let trades = expect(
+        router,
+        Method::GET,
+        &uri,
+        Some(&fixture.users[1].token),
+        None,
+        StatusCode::OK,
+    )
+    .await?;
+    let trade = trades["items"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .find(|t| {
+            t["maker_order_id"] == sell_id.to_string() && t["taker_order_id"] == buy_id.to_string()
+        })
+        .ok_or("the trade between the two orders is not listed")?;
+    if trade["price"] != fixture.price || trade["quantity"] != fixture.quantity {
+        return Err(format!("trade does not match the orders: {}", trade));
+    }
+    Ok(format!("trade {} listed", trade["id"]))
+}
+
+async fn check_positions(router: &Router, fixture: &Fixture) -> Result<String, String> {
+    let uri = format!("/positions?symbol={}", SELFTEST_SYMBOL);
+    let quantity = fixture.quantity as i64;
+    for (user, expected) in [
+        (&fixture.users[0], -quantity),
+        (&fixture.users[1], quantity),
+    ] {
+        let positions = expect(
+            router,
+            Method::GET,
+            &uri,
+            Some(&user.token),
+            None,
+            StatusCode::OK,
+        )
+        .await?;
+        if positions[0]["quantity"] != expected {
+            return Err(format!(
+                "{} position is {} (expected quantity {})",
+                user.key, positions, expected
+            ));
+        }
+    }
+    Ok("maker short and taker long one lot".to_string())
+}
+
+async fn check_persistence(state: &AppState, fixture: &Fixture) -> Result<String, String> {
+    let Some(ref db) = state.db else {
+        return Ok("no database configured".to_string());
+    };
+    let (sell_id, buy_id) = fixture.orders.ok_or("no orders were placed")?;
+    // Orders are stored as submitted, so only the taker's row carries its final status
+    for (id, status) in [(sell_id, None), (buy_id, Some("Filled"))] {
+        let row = persistence::get_order_by_id(db, id)
+            .await
+            .map_err(|e| format!("failed to load order {}: {}", id, e))?
+            .ok_or_else(|| format!("order {} is not stored", id))?;
+        if let Some(status) = status
+            && row.status != status
+        {
+            return Err(format!("stored order {} is {}", id, row.status));
+        }
+    }
+    let trades = persistence::list_trades(db, SELFTEST_SYMBOL, 10, false)
+        .await
+        .map_err(|e| format!("failed to load trades: {}", e))?;
+    if !trades
+        .iter()
+        .any(|t| t.maker_order_id == sell_id && t.taker_order_id == buy_id)
+    {
+        return Err("the trade is not stored".to_string());
+    }
+    let quantity = fixture.quantity as i64;
+    for (user, expected) in [
+        (&fixture.users[0], -quantity),
+        (&fixture.users[1], quantity),
+    ] {
+        let rows = persistence::list_positions_for_user(
+            db,
+            user.user_id,
+            Some(SELFTEST_SYMBOL),
+            Environment::Live,
+        )
+        .await
+        .map_err(|e| format!("failed to load positions: {}", e))?;
+        if rows.first().map(|r| r.quantity) != Some(expected) {
+            return Err(format!("stored position of {} is wrong", user.key));
+        }
+    }
+    Ok("orders, trade and positions stored".to_string())
+}
+
+/// The trade, both order updates and a book update must have been broadcast.
+fn check_broadcasts(
+    ws: &mut broadcast::Receiver<WsMessage>,
+    fixture: &Fixture,
+) -> Result<String, String> {
+    let (sell_id, buy_id) = fixture.orders.ok_or("no orders were placed")?;
+    let (mut trade, mut sell_update, mut buy_update, mut book_update) =
+        (false, false, false, false);
+    loop {
+        match ws.try_recv() {
+            Ok(WsMessage::Trade { symbol, trade: t }) if symbol == SELFTEST_SYMBOL => {
+                trade |= t.maker_order_id == sell_id && t.taker_order_id == buy_id;
+            }
+            Ok(WsMessage::OrderUpdate { report, .. }) => {
+                sell_update |= report.order.id == sell_id;
+                buy_update |= report.order.id == buy_id;
+            }
+            Ok(WsMessage::OrderBookUpdate { symbol, .. }) => {
+                book_update |= symbol == SELFTEST_SYMBOL;
+            }
+            Ok(_) => {}
+            Err(TryRecvError::Lagged(skipped)) => {
+                return Err(format!("missed {} broadcasts", skipped));
+            }
+            Err(TryRecvError::Empty | TryRecvError::Closed) => break,
+        }
+    }
+    let missing: Vec<&str> = [
+        (trade, "trade"),
+        (sell_update, "maker order update"),
+        (buy_update, "taker order update"),
+        (book_update, "book update"),
+    ]
+    .into_iter()
+    .filter(|(seen, _)| !seen)
+    .map(|(_, name)| name)
+    .collect();
+    if !missing.is_empty() {
+        return Err(format!("not broadcast: {}", missing.join(", ")));
+    }
+    Ok("trade, order and book updates broadcast".to_string())
+}
+
+/// Remove the users and everything recorded for the symbol: resting orders and retained
+/// trades in the book, positions, and with a database the stored rows.
+async fn cleanup(state: &AppState, fixture: &Fixture) -> Result<String, String> {
+    let user_ids: Vec<Uuid> = fixture.users.iter().map(|u| u.user_id).collect();
+    if let Some(orderbook) = state.orderbooks.get(SELFTEST_SYMBOL) {
+        let mut book = orderbook.write().await;
+        for &user_id in &user_ids {
+            book.remove_orders_for_user(user_id, |_| true, None, None);
+        }
+        book.clear_trades();
+    }
+    state
+        .positions
+        .write()
+        .await
+        .retain(|(user_id, symbol), _| symbol != SELFTEST_SYMBOL && !user_ids.contains(user_id));
+    {
+        let mut store = state.user_store.write().await;
+        for user in &fixture.users {
+            store.remove(&user.key);
+        }
+    }
+    if let Some(ref db) = state.db {
+        persistence::purge_selftest_data(db, SELFTEST_SYMBOL, &user_ids)
+            .await
+            .map_err(|e| format!("failed to delete stored rows: {}", e))?;
+    }
+    Ok(format!(
+        "removed {} users and {} data",
+        user_ids.len(),
+        SELFTEST_SYMBOL
+    ))
+}
@@ -0,0 +1,127 @@
+//! Sanity window for timestamps read back from storage or a recording. Order timestamps set
+//! time priority on hydration and drive replay, so a row dated in the future or years in the
+//! past (a skewed clock, a bad import) is flagged against the current clock and, depending on
+//! the `SkewPolicy`, rejected, clamped into the window or accepted with a warning.
+
+use chrono::{DateTime, Duration, Utc};
+use std::env;
+use std::fmt;
+
+/// What to do with a timestamp outside the window.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SkewPolicy {
+    Reject,
+    /// Move it to the nearest edge of the window.
+    Clamp,
+    #[default]
+    AcceptWithWarning,
+}
+
+impl SkewPolicy {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "reject" => Some(SkewPolicy::Reject),
+            "clamp" => Some(SkewPolicy::Clamp),
+            "accept-with-warning" | "accept" => Some(SkewPolicy::AcceptWithWarning),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for SkewPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            SkewPolicy::Reject => "reject",
+            SkewPolicy::Clamp => "clamp",
+            SkewPolicy::AcceptWithWarning => "accept-with-warning",
+        })
+    }
+}
+
+/// Timestamps from `max_age` before to `max_lead` after the current time are plausible.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SkewWindow {
+    pub max_age: Duration,
+    pub max_lead: Duration,
+    pub policy: SkewPolicy,
+}
+
+impl Default for SkewWindow {
+    fn default() -> Self {
+        Self {
+            max_age: Duration::days(365),
+            max_lead: Duration::minutes(5),
+            policy: SkewPolicy::default(),
+        }
+    }
+}
+
+/// A timestamp that passed the window check: the one to use, and what was wrong with the
+/// original if anything.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CheckedTimestamp {
+    pub timestamp: DateTime<Utc>,
+    pub warning: Option<String>,
+}
+
+impl SkewWindow {
+    /// Read `TIMESTAMP_SKEW_POLICY`, `TIMESTAMP_MAX_AGE_DAYS` and `TIMESTAMP_MAX_LEAD_SECS`,
+    /// falling back to the defaults.
+    pub fn from_env() -> Self {
+        fn var<T: std::str::FromStr>(name: &str) -> Option<T> {
+            env::var(name).ok().and_then(|v| v.parse().ok())
+        }
+        let defaults = Self::default();
+        Self {
+            max_age: var::<i64>("TIMESTAMP_MAX_AGE_DAYS")
+                .filter(|&days| days > 0)
+                .map_or(defaults.max_age, Duration::days),
+            max_lead: var::<i64>("TIMESTAMP_MAX_LEAD_SECS")
+                .filter(|&secs| secs >= 0)
+                .map_or(defaults.max_lead, Duration::seconds),
+            policy: env::var("TIMESTAMP_SKEW_POLICY")
+                .ok()
+                .and_then(|v| SkewPolicy::parse(&v))
+                .unwrap_or(defaults.policy),
+        }
+    }
+
+    /// Check `timestamp` against the window around `now`. Errors with the reason when it is
+    /// outside and the policy rejects it.
+    pub fn check(
+        &self,
+        timestamp: DateTime<Utc>,
+        now: DateTime<Utc>,
+    ) -> Result<CheckedTimestamp, String> {
+        let earliest = now - self.max_age;
+        let latest = now + self.max_lead;
+        let (problem, edge) = if timestamp < earliest {
+            (
+                format!("more than {} days old", self.max_age.num_days()),
+                earliest,
+            )
+        } else if timestamp > latest {
+            (
+                format!("more than {}s in the future", self.max_lead.num_seconds()),
+                latest,
+            )
+        } else {
+            return Ok(CheckedTimestamp {
+                timestamp,
+                warning: None,
+            });
+        };
+        let skewed = format!("timestamp {} is {}", timestamp.to_rfc3339(), problem);
+        match self.policy {
+            SkewPolicy::Reject => Err(skewed),
+            SkewPolicy::Clamp => Ok(CheckedTimestamp {
+                timestamp: edge,
+                warning: Some(format!("{}; clamped to {}", skewed, edge.to_rfc3339())),
+            }),
+            SkewPolicy::AcceptWithWarning => Ok(CheckedTimestamp {
+                timestamp,
+                warning: Some(skewed),
+            }),
+        }
+    }
+}
@@ -0,0 +1,446 @@
+//! File-backed snapshots of the whole in-memory exchange, so a development server without
+//! Postgres keeps its users, books, positions and recent trades across restarts.
+//!
+//! A snapshot file is a fixed header followed by the payload:
+//!
+//! | bytes | field                                   |
+//! |-------|-----------------------------------------|
+//! | 8     | magic `RXSNAPSH`                        |
+//! | 4     | format version, little endian           |
+//! | 8     | payload length, little endian           |
+//! | 8     | FNV-1a 64 checksum of the payload, LE   |
+//! | n     | payload: `ExchangeSnapshot`, serde JSON |
+//!
+//! Anything that does not match (wrong magic, other version, short file, bad checksum or an
+//! undecodable payload) is an error; a server never starts from a snapshot it cannot read.
+//! Password hashes are written; the JWT secret and other configuration are not.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use uuid::Uuid;
+
+use crate::api::auth::AuthUserCredential;
+use crate::api::routes::AppState;
+use crate::hydration::{self, Hydrated, HydrationReport};
+use crate::orderbook::orderbook::{OrderBook, TradingPhase};
+use crate::tasks::{RestartPolicy, ShutdownStage};
+use crate::types::order::{Order, Price};
+use crate::types::position::Position;
+use crate::types::symbol::SymbolConfig;
+use crate::types::trade::{Trade, TradeAttribution, TradeBust};
+
+pub const SNAPSHOT_MAGIC: &[u8; 8] = b"RXSNAPSH";
+/// Bump whenever `ExchangeSnapshot` changes shape; older files are then refused.
+pub const SNAPSHOT_FORMAT_VERSION: u32 = 1;
+const HEADER_LEN: usize = 8 + 4 + 8 + 8;
+
+/// A user as written to a snapshot (the argon2 hash, never the password).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SnapshotUser {
+    pub user_id: Uuid,
+    pub username: String,
+    pub password_hash: String,
+    /// Absent from snapshots written before it was recorded; restored as the snapshot time.
+    #[serde(default)]
+    pub created_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub last_login_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub login_count: u64,
+    /// The account kill switch is engaged.
+    #[serde(default)]
+    pub trading_disabled: bool,
+}
+
+/// A position as written to a snapshot.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SnapshotPosition {
+    pub user_id: Uuid,
+    pub symbol: String,
+    pub quantity: i64,
+    pub average_price: Price,
+    /// Absent from snapshots written before they were recorded; restored as the snapshot time.
+    #[serde(default)]
+    pub opened_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+/// One book: resting orders in priority order (bids best first, then asks, each level in time
+/// priority) and the retained trades, oldest first, with their fees and realized P&L.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BookState {
+    pub symbol: String,
+    pub phase: TradingPhase,
+    pub last_trade_seq: u64,
+    pub orders: Vec<Order>,
+    pub trades: Vec<Trade>,
+    /// By trade id.
+    #[serde(default)]
+    pub attributions: BTreeMap<Uuid, TradeAttribution>,
+    /// Busted retained trades, by trade id.
+    #[serde(default)]
+    pub busts: BTreeMap<Uuid, TradeBust>,
+}
+
+/// Everything a snapshot restores. Collections are sorted so that equal states give equal
+/// snapshots.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExchangeSnapshot {
+    pub taken_at: DateTime<Utc>,
+    /// By user id.
+    pub users: Vec<SnapshotUser>,
+    /// By symbol.
+    pub books: Vec<BookState>,
+    /// By user id, then symbol.
+    pub positions: Vec<SnapshotPosition>,
+    pub symbol_configs: BTreeMap<String, SymbolConfig>,
+}
+
+#[derive(Debug)]
+pub enum SnapshotError {
+    Io(io::Error),
+    /// The file does not start with `SNAPSHOT_MAGIC`.
+    NotASnapshot,
+    UnsupportedVersion(u32),
+    /// Shorter than its header says.
+    Truncated {
+        expected: u64,
+        actual: u64,
+    },
+    ChecksumMismatch,
+    Decode(String),
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "snapshot I/O failed: {}", e),
+            Self::NotASnapshot => write!(f, "not a snapshot file (bad magic)"),
+            Self::UnsupportedVersion(version) => write!(
+                f,
+                "snapshot format version {} is not supported (expected {})",
+                version, SNAPSHOT_FORMAT_VERSION
+            ),
+            Self::Truncated { expected, actual } => write!(
+                f,
+                "snapshot is truncated: {} payload bytes, header says {}",
+                actual, expected
+            ),
+            Self::ChecksumMismatch => write!(f, "snapshot checksum does not match its payload"),
+            Self::Decode(e) => write!(f, "snapshot payload is corrupt: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for SnapshotError {}
+
+impl From<io::Error> for SnapshotError {
+    fn from(e: io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+fn checksum(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+/// Header and payload of `snapshot` as written to disk.
+pub fn encode(snapshot: &ExchangeSnapshot) -> Vec<u8> {
+    let payload = serde_json::to_vec(snapshot).expect("snapshot serializes");
+    let mut bytes = Vec::with_capacity(HEADER_LEN + payload.len());
+    bytes.extend_from_slice(SNAPSHOT_MAGIC);
+    bytes.extend_from_slice(&SNAPSHOT_FORMAT_VERSION.to_le_bytes());
+    bytes.extend_from_slice(&(payload.len() as u64).to_le_bytes());
+    bytes.extend_from_slice(&checksum(&payload).to_le_bytes());
+    bytes.extend_from_slice(&payload);
+    bytes
+}
+
+/// Parse and verify a snapshot file's contents.
+pub fn decode(bytes: &[u8]) -> Result<ExchangeSnapshot, SnapshotError> {
+    if bytes.len() < SNAPSHOT_MAGIC.len() || &bytes[..8] != SNAPSHOT_MAGIC {
+        return Err(SnapshotError::NotASnapshot);
+    }
+    if bytes.len() < HEADER_LEN {
+        return Err(SnapshotError::Truncated {
+            expected: HEADER_LEN as u64,
+            actual: bytes.len() as u64,
+        });
+    }
+    let field = |at: usize| -> [u8; 8] { bytes[at..at + 8].try_into().expect("8 bytes") };
+    let version = u32::from_le_bytes(bytes[8..12].try_into().expect("4 bytes"));
+    if version != SNAPSHOT_FORMAT_VERSION {
+        return Err(SnapshotError::UnsupportedVersion(version));
+    }
+    let length = u64::from_le_bytes(field(12));
+    let expected_checksum = u64::from_le_bytes(field(20));
+    let payload = &bytes[HEADER_LEN..];
+    if payload.len() as u64 != length {
+        return Err(SnapshotError::Truncated {
+            expected: length,
+            actual: payload.len() as u64,
+        });
+    }
+    if checksum(payload) != expected_checksum {
+        return Err(SnapshotError::ChecksumMismatch);
+    }
+    serde_json::from_slice(payload).map_err(|e| SnapshotError::Decode(e.to_string()))
+}
+
+/// Write `snapshot` to `path`, replacing any previous file only once the new one is complete.
+pub fn write_file(path: &Path, snapshot: &ExchangeSnapshot) -> Result<(), SnapshotError> {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    let tmp = PathBuf::from(tmp);
+    std::fs::write(&tmp, encode(snapshot))?;
+    std::fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+/// Read `path`; Ok(None) if there is no snapshot there yet.
+pub fn read_file(path: &Path) -> Result<Option<ExchangeSnapshot>, SnapshotError> {
+    match std::fs::read(path) {
+        Ok(bytes) => decode(&bytes).map(Some),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Capture the public books, users, positions and symbol configs. All book read locks are
+/// held (in symbol order) while positions are read, so fills are never half captured.
+/// Sandbox books are not included.
+pub async fn capture(state: &AppState) -> ExchangeSnapshot {
+    let trading_disabled = state.kill_switches.engaged();
+    let mut users: Vec<SnapshotUser> = state
+        .user_store
+        .read()
+        .await
+        .values()
+        .map(|user| SnapshotUser {
+            user_id: user.user_id,
+            username: user.username.clone(),
+            password_hash: user.password_hash.clone(),
+            created_at: Some(user.created_at),
+            last_login_at: user.last_login_at,
+            login_count: user.login_count,
+            trading_disabled: trading_disabled.contains(&user.user_id),
+        })
+        .collect();
+    users.sort_by_key(|user| user.user_id);
+
+    let orderbooks: BTreeMap<&String, _> = state.orderbooks.iter().collect();
+    let mut guards = Vec::with_capacity(orderbooks.len());
+    for (symbol, orderbook) in orderbooks {
+        guards.push((symbol, orderbook.read().await));
+    }
+    let mut positions: Vec<SnapshotPosition> = state
+        .positions
+        .read()
+        .await
+        .values()
+        .map(|position| SnapshotPosition {
+            user_id: position.user_id,
+            symbol: position.symbol.clone(),
+            quantity: position.quantity,
+            average_price: position.average_price,
+            opened_at: Some(position.opened_at),
+            updated_at: Some(position.updated_at),
+        })
+        .collect();
+    let books = guards
+        .iter()
+        .map(|(symbol, book)| {
+            let trades = book.get_all_trades();
+            let attributions = trades
+                .iter()
+                .filter_map(|t| book.attribution(t.id).map(|a| (t.id, *a)))
+                .collect();
+            let busts = trades
+                .iter()
+                .filter_map(|t| book.bust(t.id).map(|b| (t.id, b.clone())))
+                .collect();
+            BookState {
+                symbol: symbol.to_string(),
+                phase: book.phase(),
+                last_trade_seq: book.last_trade_seq(),
+                orders: book.iter_orders().map(|(_, order)| order.clone()).collect(),
+                trades,
+                attributions,
+                busts,
+            }
+        })
+        .collect();
+    drop(guards);
+    positions.sort_by(|a, b| (a.user_id, &a.symbol).cmp(&(b.user_id, &b.symbol)));
+
+    let symbol_configs = state
+        .symbol_configs
+        .read()
+        .await
+        .iter()
+        .map(|(symbol, config)| (symbol.clone(), *config))
+        .collect();
+    ExchangeSnapshot {
+        taken_at: Utc::now(),
+        users,
+        books,
+        positions,
+        symbol_configs,
+    }
+}
+
+/// Rebuild startup state for `symbols` from `snapshot`, as hydration would from the database.
+/// Books for symbols no longer configured are left out and reported as errors.
+pub fn restore(snapshot: ExchangeSnapshot, symbols: &[&str]) -> Hydrated {
+    let mut report = HydrationReport::default();
+    let mut orderbooks: HashMap<String, OrderBook> = symbols
+        .iter()
+        .map(|symbol| (symbol.to_string(), OrderBook::new()))
+        .collect();
+    for state in snapshot.books {
+        let Some(book) = orderbooks.get_mut(&state.symbol) else {
+            report.errors.push(format!(
+                "snapshot has a book for unconfigured symbol {}",
+                state.symbol
+            ));
+            continue;
+        };
+        report
+            .per_symbol_restored
+            .insert(state.symbol.clone(), state.orders.len());
+        // Restored one by one, in snapshot order, so every level keeps its time priority
+        for order in state.orders {
+            book.restore_order(order);
+        }
+        book.restore_trades(state.trades);
+        book.record_attributions(state.attributions.into_values());
+        for (trade_id, bust) in state.busts {
+            book.record_bust(trade_id, bust);
+        }
+        book.set_last_trade_seq(state.last_trade_seq);
+        if state.phase == TradingPhase::Auction {
+            book.start_auction();
+        }
+    }
+
+    let mut symbol_configs: HashMap<String, SymbolConfig> = symbols
+        .iter()
+        .map(|symbol| (symbol.to_string(), SymbolConfig::for_symbol(symbol)))
+        .collect();
+    for (symbol, config) in snapshot.symbol_configs {
+        if let Some(slot) = symbol_configs.get_mut(&symbol) {
+            // Snapshots taken before symbols had assets keep the ones read off the name
+            *slot = match config.assets() {
+                Some(_) => config,
+                None => SymbolConfig {
+                    base_asset: slot.base_asset,
+                    quote_asset: slot.quote_asset,
+                    ..config
+                },
+            };
+        }
+    }
+    hydration::drop_delisted(&mut orderbooks, &mut symbol_configs, &mut report);
+    for (symbol, book) in orderbooks.iter_mut() {
+        let config = symbol_configs[symbol];
+        book.set_pricing_policy(config.pricing_policy, config.tick_size);
+    }
+
+    report.users_restored = snapshot.users.len();
+    let taken_at = snapshot.taken_at;
+    let trading_disabled = snapshot
+        .users
+        .iter()
+        .filter(|user| user.trading_disabled)
+        .map(|user| user.user_id)
+        .collect();
+    let users = snapshot
+        .users
+        .into_iter()
+        .map(|user| {
+            (
+                user.username.to_lowercase(),
+                AuthUserCredential {
+                    user_id: user.user_id,
+                    username: user.username,
+                    password_hash: user.password_hash,
+                    created_at: user.created_at.unwrap_or(taken_at),
+                    last_login_at: user.last_login_at,
+                    login_count: user.login_count,
+                },
+            )
+        })
+        .collect();
+    report.positions_restored = snapshot.positions.len();
+    let positions = snapshot
+        .positions
+        .into_iter()
+        .map(|position| {
+            (
+                (position.user_id, position.symbol.clone()),
+                Position {
+                    user_id: position.user_id,
+                    symbol: position.symbol,
+                    quantity: position.quantity,
+                    average_price: position.average_price,
+                    opened_at: position.opened_at.unwrap_or(taken_at),
+                    updated_at: position.updated_at.unwrap_or(taken_at),
+                },
+            )
+        })
+        .collect();
+
+    Hydrated {
+        orderbooks,
+        positions,
+        users,
+        trading_disabled,
+        symbol_configs,
+        report,
+    }
+}
+
+/// Capture `state` and write it to `path` off the async runtime.
+pub async fn save(state: &AppState, path: &Path) -> Result<(), SnapshotError> {
+    let snapshot = capture(state).await;
+    let path = path.to_path_buf();
+    tokio::task::spawn_blocking(move || write_file(&path, &snapshot))
+        .await
+        .map_err(|e| SnapshotError::Io(io::Error::other(e)))?
+}
+
+/// Write a snapshot of `state` to `path` every `interval`, and a last one when stopped. It
+/// stops in the `Writer` stage, after everything that changes the state.
+pub fn spawn_writer(state: AppState, path: PathBuf, interval: Duration) {
+    let tasks = state.tasks.clone();
+    tasks.spawn(
+        "snapshot_writer",
+        ShutdownStage::Writer,
+        RestartPolicy::default(),
+        move |token| {
+            let state = state.clone();
+            let path = path.clone();
+            async move {
+                let mut ticker =
+                    tokio::time::interval_at(tokio::time::Instant::now() + interval, interval);
+                while token.run_until_cancelled(ticker.tick()).await.is_some() {
+                    if let Err(e) = save(&state, &path).await {
+                        eprintln!("failed to write snapshot {}: {}", path.display(), e);
+                    }
+                }
+                match save(&state, &path).await {
+                    Ok(()) => eprintln!("snapshot written to {}", path.display()),
+                    Err(e) => eprintln!("failed to write snapshot {}: {}", path.display(), e),
+                }
+            }
+        },
+    );
+}
@@ -0,0 +1,435 @@
+//! Daily settlement statements: for each user and UTC day, the positions held at the start
+//! and the end of the day, the day's trades with their fees and realized P&L, the P&L still
+//! open at the close and the balance movements the trades made. Statements are stored in
+//! `statements` and served as CSV by `GET /account/statements`.
+//!
+//! A run covers one day. It walks the users in chunks of `chunk_size` and stores each chunk
+//! in a transaction of its own, so a large user base never holds one long transaction.
+//! Positions are replayed from the users' trades (hot and archived, busted ones left out)
+//! with the same position math as matching, fees and realized P&L are the amounts stored with
+//! each trade, and open P&L is valued at each symbol's last trade price before the close.
+//! Running a day again replaces its statements, which come out the same unless trades were
+//! busted in between. Users with no trades that day and no position at either end get none.
+//!
+//! Balance movements follow the ledger: the buyer receives the base asset and pays the
+//! notional in the quote asset, the seller the other way round. Fees are reported but not
+//! charged to balances.
+
+use chrono::{DateTime, Days, NaiveDate, NaiveTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+use std::time::Duration;
+use uuid::Uuid;
+
+use crate::api::routes::SharedSymbolConfigs;
+use crate::persistence::{self, PgPool, StatementRow, UserTradeRow};
+use crate::positions::{self, apply_fill};
+use crate::tasks::{RestartPolicy, ShutdownStage, Supervisor};
+use crate::types::money::scaled_notional;
+use crate::types::order::{OrderSide, Price};
+use crate::types::position::Position;
+use crate::types::symbol::{Symbol, SymbolConfig};
+use crate::types::trade::TradeRole;
+
+/// Users per chunk when `STATEMENT_CHUNK_SIZE` is unset.
+pub const DEFAULT_CHUNK_SIZE: usize = 500;
+
+/// A symbol the user held a position in at either end of the day, or traded during it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StatementPosition {
+    pub symbol: String,
+    pub opening_quantity: i64,
+    pub opening_average_price: Option<Price>,
+    pub closing_quantity: i64,
+    pub closing_average_price: Option<Price>,
+    /// Last trade price before the close; None if the symbol never traded.
+    pub mark_price: Option<Price>,
+    /// Open P&L of the closing position at `mark_price`, in the quote asset.
+    pub unrealized_pnl: Option<i64>,
+}
+
+/// One side of a trade the user took part in; a self-trade shows up once per side.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StatementTrade {
+    pub trade_id: Uuid,
+    pub trade_seq: u64,
+    pub symbol: String,
+    pub timestamp: DateTime<Utc>,
+    pub role: TradeRole,
+    pub side: OrderSide,
+    pub price: Price,
+    pub quantity: u64,
+    pub fee: i64,
+    pub realized_pnl: i64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BalanceMovement {
+    pub asset: String,
+    /// Net change over the day: base in lots, quote in quote units.
+    pub change: i64,
+}
+
+/// One user's statement for one day.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Statement {
+    pub user_id: Uuid,
+    pub date: NaiveDate,
+    pub positions: Vec<StatementPosition>,
+    pub trades: Vec<StatementTrade>,
+    pub balance_movements: Vec<BalanceMovement>,
+    pub fees: i64,
+    pub realized_pnl: i64,
+    pub unrealized_pnl: i64,
+}
+
+/// What one run stored.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct StatementRun {
+    pub date: NaiveDate,
+    pub users_scanned: usize,
+    pub statements: usize,
+    pub chunks: usize,
+}
+
+/// Users per chunk, from `STATEMENT_CHUNK_SIZE` (default `DEFAULT_CHUNK_SIZE`).
+pub fn chunk_size_from_env() -> usize {
+    std::env::var("STATEMENT_CHUNK_SIZE")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .filter(|&n| n > 0)
+        .unwrap_or(DEFAULT_CHUNK_SIZE)
+}
+
+/// Start and end of `date` in UTC.
+pub fn day_bounds(date: NaiveDate) -> (DateTime<Utc>, DateTime<Utc>) {
+    let start = date.and_time(NaiveTime::MIN).and_utc();
+    let end = date
+        .checked_add_days(Days::new(1))
+        .map_or(DateTime::<Utc>::MAX_UTC, |next| {
+            next.and_time(NaiveTime::MIN).and_utc()
+        });
+    (start, end)
+}
+
+/// Compute and store every statement for `date`, `chunk_size` users at a time.
+pub async fn generate(
+    pool: &PgPool,
+    symbol_configs: &HashMap<String, SymbolConfig>,
+    date: NaiveDate,
+    chunk_size: usize,
+) -> Result<StatementRun, sqlx::Error> {
+    let (_, end) = day_bounds(date);
+    let chunk_size = chunk_size.max(1);
+    let mut run = StatementRun {
+        date,
+        users_scanned: 0,
+        statements: 0,
+        chunks: 0,
+    };
+    let mut after = None;
+    loop {
+        let user_ids = persistence::list_user_ids_after(pool, after, chunk_size).await?;
+        let Some(&last) = user_ids.last() else {
+            break;
+        };
+        after = Some(last);
+        run.users_scanned += user_ids.len();
+        run.chunks += 1;
+
+        let rows = persistence::list_trades_for_users_before(pool, &user_ids, end).await?;
+        let mut symbols: Vec<String> = rows.iter().map(|r| r.trade.symbol.clone()).collect();
+        symbols.sort_unstable();
+        symbols.dedup();
+        let marks = persistence::last_trade_prices_before(pool, &symbols, end).await?;
+
+        let generated_at = Utc::now();
+        let statements: Vec<StatementRow> = user_ids
+            .iter()
+            .filter_map(|&user_id| {
+                let own = rows.iter().filter(|r| {
+                    r.trade.maker_user_id == user_id || r.trade.taker_user_id == user_id
+                });
+                build(user_id, date, own, symbol_configs, &marks)
+            })
+            .map(|statement| StatementRow {
+                user_id: statement.user_id,
+                statement_date: date,
+                trade_count: statement.trades.len().try_into().unwrap_or(i32::MAX),
+                fees: statement.fees,
+                realized_pnl: statement.realized_pnl,
+                unrealized_pnl: statement.unrealized_pnl,
+                csv: statement.to_csv(generated_at),
+                details: sqlx::types::Json(statement),
+                generated_at,
+            })
+            .collect();
+        persistence::upsert_statements(pool, &statements).await?;
+        run.statements += statements.len();
+        if user_ids.len() < chunk_size {
+            break;
+        }
+    }
+    Ok(run)
+}
+
+/// `user_id`'s statement for `date` from `rows`, the user's trades before the day's end in
+/// order; None if there is nothing to report.
+fn build<'a>(
+    user_id: Uuid,
+    date: NaiveDate,
+    rows: impl IntoIterator<Item = &'a UserTradeRow>,
+    symbol_configs: &HashMap<String, SymbolConfig>,
+    marks: &HashMap<String, Price>,
+) -> Option<Statement> {
+    let (start, _) = day_bounds(date);
+    let config = |symbol: &str| {
+        symbol_configs
+            .get(symbol)
+            .copied()
+            .unwrap_or_else(|| SymbolConfig::for_symbol(symbol))
+    };
+    let qty_scale = |symbol: &str| config(symbol).qty_scale.max(1);
+    let mut opening: BTreeMap<String, Option<Position>> = BTreeMap::new();
+    let mut held: BTreeMap<String, Option<Position>> = BTreeMap::new();
+    let mut trades = Vec::new();
+    for row in rows {
+        let Some(attribution) = row.attribution() else {
+            // Stored before trades recorded their taker side: which side was whose is unknown
+            continue;
+        };
+        let trade = row.to_trade();
+        let Ok(symbol) = Symbol::parse(&row.trade.symbol) else {
+            continue;
+        };
+        // Maker leg first, as matching applies them
+        let mut legs = Vec::with_capacity(2);
+        if trade.maker_user_id == user_id {
+            legs.push((
+                TradeRole::Maker,
+                attribution.taker_side.opposite(),
+                attribution.maker_fee,
+                attribution.realized_pnl_maker,
+            ));
+        }
+        if trade.taker_user_id == user_id {
+            legs.push((
+                TradeRole::Taker,
+                attribution.taker_side,
+                attribution.taker_fee,
+                attribution.realized_pnl_taker,
+            ));
+        }
+        for (role, side, fee, realized_pnl) in legs {
+            let position = held.entry(symbol.to_string()).or_default();
+            if trade.timestamp >= start {
+                opening
+                    .entry(symbol.to_string())
+                    .or_insert_with(|| position.clone());
+                trades.push(StatementTrade {
+                    trade_id: trade.id,
+                    trade_seq: trade.trade_seq,
+                    symbol: symbol.to_string(),
+                    timestamp: trade.timestamp,
+                    role,
+                    side,
+                    price: trade.price,
+                    quantity: trade.quantity,
+                    fee,
+                    realized_pnl,
+                });
+            }
+            *position = apply_fill(
+                position.as_ref(),
+                user_id,
+                &symbol,
+                side,
+                trade.price,
+                trade.quantity,
+                trade.timestamp,
+            );
+        }
+    }
+
+    let positions: Vec<StatementPosition> = held
+        .iter()
+        .filter_map(|(symbol, closing)| {
+            // Symbols not traded today opened the day as they closed it
+            let opening = opening.get(symbol).unwrap_or(closing);
+            if opening.is_none() && closing.is_none() && !trades.iter().any(|t| &t.symbol == symbol)
+            {
+                return None;
+            }
+            let mark_price = marks.get(symbol).copied();
+            let unrealized_pnl = match (closing, mark_price) {
+                (Some(position), Some(mark)) => {
+                    positions::scaled_unrealized_pnl(position, mark, qty_scale(symbol)).ok()
+                }
+                (None, _) => Some(0),
+                (Some(_), None) => None,
+            };
+            Some(StatementPosition {
+                symbol: symbol.clone(),
+                opening_quantity: opening.as_ref().map_or(0, |p| p.quantity),
+                opening_average_price: opening.as_ref().map(|p| p.average_price),
+                closing_quantity: closing.as_ref().map_or(0, |p| p.quantity),
+                closing_average_price: closing.as_ref().map(|p| p.average_price),
+                mark_price,
+                unrealized_pnl,
+            })
+        })
+        .collect();
+    if positions.is_empty() {
+        return None;
+    }
+
+    let mut movements: BTreeMap<String, i64> = BTreeMap::new();
+    for trade in &trades {
+        let Some((base, quote)) = config(&trade.symbol).assets() else {
+            continue;
+        };
+        let cost = scaled_notional(trade.price, trade.quantity, qty_scale(&trade.symbol))
+            .to_i64_saturating();
+        let quantity = i64::try_from(trade.quantity).unwrap_or(i64::MAX);
+        let (base_change, quote_change) = match trade.side {
+            OrderSide::Buy => (quantity, cost.saturating_neg()),
+            OrderSide::Sell => (quantity.saturating_neg(), cost),
+        };
+        for (asset, change) in [(base, base_change), (quote, quote_change)] {
+            let total = movements.entry(asset.to_string()).or_default();
+            *total = total.saturating_add(change);
+        }
+    }
+
+    Some(Statement {
+        user_id,
+        date,
+        fees: trades.iter().fold(0, |sum, t| sum.saturating_add(t.fee)),
+        realized_pnl: trades
+            .iter()
+            .fold(0, |sum, t| sum.saturating_add(t.realized_pnl)),
+        unrealized_pnl: positions
+            .iter()
+            .filter_map(|p| p.unrealized_pnl)
+            .fold(0, i64::saturating_add),
+        positions,
+        trades,
+        balance_movements: movements
+            .into_iter()
+            .map(|(asset, change)| BalanceMovement { asset, change })
+            .collect(),
+    })
+}
+
+fn optional<T: ToString>(value: Option<T>) -> String {
+    value.map(|v| v.to_string()).unwrap_or_default()
+}
+
+impl Statement {
+    /// The statement as CSV: a header block, then one section per table (positions, trades,
+    /// balance movements), each titled and with its own header row, separated by blank lines.
+    pub fn to_csv(&self, generated_at: DateTime<Utc>) -> String {
+        let mut out = String::new();
+        let _ = writeln!(
+            out,
+            "user_id,date,generated_at,trades,fees,realized_pnl,unrealized_pnl"
+        );
+        let _ = writeln!(
+            out,
+            "{},{},{},{},{},{},{}",
+            self.user_id,
+            self.date,
+            generated_at.to_rfc3339(),
+            self.trades.len(),
+            self.fees,
+            self.realized_pnl,
+            self.unrealized_pnl
+        );
+        out.push_str("\npositions\n");
+        out.push_str(
+            "symbol,opening_quantity,opening_average_price,closing_quantity,closing_average_price,mark_price,unrealized_pnl\n",
+        );
+        for p in &self.positions {
+            let _ = writeln!(
+                out,
+                "{},{},{},{},{},{},{}",
+                p.symbol,
+                p.opening_quantity,
+                optional(p.opening_average_price),
+                p.closing_quantity,
+                optional(p.closing_average_price),
+                optional(p.mark_price),
+                optional(p.unrealized_pnl)
+            );
+        }
+        out.push_str("\ntrades\n");
+        out.push_str(
+            "timestamp,trade_id,trade_seq,symbol,role,side,price,quantity,fee,realized_pnl\n",
+        );
+        for t in &self.trades {
+            let _ = writeln!(
+                out,
+                "{},{},{},{},{:?},{:?},{},{},{},{}",
+                t.timestamp.to_rfc3339(),
+                t.trade_id,
+                t.trade_seq,
+                t.symbol,
+                t.role,
+                t.side,
+                t.price,
+                t.quantity,
+                t.fee,
+                t.realized_pnl
+            );
+        }
+        out.push_str("\nbalance_movements\n");
+        out.push_str("asset,change\n");
+        for m in &self.balance_movements {
+            let _ = writeln!(out, "{},{}", m.asset, m.change);
+        }
+        out
+    }
+}
+
+/// Generate the previous day's statements every UTC midnight.
+pub fn spawn_daily(
+    tasks: &Supervisor,
+    pool: PgPool,
+    symbol_configs: SharedSymbolConfigs,
+    chunk_size: usize,
+) {
+    tasks.spawn(
+        "daily_statements",
+        ShutdownStage::Worker,
+        RestartPolicy::default(),
+        move |token| {
+            let (pool, symbol_configs) = (pool.clone(), symbol_configs.clone());
+            async move {
+                loop {
+                    let now = Utc::now();
+                    let (_, midnight) = day_bounds(now.date_naive());
+                    let wait = (midnight - now).to_std().unwrap_or(Duration::ZERO);
+                    if token
+                        .run_until_cancelled(tokio::time::sleep(wait))
+                        .await
+                        .is_none()
+                    {
+                        return;
+                    }
+                    let Some(date) = midnight.date_naive().pred_opt() else {
+                        return;
+                    };
+                    let configs = symbol_configs.read().await.clone();
+                    match generate(&pool, &configs, date, chunk_size).await {
+                        Ok(run) => eprintln!(
+                            "statements: {} stored for {} ({} users)",
+                            run.statements, run.date, run.users_scanned
+                        ),
+                        Err(e) => eprintln!("statements for {} failed: {}", date, e),
+                    }
+                }
+            }
+        },
+    );
+}
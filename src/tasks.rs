@@ -0,0 +1,307 @@
+//! Lifecycle of the server's long-running background tasks. Each is registered with the
+//! `Supervisor` under a name and a shutdown stage, and gets a `CancellationToken` it is expected
+//! to watch between units of work. The supervisor notices a task that panicked, logs it with
+//! the task's name and, if the task's `RestartPolicy` allows, starts it again after a backoff.
+//!
+//! `Supervisor::shutdown` stops the tasks stage by stage (`ShutdownStage` order: whatever takes
+//! input first, writers that flush state last), giving each stage the same timeout. A task that
+//! ignores its token past the timeout is logged as a straggler and aborted. `GET /admin/tasks`
+//! lists every task with its state.
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::any::Any;
+use std::future::Future;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+use tokio::task::{AbortHandle, JoinHandle};
+use tokio::time::Instant;
+use tokio_util::sync::CancellationToken;
+
+pub type SharedSupervisor = Arc<Supervisor>;
+
+/// When a task is stopped during shutdown; stages stop in this order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ShutdownStage {
+    /// Tasks that bring work in (the HTTP listener), so nothing new arrives while the rest stop.
+    Ingress,
+    Worker,
+    /// Tasks that persist state, so they flush what the others did last.
+    Writer,
+}
+
+impl ShutdownStage {
+    const ORDER: [ShutdownStage; 3] = [Self::Ingress, Self::Worker, Self::Writer];
+}
+
+/// What to do when a task panics.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RestartPolicy {
+    /// Leave it stopped.
+    Never,
+    /// Start it again after `initial`, doubling the wait after each further panic up to `max`;
+    /// give up after `max_restarts` restarts, if set.
+    Backoff {
+        initial: Duration,
+        max: Duration,
+        max_restarts: Option<u32>,
+    },
+}
+
+impl Default for RestartPolicy {
+    /// Restart forever, waiting 1s and at most a minute.
+    fn default() -> Self {
+        Self::Backoff {
+            initial: Duration::from_secs(1),
+            max: Duration::from_secs(60),
+            max_restarts: None,
+        }
+    }
+}
+
+impl RestartPolicy {
+    /// How long to wait before restart number `restarts + 1`, or None to give up.
+    fn backoff(&self, restarts: u32) -> Option<Duration> {
+        match *self {
+            Self::Never => None,
+            Self::Backoff {
+                initial,
+                max,
+                max_restarts,
+            } => {
+                if max_restarts.is_some_and(|limit| restarts >= limit) {
+                    return None;
+                }
+                Some(initial.saturating_mul(1 << restarts.min(16)).min(max))
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TaskState {
+    Running,
+    /// Panicked and waiting out its backoff.
+    Restarting,
+    /// Returned on its own.
+    Finished,
+    /// Stopped when its token was cancelled.
+    Cancelled,
+    /// Panicked and its policy did not restart it.
+    Panicked,
+    /// Aborted after ignoring its token past the shutdown timeout.
+    TimedOut,
+}
+
+/// One task as `GET /admin/tasks` shows it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TaskStatus {
+    pub name: String,
+    pub stage: ShutdownStage,
+    pub state: TaskState,
+    pub restarts: u32,
+    pub last_panic: Option<String>,
+    pub started_at: DateTime<Utc>,
+}
+
+/// What `Supervisor::shutdown` saw, by task name.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ShutdownReport {
+    pub stopped: Vec<String>,
+    /// Tasks that were still running at the timeout and were aborted.
+    pub timed_out: Vec<String>,
+}
+
+/// A registered task, to wait for it to stop.
+#[derive(Debug, Clone)]
+pub struct TaskHandle {
+    done: CancellationToken,
+}
+
+impl TaskHandle {
+    /// Resolves once the task stopped for good (not between restarts).
+    pub async fn stopped(&self) {
+        self.done.cancelled().await;
+    }
+}
+
+struct Task {
+    stage: ShutdownStage,
+    token: CancellationToken,
+    status: Arc<Mutex<TaskStatus>>,
+    /// The current run, to abort a straggler.
+    current: Arc<Mutex<AbortHandle>>,
+    /// Taken by the shutdown that waits for it.
+    monitor: Option<JoinHandle<()>>,
+}
+
+#[derive(Default)]
+pub struct Supervisor {
+    tasks: Mutex<Vec<Task>>,
+}
+
+impl Supervisor {
+    /// Run `task` under `name` until its token is cancelled, restarting it per `policy` when it
+    /// panics. `task` is called once per run, the first time before this returns, so whatever
+    /// it does before its future starts (subscribing to a channel, say) is in place already.
+    pub fn spawn<F, Fut>(
+        &self,
+        name: &str,
+        stage: ShutdownStage,
+        policy: RestartPolicy,
+        task: F,
+    ) -> TaskHandle
+    where
+        F: Fn(CancellationToken) -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let token = CancellationToken::new();
+        let done = CancellationToken::new();
+        let status = Arc::new(Mutex::new(TaskStatus {
+            name: name.to_string(),
+            stage,
+            state: TaskState::Running,
+            restarts: 0,
+            last_panic: None,
+            started_at: Utc::now(),
+        }));
+        let first = tokio::spawn(task(token.clone()));
+        let current = Arc::new(Mutex::new(first.abort_handle()));
+        let monitor = tokio::spawn(supervise(
+            name.to_string(),
+            policy,
+            task,
+            first,
+            token.clone(),
+            status.clone(),
+            current.clone(),
+            done.clone(),
+        ));
+        lock(&self.tasks).push(Task {
+            stage,
+            token,
+            status,
+            current,
+            monitor: Some(monitor),
+        });
+        TaskHandle { done }
+    }
+
+    /// Every registered task, in registration order.
+    pub fn statuses(&self) -> Vec<TaskStatus> {
+        lock(&self.tasks)
+            .iter()
+            .map(|task| lock(&task.status).clone())
+            .collect()
+    }
+
+    /// Cancel the tasks stage by stage and wait up to `timeout` for each stage to stop,
+    /// aborting (and logging) the tasks still running then.
+    pub async fn shutdown(&self, timeout: Duration) -> ShutdownReport {
+        let mut report = ShutdownReport::default();
+        for stage in ShutdownStage::ORDER {
+            let staged: Vec<_> = lock(&self.tasks)
+                .iter_mut()
+                .filter(|task| task.stage == stage)
+                .filter_map(|task| {
+                    task.token.cancel();
+                    let monitor = task.monitor.take()?;
+                    Some((monitor, task.status.clone(), task.current.clone()))
+                })
+                .collect();
+            let deadline = Instant::now() + timeout;
+            for (monitor, status, current) in staged {
+                let name = lock(&status).name.clone();
+                if tokio::time::timeout_at(deadline, monitor).await.is_ok() {
+                    report.stopped.push(name);
+                    continue;
+                }
+                eprintln!(
+                    "task {} did not stop within {:?} of shutdown; aborting it",
+                    name, timeout
+                );
+                lock(&status).state = TaskState::TimedOut;
+                lock(&current).abort();
+                report.timed_out.push(name);
+            }
+        }
+        report
+    }
+}
+
+/// Await each run of a task, restarting it after a panic while `policy` allows.
+#[allow(clippy::too_many_arguments)]
+async fn supervise<F, Fut>(
+    name: String,
+    policy: RestartPolicy,
+    task: F,
+    mut run: JoinHandle<()>,
+    token: CancellationToken,
+    status: Arc<Mutex<TaskStatus>>,
+    current: Arc<Mutex<AbortHandle>>,
+    done: CancellationToken,
+) where
+    F: Fn(CancellationToken) -> Fut,
+    Fut: Future<Output = ()> + Send + 'static,
+{
+    let _done = done.drop_guard();
+    loop {
+        let panic = match run.await {
+            Ok(()) => {
+                lock(&status).state = if token.is_cancelled() {
+                    TaskState::Cancelled
+                } else {
+                    TaskState::Finished
+                };
+                return;
+            }
+            // Aborted by a shutdown, which recorded it
+            Err(e) if e.is_cancelled() => return,
+            Err(e) => panic_message(e.into_panic()),
+        };
+        eprintln!("task {} panicked: {}", name, panic);
+        let restarts = {
+            let mut status = lock(&status);
+            status.last_panic = Some(panic);
+            status.restarts
+        };
+        let Some(wait) = policy.backoff(restarts).filter(|_| !token.is_cancelled()) else {
+            lock(&status).state = TaskState::Panicked;
+            return;
+        };
+        lock(&status).state = TaskState::Restarting;
+        if token
+            .run_until_cancelled(tokio::time::sleep(wait))
+            .await
+            .is_none()
+        {
+            lock(&status).state = TaskState::Cancelled;
+            return;
+        }
+        eprintln!("restarting task {} after {:?}", name, wait);
+        {
+            let mut status = lock(&status);
+            status.state = TaskState::Running;
+            status.restarts += 1;
+            status.started_at = Utc::now();
+        }
+        run = tokio::spawn(task(token.clone()));
+        *lock(&current) = run.abort_handle();
+    }
+}
+
+fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "(non-string panic payload)".to_string()
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
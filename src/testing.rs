@@ -0,0 +1,656 @@
+//! HTTP test harness (feature `test-util`): an in-memory exchange on a random local port,
+//! with typed helpers for the common requests and builders to pre-seed books, users and
+//! positions. The crate's own integration tests use it, and so can crates embedding it.
+//!
+//! ```ignore
+//! let exchange = TestExchange::start().await;
+//! let alice = exchange.register("alice", "secret").await;
+//! let report = exchange
+//!     .place_order(&alice, &OrderRequest::limit("BTCUSDT", OrderSide::Buy, 100, 1))
+//!     .await
+//!     .unwrap();
+//! ```
+//!
+//! Helpers panic on transport errors and unexpected response shapes, as a test would; API
+//! errors the caller may want to assert on come back as `ApiError`.
+
+use chrono::Utc;
+use futures_util::{SinkExt, StreamExt};
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::postgres::{PgConnectOptions, PgPoolOptions};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::TcpStream;
+use tokio::sync::RwLock;
+use tokio::task::JoinHandle;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::{MaybeTlsStream, WebSocketStream};
+use uuid::Uuid;
+
+use crate::api::auth::{self, AuthUserCredential};
+use crate::api::dto::{ExecutionReportDto, OrderDto, PositionDto, UserTradeDto};
+use crate::api::pagination::Page;
+use crate::api::routes::{AppState, app_router};
+use crate::engine::Exchange;
+use crate::orderbook::clock::SharedClock;
+use crate::orderbook::orderbook::OrderBook;
+use crate::persistence::{self, PgPool};
+use crate::types::order::{OrderSide, OrderType, Price, Qty, RejectReason};
+use crate::types::position::Position;
+
+/// Secret the harness signs tokens with.
+pub const TEST_JWT_SECRET: &[u8] = b"test-jwt-secret";
+
+/// Symbol every harness exchange trades unless others are added.
+pub const TEST_SYMBOL: &str = "BTCUSDT";
+
+/// How long `WsClient::next_json` waits for a frame.
+const WS_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// In-memory state (no database) with an empty `TEST_SYMBOL` book.
+pub fn test_app_state() -> AppState {
+    let mut state = Exchange::in_memory(&[TEST_SYMBOL]).into_state();
+    state.jwt_secret = TEST_JWT_SECRET.to_vec();
+    state
+}
+
+/// `test_app_state` persisting to `db`.
+pub fn db_app_state(db: PgPool) -> AppState {
+    let mut state = test_app_state();
+    state.db = Some(db);
+    state
+}
+
+/// Pool confined to a fresh, migrated schema named after `prefix`, or None (logged, so the
+/// caller can skip) when `TEST_DATABASE_URL` is not set. Each call migrates its own schema,
+/// so database tests run in parallel on one server.
+pub async fn schema_pool(prefix: &str) -> Option<PgPool> {
+    let Ok(url) = std::env::var("TEST_DATABASE_URL") else {
+        eprintln!("TEST_DATABASE_URL is not set; skipping");
+        return None;
+    };
+    let options = PgConnectOptions::from_str(&url).expect("parse TEST_DATABASE_URL");
+    let schema = format!("{}_{}", prefix, Uuid::new_v4().simple());
+    let admin = PgPoolOptions::new()
+        .max_connections(1)
+        .connect_with(options.clone())
+        .await
+        .expect("connect to TEST_DATABASE_URL");
+    sqlx::query(&format!("CREATE SCHEMA {}", schema))
+        .execute(&admin)
+        .await
+        .expect("create test schema");
+    let pool = PgPoolOptions::new()
+        .max_connections(5)
+        .connect_with(options.options([("search_path", schema.as_str())]))
+        .await
+        .expect("connect to test schema");
+    persistence::run_migrations(&pool)
+        .await
+        .expect("run migrations");
+    Some(pool)
+}
+
+/// A fresh, migrated database of its own and its URL, for code that connects by itself (a
+/// `schema_pool`'s search path does not carry over to a new connection), or None when
+/// `TEST_DATABASE_URL` is not set.
+pub async fn test_database() -> Option<(PgPool, String)> {
+    let url = std::env::var("TEST_DATABASE_URL").ok()?;
+    let options = PgConnectOptions::from_str(&url).expect("parse TEST_DATABASE_URL");
+    let admin = PgPoolOptions::new()
+        .max_connections(1)
+        .connect_with(options.clone())
+        .await
+        .expect("connect to TEST_DATABASE_URL");
+    let name = format!("exchange_test_{}", Uuid::new_v4().simple());
+    sqlx::query(&format!("CREATE DATABASE {}", name))
+        .execute(&admin)
+        .await
+        .expect("create test database");
+    let pool = PgPoolOptions::new()
+        .max_connections(5)
+        .connect_with(options.database(&name))
+        .await
+        .expect("connect to test database");
+    persistence::run_migrations(&pool)
+        .await
+        .expect("run migrations");
+    // The server URL with the test database's name as its path
+    let scheme_end = url.find("://").map_or(0, |i| i + 3);
+    let server_end = url[scheme_end..]
+        .find(['/', '?'])
+        .map_or(url.len(), |i| scheme_end + i);
+    let params = url[server_end..]
+        .find('?')
+        .map_or("", |i| &url[server_end + i..]);
+    let database_url = format!("{}/{}{}", &url[..server_end], name, params);
+    Some((pool, database_url))
+}
+
+/// A logged-in user.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Token {
+    pub user_id: Uuid,
+    /// Bearer token for `Authorization`.
+    pub token: String,
+}
+
+/// Body of `POST /orders`.
+#[derive(Debug, Clone, Serialize)]
+pub struct OrderRequest {
+    pub symbol: String,
+    pub side: OrderSide,
+    pub order_type: OrderType,
+    pub price: Price,
+    pub quantity: Qty,
+}
+
+impl OrderRequest {
+    pub fn limit(symbol: &str, side: OrderSide, price: Price, quantity: Qty) -> Self {
+        Self {
+            symbol: symbol.to_string(),
+            side,
+            order_type: OrderType::Limit,
+            price,
+            quantity,
+        }
+    }
+
+    pub fn market(symbol: &str, side: OrderSide, quantity: Qty) -> Self {
+        Self {
+            order_type: OrderType::Market,
+            price: 0,
+            ..Self::limit(symbol, side, 0, quantity)
+        }
+    }
+}
+
+/// A non-success response: its status and JSON body (`Null` if it had none).
+#[derive(Debug, Clone)]
+pub struct ApiError {
+    pub status: StatusCode,
+    pub body: Value,
+}
+
+impl ApiError {
+    /// The order rejection the body's `error_code` names, if it is one.
+    pub fn rejection(&self) -> Option<RejectReason> {
+        serde_json::from_value(self.body["error_code"].clone()).ok()
+    }
+}
+
+/// Aggregated levels from `GET /book`, best first.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct BookView {
+    pub bids: Vec<(Price, Qty)>,
+    pub asks: Vec<(Price, Qty)>,
+}
+
+struct SeedUser {
+    username: String,
+    password: String,
+    user_id: Uuid,
+}
+
+struct SeedOrder {
+    symbol: String,
+    username: String,
+    side: OrderSide,
+    price: Price,
+    quantity: Qty,
+}
+
+struct SeedPosition {
+    username: String,
+    symbol: String,
+    quantity: i64,
+    average_price: Price,
+}
+
+/// Pre-seeds a `TestExchange` before it starts.
+pub struct TestExchangeBuilder {
+    state: AppState,
+    users: Vec<SeedUser>,
+    orders: Vec<SeedOrder>,
+    positions: Vec<SeedPosition>,
+    admin_id: Option<Uuid>,
+}
+
+impl TestExchangeBuilder {
+    /// Add an empty book for `symbol`.
+    pub fn symbol(mut self, symbol: &str) -> Self {
+        self.state.orderbooks.insert(
+            symbol.to_string(),
+            Arc::new(RwLock::new(OrderBook::with_clock(self.state.clock.clone()))),
+        );
+        self
+    }
+
+    /// Run handlers, background tasks and every book on `clock` (e.g. a `ManualClock` to step
+    /// through expiry deadlines and candle boundaries).
+    pub fn clock(mut self, clock: SharedClock) -> Self {
+        for book in self.state.orderbooks.values() {
+            book.try_write()
+                .expect("books are not shared before start")
+                .set_clock(clock.clone());
+        }
+        self.state.clock = clock;
+        self
+    }
+
+    /// Adjust the state directly (caps, configs, admin ids, ...).
+    pub fn with_state(mut self, configure: impl FnOnce(&mut AppState)) -> Self {
+        configure(&mut self.state);
+        self
+    }
+
+    /// Add an admin, who has no login; `TestExchange::admin_token` signs its requests.
+    pub fn admin(mut self) -> Self {
+        let admin_id = Uuid::new_v4();
+        self.state.admin_user_ids.insert(admin_id);
+        self.admin_id = Some(admin_id);
+        self
+    }
+
+    /// Seed a user who can log in with `password`.
+    pub fn user(mut self, username: &str, password: &str) -> Self {
+        self.users.push(SeedUser {
+            username: username.to_string(),
+            password: password.to_string(),
+            user_id: Uuid::new_v4(),
+        });
+        self
+    }
+
+    /// Seed a limit order of a seeded user. Seeds go straight into the book (no positions or
+    /// broadcasts), so give them prices that do not cross.
+    pub fn order(
+        mut self,
+        symbol: &str,
+        username: &str,
+        side: OrderSide,
+        price: Price,
+        quantity: Qty,
+    ) -> Self {
+        self.orders.push(SeedOrder {
+            symbol: symbol.to_string(),
+            username: username.to_string(),
+            side,
+            price,
+            quantity,
+        });
+        self
+    }
+
+    /// Seed a position of a seeded user.
+    pub fn position(
+        mut self,
+        username: &str,
+        symbol: &str,
+        quantity: i64,
+        average_price: Price,
+    ) -> Self {
+        self.positions.push(SeedPosition {
+            username: username.to_string(),
+            symbol: symbol.to_string(),
+            quantity,
+            average_price,
+        });
+        self
+    }
+
+    /// Apply the seeds and serve the exchange.
+    pub async fn start(self) -> TestExchange {
+        let state = self.state;
+        for (symbol, config) in state.symbol_configs.read().await.iter() {
+            if let Some(orderbook) = state.orderbooks.get(symbol) {
+                orderbook
+                    .write()
+                    .await
+                    .set_pricing_policy(config.pricing_policy, config.tick_size);
+            }
+        }
+        let mut user_ids = HashMap::new();
+        {
+            let mut store = state.user_store.write().await;
+            for user in &self.users {
+                let password_hash = auth::hash_password(&user.password).expect("hash password");
+                store.insert(
+                    user.username.to_lowercase(),
+                    AuthUserCredential::new(
+                        user.user_id,
+                        user.username.clone(),
+                        password_hash,
+                        Utc::now(),
+                    ),
+                );
+                user_ids.insert(user.username.clone(), user.user_id);
+            }
+        }
+        let user_id = |username: &str| {
+            *user_ids
+                .get(username)
+                .unwrap_or_else(|| panic!("seed references unknown user '{}'", username))
+        };
+        for order in &self.orders {
+            let orderbook = state
+                .orderbooks
+                .get(&order.symbol)
+                .unwrap_or_else(|| panic!("seed references unknown symbol '{}'", order.symbol));
+            orderbook.write().await.add_order(
+                user_id(&order.username),
+                order.price,
+                order.quantity,
+                order.side,
+                OrderType::Limit,
+                None,
+                None,
+            );
+        }
+        {
+            let mut positions = state.positions.write().await;
+            for position in &self.positions {
+                let user_id = user_id(&position.username);
+                positions.insert(
+                    (user_id, position.symbol.clone()),
+                    Position {
+                        user_id,
+                        symbol: position.symbol.clone(),
+                        quantity: position.quantity,
+                        average_price: position.average_price,
+                        opened_at: Utc::now(),
+                        updated_at: Utc::now(),
+                    },
+                );
+            }
+        }
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("bind test listener");
+        let addr = listener.local_addr().expect("test listener address");
+        let app = app_router(state.clone()).into_make_service_with_connect_info::<SocketAddr>();
+        let handle = tokio::spawn(async move {
+            axum::serve(listener, app)
+                .await
+                .expect("serve test exchange");
+        });
+        TestExchange {
+            base_url: format!("http://{}", addr),
+            addr,
+            state,
+            client: reqwest::Client::new(),
+            handle,
+            admin_id: self.admin_id,
+        }
+    }
+}
+
+/// A running exchange; the server stops when this is dropped.
+pub struct TestExchange {
+    pub base_url: String,
+    pub addr: SocketAddr,
+    /// The served state, shared with the server (inspect or adjust it directly).
+    pub state: AppState,
+    client: reqwest::Client,
+    handle: JoinHandle<()>,
+    admin_id: Option<Uuid>,
+}
+
+impl Drop for TestExchange {
+    fn drop(&mut self) {
+        self.handle.abort();
+    }
+}
+
+impl TestExchange {
+    /// An exchange with the default state and nothing seeded.
+    pub async fn start() -> Self {
+        Self::builder().start().await
+    }
+
+    pub fn builder() -> TestExchangeBuilder {
+        Self::builder_with_state(test_app_state())
+    }
+
+    /// Seed and serve `state` instead of the default one.
+    pub fn builder_with_state(state: AppState) -> TestExchangeBuilder {
+        TestExchangeBuilder {
+            state,
+            users: Vec::new(),
+            orders: Vec::new(),
+            positions: Vec::new(),
+            admin_id: None,
+        }
+    }
+
+    pub fn client(&self) -> &reqwest::Client {
+        &self.client
+    }
+
+    /// A token for the admin added with `TestExchangeBuilder::admin`, issued on the exchange's
+    /// clock.
+    pub fn admin_token(&self) -> Token {
+        let admin_id = self
+            .admin_id
+            .expect("the exchange was built with TestExchangeBuilder::admin");
+        Token {
+            user_id: admin_id,
+            token: auth::create_token(&self.state.jwt_secret, admin_id, self.state.clock.now())
+                .expect("sign admin token"),
+        }
+    }
+
+    pub fn url(&self, path: &str) -> String {
+        format!("{}{}", self.base_url, path)
+    }
+
+    /// Register `username`, panicking unless it succeeds, then log in.
+    pub async fn register(&self, username: &str, password: &str) -> Token {
+        let res = self
+            .client
+            .post(self.url("/auth/register"))
+            .json(&serde_json::json!({ "username": username, "password": password }))
+            .send()
+            .await
+            .expect("register request");
+        assert_eq!(
+            res.status(),
+            StatusCode::CREATED,
+            "register {}: {:?}",
+            username,
+            res.text().await
+        );
+        self.login(username, password)
+            .await
+            .unwrap_or_else(|e| panic!("login {} after registering: {:?}", username, e))
+    }
+
+    pub async fn login(&self, username: &str, password: &str) -> Result<Token, ApiError> {
+        let json = self
+            .send_json(
+                self.client
+                    .post(self.url("/auth/login"))
+                    .json(&serde_json::json!({ "username": username, "password": password })),
+            )
+            .await?;
+        Ok(Token {
+            user_id: json["user_id"]
+                .as_str()
+                .and_then(|id| Uuid::parse_str(id).ok())
+                .expect("login returns user_id"),
+            token: json["token"]
+                .as_str()
+                .expect("login returns token")
+                .to_string(),
+        })
+    }
+
+    /// Submit an order. The report carries the order after matching and the trades it took
+    /// as taker; rejected orders come back as `ApiError` (see `ApiError::rejection`).
+    pub async fn place_order(
+        &self,
+        token: &Token,
+        request: &OrderRequest,
+    ) -> Result<ExecutionReportDto, ApiError> {
+        let json = self
+            .send_json(
+                self.client
+                    .post(self.url("/orders"))
+                    .bearer_auth(&token.token)
+                    .json(request),
+            )
+            .await?;
+        let order: OrderDto = serde_json::from_value(json).expect("order response");
+        let trades = self
+            .trades_me(token, Some(&request.symbol))
+            .await?
+            .into_iter()
+            .map(|t| t.trade)
+            .filter(|t| t.taker_order_id == order.id)
+            .rev()
+            .collect();
+        Ok(ExecutionReportDto {
+            order,
+            trades,
+            rejection: None,
+        })
+    }
+
+    /// `DELETE /orders/{id}`; the cancelled order as the server returns it.
+    pub async fn cancel_order(
+        &self,
+        token: &Token,
+        symbol: &str,
+        order_id: Uuid,
+    ) -> Result<Value, ApiError> {
+        self.send_json(
+            self.client
+                .delete(self.url(&format!("/orders/{}?symbol={}", order_id, symbol)))
+                .bearer_auth(&token.token),
+        )
+        .await
+    }
+
+    /// `GET /book` for `symbol`.
+    pub async fn book(&self, symbol: &str) -> BookView {
+        let json = self
+            .send_json(
+                self.client
+                    .get(self.url(&format!("/book?symbol={}", symbol))),
+            )
+            .await
+            .unwrap_or_else(|e| panic!("book {}: {:?}", symbol, e));
+        serde_json::from_value(json).expect("book response")
+    }
+
+    /// The first page of `GET /trades/me`, newest first.
+    pub async fn trades_me(
+        &self,
+        token: &Token,
+        symbol: Option<&str>,
+    ) -> Result<Vec<UserTradeDto>, ApiError> {
+        let path = match symbol {
+            Some(symbol) => format!("/trades/me?symbol={}", symbol),
+            None => "/trades/me".to_string(),
+        };
+        let json = self.get(token, &path).await?;
+        let page: Page<UserTradeDto> = serde_json::from_value(json).expect("trades response");
+        Ok(page.items)
+    }
+
+    /// `GET /positions`.
+    pub async fn positions(&self, token: &Token) -> Result<Vec<PositionDto>, ApiError> {
+        let json = self.get(token, "/positions").await?;
+        Ok(serde_json::from_value(json).expect("positions response"))
+    }
+
+    /// Authenticated `GET` of `path`, as JSON.
+    pub async fn get(&self, token: &Token, path: &str) -> Result<Value, ApiError> {
+        self.send_json(self.client.get(self.url(path)).bearer_auth(&token.token))
+            .await
+    }
+
+    /// Send `request`; the JSON body on success, else an `ApiError`.
+    pub async fn send_json(&self, request: reqwest::RequestBuilder) -> Result<Value, ApiError> {
+        let res = request.send().await.expect("request to test exchange");
+        let status = res.status();
+        let body = res.json().await.unwrap_or(Value::Null);
+        if status.is_success() {
+            Ok(body)
+        } else {
+            Err(ApiError { status, body })
+        }
+    }
+
+    /// Open a WebSocket, authenticated when a token is given.
+    pub async fn ws_client(&self, token: Option<&Token>) -> WsClient {
+        let url = match token {
+            Some(token) => format!("ws://{}/ws?token={}", self.addr, token.token),
+            None => format!("ws://{}/ws", self.addr),
+        };
+        let (socket, _) = tokio_tungstenite::connect_async(url)
+            .await
+            .expect("connect WebSocket");
+        WsClient { socket }
+    }
+}
+
+/// A WebSocket connection to a `TestExchange`.
+pub struct WsClient {
+    socket: WebSocketStream<MaybeTlsStream<TcpStream>>,
+}
+
+impl WsClient {
+    pub async fn send_json(&mut self, message: Value) {
+        self.socket
+            .send(Message::Text(message.to_string().into()))
+            .await
+            .expect("send WebSocket message");
+    }
+
+    /// Next text frame as JSON; panics if none arrives within a second.
+    pub async fn next_json(&mut self) -> Value {
+        loop {
+            let message = tokio::time::timeout(WS_TIMEOUT, self.socket.next())
+                .await
+                .expect("WebSocket message within timeout")
+                .expect("WebSocket open")
+                .expect("WebSocket frame");
+            if let Message::Text(text) = message {
+                return serde_json::from_str(&text).expect("WebSocket JSON");
+            }
+        }
+    }
+
+    /// Next message of `type`, skipping others; panics if none arrives in time.
+    pub async fn next_of_type(&mut self, message_type: &str) -> Value {
+        loop {
+            let message = self.next_json().await;
+            if message["type"] == message_type {
+                return message;
+            }
+        }
+    }
+
+    /// Subscribe to a symbol's public channel and wait for the acknowledgement and the
+    /// `ExchangeInfo` that follows it.
+    pub async fn subscribe(&mut self, symbol: &str) {
+        self.send_json(serde_json::json!({ "action": "subscribe", "symbol": symbol }))
+            .await;
+        let ack = self.next_json().await;
+        assert_eq!(ack["status"], "success", "subscribe {}: {}", symbol, ack);
+        let info = self.next_json().await;
+        assert_eq!(info["type"], "ExchangeInfo", "subscribe {}: {}", symbol, info);
+    }
+
+    /// The underlying stream, for frames the helpers do not cover.
+    pub fn socket(&mut self) -> &mut WebSocketStream<MaybeTlsStream<TcpStream>> {
+        &mut self.socket
+    }
+}
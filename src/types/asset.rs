@@ -0,0 +1,144 @@
+//! Assets and the registry of the ones the exchange knows. A symbol trades a base asset
+//! against a quote asset (`ETHBTC`: base ETH, quote BTC); both are set on its `SymbolConfig`
+//! when the symbol is created and must be registered here. Balances, locks, fees and
+//! notional amounts are denominated in them.
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+
+/// Longest asset code, in characters.
+pub const MAX_ASSET_LEN: usize = 10;
+
+/// An asset code: uppercase ASCII letters and digits, at most `MAX_ASSET_LEN` of them. Held
+/// inline so configs that carry it stay `Copy`.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Asset {
+    len: u8,
+    code: [u8; MAX_ASSET_LEN],
+}
+
+impl Asset {
+    /// Parse `raw`, trimmed and uppercased. The asset need not be registered.
+    pub fn parse(raw: &str) -> Result<Self, String> {
+        let trimmed = raw.trim();
+        if trimmed.is_empty() {
+            return Err("Asset is required".to_string());
+        }
+        if let Some(c) = trimmed.chars().find(|c| !c.is_ascii_alphanumeric()) {
+            return Err(format!(
+                "Asset '{}' contains '{}'; only letters and digits are allowed",
+                trimmed, c
+            ));
+        }
+        if trimmed.len() > MAX_ASSET_LEN {
+            return Err(format!(
+                "Asset '{}' is longer than {} characters",
+                trimmed, MAX_ASSET_LEN
+            ));
+        }
+        let mut code = [0; MAX_ASSET_LEN];
+        for (slot, byte) in code.iter_mut().zip(trimmed.bytes()) {
+            *slot = byte.to_ascii_uppercase();
+        }
+        Ok(Self {
+            len: trimmed.len() as u8,
+            code,
+        })
+    }
+
+    pub fn as_str(&self) -> &str {
+        std::str::from_utf8(&self.code[..self.len as usize]).expect("asset codes are ASCII")
+    }
+
+    /// The registry entry, if the asset is registered.
+    pub fn info(&self) -> Option<&'static AssetInfo> {
+        asset_info(self.as_str())
+    }
+}
+
+impl fmt::Display for Asset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl fmt::Debug for Asset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Asset({})", self.as_str())
+    }
+}
+
+impl Serialize for Asset {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for Asset {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let raw = String::deserialize(deserializer)?;
+        Self::parse(&raw).map_err(serde::de::Error::custom)
+    }
+}
+
+/// A registered asset.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct AssetInfo {
+    pub code: &'static str,
+    /// Units per whole asset when amounts are shown, a power of ten (1e8 shows 8 decimals).
+    pub display_scale: u64,
+}
+
+/// Every asset symbols may be created with.
+pub const ASSETS: &[AssetInfo] = &[
+    AssetInfo {
+        code: "BTC",
+        display_scale: 100_000_000,
+    },
+    AssetInfo {
+        code: "DOGE",
+        display_scale: 100_000_000,
+    },
+    AssetInfo {
+        code: "ETH",
+        display_scale: 100_000_000,
+    },
+    AssetInfo {
+        code: "EUR",
+        display_scale: 100,
+    },
+    AssetInfo {
+        code: "SOL",
+        display_scale: 1_000_000_000,
+    },
+    AssetInfo {
+        code: "USD",
+        display_scale: 100,
+    },
+    AssetInfo {
+        code: "USDC",
+        display_scale: 1_000_000,
+    },
+    AssetInfo {
+        code: "USDT",
+        display_scale: 1_000_000,
+    },
+];
+
+pub fn asset_info(code: &str) -> Option<&'static AssetInfo> {
+    ASSETS.iter().find(|info| info.code == code)
+}
+
+/// `(base, quote)` read off a symbol's name, e.g. `(ETH, BTC)` for `ETHBTC`: a registered
+/// quote suffix after a registered base, the longest quote winning where several fit. Used to
+/// set the assets of symbols created by name; None when the name is not two registered assets.
+pub fn split_symbol(symbol: &str) -> Option<(Asset, Asset)> {
+    ASSETS
+        .iter()
+        .filter_map(|quote| {
+            let base = symbol.strip_suffix(quote.code)?;
+            asset_info(base)?;
+            Some((Asset::parse(base).ok()?, Asset::parse(quote.code).ok()?))
+        })
+        .max_by_key(|(_, quote)| quote.as_str().len())
+}
@@ -0,0 +1,150 @@
+//! Decimal strings at the API boundary. Amounts are scaled integers inside the exchange (a
+//! quantity of 0.001 BTC with a `qty_scale` of 1e8 is 100_000 lots); clients may send and
+//! read them as decimal strings, converted here exactly or not at all: a string with more
+//! decimal places than the scale allows is rejected rather than rounded.
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+use crate::types::order::Qty;
+
+/// Largest scale accepted: 18 decimal places.
+pub const MAX_SCALE: u64 = 1_000_000_000_000_000_000;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DecimalError {
+    Empty,
+    /// Not a plain decimal: digits with at most one `.`, optionally signed.
+    Invalid(String),
+    /// More decimal places than `scale` represents.
+    TooPrecise {
+        raw: String,
+        scale: u64,
+    },
+    /// Does not fit the target type (or is negative where that is not allowed).
+    OutOfRange(String),
+}
+
+impl fmt::Display for DecimalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Empty => write!(f, "Decimal value is empty"),
+            Self::Invalid(raw) => write!(f, "'{}' is not a decimal number", raw),
+            Self::TooPrecise { raw, scale } => write!(
+                f,
+                "'{}' has more than {} decimal places",
+                raw,
+                decimal_places(*scale)
+            ),
+            Self::OutOfRange(raw) => write!(f, "'{}' is out of range", raw),
+        }
+    }
+}
+
+impl std::error::Error for DecimalError {}
+
+/// Whether `scale` is a power of ten no larger than `MAX_SCALE`.
+pub fn is_valid_scale(scale: u64) -> bool {
+    let mut rest = scale;
+    while rest >= 10 && rest.is_multiple_of(10) {
+        rest /= 10;
+    }
+    rest == 1 && scale <= MAX_SCALE
+}
+
+/// Decimal places a scale represents: 8 for 1e8.
+pub fn decimal_places(scale: u64) -> usize {
+    scale.max(1).ilog10() as usize
+}
+
+/// `raw` (e.g. `"-12.5"`) times `scale`, exactly. Trailing zeros past the scale's places are
+/// fine; any other digit there is `TooPrecise`.
+pub fn parse_scaled(raw: &str, scale: u64) -> Result<i128, DecimalError> {
+    let trimmed = raw.trim();
+    if trimmed.is_empty() {
+        return Err(DecimalError::Empty);
+    }
+    let invalid = || DecimalError::Invalid(trimmed.to_string());
+    let (negative, digits) = match trimmed.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, trimmed.strip_prefix('+').unwrap_or(trimmed)),
+    };
+    let (whole, fraction) = digits.split_once('.').unwrap_or((digits, ""));
+    if (whole.is_empty() && fraction.is_empty())
+        || !whole
+            .bytes()
+            .chain(fraction.bytes())
+            .all(|b| b.is_ascii_digit())
+    {
+        return Err(invalid());
+    }
+    let places = decimal_places(scale);
+    let fraction = fraction.trim_end_matches('0');
+    if fraction.len() > places {
+        return Err(DecimalError::TooPrecise {
+            raw: trimmed.to_string(),
+            scale,
+        });
+    }
+    let out_of_range = || DecimalError::OutOfRange(trimmed.to_string());
+    let whole: i128 = if whole.is_empty() {
+        0
+    } else {
+        whole.parse().map_err(|_| out_of_range())?
+    };
+    let fraction = if fraction.is_empty() {
+        0
+    } else {
+        // Pad to the scale's places: "5" of 8 places is 50_000_000
+        fraction.parse::<i128>().map_err(|_| invalid())?
+            * 10i128.pow((places - fraction.len()) as u32)
+    };
+    let value = whole
+        .checked_mul(scale as i128)
+        .and_then(|v| v.checked_add(fraction))
+        .ok_or_else(out_of_range)?;
+    Ok(if negative { -value } else { value })
+}
+
+/// `value / scale` as a decimal string without trailing zeros: 100_000 at 1e8 is `"0.001"`.
+pub fn format_scaled(value: i128, scale: u64) -> String {
+    let scale = scale.max(1) as u128;
+    let sign = if value < 0 { "-" } else { "" };
+    let magnitude = value.unsigned_abs();
+    let (whole, fraction) = (magnitude / scale, magnitude % scale);
+    if fraction == 0 {
+        return format!("{}{}", sign, whole);
+    }
+    let places = decimal_places(scale as u64);
+    let fraction = format!("{:0width$}", fraction, width = places);
+    format!("{}{}.{}", sign, whole, fraction.trim_end_matches('0'))
+}
+
+/// An order quantity as sent: a JSON number is already in lots (as before decimals were
+/// accepted), a string is a decimal amount of the base asset to scale by the symbol's
+/// `qty_scale`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum QuantityInput {
+    Lots(Qty),
+    Decimal(String),
+}
+
+impl QuantityInput {
+    /// The quantity in lots of `1 / qty_scale`.
+    pub fn to_lots(&self, qty_scale: u64) -> Result<Qty, DecimalError> {
+        match self {
+            Self::Lots(lots) => Ok(*lots),
+            Self::Decimal(raw) => {
+                let scaled = parse_scaled(raw, qty_scale)?;
+                Qty::try_from(scaled).map_err(|_| DecimalError::OutOfRange(raw.trim().to_string()))
+            }
+        }
+    }
+}
+
+impl From<Qty> for QuantityInput {
+    fn from(lots: Qty) -> Self {
+        Self::Lots(lots)
+    }
+}
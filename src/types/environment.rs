@@ -0,0 +1,82 @@
+//! Trading environments. One process can run the real (`live`) books and an isolated
+//! `paper` copy of each symbol for staging: a paper book's key is the symbol with an
+//! `@paper` suffix (`BTCUSDT@paper`), and everything keyed by symbol — the book, positions,
+//! trades, configs, WebSocket channels and persisted rows — is therefore kept apart by it.
+//! Live keys carry no suffix, so existing symbols are unchanged.
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+/// Separates a symbol from its environment in a book key.
+pub const ENVIRONMENT_SEPARATOR: char = '@';
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Environment {
+    /// The real books.
+    #[default]
+    Live,
+    /// Isolated books for staging, optionally trading auto-funded balances.
+    Paper,
+}
+
+impl Environment {
+    pub const ALL: [Environment; 2] = [Environment::Live, Environment::Paper];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Environment::Live => "live",
+            Environment::Paper => "paper",
+        }
+    }
+
+    /// Parse a name, ignoring case.
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "live" => Some(Environment::Live),
+            "paper" => Some(Environment::Paper),
+            _ => None,
+        }
+    }
+
+    /// The environment of a book key: the one after `ENVIRONMENT_SEPARATOR`, live without.
+    pub fn of(key: &str) -> Self {
+        split_key(key).1
+    }
+
+    /// The key of `symbol`'s book in this environment.
+    pub fn book_key(self, symbol: &str) -> String {
+        match self {
+            Environment::Live => symbol.to_string(),
+            _ => format!("{}{}{}", symbol, ENVIRONMENT_SEPARATOR, self.as_str()),
+        }
+    }
+}
+
+/// A book key as its symbol and environment. A suffix naming no environment is left on the
+/// symbol; `Symbol::parse` never produces one.
+pub fn split_key(key: &str) -> (&str, Environment) {
+    key.split_once(ENVIRONMENT_SEPARATOR)
+        .and_then(|(symbol, suffix)| Some((symbol, Environment::parse(suffix)?)))
+        .unwrap_or((key, Environment::Live))
+}
+
+impl fmt::Display for Environment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Environment {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse(s).ok_or_else(|| {
+            format!(
+                "unknown environment '{}' (expected live or paper)",
+                s.trim()
+            )
+        })
+    }
+}
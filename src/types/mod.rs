@@ -1,3 +1,9 @@
+pub mod asset;
+pub mod decimal;
+pub mod environment;
+pub mod money;
 pub mod order;
 pub mod position;
+pub mod string_i64;
+pub mod symbol;
 pub mod trade;
@@ -0,0 +1,129 @@
+//! Money math. A `Price` (i64, 1e8-scaled) times a `Qty` (u64) overflows i64 for realistic
+//! values, so products are carried as an i128 `Notional` (same scale as `Price`) and only
+//! converted back to i64 through checked conversions that report overflow. When a symbol
+//! counts quantity in lots of `1 / qty_scale` (see `SymbolConfig::qty_scale`), a quote amount
+//! is the product divided by the scale: `scaled_notional`.
+
+use serde::Serialize;
+use std::fmt;
+
+use crate::types::order::{Price, Qty};
+
+/// Price units per 1.0 of quote currency.
+pub const PRICE_SCALE: i64 = 100_000_000;
+
+/// Basis points in one whole (100%).
+pub const BPS_PER_UNIT: i128 = 10_000;
+
+/// An amount in price units (1e8-scaled). Any `Price` times any `Qty` fits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default, Serialize)]
+#[serde(transparent)]
+pub struct Notional(i128);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MoneyError {
+    /// The result does not fit the target type.
+    Overflow,
+    /// An average over zero quantity.
+    ZeroQuantity,
+}
+
+impl fmt::Display for MoneyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MoneyError::Overflow => write!(f, "amount out of range"),
+            MoneyError::ZeroQuantity => write!(f, "average over zero quantity"),
+        }
+    }
+}
+
+impl std::error::Error for MoneyError {}
+
+impl Notional {
+    pub const ZERO: Notional = Notional(0);
+
+    pub fn from_raw(raw: i128) -> Self {
+        Self(raw)
+    }
+
+    pub fn raw(self) -> i128 {
+        self.0
+    }
+
+    pub fn checked_add(self, other: Notional) -> Result<Notional, MoneyError> {
+        self.0
+            .checked_add(other.0)
+            .map(Notional)
+            .ok_or(MoneyError::Overflow)
+    }
+
+    pub fn checked_sub(self, other: Notional) -> Result<Notional, MoneyError> {
+        self.0
+            .checked_sub(other.0)
+            .map(Notional)
+            .ok_or(MoneyError::Overflow)
+    }
+
+    /// The amount as a scaled i64, or `Overflow` if it does not fit.
+    pub fn to_i64(self) -> Result<i64, MoneyError> {
+        i64::try_from(self.0).map_err(|_| MoneyError::Overflow)
+    }
+
+    /// The amount as a scaled i64, clamped to the i64 range.
+    pub fn to_i64_saturating(self) -> i64 {
+        self.0.clamp(i64::MIN as i128, i64::MAX as i128) as i64
+    }
+}
+
+impl fmt::Display for Notional {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// `price * qty`; never overflows.
+pub fn notional(price: Price, qty: Qty) -> Notional {
+    Notional(price as i128 * qty as i128)
+}
+
+/// `price * qty` for a quantity in lots of `1 / qty_scale`: the quote amount, rounded toward
+/// zero. Never overflows.
+pub fn scaled_notional(price: Price, qty: Qty, qty_scale: u64) -> Notional {
+    descale(notional(price, qty), qty_scale)
+}
+
+/// `amount`, a product of a price and a quantity in lots of `1 / qty_scale`, as a quote
+/// amount, rounded toward zero.
+pub fn descale(amount: Notional, qty_scale: u64) -> Notional {
+    Notional(amount.0 / qty_scale.max(1) as i128)
+}
+
+/// `price * qty` for a signed (position) quantity; never overflows.
+pub fn signed_notional(price: Price, qty: i64) -> Notional {
+    Notional(price as i128 * qty as i128)
+}
+
+/// `bps` basis points of `amount`, rounded toward zero (negative `bps` give a negative
+/// amount, e.g. a rebate). Never overflows for `|bps| <= 10_000`.
+pub fn apply_bps(amount: Notional, bps: i32) -> Result<Notional, MoneyError> {
+    // Split off whole units of 10_000 so `amount * bps` is never formed; quotient and
+    // remainder share the sign of `amount`, so the truncated parts add up exactly
+    let bps = bps as i128;
+    let whole = (amount.0 / BPS_PER_UNIT).checked_mul(bps);
+    let part = (amount.0 % BPS_PER_UNIT) * bps / BPS_PER_UNIT;
+    whole
+        .and_then(|whole| whole.checked_add(part))
+        .map(Notional)
+        .ok_or(MoneyError::Overflow)
+}
+
+/// `total / qty` as a price, rounded toward zero: the average price of `qty` units that
+/// cost `total`.
+pub fn average_price(total: Notional, qty: impl Into<i128>) -> Result<Price, MoneyError> {
+    let qty = qty.into();
+    if qty == 0 {
+        return Err(MoneyError::ZeroQuantity);
+    }
+    let average = total.0.checked_div(qty).ok_or(MoneyError::Overflow)?;
+    Notional(average).to_i64()
+}
@@ -1,10 +1,20 @@
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use uuid::Uuid;
 
 pub type Price = i64;
 pub type Qty = u64;
 pub type OrderId = Uuid;
+/// Free-form client labels on an order (e.g. `"strategy": "mm-v2"`); opaque to matching.
+pub type OrderTags = HashMap<String, String>;
+
+pub const MAX_ORDER_TAGS: usize = 5;
+pub const MAX_ORDER_TAG_LEN: usize = 64;
+/// Largest order quantity accepted at ingest. Quantities are stored as BIGINT and become
+/// signed position deltas, so every accepted order (and each of its fills) fits an i64.
+/// Sums over many orders can still exceed that; those saturate instead (see `total_quantity`).
+pub const MAX_ORDER_QUANTITY: Qty = i64::MAX as Qty;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum OrderSide {
@@ -12,6 +22,16 @@ pub enum OrderSide {
     Sell,
 }
 
+impl OrderSide {
+    /// The side a counterparty trades on.
+    pub fn opposite(self) -> Self {
+        match self {
+            OrderSide::Buy => OrderSide::Sell,
+            OrderSide::Sell => OrderSide::Buy,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
 pub enum OrderType {
     #[default]
@@ -27,14 +47,203 @@ pub enum OrderStatus {
     Cancelled,
 }
 
+/// Entry point that created an order. Set by the server for each route, never taken from the
+/// client; matching ignores it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, Default)]
+pub enum OrderSource {
+    /// `POST /orders`.
+    #[default]
+    Api,
+    Admin,
+    /// A leg of `POST /orders/spread`.
+    Spread,
+}
+
+/// Sum of quantities, saturating at `Qty::MAX` rather than wrapping. Used for every aggregate
+/// over many orders or trades (level totals, auction volume, candle volume), which
+/// `MAX_ORDER_QUANTITY` alone does not bound.
+pub fn total_quantity(quantities: impl IntoIterator<Item = Qty>) -> Qty {
+    quantities.into_iter().fold(0, Qty::saturating_add)
+}
+
+/// Why an order was rejected. Serialized as the API error code (e.g. `NO_LIQUIDITY`) and
+/// stored as the order's `close_reason`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum RejectReason {
+    MissingSymbol,
+    UnknownSymbol,
+    /// Market orders cannot execute while the book is in a call auction.
+    MarketOrderInAuction,
+    /// A market order found nothing to match against.
+    NoLiquidity,
+    /// Trading in the symbol is halted.
+    SymbolHalted,
+    /// The symbol is being delisted and only accepts cancels.
+    SymbolCancelOnly,
+    /// The limit price is not a multiple of the symbol's tick size.
+    InvalidTickSize,
+    /// The quantity is not a multiple of the symbol's lot size.
+    InvalidLotSize,
+    /// The limit price is outside the symbol's band around the last trade price.
+    PriceOutsideBand,
+    /// The remainder would rest past a resting order cap; it was cancelled.
+    BookFull,
+    /// The quantity is zero or above `MAX_ORDER_QUANTITY`.
+    InvalidQuantity,
+    /// The quantity is below the symbol's minimum.
+    BelowMinQuantity,
+    /// Not enough available balance to lock for the order.
+    InsufficientBalance,
+}
+
+impl RejectReason {
+    /// The error code, identical to the serialized form.
+    pub fn code(self) -> &'static str {
+        match self {
+            RejectReason::MissingSymbol => "MISSING_SYMBOL",
+            RejectReason::UnknownSymbol => "UNKNOWN_SYMBOL",
+            RejectReason::MarketOrderInAuction => "MARKET_ORDER_IN_AUCTION",
+            RejectReason::NoLiquidity => "NO_LIQUIDITY",
+            RejectReason::SymbolHalted => "SYMBOL_HALTED",
+            RejectReason::SymbolCancelOnly => "SYMBOL_CANCEL_ONLY",
+            RejectReason::InvalidTickSize => "INVALID_TICK_SIZE",
+            RejectReason::InvalidLotSize => "INVALID_LOT_SIZE",
+            RejectReason::PriceOutsideBand => "PRICE_OUTSIDE_BAND",
+            RejectReason::BookFull => "BOOK_FULL",
+            RejectReason::InvalidQuantity => "INVALID_QUANTITY",
+            RejectReason::BelowMinQuantity => "BELOW_MIN_QUANTITY",
+            RejectReason::InsufficientBalance => "INSUFFICIENT_BALANCE",
+        }
+    }
+}
+
+impl std::fmt::Display for RejectReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let message = match self {
+            RejectReason::MissingSymbol => "Symbol parameter is required",
+            RejectReason::UnknownSymbol => "Symbol not found",
+            RejectReason::MarketOrderInAuction => {
+                "Market orders are not accepted during an auction"
+            }
+            RejectReason::NoLiquidity => "Market order could not be filled: no liquidity",
+            RejectReason::SymbolHalted => "Trading in this symbol is halted",
+            RejectReason::SymbolCancelOnly => {
+                "This symbol is being delisted and only accepts cancels"
+            }
+            RejectReason::InvalidTickSize => "Price is not a multiple of the tick size",
+            RejectReason::InvalidLotSize => "Quantity is not a multiple of the lot size",
+            RejectReason::PriceOutsideBand => "Price is outside the allowed band",
+            RejectReason::BookFull => "Order book is full; the unfilled quantity was cancelled",
+            RejectReason::InvalidQuantity => "Quantity must be between 1 and 2^63 - 1",
+            RejectReason::BelowMinQuantity => "Quantity is below the minimum for this symbol",
+            RejectReason::InsufficientBalance => "Insufficient balance for the order",
+        };
+        f.write_str(message)
+    }
+}
+
+/// Why a resting order was cancelled. Serialized as the code stored in the order's
+/// `close_reason` (e.g. `CANCEL_ON_DISCONNECT`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum CloseReason {
+    /// The owner cancelled it.
+    UserCancelled,
+    /// A socket of the owner with cancel_on_disconnect enabled dropped.
+    CancelOnDisconnect,
+    /// An admin cancelled it.
+    AdminCancelled,
+    /// Open in the database but no longer in the book; closed by a reconciliation repair.
+    Reconciled,
+    /// The other leg of its spread filled, under the spread's `cancel_other` policy (or
+    /// `reduce_other` once that leg filled completely).
+    SpreadLegFilled,
+    /// The other leg of its spread could not be placed, so the spread was undone.
+    SpreadRolledBack,
+    /// Placed with `refresh_ttl_ms` and not refreshed in time.
+    TtlExpired,
+    /// Still resting when its symbol was delisted.
+    SymbolDelisted,
+    /// Broke its symbol's trading rules after they changed (see
+    /// `POST /admin/symbols/{symbol}/enforce-config`).
+    ConfigEnforcement,
+    /// The owner engaged the account kill switch.
+    KillSwitch,
+}
+
+impl CloseReason {
+    /// The stored code, identical to the serialized form.
+    pub fn code(self) -> &'static str {
+        match self {
+            CloseReason::UserCancelled => "USER_CANCELLED",
+            CloseReason::CancelOnDisconnect => "CANCEL_ON_DISCONNECT",
+            CloseReason::AdminCancelled => "ADMIN_CANCELLED",
+            CloseReason::Reconciled => "RECONCILED",
+            CloseReason::SpreadLegFilled => "SPREAD_LEG_FILLED",
+            CloseReason::SpreadRolledBack => "SPREAD_ROLLED_BACK",
+            CloseReason::TtlExpired => "TTL_EXPIRED",
+            CloseReason::SymbolDelisted => "SYMBOL_DELISTED",
+            CloseReason::ConfigEnforcement => "CONFIG_ENFORCEMENT",
+            CloseReason::KillSwitch => "KILL_SWITCH",
+        }
+    }
+
+    /// Whether the owner learns of the cancel only from an `OrderClosed` message, because
+    /// someone else asked for it or, for the kill switch, one request closed orders on
+    /// every book.
+    pub fn notifies_owner(self) -> bool {
+        self != CloseReason::UserCancelled
+    }
+}
+
+/// Serialized only as snapshot storage; the API writes `OrderDto`.
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Order {
     pub id: Uuid,
     pub user_id: Uuid,
     pub side: OrderSide,
     pub order_type: OrderType,
+    #[serde(deserialize_with = "crate::types::string_i64::deserialize")]
     pub price: Price,
+    #[serde(deserialize_with = "crate::types::string_i64::deserialize")]
     pub quantity: Qty,
     pub status: OrderStatus,
     pub timestamp: DateTime<Utc>,
+    #[serde(default)]
+    pub tags: OrderTags,
+    #[serde(default)]
+    pub source: OrderSource,
+}
+
+/// Check tag bounds: at most `MAX_ORDER_TAGS` keys, non-empty keys and keys/values of at
+/// most `MAX_ORDER_TAG_LEN` characters. The error names the offending tag.
+pub fn validate_order_tags(tags: &OrderTags) -> Result<(), String> {
+    if tags.len() > MAX_ORDER_TAGS {
+        return Err(format!(
+            "Too many tags: {} (max {})",
+            tags.len(),
+            MAX_ORDER_TAGS
+        ));
+    }
+    let mut keys: Vec<&String> = tags.keys().collect();
+    keys.sort();
+    for key in keys {
+        if key.is_empty() {
+            return Err("Tag key must not be empty".to_string());
+        }
+        if key.chars().count() > MAX_ORDER_TAG_LEN {
+            return Err(format!(
+                "Tag key '{}' exceeds {} characters",
+                key, MAX_ORDER_TAG_LEN
+            ));
+        }
+        if tags[key].chars().count() > MAX_ORDER_TAG_LEN {
+            return Err(format!(
+                "Tag '{}' value exceeds {} characters",
+                key, MAX_ORDER_TAG_LEN
+            ));
+        }
+    }
+    Ok(())
 }
@@ -1,13 +1,22 @@
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
 use crate::types::order::Price;
 
 /// Position per (user, symbol). Quantity is signed: positive = long, negative = short.
+/// Serialized only as snapshot and audit-record storage; the API writes `PositionDto`.
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Position {
     pub user_id: Uuid,
     pub symbol: String,
+    #[serde(deserialize_with = "crate::types::string_i64::deserialize")]
     pub quantity: i64,
+    #[serde(deserialize_with = "crate::types::string_i64::deserialize")]
     pub average_price: Price,
+    /// Fill that opened the current position; a flip through zero or a re-open after closing
+    /// starts a new one.
+    pub opened_at: DateTime<Utc>,
+    /// Latest fill applied to it.
+    pub updated_at: DateTime<Utc>,
 }
@@ -0,0 +1,114 @@
+//! `#[serde(with = "string_i64")]` for integers a JavaScript client cannot hold exactly: a
+//! value beyond `MAX_SAFE_INTEGER` (2^53 - 1) in magnitude is written as a JSON string of
+//! its digits, anything smaller stays a JSON number. Both forms are accepted on input, so
+//! clients that already send numbers keep working. `string_i64::option` does the same for
+//! `Option`s (None stays `null`).
+//!
+//! Fields written this way:
+//! - `OrderDto`: `price`, `quantity`
+//! - `TradeDto`: `price`, `quantity`
+//! - `UserTradeDto`: `fee`, `realized_pnl`
+//! - `OrderFillDto`: `price`, `quantity`, `fee`; `/orders/{id}/fills`: `filled_quantity`,
+//!   `average_price`
+//! - `TradeAttribution`: `maker_fee`, `taker_fee`, `realized_pnl_maker`, `realized_pnl_taker`
+//! - `PositionDto`: `quantity`, `average_price`
+//! - the `unrealized_pnl` of `PositionUpdate` messages and of `/admin/positions` entries
+//! - `Candle`: `open`, `high`, `low`, `close`, `volume`; `TickerPoint`: `price`, `quantity`
+//! - `RollingStats` (`/ticker`): `high`, `low`, `volume`
+//! - `AuctionResult` messages: `clearing_price`, `volume`
+//! - `/admin/stats/matching`: the `volume` counts; `/admin/book/info`: `last_trade_price`
+//! - `/account/exposure`: `quantity`, `mark_price` and the notional and P&L amounts
+//!
+//! `Order`, `Trade` and `Position` read either form from older snapshots and audit records
+//! but write plain numbers; only their DTOs are API output.
+//!
+//! Order quantities may also be sent as decimal strings (see `decimal`); those are scaled by
+//! the symbol's `qty_scale` and never reach this module.
+
+use serde::de::{self, Deserializer, Visitor};
+use serde::{Serialize, Serializer};
+use std::fmt;
+
+/// Largest magnitude a JSON number keeps exactly in a JavaScript client.
+pub const MAX_SAFE_INTEGER: i128 = (1 << 53) - 1;
+
+/// An integer field this module can write and read.
+pub trait SafeInteger: Copy + fmt::Display + Serialize + Into<i128> + TryFrom<i128> {}
+
+impl SafeInteger for i64 {}
+impl SafeInteger for u64 {}
+
+pub fn serialize<T: SafeInteger, S: Serializer>(
+    value: &T,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    if (*value).into().abs() <= MAX_SAFE_INTEGER {
+        value.serialize(serializer)
+    } else {
+        serializer.collect_str(value)
+    }
+}
+
+pub fn deserialize<'de, T: SafeInteger, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<T, D::Error> {
+    let wide = deserializer.deserialize_any(IntegerVisitor)?;
+    T::try_from(wide).map_err(|_| de::Error::custom(format!("integer {} is out of range", wide)))
+}
+
+struct IntegerVisitor;
+
+impl Visitor<'_> for IntegerVisitor {
+    type Value = i128;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("an integer, or a string of one")
+    }
+
+    fn visit_i64<E: de::Error>(self, v: i64) -> Result<i128, E> {
+        Ok(v.into())
+    }
+
+    fn visit_u64<E: de::Error>(self, v: u64) -> Result<i128, E> {
+        Ok(v.into())
+    }
+
+    fn visit_i128<E: de::Error>(self, v: i128) -> Result<i128, E> {
+        Ok(v)
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<i128, E> {
+        let digits = v.trim();
+        let unsigned = digits.strip_prefix('-').unwrap_or(digits);
+        if unsigned.is_empty() || !unsigned.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(E::invalid_value(de::Unexpected::Str(v), &self));
+        }
+        digits
+            .parse()
+            .map_err(|_| E::custom(format!("integer {} is out of range", digits)))
+    }
+}
+
+pub mod option {
+    use super::SafeInteger;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<T: SafeInteger, S: Serializer>(
+        value: &Option<T>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match value {
+            Some(value) => super::serialize(value, serializer),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, T: SafeInteger, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<T>, D::Error> {
+        #[derive(Deserialize)]
+        struct Wrapper<T: SafeInteger>(#[serde(with = "super")] T);
+
+        Ok(Option::<Wrapper<T>>::deserialize(deserializer)?.map(|Wrapper(value)| value))
+    }
+}
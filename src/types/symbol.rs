@@ -0,0 +1,603 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Deserializer, Serialize};
+use std::fmt;
+use std::ops::Deref;
+use std::str::FromStr;
+
+use crate::orderbook::orderbook::PricingPolicy;
+use crate::types::asset::{self, Asset};
+use crate::types::decimal;
+use crate::types::environment::{self, ENVIRONMENT_SEPARATOR, Environment};
+use crate::types::money::PRICE_SCALE;
+use crate::types::order::{
+    MAX_ORDER_QUANTITY, Order, OrderId, OrderType, Price, Qty, RejectReason,
+};
+
+/// Longest accepted symbol, in characters.
+pub const MAX_SYMBOL_LEN: usize = 20;
+
+/// A trading symbol in canonical form: trimmed, uppercase, ASCII letters and digits only,
+/// followed by `@paper` for the symbol's paper book (see `types::environment`). Parse every
+/// client-supplied symbol through this (directly, or by deserializing into it) so REST,
+/// WebSocket and persistence all see the same key.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize)]
+#[serde(transparent)]
+pub struct Symbol(String);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SymbolError {
+    Empty,
+    TooLong(String),
+    InvalidChar(String, char),
+    UnknownEnvironment(String),
+    /// The symbol names another environment than the one the request asked for.
+    EnvironmentMismatch(String, Environment),
+}
+
+impl fmt::Display for SymbolError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SymbolError::Empty => write!(f, "Symbol is required"),
+            SymbolError::TooLong(raw) => write!(
+                f,
+                "Symbol '{}' is longer than {} characters",
+                raw, MAX_SYMBOL_LEN
+            ),
+            SymbolError::InvalidChar(raw, c) => write!(
+                f,
+                "Symbol '{}' contains '{}'; only letters and digits are allowed",
+                raw, c
+            ),
+            SymbolError::UnknownEnvironment(raw) => write!(
+                f,
+                "Symbol '{}' names an unknown environment; expected live or paper",
+                raw
+            ),
+            SymbolError::EnvironmentMismatch(symbol, environment) => write!(
+                f,
+                "Symbol '{}' is not in the {} environment",
+                symbol, environment
+            ),
+        }
+    }
+}
+
+impl std::error::Error for SymbolError {}
+
+impl Symbol {
+    /// Parse `raw`; `BTCUSDT@paper` (any case) is the paper book of `BTCUSDT` and
+    /// `BTCUSDT@live` is `BTCUSDT`.
+    pub fn parse(raw: &str) -> Result<Self, SymbolError> {
+        let trimmed = raw.trim();
+        let (name, environment) = match trimmed.split_once(ENVIRONMENT_SEPARATOR) {
+            Some((name, suffix)) => (
+                name,
+                Environment::parse(suffix)
+                    .ok_or_else(|| SymbolError::UnknownEnvironment(trimmed.to_string()))?,
+            ),
+            None => (trimmed, Environment::Live),
+        };
+        if name.is_empty() {
+            return Err(SymbolError::Empty);
+        }
+        if let Some(c) = name.chars().find(|c| !c.is_ascii_alphanumeric()) {
+            return Err(SymbolError::InvalidChar(trimmed.to_string(), c));
+        }
+        if name.len() > MAX_SYMBOL_LEN {
+            return Err(SymbolError::TooLong(trimmed.to_string()));
+        }
+        Ok(Self(environment.book_key(&name.to_ascii_uppercase())))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// The symbol without its environment.
+    pub fn base(&self) -> &str {
+        environment::split_key(&self.0).0
+    }
+
+    pub fn environment(&self) -> Environment {
+        Environment::of(&self.0)
+    }
+
+    /// This symbol's book in `environment`. A symbol already naming an environment stays
+    /// in it; asking for another one is an error.
+    pub fn in_environment(self, environment: Environment) -> Result<Self, SymbolError> {
+        if self.environment() == environment {
+            return Ok(self);
+        }
+        if self.environment() != Environment::Live {
+            return Err(SymbolError::EnvironmentMismatch(self.0, environment));
+        }
+        Ok(Self(environment.book_key(&self.0)))
+    }
+
+    pub fn into_string(self) -> String {
+        self.0
+    }
+}
+
+impl Deref for Symbol {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl FromStr for Symbol {
+    type Err = SymbolError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse(s)
+    }
+}
+
+/// For optional symbol filters: absent or blank means no filter, anything else must parse.
+pub fn optional_symbol<'de, D>(deserializer: D) -> Result<Option<Symbol>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    match Option::<String>::deserialize(deserializer)? {
+        Some(raw) if !raw.trim().is_empty() => Symbol::parse(&raw)
+            .map(Some)
+            .map_err(serde::de::Error::custom),
+        _ => Ok(None),
+    }
+}
+
+impl<'de> Deserialize<'de> for Symbol {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let raw = String::deserialize(deserializer)?;
+        Self::parse(&raw).map_err(serde::de::Error::custom)
+    }
+}
+
+/// Most symbols an unknown-symbol error lists; past this it gives only the count.
+pub const MAX_LISTED_SYMBOLS: usize = 50;
+
+/// Furthest (in edits) a known symbol may be from an unknown one to be suggested for it.
+pub const MAX_SUGGESTION_DISTANCE: usize = 2;
+
+/// What an unknown-symbol error tells the client about the symbols that do exist.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SymbolHint {
+    /// Every known symbol, sorted; omitted past `MAX_LISTED_SYMBOLS`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub known_symbols: Option<Vec<String>>,
+    pub known_symbol_count: usize,
+    /// The closest known symbol, if one is within `MAX_SUGGESTION_DISTANCE` edits.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub did_you_mean: Option<String>,
+}
+
+impl SymbolHint {
+    /// Hint for `unknown` given the `known` symbols. Ties in distance go to the symbol that
+    /// sorts first.
+    pub fn new<'a>(unknown: &str, known: impl IntoIterator<Item = &'a str>) -> Self {
+        let mut known: Vec<String> = known.into_iter().map(str::to_string).collect();
+        known.sort();
+        let did_you_mean = known
+            .iter()
+            .map(|symbol| (edit_distance(unknown, symbol), symbol))
+            .filter(|(distance, _)| *distance <= MAX_SUGGESTION_DISTANCE)
+            .min_by_key(|(distance, _)| *distance)
+            .map(|(_, symbol)| symbol.clone());
+        Self {
+            known_symbol_count: known.len(),
+            known_symbols: (known.len() <= MAX_LISTED_SYMBOLS).then_some(known),
+            did_you_mean,
+        }
+    }
+}
+
+/// Levenshtein distance: single-character insertions, deletions and substitutions turning `a`
+/// into `b`.
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    // Distances from the prefix of `a` seen so far to every prefix of `b`
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitute = diagonal + usize::from(ca != *cb);
+            diagonal = row[j + 1];
+            row[j + 1] = substitute.min(row[j] + 1).min(diagonal + 1);
+        }
+    }
+    row[b.len()]
+}
+
+/// Largest price band and fee magnitude accepted, in basis points (100%).
+pub const MAX_BPS: i64 = 10_000;
+
+/// Trading rules for one symbol, checked on every order entry. Adjustable at runtime through
+/// `PATCH /admin/symbols/{symbol}`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SymbolConfig {
+    /// Limit prices must be a multiple of this (in price units).
+    pub tick_size: Price,
+    /// Quantities must be a multiple of this.
+    pub lot_size: Qty,
+    /// Smallest accepted order quantity.
+    #[serde(default = "one")]
+    pub min_qty: Qty,
+    /// Lots per whole unit of the base asset, a power of ten: with 1e8 a quantity of 1 is
+    /// 0.00000001 and decimal quantities are accepted to 8 places. Every quantity (lot size
+    /// and minimum included) is in lots; quote amounts are `price * quantity / qty_scale`.
+    #[serde(default = "one")]
+    pub qty_scale: u64,
+    /// Limit prices further than this from the last trade price are rejected; None disables
+    /// the band (as does a book that has not traded yet).
+    pub price_band_bps: Option<u32>,
+    /// Published for clients; negative values are rebates.
+    pub maker_fee_bps: i32,
+    pub taker_fee_bps: i32,
+    /// New orders are rejected while halted; cancels are still accepted.
+    pub halted: bool,
+    /// Price of a trade between crossing limit orders; `MakerPrice` unless configured.
+    #[serde(default)]
+    pub pricing_policy: PricingPolicy,
+    /// The asset traded and the asset it is priced in, fixed when the symbol is created (see
+    /// `types::asset`). Quantities are in the base asset; prices, fees and notional amounts in
+    /// the quote. None for symbols that are not a pair of registered assets.
+    #[serde(default)]
+    pub base_asset: Option<Asset>,
+    #[serde(default)]
+    pub quote_asset: Option<Asset>,
+    /// Whether new orders are accepted; only `POST /admin/symbols/{symbol}/delist` changes it.
+    #[serde(default)]
+    pub status: SymbolStatus,
+    /// The pending or completed delisting, if one was started.
+    #[serde(default)]
+    pub delisting: Option<Delisting>,
+}
+
+/// Lifecycle of a symbol. A delisting moves it to `CancelOnly` for a grace period, then to
+/// `Delisted` once its orders are cancelled and positions settled (see `api::delisting`).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SymbolStatus {
+    #[default]
+    Trading,
+    /// New orders are rejected; cancels are still accepted.
+    CancelOnly,
+    /// Gone from every listing and lookup; not restored on startup.
+    Delisted,
+}
+
+impl SymbolStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            SymbolStatus::Trading => "Trading",
+            SymbolStatus::CancelOnly => "CancelOnly",
+            SymbolStatus::Delisted => "Delisted",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "Trading" => Some(SymbolStatus::Trading),
+            "CancelOnly" => Some(SymbolStatus::CancelOnly),
+            "Delisted" => Some(SymbolStatus::Delisted),
+            _ => None,
+        }
+    }
+}
+
+/// What happens to open positions in a symbol when it is delisted.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PositionSettlement {
+    /// Closed at the book's last trade price (the position's average price if it never
+    /// traded), realizing the PnL.
+    #[default]
+    Close,
+    /// Left open as they are, for settlement outside the exchange.
+    Freeze,
+}
+
+impl PositionSettlement {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            PositionSettlement::Close => "Close",
+            PositionSettlement::Freeze => "Freeze",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "Close" => Some(PositionSettlement::Close),
+            "Freeze" => Some(PositionSettlement::Freeze),
+            _ => None,
+        }
+    }
+}
+
+/// When a symbol in `CancelOnly` is delisted and how its positions are settled then.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Delisting {
+    pub at: DateTime<Utc>,
+    pub positions: PositionSettlement,
+}
+
+fn one() -> u64 {
+    1
+}
+
+impl Default for SymbolConfig {
+    fn default() -> Self {
+        Self {
+            tick_size: 1,
+            lot_size: 1,
+            min_qty: 1,
+            qty_scale: 1,
+            price_band_bps: None,
+            maker_fee_bps: 0,
+            taker_fee_bps: 0,
+            halted: false,
+            pricing_policy: PricingPolicy::MakerPrice,
+            base_asset: None,
+            quote_asset: None,
+            status: SymbolStatus::Trading,
+            delisting: None,
+        }
+    }
+}
+
+/// Partial update of a `SymbolConfig`; absent fields are left unchanged. `price_band_bps: null`
+/// removes the band.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SymbolConfigPatch {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tick_size: Option<Price>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lot_size: Option<Qty>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_qty: Option<Qty>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub qty_scale: Option<u64>,
+    #[serde(
+        default,
+        deserialize_with = "present",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub price_band_bps: Option<Option<u32>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub maker_fee_bps: Option<i32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub taker_fee_bps: Option<i32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub halted: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pricing_policy: Option<PricingPolicy>,
+}
+
+/// Distinguish a field set to null (`Some(None)`) from an absent one (`None`, via `default`).
+fn present<'de, D, T>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    Option::<T>::deserialize(deserializer).map(Some)
+}
+
+impl SymbolConfigPatch {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// How to read the integers of a response about one symbol: a price `p` is
+/// `p / price_scale` of the quote asset and a quantity `q` is `q / qty_scale` of the base
+/// asset. Limit prices are multiples of `tick_size` and quantities of `lot_size`, both in
+/// those integer units. Every response carries the one `SymbolConfig::meta` produces.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ScaleMeta {
+    pub price_scale: i64,
+    pub qty_scale: u64,
+    pub tick_size: Price,
+    pub lot_size: Qty,
+}
+
+impl SymbolConfig {
+    /// The `meta` block of responses about this symbol.
+    pub fn meta(&self) -> ScaleMeta {
+        ScaleMeta {
+            price_scale: PRICE_SCALE,
+            qty_scale: self.qty_scale,
+            tick_size: self.tick_size,
+            lot_size: self.lot_size,
+        }
+    }
+
+    /// Default rules for a new symbol, its assets read off the name (see `split_symbol`);
+    /// a paper book trades the same assets as the live one.
+    pub fn for_symbol(symbol: &str) -> Self {
+        let (base_asset, quote_asset) =
+            asset::split_symbol(environment::split_key(symbol).0).unzip();
+        Self {
+            base_asset,
+            quote_asset,
+            ..Self::default()
+        }
+    }
+
+    /// The config trading `base` against `quote`, or why that pair is invalid: both must be
+    /// registered and differ.
+    pub fn with_assets(self, base: Asset, quote: Asset) -> Result<SymbolConfig, String> {
+        for asset in [base, quote] {
+            if asset.info().is_none() {
+                return Err(format!("Unknown asset '{}'", asset));
+            }
+        }
+        if base == quote {
+            return Err(format!("Base and quote asset are both '{}'", base));
+        }
+        Ok(Self {
+            base_asset: Some(base),
+            quote_asset: Some(quote),
+            ..self
+        })
+    }
+
+    /// `(base, quote)`, when the symbol has assets.
+    pub fn assets(&self) -> Option<(Asset, Asset)> {
+        self.base_asset.zip(self.quote_asset)
+    }
+
+    /// The config with `patch` applied, or why the result is invalid. Assets are not
+    /// patchable.
+    pub fn apply(&self, patch: &SymbolConfigPatch) -> Result<SymbolConfig, String> {
+        let config = SymbolConfig {
+            tick_size: patch.tick_size.unwrap_or(self.tick_size),
+            lot_size: patch.lot_size.unwrap_or(self.lot_size),
+            min_qty: patch.min_qty.unwrap_or(self.min_qty),
+            qty_scale: patch.qty_scale.unwrap_or(self.qty_scale),
+            price_band_bps: patch.price_band_bps.unwrap_or(self.price_band_bps),
+            maker_fee_bps: patch.maker_fee_bps.unwrap_or(self.maker_fee_bps),
+            taker_fee_bps: patch.taker_fee_bps.unwrap_or(self.taker_fee_bps),
+            halted: patch.halted.unwrap_or(self.halted),
+            pricing_policy: patch.pricing_policy.unwrap_or(self.pricing_policy),
+            ..*self
+        };
+        if config.tick_size <= 0 {
+            return Err("tick_size must be positive".to_string());
+        }
+        if config.lot_size == 0 {
+            return Err("lot_size must be positive".to_string());
+        }
+        if config.min_qty == 0 {
+            return Err("min_qty must be positive".to_string());
+        }
+        if !decimal::is_valid_scale(config.qty_scale) {
+            return Err(format!(
+                "qty_scale must be a power of ten no larger than {}",
+                decimal::MAX_SCALE
+            ));
+        }
+        if let Some(band) = config.price_band_bps
+            && !(1..=MAX_BPS).contains(&(band as i64))
+        {
+            return Err(format!("price_band_bps must be between 1 and {}", MAX_BPS));
+        }
+        for (name, fee) in [
+            ("maker_fee_bps", config.maker_fee_bps),
+            ("taker_fee_bps", config.taker_fee_bps),
+        ] {
+            if (fee as i64).abs() > MAX_BPS {
+                return Err(format!("{} must be within ±{}", name, MAX_BPS));
+            }
+        }
+        Ok(config)
+    }
+
+    /// Resting orders that would break a tick or lot size changed from `previous`, or all of
+    /// them if the quantity scale changed (their quantities would mean something else). A
+    /// change that leaves any is rejected rather than applied: resting orders are never
+    /// repriced or resized, so the book must be cleared of them first.
+    pub fn violating_orders<'a>(
+        &self,
+        previous: &SymbolConfig,
+        orders: impl Iterator<Item = &'a Order>,
+    ) -> Vec<OrderId> {
+        let check_tick = self.tick_size != previous.tick_size;
+        let check_lot = self.lot_size != previous.lot_size;
+        let rescaled = self.qty_scale != previous.qty_scale;
+        if !check_tick && !check_lot && !rescaled {
+            return Vec::new();
+        }
+        orders
+            .filter(|o| {
+                rescaled
+                    || (check_tick && o.price % self.tick_size != 0)
+                    || (check_lot && !o.quantity.is_multiple_of(self.lot_size))
+            })
+            .map(|o| o.id)
+            .collect()
+    }
+
+    /// Rules a resting order breaks: the tick size, price band, lot size and minimum
+    /// quantity, checked against its open quantity. The symbol's status is not the order's
+    /// fault and is left out.
+    pub fn resting_violations(
+        &self,
+        order: &Order,
+        last_price: Option<Price>,
+    ) -> Vec<RejectReason> {
+        let mut violations =
+            self.order_violations(order.price, order.quantity, order.order_type, last_price);
+        violations.retain(|reason| {
+            !matches!(
+                reason,
+                RejectReason::SymbolCancelOnly | RejectReason::SymbolHalted
+            )
+        });
+        violations
+    }
+
+    /// Check a new order against these rules; `last_price` anchors the price band. Fails with
+    /// the first of `order_violations`.
+    pub fn check_order(
+        &self,
+        price: Price,
+        quantity: Qty,
+        order_type: OrderType,
+        last_price: Option<Price>,
+    ) -> Result<(), RejectReason> {
+        match self
+            .order_violations(price, quantity, order_type, last_price)
+            .first()
+        {
+            Some(reason) => Err(*reason),
+            None => Ok(()),
+        }
+    }
+
+    /// Every rule a new order breaks, in the order `check_order` reports them. Price rules do
+    /// not apply to market orders.
+    pub fn order_violations(
+        &self,
+        price: Price,
+        quantity: Qty,
+        order_type: OrderType,
+        last_price: Option<Price>,
+    ) -> Vec<RejectReason> {
+        let mut violations = Vec::new();
+        if self.status != SymbolStatus::Trading {
+            violations.push(RejectReason::SymbolCancelOnly);
+        }
+        if self.halted {
+            violations.push(RejectReason::SymbolHalted);
+        }
+        if !(1..=MAX_ORDER_QUANTITY).contains(&quantity) {
+            violations.push(RejectReason::InvalidQuantity);
+        } else if quantity < self.min_qty {
+            violations.push(RejectReason::BelowMinQuantity);
+        }
+        if !quantity.is_multiple_of(self.lot_size) {
+            violations.push(RejectReason::InvalidLotSize);
+        }
+        if order_type == OrderType::Market {
+            return violations;
+        }
+        if price % self.tick_size != 0 {
+            violations.push(RejectReason::InvalidTickSize);
+        }
+        if let (Some(band), Some(last)) = (self.price_band_bps, last_price) {
+            let distance = (price as i128 - last as i128).abs() * MAX_BPS as i128;
+            if distance > last.abs() as i128 * band as i128 {
+                violations.push(RejectReason::PriceOutsideBand);
+            }
+        }
+        violations
+    }
+}
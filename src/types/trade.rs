@@ -2,16 +2,147 @@ use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
-use crate::types::order::{Price, Qty};
+use crate::types::order::{OrderId, OrderSide, Price, Qty};
 
+/// Serialized only as snapshot and audit-record storage; the API writes `TradeDto`.
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Trade {
     pub id: Uuid,
+    /// Per-symbol, strictly increasing sequence; use as a cursor instead of timestamps.
+    pub trade_seq: u64,
     pub maker_order_id: Uuid,
     pub taker_order_id: Uuid,
     pub maker_user_id: Uuid,
     pub taker_user_id: Uuid,
+    #[serde(deserialize_with = "crate::types::string_i64::deserialize")]
     pub price: Price,
+    #[serde(deserialize_with = "crate::types::string_i64::deserialize")]
     pub quantity: Qty,
+    /// Time of the match; every trade of one match shares it, ordered by `trade_seq`.
     pub timestamp: DateTime<Utc>,
+    /// The maker's place in its price level's queue when the trade executed (0 = front).
+    /// None for trades stored before it was recorded.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub maker_queue_rank: Option<u32>,
+}
+
+/// A user's part in a trade.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TradeRole {
+    Maker,
+    Taker,
+}
+
+/// What a trade meant for each counterparty, recorded when it executed: the fee each paid
+/// (negative for a rebate) and the P&L each realized against their position at that moment.
+/// Realized P&L depends on the position just before the fill, so it is stored rather than
+/// reconstructed later.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TradeAttribution {
+    pub trade_id: Uuid,
+    pub taker_side: OrderSide,
+    #[serde(with = "crate::types::string_i64")]
+    pub maker_fee: i64,
+    #[serde(with = "crate::types::string_i64")]
+    pub taker_fee: i64,
+    #[serde(with = "crate::types::string_i64")]
+    pub realized_pnl_maker: i64,
+    #[serde(with = "crate::types::string_i64")]
+    pub realized_pnl_taker: i64,
+}
+
+/// An admin's cancellation of a trade after the fact. The trade stays stored, but its effect
+/// on both parties' positions and balances was reversed and it no longer counts as market data.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TradeBust {
+    pub busted_at: DateTime<Utc>,
+    pub reason: String,
+}
+
+/// A trade as one of its counterparties sees it (GET /trades/me, as `UserTradeDto`). A
+/// self-trade is shown from the taker's side.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UserTrade {
+    pub trade: Trade,
+    pub role: TradeRole,
+    /// None only for trades stored before attribution whose taker order is gone.
+    pub side: Option<OrderSide>,
+    pub fee: i64,
+    pub realized_pnl: i64,
+    /// Set once the trade was busted; fee and P&L are then void.
+    pub busted_at: Option<DateTime<Utc>>,
+    pub bust_reason: Option<String>,
+}
+
+impl UserTrade {
+    /// `trade` from `user_id`'s side; fee and P&L are 0 when there is no attribution.
+    pub fn new(trade: Trade, attribution: Option<&TradeAttribution>, user_id: Uuid) -> Self {
+        let role = if trade.taker_user_id == user_id {
+            TradeRole::Taker
+        } else {
+            TradeRole::Maker
+        };
+        let taker_side = attribution.map(|a| a.taker_side);
+        let (side, fee, realized_pnl) = match (role, attribution) {
+            (TradeRole::Taker, Some(a)) => (taker_side, a.taker_fee, a.realized_pnl_taker),
+            (TradeRole::Maker, Some(a)) => (
+                taker_side.map(OrderSide::opposite),
+                a.maker_fee,
+                a.realized_pnl_maker,
+            ),
+            (_, None) => (None, 0, 0),
+        };
+        Self {
+            trade,
+            role,
+            side,
+            fee,
+            realized_pnl,
+            busted_at: None,
+            bust_reason: None,
+        }
+    }
+
+    /// Mark the trade busted by `bust`, if any.
+    pub fn with_bust(mut self, bust: Option<&TradeBust>) -> Self {
+        if let Some(bust) = bust {
+            self.busted_at = Some(bust.busted_at);
+            self.bust_reason = Some(bust.reason.clone());
+        }
+        self
+    }
+}
+
+/// A trade from the side of one of its orders (GET /orders/{id}/fills, as `OrderFillDto`).
+/// Told apart by order id, so both orders of a self-trade see their own side.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OrderFill {
+    pub trade: Trade,
+    pub role: TradeRole,
+    /// The order's fee; 0 when there is no attribution.
+    pub fee: i64,
+}
+
+impl OrderFill {
+    /// `trade` from `order_id`'s side.
+    pub fn new(trade: Trade, attribution: Option<&TradeAttribution>, order_id: OrderId) -> Self {
+        let role = if trade.taker_order_id == order_id {
+            TradeRole::Taker
+        } else {
+            TradeRole::Maker
+        };
+        let fee = attribution.map_or(0, |a| match role {
+            TradeRole::Taker => a.taker_fee,
+            TradeRole::Maker => a.maker_fee,
+        });
+        Self { trade, role, fee }
+    }
+
+    /// The user who placed the order.
+    pub fn user_id(&self) -> Uuid {
+        match self.role {
+            TradeRole::Taker => self.trade.taker_user_id,
+            TradeRole::Maker => self.trade.maker_user_id,
+        }
+    }
 }
@@ -0,0 +1,901 @@
+//! Outbound webhooks: users register URLs that receive their fills, order closes and notices
+//! of symbols they trade being delisted as signed JSON POSTs, for consumers that cannot hold
+//! a WebSocket open.
+//!
+//! A dispatcher task reads `AppState::ws_channel`, the broadcast the private WebSocket stream
+//! is built from, turns each private message into events for the users it concerns, and
+//! queues them on each matching webhook. Every webhook has its own delivery task, so a slow
+//! endpoint only delays itself. A delivery is retried with exponential backoff on transport
+//! errors, 5xx, 408 and 429; once `breaker_threshold` deliveries in a row have failed, the
+//! endpoint's circuit opens and events are logged as `CircuitOpen` without being sent until
+//! `breaker_cooldown` has passed. The next delivery after that decides: success closes the
+//! circuit, failure opens it again.
+//!
+//! Bodies are signed with HMAC-SHA256 under the webhook's secret, sent hex-encoded as
+//! `X-Webhook-Signature: sha256=<hex>`; receivers check it with `verify`. Registrations and
+//! finished deliveries are stored in the database when there is one; without one they live
+//! in memory, with the last `log_capacity` deliveries per webhook.
+
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::collections::{BTreeSet, HashMap, VecDeque};
+use std::env;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+use tokio::select;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
+use uuid::Uuid;
+
+use crate::api::routes::{AppState, WsMessage};
+use crate::persistence::{self, PgPool, WebhookDeliveryRow, WebhookRow};
+use crate::tasks::{RestartPolicy, ShutdownStage};
+use crate::types::order::{CloseReason, OrderId, OrderSide, OrderStatus, Price, Qty};
+use crate::types::symbol::SymbolStatus;
+use crate::types::trade::TradeRole;
+
+/// `sha256=` followed by the hex HMAC-SHA256 of the body under the webhook's secret.
+pub const SIGNATURE_HEADER: &str = "X-Webhook-Signature";
+/// The event's type name (e.g. `Fill`).
+pub const EVENT_HEADER: &str = "X-Webhook-Event";
+/// The payload's `id`, the same on every attempt.
+pub const DELIVERY_HEADER: &str = "X-Webhook-Delivery";
+
+/// Shortest secret a webhook may be registered with.
+pub const MIN_SECRET_LEN: usize = 16;
+pub const MAX_SECRET_LEN: usize = 256;
+pub const MAX_URL_LEN: usize = 2048;
+
+pub type SharedWebhooks = Arc<Webhooks>;
+
+#[derive(Debug, Clone)]
+pub struct WebhookConfig {
+    /// Webhooks one user may register.
+    pub max_per_user: usize,
+    /// Attempts per delivery, the first included.
+    pub max_attempts: u32,
+    /// Wait before the first retry; doubles with every further retry.
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    /// Per-attempt request timeout.
+    pub timeout: Duration,
+    /// Failed deliveries in a row that open an endpoint's circuit.
+    pub breaker_threshold: u32,
+    /// How long an open circuit skips deliveries.
+    pub breaker_cooldown: Duration,
+    /// Events waiting per webhook; further events are dropped (and logged) while it is full.
+    pub queue_capacity: usize,
+    /// Deliveries kept per webhook for `GET /webhooks/{id}/deliveries` without a database.
+    pub log_capacity: usize,
+}
+
+impl Default for WebhookConfig {
+    fn default() -> Self {
+        Self {
+            max_per_user: 10,
+            max_attempts: 5,
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(60),
+            timeout: Duration::from_secs(10),
+            breaker_threshold: 5,
+            breaker_cooldown: Duration::from_secs(5 * 60),
+            queue_capacity: 1000,
+            log_capacity: 100,
+        }
+    }
+}
+
+impl WebhookConfig {
+    /// Read `WEBHOOK_*` variables, falling back to the defaults.
+    pub fn from_env() -> Self {
+        fn var<T: std::str::FromStr>(name: &str) -> Option<T> {
+            env::var(name).ok().and_then(|v| v.parse().ok())
+        }
+        let defaults = Self::default();
+        Self {
+            max_per_user: var("WEBHOOK_MAX_PER_USER").unwrap_or(defaults.max_per_user),
+            max_attempts: var("WEBHOOK_MAX_ATTEMPTS")
+                .filter(|&n| n > 0)
+                .unwrap_or(defaults.max_attempts),
+            initial_backoff: var("WEBHOOK_INITIAL_BACKOFF_MS")
+                .map_or(defaults.initial_backoff, Duration::from_millis),
+            max_backoff: var("WEBHOOK_MAX_BACKOFF_MS")
+                .map_or(defaults.max_backoff, Duration::from_millis),
+            timeout: var::<u64>("WEBHOOK_TIMEOUT_MS")
+                .filter(|&ms| ms > 0)
+                .map_or(defaults.timeout, Duration::from_millis),
+            breaker_threshold: var("WEBHOOK_BREAKER_THRESHOLD")
+                .filter(|&n| n > 0)
+                .unwrap_or(defaults.breaker_threshold),
+            breaker_cooldown: var("WEBHOOK_BREAKER_COOLDOWN_SECS")
+                .map_or(defaults.breaker_cooldown, Duration::from_secs),
+            queue_capacity: var("WEBHOOK_QUEUE_CAPACITY")
+                .filter(|&n| n > 0)
+                .unwrap_or(defaults.queue_capacity),
+            log_capacity: var("WEBHOOK_LOG_CAPACITY").unwrap_or(defaults.log_capacity),
+        }
+    }
+
+    /// Wait before attempt `attempt` (2 is the first retry).
+    fn backoff(&self, attempt: u32) -> Duration {
+        let doublings = attempt.saturating_sub(2).min(31);
+        self.initial_backoff
+            .saturating_mul(1 << doublings)
+            .min(self.max_backoff)
+    }
+}
+
+/// What a webhook can subscribe to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum WebhookEventType {
+    Fill,
+    OrderClosed,
+    SymbolStatus,
+}
+
+impl WebhookEventType {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Fill => "Fill",
+            Self::OrderClosed => "OrderClosed",
+            Self::SymbolStatus => "SymbolStatus",
+        }
+    }
+}
+
+impl FromStr for WebhookEventType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "Fill" => Ok(Self::Fill),
+            "OrderClosed" => Ok(Self::OrderClosed),
+            "SymbolStatus" => Ok(Self::SymbolStatus),
+            _ => Err(format!("Unknown webhook event type '{}'", s)),
+        }
+    }
+}
+
+/// One side of a trade, as its owner sees it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FillEvent {
+    pub symbol: String,
+    pub order_id: OrderId,
+    pub trade_id: Uuid,
+    pub trade_seq: u64,
+    pub role: TradeRole,
+    pub side: OrderSide,
+    pub price: Price,
+    pub quantity: Qty,
+    pub timestamp: DateTime<Utc>,
+    /// Maker fills only: the order's place in its price level's queue when it executed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub maker_queue_rank: Option<u32>,
+}
+
+/// An order cancelled by someone other than its owner (see `CloseReason::notifies_owner`).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OrderClosedEvent {
+    pub symbol: String,
+    pub order_id: OrderId,
+    pub status: OrderStatus,
+    pub reason: CloseReason,
+    pub remaining_qty: Qty,
+}
+
+/// A symbol the user has resting orders or a position in changed status: a delisting
+/// started (`CancelOnly` until `delist_at`) or completed.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SymbolStatusEvent {
+    pub symbol: String,
+    pub status: SymbolStatus,
+    pub delist_at: Option<DateTime<Utc>>,
+}
+
+/// Serialized as `"event": <type>` next to the event's fields under `"data"`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "event", content = "data")]
+pub enum WebhookEvent {
+    Fill(FillEvent),
+    OrderClosed(OrderClosedEvent),
+    SymbolStatus(SymbolStatusEvent),
+}
+
+impl WebhookEvent {
+    pub fn event_type(&self) -> WebhookEventType {
+        match self {
+            Self::Fill(_) => WebhookEventType::Fill,
+            Self::OrderClosed(_) => WebhookEventType::OrderClosed,
+            Self::SymbolStatus(_) => WebhookEventType::SymbolStatus,
+        }
+    }
+}
+
+/// The body POSTed to a webhook.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WebhookPayload {
+    /// Unique per event and webhook, and the same on every attempt, so receivers can drop
+    /// duplicates.
+    pub id: Uuid,
+    pub webhook_id: Uuid,
+    pub user_id: Uuid,
+    pub created_at: DateTime<Utc>,
+    #[serde(flatten)]
+    pub event: WebhookEvent,
+}
+
+/// The events `message` means for each user it concerns: fills for both sides of every
+/// trade in an execution report, and order closes. Market data means nothing here.
+pub fn events(message: &WsMessage) -> Vec<(Uuid, WebhookEvent)> {
+    match message {
+        WsMessage::OrderUpdate { symbol, report } => {
+            let order = &report.order;
+            let mut events = Vec::with_capacity(report.trades.len() * 2);
+            for trade in &report.trades {
+                let fill = |order_id, role, side| FillEvent {
+                    symbol: symbol.clone(),
+                    order_id,
+                    trade_id: trade.id,
+                    trade_seq: trade.trade_seq,
+                    role,
+                    side,
+                    price: trade.price,
+                    quantity: trade.quantity,
+                    timestamp: trade.timestamp,
+                    maker_queue_rank: (role == TradeRole::Maker)
+                        .then_some(trade.maker_queue_rank)
+                        .flatten(),
+                };
+                events.push((
+                    trade.taker_user_id,
+                    WebhookEvent::Fill(fill(trade.taker_order_id, TradeRole::Taker, order.side)),
+                ));
+                events.push((
+                    trade.maker_user_id,
+                    WebhookEvent::Fill(fill(
+                        trade.maker_order_id,
+                        TradeRole::Maker,
+                        order.side.opposite(),
+                    )),
+                ));
+            }
+            events
+        }
+        WsMessage::OrderClosed {
+            symbol,
+            order_id,
+            user_id,
+            status,
+            reason,
+            remaining_qty,
+        } => vec![(
+            *user_id,
+            WebhookEvent::OrderClosed(OrderClosedEvent {
+                symbol: symbol.clone(),
+                order_id: *order_id,
+                status: *status,
+                reason: *reason,
+                remaining_qty: *remaining_qty,
+            }),
+        )],
+        _ => Vec::new(),
+    }
+}
+
+type HmacSha256 = Hmac<Sha256>;
+
+fn mac(secret: &str, body: &[u8]) -> HmacSha256 {
+    let mut mac =
+        HmacSha256::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(body);
+    mac
+}
+
+/// The `X-Webhook-Signature` value for `body` under `secret`.
+pub fn sign(secret: &str, body: &[u8]) -> String {
+    format!(
+        "sha256={}",
+        hex::encode(mac(secret, body).finalize().into_bytes())
+    )
+}
+
+/// Whether `signature` (an `X-Webhook-Signature` value) signs `body` under `secret`. The
+/// comparison takes constant time.
+pub fn verify(secret: &str, body: &[u8], signature: &str) -> bool {
+    let Some(expected) = signature
+        .strip_prefix("sha256=")
+        .and_then(|digest| hex::decode(digest).ok())
+    else {
+        return false;
+    };
+    mac(secret, body).verify_slice(&expected).is_ok()
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WebhookError {
+    InvalidUrl(String),
+    InvalidSecret,
+    NoEvents,
+    LimitReached(usize),
+    /// No webhook the caller owns has this id.
+    NotFound(Uuid),
+}
+
+impl fmt::Display for WebhookError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidUrl(reason) => write!(f, "Invalid webhook URL: {}", reason),
+            Self::InvalidSecret => write!(
+                f,
+                "Webhook secret must be {} to {} characters",
+                MIN_SECRET_LEN, MAX_SECRET_LEN
+            ),
+            Self::NoEvents => write!(f, "Webhook must subscribe to at least one event type"),
+            Self::LimitReached(max) => write!(f, "At most {} webhooks per user", max),
+            Self::NotFound(id) => write!(f, "Webhook {} not found", id),
+        }
+    }
+}
+
+/// A registered endpoint. The secret is never shown back.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Webhook {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub url: String,
+    pub secret: String,
+    pub events: BTreeSet<WebhookEventType>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl Webhook {
+    /// A new webhook for `user_id`, checking the URL (http or https, with a host), the secret
+    /// length and that at least one event is named.
+    pub fn new(
+        user_id: Uuid,
+        url: &str,
+        secret: String,
+        events: impl IntoIterator<Item = WebhookEventType>,
+        created_at: DateTime<Utc>,
+    ) -> Result<Self, WebhookError> {
+        let url = url.trim();
+        if url.len() > MAX_URL_LEN {
+            return Err(WebhookError::InvalidUrl(format!(
+                "longer than {} characters",
+                MAX_URL_LEN
+            )));
+        }
+        let parsed =
+            reqwest::Url::parse(url).map_err(|e| WebhookError::InvalidUrl(e.to_string()))?;
+        if !matches!(parsed.scheme(), "http" | "https") {
+            return Err(WebhookError::InvalidUrl(
+                "scheme must be http or https".to_string(),
+            ));
+        }
+        if parsed.host_str().is_none_or(str::is_empty) {
+            return Err(WebhookError::InvalidUrl("missing host".to_string()));
+        }
+        if !(MIN_SECRET_LEN..=MAX_SECRET_LEN).contains(&secret.chars().count()) {
+            return Err(WebhookError::InvalidSecret);
+        }
+        let events: BTreeSet<WebhookEventType> = events.into_iter().collect();
+        if events.is_empty() {
+            return Err(WebhookError::NoEvents);
+        }
+        Ok(Self {
+            id: Uuid::new_v4(),
+            user_id,
+            url: parsed.to_string(),
+            secret,
+            events,
+            created_at,
+        })
+    }
+
+    pub fn to_row(&self) -> WebhookRow {
+        WebhookRow {
+            id: self.id,
+            user_id: self.user_id,
+            url: self.url.clone(),
+            secret: self.secret.clone(),
+            events: self.events.iter().map(|e| e.as_str().to_string()).collect(),
+            created_at: self.created_at,
+        }
+    }
+
+    /// None when the row names an event type this build does not know.
+    pub fn from_row(row: WebhookRow) -> Option<Self> {
+        let events = row
+            .events
+            .iter()
+            .map(|e| e.parse().ok())
+            .collect::<Option<BTreeSet<_>>>()?;
+        Some(Self {
+            id: row.id,
+            user_id: row.user_id,
+            url: row.url,
+            secret: row.secret,
+            events,
+            created_at: row.created_at,
+        })
+    }
+}
+
+/// A webhook as its owner sees it, with the state of its circuit.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WebhookInfo {
+    pub id: Uuid,
+    pub url: String,
+    pub events: BTreeSet<WebhookEventType>,
+    pub created_at: DateTime<Utc>,
+    /// Deliveries that failed in a row since the last success.
+    pub consecutive_failures: u32,
+    /// Set while the circuit is open: deliveries before then are skipped.
+    pub circuit_open_until: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DeliveryStatus {
+    /// The endpoint answered 2xx.
+    Delivered,
+    /// Every attempt failed, or the endpoint answered a status not worth retrying.
+    Failed,
+    /// Not sent: the endpoint's circuit was open.
+    CircuitOpen,
+    /// Not sent: the webhook's queue was full.
+    Dropped,
+}
+
+impl DeliveryStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Delivered => "Delivered",
+            Self::Failed => "Failed",
+            Self::CircuitOpen => "CircuitOpen",
+            Self::Dropped => "Dropped",
+        }
+    }
+}
+
+impl FromStr for DeliveryStatus {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "Delivered" => Ok(Self::Delivered),
+            "Failed" => Ok(Self::Failed),
+            "CircuitOpen" => Ok(Self::CircuitOpen),
+            "Dropped" => Ok(Self::Dropped),
+            _ => Err(format!("Unknown delivery status '{}'", s)),
+        }
+    }
+}
+
+/// The outcome of delivering one event to one webhook.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DeliveryRecord {
+    /// The payload's `id`.
+    pub id: Uuid,
+    pub webhook_id: Uuid,
+    pub event: WebhookEventType,
+    pub status: DeliveryStatus,
+    pub attempts: u32,
+    /// Status of the last response, if any came back.
+    pub response_status: Option<u16>,
+    /// Why the last attempt failed.
+    pub error: Option<String>,
+    /// When the event was queued.
+    pub created_at: DateTime<Utc>,
+    pub finished_at: DateTime<Utc>,
+}
+
+impl DeliveryRecord {
+    pub fn to_row(&self) -> WebhookDeliveryRow {
+        WebhookDeliveryRow {
+            id: self.id,
+            webhook_id: self.webhook_id,
+            event: self.event.as_str().to_string(),
+            status: self.status.as_str().to_string(),
+            attempts: self.attempts as i32,
+            response_status: self.response_status.map(i32::from),
+            error: self.error.clone(),
+            created_at: self.created_at,
+            finished_at: self.finished_at,
+        }
+    }
+
+    /// None when the row names an event type or status this build does not know.
+    pub fn from_row(row: WebhookDeliveryRow) -> Option<Self> {
+        Some(Self {
+            id: row.id,
+            webhook_id: row.webhook_id,
+            event: row.event.parse().ok()?,
+            status: row.status.parse().ok()?,
+            attempts: row.attempts.max(0) as u32,
+            response_status: row.response_status.and_then(|s| u16::try_from(s).ok()),
+            error: row.error,
+            created_at: row.created_at,
+            finished_at: row.finished_at,
+        })
+    }
+}
+
+#[derive(Debug, Default)]
+struct Breaker {
+    consecutive_failures: u32,
+    open_until: Option<DateTime<Utc>>,
+}
+
+/// A registered webhook with its queue, circuit and recent deliveries.
+struct Endpoint {
+    webhook: Webhook,
+    queue: mpsc::Sender<WebhookPayload>,
+    breaker: Mutex<Breaker>,
+    log: Mutex<VecDeque<DeliveryRecord>>,
+    stop: CancellationToken,
+}
+
+impl Endpoint {
+    fn breaker(&self) -> std::sync::MutexGuard<'_, Breaker> {
+        self.breaker.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn info(&self) -> WebhookInfo {
+        let breaker = self.breaker();
+        WebhookInfo {
+            id: self.webhook.id,
+            url: self.webhook.url.clone(),
+            events: self.webhook.events.clone(),
+            created_at: self.webhook.created_at,
+            consecutive_failures: breaker.consecutive_failures,
+            circuit_open_until: breaker.open_until.filter(|&until| until > Utc::now()),
+        }
+    }
+}
+
+/// The registered webhooks, each with its running delivery task.
+pub struct Webhooks {
+    pub config: WebhookConfig,
+    client: reqwest::Client,
+    endpoints: RwLock<HashMap<Uuid, Arc<Endpoint>>>,
+}
+
+impl Default for Webhooks {
+    fn default() -> Self {
+        Self::new(WebhookConfig::default())
+    }
+}
+
+impl Webhooks {
+    pub fn new(config: WebhookConfig) -> Self {
+        let client = reqwest::Client::builder()
+            .timeout(config.timeout)
+            .redirect(reqwest::redirect::Policy::none())
+            .build()
+            .expect("build webhook HTTP client");
+        Self {
+            config,
+            client,
+            endpoints: RwLock::new(HashMap::new()),
+        }
+    }
+
+    fn endpoints(&self) -> std::sync::RwLockReadGuard<'_, HashMap<Uuid, Arc<Endpoint>>> {
+        self.endpoints.read().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Start delivering to `webhook`, logging finished deliveries to `db` when given. Fails
+    /// when its owner already has `max_per_user` webhooks. Must run inside a Tokio runtime.
+    pub fn add(&self, webhook: Webhook, db: Option<PgPool>) -> Result<WebhookInfo, WebhookError> {
+        let mut endpoints = self.endpoints.write().unwrap_or_else(|e| e.into_inner());
+        let owned = endpoints
+            .values()
+            .filter(|e| e.webhook.user_id == webhook.user_id)
+            .count();
+        if owned >= self.config.max_per_user {
+            return Err(WebhookError::LimitReached(self.config.max_per_user));
+        }
+        let endpoint = self.start(webhook, db);
+        endpoints.insert(endpoint.webhook.id, endpoint.clone());
+        Ok(endpoint.info())
+    }
+
+    /// Spawn the delivery task of `webhook`.
+    fn start(&self, webhook: Webhook, db: Option<PgPool>) -> Arc<Endpoint> {
+        let (queue, jobs) = mpsc::channel(self.config.queue_capacity.max(1));
+        let endpoint = Arc::new(Endpoint {
+            webhook,
+            queue,
+            breaker: Mutex::new(Breaker::default()),
+            log: Mutex::new(VecDeque::new()),
+            stop: CancellationToken::new(),
+        });
+        tokio::spawn(run_endpoint(
+            endpoint.clone(),
+            jobs,
+            self.client.clone(),
+            self.config.clone(),
+            db,
+        ));
+        endpoint
+    }
+
+    /// Stop delivering to the caller's webhook `id`; events still queued are discarded.
+    pub fn remove(&self, id: Uuid, user_id: Uuid) -> Result<Webhook, WebhookError> {
+        let mut endpoints = self.endpoints.write().unwrap_or_else(|e| e.into_inner());
+        match endpoints.get(&id) {
+            Some(endpoint) if endpoint.webhook.user_id == user_id => {}
+            _ => return Err(WebhookError::NotFound(id)),
+        }
+        let endpoint = endpoints.remove(&id).expect("checked above");
+        endpoint.stop.cancel();
+        Ok(endpoint.webhook.clone())
+    }
+
+    /// The caller's webhooks, oldest first.
+    pub fn list(&self, user_id: Uuid) -> Vec<WebhookInfo> {
+        let mut webhooks: Vec<WebhookInfo> = self
+            .endpoints()
+            .values()
+            .filter(|e| e.webhook.user_id == user_id)
+            .map(|e| e.info())
+            .collect();
+        webhooks.sort_by_key(|w| (w.created_at, w.id));
+        webhooks
+    }
+
+    /// The caller's webhook `id`.
+    pub fn get(&self, id: Uuid, user_id: Uuid) -> Result<WebhookInfo, WebhookError> {
+        match self.endpoints().get(&id) {
+            Some(endpoint) if endpoint.webhook.user_id == user_id => Ok(endpoint.info()),
+            _ => Err(WebhookError::NotFound(id)),
+        }
+    }
+
+    /// Up to `limit` deliveries to webhook `id` kept in memory, newest first.
+    pub fn recent_deliveries(&self, id: Uuid, limit: usize) -> Vec<DeliveryRecord> {
+        let Some(endpoint) = self.endpoints().get(&id).cloned() else {
+            return Vec::new();
+        };
+        let log = endpoint.log.lock().unwrap_or_else(|e| e.into_inner());
+        log.iter().rev().take(limit).cloned().collect()
+    }
+
+    /// Queue the events in `message` on every webhook subscribed to them.
+    pub fn dispatch(&self, message: &WsMessage) {
+        if self.endpoints().is_empty() {
+            return;
+        }
+        for (user_id, event) in events(message) {
+            self.notify(user_id, event);
+        }
+    }
+
+    /// Queue `event` on each of `user_id`'s webhooks subscribed to its type.
+    pub fn notify(&self, user_id: Uuid, event: WebhookEvent) {
+        let endpoints = self.endpoints();
+        let event_type = event.event_type();
+        for endpoint in endpoints
+            .values()
+            .filter(|e| e.webhook.user_id == user_id && e.webhook.events.contains(&event_type))
+        {
+            let payload = WebhookPayload {
+                id: Uuid::new_v4(),
+                webhook_id: endpoint.webhook.id,
+                user_id,
+                created_at: Utc::now(),
+                event: event.clone(),
+            };
+            if let Err(mpsc::error::TrySendError::Full(payload)) = endpoint.queue.try_send(payload)
+            {
+                eprintln!(
+                    "webhook {} queue full; dropping event {}",
+                    endpoint.webhook.id, payload.id
+                );
+                let now = Utc::now();
+                record(
+                    endpoint,
+                    self.config.log_capacity,
+                    DeliveryRecord {
+                        id: payload.id,
+                        webhook_id: endpoint.webhook.id,
+                        event: event_type,
+                        status: DeliveryStatus::Dropped,
+                        attempts: 0,
+                        response_status: None,
+                        error: Some("queue full".to_string()),
+                        created_at: payload.created_at,
+                        finished_at: now,
+                    },
+                );
+            }
+        }
+    }
+
+    /// Register every webhook stored in `pool`, skipping rows this build cannot read.
+    pub async fn load(&self, pool: &PgPool) -> Result<usize, sqlx::Error> {
+        let rows = persistence::list_webhooks(pool).await?;
+        let mut loaded = 0;
+        for row in rows {
+            let id = row.id;
+            let Some(webhook) = Webhook::from_row(row) else {
+                eprintln!("webhook {} names an unknown event type; not loaded", id);
+                continue;
+            };
+            // Stored webhooks were within the limit when registered; keep them all
+            let endpoint = self.start(webhook, Some(pool.clone()));
+            self.endpoints
+                .write()
+                .unwrap_or_else(|e| e.into_inner())
+                .insert(id, endpoint);
+            loaded += 1;
+        }
+        Ok(loaded)
+    }
+}
+
+/// Keep `delivery` in `endpoint`'s in-memory log, dropping the oldest past `capacity`.
+fn record(endpoint: &Endpoint, capacity: usize, delivery: DeliveryRecord) {
+    if capacity == 0 {
+        return;
+    }
+    let mut log = endpoint.log.lock().unwrap_or_else(|e| e.into_inner());
+    if log.len() >= capacity {
+        log.pop_front();
+    }
+    log.push_back(delivery);
+}
+
+/// Deliver `endpoint`'s queued events one at a time until it is removed.
+async fn run_endpoint(
+    endpoint: Arc<Endpoint>,
+    mut jobs: mpsc::Receiver<WebhookPayload>,
+    client: reqwest::Client,
+    config: WebhookConfig,
+    db: Option<PgPool>,
+) {
+    loop {
+        let payload = select! {
+            _ = endpoint.stop.cancelled() => return,
+            payload = jobs.recv() => match payload {
+                Some(payload) => payload,
+                None => return,
+            },
+        };
+        let delivery = select! {
+            _ = endpoint.stop.cancelled() => return,
+            delivery = deliver(&endpoint, &client, &config, &payload) => delivery,
+        };
+        if let Some(ref db) = db
+            && let Err(e) = persistence::insert_webhook_delivery(db, &delivery.to_row()).await
+        {
+            eprintln!(
+                "failed to log delivery {} of webhook {}: {}",
+                delivery.id, delivery.webhook_id, e
+            );
+        }
+        record(&endpoint, config.log_capacity, delivery);
+    }
+}
+
+/// POST `payload` to the endpoint, retrying as configured, and settle its circuit.
+async fn deliver(
+    endpoint: &Endpoint,
+    client: &reqwest::Client,
+    config: &WebhookConfig,
+    payload: &WebhookPayload,
+) -> DeliveryRecord {
+    let webhook = &endpoint.webhook;
+    let event_type = payload.event.event_type();
+    let mut delivery = DeliveryRecord {
+        id: payload.id,
+        webhook_id: webhook.id,
+        event: event_type,
+        status: DeliveryStatus::Failed,
+        attempts: 0,
+        response_status: None,
+        error: None,
+        created_at: payload.created_at,
+        finished_at: payload.created_at,
+    };
+    if endpoint
+        .breaker()
+        .open_until
+        .is_some_and(|until| until > Utc::now())
+    {
+        delivery.status = DeliveryStatus::CircuitOpen;
+        delivery.finished_at = Utc::now();
+        return delivery;
+    }
+
+    let body = serde_json::to_vec(payload).unwrap_or_default();
+    let signature = sign(&webhook.secret, &body);
+    for attempt in 1..=config.max_attempts {
+        if attempt > 1 {
+            tokio::time::sleep(config.backoff(attempt)).await;
+        }
+        delivery.attempts = attempt;
+        let response = client
+            .post(&webhook.url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .header(SIGNATURE_HEADER, &signature)
+            .header(EVENT_HEADER, event_type.as_str())
+            .header(DELIVERY_HEADER, payload.id.to_string())
+            .body(body.clone())
+            .send()
+            .await;
+        let retry = match response {
+            Ok(response) => {
+                let status = response.status();
+                delivery.response_status = Some(status.as_u16());
+                if status.is_success() {
+                    delivery.status = DeliveryStatus::Delivered;
+                    delivery.error = None;
+                    break;
+                }
+                delivery.error = Some(format!("HTTP {}", status.as_u16()));
+                status.is_server_error()
+                    || status == reqwest::StatusCode::REQUEST_TIMEOUT
+                    || status == reqwest::StatusCode::TOO_MANY_REQUESTS
+            }
+            Err(e) => {
+                delivery.response_status = None;
+                delivery.error = Some(e.to_string());
+                true
+            }
+        };
+        if !retry {
+            break;
+        }
+    }
+    delivery.finished_at = Utc::now();
+
+    let mut breaker = endpoint.breaker();
+    if delivery.status == DeliveryStatus::Delivered {
+        *breaker = Breaker::default();
+    } else {
+        breaker.consecutive_failures += 1;
+        if breaker.consecutive_failures >= config.breaker_threshold {
+            let until = chrono::Duration::from_std(config.breaker_cooldown)
+                .ok()
+                .and_then(|cooldown| delivery.finished_at.checked_add_signed(cooldown))
+                .unwrap_or(DateTime::<Utc>::MAX_UTC);
+            breaker.open_until = Some(until);
+            eprintln!(
+                "webhook {} failed {} deliveries in a row; pausing until {}",
+                webhook.id, breaker.consecutive_failures, until
+            );
+        }
+    }
+    delivery
+}
+
+/// Feed every private message on `state.ws_channel` to `state.webhooks`.
+pub fn spawn_dispatcher(state: AppState) {
+    let tasks = state.tasks.clone();
+    tasks.spawn(
+        "webhook_dispatcher",
+        ShutdownStage::Worker,
+        RestartPolicy::default(),
+        move |token| {
+            let state = state.clone();
+            let mut messages = state.ws_channel.subscribe();
+            async move {
+                while let Some(received) = token.run_until_cancelled(messages.recv()).await {
+                    match received {
+                        Ok(message) => state.webhooks.dispatch(&message),
+                        Err(RecvError::Lagged(skipped)) => {
+                            eprintln!("webhook dispatcher fell behind; {} messages lost", skipped)
+                        }
+                        Err(RecvError::Closed) => return,
+                    }
+                }
+            }
+        },
+    );
+}
@@ -0,0 +1,1355 @@
+//! HTTP integration tests for /admin endpoints (in-memory mode, no database).
+
+use chrono::Utc;
+use rust_exchange::api::activity::{ActivityFeed, ActivityKind, parse_types};
+use rust_exchange::api::routes::WsMessage;
+use rust_exchange::balances::Balances;
+use rust_exchange::orderbook::order_limits::{OrderLimits, RestingOrderCaps};
+use rust_exchange::orderbook::orderbook::OrderBook;
+use rust_exchange::pricefeed::IndexPrice;
+use rust_exchange::selftest::SELFTEST_SYMBOL;
+use rust_exchange::testing::{self, TEST_SYMBOL, TestExchange, Token};
+use rust_exchange::types::money::PRICE_SCALE;
+use rust_exchange::types::order::{CloseReason, OrderSide};
+use rust_exchange::types::position::Position;
+use rust_exchange::types::symbol::SymbolConfig;
+use rust_exchange::types::trade::TradeRole;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{RwLock, broadcast};
+use uuid::Uuid;
+
+async fn place(
+    client: &reqwest::Client,
+    base_url: &str,
+    token: &str,
+    side: &str,
+    price: i64,
+    qty: u64,
+) {
+    let res = client
+        .post(format!("{}/orders", base_url))
+        .bearer_auth(token)
+        .json(&serde_json::json!({
+            "symbol": "BTCUSDT",
+            "price": price,
+            "quantity": qty,
+            "side": side
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn admin_endpoints_reject_non_admin() {
+    let exchange = TestExchange::start().await;
+    let (client, base_url) = (exchange.client(), &exchange.base_url);
+    let token = exchange.register("mallory", "secret").await.token;
+
+    let res = client
+        .post(format!("{}/admin/auction/start", base_url))
+        .bearer_auth(&token)
+        .json(&serde_json::json!({ "symbol": "BTCUSDT" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status().as_u16(), 403);
+
+    let res = client
+        .get(format!("{}/admin/book/orders?symbol=BTCUSDT", base_url))
+        .bearer_auth(&token)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status().as_u16(), 403);
+}
+
+#[tokio::test]
+async fn auction_start_and_end_uncrosses_book() {
+    let exchange = TestExchange::builder().admin().start().await;
+    let (client, base_url) = (exchange.client(), &exchange.base_url);
+    let admin = exchange.admin_token().token;
+    let trader = exchange.register("trader", "secret").await.token;
+
+    let res = client
+        .post(format!("{}/admin/auction/start", base_url))
+        .bearer_auth(&admin)
+        .json(&serde_json::json!({ "symbol": "btcusdt" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status().as_u16(), 200);
+    let json: serde_json::Value = res.json().await.unwrap();
+    assert_eq!(json["phase"], "Auction");
+
+    place(client, base_url, &trader, "Buy", 101, 5).await;
+    place(client, base_url, &trader, "Sell", 100, 5).await;
+
+    let res = client
+        .post(format!("{}/admin/auction/end", base_url))
+        .bearer_auth(&admin)
+        .json(&serde_json::json!({ "symbol": "BTCUSDT" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status().as_u16(), 200);
+    let json: serde_json::Value = res.json().await.unwrap();
+    assert_eq!(json["clearing_price"], 100);
+    assert_eq!(json["volume"], 5);
+
+    let res = client
+        .post(format!("{}/admin/auction/end", base_url))
+        .bearer_auth(&admin)
+        .json(&serde_json::json!({ "symbol": "BTCUSDT" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status().as_u16(), 409);
+}
+
+#[tokio::test]
+async fn book_orders_lists_attributed_orders_with_filters_and_pages() {
+    let exchange = TestExchange::builder().admin().start().await;
+    let (client, base_url) = (exchange.client(), &exchange.base_url);
+    let admin = exchange.admin_token().token;
+    let Token {
+        user_id: alice_id,
+        token: alice,
+    } = exchange.register("alice", "secret").await;
+    let bob = exchange.register("bob", "secret").await.token;
+
+    place(client, base_url, &alice, "Buy", 100, 5).await;
+    place(client, base_url, &bob, "Buy", 100, 2).await;
+    place(client, base_url, &bob, "Buy", 99, 7).await;
+    place(client, base_url, &alice, "Sell", 105, 1).await;
+
+    let get = |query: &str| {
+        client
+            .get(format!("{}/admin/book/orders?{}", base_url, query))
+            .bearer_auth(&admin)
+            .send()
+    };
+
+    let json: serde_json::Value = get("symbol=btcusdt").await.unwrap().json().await.unwrap();
+    assert_eq!(json["total"], 4);
+    let orders = json["orders"].as_array().unwrap();
+    let summary: Vec<_> = orders
+        .iter()
+        .map(|o| {
+            (
+                o["username"].as_str().unwrap().to_string(),
+                o["price"].as_i64().unwrap(),
+                o["queue_position"].as_u64().unwrap(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        summary,
+        vec![
+            ("alice".to_string(), 100, 0),
+            ("bob".to_string(), 100, 1),
+            ("bob".to_string(), 99, 0),
+            ("alice".to_string(), 105, 0),
+        ]
+    );
+    assert!(orders[0]["age_ms"].as_i64().unwrap() >= 0);
+    assert!(orders.iter().all(|o| o["source"] == "Api"));
+
+    let json: serde_json::Value = get("symbol=BTCUSDT&side=Buy&min_qty=3")
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(json["total"], 2);
+
+    let json: serde_json::Value = get(&format!("symbol=BTCUSDT&user_id={}", alice_id))
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(json["total"], 2);
+
+    let json: serde_json::Value = get("symbol=BTCUSDT&limit=2&offset=2")
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(json["total"], 4);
+    let orders = json["orders"].as_array().unwrap();
+    assert_eq!(orders.len(), 2);
+    assert_eq!(orders[0]["price"], 99);
+    assert_eq!(orders[1]["price"], 105);
+
+    for (source, total) in [("Api", 4), ("Admin", 0)] {
+        let json: serde_json::Value = get(&format!("symbol=BTCUSDT&source={}", source))
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(json["total"], total, "{}", source);
+    }
+}
+
+#[tokio::test]
+async fn positions_by_symbol_are_sorted_by_exposure_with_pnl_and_open_interest() {
+    let state = testing::test_app_state();
+    let (long_small, short_big, long_mid) = (Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4());
+    {
+        let mut positions = state.positions.write().await;
+        for (user_id, symbol, quantity, average_price) in [
+            (long_mid, "BTCUSDT", 5, 100),
+            (short_big, "BTCUSDT", -8, 120),
+            (long_small, "BTCUSDT", 3, 90),
+            (long_mid, "ETHUSDT", 50, 10),
+        ] {
+            positions.insert(
+                (user_id, symbol.to_string()),
+                Position {
+                    user_id,
+                    symbol: symbol.to_string(),
+                    quantity,
+                    average_price,
+                    opened_at: Utc::now(),
+                    updated_at: Utc::now(),
+                },
+            );
+        }
+    }
+    state.index_prices.write().await.insert(
+        "BTCUSDT".to_string(),
+        IndexPrice {
+            price: 110,
+            updated_at: Utc::now(),
+        },
+    );
+    let exchange = TestExchange::builder_with_state(state)
+        .admin()
+        .start()
+        .await;
+    let (client, base_url) = (exchange.client(), &exchange.base_url);
+    let admin = exchange.admin_token().token;
+    let trader = exchange.register("trader", "secret").await.token;
+
+    let res = client
+        .get(format!("{}/admin/positions?symbol=BTCUSDT", base_url))
+        .bearer_auth(&trader)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status().as_u16(), 403);
+
+    let json: serde_json::Value = client
+        .get(format!("{}/admin/positions?symbol=btcusdt", base_url))
+        .bearer_auth(&admin)
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(json["symbol"], "BTCUSDT");
+    assert_eq!(json["mark_price"], 110);
+    assert_eq!(json["long_open_interest"], 8);
+    assert_eq!(json["short_open_interest"], 8);
+    assert_eq!(json["total"], 3);
+    let rows: Vec<(String, i64, i64)> = json["positions"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|p| {
+            (
+                p["user_id"].as_str().unwrap().to_string(),
+                p["quantity"].as_i64().unwrap(),
+                p["unrealized_pnl"].as_i64().unwrap(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        rows,
+        vec![
+            (short_big.to_string(), -8, 80),
+            (long_mid.to_string(), 5, 50),
+            (long_small.to_string(), 3, 60),
+        ]
+    );
+
+    // The filter narrows the page but not the open interest totals
+    let json: serde_json::Value = client
+        .get(format!(
+            "{}/admin/positions?symbol=BTCUSDT&min_abs_qty=4&limit=1&offset=1",
+            base_url
+        ))
+        .bearer_auth(&admin)
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(json["total"], 2);
+    assert_eq!(json["long_open_interest"], 8);
+    let page = json["positions"].as_array().unwrap();
+    assert_eq!(page.len(), 1);
+    assert_eq!(page[0]["user_id"], long_mid.to_string());
+
+    let res = client
+        .get(format!("{}/admin/positions?symbol=NOPE", base_url))
+        .bearer_auth(&admin)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn position_pnl_at_a_fine_qty_scale_is_descaled_before_it_is_narrowed() {
+    // 1 BTC in lots of 1e-8 BTC, bought at 50,000 and marked at 51,000: the P&L in lots
+    // (1e11 price units times 1e8 lots) is beyond an i64, the quote amount is not
+    let exchange = TestExchange::builder()
+        .admin()
+        .user("trader", "secret")
+        .position("trader", TEST_SYMBOL, 100_000_000, 50_000 * PRICE_SCALE)
+        .with_state(|state| {
+            let mut config = SymbolConfig::for_symbol(TEST_SYMBOL);
+            config.qty_scale = 100_000_000;
+            state
+                .symbol_configs
+                .try_write()
+                .unwrap()
+                .insert(TEST_SYMBOL.to_string(), config);
+        })
+        .start()
+        .await;
+    exchange.state.index_prices.write().await.insert(
+        TEST_SYMBOL.to_string(),
+        IndexPrice {
+            price: 51_000 * PRICE_SCALE,
+            updated_at: Utc::now(),
+        },
+    );
+
+    let json = exchange
+        .get(
+            &exchange.admin_token(),
+            &format!("/admin/positions?symbol={}", TEST_SYMBOL),
+        )
+        .await
+        .unwrap();
+    assert_eq!(json["positions"][0]["quantity"], 100_000_000);
+    assert_eq!(json["positions"][0]["unrealized_pnl"], 1_000 * PRICE_SCALE);
+}
+
+#[tokio::test]
+async fn hydration_report_is_served_to_admins() {
+    let exchange = TestExchange::builder().admin().start().await;
+    let (client, base_url) = (exchange.client(), &exchange.base_url);
+    let admin = exchange.admin_token().token;
+
+    let res = client
+        .get(format!("{}/admin/hydration", base_url))
+        .bearer_auth(&admin)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status().as_u16(), 200);
+    let json: serde_json::Value = res.json().await.unwrap();
+    assert_eq!(json["rows_skipped"], serde_json::json!([]));
+    assert_eq!(json["errors"], serde_json::json!([]));
+}
+
+#[tokio::test]
+async fn matching_stats_are_served_as_json_and_prometheus_metrics() {
+    let exchange = TestExchange::builder().admin().start().await;
+    let (client, base_url) = (exchange.client(), &exchange.base_url);
+    let admin = exchange.admin_token().token;
+    let maker = exchange.register("maker", "secret").await.token;
+    let taker = exchange.register("taker", "secret").await.token;
+
+    place(client, base_url, &maker, "Sell", 100, 3).await;
+    place(client, base_url, &taker, "Buy", 100, 2).await;
+
+    let json: serde_json::Value = client
+        .get(format!("{}/admin/stats/matching?symbol=btcusdt", base_url))
+        .bearer_auth(&admin)
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(json["symbol"], "BTCUSDT");
+    assert_eq!(json["time_to_first_fill"]["samples"], 2);
+    assert_eq!(json["time_to_full_fill"]["samples"], 1);
+    assert_eq!(json["limit_orders_completed"], 1);
+    assert_eq!(json["volume"]["limit_maker"], 2);
+    assert_eq!(json["volume"]["limit_taker"], 2);
+    assert_eq!(json["maker_ratio"], 0.5);
+
+    let res = client
+        .get(format!("{}/admin/stats/matching?symbol=NOPE", base_url))
+        .bearer_auth(&admin)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status().as_u16(), 404);
+
+    let res = client
+        .get(format!("{}/admin/metrics", base_url))
+        .bearer_auth(&admin)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status().as_u16(), 200);
+    assert!(
+        res.headers()["content-type"]
+            .to_str()
+            .unwrap()
+            .starts_with("text/plain")
+    );
+    let body = res.text().await.unwrap();
+    assert!(body.contains("# TYPE exchange_time_to_first_fill_seconds histogram"));
+    assert!(body.contains("exchange_time_to_first_fill_seconds_count{symbol=\"BTCUSDT\"} 2"));
+    assert!(
+        body.contains(
+            "exchange_time_to_full_fill_seconds_bucket{symbol=\"BTCUSDT\",le=\"+Inf\"} 1"
+        )
+    );
+    assert!(
+        body.contains(
+            "exchange_limit_orders_completed_total{symbol=\"BTCUSDT\",traded=\"true\"} 1"
+        )
+    );
+    assert!(body.contains(
+        "exchange_fill_volume_total{symbol=\"BTCUSDT\",liquidity=\"maker\",order_type=\"limit\"} 2"
+    ));
+    assert!(body.contains("# TYPE exchange_book_lock_wait_seconds histogram"));
+    assert!(body.contains("# TYPE exchange_engine_busy_total counter"));
+    assert!(body.contains("# TYPE exchange_ws_messages_dropped_total counter"));
+    assert!(body.contains("exchange_ws_connections 0"));
+}
+
+#[tokio::test]
+async fn book_info_reports_live_stats_and_the_refresher_exports_them() {
+    let state = testing::test_app_state();
+    let exchange = TestExchange::builder_with_state(state.clone())
+        .admin()
+        .start()
+        .await;
+    let (client, base_url) = (exchange.client(), &exchange.base_url);
+    let admin = exchange.admin_token().token;
+    let maker = exchange.register("maker", "secret").await.token;
+    let taker = exchange.register("taker", "secret").await.token;
+    let info = || async {
+        let res = client
+            .get(format!("{}/admin/book/info?symbol=btcusdt", base_url))
+            .bearer_auth(&admin)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(res.status().as_u16(), 200);
+        res.json::<serde_json::Value>().await.unwrap()
+    };
+
+    let json = info().await;
+    assert_eq!(json["symbol"], "BTCUSDT");
+    assert_eq!(json["resting_orders"], 0);
+    assert_eq!(json["estimated_bytes"], 0);
+    assert!(json["oldest_resting_at"].is_null());
+
+    place(client, base_url, &maker, "Sell", 100, 3).await;
+    place(client, base_url, &maker, "Buy", 90, 1).await;
+    place(client, base_url, &taker, "Buy", 100, 1).await;
+    let json = info().await;
+    assert_eq!(
+        (json["bid_levels"].clone(), json["ask_levels"].clone()),
+        (1.into(), 1.into())
+    );
+    assert_eq!(json["resting_orders"], 2);
+    assert_eq!(json["retained_trades"], 1);
+    assert_eq!(json["last_trade_price"], 100);
+    assert!(json["last_trade_at"].is_string());
+    assert!(json["oldest_resting_at"].is_string());
+    assert!(json["estimated_bytes"].as_u64().unwrap() > 0);
+
+    let res = client
+        .get(format!("{}/admin/book/info?symbol=NOPE", base_url))
+        .bearer_auth(&admin)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status().as_u16(), 404);
+    let res = client
+        .get(format!("{}/admin/book/info?symbol=BTCUSDT", base_url))
+        .bearer_auth(&maker)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status().as_u16(), 403);
+
+    // Gauges show the last sample, not the live book
+    let metrics = || async {
+        client
+            .get(format!("{}/admin/metrics", base_url))
+            .bearer_auth(&admin)
+            .send()
+            .await
+            .unwrap()
+            .text()
+            .await
+            .unwrap()
+    };
+    assert!(!metrics().await.contains("exchange_book_price_levels"));
+    state.book_stats.refresh(&state.orderbooks).await;
+    assert!(state.book_stats.refreshed_at().is_some());
+    let body = metrics().await;
+    assert!(body.contains("exchange_book_price_levels{symbol=\"BTCUSDT\",side=\"bid\"} 1"));
+    assert!(body.contains("exchange_book_retained_trades{symbol=\"BTCUSDT\"} 1"));
+    assert!(body.contains("exchange_book_last_trade_price{symbol=\"BTCUSDT\"} 100"));
+    assert!(body.contains(&format!(
+        "exchange_book_estimated_bytes{{symbol=\"BTCUSDT\"}} {}",
+        json["estimated_bytes"]
+    )));
+
+    place(client, base_url, &taker, "Sell", 90, 1).await;
+    assert_eq!(info().await["bid_levels"], 0);
+    assert!(
+        metrics()
+            .await
+            .contains("exchange_book_price_levels{symbol=\"BTCUSDT\",side=\"bid\"} 1")
+    );
+    state.book_stats.refresh(&state.orderbooks).await;
+    assert!(
+        metrics()
+            .await
+            .contains("exchange_book_price_levels{symbol=\"BTCUSDT\",side=\"bid\"} 0")
+    );
+}
+
+#[tokio::test]
+async fn symbol_config_change_conflicting_with_resting_orders_is_rejected() {
+    let exchange = TestExchange::builder().admin().start().await;
+    let mut ws_rx = exchange.state.ws_channel.subscribe();
+    let (client, base_url) = (exchange.client(), &exchange.base_url);
+    let admin = exchange.admin_token().token;
+    let maker = exchange.register("maker", "secret").await.token;
+
+    let patch = |token: &str, body: serde_json::Value| {
+        client
+            .patch(format!("{}/admin/symbols/btcusdt", base_url))
+            .bearer_auth(token)
+            .json(&body)
+            .send()
+    };
+    let order = |price: i64| {
+        client
+            .post(format!("{}/orders", base_url))
+            .bearer_auth(&maker)
+            .json(&serde_json::json!({
+                "symbol": "BTCUSDT",
+                "price": price,
+                "quantity": 1,
+                "side": "Sell"
+            }))
+            .send()
+    };
+    let resting: serde_json::Value = order(105).await.unwrap().json().await.unwrap();
+
+    assert_eq!(
+        patch(&maker, serde_json::json!({ "halted": true }))
+            .await
+            .unwrap()
+            .status()
+            .as_u16(),
+        403
+    );
+    for invalid in [
+        serde_json::json!({}),
+        serde_json::json!({ "tick_size": 0 }),
+        serde_json::json!({ "price_band_bps": 20_000 }),
+        serde_json::json!({ "min_qty": 0 }),
+        serde_json::json!({ "qty_scale": 3 }),
+    ] {
+        assert_eq!(patch(&admin, invalid).await.unwrap().status().as_u16(), 400);
+    }
+
+    // 105 is off a 10-unit grid: the change waits until that order is gone
+    let res = patch(&admin, serde_json::json!({ "tick_size": 10 }))
+        .await
+        .unwrap();
+    assert_eq!(res.status().as_u16(), 409);
+    let json: serde_json::Value = res.json().await.unwrap();
+    assert!(
+        json["error"]
+            .as_str()
+            .unwrap()
+            .contains(resting["id"].as_str().unwrap())
+    );
+    let rules: serde_json::Value = client
+        .get(format!("{}/symbols/BTCUSDT", base_url))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(rules["tick_size"], 1);
+    // Rescaling would change what every resting quantity means
+    let res = patch(&admin, serde_json::json!({ "qty_scale": 100 }))
+        .await
+        .unwrap();
+    assert_eq!(res.status().as_u16(), 409);
+    while let Ok(msg) = ws_rx.try_recv() {
+        assert!(!matches!(msg, WsMessage::SymbolConfigUpdate { .. }));
+    }
+
+    client
+        .delete(format!(
+            "{}/orders/{}?symbol=BTCUSDT",
+            base_url,
+            resting["id"].as_str().unwrap()
+        ))
+        .bearer_auth(&maker)
+        .send()
+        .await
+        .unwrap();
+    while ws_rx.try_recv().is_ok() {}
+    let res = patch(
+        &admin,
+        serde_json::json!({ "tick_size": 10, "maker_fee_bps": -2, "taker_fee_bps": 5 }),
+    )
+    .await
+    .unwrap();
+    assert_eq!(res.status().as_u16(), 200);
+    let json: serde_json::Value = res.json().await.unwrap();
+    assert_eq!(json["symbol"], "BTCUSDT");
+    assert_eq!(json["tick_size"], 10);
+    assert_eq!(json["maker_fee_bps"], -2);
+    assert!(matches_config_update(&mut ws_rx));
+
+    let res = order(105).await.unwrap();
+    assert_eq!(res.status().as_u16(), 400);
+    let json: serde_json::Value = res.json().await.unwrap();
+    assert_eq!(json["error_code"], "INVALID_TICK_SIZE");
+    assert_eq!(order(110).await.unwrap().status().as_u16(), 200);
+
+    patch(&admin, serde_json::json!({ "halted": true }))
+        .await
+        .unwrap();
+    let json: serde_json::Value = order(120).await.unwrap().json().await.unwrap();
+    assert_eq!(json["error_code"], "SYMBOL_HALTED");
+}
+
+#[tokio::test]
+async fn admin_cancel_closes_the_order_and_notifies_its_owner() {
+    let state = testing::test_app_state();
+    let book = state.orderbooks["BTCUSDT"].clone();
+    let mut ws_rx = state.ws_channel.subscribe();
+    let exchange = TestExchange::builder_with_state(state)
+        .admin()
+        .start()
+        .await;
+    let (client, base_url) = (exchange.client(), &exchange.base_url);
+    let admin = exchange.admin_token().token;
+    let Token {
+        user_id: maker_id,
+        token: maker,
+    } = exchange.register("maker", "secret").await;
+    let taker = exchange.register("taker", "secret").await.token;
+    place(client, base_url, &maker, "Sell", 100, 5).await;
+    place(client, base_url, &taker, "Buy", 100, 2).await;
+    let order_id = book.read().await.iter_orders().next().unwrap().1.id;
+    while ws_rx.try_recv().is_ok() {}
+
+    let cancel = |token: String| {
+        client
+            .delete(format!(
+                "{}/admin/orders/{}?symbol=BTCUSDT",
+                base_url, order_id
+            ))
+            .bearer_auth(token)
+            .send()
+    };
+    assert_eq!(cancel(maker.clone()).await.unwrap().status().as_u16(), 403);
+    let res = cancel(admin.clone()).await.unwrap();
+    assert_eq!(res.status().as_u16(), 200);
+    let json: serde_json::Value = res.json().await.unwrap();
+    assert_eq!(json["status"], "Cancelled");
+    assert_eq!(json["remaining_quantity"], 3);
+    assert_eq!(json["close_reason"], "ADMIN_CANCELLED");
+    assert_eq!(book.read().await.resting_count(), 0);
+
+    let closed = std::iter::from_fn(|| ws_rx.try_recv().ok()).find_map(|msg| match msg {
+        WsMessage::OrderClosed {
+            order_id: id,
+            user_id,
+            reason,
+            remaining_qty,
+            ..
+        } => Some((id, user_id, reason, remaining_qty)),
+        _ => None,
+    });
+    assert_eq!(
+        closed,
+        Some((order_id, maker_id, CloseReason::AdminCancelled, 3))
+    );
+
+    // A second cancel finds the closure rather than the order
+    let again = cancel(admin).await.unwrap();
+    assert_eq!(again.status().as_u16(), 200);
+    let json: serde_json::Value = again.json().await.unwrap();
+    assert_eq!(json["close_reason"], "ADMIN_CANCELLED");
+}
+
+#[tokio::test]
+async fn busting_a_trade_reverses_it_for_both_parties_and_hides_it() {
+    let state = testing::test_app_state();
+    let book = state.orderbooks["BTCUSDT"].clone();
+    let positions = state.positions.clone();
+    let market_data = state.market_data.clone();
+    let mut ws_rx = state.ws_channel.subscribe();
+    let exchange = TestExchange::builder_with_state(state)
+        .admin()
+        .start()
+        .await;
+    let (client, base_url) = (exchange.client(), &exchange.base_url);
+    let admin = exchange.admin_token().token;
+    let Token {
+        user_id: maker_id,
+        token: maker,
+    } = exchange.register("maker", "secret").await;
+    let Token {
+        user_id: taker_id,
+        token: taker,
+    } = exchange.register("taker", "secret").await;
+    // The trade to bust, then one more at 110 between the same parties
+    place(client, base_url, &maker, "Sell", 100, 2).await;
+    place(client, base_url, &taker, "Buy", 100, 2).await;
+    place(client, base_url, &maker, "Sell", 110, 2).await;
+    place(client, base_url, &taker, "Buy", 110, 2).await;
+    let trades = book.read().await.get_all_trades();
+    for trade in &trades {
+        market_data.record_trade("BTCUSDT", trade);
+    }
+    let busted = trades[0].clone();
+    while ws_rx.try_recv().is_ok() {}
+
+    let bust = |token: &str, id: Uuid, reason: &str| {
+        client
+            .post(format!("{}/admin/trades/{}/bust", base_url, id))
+            .bearer_auth(token)
+            .json(&serde_json::json!({ "reason": reason }))
+            .send()
+    };
+    let status = |res: reqwest::Response| res.status().as_u16();
+    assert_eq!(status(bust(&maker, busted.id, "mine").await.unwrap()), 403);
+    assert_eq!(status(bust(&admin, busted.id, "  ").await.unwrap()), 400);
+    assert_eq!(
+        status(bust(&admin, Uuid::new_v4(), "error trade").await.unwrap()),
+        404
+    );
+
+    let res = bust(&admin, busted.id, "error trade").await.unwrap();
+    assert_eq!(res.status().as_u16(), 200);
+    let json: serde_json::Value = res.json().await.unwrap();
+    assert_eq!(json["trade"]["id"], busted.id.to_string());
+    assert_eq!(json["reason"], "error trade");
+    assert_eq!(json["positions"].as_array().unwrap().len(), 2);
+
+    // Both are left with only the trade at 110
+    let position = |user_id| {
+        let positions = positions.clone();
+        async move {
+            let guard = positions.read().await;
+            let position = &guard[&(user_id, "BTCUSDT".to_string())];
+            (position.quantity, position.average_price)
+        }
+    };
+    assert_eq!(position(taker_id).await, (2, 110));
+    assert_eq!(position(maker_id).await, (-2, 110));
+
+    let public: serde_json::Value = client
+        .get(format!("{}/trades?symbol=BTCUSDT", base_url))
+        .bearer_auth(&taker)
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let ids: Vec<&str> = public["items"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|t| t["id"].as_str().unwrap())
+        .collect();
+    assert_eq!(ids, vec![trades[1].id.to_string()]);
+    let own: serde_json::Value = client
+        .get(format!("{}/trades/me", base_url))
+        .bearer_auth(&taker)
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let own = own["items"].as_array().unwrap();
+    assert_eq!(own.len(), 2);
+    assert_eq!(own[1]["id"], busted.id.to_string());
+    assert_eq!(own[1]["bust_reason"], "error trade");
+    assert!(own[0].get("busted_at").is_none());
+
+    let candles = market_data.recent_candles("BTCUSDT", Utc::now());
+    let totals: (u64, u64) = candles.iter().fold((0, 0), |(volume, count), c| {
+        (volume + c.volume, count + c.trade_count)
+    });
+    assert_eq!(totals, (2, 1));
+    assert_eq!(book.read().await.last_trade_price(), Some(110));
+
+    let notified: Vec<_> = std::iter::from_fn(|| ws_rx.try_recv().ok())
+        .filter_map(|msg| match msg {
+            WsMessage::TradeBusted {
+                trade_id,
+                user_id,
+                role,
+                reason,
+                ..
+            } => Some((trade_id, user_id, role, reason)),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(
+        notified,
+        vec![
+            (
+                busted.id,
+                maker_id,
+                TradeRole::Maker,
+                "error trade".to_string()
+            ),
+            (
+                busted.id,
+                taker_id,
+                TradeRole::Taker,
+                "error trade".to_string()
+            ),
+        ]
+    );
+
+    assert_eq!(status(bust(&admin, busted.id, "again").await.unwrap()), 409);
+    assert_eq!(position(taker_id).await, (2, 110));
+}
+
+/// Whether the next broadcast message is a `SymbolConfigUpdate` for BTCUSDT.
+fn matches_config_update(rx: &mut broadcast::Receiver<WsMessage>) -> bool {
+    matches!(
+        rx.try_recv(),
+        Ok(WsMessage::SymbolConfigUpdate { symbol, config }) if symbol == "BTCUSDT" && config.tick_size == 10
+    )
+}
+
+#[tokio::test]
+async fn self_test_trades_on_the_isolated_book_and_leaves_nothing_behind() {
+    let mut state = testing::test_app_state();
+    state.orderbooks.insert(
+        SELFTEST_SYMBOL.to_string(),
+        Arc::new(RwLock::new(OrderBook::new())),
+    );
+    let exchange = TestExchange::builder_with_state(state.clone())
+        .admin()
+        .start()
+        .await;
+    let (client, base_url) = (exchange.client(), &exchange.base_url);
+    let admin = exchange.admin_token().token;
+    let self_test = || {
+        client
+            .post(format!("{}/admin/self-test", base_url))
+            .bearer_auth(&admin)
+            .send()
+    };
+
+    let res = self_test().await.unwrap();
+    assert_eq!(res.status().as_u16(), 200);
+    let json: serde_json::Value = res.json().await.unwrap();
+    assert_eq!(json["passed"], true);
+    let statuses: Vec<(&str, &str)> = json["steps"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|s| (s["name"].as_str().unwrap(), s["status"].as_str().unwrap()))
+        .collect();
+    assert_eq!(
+        statuses,
+        [
+            ("isolation", "passed"),
+            ("register_users", "passed"),
+            ("place_orders", "passed"),
+            ("trades", "passed"),
+            ("positions", "passed"),
+            ("persistence", "skipped"),
+            ("ws_broadcasts", "passed"),
+            ("cleanup", "passed"),
+        ]
+    );
+
+    // No user is left, and the book retains no orders, trades or positions
+    assert!(state.user_store.read().await.is_empty());
+    let book = state.orderbooks[SELFTEST_SYMBOL].read().await;
+    assert_eq!(book.iter_orders().count(), 0);
+    assert!(book.last_trade_price().is_none());
+    assert_eq!(book.last_trade_seq(), 1);
+    drop(book);
+    assert!(state.positions.read().await.is_empty());
+
+    // A resting order on the book refuses the run and is left alone
+    let maker = exchange.register("maker", "secret").await.token;
+    let res = client
+        .post(format!("{}/orders", base_url))
+        .bearer_auth(&maker)
+        .json(&serde_json::json!({
+            "symbol": SELFTEST_SYMBOL,
+            "price": 100,
+            "quantity": 1,
+            "side": "Sell"
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status().as_u16(), 200);
+    let res = self_test().await.unwrap();
+    assert_eq!(res.status().as_u16(), 409);
+    let json: serde_json::Value = res.json().await.unwrap();
+    assert_eq!(json["refused"], true);
+    assert_eq!(json["steps"].as_array().unwrap().len(), 1);
+    assert_eq!(json["steps"][0]["status"], "failed");
+    let book = state.orderbooks[SELFTEST_SYMBOL].read().await;
+    assert_eq!(book.iter_orders().count(), 1);
+}
+
+#[tokio::test]
+async fn resting_order_caps_reject_with_book_full_until_an_admin_raises_them() {
+    let mut state = testing::test_app_state();
+    state.order_limits = Arc::new(OrderLimits::new(RestingOrderCaps {
+        global: Some(10),
+        per_symbol: Some(2),
+    }));
+    state.orderbooks["BTCUSDT"]
+        .write()
+        .await
+        .set_order_limits(state.order_limits.clone());
+    let exchange = TestExchange::builder_with_state(state)
+        .admin()
+        .start()
+        .await;
+    let (client, base_url) = (exchange.client(), &exchange.base_url);
+    let admin = exchange.admin_token().token;
+    let maker = exchange.register("maker", "secret").await.token;
+    let sell = |price: i64| {
+        client
+            .post(format!("{}/orders", base_url))
+            .bearer_auth(&maker)
+            .json(&serde_json::json!({
+                "symbol": "BTCUSDT",
+                "price": price,
+                "quantity": 1,
+                "side": "Sell"
+            }))
+            .send()
+    };
+    let limits = || async {
+        client
+            .get(format!("{}/admin/order-limits", base_url))
+            .bearer_auth(&admin)
+            .send()
+            .await
+            .unwrap()
+            .json::<serde_json::Value>()
+            .await
+            .unwrap()
+    };
+
+    assert_eq!(sell(100).await.unwrap().status().as_u16(), 200);
+    assert_eq!(sell(101).await.unwrap().status().as_u16(), 200);
+    let res = sell(102).await.unwrap();
+    assert_eq!(res.status().as_u16(), 503);
+    let json: serde_json::Value = res.json().await.unwrap();
+    assert_eq!(json["error_code"], "BOOK_FULL");
+
+    let json = limits().await;
+    assert_eq!(json["configured"]["per_symbol"], 2);
+    assert_eq!(json["override"], serde_json::Value::Null);
+    assert_eq!(json["resting_orders"], 2);
+    assert_eq!(json["resting_orders_by_symbol"]["BTCUSDT"], 2);
+    assert_eq!(json["book_full_rejections"], 1);
+
+    // An override can only raise a cap
+    let set_override = |body: serde_json::Value| {
+        client
+            .put(format!("{}/admin/order-limits/override", base_url))
+            .bearer_auth(&admin)
+            .json(&body)
+            .send()
+    };
+    let res = set_override(serde_json::json!({ "per_symbol": 1, "duration_secs": 60 }))
+        .await
+        .unwrap();
+    assert_eq!(res.status().as_u16(), 400);
+    let res = set_override(serde_json::json!({ "per_symbol": 3, "duration_secs": 60 }))
+        .await
+        .unwrap();
+    assert_eq!(res.status().as_u16(), 200);
+    let json: serde_json::Value = res.json().await.unwrap();
+    assert_eq!(json["effective"]["per_symbol"], 3);
+    assert_eq!(json["effective"]["global"], 10);
+    assert_eq!(sell(102).await.unwrap().status().as_u16(), 200);
+    assert_eq!(sell(103).await.unwrap().status().as_u16(), 503);
+
+    let res = client
+        .delete(format!("{}/admin/order-limits/override", base_url))
+        .bearer_auth(&admin)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status().as_u16(), 204);
+    assert_eq!(limits().await["effective"]["per_symbol"], 2);
+
+    let json: serde_json::Value = client
+        .get(format!("{}/admin/stats/matching?symbol=BTCUSDT", base_url))
+        .bearer_auth(&admin)
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(json["resting_orders"], 3);
+    let body = client
+        .get(format!("{}/admin/metrics", base_url))
+        .bearer_auth(&admin)
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(body.contains("exchange_resting_orders{symbol=\"BTCUSDT\"} 3"));
+    assert!(body.contains("exchange_resting_orders_limit{scope=\"per_symbol\"} 2"));
+    assert!(body.contains("exchange_book_full_rejections_total 2"));
+}
+
+#[tokio::test]
+async fn admin_credits_and_withdraws_balances() {
+    let mut state = testing::test_app_state();
+    state.balances = Arc::new(Balances::new(true));
+    let exchange = TestExchange::builder_with_state(state)
+        .admin()
+        .start()
+        .await;
+    let (client, base_url) = (exchange.client(), &exchange.base_url);
+    let admin = exchange.admin_token().token;
+    let Token {
+        user_id,
+        token: user,
+    } = exchange.register("user", "secret").await;
+
+    let credit = |token: String, amount: i64| {
+        client
+            .post(format!("{}/admin/balances/credit", base_url))
+            .bearer_auth(token)
+            .json(&serde_json::json!({ "user_id": user_id, "asset": "USDT", "amount": amount }))
+            .send()
+    };
+    assert_eq!(
+        credit(user.clone(), 100).await.unwrap().status().as_u16(),
+        403
+    );
+    let res = credit(admin.clone(), 100).await.unwrap();
+    assert_eq!(res.status().as_u16(), 200);
+    let json: serde_json::Value = res.json().await.unwrap();
+    assert_eq!(json["asset"], "USDT");
+    assert_eq!(json["available"], 100);
+    assert_eq!(json["locked"], 0);
+
+    assert_eq!(
+        credit(admin.clone(), -150).await.unwrap().status().as_u16(),
+        400
+    );
+    let json: serde_json::Value = credit(admin.clone(), -40)
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(json["available"], 60);
+    let balances: serde_json::Value = client
+        .get(format!("{}/balances", base_url))
+        .bearer_auth(&user)
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(
+        balances,
+        serde_json::json!([{ "asset": "USDT", "available": 60, "locked": 0 }])
+    );
+}
+
+#[tokio::test]
+async fn account_metadata_tracks_logins_and_admins_list_users_by_last_activity() {
+    let exchange = TestExchange::builder().admin().start().await;
+    let (client, base_url) = (exchange.client(), &exchange.base_url);
+    let admin = exchange.admin_token().token;
+    exchange.register("carol", "secret").await;
+    let alice_id = exchange.register("alice", "secret").await.user_id;
+    client
+        .post(format!("{}/auth/register", base_url))
+        .json(&serde_json::json!({ "username": "Bob", "password": "secret" }))
+        .send()
+        .await
+        .unwrap();
+
+    let me = |token: String| {
+        let client = client.clone();
+        let base_url = base_url.clone();
+        async move {
+            let res = client
+                .get(format!("{}/auth/me", base_url))
+                .bearer_auth(token)
+                .send()
+                .await
+                .unwrap();
+            assert_eq!(res.status(), 200);
+            res.json::<serde_json::Value>().await.unwrap()
+        }
+    };
+    let timestamp = |value: &serde_json::Value| {
+        value
+            .as_str()
+            .unwrap()
+            .parse::<chrono::DateTime<Utc>>()
+            .unwrap()
+    };
+
+    let token = exchange.login("alice", "secret").await.unwrap().token;
+    let first = me(token).await;
+    assert_eq!(first["user_id"], alice_id.to_string());
+    assert_eq!(first["username"], "alice");
+    assert_eq!(first["login_count"], 2);
+    assert!(timestamp(&first["last_login_at"]) > timestamp(&first["created_at"]));
+
+    let token = exchange.login("alice", "secret").await.unwrap().token;
+    let second = me(token).await;
+    assert_eq!(second["login_count"], 3);
+    assert!(timestamp(&second["last_login_at"]) > timestamp(&first["last_login_at"]));
+    assert_eq!(second["created_at"], first["created_at"]);
+
+    let usernames = |sort: &'static str| {
+        let client = client.clone();
+        let url = format!("{}/admin/users?sort={}", base_url, sort);
+        let admin = admin.clone();
+        async move {
+            let json: serde_json::Value = client
+                .get(url)
+                .bearer_auth(admin)
+                .send()
+                .await
+                .unwrap()
+                .json()
+                .await
+                .unwrap();
+            assert_eq!(json["total"], 3);
+            json["users"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|user| user["username"].as_str().unwrap().to_string())
+                .collect::<Vec<_>>()
+        }
+    };
+    // Bob never logged in, so comes last
+    assert_eq!(usernames("last_login").await, ["alice", "carol", "Bob"]);
+    assert_eq!(usernames("created_at").await, ["Bob", "alice", "carol"]);
+    assert_eq!(usernames("username").await, ["alice", "Bob", "carol"]);
+}
+
+#[tokio::test]
+async fn activity_feed_merges_public_events_newest_first() {
+    let exchange = TestExchange::builder().admin().start().await;
+    let (client, base_url) = (exchange.client(), &exchange.base_url);
+    let admin = exchange.admin_token().token;
+    let maker = exchange.register("maker", "secret").await.token;
+    let taker = exchange.register("taker", "secret").await.token;
+    let post = |path: &str, body: serde_json::Value| {
+        client
+            .post(format!("{}{}", base_url, path))
+            .bearer_auth(&admin)
+            .json(&body)
+            .send()
+    };
+    let halt = |halted: bool| {
+        client
+            .patch(format!("{}/admin/symbols/BTCUSDT", base_url))
+            .bearer_auth(&admin)
+            .json(&serde_json::json!({ "halted": halted }))
+            .send()
+    };
+
+    place(client, base_url, &maker, "Sell", 100, 2).await;
+    place(client, base_url, &taker, "Buy", 100, 2).await;
+    assert_eq!(halt(true).await.unwrap().status().as_u16(), 200);
+    assert_eq!(halt(false).await.unwrap().status().as_u16(), 200);
+    let symbol = serde_json::json!({ "symbol": "BTCUSDT" });
+    post("/admin/auction/start", symbol.clone()).await.unwrap();
+    place(client, base_url, &taker, "Buy", 101, 3).await;
+    place(client, base_url, &maker, "Sell", 99, 3).await;
+    post("/admin/auction/end", symbol).await.unwrap();
+
+    let activity = |query: &str| {
+        let url = format!("{}/activity{}", base_url, query);
+        let client = &client;
+        async move { client.get(url).send().await.unwrap() }
+    };
+    let events: Vec<serde_json::Value> = activity("").await.json().await.unwrap();
+    let types: Vec<&str> = events.iter().map(|e| e["type"].as_str().unwrap()).collect();
+    assert_eq!(
+        types,
+        [
+            "AuctionResult",
+            "Trade",
+            "AuctionStarted",
+            "Resumed",
+            "Halted",
+            "Trade"
+        ]
+    );
+    let seqs: Vec<u64> = events.iter().map(|e| e["seq"].as_u64().unwrap()).collect();
+    assert_eq!(seqs, [6, 5, 4, 3, 2, 1]);
+    let times: Vec<chrono::DateTime<Utc>> = events
+        .iter()
+        .map(|e| serde_json::from_value(e["timestamp"].clone()).unwrap())
+        .collect();
+    assert!(times.windows(2).all(|w| w[0] >= w[1]));
+    // Trades carry the market facts only, never the parties
+    let trade = events[5].as_object().unwrap();
+    let mut keys: Vec<&str> = trade.keys().map(String::as_str).collect();
+    keys.sort();
+    assert_eq!(
+        keys,
+        [
+            "price",
+            "quantity",
+            "seq",
+            "symbol",
+            "timestamp",
+            "trade_seq",
+            "type"
+        ]
+    );
+    assert_eq!(
+        (trade["price"].as_i64(), trade["quantity"].as_u64()),
+        (Some(100), Some(2))
+    );
+    assert_eq!(events[0]["clearing_price"], events[1]["price"]);
+    assert_eq!(events[0]["volume"], 3);
+
+    let filtered: Vec<serde_json::Value> = activity("?types=Halted,%20Resumed")
+        .await
+        .json()
+        .await
+        .unwrap();
+    let types: Vec<&str> = filtered
+        .iter()
+        .map(|e| e["type"].as_str().unwrap())
+        .collect();
+    assert_eq!(types, ["Resumed", "Halted"]);
+    let limited: Vec<serde_json::Value> =
+        activity("?types=Trade&limit=1").await.json().await.unwrap();
+    assert_eq!(limited.len(), 1);
+    assert_eq!(limited[0]["seq"], 5);
+
+    assert_eq!(activity("?types=Trade,Bogus").await.status().as_u16(), 400);
+    assert_eq!(activity("?types=,").await.status().as_u16(), 400);
+}
+
+#[test]
+fn activity_feed_keeps_only_the_newest_public_events() {
+    let feed = ActivityFeed::new(3);
+    for symbol in ["A", "B", "SBXPRIVATE", "C", "SELFTEST", "D", "E"] {
+        feed.record(ActivityKind::Halted {
+            symbol: symbol.to_string(),
+        });
+    }
+    let recent = feed.recent(10, None);
+    let kept: Vec<(u64, &str)> = recent.iter().map(|e| (e.seq, e.kind.symbol())).collect();
+    assert_eq!(kept, [(5, "E"), (4, "D"), (3, "C")]);
+    assert_eq!(feed.recent(1, None).len(), 1);
+
+    let resumes = parse_types("Resumed").unwrap();
+    assert!(feed.recent(10, Some(&resumes)).is_empty());
+    assert!(parse_types(" , ").is_err());
+}
+
+#[tokio::test]
+async fn admin_book_changes_wait_their_turn_for_the_book() {
+    let exchange = TestExchange::builder()
+        .admin()
+        .user("maker", "secret")
+        .order(TEST_SYMBOL, "maker", OrderSide::Sell, 101, 1)
+        .start()
+        .await;
+    let admin = exchange.admin_token();
+    let book = exchange.state.orderbooks[TEST_SYMBOL].clone();
+    let held = book.write().await;
+    let order_id = held.iter_orders().next().unwrap().1.id;
+
+    // Like an order, each gives up with ENGINE_BUSY instead of waiting past its deadline
+    let requests = [
+        exchange.client().delete(exchange.url(&format!(
+            "/admin/orders/{}?symbol={}",
+            order_id, TEST_SYMBOL
+        ))),
+        exchange
+            .client()
+            .post(exchange.url("/admin/auction/end"))
+            .json(&serde_json::json!({ "symbol": TEST_SYMBOL })),
+    ];
+    for request in requests {
+        let res = tokio::time::timeout(
+            Duration::from_secs(2),
+            request
+                .bearer_auth(&admin.token)
+                .header("X-Request-Timeout-Ms", "50")
+                .send(),
+        )
+        .await
+        .expect("the request should give up instead of hanging")
+        .unwrap();
+        assert_eq!(res.status().as_u16(), 503);
+        let json: serde_json::Value = res.json().await.unwrap();
+        assert_eq!(json["error_code"], "ENGINE_BUSY");
+    }
+    assert_eq!(
+        exchange.state.lock_waits.snapshot()[TEST_SYMBOL].timeouts,
+        2
+    );
+
+    drop(held);
+    let res = exchange
+        .client()
+        .delete(exchange.url(&format!(
+            "/admin/orders/{}?symbol={}",
+            order_id, TEST_SYMBOL
+        )))
+        .bearer_auth(&admin.token)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status().as_u16(), 200);
+}
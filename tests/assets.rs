@@ -0,0 +1,95 @@
+//! The asset registry and the base/quote assets symbols carry.
+
+use rust_exchange::types::asset::{ASSETS, Asset, asset_info, split_symbol};
+use rust_exchange::types::symbol::{SymbolConfig, SymbolConfigPatch};
+use serde_json::json;
+
+fn asset(code: &str) -> Asset {
+    Asset::parse(code).unwrap()
+}
+
+#[test]
+fn asset_codes_are_parsed_uppercase() {
+    assert_eq!(asset(" eth ").as_str(), "ETH");
+    assert_eq!(asset("usdc").to_string(), "USDC");
+    assert!(Asset::parse("").is_err());
+    assert!(Asset::parse("ETH-X").is_err());
+    assert!(Asset::parse("ABCDEFGHIJK").is_err());
+    // Parsing does not require registration; configs do
+    assert!(asset("XYZ").info().is_none());
+    assert_eq!(asset_info("BTC").unwrap().display_scale, 100_000_000);
+    assert!(ASSETS.windows(2).all(|w| w[0].code < w[1].code));
+
+    assert_eq!(serde_json::to_value(asset("sol")).unwrap(), json!("SOL"));
+    assert_eq!(
+        serde_json::from_value::<Asset>(json!("doge")).unwrap(),
+        asset("DOGE")
+    );
+    assert!(serde_json::from_value::<Asset>(json!("B T C")).is_err());
+}
+
+#[test]
+fn symbols_split_into_registered_base_and_quote() {
+    let split = |symbol| split_symbol(symbol).map(|(b, q)| (b.to_string(), q.to_string()));
+    let pair = |base: &str, quote: &str| Some((base.to_string(), quote.to_string()));
+    assert_eq!(split("BTCUSDT"), pair("BTC", "USDT"));
+    assert_eq!(split("ETHBTC"), pair("ETH", "BTC"));
+    assert_eq!(split("SOLEUR"), pair("SOL", "EUR"));
+    // USDC and USD both end the name; only USDC leaves a registered base
+    assert_eq!(split("BTCUSDC"), pair("BTC", "USDC"));
+    assert_eq!(split("BTCUSD"), pair("BTC", "USD"));
+    assert_eq!(split("USDTUSD"), pair("USDT", "USD"));
+    assert_eq!(split("BTC"), None);
+    assert_eq!(split("XYZUSDT"), None);
+    assert_eq!(split("SELFTEST"), None);
+}
+
+#[test]
+fn configs_carry_validated_assets() {
+    let config = SymbolConfig::for_symbol("ETHBTC");
+    assert_eq!(config.assets(), Some((asset("ETH"), asset("BTC"))));
+    assert_eq!(SymbolConfig::for_symbol("SELFTEST").assets(), None);
+
+    let usd = SymbolConfig::default()
+        .with_assets(asset("BTC"), asset("USD"))
+        .unwrap();
+    assert_eq!(usd.assets(), Some((asset("BTC"), asset("USD"))));
+    assert!(
+        SymbolConfig::default()
+            .with_assets(asset("XYZ"), asset("USD"))
+            .unwrap_err()
+            .contains("XYZ")
+    );
+    assert!(
+        SymbolConfig::default()
+            .with_assets(asset("BTC"), asset("BTC"))
+            .is_err()
+    );
+
+    // Patches change the rules, never the assets
+    let patched = config
+        .apply(&SymbolConfigPatch {
+            tick_size: Some(5),
+            ..Default::default()
+        })
+        .unwrap();
+    assert_eq!(patched.assets(), config.assets());
+    assert!(serde_json::from_value::<SymbolConfigPatch>(json!({ "quote_asset": "USDT" })).is_err());
+
+    let json = serde_json::to_value(config).unwrap();
+    assert_eq!(
+        (&json["base_asset"], &json["quote_asset"]),
+        (&json!("ETH"), &json!("BTC"))
+    );
+    assert_eq!(
+        serde_json::from_value::<SymbolConfig>(json).unwrap(),
+        config
+    );
+    // Configs written before symbols had assets read back without them
+    let mut legacy = serde_json::to_value(SymbolConfig::default()).unwrap();
+    let fields = legacy.as_object_mut().unwrap();
+    fields.remove("base_asset");
+    fields.remove("quote_asset");
+    let legacy = serde_json::from_value::<SymbolConfig>(legacy).unwrap();
+    assert_eq!(legacy.assets(), None);
+}
@@ -1,58 +1,35 @@
 //! Integration tests for auth: register, login, and user store.
 
-use rust_exchange::api::auth::{self, AuthUserCredential};
-use rust_exchange::api::routes::{AppState, UserStore, app_router};
-use rust_exchange::orderbook::orderbook::OrderBook;
-use rust_exchange::positions::SharedPositions;
-use std::collections::HashMap;
+use rust_exchange::api::auth::UsernameHistory;
+use rust_exchange::testing::{TestExchange, Token};
 use std::sync::Arc;
-use tokio::sync::{broadcast, RwLock};
-use uuid::Uuid;
-
-fn test_app_state(user_store: UserStore) -> AppState {
-    let mut orderbooks = HashMap::new();
-    orderbooks.insert(
-        "BTCUSDT".to_string(),
-        Arc::new(RwLock::new(OrderBook::new())),
-    );
-    let (ws_tx, _) = broadcast::channel(1000);
-    let positions: SharedPositions = Arc::new(RwLock::new(HashMap::new()));
-    let jwt_secret = b"test-jwt-secret".to_vec();
-    AppState {
-        orderbooks,
-        ws_channel: ws_tx,
-        positions,
-        jwt_secret,
-        user_store,
-        db: None,
-    }
-}
-
-/// Spawn app on a random port and return (base_url, guard that keeps server running).
-async fn spawn_app(state: AppState) -> (String, tokio::task::JoinHandle<()>) {
-    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
-    let addr = listener.local_addr().unwrap();
-    let base_url = format!("http://{}", addr);
-    let app = app_router(state);
-    let handle = tokio::spawn(async move {
-        axum::serve(listener, app).await.unwrap();
-    });
-    (base_url, handle)
+use std::time::Duration;
+
+async fn register(exchange: &TestExchange, username: &str, password: &str) -> reqwest::Response {
+    exchange
+        .client()
+        .post(exchange.url("/auth/register"))
+        .json(&serde_json::json!({ "username": username, "password": password }))
+        .send()
+        .await
+        .unwrap()
 }
 
-#[tokio::test]
-async fn register_returns_201_with_user_id_and_username() {
-    let user_store: UserStore = Arc::new(RwLock::new(HashMap::new()));
-    let state = test_app_state(user_store);
-    let (base_url, _handle) = spawn_app(state).await;
-    let client = reqwest::Client::new();
-
-    let res = client
-        .post(format!("{}/auth/register", base_url))
-        .json(&serde_json::json!({ "username": "alice", "password": "secret123" }))
+async fn login(exchange: &TestExchange, username: &str, password: &str) -> reqwest::Response {
+    exchange
+        .client()
+        .post(exchange.url("/auth/login"))
+        .json(&serde_json::json!({ "username": username, "password": password }))
         .send()
         .await
-        .unwrap();
+        .unwrap()
+}
+
+#[tokio::test]
+async fn register_returns_201_with_user_id_and_username() {
+    let exchange = TestExchange::start().await;
+
+    let res = register(&exchange, "alice", "secret123").await;
 
     assert_eq!(res.status().as_u16(), 201);
     let json: serde_json::Value = res.json().await.unwrap();
@@ -62,183 +39,209 @@ async fn register_returns_201_with_user_id_and_username() {
 
 #[tokio::test]
 async fn register_empty_username_returns_400() {
-    let user_store: UserStore = Arc::new(RwLock::new(HashMap::new()));
-    let state = test_app_state(user_store);
-    let (base_url, _handle) = spawn_app(state).await;
-    let client = reqwest::Client::new();
-
-    let res = client
-        .post(format!("{}/auth/register", base_url))
-        .json(&serde_json::json!({ "username": "", "password": "secret123" }))
-        .send()
-        .await
-        .unwrap();
+    let exchange = TestExchange::start().await;
+
+    let res = register(&exchange, "", "secret123").await;
 
     assert_eq!(res.status().as_u16(), 400);
     let json: serde_json::Value = res.json().await.unwrap();
-    assert!(json.get("error").unwrap().as_str().unwrap().contains("required"));
+    assert!(
+        json.get("error")
+            .unwrap()
+            .as_str()
+            .unwrap()
+            .contains("required")
+    );
 }
 
 #[tokio::test]
 async fn register_empty_password_returns_400() {
-    let user_store: UserStore = Arc::new(RwLock::new(HashMap::new()));
-    let state = test_app_state(user_store);
-    let (base_url, _handle) = spawn_app(state).await;
-    let client = reqwest::Client::new();
-
-    let res = client
-        .post(format!("{}/auth/register", base_url))
-        .json(&serde_json::json!({ "username": "alice", "password": "" }))
-        .send()
-        .await
-        .unwrap();
+    let exchange = TestExchange::start().await;
+
+    let res = register(&exchange, "alice", "").await;
 
     assert_eq!(res.status().as_u16(), 400);
     let json: serde_json::Value = res.json().await.unwrap();
-    assert!(json.get("error").unwrap().as_str().unwrap().contains("required"));
+    assert!(
+        json.get("error")
+            .unwrap()
+            .as_str()
+            .unwrap()
+            .contains("required")
+    );
 }
 
 #[tokio::test]
 async fn register_duplicate_username_returns_400() {
-    let user_store: UserStore = Arc::new(RwLock::new(HashMap::new()));
-    let state = test_app_state(user_store);
-    let (base_url, _handle) = spawn_app(state).await;
-    let client = reqwest::Client::new();
-
-    let r1 = client
-        .post(format!("{}/auth/register", base_url))
-        .json(&serde_json::json!({ "username": "bob", "password": "pass1" }))
-        .send()
-        .await
-        .unwrap();
+    let exchange = TestExchange::start().await;
+
+    let r1 = register(&exchange, "bob", "pass1").await;
     assert_eq!(r1.status().as_u16(), 201);
 
-    let r2 = client
-        .post(format!("{}/auth/register", base_url))
-        .json(&serde_json::json!({ "username": "bob", "password": "pass2" }))
-        .send()
-        .await
-        .unwrap();
+    let r2 = register(&exchange, "bob", "pass2").await;
     assert_eq!(r2.status().as_u16(), 400);
     let json: serde_json::Value = r2.json().await.unwrap();
-    assert!(json.get("error").unwrap().as_str().unwrap().contains("already taken"));
+    assert!(
+        json.get("error")
+            .unwrap()
+            .as_str()
+            .unwrap()
+            .contains("already taken")
+    );
 }
 
 #[tokio::test]
 async fn register_then_login_returns_token() {
-    let user_store: UserStore = Arc::new(RwLock::new(HashMap::new()));
-    let state = test_app_state(user_store);
-    let (base_url, _handle) = spawn_app(state).await;
-    let client = reqwest::Client::new();
-
-    let reg = client
-        .post(format!("{}/auth/register", base_url))
-        .json(&serde_json::json!({ "username": "carol", "password": "mypass" }))
-        .send()
-        .await
-        .unwrap();
+    let exchange = TestExchange::start().await;
+
+    let reg = register(&exchange, "carol", "mypass").await;
     assert_eq!(reg.status().as_u16(), 201);
 
-    let login = client
-        .post(format!("{}/auth/login", base_url))
-        .json(&serde_json::json!({ "username": "carol", "password": "mypass" }))
-        .send()
-        .await
-        .unwrap();
-    assert_eq!(login.status().as_u16(), 200);
-    let json: serde_json::Value = login.json().await.unwrap();
+    let res = login(&exchange, "carol", "mypass").await;
+    assert_eq!(res.status().as_u16(), 200);
+    let json: serde_json::Value = res.json().await.unwrap();
     assert!(json.get("token").and_then(|v| v.as_str()).is_some());
     assert!(json.get("user_id").and_then(|v| v.as_str()).is_some());
 }
 
 #[tokio::test]
 async fn login_case_insensitive_username() {
-    let user_store: UserStore = Arc::new(RwLock::new(HashMap::new()));
-    let state = test_app_state(user_store);
-    let (base_url, _handle) = spawn_app(state).await;
-    let client = reqwest::Client::new();
-
-    let _ = client
-        .post(format!("{}/auth/register", base_url))
-        .json(&serde_json::json!({ "username": "Alice", "password": "secret" }))
-        .send()
-        .await
-        .unwrap();
+    let exchange = TestExchange::start().await;
+    let registered = exchange.register("Alice", "secret").await;
 
-    let login = client
-        .post(format!("{}/auth/login", base_url))
-        .json(&serde_json::json!({ "username": "alice", "password": "secret" }))
-        .send()
-        .await
-        .unwrap();
-    assert_eq!(login.status().as_u16(), 200);
-    let json: serde_json::Value = login.json().await.unwrap();
-    assert!(json.get("user_id").and_then(|v| v.as_str()).is_some());
+    let token = exchange.login("alice", "secret").await.unwrap();
+    assert_eq!(token.user_id, registered.user_id);
 }
 
 #[tokio::test]
 async fn login_wrong_password_returns_401() {
-    let user_store: UserStore = Arc::new(RwLock::new(HashMap::new()));
-    let state = test_app_state(user_store);
-    let (base_url, _handle) = spawn_app(state).await;
-    let client = reqwest::Client::new();
-
-    let _ = client
-        .post(format!("{}/auth/register", base_url))
-        .json(&serde_json::json!({ "username": "dave", "password": "right" }))
-        .send()
-        .await
-        .unwrap();
+    let exchange = TestExchange::start().await;
+    exchange.register("dave", "right").await;
 
-    let res = client
-        .post(format!("{}/auth/login", base_url))
-        .json(&serde_json::json!({ "username": "dave", "password": "wrong" }))
-        .send()
-        .await
-        .unwrap();
+    let res = login(&exchange, "dave", "wrong").await;
     assert_eq!(res.status().as_u16(), 401);
 }
 
 #[tokio::test]
 async fn login_unknown_user_returns_401() {
-    let user_store: UserStore = Arc::new(RwLock::new(HashMap::new()));
-    let state = test_app_state(user_store);
-    let (base_url, _handle) = spawn_app(state).await;
-    let client = reqwest::Client::new();
-
-    let res = client
-        .post(format!("{}/auth/login", base_url))
-        .json(&serde_json::json!({ "username": "nobody", "password": "any" }))
-        .send()
-        .await
-        .unwrap();
+    let exchange = TestExchange::start().await;
+
+    let res = login(&exchange, "nobody", "any").await;
     assert_eq!(res.status().as_u16(), 401);
 }
 
 #[tokio::test]
 async fn login_with_env_seeded_user() {
-    let user_id = Uuid::new_v4();
-    let password_hash = auth::hash_password("envpass").unwrap();
-    let cred = AuthUserCredential {
-        user_id,
-        username: "seeded".to_string(),
-        password_hash,
-    };
-    let mut map = HashMap::new();
-    map.insert("seeded".to_string(), cred);
-    let user_store: UserStore = Arc::new(RwLock::new(map));
-    let state = test_app_state(user_store);
-    let (base_url, _handle) = spawn_app(state).await;
-    let client = reqwest::Client::new();
-
-    let res = client
-        .post(format!("{}/auth/login", base_url))
-        .json(&serde_json::json!({ "username": "seeded", "password": "envpass" }))
+    let exchange = TestExchange::builder()
+        .user("seeded", "envpass")
+        .start()
+        .await;
+    let user_id = exchange.state.user_store.read().await["seeded"].user_id;
+
+    let res = login(&exchange, "seeded", "envpass").await;
+    assert_eq!(res.status().as_u16(), 200);
+    let json: serde_json::Value = res.json().await.unwrap();
+    let uid_str = json.get("user_id").and_then(|v| v.as_str()).unwrap();
+    assert_eq!(uid_str, user_id.to_string());
+}
+
+// --- Username change ---
+
+async fn change_username(
+    exchange: &TestExchange,
+    token: &Token,
+    new_username: &str,
+) -> reqwest::Response {
+    exchange
+        .client()
+        .post(exchange.url("/auth/change-username"))
+        .bearer_auth(&token.token)
+        .json(&serde_json::json!({ "new_username": new_username, "password": "secret" }))
+        .send()
+        .await
+        .unwrap()
+}
+
+async fn login_status(exchange: &TestExchange, username: &str) -> u16 {
+    login(exchange, username, "secret").await.status().as_u16()
+}
+
+#[tokio::test]
+async fn change_username_to_existing_name_returns_400() {
+    let exchange = TestExchange::start().await;
+    let alice = exchange.register("alice", "secret").await;
+    exchange.register("bob", "secret").await;
+
+    let res = change_username(&exchange, &alice, "BOB").await;
+    assert_eq!(res.status().as_u16(), 400);
+    assert_eq!(login_status(&exchange, "alice").await, 200);
+}
+
+#[tokio::test]
+async fn change_username_requires_current_password() {
+    let exchange = TestExchange::start().await;
+    let alice = exchange.register("alice", "secret").await;
+
+    let res = exchange
+        .client()
+        .post(exchange.url("/auth/change-username"))
+        .bearer_auth(&alice.token)
+        .json(&serde_json::json!({ "new_username": "alicia", "password": "wrong" }))
         .send()
         .await
         .unwrap();
+    assert_eq!(res.status().as_u16(), 401);
+    let res = change_username(&exchange, &alice, "  ").await;
+    assert_eq!(res.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn login_works_with_new_name_only() {
+    let exchange = TestExchange::start().await;
+    let alice = exchange.register("alice", "secret").await;
+
+    let res = change_username(&exchange, &alice, "Alicia").await;
     assert_eq!(res.status().as_u16(), 200);
     let json: serde_json::Value = res.json().await.unwrap();
-    let uid_str = json.get("user_id").and_then(|v| v.as_str()).unwrap();
-    assert_eq!(uid_str, user_id.to_string());
+    assert_eq!(json["username"], "Alicia");
+    assert_eq!(json["previous_username"], "alice");
+
+    assert_eq!(login_status(&exchange, "alicia").await, 200);
+    assert_eq!(login_status(&exchange, "alice").await, 401);
+    // The token issued before the rename is keyed by user id and still works
+    let res = change_username(&exchange, &alice, "ALICIA").await;
+    assert_eq!(res.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn released_name_is_reserved_during_cooldown() {
+    let exchange = TestExchange::start().await;
+    let alice = exchange.register("alice", "secret").await;
+    let bob = exchange.register("bob", "secret").await;
+    change_username(&exchange, &alice, "alicia").await;
+
+    let res = change_username(&exchange, &bob, "alice").await;
+    assert_eq!(res.status().as_u16(), 400);
+    let res = register(&exchange, "Alice", "secret").await;
+    assert_eq!(res.status().as_u16(), 400);
+
+    // The previous owner may take it back
+    let res = change_username(&exchange, &alice, "alice").await;
+    assert_eq!(res.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn released_name_is_available_after_cooldown() {
+    let exchange = TestExchange::builder()
+        .with_state(|state| {
+            state.username_history = Arc::new(UsernameHistory::new(Duration::ZERO));
+        })
+        .start()
+        .await;
+    let alice = exchange.register("alice", "secret").await;
+    let bob = exchange.register("bob", "secret").await;
+    change_username(&exchange, &alice, "alicia").await;
+
+    let res = change_username(&exchange, &bob, "alice").await;
+    assert_eq!(res.status().as_u16(), 200);
 }
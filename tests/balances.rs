@@ -0,0 +1,403 @@
+//! Balance locking through the HTTP API: what orders lock, what fills spend and what leaving
+//! the book (cancel, disconnect, replace) or a reduce releases.
+
+use rust_exchange::balances::Balances;
+use rust_exchange::testing::{OrderRequest, TEST_SYMBOL, TestExchange, Token};
+use rust_exchange::types::order::{OrderSide, RejectReason};
+use rust_exchange::types::symbol::SymbolConfig;
+use serde_json::json;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::RwLock;
+
+async fn exchange_with_balances() -> TestExchange {
+    TestExchange::builder()
+        .with_state(|state| state.balances = Arc::new(Balances::new(true)))
+        .start()
+        .await
+}
+
+async fn fund(exchange: &TestExchange, token: &Token, asset: &str, amount: i64) {
+    let mut ledger = exchange.state.balances.ledger().await.unwrap();
+    ledger.credit(token.user_id, asset, amount).unwrap();
+}
+
+/// `(available, locked)` of `asset` from `GET /balances`; zeros if the user has none.
+async fn balance(exchange: &TestExchange, token: &Token, asset: &str) -> (i64, i64) {
+    let balances = exchange.get(token, "/balances").await.unwrap();
+    balances
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|b| b["asset"] == asset)
+        .map_or((0, 0), |b| {
+            (
+                b["available"].as_i64().unwrap(),
+                b["locked"].as_i64().unwrap(),
+            )
+        })
+}
+
+#[tokio::test]
+async fn cancel_after_a_partial_fill_releases_only_the_unfilled_remainder() {
+    let exchange = exchange_with_balances().await;
+    let buyer = exchange.register("buyer", "secret").await;
+    let seller = exchange.register("seller", "secret").await;
+    fund(&exchange, &buyer, "USDT", 1_000).await;
+    fund(&exchange, &seller, "BTC", 10).await;
+
+    exchange
+        .place_order(
+            &seller,
+            &OrderRequest::limit(TEST_SYMBOL, OrderSide::Sell, 90, 3),
+        )
+        .await
+        .unwrap();
+    assert_eq!(balance(&exchange, &seller, "BTC").await, (7, 3));
+
+    // Locks 5 * 100, fills 3 at 90 and gets the 3 * 10 price improvement back
+    let bid = exchange
+        .place_order(
+            &buyer,
+            &OrderRequest::limit(TEST_SYMBOL, OrderSide::Buy, 100, 5),
+        )
+        .await
+        .unwrap();
+    assert_eq!(bid.trades.len(), 1);
+    assert_eq!(balance(&exchange, &buyer, "USDT").await, (530, 200));
+    assert_eq!(balance(&exchange, &buyer, "BTC").await, (3, 0));
+    assert_eq!(balance(&exchange, &seller, "BTC").await, (7, 0));
+    assert_eq!(balance(&exchange, &seller, "USDT").await, (270, 0));
+
+    exchange
+        .cancel_order(&buyer, TEST_SYMBOL, bid.order.id)
+        .await
+        .unwrap();
+    assert_eq!(balance(&exchange, &buyer, "USDT").await, (730, 0));
+    assert_eq!(balance(&exchange, &buyer, "BTC").await, (3, 0));
+}
+
+#[tokio::test]
+async fn disconnect_cancel_after_a_partial_fill_releases_only_the_unfilled_remainder() {
+    let exchange = exchange_with_balances().await;
+    let buyer = exchange.register("buyer", "secret").await;
+    let seller = exchange.register("seller", "secret").await;
+    fund(&exchange, &buyer, "USDT", 1_000).await;
+    fund(&exchange, &seller, "BTC", 5).await;
+
+    let mut ws = exchange.ws_client(Some(&seller)).await;
+    ws.send_json(json!({ "action": "cancel_on_disconnect", "enabled": true }))
+        .await;
+    assert_eq!(ws.next_json().await["status"], "success");
+
+    exchange
+        .place_order(
+            &seller,
+            &OrderRequest::limit(TEST_SYMBOL, OrderSide::Sell, 100, 5),
+        )
+        .await
+        .unwrap();
+    exchange
+        .place_order(
+            &buyer,
+            &OrderRequest::limit(TEST_SYMBOL, OrderSide::Buy, 100, 2),
+        )
+        .await
+        .unwrap();
+    assert_eq!(balance(&exchange, &seller, "BTC").await, (0, 3));
+    assert_eq!(balance(&exchange, &seller, "USDT").await, (200, 0));
+    assert_eq!(balance(&exchange, &buyer, "USDT").await, (800, 0));
+
+    drop(ws);
+    for _ in 0..50 {
+        if exchange.book(TEST_SYMBOL).await.asks.is_empty() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    assert!(exchange.book(TEST_SYMBOL).await.asks.is_empty());
+    assert_eq!(balance(&exchange, &seller, "BTC").await, (3, 0));
+    assert_eq!(balance(&exchange, &seller, "USDT").await, (200, 0));
+}
+
+#[tokio::test]
+async fn replace_relocks_for_the_new_price_and_quantity() {
+    let exchange = exchange_with_balances().await;
+    let buyer = exchange.register("buyer", "secret").await;
+    fund(&exchange, &buyer, "USDT", 1_000).await;
+
+    let bid = exchange
+        .place_order(
+            &buyer,
+            &OrderRequest::limit(TEST_SYMBOL, OrderSide::Buy, 100, 4),
+        )
+        .await
+        .unwrap();
+    assert_eq!(balance(&exchange, &buyer, "USDT").await, (600, 400));
+
+    let replace = |order_id: &str, price: i64, quantity: u64| {
+        exchange.send_json(
+            exchange
+                .client()
+                .post(exchange.url(&format!(
+                    "/orders/{}/replace?symbol={}",
+                    order_id, TEST_SYMBOL
+                )))
+                .bearer_auth(&buyer.token)
+                .json(&json!({ "price": price, "quantity": quantity })),
+        )
+    };
+    // 900 fits only because the replaced order's 400 is released first
+    let replaced = replace(&bid.order.id.to_string(), 150, 6).await.unwrap();
+    assert_eq!(balance(&exchange, &buyer, "USDT").await, (100, 900));
+
+    let new_id = replaced["report"]["order"]["id"].as_str().unwrap();
+    let error = replace(new_id, 200, 6).await.unwrap_err();
+    assert_eq!(error.rejection(), Some(RejectReason::InsufficientBalance));
+    assert_eq!(balance(&exchange, &buyer, "USDT").await, (100, 900));
+    assert_eq!(exchange.book(TEST_SYMBOL).await.bids, vec![(150, 6)]);
+}
+
+#[tokio::test]
+async fn reduce_releases_the_lock_of_the_trimmed_quantity() {
+    let exchange = exchange_with_balances().await;
+    let buyer = exchange.register("buyer", "secret").await;
+    fund(&exchange, &buyer, "USDT", 1_000).await;
+
+    let bid = exchange
+        .place_order(
+            &buyer,
+            &OrderRequest::limit(TEST_SYMBOL, OrderSide::Buy, 100, 5),
+        )
+        .await
+        .unwrap();
+    assert_eq!(balance(&exchange, &buyer, "USDT").await, (500, 500));
+
+    exchange
+        .send_json(
+            exchange
+                .client()
+                .post(exchange.url(&format!(
+                    "/orders/{}/reduce?symbol={}",
+                    bid.order.id, TEST_SYMBOL
+                )))
+                .bearer_auth(&buyer.token)
+                .json(&json!({ "quantity": 2 })),
+        )
+        .await
+        .unwrap();
+    assert_eq!(balance(&exchange, &buyer, "USDT").await, (800, 200));
+
+    exchange
+        .cancel_order(&buyer, TEST_SYMBOL, bid.order.id)
+        .await
+        .unwrap();
+    assert_eq!(balance(&exchange, &buyer, "USDT").await, (1_000, 0));
+}
+
+#[tokio::test]
+async fn orders_beyond_the_available_balance_are_rejected() {
+    let exchange = exchange_with_balances().await;
+    let buyer = exchange.register("buyer", "secret").await;
+    let seller = exchange.register("seller", "secret").await;
+    fund(&exchange, &buyer, "USDT", 500).await;
+
+    exchange
+        .place_order(
+            &buyer,
+            &OrderRequest::limit(TEST_SYMBOL, OrderSide::Buy, 100, 4),
+        )
+        .await
+        .unwrap();
+    let error = exchange
+        .place_order(
+            &buyer,
+            &OrderRequest::limit(TEST_SYMBOL, OrderSide::Buy, 100, 2),
+        )
+        .await
+        .unwrap_err();
+    assert_eq!(error.status.as_u16(), 400);
+    assert_eq!(error.rejection(), Some(RejectReason::InsufficientBalance));
+    let error = exchange
+        .place_order(
+            &seller,
+            &OrderRequest::limit(TEST_SYMBOL, OrderSide::Sell, 100, 1),
+        )
+        .await
+        .unwrap_err();
+    assert_eq!(error.rejection(), Some(RejectReason::InsufficientBalance));
+    assert_eq!(balance(&exchange, &buyer, "USDT").await, (100, 400));
+    assert_eq!(exchange.book(TEST_SYMBOL).await.bids, vec![(100, 4)]);
+}
+
+#[tokio::test]
+async fn fractional_quantities_settle_quote_amounts_over_the_quantity_scale() {
+    // Quantities in satoshis, from a minimum of 0.001 BTC
+    let config = SymbolConfig {
+        qty_scale: 100_000_000,
+        min_qty: 100_000,
+        ..SymbolConfig::for_symbol(TEST_SYMBOL)
+    };
+    let exchange = TestExchange::builder()
+        .with_state(|state| {
+            state.balances = Arc::new(Balances::new(true));
+            state.symbol_configs = Arc::new(RwLock::new(HashMap::from([(
+                TEST_SYMBOL.to_string(),
+                config,
+            )])));
+        })
+        .start()
+        .await;
+    let buyer = exchange.register("buyer", "secret").await;
+    let seller = exchange.register("seller", "secret").await;
+    fund(&exchange, &buyer, "USDT", 10_000).await;
+    fund(&exchange, &seller, "BTC", 100_000_000).await;
+    let place = |token: &Token, side: &str, quantity: serde_json::Value| {
+        exchange.send_json(
+            exchange
+                .client()
+                .post(exchange.url("/orders"))
+                .bearer_auth(&token.token)
+                .json(&json!({
+                    "symbol": TEST_SYMBOL, "side": side, "price": 10_000, "quantity": quantity
+                })),
+        )
+    };
+
+    let ask = place(&seller, "Sell", json!("0.5")).await.unwrap();
+    assert_eq!(ask["quantity"], 50_000_000);
+    assert_eq!(ask["quantity_decimal"], "0.5");
+    assert_eq!(
+        balance(&exchange, &seller, "BTC").await,
+        (50_000_000, 50_000_000)
+    );
+
+    // 0.25 BTC at 10_000 costs 2_500 USDT, locked and spent in quote units
+    let bid = place(&buyer, "Buy", json!("0.25")).await.unwrap();
+    assert_eq!(bid["status"], "Filled");
+    assert_eq!(bid["quantity_decimal"], "0");
+    assert_eq!(balance(&exchange, &buyer, "USDT").await, (7_500, 0));
+    assert_eq!(balance(&exchange, &buyer, "BTC").await, (25_000_000, 0));
+    assert_eq!(balance(&exchange, &seller, "USDT").await, (2_500, 0));
+    let resting = exchange
+        .get(
+            &seller,
+            &format!(
+                "/orders/{}?symbol={}",
+                ask["id"].as_str().unwrap(),
+                TEST_SYMBOL
+            ),
+        )
+        .await
+        .unwrap();
+    assert_eq!(resting["quantity_decimal"], "0.25");
+
+    let error = place(&buyer, "Buy", json!("0.0000000001"))
+        .await
+        .unwrap_err();
+    assert_eq!(error.status.as_u16(), 400);
+    assert_eq!(error.body["error_code"], "INVALID_QUANTITY");
+    let error = place(&buyer, "Buy", json!("0.0001")).await.unwrap_err();
+    assert_eq!(error.rejection(), Some(RejectReason::BelowMinQuantity));
+    // Plain numbers are still lots
+    let error = place(&buyer, "Buy", json!(99_999)).await.unwrap_err();
+    assert_eq!(error.rejection(), Some(RejectReason::BelowMinQuantity));
+    place(&buyer, "Buy", json!(100_000)).await.unwrap();
+    assert_eq!(balance(&exchange, &buyer, "USDT").await, (7_490, 0));
+    assert_eq!(
+        balance(&exchange, &seller, "BTC").await,
+        (50_000_000, 24_900_000)
+    );
+}
+
+#[tokio::test]
+async fn cross_pairs_lock_and_settle_in_their_own_assets() {
+    // ETH priced in BTC: 2 ETH at 0.05 BTC is 0.1 BTC, in satoshis
+    let config = SymbolConfig {
+        qty_scale: 100_000_000,
+        taker_fee_bps: 10,
+        ..SymbolConfig::for_symbol("ETHBTC")
+    };
+    let exchange = TestExchange::builder()
+        .symbol("ETHBTC")
+        .with_state(|state| {
+            state.balances = Arc::new(Balances::new(true));
+            state.symbol_configs =
+                Arc::new(RwLock::new(HashMap::from([("ETHBTC".to_string(), config)])));
+        })
+        .start()
+        .await;
+    let buyer = exchange.register("buyer", "secret").await;
+    let seller = exchange.register("seller", "secret").await;
+    fund(&exchange, &buyer, "BTC", 50_000_000).await;
+    fund(&exchange, &seller, "ETH", 300_000_000).await;
+
+    exchange
+        .place_order(
+            &buyer,
+            &OrderRequest::limit("ETHBTC", OrderSide::Buy, 5_000_000, 200_000_000),
+        )
+        .await
+        .unwrap();
+    assert_eq!(
+        balance(&exchange, &buyer, "BTC").await,
+        (40_000_000, 10_000_000)
+    );
+    exchange
+        .place_order(
+            &seller,
+            &OrderRequest::limit("ETHBTC", OrderSide::Sell, 5_000_000, 300_000_000),
+        )
+        .await
+        .unwrap();
+    assert_eq!(balance(&exchange, &seller, "ETH").await, (0, 100_000_000));
+    assert_eq!(balance(&exchange, &seller, "BTC").await, (10_000_000, 0));
+    assert_eq!(balance(&exchange, &buyer, "ETH").await, (200_000_000, 0));
+    assert_eq!(balance(&exchange, &buyer, "BTC").await, (40_000_000, 0));
+    assert_eq!(balance(&exchange, &buyer, "USDT").await, (0, 0));
+
+    // The taker fee is 10 bps of the BTC notional
+    let trades = exchange.trades_me(&seller, Some("ETHBTC")).await.unwrap();
+    assert_eq!(trades[0].fee, 10_000);
+
+    let symbols = exchange.get(&buyer, "/symbols").await.unwrap();
+    let pair = symbols
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|s| s["symbol"] == "ETHBTC")
+        .unwrap();
+    assert_eq!(
+        (&pair["base_asset"], &pair["quote_asset"]),
+        (&json!("ETH"), &json!("BTC"))
+    );
+    let btc = symbols
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|s| s["symbol"] == TEST_SYMBOL)
+        .unwrap();
+    assert_eq!(
+        (&btc["base_asset"], &btc["quote_asset"]),
+        (&json!("BTC"), &json!("USDT"))
+    );
+    let config = exchange.get(&buyer, "/symbols/ETHBTC").await.unwrap();
+    assert_eq!(config["quote_asset"], "BTC");
+}
+
+#[tokio::test]
+async fn balances_are_unavailable_unless_enabled() {
+    let exchange = TestExchange::start().await;
+    let user = exchange.register("user", "secret").await;
+
+    let error = exchange.get(&user, "/balances").await.unwrap_err();
+    assert_eq!(error.status.as_u16(), 503);
+    exchange
+        .place_order(
+            &user,
+            &OrderRequest::limit(TEST_SYMBOL, OrderSide::Buy, 100, 1),
+        )
+        .await
+        .unwrap();
+}
@@ -0,0 +1,225 @@
+//! The order book diff stream and its gap recovery: a `diffs` subscription carries
+//! `OrderBookDiff`s chained by `prev_seq`, and `get_diffs` (WebSocket) or `GET /book/diffs`
+//! replays the buffered ones after a sequence, or answers `snapshot_required` once the buffer
+//! no longer reaches back that far. Clients here rebuild the book from the diffs alone and
+//! check it against the server's snapshot checksum.
+
+use reqwest::StatusCode;
+use rust_exchange::orderbook::diff_buffer::book_checksum;
+use rust_exchange::testing::{OrderRequest, TEST_SYMBOL, TestExchange, Token, WsClient};
+use rust_exchange::types::order::OrderSide;
+use serde_json::{Value, json};
+use std::collections::BTreeMap;
+
+/// A client's copy of the book, built from diffs only.
+#[derive(Debug, Default)]
+struct LocalBook {
+    seq: u64,
+    bids: BTreeMap<i64, u64>,
+    asks: BTreeMap<i64, u64>,
+}
+
+impl LocalBook {
+    /// Apply a diff, which must follow on from the copy, and check the copy against its
+    /// checksum.
+    fn apply(&mut self, diff: &Value) {
+        assert_eq!(diff["prev_seq"], self.seq, "diff out of sequence: {}", diff);
+        for (side, levels) in [("bids", &mut self.bids), ("asks", &mut self.asks)] {
+            for level in diff[side].as_array().unwrap() {
+                let (price, quantity) = (level[0].as_i64().unwrap(), level[1].as_u64().unwrap());
+                if quantity == 0 {
+                    assert!(
+                        levels.remove(&price).is_some(),
+                        "{} removes a missing level",
+                        diff
+                    );
+                } else {
+                    levels.insert(price, quantity);
+                }
+            }
+        }
+        self.seq = diff["seq"].as_u64().unwrap();
+        assert_eq!(diff["checksum"], self.checksum(), "after {}", diff);
+    }
+
+    fn checksum(&self) -> u32 {
+        let bids: Vec<_> = self.bids.iter().rev().map(|(&p, &q)| (p, q)).collect();
+        let asks: Vec<_> = self.asks.iter().map(|(&p, &q)| (p, q)).collect();
+        book_checksum(&bids, &asks)
+    }
+}
+
+async fn subscribe_diffs(ws: &mut WsClient) {
+    ws.send_json(json!({"action": "subscribe", "symbol": TEST_SYMBOL, "channel": "diffs"}))
+        .await;
+    let ack = ws.next_json().await;
+    assert_eq!(ack["status"], "success", "{}", ack);
+    assert_eq!(ws.next_json().await["type"], "ExchangeInfo");
+}
+
+async fn place(exchange: &TestExchange, token: &Token, side: OrderSide, price: i64, qty: u64) {
+    exchange
+        .place_order(token, &OrderRequest::limit(TEST_SYMBOL, side, price, qty))
+        .await
+        .unwrap();
+}
+
+/// The server's snapshot of the whole book, with its sequence and checksum.
+async fn snapshot(exchange: &TestExchange) -> Value {
+    let url = exchange.url(&format!("/books?symbols={}&depth=500", TEST_SYMBOL));
+    let books = exchange
+        .send_json(exchange.client().get(url))
+        .await
+        .unwrap();
+    books["books"][TEST_SYMBOL].clone()
+}
+
+async fn diffs(
+    exchange: &TestExchange,
+    query: &str,
+) -> Result<Value, rust_exchange::testing::ApiError> {
+    let url = exchange.url(&format!("/book/diffs?symbol={}&{}", TEST_SYMBOL, query));
+    exchange.send_json(exchange.client().get(url)).await
+}
+
+#[tokio::test]
+async fn a_paused_client_replays_the_diffs_it_missed() {
+    let exchange = TestExchange::start().await;
+    let maker = exchange.register("maker", "secret").await;
+    let taker = exchange.register("taker", "secret").await;
+    let mut ws = exchange.ws_client(None).await;
+    subscribe_diffs(&mut ws).await;
+
+    let mut book = LocalBook::default();
+    place(&exchange, &maker, OrderSide::Sell, 101, 2).await;
+    let first = ws.next_json().await;
+    assert_eq!(first["type"], "OrderBookDiff");
+    assert_eq!(first["symbol"], TEST_SYMBOL);
+    assert_eq!(first["asks"], json!([[101, 2]]));
+    book.apply(&first);
+
+    // The client pauses (its connection drops) while the book moves on
+    drop(ws);
+    place(&exchange, &maker, OrderSide::Sell, 102, 1).await;
+    let bid = exchange
+        .place_order(
+            &maker,
+            &OrderRequest::limit(TEST_SYMBOL, OrderSide::Buy, 99, 3),
+        )
+        .await
+        .unwrap();
+    place(&exchange, &taker, OrderSide::Buy, 101, 1).await;
+    exchange
+        .cancel_order(&maker, TEST_SYMBOL, bid.order.id)
+        .await
+        .unwrap();
+    place(&exchange, &taker, OrderSide::Buy, 101, 1).await;
+
+    let mut ws = exchange.ws_client(None).await;
+    ws.send_json(json!({"action": "get_diffs", "symbol": "btcusdt", "from_seq": book.seq}))
+        .await;
+    let reply = ws.next_json().await;
+    assert_eq!(reply["action"], "get_diffs");
+    assert_eq!(reply["status"], "replayed");
+    assert_eq!(reply["symbol"], TEST_SYMBOL);
+    let missed = reply["diffs"].as_array().unwrap();
+    assert_eq!(missed.len(), 5);
+    for diff in missed {
+        book.apply(diff);
+    }
+    assert_eq!(reply["seq"], book.seq);
+
+    let server = snapshot(&exchange).await;
+    assert_eq!(server["seq"], book.seq);
+    assert_eq!(server["checksum"], book.checksum());
+    assert_eq!(server["asks"], json!([[102, 1]]));
+    assert_eq!(server["bids"], json!([]));
+
+    // Caught up: nothing more to replay
+    ws.send_json(json!({"action": "get_diffs", "symbol": TEST_SYMBOL, "from_seq": book.seq}))
+        .await;
+    let reply = ws.next_json().await;
+    assert_eq!(reply["status"], "replayed");
+    assert_eq!(reply["diffs"], json!([]));
+
+    ws.send_json(json!({"action": "get_diffs", "symbol": "DOGEUSDT", "from_seq": 0}))
+        .await;
+    let reply = ws.next_json().await;
+    assert_eq!(reply["status"], "error");
+    assert_eq!(reply["code"], "UNKNOWN_SYMBOL");
+}
+
+#[tokio::test]
+async fn diffs_older_than_the_buffer_need_a_snapshot() {
+    let exchange = TestExchange::builder()
+        .with_state(|state| {
+            state.orderbooks[TEST_SYMBOL]
+                .try_write()
+                .unwrap()
+                .set_diff_buffer_capacity(2);
+        })
+        .start()
+        .await;
+    let maker = exchange.register("maker", "secret").await;
+    for price in [101, 102, 103, 104] {
+        place(&exchange, &maker, OrderSide::Sell, price, 1).await;
+    }
+
+    let evicted = diffs(&exchange, "from_seq=0").await.unwrap();
+    assert_eq!(evicted["status"], "snapshot_required");
+    assert_eq!(evicted["diffs"], json!([]));
+    let oldest_seq = evicted["oldest_seq"].as_u64().unwrap();
+    assert!(oldest_seq > 0);
+
+    // From the oldest buffered sequence on, a page at a time through signed cursors
+    let first = diffs(&exchange, &format!("from_seq={}&limit=1", oldest_seq))
+        .await
+        .unwrap();
+    assert_eq!(first["status"], "replayed");
+    assert_eq!(first["diffs"][0]["prev_seq"], oldest_seq);
+    assert_eq!(first["diffs"][0]["asks"], json!([[103, 1]]));
+    let cursor = first["next_cursor"].as_str().unwrap();
+    let second = diffs(&exchange, &format!("cursor={}&limit=1", cursor))
+        .await
+        .unwrap();
+    assert_eq!(second["diffs"][0]["prev_seq"], first["diffs"][0]["seq"]);
+    assert_eq!(second["diffs"][0]["asks"], json!([[104, 1]]));
+    assert!(second.get("next_cursor").is_none());
+    let server = snapshot(&exchange).await;
+    assert_eq!(second["diffs"][0]["seq"], server["seq"]);
+    assert_eq!(second["diffs"][0]["checksum"], server["checksum"]);
+
+    let forged = match cursor.strip_suffix('A') {
+        Some(rest) => format!("{}B", rest),
+        None => format!("{}A", &cursor[..cursor.len() - 1]),
+    };
+    let err = diffs(&exchange, &format!("cursor={}", forged))
+        .await
+        .unwrap_err();
+    assert_eq!(err.status, StatusCode::BAD_REQUEST);
+    assert_eq!(err.body["error_code"], "INVALID_CURSOR");
+    let err = diffs(&exchange, "limit=1").await.unwrap_err();
+    assert_eq!(err.status, StatusCode::BAD_REQUEST);
+
+    let stats = exchange.state.orderbooks[TEST_SYMBOL].read().await.stats();
+    assert_eq!(stats.buffered_diffs, 2);
+    assert_eq!(stats.diff_buffer_capacity, 2);
+    assert_eq!(stats.diffs_evicted, 2);
+    assert!(stats.diff_buffer_bytes > 0);
+}
+
+#[tokio::test]
+async fn the_default_subscription_carries_no_diffs() {
+    let exchange = TestExchange::start().await;
+    let maker = exchange.register("maker", "secret").await;
+    let mut ws = exchange.ws_client(None).await;
+    ws.subscribe(TEST_SYMBOL).await;
+
+    place(&exchange, &maker, OrderSide::Sell, 101, 2).await;
+    let update = ws.next_json().await;
+    assert_eq!(update["type"], "OrderBookUpdate");
+    assert_eq!(update["asks"], json!([[101, 2]]));
+    assert!(update.get("diff").is_none());
+    place(&exchange, &maker, OrderSide::Sell, 102, 1).await;
+    assert_eq!(ws.next_json().await["type"], "OrderBookUpdate");
+}
@@ -0,0 +1,121 @@
+//! Startup without Postgres: `PERSISTENCE_MODE=memory` builds the same state as the database
+//! mode through `bootstrap::build_state`, with empty books and the configured seed users.
+
+use rust_exchange::bootstrap::{self, Config, PersistenceMode, SeedUser};
+use rust_exchange::testing::{OrderRequest, TestExchange};
+use rust_exchange::types::order::OrderSide;
+use uuid::Uuid;
+
+const SYMBOL: &str = "BTCUSDT";
+
+#[test]
+fn persistence_mode_and_seed_users_parse_from_their_env_syntax() {
+    assert_eq!("memory".parse(), Ok(PersistenceMode::Memory));
+    assert_eq!(" Postgres ".parse(), Ok(PersistenceMode::Postgres));
+    assert!("sqlite".parse::<PersistenceMode>().is_err());
+
+    let id = Uuid::new_v4();
+    let seeds =
+        bootstrap::parse_seed_users(&format!("alice:secret123:{}, bob:hunter22,", id)).unwrap();
+    assert_eq!(seeds.len(), 2);
+    assert_eq!(
+        seeds[0],
+        SeedUser {
+            username: "alice".into(),
+            password: "secret123".into(),
+            user_id: id,
+        }
+    );
+    assert_eq!(
+        (seeds[1].username.as_str(), seeds[1].password.as_str()),
+        ("bob", "hunter22")
+    );
+    assert!(bootstrap::parse_seed_users("").unwrap().is_empty());
+    assert!(bootstrap::parse_seed_users("nopassword").is_err());
+    assert!(bootstrap::parse_seed_users("carol:pw:not-a-uuid").is_err());
+}
+
+#[test]
+fn served_symbols_parse_normalized_without_duplicates() {
+    assert_eq!(
+        bootstrap::parse_symbols(" btcusdt,ETHUSDT,, BTCUSDT ").unwrap(),
+        ["BTCUSDT", "ETHUSDT"]
+    );
+    assert!(bootstrap::parse_symbols("").unwrap().is_empty());
+    assert!(bootstrap::parse_symbols("BTC-USDT").is_err());
+    assert!(bootstrap::parse_symbols("BTCUSDT@paper").is_err());
+}
+
+#[tokio::test]
+async fn lazy_symbols_are_hydrated_at_startup_without_a_database() {
+    let config = Config {
+        symbols: vec![SYMBOL.to_string()],
+        lazy_symbols: vec!["ETHUSDT".to_string()],
+        ..Config::memory()
+    };
+    let state = bootstrap::build_state(&config).await.unwrap();
+    let mut served: Vec<&String> = state.orderbooks.keys().collect();
+    served.sort();
+    assert_eq!(served, ["BTCUSDT", "ETHUSDT"]);
+    assert!(state.hydration_report.symbols_lazy.is_empty());
+    assert!(!state.lazy_books.is_pending("ETHUSDT"));
+}
+
+#[tokio::test]
+async fn memory_mode_boots_empty_books_and_seed_users_and_trades() {
+    let maker_id = Uuid::new_v4();
+    let config = Config {
+        seed_users: bootstrap::parse_seed_users(&format!(
+            "maker:password123:{},taker:password123",
+            maker_id
+        ))
+        .unwrap(),
+        ..Config::memory()
+    };
+    let state = bootstrap::build_state(&config).await.unwrap();
+    assert!(state.db.is_none());
+    for symbol in &config.symbols {
+        let book = state.orderbooks[symbol].read().await;
+        assert_eq!(
+            (book.best_bid(), book.best_ask()),
+            (None, None),
+            "{}",
+            symbol
+        );
+    }
+    bootstrap::spawn_background(&state, &config);
+    let exchange = TestExchange::builder_with_state(state).start().await;
+
+    let maker = exchange.login("maker", "password123").await.unwrap();
+    assert_eq!(maker.user_id, maker_id);
+    let taker = exchange.login("taker", "password123").await.unwrap();
+    assert!(exchange.login("maker", "wrong-password").await.is_err());
+
+    exchange
+        .place_order(
+            &maker,
+            &OrderRequest::limit(SYMBOL, OrderSide::Sell, 100, 3),
+        )
+        .await
+        .unwrap();
+    let report = exchange
+        .place_order(&taker, &OrderRequest::limit(SYMBOL, OrderSide::Buy, 100, 2))
+        .await
+        .unwrap();
+    assert_eq!(report.trades.len(), 1);
+    assert_eq!(report.trades[0].quantity, 2);
+    assert_eq!(exchange.book(SYMBOL).await.asks, vec![(100, 1)]);
+
+    let positions = exchange.positions(&taker).await.unwrap();
+    assert_eq!(positions.len(), 1);
+    assert_eq!(
+        (positions[0].quantity, positions[0].average_price),
+        (2, 100)
+    );
+    let positions = exchange.positions(&maker).await.unwrap();
+    assert_eq!(positions[0].quantity, -2);
+
+    // Registration works the same without a database
+    let newcomer = exchange.register("newcomer", "password123").await;
+    assert!(exchange.positions(&newcomer).await.unwrap().is_empty());
+}
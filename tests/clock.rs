@@ -0,0 +1,203 @@
+//! Time-driven behaviour stepped through with a `ManualClock` on `AppState`: the expiry
+//! sweeper, candle boundaries and token expiry move only when the clock does.
+
+use chrono::{DateTime, Utc};
+use rust_exchange::api::expiry;
+use rust_exchange::market_data;
+use rust_exchange::orderbook::clock::{Clock, ManualClock};
+use rust_exchange::testing::{OrderRequest, TEST_SYMBOL, TestExchange, Token};
+use rust_exchange::types::order::{CloseReason, OrderSide};
+use serde_json::{Value, json};
+use std::sync::Arc;
+use std::time::Duration;
+use uuid::Uuid;
+
+fn start_time() -> DateTime<Utc> {
+    "2026-01-01T00:00:00Z".parse().unwrap()
+}
+
+async fn start_at(clock: &Arc<ManualClock>) -> TestExchange {
+    TestExchange::builder().clock(clock.clone()).start().await
+}
+
+/// A sell quote at `price` that lapses unless refreshed within a minute.
+async fn place_quote(exchange: &TestExchange, token: &Token, price: i64) -> Value {
+    exchange
+        .send_json(
+            exchange
+                .client()
+                .post(exchange.url("/orders"))
+                .bearer_auth(&token.token)
+                .json(&json!({
+                    "symbol": TEST_SYMBOL,
+                    "side": "Sell",
+                    "price": price,
+                    "quantity": 1,
+                    "refresh_ttl_ms": 60_000,
+                })),
+        )
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn the_sweeper_expires_a_quote_when_the_clock_crosses_its_deadline() {
+    let clock = Arc::new(ManualClock::new(start_time()));
+    let exchange = start_at(&clock).await;
+    expiry::spawn_sweeper(exchange.state.clone());
+    let maker = exchange.register("maker", "secret").await;
+    let mut ws = exchange.ws_client(Some(&maker)).await;
+    let quote = place_quote(&exchange, &maker, 101).await;
+    assert_eq!(
+        exchange.state.order_expiry.next_deadline(),
+        Some(start_time() + chrono::Duration::seconds(60))
+    );
+
+    // Wall-clock time passing does nothing, nor does stepping short of the deadline
+    clock.advance(Duration::from_secs(59));
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert_eq!(exchange.book(TEST_SYMBOL).await.asks, vec![(101, 1)]);
+
+    clock.advance(Duration::from_secs(1));
+    let closed = ws.next_of_type("OrderClosed").await;
+    assert_eq!(closed["order_id"], quote["id"]);
+    assert_eq!(closed["reason"], "TTL_EXPIRED");
+    assert!(exchange.book(TEST_SYMBOL).await.asks.is_empty());
+    assert_eq!(exchange.state.order_expiry.next_deadline(), None);
+}
+
+#[tokio::test]
+async fn a_cancel_racing_the_sweeper_reports_whichever_closed_the_order() {
+    let clock = Arc::new(ManualClock::new(start_time()));
+    let exchange = start_at(&clock).await;
+    let maker = exchange.register("maker", "secret").await;
+    let raced = place_quote(&exchange, &maker, 101).await;
+    let swept = place_quote(&exchange, &maker, 102).await;
+    let raced_id: Uuid = raced["id"].as_str().unwrap().parse().unwrap();
+    let swept_id: Uuid = swept["id"].as_str().unwrap().parse().unwrap();
+    clock.advance(Duration::from_secs(60));
+
+    let (expired, cancelled) = tokio::join!(
+        expiry::expire_due(&exchange.state, clock.now()),
+        exchange.cancel_order(&maker, TEST_SYMBOL, raced_id),
+    );
+    let cancelled = cancelled.expect("the losing side still gets the order back");
+    let winner = if expired.iter().any(|c| c.order.id == raced_id) {
+        CloseReason::TtlExpired
+    } else {
+        CloseReason::UserCancelled
+    };
+    assert_eq!(cancelled["id"], raced["id"]);
+    assert_eq!(cancelled["status"], "Cancelled");
+    assert_eq!(cancelled["close_reason"], winner.code());
+    assert!(exchange.book(TEST_SYMBOL).await.asks.is_empty());
+
+    // The sweeper certainly got to the other quote first; cancelling it answers with its reason
+    assert!(expired.iter().any(|c| c.order.id == swept_id));
+    let late = exchange
+        .cancel_order(&maker, TEST_SYMBOL, swept_id)
+        .await
+        .unwrap();
+    assert_eq!(late["close_reason"], "TTL_EXPIRED");
+    assert_eq!(late["remaining_quantity"], 1);
+    // Repeating either cancel, or sweeping again, changes nothing
+    let again = exchange
+        .cancel_order(&maker, TEST_SYMBOL, raced_id)
+        .await
+        .unwrap();
+    assert_eq!(again["close_reason"], winner.code());
+    assert!(
+        expiry::expire_due(&exchange.state, clock.now())
+            .await
+            .is_empty()
+    );
+
+    // Someone else's cancel of a closed order is still a 404
+    let other = exchange.register("other", "secret").await;
+    let refused = exchange
+        .cancel_order(&other, TEST_SYMBOL, swept_id)
+        .await
+        .unwrap_err();
+    assert_eq!(refused.status, 404);
+}
+
+#[tokio::test]
+async fn trades_either_side_of_a_minute_fall_in_separate_candles() {
+    let clock = Arc::new(ManualClock::new(
+        start_time() + chrono::Duration::seconds(50),
+    ));
+    let exchange = start_at(&clock).await;
+    market_data::spawn_aggregator(
+        &exchange.state.tasks,
+        exchange.state.market_data.clone(),
+        &exchange.state.ws_channel,
+        None,
+    );
+    let maker = exchange.register("maker", "secret").await;
+    let taker = exchange.register("taker", "secret").await;
+    let trade = |price| {
+        let (exchange, maker, taker) = (&exchange, &maker, &taker);
+        async move {
+            exchange
+                .place_order(
+                    maker,
+                    &OrderRequest::limit(TEST_SYMBOL, OrderSide::Sell, price, 1),
+                )
+                .await
+                .unwrap();
+            exchange
+                .place_order(
+                    taker,
+                    &OrderRequest::limit(TEST_SYMBOL, OrderSide::Buy, price, 1),
+                )
+                .await
+                .unwrap();
+        }
+    };
+    let candles = || async {
+        for _ in 0..50 {
+            let klines = exchange
+                .get(&maker, &format!("/klines/recent?symbol={}", TEST_SYMBOL))
+                .await
+                .unwrap();
+            if klines["candles"].as_array().unwrap().len() == 2 {
+                return klines["candles"].clone();
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        panic!("the second candle never opened");
+    };
+
+    trade(100).await;
+    clock.advance(Duration::from_secs(9));
+    trade(105).await;
+    // 00:00:59 is still in the first minute; 00:01:00 opens the next
+    clock.advance(Duration::from_secs(1));
+    trade(95).await;
+
+    let candles = candles().await;
+    assert_eq!(candles[0]["open_time"], "2026-01-01T00:00:00Z");
+    assert_eq!(
+        (&candles[0]["open"], &candles[0]["close"]),
+        (&json!(100), &json!(105))
+    );
+    assert_eq!(candles[0]["trade_count"], 2);
+    assert_eq!(candles[0]["closed"], true);
+    assert_eq!(candles[1]["open_time"], "2026-01-01T00:01:00Z");
+    assert_eq!(candles[1]["close"], 95);
+    assert_eq!(candles[1]["closed"], false);
+}
+
+#[tokio::test]
+async fn tokens_expire_a_day_after_login_on_the_exchange_clock() {
+    let clock = Arc::new(ManualClock::new(start_time()));
+    let exchange = start_at(&clock).await;
+    let user = exchange.register("user", "secret").await;
+    let path = "/orders/me";
+
+    clock.advance(Duration::from_secs(23 * 60 * 60));
+    assert!(exchange.get(&user, path).await.is_ok());
+    clock.advance(Duration::from_secs(2 * 60 * 60));
+    let err = exchange.get(&user, path).await.unwrap_err();
+    assert_eq!(err.status.as_u16(), 401);
+}
@@ -0,0 +1,198 @@
+//! Enforcing a changed symbol config on resting orders: `enforce-config` in dry-run and apply
+//! mode, and `PATCH /admin/symbols/{symbol}?cancel_violating=true` cancelling what the new rules
+//! leave behind instead of refusing the change.
+
+use reqwest::StatusCode;
+use rust_exchange::testing::{OrderRequest, TEST_SYMBOL, TestExchange, Token};
+use rust_exchange::types::order::OrderSide;
+use serde_json::{Value, json};
+
+async fn start() -> (TestExchange, Token) {
+    let exchange = TestExchange::builder().admin().start().await;
+    let admin = exchange.admin_token();
+    (exchange, admin)
+}
+
+async fn place(
+    exchange: &TestExchange,
+    token: &Token,
+    side: OrderSide,
+    price: i64,
+    quantity: u64,
+) -> String {
+    exchange
+        .place_order(
+            token,
+            &OrderRequest::limit(TEST_SYMBOL, side, price, quantity),
+        )
+        .await
+        .unwrap()
+        .order
+        .id
+        .to_string()
+}
+
+async fn enforce(exchange: &TestExchange, admin: &Token, body: Value) -> Value {
+    exchange
+        .send_json(
+            exchange
+                .client()
+                .post(exchange.url(&format!("/admin/symbols/{}/enforce-config", TEST_SYMBOL)))
+                .bearer_auth(&admin.token)
+                .json(&body),
+        )
+        .await
+        .unwrap()
+}
+
+async fn patch(
+    exchange: &TestExchange,
+    admin: &Token,
+    query: &str,
+    body: Value,
+) -> Result<Value, StatusCode> {
+    exchange
+        .send_json(
+            exchange
+                .client()
+                .patch(exchange.url(&format!("/admin/symbols/{}{}", TEST_SYMBOL, query)))
+                .bearer_auth(&admin.token)
+                .json(&body),
+        )
+        .await
+        .map_err(|e| e.status)
+}
+
+/// (order id, reasons) of each reported violation.
+fn violations(body: &Value) -> Vec<(String, Vec<String>)> {
+    body["violations"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|v| {
+            let reasons = v["reasons"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|r| r.as_str().unwrap().to_string())
+                .collect();
+            (v["order_id"].as_str().unwrap().to_string(), reasons)
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn a_dry_run_reports_violations_and_apply_cancels_only_them() {
+    let (exchange, admin) = start().await;
+    let seller = exchange.register("seller", "secret").await;
+    let buyer = exchange.register("buyer", "secret").await;
+    let maker = exchange.register("maker", "secret").await;
+    // The last trade at 1,000 anchors the band
+    place(&exchange, &seller, OrderSide::Sell, 1_000, 1).await;
+    place(&exchange, &buyer, OrderSide::Buy, 1_000, 1).await;
+    let conforming = place(&exchange, &maker, OrderSide::Buy, 990, 4).await;
+    let small = place(&exchange, &maker, OrderSide::Sell, 1_010, 1).await;
+    let far = place(&exchange, &maker, OrderSide::Sell, 1_200, 4).await;
+    let mut ws = exchange.ws_client(Some(&maker)).await;
+    ws.subscribe(TEST_SYMBOL).await;
+
+    // Neither rule is checked against resting orders when it changes
+    patch(
+        &exchange,
+        &admin,
+        "",
+        json!({"price_band_bps": 1_000, "min_qty": 2}),
+    )
+    .await
+    .unwrap();
+    let expected = vec![
+        (small.clone(), vec!["BELOW_MIN_QUANTITY".to_string()]),
+        (far.clone(), vec!["PRICE_OUTSIDE_BAND".to_string()]),
+    ];
+
+    let dry_run = enforce(&exchange, &admin, json!({})).await;
+    assert_eq!(dry_run["symbol"], TEST_SYMBOL);
+    assert_eq!(dry_run["applied"], false);
+    assert_eq!(violations(&dry_run), expected);
+    assert_eq!(
+        dry_run["violations"][1]["user_id"],
+        maker.user_id.to_string()
+    );
+    assert_eq!(dry_run["violations"][1]["price"], 1_200);
+    let book = exchange.book(TEST_SYMBOL).await;
+    assert_eq!(book.bids, vec![(990, 4)]);
+    assert_eq!(book.asks, vec![(1_010, 1), (1_200, 4)]);
+
+    let applied = enforce(&exchange, &admin, json!({"apply": true})).await;
+    assert_eq!(applied["applied"], true);
+    assert_eq!(violations(&applied), expected);
+    let book = exchange.book(TEST_SYMBOL).await;
+    assert_eq!(book.bids, vec![(990, 4)]);
+    assert!(book.asks.is_empty());
+    for id in [&small, &far] {
+        let closed = ws.next_of_type("OrderClosed").await;
+        assert_eq!(closed["order_id"], *id);
+        assert_eq!(closed["reason"], "CONFIG_ENFORCEMENT");
+    }
+
+    // Nothing left to enforce; the conforming bid is still there
+    let again = enforce(&exchange, &admin, json!({"apply": true})).await;
+    assert!(violations(&again).is_empty());
+    let open = exchange
+        .get(&maker, &format!("/orders/me?symbol={}", TEST_SYMBOL))
+        .await
+        .unwrap();
+    let order = open["items"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|o| o["id"] == conforming)
+        .unwrap();
+    assert_eq!(order["status"], "Pending");
+}
+
+#[tokio::test]
+async fn a_config_change_can_cancel_the_orders_it_would_be_refused_for() {
+    let (exchange, admin) = start().await;
+    let maker = exchange.register("maker", "secret").await;
+    place(&exchange, &maker, OrderSide::Buy, 990, 4).await;
+    let off_tick = place(&exchange, &maker, OrderSide::Buy, 995, 4).await;
+
+    assert_eq!(
+        patch(&exchange, &admin, "", json!({"tick_size": 10}))
+            .await
+            .unwrap_err(),
+        StatusCode::CONFLICT
+    );
+    assert_eq!(
+        exchange.book(TEST_SYMBOL).await.bids,
+        vec![(995, 4), (990, 4)]
+    );
+
+    let changed = patch(
+        &exchange,
+        &admin,
+        "?cancel_violating=true",
+        json!({"tick_size": 10}),
+    )
+    .await
+    .unwrap();
+    assert_eq!(changed["tick_size"], 10);
+    let cancelled = changed["cancelled"].as_array().unwrap();
+    assert_eq!(cancelled.len(), 1);
+    assert_eq!(cancelled[0]["id"], off_tick);
+    assert_eq!(cancelled[0]["close_reason"], "CONFIG_ENFORCEMENT");
+    assert_eq!(exchange.book(TEST_SYMBOL).await.bids, vec![(990, 4)]);
+
+    // Nothing to cancel: the response carries no `cancelled` list
+    let changed = patch(
+        &exchange,
+        &admin,
+        "?cancel_violating=true",
+        json!({"maker_fee_bps": 5}),
+    )
+    .await
+    .unwrap();
+    assert!(changed.get("cancelled").is_none());
+    assert_eq!(exchange.book(TEST_SYMBOL).await.bids, vec![(990, 4)]);
+}
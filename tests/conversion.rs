@@ -0,0 +1,118 @@
+//! Asset conversion over fixture mark prices: direct and inverse hops, routing through a third
+//! asset, and the cases with no usable path.
+
+use rust_exchange::conversion::{ConversionGraph, SymbolMark};
+use rust_exchange::types::asset::Asset;
+use rust_exchange::types::money::{MoneyError, PRICE_SCALE};
+
+fn asset(code: &str) -> Asset {
+    Asset::parse(code).unwrap()
+}
+
+/// `symbol` at `price` whole units of quote per whole base (scaled).
+fn mark(symbol: &str, base: &str, quote: &str, price: f64) -> SymbolMark {
+    SymbolMark {
+        symbol: symbol.to_string(),
+        base: asset(base),
+        quote: asset(quote),
+        price: (price * PRICE_SCALE as f64).round() as i64,
+    }
+}
+
+/// BTC at 60,000 USDT, ETH at 0.05 BTC, SOL at 100 EUR (an island), no ETHUSDT.
+fn fixture() -> ConversionGraph {
+    ConversionGraph::new([
+        mark("BTCUSDT", "BTC", "USDT", 60_000.0),
+        mark("ETHBTC", "ETH", "BTC", 0.05),
+        mark("SOLEUR", "SOL", "EUR", 100.0),
+    ])
+}
+
+fn units(amount: f64) -> i64 {
+    (amount * PRICE_SCALE as f64).round() as i64
+}
+
+#[test]
+fn a_symbol_converts_its_base_into_its_quote_and_back() {
+    let graph = fixture();
+    let to_usdt = graph.path(asset("BTC"), asset("USDT")).unwrap();
+    assert_eq!(to_usdt.symbols(), ["BTCUSDT"]);
+    assert!(!to_usdt.hops[0].inverse);
+    assert_eq!(to_usdt.convert(units(0.5)), Ok(units(30_000.0)));
+    assert_eq!(to_usdt.convert(-units(2.0)), Ok(-units(120_000.0)));
+
+    let to_btc = graph.path(asset("USDT"), asset("BTC")).unwrap();
+    assert!(to_btc.hops[0].inverse);
+    assert_eq!(to_btc.convert(units(30_000.0)), Ok(units(0.5)));
+}
+
+#[test]
+fn assets_without_a_common_symbol_route_through_one_that_links_them() {
+    let graph = fixture();
+    let path = graph.path(asset("ETH"), asset("USDT")).unwrap();
+    assert_eq!(path.symbols(), ["ETHBTC", "BTCUSDT"]);
+    assert_eq!(
+        (path.hops[0].from, path.hops[0].to),
+        (asset("ETH"), asset("BTC"))
+    );
+    // 2 ETH = 0.1 BTC = 6,000 USDT
+    assert_eq!(path.convert(units(2.0)), Ok(units(6_000.0)));
+
+    let back = graph.path(asset("USDT"), asset("ETH")).unwrap();
+    assert_eq!(back.symbols(), ["BTCUSDT", "ETHBTC"]);
+    assert_eq!(back.convert(units(6_000.0)), Ok(units(2.0)));
+}
+
+#[test]
+fn the_same_asset_needs_no_hops_and_unlinked_assets_have_no_path() {
+    let graph = fixture();
+    let same = graph.path(asset("USDT"), asset("USDT")).unwrap();
+    assert!(same.hops.is_empty());
+    assert_eq!(same.convert(12_345), Ok(12_345));
+
+    assert_eq!(graph.path(asset("SOL"), asset("USDT")), None);
+    assert_eq!(graph.path(asset("USDT"), asset("EUR")), None);
+    // Registered but traded nowhere
+    assert_eq!(graph.path(asset("DOGE"), asset("USDT")), None);
+}
+
+#[test]
+fn symbols_without_a_positive_mark_link_nothing() {
+    let graph = ConversionGraph::new([
+        mark("BTCUSDT", "BTC", "USDT", 0.0),
+        mark("ETHUSDT", "ETH", "USDT", -1.0),
+    ]);
+    assert_eq!(graph.path(asset("BTC"), asset("USDT")), None);
+    assert_eq!(graph.path(asset("ETH"), asset("USDT")), None);
+}
+
+#[test]
+fn the_shortest_path_wins_and_ties_go_to_the_first_symbols() {
+    let graph = ConversionGraph::new([
+        mark("ETHBTC", "ETH", "BTC", 0.05),
+        mark("BTCUSDT", "BTC", "USDT", 60_000.0),
+        mark("ETHUSDT", "ETH", "USDT", 3_000.0),
+        mark("ETHUSDC", "ETH", "USDC", 3_001.0),
+        mark("USDCUSDT", "USDC", "USDT", 1.0),
+    ]);
+    // Direct beats the two routes through BTC and USDC
+    let path = graph.path(asset("ETH"), asset("USDT")).unwrap();
+    assert_eq!(path.symbols(), ["ETHUSDT"]);
+    // Two equally short routes from BTC to USDC: through ETH (ETHBTC, ETHUSDC) or through
+    // USDT (BTCUSDT, USDCUSDT); BTCUSDT sorts first
+    let path = graph.path(asset("BTC"), asset("USDC")).unwrap();
+    assert_eq!(path.symbols(), ["BTCUSDT", "USDCUSDT"]);
+}
+
+#[test]
+fn conversions_round_toward_zero_and_report_overflow() {
+    let graph = fixture();
+    let to_btc = graph.path(asset("USDT"), asset("BTC")).unwrap();
+    // 1 unit of USDT is 1 / 60,000 of a BTC unit
+    assert_eq!(to_btc.convert(1), Ok(0));
+    assert_eq!(to_btc.convert(-59_999), Ok(0));
+    assert_eq!(to_btc.convert(60_000), Ok(1));
+
+    let to_usdt = graph.path(asset("BTC"), asset("USDT")).unwrap();
+    assert_eq!(to_usdt.convert(i64::MAX), Err(MoneyError::Overflow));
+}
@@ -0,0 +1,3396 @@
+//! Database-backed integration tests. Each test creates a fresh database on the server at
+//! `TEST_DATABASE_URL` and runs migrations; tests are skipped when the variable is unset.
+
+use axum::Router;
+use axum::http::StatusCode;
+use axum::routing::get;
+use chrono::{NaiveDate, SubsecRound, Utc};
+use rust_exchange::api::dto::{TradeDto, UserTradeDto};
+use rust_exchange::api::exchange_info::{RuleChange, RuleChangeSource, RulesLog};
+use rust_exchange::api::expiry::{self};
+use rust_exchange::api::lazy_books::{self};
+use rust_exchange::api::pagination::Page;
+use rust_exchange::api::routes::{ErrorResponse, WsMessage, app_router};
+use rust_exchange::api::surveillance::{Surveillance, SurveillanceConfig};
+use rust_exchange::bootstrap::{self, Config, PersistenceMode};
+use rust_exchange::hydration::{self};
+use rust_exchange::market_data::{self, MarketDataStore};
+use rust_exchange::orderbook::clock::ManualClock;
+use rust_exchange::orderbook::orderbook::{OrderBook, PricingPolicy};
+use rust_exchange::persistence::{self, PgPool, PlannedQuery, TradePersistenceMetrics};
+use rust_exchange::positions::SharedPositions;
+use rust_exchange::replica::ServerRole;
+use rust_exchange::retention::{Retention, RetentionConfig};
+use rust_exchange::selftest::{self, SELFTEST_SYMBOL, StepStatus};
+use rust_exchange::skew::{SkewPolicy, SkewWindow};
+use rust_exchange::statements;
+use rust_exchange::tasks::Supervisor;
+use rust_exchange::testing::{self, TestExchange, Token};
+use rust_exchange::types::asset::Asset;
+use rust_exchange::types::environment::Environment;
+use rust_exchange::types::money::PRICE_SCALE;
+use rust_exchange::types::order::{OrderSide, OrderSource, OrderStatus, OrderType};
+use rust_exchange::types::symbol::SymbolConfig;
+use rust_exchange::types::trade::{Trade, TradeRole};
+use rust_exchange::webhooks::{self, WebhookEventType, Webhooks};
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{RwLock, broadcast};
+use uuid::Uuid;
+
+async fn place(
+    client: &reqwest::Client,
+    base_url: &str,
+    token: &str,
+    side: &str,
+    price: i64,
+    qty: u64,
+) -> serde_json::Value {
+    let res = client
+        .post(format!("{}/orders", base_url))
+        .bearer_auth(token)
+        .json(&serde_json::json!({
+            "symbol": "BTCUSDT",
+            "price": price,
+            "quantity": qty,
+            "side": side
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status().as_u16(), 200);
+    res.json().await.unwrap()
+}
+
+/// Positions table as a map comparable with the in-memory store.
+async fn db_positions(pool: &PgPool) -> HashMap<(Uuid, String), (i64, i64)> {
+    persistence::list_positions(pool)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|r| ((r.user_id, r.symbol), (r.quantity, r.average_price)))
+        .collect()
+}
+
+async fn memory_positions(store: &SharedPositions) -> HashMap<(Uuid, String), (i64, i64)> {
+    store
+        .read()
+        .await
+        .iter()
+        .map(|(k, p)| (k.clone(), (p.quantity, p.average_price)))
+        .collect()
+}
+
+#[tokio::test]
+async fn concurrent_fills_persist_same_positions_as_memory() {
+    let Some(pool) = testing::schema_pool("db").await else {
+        return;
+    };
+    let state = testing::db_app_state(pool.clone());
+    let positions = state.positions.clone();
+    let exchange = TestExchange::builder_with_state(state).start().await;
+    let (client, base_url) = (exchange.client(), &exchange.base_url);
+    let maker = exchange.register("maker", "secret").await.token;
+    let taker_a = exchange.register("taker_a", "secret").await.token;
+    let taker_b = exchange.register("taker_b", "secret").await.token;
+
+    place(client, base_url, &maker, "Sell", 100, 5).await;
+    place(client, base_url, &maker, "Sell", 101, 5).await;
+    tokio::join!(
+        place(client, base_url, &taker_a, "Buy", 101, 4),
+        place(client, base_url, &taker_b, "Buy", 101, 4),
+    );
+
+    assert_eq!(
+        db_positions(&pool).await,
+        memory_positions(&positions).await
+    );
+}
+
+#[tokio::test]
+async fn sandbox_orders_are_replaced_without_being_stored() {
+    let Some(pool) = testing::schema_pool("db").await else {
+        return;
+    };
+    let exchange = TestExchange::builder_with_state(testing::db_app_state(pool.clone()))
+        .start()
+        .await;
+    let (client, base_url) = (exchange.client(), &exchange.base_url);
+    let owner = exchange.register("owner", "secret").await.token;
+    let res = client
+        .post(format!("{}/sandbox/symbols", base_url))
+        .bearer_auth(&owner)
+        .json(&serde_json::json!({ "name": "bot1" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::CREATED);
+    let ask: serde_json::Value = client
+        .post(format!("{}/orders", base_url))
+        .bearer_auth(&owner)
+        .json(&serde_json::json!({
+            "symbol": "SBXBOT1",
+            "price": 100,
+            "quantity": 5,
+            "side": "Sell"
+        }))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let ask_id = Uuid::parse_str(ask["id"].as_str().unwrap()).unwrap();
+
+    let res = client
+        .post(format!(
+            "{}/orders/{}/replace?symbol=sbxbot1",
+            base_url, ask_id
+        ))
+        .bearer_auth(&owner)
+        .json(&serde_json::json!({ "price": 101, "quantity": 4 }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    let json: serde_json::Value = res.json().await.unwrap();
+    assert_eq!(json["cancelled"]["id"], ask["id"]);
+    let new_id = Uuid::parse_str(json["report"]["order"]["id"].as_str().unwrap()).unwrap();
+    for id in [ask_id, new_id] {
+        assert!(
+            persistence::get_order_by_id(&pool, id)
+                .await
+                .unwrap()
+                .is_none()
+        );
+    }
+}
+
+#[tokio::test]
+async fn closed_positions_are_deleted_from_db() {
+    let Some(pool) = testing::schema_pool("db").await else {
+        return;
+    };
+    let state = testing::db_app_state(pool.clone());
+    let positions = state.positions.clone();
+    let exchange = TestExchange::builder_with_state(state).start().await;
+    let (client, base_url) = (exchange.client(), &exchange.base_url);
+    let alice = exchange.register("alice", "secret").await.token;
+    let bob = exchange.register("bob", "secret").await.token;
+
+    place(client, base_url, &alice, "Sell", 100, 3).await;
+    place(client, base_url, &bob, "Buy", 100, 3).await;
+    assert_eq!(db_positions(&pool).await.len(), 2);
+
+    place(client, base_url, &bob, "Sell", 100, 3).await;
+    place(client, base_url, &alice, "Buy", 100, 3).await;
+
+    assert!(memory_positions(&positions).await.is_empty());
+    assert!(db_positions(&pool).await.is_empty());
+}
+
+#[tokio::test]
+async fn replace_persists_the_cancel_new_order_and_fills_together() {
+    let Some(pool) = testing::schema_pool("db").await else {
+        return;
+    };
+    let state = testing::db_app_state(pool.clone());
+    let positions = state.positions.clone();
+    let exchange = TestExchange::builder_with_state(state).start().await;
+    let (client, base_url) = (exchange.client(), &exchange.base_url);
+    let maker = exchange.register("maker", "secret").await.token;
+    let quoter = exchange.register("quoter", "secret").await.token;
+
+    place(client, base_url, &maker, "Sell", 101, 2).await;
+    let quote = place(client, base_url, &quoter, "Buy", 99, 3).await;
+    let quote_id = Uuid::parse_str(quote["id"].as_str().unwrap()).unwrap();
+
+    let replace = |id: Uuid| {
+        client
+            .post(format!("{}/orders/{}/replace?symbol=BTCUSDT", base_url, id))
+            .bearer_auth(&quoter)
+            .json(&serde_json::json!({ "price": 101, "quantity": 3 }))
+            .send()
+    };
+    let res = replace(quote_id).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    let json: serde_json::Value = res.json().await.unwrap();
+    let new_id = Uuid::parse_str(json["report"]["order"]["id"].as_str().unwrap()).unwrap();
+
+    let old_row = persistence::get_order_by_id(&pool, quote_id)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(old_row.status, "Cancelled");
+    assert_eq!(
+        (old_row.quantity, old_row.close_reason.as_deref()),
+        (3, Some("USER_CANCELLED"))
+    );
+    let new_row = persistence::get_order_by_id(&pool, new_id)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(new_row.status, "PartiallyFilled");
+    assert_eq!((new_row.price, new_row.quantity), (101, 1));
+    let trades = persistence::list_trades(&pool, "BTCUSDT", 10, false)
+        .await
+        .unwrap();
+    assert_eq!(trades.len(), 1);
+    assert_eq!(trades[0].taker_order_id, new_id);
+    assert_eq!(
+        db_positions(&pool).await,
+        memory_positions(&positions).await
+    );
+
+    // Cancelling the replaced original reports how it closed, like a repeated cancel
+    let res = client
+        .delete(format!("{}/orders/{}?symbol=BTCUSDT", base_url, quote_id))
+        .bearer_auth(&quoter)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    let json: serde_json::Value = res.json().await.unwrap();
+    assert_eq!(json["remaining_quantity"], 3);
+    assert_eq!(json["close_reason"], "USER_CANCELLED");
+
+    // A cancelled original conflicts; an id that never existed is not found
+    assert_eq!(
+        replace(quote_id).await.unwrap().status(),
+        StatusCode::CONFLICT
+    );
+    assert_eq!(
+        replace(Uuid::new_v4()).await.unwrap().status(),
+        StatusCode::NOT_FOUND
+    );
+}
+
+#[tokio::test]
+async fn cancel_persists_the_open_quantity_and_reason() {
+    let Some(pool) = testing::schema_pool("db").await else {
+        return;
+    };
+    let exchange = TestExchange::builder_with_state(testing::db_app_state(pool.clone()))
+        .start()
+        .await;
+    let (client, base_url) = (exchange.client(), &exchange.base_url);
+    let maker = exchange.register("maker", "secret").await.token;
+    let taker = exchange.register("taker", "secret").await.token;
+
+    let ask = place(client, base_url, &maker, "Sell", 100, 5).await;
+    let ask_id = Uuid::parse_str(ask["id"].as_str().unwrap()).unwrap();
+    place(client, base_url, &taker, "Buy", 100, 3).await;
+
+    let res = client
+        .delete(format!("{}/orders/{}?symbol=BTCUSDT", base_url, ask_id))
+        .bearer_auth(&maker)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    let json: serde_json::Value = res.json().await.unwrap();
+    assert_eq!(json["remaining_quantity"], 2);
+
+    let row = persistence::get_order_by_id(&pool, ask_id)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(row.status, "Cancelled");
+    assert_eq!(row.quantity, 2);
+    assert_eq!(row.close_reason.as_deref(), Some("USER_CANCELLED"));
+
+    // A later close of the same order leaves the stored one alone
+    let changed = persistence::cancel_order(&pool, ask_id, 0, "TTL_EXPIRED")
+        .await
+        .unwrap();
+    assert!(!changed);
+    let row = persistence::get_order_by_id(&pool, ask_id)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(
+        (row.quantity, row.close_reason.as_deref()),
+        (2, Some("USER_CANCELLED"))
+    );
+}
+
+#[tokio::test]
+async fn surveillance_alerts_are_stored_and_acknowledged_in_the_database() {
+    let Some(pool) = testing::schema_pool("db").await else {
+        return;
+    };
+    let mut state = testing::db_app_state(pool.clone());
+    state.surveillance = Arc::new(Surveillance::new(SurveillanceConfig {
+        min_cancels: 3,
+        max_cancel_to_fill: 1.0,
+        min_avg_lifetime: Duration::from_secs(3600),
+        ..SurveillanceConfig::default()
+    }));
+    let exchange = TestExchange::builder_with_state(state)
+        .admin()
+        .start()
+        .await;
+    let (client, base_url) = (exchange.client(), &exchange.base_url);
+    let Token {
+        user_id: admin_id,
+        token: admin,
+    } = exchange.admin_token();
+    let Token {
+        user_id: trader_id,
+        token: trader,
+    } = exchange.register("trader", "secret").await;
+
+    for price in [100, 101, 100] {
+        let ask = place(client, base_url, &trader, "Sell", price, 1).await;
+        let res = client
+            .delete(format!(
+                "{}/orders/{}?symbol=BTCUSDT",
+                base_url,
+                ask["id"].as_str().unwrap()
+            ))
+            .bearer_auth(&trader)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+    }
+
+    let stored = persistence::list_surveillance_alerts(&pool, Some(false), Some(trader_id), 10)
+        .await
+        .unwrap();
+    let mut kinds: Vec<&str> = stored.iter().map(|row| row.kind.as_str()).collect();
+    kinds.sort();
+    assert_eq!(kinds, ["cancel_to_fill", "short_lifetime"]);
+    assert!(stored.iter().all(|row| row.symbol == "BTCUSDT"));
+    assert_eq!(stored[0].metrics.0["cancelled"], 3);
+
+    let listed: serde_json::Value = client
+        .get(format!(
+            "{}/admin/surveillance/alerts?user_id={}",
+            base_url, trader_id
+        ))
+        .bearer_auth(&admin)
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(listed.as_array().unwrap().len(), 2);
+    let id = listed[0]["id"].as_str().unwrap().to_string();
+    let ack = || {
+        client
+            .post(format!("{}/admin/surveillance/alerts/{}/ack", base_url, id))
+            .bearer_auth(&admin)
+            .send()
+    };
+    let first: serde_json::Value = ack().await.unwrap().json().await.unwrap();
+    assert_eq!(first["acknowledged_by"], admin_id.to_string());
+    // Acknowledging again keeps the first acknowledgment
+    let again: serde_json::Value = ack().await.unwrap().json().await.unwrap();
+    assert_eq!(again["acknowledged_at"], first["acknowledged_at"]);
+
+    let pending = persistence::list_surveillance_alerts(&pool, Some(false), None, 10)
+        .await
+        .unwrap();
+    assert_eq!(pending.len(), 1);
+    assert_ne!(pending[0].id.to_string(), id);
+    let acknowledged = persistence::list_surveillance_alerts(&pool, Some(true), None, 10)
+        .await
+        .unwrap();
+    assert_eq!(acknowledged[0].acknowledged_by, Some(admin_id));
+}
+
+#[tokio::test]
+async fn symbol_config_changes_are_persisted_audited_and_hydrated() {
+    let Some(pool) = testing::schema_pool("db").await else {
+        return;
+    };
+    let state = testing::db_app_state(pool.clone());
+    let exchange = TestExchange::builder_with_state(state)
+        .admin()
+        .start()
+        .await;
+    let (client, base_url) = (exchange.client(), &exchange.base_url);
+    let Token {
+        user_id: admin_id,
+        token: admin,
+    } = exchange.admin_token();
+
+    let patch = |body: serde_json::Value| {
+        client
+            .patch(format!("{}/admin/symbols/BTCUSDT", base_url))
+            .bearer_auth(&admin)
+            .json(&body)
+            .send()
+    };
+    let res = patch(serde_json::json!({ "tick_size": 5, "price_band_bps": 250 }))
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    let res = patch(serde_json::json!({
+        "price_band_bps": null,
+        "halted": true,
+        "pricing_policy": "Midpoint"
+    }))
+    .await
+    .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let audit = persistence::list_symbol_config_audit(&pool, "BTCUSDT")
+        .await
+        .unwrap();
+    assert_eq!(audit.len(), 2);
+    assert!(audit.iter().all(|a| a.admin_user_id == admin_id));
+    assert_eq!(audit[0].changes.price_band_bps, Some(None));
+    assert_eq!(audit[0].previous.price_band_bps, Some(250));
+    assert_eq!(audit[1].changes.tick_size, Some(5));
+    assert_eq!(audit[1].previous.tick_size, 1);
+
+    let hydrated = hydration::hydrate(&pool, &["BTCUSDT", "ETHUSDT"], true)
+        .await
+        .expect("strict hydration accepts stored configs");
+    let config = hydrated.symbol_configs["BTCUSDT"];
+    assert_eq!(config.tick_size, 5);
+    assert_eq!(config.price_band_bps, None);
+    assert!(config.halted);
+    assert_eq!(config.pricing_policy, PricingPolicy::Midpoint);
+    assert_eq!(
+        hydrated.orderbooks["BTCUSDT"].pricing_policy(),
+        PricingPolicy::Midpoint
+    );
+    assert_eq!(
+        hydrated.symbol_configs["ETHUSDT"],
+        SymbolConfig::for_symbol("ETHUSDT")
+    );
+}
+
+#[tokio::test]
+async fn rules_changes_are_stored_with_the_config_and_read_back_after_a_restart() {
+    let Some(pool) = testing::schema_pool("db").await else {
+        return;
+    };
+    let mut state = testing::db_app_state(pool.clone());
+    let exchange = TestExchange::builder_with_state(state.clone())
+        .admin()
+        .start()
+        .await;
+    let (client, base_url) = (exchange.client(), &exchange.base_url);
+    let Token {
+        user_id: admin_id,
+        token: admin,
+    } = exchange.admin_token();
+
+    for body in [
+        serde_json::json!({ "tick_size": 5 }),
+        serde_json::json!({ "tick_size": 5 }),
+        serde_json::json!({ "halted": true }),
+    ] {
+        let res = client
+            .patch(format!("{}/admin/symbols/BTCUSDT", base_url))
+            .bearer_auth(&admin)
+            .json(&body)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+    }
+    drop(exchange);
+
+    // The repeated patch changed nothing and took no version
+    assert_eq!(persistence::get_rules_version(&pool).await.unwrap(), 2);
+    let rows = persistence::list_rule_changes_since(&pool, 0, 10)
+        .await
+        .unwrap();
+    let stored: Vec<_> = rows
+        .into_iter()
+        .map(|row| RuleChange::from_row(row).unwrap())
+        .collect();
+    assert_eq!(stored.iter().map(|c| c.version).collect::<Vec<_>>(), [1, 2]);
+    assert!(
+        stored
+            .iter()
+            .all(|c| c.source == RuleChangeSource::AdminPatch
+                && c.changed_by == Some(admin_id)
+                && c.symbol == "BTCUSDT")
+    );
+    assert_eq!(stored[0].changes["tick_size"].from, 1);
+    assert_eq!(stored[0].changes["tick_size"].to, 5);
+    assert_eq!(stored[1].changes.keys().collect::<Vec<_>>(), ["halted"]);
+
+    let reloaded = RulesLog::default();
+    assert_eq!(reloaded.load(&pool).await.unwrap(), 2);
+    assert_eq!(reloaded.version(), 2);
+    assert_eq!(reloaded.since(1).unwrap(), stored[1..]);
+
+    // A log that keeps nothing in memory reads the changes from the database
+    state.rules = Arc::new(RulesLog::new(0));
+    state.rules.load(&pool).await.unwrap();
+    let exchange = TestExchange::builder_with_state(state).start().await;
+    let (client, base_url) = (exchange.client(), &exchange.base_url);
+    let body: serde_json::Value = client
+        .get(format!("{}/exchangeInfo/changes?since_version=0", base_url))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(body["rules_version"], 2);
+    assert_eq!(body["changes"].as_array().unwrap().len(), 2);
+    assert_eq!(body["changes"][1]["source"], "admin_patch");
+    assert_eq!(body["changes"][1]["changes"]["halted"]["to"], true);
+}
+
+#[tokio::test]
+async fn symbol_assets_are_persisted_and_checked_on_hydration() {
+    let Some(pool) = testing::schema_pool("db").await else {
+        return;
+    };
+    let usd = SymbolConfig::for_symbol("BTCUSDT")
+        .with_assets(Asset::parse("ETH").unwrap(), Asset::parse("USD").unwrap())
+        .unwrap();
+    persistence::upsert_symbol_config(&pool, "BTCUSDT", &usd)
+        .await
+        .unwrap();
+    persistence::upsert_symbol_config(&pool, "ETHBTC", &SymbolConfig::for_symbol("ETHBTC"))
+        .await
+        .unwrap();
+    let hydrated = hydration::hydrate(&pool, &["BTCUSDT", "ETHBTC"], true)
+        .await
+        .expect("strict hydration accepts stored assets");
+    assert_eq!(hydrated.symbol_configs["BTCUSDT"].assets(), usd.assets());
+    assert_eq!(
+        hydrated.symbol_configs["ETHBTC"],
+        SymbolConfig::for_symbol("ETHBTC")
+    );
+
+    // Rows saved before symbols had assets take them from the name
+    sqlx::query("UPDATE symbols SET base_asset = NULL, quote_asset = NULL")
+        .execute(&pool)
+        .await
+        .unwrap();
+    let hydrated = hydration::hydrate(&pool, &["BTCUSDT", "ETHBTC"], true)
+        .await
+        .unwrap();
+    assert_eq!(
+        hydrated.symbol_configs["BTCUSDT"],
+        SymbolConfig::for_symbol("BTCUSDT")
+    );
+
+    sqlx::query(
+        "UPDATE symbols SET base_asset = 'XYZ', quote_asset = 'BTC' WHERE symbol = 'ETHBTC'",
+    )
+    .execute(&pool)
+    .await
+    .unwrap();
+    let report = hydration::hydrate(&pool, &["BTCUSDT", "ETHBTC"], true)
+        .await
+        .map(|_| ())
+        .unwrap_err();
+    assert!(
+        report
+            .errors
+            .iter()
+            .any(|e| e.contains("ETHBTC") && e.contains("XYZ")),
+        "{}",
+        report
+    );
+}
+
+#[tokio::test]
+async fn admin_positions_by_symbol_are_read_from_the_database() {
+    let Some(pool) = testing::schema_pool("db").await else {
+        return;
+    };
+    let state = testing::db_app_state(pool.clone());
+    let exchange = TestExchange::builder_with_state(state)
+        .admin()
+        .start()
+        .await;
+    let (client, base_url) = (exchange.client(), &exchange.base_url);
+    let admin = exchange.admin_token().token;
+
+    // Rows only in the database: the endpoint must not fall back to memory in DB mode
+    let (small, big) = (Uuid::new_v4(), Uuid::new_v4());
+    persistence::upsert_position(&pool, small, "BTCUSDT", 2, 100, Utc::now(), Utc::now())
+        .await
+        .unwrap();
+    persistence::upsert_position(&pool, big, "BTCUSDT", -7, 100, Utc::now(), Utc::now())
+        .await
+        .unwrap();
+    persistence::upsert_position(&pool, big, "ETHUSDT", 9, 100, Utc::now(), Utc::now())
+        .await
+        .unwrap();
+
+    let json: serde_json::Value = client
+        .get(format!("{}/admin/positions?symbol=BTCUSDT", base_url))
+        .bearer_auth(&admin)
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(json["total"], 2);
+    assert_eq!(json["long_open_interest"], 2);
+    assert_eq!(json["short_open_interest"], 7);
+    assert!(json["mark_price"].is_null());
+    let positions = json["positions"].as_array().unwrap();
+    assert_eq!(positions[0]["user_id"], big.to_string());
+    assert_eq!(positions[1]["user_id"], small.to_string());
+    assert!(positions[0]["unrealized_pnl"].is_null());
+}
+
+#[tokio::test]
+async fn order_history_filters_by_tag_containment() {
+    let Some(pool) = testing::schema_pool("db").await else {
+        return;
+    };
+    let exchange = TestExchange::builder_with_state(testing::db_app_state(pool))
+        .start()
+        .await;
+    let (client, base_url) = (exchange.client(), &exchange.base_url);
+    let maker = exchange.register("maker", "secret").await.token;
+    let taker = exchange.register("taker", "secret").await.token;
+
+    place(client, base_url, &maker, "Sell", 100, 1).await;
+    for strategy in ["mm-v2", "arb"] {
+        let res = client
+            .post(format!("{}/orders", base_url))
+            .bearer_auth(&taker)
+            .json(&serde_json::json!({
+                "symbol": "BTCUSDT",
+                "price": 100,
+                "quantity": 1,
+                "side": "Buy",
+                "tags": { "strategy": strategy }
+            }))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(res.status().as_u16(), 200);
+    }
+
+    let orders = client
+        .get(format!("{}/orders/me?tag=strategy:mm-v2", base_url))
+        .bearer_auth(&taker)
+        .send()
+        .await
+        .unwrap()
+        .json::<Page<serde_json::Value>>()
+        .await
+        .unwrap()
+        .items;
+    assert_eq!(orders.len(), 1);
+    assert_eq!(orders[0]["status"], "Filled");
+    assert_eq!(orders[0]["tags"]["strategy"], "mm-v2");
+
+    let orders = client
+        .get(format!("{}/orders/me", base_url))
+        .bearer_auth(&taker)
+        .send()
+        .await
+        .unwrap()
+        .json::<Page<serde_json::Value>>()
+        .await
+        .unwrap()
+        .items;
+    assert_eq!(orders.len(), 2);
+}
+
+#[tokio::test]
+async fn trade_seq_continues_after_restart() {
+    let Some(pool) = testing::schema_pool("db").await else {
+        return;
+    };
+    let exchange = TestExchange::builder_with_state(testing::db_app_state(pool.clone()))
+        .start()
+        .await;
+    let (client, base_url) = (exchange.client(), &exchange.base_url);
+    let maker = exchange.register("maker", "secret").await.token;
+    let taker = exchange.register("taker", "secret").await.token;
+    place(client, base_url, &maker, "Sell", 100, 3).await;
+    place(client, base_url, &taker, "Buy", 100, 1).await;
+    place(client, base_url, &taker, "Buy", 100, 1).await;
+    drop(exchange);
+
+    // Restart as main does: seed the fresh book from MAX(trade_seq)
+    let state = testing::db_app_state(pool.clone());
+    let last = persistence::max_trade_seq(&pool, "BTCUSDT").await.unwrap();
+    assert_eq!(last, 2);
+    state.orderbooks["BTCUSDT"]
+        .write()
+        .await
+        .set_last_trade_seq(last);
+    let exchange = TestExchange::builder_with_state(state).start().await;
+    let (client, base_url) = (exchange.client(), &exchange.base_url);
+    place(client, base_url, &maker, "Sell", 100, 1).await;
+    place(client, base_url, &taker, "Buy", 100, 2).await;
+
+    let trades = client
+        .get(format!("{}/trades?symbol=BTCUSDT&from_seq=2", base_url))
+        .bearer_auth(&taker)
+        .send()
+        .await
+        .unwrap()
+        .json::<Page<serde_json::Value>>()
+        .await
+        .unwrap()
+        .items;
+    let seqs: Vec<u64> = trades
+        .iter()
+        .map(|t| t["trade_seq"].as_u64().unwrap())
+        .collect();
+    assert_eq!(seqs, vec![2, 3]);
+}
+
+// --- Retention ---
+
+fn retention_config(archive: bool, batch_size: usize) -> RetentionConfig {
+    RetentionConfig {
+        interval: None,
+        order_max_age: Duration::from_secs(30 * 24 * 3600),
+        trade_max_age: Duration::from_secs(30 * 24 * 3600),
+        batch_size,
+        batch_pause: Duration::from_millis(1),
+        archive,
+    }
+}
+
+async fn seed_order(pool: &PgPool, status: OrderStatus, age_days: i64) -> Uuid {
+    let id = Uuid::new_v4();
+    persistence::insert_order(
+        pool,
+        id,
+        Uuid::new_v4(),
+        "BTCUSDT",
+        OrderSide::Buy,
+        OrderType::Limit,
+        100,
+        1,
+        status,
+        Utc::now() - chrono::Duration::days(age_days),
+        &Default::default(),
+        OrderSource::Api,
+        None,
+    )
+    .await
+    .unwrap();
+    id
+}
+
+async fn seed_trade(pool: &PgPool, trade_seq: u64, age_days: i64) -> Uuid {
+    let id = Uuid::new_v4();
+    persistence::insert_trade(
+        pool,
+        id,
+        trade_seq,
+        Uuid::new_v4(),
+        Uuid::new_v4(),
+        Uuid::new_v4(),
+        Uuid::new_v4(),
+        "BTCUSDT",
+        100,
+        1,
+        Utc::now() - chrono::Duration::days(age_days),
+    )
+    .await
+    .unwrap();
+    id
+}
+
+async fn ids_in(pool: &PgPool, table: &str) -> HashSet<Uuid> {
+    sqlx::query_scalar::<_, Uuid>(&format!("SELECT id FROM {}", table))
+        .fetch_all(pool)
+        .await
+        .unwrap()
+        .into_iter()
+        .collect()
+}
+
+#[tokio::test]
+async fn retention_archives_exactly_the_old_terminal_rows() {
+    let Some(pool) = testing::schema_pool("db").await else {
+        return;
+    };
+    let old_filled = seed_order(&pool, OrderStatus::Filled, 60).await;
+    let old_cancelled = seed_order(&pool, OrderStatus::Cancelled, 45).await;
+    let old_open = seed_order(&pool, OrderStatus::Pending, 60).await;
+    let new_filled = seed_order(&pool, OrderStatus::Filled, 1).await;
+    let mut old_trades = HashSet::new();
+    for seq in 1..=5 {
+        old_trades.insert(seed_trade(&pool, seq, 60).await);
+    }
+    let new_trade = seed_trade(&pool, 6, 1).await;
+
+    let retention = Retention::new(retention_config(true, 2));
+    let run = retention.run_once(&pool).await.unwrap();
+    assert_eq!(run.orders_moved, 2);
+    assert_eq!(run.trades_moved, 5);
+
+    assert_eq!(
+        ids_in(&pool, "orders").await,
+        HashSet::from([old_open, new_filled])
+    );
+    assert_eq!(
+        ids_in(&pool, "orders_archive").await,
+        HashSet::from([old_filled, old_cancelled])
+    );
+    assert_eq!(ids_in(&pool, "trades").await, HashSet::from([new_trade]));
+    assert_eq!(ids_in(&pool, "trades_archive").await, old_trades);
+
+    let progress = retention.progress.read().await;
+    assert!(!progress.running);
+    assert_eq!(progress.trades_moved_total, 5);
+    drop(progress);
+
+    let recent = persistence::list_trades(&pool, "BTCUSDT", 100, false)
+        .await
+        .unwrap();
+    assert_eq!(recent.len(), 1);
+    let all = persistence::list_trades(&pool, "BTCUSDT", 100, true)
+        .await
+        .unwrap();
+    let seqs: Vec<u64> = all.iter().map(|t| t.trade_seq).collect();
+    assert_eq!(seqs, vec![6, 5, 4, 3, 2, 1]);
+    assert_eq!(
+        persistence::max_trade_seq(&pool, "BTCUSDT").await.unwrap(),
+        6
+    );
+}
+
+#[tokio::test]
+async fn retention_overwrites_rows_already_archived_and_keeps_draining() {
+    let Some(pool) = testing::schema_pool("db").await else {
+        return;
+    };
+    let stale = seed_order(&pool, OrderStatus::Filled, 90).await;
+    let others = [
+        seed_order(&pool, OrderStatus::Filled, 80).await,
+        seed_order(&pool, OrderStatus::Cancelled, 70).await,
+    ];
+    // An earlier, interrupted move left a copy of the oldest order in the archive
+    sqlx::query(
+        "INSERT INTO orders_archive (id, user_id, symbol, side, order_type, price, quantity, \
+         status, created_at) \
+         SELECT id, user_id, symbol, side, order_type, price, 7, 'Cancelled', created_at \
+         FROM orders WHERE id = $1",
+    )
+    .bind(stale)
+    .execute(&pool)
+    .await
+    .unwrap();
+
+    let run = Retention::new(retention_config(true, 2))
+        .run_once(&pool)
+        .await
+        .unwrap();
+    assert_eq!(run.orders_moved, 3);
+    assert!(ids_in(&pool, "orders").await.is_empty());
+    assert_eq!(
+        ids_in(&pool, "orders_archive").await,
+        HashSet::from([stale, others[0], others[1]])
+    );
+    let (quantity, status): (i64, String) =
+        sqlx::query_as("SELECT quantity, status FROM orders_archive WHERE id = $1")
+            .bind(stale)
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+    assert_eq!((quantity, status.as_str()), (1, "Filled"));
+}
+
+#[tokio::test]
+async fn retention_without_archive_deletes_old_rows() {
+    let Some(pool) = testing::schema_pool("db").await else {
+        return;
+    };
+    seed_order(&pool, OrderStatus::Filled, 60).await;
+    let new_filled = seed_order(&pool, OrderStatus::Filled, 1).await;
+    seed_trade(&pool, 1, 60).await;
+
+    Retention::new(retention_config(false, 10))
+        .run_once(&pool)
+        .await
+        .unwrap();
+
+    assert_eq!(ids_in(&pool, "orders").await, HashSet::from([new_filled]));
+    assert!(ids_in(&pool, "trades").await.is_empty());
+    assert!(ids_in(&pool, "orders_archive").await.is_empty());
+    assert!(ids_in(&pool, "trades_archive").await.is_empty());
+}
+
+#[tokio::test]
+async fn retention_runs_alongside_concurrent_inserts() {
+    let Some(pool) = testing::schema_pool("db").await else {
+        return;
+    };
+    let mut old_trades = HashSet::new();
+    for seq in 1..=200 {
+        old_trades.insert(seed_trade(&pool, seq, 60).await);
+    }
+
+    let retention = Retention::new(retention_config(true, 10));
+    let writer = async {
+        let mut new_trades = HashSet::new();
+        for seq in 201..=300 {
+            new_trades.insert(seed_trade(&pool, seq, 0).await);
+        }
+        new_trades
+    };
+    let (run, new_trades) = tokio::join!(retention.run_once(&pool), writer);
+
+    assert_eq!(run.unwrap().trades_moved, 200);
+    assert_eq!(ids_in(&pool, "trades").await, new_trades);
+    assert_eq!(ids_in(&pool, "trades_archive").await, old_trades);
+}
+
+#[tokio::test]
+async fn admin_can_trigger_retention_and_poll_progress() {
+    let Some(pool) = testing::schema_pool("db").await else {
+        return;
+    };
+    let old_trade = seed_trade(&pool, 1, 400).await;
+    let state = testing::db_app_state(pool.clone());
+    let exchange = TestExchange::builder_with_state(state)
+        .admin()
+        .start()
+        .await;
+    let (client, base_url) = (exchange.client(), &exchange.base_url);
+    let admin = exchange.admin_token().token;
+
+    let res = client
+        .post(format!("{}/admin/retention/run", base_url))
+        .bearer_auth(&admin)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status().as_u16(), 202);
+
+    let mut progress = serde_json::Value::Null;
+    for _ in 0..50 {
+        progress = client
+            .get(format!("{}/admin/retention", base_url))
+            .bearer_auth(&admin)
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        if progress["running"] == false {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    assert_eq!(progress["running"], false);
+    assert_eq!(progress["last_run"]["trades_moved"], 1);
+    assert_eq!(
+        ids_in(&pool, "trades_archive").await,
+        HashSet::from([old_trade])
+    );
+
+    let res = client
+        .get(format!(
+            "{}/trades?symbol=BTCUSDT&include_archived=true",
+            base_url
+        ))
+        .bearer_auth(&admin)
+        .send()
+        .await
+        .unwrap();
+    let trades = res.json::<Page<serde_json::Value>>().await.unwrap().items;
+    assert_eq!(trades.len(), 1);
+}
+
+// --- Hydration ---
+
+async fn seed_raw_order(pool: &PgPool, side: &str, status: &str) -> Uuid {
+    let id = Uuid::new_v4();
+    sqlx::query(
+        "INSERT INTO orders (id, user_id, symbol, side, order_type, price, quantity, status) \
+         VALUES ($1, $2, 'BTCUSDT', $3, 'Limit', 100, 1, $4)",
+    )
+    .bind(id)
+    .bind(Uuid::new_v4())
+    .bind(side)
+    .bind(status)
+    .execute(pool)
+    .await
+    .unwrap();
+    id
+}
+
+#[tokio::test]
+async fn hydration_reports_skipped_rows_and_strict_mode_fails() {
+    let Some(pool) = testing::schema_pool("db").await else {
+        return;
+    };
+    persistence::insert_user(&pool, Uuid::new_v4(), "alice", "hash", Utc::now())
+        .await
+        .unwrap();
+    seed_raw_order(&pool, "Buy", "Pending").await;
+    seed_raw_order(&pool, "Sell", "PartiallyFilled").await;
+    let bad = seed_raw_order(&pool, "Sideways", "Pending").await;
+    persistence::upsert_position(
+        &pool,
+        Uuid::new_v4(),
+        "BTCUSDT",
+        3,
+        100,
+        Utc::now(),
+        Utc::now(),
+    )
+    .await
+    .unwrap();
+
+    let hydrated = hydration::hydrate(&pool, &["BTCUSDT", "ETHUSDT"], false)
+        .await
+        .expect("lenient hydration succeeds");
+    let report = &hydrated.report;
+    assert_eq!(report.per_symbol_restored["BTCUSDT"], 2);
+    assert_eq!(report.per_symbol_restored["ETHUSDT"], 0);
+    assert_eq!(report.users_restored, 1);
+    assert_eq!(report.positions_restored, 1);
+    assert!(report.errors.is_empty());
+    assert_eq!(report.rows_skipped.len(), 1);
+    assert_eq!(report.rows_skipped[0].id, bad);
+    assert_eq!(report.rows_skipped[0].reason, "invalid side 'Sideways'");
+    assert_eq!(hydrated.orderbooks["BTCUSDT"].get_bids(), vec![(100, 1)]);
+
+    let report = hydration::hydrate(&pool, &["BTCUSDT"], true)
+        .await
+        .err()
+        .expect("strict hydration rejects skipped rows");
+    assert_eq!(report.rows_skipped[0].id, bad);
+
+    sqlx::query("DELETE FROM orders WHERE id = $1")
+        .bind(bad)
+        .execute(&pool)
+        .await
+        .unwrap();
+    let hydrated = hydration::hydrate(&pool, &["BTCUSDT"], true)
+        .await
+        .expect("strict hydration accepts a clean database");
+    assert!(hydrated.report.is_clean());
+}
+
+#[tokio::test]
+async fn hydration_applies_the_skew_policy_to_order_timestamps() {
+    let Some(pool) = testing::schema_pool("db").await else {
+        return;
+    };
+    let now = Utc::now();
+    let on_time = seed_raw_order(&pool, "Buy", "Pending").await;
+    let ahead = seed_raw_order(&pool, "Buy", "Pending").await;
+    sqlx::query("UPDATE orders SET created_at = $2 WHERE id = $1")
+        .bind(ahead)
+        .bind(now + chrono::Duration::days(1))
+        .execute(&pool)
+        .await
+        .unwrap();
+    let hydrate = |policy| {
+        let pool = pool.clone();
+        async move {
+            let skew = SkewWindow {
+                policy,
+                ..SkewWindow::default()
+            };
+            hydration::hydrate_with(&pool, &["BTCUSDT"], true, &skew, now).await
+        }
+    };
+
+    // Accepted by default, but reported; strict mode does not mind
+    let hydrated = hydrate(SkewPolicy::AcceptWithWarning).await.unwrap();
+    let skewed = &hydrated.report.timestamps_skewed;
+    assert_eq!(skewed.len(), 1);
+    assert_eq!((skewed[0].table.as_str(), skewed[0].id), ("orders", ahead));
+    assert!(
+        skewed[0].warning.contains("in the future"),
+        "{}",
+        skewed[0].warning
+    );
+    assert!(hydrated.report.to_string().contains("1 timestamps skewed"));
+    let book = &hydrated.orderbooks["BTCUSDT"];
+    assert_eq!(book.get_bids(), vec![(100, 2)]);
+    assert!(book.get_order_by_id(ahead).unwrap().timestamp > now);
+
+    // Clamped to the edge of the window, still behind the order that is on time
+    let hydrated = hydrate(SkewPolicy::Clamp).await.unwrap();
+    assert_eq!(hydrated.report.timestamps_skewed.len(), 1);
+    let book = &hydrated.orderbooks["BTCUSDT"];
+    let clamped = book.get_order_by_id(ahead).unwrap().timestamp;
+    assert_eq!(clamped, now + SkewWindow::default().max_lead);
+    assert!(book.get_order_by_id(on_time).unwrap().timestamp < clamped);
+
+    let report = hydrate(SkewPolicy::Reject).await.err().unwrap();
+    assert_eq!(report.rows_skipped.len(), 1);
+    assert_eq!(report.rows_skipped[0].id, ahead);
+    assert!(report.timestamps_skewed.is_empty());
+}
+
+// --- Duplicate-safe trade persistence ---
+
+fn sample_trade(trade_seq: u64) -> Trade {
+    Trade {
+        id: Uuid::new_v4(),
+        trade_seq,
+        maker_order_id: Uuid::new_v4(),
+        taker_order_id: Uuid::new_v4(),
+        maker_user_id: Uuid::new_v4(),
+        taker_user_id: Uuid::new_v4(),
+        price: 100,
+        quantity: 1,
+        timestamp: Utc::now(),
+        maker_queue_rank: Some(0),
+    }
+}
+
+#[tokio::test]
+async fn inserting_the_same_trade_batch_twice_stores_one_copy() {
+    let Some(pool) = testing::schema_pool("db").await else {
+        return;
+    };
+    let batch: Vec<Trade> = (1..=3).map(sample_trade).collect();
+
+    let first = persistence::insert_trades_bulk(&pool, "BTCUSDT", &batch, &[])
+        .await
+        .unwrap();
+    let second = persistence::insert_trades_bulk(&pool, "BTCUSDT", &batch, &[])
+        .await
+        .unwrap();
+    assert_eq!((first, second), (3, 0));
+
+    let t = &batch[0];
+    let single = persistence::insert_trade(
+        &pool,
+        t.id,
+        t.trade_seq,
+        t.maker_order_id,
+        t.taker_order_id,
+        t.maker_user_id,
+        t.taker_user_id,
+        "BTCUSDT",
+        t.price,
+        t.quantity,
+        t.timestamp,
+    )
+    .await
+    .unwrap();
+    assert_eq!(single, 0);
+
+    let stored = persistence::list_trades(&pool, "BTCUSDT", 100, false)
+        .await
+        .unwrap();
+    assert_eq!(stored.len(), 3);
+
+    let metrics = TradePersistenceMetrics::default();
+    metrics.record_insert(batch.len(), second);
+    assert_eq!(metrics.snapshot().duplicates_skipped, 3);
+}
+
+#[tokio::test]
+async fn reconcile_reports_trades_missing_from_db() {
+    let Some(pool) = testing::schema_pool("db").await else {
+        return;
+    };
+    let batch: Vec<Trade> = (1..=4).map(sample_trade).collect();
+    persistence::insert_trades_bulk(&pool, "BTCUSDT", &batch, &[])
+        .await
+        .unwrap();
+    let ids: Vec<Uuid> = batch.iter().map(|t| t.id).collect();
+    assert!(
+        persistence::reconcile_trades(&pool, "BTCUSDT", &ids)
+            .await
+            .unwrap()
+            .is_empty()
+    );
+
+    sqlx::query("DELETE FROM trades WHERE id = $1")
+        .bind(batch[2].id)
+        .execute(&pool)
+        .await
+        .unwrap();
+    let missing = persistence::reconcile_trades(&pool, "BTCUSDT", &ids)
+        .await
+        .unwrap();
+    assert_eq!(missing, vec![batch[2].id]);
+
+    // Ids stored under another symbol do not count as present
+    let missing = persistence::reconcile_trades(&pool, "ETHUSDT", &ids[..1])
+        .await
+        .unwrap();
+    assert_eq!(missing, vec![batch[0].id]);
+}
+
+#[tokio::test]
+async fn admin_reconcile_detects_and_repairs_memory_database_drift() {
+    let Some(pool) = testing::schema_pool("db").await else {
+        return;
+    };
+    let state = testing::db_app_state(pool.clone());
+    let exchange = TestExchange::builder_with_state(state)
+        .admin()
+        .start()
+        .await;
+    let (client, base_url) = (exchange.client(), &exchange.base_url);
+    let Token {
+        user_id: admin_id,
+        token: admin,
+    } = exchange.admin_token();
+    let Token {
+        user_id: maker_id,
+        token: maker,
+    } = exchange.register("maker", "secret").await;
+    let Token {
+        user_id: taker_id,
+        token: taker,
+    } = exchange.register("taker", "secret").await;
+
+    let ask = place(client, base_url, &maker, "Sell", 100, 5).await;
+    let ask_id = Uuid::parse_str(ask["id"].as_str().unwrap()).unwrap();
+    place(client, base_url, &taker, "Buy", 100, 3).await;
+
+    // Drift: a maker row that missed its fill, a stale open row the book never held, a lost
+    // position and a wrong one
+    persistence::update_order_state(&pool, ask_id, OrderStatus::Pending, 5)
+        .await
+        .unwrap();
+    let stale_id = Uuid::new_v4();
+    persistence::insert_order(
+        &pool,
+        stale_id,
+        maker_id,
+        "BTCUSDT",
+        OrderSide::Buy,
+        OrderType::Limit,
+        90,
+        1,
+        OrderStatus::Pending,
+        Utc::now(),
+        &Default::default(),
+        OrderSource::Api,
+        None,
+    )
+    .await
+    .unwrap();
+    persistence::delete_position(&pool, taker_id, "BTCUSDT")
+        .await
+        .unwrap();
+    persistence::upsert_position(&pool, maker_id, "BTCUSDT", -1, 100, Utc::now(), Utc::now())
+        .await
+        .unwrap();
+
+    let reconcile = |query: &'static str| {
+        client
+            .get(format!(
+                "{}/admin/reconcile?symbol=BTCUSDT{}",
+                base_url, query
+            ))
+            .bearer_auth(&admin)
+            .send()
+    };
+    let res = reconcile("").await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    let report: serde_json::Value = res.json().await.unwrap();
+    assert_eq!(report["consistent"], false);
+    assert_eq!(report["repaired"], serde_json::Value::Null);
+    assert_eq!(report["orders"]["checked"], 1);
+    assert_eq!(report["orders"]["missing"], serde_json::json!([]));
+    assert_eq!(report["orders"]["extra"], serde_json::json!([stale_id]));
+    // The maker row holds its quantity from before the fill
+    let mismatched = &report["orders"]["mismatched"][0];
+    assert_eq!(mismatched["order_id"], ask_id.to_string());
+    assert_eq!(
+        mismatched["memory"],
+        serde_json::json!({ "status": "PartiallyFilled", "quantity": 2 })
+    );
+    assert_eq!(mismatched["database"]["quantity"], 5);
+    assert_eq!(
+        report["positions"]["missing"],
+        serde_json::json!([taker_id])
+    );
+    assert_eq!(
+        report["positions"]["mismatched"][0]["memory"],
+        serde_json::json!({ "quantity": -3, "average_price": 100 })
+    );
+    // A plain check writes nothing
+    assert_eq!(
+        persistence::list_open_orders_by_symbol(&pool, "BTCUSDT")
+            .await
+            .unwrap()
+            .len(),
+        2
+    );
+
+    for (query, target) in [
+        ("&repair=orders", "orders"),
+        ("&repair=positions", "positions"),
+    ] {
+        let report: serde_json::Value = reconcile(query).await.unwrap().json().await.unwrap();
+        assert_eq!(report["repaired"], target);
+    }
+    let report: serde_json::Value = reconcile("").await.unwrap().json().await.unwrap();
+    assert_eq!(report["consistent"], true);
+
+    let stale = persistence::get_order_by_id(&pool, stale_id)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(stale.status, "Cancelled");
+    assert_eq!(stale.close_reason.as_deref(), Some("RECONCILED"));
+    let audit = persistence::list_reconcile_audit(&pool, "BTCUSDT")
+        .await
+        .unwrap();
+    let mut actions: Vec<(&str, &str)> = audit
+        .iter()
+        .map(|row| (row.entity.as_str(), row.action.as_str()))
+        .collect();
+    actions.sort();
+    assert_eq!(
+        actions,
+        vec![
+            ("order", "closed"),
+            ("order", "updated"),
+            ("position", "inserted"),
+            ("position", "updated"),
+        ]
+    );
+    assert!(audit.iter().all(|row| row.admin_user_id == admin_id));
+
+    let res = reconcile("&repair=trades").await.unwrap();
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn admin_reconcile_reinserts_missing_book_trades() {
+    let Some(pool) = testing::schema_pool("db").await else {
+        return;
+    };
+    let state = testing::db_app_state(pool.clone());
+    let exchange = TestExchange::builder_with_state(state)
+        .admin()
+        .start()
+        .await;
+    let (client, base_url) = (exchange.client(), &exchange.base_url);
+    let admin = exchange.admin_token().token;
+    let trader = exchange.register("trader", "secret").await.token;
+
+    place(client, base_url, &trader, "Sell", 100, 2).await;
+    place(client, base_url, &trader, "Buy", 100, 1).await;
+    place(client, base_url, &trader, "Buy", 100, 1).await;
+    let stored = persistence::list_trades(&pool, "BTCUSDT", 10, false)
+        .await
+        .unwrap();
+    assert_eq!(stored.len(), 2);
+    sqlx::query("DELETE FROM trades WHERE id = $1")
+        .bind(stored[0].id)
+        .execute(&pool)
+        .await
+        .unwrap();
+
+    let json: serde_json::Value = client
+        .post(format!("{}/admin/trades/reconcile", base_url))
+        .bearer_auth(&admin)
+        .json(&serde_json::json!({ "symbol": "BTCUSDT" }))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(json["checked"], 2);
+    assert_eq!(json["missing"], serde_json::json!([stored[0].id]));
+    assert_eq!(json["reinserted"], 1);
+    assert_eq!(
+        persistence::list_trades(&pool, "BTCUSDT", 10, false)
+            .await
+            .unwrap()
+            .len(),
+        2
+    );
+
+    let metrics: serde_json::Value = client
+        .get(format!("{}/admin/persistence", base_url))
+        .bearer_auth(&admin)
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(metrics["missing_found"], 1);
+    assert_eq!(metrics["inserted"], 3);
+}
+
+#[tokio::test]
+async fn statement_timeout_returns_query_timeout_error() {
+    let Some(pool) = testing::schema_pool("db").await else {
+        return;
+    };
+    let options = (*pool.connect_options()).clone();
+    let short = persistence::pool_options(Some(Duration::from_millis(200)))
+        .connect_with(options)
+        .await
+        .unwrap();
+
+    // Test-only route that runs a query far slower than the statement timeout
+    let sleep_pool = short.clone();
+    let sleep = Router::new().route(
+        "/test/sleep",
+        get(move || async move {
+            sqlx::query("SELECT pg_sleep(5)")
+                .execute(&sleep_pool)
+                .await
+                .map(|_| StatusCode::OK)
+                .map_err(|e| ErrorResponse::from_db("Failed to sleep", e))
+        }),
+    );
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let base_url = format!("http://{}", listener.local_addr().unwrap());
+    let app = app_router(testing::db_app_state(short)).merge(sleep);
+    tokio::spawn(async move {
+        axum::serve(listener, app).await.unwrap();
+    });
+
+    let started = std::time::Instant::now();
+    let resp = reqwest::Client::new()
+        .get(format!("{}/test/sleep", base_url))
+        .send()
+        .await
+        .unwrap();
+    assert!(started.elapsed() < Duration::from_secs(3));
+    assert_eq!(resp.status(), 503);
+    let json: serde_json::Value = resp.json().await.unwrap();
+    assert_eq!(json["error_code"], "QUERY_TIMEOUT");
+    assert_eq!(json["code"], 503);
+
+    // Persistence calls feed the per-query latency histogram
+    persistence::list_trades(&pool, "BTCUSDT", 10, false)
+        .await
+        .unwrap();
+    assert!(persistence::query_histograms()["list_trades"].count >= 1);
+}
+
+#[tokio::test]
+async fn change_username_persists_rename_and_history() {
+    let Some(pool) = testing::schema_pool("db").await else {
+        return;
+    };
+    let state = testing::db_app_state(pool.clone());
+    let exchange = TestExchange::builder_with_state(state.clone())
+        .start()
+        .await;
+    let (client, base_url) = (exchange.client(), &exchange.base_url);
+    let Token {
+        user_id: alice_id,
+        token: alice,
+    } = exchange.register("alice", "secret").await;
+    let bob = exchange.register("bob", "secret").await.token;
+
+    let rename = |token: &str, name: &str| {
+        client
+            .post(format!("{}/auth/change-username", base_url))
+            .bearer_auth(token)
+            .json(&serde_json::json!({ "new_username": name, "password": "secret" }))
+            .send()
+    };
+    assert_eq!(rename(&alice, "alicia").await.unwrap().status(), 200);
+    let row = persistence::get_user_by_username(&pool, "alicia")
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(row.id, alice_id);
+    assert!(
+        persistence::get_user_by_username(&pool, "alice")
+            .await
+            .unwrap()
+            .is_none()
+    );
+    let since = Utc::now() - chrono::Duration::days(1);
+    assert_eq!(
+        persistence::username_released_by(&pool, "alice", since)
+            .await
+            .unwrap(),
+        Some(alice_id)
+    );
+
+    // The cooldown is read from username_history, not the in-memory history
+    let mut restarted = testing::db_app_state(pool.clone());
+    restarted.user_store = state.user_store.clone();
+    let exchange = TestExchange::builder_with_state(restarted).start().await;
+    let (client, base_url) = (exchange.client(), &exchange.base_url);
+    let res = client
+        .post(format!("{}/auth/change-username", base_url))
+        .bearer_auth(&bob)
+        .json(&serde_json::json!({ "new_username": "alice", "password": "secret" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), 400);
+    let json: serde_json::Value = res.json().await.unwrap();
+    assert!(
+        json["error"]
+            .as_str()
+            .unwrap()
+            .contains("recently released")
+    );
+}
+
+#[tokio::test]
+async fn rejected_order_is_stored_with_close_reason() {
+    let Some(pool) = testing::schema_pool("db").await else {
+        return;
+    };
+    let exchange = TestExchange::builder_with_state(testing::db_app_state(pool.clone()))
+        .start()
+        .await;
+    let (client, base_url) = (exchange.client(), &exchange.base_url);
+    let Token { user_id, token } = exchange.register("alice", "secret").await;
+
+    let res = client
+        .post(format!("{}/orders", base_url))
+        .bearer_auth(&token)
+        .json(&serde_json::json!({
+            "symbol": "BTCUSDT",
+            "price": 0,
+            "quantity": 3,
+            "side": "Sell",
+            "order_type": "Market"
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), 400);
+
+    let rows = persistence::list_orders_for_user(
+        &pool,
+        user_id,
+        None,
+        Environment::Live,
+        &Default::default(),
+        None,
+        None,
+        10,
+    )
+    .await
+    .unwrap();
+    assert_eq!(rows.len(), 1);
+    assert_eq!(rows[0].status, "Cancelled");
+    assert_eq!(rows[0].close_reason.as_deref(), Some("NO_LIQUIDITY"));
+    assert_eq!(rows[0].source, "Api");
+}
+
+#[tokio::test]
+async fn order_history_filters_by_source() {
+    let Some(pool) = testing::schema_pool("db").await else {
+        return;
+    };
+    let exchange = TestExchange::builder_with_state(testing::db_app_state(pool.clone()))
+        .start()
+        .await;
+    let (client, base_url) = (exchange.client(), &exchange.base_url);
+    let token = exchange.register("alice", "secret").await.token;
+    place(client, base_url, &token, "Buy", 100, 1).await;
+
+    for (source, count) in [("Api", 1), ("Admin", 0)] {
+        let orders = client
+            .get(format!("{}/orders/me?source={}", base_url, source))
+            .bearer_auth(&token)
+            .send()
+            .await
+            .unwrap()
+            .json::<Page<serde_json::Value>>()
+            .await
+            .unwrap()
+            .items;
+        assert_eq!(orders.len(), count, "{}", source);
+        if count > 0 {
+            assert_eq!(orders[0]["source"], source);
+        }
+    }
+}
+
+#[tokio::test]
+async fn closed_candles_are_persisted_and_reloaded() {
+    let Some(pool) = testing::schema_pool("db").await else {
+        return;
+    };
+    let (ws_tx, _) = broadcast::channel(16);
+    let store = Arc::new(MarketDataStore::default());
+    let tasks = Supervisor::default();
+    market_data::spawn_aggregator(&tasks, store.clone(), &ws_tx, Some(pool.clone()));
+
+    // Two trades in the minute before last, one that opens the last minute and closes it
+    let now = Utc::now();
+    let minute = market_data::candle_open_time(now) - chrono::Duration::minutes(1);
+    for (seq, secs, price) in [(1, 5, 100), (2, 30, 120), (3, 65, 90)] {
+        let trade = Trade {
+            price,
+            timestamp: minute + chrono::Duration::seconds(secs),
+            ..sample_trade(seq)
+        };
+        ws_tx
+            .send(WsMessage::Trade {
+                symbol: "BTCUSDT".to_string(),
+                trade,
+            })
+            .unwrap();
+    }
+
+    let since = now - chrono::Duration::hours(1);
+    let mut rows = Vec::new();
+    for _ in 0..50 {
+        rows = persistence::list_candles_since(&pool, since).await.unwrap();
+        if !rows.is_empty() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    // Only the closed candle is stored; the open one is still being built
+    assert_eq!(rows.len(), 1);
+    let candle = rows[0].to_candle();
+    assert_eq!(rows[0].symbol, "BTCUSDT");
+    assert_eq!(candle.open_time, minute);
+    assert_eq!((candle.open, candle.high, candle.close), (100, 120, 120));
+    assert_eq!((candle.volume, candle.trade_count), (2, 2));
+
+    // A restarted process seeds its history from the table
+    let restarted = MarketDataStore::default();
+    restarted.load_candles("BTCUSDT", vec![candle]);
+    assert_eq!(restarted.recent_candles("BTCUSDT", now), vec![candle]);
+}
+
+#[tokio::test]
+async fn rolling_ticker_is_seeded_from_stored_trades_after_a_restart() {
+    let Some(pool) = testing::schema_pool("db").await else {
+        return;
+    };
+    let now = Utc::now();
+    let mut busted = None;
+    for (trade_seq, price, quantity, age_minutes) in [
+        // Older than a day: out of the window
+        (1, 150, 2, 25 * 60),
+        (2, 100, 3, 23 * 60),
+        (3, 90, 1, 120),
+        (4, 110, 4, 10),
+        // Busted below
+        (5, 200, 5, 5),
+    ] {
+        let id = Uuid::new_v4();
+        persistence::insert_trade(
+            &pool,
+            id,
+            trade_seq,
+            Uuid::new_v4(),
+            Uuid::new_v4(),
+            Uuid::new_v4(),
+            Uuid::new_v4(),
+            "BTCUSDT",
+            price,
+            quantity,
+            now - chrono::Duration::minutes(age_minutes),
+        )
+        .await
+        .unwrap();
+        busted = Some(id);
+    }
+    sqlx::query("UPDATE trades SET busted_at = NOW(), bust_reason = 'test' WHERE id = $1")
+        .bind(busted.unwrap())
+        .execute(&pool)
+        .await
+        .unwrap();
+
+    // A restarted server: empty in memory until seeded from the trades table
+    let state = testing::db_app_state(pool.clone());
+    market_data::load_rolling_stats(
+        &state.market_data,
+        &pool,
+        &["BTCUSDT".to_string()],
+        Utc::now(),
+        None,
+    )
+    .await
+    .unwrap();
+    market_data::spawn_aggregator(
+        &state.tasks,
+        state.market_data.clone(),
+        &state.ws_channel,
+        None,
+    );
+    let exchange = TestExchange::builder_with_state(state.clone())
+        .start()
+        .await;
+    let (client, base_url) = (exchange.client(), &exchange.base_url);
+    let ticker = || async {
+        client
+            .get(format!("{}/ticker?symbol=btcusdt", base_url))
+            .send()
+            .await
+            .unwrap()
+            .json::<serde_json::Value>()
+            .await
+            .unwrap()
+    };
+    let json = ticker().await;
+    assert_eq!(json["symbol"], "BTCUSDT");
+    assert_eq!(json["high"], 110);
+    assert_eq!(json["low"], 90);
+    assert_eq!(json["volume"], 8);
+    assert_eq!(json["trade_count"], 3);
+
+    // New trades are added in memory between refreshes
+    let maker = exchange.register("maker", "secret").await.token;
+    let taker = exchange.register("taker", "secret").await.token;
+    place(client, base_url, &maker, "Sell", 80, 2).await;
+    place(client, base_url, &taker, "Buy", 80, 2).await;
+    let mut json = ticker().await;
+    for _ in 0..50 {
+        if json["trade_count"] == 4 {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        json = ticker().await;
+    }
+    assert_eq!(json["trade_count"], 4);
+    assert_eq!(json["low"], 80);
+    assert_eq!(json["volume"], 10);
+
+    // A refresh rewrites the settled buckets from the database and keeps the newer ones
+    // (the trades seeded above are all in intervals before `now`'s, the new one is not)
+    let settled = Some(market_data::candle_open_time(now));
+    state
+        .market_data
+        .load_rolling("BTCUSDT", Vec::new(), settled);
+    let json = ticker().await;
+    assert_eq!(json["trade_count"], 1);
+    assert_eq!(json["high"], 80);
+    market_data::load_rolling_stats(
+        &state.market_data,
+        &pool,
+        &["BTCUSDT".to_string()],
+        Utc::now(),
+        settled,
+    )
+    .await
+    .unwrap();
+    let json = ticker().await;
+    assert_eq!(json["trade_count"], 4);
+    assert_eq!(json["high"], 110);
+}
+
+#[tokio::test]
+async fn trades_of_one_sweep_are_listed_in_sequence_order() {
+    let Some(pool) = testing::schema_pool("db").await else {
+        return;
+    };
+    let exchange = TestExchange::builder_with_state(testing::db_app_state(pool.clone()))
+        .start()
+        .await;
+    let (client, base_url) = (exchange.client(), &exchange.base_url);
+    let seller = exchange.register("seller", "secret").await.token;
+    let buyer = exchange.register("buyer", "secret").await.token;
+    for price in [100, 101, 102, 103] {
+        place(client, base_url, &seller, "Sell", price, 1).await;
+    }
+    place(client, base_url, &buyer, "Buy", 103, 4).await;
+
+    let mut listings = Vec::new();
+    for path in ["/trades/me", "/trades/me?symbol=BTCUSDT"] {
+        let trades = client
+            .get(format!("{}{}", base_url, path))
+            .bearer_auth(&buyer)
+            .send()
+            .await
+            .unwrap()
+            .json::<Page<TradeDto>>()
+            .await
+            .unwrap()
+            .items;
+        listings.push(trades);
+    }
+    for trades in &listings {
+        let seqs: Vec<u64> = trades.iter().map(|t| t.trade_seq).collect();
+        assert_eq!(seqs, vec![4, 3, 2, 1]);
+        let prices: Vec<i64> = trades.iter().map(|t| t.price).collect();
+        assert_eq!(prices, vec![103, 102, 101, 100]);
+        assert!(trades.iter().all(|t| t.timestamp == trades[0].timestamp));
+    }
+}
+
+#[tokio::test]
+async fn busted_trades_are_marked_audited_and_hidden_from_public_listings() {
+    let Some(pool) = testing::schema_pool("db").await else {
+        return;
+    };
+    let state = testing::db_app_state(pool.clone());
+    let positions = state.positions.clone();
+    let exchange = TestExchange::builder_with_state(state)
+        .admin()
+        .start()
+        .await;
+    let (client, base_url) = (exchange.client(), &exchange.base_url);
+    let Token {
+        user_id: admin_id,
+        token: admin,
+    } = exchange.admin_token();
+    let seller = exchange.register("seller", "secret").await.token;
+    let buyer = exchange.register("buyer", "secret").await.token;
+    for price in [100, 110] {
+        place(client, base_url, &seller, "Sell", price, 2).await;
+        place(client, base_url, &buyer, "Buy", price, 2).await;
+    }
+    let busted = persistence::list_trades_from_seq(&pool, "BTCUSDT", 1, 1, false)
+        .await
+        .unwrap()
+        .remove(0);
+
+    let bust = || {
+        client
+            .post(format!("{}/admin/trades/{}/bust", base_url, busted.id))
+            .bearer_auth(&admin)
+            .json(&serde_json::json!({ "reason": "fat finger" }))
+            .send()
+    };
+    assert_eq!(bust().await.unwrap().status(), StatusCode::OK);
+
+    let row = persistence::find_trade(&pool, busted.id)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(row.bust_reason.as_deref(), Some("fat finger"));
+    assert!(row.busted_at.is_some());
+    let public = client
+        .get(format!("{}/trades?symbol=BTCUSDT", base_url))
+        .bearer_auth(&buyer)
+        .send()
+        .await
+        .unwrap()
+        .json::<Page<TradeDto>>()
+        .await
+        .unwrap()
+        .items;
+    assert_eq!(
+        public.iter().map(|t| t.price).collect::<Vec<_>>(),
+        vec![110]
+    );
+    let own = client
+        .get(format!("{}/trades/me", base_url))
+        .bearer_auth(&buyer)
+        .send()
+        .await
+        .unwrap()
+        .json::<Page<UserTradeDto>>()
+        .await
+        .unwrap()
+        .items;
+    assert_eq!(own.len(), 2);
+    assert_eq!(own[1].bust_reason.as_deref(), Some("fat finger"));
+
+    let audit = persistence::list_trade_bust_audit(&pool, "BTCUSDT")
+        .await
+        .unwrap();
+    assert_eq!(audit.len(), 1);
+    assert_eq!(
+        (audit[0].trade_id, audit[0].admin_user_id),
+        (busted.id, admin_id)
+    );
+    assert_eq!(audit[0].positions.0.as_array().unwrap().len(), 2);
+    assert_eq!(
+        db_positions(&pool).await,
+        memory_positions(&positions).await
+    );
+
+    assert_eq!(bust().await.unwrap().status(), StatusCode::CONFLICT);
+}
+
+#[tokio::test]
+async fn trade_fees_and_realized_pnl_are_stored_and_survive_archiving() {
+    let Some(pool) = testing::schema_pool("db").await else {
+        return;
+    };
+    let state = testing::db_app_state(pool.clone());
+    state.symbol_configs.write().await.insert(
+        "BTCUSDT".to_string(),
+        SymbolConfig {
+            maker_fee_bps: -2,
+            taker_fee_bps: 10,
+            ..SymbolConfig::for_symbol("BTCUSDT")
+        },
+    );
+    let exchange = TestExchange::builder_with_state(state).start().await;
+    let (client, base_url) = (exchange.client(), &exchange.base_url);
+    let trader = exchange.register("trader", "secret").await.token;
+    let maker = exchange.register("maker", "secret").await.token;
+    place(client, base_url, &maker, "Sell", 10_000, 5).await;
+    place(client, base_url, &trader, "Buy", 10_000, 5).await;
+    // Partial close across two fills: 2 at 10_200, then 1 at 10_100
+    place(client, base_url, &maker, "Buy", 10_100, 4).await;
+    place(client, base_url, &maker, "Buy", 10_200, 2).await;
+    place(client, base_url, &trader, "Sell", 10_100, 3).await;
+
+    let trades_me = |path: &'static str| {
+        let client = client.clone();
+        let url = format!("{}{}", base_url, path);
+        let trader = trader.clone();
+        async move {
+            let trades = client
+                .get(url)
+                .bearer_auth(&trader)
+                .send()
+                .await
+                .unwrap()
+                .json::<Page<UserTradeDto>>()
+                .await
+                .unwrap()
+                .items;
+            trades
+                .iter()
+                .map(|t| (t.role, t.side, t.fee, t.realized_pnl))
+                .collect::<Vec<_>>()
+        }
+    };
+    let expected = vec![
+        (TradeRole::Taker, Some(OrderSide::Sell), 10, 100),
+        (TradeRole::Taker, Some(OrderSide::Sell), 20, 400),
+        (TradeRole::Taker, Some(OrderSide::Buy), 50, 0),
+    ];
+    assert_eq!(trades_me("/trades/me").await, expected);
+
+    let moved = persistence::archive_trades_batch(
+        &pool,
+        Utc::now() + chrono::Duration::seconds(1),
+        100,
+        true,
+    )
+    .await
+    .unwrap();
+    assert_eq!(moved, 3);
+    assert!(trades_me("/trades/me").await.is_empty());
+    assert_eq!(
+        trades_me("/trades/me?include_archived=true").await,
+        expected
+    );
+}
+
+#[tokio::test]
+async fn daily_statements_reconcile_with_the_days_trades() {
+    let Some(pool) = testing::schema_pool("db").await else {
+        return;
+    };
+    let day = NaiveDate::from_ymd_opt(2025, 3, 10).unwrap();
+    let at = |d: NaiveDate, hour: u32| d.and_hms_opt(hour, 0, 0).unwrap().and_utc();
+    let clock = Arc::new(ManualClock::new(at(day.pred_opt().unwrap(), 12)));
+    let mut state = testing::db_app_state(pool.clone());
+    state.orderbooks.insert(
+        "BTCUSDT".to_string(),
+        Arc::new(RwLock::new(OrderBook::with_clock(clock.clone()))),
+    );
+    state.symbol_configs.write().await.insert(
+        "BTCUSDT".to_string(),
+        SymbolConfig {
+            maker_fee_bps: -2,
+            taker_fee_bps: 10,
+            ..SymbolConfig::for_symbol("BTCUSDT")
+        },
+    );
+    let exchange = TestExchange::builder_with_state(state)
+        .admin()
+        .start()
+        .await;
+    let (client, base_url) = (exchange.client(), &exchange.base_url);
+    let admin = exchange.admin_token().token;
+    let Token {
+        user_id: trader_id,
+        token: trader,
+    } = exchange.register("trader", "secret").await;
+    let maker = exchange.register("maker", "secret").await.token;
+    exchange.register("idle", "secret").await;
+
+    // The day before: the trader opens long 5
+    place(client, base_url, &maker, "Sell", 10_000, 5).await;
+    place(client, base_url, &trader, "Buy", 10_000, 5).await;
+    // The day: sells 2 as taker, buys 1 back as maker
+    clock.set(at(day, 10));
+    place(client, base_url, &maker, "Buy", 10_200, 2).await;
+    place(client, base_url, &trader, "Sell", 10_200, 2).await;
+    clock.set(at(day, 15));
+    place(client, base_url, &trader, "Buy", 10_300, 1).await;
+    place(client, base_url, &maker, "Sell", 10_300, 1).await;
+    // The day after: not on the statement, and not the day's mark
+    clock.set(at(day.succ_opt().unwrap(), 1));
+    place(client, base_url, &maker, "Buy", 9_000, 1).await;
+    place(client, base_url, &trader, "Sell", 9_000, 1).await;
+
+    let generate = || {
+        client
+            .post(format!(
+                "{}/admin/statements/generate?date={}",
+                base_url, day
+            ))
+            .bearer_auth(&admin)
+            .send()
+    };
+    let run: serde_json::Value = generate().await.unwrap().json().await.unwrap();
+    assert_eq!(run["users_scanned"], 3);
+    // The idle user never traded
+    assert_eq!(run["statements"], 2);
+    let first = persistence::get_statement(&pool, trader_id, day)
+        .await
+        .unwrap()
+        .unwrap();
+
+    let (start, end) = (at(day, 0), at(day.succ_opt().unwrap(), 0));
+    let days_trades: Vec<UserTradeDto> = client
+        .get(format!("{}/trades/me", base_url))
+        .bearer_auth(&trader)
+        .send()
+        .await
+        .unwrap()
+        .json::<Page<UserTradeDto>>()
+        .await
+        .unwrap()
+        .items
+        .into_iter()
+        .filter(|t| t.trade.timestamp >= start && t.trade.timestamp < end)
+        .collect();
+    assert_eq!(days_trades.len(), 2);
+    assert_eq!(first.trade_count, 2);
+    assert_eq!(first.fees, days_trades.iter().map(|t| t.fee).sum::<i64>());
+    assert_eq!(
+        first.realized_pnl,
+        days_trades.iter().map(|t| t.realized_pnl).sum::<i64>()
+    );
+    assert_eq!((first.fees, first.realized_pnl), (20 - 2, 400));
+
+    let statement = &first.details.0;
+    assert_eq!(statement.positions.len(), 1);
+    let position = &statement.positions[0];
+    assert_eq!(
+        (position.opening_quantity, position.closing_quantity),
+        (5, 4)
+    );
+    assert_eq!(position.opening_average_price, Some(10_000));
+    assert_eq!(position.mark_price, Some(10_300));
+    let closing_average = position.closing_average_price.unwrap();
+    assert_eq!(
+        position.unrealized_pnl,
+        Some((10_300 - closing_average) * 4)
+    );
+    assert_eq!(first.unrealized_pnl, position.unrealized_pnl.unwrap());
+    let movements: Vec<(&str, i64)> = statement
+        .balance_movements
+        .iter()
+        .map(|m| (m.asset.as_str(), m.change))
+        .collect();
+    assert_eq!(movements, vec![("BTC", -1), ("USDT", 2 * 10_200 - 10_300)]);
+
+    // Running the day again replaces rather than adds
+    let rerun: serde_json::Value = generate().await.unwrap().json().await.unwrap();
+    assert_eq!(rerun["statements"], 2);
+    let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM statements")
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert_eq!(count, 2);
+    let second = persistence::get_statement(&pool, trader_id, day)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(second.details.0, first.details.0);
+
+    let res = client
+        .get(format!("{}/account/statements?date={}", base_url, day))
+        .bearer_auth(&trader)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.headers()["content-type"], "text/csv");
+    let csv = res.text().await.unwrap();
+    assert_eq!(csv, second.csv);
+    assert!(csv.contains("\ntrades\n"));
+    assert_eq!(csv.lines().filter(|l| l.contains(",BTCUSDT,")).count(), 2);
+
+    let res = client
+        .get(format!(
+            "{}/account/statements?date={}",
+            base_url,
+            day.succ_opt().unwrap()
+        ))
+        .bearer_auth(&trader)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn statements_value_positions_counted_in_fine_lots() {
+    let Some(pool) = testing::schema_pool("db").await else {
+        return;
+    };
+    let day = NaiveDate::from_ymd_opt(2025, 3, 10).unwrap();
+    let clock = Arc::new(ManualClock::new(
+        day.and_hms_opt(12, 0, 0).unwrap().and_utc(),
+    ));
+    let mut state = testing::db_app_state(pool.clone());
+    state.orderbooks.insert(
+        "BTCUSDT".to_string(),
+        Arc::new(RwLock::new(OrderBook::with_clock(clock.clone()))),
+    );
+    // Quantities in lots of 1e-8 BTC, prices at the real scale
+    let configs = HashMap::from([(
+        "BTCUSDT".to_string(),
+        SymbolConfig {
+            qty_scale: 100_000_000,
+            ..SymbolConfig::for_symbol("BTCUSDT")
+        },
+    )]);
+    *state.symbol_configs.write().await = configs.clone();
+    let exchange = TestExchange::builder_with_state(state).start().await;
+    let (client, base_url) = (exchange.client(), &exchange.base_url);
+    let Token {
+        user_id: trader_id,
+        token: trader,
+    } = exchange.register("trader", "secret").await;
+    let maker = exchange.register("maker", "secret").await.token;
+    let other = exchange.register("other", "secret").await.token;
+
+    // The trader buys 1 BTC at 50,000 and the day closes at 60,000: the P&L in lots (1e12
+    // price units times 1e8 lots) is beyond an i64, the quote amount is not
+    let btc = 100_000_000;
+    place(client, base_url, &maker, "Sell", 50_000 * PRICE_SCALE, btc).await;
+    place(client, base_url, &trader, "Buy", 50_000 * PRICE_SCALE, btc).await;
+    place(client, base_url, &maker, "Sell", 60_000 * PRICE_SCALE, 1).await;
+    place(client, base_url, &other, "Buy", 60_000 * PRICE_SCALE, 1).await;
+
+    statements::generate(&pool, &configs, day, 10)
+        .await
+        .unwrap();
+    let statement = persistence::get_statement(&pool, trader_id, day)
+        .await
+        .unwrap()
+        .unwrap();
+    let position = &statement.details.0.positions[0];
+    assert_eq!(position.closing_quantity, btc as i64);
+    assert_eq!(position.mark_price, Some(60_000 * PRICE_SCALE));
+    assert_eq!(position.unrealized_pnl, Some(10_000 * PRICE_SCALE));
+    assert_eq!(statement.unrealized_pnl, 10_000 * PRICE_SCALE);
+}
+
+#[tokio::test]
+async fn self_test_round_trips_through_the_database_and_removes_its_rows() {
+    let Some(pool) = testing::schema_pool("db").await else {
+        return;
+    };
+    let mut state = testing::db_app_state(pool.clone());
+    state.orderbooks.insert(
+        SELFTEST_SYMBOL.to_string(),
+        Arc::new(RwLock::new(OrderBook::new())),
+    );
+
+    // Cleanup leaves the book free for the next run
+    for _ in 0..2 {
+        let report = selftest::run(&state).await;
+        assert!(report.passed, "{:?}", report.steps);
+        let persistence_step = report.steps.iter().find(|s| s.name == "persistence");
+        assert_eq!(persistence_step.unwrap().status, StepStatus::Passed);
+    }
+
+    for table in ["orders", "trades", "positions", "users"] {
+        let count: i64 = sqlx::query_scalar(&format!("SELECT COUNT(*) FROM {}", table))
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(count, 0, "{} rows left behind", table);
+    }
+    assert!(state.user_store.read().await.is_empty());
+}
+
+#[tokio::test]
+async fn operator_ws_pushes_are_audited() {
+    let Some(pool) = testing::schema_pool("db").await else {
+        return;
+    };
+    let state = testing::db_app_state(pool.clone());
+    let exchange = TestExchange::builder_with_state(state)
+        .admin()
+        .start()
+        .await;
+    let (client, base_url) = (exchange.client(), &exchange.base_url);
+    let Token {
+        user_id: admin_id,
+        token: admin,
+    } = exchange.admin_token();
+    let trader = exchange.register("trader", "secret").await.token;
+
+    let res = client
+        .post(format!("{}/admin/ws/broadcast", base_url))
+        .bearer_auth(&trader)
+        .json(&serde_json::json!({ "message": "hello" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::FORBIDDEN);
+    let res = client
+        .post(format!(
+            "{}/admin/ws/rebroadcast?symbol=BTCUSDT&resync=true",
+            base_url
+        ))
+        .bearer_auth(&admin)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    let res = client
+        .post(format!("{}/admin/ws/broadcast", base_url))
+        .bearer_auth(&admin)
+        .json(&serde_json::json!({ "message": "Trading resumes at 10:00" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let rows = persistence::list_ws_broadcast_audit(&pool, 10)
+        .await
+        .unwrap();
+    assert_eq!(rows.len(), 2);
+    assert!(rows.iter().all(|row| row.admin_user_id == admin_id));
+    assert_eq!(rows[0].action, "announcement");
+    assert_eq!(rows[0].symbol, None);
+    assert_eq!(
+        rows[0].details.0,
+        serde_json::json!({ "message": "Trading resumes at 10:00", "severity": "info" })
+    );
+    assert_eq!(rows[1].action, "rebroadcast");
+    assert_eq!(rows[1].symbol.as_deref(), Some("BTCUSDT"));
+    assert_eq!(
+        rows[1].details.0,
+        serde_json::json!({ "symbols": ["BTCUSDT"], "resync": true })
+    );
+}
+
+#[tokio::test]
+async fn login_activity_is_persisted_and_hydrated() {
+    let Some(pool) = testing::schema_pool("db").await else {
+        return;
+    };
+    let exchange = TestExchange::builder_with_state(testing::db_app_state(pool.clone()))
+        .start()
+        .await;
+    let (client, base_url) = (exchange.client(), &exchange.base_url);
+    let alice_id = exchange.register("alice", "secret").await.user_id;
+    let first = persistence::get_user_by_username(&pool, "alice")
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(first.login_count, 1);
+    let first_login = first.last_login_at.expect("login recorded");
+    assert!(first_login >= first.created_at);
+
+    let token = exchange.login("alice", "secret").await.unwrap().token;
+    let second = persistence::get_user_by_username(&pool, "alice")
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(second.login_count, 2);
+    assert!(second.last_login_at.unwrap() > first_login);
+    assert_eq!(second.created_at, first.created_at);
+    let me: serde_json::Value = client
+        .get(format!("{}/auth/me", base_url))
+        .bearer_auth(&token)
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(me["login_count"], 2);
+
+    let hydrated = hydration::hydrate(&pool, &["BTCUSDT"], true)
+        .await
+        .expect("clean hydration");
+    let restored = &hydrated.users["alice"];
+    assert_eq!(restored.user_id, alice_id);
+    assert_eq!(restored.created_at, second.created_at);
+    assert_eq!(restored.last_login_at, second.last_login_at);
+    assert_eq!(restored.login_count, 2);
+}
+
+#[tokio::test]
+async fn kill_switch_is_persisted_audited_and_hydrated() {
+    let Some(pool) = testing::schema_pool("db").await else {
+        return;
+    };
+    let exchange = TestExchange::builder_with_state(testing::db_app_state(pool.clone()))
+        .start()
+        .await;
+    let (client, base_url) = (exchange.client(), &exchange.base_url);
+    let Token {
+        user_id: alice_id,
+        token: alice,
+    } = exchange.register("alice", "secret").await;
+    place(client, base_url, &alice, "Buy", 100, 1).await;
+
+    let engaged: serde_json::Value = client
+        .post(format!("{}/account/kill-switch", base_url))
+        .bearer_auth(&alice)
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let order_id = Uuid::parse_str(engaged["cancelled"][0]["id"].as_str().unwrap()).unwrap();
+    let row = persistence::get_order_by_id(&pool, order_id)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(row.close_reason.as_deref(), Some("KILL_SWITCH"));
+    let user = persistence::get_user_by_username(&pool, "alice")
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(user.trading_disabled);
+    let hydrated = hydration::hydrate(&pool, &["BTCUSDT"], true)
+        .await
+        .expect("clean hydration");
+    assert!(hydrated.trading_disabled.contains(&alice_id));
+
+    let res = client
+        .post(format!("{}/account/kill-switch/disable", base_url))
+        .bearer_auth(&alice)
+        .json(&serde_json::json!({ "password": "secret" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), 200);
+    let user = persistence::get_user_by_username(&pool, "alice")
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(!user.trading_disabled);
+    let audit = persistence::list_kill_switch_audit(&pool, alice_id, 10)
+        .await
+        .unwrap();
+    let actions: Vec<(&str, i32)> = audit
+        .iter()
+        .map(|row| (row.action.as_str(), row.cancelled_orders))
+        .collect();
+    assert_eq!(actions, vec![("disabled", 0), ("engaged", 1)]);
+}
+
+#[tokio::test]
+async fn order_fills_are_read_from_the_trades_table() {
+    let Some(pool) = testing::schema_pool("db").await else {
+        return;
+    };
+    let exchange = TestExchange::builder_with_state(testing::db_app_state(pool.clone()))
+        .start()
+        .await;
+    let (client, base_url) = (exchange.client(), &exchange.base_url);
+    let seller = exchange.register("seller", "secret").await.token;
+    let buyer = exchange.register("buyer", "secret").await.token;
+    for (price, qty) in [(100, 1), (101, 2)] {
+        place(client, base_url, &seller, "Sell", price, qty).await;
+    }
+    let taker = place(client, base_url, &buyer, "Buy", 101, 3).await;
+    let resting = place(client, base_url, &buyer, "Buy", 90, 1).await;
+    let fills_of = |order: &serde_json::Value| {
+        format!(
+            "{}/orders/{}/fills?symbol=BTCUSDT",
+            base_url,
+            order["id"].as_str().unwrap()
+        )
+    };
+
+    let body: serde_json::Value = client
+        .get(fills_of(&taker))
+        .bearer_auth(&buyer)
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let fills: Vec<(i64, u64, &str)> = body["fills"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|f| {
+            (
+                f["price"].as_i64().unwrap(),
+                f["quantity"].as_u64().unwrap(),
+                f["role"].as_str().unwrap(),
+            )
+        })
+        .collect();
+    assert_eq!(fills, vec![(100, 1, "Taker"), (101, 2, "Taker")]);
+    assert_eq!(body["filled_quantity"], 3);
+    assert_eq!(body["average_price"], 100);
+
+    let body: serde_json::Value = client
+        .get(fills_of(&resting))
+        .bearer_auth(&buyer)
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert!(body["fills"].as_array().unwrap().is_empty());
+    assert_eq!(body["average_price"], serde_json::Value::Null);
+    let res = client
+        .get(fills_of(&resting))
+        .bearer_auth(&seller)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), 403);
+}
+
+/// Roll the latest migration back: drop what it added and forget it was applied.
+async fn unapply_latest_migration(pool: &PgPool) -> i64 {
+    let latest = persistence::MIGRATOR
+        .iter()
+        .map(|m| m.version)
+        .max()
+        .unwrap();
+    assert_eq!(
+        latest, 20250131000033,
+        "update this rollback for the new migration"
+    );
+    for table in [
+        "orders",
+        "orders_archive",
+        "trades",
+        "trades_archive",
+        "positions",
+        "candles",
+    ] {
+        sqlx::query(&format!("ALTER TABLE {} DROP COLUMN environment", table))
+            .execute(pool)
+            .await
+            .unwrap();
+    }
+    sqlx::query("DELETE FROM _sqlx_migrations WHERE version = $1")
+        .bind(latest)
+        .execute(pool)
+        .await
+        .unwrap();
+    latest
+}
+
+#[tokio::test]
+async fn startup_refuses_a_schema_behind_unless_migrating() {
+    let Some(pool) = testing::schema_pool("db").await else {
+        return;
+    };
+    let state = testing::db_app_state(pool.clone());
+    let exchange = TestExchange::builder_with_state(state)
+        .admin()
+        .start()
+        .await;
+    let (client, base_url) = (exchange.client(), &exchange.base_url);
+    let admin = exchange.admin_token().token;
+    let latest = unapply_latest_migration(&pool).await;
+
+    let status = persistence::schema_status(&pool).await.unwrap();
+    assert!(!status.is_current());
+    assert_eq!(status.expected_version, Some(latest));
+    assert!(status.current_version < Some(latest));
+    let pending: Vec<i64> = status.pending.iter().map(|m| m.version).collect();
+    assert_eq!(pending, vec![latest]);
+
+    let body: serde_json::Value = client
+        .get(format!("{}/admin/schema", base_url))
+        .bearer_auth(&admin)
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(body["expected_version"], latest);
+    assert_eq!(body["pending"][0]["version"], latest);
+    assert_eq!(body["pending"][0]["installed_on"], serde_json::Value::Null);
+
+    let err = persistence::ensure_schema(&pool, false).await.unwrap_err();
+    assert!(matches!(err, persistence::SchemaError::Behind(ref p) if p.len() == 1));
+    let message = err.to_string();
+    assert!(message.contains(&latest.to_string()), "{}", message);
+    assert!(message.contains("MIGRATE_ON_START"), "{}", message);
+
+    let status = persistence::ensure_schema(&pool, true).await.unwrap();
+    assert!(status.is_current());
+    assert_eq!(status.current_version, Some(latest));
+    let indexes: i64 = sqlx::query_scalar(
+        "SELECT COUNT(*) FROM pg_indexes \
+         WHERE schemaname = current_schema() AND indexname LIKE 'idx_trades%_order_id'",
+    )
+    .fetch_one(&pool)
+    .await
+    .unwrap();
+    assert_eq!(indexes, 4);
+    // A second start finds nothing to do.
+    let again = persistence::ensure_schema(&pool, false).await.unwrap();
+    assert_eq!(again.applied.len(), status.applied.len());
+}
+
+#[tokio::test]
+async fn startup_accepts_a_schema_migrated_by_a_newer_binary() {
+    let Some(pool) = testing::schema_pool("db").await else {
+        return;
+    };
+    let future = 29991231000000_i64;
+    sqlx::query(
+        "INSERT INTO _sqlx_migrations \
+         (version, description, success, checksum, execution_time) \
+         VALUES ($1, 'from the future', TRUE, '\\x00', 0)",
+    )
+    .bind(future)
+    .execute(&pool)
+    .await
+    .unwrap();
+
+    let status = persistence::ensure_schema(&pool, false).await.unwrap();
+    assert!(status.is_current());
+    assert_eq!(status.current_version, Some(future));
+    assert_eq!(status.unknown.len(), 1);
+    assert_eq!(status.unknown[0].description, "from the future");
+    // Migrating leaves the newer migration alone.
+    persistence::run_pending(&pool).await.unwrap();
+}
+
+#[tokio::test]
+async fn position_timestamps_are_persisted_and_reset_on_flip() {
+    let Some(pool) = testing::schema_pool("db").await else {
+        return;
+    };
+    let exchange = TestExchange::builder_with_state(testing::db_app_state(pool.clone()))
+        .start()
+        .await;
+    let (client, base_url) = (exchange.client(), &exchange.base_url);
+    let Token {
+        user_id: alice_id,
+        token: alice,
+    } = exchange.register("alice", "secret").await;
+    let Token {
+        user_id: bob_id,
+        token: bob,
+    } = exchange.register("bob", "secret").await;
+    let bob_row = || async {
+        persistence::list_positions_for_user(&pool, bob_id, Some("BTCUSDT"), Environment::Live)
+            .await
+            .unwrap()
+            .remove(0)
+    };
+
+    place(client, base_url, &alice, "Sell", 100, 3).await;
+    place(client, base_url, &bob, "Buy", 100, 3).await;
+    let opened = bob_row().await;
+    assert_eq!(opened.opened_at, opened.updated_at);
+
+    place(client, base_url, &alice, "Sell", 100, 2).await;
+    place(client, base_url, &bob, "Buy", 100, 2).await;
+    let added = bob_row().await;
+    assert_eq!(added.quantity, 5);
+    assert_eq!(added.opened_at, opened.opened_at);
+    assert!(added.updated_at > opened.updated_at);
+
+    // Bob sells 7 into Alice's bid: long 5 becomes short 2, opened by this fill
+    place(client, base_url, &alice, "Buy", 100, 7).await;
+    place(client, base_url, &bob, "Sell", 100, 7).await;
+    let flipped = bob_row().await;
+    assert_eq!(flipped.quantity, -2);
+    assert!(flipped.opened_at > added.updated_at);
+    assert_eq!(flipped.opened_at, flipped.updated_at);
+
+    let listed: serde_json::Value = client
+        .get(format!("{}/positions?symbol=BTCUSDT", base_url))
+        .bearer_auth(&bob)
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let opened_at: chrono::DateTime<Utc> =
+        serde_json::from_value(listed[0]["opened_at"].clone()).unwrap();
+    assert_eq!(opened_at, flipped.opened_at);
+
+    let hydrated = hydration::hydrate(&pool, &["BTCUSDT"], true)
+        .await
+        .expect("clean hydration");
+    let restored = &hydrated.positions[&(bob_id, "BTCUSDT".to_string())];
+    assert_eq!(restored.opened_at, flipped.opened_at);
+    assert_eq!(restored.updated_at, flipped.updated_at);
+    assert!(
+        hydrated
+            .positions
+            .contains_key(&(alice_id, "BTCUSDT".to_string()))
+    );
+}
+
+#[tokio::test]
+async fn webhooks_and_their_deliveries_are_persisted_and_reloaded() {
+    let Some(pool) = testing::schema_pool("db").await else {
+        return;
+    };
+    let receiver = Router::new().route("/hook", axum::routing::post(|| async { StatusCode::OK }));
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let hook_url = format!("http://{}/hook", listener.local_addr().unwrap());
+    tokio::spawn(async move { axum::serve(listener, receiver).await.unwrap() });
+
+    let state = testing::db_app_state(pool.clone());
+    webhooks::spawn_dispatcher(state.clone());
+    let exchange = TestExchange::builder_with_state(state).start().await;
+    let (client, base_url) = (exchange.client(), &exchange.base_url);
+    let Token {
+        user_id: alice_id,
+        token: alice,
+    } = exchange.register("alice", "secret").await;
+    let bob = exchange.register("bob", "secret").await.token;
+    let webhook: serde_json::Value = client
+        .post(format!("{}/webhooks", base_url))
+        .bearer_auth(&alice)
+        .json(&serde_json::json!({
+            "url": hook_url,
+            "secret": "alice-webhook-secret",
+            "events": ["Fill", "OrderClosed"]
+        }))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let id = Uuid::parse_str(webhook["id"].as_str().unwrap()).unwrap();
+
+    place(client, base_url, &alice, "Sell", 100, 1).await;
+    place(client, base_url, &bob, "Buy", 100, 1).await;
+    let mut rows = Vec::new();
+    for _ in 0..200 {
+        rows = persistence::list_webhook_deliveries(&pool, id, 10)
+            .await
+            .unwrap();
+        if !rows.is_empty() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    assert_eq!(rows.len(), 1);
+    assert_eq!(
+        (
+            rows[0].event.as_str(),
+            rows[0].status.as_str(),
+            rows[0].attempts
+        ),
+        ("Fill", "Delivered", 1)
+    );
+    let listed: serde_json::Value = client
+        .get(format!("{}/webhooks/{}/deliveries", base_url, id))
+        .bearer_auth(&alice)
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(listed[0]["id"], rows[0].id.to_string());
+
+    // A restarted server delivers to the same webhook
+    let reloaded = Webhooks::default();
+    assert_eq!(reloaded.load(&pool).await.unwrap(), 1);
+    let restored = reloaded.list(alice_id);
+    assert_eq!(restored[0].id, id);
+    assert_eq!(
+        restored[0].events,
+        [WebhookEventType::Fill, WebhookEventType::OrderClosed].into()
+    );
+}
+
+#[tokio::test]
+async fn replica_follows_the_primary_through_the_database() {
+    let Some(pool) = testing::schema_pool("db").await else {
+        return;
+    };
+    let primary = TestExchange::builder_with_state(testing::db_app_state(pool.clone()))
+        .start()
+        .await;
+    let (client, base_url) = (primary.client(), &primary.base_url);
+    let maker = primary.register("maker", "secret").await.token;
+    let taker = primary.register("taker", "secret").await.token;
+    place(client, base_url, &maker, "Sell", 100, 3).await;
+    place(client, base_url, &maker, "Sell", 101, 1).await;
+    place(client, base_url, &taker, "Buy", 100, 1).await;
+
+    let mut replica = testing::db_app_state(pool.clone());
+    replica.role = ServerRole::MarketDataReplica;
+    let replica_exchange = TestExchange::builder_with_state(replica.clone())
+        .start()
+        .await;
+    let replica_url = &replica_exchange.base_url;
+    rust_exchange::replica::refresh(&replica, &pool)
+        .await
+        .unwrap();
+    let book = |url: String| {
+        let client = client.clone();
+        async move {
+            let body: serde_json::Value = client
+                .get(format!("{}/book?symbol=BTCUSDT", url))
+                .send()
+                .await
+                .unwrap()
+                .json()
+                .await
+                .unwrap();
+            body["asks"].clone()
+        }
+    };
+    assert_eq!(
+        book(replica_url.clone()).await,
+        serde_json::json!([[100, 2], [101, 1]])
+    );
+
+    let res = client
+        .post(format!("{}/orders", replica_url))
+        .bearer_auth(&taker)
+        .json(&serde_json::json!({
+            "symbol": "BTCUSDT", "price": 100, "quantity": 1, "side": "Buy"
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::FORBIDDEN);
+
+    place(client, base_url, &taker, "Buy", 101, 3).await;
+    rust_exchange::replica::refresh(&replica, &pool)
+        .await
+        .unwrap();
+    assert_eq!(
+        book(replica_url.clone()).await,
+        book(base_url.clone()).await
+    );
+    let book = replica.orderbooks["BTCUSDT"].read().await;
+    assert_eq!(book.last_trade_seq(), 3);
+    let seqs: Vec<u64> = book
+        .get_recent_trades(10)
+        .iter()
+        .map(|t| t.trade_seq)
+        .collect();
+    assert_eq!(seqs, vec![3, 2, 1]);
+}
+
+#[tokio::test]
+async fn orders_whose_deadline_passed_while_down_are_expired_at_startup() {
+    let Some((pool, database_url)) = testing::test_database().await else {
+        return;
+    };
+    let exchange = TestExchange::builder_with_state(testing::db_app_state(pool.clone()))
+        .start()
+        .await;
+    let (client, base_url) = (exchange.client(), &exchange.base_url);
+    let Token { user_id, token } = exchange.register("alice", "secret").await;
+    // A TTL order placed through the API stores its deadline
+    let res = client
+        .post(format!("{}/orders", base_url))
+        .bearer_auth(&token)
+        .json(&serde_json::json!({
+            "symbol": "BTCUSDT", "price": 110, "quantity": 1, "side": "Sell",
+            "refresh_ttl_ms": 60_000
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), 200);
+    let placed: serde_json::Value = res.json().await.unwrap();
+    let placed_id = Uuid::parse_str(placed["id"].as_str().unwrap()).unwrap();
+    let stored = persistence::list_open_order_deadlines(&pool).await.unwrap();
+    assert_eq!(stored.len(), 1);
+    assert_eq!(
+        (stored[0].id, stored[0].refresh_ttl_ms),
+        (placed_id, 60_000)
+    );
+    drop(exchange);
+
+    // Rows as a server that went down would have left them; Postgres keeps microseconds
+    let now = Utc::now().trunc_subsecs(6);
+    let insert = |symbol: &'static str, price: i64, expires_at: Option<chrono::DateTime<Utc>>| {
+        let pool = pool.clone();
+        async move {
+            let id = Uuid::new_v4();
+            persistence::insert_order(
+                &pool,
+                id,
+                user_id,
+                symbol,
+                OrderSide::Sell,
+                OrderType::Limit,
+                price,
+                1,
+                OrderStatus::Pending,
+                now - chrono::Duration::minutes(5),
+                &Default::default(),
+                OrderSource::Api,
+                None,
+            )
+            .await
+            .unwrap();
+            if let Some(expires_at) = expires_at {
+                persistence::set_order_expiry(&pool, id, expires_at, 60_000)
+                    .await
+                    .unwrap();
+            }
+            id
+        }
+    };
+    let lapsed = insert("BTCUSDT", 101, Some(now - chrono::Duration::minutes(1))).await;
+    let alive = insert("BTCUSDT", 102, Some(now + chrono::Duration::minutes(10))).await;
+    let plain = insert("BTCUSDT", 103, None).await;
+    // On a symbol the server does not load, so it never reaches a book
+    let orphan = insert("SOLUSDT", 104, Some(now - chrono::Duration::minutes(1))).await;
+    persistence::set_order_expiry(&pool, placed_id, now - chrono::Duration::seconds(1), 60_000)
+        .await
+        .unwrap();
+
+    let config = Config {
+        persistence: PersistenceMode::Postgres,
+        database_url: Some(database_url),
+        ..Config::memory()
+    };
+    let state = bootstrap::build_state(&config).await.unwrap();
+    assert_eq!(state.hydration_report.orders_expired, 3);
+    assert!(
+        state
+            .hydration_report
+            .to_string()
+            .contains("3 orders expired")
+    );
+    {
+        let book = state.orderbooks["BTCUSDT"].read().await;
+        for id in [lapsed, placed_id] {
+            assert!(book.get_order_by_id(id).is_none());
+        }
+        assert!(book.get_order_by_id(alive).is_some());
+        assert!(book.get_order_by_id(plain).is_some());
+    }
+    for id in [lapsed, placed_id, orphan] {
+        let row = persistence::get_order_by_id(&pool, id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(row.status, "Cancelled");
+        assert_eq!(row.close_reason.as_deref(), Some("TTL_EXPIRED"));
+    }
+    assert_eq!(
+        persistence::get_order_by_id(&pool, alive)
+            .await
+            .unwrap()
+            .unwrap()
+            .status,
+        "Pending"
+    );
+
+    // The surviving deadline is tracked again; the order without one is not
+    let alive_deadline = now + chrono::Duration::minutes(10);
+    assert_eq!(
+        state.order_expiry.deadline_of(alive),
+        Some((alive_deadline, Duration::from_secs(60)))
+    );
+    assert_eq!(state.order_expiry.deadline_of(plain), None);
+
+    let exchange = TestExchange::builder_with_state(state.clone())
+        .start()
+        .await;
+    let (client, base_url) = (exchange.client(), &exchange.base_url);
+    let json: serde_json::Value = client
+        .post(format!("{}/auth/login", base_url))
+        .json(&serde_json::json!({ "username": "alice", "password": "secret" }))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let token = json["token"].as_str().unwrap().to_string();
+    let orders = client
+        .get(format!("{}/orders/me?symbol=BTCUSDT", base_url))
+        .bearer_auth(&token)
+        .send()
+        .await
+        .unwrap()
+        .json::<Page<serde_json::Value>>()
+        .await
+        .unwrap()
+        .items;
+    let open: HashSet<Uuid> = orders
+        .iter()
+        .filter(|o| o["status"] != "Cancelled")
+        .map(|o| Uuid::parse_str(o["id"].as_str().unwrap()).unwrap())
+        .collect();
+    assert_eq!(open, HashSet::from([alive, plain]));
+    let book: serde_json::Value = client
+        .get(format!("{}/book?symbol=BTCUSDT", base_url))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(book["asks"], serde_json::json!([[102, 1], [103, 1]]));
+
+    // The sweeper's heap holds the restored deadline
+    let expired = expiry::expire_due(&state, alive_deadline).await;
+    assert_eq!(expired.len(), 1);
+    assert_eq!(expired[0].order.id, alive);
+}
+
+#[tokio::test]
+async fn lazy_symbols_are_hydrated_once_by_concurrent_first_orders() {
+    let Some((pool, database_url)) = testing::test_database().await else {
+        return;
+    };
+    // Asks left resting on ETHUSDT and SOLUSDT by an earlier run
+    let now = Utc::now().trunc_subsecs(6);
+    let maker = Uuid::new_v4();
+    let mut asks = Vec::new();
+    for (symbol, price) in [
+        ("ETHUSDT", 100),
+        ("ETHUSDT", 101),
+        ("ETHUSDT", 120),
+        ("SOLUSDT", 50),
+    ] {
+        let id = Uuid::new_v4();
+        persistence::insert_order(
+            &pool,
+            id,
+            maker,
+            symbol,
+            OrderSide::Sell,
+            OrderType::Limit,
+            price,
+            1,
+            OrderStatus::Pending,
+            now - chrono::Duration::minutes(5),
+            &Default::default(),
+            OrderSource::Api,
+            None,
+        )
+        .await
+        .unwrap();
+        asks.push(id);
+    }
+    let deadline = now + chrono::Duration::minutes(10);
+    persistence::set_order_expiry(&pool, asks[2], deadline, 60_000)
+        .await
+        .unwrap();
+
+    let config = Config {
+        persistence: PersistenceMode::Postgres,
+        database_url: Some(database_url),
+        symbols: vec!["BTCUSDT".to_string()],
+        lazy_symbols: vec!["ETHUSDT".to_string()],
+        serve_stored_symbols: false,
+        ..Config::memory()
+    };
+    let state = bootstrap::build_state(&config).await.unwrap();
+    let report = &state.hydration_report;
+    assert_eq!(report.symbols_lazy, ["ETHUSDT"]);
+    assert_eq!(report.symbols_not_served, ["SOLUSDT"]);
+    assert!(!report.per_symbol_restored.contains_key("ETHUSDT"));
+    assert!(state.lazy_books.is_pending("ETHUSDT"));
+    assert_eq!(state.orderbooks["ETHUSDT"].read().await.best_ask(), None);
+    assert_eq!(state.order_expiry.deadline_of(asks[2]), None);
+
+    let exchange = TestExchange::builder_with_state(state.clone())
+        .start()
+        .await;
+    let (client, base_url) = (exchange.client(), &exchange.base_url);
+    let mut takers = Vec::new();
+    for i in 0..8 {
+        takers.push(
+            exchange
+                .register(&format!("taker{}", i), "secret")
+                .await
+                .token,
+        );
+    }
+    // Every order is a first request for the book; one load serves them all
+    let placed = futures_util::future::join_all(takers.iter().map(|token| {
+        client
+            .post(format!("{}/orders", base_url))
+            .bearer_auth(token)
+            .json(&serde_json::json!({
+                "symbol": "ETHUSDT", "price": 101, "quantity": 1, "side": "Buy"
+            }))
+            .send()
+    }))
+    .await;
+    let mut filled = 0;
+    for res in placed {
+        let res = res.unwrap();
+        assert_eq!(res.status(), 200);
+        let order: serde_json::Value = res.json().await.unwrap();
+        filled += usize::from(order["status"] == "Filled");
+    }
+    assert_eq!(filled, 2);
+    assert_eq!(state.lazy_books.loads(), 1);
+    assert!(!state.lazy_books.is_pending("ETHUSDT"));
+    {
+        let book = state.orderbooks["ETHUSDT"].read().await;
+        assert_eq!(book.get_asks(), vec![(120, 1)]);
+        assert_eq!(book.get_bids(), vec![(101, 6)]);
+    }
+    assert_eq!(
+        state.order_expiry.deadline_of(asks[2]),
+        Some((deadline, Duration::from_secs(60)))
+    );
+
+    // A symbol this instance does not serve is unknown, and its orders are left alone
+    let res = client
+        .post(format!("{}/orders", base_url))
+        .bearer_auth(&takers[0])
+        .json(&serde_json::json!({
+            "symbol": "SOLUSDT", "price": 50, "quantity": 1, "side": "Buy"
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), 404);
+    let stored = persistence::get_order_by_id(&pool, asks[3])
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(stored.status, "Pending");
+}
+
+#[tokio::test]
+async fn the_kill_switch_cancels_orders_stored_on_a_lazy_book_not_yet_loaded() {
+    let Some((pool, database_url)) = testing::test_database().await else {
+        return;
+    };
+    let config = Config {
+        persistence: PersistenceMode::Postgres,
+        database_url: Some(database_url),
+        symbols: vec!["BTCUSDT".to_string()],
+        lazy_symbols: vec!["ETHUSDT".to_string()],
+        ..Config::memory()
+    };
+    let state = bootstrap::build_state(&config).await.unwrap();
+    let exchange = TestExchange::builder_with_state(state.clone())
+        .start()
+        .await;
+    let (client, base_url) = (exchange.client(), &exchange.base_url);
+    let Token {
+        user_id: alice_id,
+        token: alice,
+    } = exchange.register("alice", "secret").await;
+    // Left resting by an earlier run
+    let stored = Uuid::new_v4();
+    persistence::insert_order(
+        &pool,
+        stored,
+        alice_id,
+        "ETHUSDT",
+        OrderSide::Sell,
+        OrderType::Limit,
+        100,
+        1,
+        OrderStatus::Pending,
+        Utc::now() - chrono::Duration::minutes(5),
+        &Default::default(),
+        OrderSource::Api,
+        None,
+    )
+    .await
+    .unwrap();
+    assert!(state.lazy_books.is_pending("ETHUSDT"));
+
+    let engaged: serde_json::Value = client
+        .post(format!("{}/account/kill-switch", base_url))
+        .bearer_auth(&alice)
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(engaged["cancelled"][0]["symbol"], "ETHUSDT");
+    assert_eq!(engaged["cancelled"][0]["id"], stored.to_string());
+    let row = persistence::get_order_by_id(&pool, stored)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(row.close_reason.as_deref(), Some("KILL_SWITCH"));
+    assert_eq!(state.orderbooks["ETHUSDT"].read().await.best_ask(), None);
+
+    // A later start does not bring the order back
+    let restarted = bootstrap::build_state(&config).await.unwrap();
+    assert!(lazy_books::ensure_loaded(&restarted, "ETHUSDT").await);
+    assert_eq!(
+        restarted.orderbooks["ETHUSDT"].read().await.best_ask(),
+        None
+    );
+}
+
+#[tokio::test]
+async fn stored_symbols_are_served_unless_symbols_are_declared() {
+    let Some((pool, database_url)) = testing::test_database().await else {
+        return;
+    };
+    persistence::upsert_symbol_config(&pool, "ADAUSDT", &SymbolConfig::for_symbol("ADAUSDT"))
+        .await
+        .unwrap();
+    let config = Config {
+        persistence: PersistenceMode::Postgres,
+        database_url: Some(database_url),
+        ..Config::memory()
+    };
+
+    let state = bootstrap::build_state(&config).await.unwrap();
+    for symbol in ["ADAUSDT", "BTCUSDT", "ETHUSDT"] {
+        assert!(state.orderbooks.contains_key(symbol), "{}", symbol);
+    }
+    let declared = Config {
+        symbols: vec!["BTCUSDT".to_string()],
+        serve_stored_symbols: false,
+        ..config
+    };
+    let state = bootstrap::build_state(&declared).await.unwrap();
+    let mut served: Vec<&String> = state.orderbooks.keys().collect();
+    served.sort();
+    assert_eq!(served, ["BTCUSDT"]);
+}
+
+#[tokio::test]
+async fn trades_me_merges_both_sides_without_repeating_self_trades() {
+    let Some(pool) = testing::schema_pool("db").await else {
+        return;
+    };
+    let (alice, bob) = (Uuid::new_v4(), Uuid::new_v4());
+    let start = Utc::now().trunc_subsecs(6) - chrono::Duration::minutes(10);
+    let mut seeded = Vec::new();
+    for (seq, (maker, taker)) in [
+        (alice, bob),
+        (bob, alice),
+        (alice, alice),
+        (Uuid::new_v4(), Uuid::new_v4()),
+        (bob, alice),
+    ]
+    .into_iter()
+    .enumerate()
+    {
+        let id = Uuid::new_v4();
+        let created_at = start + chrono::Duration::seconds(seq as i64);
+        persistence::insert_trade(
+            &pool,
+            id,
+            seq as u64 + 1,
+            Uuid::new_v4(),
+            Uuid::new_v4(),
+            maker,
+            taker,
+            "BTCUSDT",
+            100,
+            1,
+            created_at,
+        )
+        .await
+        .unwrap();
+        seeded.push((id, created_at, seq as u64 + 1));
+    }
+    let ids = |trades: &[rust_exchange::types::trade::UserTrade]| -> Vec<Uuid> {
+        trades.iter().map(|t| t.trade.id).collect()
+    };
+
+    // Newest first, the self-trade once, the unrelated trade left out
+    let all =
+        persistence::list_trades_for_user(&pool, alice, None, Environment::Live, None, 10, true)
+            .await
+            .unwrap();
+    let expected = [seeded[4].0, seeded[2].0, seeded[1].0, seeded[0].0];
+    assert_eq!(ids(&all), expected);
+
+    // Pages continue after the keyset, across both branches
+    let first = persistence::list_trades_for_user(
+        &pool,
+        alice,
+        Some("BTCUSDT"),
+        Environment::Live,
+        None,
+        2,
+        false,
+    )
+    .await
+    .unwrap();
+    assert_eq!(ids(&first), expected[..2]);
+    let (_, created_at, seq) = seeded[2];
+    let rest = persistence::list_trades_for_user(
+        &pool,
+        alice,
+        Some("BTCUSDT"),
+        Environment::Live,
+        Some((created_at, seq, seeded[2].0)),
+        2,
+        false,
+    )
+    .await
+    .unwrap();
+    assert_eq!(ids(&rest), expected[2..]);
+    let other_symbol = persistence::list_trades_for_user(
+        &pool,
+        alice,
+        Some("ETHUSDT"),
+        Environment::Live,
+        None,
+        10,
+        false,
+    )
+    .await
+    .unwrap();
+    assert!(other_symbol.is_empty());
+}
+
+#[tokio::test]
+async fn paper_rows_carry_their_environment_and_are_listed_apart() {
+    let Some(pool) = testing::schema_pool("db").await else {
+        return;
+    };
+    let (maker, taker) = (Uuid::new_v4(), Uuid::new_v4());
+    let mut seeded = Vec::new();
+    for (seq, symbol) in ["BTCUSDT", "BTCUSDT@paper"].into_iter().enumerate() {
+        let id = Uuid::new_v4();
+        persistence::insert_trade(
+            &pool,
+            id,
+            seq as u64 + 1,
+            Uuid::new_v4(),
+            Uuid::new_v4(),
+            maker,
+            taker,
+            symbol,
+            100,
+            1,
+            Utc::now(),
+        )
+        .await
+        .unwrap();
+        seeded.push(id);
+    }
+    let environment: String = sqlx::query_scalar("SELECT environment FROM trades WHERE id = $1")
+        .bind(seeded[1])
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert_eq!(environment, "paper");
+
+    for (environment, id) in [
+        (Environment::Live, seeded[0]),
+        (Environment::Paper, seeded[1]),
+    ] {
+        let listed =
+            persistence::list_trades_for_user(&pool, taker, None, environment, None, 10, false)
+                .await
+                .unwrap();
+        let ids: Vec<Uuid> = listed.iter().map(|t| t.trade.id).collect();
+        assert_eq!(ids, [id], "{}", environment);
+    }
+}
+
+/// `users` users trading round-robin, `trades` trades, and `orders` orders of which one in
+/// a hundred is still open; analyzed, so the planner sees the real sizes.
+async fn seed_volume(pool: &PgPool, users: i64, trades: i64, orders: i64) {
+    sqlx::query(
+        "INSERT INTO trades (id, trade_seq, maker_order_id, taker_order_id, maker_user_id, taker_user_id, symbol, price, quantity, created_at) \
+         SELECT gen_random_uuid(), n, gen_random_uuid(), gen_random_uuid(), \
+         ('00000000-0000-0000-0000-' || lpad((n % $1)::TEXT, 12, '0'))::UUID, \
+         ('00000000-0000-0000-0000-' || lpad(((n + 1) % $1)::TEXT, 12, '0'))::UUID, \
+         'BTCUSDT', 100, 1, NOW() - n * INTERVAL '1 second' \
+         FROM generate_series(1, $2) AS n",
+    )
+    .bind(users)
+    .bind(trades)
+    .execute(pool)
+    .await
+    .unwrap();
+    sqlx::query(
+        "INSERT INTO orders (id, user_id, symbol, side, order_type, price, quantity, status, created_at) \
+         SELECT gen_random_uuid(), gen_random_uuid(), 'BTCUSDT', 'Buy', 'Limit', 100, 1, \
+         CASE WHEN n % 100 = 0 THEN 'Pending' ELSE 'Filled' END, NOW() - n * INTERVAL '1 second' \
+         FROM generate_series(1, $1) AS n",
+    )
+    .bind(orders)
+    .execute(pool)
+    .await
+    .unwrap();
+    sqlx::query("ANALYZE trades, orders")
+        .execute(pool)
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn hot_reads_are_planned_on_their_indexes() {
+    let Some(pool) = testing::schema_pool("db").await else {
+        return;
+    };
+    seed_volume(&pool, 500, 50_000, 50_000).await;
+    let user_id: Uuid = "00000000-0000-0000-0000-000000000007".parse().unwrap();
+
+    let plan = persistence::explain(
+        &pool,
+        &PlannedQuery::TradesForUser {
+            user_id,
+            limit: 50,
+            include_archived: false,
+        },
+    )
+    .await
+    .unwrap();
+    assert!(
+        plan.contains("idx_trades_maker_user_created_at"),
+        "{}",
+        plan
+    );
+    assert!(
+        plan.contains("idx_trades_taker_user_created_at"),
+        "{}",
+        plan
+    );
+    assert!(!plan.contains("Seq Scan"), "{}", plan);
+
+    let plan = persistence::explain(
+        &pool,
+        &PlannedQuery::OpenOrdersBySymbol {
+            symbol: "BTCUSDT".to_string(),
+        },
+    )
+    .await
+    .unwrap();
+    assert!(
+        plan.contains("idx_orders_open_symbol_created_at"),
+        "{}",
+        plan
+    );
+    assert!(!plan.contains("Seq Scan"), "{}", plan);
+
+    // And the rewritten query still answers: the user is maker of every 500th trade and taker
+    // of the one before it
+    let page =
+        persistence::list_trades_for_user(&pool, user_id, None, Environment::Live, None, 50, true)
+            .await
+            .unwrap();
+    assert_eq!(page.len(), 50);
+    assert!(
+        page.windows(2)
+            .all(|pair| pair[0].trade.timestamp >= pair[1].trade.timestamp)
+    );
+    let open = persistence::list_open_orders_by_symbol(&pool, "BTCUSDT")
+        .await
+        .unwrap();
+    assert_eq!(open.len(), 500);
+}
@@ -0,0 +1,92 @@
+//! Decimal strings to scaled integers and back: exact conversion, or a rejection.
+
+use proptest::prelude::*;
+use rust_exchange::types::decimal::{
+    DecimalError, QuantityInput, format_scaled, is_valid_scale, parse_scaled,
+};
+
+const SATS: u64 = 100_000_000;
+
+#[test]
+fn decimals_convert_exactly_at_the_scale() {
+    assert_eq!(parse_scaled("0.001", SATS), Ok(100_000));
+    assert_eq!(parse_scaled("1", SATS), Ok(100_000_000));
+    assert_eq!(parse_scaled(" 12.5 ", SATS), Ok(1_250_000_000));
+    assert_eq!(parse_scaled(".5", 10), Ok(5));
+    assert_eq!(parse_scaled("3.", 10), Ok(30));
+    assert_eq!(parse_scaled("-0.25", 100), Ok(-25));
+    assert_eq!(parse_scaled("+7", 1), Ok(7));
+    // Zeros past the last place lose nothing
+    assert_eq!(parse_scaled("0.00100000000000", SATS), Ok(100_000));
+    assert_eq!(parse_scaled("0.00000001", SATS), Ok(1));
+}
+
+#[test]
+fn decimals_that_would_lose_precision_or_are_not_numbers_are_rejected() {
+    assert_eq!(
+        parse_scaled("0.0000000001", SATS),
+        Err(DecimalError::TooPrecise {
+            raw: "0.0000000001".to_string(),
+            scale: SATS
+        })
+    );
+    assert!(matches!(
+        parse_scaled("1.5", 1),
+        Err(DecimalError::TooPrecise { .. })
+    ));
+    assert_eq!(parse_scaled("  ", SATS), Err(DecimalError::Empty));
+    for raw in [".", "-", "1e8", "1.2.3", "0x10", "1,5", "--1", "½"] {
+        assert!(
+            matches!(parse_scaled(raw, SATS), Err(DecimalError::Invalid(_))),
+            "{raw}"
+        );
+    }
+    let huge = "1".repeat(40);
+    assert!(matches!(
+        parse_scaled(&huge, SATS),
+        Err(DecimalError::OutOfRange(_))
+    ));
+}
+
+#[test]
+fn formatting_drops_trailing_zeros() {
+    assert_eq!(format_scaled(100_000, SATS), "0.001");
+    assert_eq!(format_scaled(150_000_000, SATS), "1.5");
+    assert_eq!(format_scaled(200_000_000, SATS), "2");
+    assert_eq!(format_scaled(-1, SATS), "-0.00000001");
+    assert_eq!(format_scaled(0, SATS), "0");
+    assert_eq!(format_scaled(42, 1), "42");
+}
+
+#[test]
+fn scales_are_powers_of_ten_up_to_eighteen_places() {
+    for scale in [1, 10, 1_000, SATS, 1_000_000_000_000_000_000] {
+        assert!(is_valid_scale(scale), "{scale}");
+    }
+    for scale in [0, 2, 20, 1_500, 10_000_000_000_000_000_000] {
+        assert!(!is_valid_scale(scale), "{scale}");
+    }
+}
+
+#[test]
+fn quantities_are_lots_as_numbers_and_decimals_as_strings() {
+    let lots: QuantityInput = serde_json::from_str("5").unwrap();
+    assert_eq!(lots.to_lots(SATS), Ok(5));
+    let decimal: QuantityInput = serde_json::from_str("\"0.05\"").unwrap();
+    assert_eq!(decimal.to_lots(SATS), Ok(5_000_000));
+    let negative: QuantityInput = serde_json::from_str("\"-1\"").unwrap();
+    assert!(matches!(
+        negative.to_lots(SATS),
+        Err(DecimalError::OutOfRange(_))
+    ));
+    assert!(serde_json::from_str::<QuantityInput>("-1").is_err());
+}
+
+proptest! {
+    #[test]
+    fn formatted_values_parse_back(value in any::<i64>(), places in 0u32..=18) {
+        let scale = 10u64.pow(places);
+        let formatted = format_scaled(value.into(), scale);
+        prop_assert_eq!(parse_scaled(&formatted, scale), Ok(value as i128));
+    }
+}
@@ -0,0 +1,339 @@
+//! Symbol delisting end to end: `CancelOnly` during the grace period, then at the deadline
+//! (stepped with a `ManualClock`) resting orders cancelled with owner notifications,
+//! positions closed or frozen, and the symbol gone from every lookup. Users with orders or
+//! positions in the symbol are told through their webhooks. The database test needs
+//! `TEST_DATABASE_URL` (see `e2e_db.rs`) and is skipped when it is unset.
+
+use axum::Router;
+use axum::body::Bytes;
+use axum::routing::post;
+use chrono::{DateTime, Utc};
+use reqwest::StatusCode;
+use rust_exchange::api::delisting;
+use rust_exchange::hydration;
+use rust_exchange::orderbook::clock::{Clock, ManualClock};
+use rust_exchange::persistence::{self, PgPool};
+use rust_exchange::testing::{self, OrderRequest, TEST_SYMBOL, TestExchange, Token};
+use rust_exchange::types::order::{OrderSide, RejectReason};
+use rust_exchange::types::symbol::SymbolStatus;
+use rust_exchange::webhooks::{SymbolStatusEvent, WebhookEvent, WebhookPayload};
+use serde_json::{Value, json};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use uuid::Uuid;
+
+/// Still listed after `TEST_SYMBOL` is delisted.
+const OTHER_SYMBOL: &str = "ETHUSDT";
+
+fn start_time() -> DateTime<Utc> {
+    "2026-01-01T00:00:00Z".parse().unwrap()
+}
+
+/// A harness exchange on `clock` with a second symbol, persisting to `pool` when given, and
+/// an admin token.
+async fn start(clock: &Arc<ManualClock>, pool: Option<&PgPool>) -> (TestExchange, Token) {
+    let mut state = testing::test_app_state();
+    state.db = pool.cloned();
+    let exchange = TestExchange::builder_with_state(state)
+        .admin()
+        .symbol(OTHER_SYMBOL)
+        .clock(clock.clone())
+        .start()
+        .await;
+    let admin = exchange.admin_token();
+    (exchange, admin)
+}
+
+async fn delist(exchange: &TestExchange, admin: &Token, body: Value) -> Result<Value, StatusCode> {
+    exchange
+        .send_json(
+            exchange
+                .client()
+                .post(exchange.url(&format!("/admin/symbols/{}/delist", TEST_SYMBOL)))
+                .bearer_auth(&admin.token)
+                .json(&body),
+        )
+        .await
+        .map_err(|e| e.status)
+}
+
+/// Cross `quantity` at `price`: `seller` rests, `buyer` takes it.
+async fn trade(exchange: &TestExchange, seller: &Token, buyer: &Token, price: i64, quantity: u64) {
+    for (token, side) in [(seller, OrderSide::Sell), (buyer, OrderSide::Buy)] {
+        exchange
+            .place_order(
+                token,
+                &OrderRequest::limit(TEST_SYMBOL, side, price, quantity),
+            )
+            .await
+            .unwrap();
+    }
+}
+
+/// Traders with a long and a short of 2 opened at 100, a last trade at 110 between two
+/// others, and one resting ask. Returns (long, short, the ask's id).
+async fn open_positions(exchange: &TestExchange) -> (Token, Token, Uuid) {
+    let long = exchange.register("long", "secret").await;
+    let short = exchange.register("short", "secret").await;
+    let seller = exchange.register("seller", "secret").await;
+    let buyer = exchange.register("buyer", "secret").await;
+    trade(exchange, &short, &long, 100, 2).await;
+    trade(exchange, &seller, &buyer, 110, 1).await;
+    let ask = exchange
+        .place_order(
+            &short,
+            &OrderRequest::limit(TEST_SYMBOL, OrderSide::Sell, 120, 5),
+        )
+        .await
+        .unwrap();
+    (long, short, ask.order.id)
+}
+
+/// Position quantity per symbol for `token`.
+async fn positions(exchange: &TestExchange, token: &Token) -> Vec<(String, i64)> {
+    exchange
+        .positions(token)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|p| (p.symbol, p.quantity))
+        .collect()
+}
+
+#[tokio::test]
+async fn a_delisting_cancels_orders_and_closes_positions_at_the_deadline() {
+    let clock = Arc::new(ManualClock::new(start_time()));
+    let (exchange, admin) = start(&clock, None).await;
+    delisting::spawn_finalizer(exchange.state.clone());
+    let (long, short, ask_id) = open_positions(&exchange).await;
+    let mut ws = exchange.ws_client(Some(&short)).await;
+    ws.subscribe(TEST_SYMBOL).await;
+
+    let started = delist(&exchange, &admin, json!({"grace_secs": 60}))
+        .await
+        .unwrap();
+    assert_eq!(started["status"], "CancelOnly");
+    assert_eq!(started["delisting"]["at"], "2026-01-01T00:01:00Z");
+    assert_eq!(started["delisting"]["positions"], "Close");
+    let status = ws.next_of_type("SymbolStatus").await;
+    assert_eq!(status["status"], "CancelOnly");
+    assert_eq!(status["delist_at"], "2026-01-01T00:01:00Z");
+    assert_eq!(
+        delist(&exchange, &admin, json!({})).await.unwrap_err(),
+        StatusCode::CONFLICT
+    );
+
+    // New orders are refused, cancels still go through
+    let err = exchange
+        .place_order(
+            &long,
+            &OrderRequest::limit(TEST_SYMBOL, OrderSide::Buy, 90, 1),
+        )
+        .await
+        .unwrap_err();
+    assert_eq!(err.rejection(), Some(RejectReason::SymbolCancelOnly));
+    exchange
+        .place_order(
+            &long,
+            &OrderRequest::limit(OTHER_SYMBOL, OrderSide::Buy, 90, 1),
+        )
+        .await
+        .unwrap();
+
+    clock.advance(Duration::from_secs(59));
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert_eq!(exchange.book(TEST_SYMBOL).await.asks, vec![(120, 5)]);
+
+    clock.advance(Duration::from_secs(1));
+    let closed = ws.next_of_type("OrderClosed").await;
+    assert_eq!(closed["order_id"], ask_id.to_string());
+    assert_eq!(closed["reason"], "SYMBOL_DELISTED");
+    let status = ws.next_of_type("SymbolStatus").await;
+    assert_eq!(status["status"], "Delisted");
+
+    assert!(positions(&exchange, &long).await.is_empty());
+    assert!(positions(&exchange, &short).await.is_empty());
+    assert!(exchange.state.delistings.is_delisted(TEST_SYMBOL));
+    assert_eq!(
+        exchange.state.symbol_configs.read().await[TEST_SYMBOL].status,
+        SymbolStatus::Delisted
+    );
+    // Unknown everywhere, with only the remaining symbol as a hint
+    let err = exchange
+        .get(&long, &format!("/book?symbol={}", TEST_SYMBOL))
+        .await
+        .unwrap_err();
+    assert_eq!(err.status, StatusCode::NOT_FOUND);
+    assert_eq!(err.body["known_symbols"], json!([OTHER_SYMBOL]));
+    let listed = exchange.get(&long, "/symbols").await.unwrap();
+    let listed: Vec<&str> = listed
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|s| s["symbol"].as_str().unwrap())
+        .collect();
+    assert_eq!(listed, vec![OTHER_SYMBOL]);
+}
+
+/// Serve a webhook receiver on a random port; returns the bodies it gets and its URL.
+async fn spawn_receiver() -> (mpsc::UnboundedReceiver<Bytes>, String) {
+    let (tx, rx) = mpsc::unbounded_channel();
+    let app = Router::new().route(
+        "/hook",
+        post(move |body: Bytes| async move {
+            let _ = tx.send(body);
+        }),
+    );
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}/hook", listener.local_addr().unwrap());
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+    (rx, url)
+}
+
+#[tokio::test]
+async fn users_with_resting_orders_get_a_symbol_status_webhook() {
+    let clock = Arc::new(ManualClock::new(start_time()));
+    let (exchange, admin) = start(&clock, None).await;
+    let (_, short, _) = open_positions(&exchange).await;
+    let (mut received, url) = spawn_receiver().await;
+    exchange
+        .send_json(
+            exchange
+                .client()
+                .post(exchange.url("/webhooks"))
+                .bearer_auth(&short.token)
+                .json(&json!({
+                    "url": url,
+                    "secret": "receiver-shared-secret",
+                    "events": ["SymbolStatus"],
+                })),
+        )
+        .await
+        .unwrap();
+
+    delist(&exchange, &admin, json!({"grace_secs": 60}))
+        .await
+        .unwrap();
+    let body = tokio::time::timeout(Duration::from_secs(5), received.recv())
+        .await
+        .expect("no webhook delivery")
+        .unwrap();
+    let payload: WebhookPayload = serde_json::from_slice(&body).unwrap();
+    assert_eq!(payload.user_id, short.user_id);
+    assert_eq!(
+        payload.event,
+        WebhookEvent::SymbolStatus(SymbolStatusEvent {
+            symbol: TEST_SYMBOL.to_string(),
+            status: SymbolStatus::CancelOnly,
+            delist_at: Some(start_time() + chrono::Duration::seconds(60)),
+        })
+    );
+}
+
+#[tokio::test]
+async fn frozen_positions_outlive_the_delisting() {
+    let clock = Arc::new(ManualClock::new(start_time()));
+    let (exchange, admin) = start(&clock, None).await;
+    let (long, short, _) = open_positions(&exchange).await;
+
+    delist(
+        &exchange,
+        &admin,
+        json!({"grace_secs": 0, "positions": "Freeze"}),
+    )
+    .await
+    .unwrap();
+    let delisted = delisting::finalize_due(&exchange.state, clock.now()).await;
+    assert_eq!(delisted.len(), 1);
+    assert_eq!(delisted[0].orders_cancelled, 1);
+    assert!(delisted[0].positions_closed.is_empty());
+    assert_eq!(delisted[0].positions_frozen, 4);
+
+    assert_eq!(
+        positions(&exchange, &long).await,
+        vec![(TEST_SYMBOL.to_string(), 2)]
+    );
+    assert_eq!(
+        positions(&exchange, &short).await,
+        vec![(TEST_SYMBOL.to_string(), -2)]
+    );
+    // Completed once only
+    assert!(
+        delisting::finalize_due(&exchange.state, clock.now())
+            .await
+            .is_empty()
+    );
+}
+
+#[tokio::test]
+async fn a_completed_delisting_is_stored_and_not_rehydrated() {
+    let Some(pool) = testing::schema_pool("delisting").await else {
+        return;
+    };
+    let clock = Arc::new(ManualClock::new(start_time()));
+    let (exchange, admin) = start(&clock, Some(&pool)).await;
+    let (long, short, ask_id) = open_positions(&exchange).await;
+
+    delist(&exchange, &admin, json!({"grace_secs": 3600}))
+        .await
+        .unwrap();
+    let (status, delist_at): (String, Option<DateTime<Utc>>) =
+        sqlx::query_as("SELECT status, delist_at FROM symbols WHERE symbol = $1")
+            .bind(TEST_SYMBOL)
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+    assert_eq!(status, "CancelOnly");
+    assert_eq!(
+        delist_at,
+        Some(start_time() + chrono::Duration::seconds(3600))
+    );
+    // A restart during the grace period picks the deadline back up
+    let hydrated = hydration::hydrate(&pool, &[TEST_SYMBOL, OTHER_SYMBOL], true)
+        .await
+        .unwrap();
+    let pending = delisting::Delistings::from_configs(&hydrated.symbol_configs);
+    assert_eq!(
+        pending.next_deadline(),
+        Some(start_time() + chrono::Duration::seconds(3600))
+    );
+
+    clock.advance(Duration::from_secs(3600));
+    let delisted = delisting::finalize_due(&exchange.state, clock.now()).await;
+    assert_eq!(delisted.len(), 1);
+    assert_eq!(delisted[0].orders_cancelled, 1);
+    // Closed at the last trade price of 110
+    assert_eq!(delisted[0].positions_closed[&long.user_id], 20);
+    assert_eq!(delisted[0].positions_closed[&short.user_id], -20);
+    assert_eq!(delisted[0].positions_frozen, 0);
+
+    let (status,): (String,) = sqlx::query_as("SELECT status FROM symbols WHERE symbol = $1")
+        .bind(TEST_SYMBOL)
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert_eq!(status, "Delisted");
+    let (order_status, close_reason): (String, Option<String>) =
+        sqlx::query_as("SELECT status, close_reason FROM orders WHERE id = $1")
+            .bind(ask_id)
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+    assert_eq!(order_status, "Cancelled");
+    assert_eq!(close_reason.as_deref(), Some("SYMBOL_DELISTED"));
+    let stored = persistence::list_positions(&pool).await.unwrap();
+    assert!(
+        stored.iter().all(|p| p.symbol != TEST_SYMBOL),
+        "{:?}",
+        stored
+    );
+
+    let hydrated = hydration::hydrate(&pool, &[TEST_SYMBOL, OTHER_SYMBOL], true)
+        .await
+        .unwrap();
+    assert!(!hydrated.orderbooks.contains_key(TEST_SYMBOL));
+    assert!(!hydrated.symbol_configs.contains_key(TEST_SYMBOL));
+    assert!(hydrated.orderbooks.contains_key(OTHER_SYMBOL));
+    assert_eq!(hydrated.report.symbols_delisted, vec![TEST_SYMBOL]);
+}
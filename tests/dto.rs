@@ -0,0 +1,336 @@
+//! The wire shape of every DTO, field by field, and of the WebSocket messages that carry
+//! engine types. A change here is an API change.
+
+use chrono::{DateTime, TimeZone, Utc};
+use rust_exchange::api::dto::{
+    BookDiffDto, BookLevelDto, BookSnapshotDto, CancelledOrderDto, ExecutionReportDto, OrderDto,
+    PositionDto, ReplacedOrderDto, TradeDto, UserTradeDto, WithMeta,
+};
+use rust_exchange::api::routes::WsMessage;
+use rust_exchange::api::service::CancelledOrder;
+use rust_exchange::orderbook::diff_buffer::BookDiff;
+use rust_exchange::orderbook::orderbook::{BookSnapshot, ExecutionReport};
+use rust_exchange::types::order::{
+    CloseReason, Order, OrderSide, OrderSource, OrderStatus, OrderTags, OrderType, RejectReason,
+};
+use rust_exchange::types::position::Position;
+use rust_exchange::types::symbol::SymbolConfig;
+use rust_exchange::types::trade::{Trade, TradeAttribution, TradeBust, UserTrade};
+use serde_json::{Value, json};
+use uuid::Uuid;
+
+const ORDER_ID: Uuid = Uuid::from_u128(1);
+const USER_ID: Uuid = Uuid::from_u128(2);
+const MAKER_ORDER_ID: Uuid = Uuid::from_u128(3);
+const MAKER_ID: Uuid = Uuid::from_u128(4);
+const TRADE_ID: Uuid = Uuid::from_u128(5);
+
+fn at() -> DateTime<Utc> {
+    Utc.with_ymd_and_hms(2025, 1, 2, 9, 30, 0).unwrap()
+}
+
+fn order() -> Order {
+    Order {
+        id: ORDER_ID,
+        user_id: USER_ID,
+        side: OrderSide::Buy,
+        order_type: OrderType::Limit,
+        price: 101,
+        quantity: 4,
+        status: OrderStatus::PartiallyFilled,
+        timestamp: at(),
+        tags: OrderTags::from([("strategy".to_string(), "mm".to_string())]),
+        source: OrderSource::Spread,
+    }
+}
+
+fn order_json() -> Value {
+    json!({
+        "id": ORDER_ID,
+        "user_id": USER_ID,
+        "side": "Buy",
+        "order_type": "Limit",
+        "price": 101,
+        "quantity": 4,
+        "status": "PartiallyFilled",
+        "timestamp": "2025-01-02T09:30:00Z",
+        "tags": { "strategy": "mm" },
+        "source": "Spread",
+    })
+}
+
+fn trade() -> Trade {
+    Trade {
+        id: TRADE_ID,
+        trade_seq: 7,
+        maker_order_id: MAKER_ORDER_ID,
+        taker_order_id: ORDER_ID,
+        maker_user_id: MAKER_ID,
+        taker_user_id: USER_ID,
+        price: 100,
+        quantity: 2,
+        timestamp: at(),
+        maker_queue_rank: Some(0),
+    }
+}
+
+fn trade_json() -> Value {
+    json!({
+        "id": TRADE_ID,
+        "trade_seq": 7,
+        "maker_order_id": MAKER_ORDER_ID,
+        "taker_order_id": ORDER_ID,
+        "maker_user_id": MAKER_ID,
+        "taker_user_id": USER_ID,
+        "price": 100,
+        "quantity": 2,
+        "timestamp": "2025-01-02T09:30:00Z",
+        "maker_queue_rank": 0,
+    })
+}
+
+fn position() -> Position {
+    Position {
+        user_id: USER_ID,
+        symbol: "BTCUSDT".to_string(),
+        quantity: -2,
+        average_price: 100,
+        opened_at: at(),
+        updated_at: at(),
+    }
+}
+
+fn position_json() -> Value {
+    json!({
+        "user_id": USER_ID,
+        "symbol": "BTCUSDT",
+        "quantity": -2,
+        "average_price": 100,
+        "opened_at": "2025-01-02T09:30:00Z",
+        "updated_at": "2025-01-02T09:30:00Z",
+    })
+}
+
+fn report() -> ExecutionReport {
+    ExecutionReport {
+        order: order(),
+        trades: vec![trade()],
+        rejection: Some(RejectReason::BookFull),
+    }
+}
+
+fn report_json() -> Value {
+    json!({
+        "order": order_json(),
+        "trades": [trade_json()],
+        "rejection": "BOOK_FULL",
+    })
+}
+
+/// `dto` serializes to exactly `expected` and reads back unchanged.
+fn assert_shape<T>(dto: &T, expected: Value)
+where
+    T: serde::Serialize + serde::de::DeserializeOwned + PartialEq + std::fmt::Debug,
+{
+    assert_eq!(serde_json::to_value(dto).unwrap(), expected);
+    assert_eq!(&serde_json::from_value::<T>(expected).unwrap(), dto);
+}
+
+#[test]
+fn order_trade_and_position_dtos() {
+    assert_shape(&OrderDto::from(order()), order_json());
+    assert_shape(&TradeDto::from(trade()), trade_json());
+    assert_shape(&PositionDto::from(position()), position_json());
+
+    // Trades stored before queue ranks were recorded leave the field out
+    let unranked = Trade {
+        maker_queue_rank: None,
+        ..trade()
+    };
+    let mut expected = trade_json();
+    expected.as_object_mut().unwrap().remove("maker_queue_rank");
+    assert_shape(&TradeDto::from(unranked), expected);
+}
+
+#[test]
+fn user_trade_dto_flattens_the_trade() {
+    let attribution = TradeAttribution {
+        trade_id: TRADE_ID,
+        taker_side: OrderSide::Buy,
+        maker_fee: -1,
+        taker_fee: 3,
+        realized_pnl_maker: 0,
+        realized_pnl_taker: 12,
+    };
+    let user_trade = UserTrade::new(trade(), Some(&attribution), USER_ID);
+    let mut expected = trade_json();
+    expected.as_object_mut().unwrap().extend([
+        ("role".to_string(), json!("Taker")),
+        ("side".to_string(), json!("Buy")),
+        ("fee".to_string(), json!(3)),
+        ("realized_pnl".to_string(), json!(12)),
+    ]);
+    assert_shape(&UserTradeDto::from(user_trade.clone()), expected.clone());
+
+    let bust = TradeBust {
+        busted_at: at(),
+        reason: "fat finger".to_string(),
+    };
+    let busted = UserTradeDto::from(user_trade.with_bust(Some(&bust)));
+    expected.as_object_mut().unwrap().extend([
+        ("busted_at".to_string(), json!("2025-01-02T09:30:00Z")),
+        ("bust_reason".to_string(), json!("fat finger")),
+    ]);
+    assert_shape(&busted, expected);
+}
+
+#[test]
+fn book_dtos_write_levels_as_pairs() {
+    assert_shape(&BookLevelDto::from((100, 3)), json!([100, 3]));
+    let snapshot = BookSnapshot {
+        seq: 9,
+        captured_at: at(),
+        bids: vec![(100, 3), (99, 1)],
+        asks: vec![(101, 2)],
+        is_empty: false,
+        checksum: 7,
+    };
+    assert_shape(
+        &BookSnapshotDto::from(snapshot),
+        json!({
+            "seq": 9,
+            "captured_at": "2025-01-02T09:30:00Z",
+            "bids": [[100, 3], [99, 1]],
+            "asks": [[101, 2]],
+            "is_empty": false,
+            "checksum": 7,
+        }),
+    );
+    let diff = BookDiff {
+        seq: 11,
+        prev_seq: 9,
+        bids: vec![(100, 0)],
+        asks: vec![(101, 5)],
+        checksum: 8,
+    };
+    assert_shape(
+        &BookDiffDto::from(diff),
+        json!({
+            "seq": 11,
+            "prev_seq": 9,
+            "bids": [[100, 0]],
+            "asks": [[101, 5]],
+            "checksum": 8,
+        }),
+    );
+}
+
+#[test]
+fn report_and_cancel_dtos() {
+    assert_shape(&ExecutionReportDto::from(report()), report_json());
+
+    let cancelled = CancelledOrder {
+        order: Order {
+            status: OrderStatus::Cancelled,
+            ..order()
+        },
+        remaining_quantity: 4,
+        closed_at: at(),
+        close_reason: CloseReason::AdminCancelled,
+    };
+    let mut expected = order_json();
+    expected.as_object_mut().unwrap().extend([
+        ("status".to_string(), json!("Cancelled")),
+        ("remaining_quantity".to_string(), json!(4)),
+        ("closed_at".to_string(), json!("2025-01-02T09:30:00Z")),
+        ("close_reason".to_string(), json!("ADMIN_CANCELLED")),
+    ]);
+    assert_shape(&CancelledOrderDto::from(cancelled), expected);
+
+    let replaced = ReplacedOrderDto {
+        cancelled: OrderDto::from(order()),
+        report: ExecutionReportDto::from(report()),
+    };
+    assert_shape(
+        &replaced,
+        json!({ "cancelled": order_json(), "report": report_json() }),
+    );
+}
+
+#[test]
+fn ws_messages_write_engine_types_as_dtos() {
+    let frame = |message: WsMessage| serde_json::to_value(message).unwrap();
+    assert_eq!(
+        frame(WsMessage::Trade {
+            symbol: "BTCUSDT".to_string(),
+            trade: trade(),
+        }),
+        json!({ "type": "Trade", "symbol": "BTCUSDT", "trade": trade_json() })
+    );
+    assert_eq!(
+        frame(WsMessage::OrderUpdate {
+            symbol: "BTCUSDT".to_string(),
+            report: report(),
+        }),
+        json!({ "type": "OrderUpdate", "symbol": "BTCUSDT", "report": report_json() })
+    );
+    assert_eq!(
+        frame(WsMessage::PositionUpdate {
+            position: position(),
+            mark_price: Some(99),
+            unrealized_pnl: Some(2),
+        }),
+        json!({
+            "type": "PositionUpdate",
+            "position": position_json(),
+            "mark_price": 99,
+            "unrealized_pnl": 2,
+        })
+    );
+    assert_eq!(
+        frame(WsMessage::OrderBookUpdate {
+            symbol: "BTCUSDT".to_string(),
+            bids: vec![(100, 3)],
+            asks: vec![],
+            diff: None,
+        }),
+        json!({ "type": "OrderBookUpdate", "symbol": "BTCUSDT", "bids": [[100, 3]], "asks": [] })
+    );
+}
+
+#[test]
+fn scale_meta_rides_along_flattened_and_as_exchange_info() {
+    let config = SymbolConfig {
+        tick_size: 5,
+        lot_size: 10,
+        qty_scale: 1_000,
+        ..SymbolConfig::default()
+    };
+    let meta = json!({
+        "price_scale": 100_000_000,
+        "qty_scale": 1_000,
+        "tick_size": 5,
+        "lot_size": 10,
+    });
+    assert_eq!(serde_json::to_value(config.meta()).unwrap(), meta);
+
+    let replaced = ReplacedOrderDto {
+        cancelled: OrderDto::from(order()),
+        report: ExecutionReportDto::from(report()),
+    };
+    let mut expected = serde_json::to_value(&replaced).unwrap();
+    expected["meta"] = meta.clone();
+    assert_eq!(
+        serde_json::to_value(WithMeta::new(replaced, &config)).unwrap(),
+        expected
+    );
+
+    assert_eq!(
+        serde_json::to_value(WsMessage::ExchangeInfo {
+            symbol: "BTCUSDT".to_string(),
+            meta: config.meta(),
+        })
+        .unwrap(),
+        json!({ "type": "ExchangeInfo", "symbol": "BTCUSDT", "meta": meta })
+    );
+}
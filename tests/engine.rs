@@ -0,0 +1,144 @@
+//! `engine::Exchange` driven directly, with no server: a matched trade from placement through
+//! positions, book and cancel, the typed errors, and an embedded exchange sharing its state
+//! with the HTTP API.
+
+use rust_exchange::engine::{EngineError, Exchange, PlaceOrder};
+use rust_exchange::testing::{self, OrderRequest, TEST_SYMBOL, TestExchange};
+use rust_exchange::types::order::{OrderSide, OrderStatus, RejectReason};
+use rust_exchange::types::symbol::Symbol;
+use std::time::Duration;
+use uuid::Uuid;
+
+fn btc() -> Symbol {
+    Symbol::parse(TEST_SYMBOL).unwrap()
+}
+
+#[tokio::test]
+async fn a_matched_trade_updates_book_and_positions_without_http() {
+    let exchange = Exchange::in_memory(&[TEST_SYMBOL]);
+    let maker = Uuid::new_v4();
+    let taker = Uuid::new_v4();
+
+    let resting = exchange
+        .place_order(maker, PlaceOrder::limit(btc(), OrderSide::Sell, 100, 5))
+        .await
+        .unwrap();
+    assert!(resting.trades.is_empty());
+    assert_eq!(resting.order.status, OrderStatus::Pending);
+
+    let taken = exchange
+        .place_order(taker, PlaceOrder::limit(btc(), OrderSide::Buy, 101, 3))
+        .await
+        .unwrap();
+    assert_eq!(taken.order.status, OrderStatus::Filled);
+    assert_eq!(taken.trades.len(), 1);
+    let trade = &taken.trades[0];
+    assert_eq!((trade.price, trade.quantity), (100, 3));
+    assert_eq!(trade.maker_order_id, resting.order.id);
+    assert_eq!(trade.taker_user_id, taker);
+
+    let book = exchange.book(TEST_SYMBOL).await.unwrap();
+    assert!(book.bids.is_empty());
+    assert_eq!(book.asks, vec![(100, 2)]);
+
+    let [maker_position] = exchange.positions(maker).await.try_into().unwrap();
+    assert_eq!(
+        (maker_position.quantity, maker_position.average_price),
+        (-3, 100)
+    );
+    let [taker_position] = exchange.positions(taker).await.try_into().unwrap();
+    assert_eq!(
+        (taker_position.quantity, taker_position.average_price),
+        (3, 100)
+    );
+
+    let cancelled = exchange.cancel(maker, resting.order.id).await.unwrap();
+    assert_eq!(cancelled.remaining_quantity, 2);
+    assert!(exchange.book(TEST_SYMBOL).await.unwrap().is_empty);
+    // Cancelling again answers with the same closure; to anyone else the order does not exist
+    let again = exchange.cancel(maker, resting.order.id).await.unwrap();
+    assert_eq!(
+        (again.remaining_quantity, again.closed_at),
+        (2, cancelled.closed_at)
+    );
+    assert_eq!(
+        exchange.cancel(taker, resting.order.id).await.unwrap_err(),
+        EngineError::OrderNotFound(resting.order.id)
+    );
+}
+
+#[tokio::test]
+async fn failures_come_back_as_typed_errors() {
+    let exchange = Exchange::in_memory(&[TEST_SYMBOL]);
+    let user = Uuid::new_v4();
+
+    let err = exchange
+        .place_order(user, PlaceOrder::market(btc(), OrderSide::Buy, 1))
+        .await
+        .unwrap_err();
+    assert_eq!(err, EngineError::Rejected(RejectReason::NoLiquidity));
+    assert_eq!(err.status(), 400);
+
+    let unknown = Symbol::parse("BTCUSDX").unwrap();
+    match exchange
+        .place_order(user, PlaceOrder::limit(unknown, OrderSide::Buy, 100, 1))
+        .await
+    {
+        Err(EngineError::UnknownSymbol { symbol, hint }) => {
+            assert_eq!(symbol, "BTCUSDX");
+            assert_eq!(hint.did_you_mean.as_deref(), Some(TEST_SYMBOL));
+        }
+        other => panic!("expected UnknownSymbol, got {:?}", other),
+    }
+    assert!(matches!(
+        exchange.book("BTCUSDX").await,
+        Err(EngineError::UnknownSymbol { .. })
+    ));
+
+    let ttl_on_market = PlaceOrder {
+        refresh_ttl: Some(Duration::from_secs(1)),
+        ..PlaceOrder::market(btc(), OrderSide::Buy, 1)
+    };
+    assert!(matches!(
+        exchange.place_order(user, ttl_on_market).await,
+        Err(EngineError::Invalid(_))
+    ));
+
+    let resting = exchange
+        .place_order(user, PlaceOrder::limit(btc(), OrderSide::Buy, 100, 1))
+        .await
+        .unwrap();
+    assert_eq!(
+        exchange
+            .cancel(Uuid::new_v4(), resting.order.id)
+            .await
+            .unwrap_err(),
+        EngineError::NotOwner(resting.order.id)
+    );
+}
+
+#[tokio::test]
+async fn an_embedded_exchange_shares_books_and_positions_with_the_http_api() {
+    let state = testing::test_app_state();
+    let exchange = Exchange::new(state.clone());
+    let server = TestExchange::builder_with_state(state).start().await;
+    let alice = server.register("alice", "password123").await;
+
+    exchange
+        .place_order(
+            alice.user_id,
+            PlaceOrder::limit(btc(), OrderSide::Sell, 100, 4),
+        )
+        .await
+        .unwrap();
+    assert_eq!(server.book(TEST_SYMBOL).await.asks, vec![(100, 4)]);
+
+    let bob = server.register("bob", "password123").await;
+    server
+        .place_order(&bob, &OrderRequest::market(TEST_SYMBOL, OrderSide::Buy, 4))
+        .await
+        .unwrap();
+    let [position] = exchange.positions(bob.user_id).await.try_into().unwrap();
+    assert_eq!(position.quantity, 4);
+    assert!(exchange.book(TEST_SYMBOL).await.unwrap().is_empty);
+}
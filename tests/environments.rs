@@ -0,0 +1,245 @@
+//! Paper books beside the live ones: `BTCUSDT@paper` (or an order with `environment: paper`)
+//! trades on a book of its own, and nothing — resting orders, fills, positions, order and
+//! trade history, WebSocket channels or balances — crosses between it and `BTCUSDT`.
+
+use reqwest::StatusCode;
+use rust_exchange::balances::Balances;
+use rust_exchange::testing::{OrderRequest, TEST_SYMBOL, TestExchange, Token};
+use rust_exchange::types::order::{OrderSide, RejectReason};
+use serde_json::{Value, json};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+const PAPER_SYMBOL: &str = "BTCUSDT@paper";
+
+async fn start() -> TestExchange {
+    TestExchange::builder().symbol(PAPER_SYMBOL).start().await
+}
+
+/// Place a limit order through `POST /orders` with extra body fields.
+async fn place(
+    exchange: &TestExchange,
+    token: &Token,
+    body: Value,
+) -> Result<Value, rust_exchange::testing::ApiError> {
+    exchange
+        .send_json(
+            exchange
+                .client()
+                .post(exchange.url("/orders"))
+                .bearer_auth(&token.token)
+                .json(&body),
+        )
+        .await
+}
+
+fn items(page: &Value) -> &Vec<Value> {
+    page["items"].as_array().unwrap()
+}
+
+#[tokio::test]
+async fn paper_orders_match_only_each_other_and_are_listed_apart() {
+    let exchange = start().await;
+    let alice = exchange.register("alice", "secret").await;
+    let bob = exchange.register("bob", "secret").await;
+
+    exchange
+        .place_order(
+            &alice,
+            &OrderRequest::limit(TEST_SYMBOL, OrderSide::Sell, 100, 2),
+        )
+        .await
+        .unwrap();
+    // The same price on the paper book does not reach the live ask
+    let resting = place(
+        &exchange,
+        &bob,
+        json!({"symbol": TEST_SYMBOL, "environment": "paper", "side": "Buy", "price": 100, "quantity": 1}),
+    )
+    .await
+    .unwrap();
+    assert_eq!(resting["status"], "Pending");
+    assert_eq!(exchange.book(PAPER_SYMBOL).await.bids, vec![(100, 1)]);
+    let filled = exchange
+        .place_order(
+            &alice,
+            &OrderRequest::limit("btcusdt@PAPER", OrderSide::Sell, 100, 1),
+        )
+        .await
+        .unwrap();
+    assert_eq!(filled.trades.len(), 1);
+
+    assert_eq!(exchange.book(TEST_SYMBOL).await.asks, vec![(100, 2)]);
+    let paper = exchange.book(PAPER_SYMBOL).await;
+    assert!(paper.bids.is_empty() && paper.asks.is_empty());
+
+    // Positions, trades and orders of the caller default to live
+    assert!(exchange.positions(&bob).await.unwrap().is_empty());
+    let positions = exchange
+        .get(&bob, "/positions?environment=paper")
+        .await
+        .unwrap();
+    assert_eq!(positions.as_array().unwrap().len(), 1);
+    assert_eq!(positions[0]["symbol"], PAPER_SYMBOL);
+    assert_eq!(positions[0]["quantity"], 1);
+
+    assert!(exchange.trades_me(&bob, None).await.unwrap().is_empty());
+    assert!(
+        exchange
+            .trades_me(&bob, Some(TEST_SYMBOL))
+            .await
+            .unwrap()
+            .is_empty()
+    );
+    assert_eq!(
+        exchange
+            .trades_me(&bob, Some(PAPER_SYMBOL))
+            .await
+            .unwrap()
+            .len(),
+        1
+    );
+    let trades = exchange
+        .get(&bob, "/trades/me?environment=paper")
+        .await
+        .unwrap();
+    assert_eq!(items(&trades).len(), 1);
+
+    let quote = exchange
+        .place_order(
+            &alice,
+            &OrderRequest::limit(PAPER_SYMBOL, OrderSide::Sell, 105, 1),
+        )
+        .await
+        .unwrap();
+    let live_orders = exchange.get(&alice, "/orders/me").await.unwrap();
+    assert_eq!(items(&live_orders).len(), 1);
+    assert_eq!(items(&live_orders)[0]["quantity"], 2);
+    let paper_orders = exchange
+        .get(&alice, "/orders/me?environment=paper")
+        .await
+        .unwrap();
+    assert_eq!(items(&paper_orders).len(), 1);
+    assert_eq!(items(&paper_orders)[0]["id"], quote.order.id.to_string());
+    let public_trades = exchange
+        .get(&alice, &format!("/trades?symbol={}", TEST_SYMBOL))
+        .await
+        .unwrap();
+    assert!(items(&public_trades).is_empty());
+    let paper_trades = exchange
+        .get(&alice, &format!("/trades?symbol={}", PAPER_SYMBOL))
+        .await
+        .unwrap();
+    assert_eq!(items(&paper_trades).len(), 1);
+}
+
+#[tokio::test]
+async fn a_symbol_and_an_environment_must_agree() {
+    let exchange = start().await;
+    let trader = exchange.register("trader", "secret").await;
+
+    let err = place(
+        &exchange,
+        &trader,
+        json!({"symbol": PAPER_SYMBOL, "environment": "live", "side": "Buy", "price": 100, "quantity": 1}),
+    )
+    .await
+    .unwrap_err();
+    assert_eq!(err.status, StatusCode::BAD_REQUEST);
+    assert_eq!(err.body["error_code"], "INVALID_SYMBOL");
+    let err = place(
+        &exchange,
+        &trader,
+        json!({"symbol": "BTCUSDT@staging", "side": "Buy", "price": 100, "quantity": 1}),
+    )
+    .await
+    .unwrap_err();
+    assert_eq!(err.body["error_code"], "INVALID_SYMBOL");
+    // A paper book only exists where one was opened
+    let err = place(
+        &exchange,
+        &trader,
+        json!({"symbol": "ETHUSDT", "environment": "paper", "side": "Buy", "price": 100, "quantity": 1}),
+    )
+    .await
+    .unwrap_err();
+    assert_eq!(err.status, StatusCode::NOT_FOUND);
+
+    let err = exchange
+        .get(&trader, "/orders/me?symbol=BTCUSDT@paper&environment=live")
+        .await
+        .unwrap_err();
+    assert_eq!(err.status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn the_paper_channel_carries_only_paper_trades() {
+    let exchange = start().await;
+    let maker = exchange.register("maker", "secret").await;
+    let taker = exchange.register("taker", "secret").await;
+    let mut ws = exchange.ws_client(None).await;
+    ws.subscribe(PAPER_SYMBOL).await;
+
+    for symbol in [TEST_SYMBOL, PAPER_SYMBOL] {
+        exchange
+            .place_order(
+                &maker,
+                &OrderRequest::limit(symbol, OrderSide::Sell, 100, 1),
+            )
+            .await
+            .unwrap();
+        exchange
+            .place_order(&taker, &OrderRequest::limit(symbol, OrderSide::Buy, 100, 1))
+            .await
+            .unwrap();
+    }
+
+    // The live trade happened first; the paper one is the first the channel delivers
+    let trade = ws.next_of_type("Trade").await;
+    assert_eq!(trade["symbol"], PAPER_SYMBOL);
+    let update = ws.next_of_type("OrderBookUpdate").await;
+    assert_eq!(update["symbol"], PAPER_SYMBOL);
+}
+
+#[tokio::test]
+async fn paper_balances_are_funded_and_kept_apart_from_live_ones() {
+    let exchange = TestExchange::builder()
+        .symbol(PAPER_SYMBOL)
+        .with_state(|state| {
+            state.balances = Arc::new(Balances::new(true));
+            state.paper_balances = Arc::new(Balances::funded(HashMap::from([(
+                "USDT".to_string(),
+                1_000,
+            )])));
+        })
+        .start()
+        .await;
+    let trader = exchange.register("trader", "secret").await;
+
+    let refused = exchange
+        .place_order(
+            &trader,
+            &OrderRequest::limit(TEST_SYMBOL, OrderSide::Buy, 100, 2),
+        )
+        .await
+        .unwrap_err();
+    assert_eq!(refused.rejection(), Some(RejectReason::InsufficientBalance));
+    exchange
+        .place_order(
+            &trader,
+            &OrderRequest::limit(PAPER_SYMBOL, OrderSide::Buy, 100, 2),
+        )
+        .await
+        .unwrap();
+
+    let paper = exchange
+        .get(&trader, "/balances?environment=paper")
+        .await
+        .unwrap();
+    assert_eq!(
+        paper,
+        json!([{"asset": "USDT", "available": 800, "locked": 200}])
+    );
+    let live = exchange.get(&trader, "/balances").await.unwrap();
+    assert_eq!(live, json!([]));
+}
@@ -0,0 +1,178 @@
+//! `GET /exchangeInfo` and its changelog: every change to a symbol's rules bumps
+//! `rules_version` by one and is listed, field by field, by `GET /exchangeInfo/changes`.
+
+use reqwest::StatusCode;
+use rust_exchange::api::exchange_info::RulesLog;
+use rust_exchange::api::incidents::PANIC_TAG;
+use rust_exchange::testing::{self, OrderRequest, TEST_SYMBOL, TestExchange, Token};
+use rust_exchange::types::order::OrderSide;
+use serde_json::{Value, json};
+use std::sync::Arc;
+
+async fn start(rules: RulesLog) -> (TestExchange, Token) {
+    let mut state = testing::test_app_state();
+    state.rules = Arc::new(rules);
+    let exchange = TestExchange::builder_with_state(state)
+        .admin()
+        .start()
+        .await;
+    let admin = exchange.admin_token();
+    (exchange, admin)
+}
+
+async fn patch(exchange: &TestExchange, admin: &Token, body: Value) -> Value {
+    exchange
+        .send_json(
+            exchange
+                .client()
+                .patch(exchange.url(&format!("/admin/symbols/{}", TEST_SYMBOL)))
+                .bearer_auth(&admin.token)
+                .json(&body),
+        )
+        .await
+        .unwrap()
+}
+
+async fn exchange_info(exchange: &TestExchange) -> Value {
+    exchange
+        .send_json(exchange.client().get(exchange.url("/exchangeInfo")))
+        .await
+        .unwrap()
+}
+
+async fn changes(exchange: &TestExchange, since_version: u64) -> Result<Value, StatusCode> {
+    exchange
+        .send_json(exchange.client().get(exchange.url(&format!(
+            "/exchangeInfo/changes?since_version={}",
+            since_version
+        ))))
+        .await
+        .map_err(|err| err.status)
+}
+
+fn symbol<'a>(info: &'a Value, symbol: &str) -> &'a Value {
+    info["symbols"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|listed| listed["symbol"] == symbol)
+        .unwrap()
+}
+
+#[tokio::test]
+async fn each_rules_change_bumps_the_version_and_lists_what_changed() {
+    let (exchange, admin) = start(RulesLog::default()).await;
+    let info = exchange_info(&exchange).await;
+    assert_eq!(info["rules_version"], 0);
+    assert_eq!(symbol(&info, TEST_SYMBOL)["tick_size"], 1);
+
+    patch(&exchange, &admin, json!({"tick_size": 5})).await;
+    // Patching in what is already there is not a change
+    patch(&exchange, &admin, json!({"tick_size": 5})).await;
+    patch(&exchange, &admin, json!({"halted": true})).await;
+
+    let info = exchange_info(&exchange).await;
+    assert_eq!(info["rules_version"], 2);
+    let config = symbol(&info, TEST_SYMBOL);
+    assert_eq!(
+        (config["tick_size"].clone(), config["halted"].clone()),
+        (json!(5), json!(true))
+    );
+
+    let all = changes(&exchange, 0).await.unwrap();
+    assert_eq!(all["rules_version"], 2);
+    let listed = all["changes"].as_array().unwrap();
+    assert_eq!(listed.len(), 2);
+    assert_eq!(listed[0]["version"], 1);
+    assert_eq!(listed[0]["symbol"], TEST_SYMBOL);
+    assert_eq!(listed[0]["source"], "admin_patch");
+    assert_eq!(listed[0]["changed_by"], admin.user_id.to_string());
+    assert_eq!(
+        listed[0]["changes"],
+        json!({"tick_size": {"from": 1, "to": 5}})
+    );
+    assert_eq!(
+        listed[1]["changes"],
+        json!({"halted": {"from": false, "to": true}})
+    );
+
+    let later = changes(&exchange, 1).await.unwrap();
+    assert_eq!(later["changes"].as_array().unwrap().len(), 1);
+    assert_eq!(later["changes"][0]["version"], 2);
+    let current = changes(&exchange, 2).await.unwrap();
+    assert!(current["changes"].as_array().unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn delistings_and_incident_halts_are_versioned_too() {
+    let (exchange, admin) = start(RulesLog::default()).await;
+    let maker = exchange.register("maker", "secret").await;
+    let taker = exchange.register("taker", "secret").await;
+    exchange
+        .place_order(
+            &maker,
+            &OrderRequest::limit(TEST_SYMBOL, OrderSide::Sell, 101, 2),
+        )
+        .await
+        .unwrap();
+    let err = exchange
+        .send_json(
+            exchange
+                .client()
+                .post(exchange.url("/orders"))
+                .bearer_auth(&taker.token)
+                .json(&json!({
+                    "symbol": TEST_SYMBOL,
+                    "side": "Buy",
+                    "price": 101,
+                    "quantity": 1,
+                    "tags": { PANIC_TAG: "1" },
+                })),
+        )
+        .await
+        .unwrap_err();
+    assert_eq!(err.status, StatusCode::INTERNAL_SERVER_ERROR);
+
+    exchange
+        .send_json(
+            exchange
+                .client()
+                .post(exchange.url(&format!("/admin/symbols/{}/delist", TEST_SYMBOL)))
+                .bearer_auth(&admin.token)
+                .json(&json!({"grace_secs": 60})),
+        )
+        .await
+        .unwrap();
+
+    let listed = changes(&exchange, 0).await.unwrap();
+    let listed = listed["changes"].as_array().unwrap();
+    assert_eq!(listed.len(), 2);
+    assert_eq!(listed[0]["source"], "incident_halt");
+    assert_eq!(listed[0]["changed_by"], Value::Null);
+    assert_eq!(
+        listed[0]["changes"],
+        json!({"halted": {"from": false, "to": true}})
+    );
+    assert_eq!(listed[1]["version"], 2);
+    assert_eq!(listed[1]["source"], "delisting_started");
+    assert_eq!(
+        listed[1]["changes"]["status"],
+        json!({"from": "Trading", "to": "CancelOnly"})
+    );
+    assert_eq!(listed[1]["changes"]["delisting"]["from"], Value::Null);
+}
+
+#[tokio::test]
+async fn changes_no_longer_kept_are_gone_without_a_database() {
+    let (exchange, admin) = start(RulesLog::new(1)).await;
+    patch(&exchange, &admin, json!({"tick_size": 5})).await;
+    patch(&exchange, &admin, json!({"lot_size": 2})).await;
+
+    assert_eq!(changes(&exchange, 0).await.unwrap_err(), StatusCode::GONE);
+    let kept = changes(&exchange, 1).await.unwrap();
+    assert_eq!(kept["changes"][0]["version"], 2);
+    assert_eq!(
+        kept["changes"][0]["changes"],
+        json!({"lot_size": {"from": 1, "to": 2}})
+    );
+}
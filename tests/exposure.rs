@@ -0,0 +1,222 @@
+//! GET /account/exposure: positions across books valued in one currency, converted directly or
+//! through a routing hop, and flagged when they cannot be.
+
+use chrono::Utc;
+use rust_exchange::pricefeed::IndexPrice;
+use rust_exchange::testing::{OrderRequest, TestExchange, Token};
+use rust_exchange::types::money::PRICE_SCALE;
+use rust_exchange::types::order::OrderSide;
+use rust_exchange::types::symbol::SymbolConfig;
+use serde_json::Value;
+
+fn units(whole: f64) -> i64 {
+    (whole * PRICE_SCALE as f64).round() as i64
+}
+
+async fn set_index(exchange: &TestExchange, symbol: &str, price: i64, age_secs: i64) {
+    exchange.state.index_prices.write().await.insert(
+        symbol.to_string(),
+        IndexPrice {
+            price,
+            updated_at: Utc::now() - chrono::Duration::seconds(age_secs),
+        },
+    );
+}
+
+async fn exposure(exchange: &TestExchange, token: &Token, currency: &str) -> Value {
+    exchange
+        .get(token, &format!("/account/exposure?currency={}", currency))
+        .await
+        .unwrap()
+}
+
+fn position<'a>(body: &'a Value, symbol: &str) -> &'a Value {
+    body["positions"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|p| p["symbol"] == symbol)
+        .unwrap_or_else(|| panic!("no {} position in {}", symbol, body))
+}
+
+fn amount(value: &Value) -> i64 {
+    match value {
+        Value::String(s) => s.parse().unwrap(),
+        other => other.as_i64().unwrap(),
+    }
+}
+
+fn path(entry: &Value) -> Vec<&str> {
+    entry["path"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|hop| hop["symbol"].as_str().unwrap())
+        .collect()
+}
+
+/// Books for BTCUSDT, ETHBTC (no ETHUSDT), SOLEUR (nothing links EUR) and DOGEUSDT (never
+/// traded, no index); alice holds a position in each.
+async fn start() -> (TestExchange, Token) {
+    let exchange = TestExchange::builder()
+        .symbol("ETHBTC")
+        .symbol("SOLEUR")
+        .symbol("DOGEUSDT")
+        .user("alice", "password123")
+        .position("alice", "BTCUSDT", -1, units(61_000.0))
+        .position("alice", "ETHBTC", 2, units(0.04))
+        .position("alice", "SOLEUR", 3, units(90.0))
+        .position("alice", "DOGEUSDT", 10, units(0.1))
+        .start()
+        .await;
+    let alice = exchange.login("alice", "password123").await.unwrap();
+    (exchange, alice)
+}
+
+#[tokio::test]
+async fn positions_convert_directly_or_through_a_hop_and_the_rest_are_flagged() {
+    let (exchange, alice) = start().await;
+    set_index(&exchange, "BTCUSDT", units(60_000.0), 0).await;
+    set_index(&exchange, "ETHBTC", units(0.05), 0).await;
+    set_index(&exchange, "SOLEUR", units(100.0), 0).await;
+
+    let body = exposure(&exchange, &alice, "usdt").await;
+    assert_eq!(body["currency"], "USDT");
+
+    let btc = position(&body, "BTCUSDT");
+    assert_eq!(btc["mark_source"], "index");
+    assert_eq!(amount(&btc["notional"]), -units(60_000.0));
+    assert_eq!(amount(&btc["unrealized_pnl"]), units(1_000.0));
+    assert_eq!(amount(&btc["converted_notional"]), -units(60_000.0));
+    assert!(path(btc).is_empty());
+
+    // 2 ETH at 0.05 BTC is 0.1 BTC, 6,000 USDT through BTCUSDT; bought at 0.04, up 0.02 BTC,
+    // 1,200 USDT
+    let eth = position(&body, "ETHBTC");
+    assert_eq!(eth["quote_asset"], "BTC");
+    assert_eq!(amount(&eth["notional"]), units(0.1));
+    assert_eq!(amount(&eth["unrealized_pnl"]), units(0.02));
+    assert_eq!(path(eth), ["BTCUSDT"]);
+    assert_eq!(amount(&eth["converted_notional"]), units(6_000.0));
+    assert_eq!(amount(&eth["converted_unrealized_pnl"]), units(1_200.0));
+    assert_eq!(eth["unconvertible"], Value::Null);
+
+    let sol = position(&body, "SOLEUR");
+    assert_eq!(amount(&sol["notional"]), units(300.0));
+    assert_eq!(sol["converted_notional"], Value::Null);
+    assert_eq!(sol["unconvertible"], "no_conversion_path");
+
+    // An empty book that never traded has no mark
+    let doge = position(&body, "DOGEUSDT");
+    assert_eq!(doge["mark_price"], Value::Null);
+    assert_eq!(doge["notional"], Value::Null);
+    assert_eq!(doge["unconvertible"], "no_mark_price");
+
+    // Totals cover BTCUSDT and ETHBTC only
+    assert_eq!(amount(&body["net_notional"]), -units(54_000.0));
+    assert_eq!(amount(&body["gross_notional"]), units(66_000.0));
+    assert_eq!(amount(&body["unrealized_pnl"]), units(2_200.0));
+    assert_eq!(body["stale_index_symbols"], serde_json::json!([]));
+
+    // The same positions in BTC: BTCUSDT is converted back through its own mark
+    let body = exposure(&exchange, &alice, "BTC").await;
+    let btc = position(&body, "BTCUSDT");
+    assert_eq!(amount(&btc["converted_notional"]), -units(1.0));
+    assert_eq!(btc["path"][0]["inverse"], true);
+    assert!(path(position(&body, "ETHBTC")).is_empty());
+    assert_eq!(amount(&body["net_notional"]), -units(0.9));
+}
+
+#[tokio::test]
+async fn a_stale_index_falls_back_to_the_last_trade_and_is_reported() {
+    let (exchange, alice) = start().await;
+    set_index(&exchange, "ETHBTC", units(0.05), 0).await;
+    // Far older than the index max age
+    set_index(&exchange, "BTCUSDT", units(60_000.0), 3_600).await;
+
+    // No trade yet: BTCUSDT has no mark, so nothing links BTC to USDT
+    let body = exposure(&exchange, &alice, "USDT").await;
+    assert_eq!(body["stale_index_symbols"], serde_json::json!(["BTCUSDT"]));
+    assert_eq!(position(&body, "BTCUSDT")["unconvertible"], "no_mark_price");
+    assert_eq!(
+        position(&body, "ETHBTC")["unconvertible"],
+        "no_conversion_path"
+    );
+    assert_eq!(amount(&body["gross_notional"]), 0);
+
+    // A trade at 50,000 leaves the book empty but gives it a last trade price
+    let maker = exchange.register("maker", "password123").await;
+    let taker = exchange.register("taker", "password123").await;
+    let price = units(50_000.0);
+    exchange
+        .place_order(
+            &maker,
+            &OrderRequest::limit("BTCUSDT", OrderSide::Sell, price, 1),
+        )
+        .await
+        .unwrap();
+    exchange
+        .place_order(
+            &taker,
+            &OrderRequest::limit("BTCUSDT", OrderSide::Buy, price, 1),
+        )
+        .await
+        .unwrap();
+    assert!(exchange.book("BTCUSDT").await.asks.is_empty());
+
+    let body = exposure(&exchange, &alice, "USDT").await;
+    assert_eq!(body["stale_index_symbols"], serde_json::json!(["BTCUSDT"]));
+    let btc = position(&body, "BTCUSDT");
+    assert_eq!(btc["mark_source"], "last_trade");
+    assert_eq!(amount(&btc["mark_price"]), price);
+    let eth = position(&body, "ETHBTC");
+    assert_eq!(eth["mark_source"], "index");
+    assert_eq!(path(eth), ["BTCUSDT"]);
+    assert_eq!(amount(&eth["converted_notional"]), units(5_000.0));
+}
+
+#[tokio::test]
+async fn unknown_currencies_are_rejected_and_no_positions_is_zero() {
+    let (exchange, alice) = start().await;
+    for currency in ["XYZ", "US-D", ""] {
+        let err = exchange
+            .get(&alice, &format!("/account/exposure?currency={}", currency))
+            .await
+            .unwrap_err();
+        assert_eq!(err.status.as_u16(), 400, "{}", currency);
+    }
+
+    let bob = exchange.register("bob", "password123").await;
+    let body = exposure(&exchange, &bob, "USDT").await;
+    assert_eq!(body["positions"], serde_json::json!([]));
+    assert_eq!(amount(&body["net_notional"]), 0);
+}
+
+#[tokio::test]
+async fn positions_counted_in_fine_lots_are_valued_without_overflow() {
+    // 1.5 BTC in lots of 1e-8 BTC, bought at 50,000: its P&L in lots is beyond an i64
+    let exchange = TestExchange::builder()
+        .user("alice", "password123")
+        .position("alice", "BTCUSDT", 150_000_000, units(50_000.0))
+        .with_state(|state| {
+            let mut config = SymbolConfig::for_symbol("BTCUSDT");
+            config.qty_scale = 100_000_000;
+            state
+                .symbol_configs
+                .try_write()
+                .unwrap()
+                .insert("BTCUSDT".to_string(), config);
+        })
+        .start()
+        .await;
+    let alice = exchange.login("alice", "password123").await.unwrap();
+    set_index(&exchange, "BTCUSDT", units(60_000.0), 0).await;
+
+    let body = exposure(&exchange, &alice, "USDT").await;
+    let btc = position(&body, "BTCUSDT");
+    assert_eq!(btc["unconvertible"], Value::Null);
+    assert_eq!(amount(&btc["notional"]), units(90_000.0));
+    assert_eq!(amount(&btc["unrealized_pnl"]), units(15_000.0));
+    assert_eq!(amount(&btc["converted_unrealized_pnl"]), units(15_000.0));
+    assert_eq!(amount(&body["unrealized_pnl"]), units(15_000.0));
+}
@@ -0,0 +1,242 @@
+//! Failure paths driven through the `FaultPlan`: a fill write that fails or loses the
+//! database mid-transaction leaves nothing behind and is recovered by reconciliation, an
+//! idempotency lookup that fails refuses the order rather than risk running it twice, slow
+//! writes are measured, and a socket whose feed lags is resynced. The persistence tests need
+//! `TEST_DATABASE_URL` (see `e2e_db.rs`) and are skipped when it is unset.
+
+use reqwest::StatusCode;
+use rust_exchange::persistence::{self, PgPool};
+use rust_exchange::testing::{self, OrderRequest, TEST_SYMBOL, TestExchange, Token};
+use rust_exchange::types::order::OrderSide;
+use serde_json::{Value, json};
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+/// A harness exchange persisting to `pool`, with an admin token.
+async fn start_with(pool: &PgPool) -> (TestExchange, Token) {
+    let exchange = TestExchange::builder_with_state(testing::db_app_state(pool.clone()))
+        .admin()
+        .start()
+        .await;
+    let admin = exchange.admin_token();
+    (exchange, admin)
+}
+
+/// `POST /orders` for a limit order; the order id. Trades are read from the book, since
+/// `/trades/me` reads the database the faults are injected into.
+async fn place(
+    exchange: &TestExchange,
+    token: &Token,
+    side: OrderSide,
+    price: i64,
+    qty: u64,
+) -> Uuid {
+    let order = exchange
+        .send_json(
+            exchange
+                .client()
+                .post(exchange.url("/orders"))
+                .bearer_auth(&token.token)
+                .json(&OrderRequest::limit(TEST_SYMBOL, side, price, qty)),
+        )
+        .await
+        .unwrap();
+    order["id"].as_str().unwrap().parse().unwrap()
+}
+
+async fn book_trades(exchange: &TestExchange) -> usize {
+    exchange.state.orderbooks[TEST_SYMBOL]
+        .read()
+        .await
+        .get_all_trades()
+        .len()
+}
+
+async fn count(pool: &PgPool, table: &str) -> i64 {
+    sqlx::query_scalar(&format!("SELECT COUNT(*) FROM {}", table))
+        .fetch_one(pool)
+        .await
+        .unwrap()
+}
+
+async fn stored_quantity(pool: &PgPool, order_id: Uuid) -> Option<i64> {
+    sqlx::query_scalar("SELECT quantity FROM orders WHERE id = $1")
+        .bind(order_id)
+        .fetch_optional(pool)
+        .await
+        .unwrap()
+}
+
+async fn reconcile(exchange: &TestExchange, admin: &Token, repair: Option<&str>) -> Value {
+    let path = match repair {
+        Some(target) => format!("/admin/reconcile?symbol={}&repair={}", TEST_SYMBOL, target),
+        None => format!("/admin/reconcile?symbol={}", TEST_SYMBOL),
+    };
+    exchange.get(admin, &path).await.unwrap()
+}
+
+/// Bring the database back in line with memory: the trades first, then the book and the
+/// positions. Returns how many trades were re-inserted.
+async fn recover(exchange: &TestExchange, admin: &Token) -> u64 {
+    let reconciled = exchange
+        .send_json(
+            exchange
+                .client()
+                .post(exchange.url("/admin/trades/reconcile"))
+                .bearer_auth(&admin.token)
+                .json(&json!({ "symbol": TEST_SYMBOL })),
+        )
+        .await
+        .unwrap();
+    reconcile(exchange, admin, Some("orders")).await;
+    reconcile(exchange, admin, Some("positions")).await;
+    assert_eq!(reconcile(exchange, admin, None).await["consistent"], true);
+    reconciled["reinserted"].as_u64().unwrap()
+}
+
+#[tokio::test]
+async fn a_failed_fill_write_rolls_back_the_whole_step() {
+    let Some(pool) = testing::schema_pool("faults").await else {
+        return;
+    };
+    let (exchange, admin) = start_with(&pool).await;
+    let maker = exchange.register("maker", "secret").await;
+    let taker = exchange.register("taker", "secret").await;
+    let ask = place(&exchange, &maker, OrderSide::Sell, 100, 3).await;
+
+    // The order row and the maker's fill are written before the trades, in one transaction
+    exchange.state.faults.fail_call("insert_trades_bulk", 1);
+    let bid = place(&exchange, &taker, OrderSide::Buy, 100, 2).await;
+    assert_eq!(book_trades(&exchange).await, 1);
+    assert_eq!(stored_quantity(&pool, bid).await, None);
+    assert_eq!(stored_quantity(&pool, ask).await, Some(3));
+    assert_eq!(count(&pool, "trades").await, 0);
+    assert_eq!(count(&pool, "positions").await, 0);
+
+    let report = reconcile(&exchange, &admin, None).await;
+    assert_eq!(report["consistent"], false);
+    assert_eq!(report["orders"]["mismatched"][0]["order_id"], json!(ask));
+    assert_eq!(report["positions"]["missing"].as_array().unwrap().len(), 2);
+
+    // The fault fired once; later steps persist, and reconciliation restores the lost one
+    assert_eq!(recover(&exchange, &admin).await, 1);
+    assert_eq!(stored_quantity(&pool, ask).await, Some(1));
+    assert_eq!(count(&pool, "trades").await, 1);
+    assert_eq!(count(&pool, "positions").await, 2);
+}
+
+#[tokio::test]
+async fn losing_the_database_mid_step_persists_nothing_until_it_is_back() {
+    let Some(pool) = testing::schema_pool("faults").await else {
+        return;
+    };
+    let (exchange, admin) = start_with(&pool).await;
+    let maker = exchange.register("maker", "secret").await;
+    let taker = exchange.register("taker", "secret").await;
+    place(&exchange, &maker, OrderSide::Sell, 100, 3).await;
+
+    // The taker's order row goes through, then the connection is gone
+    exchange.state.faults.disconnect_after("insert_order", 1);
+    let bid = place(&exchange, &taker, OrderSide::Buy, 100, 1).await;
+    assert_eq!(book_trades(&exchange).await, 1);
+    assert_eq!(stored_quantity(&pool, bid).await, None);
+    assert_eq!(count(&pool, "trades").await, 0);
+    // Matching carries on in memory; anything that needs the database fails
+    place(&exchange, &taker, OrderSide::Buy, 100, 1).await;
+    let error = exchange
+        .client()
+        .post(exchange.url("/auth/register"))
+        .json(&json!({ "username": "late", "password": "secret" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(error.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    assert_eq!(count(&pool, "trades").await, 0);
+
+    exchange.state.faults.reconnect();
+    exchange.register("late", "secret").await;
+    assert_eq!(recover(&exchange, &admin).await, 2);
+    assert_eq!(count(&pool, "trades").await, 2);
+}
+
+#[tokio::test]
+async fn an_idempotency_key_lookup_that_fails_does_not_run_the_order() {
+    let Some(pool) = testing::schema_pool("faults").await else {
+        return;
+    };
+    let (exchange, _admin) = start_with(&pool).await;
+    let maker = exchange.register("maker", "secret").await;
+    let keyed = || {
+        exchange
+            .client()
+            .post(exchange.url("/orders"))
+            .bearer_auth(&maker.token)
+            .header("Idempotency-Key", "order-1")
+            .json(&OrderRequest::limit(TEST_SYMBOL, OrderSide::Sell, 100, 1))
+    };
+
+    exchange.state.faults.fail_call("get_idempotency_key", 1);
+    let res = keyed().send().await.unwrap();
+    assert_eq!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(exchange.book(TEST_SYMBOL).await.asks, vec![]);
+
+    // The key was released: the retry runs the order, and the one after replays it
+    let res = keyed().send().await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    assert!(res.headers().get("idempotent-replay").is_none());
+    let res = keyed().send().await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.headers()["idempotent-replay"], "true");
+    assert_eq!(exchange.book(TEST_SYMBOL).await.asks, vec![(100, 1)]);
+}
+
+#[tokio::test]
+async fn slow_writes_hold_the_step_and_are_measured() {
+    let Some(pool) = testing::schema_pool("faults").await else {
+        return;
+    };
+    let (exchange, _admin) = start_with(&pool).await;
+    let maker = exchange.register("maker", "secret").await;
+    let taker = exchange.register("taker", "secret").await;
+    place(&exchange, &maker, OrderSide::Sell, 100, 1).await;
+
+    let delay = Duration::from_millis(150);
+    exchange.state.faults.delay("upsert_position", delay);
+    let started = Instant::now();
+    place(&exchange, &taker, OrderSide::Buy, 100, 1).await;
+    // Both sides' positions are written before the order is acknowledged
+    assert!(started.elapsed() >= delay * 2);
+    let histograms = persistence::query_histograms();
+    assert!(histograms["upsert_position"].max_us >= delay.as_micros() as u64);
+    assert_eq!(count(&pool, "positions").await, 2);
+
+    exchange.state.faults.clear();
+    let started = Instant::now();
+    place(&exchange, &maker, OrderSide::Sell, 100, 1).await;
+    place(&exchange, &taker, OrderSide::Buy, 100, 1).await;
+    assert!(started.elapsed() < delay * 2);
+}
+
+#[tokio::test]
+async fn an_induced_lag_resyncs_the_socket() {
+    let exchange = TestExchange::start().await;
+    let maker = exchange.register("maker", "secret").await;
+    place(&exchange, &maker, OrderSide::Sell, 101, 2).await;
+    let mut ws = exchange.ws_client(None).await;
+    ws.subscribe(TEST_SYMBOL).await;
+
+    exchange.state.faults.lag_ws(7);
+    place(&exchange, &maker, OrderSide::Buy, 99, 1).await;
+    let notice = ws.next_json().await;
+    assert_eq!(notice, json!({ "type": "Lagged", "skipped": 7 }));
+    // The snapshot replaces the lost update, so the book is whole again
+    let snapshot = ws.next_json().await;
+    assert_eq!(snapshot["type"], "OrderBookUpdate");
+    assert_eq!(snapshot["bids"], json!([[99, 1]]));
+    assert_eq!(snapshot["asks"], json!([[101, 2]]));
+    assert_eq!(exchange.state.ws_connections.totals().lag_events, 1);
+
+    // Only one delivery was affected
+    place(&exchange, &maker, OrderSide::Buy, 98, 1).await;
+    assert_eq!(ws.next_json().await["bids"], json!([[99, 1], [98, 1]]));
+}
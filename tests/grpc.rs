@@ -0,0 +1,239 @@
+//! The gRPC service beside the HTTP API: a tonic client authenticated with the HTTP API's JWT
+//! places and cancels orders, reads the book and follows trades and book changes on the
+//! server streams.
+
+use rust_exchange::grpc::{self, proto};
+use rust_exchange::testing::{TEST_SYMBOL, TestExchange, Token};
+use std::future;
+use std::time::Duration;
+use tonic::transport::Channel;
+use tonic::{Code, Request, Streaming};
+
+type Client = proto::exchange_client::ExchangeClient<Channel>;
+
+/// A gRPC client of a server over `exchange`'s state.
+async fn connect(exchange: &TestExchange) -> Client {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(grpc::serve(
+        exchange.state.clone(),
+        listener,
+        future::pending(),
+    ));
+    Client::connect(format!("http://{}", addr)).await.unwrap()
+}
+
+fn authorized<T>(token: &Token, message: T) -> Request<T> {
+    let mut request = Request::new(message);
+    request.metadata_mut().insert(
+        "authorization",
+        format!("Bearer {}", token.token).parse().unwrap(),
+    );
+    request
+}
+
+fn limit(side: proto::Side, price: i64, quantity: u64) -> proto::PlaceOrderRequest {
+    let mut order = proto::PlaceOrderRequest {
+        symbol: TEST_SYMBOL.to_string(),
+        price,
+        quantity,
+        ..Default::default()
+    };
+    order.set_side(side);
+    order
+}
+
+async fn next<T>(stream: &mut Streaming<T>) -> T {
+    tokio::time::timeout(Duration::from_secs(5), stream.message())
+        .await
+        .expect("no message on the stream")
+        .unwrap()
+        .expect("the stream ended")
+}
+
+#[tokio::test]
+async fn an_order_placed_over_grpc_trades_onto_the_stream() {
+    let exchange = TestExchange::start().await;
+    let maker = exchange.register("maker", "secret").await;
+    let taker = exchange.register("taker", "secret").await;
+    let mut client = connect(&exchange).await;
+    let mut trades = client
+        .subscribe_trades(proto::SubscribeRequest {
+            symbol: TEST_SYMBOL.to_lowercase(),
+        })
+        .await
+        .unwrap()
+        .into_inner();
+
+    let ask = client
+        .place_order(authorized(&maker, limit(proto::Side::Sell, 101, 3)))
+        .await
+        .unwrap()
+        .into_inner();
+    let ask = ask.order.unwrap();
+    assert_eq!(ask.user_id, maker.user_id.to_string());
+    assert_eq!(ask.status(), proto::OrderStatus::Pending);
+
+    let report = client
+        .place_order(authorized(&taker, limit(proto::Side::Buy, 101, 2)))
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(report.symbol, TEST_SYMBOL);
+    assert_eq!(report.order.unwrap().status(), proto::OrderStatus::Filled);
+    assert_eq!(report.trades.len(), 1);
+
+    let trade = next(&mut trades).await;
+    assert_eq!(trade, report.trades[0]);
+    assert_eq!(trade.symbol, TEST_SYMBOL);
+    assert_eq!(trade.maker_order_id, ask.id);
+    assert_eq!(trade.taker_user_id, taker.user_id.to_string());
+    assert_eq!((trade.price, trade.quantity), (101, 2));
+
+    // The HTTP API sees the same book
+    let book = client
+        .get_book(proto::GetBookRequest {
+            symbol: TEST_SYMBOL.to_string(),
+        })
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(
+        book.asks,
+        vec![proto::Level {
+            price: 101,
+            quantity: 1
+        }]
+    );
+    assert!(book.bids.is_empty());
+    let url = exchange.url(&format!("/books?symbols={}", TEST_SYMBOL));
+    let http = exchange
+        .send_json(exchange.client().get(url))
+        .await
+        .unwrap();
+    assert_eq!(http["books"][TEST_SYMBOL]["seq"], book.seq);
+    assert_eq!(http["books"][TEST_SYMBOL]["checksum"], book.checksum);
+
+    let cancelled = client
+        .cancel_order(authorized(
+            &maker,
+            proto::CancelOrderRequest {
+                order_id: ask.id.clone(),
+            },
+        ))
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(cancelled.remaining_quantity, 1);
+    assert_eq!(
+        cancelled.order.unwrap().status(),
+        proto::OrderStatus::Cancelled
+    );
+}
+
+#[tokio::test]
+async fn the_book_stream_starts_with_the_book_and_follows_each_change() {
+    let exchange = TestExchange::start().await;
+    let maker = exchange.register("maker", "secret").await;
+    let mut client = connect(&exchange).await;
+    client
+        .place_order(authorized(&maker, limit(proto::Side::Buy, 99, 4)))
+        .await
+        .unwrap();
+
+    let mut books = client
+        .subscribe_book(proto::SubscribeRequest {
+            symbol: TEST_SYMBOL.to_string(),
+        })
+        .await
+        .unwrap()
+        .into_inner();
+    let first = next(&mut books).await;
+    assert_eq!(
+        first.bids,
+        vec![proto::Level {
+            price: 99,
+            quantity: 4
+        }]
+    );
+    assert!(first.asks.is_empty());
+
+    client
+        .place_order(authorized(&maker, limit(proto::Side::Sell, 102, 1)))
+        .await
+        .unwrap();
+    let second = next(&mut books).await;
+    assert!(second.seq > first.seq);
+    assert_eq!(second.bids, first.bids);
+    assert_eq!(
+        second.asks,
+        vec![proto::Level {
+            price: 102,
+            quantity: 1
+        }]
+    );
+    let snapshot = exchange.state.orderbooks[TEST_SYMBOL]
+        .read()
+        .await
+        .snapshot(usize::MAX);
+    assert_eq!(
+        (second.seq, second.checksum),
+        (snapshot.seq, snapshot.checksum)
+    );
+}
+
+#[tokio::test]
+async fn order_entry_needs_a_valid_token_and_reports_errors_as_grpc_codes() {
+    let exchange = TestExchange::start().await;
+    let user = exchange.register("user", "secret").await;
+    let mut client = connect(&exchange).await;
+
+    let err = client
+        .place_order(limit(proto::Side::Buy, 100, 1))
+        .await
+        .unwrap_err();
+    assert_eq!(err.code(), Code::Unauthenticated);
+    let forged = Token {
+        token: format!("{}x", user.token),
+        ..user.clone()
+    };
+    let err = client
+        .place_order(authorized(&forged, limit(proto::Side::Buy, 100, 1)))
+        .await
+        .unwrap_err();
+    assert_eq!(err.code(), Code::Unauthenticated);
+
+    let err = client
+        .place_order(authorized(&user, limit(proto::Side::Unspecified, 100, 1)))
+        .await
+        .unwrap_err();
+    assert_eq!(err.code(), Code::InvalidArgument);
+
+    // A market order with nothing to match is rejected with the HTTP API's error code
+    let mut market = limit(proto::Side::Buy, 0, 1);
+    market.set_order_type(proto::OrderType::Market);
+    let err = client
+        .place_order(authorized(&user, market))
+        .await
+        .unwrap_err();
+    assert_eq!(err.code(), Code::InvalidArgument);
+    assert_eq!(err.metadata().get("error-code").unwrap(), "NO_LIQUIDITY");
+
+    let err = client
+        .get_book(proto::GetBookRequest {
+            symbol: "DOGEUSDT".to_string(),
+        })
+        .await
+        .unwrap_err();
+    assert_eq!(err.code(), Code::NotFound);
+    let err = client
+        .cancel_order(authorized(
+            &user,
+            proto::CancelOrderRequest {
+                order_id: uuid::Uuid::new_v4().to_string(),
+            },
+        ))
+        .await
+        .unwrap_err();
+    assert_eq!(err.code(), Code::NotFound);
+}
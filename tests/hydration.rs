@@ -0,0 +1,162 @@
+use chrono::{DateTime, Duration, Utc};
+use rust_exchange::hydration::{HydrationReport, restore_orders};
+use rust_exchange::orderbook::orderbook::OrderBook;
+use rust_exchange::persistence::OrderRow;
+use rust_exchange::skew::{SkewPolicy, SkewWindow};
+use rust_exchange::types::order::OrderSource;
+use sqlx::types::Json;
+use uuid::Uuid;
+
+fn row(side: &str, order_type: &str, status: &str, quantity: i64) -> OrderRow {
+    row_from(side, order_type, status, quantity, "Api")
+}
+
+fn row_from(side: &str, order_type: &str, status: &str, quantity: i64, source: &str) -> OrderRow {
+    OrderRow {
+        id: Uuid::new_v4(),
+        user_id: Uuid::new_v4(),
+        symbol: "BTCUSDT".to_string(),
+        side: side.to_string(),
+        order_type: order_type.to_string(),
+        price: 100,
+        quantity,
+        status: status.to_string(),
+        created_at: Utc::now(),
+        tags: Json(Default::default()),
+        source: source.to_string(),
+        close_reason: None,
+    }
+}
+
+fn restore(book: &mut OrderBook, rows: &[OrderRow], report: &mut HydrationReport) -> usize {
+    restore_orders(book, rows, &SkewWindow::default(), Utc::now(), report)
+}
+
+fn created_at(at: DateTime<Utc>) -> OrderRow {
+    OrderRow {
+        created_at: at,
+        ..row("Sell", "Limit", "Pending", 1)
+    }
+}
+
+#[test]
+fn restore_orders_skips_malformed_rows_with_reasons() {
+    let rows = vec![
+        row("Buy", "Limit", "Pending", 5),
+        row("Sell", "Limit", "PartiallyFilled", 2),
+        row("Up", "Limit", "Pending", 1),
+        row("Buy", "Limit", "Bogus", 1),
+        row("Buy", "Limit", "Filled", 1),
+        row("Buy", "Market", "Pending", 1),
+        row("Sell", "Limit", "Pending", 0),
+        row_from("Buy", "Limit", "Pending", 1, "Client"),
+    ];
+    let mut book = OrderBook::new();
+    let mut report = HydrationReport::default();
+
+    let restored = restore(&mut book, &rows, &mut report);
+
+    assert_eq!(restored, 2);
+    assert_eq!(book.get_bids(), vec![(100, 5)]);
+    assert_eq!(book.get_asks(), vec![(100, 2)]);
+    assert!(!report.is_clean());
+    let reasons: Vec<&str> = report
+        .rows_skipped
+        .iter()
+        .map(|r| r.reason.as_str())
+        .collect();
+    assert_eq!(
+        reasons,
+        vec![
+            "invalid side 'Up'",
+            "invalid status 'Bogus'",
+            "status 'Filled' is not open",
+            "market orders cannot rest in the book",
+            "invalid quantity 0",
+            "invalid source 'Client'",
+        ]
+    );
+    assert_eq!(report.rows_skipped[0].id, rows[2].id);
+    assert_eq!(report.rows_skipped[0].table, "orders");
+}
+
+#[test]
+fn restored_orders_keep_their_source() {
+    let rows = vec![row_from("Buy", "Limit", "Pending", 1, "Spread")];
+    let mut book = OrderBook::new();
+    let mut report = HydrationReport::default();
+    restore(&mut book, &rows, &mut report);
+    let order = book.get_order_by_id(rows[0].id).unwrap();
+    assert_eq!(order.source, OrderSource::Spread);
+}
+
+#[test]
+fn empty_report_is_clean() {
+    let mut book = OrderBook::new();
+    let mut report = HydrationReport::default();
+    let rows = vec![row("Buy", "Limit", "Pending", 1)];
+    restore(&mut book, &rows, &mut report);
+    assert!(report.is_clean());
+}
+
+#[test]
+fn skewed_creation_times_are_flagged_by_policy() {
+    let now = Utc::now();
+    let rows = vec![
+        created_at(now - Duration::days(400)),
+        created_at(now),
+        created_at(now + Duration::hours(1)),
+    ];
+    let run = |policy| {
+        let skew = SkewWindow {
+            policy,
+            ..SkewWindow::default()
+        };
+        let mut book = OrderBook::new();
+        let mut report = HydrationReport::default();
+        let restored = restore_orders(&mut book, &rows, &skew, now, &mut report);
+        (book, report, restored)
+    };
+    let timestamp =
+        |book: &OrderBook, row: &OrderRow| book.get_order_by_id(row.id).unwrap().timestamp;
+
+    let (book, report, restored) = run(SkewPolicy::AcceptWithWarning);
+    assert_eq!(restored, 3);
+    assert!(report.is_clean());
+    let flagged: Vec<_> = report.timestamps_skewed.iter().map(|r| r.id).collect();
+    assert_eq!(flagged, vec![rows[0].id, rows[2].id]);
+    assert!(
+        report.timestamps_skewed[0]
+            .warning
+            .contains("more than 365 days old")
+    );
+    assert_eq!(timestamp(&book, &rows[2]), rows[2].created_at);
+
+    let (book, report, restored) = run(SkewPolicy::Clamp);
+    assert_eq!(restored, 3);
+    assert_eq!(report.timestamps_skewed.len(), 2);
+    assert!(report.timestamps_skewed[1].warning.contains("clamped to"));
+    assert_eq!(timestamp(&book, &rows[0]), now - Duration::days(365));
+    assert_eq!(timestamp(&book, &rows[1]), rows[1].created_at);
+    assert_eq!(timestamp(&book, &rows[2]), now + Duration::minutes(5));
+
+    let (book, report, restored) = run(SkewPolicy::Reject);
+    assert_eq!(restored, 1);
+    assert!(report.timestamps_skewed.is_empty());
+    let skipped: Vec<_> = report.rows_skipped.iter().map(|r| r.id).collect();
+    assert_eq!(skipped, vec![rows[0].id, rows[2].id]);
+    assert!(report.rows_skipped[1].reason.contains("in the future"));
+    assert!(book.get_order_by_id(rows[1].id).is_some());
+}
+
+#[test]
+fn skew_policies_parse_by_name() {
+    assert_eq!(SkewPolicy::parse("Reject"), Some(SkewPolicy::Reject));
+    assert_eq!(SkewPolicy::parse("clamp"), Some(SkewPolicy::Clamp));
+    assert_eq!(
+        SkewPolicy::parse("accept-with-warning"),
+        Some(SkewPolicy::AcceptWithWarning)
+    );
+    assert_eq!(SkewPolicy::parse("ignore"), None);
+    assert_eq!(SkewPolicy::default().to_string(), "accept-with-warning");
+}
@@ -0,0 +1,190 @@
+//! A panic inside matching halts its symbol and quarantines the book; other symbols keep
+//! trading. The panic is triggered by an order tagged with `incidents::PANIC_TAG`.
+
+use rust_exchange::api::incidents::{Incidents, PANIC_TAG};
+use rust_exchange::snapshot;
+use rust_exchange::testing::{OrderRequest, TEST_SYMBOL, TestExchange, Token};
+use rust_exchange::types::order::{OrderSide, RejectReason};
+use serde_json::{Value, json};
+use std::sync::Arc;
+
+const OTHER_SYMBOL: &str = "ETHUSDT";
+
+/// A buy on `TEST_SYMBOL` that panics in matching once it has taken liquidity.
+async fn place_poisoned(exchange: &TestExchange, token: &Token) -> Value {
+    let err = exchange
+        .send_json(
+            exchange
+                .client()
+                .post(exchange.url("/orders"))
+                .bearer_auth(&token.token)
+                .json(&json!({
+                    "symbol": TEST_SYMBOL,
+                    "side": "Buy",
+                    "price": 101,
+                    "quantity": 1,
+                    "tags": { PANIC_TAG: "1" },
+                })),
+        )
+        .await
+        .unwrap_err();
+    assert_eq!(err.status.as_u16(), 500, "{}", err.body);
+    err.body
+}
+
+#[tokio::test]
+async fn matching_panic_halts_only_its_symbol() {
+    let exchange = TestExchange::builder().symbol(OTHER_SYMBOL).start().await;
+    let maker = exchange.register("maker", "secret").await;
+    let taker = exchange.register("taker", "secret").await;
+    let mut ws = exchange.ws_client(None).await;
+    for symbol in [TEST_SYMBOL, OTHER_SYMBOL] {
+        exchange
+            .place_order(
+                &maker,
+                &OrderRequest::limit(symbol, OrderSide::Sell, 101, 2),
+            )
+            .await
+            .unwrap();
+    }
+
+    let body = place_poisoned(&exchange, &taker).await;
+    assert_eq!(body["error_code"], "MATCHING_INCIDENT");
+    let incidents = exchange.state.incidents.recent();
+    assert_eq!(incidents.len(), 1);
+    let code = incidents[0].code.as_str();
+    assert!(code.starts_with("INC-"), "{}", code);
+    assert!(body["error"].as_str().unwrap().contains(code), "{}", body);
+
+    let announcement = ws.next_of_type("Announcement").await;
+    assert_eq!(announcement["severity"], "critical");
+    assert!(
+        announcement["message"].as_str().unwrap().contains(code),
+        "{}",
+        announcement
+    );
+    let config = exchange.state.symbol_configs.read().await[TEST_SYMBOL];
+    assert!(config.halted);
+    assert_eq!(incidents[0].symbol, TEST_SYMBOL);
+    assert!(!incidents[0].restored_from_snapshot);
+    assert!(
+        exchange
+            .state
+            .incidents
+            .render_prometheus()
+            .contains(&format!(
+                "exchange_matching_panics_total{{symbol=\"{}\"}} 1",
+                TEST_SYMBOL
+            ))
+    );
+
+    // The halted symbol refuses new orders
+    let err = exchange
+        .place_order(
+            &taker,
+            &OrderRequest::limit(TEST_SYMBOL, OrderSide::Buy, 101, 1),
+        )
+        .await
+        .unwrap_err();
+    assert_eq!(err.rejection(), Some(RejectReason::SymbolHalted));
+
+    // The other symbol still matches
+    let order = exchange
+        .place_order(
+            &taker,
+            &OrderRequest::limit(OTHER_SYMBOL, OrderSide::Buy, 101, 1),
+        )
+        .await
+        .unwrap()
+        .order;
+    assert_eq!(order.quantity, 0);
+    assert_eq!(exchange.book(OTHER_SYMBOL).await.asks, vec![(101, 1)]);
+}
+
+#[tokio::test]
+async fn quarantined_book_is_restored_from_the_snapshot() {
+    let dir = std::env::temp_dir().join(format!("incidents-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("exchange.snapshot");
+    let snapshot_path = path.clone();
+    let exchange = TestExchange::builder()
+        .with_state(|state| state.incidents = Arc::new(Incidents::new(Some(snapshot_path))))
+        .start()
+        .await;
+    let maker = exchange.register("maker", "secret").await;
+    let taker = exchange.register("taker", "secret").await;
+    exchange
+        .place_order(
+            &maker,
+            &OrderRequest::limit(TEST_SYMBOL, OrderSide::Sell, 101, 2),
+        )
+        .await
+        .unwrap();
+    snapshot::save(&exchange.state, &path).await.unwrap();
+    exchange
+        .place_order(
+            &maker,
+            &OrderRequest::limit(TEST_SYMBOL, OrderSide::Sell, 102, 1),
+        )
+        .await
+        .unwrap();
+
+    place_poisoned(&exchange, &taker).await;
+    // The panic struck after the fill; the book is back to the snapshot, still halted
+    assert_eq!(exchange.book(TEST_SYMBOL).await.asks, vec![(101, 2)]);
+    assert!(exchange.state.incidents.recent()[0].restored_from_snapshot);
+    assert!(exchange.state.symbol_configs.read().await[TEST_SYMBOL].halted);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn a_panic_while_replacing_quarantines_the_symbol() {
+    let exchange = TestExchange::start().await;
+    let maker = exchange.register("maker", "secret").await;
+    let taker = exchange.register("taker", "secret").await;
+    exchange
+        .place_order(
+            &maker,
+            &OrderRequest::limit(TEST_SYMBOL, OrderSide::Sell, 101, 2),
+        )
+        .await
+        .unwrap();
+    // The tagged bid rests untouched until its replacement crosses the spread
+    let bid = exchange
+        .send_json(
+            exchange
+                .client()
+                .post(exchange.url("/orders"))
+                .bearer_auth(&taker.token)
+                .json(&json!({
+                    "symbol": TEST_SYMBOL,
+                    "side": "Buy",
+                    "price": 99,
+                    "quantity": 1,
+                    "tags": { PANIC_TAG: "1" },
+                })),
+        )
+        .await
+        .unwrap();
+
+    let err = exchange
+        .send_json(
+            exchange
+                .client()
+                .post(exchange.url(&format!(
+                    "/orders/{}/replace?symbol={}",
+                    bid["id"].as_str().unwrap(),
+                    TEST_SYMBOL
+                )))
+                .bearer_auth(&taker.token)
+                .json(&json!({ "price": 101, "quantity": 1 })),
+        )
+        .await
+        .unwrap_err();
+    assert_eq!(err.status.as_u16(), 500, "{}", err.body);
+    assert_eq!(err.body["error_code"], "MATCHING_INCIDENT");
+    let incidents = exchange.state.incidents.recent();
+    assert_eq!(incidents.len(), 1);
+    assert_eq!(incidents[0].symbol, TEST_SYMBOL);
+    assert!(exchange.state.symbol_configs.read().await[TEST_SYMBOL].halted);
+}
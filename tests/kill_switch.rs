@@ -0,0 +1,176 @@
+//! The account kill switch: engaging it cancels the user's orders and refuses new ones until
+//! they disable it with their password, and the flag survives a restart from a snapshot.
+
+use std::sync::Arc;
+
+use rust_exchange::api::kill_switch::KillSwitches;
+use rust_exchange::snapshot;
+use rust_exchange::testing::{
+    ApiError, OrderRequest, TEST_SYMBOL, TestExchange, Token, test_app_state,
+};
+use rust_exchange::types::order::OrderSide;
+use serde_json::{Value, json};
+use tokio::sync::RwLock;
+
+async fn post(
+    exchange: &TestExchange,
+    token: &Token,
+    path: &str,
+    body: Value,
+) -> Result<Value, ApiError> {
+    exchange
+        .send_json(
+            exchange
+                .client()
+                .post(exchange.url(path))
+                .bearer_auth(&token.token)
+                .json(&body),
+        )
+        .await
+}
+
+async fn engage(exchange: &TestExchange, token: &Token) -> Value {
+    post(exchange, token, "/account/kill-switch", json!({}))
+        .await
+        .unwrap()
+}
+
+async fn disable(
+    exchange: &TestExchange,
+    token: &Token,
+    password: &str,
+) -> Result<Value, ApiError> {
+    post(
+        exchange,
+        token,
+        "/account/kill-switch/disable",
+        json!({ "password": password }),
+    )
+    .await
+}
+
+async fn rest(
+    exchange: &TestExchange,
+    token: &Token,
+    side: OrderSide,
+    price: i64,
+) -> Result<String, ApiError> {
+    let report = exchange
+        .place_order(token, &OrderRequest::limit(TEST_SYMBOL, side, price, 1))
+        .await?;
+    Ok(report.order.id.to_string())
+}
+
+#[tokio::test]
+async fn engaging_cancels_and_blocks_until_disabled_with_the_password() {
+    let exchange = TestExchange::start().await;
+    let trader = exchange.register("trader", "secret").await;
+    let other = exchange.register("other", "secret").await;
+    rest(&exchange, &trader, OrderSide::Buy, 900).await.unwrap();
+    rest(&exchange, &trader, OrderSide::Sell, 1_100)
+        .await
+        .unwrap();
+    rest(&exchange, &other, OrderSide::Sell, 1_200)
+        .await
+        .unwrap();
+    let mut ws = exchange.ws_client(Some(&trader)).await;
+
+    let engaged = engage(&exchange, &trader).await;
+    assert_eq!(engaged["trading_disabled"], true);
+    let cancelled = engaged["cancelled"].as_array().unwrap();
+    assert_eq!(cancelled.len(), 2);
+    for order in cancelled {
+        assert_eq!(order["symbol"], TEST_SYMBOL);
+        assert_eq!(order["close_reason"], "KILL_SWITCH");
+    }
+    for _ in 0..2 {
+        let closed = ws.next_of_type("OrderClosed").await;
+        assert_eq!(closed["reason"], "KILL_SWITCH");
+    }
+    let notice = ws.next_of_type("KillSwitch").await;
+    assert_eq!(notice["trading_disabled"], true);
+    assert_eq!(notice["cancelled_orders"], 2);
+    // Only the other user's order is left
+    let book = exchange.book(TEST_SYMBOL).await;
+    assert!(book.bids.is_empty());
+    assert_eq!(book.asks, vec![(1_200, 1)]);
+
+    let refused = rest(&exchange, &trader, OrderSide::Buy, 900)
+        .await
+        .unwrap_err();
+    assert_eq!(refused.status, 403);
+    assert_eq!(refused.body["error_code"], "TRADING_DISABLED");
+    // Reads keep working; nothing of theirs rests
+    let mine = exchange
+        .get(&trader, &format!("/orders/me?symbol={}", TEST_SYMBOL))
+        .await
+        .unwrap();
+    assert!(mine["items"].as_array().unwrap().is_empty());
+
+    let wrong = disable(&exchange, &trader, "guess").await.unwrap_err();
+    assert_eq!(wrong.status, 401);
+    assert!(rest(&exchange, &trader, OrderSide::Buy, 900).await.is_err());
+
+    let disabled = disable(&exchange, &trader, "secret").await.unwrap();
+    assert_eq!(disabled["trading_disabled"], false);
+    assert_eq!(
+        ws.next_of_type("KillSwitch").await["trading_disabled"],
+        false
+    );
+    rest(&exchange, &trader, OrderSide::Buy, 900).await.unwrap();
+}
+
+#[tokio::test]
+async fn cancels_go_through_and_replacements_are_refused_while_engaged() {
+    let exchange = TestExchange::start().await;
+    let trader = exchange.register("trader", "secret").await;
+    let order_id = rest(&exchange, &trader, OrderSide::Buy, 900).await.unwrap();
+    let kept = rest(&exchange, &trader, OrderSide::Buy, 800).await.unwrap();
+    // Engaged without the cancel pass, so both orders still rest
+    exchange.state.kill_switches.set(trader.user_id, true);
+
+    let replaced = post(
+        &exchange,
+        &trader,
+        &format!("/orders/{}/replace?symbol={}", order_id, TEST_SYMBOL),
+        json!({ "price": 950, "quantity": 1 }),
+    )
+    .await
+    .unwrap_err();
+    assert_eq!(replaced.status, 403);
+    assert_eq!(replaced.body["error_code"], "TRADING_DISABLED");
+
+    let cancelled = exchange
+        .cancel_order(&trader, TEST_SYMBOL, order_id.parse().unwrap())
+        .await
+        .unwrap();
+    assert_eq!(cancelled["close_reason"], "USER_CANCELLED");
+    assert_eq!(exchange.book(TEST_SYMBOL).await.bids, vec![(800, 1)]);
+    let engaged = engage(&exchange, &trader).await;
+    assert_eq!(engaged["cancelled"][0]["id"], kept);
+}
+
+#[tokio::test]
+async fn the_switch_survives_a_restart_from_a_snapshot() {
+    let exchange = TestExchange::start().await;
+    let trader = exchange.register("trader", "secret").await;
+    engage(&exchange, &trader).await;
+    let saved = snapshot::capture(&exchange.state).await;
+    assert!(saved.users[0].trading_disabled);
+
+    let hydrated = snapshot::restore(saved, &[TEST_SYMBOL]);
+    let mut state = test_app_state();
+    state.user_store = Arc::new(RwLock::new(hydrated.users));
+    state.kill_switches = Arc::new(KillSwitches::new(hydrated.trading_disabled));
+    let restarted = TestExchange::builder_with_state(state).start().await;
+    let trader = restarted.login("trader", "secret").await.unwrap();
+    let refused = rest(&restarted, &trader, OrderSide::Buy, 900)
+        .await
+        .unwrap_err();
+    assert_eq!(refused.body["error_code"], "TRADING_DISABLED");
+
+    disable(&restarted, &trader, "secret").await.unwrap();
+    rest(&restarted, &trader, OrderSide::Buy, 900)
+        .await
+        .unwrap();
+}
@@ -0,0 +1,56 @@
+//! The in-memory market data store fed trades out of time order.
+
+use chrono::{DateTime, Duration, Utc};
+use rust_exchange::market_data::MarketDataStore;
+use rust_exchange::types::trade::Trade;
+use uuid::Uuid;
+
+fn trade(timestamp: DateTime<Utc>, price: i64) -> Trade {
+    Trade {
+        id: Uuid::new_v4(),
+        trade_seq: 0,
+        maker_order_id: Uuid::new_v4(),
+        taker_order_id: Uuid::new_v4(),
+        maker_user_id: Uuid::new_v4(),
+        taker_user_id: Uuid::new_v4(),
+        price,
+        quantity: 1,
+        timestamp,
+        maker_queue_rank: None,
+    }
+}
+
+#[test]
+fn late_and_early_trades_keep_candles_and_ticker_in_time_order() {
+    let t0: DateTime<Utc> = "2026-01-01T12:00:30Z".parse().unwrap();
+    let store = MarketDataStore::new(std::time::Duration::from_secs(60 * 60));
+    let at = |minutes| t0 + Duration::minutes(minutes);
+
+    store.record_trade("BTCUSDT", &trade(t0, 100));
+    store.record_trade("BTCUSDT", &trade(at(2), 110));
+    // Late: inside the history but behind the current candle
+    assert_eq!(store.record_trade("BTCUSDT", &trade(at(1), 105)), None);
+    // Far behind the history and the rolling window
+    assert_eq!(
+        store.record_trade("BTCUSDT", &trade(t0 - Duration::days(3), 1)),
+        None
+    );
+
+    let candles = store.recent_candles("BTCUSDT", at(2));
+    let opens: Vec<_> = candles.iter().map(|c| c.open_time - t0).collect();
+    assert_eq!(opens, vec![Duration::seconds(-30), Duration::seconds(90)]);
+    assert_eq!(candles[1].close, 110);
+
+    let points = store.ticker_history("BTCUSDT", t0 - Duration::days(7));
+    let prices: Vec<_> = points.iter().map(|p| p.price).collect();
+    assert_eq!(prices, vec![100, 105, 110]);
+    assert!(points.windows(2).all(|w| w[0].timestamp <= w[1].timestamp));
+
+    let stats = store.rolling_stats("BTCUSDT", at(2));
+    assert_eq!(stats.trade_count, 3);
+    assert_eq!((stats.low, stats.high), (Some(100), Some(110)));
+    // The trade from before the window was dropped, not kept as a bucket
+    let stats = store.rolling_stats("BTCUSDT", t0 - Duration::days(3));
+    assert_eq!(stats.trade_count, 3);
+    assert_eq!(stats.low, Some(100));
+}
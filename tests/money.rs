@@ -0,0 +1,123 @@
+//! Money math: notional products, basis points and checked conversions at the extremes.
+
+use proptest::prelude::*;
+use rust_exchange::types::money::{
+    MoneyError, Notional, apply_bps, average_price, notional, signed_notional,
+};
+
+#[test]
+fn products_at_the_extremes_are_exact() {
+    let max = notional(i64::MAX, u64::MAX);
+    assert_eq!(max.raw(), i64::MAX as i128 * u64::MAX as i128);
+    let min = notional(i64::MIN, u64::MAX);
+    assert_eq!(min.raw(), i64::MIN as i128 * u64::MAX as i128);
+    assert_eq!(notional(i64::MAX, 0), Notional::ZERO);
+    assert_eq!(notional(0, u64::MAX), Notional::ZERO);
+    assert_eq!(
+        signed_notional(i64::MIN, i64::MIN).raw(),
+        i64::MIN as i128 * i64::MIN as i128
+    );
+    assert_eq!(max.to_i64(), Err(MoneyError::Overflow));
+    assert_eq!(min.to_i64(), Err(MoneyError::Overflow));
+}
+
+#[test]
+fn conversion_back_to_i64_fails_exactly_past_the_boundary() {
+    assert_eq!(notional(i64::MAX, 1).to_i64(), Ok(i64::MAX));
+    assert_eq!(notional(i64::MIN, 1).to_i64(), Ok(i64::MIN));
+    let one = Notional::from_raw(1);
+    assert_eq!(
+        notional(i64::MAX, 1).checked_add(one).unwrap().to_i64(),
+        Err(MoneyError::Overflow)
+    );
+    assert_eq!(
+        notional(i64::MIN, 1).checked_sub(one).unwrap().to_i64(),
+        Err(MoneyError::Overflow)
+    );
+    // A realistic trade: 100k at 1e8 scale times 1e6 units no longer fits an i64
+    assert_eq!(
+        notional(100_000 * 100_000_000, 1_000_000).to_i64(),
+        Err(MoneyError::Overflow)
+    );
+}
+
+#[test]
+fn sums_report_i128_overflow() {
+    let max = Notional::from_raw(i128::MAX);
+    let min = Notional::from_raw(i128::MIN);
+    assert_eq!(
+        max.checked_add(Notional::from_raw(1)),
+        Err(MoneyError::Overflow)
+    );
+    assert_eq!(
+        min.checked_sub(Notional::from_raw(1)),
+        Err(MoneyError::Overflow)
+    );
+    assert_eq!(max.checked_add(min), Ok(Notional::from_raw(-1)));
+}
+
+#[test]
+fn bps_round_toward_zero_and_keep_their_sign() {
+    let amount = notional(12_345, 1);
+    assert_eq!(apply_bps(amount, 10), Ok(Notional::from_raw(12)));
+    assert_eq!(apply_bps(amount, -10), Ok(Notional::from_raw(-12)));
+    assert_eq!(apply_bps(amount, 10_000), Ok(amount));
+    assert_eq!(apply_bps(amount, 0), Ok(Notional::ZERO));
+    // Any amount takes any fee up to 100%; only larger multipliers can overflow
+    let max = Notional::from_raw(i128::MAX);
+    assert_eq!(apply_bps(max, 10_000), Ok(max));
+    assert_eq!(apply_bps(max, -10_000).unwrap().raw(), -i128::MAX);
+    assert_eq!(apply_bps(max, 20_000), Err(MoneyError::Overflow));
+}
+
+#[test]
+fn averages_check_quantity_and_range() {
+    let total = notional(100, 3).checked_add(notional(130, 1)).unwrap();
+    assert_eq!(average_price(total, 4u64), Ok(107));
+    assert_eq!(average_price(total, 0u64), Err(MoneyError::ZeroQuantity));
+    assert_eq!(average_price(total, -4i64), Ok(-107));
+    let max = notional(i64::MAX, u64::MAX);
+    assert_eq!(average_price(max, u64::MAX), Ok(i64::MAX));
+    assert_eq!(average_price(max, 1u64), Err(MoneyError::Overflow));
+    let min = Notional::from_raw(i128::MIN);
+    assert_eq!(average_price(min, -1i64), Err(MoneyError::Overflow));
+}
+
+proptest! {
+    #[test]
+    fn round_trips_never_truncate(price in any::<i64>(), qty in any::<u64>()) {
+        let amount = notional(price, qty);
+        match amount.to_i64() {
+            Ok(value) => prop_assert_eq!(value as i128, amount.raw()),
+            Err(e) => {
+                prop_assert_eq!(e, MoneyError::Overflow);
+                prop_assert!(amount.raw() > i64::MAX as i128 || amount.raw() < i64::MIN as i128);
+            }
+        }
+        if qty > 0 {
+            prop_assert_eq!(average_price(amount, qty), Ok(price));
+        }
+    }
+
+    #[test]
+    fn bps_match_exact_arithmetic(
+        price in any::<i64>(),
+        qty in any::<u64>(),
+        bps in -10_000i32..=10_000,
+    ) {
+        let amount = notional(price, qty);
+        let fee = apply_bps(amount, bps).unwrap();
+        prop_assert!(fee.raw().abs() <= amount.raw().abs());
+        // Wherever the exact product fits, the fee is it divided and truncated toward zero
+        if let Some(exact) = amount.raw().checked_mul(bps as i128) {
+            prop_assert_eq!(fee.raw(), exact / 10_000);
+        }
+    }
+
+    #[test]
+    fn bps_on_small_amounts_match_exact_arithmetic(raw in any::<i64>(), bps in any::<i32>()) {
+        let exact = raw as i128 * bps as i128;
+        let fee = apply_bps(Notional::from_raw(raw as i128), bps);
+        prop_assert_eq!(fee, Ok(Notional::from_raw(exact / 10_000)));
+    }
+}
@@ -0,0 +1,122 @@
+//! `GET /orders/{id}/fills` without a database: the fills come from the book's trade archive,
+//! each from the order's own side, with their fees and the average fill price.
+
+use rust_exchange::testing::{OrderRequest, TEST_SYMBOL, TestExchange, Token};
+use rust_exchange::types::order::OrderSide;
+use rust_exchange::types::symbol::SymbolConfig;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+use uuid::Uuid;
+
+async fn start() -> TestExchange {
+    let config = SymbolConfig {
+        maker_fee_bps: -2,
+        taker_fee_bps: 10,
+        ..SymbolConfig::for_symbol(TEST_SYMBOL)
+    };
+    TestExchange::builder()
+        .with_state(|state| {
+            state.symbol_configs = Arc::new(RwLock::new(HashMap::from([(
+                TEST_SYMBOL.to_string(),
+                config,
+            )])));
+        })
+        .start()
+        .await
+}
+
+async fn place(
+    exchange: &TestExchange,
+    token: &Token,
+    side: OrderSide,
+    price: i64,
+    quantity: u64,
+) -> Uuid {
+    exchange
+        .place_order(
+            token,
+            &OrderRequest::limit(TEST_SYMBOL, side, price, quantity),
+        )
+        .await
+        .unwrap()
+        .order
+        .id
+}
+
+async fn fills(exchange: &TestExchange, token: &Token, order_id: Uuid) -> Value {
+    exchange
+        .get(
+            token,
+            &format!("/orders/{}/fills?symbol={}", order_id, TEST_SYMBOL),
+        )
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn an_order_filled_across_levels_lists_each_fill_and_the_average_price() {
+    let exchange = start().await;
+    let maker = exchange.register("maker", "secret").await;
+    let taker = exchange.register("taker", "secret").await;
+    let near = place(&exchange, &maker, OrderSide::Sell, 10_000, 2).await;
+    place(&exchange, &maker, OrderSide::Sell, 10_100, 3).await;
+    place(&exchange, &maker, OrderSide::Sell, 10_300, 5).await;
+    let order_id = place(&exchange, &taker, OrderSide::Buy, 10_300, 6).await;
+
+    let body = fills(&exchange, &taker, order_id).await;
+    assert_eq!(body["order_id"], order_id.to_string());
+    let listed = body["fills"].as_array().unwrap();
+    let levels: Vec<(i64, u64)> = listed
+        .iter()
+        .map(|f| {
+            (
+                f["price"].as_i64().unwrap(),
+                f["quantity"].as_u64().unwrap(),
+            )
+        })
+        .collect();
+    assert_eq!(levels, vec![(10_000, 2), (10_100, 3), (10_300, 1)]);
+    for fill in listed {
+        assert_eq!(fill["role"], "Taker");
+        assert!(fill["fee"].as_i64().unwrap() > 0, "{}", fill);
+    }
+    let seqs: Vec<u64> = listed
+        .iter()
+        .map(|f| f["trade_seq"].as_u64().unwrap())
+        .collect();
+    assert_eq!(seqs, vec![1, 2, 3]);
+    assert_eq!(body["filled_quantity"], 6);
+    // (2 * 10,000 + 3 * 10,100 + 10,300) / 6, rounded toward zero
+    assert_eq!(body["average_price"], 10_100);
+    assert!(body["meta"].is_object());
+
+    // The maker's first order sees the same trade from its side, with the rebate
+    let body = fills(&exchange, &maker, near).await;
+    assert_eq!(body["fills"].as_array().unwrap().len(), 1);
+    assert_eq!(body["fills"][0]["role"], "Maker");
+    assert!(body["fills"][0]["fee"].as_i64().unwrap() < 0);
+    assert_eq!(body["average_price"], 10_000);
+}
+
+#[tokio::test]
+async fn an_order_without_fills_has_an_empty_history_and_only_its_owner_sees_it() {
+    let exchange = start().await;
+    let owner = exchange.register("owner", "secret").await;
+    let other = exchange.register("other", "secret").await;
+    let order_id = place(&exchange, &owner, OrderSide::Buy, 9_000, 1).await;
+
+    let body = fills(&exchange, &owner, order_id).await;
+    assert!(body["fills"].as_array().unwrap().is_empty());
+    assert_eq!(body["filled_quantity"], 0);
+    assert_eq!(body["average_price"], Value::Null);
+
+    let path = format!("/orders/{}/fills?symbol={}", order_id, TEST_SYMBOL);
+    assert_eq!(exchange.get(&other, &path).await.unwrap_err().status, 403);
+    let unknown = format!("/orders/{}/fills?symbol={}", Uuid::new_v4(), TEST_SYMBOL);
+    assert_eq!(
+        exchange.get(&owner, &unknown).await.unwrap_err().status,
+        404
+    );
+}
@@ -0,0 +1,238 @@
+//! Orders placed with `refresh_ttl_ms`: refreshing keeps them alive, letting the TTL lapse
+//! cancels them with reason `TTL_EXPIRED`.
+
+use chrono::{DateTime, Utc};
+use rust_exchange::api::expiry;
+use rust_exchange::testing::{OrderRequest, TEST_SYMBOL, TestExchange, Token, WsClient};
+use rust_exchange::types::order::{CloseReason, OrderSide};
+use serde_json::{Value, json};
+use std::time::Duration;
+use uuid::Uuid;
+
+async fn place_quote(exchange: &TestExchange, token: &Token, price: i64, ttl_ms: u64) -> Value {
+    exchange
+        .send_json(
+            exchange
+                .client()
+                .post(exchange.url("/orders"))
+                .bearer_auth(&token.token)
+                .json(&json!({
+                    "symbol": TEST_SYMBOL,
+                    "side": "Sell",
+                    "price": price,
+                    "quantity": 1,
+                    "refresh_ttl_ms": ttl_ms,
+                })),
+        )
+        .await
+        .unwrap()
+}
+
+async fn heartbeat(exchange: &TestExchange, token: &Token, order_id: &Value) -> Value {
+    exchange
+        .send_json(
+            exchange
+                .client()
+                .post(exchange.url(&format!("/orders/{}/heartbeat", order_id.as_str().unwrap())))
+                .bearer_auth(&token.token),
+        )
+        .await
+        .unwrap()
+}
+
+/// Next acknowledgement, skipping pushed messages.
+async fn next_ack(ws: &mut WsClient) -> Value {
+    loop {
+        let message = ws.next_json().await;
+        if message.get("status").is_some() && message.get("type").is_none() {
+            return message;
+        }
+    }
+}
+
+#[tokio::test]
+async fn lapsed_deadlines_cancel_exactly_the_stale_quotes() {
+    let exchange = TestExchange::start().await;
+    let maker = exchange.register("maker", "secret").await;
+    let stale = place_quote(&exchange, &maker, 101, 60_000).await;
+    let fresh = place_quote(&exchange, &maker, 102, 60_000).await;
+    let plain = exchange
+        .place_order(
+            &maker,
+            &OrderRequest::limit(TEST_SYMBOL, OrderSide::Sell, 103, 1),
+        )
+        .await
+        .unwrap()
+        .order;
+    tokio::time::sleep(Duration::from_millis(20)).await;
+
+    let refreshed = heartbeat(&exchange, &maker, &fresh["id"]).await;
+    assert_eq!(refreshed["order_id"], fresh["id"]);
+    let fresh_deadline: DateTime<Utc> =
+        serde_json::from_value(refreshed["expires_at"].clone()).unwrap();
+
+    // Just before the refreshed deadline only the quote that was not refreshed has lapsed
+    let expired = expiry::expire_due(
+        &exchange.state,
+        fresh_deadline - chrono::Duration::milliseconds(1),
+    )
+    .await;
+    let ids: Vec<String> = expired.iter().map(|c| c.order.id.to_string()).collect();
+    assert_eq!(ids, vec![stale["id"].as_str().unwrap()]);
+    assert_eq!(expired[0].close_reason, CloseReason::TtlExpired);
+    assert_eq!(
+        exchange.book(TEST_SYMBOL).await.asks,
+        vec![(102, 1), (103, 1)]
+    );
+
+    let expired = expiry::expire_due(&exchange.state, fresh_deadline).await;
+    assert_eq!(expired.len(), 1);
+    assert_eq!(
+        expired[0].order.id.to_string(),
+        fresh["id"].as_str().unwrap()
+    );
+    // Orders placed without a TTL never expire
+    assert!(
+        expiry::expire_due(&exchange.state, fresh_deadline + chrono::Duration::days(1))
+            .await
+            .is_empty()
+    );
+    assert_eq!(exchange.book(TEST_SYMBOL).await.asks, vec![(103, 1)]);
+    let err = exchange
+        .send_json(
+            exchange
+                .client()
+                .post(exchange.url(&format!("/orders/{}/heartbeat", plain.id)))
+                .bearer_auth(&maker.token),
+        )
+        .await
+        .unwrap_err();
+    assert_eq!(err.status.as_u16(), 404);
+}
+
+#[tokio::test]
+async fn ws_refresh_keeps_a_quote_alive_until_it_stops() {
+    let exchange = TestExchange::start().await;
+    expiry::spawn_sweeper(exchange.state.clone());
+    let maker = exchange.register("maker", "secret").await;
+    let mut ws = exchange.ws_client(Some(&maker)).await;
+    let quote = place_quote(&exchange, &maker, 101, 300).await;
+
+    for _ in 0..5 {
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        ws.send_json(json!({ "action": "refresh", "order_id": quote["id"] }))
+            .await;
+        let ack = next_ack(&mut ws).await;
+        assert_eq!(ack["status"], "success", "{}", ack);
+        assert_eq!(ack["symbol"], TEST_SYMBOL);
+    }
+    assert_eq!(exchange.book(TEST_SYMBOL).await.asks, vec![(101, 1)]);
+
+    let closed = ws.next_of_type("OrderClosed").await;
+    assert_eq!(closed["order_id"], quote["id"]);
+    assert_eq!(closed["reason"], "TTL_EXPIRED");
+    assert!(exchange.book(TEST_SYMBOL).await.asks.is_empty());
+
+    ws.send_json(json!({ "action": "refresh", "order_id": quote["id"] }))
+        .await;
+    assert_eq!(next_ack(&mut ws).await["status"], "error");
+}
+
+#[tokio::test]
+async fn refresh_ttl_is_checked_on_entry_and_refresh_by_owner() {
+    let exchange = TestExchange::start().await;
+    let maker = exchange.register("maker", "secret").await;
+    let other = exchange.register("other", "secret").await;
+
+    for (order_type, ttl) in [("Market", 1_000), ("Limit", 0)] {
+        let err = exchange
+            .send_json(
+                exchange
+                    .client()
+                    .post(exchange.url("/orders"))
+                    .bearer_auth(&maker.token)
+                    .json(&json!({
+                        "symbol": TEST_SYMBOL,
+                        "side": "Buy",
+                        "order_type": order_type,
+                        "price": 100,
+                        "quantity": 1,
+                        "refresh_ttl_ms": ttl,
+                    })),
+            )
+            .await
+            .unwrap_err();
+        assert_eq!(err.status.as_u16(), 400, "{}", err.body);
+    }
+
+    let quote = place_quote(&exchange, &maker, 101, 60_000).await;
+    let err = exchange
+        .send_json(
+            exchange
+                .client()
+                .post(exchange.url(&format!(
+                    "/orders/{}/heartbeat",
+                    quote["id"].as_str().unwrap()
+                )))
+                .bearer_auth(&other.token),
+        )
+        .await
+        .unwrap_err();
+    assert_eq!(err.status.as_u16(), 403);
+
+    // A quote that filled is no longer refreshed
+    exchange
+        .place_order(
+            &other,
+            &OrderRequest::limit(TEST_SYMBOL, OrderSide::Buy, 101, 1),
+        )
+        .await
+        .unwrap();
+    let err = exchange
+        .send_json(
+            exchange
+                .client()
+                .post(exchange.url(&format!(
+                    "/orders/{}/heartbeat",
+                    quote["id"].as_str().unwrap()
+                )))
+                .bearer_auth(&maker.token),
+        )
+        .await
+        .unwrap_err();
+    assert_eq!(err.status.as_u16(), 404);
+}
+
+#[test]
+fn a_restored_deadline_is_due_as_soon_as_it_has_passed() {
+    let deadlines = expiry::OrderExpiry::default();
+    let (user_id, lapsed, later) = (Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4());
+    let now = Utc::now();
+    let ttl = Duration::from_secs(60);
+    deadlines.restore(
+        later,
+        TEST_SYMBOL,
+        user_id,
+        ttl,
+        now + chrono::Duration::minutes(5),
+    );
+    deadlines.restore(
+        lapsed,
+        TEST_SYMBOL,
+        user_id,
+        ttl,
+        now - chrono::Duration::minutes(1),
+    );
+    assert_eq!(
+        deadlines.deadline_of(later),
+        Some((now + chrono::Duration::minutes(5), ttl))
+    );
+
+    // A deadline that passed while the server was down is due at once
+    assert_eq!(
+        deadlines.take_due(now),
+        vec![(TEST_SYMBOL.to_string(), user_id, lapsed)]
+    );
+    assert_eq!(deadlines.deadline_of(lapsed), None);
+    assert!(deadlines.deadline_of(later).is_some());
+}
@@ -1,8 +1,19 @@
 //! Orderbook integration tests: matching engine, lifecycle, edge cases, WebSocket broadcasts.
 
+use chrono::{TimeZone, Utc};
+use proptest::prelude::*;
 use rust_exchange::api::routes::WsMessage;
-use rust_exchange::orderbook::orderbook::OrderBook;
-use rust_exchange::types::order::{OrderSide, OrderStatus, OrderType};
+use rust_exchange::orderbook::clock::ManualClock;
+use rust_exchange::orderbook::order_limits::{CapOverride, OrderLimits, RestingOrderCaps};
+use rust_exchange::orderbook::orderbook::{
+    ExecutionReport, OrderBook, PricingPolicy, ReduceError, TradingPhase,
+};
+use rust_exchange::types::order::{
+    MAX_ORDER_QUANTITY, Order, OrderSide, OrderSource, OrderStatus, OrderType, RejectReason,
+};
+use rust_exchange::types::symbol::SymbolConfig;
+use rust_exchange::types::trade::Trade;
+use std::sync::Arc;
 use std::time::Duration;
 use tokio::sync::broadcast;
 use uuid::Uuid;
@@ -22,7 +33,7 @@ fn no_match_order_rests() {
     let price = scale_price(50_000);
     let qty = 10u64;
 
-    let (order, trades) = book.add_order(
+    let ExecutionReport { order, trades, .. } = book.add_order(
         user_id,
         price,
         qty,
@@ -48,7 +59,11 @@ fn full_fill_buy() {
     let price = scale_price(50_000);
     let qty = 10u64;
 
-    let (sell_order, sell_trades) = book.add_order(
+    let ExecutionReport {
+        order: sell_order,
+        trades: sell_trades,
+        ..
+    } = book.add_order(
         seller,
         price,
         qty,
@@ -60,7 +75,11 @@ fn full_fill_buy() {
     assert!(sell_trades.is_empty());
     assert_eq!(sell_order.quantity, qty);
 
-    let (buy_order, buy_trades) = book.add_order(
+    let ExecutionReport {
+        order: buy_order,
+        trades: buy_trades,
+        ..
+    } = book.add_order(
         buyer,
         price,
         qty,
@@ -87,7 +106,9 @@ fn full_fill_sell() {
     let price = scale_price(50_000);
     let qty = 10u64;
 
-    let (_buy_order, buy_trades) = book.add_order(
+    let ExecutionReport {
+        trades: buy_trades, ..
+    } = book.add_order(
         buyer,
         price,
         qty,
@@ -98,7 +119,11 @@ fn full_fill_sell() {
     );
     assert!(buy_trades.is_empty());
 
-    let (sell_order, sell_trades) = book.add_order(
+    let ExecutionReport {
+        order: sell_order,
+        trades: sell_trades,
+        ..
+    } = book.add_order(
         seller,
         price,
         qty,
@@ -123,7 +148,9 @@ fn partial_fill() {
     let buyer = Uuid::new_v4();
     let price = scale_price(50_000);
 
-    let (sell_order, _) = book.add_order(
+    let ExecutionReport {
+        order: sell_order, ..
+    } = book.add_order(
         seller,
         price,
         10,
@@ -132,7 +159,11 @@ fn partial_fill() {
         None,
         None,
     );
-    let (buy_order, buy_trades) = book.add_order(
+    let ExecutionReport {
+        order: buy_order,
+        trades: buy_trades,
+        ..
+    } = book.add_order(
         buyer,
         price,
         4,
@@ -162,7 +193,7 @@ fn multiple_price_levels_fifo() {
     let buyer = Uuid::new_v4();
     let price = scale_price(50_000);
 
-    let (sell1, _) = book.add_order(
+    let ExecutionReport { order: sell1, .. } = book.add_order(
         user1,
         price,
         2,
@@ -171,7 +202,7 @@ fn multiple_price_levels_fifo() {
         None,
         None,
     );
-    let (sell2, _) = book.add_order(
+    let ExecutionReport { order: sell2, .. } = book.add_order(
         user2,
         price,
         2,
@@ -181,7 +212,11 @@ fn multiple_price_levels_fifo() {
         None,
     );
 
-    let (buy_order, trades) = book.add_order(
+    let ExecutionReport {
+        order: buy_order,
+        trades,
+        ..
+    } = book.add_order(
         buyer,
         price,
         3,
@@ -203,13 +238,59 @@ fn multiple_price_levels_fifo() {
     assert_eq!(asks[0], (price, 1));
 }
 
+#[test]
+fn sweep_trades_share_one_timestamp_and_are_emitted_in_sequence() {
+    let mut book = OrderBook::new();
+    let (tx, mut rx) = broadcast::channel(64);
+    let seller = Uuid::new_v4();
+    for (price, qty) in [(50_000, 1), (50_001, 2), (50_002, 1)] {
+        book.add_order(
+            seller,
+            scale_price(price),
+            qty,
+            OrderSide::Sell,
+            OrderType::Limit,
+            None,
+            None,
+        );
+    }
+
+    let ExecutionReport { trades, .. } = book.add_order(
+        Uuid::new_v4(),
+        scale_price(50_002),
+        4,
+        OrderSide::Buy,
+        OrderType::Limit,
+        Some(&tx),
+        Some(SYMBOL),
+    );
+    assert_eq!(trades.len(), 3);
+    let seqs: Vec<u64> = trades.iter().map(|t| t.trade_seq).collect();
+    assert_eq!(seqs, vec![1, 2, 3]);
+    assert!(trades.iter().all(|t| t.timestamp == trades[0].timestamp));
+
+    let mut emitted = Vec::new();
+    while let Ok(msg) = rx.try_recv() {
+        if let WsMessage::Trade { trade, .. } = msg {
+            emitted.push(trade.trade_seq);
+        }
+    }
+    assert_eq!(emitted, seqs);
+    let recent: Vec<u64> = book
+        .get_recent_trades(10)
+        .iter()
+        .map(|t| t.trade_seq)
+        .collect();
+    assert_eq!(recent, vec![3, 2, 1]);
+}
+
 // --- Order lifecycle ---
 
 #[test]
 fn create_rest_get_order_by_id() {
     let mut book = OrderBook::new();
     let user_id = Uuid::new_v4();
-    let (order, _) = book.add_order(
+    let ExecutionReport { order, .. } = book.add_order(
         user_id,
         scale_price(50_000),
         5,
@@ -225,6 +306,51 @@ fn create_rest_get_order_by_id() {
     assert_eq!(book.get_bids().len(), 1);
 }
 
+#[test]
+fn find_orders_scans_resting_orders_for_config_violations() {
+    let mut book = OrderBook::new();
+    let user_id = Uuid::new_v4();
+    let mut rest = |price: i64, quantity: u64, side: OrderSide| {
+        book.add_order(user_id, price, quantity, side, OrderType::Limit, None, None)
+            .order
+            .id
+    };
+    // A trade at 1,000 anchors the band
+    rest(1_000, 1, OrderSide::Sell);
+    rest(1_000, 1, OrderSide::Buy);
+    let conforming = rest(990, 4, OrderSide::Buy);
+    let off_tick = rest(995, 4, OrderSide::Buy);
+    let outside_band = rest(1_200, 4, OrderSide::Sell);
+    let too_small = rest(1_010, 1, OrderSide::Sell);
+
+    let config = SymbolConfig {
+        tick_size: 10,
+        min_qty: 2,
+        price_band_bps: Some(1_000),
+        // Halting is not the orders' fault
+        halted: true,
+        ..SymbolConfig::default()
+    };
+    let last_price = book.last_trade_price();
+    let found: Vec<(Uuid, Vec<RejectReason>)> = book
+        .find_orders(|order| !config.resting_violations(order, last_price).is_empty())
+        .into_iter()
+        .map(|order| (order.id, config.resting_violations(order, last_price)))
+        .collect();
+    // Bids first, then asks from the best price
+    assert_eq!(
+        found,
+        vec![
+            (off_tick, vec![RejectReason::InvalidTickSize]),
+            (too_small, vec![RejectReason::BelowMinQuantity]),
+            (outside_band, vec![RejectReason::PriceOutsideBand]),
+        ]
+    );
+    assert!(!found.iter().any(|(id, _)| *id == conforming));
+    assert_eq!(book.find_orders(|_| true).len(), 4);
+    assert!(book.find_orders(|order| order.price > 2_000).is_empty());
+}
+
 #[test]
 fn create_match_full_fill_both_filled() {
     let mut book = OrderBook::new();
@@ -233,7 +359,9 @@ fn create_match_full_fill_both_filled() {
     let price = scale_price(50_000);
     let qty = 10u64;
 
-    let (sell_order, _) = book.add_order(
+    let ExecutionReport {
+        order: sell_order, ..
+    } = book.add_order(
         seller,
         price,
         qty,
@@ -242,7 +370,11 @@ fn create_match_full_fill_both_filled() {
         None,
         None,
     );
-    let (buy_order, trades) = book.add_order(
+    let ExecutionReport {
+        order: buy_order,
+        trades,
+        ..
+    } = book.add_order(
         buyer,
         price,
         qty,
@@ -269,7 +401,9 @@ fn create_match_partial_fill_remainder_on_book() {
     let buyer = Uuid::new_v4();
     let price = scale_price(50_000);
 
-    let (sell_order, _) = book.add_order(
+    let ExecutionReport {
+        order: sell_order, ..
+    } = book.add_order(
         seller,
         price,
         10,
@@ -299,7 +433,7 @@ fn create_match_partial_fill_remainder_on_book() {
 fn cancel_removes_order_and_updates_book() {
     let mut book = OrderBook::new();
     let user_id = Uuid::new_v4();
-    let (order, _) = book.add_order(
+    let ExecutionReport { order, .. } = book.add_order(
         user_id,
         scale_price(50_000),
         10,
@@ -315,13 +449,86 @@ fn cancel_removes_order_and_updates_book() {
     assert!(book.get_bids().is_empty());
 }
 
+#[test]
+fn reduce_trims_in_place_and_keeps_time_priority() {
+    let mut book = OrderBook::new();
+    let (first, second) = (Uuid::new_v4(), Uuid::new_v4());
+    let ExecutionReport { order: front, .. } =
+        book.add_order(first, 100, 5, OrderSide::Sell, OrderType::Limit, None, None);
+    rest(&mut book, second, OrderSide::Sell, 100, 4);
+    let seq = book.book_seq();
+
+    let reduced = book.reduce_order(front.id, 2, None, None).unwrap();
+    assert_eq!((reduced.id, reduced.quantity), (front.id, 2));
+    assert_eq!(reduced.timestamp, front.timestamp);
+    assert_eq!(book.get_asks(), vec![(100, 6)]);
+    assert_eq!(book.book_seq(), seq + 1);
+    assert_eq!(book.check_invariants(), Ok(()));
+
+    // The order behind did not move up: a taker for 3 fills the reduced order first
+    let ExecutionReport { trades, .. } = book.add_order(
+        Uuid::new_v4(),
+        100,
+        3,
+        OrderSide::Buy,
+        OrderType::Limit,
+        None,
+        None,
+    );
+    let fills: Vec<(Uuid, u64)> = trades
+        .iter()
+        .map(|t| (t.maker_user_id, t.quantity))
+        .collect();
+    assert_eq!(fills, vec![(first, 2), (second, 1)]);
+}
+
+#[test]
+fn reduce_rejects_growth_zero_and_orders_no_longer_resting() {
+    let mut book = OrderBook::new();
+    let ExecutionReport { order, .. } = book.add_order(
+        Uuid::new_v4(),
+        100,
+        5,
+        OrderSide::Buy,
+        OrderType::Limit,
+        None,
+        None,
+    );
+    let seq = book.book_seq();
+
+    assert_eq!(
+        book.reduce_order(order.id, 6, None, None),
+        Err(ReduceError::InvalidQuantity { remaining: 5 })
+    );
+    assert_eq!(
+        book.reduce_order(order.id, 0, None, None),
+        Err(ReduceError::InvalidQuantity { remaining: 5 })
+    );
+    // Reducing to what is open is a no-op
+    assert_eq!(
+        book.reduce_order(order.id, 5, None, None).unwrap().quantity,
+        5
+    );
+    assert_eq!(book.book_seq(), seq);
+
+    book.remove_order(order.id, None, None);
+    assert_eq!(
+        book.reduce_order(order.id, 1, None, None),
+        Err(ReduceError::NotResting)
+    );
+}
+
 #[test]
 fn no_match_price_gap_both_rest() {
     let mut book = OrderBook::new();
     let buyer = Uuid::new_v4();
     let seller = Uuid::new_v4();
 
-    let (buy_order, buy_trades) = book.add_order(
+    let ExecutionReport {
+        order: buy_order,
+        trades: buy_trades,
+        ..
+    } = book.add_order(
         buyer,
         scale_price(49_000),
         10,
@@ -330,7 +537,11 @@ fn no_match_price_gap_both_rest() {
         None,
         None,
     );
-    let (sell_order, sell_trades) = book.add_order(
+    let ExecutionReport {
+        order: sell_order,
+        trades: sell_trades,
+        ..
+    } = book.add_order(
         seller,
         scale_price(51_000),
         10,
@@ -355,7 +566,9 @@ fn partial_fill_resting_fully_filled_incoming_rests() {
     let buyer = Uuid::new_v4();
     let price = scale_price(50_000);
 
-    let (sell_order, _) = book.add_order(
+    let ExecutionReport {
+        order: sell_order, ..
+    } = book.add_order(
         seller,
         price,
         5,
@@ -364,7 +577,11 @@ fn partial_fill_resting_fully_filled_incoming_rests() {
         None,
         None,
     );
-    let (buy_order, trades) = book.add_order(
+    let ExecutionReport {
+        order: buy_order,
+        trades,
+        ..
+    } = book.add_order(
         buyer,
         price,
         10,
@@ -403,8 +620,11 @@ fn market_buy_with_liquidity_full_fill() {
         None,
         None,
     );
-    let (buy_order, trades) =
-        book.add_order(buyer, 0, qty, OrderSide::Buy, OrderType::Market, None, None);
+    let ExecutionReport {
+        order: buy_order,
+        trades,
+        ..
+    } = book.add_order(buyer, 0, qty, OrderSide::Buy, OrderType::Market, None, None);
 
     assert_eq!(trades.len(), 1);
     assert_eq!(trades[0].price, price);
@@ -430,8 +650,11 @@ fn market_buy_partial_fill_does_not_rest() {
         None,
         None,
     );
-    let (buy_order, trades) =
-        book.add_order(buyer, 0, 10, OrderSide::Buy, OrderType::Market, None, None);
+    let ExecutionReport {
+        order: buy_order,
+        trades,
+        ..
+    } = book.add_order(buyer, 0, 10, OrderSide::Buy, OrderType::Market, None, None);
 
     assert_eq!(trades.len(), 1);
     assert_eq!(trades[0].quantity, 3);
@@ -446,12 +669,16 @@ fn market_buy_no_liquidity() {
     let buyer = Uuid::new_v4();
     let qty = 5u64;
 
-    let (order, trades) =
-        book.add_order(buyer, 0, qty, OrderSide::Buy, OrderType::Market, None, None);
+    let ExecutionReport {
+        order,
+        trades,
+        rejection,
+    } = book.add_order(buyer, 0, qty, OrderSide::Buy, OrderType::Market, None, None);
 
     assert!(trades.is_empty());
+    assert_eq!(rejection, Some(RejectReason::NoLiquidity));
     assert_eq!(order.quantity, qty);
-    assert_eq!(order.status, OrderStatus::Pending);
+    assert_eq!(order.status, OrderStatus::Cancelled);
     assert!(book.get_bids().is_empty());
 }
 
@@ -472,7 +699,11 @@ fn market_sell_with_liquidity_full_fill() {
         None,
         None,
     );
-    let (sell_order, trades) = book.add_order(
+    let ExecutionReport {
+        order: sell_order,
+        trades,
+        ..
+    } = book.add_order(
         seller,
         0,
         qty,
@@ -506,7 +737,11 @@ fn market_sell_partial_fill_does_not_rest() {
         None,
         None,
     );
-    let (sell_order, trades) = book.add_order(
+    let ExecutionReport {
+        order: sell_order,
+        trades,
+        ..
+    } = book.add_order(
         seller,
         0,
         10,
@@ -529,7 +764,11 @@ fn market_sell_no_liquidity() {
     let seller = Uuid::new_v4();
     let qty = 5u64;
 
-    let (order, trades) = book.add_order(
+    let ExecutionReport {
+        order,
+        trades,
+        rejection,
+    } = book.add_order(
         seller,
         0,
         qty,
@@ -540,11 +779,407 @@ fn market_sell_no_liquidity() {
     );
 
     assert!(trades.is_empty());
+    assert_eq!(rejection, Some(RejectReason::NoLiquidity));
     assert_eq!(order.quantity, qty);
-    assert_eq!(order.status, OrderStatus::Pending);
+    assert_eq!(order.status, OrderStatus::Cancelled);
     assert!(book.get_asks().is_empty());
 }
 
+// --- Order preview ---
+
+#[test]
+fn simulate_order_reports_fills_without_mutating_book() {
+    let mut book = OrderBook::new();
+    let seller = Uuid::new_v4();
+    let buyer = Uuid::new_v4();
+
+    book.add_order(
+        seller,
+        scale_price(50_000),
+        5,
+        OrderSide::Sell,
+        OrderType::Limit,
+        None,
+        None,
+    );
+    book.add_order(
+        seller,
+        scale_price(50_100),
+        5,
+        OrderSide::Sell,
+        OrderType::Limit,
+        None,
+        None,
+    );
+    let asks_before = book.get_asks();
+
+    let ExecutionReport { order, trades, .. } = book.simulate_order(
+        buyer,
+        scale_price(50_100),
+        8,
+        OrderSide::Buy,
+        OrderType::Limit,
+    );
+
+    assert_eq!(trades.len(), 2);
+    assert_eq!(trades[0].price, scale_price(50_000));
+    assert_eq!(trades[0].quantity, 5);
+    assert_eq!(trades[1].price, scale_price(50_100));
+    assert_eq!(trades[1].quantity, 3);
+    assert_eq!(order.quantity, 0);
+    assert_eq!(order.status, OrderStatus::Filled);
+
+    assert_eq!(book.get_asks(), asks_before);
+    assert!(book.get_all_trades().is_empty());
+}
+
+#[test]
+fn simulate_order_matches_add_order_outcome() {
+    let mut book = OrderBook::new();
+    let seller = Uuid::new_v4();
+    let buyer = Uuid::new_v4();
+
+    book.add_order(
+        seller,
+        scale_price(50_000),
+        4,
+        OrderSide::Sell,
+        OrderType::Limit,
+        None,
+        None,
+    );
+    book.add_order(
+        seller,
+        scale_price(51_000),
+        4,
+        OrderSide::Sell,
+        OrderType::Limit,
+        None,
+        None,
+    );
+
+    let ExecutionReport {
+        order: preview,
+        trades: preview_trades,
+        ..
+    } = book.simulate_order(
+        buyer,
+        scale_price(50_500),
+        10,
+        OrderSide::Buy,
+        OrderType::Limit,
+    );
+    let ExecutionReport { order, trades, .. } = book.add_order(
+        buyer,
+        scale_price(50_500),
+        10,
+        OrderSide::Buy,
+        OrderType::Limit,
+        None,
+        None,
+    );
+
+    assert_eq!(preview.quantity, order.quantity);
+    assert_eq!(preview.status, order.status);
+    assert_eq!(preview_trades.len(), trades.len());
+    for (p, t) in preview_trades.iter().zip(&trades) {
+        assert_eq!(p.maker_order_id, t.maker_order_id);
+        assert_eq!(p.price, t.price);
+        assert_eq!(p.quantity, t.quantity);
+    }
+}
+
+// --- Pricing policy ---
+
+/// Trade prices when a limit order on `side` at `limit` sweeps two makers at 100 and 104
+/// (asks for a buy) or 110 and 106 (bids for a sell) under `policy`.
+fn sweep_prices(policy: PricingPolicy, side: OrderSide, limit: i64) -> Vec<i64> {
+    let mut book = OrderBook::new();
+    book.set_pricing_policy(policy, 1);
+    let (maker_side, makers) = match side {
+        OrderSide::Buy => (OrderSide::Sell, [100, 104]),
+        OrderSide::Sell => (OrderSide::Buy, [110, 106]),
+    };
+    for price in makers {
+        rest(&mut book, Uuid::new_v4(), maker_side, price, 1);
+    }
+    let preview = book.simulate_order(Uuid::new_v4(), limit, 2, side, OrderType::Limit);
+    let report = book.add_order(Uuid::new_v4(), limit, 2, side, OrderType::Limit, None, None);
+    assert_eq!(preview.trades.len(), report.trades.len());
+    for (previewed, traded) in preview.trades.iter().zip(&report.trades) {
+        assert_eq!(previewed.price, traded.price);
+    }
+    report.trades.iter().map(|t| t.price).collect()
+}
+
+#[test]
+fn maker_price_is_the_default_policy() {
+    assert_eq!(OrderBook::new().pricing_policy(), PricingPolicy::MakerPrice);
+    let mut book = OrderBook::new();
+    rest(&mut book, Uuid::new_v4(), OrderSide::Sell, 100, 1);
+    let report = book.add_order(
+        Uuid::new_v4(),
+        110,
+        1,
+        OrderSide::Buy,
+        OrderType::Limit,
+        None,
+        None,
+    );
+    assert_eq!(report.trades[0].price, 100);
+
+    assert_eq!(
+        sweep_prices(PricingPolicy::MakerPrice, OrderSide::Buy, 110),
+        vec![100, 104]
+    );
+    assert_eq!(
+        sweep_prices(PricingPolicy::MakerPrice, OrderSide::Sell, 100),
+        vec![110, 106]
+    );
+}
+
+#[test]
+fn taker_price_policy_trades_at_the_incoming_limit() {
+    assert_eq!(
+        sweep_prices(PricingPolicy::TakerPrice, OrderSide::Buy, 110),
+        vec![110, 110]
+    );
+    assert_eq!(
+        sweep_prices(PricingPolicy::TakerPrice, OrderSide::Sell, 100),
+        vec![100, 100]
+    );
+}
+
+#[test]
+fn midpoint_policy_splits_the_improvement_rounding_toward_the_maker() {
+    // Buy at 110: (100 + 110) / 2 = 105, then (104 + 110) / 2 = 107
+    assert_eq!(
+        sweep_prices(PricingPolicy::Midpoint, OrderSide::Buy, 110),
+        vec![105, 107]
+    );
+    // Buy at 109 has odd spreads: 104.5 rounds down to 104, 106.5 down to 106
+    assert_eq!(
+        sweep_prices(PricingPolicy::Midpoint, OrderSide::Buy, 109),
+        vec![104, 106]
+    );
+    // Sell at 101: 105.5 rounds up to 106, 103.5 up to 104
+    assert_eq!(
+        sweep_prices(PricingPolicy::Midpoint, OrderSide::Sell, 101),
+        vec![106, 104]
+    );
+
+    let mid =
+        |maker, taker, tick| PricingPolicy::Midpoint.execution_price(maker, Some(taker), tick);
+    assert_eq!(mid(100, 101, 1), 100);
+    assert_eq!(mid(100, 120, 5), 110);
+    assert_eq!(mid(100, 115, 5), 105);
+    assert_eq!(mid(115, 100, 5), 110);
+    assert_eq!(mid(100, 104, 5), 100);
+    assert_eq!(mid(100, 100, 5), 100);
+    assert_eq!(mid(i64::MAX - 1, i64::MAX, 1), i64::MAX - 1);
+    assert_eq!(mid(i64::MIN + 2, i64::MIN, 1), i64::MIN + 1);
+}
+
+#[test]
+fn market_orders_trade_at_the_maker_price_under_any_policy() {
+    for policy in [PricingPolicy::TakerPrice, PricingPolicy::Midpoint] {
+        let mut book = OrderBook::new();
+        book.set_pricing_policy(policy, 1);
+        rest(&mut book, Uuid::new_v4(), OrderSide::Sell, 100, 1);
+        rest(&mut book, Uuid::new_v4(), OrderSide::Buy, 90, 1);
+        let buy = book.add_order(
+            Uuid::new_v4(),
+            0,
+            1,
+            OrderSide::Buy,
+            OrderType::Market,
+            None,
+            None,
+        );
+        assert_eq!(buy.trades[0].price, 100);
+        let sell = book.add_order(
+            Uuid::new_v4(),
+            0,
+            1,
+            OrderSide::Sell,
+            OrderType::Market,
+            None,
+            None,
+        );
+        assert_eq!(sell.trades[0].price, 90);
+        assert_eq!(policy.execution_price(100, None, 1), 100);
+    }
+}
+
+// --- Call auction ---
+
+fn rest(book: &mut OrderBook, user_id: Uuid, side: OrderSide, price: i64, qty: u64) {
+    book.add_order(user_id, price, qty, side, OrderType::Limit, None, None);
+}
+
+#[test]
+fn level_totals_and_auction_volume_saturate_instead_of_wrapping() {
+    let mut book = OrderBook::new();
+    book.start_auction();
+    for _ in 0..3 {
+        rest(
+            &mut book,
+            Uuid::new_v4(),
+            OrderSide::Sell,
+            100,
+            MAX_ORDER_QUANTITY,
+        );
+        rest(
+            &mut book,
+            Uuid::new_v4(),
+            OrderSide::Buy,
+            100,
+            MAX_ORDER_QUANTITY,
+        );
+    }
+    // Three maximal orders sum past u64::MAX
+    assert_eq!(book.get_asks(), vec![(100, u64::MAX)]);
+    assert_eq!(book.get_bids(), vec![(100, u64::MAX)]);
+    assert_eq!(book.auction_clearing_price(), Some((100, u64::MAX)));
+
+    let result = book.run_auction(None, None);
+    assert_eq!(result.trades.len(), 3);
+    assert!(
+        result
+            .trades
+            .iter()
+            .all(|t| t.quantity == MAX_ORDER_QUANTITY)
+    );
+    assert_eq!(result.volume, u64::MAX);
+    assert!(book.get_asks().is_empty() && book.get_bids().is_empty());
+}
+
+#[test]
+fn auction_accumulates_crossing_orders_without_matching() {
+    let mut book = OrderBook::new();
+    book.start_auction();
+    assert_eq!(book.phase(), TradingPhase::Auction);
+
+    rest(&mut book, Uuid::new_v4(), OrderSide::Buy, 101, 5);
+    let ExecutionReport {
+        order: sell,
+        trades,
+        ..
+    } = book.add_order(
+        Uuid::new_v4(),
+        99,
+        5,
+        OrderSide::Sell,
+        OrderType::Limit,
+        None,
+        None,
+    );
+
+    assert!(trades.is_empty());
+    assert_eq!(sell.status, OrderStatus::Pending);
+    assert_eq!(book.best_bid(), Some(101));
+    assert_eq!(book.best_ask(), Some(99));
+}
+
+#[test]
+fn market_order_during_auction_is_rejected_without_touching_book() {
+    let mut book = OrderBook::new();
+    book.start_auction();
+    rest(&mut book, Uuid::new_v4(), OrderSide::Sell, 100, 5);
+
+    let preview = book.simulate_order(Uuid::new_v4(), 0, 5, OrderSide::Buy, OrderType::Market);
+    let report = book.add_order(
+        Uuid::new_v4(),
+        0,
+        5,
+        OrderSide::Buy,
+        OrderType::Market,
+        None,
+        None,
+    );
+
+    assert_eq!(report.rejection, Some(RejectReason::MarketOrderInAuction));
+    assert_eq!(preview.rejection, report.rejection);
+    assert_eq!(report.order.status, OrderStatus::Cancelled);
+    assert!(report.trades.is_empty());
+    assert_eq!(book.get_asks(), vec![(100, 5)]);
+    assert!(book.get_bids().is_empty());
+}
+
+#[test]
+fn auction_clears_at_max_volume_min_imbalance_price() {
+    let mut book = OrderBook::new();
+    book.start_auction();
+    let buyer = Uuid::new_v4();
+    let seller = Uuid::new_v4();
+    // Volume: 99 -> 5, 100 -> 10 (imbalance 5), 101 -> 10 (imbalance 0), 102 -> 5
+    rest(&mut book, buyer, OrderSide::Buy, 102, 5);
+    rest(&mut book, buyer, OrderSide::Buy, 101, 5);
+    rest(&mut book, buyer, OrderSide::Buy, 100, 5);
+    rest(&mut book, seller, OrderSide::Sell, 99, 5);
+    rest(&mut book, seller, OrderSide::Sell, 100, 5);
+    rest(&mut book, seller, OrderSide::Sell, 103, 5);
+
+    assert_eq!(book.auction_clearing_price(), Some((101, 10)));
+    let result = book.run_auction(None, None);
+
+    assert_eq!(result.clearing_price, Some(101));
+    assert_eq!(result.volume, 10);
+    assert!(result.trades.iter().all(|t| t.price == 101));
+    assert!(result.trades.iter().all(|t| t.taker_user_id == buyer));
+    assert_eq!(book.phase(), TradingPhase::Continuous);
+    assert_eq!(book.get_bids(), vec![(100, 5)]);
+    assert_eq!(book.get_asks(), vec![(103, 5)]);
+}
+
+#[test]
+fn auction_tie_breaks_to_lowest_price() {
+    let mut book = OrderBook::new();
+    book.start_auction();
+    rest(&mut book, Uuid::new_v4(), OrderSide::Buy, 101, 5);
+    rest(&mut book, Uuid::new_v4(), OrderSide::Sell, 100, 5);
+
+    let result = book.run_auction(None, None);
+    assert_eq!(result.clearing_price, Some(100));
+    assert_eq!(result.volume, 5);
+    assert!(book.get_bids().is_empty());
+    assert!(book.get_asks().is_empty());
+}
+
+#[test]
+fn auction_partial_fill_leaves_remainder_in_time_priority() {
+    let mut book = OrderBook::new();
+    book.start_auction();
+    let early = Uuid::new_v4();
+    let late = Uuid::new_v4();
+    rest(&mut book, early, OrderSide::Buy, 100, 4);
+    rest(&mut book, late, OrderSide::Buy, 100, 4);
+    rest(&mut book, Uuid::new_v4(), OrderSide::Sell, 100, 6);
+
+    let result = book.run_auction(None, None);
+    assert_eq!(result.clearing_price, Some(100));
+    assert_eq!(result.volume, 6);
+    assert_eq!(result.trades[0].taker_user_id, early);
+    assert_eq!(result.trades[0].quantity, 4);
+    assert_eq!(result.trades[1].taker_user_id, late);
+    assert_eq!(result.trades[1].quantity, 2);
+    assert_eq!(book.get_bids(), vec![(100, 2)]);
+}
+
+#[test]
+fn auction_without_cross_has_no_clearing_price() {
+    let mut book = OrderBook::new();
+    book.start_auction();
+    rest(&mut book, Uuid::new_v4(), OrderSide::Buy, 99, 5);
+    rest(&mut book, Uuid::new_v4(), OrderSide::Sell, 100, 5);
+
+    let result = book.run_auction(None, None);
+    assert_eq!(result.clearing_price, None);
+    assert_eq!(result.volume, 0);
+    assert!(result.trades.is_empty());
+    assert_eq!(book.phase(), TradingPhase::Continuous);
+}
+
 // --- WebSocket broadcasts ---
 
 #[tokio::test]
@@ -631,13 +1266,16 @@ async fn orderbook_update_broadcast_after_trade() {
                 assert_eq!(symbol, SYMBOL);
                 seen_trade = true;
             }
-            WsMessage::OrderBookUpdate { symbol, bids, asks } => {
+            WsMessage::OrderBookUpdate {
+                symbol, bids, asks, ..
+            } => {
                 assert_eq!(symbol, SYMBOL);
                 if bids.is_empty() && asks.is_empty() {
                     seen_empty_ob = true;
                     break;
                 }
             }
+            _ => {}
         }
     }
     assert!(seen_trade, "expected at least one Trade message");
@@ -651,7 +1289,7 @@ async fn cancel_broadcast_orderbook_update() {
     let mut rx = tx.subscribe();
     let user_id = Uuid::new_v4();
 
-    let (order, _) = book.add_order(
+    let ExecutionReport { order, .. } = book.add_order(
         user_id,
         scale_price(50_000),
         10,
@@ -664,6 +1302,10 @@ async fn cancel_broadcast_orderbook_update() {
         .await
         .expect("timeout")
         .expect("recv");
+    let _order_update = tokio::time::timeout(Duration::from_millis(100), rx.recv())
+        .await
+        .expect("timeout")
+        .expect("recv");
 
     book.remove_order(order.id, Some(&tx), Some(SYMBOL));
     let msg = tokio::time::timeout(Duration::from_millis(100), rx.recv())
@@ -678,3 +1320,738 @@ async fn cancel_broadcast_orderbook_update() {
         _ => panic!("expected OrderBookUpdate after cancel, got {:?}", msg),
     }
 }
+
+// --- Book sequence ---
+
+#[test]
+fn book_seq_advances_only_when_resting_orders_change() {
+    let mut book = OrderBook::new();
+    let user = Uuid::new_v4();
+    assert_eq!(book.book_seq(), 0);
+
+    let resting = book.add_order(user, 100, 2, OrderSide::Sell, OrderType::Limit, None, None);
+    assert_eq!(book.book_seq(), 1);
+    book.add_order(user, 0, 1, OrderSide::Buy, OrderType::Market, None, None);
+    assert_eq!(book.book_seq(), 2);
+    // A rejected order leaves the book untouched
+    book.add_order(user, 0, 1, OrderSide::Sell, OrderType::Market, None, None);
+    assert_eq!(book.book_seq(), 2);
+    book.remove_order(resting.order.id, None, None);
+    assert_eq!(book.book_seq(), 3);
+    assert!(book.remove_order(resting.order.id, None, None).is_none());
+    assert_eq!(book.book_seq(), 3);
+
+    let snapshot = book.snapshot(10);
+    assert_eq!(snapshot.seq, 3);
+    assert!(snapshot.bids.is_empty() && snapshot.asks.is_empty());
+}
+
+#[test]
+fn snapshot_truncates_to_depth_best_first() {
+    let mut book = OrderBook::new();
+    let user = Uuid::new_v4();
+    for price in [97, 99, 98] {
+        rest(&mut book, user, OrderSide::Buy, price, 1);
+    }
+    for price in [103, 101, 102] {
+        rest(&mut book, user, OrderSide::Sell, price, 1);
+    }
+
+    let snapshot = book.snapshot(2);
+    assert_eq!(snapshot.bids, vec![(99, 1), (98, 1)]);
+    assert_eq!(snapshot.asks, vec![(101, 1), (102, 1)]);
+    assert_eq!(snapshot.seq, 6);
+}
+
+// --- Bulk restore ---
+
+fn stored_order(side: OrderSide, order_type: OrderType, price: i64, qty: u64, ms: i64) -> Order {
+    Order {
+        id: Uuid::new_v4(),
+        user_id: Uuid::new_v4(),
+        side,
+        order_type,
+        price,
+        quantity: qty,
+        status: OrderStatus::Pending,
+        timestamp: Utc.timestamp_millis_opt(ms).unwrap(),
+        tags: Default::default(),
+        source: OrderSource::Api,
+    }
+}
+
+/// Everything observable about resting orders, for comparing two books.
+#[derive(Debug, PartialEq)]
+struct BookState {
+    bids: Vec<(i64, u64)>,
+    asks: Vec<(i64, u64)>,
+    orders: Vec<(usize, Order)>,
+}
+
+fn book_state(book: &OrderBook) -> BookState {
+    BookState {
+        bids: book.get_bids(),
+        asks: book.get_asks(),
+        orders: book
+            .iter_orders()
+            .map(|(pos, o)| (pos, o.clone()))
+            .collect(),
+    }
+}
+
+fn stored_order_strategy() -> impl Strategy<Value = Order> {
+    (any::<bool>(), any::<bool>(), 95i64..105, 0u64..4, 0i64..5).prop_map(
+        |(buy, limit, price, qty, ms)| {
+            let side = if buy { OrderSide::Buy } else { OrderSide::Sell };
+            let order_type = if limit {
+                OrderType::Limit
+            } else {
+                OrderType::Market
+            };
+            stored_order(side, order_type, price, qty, ms)
+        },
+    )
+}
+
+proptest! {
+    #[test]
+    fn bulk_restore_matches_incremental_restore(
+        seed in prop::collection::vec(stored_order_strategy(), 0..10),
+        orders in prop::collection::vec(stored_order_strategy(), 0..60),
+    ) {
+        let mut incremental = OrderBook::new();
+        let mut bulk = OrderBook::new();
+        for order in &seed {
+            incremental.restore_order(order.clone());
+            bulk.restore_order(order.clone());
+        }
+
+        // Incremental restore keeps arrival order within a level; feed it time priority
+        let mut by_time = orders.clone();
+        by_time.sort_by_key(|o| (o.timestamp, o.id));
+        for order in by_time {
+            incremental.restore_order(order);
+        }
+        let expected = orders
+            .iter()
+            .filter(|o| o.quantity > 0 && o.order_type == OrderType::Limit)
+            .count();
+        prop_assert_eq!(bulk.restore_orders(orders).unwrap(), expected);
+
+        prop_assert_eq!(book_state(&bulk), book_state(&incremental));
+        prop_assert!(bulk.check_invariants().is_ok());
+        prop_assert!(incremental.check_invariants().is_ok());
+    }
+}
+
+#[test]
+fn bulk_restore_skips_and_reports_duplicate_orders() {
+    let mut book = OrderBook::new();
+    let order = stored_order(OrderSide::Buy, OrderType::Limit, 100, 1, 0);
+    let mut moved = order.clone();
+    moved.price = 99;
+    let err = book.restore_orders(vec![order, moved]).unwrap_err();
+    assert!(err.contains("1 orders skipped"), "{}", err);
+    // The first copy wins and the book stays consistent
+    assert_eq!(book.get_bids(), vec![(100, 1)]);
+    assert_eq!(book.check_invariants(), Ok(()));
+
+    let mut book = OrderBook::new();
+    let first = book.restore_orders(vec![
+        stored_order(OrderSide::Buy, OrderType::Limit, 100, 2, 0),
+        stored_order(OrderSide::Sell, OrderType::Limit, 101, 1, 0),
+    ]);
+    assert_eq!(first, Ok(2));
+    assert_eq!(book.book_seq(), 1);
+}
+
+// --- Sweep equivalence ---
+
+/// A trade without its random id, for comparing two matchers.
+type Fill = (u64, Uuid, Uuid, i64, u64, Option<u32>);
+
+/// Fill `taker` the way matching always has: one maker at a time from the front of the best
+/// level, in `iter_orders` priority, re-reading the best level after every fill.
+fn reference_match(
+    book: &OrderBook,
+    taker: &Order,
+    policy: PricingPolicy,
+) -> (Vec<Fill>, Vec<(Uuid, u64)>, u64) {
+    let mut makers: Vec<Order> = book
+        .iter_orders()
+        .map(|(_, o)| o.clone())
+        .filter(|o| o.side != taker.side)
+        .collect();
+    let limit = (taker.order_type == OrderType::Limit).then_some(taker.price);
+    let mut remaining = taker.quantity;
+    let mut seq = book.last_trade_seq();
+    let mut fills = Vec::new();
+    while remaining > 0 && !makers.is_empty() {
+        let maker = &mut makers[0];
+        let crosses = match taker.side {
+            OrderSide::Buy => taker.price >= maker.price,
+            OrderSide::Sell => taker.price <= maker.price,
+        };
+        if taker.order_type != OrderType::Market && !crosses {
+            break;
+        }
+        let qty = remaining.min(maker.quantity);
+        seq += 1;
+        let price = policy.execution_price(maker.price, limit, 1);
+        fills.push((seq, maker.id, taker.id, price, qty, Some(0)));
+        remaining -= qty;
+        maker.quantity -= qty;
+        if maker.quantity == 0 {
+            makers.remove(0);
+        }
+    }
+    let left = makers.iter().map(|o| (o.id, o.quantity)).collect();
+    (fills, left, remaining)
+}
+
+fn fills(trades: &[Trade]) -> Vec<Fill> {
+    trades
+        .iter()
+        .map(|t| {
+            (
+                t.trade_seq,
+                t.maker_order_id,
+                t.taker_order_id,
+                t.price,
+                t.quantity,
+                t.maker_queue_rank,
+            )
+        })
+        .collect()
+}
+
+fn policy_strategy() -> impl Strategy<Value = PricingPolicy> {
+    prop_oneof![
+        Just(PricingPolicy::MakerPrice),
+        Just(PricingPolicy::TakerPrice),
+        Just(PricingPolicy::Midpoint),
+    ]
+}
+
+/// One operation of a random book history.
+#[derive(Debug, Clone)]
+enum BookOp {
+    Add(OrderSide, OrderType, i64, u64),
+    Cancel(usize),
+    Reduce(usize, u64),
+    Replace(usize, i64, u64),
+    Auction,
+}
+
+fn book_op_strategy() -> impl Strategy<Value = BookOp> {
+    let side = prop_oneof![Just(OrderSide::Buy), Just(OrderSide::Sell)];
+    let order_type = prop_oneof![4 => Just(OrderType::Limit), 1 => Just(OrderType::Market)];
+    prop_oneof![
+        6 => (side, order_type, 95i64..105, 1u64..20)
+            .prop_map(|(side, order_type, price, qty)| BookOp::Add(side, order_type, price, qty)),
+        2 => any::<usize>().prop_map(BookOp::Cancel),
+        2 => (any::<usize>(), 1u64..20).prop_map(|(i, qty)| BookOp::Reduce(i, qty)),
+        2 => (any::<usize>(), 95i64..105, 1u64..20)
+            .prop_map(|(i, price, qty)| BookOp::Replace(i, price, qty)),
+        1 => Just(BookOp::Auction),
+    ]
+}
+
+proptest! {
+    #[test]
+    fn sweeps_fill_makers_exactly_like_one_at_a_time_matching(
+        makers in prop::collection::vec((any::<bool>(), 95i64..105, 1u64..10), 0..80),
+        buy in any::<bool>(),
+        market in any::<bool>(),
+        price in 93i64..107,
+        qty in 1u64..200,
+        policy in policy_strategy(),
+    ) {
+        let mut book = OrderBook::new();
+        book.set_pricing_policy(policy, 1);
+        for (maker_buy, maker_price, maker_qty) in makers {
+            // Makers never cross: bids below 100, asks from 100
+            let (side, maker_price) = if maker_buy {
+                (OrderSide::Buy, maker_price.min(99))
+            } else {
+                (OrderSide::Sell, maker_price.max(100))
+            };
+            rest(&mut book, Uuid::new_v4(), side, maker_price, maker_qty);
+        }
+        let side = if buy { OrderSide::Buy } else { OrderSide::Sell };
+        let order_type = if market { OrderType::Market } else { OrderType::Limit };
+        let mut taker = stored_order(side, order_type, price, qty, 0);
+        let (expected, left, remaining) = reference_match(&book, &taker, policy);
+
+        let trades = match side {
+            OrderSide::Buy => book.match_buy_order(&mut taker),
+            OrderSide::Sell => book.match_sell_order(&mut taker),
+        };
+        prop_assert_eq!(fills(&trades), expected);
+        prop_assert_eq!(taker.quantity, remaining);
+        let status = if remaining == 0 {
+            OrderStatus::Filled
+        } else if remaining < qty {
+            OrderStatus::PartiallyFilled
+        } else {
+            OrderStatus::Pending
+        };
+        prop_assert_eq!(taker.status, status);
+        let resting: Vec<(Uuid, u64)> = book
+            .iter_orders()
+            .map(|(_, o)| o)
+            .filter(|o| o.side != side)
+            .map(|o| (o.id, o.quantity))
+            .collect();
+        prop_assert_eq!(resting, left);
+        // A maker the sweep only partly filled says so
+        for (_, order) in book.iter_orders() {
+            let filled = trades
+                .iter()
+                .filter(|t| t.maker_order_id == order.id)
+                .map(|t| t.quantity)
+                .sum::<u64>();
+            if filled > 0 {
+                prop_assert_eq!(order.status, OrderStatus::PartiallyFilled);
+            }
+        }
+        prop_assert_eq!(book.check_invariants(), Ok(()));
+    }
+
+    #[test]
+    fn cached_level_totals_survive_any_history(
+        ops in prop::collection::vec(book_op_strategy(), 0..120),
+    ) {
+        let mut book = OrderBook::new();
+        for op in ops {
+            let resting: Vec<Order> = book.iter_orders().map(|(_, o)| o.clone()).collect();
+            let pick = |i: usize| resting.get(i % resting.len().max(1)).map(|o| o.id);
+            match op {
+                BookOp::Add(side, order_type, price, qty) => {
+                    book.add_order(Uuid::new_v4(), price, qty, side, order_type, None, None);
+                }
+                BookOp::Cancel(i) => {
+                    if let Some(id) = pick(i) {
+                        book.remove_order(id, None, None);
+                    }
+                }
+                BookOp::Reduce(i, qty) => {
+                    if let Some(id) = pick(i) {
+                        let _ = book.reduce_order(id, qty, None, None);
+                    }
+                }
+                BookOp::Replace(i, price, qty) => {
+                    if let Some(id) = pick(i) {
+                        book.replace_order(id, price, qty, None, None);
+                    }
+                }
+                BookOp::Auction => {
+                    if book.phase() == TradingPhase::Auction {
+                        book.run_auction(None, None);
+                    } else {
+                        book.start_auction();
+                    }
+                }
+            }
+            prop_assert_eq!(book.check_invariants(), Ok(()));
+            // Level totals agree with the orders behind them
+            let sides = [(book.get_bids(), OrderSide::Buy), (book.get_asks(), OrderSide::Sell)];
+            for (levels, side) in sides {
+                for (price, total) in levels {
+                    let sum: u64 = book
+                        .iter_orders()
+                        .map(|(_, o)| o)
+                        .filter(|o| o.side == side && o.price == price)
+                        .map(|o| o.quantity)
+                        .sum();
+                    prop_assert_eq!(total, sum);
+                }
+            }
+        }
+    }
+}
+
+// --- iter_orders ---
+
+#[test]
+fn iter_orders_yields_price_then_time_priority() {
+    let mut book = OrderBook::new();
+    let user = Uuid::new_v4();
+    rest(&mut book, user, OrderSide::Buy, 99, 1);
+    rest(&mut book, user, OrderSide::Buy, 100, 2);
+    rest(&mut book, user, OrderSide::Buy, 100, 3);
+    rest(&mut book, user, OrderSide::Sell, 102, 4);
+    rest(&mut book, user, OrderSide::Sell, 101, 5);
+
+    let listed: Vec<(usize, i64, u64)> = book
+        .iter_orders()
+        .map(|(pos, o)| (pos, o.price, o.quantity))
+        .collect();
+    assert_eq!(
+        listed,
+        vec![
+            (0, 100, 2),
+            (1, 100, 3),
+            (0, 99, 1),
+            (0, 101, 5),
+            (0, 102, 4)
+        ]
+    );
+}
+
+// --- Trade sequence ---
+
+#[test]
+fn trade_seq_strictly_increases_across_a_burst_of_fills() {
+    let mut book = OrderBook::new();
+    let maker = Uuid::new_v4();
+    let taker = Uuid::new_v4();
+    for price in 100..110 {
+        rest(&mut book, maker, OrderSide::Sell, price, 1);
+        rest(&mut book, maker, OrderSide::Sell, price, 1);
+    }
+
+    let ExecutionReport { trades: first, .. } =
+        book.add_order(taker, 104, 7, OrderSide::Buy, OrderType::Limit, None, None);
+    let ExecutionReport { trades: second, .. } =
+        book.add_order(taker, 0, 13, OrderSide::Buy, OrderType::Market, None, None);
+    let seqs: Vec<u64> = first.iter().chain(&second).map(|t| t.trade_seq).collect();
+    assert_eq!(seqs, (1..=20).collect::<Vec<u64>>());
+    assert_eq!(book.last_trade_seq(), 20);
+
+    let from: Vec<u64> = book
+        .get_trades_from_seq(18, 10)
+        .iter()
+        .map(|t| t.trade_seq)
+        .collect();
+    assert_eq!(from, vec![18, 19, 20]);
+}
+
+#[test]
+fn trade_seq_resumes_after_restored_counter() {
+    let mut book = OrderBook::new();
+    book.set_last_trade_seq(41);
+    let user = Uuid::new_v4();
+    rest(&mut book, user, OrderSide::Sell, 100, 1);
+    let ExecutionReport {
+        trades: preview, ..
+    } = book.simulate_order(user, 100, 1, OrderSide::Buy, OrderType::Limit);
+    let ExecutionReport { trades, .. } =
+        book.add_order(user, 100, 1, OrderSide::Buy, OrderType::Limit, None, None);
+    assert_eq!(preview[0].trade_seq, 42);
+    assert_eq!(trades[0].trade_seq, 42);
+}
+
+// --- Clock ---
+
+#[test]
+fn order_and_trade_timestamps_come_from_the_injected_clock() {
+    let opening = Utc.with_ymd_and_hms(2025, 1, 2, 9, 30, 0).unwrap();
+    let clock = Arc::new(ManualClock::new(opening));
+    let mut book = OrderBook::with_clock(clock.clone());
+    let maker = Uuid::new_v4();
+    let taker = Uuid::new_v4();
+
+    let ExecutionReport { order: resting, .. } =
+        book.add_order(maker, 100, 5, OrderSide::Sell, OrderType::Limit, None, None);
+    assert_eq!(resting.timestamp, opening);
+
+    let later = opening + chrono::Duration::seconds(90);
+    clock.set(later);
+    let ExecutionReport { order, trades, .. } =
+        book.add_order(taker, 100, 2, OrderSide::Buy, OrderType::Limit, None, None);
+    assert_eq!(order.timestamp, later);
+    assert_eq!(trades[0].timestamp, later);
+    assert_eq!(book.get_order_by_id(resting.id).unwrap().timestamp, opening);
+    assert_eq!(book.snapshot(5).captured_at, later);
+}
+
+/// Drive a scripted session on a stepped clock: asks at 100 and 101 and a bid at 90 rest at
+/// t=0, a limit buy lifts 1 at t=10s, a market buy sweeps the asks at t=30s, and the bid plus a
+/// later ask at 102 are cancelled unfilled.
+fn scripted_matching_session(window: usize) -> OrderBook {
+    let opening = Utc.with_ymd_and_hms(2025, 1, 2, 9, 30, 0).unwrap();
+    let clock = Arc::new(ManualClock::new(opening));
+    let mut book = OrderBook::with_clock(clock.clone());
+    book.set_matching_window(window);
+    let maker = Uuid::new_v4();
+    let taker = Uuid::new_v4();
+    let at = |secs| opening + chrono::Duration::seconds(secs);
+
+    book.add_order(maker, 100, 4, OrderSide::Sell, OrderType::Limit, None, None);
+    book.add_order(maker, 101, 2, OrderSide::Sell, OrderType::Limit, None, None);
+    let bid = book.add_order(maker, 90, 1, OrderSide::Buy, OrderType::Limit, None, None);
+    clock.set(at(10));
+    book.add_order(taker, 100, 1, OrderSide::Buy, OrderType::Limit, None, None);
+    clock.set(at(30));
+    book.add_order(taker, 0, 5, OrderSide::Buy, OrderType::Market, None, None);
+    clock.set(at(40));
+    book.remove_order(bid.order.id, None, None);
+    let ask = book.add_order(maker, 102, 1, OrderSide::Sell, OrderType::Limit, None, None);
+    clock.set(at(50));
+    book.remove_order(ask.order.id, None, None);
+    book
+}
+
+#[test]
+fn matching_stats_report_time_to_fill_percentiles_and_fill_ratios() {
+    let report = scripted_matching_session(1000).matching_stats().report();
+
+    // First fills: the aggressive limit buy (0s), the 100 ask (10s), the 101 ask (30s)
+    let first = report.time_to_first_fill;
+    assert_eq!(first.samples, 3);
+    assert_eq!(first.p50_ms, Some(10_000));
+    assert_eq!(first.p90_ms, Some(30_000));
+    assert_eq!(first.max_ms, Some(30_000));
+    // Full fills: the limit buy (0s), then both asks when the market order swept them (30s)
+    let full = report.time_to_full_fill;
+    assert_eq!(full.samples, 3);
+    assert_eq!(full.p50_ms, Some(30_000));
+    assert_eq!(full.p99_ms, Some(30_000));
+
+    assert_eq!(report.limit_orders_completed, 5);
+    assert_eq!(report.limit_orders_traded, 3);
+    assert_eq!(report.limit_fill_ratio, Some(0.6));
+    assert_eq!(report.volume.limit_maker, 6);
+    assert_eq!(report.volume.limit_taker, 1);
+    assert_eq!(report.volume.market_taker, 5);
+    assert_eq!(report.maker_ratio, Some(6.0 / 7.0));
+}
+
+#[test]
+fn matching_stats_window_keeps_only_recent_samples() {
+    let book = scripted_matching_session(2);
+    let report = book.matching_stats().report();
+    assert_eq!(report.window, 2);
+    assert_eq!(report.time_to_first_fill.samples, 2);
+    assert_eq!(report.time_to_first_fill.p50_ms, Some(10_000));
+    // The two cancelled orders were the last to complete
+    assert_eq!(report.limit_orders_completed, 2);
+    assert_eq!(report.limit_fill_ratio, Some(0.0));
+    // Only the last fill's two sides (the market order taking 2 from the 101 ask) remain
+    assert_eq!(report.volume.limit_maker, 2);
+    assert_eq!(report.volume.market_taker, 2);
+
+    // The cumulative histogram still counts every sample
+    let metrics = book.matching_stats().metrics();
+    assert_eq!(metrics.time_to_first_fill.count, 3);
+    assert_eq!(metrics.limit_orders_completed, 5);
+    assert_eq!(metrics.volume.market_taker, 5);
+}
+
+// --- Resting order caps ---
+
+#[test]
+fn resting_caps_reject_new_resting_orders_but_still_allow_taking() {
+    let limits = Arc::new(OrderLimits::new(RestingOrderCaps {
+        global: Some(3),
+        per_symbol: Some(2),
+    }));
+    let mut btc = OrderBook::new();
+    let mut eth = OrderBook::new();
+    btc.set_order_limits(limits.clone());
+    eth.set_order_limits(limits.clone());
+    let user = Uuid::new_v4();
+    let sell = |book: &mut OrderBook, price| {
+        book.add_order(
+            user,
+            price,
+            1,
+            OrderSide::Sell,
+            OrderType::Limit,
+            None,
+            None,
+        )
+    };
+
+    sell(&mut btc, 100);
+    sell(&mut btc, 101);
+    let full = sell(&mut btc, 102);
+    assert_eq!(full.rejection, Some(RejectReason::BookFull));
+    assert_eq!(full.order.status, OrderStatus::Cancelled);
+    assert!(full.trades.is_empty());
+    assert!(btc.get_order_by_id(full.order.id).is_none());
+    assert_eq!(btc.resting_count(), 2);
+    let preview = btc.simulate_order(user, 103, 1, OrderSide::Sell, OrderType::Limit);
+    assert_eq!(preview.rejection, Some(RejectReason::BookFull));
+
+    // The per-symbol cap leaves room in ETH, but the global cap is reached
+    sell(&mut eth, 200);
+    assert_eq!(limits.resting(), 3);
+    assert_eq!(sell(&mut eth, 201).rejection, Some(RejectReason::BookFull));
+
+    // Taking liquidity at the cap is allowed and frees a slot
+    let taker = Uuid::new_v4();
+    let take = btc.add_order(taker, 100, 1, OrderSide::Buy, OrderType::Limit, None, None);
+    assert_eq!(take.rejection, None);
+    assert_eq!(take.order.status, OrderStatus::Filled);
+    assert_eq!(take.trades.len(), 1);
+    assert_eq!(limits.resting(), 2);
+    assert_eq!(sell(&mut eth, 201).rejection, None);
+    assert_eq!(limits.book_full_rejections(), 2);
+
+    drop(eth);
+    assert_eq!(limits.resting(), 1);
+}
+
+#[test]
+fn remainder_past_a_lapsed_override_is_cancelled_after_its_fills() {
+    let opening = Utc.with_ymd_and_hms(2025, 1, 2, 9, 30, 0).unwrap();
+    let clock = Arc::new(ManualClock::new(opening));
+    let limits = Arc::new(OrderLimits::new(RestingOrderCaps {
+        global: None,
+        per_symbol: Some(2),
+    }));
+    let raise = |caps, secs| {
+        limits.set_override(CapOverride {
+            caps,
+            expires_at: opening + chrono::Duration::seconds(secs),
+        })
+    };
+    assert!(raise(RestingOrderCaps::default(), 60).is_err());
+    let lower = RestingOrderCaps {
+        global: None,
+        per_symbol: Some(1),
+    };
+    assert!(raise(lower, 60).is_err());
+    let unlimited_global = RestingOrderCaps {
+        global: Some(10),
+        per_symbol: None,
+    };
+    assert!(raise(unlimited_global, 60).is_err());
+    let raised = RestingOrderCaps {
+        global: None,
+        per_symbol: Some(3),
+    };
+    raise(raised, 60).unwrap();
+
+    let mut book = OrderBook::with_clock(clock.clone());
+    book.set_order_limits(limits.clone());
+    let maker = Uuid::new_v4();
+    for price in [100, 101, 102] {
+        let report = book.add_order(
+            maker,
+            price,
+            1,
+            OrderSide::Sell,
+            OrderType::Limit,
+            None,
+            None,
+        );
+        assert_eq!(report.rejection, None);
+    }
+
+    // Three orders rest under a cap of two once the override lapses: a buy that fills one
+    // ask frees a slot, but its remainder would be the third resting order
+    clock.set(opening + chrono::Duration::seconds(61));
+    assert_eq!(limits.effective(book.now()).per_symbol, Some(2));
+    let report = book.add_order(
+        Uuid::new_v4(),
+        100,
+        3,
+        OrderSide::Buy,
+        OrderType::Limit,
+        None,
+        None,
+    );
+    assert_eq!(report.trades.len(), 1);
+    assert_eq!(report.rejection, Some(RejectReason::BookFull));
+    assert_eq!(report.order.status, OrderStatus::Cancelled);
+    assert_eq!(report.order.quantity, 2);
+    assert!(book.get_order_by_id(report.order.id).is_none());
+    assert_eq!(book.resting_count(), 2);
+    assert!(limits.active_override(book.now()).is_none());
+}
+
+// --- Book stats ---
+
+#[test]
+fn stats_follow_orders_as_they_rest_fill_and_cancel() {
+    let opening = Utc.with_ymd_and_hms(2025, 1, 2, 9, 30, 0).unwrap();
+    let clock = Arc::new(ManualClock::new(opening));
+    let mut book = OrderBook::with_clock(clock.clone());
+    let maker = Uuid::new_v4();
+    let taker = Uuid::new_v4();
+
+    let empty = book.stats();
+    assert_eq!(
+        (empty.bid_levels, empty.ask_levels, empty.resting_orders),
+        (0, 0, 0)
+    );
+    assert_eq!(empty.estimated_bytes, 0);
+    assert_eq!(
+        (empty.last_trade_price, empty.oldest_resting_at),
+        (None, None)
+    );
+
+    let ExecutionReport { order: ask, .. } =
+        book.add_order(maker, 101, 2, OrderSide::Sell, OrderType::Limit, None, None);
+    clock.set(opening + chrono::Duration::seconds(10));
+    book.add_order(maker, 101, 3, OrderSide::Sell, OrderType::Limit, None, None);
+    let ExecutionReport { order: bid, .. } =
+        book.add_order(maker, 99, 1, OrderSide::Buy, OrderType::Limit, None, None);
+    let resting = book.stats();
+    assert_eq!((resting.bid_levels, resting.ask_levels), (1, 1));
+    assert_eq!(resting.resting_orders, 3);
+    assert_eq!(resting.oldest_resting_at, Some(opening));
+    assert_eq!(resting.book_seq, book.book_seq());
+    assert!(resting.estimated_bytes > 0);
+
+    // Filling the oldest order moves the oldest time on and records the trade
+    let fill_time = opening + chrono::Duration::seconds(20);
+    clock.set(fill_time);
+    book.add_order(taker, 101, 2, OrderSide::Buy, OrderType::Limit, None, None);
+    let filled = book.stats();
+    assert!(book.get_order_by_id(ask.id).is_none());
+    assert_eq!(filled.resting_orders, 2);
+    assert_eq!(
+        filled.oldest_resting_at,
+        Some(opening + chrono::Duration::seconds(10))
+    );
+    assert_eq!(filled.retained_trades, 1);
+    assert_eq!(
+        (filled.last_trade_seq, filled.last_trade_price),
+        (1, Some(101))
+    );
+    assert_eq!(filled.last_trade_at, Some(fill_time));
+    assert!(filled.book_seq > resting.book_seq);
+
+    // Cancelling empties the bid side; the trade stays retained
+    book.remove_order(bid.id, None, None);
+    let cancelled = book.stats();
+    assert_eq!((cancelled.bid_levels, cancelled.ask_levels), (0, 1));
+    assert_eq!(cancelled.resting_orders, 1);
+    assert!(cancelled.estimated_bytes < filled.estimated_bytes);
+    assert_eq!(cancelled.retained_trades, 1);
+
+    book.clear_trades();
+    let cleared = book.stats();
+    assert_eq!(
+        (cleared.retained_trades, cleared.last_trade_price),
+        (0, None)
+    );
+    assert_eq!(cleared.last_trade_seq, 1);
+    assert!(cleared.estimated_bytes < cancelled.estimated_bytes);
+}
+
+// --- Serialization ---
+
+#[test]
+fn order_serializes_source_and_defaults_it_when_absent() {
+    let mut book = OrderBook::new();
+    let report = book.add_order(
+        Uuid::new_v4(),
+        100,
+        1,
+        OrderSide::Buy,
+        OrderType::Limit,
+        None,
+        None,
+    );
+    let mut json = serde_json::to_value(&report.order).unwrap();
+    assert_eq!(json["source"], "Api");
+
+    json.as_object_mut().unwrap().remove("source");
+    let order: Order = serde_json::from_value(json).unwrap();
+    assert_eq!(order.source, OrderSource::Api);
+}
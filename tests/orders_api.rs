@@ -0,0 +1,1592 @@
+//! HTTP integration tests for order endpoints (in-memory mode, no database).
+
+use chrono::{DateTime, Utc};
+use rust_exchange::api::dto::{OrderDto, PositionDto, TradeDto};
+use rust_exchange::api::fields::Selectable;
+use rust_exchange::api::ingress::Ingress;
+use rust_exchange::api::routes::WsMessage;
+use rust_exchange::market_data::{self, MarketDataStore};
+use rust_exchange::orderbook::clock::ManualClock;
+use rust_exchange::orderbook::orderbook::OrderBook;
+use rust_exchange::testing::{self, TestExchange};
+use rust_exchange::types::symbol::{MAX_LISTED_SYMBOLS, SymbolConfig, SymbolHint, edit_distance};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::RwLock;
+
+fn scale_price(p: i64) -> i64 {
+    p * 100_000_000
+}
+
+#[tokio::test]
+async fn preview_order_returns_fills_and_leaves_book_untouched() {
+    let exchange = TestExchange::start().await;
+    let (client, base_url) = (exchange.client(), &exchange.base_url);
+    let maker = exchange.register("maker", "secret").await.token;
+    let taker = exchange.register("taker", "secret").await.token;
+
+    for (price, qty) in [(50_000, 2), (50_100, 2)] {
+        let res = client
+            .post(format!("{}/orders", base_url))
+            .bearer_auth(&maker)
+            .json(&serde_json::json!({
+                "symbol": "BTCUSDT",
+                "price": scale_price(price),
+                "quantity": qty,
+                "side": "Sell"
+            }))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(res.status().as_u16(), 200);
+    }
+
+    let res = client
+        .post(format!("{}/orders/preview", base_url))
+        .bearer_auth(&taker)
+        .json(&serde_json::json!({
+            "symbol": "btcusdt",
+            "price": scale_price(50_100),
+            "quantity": 3,
+            "side": "Buy"
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status().as_u16(), 200);
+    let json: serde_json::Value = res.json().await.unwrap();
+    assert_eq!(json["trades"].as_array().unwrap().len(), 2);
+    assert_eq!(json["filled_quantity"], 3);
+    assert_eq!(json["remaining_quantity"], 0);
+    assert_eq!(
+        json["average_price"],
+        (scale_price(50_000) * 2 + scale_price(50_100)) / 3
+    );
+    assert_eq!(json["position_change"], 3);
+    assert_eq!(json["resulting_position"]["quantity"], 3);
+
+    let book: serde_json::Value = client
+        .get(format!("{}/book?symbol=BTCUSDT", base_url))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(book["asks"].as_array().unwrap().len(), 2);
+}
+
+#[tokio::test]
+async fn preview_market_order_without_liquidity_returns_400() {
+    let exchange = TestExchange::start().await;
+    let (client, base_url) = (exchange.client(), &exchange.base_url);
+    let token = exchange.register("alice", "secret").await.token;
+
+    let res = client
+        .post(format!("{}/orders/preview", base_url))
+        .bearer_auth(&token)
+        .json(&serde_json::json!({
+            "symbol": "BTCUSDT",
+            "price": 0,
+            "quantity": 1,
+            "side": "Buy",
+            "order_type": "Market"
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status().as_u16(), 400);
+    let json: serde_json::Value = res.json().await.unwrap();
+    assert_eq!(json["error_code"], "NO_LIQUIDITY");
+}
+
+#[tokio::test]
+async fn order_rejections_carry_reason_code() {
+    let exchange = TestExchange::start().await;
+    let (client, base_url) = (exchange.client(), &exchange.base_url);
+    let token = exchange.register("alice", "secret").await.token;
+
+    let cases = [
+        ("", "Limit", 400, "MISSING_SYMBOL"),
+        ("DOGEUSDT", "Limit", 404, "UNKNOWN_SYMBOL"),
+        ("BTCUSDT", "Market", 400, "NO_LIQUIDITY"),
+    ];
+    for (symbol, order_type, status, code) in cases {
+        let res = client
+            .post(format!("{}/orders", base_url))
+            .bearer_auth(&token)
+            .json(&serde_json::json!({
+                "symbol": symbol,
+                "price": scale_price(50_000),
+                "quantity": 1,
+                "side": "Buy",
+                "order_type": order_type
+            }))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(res.status().as_u16(), status, "{}", code);
+        let json: serde_json::Value = res.json().await.unwrap();
+        assert_eq!(json["error_code"], code);
+    }
+}
+
+#[tokio::test]
+async fn idempotency_key_replays_stored_response() {
+    let exchange = TestExchange::start().await;
+    let (client, base_url) = (exchange.client(), &exchange.base_url);
+    let token = exchange.register("alice", "secret").await.token;
+    let body = serde_json::json!({
+        "symbol": "BTCUSDT",
+        "price": scale_price(50_000),
+        "quantity": 1,
+        "side": "Buy"
+    });
+
+    let first = client
+        .post(format!("{}/orders", base_url))
+        .bearer_auth(&token)
+        .header("Idempotency-Key", "order-1")
+        .json(&body)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(first.status().as_u16(), 200);
+    assert!(first.headers().get("idempotent-replay").is_none());
+    let first: serde_json::Value = first.json().await.unwrap();
+
+    let replay = client
+        .post(format!("{}/orders", base_url))
+        .bearer_auth(&token)
+        .header("Idempotency-Key", "order-1")
+        .json(&body)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(replay.status().as_u16(), 200);
+    assert_eq!(replay.headers().get("idempotent-replay").unwrap(), "true");
+    let replay: serde_json::Value = replay.json().await.unwrap();
+    assert_eq!(replay["id"], first["id"]);
+
+    let book: serde_json::Value = client
+        .get(format!("{}/book?symbol=BTCUSDT", base_url))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(book["bids"][0][1], 1);
+}
+
+#[tokio::test]
+async fn idempotency_key_with_different_body_returns_422() {
+    let exchange = TestExchange::start().await;
+    let (client, base_url) = (exchange.client(), &exchange.base_url);
+    let token = exchange.register("alice", "secret").await.token;
+
+    for (qty, expected) in [(1, 200), (2, 422)] {
+        let res = client
+            .post(format!("{}/orders", base_url))
+            .bearer_auth(&token)
+            .header("Idempotency-Key", "order-1")
+            .json(&serde_json::json!({
+                "symbol": "BTCUSDT",
+                "price": scale_price(50_000),
+                "quantity": qty,
+                "side": "Buy"
+            }))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(res.status().as_u16(), expected);
+    }
+}
+
+#[tokio::test]
+async fn concurrent_requests_with_same_key_execute_once() {
+    let exchange = TestExchange::start().await;
+    let (client, base_url) = (exchange.client(), &exchange.base_url);
+    let token = exchange.register("alice", "secret").await.token;
+    let body = serde_json::json!({
+        "symbol": "BTCUSDT",
+        "price": scale_price(50_000),
+        "quantity": 1,
+        "side": "Buy"
+    });
+
+    let send = || {
+        client
+            .post(format!("{}/orders", base_url))
+            .bearer_auth(&token)
+            .header("Idempotency-Key", "same-key")
+            .json(&body)
+            .send()
+    };
+    let (a, b) = tokio::join!(send(), send());
+    let statuses = [a.unwrap().status().as_u16(), b.unwrap().status().as_u16()];
+    assert!(statuses.contains(&200));
+    assert!(statuses.iter().all(|s| *s == 200 || *s == 409));
+
+    let book: serde_json::Value = client
+        .get(format!("{}/book?symbol=BTCUSDT", base_url))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(book["bids"][0][1], 1);
+}
+
+#[tokio::test]
+async fn order_tags_are_returned_and_filterable() {
+    let exchange = TestExchange::start().await;
+    let (client, base_url) = (exchange.client(), &exchange.base_url);
+    let token = exchange.register("tagger", "secret").await.token;
+
+    for (price, strategy) in [(100, "mm-v2"), (101, "arb"), (102, "mm-v2")] {
+        let res = client
+            .post(format!("{}/orders", base_url))
+            .bearer_auth(&token)
+            .json(&serde_json::json!({
+                "symbol": "BTCUSDT",
+                "price": scale_price(price),
+                "quantity": 1,
+                "side": "Buy",
+                "tags": { "strategy": strategy, "batch": "2024-06-01" }
+            }))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(res.status().as_u16(), 200);
+        let order: serde_json::Value = res.json().await.unwrap();
+        assert_eq!(order["tags"]["strategy"], strategy);
+        assert_eq!(order["tags"]["batch"], "2024-06-01");
+    }
+
+    let res = client
+        .get(format!("{}/orders/me?tag=strategy:mm-v2", base_url))
+        .bearer_auth(&token)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status().as_u16(), 200);
+    let page: serde_json::Value = res.json().await.unwrap();
+    let prices: Vec<i64> = page["items"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|o| o["price"].as_i64().unwrap())
+        .collect();
+    assert_eq!(prices, vec![scale_price(102), scale_price(100)]);
+
+    let res = client
+        .get(format!("{}/orders/me?tag=strategy", base_url))
+        .bearer_auth(&token)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn order_source_is_set_by_the_server_and_filterable() {
+    let exchange = TestExchange::start().await;
+    let (client, base_url) = (exchange.client(), &exchange.base_url);
+    let token = exchange.register("alice", "secret").await.token;
+    let mut body = serde_json::json!({
+        "symbol": "BTCUSDT",
+        "price": scale_price(50_000),
+        "quantity": 1,
+        "side": "Buy",
+        "source": "Admin"
+    });
+
+    for path in ["orders", "orders/preview"] {
+        let res = client
+            .post(format!("{}/{}", base_url, path))
+            .bearer_auth(&token)
+            .json(&body)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(res.status().as_u16(), 400, "{}", path);
+    }
+
+    body.as_object_mut().unwrap().remove("source");
+    let order: serde_json::Value = client
+        .post(format!("{}/orders", base_url))
+        .bearer_auth(&token)
+        .json(&body)
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(order["source"], "Api");
+
+    for (source, count) in [("Api", 1), ("Admin", 0)] {
+        let page: serde_json::Value = client
+            .get(format!("{}/orders/me?source={}", base_url, source))
+            .bearer_auth(&token)
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(page["items"].as_array().unwrap().len(), count, "{}", source);
+    }
+    let res = client
+        .get(format!("{}/orders/me?source=Liquidation", base_url))
+        .bearer_auth(&token)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn order_tags_over_limits_are_rejected_naming_the_tag() {
+    let exchange = TestExchange::start().await;
+    let (client, base_url) = (exchange.client(), &exchange.base_url);
+    let token = exchange.register("tagger", "secret").await.token;
+
+    let submit = |tags: serde_json::Value| {
+        client
+            .post(format!("{}/orders", base_url))
+            .bearer_auth(&token)
+            .json(&serde_json::json!({
+                "symbol": "BTCUSDT",
+                "price": scale_price(100),
+                "quantity": 1,
+                "side": "Buy",
+                "tags": tags
+            }))
+            .send()
+    };
+
+    let res = submit(serde_json::json!({ "note": "x".repeat(65) }))
+        .await
+        .unwrap();
+    assert_eq!(res.status().as_u16(), 400);
+    let json: serde_json::Value = res.json().await.unwrap();
+    assert!(json["error"].as_str().unwrap().contains("'note'"));
+
+    let res =
+        submit(serde_json::json!({ "a": "1", "b": "2", "c": "3", "d": "4", "e": "5", "f": "6" }))
+            .await
+            .unwrap();
+    assert_eq!(res.status().as_u16(), 400);
+
+    let res = submit(serde_json::json!({ "note": "x".repeat(64) }))
+        .await
+        .unwrap();
+    assert_eq!(res.status().as_u16(), 200);
+}
+
+// --- Multi-symbol books ---
+
+#[tokio::test]
+async fn books_reports_unknown_symbols_per_symbol_or_fails_all_or_nothing() {
+    let exchange = TestExchange::builder().symbol("ETHUSDT").start().await;
+    let (client, base_url) = (exchange.client(), &exchange.base_url);
+
+    let res = client
+        .get(format!(
+            "{}/books?symbols=btcusdt,DOGEUSDT&depth=5",
+            base_url
+        ))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status().as_u16(), 200);
+    let json: serde_json::Value = res.json().await.unwrap();
+    assert_eq!(json["books"]["BTCUSDT"]["seq"], 0);
+    assert!(json["books"]["BTCUSDT"]["captured_at"].is_string());
+    assert!(json["books"].get("DOGEUSDT").is_none());
+    assert!(json["errors"]["DOGEUSDT"].is_string());
+
+    let res = client
+        .get(format!(
+            "{}/books?symbols=BTCUSDT,DOGEUSDT&all_or_nothing=true",
+            base_url
+        ))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status().as_u16(), 404);
+
+    let many: Vec<String> = (0..11).map(|i| format!("SYM{}", i)).collect();
+    let res = client
+        .get(format!("{}/books?symbols={}", base_url, many.join(",")))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn books_snapshots_are_consistent_with_their_sequence() {
+    let exchange = TestExchange::builder().symbol("ETHUSDT").start().await;
+    let (client, base_url) = (exchange.client(), &exchange.base_url);
+    let token = exchange.register("alice", "secret").await.token;
+    const ORDERS_PER_SYMBOL: u64 = 40;
+
+    // Non-crossing bids of quantity 1: every accepted order bumps the book sequence by one
+    // and adds one unit, so a consistent snapshot has total bid quantity == seq
+    let mut writers = Vec::new();
+    for symbol in ["BTCUSDT", "ETHUSDT"] {
+        let client = client.clone();
+        let base_url = base_url.clone();
+        let token = token.clone();
+        writers.push(tokio::spawn(async move {
+            for i in 0..ORDERS_PER_SYMBOL {
+                let res = client
+                    .post(format!("{}/orders", base_url))
+                    .bearer_auth(&token)
+                    .json(&serde_json::json!({
+                        "symbol": symbol,
+                        "price": 100 + (i % 10) as i64,
+                        "quantity": 1,
+                        "side": "Buy"
+                    }))
+                    .send()
+                    .await
+                    .unwrap();
+                assert_eq!(res.status().as_u16(), 200);
+            }
+        }));
+    }
+
+    let check = |json: &serde_json::Value| {
+        for symbol in ["BTCUSDT", "ETHUSDT"] {
+            let book = &json["books"][symbol];
+            let total: u64 = book["bids"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|level| level[1].as_u64().unwrap())
+                .sum();
+            assert_eq!(total, book["seq"].as_u64().unwrap(), "{}", symbol);
+        }
+    };
+    while !writers.iter().all(|w| w.is_finished()) {
+        let json: serde_json::Value = client
+            .get(format!("{}/books?symbols=BTCUSDT,ETHUSDT", base_url))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        check(&json);
+    }
+    for writer in writers {
+        writer.await.unwrap();
+    }
+
+    let json: serde_json::Value = client
+        .get(format!("{}/books?symbols=ETHUSDT,BTCUSDT", base_url))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    check(&json);
+    assert_eq!(json["books"]["BTCUSDT"]["seq"], ORDERS_PER_SYMBOL);
+    assert_eq!(json["books"]["ETHUSDT"]["seq"], ORDERS_PER_SYMBOL);
+}
+
+// --- GET /book caching ---
+
+#[tokio::test]
+async fn book_etag_answers_304_and_reuses_cached_body_until_the_book_changes() {
+    let state = testing::test_app_state();
+    let cache = state.book_cache.clone();
+    let exchange = TestExchange::builder_with_state(state).start().await;
+    let (client, base_url) = (exchange.client(), &exchange.base_url);
+    let token = exchange.register("alice", "secret").await.token;
+    let place = |price: i64| {
+        client
+            .post(format!("{}/orders", base_url))
+            .bearer_auth(&token)
+            .json(&serde_json::json!({
+                "symbol": "BTCUSDT",
+                "price": scale_price(price),
+                "quantity": 1,
+                "side": "Buy"
+            }))
+            .send()
+    };
+    assert_eq!(place(50_000).await.unwrap().status().as_u16(), 200);
+
+    let book_url = format!("{}/book?symbol=BTCUSDT&depth=5", base_url);
+    let first = client.get(&book_url).send().await.unwrap();
+    assert_eq!(first.status().as_u16(), 200);
+    let etag = first.headers()["etag"].to_str().unwrap().to_string();
+    let first_body = first.text().await.unwrap();
+    assert_eq!(cache.stats().hits, 0);
+
+    // Same sequence: served from the cache, byte-for-byte, under the same tag
+    let second = client.get(&book_url).send().await.unwrap();
+    assert_eq!(second.headers()["etag"].to_str().unwrap(), etag);
+    assert_eq!(second.text().await.unwrap(), first_body);
+    assert_eq!(cache.stats().hits, 1);
+
+    let res = client
+        .get(&book_url)
+        .header("If-None-Match", &etag)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status().as_u16(), 304);
+    assert_eq!(res.headers()["etag"].to_str().unwrap(), etag);
+    assert!(res.bytes().await.unwrap().is_empty());
+
+    // A different depth is a different representation
+    let res = client
+        .get(format!("{}/book?symbol=BTCUSDT", base_url))
+        .header("If-None-Match", &etag)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status().as_u16(), 200);
+
+    assert_eq!(place(50_100).await.unwrap().status().as_u16(), 200);
+    let res = client
+        .get(&book_url)
+        .header("If-None-Match", &etag)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status().as_u16(), 200);
+    assert_ne!(res.headers()["etag"].to_str().unwrap(), etag);
+    let json: serde_json::Value = res.json().await.unwrap();
+    assert_eq!(json["bids"].as_array().unwrap().len(), 2);
+    assert_eq!(json["bids"][0][0], scale_price(50_100));
+}
+
+#[tokio::test]
+async fn book_responses_are_gzip_compressed_when_accepted() {
+    let exchange = TestExchange::start().await;
+    let (client, base_url) = (exchange.client(), &exchange.base_url);
+    let token = exchange.register("alice", "secret").await.token;
+    for i in 0..200 {
+        let res = client
+            .post(format!("{}/orders", base_url))
+            .bearer_auth(&token)
+            .json(&serde_json::json!({
+                "symbol": "BTCUSDT",
+                "price": scale_price(40_000 + i),
+                "quantity": 1,
+                "side": "Buy"
+            }))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(res.status().as_u16(), 200);
+    }
+
+    let res = client
+        .get(format!("{}/book?symbol=BTCUSDT", base_url))
+        .header("Accept-Encoding", "gzip")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status().as_u16(), 200);
+    assert_eq!(res.headers()["content-encoding"], "gzip");
+    assert!(res.headers().contains_key("etag"));
+}
+
+#[tokio::test]
+async fn replace_order_swaps_the_order_atomically_and_broadcasts_one_book_update() {
+    let state = testing::test_app_state();
+    let mut ws_rx = state.ws_channel.subscribe();
+    let exchange = TestExchange::builder_with_state(state).start().await;
+    let (client, base_url) = (exchange.client(), &exchange.base_url);
+    let maker = exchange.register("maker", "secret").await.token;
+    let quoter = exchange.register("quoter", "secret").await.token;
+
+    let place = |token: &String, side: &str, price: i64, qty: u64| {
+        client
+            .post(format!("{}/orders", base_url))
+            .bearer_auth(token)
+            .json(&serde_json::json!({
+                "symbol": "BTCUSDT",
+                "price": scale_price(price),
+                "quantity": qty,
+                "side": side,
+                "tags": { "strategy": "mm" }
+            }))
+            .send()
+    };
+    place(&maker, "Sell", 50_100, 2).await.unwrap();
+    let quote: serde_json::Value = place(&quoter, "Buy", 50_000, 3)
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let quote_id = quote["id"].as_str().unwrap().to_string();
+    while ws_rx.try_recv().is_ok() {}
+
+    let replace = |token: &String, id: &str, quantity: u64| {
+        client
+            .post(format!("{}/orders/{}/replace?symbol=btcusdt", base_url, id))
+            .bearer_auth(token)
+            .json(&serde_json::json!({ "price": scale_price(50_100), "quantity": quantity }))
+            .send()
+    };
+    assert_eq!(
+        replace(&maker, &quote_id, 3)
+            .await
+            .unwrap()
+            .status()
+            .as_u16(),
+        403
+    );
+    assert_eq!(
+        replace(&quoter, &quote_id, 0)
+            .await
+            .unwrap()
+            .status()
+            .as_u16(),
+        400
+    );
+
+    // The replacement crosses the resting ask: it fills 2 and rests the remaining 1
+    let res = replace(&quoter, &quote_id, 3).await.unwrap();
+    assert_eq!(res.status().as_u16(), 200);
+    let json: serde_json::Value = res.json().await.unwrap();
+    assert_eq!(json["cancelled"]["id"], quote_id.as_str());
+    assert_eq!(json["cancelled"]["status"], "Cancelled");
+    let new_order = &json["report"]["order"];
+    assert_ne!(new_order["id"], quote_id.as_str());
+    assert_eq!(new_order["side"], "Buy");
+    assert_eq!(new_order["quantity"], 1);
+    assert_eq!(new_order["tags"]["strategy"], "mm");
+    assert_eq!(json["report"]["trades"].as_array().unwrap().len(), 1);
+    assert_eq!(json["report"]["trades"][0]["quantity"], 2);
+
+    let mut book_updates = 0;
+    while let Ok(msg) = ws_rx.try_recv() {
+        if matches!(msg, WsMessage::OrderBookUpdate { .. }) {
+            book_updates += 1;
+        }
+    }
+    assert_eq!(book_updates, 1);
+
+    let book: serde_json::Value = client
+        .get(format!("{}/book?symbol=BTCUSDT", base_url))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(book["bids"], serde_json::json!([[scale_price(50_100), 1]]));
+    assert_eq!(book["asks"], serde_json::json!([]));
+
+    // The original is gone: replacing it again places nothing
+    let res = replace(&quoter, &quote_id, 3).await.unwrap();
+    assert_eq!(res.status().as_u16(), 409);
+    let orders: serde_json::Value = client
+        .get(format!("{}/orders/me?symbol=BTCUSDT", base_url))
+        .bearer_auth(&quoter)
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(orders["items"].as_array().unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn orders_outside_the_price_band_or_lot_size_are_rejected() {
+    let state = testing::test_app_state();
+    state.symbol_configs.write().await.insert(
+        "BTCUSDT".to_string(),
+        SymbolConfig {
+            lot_size: 2,
+            price_band_bps: Some(100),
+            ..SymbolConfig::default()
+        },
+    );
+    let exchange = TestExchange::builder_with_state(state).start().await;
+    let (client, base_url) = (exchange.client(), &exchange.base_url);
+    let maker = exchange.register("maker", "secret").await.token;
+    let taker = exchange.register("taker", "secret").await.token;
+
+    let place = |token: &String, side: &str, order_type: &str, price: i64, qty: u64| {
+        client
+            .post(format!("{}/orders", base_url))
+            .bearer_auth(token)
+            .json(&serde_json::json!({
+                "symbol": "BTCUSDT",
+                "price": price,
+                "quantity": qty,
+                "side": side,
+                "order_type": order_type
+            }))
+            .send()
+    };
+    let error_code = |res: reqwest::Response| async move {
+        assert_eq!(res.status().as_u16(), 400);
+        let json: serde_json::Value = res.json().await.unwrap();
+        json["error_code"].as_str().unwrap().to_string()
+    };
+
+    assert_eq!(
+        error_code(place(&maker, "Sell", "Limit", 10_000, 3).await.unwrap()).await,
+        "INVALID_LOT_SIZE"
+    );
+    // No trade yet, so no band to check against
+    place(&maker, "Sell", "Limit", 10_000, 4).await.unwrap();
+    place(&taker, "Buy", "Market", 0, 2).await.unwrap();
+
+    // 1% around the last trade at 10_000
+    assert_eq!(
+        error_code(place(&maker, "Sell", "Limit", 10_101, 2).await.unwrap()).await,
+        "PRICE_OUTSIDE_BAND"
+    );
+    let res = client
+        .post(format!("{}/orders/preview", base_url))
+        .bearer_auth(&taker)
+        .json(&serde_json::json!({
+            "symbol": "BTCUSDT",
+            "price": 9_899,
+            "quantity": 2,
+            "side": "Buy"
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(error_code(res).await, "PRICE_OUTSIDE_BAND");
+    assert_eq!(
+        place(&maker, "Sell", "Limit", 10_100, 2)
+            .await
+            .unwrap()
+            .status()
+            .as_u16(),
+        200
+    );
+}
+
+#[tokio::test]
+async fn order_requests_fail_fast_with_engine_busy_while_the_book_lock_is_held() {
+    let mut state = testing::test_app_state();
+    state.request_timeout = Duration::from_millis(300);
+    let exchange = TestExchange::builder_with_state(state.clone())
+        .start()
+        .await;
+    let (client, base_url) = (exchange.client(), &exchange.base_url);
+    let token = exchange.register("trader", "secret").await.token;
+    let order = serde_json::json!({
+        "symbol": "BTCUSDT",
+        "price": scale_price(50_000),
+        "quantity": 1,
+        "side": "Buy"
+    });
+
+    let res = client
+        .post(format!("{}/orders", base_url))
+        .bearer_auth(&token)
+        .header("X-Request-Timeout-Ms", "soon")
+        .json(&order)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status().as_u16(), 400);
+
+    let resting: serde_json::Value = client
+        .post(format!("{}/orders", base_url))
+        .bearer_auth(&token)
+        .json(&order)
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let order_id = resting["id"].as_str().unwrap().to_string();
+
+    let book = state.orderbooks["BTCUSDT"].clone();
+    let held = book.write().await;
+
+    // The header shortens the budget; a larger value is capped at the configured 300ms.
+    for timeout_ms in ["50", "60000"] {
+        let res = tokio::time::timeout(
+            Duration::from_secs(2),
+            client
+                .post(format!("{}/orders", base_url))
+                .bearer_auth(&token)
+                .header("X-Request-Timeout-Ms", timeout_ms)
+                .json(&order)
+                .send(),
+        )
+        .await
+        .expect("create_order should give up instead of hanging")
+        .unwrap();
+        assert_eq!(res.status().as_u16(), 503);
+        let json: serde_json::Value = res.json().await.unwrap();
+        assert_eq!(json["error_code"], "ENGINE_BUSY");
+    }
+
+    let res = tokio::time::timeout(
+        Duration::from_secs(2),
+        client
+            .delete(format!("{}/orders/{}?symbol=BTCUSDT", base_url, order_id))
+            .bearer_auth(&token)
+            .send(),
+    )
+    .await
+    .expect("cancel_order should give up instead of hanging")
+    .unwrap();
+    assert_eq!(res.status().as_u16(), 503);
+    let json: serde_json::Value = res.json().await.unwrap();
+    assert_eq!(json["error_code"], "ENGINE_BUSY");
+
+    drop(held);
+    assert_eq!(
+        book.read()
+            .await
+            .get_order_by_id(order_id.parse().unwrap())
+            .unwrap()
+            .quantity,
+        1
+    );
+    let res = client
+        .delete(format!("{}/orders/{}?symbol=BTCUSDT", base_url, order_id))
+        .bearer_auth(&token)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status().as_u16(), 200);
+
+    let waits = state.lock_waits.snapshot();
+    let btc = &waits["BTCUSDT"];
+    assert_eq!(btc.timeouts, 3);
+    assert_eq!(btc.count, 5);
+    assert!(btc.sum_us >= 50_000 + 2 * 300_000);
+}
+
+#[tokio::test]
+async fn symbols_are_trimmed_uppercased_and_validated_on_every_route() {
+    let exchange = TestExchange::start().await;
+    let (client, base_url) = (exchange.client(), &exchange.base_url);
+    let token = exchange.register("alice", "secret").await.token;
+
+    let res = client
+        .post(format!("{}/orders", base_url))
+        .bearer_auth(&token)
+        .json(&serde_json::json!({
+            "symbol": "BTC/USDT",
+            "price": scale_price(50_000),
+            "quantity": 1,
+            "side": "Buy"
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status().as_u16(), 400);
+    let json: serde_json::Value = res.json().await.unwrap();
+    assert_eq!(json["error_code"], "INVALID_SYMBOL");
+    assert_eq!(
+        json["error"],
+        "Symbol 'BTC/USDT' contains '/'; only letters and digits are allowed"
+    );
+
+    let order: serde_json::Value = client
+        .post(format!("{}/orders", base_url))
+        .bearer_auth(&token)
+        .json(&serde_json::json!({
+            "symbol": " btcusdt ",
+            "price": scale_price(50_000),
+            "quantity": 1,
+            "side": "Buy"
+        }))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let order_id = order["id"].as_str().unwrap();
+
+    // Query strings go through the same parser
+    let res = client
+        .get(format!("{}/book?symbol=%20btcusdt%20", base_url))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status().as_u16(), 200);
+    let book: serde_json::Value = res.json().await.unwrap();
+    assert_eq!(book["bids"].as_array().unwrap().len(), 1);
+
+    let res = client
+        .get(format!("{}/book?symbol=BTC%2FUSDT", base_url))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status().as_u16(), 400);
+    assert!(
+        res.text()
+            .await
+            .unwrap()
+            .contains("Symbol 'BTC/USDT' contains '/'")
+    );
+
+    let res = client
+        .get(format!("{}/books?symbols=btcusdt,ETH-USDT", base_url))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status().as_u16(), 400);
+    let json: serde_json::Value = res.json().await.unwrap();
+    assert_eq!(json["error_code"], "INVALID_SYMBOL");
+
+    let res = client
+        .get(format!("{}/orders/me?symbol=", base_url))
+        .bearer_auth(&token)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status().as_u16(), 200);
+    let orders: serde_json::Value = res.json().await.unwrap();
+    assert_eq!(orders["items"].as_array().unwrap().len(), 1);
+
+    let res = client
+        .delete(format!("{}/orders/{}?symbol=+btcUSDT", base_url, order_id))
+        .bearer_auth(&token)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status().as_u16(), 200);
+}
+
+/// Rest a sell from `seller` and cross it with a buy from `buyer`: one trade at `price`.
+async fn trade_at(
+    client: &reqwest::Client,
+    base_url: &str,
+    (seller, buyer): (&str, &str),
+    price: i64,
+    quantity: u64,
+) {
+    for (token, side) in [(seller, "Sell"), (buyer, "Buy")] {
+        let res = client
+            .post(format!("{}/orders", base_url))
+            .bearer_auth(token)
+            .json(&serde_json::json!({
+                "symbol": "BTCUSDT",
+                "price": price,
+                "quantity": quantity,
+                "side": side
+            }))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(res.status().as_u16(), 200);
+    }
+}
+
+/// GET `path`, polling until its `total` reaches `expected` (the aggregator runs in a task).
+async fn history(
+    client: &reqwest::Client,
+    base_url: &str,
+    path: &str,
+    expected: u64,
+) -> serde_json::Value {
+    let mut json = serde_json::Value::Null;
+    for _ in 0..50 {
+        json = client
+            .get(format!("{}{}", base_url, path))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        if json["total"] == expected {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    json
+}
+
+#[tokio::test]
+async fn recent_klines_and_ticker_history_follow_a_stepped_clock() {
+    let t0 = "2026-01-01T00:00:00Z".parse::<DateTime<Utc>>().unwrap();
+    let at = |secs: i64| t0 + chrono::Duration::seconds(secs);
+    let clock = Arc::new(ManualClock::new(t0));
+    let mut state = testing::test_app_state();
+    state.clock = clock.clone();
+    let book = OrderBook::with_clock(clock.clone());
+    state
+        .orderbooks
+        .insert("BTCUSDT".to_string(), Arc::new(RwLock::new(book)));
+    state.market_data = Arc::new(MarketDataStore::new(Duration::from_secs(10 * 60)));
+    market_data::spawn_aggregator(
+        &state.tasks,
+        state.market_data.clone(),
+        &state.ws_channel,
+        None,
+    );
+    let exchange = TestExchange::builder_with_state(state).start().await;
+    let (client, base_url) = (exchange.client(), &exchange.base_url);
+    let alice = exchange.register("alice", "secret").await.token;
+    let bob = exchange.register("bob", "secret").await.token;
+    let users = (alice.as_str(), bob.as_str());
+
+    for (secs, price, quantity) in [(10, 100, 2), (40, 110, 1), (65, 90, 3)] {
+        clock.set(at(secs));
+        trade_at(client, base_url, users, price, quantity).await;
+    }
+    clock.set(at(90));
+
+    let klines = history(client, base_url, "/klines/recent?symbol=btcusdt", 2).await;
+    assert_eq!(klines["interval_secs"], 60);
+    let candles = klines["candles"].as_array().unwrap();
+    assert_eq!(candles[0]["open_time"], "2026-01-01T00:00:00Z");
+    assert_eq!(
+        (&candles[0]["open"], &candles[0]["high"], &candles[0]["low"]),
+        (&100.into(), &110.into(), &100.into())
+    );
+    assert_eq!(candles[0]["close"], 110);
+    assert_eq!(candles[0]["volume"], 3);
+    assert_eq!(candles[0]["trade_count"], 2);
+    assert_eq!(candles[0]["closed"], true);
+    assert_eq!(candles[1]["open_time"], "2026-01-01T00:01:00Z");
+    assert_eq!(candles[1]["close"], 90);
+    assert_eq!(candles[1]["closed"], false);
+
+    let page = history(
+        client,
+        base_url,
+        "/klines/recent?symbol=BTCUSDT&limit=1&offset=1",
+        2,
+    )
+    .await;
+    let candles = page["candles"].as_array().unwrap();
+    assert_eq!(candles.len(), 1);
+    assert_eq!(candles[0]["open_time"], "2026-01-01T00:01:00Z");
+
+    // The last minute from 00:01:30 covers the trades at 00:00:40 and 00:01:05
+    let ticker = history(
+        client,
+        base_url,
+        "/ticker/history?symbol=BTCUSDT&minutes=1",
+        2,
+    )
+    .await;
+    assert_eq!(ticker["since"], "2026-01-01T00:00:30Z");
+    let points = ticker["points"].as_array().unwrap();
+    assert_eq!(points[0]["timestamp"], "2026-01-01T00:00:40Z");
+    assert_eq!(points[1]["price"], 90);
+    assert_eq!(points[1]["quantity"], 3);
+
+    // Twelve minutes later everything before 00:02 has aged out of the ten minute history
+    clock.set(at(12 * 60));
+    trade_at(client, base_url, users, 120, 1).await;
+    let klines = history(client, base_url, "/klines/recent?symbol=BTCUSDT", 1).await;
+    assert_eq!(klines["candles"][0]["open_time"], "2026-01-01T00:12:00Z");
+    let path = "/ticker/history?symbol=BTCUSDT&minutes=10";
+    let ticker = history(client, base_url, path, 1).await;
+    assert_eq!(ticker["points"][0]["price"], 120);
+
+    let res = client
+        .get(format!(
+            "{}/ticker/history?symbol=BTCUSDT&minutes=11",
+            base_url
+        ))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn rolling_ticker_covers_the_last_day_to_the_minute() {
+    let t0 = "2026-01-01T00:00:30Z".parse::<DateTime<Utc>>().unwrap();
+    let clock = Arc::new(ManualClock::new(t0));
+    let mut state = testing::test_app_state();
+    state.clock = clock.clone();
+    let book = OrderBook::with_clock(clock.clone());
+    state
+        .orderbooks
+        .insert("BTCUSDT".to_string(), Arc::new(RwLock::new(book)));
+    market_data::spawn_aggregator(
+        &state.tasks,
+        state.market_data.clone(),
+        &state.ws_channel,
+        None,
+    );
+    let exchange = TestExchange::builder_with_state(state).start().await;
+    let (client, base_url) = (exchange.client(), &exchange.base_url);
+    let alice = exchange.register("alice", "secret").await.token;
+    let bob = exchange.register("bob", "secret").await.token;
+    let users = (alice.as_str(), bob.as_str());
+    let ticker = |expected: u64| {
+        let client = &client;
+        let base_url = &base_url;
+        async move {
+            let mut json = serde_json::Value::Null;
+            for _ in 0..50 {
+                json = client
+                    .get(format!("{}/ticker?symbol=btcusdt", base_url))
+                    .send()
+                    .await
+                    .unwrap()
+                    .json()
+                    .await
+                    .unwrap();
+                if json["trade_count"] == expected {
+                    break;
+                }
+                tokio::time::sleep(Duration::from_millis(20)).await;
+            }
+            json
+        }
+    };
+
+    let json = ticker(0).await;
+    assert!(json["high"].is_null() && json["low"].is_null());
+    assert_eq!(json["volume"], 0);
+
+    for (hours, price, quantity) in [(0, 100, 2), (12, 130, 1), (23, 90, 3)] {
+        clock.set(t0 + chrono::Duration::hours(hours));
+        trade_at(client, base_url, users, price, quantity).await;
+    }
+    let json = ticker(3).await;
+    assert_eq!(
+        (&json["high"], &json["low"], &json["volume"]),
+        (&130.into(), &90.into(), &6.into())
+    );
+    assert_eq!(json["window_start"], "2025-12-31T23:00:00Z");
+
+    // A day after the first trade's minute began, it has left the window
+    clock.set(t0 + chrono::Duration::hours(24) + chrono::Duration::seconds(30));
+    let json = ticker(2).await;
+    assert_eq!(json["window_start"], "2026-01-01T00:01:00Z");
+    assert_eq!(json["low"], 90);
+    assert_eq!(json["volume"], 4);
+}
+
+async fn get_json(
+    client: &reqwest::Client,
+    base_url: &str,
+    token: &str,
+    path: &str,
+) -> (u16, serde_json::Value) {
+    let res = client
+        .get(format!("{}{}", base_url, path))
+        .bearer_auth(token)
+        .send()
+        .await
+        .unwrap();
+    (res.status().as_u16(), res.json().await.unwrap())
+}
+
+/// Sorted top-level keys of every object in a JSON array, or in a page's items.
+fn keys_of(json: &serde_json::Value) -> Vec<Vec<String>> {
+    json.get("items")
+        .unwrap_or(json)
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|item| {
+            let mut keys: Vec<String> = item.as_object().unwrap().keys().cloned().collect();
+            keys.sort();
+            keys
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn list_endpoints_return_only_the_selected_fields() {
+    let exchange = TestExchange::start().await;
+    let (client, base_url) = (exchange.client(), &exchange.base_url);
+    let alice = exchange.register("alice", "secret").await.token;
+    let bob = exchange.register("bob", "secret").await.token;
+    for price in [100, 101, 102] {
+        trade_at(client, base_url, (&alice, &bob), price, 1).await;
+    }
+    // One resting order so /orders/me has something to list
+    let res = client
+        .post(format!("{}/orders", base_url))
+        .bearer_auth(&alice)
+        .json(&serde_json::json!({
+            "symbol": "BTCUSDT",
+            "price": 500,
+            "quantity": 1,
+            "side": "Sell"
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status().as_u16(), 200);
+
+    // Without a selection every field is returned; each type's field list is complete
+    let (status, trades) = get_json(client, base_url, &bob, "/trades?symbol=BTCUSDT").await;
+    assert_eq!(status, 200);
+    let mut all = TradeDto::FIELDS.to_vec();
+    all.sort();
+    assert_eq!(keys_of(&trades)[0], all);
+    let (_, positions) = get_json(client, base_url, &bob, "/positions").await;
+    let mut all = PositionDto::FIELDS.to_vec();
+    all.sort();
+    assert_eq!(keys_of(&positions)[0], all);
+
+    // The selection applies to each item after pagination; spaces and empty entries are ignored
+    let path = "/trades?symbol=BTCUSDT&limit=2&from_seq=2&fields=trade_seq,%20price,";
+    let (status, page) = get_json(client, base_url, &bob, path).await;
+    assert_eq!(status, 200);
+    assert_eq!(
+        page["items"],
+        serde_json::json!([
+            { "trade_seq": 2, "price": 101 },
+            { "trade_seq": 3, "price": 102 }
+        ])
+    );
+    assert_eq!(page["has_more"], false);
+
+    let path = "/positions?fields=symbol,quantity";
+    let (_, positions) = get_json(client, base_url, &bob, path).await;
+    assert_eq!(
+        positions,
+        serde_json::json!([{ "symbol": "BTCUSDT", "quantity": 3 }])
+    );
+
+    let path = "/orders/me?fields=price,status";
+    let (status, orders) = get_json(client, base_url, &alice, path).await;
+    assert_eq!(status, 200);
+    assert_eq!(keys_of(&orders), vec![vec!["price", "status"]]);
+    let mut all = OrderDto::FIELDS.to_vec();
+    all.sort();
+    let (_, orders) = get_json(client, base_url, &alice, "/orders/me").await;
+    assert_eq!(keys_of(&orders)[0], all);
+
+    for (path, message) in [
+        (
+            "/trades?symbol=BTCUSDT&fields=price,secret",
+            "Unknown field 'secret'",
+        ),
+        ("/positions?fields=", "fields must name at least one field"),
+        (
+            "/orders/me?fields=,,",
+            "fields must name at least one field",
+        ),
+    ] {
+        let (status, json) = get_json(client, base_url, &alice, path).await;
+        assert_eq!(status, 400, "{}", path);
+        assert!(
+            json["error"].as_str().unwrap().starts_with(message),
+            "{}: {}",
+            path,
+            json
+        );
+    }
+}
+
+#[tokio::test]
+async fn ingress_tickets_take_turns_in_admission_order_even_when_given_up_out_of_turn() {
+    let ingress = Arc::new(Ingress::default());
+    let first = ingress.admit("BTCUSDT");
+    let second = ingress.admit("BTCUSDT");
+    let third = ingress.admit("BTCUSDT");
+    let other = ingress.admit("ETHUSDT");
+    assert_eq!((first.seq(), second.seq(), third.seq()), (0, 1, 2));
+    assert_eq!(other.seq(), 0);
+    tokio::time::timeout(Duration::from_millis(100), other.turn())
+        .await
+        .expect("symbols queue independently");
+
+    let waiting = tokio::spawn(async move { third.turn().await });
+    drop(second);
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert!(!waiting.is_finished(), "the first ticket is still ahead");
+    drop(first);
+    tokio::time::timeout(Duration::from_secs(1), waiting)
+        .await
+        .expect("third ticket's turn once both earlier tickets are through")
+        .unwrap();
+}
+
+#[tokio::test]
+async fn idle_ingress_queues_are_dropped_and_their_sequence_carries_on() {
+    let ingress = Arc::new(Ingress::default());
+    let first = ingress.admit("BTCUSDT");
+    let second = ingress.admit("BTCUSDT");
+    drop(first);
+    assert_eq!(
+        ingress.queued_symbols(),
+        1,
+        "the second ticket is still out"
+    );
+    drop(second);
+    assert_eq!(ingress.queued_symbols(), 0);
+    assert_eq!(ingress.admit("BTCUSDT").seq(), 2);
+    assert_eq!(ingress.queued_symbols(), 0);
+}
+
+#[tokio::test]
+async fn order_entry_for_unknown_symbols_opens_no_ingress_queue() {
+    let state = testing::test_app_state();
+    let ingress = state.ingress.clone();
+    let exchange = TestExchange::builder_with_state(state).start().await;
+    let (client, base_url) = (exchange.client(), &exchange.base_url);
+    let token = exchange.register("alice", "secret").await.token;
+
+    for i in 0..20 {
+        let symbol = format!("NOPE{}USDT", i);
+        let res = client
+            .post(format!("{}/orders", base_url))
+            .bearer_auth(&token)
+            .json(&serde_json::json!({
+                "symbol": symbol,
+                "price": 100,
+                "quantity": 1,
+                "side": "Buy",
+                "order_type": "Limit"
+            }))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(res.status().as_u16(), 404);
+        assert!(res.headers().get("x-ingress-seq").is_none());
+        let res = client
+            .delete(format!(
+                "{}/orders/{}?symbol={}",
+                base_url,
+                uuid::Uuid::new_v4(),
+                symbol
+            ))
+            .bearer_auth(&token)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(res.status().as_u16(), 404);
+    }
+    assert_eq!(ingress.queued_symbols(), 0);
+}
+
+#[tokio::test]
+async fn unknown_symbols_list_the_known_ones_and_empty_books_are_not_errors() {
+    let mut state = testing::test_app_state();
+    state.orderbooks.insert(
+        "ETHUSDT".to_string(),
+        Arc::new(RwLock::new(OrderBook::new())),
+    );
+    let exchange = TestExchange::builder_with_state(state).start().await;
+    let (client, base_url) = (exchange.client(), &exchange.base_url);
+    let token = exchange.register("alice", "secret").await.token;
+
+    let res = client
+        .get(format!("{}/book?symbol=btcustd", base_url))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status().as_u16(), 404);
+    let json: serde_json::Value = res.json().await.unwrap();
+    assert_eq!(
+        json,
+        serde_json::json!({
+            "error": "Symbol 'BTCUSTD' not found",
+            "code": 404,
+            "error_code": "UNKNOWN_SYMBOL",
+            "known_symbols": ["BTCUSDT", "ETHUSDT"],
+            "known_symbol_count": 2,
+            "did_you_mean": "BTCUSDT"
+        })
+    );
+
+    // Same payload from order entry; nothing is close enough to suggest
+    let res = client
+        .post(format!("{}/orders", base_url))
+        .bearer_auth(&token)
+        .json(&serde_json::json!({
+            "symbol": "DOGEUSDT",
+            "price": 100,
+            "quantity": 1,
+            "side": "Buy"
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status().as_u16(), 404);
+    let json: serde_json::Value = res.json().await.unwrap();
+    assert_eq!(json["error_code"], "UNKNOWN_SYMBOL");
+    assert_eq!(
+        json["known_symbols"],
+        serde_json::json!(["BTCUSDT", "ETHUSDT"])
+    );
+    assert!(json.get("did_you_mean").is_none());
+
+    let json: serde_json::Value = client
+        .get(format!("{}/book?symbol=ETHUSDT", base_url))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(
+        json,
+        serde_json::json!({
+            "bids": [],
+            "asks": [],
+            "is_empty": true,
+            "meta": {"price_scale": 100_000_000, "qty_scale": 1, "tick_size": 1, "lot_size": 1}
+        })
+    );
+}
+
+#[test]
+fn symbol_hints_suggest_the_nearest_symbol_and_count_long_lists() {
+    assert_eq!(edit_distance("BTCUSDT", "BTCUSDT"), 0);
+    assert_eq!(edit_distance("BTCUSTD", "BTCUSDT"), 2);
+    assert_eq!(edit_distance("BTCUSD", "BTCUSDT"), 1);
+    assert_eq!(edit_distance("", "ETH"), 3);
+
+    let hint = SymbolHint::new("ETHUSD", ["ETHUSDC", "ETHUSDT", "BTCUSDT"]);
+    assert_eq!(hint.did_you_mean.as_deref(), Some("ETHUSDC"));
+    assert_eq!(hint.known_symbol_count, 3);
+
+    let many: Vec<String> = (0..=MAX_LISTED_SYMBOLS)
+        .map(|i| format!("SYM{}", i))
+        .collect();
+    let hint = SymbolHint::new("SYM", many.iter().map(String::as_str));
+    assert_eq!(hint.known_symbols, None);
+    assert_eq!(hint.known_symbol_count, MAX_LISTED_SYMBOLS + 1);
+    assert_eq!(hint.did_you_mean.as_deref(), Some("SYM0"));
+}
+
+#[tokio::test]
+async fn validate_lists_every_violation_and_agrees_with_order_entry() {
+    let state = testing::test_app_state();
+    state.symbol_configs.write().await.insert(
+        "BTCUSDT".to_string(),
+        SymbolConfig {
+            tick_size: 5,
+            lot_size: 2,
+            price_band_bps: Some(100),
+            ..SymbolConfig::default()
+        },
+    );
+    let exchange = TestExchange::builder_with_state(state).start().await;
+    let (client, base_url) = (exchange.client(), &exchange.base_url);
+    let maker = exchange.register("maker", "secret").await.token;
+    let taker = exchange.register("taker", "secret").await.token;
+
+    let post = |path: &'static str, token: &String, body: serde_json::Value| {
+        client
+            .post(format!("{}/{}", base_url, path))
+            .bearer_auth(token)
+            .json(&body)
+            .send()
+    };
+    let order = |side: &str, order_type: &str, price: i64, qty: u64| {
+        serde_json::json!({
+            "symbol": "BTCUSDT",
+            "price": price,
+            "quantity": qty,
+            "side": side,
+            "order_type": order_type
+        })
+    };
+
+    post("orders", &maker, order("Sell", "Limit", 10_000, 4))
+        .await
+        .unwrap();
+    post("orders", &taker, order("Buy", "Market", 0, 2))
+        .await
+        .unwrap();
+
+    // Off tick, odd lot and outside the 1% band around the last trade at 10_000
+    let bad = order("Sell", "Limit", 10_201, 3);
+    let res = post("orders/validate", &maker, bad.clone()).await.unwrap();
+    assert_eq!(res.status().as_u16(), 400);
+    let json: serde_json::Value = res.json().await.unwrap();
+    assert_eq!(json["valid"], false);
+    assert_eq!(json["error_code"], "VALIDATION_FAILED");
+    let codes: Vec<&str> = json["violations"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|v| v["error_code"].as_str().unwrap())
+        .collect();
+    assert_eq!(
+        codes,
+        [
+            "INVALID_LOT_SIZE",
+            "INVALID_TICK_SIZE",
+            "PRICE_OUTSIDE_BAND"
+        ]
+    );
+    // Order entry reports the first of them, with the same body
+    let res = post("orders", &maker, bad).await.unwrap();
+    assert_eq!(res.status().as_u16(), 400);
+    let placed: serde_json::Value = res.json().await.unwrap();
+    assert_eq!(placed, json["violations"][0]);
+
+    // Request-level checks are aggregated with the rest
+    let mut tagged = order("Sell", "Limit", 10_001, 2);
+    tagged["source"] = serde_json::json!("Admin");
+    let json: serde_json::Value = post("orders/validate", &maker, tagged)
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(json["violations"].as_array().unwrap().len(), 2);
+    assert_eq!(json["violations"][1]["error_code"], "INVALID_TICK_SIZE");
+
+    let res = post("orders/validate", &maker, order("Sell", "Limit", 10, 2))
+        .await
+        .unwrap();
+    let json: serde_json::Value = res.json().await.unwrap();
+    let banded = json["violations"].as_array().unwrap();
+    assert_eq!(banded.len(), 1);
+    assert_eq!(banded[0]["error_code"], "PRICE_OUTSIDE_BAND");
+    let mut missing = order("Sell", "Limit", 10_000, 2);
+    missing["symbol"] = serde_json::json!("DOGEUSDT");
+    let json: serde_json::Value = post("orders/validate", &maker, missing)
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(json["violations"][0]["error_code"], "UNKNOWN_SYMBOL");
+
+    // Valid orders pass with warnings about matching, and then place
+    let json: serde_json::Value = post("orders/validate", &taker, order("Buy", "Limit", 10_000, 2))
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(json["valid"], true);
+    assert_eq!(json["warnings"][0]["code"], "CROSSES_BOOK");
+    let json: serde_json::Value = post("orders/validate", &maker, order("Sell", "Market", 0, 2))
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(json["warnings"][0]["code"], "NO_LIQUIDITY");
+    let res = post("orders/validate", &maker, order("Sell", "Limit", 10_050, 2))
+        .await
+        .unwrap();
+    assert_eq!(res.status().as_u16(), 200);
+    let json: serde_json::Value = res.json().await.unwrap();
+    assert_eq!(json["warnings"], serde_json::json!([]));
+    let res = post("orders", &maker, order("Sell", "Limit", 10_050, 2))
+        .await
+        .unwrap();
+    assert_eq!(res.status().as_u16(), 200);
+}
@@ -0,0 +1,194 @@
+//! Cursor pagination shared by `/trades`, `/trades/me` and `/orders/me`.
+
+use reqwest::StatusCode;
+use rust_exchange::api::pagination::{self, CursorError};
+use rust_exchange::testing::{OrderRequest, TEST_SYMBOL, TestExchange, Token};
+use rust_exchange::types::order::OrderSide;
+use serde_json::Value;
+
+const SECRET: &[u8] = b"pagination-test-secret";
+
+#[test]
+fn cursors_round_trip_only_for_their_listing_and_secret() {
+    let key = (7u64, "abc".to_string());
+    let cursor = pagination::encode_cursor(SECRET, "trades", &key);
+    assert_eq!(
+        pagination::decode_cursor::<(u64, String)>(SECRET, "trades", &cursor),
+        Ok(key)
+    );
+    assert_eq!(
+        pagination::decode_cursor::<(u64, String)>(SECRET, "orders/me", &cursor),
+        Err(CursorError::Forged)
+    );
+    assert_eq!(
+        pagination::decode_cursor::<(u64, String)>(b"other-secret", "trades", &cursor),
+        Err(CursorError::Forged)
+    );
+
+    // A re-encoded payload keeps the old MAC
+    let (_, tag) = cursor.split_once('.').unwrap();
+    let other = pagination::encode_cursor(SECRET, "trades", &(8u64, "abc"));
+    let (payload, _) = other.split_once('.').unwrap();
+    let spliced = format!("{payload}.{tag}");
+    assert_eq!(
+        pagination::decode_cursor::<(u64, String)>(SECRET, "trades", &spliced),
+        Err(CursorError::Forged)
+    );
+
+    for garbage in ["", "no-dot", "a.b", "!!.!!"] {
+        assert_eq!(
+            pagination::decode_cursor::<(u64, String)>(SECRET, "trades", garbage),
+            Err(CursorError::Malformed),
+            "{garbage}"
+        );
+    }
+}
+
+/// Five single-lot trades at 100 on `TEST_SYMBOL`, taken by `taker`.
+async fn five_trades() -> (TestExchange, Token) {
+    let mut builder = TestExchange::builder().user("maker", "secret");
+    for _ in 0..5 {
+        builder = builder.order(TEST_SYMBOL, "maker", OrderSide::Sell, 100, 1);
+    }
+    let exchange = builder.start().await;
+    let taker = exchange.register("taker", "secret").await;
+    exchange
+        .place_order(
+            &taker,
+            &OrderRequest::limit(TEST_SYMBOL, OrderSide::Buy, 100, 5),
+        )
+        .await
+        .unwrap();
+    (exchange, taker)
+}
+
+/// Follow `Link` headers from `path`, returning each page's body.
+async fn walk(exchange: &TestExchange, token: &Token, path: &str) -> Vec<Value> {
+    let mut pages = Vec::new();
+    let mut next = Some(exchange.url(path));
+    while let Some(url) = next.take() {
+        let res = exchange
+            .client()
+            .get(url)
+            .bearer_auth(&token.token)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        let link = res
+            .headers()
+            .get("link")
+            .map(|v| v.to_str().unwrap().to_string());
+        let page: Value = res.json().await.unwrap();
+        assert_eq!(page["has_more"].as_bool().unwrap(), link.is_some());
+        assert_eq!(page["next_cursor"].is_string(), link.is_some());
+        next = link.map(|link| {
+            let target = link
+                .strip_prefix('<')
+                .and_then(|l| l.strip_suffix(">; rel=\"next\""))
+                .unwrap();
+            assert!(target.contains(page["next_cursor"].as_str().unwrap()));
+            exchange.url(target)
+        });
+        pages.push(page);
+        assert!(pages.len() <= 10, "pagination does not terminate");
+    }
+    pages
+}
+
+fn field(pages: &[Value], name: &str) -> Vec<Vec<Value>> {
+    pages
+        .iter()
+        .map(|p| {
+            p["items"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|item| item[name].clone())
+                .collect()
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn public_trades_walk_newest_first_across_pages() {
+    let (exchange, taker) = five_trades().await;
+    let pages = walk(
+        &exchange,
+        &taker,
+        &format!("/trades?symbol={}&limit=2", TEST_SYMBOL),
+    )
+    .await;
+    assert_eq!(
+        field(&pages, "trade_seq"),
+        vec![
+            vec![Value::from(5), Value::from(4)],
+            vec![Value::from(3), Value::from(2)],
+            vec![Value::from(1)],
+        ]
+    );
+
+    // from_seq pages forward, oldest first
+    let pages = walk(
+        &exchange,
+        &taker,
+        &format!("/trades?symbol={}&from_seq=2&limit=3", TEST_SYMBOL),
+    )
+    .await;
+    assert_eq!(
+        field(&pages, "trade_seq"),
+        vec![
+            vec![Value::from(2), Value::from(3), Value::from(4)],
+            vec![Value::from(5)],
+        ]
+    );
+}
+
+#[tokio::test]
+async fn own_trades_and_orders_walk_without_gaps_or_repeats() {
+    let (exchange, taker) = five_trades().await;
+    let pages = walk(
+        &exchange,
+        &taker,
+        &format!("/trades/me?symbol={}&limit=2", TEST_SYMBOL),
+    )
+    .await;
+    let seqs: Vec<Value> = field(&pages, "trade_seq").concat();
+    assert_eq!(seqs, (1..=5).rev().map(Value::from).collect::<Vec<_>>());
+    assert_eq!(pages.len(), 3);
+
+    for price in [90, 91, 92, 93] {
+        exchange
+            .place_order(
+                &taker,
+                &OrderRequest::limit(TEST_SYMBOL, OrderSide::Buy, price, 1),
+            )
+            .await
+            .unwrap();
+    }
+    let pages = walk(&exchange, &taker, "/orders/me?limit=3").await;
+    let prices: Vec<Value> = field(&pages, "price").concat();
+    assert_eq!(prices, [93, 92, 91, 90].map(Value::from));
+    assert_eq!(pages.len(), 2);
+}
+
+#[tokio::test]
+async fn cursors_are_rejected_outside_their_listing() {
+    let (exchange, taker) = five_trades().await;
+    let first = exchange
+        .get(&taker, &format!("/trades?symbol={}&limit=1", TEST_SYMBOL))
+        .await
+        .unwrap();
+    let cursor = first["next_cursor"].as_str().unwrap();
+
+    for path in [
+        format!("/trades/me?cursor={}", cursor),
+        format!("/orders/me?cursor={}", cursor),
+        format!("/trades?symbol={}&cursor=not-a-cursor", TEST_SYMBOL),
+        format!("/trades?symbol={}&cursor={}x", TEST_SYMBOL, cursor),
+    ] {
+        let err = exchange.get(&taker, &path).await.unwrap_err();
+        assert_eq!(err.status, StatusCode::BAD_REQUEST, "{}", path);
+        assert_eq!(err.body["error_code"], "INVALID_CURSOR", "{}", path);
+    }
+}
@@ -0,0 +1,117 @@
+//! Phase timing of order placement: the `X-Debug-Timings` breakdown a request can ask for, and
+//! the per-phase histograms `/admin/metrics` exports.
+
+use rust_exchange::api::phase_timer::PhaseTimings;
+use rust_exchange::testing::{self, OrderRequest, TEST_SYMBOL, TestExchange, Token};
+use rust_exchange::types::order::OrderSide;
+use std::sync::Arc;
+
+const PHASES: [&str; 5] = ["lock_wait", "match", "positions", "broadcast", "persist"];
+
+/// A harness exchange with `DEBUG_TIMINGS` as given, and an admin token.
+async fn start(debug_timings: bool) -> (TestExchange, Token) {
+    let mut state = testing::test_app_state();
+    state.phase_timings = Arc::new(PhaseTimings::new(debug_timings));
+    let exchange = TestExchange::builder_with_state(state)
+        .admin()
+        .start()
+        .await;
+    let admin = exchange.admin_token();
+    (exchange, admin)
+}
+
+/// `X-Debug-Timings` of an order placed by `token` that asked for it, if it came back.
+async fn debug_timings(exchange: &TestExchange, token: &Token) -> Option<String> {
+    let res = exchange
+        .client()
+        .post(exchange.url("/orders"))
+        .bearer_auth(&token.token)
+        .header("X-Debug-Timings", "1")
+        .json(&OrderRequest::limit(TEST_SYMBOL, OrderSide::Buy, 100, 1))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status().as_u16(), 200);
+    res.headers()
+        .get("x-debug-timings")
+        .map(|v| v.to_str().unwrap().to_string())
+}
+
+fn assert_all_phases(header: &str) {
+    let phases: Vec<&str> = header
+        .split(", ")
+        .map(|entry| {
+            let (phase, duration) = entry.split_once(";dur=").unwrap();
+            assert!(duration.parse::<f64>().unwrap() >= 0.0, "{}", header);
+            phase
+        })
+        .collect();
+    assert_eq!(phases, PHASES);
+}
+
+#[tokio::test]
+async fn the_breakdown_header_is_only_returned_to_admins_unless_debug_timings_is_set() {
+    let (exchange, admin) = start(false).await;
+    let user = exchange.register("user", "password123").await;
+    assert_eq!(debug_timings(&exchange, &user).await, None);
+    assert_all_phases(&debug_timings(&exchange, &admin).await.unwrap());
+    // Not asked for, not sent
+    let res = exchange
+        .client()
+        .post(exchange.url("/orders"))
+        .bearer_auth(&admin.token)
+        .json(&OrderRequest::limit(TEST_SYMBOL, OrderSide::Buy, 100, 1))
+        .send()
+        .await
+        .unwrap();
+    assert!(!res.headers().contains_key("x-debug-timings"));
+
+    let (exchange, _) = start(true).await;
+    let user = exchange.register("user", "password123").await;
+    assert_all_phases(&debug_timings(&exchange, &user).await.unwrap());
+}
+
+#[tokio::test]
+async fn every_placement_under_load_lands_in_each_phase_histogram() {
+    let (exchange, admin) = start(false).await;
+    let maker = exchange.register("maker", "password123").await;
+    let taker = exchange.register("taker", "password123").await;
+
+    let sell = OrderRequest::limit(TEST_SYMBOL, OrderSide::Sell, 100, 1);
+    let buy = OrderRequest::limit(TEST_SYMBOL, OrderSide::Buy, 100, 1);
+    let orders = (0..20).map(|i| match i % 2 {
+        0 => exchange.place_order(&maker, &sell),
+        _ => exchange.place_order(&taker, &buy),
+    });
+    for placed in futures_util::future::join_all(orders).await {
+        placed.unwrap();
+    }
+
+    let body = exchange
+        .client()
+        .get(exchange.url("/admin/metrics"))
+        .bearer_auth(&admin.token)
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(body.contains("# TYPE exchange_request_phase_seconds histogram"));
+    for phase in PHASES {
+        let labels = format!("operation=\"place_order\",phase=\"{}\"", phase);
+        assert!(
+            body.contains(&format!(
+                "exchange_request_phase_seconds_count{{{}}} 20",
+                labels
+            )),
+            "{} missing from\n{}",
+            phase,
+            body
+        );
+        assert!(body.contains(&format!(
+            "exchange_request_phase_seconds_bucket{{{},le=\"+Inf\"}} 20",
+            labels
+        )));
+    }
+}
@@ -1,7 +1,17 @@
-//! Position tracking integration tests: update_position, get_positions, unrealized_pnl.
+//! Position tracking integration tests: update_position, reverse_position, get_positions,
+//! unrealized_pnl, scaled_unrealized_pnl.
 
-use rust_exchange::positions::{SharedPositions, get_positions, unrealized_pnl, update_position};
-use rust_exchange::types::order::OrderSide;
+use chrono::{DateTime, Utc};
+use proptest::prelude::*;
+use rust_exchange::positions::{
+    RealizedPnl, SharedPositions, apply_fill, apply_trades, get_positions, realized_pnl,
+    reverse_fill, reverse_position, scaled_unrealized_pnl, unrealized_pnl, update_position,
+};
+use rust_exchange::types::money::MoneyError;
+use rust_exchange::types::order::{MAX_ORDER_QUANTITY, OrderSide};
+use rust_exchange::types::position::Position;
+use rust_exchange::types::symbol::Symbol;
+use rust_exchange::types::trade::Trade;
 use std::collections::HashMap;
 use std::sync::Arc;
 use tokio::sync::RwLock;
@@ -11,6 +21,14 @@ fn scale_price(p: i64) -> i64 {
     p * 100_000_000
 }
 
+fn btc() -> Symbol {
+    "BTCUSDT".parse().unwrap()
+}
+
+fn eth() -> Symbol {
+    "ETHUSDT".parse().unwrap()
+}
+
 fn fresh_store() -> SharedPositions {
     Arc::new(RwLock::new(HashMap::new()))
 }
@@ -22,7 +40,16 @@ async fn update_position_new_position() {
     let price = scale_price(50_000);
     let qty = 10u64;
 
-    update_position(&store, user_id, "BTCUSDT", OrderSide::Buy, price, qty).await;
+    update_position(
+        &store,
+        user_id,
+        &btc(),
+        OrderSide::Buy,
+        price,
+        qty,
+        Utc::now(),
+    )
+    .await;
 
     let positions = get_positions(&store, user_id, None).await;
     assert_eq!(positions.len(), 1);
@@ -39,8 +66,8 @@ async fn update_position_add_weighted_average() {
     let p1 = scale_price(50_000);
     let p2 = scale_price(52_000);
 
-    update_position(&store, user_id, "BTCUSDT", OrderSide::Buy, p1, 10).await;
-    update_position(&store, user_id, "BTCUSDT", OrderSide::Buy, p2, 5).await;
+    update_position(&store, user_id, &btc(), OrderSide::Buy, p1, 10, Utc::now()).await;
+    update_position(&store, user_id, &btc(), OrderSide::Buy, p2, 5, Utc::now()).await;
 
     let positions = get_positions(&store, user_id, None).await;
     assert_eq!(positions.len(), 1);
@@ -55,8 +82,26 @@ async fn update_position_reduce_position() {
     let user_id = Uuid::new_v4();
     let price = scale_price(50_000);
 
-    update_position(&store, user_id, "BTCUSDT", OrderSide::Buy, price, 10).await;
-    update_position(&store, user_id, "BTCUSDT", OrderSide::Sell, price, 4).await;
+    update_position(
+        &store,
+        user_id,
+        &btc(),
+        OrderSide::Buy,
+        price,
+        10,
+        Utc::now(),
+    )
+    .await;
+    update_position(
+        &store,
+        user_id,
+        &btc(),
+        OrderSide::Sell,
+        price,
+        4,
+        Utc::now(),
+    )
+    .await;
 
     let positions = get_positions(&store, user_id, None).await;
     assert_eq!(positions.len(), 1);
@@ -70,8 +115,26 @@ async fn update_position_close_position_removed() {
     let user_id = Uuid::new_v4();
     let price = scale_price(50_000);
 
-    update_position(&store, user_id, "BTCUSDT", OrderSide::Buy, price, 10).await;
-    update_position(&store, user_id, "BTCUSDT", OrderSide::Sell, price, 10).await;
+    update_position(
+        &store,
+        user_id,
+        &btc(),
+        OrderSide::Buy,
+        price,
+        10,
+        Utc::now(),
+    )
+    .await;
+    update_position(
+        &store,
+        user_id,
+        &btc(),
+        OrderSide::Sell,
+        price,
+        10,
+        Utc::now(),
+    )
+    .await;
 
     let positions = get_positions(&store, user_id, None).await;
     assert!(positions.is_empty());
@@ -83,15 +146,33 @@ async fn get_positions_filter_by_symbol() {
     let user_id = Uuid::new_v4();
     let price = scale_price(50_000);
 
-    update_position(&store, user_id, "BTCUSDT", OrderSide::Buy, price, 5).await;
-    update_position(&store, user_id, "ETHUSDT", OrderSide::Buy, price, 3).await;
+    update_position(
+        &store,
+        user_id,
+        &btc(),
+        OrderSide::Buy,
+        price,
+        5,
+        Utc::now(),
+    )
+    .await;
+    update_position(
+        &store,
+        user_id,
+        &eth(),
+        OrderSide::Buy,
+        price,
+        3,
+        Utc::now(),
+    )
+    .await;
 
-    let btc_only = get_positions(&store, user_id, Some("BTCUSDT")).await;
+    let btc_only = get_positions(&store, user_id, Some(&btc())).await;
     assert_eq!(btc_only.len(), 1);
     assert_eq!(btc_only[0].symbol, "BTCUSDT");
     assert_eq!(btc_only[0].quantity, 5);
 
-    let eth_only = get_positions(&store, user_id, Some("ETHUSDT")).await;
+    let eth_only = get_positions(&store, user_id, Some(&eth())).await;
     assert_eq!(eth_only.len(), 1);
     assert_eq!(eth_only[0].symbol, "ETHUSDT");
 
@@ -106,11 +187,11 @@ async fn unrealized_pnl_long() {
     let avg = scale_price(50_000);
     let current = scale_price(52_000);
 
-    update_position(&store, user_id, "BTCUSDT", OrderSide::Buy, avg, 10).await;
+    update_position(&store, user_id, &btc(), OrderSide::Buy, avg, 10, Utc::now()).await;
     let positions = get_positions(&store, user_id, None).await;
     let pos = &positions[0];
 
-    let pnl = unrealized_pnl(pos, current);
+    let pnl = unrealized_pnl(pos, current).unwrap();
     let expected = (current - avg) * 10;
     assert_eq!(pnl, expected);
     assert!(pnl > 0);
@@ -123,13 +204,557 @@ async fn unrealized_pnl_short() {
     let avg = scale_price(50_000);
     let current = scale_price(48_000);
 
-    update_position(&store, user_id, "BTCUSDT", OrderSide::Sell, avg, 10).await;
+    update_position(
+        &store,
+        user_id,
+        &btc(),
+        OrderSide::Sell,
+        avg,
+        10,
+        Utc::now(),
+    )
+    .await;
     let positions = get_positions(&store, user_id, None).await;
     let pos = &positions[0];
     assert!(pos.quantity < 0);
 
-    let pnl = unrealized_pnl(pos, current);
+    let pnl = unrealized_pnl(pos, current).unwrap();
     let expected = (current - avg) * pos.quantity;
     assert_eq!(pnl, expected);
     assert!(pnl > 0);
 }
+
+// --- Batched application ---
+
+fn trade(maker: Uuid, taker: Uuid, price: i64, quantity: u64) -> Trade {
+    Trade {
+        id: Uuid::new_v4(),
+        trade_seq: 0,
+        maker_order_id: Uuid::new_v4(),
+        taker_order_id: Uuid::new_v4(),
+        maker_user_id: maker,
+        taker_user_id: taker,
+        price,
+        quantity,
+        timestamp: Utc::now(),
+        maker_queue_rank: Some(0),
+    }
+}
+
+async fn apply_sequentially(
+    store: &SharedPositions,
+    taker: Uuid,
+    taker_side: OrderSide,
+    trades: &[Trade],
+) {
+    let maker_side = match taker_side {
+        OrderSide::Buy => OrderSide::Sell,
+        OrderSide::Sell => OrderSide::Buy,
+    };
+    for t in trades {
+        update_position(
+            store,
+            t.maker_user_id,
+            &btc(),
+            maker_side,
+            t.price,
+            t.quantity,
+            t.timestamp,
+        )
+        .await;
+        update_position(
+            store,
+            taker,
+            &btc(),
+            taker_side,
+            t.price,
+            t.quantity,
+            t.timestamp,
+        )
+        .await;
+    }
+}
+
+#[tokio::test]
+async fn apply_trades_flips_taker_position() {
+    let store = fresh_store();
+    let taker = Uuid::new_v4();
+    let maker = Uuid::new_v4();
+    update_position(
+        &store,
+        taker,
+        &btc(),
+        OrderSide::Buy,
+        scale_price(100),
+        3,
+        Utc::now(),
+    )
+    .await;
+
+    let trades = vec![
+        trade(maker, taker, scale_price(110), 2),
+        trade(maker, taker, scale_price(120), 3),
+    ];
+    apply_trades(&store, taker, OrderSide::Sell, &btc(), &trades).await;
+
+    let taker_pos = get_positions(&store, taker, None).await;
+    assert_eq!(taker_pos[0].quantity, -2);
+    assert_eq!(taker_pos[0].average_price, scale_price(120));
+    let maker_pos = get_positions(&store, maker, None).await;
+    assert_eq!(maker_pos[0].quantity, 5);
+}
+
+#[tokio::test]
+async fn apply_trades_realizes_pnl_per_fill_of_a_partial_close() {
+    let store = fresh_store();
+    let taker = Uuid::new_v4();
+    let (first, second) = (Uuid::new_v4(), Uuid::new_v4());
+    update_position(
+        &store,
+        taker,
+        &btc(),
+        OrderSide::Buy,
+        scale_price(100),
+        5,
+        Utc::now(),
+    )
+    .await;
+    update_position(
+        &store,
+        second,
+        &btc(),
+        OrderSide::Sell,
+        scale_price(130),
+        4,
+        Utc::now(),
+    )
+    .await;
+
+    // Sell 4 of the 5 across two fills; the second maker covers part of a short
+    let trades = vec![
+        trade(first, taker, scale_price(110), 2),
+        trade(second, taker, scale_price(105), 2),
+    ];
+    let applied = apply_trades(&store, taker, OrderSide::Sell, &btc(), &trades).await;
+
+    assert_eq!(
+        applied.realized,
+        vec![
+            RealizedPnl {
+                maker: 0,
+                taker: scale_price(10) * 2,
+            },
+            RealizedPnl {
+                maker: scale_price(25) * 2,
+                taker: scale_price(5) * 2,
+            },
+        ]
+    );
+    let taker_pos = get_positions(&store, taker, None).await;
+    assert_eq!(taker_pos[0].quantity, 1);
+    assert_eq!(taker_pos[0].average_price, scale_price(100));
+}
+
+#[test]
+fn realized_pnl_counts_only_the_closed_part_of_a_fill() {
+    let user_id = Uuid::new_v4();
+    let long = apply_fill(None, user_id, &btc(), OrderSide::Buy, 100, 3, Utc::now());
+    let short = apply_fill(None, user_id, &btc(), OrderSide::Sell, 100, 3, Utc::now());
+
+    assert_eq!(realized_pnl(None, OrderSide::Sell, 120, 2), 0);
+    assert_eq!(realized_pnl(long.as_ref(), OrderSide::Buy, 120, 2), 0);
+    assert_eq!(realized_pnl(long.as_ref(), OrderSide::Sell, 90, 2), -20);
+    // A flip realizes only the 3 that were open
+    assert_eq!(realized_pnl(long.as_ref(), OrderSide::Sell, 120, 10), 60);
+    assert_eq!(realized_pnl(short.as_ref(), OrderSide::Buy, 90, 10), 30);
+
+    let huge = apply_fill(
+        None,
+        user_id,
+        &btc(),
+        OrderSide::Buy,
+        0,
+        MAX_ORDER_QUANTITY,
+        Utc::now(),
+    );
+    assert_eq!(
+        realized_pnl(huge.as_ref(), OrderSide::Sell, i64::MAX, MAX_ORDER_QUANTITY),
+        i64::MAX
+    );
+}
+
+#[tokio::test]
+async fn averages_and_pnl_at_large_notionals_do_not_overflow() {
+    let store = fresh_store();
+    let user_id = Uuid::new_v4();
+    // 1M units at 100k and 120k: each price * qty is far past i64::MAX
+    let qty = 1_000_000;
+    update_position(
+        &store,
+        user_id,
+        &btc(),
+        OrderSide::Buy,
+        scale_price(100_000),
+        qty,
+        Utc::now(),
+    )
+    .await;
+    update_position(
+        &store,
+        user_id,
+        &btc(),
+        OrderSide::Buy,
+        scale_price(120_000),
+        qty,
+        Utc::now(),
+    )
+    .await;
+    let positions = get_positions(&store, user_id, None).await;
+    let pos = &positions[0];
+    assert_eq!(pos.quantity, 2_000_000);
+    assert_eq!(pos.average_price, scale_price(110_000));
+
+    let pnl = unrealized_pnl(pos, scale_price(120_000));
+    assert_eq!(pnl, Ok(scale_price(10_000) * 2_000_000));
+    // A P&L that no longer fits an i64 is reported, not wrapped
+    assert_eq!(
+        unrealized_pnl(pos, scale_price(200_000)),
+        Err(MoneyError::Overflow)
+    );
+    assert_eq!(unrealized_pnl(pos, pos.average_price), Ok(0));
+}
+
+#[test]
+fn scaled_pnl_is_descaled_before_it_is_narrowed() {
+    // 0.5 BTC in lots of 1e-8 BTC, long from 50,000 and short from 60,000
+    let position = |quantity, average_price| Position {
+        user_id: Uuid::new_v4(),
+        symbol: "BTCUSDT".to_string(),
+        quantity,
+        average_price: scale_price(average_price),
+        opened_at: Utc::now(),
+        updated_at: Utc::now(),
+    };
+    let (long, short) = (position(50_000_000, 50_000), position(-50_000_000, 60_000));
+    let mark = scale_price(70_000);
+    assert_eq!(unrealized_pnl(&long, mark), Err(MoneyError::Overflow));
+    assert_eq!(
+        scaled_unrealized_pnl(&long, mark, 100_000_000),
+        Ok(scale_price(10_000))
+    );
+    assert_eq!(
+        scaled_unrealized_pnl(&short, mark, 100_000_000),
+        Ok(scale_price(-5_000))
+    );
+    assert_eq!(
+        scaled_unrealized_pnl(&long, mark, 1),
+        Err(MoneyError::Overflow)
+    );
+}
+
+#[test]
+fn position_quantity_saturates_at_the_i64_limits() {
+    let user_id = Uuid::new_v4();
+    let fill =
+        |current, side, qty| apply_fill(current, user_id, &btc(), side, 100, qty, Utc::now());
+
+    let long = fill(None, OrderSide::Buy, MAX_ORDER_QUANTITY).unwrap();
+    assert_eq!(long.quantity, i64::MAX);
+    let long = fill(Some(&long), OrderSide::Buy, 5).unwrap();
+    assert_eq!((long.quantity, long.average_price), (i64::MAX, 100));
+    assert!(fill(Some(&long), OrderSide::Sell, MAX_ORDER_QUANTITY).is_none());
+
+    // A quantity past i64 (never accepted at ingest) is clamped, not wrapped negative
+    let short = fill(None, OrderSide::Sell, u64::MAX).unwrap();
+    assert_eq!(short.quantity, -i64::MAX);
+    let short = fill(Some(&short), OrderSide::Sell, MAX_ORDER_QUANTITY).unwrap();
+    assert_eq!(short.quantity, i64::MIN);
+}
+
+fn fill_strategy() -> impl Strategy<Value = (usize, i64, u64)> {
+    (0usize..3, 1i64..1_000, 1u64..20)
+}
+
+proptest! {
+    #[test]
+    fn apply_trades_matches_sequential_updates(
+        taker_is_buy in any::<bool>(),
+        seed in prop::collection::vec((any::<bool>(), 1i64..1_000, 1u64..20), 0..4),
+        fills in prop::collection::vec(fill_strategy(), 1..30),
+    ) {
+        let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+        runtime.block_on(async {
+            // Users 0..3 are makers; user 0 doubles as the taker to cover self-trades
+            let users: Vec<Uuid> = (0..3).map(|_| Uuid::new_v4()).collect();
+            let taker = users[0];
+            let taker_side = if taker_is_buy { OrderSide::Buy } else { OrderSide::Sell };
+            let trades: Vec<Trade> = fills
+                .iter()
+                .map(|&(maker, price, qty)| trade(users[maker], taker, price, qty))
+                .collect();
+
+            let batched = fresh_store();
+            let sequential = fresh_store();
+            let seeded_at = Utc::now();
+            for (i, &(buy, price, qty)) in seed.iter().enumerate() {
+                let side = if buy { OrderSide::Buy } else { OrderSide::Sell };
+                let user = users[i % users.len()];
+                update_position(&batched, user, &btc(), side, price, qty, seeded_at).await;
+                update_position(&sequential, user, &btc(), side, price, qty, seeded_at).await;
+            }
+
+            apply_trades(&batched, taker, taker_side, &btc(), &trades).await;
+            apply_sequentially(&sequential, taker, taker_side, &trades).await;
+
+            let batched = batched.read().await.clone();
+            let sequential = sequential.read().await.clone();
+            prop_assert_eq!(batched, sequential);
+            Ok(())
+        })?;
+    }
+}
+
+/// A fixed time `secs` after an arbitrary epoch.
+fn t(secs: i64) -> DateTime<Utc> {
+    DateTime::from_timestamp(1_700_000_000 + secs, 0).unwrap()
+}
+
+#[test]
+fn opened_at_restarts_on_open_flip_and_reopen() {
+    let user_id = Uuid::new_v4();
+    let fill = |current, side, qty, at| apply_fill(current, user_id, &btc(), side, 100, qty, at);
+
+    let long = fill(None, OrderSide::Buy, 5, t(0)).unwrap();
+    assert_eq!((long.opened_at, long.updated_at), (t(0), t(0)));
+    // Adding to and reducing a position keep when it opened
+    let long = fill(Some(&long), OrderSide::Buy, 5, t(10)).unwrap();
+    assert_eq!((long.opened_at, long.updated_at), (t(0), t(10)));
+    let long = fill(Some(&long), OrderSide::Sell, 4, t(20)).unwrap();
+    assert_eq!(
+        (long.quantity, long.opened_at, long.updated_at),
+        (6, t(0), t(20))
+    );
+
+    // Flipping through zero opens a new (short) position at the flip
+    let short = fill(Some(&long), OrderSide::Sell, 8, t(30)).unwrap();
+    assert_eq!(
+        (short.quantity, short.opened_at, short.updated_at),
+        (-2, t(30), t(30))
+    );
+
+    // Closing and re-opening starts over
+    assert!(fill(Some(&short), OrderSide::Buy, 2, t(40)).is_none());
+    let reopened = fill(None, OrderSide::Buy, 1, t(50)).unwrap();
+    assert_eq!((reopened.opened_at, reopened.updated_at), (t(50), t(50)));
+}
+
+#[test]
+fn a_flip_prices_what_remains_at_the_flipping_fill() {
+    let user_id = Uuid::new_v4();
+    let fill = |current, side, price, qty| {
+        apply_fill(current, user_id, &btc(), side, price, qty, Utc::now())
+    };
+
+    // Long 4 at 100, sold 10 at 130: short 6 at 130, not at the long's 100
+    let long = fill(None, OrderSide::Buy, 100, 4).unwrap();
+    let short = fill(Some(&long), OrderSide::Sell, 130, 10).unwrap();
+    assert_eq!((short.quantity, short.average_price), (-6, 130));
+    // Buying 2 back at 120 gains 10 each on the short
+    assert_eq!(realized_pnl(Some(&short), OrderSide::Buy, 120, 2), 20);
+
+    // And through zero the other way
+    let long = fill(Some(&short), OrderSide::Buy, 90, 9).unwrap();
+    assert_eq!((long.quantity, long.average_price), (3, 90));
+    assert_eq!(realized_pnl(Some(&long), OrderSide::Sell, 100, 3), 30);
+}
+
+#[tokio::test]
+async fn apply_trades_stamps_positions_with_trade_times() {
+    let store = fresh_store();
+    let (maker, taker) = (Uuid::new_v4(), Uuid::new_v4());
+    update_position(&store, maker, &btc(), OrderSide::Buy, 100, 2, t(0)).await;
+
+    // The maker sells 2 (closing its long) then 3 more (opening a short); the taker buys 5
+    let mut first = trade(maker, taker, 100, 2);
+    first.timestamp = t(10);
+    let mut second = trade(maker, taker, 100, 3);
+    second.timestamp = t(11);
+    let applied = apply_trades(&store, taker, OrderSide::Buy, &btc(), &[first, second]).await;
+
+    let maker_delta = applied.deltas.iter().find(|d| d.user_id == maker).unwrap();
+    assert_eq!(maker_delta.new_qty, -3);
+    assert_eq!(
+        (maker_delta.opened_at, maker_delta.updated_at),
+        (t(11), t(11))
+    );
+    let taker_delta = applied.deltas.iter().find(|d| d.user_id == taker).unwrap();
+    assert_eq!(
+        (taker_delta.opened_at, taker_delta.updated_at),
+        (t(10), t(11))
+    );
+    let stored = get_positions(&store, taker, None).await;
+    assert_eq!(stored[0].opened_at, t(10));
+
+    // A close reports when the closed position opened
+    let mut close = trade(maker, taker, 100, 3);
+    close.timestamp = t(20);
+    let applied = apply_trades(&store, taker, OrderSide::Sell, &btc(), &[close]).await;
+    let maker_delta = applied.deltas.iter().find(|d| d.user_id == maker).unwrap();
+    assert!(maker_delta.closed);
+    assert_eq!(
+        (maker_delta.opened_at, maker_delta.updated_at),
+        (t(11), t(20))
+    );
+    assert_eq!(maker_delta.position().quantity, 0);
+}
+
+// --- Reversal (busted trades) ---
+
+#[tokio::test]
+async fn reversing_the_only_fill_leaves_no_position() {
+    let store = fresh_store();
+    let user_id = Uuid::new_v4();
+    update_position(&store, user_id, &btc(), OrderSide::Buy, 100, 10, t(0)).await;
+    let delta = reverse_position(&store, user_id, &btc(), OrderSide::Buy, 100, 10, t(5)).await;
+    assert!(delta.closed);
+    assert!(get_positions(&store, user_id, None).await.is_empty());
+}
+
+#[test]
+fn reversing_a_fill_after_adding_more_leaves_only_the_later_fills() {
+    let user_id = Uuid::new_v4();
+    let fill =
+        |current, side, price, qty, at| apply_fill(current, user_id, &btc(), side, price, qty, at);
+    // Long 10 @ 100 (the fill to bust), then 10 @ 200 and 5 @ 110 more
+    let busted = fill(None, OrderSide::Buy, 100, 10, t(0));
+    let later = fill(busted.as_ref(), OrderSide::Buy, 200, 10, t(10));
+    let later = fill(later.as_ref(), OrderSide::Buy, 110, 5, t(20)).unwrap();
+    assert_eq!((later.quantity, later.average_price), (25, 142));
+
+    let reversed = reverse_fill(
+        Some(&later),
+        user_id,
+        &btc(),
+        OrderSide::Buy,
+        100,
+        10,
+        t(30),
+    )
+    .unwrap();
+    let without = fill(None, OrderSide::Buy, 200, 10, t(10));
+    let without = fill(without.as_ref(), OrderSide::Buy, 110, 5, t(20)).unwrap();
+    assert_eq!(
+        (reversed.quantity, reversed.average_price),
+        (without.quantity, without.average_price)
+    );
+    assert_eq!((reversed.quantity, reversed.average_price), (15, 170));
+    assert_eq!((reversed.opened_at, reversed.updated_at), (t(0), t(30)));
+}
+
+#[test]
+fn reversing_a_reducing_fill_restores_quantity_at_the_average() {
+    let user_id = Uuid::new_v4();
+    let long = apply_fill(None, user_id, &btc(), OrderSide::Buy, 100, 10, t(0));
+    // Sold 4 @ 130 (busted), then bought 2 more @ 160
+    let reduced = apply_fill(
+        long.as_ref(),
+        user_id,
+        &btc(),
+        OrderSide::Sell,
+        130,
+        4,
+        t(10),
+    );
+    let now = apply_fill(
+        reduced.as_ref(),
+        user_id,
+        &btc(),
+        OrderSide::Buy,
+        160,
+        2,
+        t(20),
+    )
+    .unwrap();
+    assert_eq!((now.quantity, now.average_price), (8, 115));
+
+    let reversed =
+        reverse_fill(Some(&now), user_id, &btc(), OrderSide::Sell, 130, 4, t(30)).unwrap();
+    assert_eq!((reversed.quantity, reversed.average_price), (12, 115));
+}
+
+#[test]
+fn reversing_past_zero_opens_at_the_trade_price() {
+    let user_id = Uuid::new_v4();
+    // Bought 10 @ 100 (busted), since sold 4: undoing the buy sells 6 more than is held
+    let long = apply_fill(None, user_id, &btc(), OrderSide::Buy, 100, 10, t(0));
+    let now = apply_fill(
+        long.as_ref(),
+        user_id,
+        &btc(),
+        OrderSide::Sell,
+        120,
+        4,
+        t(10),
+    )
+    .unwrap();
+    let reversed =
+        reverse_fill(Some(&now), user_id, &btc(), OrderSide::Buy, 100, 10, t(20)).unwrap();
+    assert_eq!(
+        (
+            reversed.quantity,
+            reversed.average_price,
+            reversed.opened_at
+        ),
+        (-4, 100, t(20))
+    );
+
+    // A fill that had closed the position reopens it
+    let reopened = reverse_fill(None, user_id, &btc(), OrderSide::Sell, 120, 6, t(30)).unwrap();
+    assert_eq!((reopened.quantity, reopened.average_price), (6, 120));
+}
+
+#[tokio::test]
+async fn reversing_a_self_trade_puts_both_legs_back() {
+    let store = fresh_store();
+    let user_id = Uuid::new_v4();
+    update_position(&store, user_id, &btc(), OrderSide::Sell, 90, 3, t(0)).await;
+    let self_trade = trade(user_id, user_id, 100, 2);
+    apply_trades(&store, user_id, OrderSide::Buy, &btc(), &[self_trade]).await;
+    // Last applied first: the taker's buy, then the maker's sell
+    for side in [OrderSide::Buy, OrderSide::Sell] {
+        reverse_position(&store, user_id, &btc(), side, 100, 2, t(10)).await;
+    }
+    let positions = get_positions(&store, user_id, None).await;
+    assert_eq!(
+        (positions[0].quantity, positions[0].average_price),
+        (-3, 90)
+    );
+}
+
+proptest! {
+    #[test]
+    fn reversing_the_last_fill_restores_the_quantity(
+        seed in prop::collection::vec((any::<bool>(), 1i64..1_000, 1u64..20), 0..6),
+        (buy, price, qty) in (any::<bool>(), 1i64..1_000, 1u64..20),
+    ) {
+        let user_id = Uuid::new_v4();
+        let side = if buy { OrderSide::Buy } else { OrderSide::Sell };
+        let mut position = None;
+        for (buy, price, qty) in seed {
+            let side = if buy { OrderSide::Buy } else { OrderSide::Sell };
+            position = apply_fill(position.as_ref(), user_id, &btc(), side, price, qty, t(0));
+        }
+        let before = position.as_ref().map_or(0, |p| p.quantity);
+        let after = apply_fill(position.as_ref(), user_id, &btc(), side, price, qty, t(1));
+        let reversed = reverse_fill(after.as_ref(), user_id, &btc(), side, price, qty, t(2));
+        prop_assert_eq!(reversed.as_ref().map_or(0, |p| p.quantity), before);
+        // Undoing an add gives the average back, up to the weighted average's rounding
+        // spread over what is left
+        if let (Some(before), Some(reversed), Some(after)) = (&position, &reversed, &after)
+            && (before.quantity > 0) == buy
+        {
+            let error = (before.average_price - reversed.average_price).abs();
+            prop_assert!(error <= after.quantity.abs() / before.quantity.abs() + 1);
+        }
+    }
+}
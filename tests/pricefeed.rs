@@ -0,0 +1,121 @@
+//! Index price feed tests: config parsing, JSON extraction, polling with a mock source, staleness.
+
+use chrono::Utc;
+use rust_exchange::api::routes::WsMessage;
+use rust_exchange::pricefeed::{
+    IndexPrice, PriceFeedError, PriceSource, SharedIndexPrices, extract_price, fresh_index_price,
+    is_stale, parse_feed_config, poll_once,
+};
+use rust_exchange::types::order::Price;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::{RwLock, broadcast};
+
+/// Mock source returning queued results per call (last result repeats).
+struct MockSource {
+    results: Mutex<Vec<Result<Price, String>>>,
+}
+
+impl MockSource {
+    fn new(results: Vec<Result<Price, String>>) -> Self {
+        Self {
+            results: Mutex::new(results),
+        }
+    }
+}
+
+impl PriceSource for MockSource {
+    async fn fetch(&self, _symbol: &str) -> Result<Price, PriceFeedError> {
+        let mut results = self.results.lock().unwrap();
+        let next = if results.len() > 1 {
+            results.remove(0)
+        } else {
+            results[0].clone()
+        };
+        next.map_err(PriceFeedError::InvalidPrice)
+    }
+}
+
+fn fresh_store() -> SharedIndexPrices {
+    Arc::new(RwLock::new(HashMap::new()))
+}
+
+#[test]
+fn parse_feed_config_reads_entries_and_skips_malformed() {
+    let feeds = parse_feed_config(
+        "btcusdt=https://a.example/btc|/data/price, bad-entry, BTC/USD=https://c.example|/p, ETHUSDT=https://b.example/eth|/p",
+    );
+    assert_eq!(feeds.len(), 2);
+    assert_eq!(feeds["BTCUSDT"].url, "https://a.example/btc");
+    assert_eq!(feeds["BTCUSDT"].pointer, "/data/price");
+    assert_eq!(feeds["ETHUSDT"].pointer, "/p");
+}
+
+#[test]
+fn extract_price_accepts_numbers_and_numeric_strings() {
+    let json = serde_json::json!({ "data": { "price": 50000.5, "last": "3000.25" } });
+    assert_eq!(extract_price(&json, "/data/price", 100).unwrap(), 5_000_050);
+    assert_eq!(extract_price(&json, "/data/last", 100).unwrap(), 300_025);
+    assert!(matches!(
+        extract_price(&json, "/data/missing", 100),
+        Err(PriceFeedError::MissingValue(_))
+    ));
+}
+
+#[tokio::test]
+async fn poll_once_updates_store_and_broadcasts_on_change() {
+    let store = fresh_store();
+    let (tx, mut rx) = broadcast::channel(8);
+    let source = MockSource::new(vec![Ok(100), Ok(100), Ok(105)]);
+    let symbols = vec!["BTCUSDT".to_string()];
+
+    poll_once(&source, &symbols, &store, Some(&tx)).await;
+    poll_once(&source, &symbols, &store, Some(&tx)).await;
+    poll_once(&source, &symbols, &store, Some(&tx)).await;
+
+    assert_eq!(store.read().await["BTCUSDT"].price, 105);
+    let mut prices = Vec::new();
+    while let Ok(WsMessage::IndexPrice { price, .. }) = rx.try_recv() {
+        prices.push(price);
+    }
+    assert_eq!(prices, vec![100, 105]);
+}
+
+#[tokio::test]
+async fn poll_once_keeps_last_value_on_failure() {
+    let store = fresh_store();
+    let source = MockSource::new(vec![Ok(100), Err("down".to_string())]);
+    let symbols = vec!["BTCUSDT".to_string()];
+
+    poll_once(&source, &symbols, &store, None).await;
+    let first = store.read().await["BTCUSDT"];
+    poll_once(&source, &symbols, &store, None).await;
+
+    assert_eq!(store.read().await["BTCUSDT"], first);
+}
+
+#[tokio::test]
+async fn stale_prices_are_not_used_as_reference() {
+    let store = fresh_store();
+    let old = IndexPrice {
+        price: 100,
+        updated_at: Utc::now() - chrono::Duration::seconds(60),
+    };
+    store.write().await.insert("BTCUSDT".to_string(), old);
+
+    assert!(is_stale(&old, Duration::from_secs(30), Utc::now()));
+    assert_eq!(
+        fresh_index_price(&store, &"btcusdt".parse().unwrap(), Duration::from_secs(30)).await,
+        None
+    );
+    assert_eq!(
+        fresh_index_price(
+            &store,
+            &"BTCUSDT".parse().unwrap(),
+            Duration::from_secs(120)
+        )
+        .await,
+        Some(100)
+    );
+}
@@ -0,0 +1,183 @@
+//! `POST /orders/{id}/reduce`: trimming a resting order in place keeps its id and time
+//! priority, broadcasts the level, enforces ownership and bounds, and races a fill in
+//! ingress order.
+
+use reqwest::StatusCode;
+use rust_exchange::testing::{OrderRequest, TEST_SYMBOL, TestExchange, Token};
+use rust_exchange::types::order::OrderSide;
+use serde_json::json;
+use uuid::Uuid;
+
+fn reduce_request(
+    exchange: &TestExchange,
+    token: &Token,
+    order_id: Uuid,
+    quantity: u64,
+) -> reqwest::RequestBuilder {
+    exchange
+        .client()
+        .post(exchange.url(&format!(
+            "/orders/{}/reduce?symbol={}",
+            order_id, TEST_SYMBOL
+        )))
+        .bearer_auth(&token.token)
+        .json(&json!({ "quantity": quantity }))
+}
+
+#[tokio::test]
+async fn reduce_keeps_the_order_in_front_of_the_queue_and_broadcasts_the_level() {
+    let exchange = TestExchange::start().await;
+    let first = exchange.register("first", "password123").await;
+    let second = exchange.register("second", "password123").await;
+    let taker = exchange.register("taker", "password123").await;
+    let mut feed = exchange.ws_client(None).await;
+    feed.subscribe(TEST_SYMBOL).await;
+
+    let front = exchange
+        .place_order(
+            &first,
+            &OrderRequest::limit(TEST_SYMBOL, OrderSide::Sell, 100, 5),
+        )
+        .await
+        .unwrap()
+        .order;
+    exchange
+        .place_order(
+            &second,
+            &OrderRequest::limit(TEST_SYMBOL, OrderSide::Sell, 100, 4),
+        )
+        .await
+        .unwrap();
+
+    let reduced = exchange
+        .send_json(reduce_request(&exchange, &first, front.id, 2))
+        .await
+        .unwrap();
+    assert_eq!(reduced["id"], front.id.to_string());
+    assert_eq!(reduced["quantity"], 2);
+    assert_eq!(reduced["status"], "Pending");
+    assert_eq!(exchange.book(TEST_SYMBOL).await.asks, vec![(100, 6)]);
+    loop {
+        let update = feed.next_of_type("OrderBookUpdate").await;
+        if update["asks"] == json!([[100, 6]]) {
+            break;
+        }
+    }
+
+    // The reduced order still trades first
+    let taken = exchange
+        .place_order(
+            &taker,
+            &OrderRequest::limit(TEST_SYMBOL, OrderSide::Buy, 100, 3),
+        )
+        .await
+        .unwrap();
+    let fills: Vec<(Uuid, u64)> = taken
+        .trades
+        .iter()
+        .map(|t| (t.maker_order_id, t.quantity))
+        .collect();
+    assert_eq!(fills[0], (front.id, 2));
+    assert_eq!(fills.len(), 2);
+    assert_eq!(fills[1].1, 1);
+    assert_eq!(exchange.book(TEST_SYMBOL).await.asks, vec![(100, 3)]);
+}
+
+async fn reduce_status(
+    exchange: &TestExchange,
+    token: &Token,
+    order_id: Uuid,
+    quantity: u64,
+) -> StatusCode {
+    reduce_request(exchange, token, order_id, quantity)
+        .send()
+        .await
+        .unwrap()
+        .status()
+}
+
+#[tokio::test]
+async fn reduce_checks_owner_bounds_and_that_the_order_still_rests() {
+    let exchange = TestExchange::start().await;
+    let owner = exchange.register("owner", "password123").await;
+    let other = exchange.register("other", "password123").await;
+    let order = exchange
+        .place_order(
+            &owner,
+            &OrderRequest::limit(TEST_SYMBOL, OrderSide::Buy, 100, 5),
+        )
+        .await
+        .unwrap()
+        .order;
+
+    assert_eq!(
+        reduce_status(&exchange, &other, order.id, 1).await,
+        StatusCode::FORBIDDEN
+    );
+    assert_eq!(
+        reduce_status(&exchange, &owner, order.id, 0).await,
+        StatusCode::BAD_REQUEST
+    );
+    assert_eq!(
+        reduce_status(&exchange, &owner, order.id, 6).await,
+        StatusCode::BAD_REQUEST
+    );
+    assert_eq!(exchange.book(TEST_SYMBOL).await.bids, vec![(100, 5)]);
+
+    exchange
+        .cancel_order(&owner, TEST_SYMBOL, order.id)
+        .await
+        .unwrap();
+    assert_eq!(
+        reduce_status(&exchange, &owner, order.id, 1).await,
+        StatusCode::CONFLICT
+    );
+}
+
+/// The request's place in the symbol's queue, from `X-Ingress-Seq`.
+fn ingress_seq(res: &reqwest::Response) -> u64 {
+    res.headers()["x-ingress-seq"]
+        .to_str()
+        .unwrap()
+        .parse()
+        .unwrap()
+}
+
+#[tokio::test]
+async fn a_fill_racing_a_reduce_resolves_in_ingress_order() {
+    for _ in 0..5 {
+        let exchange = TestExchange::start().await;
+        let maker = exchange.register("maker", "password123").await;
+        let taker = exchange.register("taker", "password123").await;
+        let resting = exchange
+            .place_order(
+                &maker,
+                &OrderRequest::limit(TEST_SYMBOL, OrderSide::Sell, 100, 5),
+            )
+            .await
+            .unwrap()
+            .order;
+
+        let take = exchange
+            .client()
+            .post(exchange.url("/orders"))
+            .bearer_auth(&taker.token)
+            .json(&OrderRequest::limit(TEST_SYMBOL, OrderSide::Buy, 100, 3));
+        let (reduced, taken) = tokio::join!(
+            reduce_request(&exchange, &maker, resting.id, 2).send(),
+            take.send()
+        );
+        let (reduced, taken) = (reduced.unwrap(), taken.unwrap());
+        assert_eq!(reduced.status(), StatusCode::OK);
+        assert_eq!(taken.status(), StatusCode::OK);
+
+        let book = exchange.book(TEST_SYMBOL).await;
+        if ingress_seq(&reduced) < ingress_seq(&taken) {
+            // Trimmed to 2 first: the taker fills those and rests the rest
+            assert_eq!((book.asks, book.bids), (vec![], vec![(100, 1)]));
+        } else {
+            // Filled 3 first: the 2 left already match the reduce, which changes nothing
+            assert_eq!((book.asks, book.bids), (vec![(100, 2)], vec![]));
+        }
+    }
+}
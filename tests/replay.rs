@@ -0,0 +1,277 @@
+//! Replay integration tests: event parsing, driving the book from a file, expected-trade
+//! comparison, and the replay binary.
+
+use chrono::{DateTime, Utc};
+use rust_exchange::replay::{
+    EventReader, ExpectedTrades, InputFormat, Replay, ReplayError, ReplayTrade,
+};
+use rust_exchange::skew::{SkewPolicy, SkewWindow};
+use std::io::Cursor;
+use std::process::Command;
+
+const SESSION_NDJSON: &str = r#"
+# resting liquidity
+{"ts":"2025-01-02T09:30:00Z","action":"submit","ref":"a1","user":"alice","side":"Sell","price":101,"quantity":3}
+{"ts":"2025-01-02T09:30:00Z","action":"submit","ref":"a2","user":"alice","side":"Sell","type":"Limit","price":102,"quantity":4}
+{"ts":"2025-01-02T09:30:01Z","action":"submit","ref":"c1","user":"carol","side":"Buy","price":99,"quantity":2}
+{"ts":"2025-01-02T09:30:05Z","action":"submit","ref":"b1","user":"bob","side":"Buy","type":"Market","quantity":5}
+{"ts":"2025-01-02T09:30:06Z","action":"cancel","ref":"a1"}
+{"ts":"2025-01-02T09:30:07Z","action":"cancel","ref":"c1"}
+{"ts":"2025-01-02T09:30:08Z","action":"submit","ref":"b2","user":"bob","side":"Sell","type":"Market","quantity":1}
+"#;
+
+const SESSION_CSV: &str = "ts,action,ref,user,side,type,price,quantity
+2025-01-02T09:30:00Z,submit,a1,alice,Sell,,101,3
+2025-01-02T09:30:00Z,submit,a2,alice,Sell,Limit,102,4
+2025-01-02T09:30:01Z,submit,c1,carol,Buy,Limit,99,2
+2025-01-02T09:30:05Z,submit,b1,bob,Buy,Market,,5
+2025-01-02T09:30:06Z,cancel,a1,,,,,
+2025-01-02T09:30:07Z,cancel,c1,,,,,
+2025-01-02T09:30:08Z,submit,b2,bob,Sell,Market,,1
+";
+
+fn run(input: &str, format: InputFormat) -> (Replay, Vec<ReplayTrade>) {
+    let mut replay = Replay::new();
+    let mut trades = Vec::new();
+    for event in EventReader::new(Cursor::new(input), format) {
+        let (_, event) = event.unwrap();
+        trades.extend(replay.apply(&event).unwrap());
+    }
+    (replay, trades)
+}
+
+fn to_ndjson(trades: &[ReplayTrade]) -> String {
+    trades
+        .iter()
+        .map(|t| serde_json::to_string(t).unwrap() + "\n")
+        .collect()
+}
+
+#[test]
+fn replay_drives_the_book_with_file_timestamps_and_refs() {
+    let (replay, trades) = run(SESSION_NDJSON, InputFormat::Ndjson);
+
+    let fills: Vec<(&str, &str, i64, u64)> = trades
+        .iter()
+        .map(|t| {
+            (
+                t.maker_ref.as_str(),
+                t.taker_ref.as_str(),
+                t.price,
+                t.quantity,
+            )
+        })
+        .collect();
+    assert_eq!(fills, vec![("a1", "b1", 101, 3), ("a2", "b1", 102, 2)]);
+    assert!(
+        trades
+            .iter()
+            .all(|t| t.maker_user == "alice" && t.taker_user == "bob")
+    );
+    assert_eq!(trades[0].ts.to_rfc3339(), "2025-01-02T09:30:05+00:00");
+    assert_eq!(
+        trades.iter().map(|t| t.trade_seq).collect::<Vec<_>>(),
+        vec![1, 2]
+    );
+
+    let stats = replay.stats();
+    assert_eq!(stats.events, 7);
+    assert_eq!(stats.submits, 5);
+    assert_eq!(stats.cancels, 2);
+    // a1 was filled before its cancel arrived; b2 found no bids once c1 was cancelled
+    assert_eq!(stats.cancel_misses, 1);
+    assert_eq!(stats.rejected, 1);
+    assert_eq!(stats.trades, 2);
+    assert_eq!(stats.volume, 5);
+
+    let book = replay.final_book();
+    assert!(book.bids.is_empty());
+    assert_eq!(book.asks, vec![(102, 2)]);
+    assert_eq!(book.orders.len(), 1);
+    assert_eq!(book.orders[0].order_ref, "a2");
+    assert_eq!(book.orders[0].user, "alice");
+    assert_eq!(book.last_trade_seq, 2);
+}
+
+#[test]
+fn csv_and_ndjson_sessions_replay_identically() {
+    let (ndjson, ndjson_trades) = run(SESSION_NDJSON, InputFormat::Ndjson);
+    let (csv, csv_trades) = run(SESSION_CSV, InputFormat::Csv);
+    assert_eq!(ndjson_trades, csv_trades);
+    assert_eq!(ndjson.stats(), csv.stats());
+    assert_eq!(ndjson.final_book(), csv.final_book());
+}
+
+#[test]
+fn malformed_and_out_of_order_events_are_reported_with_their_line() {
+    let bad_side = "ts,action,ref,user,side,type,price,quantity\n\
+                    2025-01-02T09:30:00Z,submit,o1,alice,Hold,Limit,100,1\n";
+    let errors: Vec<_> = EventReader::new(Cursor::new(bad_side), InputFormat::Csv).collect();
+    match &errors[..] {
+        [Err(ReplayError::Parse { line: 2, message })] => assert!(message.contains("Hold")),
+        other => panic!("unexpected {:?}", other),
+    }
+
+    let missing_header = "2025-01-02T09:30:00Z,cancel,o1,,,,,\n";
+    let mut events = EventReader::new(Cursor::new(missing_header), InputFormat::Csv);
+    assert!(matches!(
+        events.next(),
+        Some(Err(ReplayError::Parse { line: 1, .. }))
+    ));
+
+    let backwards = r#"{"ts":"2025-01-02T09:30:05Z","action":"submit","ref":"o1","user":"u","side":"Buy","price":1,"quantity":1}
+{"ts":"2025-01-02T09:30:04Z","action":"cancel","ref":"o1"}"#;
+    let mut replay = Replay::new();
+    let mut events = EventReader::new(Cursor::new(backwards), InputFormat::Ndjson);
+    let (_, first) = events.next().unwrap().unwrap();
+    replay.apply(&first).unwrap();
+    let (line, second) = events.next().unwrap().unwrap();
+    assert_eq!(line, 2);
+    assert!(replay.apply(&second).unwrap_err().contains("earlier"));
+    // The rejected event did not cancel the order
+    assert_eq!(replay.final_book().bids, vec![(1, 1)]);
+
+    let (_, duplicate) = EventReader::new(Cursor::new(backwards), InputFormat::Ndjson)
+        .next()
+        .unwrap()
+        .unwrap();
+    assert!(
+        replay
+            .apply(&duplicate)
+            .unwrap_err()
+            .contains("already resting")
+    );
+}
+
+#[test]
+fn skewed_event_timestamps_follow_the_window_policy() {
+    // A session recorded at 09:30, one event stamped a day ahead by a skewed recorder
+    let session = r#"{"ts":"2025-01-02T09:30:00Z","action":"submit","ref":"a1","user":"alice","side":"Sell","price":101,"quantity":1}
+{"ts":"2025-01-03T09:30:00Z","action":"submit","ref":"b1","user":"bob","side":"Buy","price":101,"quantity":1}
+{"ts":"2025-01-02T09:30:10Z","action":"submit","ref":"a2","user":"alice","side":"Sell","price":102,"quantity":1}"#;
+    let now: DateTime<Utc> = "2025-01-02T09:31:00Z".parse().unwrap();
+    let run = |policy| {
+        let window = SkewWindow {
+            policy,
+            ..SkewWindow::default()
+        };
+        let mut replay = Replay::new().with_skew_window(window, now);
+        let results: Vec<_> = EventReader::new(Cursor::new(session), InputFormat::Ndjson)
+            .map(|event| replay.apply(&event.unwrap().1))
+            .collect();
+        (replay, results)
+    };
+
+    // Accepted, the skewed event then makes the next one look out of order
+    let (replay, results) = run(SkewPolicy::AcceptWithWarning);
+    let trades = results[1].as_ref().unwrap();
+    assert_eq!(
+        trades[0].ts,
+        "2025-01-03T09:30:00Z".parse::<DateTime<Utc>>().unwrap()
+    );
+    assert!(results[2].as_ref().unwrap_err().contains("earlier"));
+    assert_eq!(replay.stats().timestamps_skewed, 1);
+    assert!(replay.stats().to_string().ends_with("1 skewed timestamps"));
+
+    // Clamped to five minutes ahead of now; still later than the next event
+    let (replay, results) = run(SkewPolicy::Clamp);
+    let trades = results[1].as_ref().unwrap();
+    assert_eq!(
+        trades[0].ts,
+        "2025-01-02T09:36:00Z".parse::<DateTime<Utc>>().unwrap()
+    );
+    assert!(results[2].is_err());
+    assert_eq!(replay.stats().timestamps_skewed, 1);
+
+    let (replay, results) = run(SkewPolicy::Reject);
+    assert!(results[1].as_ref().unwrap_err().contains("in the future"));
+    assert!(results[2].is_ok());
+    assert_eq!(replay.stats().events, 2);
+    assert_eq!(replay.stats().timestamps_skewed, 0);
+    assert_eq!(replay.final_book().asks, vec![(101, 1), (102, 1)]);
+
+    // Without a window the file is trusted as before
+    let mut replay = Replay::new();
+    let mut events = EventReader::new(Cursor::new(session), InputFormat::Ndjson);
+    for _ in 0..2 {
+        replay.apply(&events.next().unwrap().unwrap().1).unwrap();
+    }
+    assert_eq!(replay.stats().timestamps_skewed, 0);
+}
+
+#[test]
+fn expected_trades_comparison_reports_divergences() {
+    let (_, trades) = run(SESSION_NDJSON, InputFormat::Ndjson);
+
+    let mut same = ExpectedTrades::new(Cursor::new(to_ndjson(&trades)));
+    for trade in &trades {
+        same.check(trade).unwrap();
+    }
+    let comparison = same.finish().unwrap();
+    assert!(comparison.matches());
+    assert_eq!(comparison.compared, 2);
+
+    let mut altered = trades.clone();
+    altered[1].price = 103;
+    let mut expected = ExpectedTrades::new(Cursor::new(to_ndjson(&altered)));
+    expected.check(&trades[0]).unwrap();
+    expected.check(&trades[1]).unwrap();
+    let comparison = expected.finish().unwrap();
+    assert_eq!(comparison.divergences, 1);
+    assert!(comparison.samples[0].starts_with("trade 2"));
+
+    // The replay stopped producing trades the expectation still lists
+    let mut expected = ExpectedTrades::new(Cursor::new(to_ndjson(&trades)));
+    expected.check(&trades[0]).unwrap();
+    let comparison = expected.finish().unwrap();
+    assert_eq!(comparison.divergences, 1);
+    assert!(comparison.samples[0].contains("missing"));
+}
+
+#[test]
+fn replay_binary_writes_outputs_and_exits_nonzero_on_divergence() {
+    let dir = std::env::temp_dir().join(format!("replay-test-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let input = dir.join("session.csv");
+    let trades_out = dir.join("trades.ndjson");
+    let book_out = dir.join("book.json");
+    std::fs::write(&input, SESSION_CSV).unwrap();
+
+    let status = Command::new(env!("CARGO_BIN_EXE_replay"))
+        .arg(&input)
+        .arg("--trades-out")
+        .arg(&trades_out)
+        .arg("--book-out")
+        .arg(&book_out)
+        .status()
+        .unwrap();
+    assert!(status.success());
+    let recorded = std::fs::read_to_string(&trades_out).unwrap();
+    assert_eq!(recorded.lines().count(), 2);
+    let book: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(&book_out).unwrap()).unwrap();
+    assert_eq!(book["orders"][0]["ref"], "a2");
+
+    // The recorded output is its own expectation
+    let replay_against = |expected: &std::path::Path| {
+        Command::new(env!("CARGO_BIN_EXE_replay"))
+            .arg(&input)
+            .arg("--trades-out")
+            .arg(dir.join("rerun.ndjson"))
+            .arg("--expected")
+            .arg(expected)
+            .status()
+            .unwrap()
+    };
+    assert!(replay_against(&trades_out).success());
+
+    let diverging = dir.join("diverging.ndjson");
+    std::fs::write(
+        &diverging,
+        recorded.replace("\"price\":102", "\"price\":103"),
+    )
+    .unwrap();
+    assert_eq!(replay_against(&diverging).code(), Some(1));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
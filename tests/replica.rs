@@ -0,0 +1,197 @@
+//! Market-data replica mode: writes are refused and the books follow what the primary
+//! publishes.
+
+use chrono::Utc;
+use reqwest::StatusCode;
+use rust_exchange::api::auth;
+use rust_exchange::replica::{self, Refreshed, ServerRole};
+use rust_exchange::testing::{TEST_SYMBOL, TestExchange, Token};
+use rust_exchange::types::order::{Order, OrderSide, OrderStatus, OrderType};
+use rust_exchange::types::trade::Trade;
+use serde_json::{Value, json};
+use uuid::Uuid;
+
+async fn replica() -> TestExchange {
+    TestExchange::builder()
+        .with_state(|state| state.role = ServerRole::MarketDataReplica)
+        .user("maker", "secret")
+        .order(TEST_SYMBOL, "maker", OrderSide::Sell, 101, 2)
+        .start()
+        .await
+}
+
+/// Replicas cannot register or log in users; tokens come from the primary's shared secret.
+fn token(exchange: &TestExchange) -> Token {
+    let user_id = Uuid::new_v4();
+    Token {
+        user_id,
+        token: auth::create_token(
+            &exchange.state.jwt_secret,
+            user_id,
+            exchange.state.clock.now(),
+        )
+        .unwrap(),
+    }
+}
+
+fn resting(side: OrderSide, price: i64, quantity: u64) -> Order {
+    Order {
+        id: Uuid::new_v4(),
+        user_id: Uuid::new_v4(),
+        side,
+        order_type: OrderType::Limit,
+        price,
+        quantity,
+        status: OrderStatus::Pending,
+        timestamp: Utc::now(),
+        tags: Default::default(),
+        source: Default::default(),
+    }
+}
+
+fn trade(trade_seq: u64, maker: &Order, price: i64, quantity: u64) -> Trade {
+    Trade {
+        id: Uuid::new_v4(),
+        trade_seq,
+        maker_order_id: maker.id,
+        taker_order_id: Uuid::new_v4(),
+        maker_user_id: maker.user_id,
+        taker_user_id: Uuid::new_v4(),
+        price,
+        quantity,
+        timestamp: Utc::now(),
+        maker_queue_rank: Some(0),
+    }
+}
+
+#[tokio::test]
+async fn replica_refuses_every_mutating_route() {
+    let exchange = replica().await;
+    let token = token(&exchange);
+    let client = exchange.client();
+    let order = json!({
+        "symbol": TEST_SYMBOL, "side": "Buy", "order_type": "Limit", "price": 101, "quantity": 1
+    });
+    let requests = [
+        client.post(exchange.url("/orders")).json(&order),
+        client.post(exchange.url("/orders/preview")).json(&order),
+        client.delete(exchange.url(&format!("/orders/{}", Uuid::new_v4()))),
+        client.post(exchange.url("/auth/register")).json(&json!({
+            "username": "someone", "password": "secret"
+        })),
+        client.post(exchange.url("/auth/login")).json(&json!({
+            "username": "maker", "password": "secret"
+        })),
+        client.post(exchange.url("/webhooks")).json(&json!({
+            "url": "https://example.com/hook", "secret": "0123456789abcdef", "events": ["Fill"]
+        })),
+        client
+            .post(exchange.url("/admin/auction/start"))
+            .json(&json!({
+                "symbol": TEST_SYMBOL
+            })),
+        client
+            .patch(exchange.url(&format!("/admin/symbols/{}", TEST_SYMBOL)))
+            .json(&json!({ "halted": true })),
+    ];
+    for request in requests {
+        let err = exchange
+            .send_json(request.bearer_auth(&token.token))
+            .await
+            .unwrap_err();
+        assert_eq!(err.status, StatusCode::FORBIDDEN, "{}", err.body);
+        assert_eq!(err.body["error_code"], "READ_ONLY_REPLICA");
+    }
+
+    // Nothing reached the book, and reads are still served
+    assert_eq!(exchange.book(TEST_SYMBOL).await.asks, vec![(101, 2)]);
+    let trades = exchange
+        .get(&token, &format!("/trades?symbol={}", TEST_SYMBOL))
+        .await
+        .unwrap();
+    assert_eq!(trades["items"], json!([]));
+}
+
+#[tokio::test]
+async fn replica_serves_and_streams_refreshed_snapshots() {
+    let exchange = replica().await;
+    let token = token(&exchange);
+    let mut feed = exchange.ws_client(None).await;
+    feed.subscribe(TEST_SYMBOL).await;
+
+    // The primary's book now has a bid and two asks, after one trade
+    let bid = resting(OrderSide::Buy, 99, 3);
+    let first = resting(OrderSide::Sell, 101, 1);
+    let second = resting(OrderSide::Sell, 101, 4);
+    let orders = vec![bid.clone(), first.clone(), second.clone()];
+    let traded = trade(1, &first, 101, 2);
+    let refreshed = replica::apply(&exchange.state, TEST_SYMBOL, orders, vec![traded.clone()])
+        .await
+        .unwrap();
+    assert_eq!(
+        refreshed,
+        Refreshed {
+            book_changed: true,
+            new_trades: 1
+        }
+    );
+
+    let book = exchange.book(TEST_SYMBOL).await;
+    assert_eq!((book.bids, book.asks), (vec![(99, 3)], vec![(101, 5)]));
+    let trades = exchange
+        .get(&token, &format!("/trades?symbol={}", TEST_SYMBOL))
+        .await
+        .unwrap();
+    assert_eq!(trades["items"][0]["id"], traded.id.to_string());
+    let message = feed.next_of_type("Trade").await;
+    assert_eq!(message["trade"]["id"], traded.id.to_string());
+    let message = feed.next_of_type("OrderBookUpdate").await;
+    assert_eq!(message["asks"], json!([[101, 5]]));
+
+    // The same state again changes nothing; trades already seen are not repeated
+    let unchanged = replica::apply(
+        &exchange.state,
+        TEST_SYMBOL,
+        vec![bid.clone(), first.clone(), second.clone()],
+        vec![traded.clone()],
+    )
+    .await
+    .unwrap();
+    assert_eq!(unchanged, Refreshed::default());
+
+    // The first ask is consumed and the second partly filled
+    let later = trade(2, &first, 101, 1);
+    let partial = trade(3, &second, 101, 1);
+    let second_left = Order {
+        quantity: 3,
+        ..second.clone()
+    };
+    replica::apply(
+        &exchange.state,
+        TEST_SYMBOL,
+        vec![bid, second_left],
+        vec![traded, later, partial.clone()],
+    )
+    .await
+    .unwrap();
+    assert_eq!(exchange.book(TEST_SYMBOL).await.asks, vec![(101, 3)]);
+    let trades: Vec<Value> = serde_json::from_value(
+        exchange
+            .get(&token, &format!("/trades?symbol={}", TEST_SYMBOL))
+            .await
+            .unwrap()["items"]
+            .clone(),
+    )
+    .unwrap();
+    let seqs: Vec<_> = trades.iter().map(|t| t["trade_seq"].clone()).collect();
+    assert_eq!(seqs, vec![json!(3), json!(2), json!(1)]);
+    let message = feed.next_of_type("OrderBookUpdate").await;
+    assert_eq!(message["asks"], json!([[101, 3]]));
+    assert_eq!(
+        exchange.state.orderbooks[TEST_SYMBOL]
+            .read()
+            .await
+            .last_trade_seq(),
+        partial.trade_seq
+    );
+}
@@ -0,0 +1,104 @@
+//! The `meta` block of scales (`price_scale`, `qty_scale`, `tick_size`, `lot_size`) carried by
+//! market data, order and trade responses and sent as `ExchangeInfo` after a WS subscribe.
+
+use rust_exchange::testing::{OrderRequest, TEST_SYMBOL, TestExchange, Token};
+use rust_exchange::types::order::OrderSide;
+use serde_json::{Value, json};
+
+async fn start() -> (TestExchange, Token) {
+    let exchange = TestExchange::builder().admin().start().await;
+    let admin = exchange.admin_token();
+    (exchange, admin)
+}
+
+fn meta(tick_size: i64) -> Value {
+    json!({"price_scale": 100_000_000, "qty_scale": 1, "tick_size": tick_size, "lot_size": 1})
+}
+
+/// The body and ETag of `GET /book`.
+async fn book(exchange: &TestExchange) -> (Value, String) {
+    let res = exchange
+        .client()
+        .get(exchange.url(&format!("/book?symbol={}", TEST_SYMBOL)))
+        .send()
+        .await
+        .unwrap();
+    let etag = res.headers()["etag"].to_str().unwrap().to_string();
+    (res.json().await.unwrap(), etag)
+}
+
+#[tokio::test]
+async fn responses_about_a_symbol_carry_its_scales() {
+    let (exchange, admin) = start().await;
+    let seller = exchange.register("seller", "secret").await;
+    let buyer = exchange.register("buyer", "secret").await;
+    let placed = exchange
+        .send_json(
+            exchange
+                .client()
+                .post(exchange.url("/orders"))
+                .bearer_auth(&seller.token)
+                .json(&OrderRequest::limit(TEST_SYMBOL, OrderSide::Sell, 1_000, 2)),
+        )
+        .await
+        .unwrap();
+    assert_eq!(placed["meta"], meta(1));
+    exchange
+        .place_order(
+            &buyer,
+            &OrderRequest::limit(TEST_SYMBOL, OrderSide::Buy, 1_000, 1),
+        )
+        .await
+        .unwrap();
+
+    let order_id = placed["id"].as_str().unwrap();
+    let paths = [
+        format!("/orders/{}?symbol={}", order_id, TEST_SYMBOL),
+        format!("/orders/me?symbol={}", TEST_SYMBOL),
+        format!("/trades?symbol={}", TEST_SYMBOL),
+        format!("/trades/me?symbol={}", TEST_SYMBOL),
+        format!("/ticker?symbol={}", TEST_SYMBOL),
+        format!("/symbols/{}", TEST_SYMBOL),
+    ];
+    for path in &paths {
+        let body = exchange.get(&seller, path).await.unwrap();
+        assert_eq!(body["meta"], meta(1), "{}", path);
+    }
+    let symbols = exchange.get(&seller, "/symbols").await.unwrap();
+    let listed = symbols
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|s| s["symbol"] == TEST_SYMBOL)
+        .unwrap();
+    assert_eq!(listed["meta"], meta(1));
+    // Without a symbol filter a listing may span symbols and has no single set of scales
+    let mine = exchange.get(&seller, "/orders/me").await.unwrap();
+    assert!(mine.get("meta").is_none());
+
+    let (body, etag) = book(&exchange).await;
+    assert_eq!(body["meta"], meta(1));
+    let mut ws = exchange.ws_client(None).await;
+    ws.send_json(json!({"action": "subscribe", "symbol": TEST_SYMBOL}))
+        .await;
+    assert_eq!(ws.next_json().await["status"], "success");
+    let info = ws.next_json().await;
+    assert_eq!(info["type"], "ExchangeInfo");
+    assert_eq!(info["symbol"], TEST_SYMBOL);
+    assert_eq!(info["meta"], meta(1));
+
+    // A scale change is a new book body even though no order moved
+    exchange
+        .send_json(
+            exchange
+                .client()
+                .patch(exchange.url(&format!("/admin/symbols/{}", TEST_SYMBOL)))
+                .bearer_auth(&admin.token)
+                .json(&json!({"tick_size": 10})),
+        )
+        .await
+        .unwrap();
+    let (body, changed) = book(&exchange).await;
+    assert_eq!(body["meta"], meta(10));
+    assert_ne!(changed, etag);
+}
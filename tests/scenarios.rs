@@ -0,0 +1,756 @@
+//! End-to-end scenarios through the public HTTP and WebSocket surface, on the
+//! `rust_exchange::testing` harness.
+
+use chrono::Utc;
+use rust_exchange::orderbook::orderbook::PricingPolicy;
+use rust_exchange::testing::{OrderRequest, TEST_SYMBOL, TestExchange, Token};
+use rust_exchange::types::order::{MAX_ORDER_QUANTITY, OrderSide, OrderStatus, RejectReason};
+use rust_exchange::types::symbol::SymbolConfig;
+use rust_exchange::types::trade::TradeRole;
+use serde_json::{Value, json};
+use std::collections::HashMap;
+use uuid::Uuid;
+
+#[tokio::test]
+async fn register_place_match_then_positions_and_trades_agree() {
+    let exchange = TestExchange::start().await;
+    let maker = exchange.register("maker", "secret").await;
+    let taker = exchange.register("taker", "secret").await;
+    let mut feed = exchange.ws_client(None).await;
+    feed.subscribe(TEST_SYMBOL).await;
+
+    let ask = exchange
+        .place_order(
+            &maker,
+            &OrderRequest::limit(TEST_SYMBOL, OrderSide::Sell, 100, 5),
+        )
+        .await
+        .unwrap();
+    assert_eq!(ask.order.status, OrderStatus::Pending);
+    assert!(ask.trades.is_empty());
+    let book = exchange.book(TEST_SYMBOL).await;
+    assert_eq!(book.asks, vec![(100, 5)]);
+
+    let bid = exchange
+        .place_order(
+            &taker,
+            &OrderRequest::limit(TEST_SYMBOL, OrderSide::Buy, 100, 3),
+        )
+        .await
+        .unwrap();
+    assert_eq!(bid.order.status, OrderStatus::Filled);
+    assert_eq!(bid.trades.len(), 1);
+    let trade = &bid.trades[0];
+    assert_eq!((trade.price, trade.quantity), (100, 3));
+    assert_eq!(trade.maker_order_id, ask.order.id);
+    assert_eq!(exchange.book(TEST_SYMBOL).await.asks, vec![(100, 2)]);
+
+    let broadcast = feed.next_of_type("Trade").await;
+    assert_eq!(broadcast["trade"]["id"], trade.id.to_string());
+
+    let maker_positions = exchange.positions(&maker).await.unwrap();
+    assert_eq!(maker_positions.len(), 1);
+    assert_eq!(maker_positions[0].quantity, -3);
+    let taker_positions = exchange.positions(&taker).await.unwrap();
+    assert_eq!(taker_positions[0].quantity, 3);
+    assert_eq!(taker_positions[0].average_price, 100);
+
+    for token in [&maker, &taker] {
+        let trades = exchange.trades_me(token, None).await.unwrap();
+        assert_eq!(trades.len(), 1);
+        assert_eq!(trades[0].trade, *trade);
+    }
+
+    // A market order larger than the book fills what rests and nothing more
+    let sweep = exchange
+        .place_order(
+            &taker,
+            &OrderRequest::market(TEST_SYMBOL, OrderSide::Buy, 4),
+        )
+        .await
+        .unwrap();
+    assert_eq!(sweep.trades.iter().map(|t| t.quantity).sum::<u64>(), 2);
+    assert!(exchange.book(TEST_SYMBOL).await.asks.is_empty());
+    let error = exchange
+        .place_order(
+            &taker,
+            &OrderRequest::market(TEST_SYMBOL, OrderSide::Buy, 1),
+        )
+        .await
+        .unwrap_err();
+    assert_eq!(error.status.as_u16(), 400);
+    assert_eq!(error.rejection(), Some(RejectReason::NoLiquidity));
+}
+
+#[tokio::test]
+async fn seeded_books_users_and_positions_are_served() {
+    let exchange = TestExchange::builder()
+        .symbol("ETHUSDT")
+        .user("alice", "secret")
+        .user("bob", "secret")
+        .order("ETHUSDT", "alice", OrderSide::Buy, 90, 2)
+        .order("ETHUSDT", "bob", OrderSide::Sell, 95, 1)
+        .position("alice", "ETHUSDT", 4, 80)
+        .start()
+        .await;
+
+    let book = exchange.book("ETHUSDT").await;
+    assert_eq!(book.bids, vec![(90, 2)]);
+    assert_eq!(book.asks, vec![(95, 1)]);
+    assert!(exchange.book(TEST_SYMBOL).await.bids.is_empty());
+
+    let alice = exchange.login("alice", "secret").await.unwrap();
+    let positions = exchange.positions(&alice).await.unwrap();
+    assert_eq!(positions.len(), 1);
+    assert_eq!((positions[0].quantity, positions[0].average_price), (4, 80));
+
+    // Seeded liquidity trades like any other
+    let bob = exchange.login("bob", "secret").await.unwrap();
+    let report = exchange
+        .place_order(
+            &bob,
+            &OrderRequest::limit("ETHUSDT", OrderSide::Sell, 90, 2),
+        )
+        .await
+        .unwrap();
+    assert_eq!(report.order.status, OrderStatus::Filled);
+    let positions = exchange.positions(&alice).await.unwrap();
+    assert_eq!(positions[0].quantity, 6);
+}
+
+#[tokio::test]
+async fn cancel_after_a_partial_fill_returns_what_was_still_open() {
+    let exchange = TestExchange::start().await;
+    let maker = exchange.register("maker", "secret").await;
+    let taker = exchange.register("taker", "secret").await;
+
+    let ask = exchange
+        .place_order(
+            &maker,
+            &OrderRequest::limit(TEST_SYMBOL, OrderSide::Sell, 100, 5),
+        )
+        .await
+        .unwrap();
+    exchange
+        .place_order(
+            &taker,
+            &OrderRequest::limit(TEST_SYMBOL, OrderSide::Buy, 100, 3),
+        )
+        .await
+        .unwrap();
+
+    let error = exchange
+        .cancel_order(&taker, TEST_SYMBOL, ask.order.id)
+        .await
+        .unwrap_err();
+    assert_eq!(error.status.as_u16(), 403);
+    let cancelled = exchange
+        .cancel_order(&maker, TEST_SYMBOL, ask.order.id)
+        .await
+        .unwrap();
+    assert_eq!(cancelled["id"], ask.order.id.to_string());
+    assert_eq!(cancelled["status"], "Cancelled");
+    assert_eq!(cancelled["quantity"], 2);
+    assert_eq!(cancelled["remaining_quantity"], 2);
+    assert_eq!(cancelled["close_reason"], "USER_CANCELLED");
+    assert!(cancelled["closed_at"].is_string());
+    assert!(exchange.book(TEST_SYMBOL).await.asks.is_empty());
+
+    // A repeated cancel is answered with the same closure
+    let repeated = exchange
+        .cancel_order(&maker, TEST_SYMBOL, ask.order.id)
+        .await
+        .unwrap();
+    assert_eq!(repeated, cancelled);
+    let error = exchange
+        .cancel_order(&taker, TEST_SYMBOL, ask.order.id)
+        .await
+        .unwrap_err();
+    assert_eq!(error.status.as_u16(), 404);
+}
+
+/// One SSE event as (event name, id, data), skipping keep-alive comments; panics if none
+/// arrives within a second.
+async fn next_sse_event(
+    res: &mut reqwest::Response,
+    buffer: &mut String,
+) -> (String, String, serde_json::Value) {
+    loop {
+        if let Some(end) = buffer.find("\n\n") {
+            let frame: String = buffer.drain(..end + 2).collect();
+            let mut fields = std::collections::HashMap::new();
+            for line in frame
+                .lines()
+                .filter(|line| !line.is_empty() && !line.starts_with(':'))
+            {
+                let (name, value) = line.split_once(':').unwrap();
+                fields.insert(name.to_string(), value.trim_start().to_string());
+            }
+            if fields.is_empty() {
+                continue;
+            }
+            return (
+                fields.remove("event").unwrap(),
+                fields.remove("id").unwrap(),
+                serde_json::from_str(&fields["data"]).unwrap(),
+            );
+        }
+        let chunk = tokio::time::timeout(std::time::Duration::from_secs(1), res.chunk())
+            .await
+            .expect("SSE event within timeout")
+            .unwrap()
+            .expect("SSE stream still open");
+        buffer.push_str(std::str::from_utf8(&chunk).unwrap());
+    }
+}
+
+#[tokio::test]
+async fn sse_market_stream_frames_book_and_trade_events() {
+    let exchange = TestExchange::start().await;
+    let maker = exchange.register("maker", "secret").await;
+    let taker = exchange.register("taker", "secret").await;
+    let open = |query: &str, last_event_id: Option<&str>| {
+        let mut request = exchange
+            .client()
+            .get(exchange.url(&format!("/sse/market?{}", query)));
+        if let Some(id) = last_event_id {
+            request = request.header("Last-Event-ID", id);
+        }
+        request.send()
+    };
+
+    let mut res = open("symbol=btcusdt", None).await.unwrap();
+    assert_eq!(res.status().as_u16(), 200);
+    assert_eq!(res.headers()["content-type"], "text/event-stream");
+    let mut buffer = String::new();
+
+    exchange
+        .place_order(
+            &maker,
+            &OrderRequest::limit(TEST_SYMBOL, OrderSide::Sell, 100, 5),
+        )
+        .await
+        .unwrap();
+    let (event, id, data) = next_sse_event(&mut res, &mut buffer).await;
+    assert_eq!((event.as_str(), id.as_str()), ("book", "1"));
+    assert_eq!(data["type"], "OrderBookUpdate");
+    assert_eq!(data["asks"], serde_json::json!([[100, 5]]));
+
+    exchange
+        .place_order(
+            &taker,
+            &OrderRequest::limit(TEST_SYMBOL, OrderSide::Buy, 100, 2),
+        )
+        .await
+        .unwrap();
+    let mut events = Vec::new();
+    for _ in 0..2 {
+        let (event, id, _) = next_sse_event(&mut res, &mut buffer).await;
+        events.push((event, id));
+    }
+    events.sort();
+    assert_eq!(
+        events,
+        vec![
+            ("book".to_string(), "3".to_string()),
+            ("trades".to_string(), "2".to_string())
+        ]
+    );
+
+    // A reconnect gets a snapshot first, numbered after the last id seen
+    let mut res = open("symbol=BTCUSDT&channels=book", Some("3"))
+        .await
+        .unwrap();
+    let mut buffer = String::new();
+    let (event, id, data) = next_sse_event(&mut res, &mut buffer).await;
+    assert_eq!((event.as_str(), id.as_str()), ("book", "4"));
+    assert_eq!(data["asks"], serde_json::json!([[100, 3]]));
+
+    assert_eq!(
+        open("symbol=DOGEUSDT", None)
+            .await
+            .unwrap()
+            .status()
+            .as_u16(),
+        404
+    );
+    assert_eq!(
+        open("symbol=BTCUSDT&channels=book,quotes", None)
+            .await
+            .unwrap()
+            .status()
+            .as_u16(),
+        400
+    );
+}
+
+#[tokio::test]
+async fn order_quantities_are_bounded_at_ingest() {
+    let exchange = TestExchange::start().await;
+    let trader = exchange.register("trader", "secret").await;
+
+    for quantity in [0, MAX_ORDER_QUANTITY + 1, u64::MAX] {
+        let error = exchange
+            .place_order(
+                &trader,
+                &OrderRequest::limit(TEST_SYMBOL, OrderSide::Buy, 100, quantity),
+            )
+            .await
+            .unwrap_err();
+        assert_eq!(error.status.as_u16(), 400);
+        assert_eq!(error.rejection(), Some(RejectReason::InvalidQuantity));
+    }
+    let report = exchange
+        .place_order(
+            &trader,
+            &OrderRequest::limit(TEST_SYMBOL, OrderSide::Buy, 100, MAX_ORDER_QUANTITY),
+        )
+        .await
+        .unwrap();
+    assert_eq!(report.order.quantity, MAX_ORDER_QUANTITY);
+    assert_eq!(
+        exchange.book(TEST_SYMBOL).await.bids,
+        vec![(100, MAX_ORDER_QUANTITY)]
+    );
+}
+
+#[tokio::test]
+async fn configured_pricing_policy_sets_the_price_positions_see() {
+    let exchange = TestExchange::builder()
+        .with_state(|state| {
+            state.symbol_configs.try_write().unwrap().insert(
+                TEST_SYMBOL.to_string(),
+                SymbolConfig {
+                    tick_size: 10,
+                    pricing_policy: PricingPolicy::Midpoint,
+                    ..SymbolConfig::default()
+                },
+            );
+        })
+        .start()
+        .await;
+    let maker = exchange.register("maker", "secret").await;
+    let taker = exchange.register("taker", "secret").await;
+
+    exchange
+        .place_order(
+            &maker,
+            &OrderRequest::limit(TEST_SYMBOL, OrderSide::Sell, 1000, 2),
+        )
+        .await
+        .unwrap();
+    // Midpoint of 1000 and 1030 is 1015, rounded toward the maker onto the 10 tick
+    let report = exchange
+        .place_order(
+            &taker,
+            &OrderRequest::limit(TEST_SYMBOL, OrderSide::Buy, 1030, 2),
+        )
+        .await
+        .unwrap();
+    assert_eq!(report.trades[0].price, 1010);
+
+    let taker_positions = exchange.positions(&taker).await.unwrap();
+    assert_eq!(taker_positions[0].average_price, 1010);
+    let maker_positions = exchange.positions(&maker).await.unwrap();
+    assert_eq!(maker_positions[0].average_price, 1010);
+    let trades = exchange.trades_me(&maker, None).await.unwrap();
+    assert_eq!(trades[0].trade.price, 1010);
+}
+
+#[tokio::test]
+async fn trades_me_attributes_fees_and_realized_pnl_to_each_fill() {
+    let exchange = TestExchange::builder()
+        .with_state(|state| {
+            state.symbol_configs.try_write().unwrap().insert(
+                TEST_SYMBOL.to_string(),
+                SymbolConfig {
+                    maker_fee_bps: -2,
+                    taker_fee_bps: 10,
+                    ..SymbolConfig::default()
+                },
+            );
+        })
+        .start()
+        .await;
+    let trader = exchange.register("trader", "secret").await;
+    let maker = exchange.register("maker", "secret").await;
+    let order = |side, price, qty| OrderRequest::limit(TEST_SYMBOL, side, price, qty);
+
+    exchange
+        .place_order(&maker, &order(OrderSide::Sell, 10_000, 5))
+        .await
+        .unwrap();
+    exchange
+        .place_order(&trader, &order(OrderSide::Buy, 10_000, 5))
+        .await
+        .unwrap();
+    // Close 3 of the 5 against two bids: 2 at 10_200, then 1 at 10_100
+    exchange
+        .place_order(&maker, &order(OrderSide::Buy, 10_100, 4))
+        .await
+        .unwrap();
+    exchange
+        .place_order(&maker, &order(OrderSide::Buy, 10_200, 2))
+        .await
+        .unwrap();
+    exchange
+        .place_order(&trader, &order(OrderSide::Sell, 10_100, 3))
+        .await
+        .unwrap();
+
+    let trades = exchange.trades_me(&trader, None).await.unwrap();
+    let seen: Vec<_> = trades
+        .iter()
+        .map(|t| (t.role, t.side, t.trade.price, t.fee, t.realized_pnl))
+        .collect();
+    assert_eq!(
+        seen,
+        vec![
+            (TradeRole::Taker, Some(OrderSide::Sell), 10_100, 10, 100),
+            (TradeRole::Taker, Some(OrderSide::Sell), 10_200, 20, 400),
+            (TradeRole::Taker, Some(OrderSide::Buy), 10_000, 50, 0),
+        ]
+    );
+    assert_eq!(exchange.positions(&trader).await.unwrap()[0].quantity, 2);
+
+    // The maker was short 5 at 10_000 and covered 3 of it at a loss, with rebates
+    let trades = exchange.trades_me(&maker, None).await.unwrap();
+    let seen: Vec<_> = trades
+        .iter()
+        .map(|t| (t.role, t.side, t.fee, t.realized_pnl))
+        .collect();
+    assert_eq!(
+        seen,
+        vec![
+            (TradeRole::Maker, Some(OrderSide::Buy), -2, -100),
+            (TradeRole::Maker, Some(OrderSide::Buy), -4, -400),
+            (TradeRole::Maker, Some(OrderSide::Sell), -10, 0),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn makers_see_their_queue_rank_on_each_fill() {
+    let exchange = TestExchange::start().await;
+    let taker = exchange.register("taker", "secret").await;
+    let mut makers = Vec::new();
+    for name in ["first", "second", "third"] {
+        let maker = exchange.register(name, "secret").await;
+        exchange
+            .place_order(
+                &maker,
+                &OrderRequest::limit(TEST_SYMBOL, OrderSide::Sell, 100, 2),
+            )
+            .await
+            .unwrap();
+        makers.push(maker);
+    }
+
+    // Fills the first maker at the front, then the second once the first is gone
+    exchange
+        .place_order(
+            &taker,
+            &OrderRequest::limit(TEST_SYMBOL, OrderSide::Buy, 100, 3),
+        )
+        .await
+        .unwrap();
+
+    for maker in &makers[..2] {
+        let trades = exchange.trades_me(maker, None).await.unwrap();
+        assert_eq!(trades.len(), 1);
+        assert_eq!(trades[0].role, TradeRole::Maker);
+        assert_eq!(trades[0].trade.maker_queue_rank, Some(0));
+    }
+    let fills: Vec<_> = exchange
+        .trades_me(&taker, None)
+        .await
+        .unwrap()
+        .iter()
+        .map(|t| (t.trade.quantity, t.trade.maker_queue_rank))
+        .collect();
+    assert_eq!(fills, vec![(1, Some(0)), (2, Some(0))]);
+    assert!(
+        exchange
+            .trades_me(&makers[2], None)
+            .await
+            .unwrap()
+            .is_empty()
+    );
+}
+
+#[tokio::test]
+async fn sandbox_symbols_are_private_to_their_owner_and_invitees() {
+    let exchange = TestExchange::start().await;
+    let owner = exchange.register("owner", "secret").await;
+    let guest = exchange.register("guest", "secret").await;
+    let outsider = exchange.register("outsider", "secret").await;
+    let sandbox = |token: &Token, method: reqwest::Method, path: &str| {
+        exchange
+            .client()
+            .request(method, exchange.url(&format!("/sandbox/symbols{}", path)))
+            .bearer_auth(&token.token)
+    };
+
+    let created = exchange
+        .send_json(sandbox(&owner, reqwest::Method::POST, "").json(&json!({ "name": "bot1" })))
+        .await
+        .unwrap();
+    assert_eq!(created["symbol"], "SBXBOT1");
+    assert_eq!(created["owner"], owner.user_id.to_string());
+    let ask = exchange
+        .place_order(
+            &owner,
+            &OrderRequest::limit("SBXBOT1", OrderSide::Sell, 100, 5),
+        )
+        .await
+        .unwrap();
+    assert_eq!(ask.order.status, OrderStatus::Pending);
+
+    // To anyone else the symbol does not exist, on any surface
+    let error = exchange
+        .place_order(
+            &outsider,
+            &OrderRequest::limit("SBXBOT1", OrderSide::Buy, 100, 1),
+        )
+        .await
+        .unwrap_err();
+    assert_eq!(error.rejection(), Some(RejectReason::UnknownSymbol));
+    let error = exchange
+        .cancel_order(&outsider, "SBXBOT1", ask.order.id)
+        .await
+        .unwrap_err();
+    assert_eq!(error.status.as_u16(), 404);
+    let error = exchange
+        .send_json(sandbox(&outsider, reqwest::Method::GET, "/SBXBOT1/book"))
+        .await
+        .unwrap_err();
+    assert_eq!(error.status.as_u16(), 404);
+    for path in [
+        "/book?symbol=SBXBOT1",
+        "/symbols/SBXBOT1",
+        "/sse/market?symbol=SBXBOT1",
+    ] {
+        let res = exchange
+            .client()
+            .get(exchange.url(path))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(res.status().as_u16(), 404, "{}", path);
+    }
+    let books = exchange
+        .send_json(
+            exchange
+                .client()
+                .get(exchange.url("/books?symbols=BTCUSDT,SBXBOT1")),
+        )
+        .await
+        .unwrap();
+    assert!(books["errors"]["SBXBOT1"].is_string());
+    let mut socket = exchange.ws_client(Some(&outsider)).await;
+    socket
+        .send_json(json!({ "action": "subscribe", "symbol": "SBXBOT1" }))
+        .await;
+    assert_eq!(socket.next_json().await["status"], "error");
+    let mut socket = exchange.ws_client(Some(&owner)).await;
+    socket.subscribe("SBXBOT1").await;
+
+    // Only the owner can invite; invitees trade like the owner
+    let error = exchange
+        .send_json(
+            sandbox(&guest, reqwest::Method::POST, "/SBXBOT1/members")
+                .json(&json!({ "username": "guest" })),
+        )
+        .await
+        .unwrap_err();
+    assert_eq!(error.status.as_u16(), 404);
+    let invited = exchange
+        .send_json(
+            sandbox(&owner, reqwest::Method::POST, "/SBXBOT1/members")
+                .json(&json!({ "username": "Guest" })),
+        )
+        .await
+        .unwrap();
+    assert_eq!(invited["members"], json!([guest.user_id.to_string()]));
+    let bid = exchange
+        .place_order(
+            &guest,
+            &OrderRequest::limit("SBXBOT1", OrderSide::Buy, 100, 2),
+        )
+        .await
+        .unwrap();
+    assert_eq!(bid.order.status, OrderStatus::Filled);
+    assert_eq!(socket.next_of_type("Trade").await["symbol"], "SBXBOT1");
+    let book = exchange
+        .send_json(sandbox(&guest, reqwest::Method::GET, "/SBXBOT1/book"))
+        .await
+        .unwrap();
+    assert_eq!(book["asks"], json!([[100, 3]]));
+    let positions = exchange.positions(&guest).await.unwrap();
+    assert_eq!(
+        (positions[0].symbol.as_str(), positions[0].quantity),
+        ("SBXBOT1", 2)
+    );
+    let listed = exchange
+        .send_json(sandbox(&guest, reqwest::Method::GET, ""))
+        .await
+        .unwrap();
+    assert_eq!(listed.as_array().unwrap().len(), 1);
+
+    // A user may own a limited number of sandboxes, and names are unique
+    for name in ["bot2", "bot3"] {
+        exchange
+            .send_json(sandbox(&owner, reqwest::Method::POST, "").json(&json!({ "name": name })))
+            .await
+            .unwrap();
+    }
+    for (token, name) in [(&owner, "bot4"), (&outsider, "bot1")] {
+        let error = exchange
+            .send_json(sandbox(token, reqwest::Method::POST, "").json(&json!({ "name": name })))
+            .await
+            .unwrap_err();
+        assert_eq!(error.status.as_u16(), 409);
+    }
+
+    // Closing a sandbox drops its book and the positions on it
+    let res = sandbox(&guest, reqwest::Method::DELETE, "/SBXBOT1")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status().as_u16(), 403);
+    let res = sandbox(&owner, reqwest::Method::DELETE, "/SBXBOT1")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status().as_u16(), 204);
+    assert!(exchange.positions(&guest).await.unwrap().is_empty());
+    let error = exchange
+        .place_order(
+            &owner,
+            &OrderRequest::limit("SBXBOT1", OrderSide::Buy, 100, 1),
+        )
+        .await
+        .unwrap_err();
+    assert_eq!(error.rejection(), Some(RejectReason::UnknownSymbol));
+}
+
+#[tokio::test]
+async fn idle_sandboxes_expire() {
+    let exchange = TestExchange::start().await;
+    let owner = exchange.register("owner", "secret").await;
+    let create = |name: &str| {
+        exchange.send_json(
+            exchange
+                .client()
+                .post(exchange.url("/sandbox/symbols"))
+                .bearer_auth(&owner.token)
+                .json(&json!({ "name": name })),
+        )
+    };
+    create("idle").await.unwrap();
+    create("busy").await.unwrap();
+
+    let sandboxes = &exchange.state.sandboxes;
+    let later = Utc::now() + chrono::Duration::minutes(20);
+    assert!(
+        sandboxes
+            .book_for("SBXBUSY", owner.user_id, later)
+            .await
+            .is_some()
+    );
+    let expired = sandboxes
+        .take_idle(later + chrono::Duration::minutes(15))
+        .await;
+    let symbols: Vec<&str> = expired.iter().map(|(symbol, _)| symbol.as_str()).collect();
+    assert_eq!(symbols, vec!["SBXIDLE"]);
+    let listed = sandboxes.list(owner.user_id).await;
+    assert_eq!(listed.len(), 1);
+    assert_eq!(listed[0].symbol, "SBXBUSY");
+}
+
+/// Admission sequence and JSON body of an order-entry response.
+async fn sequenced(request: reqwest::RequestBuilder) -> (u64, u16, Value) {
+    let res = request.send().await.unwrap();
+    let seq = res.headers()["x-ingress-seq"]
+        .to_str()
+        .unwrap()
+        .parse()
+        .unwrap();
+    let status = res.status().as_u16();
+    (seq, status, res.json().await.unwrap_or(Value::Null))
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn no_order_fills_after_a_cancel_admitted_before_the_crossing_order() {
+    let exchange = TestExchange::start().await;
+    let maker = exchange.register("maker", "secret").await;
+    let mut takers = Vec::new();
+    for i in 0..4 {
+        takers.push(exchange.register(&format!("taker{}", i), "secret").await);
+    }
+    let mut asks = Vec::new();
+    for _ in 0..20 {
+        let ask = exchange
+            .place_order(
+                &maker,
+                &OrderRequest::limit(TEST_SYMBOL, OrderSide::Sell, 100, 2),
+            )
+            .await
+            .unwrap();
+        asks.push(ask.order.id);
+    }
+
+    // Interleave a cancel of every ask with two crossing buys, all in flight at once
+    let mut cancels = Vec::new();
+    let mut buys = Vec::new();
+    for (i, ask) in asks.iter().enumerate() {
+        for taker in [&takers[i % 4], &takers[(i + 1) % 4]] {
+            let request = exchange
+                .client()
+                .post(exchange.url("/orders"))
+                .bearer_auth(&taker.token)
+                .json(&OrderRequest::limit(TEST_SYMBOL, OrderSide::Buy, 100, 1));
+            buys.push(tokio::spawn(sequenced(request)));
+        }
+        let request = exchange
+            .client()
+            .delete(exchange.url(&format!("/orders/{}?symbol={}", ask, TEST_SYMBOL)))
+            .bearer_auth(&maker.token);
+        cancels.push((*ask, tokio::spawn(sequenced(request))));
+    }
+
+    let mut cancel_seqs = HashMap::new();
+    for (ask, cancel) in cancels {
+        let (seq, status, _) = cancel.await.unwrap();
+        assert!(status == 200 || status == 404, "cancel {}", status);
+        cancel_seqs.insert(ask, seq);
+    }
+    let mut buy_seqs: HashMap<Uuid, u64> = HashMap::new();
+    for buy in buys {
+        let (seq, status, body) = buy.await.unwrap();
+        assert_eq!(status, 200);
+        buy_seqs.insert(body["id"].as_str().unwrap().parse().unwrap(), seq);
+    }
+
+    let fills = exchange
+        .get(
+            &maker,
+            &format!("/trades/me?symbol={}&limit=1000", TEST_SYMBOL),
+        )
+        .await
+        .unwrap();
+    assert_eq!(fills["has_more"], false);
+    let fills = fills["items"].as_array().unwrap();
+    assert!(!fills.is_empty());
+    for fill in fills {
+        let ask: Uuid = fill["maker_order_id"].as_str().unwrap().parse().unwrap();
+        let buy: Uuid = fill["taker_order_id"].as_str().unwrap().parse().unwrap();
+        assert!(
+            buy_seqs[&buy] < cancel_seqs[&ask],
+            "ask {} filled by order admitted at {} after its cancel at {}",
+            ask,
+            buy_seqs[&buy],
+            cancel_seqs[&ask]
+        );
+    }
+}
@@ -0,0 +1,243 @@
+//! File snapshots of the in-memory exchange: round trips through the file format and the
+//! errors a damaged or foreign file gives.
+
+use std::sync::Arc;
+
+use rust_exchange::api::routes::AppState;
+use rust_exchange::hydration::Hydrated;
+use rust_exchange::orderbook::orderbook::{PricingPolicy, TradingPhase};
+use rust_exchange::snapshot::{
+    self, ExchangeSnapshot, SNAPSHOT_FORMAT_VERSION, SNAPSHOT_MAGIC, SnapshotError,
+};
+use rust_exchange::testing::{
+    OrderRequest, TEST_JWT_SECRET, TEST_SYMBOL, TestExchange, test_app_state,
+};
+use rust_exchange::types::order::OrderSide;
+use rust_exchange::types::symbol::SymbolConfig;
+use tokio::sync::RwLock;
+
+const SYMBOLS: &[&str] = &[TEST_SYMBOL, "ETHUSDT"];
+
+/// A fresh state holding what `hydrated` restored.
+fn state_from(hydrated: Hydrated) -> AppState {
+    let mut state = test_app_state();
+    state.orderbooks = hydrated
+        .orderbooks
+        .into_iter()
+        .map(|(symbol, book)| (symbol, Arc::new(RwLock::new(book))))
+        .collect();
+    state.user_store = Arc::new(RwLock::new(hydrated.users));
+    state.positions = Arc::new(RwLock::new(hydrated.positions));
+    state.symbol_configs = Arc::new(RwLock::new(hydrated.symbol_configs));
+    state
+}
+
+fn temp_path(name: &str) -> std::path::PathBuf {
+    std::env::temp_dir().join(format!("{}-{}.snap", name, uuid::Uuid::new_v4()))
+}
+
+#[tokio::test]
+async fn restored_state_equals_the_state_that_was_saved() {
+    let exchange = TestExchange::builder()
+        .symbol("ETHUSDT")
+        .with_state(|state| {
+            // Restoring gives every configured symbol a config, so start with one each
+            let mut configs = state.symbol_configs.try_write().unwrap();
+            configs.insert(
+                TEST_SYMBOL.to_string(),
+                SymbolConfig::for_symbol(TEST_SYMBOL),
+            );
+            configs.insert(
+                "ETHUSDT".to_string(),
+                SymbolConfig {
+                    pricing_policy: PricingPolicy::Midpoint,
+                    ..SymbolConfig::for_symbol("ETHUSDT")
+                },
+            );
+        })
+        .start()
+        .await;
+    let maker = exchange.register("maker", "secret").await;
+    let other = exchange.register("Other", "hunter2").await;
+    let taker = exchange.register("taker", "secret").await;
+    // Two makers queued at one price, then a partial fill of the first
+    for (token, price, qty) in [(&maker, 100, 5), (&other, 100, 4), (&maker, 102, 1)] {
+        exchange
+            .place_order(
+                token,
+                &OrderRequest::limit(TEST_SYMBOL, OrderSide::Sell, price, qty),
+            )
+            .await
+            .unwrap();
+    }
+    exchange
+        .place_order(
+            &maker,
+            &OrderRequest::limit("ETHUSDT", OrderSide::Buy, 50, 2),
+        )
+        .await
+        .unwrap();
+    exchange
+        .place_order(
+            &taker,
+            &OrderRequest::limit(TEST_SYMBOL, OrderSide::Buy, 100, 3),
+        )
+        .await
+        .unwrap();
+    exchange.state.orderbooks["ETHUSDT"]
+        .write()
+        .await
+        .start_auction();
+
+    let path = temp_path("round-trip");
+    snapshot::save(&exchange.state, &path).await.unwrap();
+    let saved = snapshot::capture(&exchange.state).await;
+    let loaded = snapshot::read_file(&path)
+        .unwrap()
+        .expect("snapshot written");
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(loaded.users.len(), 3);
+    assert_eq!(loaded.positions.len(), 2);
+
+    let hydrated = snapshot::restore(loaded, SYMBOLS);
+    assert!(hydrated.report.is_clean(), "{}", hydrated.report);
+    assert_eq!(hydrated.report.per_symbol_restored[TEST_SYMBOL], 3);
+    let eth = &hydrated.orderbooks["ETHUSDT"];
+    assert_eq!(eth.phase(), TradingPhase::Auction);
+    assert_eq!(eth.pricing_policy(), PricingPolicy::Midpoint);
+    let restored = TestExchange::builder_with_state(state_from(hydrated))
+        .start()
+        .await;
+    let recaptured = snapshot::capture(&restored.state).await;
+    assert_eq!(
+        ExchangeSnapshot {
+            taken_at: saved.taken_at,
+            ..recaptured
+        },
+        saved
+    );
+
+    // The restored exchange carries on: logins work, time priority and sequencing hold
+    let other = restored.login("other", "hunter2").await.unwrap();
+    let report = restored
+        .place_order(
+            &other,
+            &OrderRequest::limit(TEST_SYMBOL, OrderSide::Buy, 100, 3),
+        )
+        .await
+        .unwrap();
+    let makers: Vec<_> = report
+        .trades
+        .iter()
+        .map(|t| (t.maker_user_id, t.quantity))
+        .collect();
+    assert_eq!(makers, vec![(maker.user_id, 2), (other.user_id, 1)]);
+    let last_seq = saved.books.iter().map(|b| b.last_trade_seq).max().unwrap();
+    assert_eq!(report.trades[0].trade_seq, last_seq + 1);
+    let taker = restored.login("taker", "secret").await.unwrap();
+    let positions = restored.positions(&taker).await.unwrap();
+    assert_eq!(positions[0].quantity, 3);
+}
+
+#[tokio::test]
+async fn snapshots_hold_password_hashes_but_no_secrets() {
+    let exchange = TestExchange::start().await;
+    exchange.register("alice", "correct horse").await;
+    let bytes = snapshot::encode(&snapshot::capture(&exchange.state).await);
+    let text = String::from_utf8_lossy(&bytes);
+    assert!(text.contains("$argon2"));
+    assert!(!text.contains("correct horse"));
+    assert!(!text.contains(std::str::from_utf8(TEST_JWT_SECRET).unwrap()));
+}
+
+#[test]
+fn damaged_or_foreign_files_are_refused() {
+    let snapshot = ExchangeSnapshot {
+        symbol_configs: [(TEST_SYMBOL.to_string(), SymbolConfig::default())].into(),
+        ..ExchangeSnapshot::default()
+    };
+    let good = snapshot::encode(&snapshot);
+    assert_eq!(&good[..8], SNAPSHOT_MAGIC);
+    assert_eq!(snapshot::decode(&good).unwrap(), snapshot);
+
+    let mut foreign = good.clone();
+    foreign[0] = b'X';
+    assert!(matches!(
+        snapshot::decode(&foreign),
+        Err(SnapshotError::NotASnapshot)
+    ));
+    assert!(matches!(
+        snapshot::decode(b"{}"),
+        Err(SnapshotError::NotASnapshot)
+    ));
+
+    let mut newer = good.clone();
+    newer[8..12].copy_from_slice(&(SNAPSHOT_FORMAT_VERSION + 1).to_le_bytes());
+    let error = snapshot::decode(&newer).unwrap_err();
+    assert!(
+        matches!(error, SnapshotError::UnsupportedVersion(v) if v == SNAPSHOT_FORMAT_VERSION + 1)
+    );
+    assert!(error.to_string().contains("not supported"));
+
+    let truncated = &good[..good.len() - 1];
+    assert!(matches!(
+        snapshot::decode(truncated),
+        Err(SnapshotError::Truncated { .. })
+    ));
+    assert!(matches!(
+        snapshot::decode(&good[..10]),
+        Err(SnapshotError::Truncated { .. })
+    ));
+
+    let mut flipped = good.clone();
+    let last = flipped.len() - 2;
+    flipped[last] ^= 0x01;
+    assert!(matches!(
+        snapshot::decode(&flipped),
+        Err(SnapshotError::ChecksumMismatch)
+    ));
+
+    // A payload that checks out but is not a snapshot (e.g. written by another program)
+    let payload = br#"{"users":42}"#;
+    let mut garbage = good[..12].to_vec();
+    garbage.extend_from_slice(&(payload.len() as u64).to_le_bytes());
+    garbage.extend_from_slice(&fnv1a(payload).to_le_bytes());
+    garbage.extend_from_slice(payload);
+    assert!(matches!(
+        snapshot::decode(&garbage),
+        Err(SnapshotError::Decode(_))
+    ));
+
+    let missing = temp_path("missing");
+    assert!(snapshot::read_file(&missing).unwrap().is_none());
+    std::fs::write(&missing, &flipped).unwrap();
+    let error = snapshot::read_file(&missing).unwrap_err();
+    std::fs::remove_file(&missing).unwrap();
+    assert!(matches!(error, SnapshotError::ChecksumMismatch));
+}
+
+#[test]
+fn books_for_unconfigured_symbols_are_reported() {
+    let mut saved = ExchangeSnapshot::default();
+    saved.books.push(snapshot::BookState {
+        symbol: "DOGEUSDT".to_string(),
+        phase: TradingPhase::Continuous,
+        last_trade_seq: 0,
+        orders: Vec::new(),
+        trades: Vec::new(),
+        attributions: Default::default(),
+        busts: Default::default(),
+    });
+    let hydrated = snapshot::restore(saved, SYMBOLS);
+    assert!(!hydrated.report.is_clean());
+    assert!(hydrated.report.errors[0].contains("DOGEUSDT"));
+    let mut restored: Vec<_> = hydrated.orderbooks.keys().cloned().collect();
+    restored.sort();
+    assert_eq!(restored, vec!["BTCUSDT", "ETHUSDT"]);
+}
+
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
@@ -0,0 +1,211 @@
+//! Spread orders across two books: both legs or neither, and what a fill on one leg does to
+//! the other.
+
+use rust_exchange::orderbook::order_limits::{OrderLimits, RestingOrderCaps};
+use rust_exchange::testing::{ApiError, BookView, OrderRequest, TEST_SYMBOL, TestExchange, Token};
+use rust_exchange::types::order::OrderSide;
+use serde_json::{Value, json};
+use std::sync::Arc;
+use std::time::Duration;
+
+const OTHER: &str = "ETHUSDT";
+
+async fn place_spread(
+    exchange: &TestExchange,
+    token: &Token,
+    legs: [Value; 2],
+    policy: &str,
+) -> Result<Value, ApiError> {
+    exchange
+        .send_json(
+            exchange
+                .client()
+                .post(exchange.url("/orders/spread"))
+                .bearer_auth(&token.token)
+                .json(&json!({ "legs": legs, "policy": policy })),
+        )
+        .await
+}
+
+fn leg(symbol: &str, side: &str, price: i64, quantity: u64) -> Value {
+    json!({ "symbol": symbol, "side": side, "price": price, "quantity": quantity })
+}
+
+/// The book of `symbol` once the spread's policy has run, waiting briefly for it to.
+async fn settled_book(
+    exchange: &TestExchange,
+    symbol: &str,
+    expected: impl Fn(&BookView) -> bool,
+) -> BookView {
+    for _ in 0..100 {
+        let book = exchange.book(symbol).await;
+        if expected(&book) {
+            return book;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    exchange.book(symbol).await
+}
+
+#[tokio::test]
+async fn cancel_other_cancels_the_second_leg_on_the_first_fill() {
+    let exchange = TestExchange::builder().symbol(OTHER).start().await;
+    let trader = exchange.register("trader", "secret").await;
+    let taker = exchange.register("taker", "secret").await;
+
+    let spread = place_spread(
+        &exchange,
+        &trader,
+        [leg(TEST_SYMBOL, "Buy", 100, 4), leg(OTHER, "Sell", 50, 8)],
+        "cancel_other",
+    )
+    .await
+    .unwrap();
+    assert_eq!(spread["policy"], "cancel_other");
+    assert_eq!(spread["orders"].as_array().unwrap().len(), 2);
+    assert_eq!(spread["orders"][0]["source"], "Spread");
+    assert_eq!(spread["legs"][1]["order_id"], spread["orders"][1]["id"]);
+    assert_eq!(exchange.book(TEST_SYMBOL).await.bids, vec![(100, 4)]);
+    assert_eq!(exchange.book(OTHER).await.asks, vec![(50, 8)]);
+
+    exchange
+        .place_order(
+            &taker,
+            &OrderRequest::limit(TEST_SYMBOL, OrderSide::Sell, 100, 1),
+        )
+        .await
+        .unwrap();
+    let other = settled_book(&exchange, OTHER, |b| b.asks.is_empty()).await;
+    assert!(other.asks.is_empty());
+    // The filled leg keeps its remainder
+    assert_eq!(exchange.book(TEST_SYMBOL).await.bids, vec![(100, 3)]);
+}
+
+#[tokio::test]
+async fn reduce_other_keeps_the_legs_ratio_through_partial_fills() {
+    let exchange = TestExchange::builder().symbol(OTHER).start().await;
+    let trader = exchange.register("trader", "secret").await;
+    let taker = exchange.register("taker", "secret").await;
+
+    let spread = place_spread(
+        &exchange,
+        &trader,
+        [leg(TEST_SYMBOL, "Buy", 100, 4), leg(OTHER, "Sell", 50, 8)],
+        "reduce_other",
+    )
+    .await
+    .unwrap();
+    assert_eq!(spread["policy"], "reduce_other");
+
+    exchange
+        .place_order(
+            &taker,
+            &OrderRequest::limit(TEST_SYMBOL, OrderSide::Sell, 100, 1),
+        )
+        .await
+        .unwrap();
+    let other = settled_book(&exchange, OTHER, |b| b.asks == vec![(50, 6)]).await;
+    assert_eq!(other.asks, vec![(50, 6)]);
+
+    // A fill on the reduced leg, now a new order, reduces the first in turn
+    exchange
+        .place_order(&taker, &OrderRequest::limit(OTHER, OrderSide::Buy, 50, 3))
+        .await
+        .unwrap();
+    let first = settled_book(&exchange, TEST_SYMBOL, |b| b.bids == vec![(100, 1)]).await;
+    assert_eq!(first.bids, vec![(100, 1)]);
+    assert_eq!(exchange.book(OTHER).await.asks, vec![(50, 3)]);
+
+    // Filling a leg completely cancels the other
+    exchange
+        .place_order(
+            &taker,
+            &OrderRequest::limit(TEST_SYMBOL, OrderSide::Sell, 100, 1),
+        )
+        .await
+        .unwrap();
+    let other = settled_book(&exchange, OTHER, |b| b.asks.is_empty()).await;
+    assert!(other.asks.is_empty());
+    let mut positions: Vec<_> = exchange
+        .positions(&trader)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|p| (p.symbol, p.quantity))
+        .collect();
+    positions.sort();
+    assert_eq!(
+        positions,
+        vec![(TEST_SYMBOL.to_string(), 2), (OTHER.to_string(), -3)]
+    );
+}
+
+#[tokio::test]
+async fn a_failed_second_leg_rolls_back_the_first() {
+    let limits = Arc::new(OrderLimits::new(RestingOrderCaps {
+        global: Some(1),
+        per_symbol: None,
+    }));
+    let exchange = TestExchange::builder()
+        .symbol(OTHER)
+        .with_state(|state| {
+            for book in state.orderbooks.values() {
+                book.try_write().unwrap().set_order_limits(limits.clone());
+            }
+        })
+        .start()
+        .await;
+    let trader = exchange.register("trader", "secret").await;
+
+    let err = place_spread(
+        &exchange,
+        &trader,
+        [leg(TEST_SYMBOL, "Buy", 100, 4), leg(OTHER, "Sell", 50, 8)],
+        "cancel_other",
+    )
+    .await
+    .unwrap_err();
+    assert_eq!(err.status.as_u16(), 503);
+    assert_eq!(err.body["error_code"], "BOOK_FULL");
+    assert!(exchange.book(TEST_SYMBOL).await.bids.is_empty());
+    assert!(exchange.book(OTHER).await.asks.is_empty());
+    assert_eq!(limits.resting(), 0);
+}
+
+#[tokio::test]
+async fn legs_that_would_trade_or_share_a_symbol_are_rejected_before_placing() {
+    let exchange = TestExchange::builder()
+        .symbol(OTHER)
+        .user("maker", "secret")
+        .order(OTHER, "maker", OrderSide::Buy, 60, 1)
+        .start()
+        .await;
+    let trader = exchange.register("trader", "secret").await;
+
+    let err = place_spread(
+        &exchange,
+        &trader,
+        [leg(TEST_SYMBOL, "Buy", 100, 4), leg(OTHER, "Sell", 50, 8)],
+        "cancel_other",
+    )
+    .await
+    .unwrap_err();
+    assert_eq!(err.status.as_u16(), 400);
+    assert_eq!(err.body["error_code"], "SPREAD_LEG_WOULD_TRADE");
+    assert!(exchange.book(TEST_SYMBOL).await.bids.is_empty());
+    assert_eq!(exchange.book(OTHER).await.bids, vec![(60, 1)]);
+
+    let err = place_spread(
+        &exchange,
+        &trader,
+        [
+            leg(TEST_SYMBOL, "Buy", 100, 4),
+            leg(TEST_SYMBOL, "Sell", 200, 4),
+        ],
+        "reduce_other",
+    )
+    .await
+    .unwrap_err();
+    assert_eq!(err.status.as_u16(), 400);
+    assert!(exchange.book(TEST_SYMBOL).await.asks.is_empty());
+}
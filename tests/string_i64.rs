@@ -0,0 +1,250 @@
+//! Integers beyond JavaScript's safe range are written as JSON strings; both forms are read.
+
+use chrono::{TimeZone, Utc};
+use rust_exchange::api::dto::{OrderDto, PositionDto, TradeDto, UserTradeDto};
+use rust_exchange::api::routes::WsMessage;
+use rust_exchange::market_data::{Candle, TickerPoint};
+use rust_exchange::orderbook::matching_stats::FillVolume;
+use rust_exchange::types::order::{Order, OrderSide, OrderSource, OrderStatus, OrderType};
+use rust_exchange::types::position::Position;
+use rust_exchange::types::string_i64::{self, MAX_SAFE_INTEGER};
+use rust_exchange::types::trade::{Trade, TradeAttribution, UserTrade};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use uuid::Uuid;
+
+const SAFE: i64 = MAX_SAFE_INTEGER as i64;
+const UNSAFE: i64 = SAFE + 1;
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct Fields {
+    #[serde(with = "string_i64")]
+    signed: i64,
+    #[serde(with = "string_i64")]
+    unsigned: u64,
+    #[serde(default, with = "string_i64::option")]
+    optional: Option<i64>,
+}
+
+#[test]
+fn only_values_past_the_safe_range_become_strings() {
+    let fields = |signed, unsigned, optional| Fields {
+        signed,
+        unsigned,
+        optional,
+    };
+    assert_eq!(
+        serde_json::to_value(fields(SAFE, SAFE as u64, None)).unwrap(),
+        json!({ "signed": 9_007_199_254_740_991i64, "unsigned": 9_007_199_254_740_991u64, "optional": null })
+    );
+    assert_eq!(
+        serde_json::to_value(fields(-UNSAFE, u64::MAX, Some(i64::MIN))).unwrap(),
+        json!({
+            "signed": "-9007199254740992",
+            "unsigned": "18446744073709551615",
+            "optional": "-9223372036854775808"
+        })
+    );
+    assert_eq!(
+        serde_json::to_value(fields(-SAFE, 0, Some(UNSAFE))).unwrap(),
+        json!({ "signed": -9_007_199_254_740_991i64, "unsigned": 0, "optional": "9007199254740992" })
+    );
+}
+
+#[test]
+fn numbers_and_strings_are_both_read() {
+    let parse = |value: serde_json::Value| serde_json::from_value::<Fields>(value);
+    let expected = Fields {
+        signed: -UNSAFE,
+        unsigned: u64::MAX,
+        optional: Some(7),
+    };
+    assert_eq!(
+        parse(json!({ "signed": -UNSAFE, "unsigned": u64::MAX, "optional": 7 })).unwrap(),
+        expected
+    );
+    assert_eq!(
+        parse(json!({ "signed": "-9007199254740992", "unsigned": "18446744073709551615", "optional": "7" }))
+            .unwrap(),
+        expected
+    );
+    assert_eq!(
+        parse(json!({ "signed": 1, "unsigned": 2 }))
+            .unwrap()
+            .optional,
+        None
+    );
+    for bad in [
+        json!({ "signed": "1.5", "unsigned": 0 }),
+        json!({ "signed": "", "unsigned": 0 }),
+        json!({ "signed": "1e3", "unsigned": 0 }),
+        json!({ "signed": 1.5, "unsigned": 0 }),
+        json!({ "signed": 0, "unsigned": -1 }),
+        json!({ "signed": 0, "unsigned": "-1" }),
+        json!({ "signed": "9223372036854775808", "unsigned": 0 }),
+    ] {
+        assert!(parse(bad.clone()).is_err(), "{bad}");
+    }
+}
+
+fn at() -> chrono::DateTime<Utc> {
+    Utc.with_ymd_and_hms(2025, 1, 2, 9, 30, 0).unwrap()
+}
+
+fn trade() -> Trade {
+    Trade {
+        id: Uuid::nil(),
+        trade_seq: 1,
+        maker_order_id: Uuid::nil(),
+        taker_order_id: Uuid::nil(),
+        maker_user_id: Uuid::nil(),
+        taker_user_id: Uuid::nil(),
+        price: UNSAFE,
+        quantity: 3,
+        timestamp: at(),
+        maker_queue_rank: None,
+    }
+}
+
+#[test]
+fn orders_trades_and_positions_pin_their_large_fields() {
+    let order = Order {
+        id: Uuid::nil(),
+        user_id: Uuid::nil(),
+        side: OrderSide::Buy,
+        order_type: OrderType::Limit,
+        price: 5_000_000_000_000,
+        quantity: u64::MAX,
+        status: OrderStatus::Pending,
+        timestamp: at(),
+        tags: Default::default(),
+        source: OrderSource::Api,
+    };
+    let dto = OrderDto::from(order.clone());
+    let json = serde_json::to_value(&dto).unwrap();
+    assert_eq!(json["price"], json!(5_000_000_000_000i64));
+    assert_eq!(json["quantity"], json!("18446744073709551615"));
+    // Engine types still read the string form, as older snapshots wrote it
+    assert_eq!(
+        serde_json::from_value::<Order>(json.clone()).unwrap(),
+        order
+    );
+    assert_eq!(serde_json::from_value::<OrderDto>(json).unwrap(), dto);
+
+    let trade = trade();
+    let dto = TradeDto::from(trade.clone());
+    let json = serde_json::to_value(&dto).unwrap();
+    assert_eq!(
+        (&json["price"], &json["quantity"]),
+        (&json!("9007199254740992"), &json!(3))
+    );
+    assert_eq!(
+        serde_json::from_value::<Trade>(json.clone()).unwrap(),
+        trade
+    );
+    assert_eq!(serde_json::from_value::<TradeDto>(json).unwrap(), dto);
+
+    let attribution = TradeAttribution {
+        trade_id: Uuid::nil(),
+        taker_side: OrderSide::Sell,
+        maker_fee: -UNSAFE,
+        taker_fee: 10,
+        realized_pnl_maker: i64::MAX,
+        realized_pnl_taker: -5,
+    };
+    let json = serde_json::to_value(attribution).unwrap();
+    assert_eq!(json["maker_fee"], json!("-9007199254740992"));
+    assert_eq!(json["taker_fee"], json!(10));
+    assert_eq!(json["realized_pnl_maker"], json!("9223372036854775807"));
+    assert_eq!(json["realized_pnl_taker"], json!(-5));
+    assert_eq!(
+        serde_json::from_value::<TradeAttribution>(json).unwrap(),
+        attribution
+    );
+
+    let user_trade = UserTradeDto::from(UserTrade::new(trade, Some(&attribution), Uuid::nil()));
+    let json = serde_json::to_value(&user_trade).unwrap();
+    assert_eq!(json["price"], json!("9007199254740992"));
+    assert_eq!(json["fee"], json!(10));
+    assert_eq!(json["realized_pnl"], json!(-5));
+    assert_eq!(
+        serde_json::from_value::<UserTradeDto>(json).unwrap(),
+        user_trade
+    );
+
+    let position = Position {
+        user_id: Uuid::nil(),
+        symbol: "BTCUSDT".to_string(),
+        quantity: -UNSAFE,
+        average_price: 100,
+        opened_at: at(),
+        updated_at: at(),
+    };
+    let dto = PositionDto::from(position.clone());
+    let json = serde_json::to_value(&dto).unwrap();
+    assert_eq!(json["quantity"], json!("-9007199254740992"));
+    assert_eq!(json["average_price"], json!(100));
+    assert_eq!(
+        serde_json::from_value::<Position>(json.clone()).unwrap(),
+        position
+    );
+    assert_eq!(serde_json::from_value::<PositionDto>(json).unwrap(), dto);
+}
+
+#[test]
+fn market_data_stats_and_messages_pin_their_large_fields() {
+    let candle = Candle {
+        open_time: at(),
+        open: 1,
+        high: UNSAFE,
+        low: 1,
+        close: 2,
+        volume: u64::MAX,
+        trade_count: 2,
+        closed: true,
+    };
+    let json = serde_json::to_value(candle).unwrap();
+    assert_eq!(json["high"], json!("9007199254740992"));
+    assert_eq!(json["volume"], json!("18446744073709551615"));
+    assert_eq!(
+        (&json["open"], &json["trade_count"]),
+        (&json!(1), &json!(2))
+    );
+
+    let point = TickerPoint {
+        timestamp: at(),
+        price: UNSAFE,
+        quantity: 1,
+    };
+    let json = serde_json::to_value(point).unwrap();
+    assert_eq!(
+        (&json["price"], &json["quantity"]),
+        (&json!("9007199254740992"), &json!(1))
+    );
+
+    let volume = FillVolume {
+        limit_maker: u64::MAX,
+        limit_taker: 1,
+        market_taker: 0,
+        auction: 0,
+    };
+    let json = serde_json::to_value(volume).unwrap();
+    assert_eq!(json["limit_maker"], json!("18446744073709551615"));
+    assert_eq!(json["limit_taker"], json!(1));
+
+    let auction = WsMessage::AuctionResult {
+        symbol: "BTCUSDT".to_string(),
+        clearing_price: Some(UNSAFE),
+        volume: 4,
+    };
+    let json = serde_json::to_value(auction).unwrap();
+    assert_eq!(json["clearing_price"], json!("9007199254740992"));
+    assert_eq!(json["volume"], json!(4));
+    let json = serde_json::to_value(WsMessage::AuctionResult {
+        symbol: "BTCUSDT".to_string(),
+        clearing_price: None,
+        volume: 0,
+    })
+    .unwrap();
+    assert!(json["clearing_price"].is_null());
+}
@@ -0,0 +1,280 @@
+//! Market abuse surveillance: synthetic spoofing sequences (layered orders pulled after a
+//! fill on the other side, rapid place/cancel churn) raise alerts while a market maker
+//! quoting and requoting does not, and `GET /admin/surveillance/alerts` lists the alerts
+//! until an admin acknowledges them.
+
+use chrono::{DateTime, Utc};
+use rust_exchange::api::surveillance::{AlertKind, Surveillance, SurveillanceConfig};
+use rust_exchange::orderbook::clock::ManualClock;
+use rust_exchange::testing::{self, OrderRequest, TEST_SYMBOL, TestExchange, Token};
+use rust_exchange::types::order::{Order, OrderSide, OrderStatus, OrderType};
+use rust_exchange::types::trade::Trade;
+use serde_json::Value;
+use std::sync::Arc;
+use std::time::Duration;
+use uuid::Uuid;
+
+fn start_time() -> DateTime<Utc> {
+    "2026-01-01T00:00:00Z".parse().unwrap()
+}
+
+fn at(ms: i64) -> DateTime<Utc> {
+    start_time() + chrono::Duration::milliseconds(ms)
+}
+
+fn config() -> SurveillanceConfig {
+    SurveillanceConfig {
+        window: Duration::from_secs(60),
+        min_cancels: 20,
+        max_cancel_to_fill: 10.0,
+        min_avg_lifetime: Duration::from_millis(500),
+        layer_window: Duration::from_secs(1),
+        layer_min_levels: 4,
+        cooldown: Duration::from_secs(300),
+        max_events: 500,
+    }
+}
+
+fn order(user_id: Uuid, side: OrderSide, price: i64, placed_ms: i64) -> Order {
+    Order {
+        id: Uuid::new_v4(),
+        user_id,
+        side,
+        order_type: OrderType::Limit,
+        price,
+        quantity: 1,
+        status: OrderStatus::Pending,
+        timestamp: at(placed_ms),
+        tags: Default::default(),
+        source: Default::default(),
+    }
+}
+
+/// `taker` taking one lot from `maker` at `ms`.
+fn fill(surveillance: &Surveillance, taker: Uuid, side: OrderSide, maker: Uuid, ms: i64) {
+    let trade = Trade {
+        id: Uuid::new_v4(),
+        trade_seq: 0,
+        maker_order_id: Uuid::new_v4(),
+        taker_order_id: Uuid::new_v4(),
+        maker_user_id: maker,
+        taker_user_id: taker,
+        price: 100,
+        quantity: 1,
+        timestamp: at(ms),
+        maker_queue_rank: None,
+    };
+    surveillance.observe_fills(TEST_SYMBOL, taker, side, &[trade]);
+}
+
+fn kinds(surveillance: &Surveillance, user_id: Uuid) -> Vec<AlertKind> {
+    surveillance
+        .recent()
+        .into_iter()
+        .filter(|alert| alert.user_id == user_id)
+        .map(|alert| alert.kind)
+        .collect()
+}
+
+#[test]
+fn layered_bids_pulled_after_selling_into_them_raise_a_layering_alert() {
+    let surveillance = Surveillance::new(config());
+    let (spoofer, victim) = (Uuid::new_v4(), Uuid::new_v4());
+    let layers: Vec<Order> = (0..5)
+        .map(|level| order(spoofer, OrderSide::Buy, 99 - level, level * 10))
+        .collect();
+    for layer in &layers {
+        surveillance.observe_placed(TEST_SYMBOL, layer);
+    }
+    // The bids draw buyers up; the spoofer sells to them, then pulls every bid
+    fill(&surveillance, victim, OrderSide::Buy, spoofer, 2_000);
+    let alerts: Vec<_> = layers
+        .iter()
+        .enumerate()
+        .flat_map(|(i, layer)| {
+            surveillance.observe_cancel(TEST_SYMBOL, layer, at(2_100 + i as i64 * 20))
+        })
+        .collect();
+
+    assert_eq!(alerts.len(), 1, "{:?}", alerts);
+    let alert = &alerts[0];
+    assert_eq!(alert.kind, AlertKind::Layering);
+    assert_eq!(alert.user_id, spoofer);
+    assert_eq!(alert.metrics.layered_side, Some(OrderSide::Buy));
+    assert_eq!(alert.metrics.layered_prices, [96, 97, 98, 99]);
+    assert_eq!(alert.metrics.fills, 1);
+    assert!(kinds(&surveillance, victim).is_empty());
+}
+
+#[test]
+fn rapid_place_and_cancel_without_fills_raises_ratio_and_lifetime_alerts_once() {
+    let surveillance = Surveillance::new(config());
+    let churner = Uuid::new_v4();
+    let mut alerts = Vec::new();
+    // An order every 100ms, each pulled after 50ms, alternating levels so no layers form
+    for i in 0..200 {
+        let placed = order(churner, OrderSide::Sell, 101 + i % 2, i * 100);
+        surveillance.observe_placed(TEST_SYMBOL, &placed);
+        alerts.extend(surveillance.observe_cancel(TEST_SYMBOL, &placed, at(i * 100 + 50)));
+    }
+
+    let raised: Vec<AlertKind> = alerts.iter().map(|alert| alert.kind).collect();
+    assert_eq!(raised, [AlertKind::CancelToFill, AlertKind::ShortLifetime]);
+    let metrics = &alerts[0].metrics;
+    assert_eq!(
+        (metrics.placed, metrics.cancelled, metrics.fills),
+        (20, 20, 0)
+    );
+    assert_eq!(metrics.cancel_to_fill_ratio, 20.0);
+    assert_eq!(metrics.avg_lifetime_ms, Some(50));
+    assert!(
+        surveillance
+            .render_prometheus()
+            .contains("exchange_surveillance_alerts_total{kind=\"short_lifetime\"} 1")
+    );
+}
+
+#[test]
+fn a_market_maker_requoting_both_sides_is_not_alerted() {
+    let surveillance = Surveillance::new(config());
+    let (maker, takers) = (Uuid::new_v4(), Uuid::new_v4());
+    // Ten minutes of two-sided quotes refreshed every 2s; a taker lifts one side every third
+    // refresh, and the maker pulls both quotes together to requote
+    for round in 0..300 {
+        let t = round * 2_000;
+        let bid = order(maker, OrderSide::Buy, 99, t);
+        let ask = order(maker, OrderSide::Sell, 101, t);
+        surveillance.observe_placed(TEST_SYMBOL, &bid);
+        surveillance.observe_placed(TEST_SYMBOL, &ask);
+        if round % 3 == 0 {
+            let side = if round % 2 == 0 {
+                OrderSide::Buy
+            } else {
+                OrderSide::Sell
+            };
+            fill(&surveillance, takers, side, maker, t + 1_000);
+        }
+        for quote in [&bid, &ask] {
+            let alerts = surveillance.observe_cancel(TEST_SYMBOL, quote, at(t + 1_900));
+            assert!(alerts.is_empty(), "round {}: {:?}", round, alerts);
+        }
+    }
+    assert!(surveillance.recent().is_empty());
+}
+
+#[test]
+fn a_window_is_bounded_by_max_events() {
+    let surveillance = Surveillance::new(SurveillanceConfig {
+        max_events: 10,
+        min_cancels: 11,
+        ..config()
+    });
+    let user_id = Uuid::new_v4();
+    // Churn that would alert if more than ten events were kept at once
+    for i in 0..100 {
+        let placed = order(user_id, OrderSide::Sell, 101 + i % 2, i * 100);
+        surveillance.observe_placed(TEST_SYMBOL, &placed);
+        assert!(
+            surveillance
+                .observe_cancel(TEST_SYMBOL, &placed, at(i * 100 + 50))
+                .is_empty()
+        );
+    }
+}
+
+async fn start(clock: &Arc<ManualClock>) -> (TestExchange, Token) {
+    let mut state = testing::test_app_state();
+    state.surveillance = Arc::new(Surveillance::new(config()));
+    let exchange = TestExchange::builder_with_state(state)
+        .admin()
+        .clock(clock.clone())
+        .start()
+        .await;
+    let admin = exchange.admin_token();
+    (exchange, admin)
+}
+
+#[tokio::test]
+async fn a_spoof_through_the_api_is_listed_until_acknowledged() {
+    let clock = Arc::new(ManualClock::new(start_time()));
+    let (exchange, admin) = start(&clock).await;
+    let spoofer = exchange.register("spoofer", "secret").await;
+    let buyer = exchange.register("buyer", "secret").await;
+
+    let mut layers = Vec::new();
+    for price in [99, 98, 97, 96] {
+        let report = exchange
+            .place_order(
+                &spoofer,
+                &OrderRequest::limit(TEST_SYMBOL, OrderSide::Buy, price, 5),
+            )
+            .await
+            .unwrap();
+        layers.push(report.order.id);
+    }
+    exchange
+        .place_order(
+            &buyer,
+            &OrderRequest::limit(TEST_SYMBOL, OrderSide::Buy, 100, 1),
+        )
+        .await
+        .unwrap();
+    clock.advance(Duration::from_millis(500));
+    exchange
+        .place_order(
+            &spoofer,
+            &OrderRequest::limit(TEST_SYMBOL, OrderSide::Sell, 100, 1),
+        )
+        .await
+        .unwrap();
+    clock.advance(Duration::from_millis(200));
+    for id in layers {
+        exchange
+            .cancel_order(&spoofer, TEST_SYMBOL, id)
+            .await
+            .unwrap();
+    }
+
+    let listed = exchange
+        .get(&admin, "/admin/surveillance/alerts?acknowledged=false")
+        .await
+        .unwrap();
+    let listed = listed.as_array().unwrap();
+    assert_eq!(listed.len(), 1, "{:?}", listed);
+    assert_eq!(listed[0]["kind"], "layering");
+    assert_eq!(listed[0]["user_id"], spoofer.user_id.to_string());
+    assert_eq!(listed[0]["symbol"], TEST_SYMBOL);
+    assert_eq!(listed[0]["metrics"]["layered_side"], "Buy");
+    assert_eq!(listed[0]["acknowledged_at"], Value::Null);
+    let id = listed[0]["id"].as_str().unwrap().to_string();
+
+    let ack = |id: String| {
+        exchange.send_json(
+            exchange
+                .client()
+                .post(exchange.url(&format!("/admin/surveillance/alerts/{}/ack", id)))
+                .bearer_auth(&admin.token),
+        )
+    };
+    let acked = ack(id.clone()).await.unwrap();
+    assert_eq!(acked["acknowledged_by"], admin.user_id.to_string());
+    assert_eq!(acked["acknowledged_at"], "2026-01-01T00:00:00.700Z");
+    let missing = ack(Uuid::new_v4().to_string()).await.unwrap_err();
+    assert_eq!(missing.status, 404);
+
+    let pending = exchange
+        .get(&admin, "/admin/surveillance/alerts?acknowledged=false")
+        .await
+        .unwrap();
+    assert!(pending.as_array().unwrap().is_empty());
+    let all = exchange
+        .get(&admin, "/admin/surveillance/alerts")
+        .await
+        .unwrap();
+    assert_eq!(all[0]["id"], id);
+    let refused = exchange
+        .get(&spoofer, "/admin/surveillance/alerts")
+        .await
+        .unwrap_err();
+    assert_eq!(refused.status, 403);
+}
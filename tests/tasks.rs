@@ -0,0 +1,142 @@
+//! The background task supervisor: a panicking task is restarted with backoff until its policy
+//! gives up, shutdown stops the stages in order and aborts a task that ignores its token, and
+//! `GET /admin/tasks` lists the tasks for admins.
+
+use rust_exchange::tasks::{RestartPolicy, ShutdownStage, Supervisor, TaskState, TaskStatus};
+use rust_exchange::testing::TestExchange;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+fn status(tasks: &Supervisor, name: &str) -> TaskStatus {
+    tasks
+        .statuses()
+        .into_iter()
+        .find(|task| task.name == name)
+        .unwrap()
+}
+
+/// Poll until `name` reaches `state`.
+async fn wait_for(tasks: &Supervisor, name: &str, state: TaskState) -> TaskStatus {
+    for _ in 0..200 {
+        let current = status(tasks, name);
+        if current.state == state {
+            return current;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    panic!(
+        "{} never reached {:?}: {:?}",
+        name,
+        state,
+        status(tasks, name)
+    );
+}
+
+#[tokio::test]
+async fn a_panicking_task_is_restarted_until_its_policy_gives_up() {
+    let tasks = Supervisor::default();
+    let runs = Arc::new(AtomicU32::new(0));
+    let counted = runs.clone();
+    let policy = RestartPolicy::Backoff {
+        initial: Duration::from_millis(100),
+        max: Duration::from_millis(100),
+        max_restarts: Some(2),
+    };
+    let handle = tasks.spawn("flaky", ShutdownStage::Worker, policy, move |_token| {
+        let run = counted.fetch_add(1, Ordering::SeqCst) + 1;
+        async move { panic!("run {} failed", run) }
+    });
+
+    let first = wait_for(&tasks, "flaky", TaskState::Restarting).await;
+    assert_eq!(first.restarts, 0);
+    assert_eq!(first.last_panic.as_deref(), Some("run 1 failed"));
+
+    tokio::time::timeout(Duration::from_secs(2), handle.stopped())
+        .await
+        .expect("the task stops once its restarts are used up");
+    let last = status(&tasks, "flaky");
+    assert_eq!(last.state, TaskState::Panicked);
+    assert_eq!(last.restarts, 2);
+    assert_eq!(last.last_panic.as_deref(), Some("run 3 failed"));
+    assert_eq!(runs.load(Ordering::SeqCst), 3);
+}
+
+#[tokio::test]
+async fn a_task_that_stops_cleanly_is_not_restarted() {
+    let tasks = Supervisor::default();
+    let handle = tasks.spawn(
+        "one_shot",
+        ShutdownStage::Worker,
+        RestartPolicy::default(),
+        |_token| async {},
+    );
+    handle.stopped().await;
+    let done = status(&tasks, "one_shot");
+    assert_eq!((done.state, done.restarts), (TaskState::Finished, 0));
+}
+
+#[tokio::test]
+async fn shutdown_stops_stages_in_order_and_aborts_a_task_that_ignores_its_token() {
+    let tasks = Supervisor::default();
+    let stopped = Arc::new(Mutex::new(Vec::new()));
+    // Registered in reverse, to show the stage decides the order
+    for (name, stage) in [
+        ("writer", ShutdownStage::Writer),
+        ("worker", ShutdownStage::Worker),
+        ("ingress", ShutdownStage::Ingress),
+    ] {
+        let stopped = stopped.clone();
+        tasks.spawn(name, stage, RestartPolicy::default(), move |token| {
+            let stopped = stopped.clone();
+            async move {
+                token.cancelled().await;
+                stopped.lock().unwrap().push(name);
+            }
+        });
+    }
+    tasks.spawn(
+        "stubborn",
+        ShutdownStage::Worker,
+        RestartPolicy::default(),
+        |_token| async {
+            tokio::time::sleep(Duration::from_secs(3600)).await;
+        },
+    );
+
+    let report = tasks.shutdown(Duration::from_millis(100)).await;
+    assert_eq!(*stopped.lock().unwrap(), ["ingress", "worker", "writer"]);
+    assert_eq!(report.timed_out, ["stubborn"]);
+    let mut clean = report.stopped.clone();
+    clean.sort();
+    assert_eq!(clean, ["ingress", "worker", "writer"]);
+    assert_eq!(status(&tasks, "stubborn").state, TaskState::TimedOut);
+    assert_eq!(status(&tasks, "writer").state, TaskState::Cancelled);
+}
+
+#[tokio::test]
+async fn admins_list_the_tasks() {
+    let exchange = TestExchange::builder().admin().start().await;
+    let admin = exchange.admin_token();
+    exchange.state.tasks.spawn(
+        "idle",
+        ShutdownStage::Worker,
+        RestartPolicy::Never,
+        |token| async move { token.cancelled().await },
+    );
+
+    let listed = exchange.get(&admin, "/admin/tasks").await.unwrap();
+    let idle = listed
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|task| task["name"] == "idle")
+        .unwrap();
+    assert_eq!(idle["stage"], "worker");
+    assert_eq!(idle["state"], "running");
+    assert_eq!(idle["restarts"], 0);
+
+    let user = exchange.register("trader", "secret").await;
+    let refused = exchange.get(&user, "/admin/tasks").await.unwrap_err();
+    assert_eq!(refused.status, 403);
+}
@@ -0,0 +1,119 @@
+//! Replay from the in-memory trade archive: `/trades?from_seq=` without a database and a
+//! WebSocket subscribe with `replay_from_seq`, both refusing to skip trades the archive evicted.
+
+use rust_exchange::testing::{OrderRequest, TEST_SYMBOL, TestExchange, Token};
+use rust_exchange::types::order::OrderSide;
+use serde_json::{Value, json};
+
+/// Three trades (seq 1..=3) between `seller` and `buyer` at 1,000, with only the last two
+/// still archived.
+async fn start() -> (TestExchange, Token, Token) {
+    let exchange = TestExchange::start().await;
+    exchange.state.orderbooks[TEST_SYMBOL]
+        .write()
+        .await
+        .set_trade_archive_capacity(2);
+    let seller = exchange.register("seller", "secret").await;
+    let buyer = exchange.register("buyer", "secret").await;
+    exchange
+        .place_order(
+            &seller,
+            &OrderRequest::limit(TEST_SYMBOL, OrderSide::Sell, 1_000, 4),
+        )
+        .await
+        .unwrap();
+    for _ in 0..3 {
+        trade(&exchange, &buyer).await;
+    }
+    (exchange, seller, buyer)
+}
+
+async fn trade(exchange: &TestExchange, buyer: &Token) {
+    exchange
+        .place_order(
+            buyer,
+            &OrderRequest::limit(TEST_SYMBOL, OrderSide::Buy, 1_000, 1),
+        )
+        .await
+        .unwrap();
+}
+
+fn trade_seqs(page: &Value) -> Vec<u64> {
+    page["items"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|t| t["trade_seq"].as_u64().unwrap())
+        .collect()
+}
+
+#[tokio::test]
+async fn trades_from_seq_reads_the_archive_and_refuses_evicted_sequences() {
+    let (exchange, seller, _) = start().await;
+
+    let page = exchange
+        .get(
+            &seller,
+            &format!("/trades?symbol={}&from_seq=2", TEST_SYMBOL),
+        )
+        .await
+        .unwrap();
+    assert_eq!(trade_seqs(&page), vec![2, 3]);
+
+    let evicted = exchange
+        .get(
+            &seller,
+            &format!("/trades?symbol={}&from_seq=1", TEST_SYMBOL),
+        )
+        .await
+        .unwrap_err();
+    assert_eq!(evicted.status, 410);
+    assert_eq!(evicted.body["error_code"], "TRADES_EVICTED");
+    assert!(
+        evicted.body["error"]
+            .as_str()
+            .unwrap()
+            .contains("before seq 2"),
+        "{}",
+        evicted.body
+    );
+
+    let info = exchange.state.orderbooks[TEST_SYMBOL].read().await.stats();
+    assert_eq!(
+        (
+            info.archived_trades,
+            info.archive_oldest_seq,
+            info.archive_evicted
+        ),
+        (2, 2, 1)
+    );
+}
+
+#[tokio::test]
+async fn a_subscribe_replays_archived_trades_then_goes_live() {
+    let (exchange, _, buyer) = start().await;
+    let mut ws = exchange.ws_client(None).await;
+
+    ws.send_json(json!({"action": "subscribe", "symbol": TEST_SYMBOL, "replay_from_seq": 1}))
+        .await;
+    let ack = ws.next_json().await;
+    assert_eq!(ack["status"], "error");
+    assert_eq!(ack["code"], "TRADES_EVICTED");
+    assert_eq!(ack["oldest_available_seq"], 2);
+
+    ws.send_json(json!({"action": "subscribe", "symbol": TEST_SYMBOL, "replay_from_seq": 2}))
+        .await;
+    let ack = ws.next_json().await;
+    assert_eq!(ack["status"], "success");
+    assert_eq!(ack["replayed"], 2);
+    assert_eq!(ws.next_json().await["type"], "ExchangeInfo");
+    for seq in [2, 3] {
+        let replayed = ws.next_json().await;
+        assert_eq!(replayed["type"], "Trade");
+        assert_eq!(replayed["trade"]["trade_seq"], seq);
+    }
+
+    trade(&exchange, &buyer).await;
+    let live = ws.next_of_type("Trade").await;
+    assert_eq!(live["trade"]["trade_seq"], 4);
+}
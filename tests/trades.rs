@@ -1,7 +1,11 @@
-//! Trade creation and structure integration tests: add_order trades, get_recent_trades, trade fields.
+//! Trade creation and structure integration tests: add_order trades, get_recent_trades, trade
+//! fields, and the trade archive behind replay by sequence.
 
-use rust_exchange::orderbook::orderbook::OrderBook;
+use chrono::Utc;
+use rust_exchange::orderbook::orderbook::{ExecutionReport, OrderBook};
+use rust_exchange::orderbook::trade_archive::{TradeArchive, TradesEvicted};
 use rust_exchange::types::order::{OrderSide, OrderType};
+use rust_exchange::types::trade::{Trade, TradeBust};
 use uuid::Uuid;
 
 fn scale_price(p: i64) -> i64 {
@@ -16,7 +20,9 @@ fn trade_creation_on_match_fields() {
     let price = scale_price(50_000);
     let qty = 10u64;
 
-    let (sell_order, _) = book.add_order(
+    let ExecutionReport {
+        order: sell_order, ..
+    } = book.add_order(
         seller,
         price,
         qty,
@@ -25,7 +31,11 @@ fn trade_creation_on_match_fields() {
         None,
         None,
     );
-    let (buy_order, trades) = book.add_order(
+    let ExecutionReport {
+        order: buy_order,
+        trades,
+        ..
+    } = book.add_order(
         buyer,
         price,
         qty,
@@ -53,7 +63,7 @@ fn multiple_trades_fifo_recent_first() {
     let buyer = Uuid::new_v4();
     let price = scale_price(50_000);
 
-    let (sell1, _) = book.add_order(
+    let ExecutionReport { order: sell1, .. } = book.add_order(
         user1,
         price,
         2,
@@ -62,7 +72,7 @@ fn multiple_trades_fifo_recent_first() {
         None,
         None,
     );
-    let (sell2, _) = book.add_order(
+    let ExecutionReport { order: sell2, .. } = book.add_order(
         user2,
         price,
         2,
@@ -71,7 +81,7 @@ fn multiple_trades_fifo_recent_first() {
         None,
         None,
     );
-    let (_buy_order, trades) = book.add_order(
+    let ExecutionReport { trades, .. } = book.add_order(
         buyer,
         price,
         3,
@@ -101,7 +111,9 @@ fn trade_storage_after_match() {
     let price = scale_price(50_000);
     let qty = 5u64;
 
-    let (sell_order, _) = book.add_order(
+    let ExecutionReport {
+        order: sell_order, ..
+    } = book.add_order(
         seller,
         price,
         qty,
@@ -110,7 +122,11 @@ fn trade_storage_after_match() {
         None,
         None,
     );
-    let (buy_order, trades) = book.add_order(
+    let ExecutionReport {
+        order: buy_order,
+        trades,
+        ..
+    } = book.add_order(
         buyer,
         price,
         qty,
@@ -134,3 +150,113 @@ fn trade_storage_after_match() {
     assert_eq!(recent.len(), 1);
     assert_eq!(recent[0].id, stored[0].id);
 }
+
+fn archived(trade_seq: u64) -> Trade {
+    Trade {
+        id: Uuid::new_v4(),
+        trade_seq,
+        maker_order_id: Uuid::new_v4(),
+        taker_order_id: Uuid::new_v4(),
+        maker_user_id: Uuid::new_v4(),
+        taker_user_id: Uuid::new_v4(),
+        price: scale_price(100),
+        quantity: 1,
+        timestamp: Utc::now(),
+        maker_queue_rank: None,
+    }
+}
+
+fn seqs(trades: Vec<Trade>) -> Vec<u64> {
+    trades.into_iter().map(|t| t.trade_seq).collect()
+}
+
+#[test]
+fn trade_archive_wraps_around_and_reports_where_it_starts() {
+    let mut archive = TradeArchive::new(3);
+    assert_eq!(seqs(archive.since(0, 10).unwrap()), Vec::<u64>::new());
+    for seq in 1..=5 {
+        archive.push(archived(seq));
+    }
+    assert_eq!(archive.len(), 3);
+    assert_eq!(archive.evicted(), 2);
+    assert_eq!(archive.oldest_available_seq(), 3);
+    assert_eq!(seqs(archive.since(3, 10).unwrap()), vec![3, 4, 5]);
+    assert_eq!(seqs(archive.since(4, 1).unwrap()), vec![4]);
+    assert!(archive.since(6, 10).unwrap().is_empty());
+    for from_seq in [0, 1, 2] {
+        assert_eq!(
+            archive.since(from_seq, 10),
+            Err(TradesEvicted {
+                oldest_available_seq: 3
+            })
+        );
+    }
+
+    // A trade already archived is not taken twice
+    archive.push(archived(5));
+    assert_eq!(seqs(archive.since(3, 10).unwrap()), vec![3, 4, 5]);
+    archive.set_capacity(1);
+    assert_eq!(archive.oldest_available_seq(), 5);
+    assert_eq!(archive.evicted(), 4);
+
+    // After a restart sequencing resumes past trades the archive never saw
+    let mut archive = TradeArchive::new(3);
+    archive.resume_after(500);
+    assert_eq!(archive.oldest_available_seq(), 501);
+    assert!(archive.since(1, 10).is_err());
+    archive.push(archived(501));
+    assert_eq!(seqs(archive.since(501, 10).unwrap()), vec![501]);
+}
+
+#[test]
+fn book_archives_more_trades_than_it_retains_and_leaves_busts_out() {
+    let mut book = OrderBook::new();
+    book.set_trade_archive_capacity(2);
+    let seller = Uuid::new_v4();
+    let buyer = Uuid::new_v4();
+    let price = scale_price(100);
+    book.add_order(
+        seller,
+        price,
+        3,
+        OrderSide::Sell,
+        OrderType::Limit,
+        None,
+        None,
+    );
+    let mut trades = Vec::new();
+    for _ in 0..3 {
+        let report = book.add_order(
+            buyer,
+            price,
+            1,
+            OrderSide::Buy,
+            OrderType::Limit,
+            None,
+            None,
+        );
+        trades.extend(report.trades);
+    }
+
+    let stats = book.stats();
+    assert_eq!(stats.retained_trades, 3);
+    assert_eq!(stats.archived_trades, 2);
+    assert_eq!(stats.archive_capacity, 2);
+    assert_eq!(stats.archive_oldest_seq, 2);
+    assert_eq!(stats.archive_evicted, 1);
+    assert_eq!(seqs(book.trade_archive().since(2, 10).unwrap()), vec![2, 3]);
+
+    book.record_bust(
+        trades[2].id,
+        TradeBust {
+            busted_at: Utc::now(),
+            reason: "fat finger".to_string(),
+        },
+    );
+    assert_eq!(seqs(book.trade_archive().since(2, 10).unwrap()), vec![2]);
+
+    // Cleared trades are gone from the archive too, and sequencing carries on
+    book.clear_trades();
+    assert_eq!(book.trade_archive().oldest_available_seq(), 4);
+    assert!(book.trade_archive().since(3, 10).is_err());
+}
@@ -0,0 +1,391 @@
+//! Outbound webhooks against a local receiver: registration, signed delivery, retries and the
+//! circuit breaker (in-memory mode, no database).
+
+use axum::Router;
+use axum::body::Bytes;
+use axum::extract::State;
+use axum::http::{HeaderMap, StatusCode};
+use axum::routing::post;
+use chrono::Utc;
+use rust_exchange::api::routes::WsMessage;
+use rust_exchange::orderbook::orderbook::ExecutionReport;
+use rust_exchange::testing::{OrderRequest, TEST_SYMBOL, TestExchange, Token};
+use rust_exchange::types::order::{
+    CloseReason, Order, OrderSide, OrderSource, OrderStatus, OrderType,
+};
+use rust_exchange::types::trade::{Trade, TradeRole};
+use rust_exchange::webhooks::{
+    self, DELIVERY_HEADER, EVENT_HEADER, SIGNATURE_HEADER, WebhookConfig, WebhookEvent,
+    WebhookEventType, WebhookPayload, Webhooks,
+};
+use serde_json::{Value, json};
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use uuid::Uuid;
+
+const SECRET: &str = "receiver-shared-secret";
+
+/// Requests a `Receiver` got, and the statuses it answers with next (200 once they run out).
+#[derive(Clone, Default)]
+struct Receiver {
+    requests: Arc<Mutex<Vec<(HeaderMap, Bytes)>>>,
+    statuses: Arc<Mutex<VecDeque<StatusCode>>>,
+}
+
+impl Receiver {
+    fn answer(&self, statuses: impl IntoIterator<Item = StatusCode>) {
+        self.statuses.lock().unwrap().extend(statuses);
+    }
+
+    fn requests(&self) -> Vec<(HeaderMap, Bytes)> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+async fn receive(State(receiver): State<Receiver>, headers: HeaderMap, body: Bytes) -> StatusCode {
+    receiver.requests.lock().unwrap().push((headers, body));
+    receiver
+        .statuses
+        .lock()
+        .unwrap()
+        .pop_front()
+        .unwrap_or(StatusCode::OK)
+}
+
+/// Serve a receiver on a random port; returns it and its URL.
+async fn spawn_receiver() -> (Receiver, String) {
+    let receiver = Receiver::default();
+    let app = Router::new()
+        .route("/hook", post(receive))
+        .with_state(receiver.clone());
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}/hook", listener.local_addr().unwrap());
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+    (receiver, url)
+}
+
+/// Fast retries and a breaker that opens after two failed deliveries and stays open.
+fn test_config() -> WebhookConfig {
+    WebhookConfig {
+        max_attempts: 3,
+        initial_backoff: Duration::from_millis(10),
+        max_backoff: Duration::from_millis(20),
+        timeout: Duration::from_secs(2),
+        breaker_threshold: 2,
+        breaker_cooldown: Duration::from_secs(3600),
+        max_per_user: 2,
+        ..WebhookConfig::default()
+    }
+}
+
+async fn start_exchange(config: WebhookConfig) -> TestExchange {
+    let exchange = TestExchange::builder()
+        .with_state(|state| state.webhooks = Arc::new(Webhooks::new(config)))
+        .start()
+        .await;
+    webhooks::spawn_dispatcher(exchange.state.clone());
+    exchange
+}
+
+async fn register_webhook(
+    exchange: &TestExchange,
+    token: &Token,
+    body: Value,
+) -> Result<Value, rust_exchange::testing::ApiError> {
+    exchange
+        .send_json(
+            exchange
+                .client()
+                .post(exchange.url("/webhooks"))
+                .bearer_auth(&token.token)
+                .json(&body),
+        )
+        .await
+}
+
+/// Cross `quantity` at `price`: `maker` rests, `taker` takes it.
+async fn trade(exchange: &TestExchange, maker: &Token, taker: &Token, price: i64, quantity: u64) {
+    for (token, side) in [(maker, OrderSide::Sell), (taker, OrderSide::Buy)] {
+        exchange
+            .place_order(
+                token,
+                &OrderRequest::limit(TEST_SYMBOL, side, price, quantity),
+            )
+            .await
+            .unwrap();
+    }
+}
+
+/// Poll the webhook's delivery log until it has `count` entries (newest first).
+async fn deliveries(exchange: &TestExchange, token: &Token, id: &str, count: usize) -> Vec<Value> {
+    let path = format!("/webhooks/{}/deliveries", id);
+    for _ in 0..200 {
+        let log = exchange.get(token, &path).await.unwrap();
+        let log = log.as_array().unwrap();
+        if log.len() >= count {
+            return log.clone();
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    panic!("webhook {} did not log {} deliveries", id, count);
+}
+
+#[tokio::test]
+async fn fills_are_delivered_signed_to_the_owners_webhook() {
+    let exchange = start_exchange(test_config()).await;
+    let (receiver, url) = spawn_receiver().await;
+    let maker = exchange.register("maker", "secret").await;
+    let taker = exchange.register("taker", "secret").await;
+    let webhook = register_webhook(
+        &exchange,
+        &maker,
+        json!({ "url": url, "secret": SECRET, "events": ["Fill"] }),
+    )
+    .await
+    .unwrap();
+    assert!(webhook.get("secret").is_none());
+    assert_eq!(webhook["events"], json!(["Fill"]));
+    let id = webhook["id"].as_str().unwrap();
+
+    trade(&exchange, &maker, &taker, 100, 2).await;
+    let log = deliveries(&exchange, &maker, id, 1).await;
+    assert_eq!(log[0]["status"], "Delivered");
+    assert_eq!(log[0]["attempts"], 1);
+    assert_eq!(log[0]["event"], "Fill");
+    assert_eq!(log[0]["response_status"], 200);
+
+    // Only the maker registered, so only the maker's side arrives
+    let requests = receiver.requests();
+    assert_eq!(requests.len(), 1);
+    let (headers, body) = &requests[0];
+    let signature = headers[SIGNATURE_HEADER].to_str().unwrap();
+    assert!(webhooks::verify(SECRET, body, signature));
+    assert!(!webhooks::verify(
+        "another-secret-entirely",
+        body,
+        signature
+    ));
+    let mut tampered = body.to_vec();
+    tampered[0] = b' ';
+    assert!(!webhooks::verify(SECRET, &tampered, signature));
+    assert_eq!(headers[EVENT_HEADER], "Fill");
+
+    let payload: WebhookPayload = serde_json::from_slice(body).unwrap();
+    assert_eq!(headers[DELIVERY_HEADER], payload.id.to_string());
+    assert_eq!(log[0]["id"], payload.id.to_string());
+    assert_eq!(payload.user_id, maker.user_id);
+    let WebhookEvent::Fill(fill) = payload.event else {
+        panic!("expected a fill, got {:?}", payload.event);
+    };
+    assert_eq!(
+        (fill.role, fill.side, fill.price, fill.quantity),
+        (TradeRole::Maker, OrderSide::Sell, 100, 2)
+    );
+}
+
+#[tokio::test]
+async fn a_failing_endpoint_is_retried_with_the_same_payload() {
+    let exchange = start_exchange(test_config()).await;
+    let (receiver, url) = spawn_receiver().await;
+    receiver.answer([StatusCode::INTERNAL_SERVER_ERROR, StatusCode::BAD_GATEWAY]);
+    let maker = exchange.register("maker", "secret").await;
+    let taker = exchange.register("taker", "secret").await;
+    let webhook = register_webhook(
+        &exchange,
+        &taker,
+        json!({ "url": url, "secret": SECRET, "events": ["Fill", "OrderClosed"] }),
+    )
+    .await
+    .unwrap();
+    let id = webhook["id"].as_str().unwrap();
+
+    trade(&exchange, &maker, &taker, 100, 1).await;
+    let log = deliveries(&exchange, &taker, id, 1).await;
+    assert_eq!(log[0]["status"], "Delivered");
+    assert_eq!(log[0]["attempts"], 3);
+
+    let requests = receiver.requests();
+    assert_eq!(requests.len(), 3);
+    assert!(requests.iter().all(|(_, body)| *body == requests[0].1));
+    assert!(requests.iter().all(|(headers, body)| webhooks::verify(
+        SECRET,
+        body,
+        headers[SIGNATURE_HEADER].to_str().unwrap()
+    )));
+
+    // A 4xx other than 408/429 is not retried
+    receiver.answer([StatusCode::GONE]);
+    trade(&exchange, &maker, &taker, 100, 1).await;
+    let log = deliveries(&exchange, &taker, id, 2).await;
+    assert_eq!(
+        (
+            &log[0]["status"],
+            &log[0]["attempts"],
+            &log[0]["response_status"]
+        ),
+        (&json!("Failed"), &json!(1), &json!(410))
+    );
+}
+
+#[tokio::test]
+async fn repeated_failures_open_the_circuit() {
+    let exchange = start_exchange(test_config()).await;
+    let (receiver, url) = spawn_receiver().await;
+    receiver.answer([StatusCode::INTERNAL_SERVER_ERROR; 6]);
+    let maker = exchange.register("maker", "secret").await;
+    let taker = exchange.register("taker", "secret").await;
+    let webhook = register_webhook(
+        &exchange,
+        &taker,
+        json!({ "url": url, "secret": SECRET, "events": ["Fill"] }),
+    )
+    .await
+    .unwrap();
+    let id = webhook["id"].as_str().unwrap();
+
+    for _ in 0..3 {
+        trade(&exchange, &maker, &taker, 100, 1).await;
+    }
+    let log = deliveries(&exchange, &taker, id, 3).await;
+    let outcomes: Vec<(&str, u64)> = log
+        .iter()
+        .map(|d| {
+            (
+                d["status"].as_str().unwrap(),
+                d["attempts"].as_u64().unwrap(),
+            )
+        })
+        .collect();
+    assert_eq!(outcomes, [("CircuitOpen", 0), ("Failed", 3), ("Failed", 3)]);
+    // The third event was never sent
+    assert_eq!(receiver.requests().len(), 6);
+
+    let listed = exchange.get(&taker, "/webhooks").await.unwrap();
+    assert_eq!(listed[0]["consecutive_failures"], 2);
+    assert!(listed[0]["circuit_open_until"].is_string());
+}
+
+#[tokio::test]
+async fn registration_is_validated_and_scoped_to_its_owner() {
+    let exchange = start_exchange(test_config()).await;
+    let alice = exchange.register("alice", "secret").await;
+    let bob = exchange.register("bob", "secret").await;
+    let valid = json!({ "url": "https://example.com/hook", "secret": SECRET, "events": ["Fill"] });
+    for (field, value) in [
+        ("url", json!("ftp://example.com/hook")),
+        ("url", json!("not a url")),
+        ("secret", json!("short")),
+        ("events", json!([])),
+    ] {
+        let mut body = valid.clone();
+        body[field] = value;
+        let err = register_webhook(&exchange, &alice, body).await.unwrap_err();
+        assert_eq!(err.status.as_u16(), 400, "{}: {}", field, err.body);
+    }
+
+    let first = register_webhook(&exchange, &alice, valid.clone())
+        .await
+        .unwrap();
+    register_webhook(&exchange, &alice, valid.clone())
+        .await
+        .unwrap();
+    let err = register_webhook(&exchange, &alice, valid.clone())
+        .await
+        .unwrap_err();
+    assert_eq!(err.status.as_u16(), 409);
+
+    let id = first["id"].as_str().unwrap();
+    let path = format!("/webhooks/{}/deliveries", id);
+    assert_eq!(exchange.get(&bob, &path).await.unwrap_err().status, 404);
+    assert_eq!(exchange.get(&bob, "/webhooks").await.unwrap(), json!([]));
+    let delete = |token: &Token| {
+        exchange.send_json(
+            exchange
+                .client()
+                .delete(exchange.url(&format!("/webhooks/{}", id)))
+                .bearer_auth(&token.token),
+        )
+    };
+    assert_eq!(delete(&bob).await.unwrap_err().status, 404);
+    delete(&alice).await.unwrap();
+    let remaining = exchange.get(&alice, "/webhooks").await.unwrap();
+    assert_eq!(remaining.as_array().unwrap().len(), 1);
+    assert_ne!(remaining[0]["id"], first["id"]);
+}
+
+fn order(user_id: Uuid, side: OrderSide, quantity: u64) -> Order {
+    Order {
+        id: Uuid::new_v4(),
+        user_id,
+        side,
+        order_type: OrderType::Market,
+        price: 0,
+        quantity,
+        status: OrderStatus::Filled,
+        timestamp: Utc::now(),
+        tags: Default::default(),
+        source: OrderSource::Api,
+    }
+}
+
+#[test]
+fn private_messages_become_events_for_each_party() {
+    let (taker, maker) = (Uuid::new_v4(), Uuid::new_v4());
+    let taker_order = order(taker, OrderSide::Sell, 0);
+    let trade = Trade {
+        id: Uuid::new_v4(),
+        trade_seq: 7,
+        maker_order_id: Uuid::new_v4(),
+        taker_order_id: taker_order.id,
+        maker_user_id: maker,
+        taker_user_id: taker,
+        price: 95,
+        quantity: 4,
+        timestamp: Utc::now(),
+        maker_queue_rank: Some(0),
+    };
+    let message = WsMessage::OrderUpdate {
+        symbol: TEST_SYMBOL.to_string(),
+        report: ExecutionReport {
+            order: taker_order.clone(),
+            trades: vec![trade.clone()],
+            rejection: None,
+        },
+    };
+    let events = webhooks::events(&message);
+    let summary: Vec<(Uuid, WebhookEventType)> = events
+        .iter()
+        .map(|(user, e)| (*user, e.event_type()))
+        .collect();
+    assert_eq!(
+        summary,
+        [
+            (taker, WebhookEventType::Fill),
+            (maker, WebhookEventType::Fill),
+        ]
+    );
+    let WebhookEvent::Fill(ref maker_fill) = events[1].1 else {
+        unreachable!()
+    };
+    assert_eq!(
+        (maker_fill.order_id, maker_fill.role, maker_fill.side),
+        (trade.maker_order_id, TradeRole::Maker, OrderSide::Buy)
+    );
+
+    let closed = WsMessage::OrderClosed {
+        symbol: TEST_SYMBOL.to_string(),
+        order_id: Uuid::new_v4(),
+        user_id: maker,
+        status: OrderStatus::Cancelled,
+        reason: CloseReason::AdminCancelled,
+        remaining_qty: 3,
+    };
+    let events = webhooks::events(&closed);
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].0, maker);
+    assert_eq!(
+        serde_json::to_value(&events[0].1).unwrap()["event"],
+        "OrderClosed"
+    );
+}
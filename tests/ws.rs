@@ -0,0 +1,1185 @@
+//! WebSocket session tests over a real socket: auth on upgrade, cancel-on-disconnect and the
+//! admin connection registry.
+
+use futures_util::{SinkExt, StreamExt};
+use rust_exchange::api::activity::ActivityFeed;
+use rust_exchange::api::auth::UsernameHistory;
+use rust_exchange::api::book_cache::BookCache;
+use rust_exchange::api::deadline::{DEFAULT_REQUEST_TIMEOUT, LockWaitMetrics};
+use rust_exchange::api::delisting::Delistings;
+use rust_exchange::api::exchange_info::RulesLog;
+use rust_exchange::api::expiry::OrderExpiry;
+use rust_exchange::api::fanout::{Delivery, Fanout, Subscriber};
+use rust_exchange::api::idempotency::IdempotencyCache;
+use rust_exchange::api::incidents::Incidents;
+use rust_exchange::api::ingress::Ingress;
+use rust_exchange::api::kill_switch::KillSwitches;
+use rust_exchange::api::lazy_books::LazyBooks;
+use rust_exchange::api::phase_timer::PhaseTimings;
+use rust_exchange::api::routes::{AppState, UserStore, WsMessage, app_router};
+use rust_exchange::api::spreads::Spreads;
+use rust_exchange::api::surveillance::Surveillance;
+use rust_exchange::api::ws::{self, SymbolSubscription};
+use rust_exchange::api::ws_connections::{
+    OverflowPolicy, WsConfig, WsConnections, skipped_broadcasts,
+};
+use rust_exchange::balances::Balances;
+use rust_exchange::faults::FaultPlan;
+use rust_exchange::hydration::HydrationReport;
+use rust_exchange::market_data::MarketDataStore;
+use rust_exchange::orderbook::book_stats::BookStatsGauges;
+use rust_exchange::orderbook::clock::SystemClock;
+use rust_exchange::orderbook::order_limits::OrderLimits;
+use rust_exchange::orderbook::orderbook::OrderBook;
+use rust_exchange::persistence::TradePersistenceMetrics;
+use rust_exchange::positions::SharedPositions;
+use rust_exchange::replica::ServerRole;
+use rust_exchange::retention::Retention;
+use rust_exchange::sandbox::Sandboxes;
+use rust_exchange::tasks::Supervisor;
+use rust_exchange::webhooks::Webhooks;
+use std::collections::{HashMap, HashSet};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{RwLock, broadcast};
+use tokio_tungstenite::tungstenite::Message;
+use uuid::Uuid;
+
+fn test_app_state() -> AppState {
+    let mut orderbooks = HashMap::new();
+    orderbooks.insert(
+        "BTCUSDT".to_string(),
+        Arc::new(RwLock::new(OrderBook::new())),
+    );
+    let (ws_tx, _) = broadcast::channel(1000);
+    let positions: SharedPositions = Arc::new(RwLock::new(HashMap::new()));
+    let user_store: UserStore = Arc::new(RwLock::new(HashMap::new()));
+    AppState {
+        orderbooks,
+        ws_channel: ws_tx,
+        positions,
+        jwt_secret: b"test-jwt-secret".to_vec(),
+        user_store,
+        db: None,
+        admin_user_ids: HashSet::new(),
+        index_prices: Arc::new(RwLock::new(HashMap::new())),
+        index_price_max_age: Duration::from_secs(30),
+        idempotency: Arc::new(tokio::sync::Mutex::new(IdempotencyCache::default())),
+        order_sessions: Arc::new(RwLock::new(HashMap::new())),
+        retention: Arc::new(Retention::default()),
+        hydration_report: Arc::new(HydrationReport::default()),
+        trade_metrics: Arc::new(TradePersistenceMetrics::default()),
+        username_history: Arc::new(UsernameHistory::default()),
+        book_cache: Arc::new(BookCache::default()),
+        ws_connections: Arc::new(WsConnections::default()),
+        symbol_configs: Arc::new(RwLock::new(HashMap::new())),
+        request_timeout: DEFAULT_REQUEST_TIMEOUT,
+        lock_waits: Arc::new(LockWaitMetrics::default()),
+        phase_timings: Arc::new(PhaseTimings::default()),
+        book_stats: Arc::new(BookStatsGauges::default()),
+        spreads: Arc::new(Spreads::default()),
+        order_expiry: Arc::new(OrderExpiry::default()),
+        incidents: Arc::new(Incidents::default()),
+        market_data: Arc::new(MarketDataStore::default()),
+        order_limits: Arc::new(OrderLimits::default()),
+        sandboxes: Arc::new(Sandboxes::default()),
+        balances: Arc::new(Balances::default()),
+        paper_balances: Arc::new(Balances::default()),
+        ingress: Arc::new(Ingress::default()),
+        ws_fanout: Arc::new(Fanout::default()),
+        activity: Arc::new(ActivityFeed::default()),
+        webhooks: Arc::new(Webhooks::default()),
+        role: ServerRole::Primary,
+        faults: Arc::new(FaultPlan::default()),
+        clock: Arc::new(SystemClock),
+        delistings: Arc::new(Delistings::default()),
+        kill_switches: Arc::new(KillSwitches::default()),
+        tasks: Arc::new(Supervisor::default()),
+        rules: Arc::new(RulesLog::default()),
+        surveillance: Arc::new(Surveillance::default()),
+        lazy_books: Arc::new(LazyBooks::default()),
+    }
+}
+
+/// Spawn app on a random port and return (host:port, guard that keeps server running).
+async fn spawn_app(state: AppState) -> (String, tokio::task::JoinHandle<()>) {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap().to_string();
+    let app = app_router(state).into_make_service_with_connect_info::<SocketAddr>();
+    let handle = tokio::spawn(async move {
+        axum::serve(listener, app).await.unwrap();
+    });
+    (addr, handle)
+}
+
+/// Register and log in a user, returning the bearer token.
+async fn login_token(client: &reqwest::Client, addr: &str, username: &str) -> String {
+    let creds = serde_json::json!({ "username": username, "password": "secret" });
+    client
+        .post(format!("http://{}/auth/register", addr))
+        .json(&creds)
+        .send()
+        .await
+        .unwrap();
+    let json: serde_json::Value = client
+        .post(format!("http://{}/auth/login", addr))
+        .json(&creds)
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    json["token"].as_str().unwrap().to_string()
+}
+
+async fn place_bid(
+    client: &reqwest::Client,
+    addr: &str,
+    token: &str,
+    session_scope: Option<&str>,
+) -> serde_json::Value {
+    client
+        .post(format!("http://{}/orders", addr))
+        .bearer_auth(token)
+        .json(&serde_json::json!({
+            "symbol": "BTCUSDT",
+            "price": 100,
+            "quantity": 1,
+            "side": "Buy",
+            "session_scope": session_scope
+        }))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap()
+}
+
+async fn bid_count(client: &reqwest::Client, addr: &str) -> u64 {
+    let book: serde_json::Value = client
+        .get(format!("http://{}/book?symbol=BTCUSDT", addr))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    book["bids"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|level| level[1].as_u64().unwrap())
+        .sum()
+}
+
+/// Connect with a token and set the cancel-on-disconnect flag; returns (socket, session id).
+async fn connect_with_flag(
+    addr: &str,
+    token: &str,
+    enabled: bool,
+) -> (
+    tokio_tungstenite::WebSocketStream<tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>>,
+    String,
+) {
+    let (mut socket, _) =
+        tokio_tungstenite::connect_async(format!("ws://{}/ws?token={}", addr, token))
+            .await
+            .unwrap();
+    socket
+        .send(Message::Text(
+            serde_json::json!({ "action": "cancel_on_disconnect", "enabled": enabled })
+                .to_string()
+                .into(),
+        ))
+        .await
+        .unwrap();
+    let ack = socket.next().await.unwrap().unwrap();
+    let ack: serde_json::Value = serde_json::from_str(ack.to_text().unwrap()).unwrap();
+    assert_eq!(ack["status"], "success");
+    let session_id = ack["session_id"].as_str().unwrap().to_string();
+    (socket, session_id)
+}
+
+async fn wait_for_bids(client: &reqwest::Client, addr: &str, expected: u64) -> u64 {
+    for _ in 0..50 {
+        let count = bid_count(client, addr).await;
+        if count == expected {
+            return count;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    bid_count(client, addr).await
+}
+
+#[tokio::test]
+async fn invalid_token_rejects_upgrade() {
+    let (addr, _handle) = spawn_app(test_app_state()).await;
+    let result = tokio_tungstenite::connect_async(format!("ws://{}/ws?token=garbage", addr)).await;
+    assert!(result.is_err());
+}
+
+#[tokio::test]
+async fn cancel_on_disconnect_cancels_orders_when_socket_drops() {
+    let (addr, _handle) = spawn_app(test_app_state()).await;
+    let client = reqwest::Client::new();
+    let token = login_token(&client, &addr, "alice").await;
+
+    let (socket, _) = connect_with_flag(&addr, &token, true).await;
+    place_bid(&client, &addr, &token, None).await;
+    place_bid(&client, &addr, &token, None).await;
+    assert_eq!(bid_count(&client, &addr).await, 2);
+
+    drop(socket);
+    assert_eq!(wait_for_bids(&client, &addr, 0).await, 0);
+}
+
+#[tokio::test]
+async fn orders_remain_when_flag_is_off() {
+    let (addr, _handle) = spawn_app(test_app_state()).await;
+    let client = reqwest::Client::new();
+    let token = login_token(&client, &addr, "alice").await;
+
+    let (socket, _) = connect_with_flag(&addr, &token, false).await;
+    place_bid(&client, &addr, &token, None).await;
+
+    drop(socket);
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert_eq!(bid_count(&client, &addr).await, 1);
+}
+
+#[tokio::test]
+async fn session_scoped_orders_only_follow_their_session() {
+    let (addr, _handle) = spawn_app(test_app_state()).await;
+    let client = reqwest::Client::new();
+    let token = login_token(&client, &addr, "alice").await;
+
+    let (first, first_session) = connect_with_flag(&addr, &token, true).await;
+    let (_second, second_session) = connect_with_flag(&addr, &token, false).await;
+    place_bid(&client, &addr, &token, Some(&first_session)).await;
+    place_bid(&client, &addr, &token, Some(&second_session)).await;
+
+    drop(first);
+    assert_eq!(wait_for_bids(&client, &addr, 1).await, 1);
+}
+
+#[tokio::test]
+async fn session_scope_must_name_one_of_the_callers_open_sessions() {
+    let (addr, _handle) = spawn_app(test_app_state()).await;
+    let client = reqwest::Client::new();
+    let alice = login_token(&client, &addr, "alice").await;
+    let bob = login_token(&client, &addr, "bob").await;
+    let (_bob_socket, bob_session) = connect_with_flag(&addr, &bob, true).await;
+    let (alice_socket, alice_session) = connect_with_flag(&addr, &alice, true).await;
+    place_bid(&client, &addr, &alice, None).await;
+    drop(alice_socket);
+    // The session is gone once its disconnect sweep has taken the bid
+    assert_eq!(wait_for_bids(&client, &addr, 0).await, 0);
+
+    let stranger = Uuid::new_v4().to_string();
+    for scope in [&bob_session, &alice_session, &stranger] {
+        let res = client
+            .post(format!("http://{}/orders", addr))
+            .bearer_auth(&alice)
+            .json(&serde_json::json!({
+                "symbol": "BTCUSDT",
+                "price": 100,
+                "quantity": 1,
+                "side": "Buy",
+                "session_scope": scope
+            }))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(res.status().as_u16(), 400, "{}", scope);
+    }
+    assert_eq!(bid_count(&client, &addr).await, 0);
+}
+
+#[tokio::test]
+async fn a_scoped_order_filled_completely_leaves_its_session() {
+    let state = test_app_state();
+    let order_sessions = state.order_sessions.clone();
+    let (addr, _handle) = spawn_app(state).await;
+    let client = reqwest::Client::new();
+    let alice = login_token(&client, &addr, "alice").await;
+    let bob = login_token(&client, &addr, "bob").await;
+    let (_socket, session) = connect_with_flag(&addr, &alice, true).await;
+    let bid = place_bid(&client, &addr, &alice, Some(&session)).await;
+    let bid_id = Uuid::parse_str(bid["id"].as_str().unwrap()).unwrap();
+    assert!(order_sessions.read().await.contains_key(&bid_id));
+
+    let res = client
+        .post(format!("http://{}/orders", addr))
+        .bearer_auth(&bob)
+        .json(&serde_json::json!({
+            "symbol": "BTCUSDT",
+            "price": 100,
+            "quantity": 1,
+            "side": "Sell"
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status().as_u16(), 200);
+    assert_eq!(bid_count(&client, &addr).await, 0);
+    assert!(order_sessions.read().await.is_empty());
+}
+
+#[tokio::test]
+async fn disconnect_cancel_notifies_the_owners_other_sockets() {
+    let (addr, _handle) = spawn_app(test_app_state()).await;
+    let client = reqwest::Client::new();
+    let token = login_token(&client, &addr, "alice").await;
+    let other_token = login_token(&client, &addr, "bob").await;
+
+    let (first, _) = connect_with_flag(&addr, &token, true).await;
+    let (mut second, _) = connect_with_flag(&addr, &token, false).await;
+    let (mut other, _) = connect_with_flag(&addr, &other_token, false).await;
+    let bid = place_bid(&client, &addr, &token, None).await;
+    assert_eq!(next_json(&mut second).await["type"], "OrderUpdate");
+
+    drop(first);
+    let json = next_json(&mut second).await;
+    assert_eq!(json["type"], "OrderClosed");
+    assert_eq!(json["order_id"], bid["id"]);
+    assert_eq!(json["symbol"], "BTCUSDT");
+    assert_eq!(json["status"], "Cancelled");
+    assert_eq!(json["reason"], "CANCEL_ON_DISCONNECT");
+    assert_eq!(json["remaining_qty"], 1);
+    assert!(
+        tokio::time::timeout(Duration::from_millis(200), other.next())
+            .await
+            .is_err()
+    );
+}
+
+/// Next text frame as JSON, failing the test if none arrives within a second.
+async fn next_json(
+    socket: &mut tokio_tungstenite::WebSocketStream<
+        tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>,
+    >,
+) -> serde_json::Value {
+    let msg = tokio::time::timeout(Duration::from_secs(1), socket.next())
+        .await
+        .expect("message within timeout")
+        .unwrap()
+        .unwrap();
+    serde_json::from_str(msg.to_text().unwrap()).unwrap()
+}
+
+#[tokio::test]
+async fn order_updates_go_only_to_the_owner() {
+    let (addr, _handle) = spawn_app(test_app_state()).await;
+    let client = reqwest::Client::new();
+    let alice = login_token(&client, &addr, "alice").await;
+    let bob = login_token(&client, &addr, "bob").await;
+    let (mut alice_socket, _) = connect_with_flag(&addr, &alice, false).await;
+    let (mut bob_socket, _) = connect_with_flag(&addr, &bob, false).await;
+
+    let res = client
+        .post(format!("http://{}/orders", addr))
+        .bearer_auth(&bob)
+        .json(&serde_json::json!({
+            "symbol": "BTCUSDT",
+            "price": 0,
+            "quantity": 1,
+            "side": "Buy",
+            "order_type": "Market"
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), 400);
+    let json: serde_json::Value = res.json().await.unwrap();
+    assert_eq!(json["error_code"], "NO_LIQUIDITY");
+    let alice_order = place_bid(&client, &addr, &alice, None).await;
+
+    let update = next_json(&mut bob_socket).await;
+    assert_eq!(update["type"], "OrderUpdate");
+    assert_eq!(update["symbol"], "BTCUSDT");
+    assert_eq!(update["report"]["rejection"], "NO_LIQUIDITY");
+    assert_eq!(update["report"]["order"]["status"], "Cancelled");
+
+    // Alice sees her own order, not Bob's rejection
+    let update = next_json(&mut alice_socket).await;
+    assert_eq!(update["type"], "OrderUpdate");
+    assert_eq!(update["report"]["order"]["id"], alice_order["id"]);
+    assert!(update["report"]["rejection"].is_null());
+}
+
+type WsStream =
+    tokio_tungstenite::WebSocketStream<tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>>;
+
+async fn subscribe(socket: &mut WsStream, symbol: &str) {
+    socket
+        .send(Message::Text(
+            serde_json::json!({ "action": "subscribe", "symbol": symbol })
+                .to_string()
+                .into(),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(next_json(socket).await["status"], "success");
+    assert_eq!(next_json(socket).await["type"], "ExchangeInfo");
+}
+
+async fn list_connections(
+    client: &reqwest::Client,
+    addr: &str,
+    token: &str,
+    filter: &str,
+) -> serde_json::Value {
+    client
+        .get(format!("http://{}/admin/ws/connections{}", addr, filter))
+        .bearer_auth(token)
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap()
+}
+
+/// Poll the registry until it holds `expected` connections.
+async fn wait_for_connections(
+    client: &reqwest::Client,
+    addr: &str,
+    token: &str,
+    expected: u64,
+) -> serde_json::Value {
+    for _ in 0..50 {
+        let json = list_connections(client, addr, token, "").await;
+        if json["counts"]["connections"] == expected {
+            return json;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    list_connections(client, addr, token, "").await
+}
+
+#[tokio::test]
+async fn admin_lists_and_force_closes_ws_connections() {
+    let mut state = test_app_state();
+    state.orderbooks.insert(
+        "ETHUSDT".to_string(),
+        Arc::new(RwLock::new(OrderBook::new())),
+    );
+    // Register the admin once to learn its id, then restart with it configured
+    let (addr, handle) = spawn_app(state.clone()).await;
+    let client = reqwest::Client::new();
+    let admin = login_token(&client, &addr, "admin").await;
+    let alice = login_token(&client, &addr, "alice").await;
+    let admin_id: serde_json::Value = client
+        .post(format!("http://{}/auth/login", addr))
+        .json(&serde_json::json!({ "username": "admin", "password": "secret" }))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    handle.abort();
+    state
+        .admin_user_ids
+        .insert(admin_id["user_id"].as_str().unwrap().parse().unwrap());
+    let (addr, _handle) = spawn_app(state).await;
+
+    let (mut alice_socket, _) = connect_with_flag(&addr, &alice, false).await;
+    subscribe(&mut alice_socket, "BTCUSDT").await;
+    subscribe(&mut alice_socket, "ethusdt").await;
+    let (mut anonymous, _) = tokio_tungstenite::connect_async(format!("ws://{}/ws", addr))
+        .await
+        .unwrap();
+    subscribe(&mut anonymous, "BTCUSDT").await;
+    let (_idle, _) = tokio_tungstenite::connect_async(format!("ws://{}/ws", addr))
+        .await
+        .unwrap();
+
+    let json = wait_for_connections(&client, &addr, &admin, 3).await;
+    assert_eq!(json["counts"]["authenticated"], 1);
+    assert_eq!(json["counts"]["subscribers"]["BTCUSDT"], 2);
+    assert_eq!(json["counts"]["subscribers"]["ETHUSDT"], 1);
+    assert!(
+        json["connections"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .all(|c| c["remote_addr"].as_str().unwrap().starts_with("127.0.0.1:"))
+    );
+
+    let eth = list_connections(&client, &addr, &admin, "?symbol=ethusdt").await;
+    let eth = eth["connections"].as_array().unwrap();
+    assert_eq!(eth.len(), 1);
+    assert_eq!(
+        eth[0]["subscriptions"],
+        serde_json::json!(["BTCUSDT", "ETHUSDT"])
+    );
+    // The cancel_on_disconnect ack and two subscription acks, each followed by its ExchangeInfo
+    assert_eq!(eth[0]["messages_sent"], 5);
+    let alice_id = eth[0]["user_id"].as_str().unwrap().to_string();
+    let by_user = list_connections(&client, &addr, &admin, &format!("?user_id={}", alice_id)).await;
+    assert_eq!(by_user["connections"][0]["id"], eth[0]["id"]);
+
+    let btc = list_connections(&client, &addr, &admin, "?symbol=BTCUSDT").await;
+    let anonymous_id = btc["connections"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|c| c["user_id"].is_null())
+        .unwrap()["id"]
+        .as_str()
+        .unwrap()
+        .to_string();
+
+    let close_url = format!("http://{}/admin/ws/connections/{}", addr, anonymous_id);
+    let res = client
+        .delete(&close_url)
+        .bearer_auth(&alice)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), 403);
+    let res = client
+        .delete(&close_url)
+        .bearer_auth(&admin)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), 204);
+
+    let frame = tokio::time::timeout(Duration::from_secs(1), anonymous.next())
+        .await
+        .expect("close frame within timeout")
+        .unwrap()
+        .unwrap();
+    match frame {
+        Message::Close(Some(close)) => assert_eq!(u16::from(close.code), 1008),
+        other => panic!("expected a close frame, got {:?}", other),
+    }
+
+    let json = wait_for_connections(&client, &addr, &admin, 2).await;
+    assert_eq!(json["counts"]["subscribers"]["BTCUSDT"], 1);
+    let res = client
+        .delete(&close_url)
+        .bearer_auth(&admin)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), 404);
+}
+
+/// Next message of `message_type`, skipping others.
+async fn next_of_type(socket: &mut WsStream, message_type: &str) -> serde_json::Value {
+    loop {
+        let message = next_json(socket).await;
+        if message["type"] == message_type {
+            return message;
+        }
+    }
+}
+
+#[tokio::test]
+async fn admin_rebroadcasts_snapshots_and_announces_to_every_socket() {
+    let mut state = test_app_state();
+    state.orderbooks.insert(
+        "ETHUSDT".to_string(),
+        Arc::new(RwLock::new(OrderBook::new())),
+    );
+    let (addr, handle) = spawn_app(state.clone()).await;
+    let client = reqwest::Client::new();
+    let admin = login_token(&client, &addr, "admin").await;
+    let alice = login_token(&client, &addr, "alice").await;
+    let admin_id: serde_json::Value = client
+        .post(format!("http://{}/auth/login", addr))
+        .json(&serde_json::json!({ "username": "admin", "password": "secret" }))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    handle.abort();
+    state
+        .admin_user_ids
+        .insert(admin_id["user_id"].as_str().unwrap().parse().unwrap());
+    let (addr, _handle) = spawn_app(state).await;
+    place_bid(&client, &addr, &alice, None).await;
+
+    let (mut subscriber, _) = tokio_tungstenite::connect_async(format!("ws://{}/ws", addr))
+        .await
+        .unwrap();
+    subscribe(&mut subscriber, "BTCUSDT").await;
+    let (mut idle, _) = tokio_tungstenite::connect_async(format!("ws://{}/ws", addr))
+        .await
+        .unwrap();
+
+    let rebroadcast_url = format!(
+        "http://{}/admin/ws/rebroadcast?symbol=btcusdt&resync=true",
+        addr
+    );
+    let res = client
+        .post(&rebroadcast_url)
+        .bearer_auth(&alice)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), 403);
+    let res = client
+        .post(format!(
+            "http://{}/admin/ws/rebroadcast?symbol=DOGEUSDT",
+            addr
+        ))
+        .bearer_auth(&admin)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), 404);
+    let json: serde_json::Value = client
+        .post(&rebroadcast_url)
+        .bearer_auth(&admin)
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(json["symbols"], serde_json::json!(["BTCUSDT"]));
+
+    let resync = next_of_type(&mut subscriber, "Resync").await;
+    assert_eq!(resync["symbol"], "BTCUSDT");
+    let snapshot = next_json(&mut subscriber).await;
+    assert_eq!(snapshot["type"], "OrderBookUpdate");
+    assert_eq!(snapshot["bids"], serde_json::json!([[100, 1]]));
+
+    // Without a symbol every book is sent, each only to its own subscribers
+    let json: serde_json::Value = client
+        .post(format!("http://{}/admin/ws/rebroadcast", addr))
+        .bearer_auth(&admin)
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(json["resync"], false);
+    assert_eq!(json["symbols"], serde_json::json!(["BTCUSDT", "ETHUSDT"]));
+    let snapshot = next_json(&mut subscriber).await;
+    assert_eq!(snapshot["type"], "OrderBookUpdate");
+    assert_eq!(snapshot["symbol"], "BTCUSDT");
+
+    let broadcast_url = format!("http://{}/admin/ws/broadcast", addr);
+    let res = client
+        .post(&broadcast_url)
+        .bearer_auth(&admin)
+        .json(&serde_json::json!({ "message": "  " }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), 400);
+    let json: serde_json::Value = client
+        .post(&broadcast_url)
+        .bearer_auth(&admin)
+        .json(&serde_json::json!({
+            "message": "Maintenance in 10 minutes",
+            "severity": "warning"
+        }))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(json["delivered"], 2);
+    for socket in [&mut subscriber, &mut idle] {
+        let announcement = next_json(socket).await;
+        assert_eq!(announcement["type"], "Announcement");
+        assert_eq!(announcement["message"], "Maintenance in 10 minutes");
+        assert_eq!(announcement["severity"], "warning");
+    }
+}
+
+#[tokio::test]
+async fn ws_and_rest_normalize_symbols_the_same_way() {
+    let (addr, _handle) = spawn_app(test_app_state()).await;
+    let client = reqwest::Client::new();
+    let token = login_token(&client, &addr, "alice").await;
+    let (mut socket, _) = tokio_tungstenite::connect_async(format!("ws://{}/ws", addr))
+        .await
+        .unwrap();
+
+    for (symbol, status, expected) in [
+        (" btcusdt ", "success", "Subscribed to BTCUSDT"),
+        ("BTC/USDT", "error", "Symbol 'BTC/USDT' contains '/'"),
+        ("", "error", "Symbol is required"),
+        ("BTCUSTD", "error", "Symbol 'BTCUSTD' not found"),
+    ] {
+        socket
+            .send(Message::Text(
+                serde_json::json!({ "action": "subscribe", "symbol": symbol })
+                    .to_string()
+                    .into(),
+            ))
+            .await
+            .unwrap();
+        let ack = next_json(&mut socket).await;
+        assert_eq!(ack["status"], status, "{}", symbol);
+        assert!(
+            ack["message"].as_str().unwrap().starts_with(expected),
+            "{}",
+            ack["message"]
+        );
+        if status == "success" {
+            // The scales of the normalized symbol follow the ack
+            let info = next_json(&mut socket).await;
+            assert_eq!(info["type"], "ExchangeInfo");
+            assert_eq!(info["symbol"], "BTCUSDT");
+            assert_eq!(
+                info["meta"],
+                serde_json::json!({
+                    "price_scale": 100_000_000,
+                    "qty_scale": 1,
+                    "tick_size": 1,
+                    "lot_size": 1
+                })
+            );
+        }
+    }
+    // The unknown-symbol ack says which symbols exist
+    socket
+        .send(Message::Text(
+            serde_json::json!({ "action": "subscribe", "symbol": "btcusd" })
+                .to_string()
+                .into(),
+        ))
+        .await
+        .unwrap();
+    let ack = next_json(&mut socket).await;
+    assert_eq!(ack["status"], "error");
+    assert_eq!(ack["code"], "UNKNOWN_SYMBOL");
+    assert_eq!(ack["known_symbols"], serde_json::json!(["BTCUSDT"]));
+    assert_eq!(ack["known_symbol_count"], 1);
+    assert_eq!(ack["did_you_mean"], "BTCUSDT");
+
+    let res = client
+        .post(format!("http://{}/orders", addr))
+        .bearer_auth(&token)
+        .json(&serde_json::json!({
+            "symbol": " btcusdt ",
+            "price": 100,
+            "quantity": 1,
+            "side": "Buy"
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status().as_u16(), 200);
+    let update = next_json(&mut socket).await;
+    assert_eq!(update["type"], "OrderBookUpdate");
+    assert_eq!(update["symbol"], "BTCUSDT");
+}
+
+async fn place(
+    client: &reqwest::Client,
+    addr: &str,
+    token: &str,
+    side: &str,
+    price: i64,
+    qty: u64,
+) {
+    let res = client
+        .post(format!("http://{}/orders", addr))
+        .bearer_auth(token)
+        .json(&serde_json::json!({
+            "symbol": "BTCUSDT",
+            "price": price,
+            "quantity": qty,
+            "side": side
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status().as_u16(), 200);
+}
+
+/// Position updates received until the socket has been quiet for a moment.
+async fn drain_position_updates(socket: &mut WsStream) -> Vec<serde_json::Value> {
+    let mut updates = Vec::new();
+    while let Ok(Some(Ok(Message::Text(text)))) =
+        tokio::time::timeout(Duration::from_millis(200), socket.next()).await
+    {
+        let json: serde_json::Value = serde_json::from_str(&text).unwrap();
+        if json["type"] == "PositionUpdate" {
+            updates.push(json);
+        }
+    }
+    updates
+}
+
+#[tokio::test]
+async fn position_updates_go_only_to_the_owner_once_per_order() {
+    let (addr, _handle) = spawn_app(test_app_state()).await;
+    let client = reqwest::Client::new();
+    let mut sockets = Vec::new();
+    for name in ["alice", "bob", "carol"] {
+        let token = login_token(&client, &addr, name).await;
+        let (mut socket, _) =
+            tokio_tungstenite::connect_async(format!("ws://{}/ws?token={}", addr, token))
+                .await
+                .unwrap();
+        socket
+            .send(Message::Text(
+                r#"{"action":"subscribe_positions"}"#.to_string().into(),
+            ))
+            .await
+            .unwrap();
+        assert_eq!(next_json(&mut socket).await["status"], "success");
+        sockets.push((token, socket));
+    }
+    let [(alice, alice_socket), (bob, bob_socket), (_, carol_socket)] = &mut sockets[..] else {
+        unreachable!();
+    };
+
+    // Anonymous sockets cannot subscribe to positions
+    let (mut anonymous, _) = tokio_tungstenite::connect_async(format!("ws://{}/ws", addr))
+        .await
+        .unwrap();
+    anonymous
+        .send(Message::Text(
+            r#"{"action":"subscribe_positions"}"#.to_string().into(),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(next_json(&mut anonymous).await["status"], "error");
+
+    place(&client, &addr, alice, "Sell", 100, 1).await;
+    place(&client, &addr, alice, "Sell", 110, 1).await;
+    // One order, two fills: one update per side
+    place(&client, &addr, bob, "Buy", 110, 2).await;
+
+    let bob_updates = drain_position_updates(bob_socket).await;
+    assert_eq!(bob_updates.len(), 1, "{:?}", bob_updates);
+    assert_eq!(bob_updates[0]["position"]["quantity"], 2);
+    assert_eq!(bob_updates[0]["position"]["average_price"], 105);
+    assert_eq!(bob_updates[0]["mark_price"], 110);
+    assert_eq!(bob_updates[0]["unrealized_pnl"], 10);
+    let alice_updates = drain_position_updates(alice_socket).await;
+    assert_eq!(alice_updates.len(), 1, "{:?}", alice_updates);
+    assert_eq!(alice_updates[0]["position"]["quantity"], -2);
+    assert_ne!(
+        alice_updates[0]["position"]["user_id"],
+        bob_updates[0]["position"]["user_id"]
+    );
+    assert!(drain_position_updates(carol_socket).await.is_empty());
+
+    // Closing both positions sends a terminal update with quantity 0
+    place(&client, &addr, alice, "Buy", 100, 2).await;
+    place(&client, &addr, bob, "Sell", 100, 2).await;
+    for socket in [alice_socket, bob_socket] {
+        let updates = drain_position_updates(socket).await;
+        assert_eq!(updates.len(), 1, "{:?}", updates);
+        assert_eq!(updates[0]["position"]["quantity"], 0);
+        assert_eq!(updates[0]["unrealized_pnl"], 0);
+    }
+    assert!(drain_position_updates(carol_socket).await.is_empty());
+}
+
+/// Broadcast `count` book updates without yielding, so a socket task on this runtime cannot
+/// keep up and falls behind a small channel.
+fn flood(state: &AppState, count: usize) {
+    for _ in 0..count {
+        let _ = state.ws_channel.send(WsMessage::OrderBookUpdate {
+            symbol: "BTCUSDT".to_string(),
+            bids: vec![],
+            asks: vec![],
+            diff: None,
+        });
+    }
+}
+
+/// State whose broadcast channel holds only four messages.
+fn small_channel_state(overflow_policy: OverflowPolicy, max_lags: usize) -> AppState {
+    let config = WsConfig {
+        channel_capacity: 4,
+        queue_capacity: 4,
+        overflow_policy,
+        max_lags,
+        lag_window: Duration::from_secs(60),
+    };
+    let (ws_tx, _) = broadcast::channel(config.channel_capacity);
+    AppState {
+        ws_channel: ws_tx,
+        ws_connections: Arc::new(WsConnections::new(config)),
+        ..test_app_state()
+    }
+}
+
+/// Read the lag notice, the resync snapshot and the four messages the channel kept.
+async fn expect_resync(socket: &mut WsStream, skipped: u64) {
+    let notice = next_json(socket).await;
+    assert_eq!(notice["type"], "Lagged");
+    assert_eq!(notice["skipped"], skipped);
+    for _ in 0..5 {
+        assert_eq!(next_json(socket).await["type"], "OrderBookUpdate");
+    }
+}
+
+#[tokio::test]
+async fn lagging_sockets_are_resynced_and_counted_under_drop_oldest() {
+    let state = small_channel_state(OverflowPolicy::DropOldest, 0);
+    // Nobody is listening yet, so the update is never built
+    let before = skipped_broadcasts();
+    ws::broadcast_orderbook_update(&state.ws_channel, "BTCUSDT", &mut OrderBook::new());
+    assert!(skipped_broadcasts() > before);
+
+    let (addr, _handle) = spawn_app(state.clone()).await;
+    let (mut socket, _) = tokio_tungstenite::connect_async(format!("ws://{}/ws", addr))
+        .await
+        .unwrap();
+    subscribe(&mut socket, "BTCUSDT").await;
+
+    for round in 1..=2 {
+        flood(&state, 10);
+        expect_resync(&mut socket, 6).await;
+        let totals = state.ws_connections.totals();
+        assert_eq!(totals.lag_events, round);
+        assert_eq!(totals.messages_dropped, 6 * round);
+        assert_eq!(totals.slow_consumers_disconnected, 0);
+    }
+    let connections = state.ws_connections.list(None, None);
+    assert_eq!(connections[0].lag_events, 2);
+    assert_eq!(connections[0].messages_dropped, 12);
+}
+
+#[tokio::test]
+async fn slow_consumers_are_disconnected_after_too_many_lags() {
+    let state = small_channel_state(OverflowPolicy::DisconnectSlowConsumers, 1);
+    let (addr, _handle) = spawn_app(state.clone()).await;
+    let (mut socket, _) = tokio_tungstenite::connect_async(format!("ws://{}/ws", addr))
+        .await
+        .unwrap();
+    subscribe(&mut socket, "BTCUSDT").await;
+
+    // The first lag is tolerated
+    flood(&state, 10);
+    expect_resync(&mut socket, 6).await;
+
+    flood(&state, 10);
+    let msg = tokio::time::timeout(Duration::from_secs(1), socket.next())
+        .await
+        .expect("close within timeout")
+        .unwrap()
+        .unwrap();
+    let Message::Close(Some(frame)) = msg else {
+        panic!("expected a close frame, got {:?}", msg);
+    };
+    assert_eq!(u16::from(frame.code), 1008);
+    assert_eq!(frame.reason, "Slow consumer");
+
+    let totals = state.ws_connections.totals();
+    assert_eq!(totals.lag_events, 2);
+    assert_eq!(totals.slow_consumers_disconnected, 1);
+}
+
+/// A book update whose only bid is at `price`, to tell frames apart.
+fn book_update(symbol: &str, price: i64) -> WsMessage {
+    WsMessage::OrderBookUpdate {
+        symbol: symbol.to_string(),
+        bids: vec![(price, 1)],
+        asks: vec![],
+        diff: None,
+    }
+}
+
+async fn next_frame(subscriber: &Subscriber) -> Arc<str> {
+    match tokio::time::timeout(Duration::from_secs(1), subscriber.next())
+        .await
+        .expect("frame within timeout")
+    {
+        Delivery::Frame(frame) => frame,
+        other => panic!("expected a frame, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn fanout_serializes_each_frame_once_and_a_full_outbox_drops_only_its_own_oldest() {
+    let (ws_tx, _) = broadcast::channel(1000);
+    let fanout = Fanout::default();
+    let fast = fanout.connect(&ws_tx, Uuid::new_v4(), None, 4);
+    let slow = fanout.connect(&ws_tx, Uuid::new_v4(), None, 4);
+    for subscriber in [&fast, &slow] {
+        fanout.subscribe(&ws_tx, subscriber, "BTCUSDT", SymbolSubscription::default());
+    }
+
+    // The fast socket keeps up; the slow one reads nothing until the end
+    let mut fast_frames = Vec::new();
+    for price in 1..=10 {
+        ws_tx.send(book_update("ETHUSDT", price)).unwrap();
+        ws_tx.send(book_update("BTCUSDT", price)).unwrap();
+        fast_frames.push(next_frame(&fast).await);
+    }
+    let last: serde_json::Value = serde_json::from_str(&fast_frames[9]).unwrap();
+    assert_eq!(last["symbol"], "BTCUSDT");
+    assert_eq!(last["bids"][0][0], 10);
+
+    assert_eq!(slow.next().await, Delivery::Lagged(6));
+    for expected in &fast_frames[6..] {
+        // The very frame the fast socket got, not a copy
+        assert!(Arc::ptr_eq(&next_frame(&slow).await, expected));
+    }
+
+    // The feed stops, and lets go of the channel, once its last socket leaves
+    assert_eq!(fanout.feed_count(), 1);
+    fanout.disconnect(&fast);
+    assert_eq!(fanout.feed_count(), 1);
+    fanout.disconnect(&slow);
+    assert_eq!(fanout.feed_count(), 0);
+    tokio::time::timeout(Duration::from_secs(1), async {
+        while ws_tx.receiver_count() > 0 {
+            tokio::task::yield_now().await;
+        }
+    })
+    .await
+    .expect("feed task exits");
+}
+
+#[tokio::test]
+async fn a_feed_that_falls_behind_the_channel_charges_its_sockets() {
+    let (ws_tx, _) = broadcast::channel(2);
+    let fanout = Fanout::default();
+    let subscriber = fanout.connect(&ws_tx, Uuid::new_v4(), None, 100);
+    fanout.subscribe(
+        &ws_tx,
+        &subscriber,
+        "BTCUSDT",
+        SymbolSubscription::default(),
+    );
+
+    // Sent without yielding, so the feed task only sees the last two
+    for price in 1..=5 {
+        ws_tx.send(book_update("BTCUSDT", price)).unwrap();
+    }
+    assert_eq!(subscriber.next().await, Delivery::Lagged(3));
+    for price in 4..=5 {
+        let frame: serde_json::Value =
+            serde_json::from_str(&next_frame(&subscriber).await).unwrap();
+        assert_eq!(frame["bids"][0][0], price);
+    }
+}
+
+/// Send a subscribe with extra fields; the ack.
+/// The ack of `request`; a successful subscribe's `ExchangeInfo` is read past.
+async fn subscribe_with(socket: &mut WsStream, request: serde_json::Value) -> serde_json::Value {
+    socket
+        .send(Message::Text(request.to_string().into()))
+        .await
+        .unwrap();
+    let ack = next_json(socket).await;
+    if ack["status"] == "success" {
+        assert_eq!(next_json(socket).await["type"], "ExchangeInfo");
+    }
+    ack
+}
+
+/// Every frame that arrives until the socket goes quiet for 200ms.
+async fn drain(socket: &mut WsStream) -> Vec<serde_json::Value> {
+    let mut messages = Vec::new();
+    while let Ok(Some(Ok(msg))) =
+        tokio::time::timeout(Duration::from_millis(200), socket.next()).await
+    {
+        messages.push(serde_json::from_str(msg.to_text().unwrap()).unwrap());
+    }
+    messages
+}
+
+#[tokio::test]
+async fn own_trades_filter_delivers_only_the_users_fills_with_their_role() {
+    let (addr, _handle) = spawn_app(test_app_state()).await;
+    let client = reqwest::Client::new();
+    let alice = login_token(&client, &addr, "alice").await;
+    let bob = login_token(&client, &addr, "bob").await;
+    let carol = login_token(&client, &addr, "carol").await;
+    let own_trades = serde_json::json!({
+        "action": "subscribe",
+        "symbol": "BTCUSDT",
+        "channel": "trades",
+        "filter": "own"
+    });
+
+    let (mut anonymous, _) = tokio_tungstenite::connect_async(format!("ws://{}/ws", addr))
+        .await
+        .unwrap();
+    let ack = subscribe_with(&mut anonymous, own_trades.clone()).await;
+    assert_eq!(ack["status"], "error");
+    assert_eq!(ack["message"], "Authentication required for own trades");
+    let ack = subscribe_with(
+        &mut anonymous,
+        serde_json::json!({ "action": "subscribe", "symbol": "BTCUSDT", "filter": "own" }),
+    )
+    .await;
+    assert_eq!(ack["status"], "error");
+    subscribe(&mut anonymous, "BTCUSDT").await;
+
+    let mut sockets = Vec::new();
+    for token in [&alice, &bob] {
+        let (mut socket, _) =
+            tokio_tungstenite::connect_async(format!("ws://{}/ws?token={}", addr, token))
+                .await
+                .unwrap();
+        let ack = subscribe_with(&mut socket, own_trades.clone()).await;
+        assert_eq!(ack["status"], "success");
+        assert_eq!(ack["message"], "Subscribed to BTCUSDT trades (own)");
+        sockets.push(socket);
+    }
+
+    let order = |token: &String, side: &str, price: i64| {
+        client
+            .post(format!("http://{}/orders", addr))
+            .bearer_auth(token)
+            .json(&serde_json::json!({
+                "symbol": "BTCUSDT",
+                "price": price,
+                "quantity": 1,
+                "side": side
+            }))
+            .send()
+    };
+    // Alice sells to Bob, then Carol sells to Alice
+    order(&alice, "Sell", 100).await.unwrap();
+    order(&bob, "Buy", 100).await.unwrap();
+    order(&carol, "Sell", 90).await.unwrap();
+    order(&alice, "Buy", 90).await.unwrap();
+
+    let trades_of = |messages: Vec<serde_json::Value>| -> Vec<(i64, serde_json::Value)> {
+        assert!(messages.iter().all(|m| m["type"] != "OrderBookUpdate"));
+        messages
+            .into_iter()
+            .filter(|m| m["type"] == "Trade")
+            .map(|m| (m["trade"]["price"].as_i64().unwrap(), m["role"].clone()))
+            .collect()
+    };
+    let mut bob_socket = sockets.pop().unwrap();
+    let mut alice_socket = sockets.pop().unwrap();
+    assert_eq!(
+        trades_of(drain(&mut alice_socket).await),
+        vec![
+            (100, serde_json::json!("Maker")),
+            (90, serde_json::json!("Taker"))
+        ]
+    );
+    assert_eq!(
+        trades_of(drain(&mut bob_socket).await),
+        vec![(100, serde_json::json!("Taker"))]
+    );
+
+    // The unfiltered subscription still sees every trade, in the public form
+    let public: Vec<serde_json::Value> = drain(&mut anonymous)
+        .await
+        .into_iter()
+        .filter(|m| m["type"] == "Trade")
+        .collect();
+    assert_eq!(public.len(), 2);
+    assert!(public.iter().all(|m| m.get("role").is_none()));
+}
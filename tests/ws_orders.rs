@@ -0,0 +1,140 @@
+//! Order entry over the WebSocket: `place_order` and `cancel_order` go through the same path as
+//! REST and are answered with a reply echoing the client's `id`, while the usual private
+//! messages still arrive.
+
+use rust_exchange::testing::{TEST_SYMBOL, TestExchange, WsClient};
+use serde_json::{Value, json};
+
+async fn place(ws: &mut WsClient, id: Value, side: &str, price: i64, quantity: u64) -> Value {
+    ws.send_json(json!({
+        "action": "place_order",
+        "id": id,
+        "symbol": TEST_SYMBOL,
+        "side": side,
+        "price": price,
+        "quantity": quantity,
+    }))
+    .await;
+    reply(ws).await
+}
+
+/// The next order entry reply, skipping pushed messages.
+async fn reply(ws: &mut WsClient) -> Value {
+    loop {
+        let message = ws.next_json().await;
+        if message.get("action").is_some() {
+            return message;
+        }
+    }
+}
+
+#[tokio::test]
+async fn orders_placed_over_two_sockets_match_and_both_sides_hear_of_it() {
+    let exchange = TestExchange::start().await;
+    let maker = exchange.register("maker", "secret").await;
+    let taker = exchange.register("taker", "secret").await;
+    let mut maker_ws = exchange.ws_client(Some(&maker)).await;
+    let mut taker_ws = exchange.ws_client(Some(&taker)).await;
+    maker_ws
+        .send_json(json!({"action": "subscribe_positions"}))
+        .await;
+    assert_eq!(maker_ws.next_json().await["status"], "success");
+
+    let resting = place(&mut maker_ws, json!(123), "Sell", 1_000, 2).await;
+    assert_eq!(resting["id"], 123);
+    assert_eq!(resting["action"], "place_order");
+    assert_eq!(resting["status"], "success", "{}", resting);
+    assert_eq!(resting["order"]["status"], "Pending");
+    assert_eq!(resting["order"]["user_id"], maker.user_id.to_string());
+    assert_eq!(resting["order"]["meta"]["lot_size"], 1);
+    assert!(resting["ingress_seq"].is_u64());
+    assert_eq!(exchange.book(TEST_SYMBOL).await.asks, vec![(1_000, 2)]);
+
+    let filled = place(&mut taker_ws, json!("taker-1"), "Buy", 1_000, 2).await;
+    assert_eq!(filled["id"], "taker-1");
+    assert_eq!(filled["status"], "success", "{}", filled);
+    assert_eq!(filled["order"]["status"], "Filled");
+
+    // The taker's execution report and the maker's position arrive as private messages
+    let update = taker_ws.next_of_type("OrderUpdate").await;
+    assert_eq!(update["report"]["order"]["id"], filled["order"]["id"]);
+    assert_eq!(update["report"]["trades"][0]["quantity"], 2);
+    let position = maker_ws.next_of_type("PositionUpdate").await;
+    assert_eq!(position["position"]["user_id"], maker.user_id.to_string());
+    assert!(exchange.book(TEST_SYMBOL).await.asks.is_empty());
+    // The fill is recorded like one placed over REST
+    assert_eq!(exchange.positions(&taker).await.unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn cancels_find_the_book_or_take_the_symbol_and_errors_echo_the_id() {
+    let exchange = TestExchange::start().await;
+    let trader = exchange.register("trader", "secret").await;
+    let mut ws = exchange.ws_client(Some(&trader)).await;
+    let first = place(&mut ws, json!(1), "Buy", 900, 1).await;
+    let second = place(&mut ws, json!(2), "Buy", 800, 1).await;
+
+    ws.send_json(json!({"action": "cancel_order", "id": 3, "order_id": first["order"]["id"]}))
+        .await;
+    let cancelled = reply(&mut ws).await;
+    assert_eq!(cancelled["id"], 3);
+    assert_eq!(cancelled["status"], "success", "{}", cancelled);
+    assert_eq!(cancelled["order"]["close_reason"], "USER_CANCELLED");
+    assert!(cancelled.get("ingress_seq").is_none());
+
+    ws.send_json(json!({
+        "action": "cancel_order",
+        "id": 4,
+        "order_id": second["order"]["id"],
+        "symbol": TEST_SYMBOL.to_lowercase(),
+    }))
+    .await;
+    let cancelled = reply(&mut ws).await;
+    assert_eq!(cancelled["status"], "success", "{}", cancelled);
+    assert!(cancelled["ingress_seq"].is_u64());
+    assert!(exchange.book(TEST_SYMBOL).await.bids.is_empty());
+
+    // Cancelling it again answers with how it closed
+    ws.send_json(json!({"action": "cancel_order", "id": 5, "order_id": first["order"]["id"]}))
+        .await;
+    let repeated = reply(&mut ws).await;
+    assert_eq!(
+        (repeated["id"].clone(), repeated["status"].clone()),
+        (json!(5), json!("success"))
+    );
+    assert_eq!(repeated["order"]["close_reason"], "USER_CANCELLED");
+
+    ws.send_json(json!({"action": "cancel_order", "id": 7, "order_id": uuid::Uuid::new_v4()}))
+        .await;
+    let missing = reply(&mut ws).await;
+    assert_eq!(
+        (missing["id"].clone(), missing["status"].clone()),
+        (json!(7), json!("error"))
+    );
+    assert_eq!(missing["code"], 404);
+
+    // Validation is the REST endpoint's
+    let invalid = place(&mut ws, json!(6), "Buy", 900, 0).await;
+    assert_eq!(invalid["status"], "error");
+    assert_eq!(invalid["code"], 400);
+    assert!(invalid.get("order").is_none());
+}
+
+#[tokio::test]
+async fn order_entry_needs_an_authenticated_socket_and_honours_the_kill_switch() {
+    let exchange = TestExchange::start().await;
+    let trader = exchange.register("trader", "secret").await;
+
+    let mut anonymous = exchange.ws_client(None).await;
+    let refused = place(&mut anonymous, json!(1), "Buy", 900, 1).await;
+    assert_eq!(refused["status"], "error");
+    assert_eq!(refused["code"], 401);
+
+    let mut ws = exchange.ws_client(Some(&trader)).await;
+    exchange.state.kill_switches.set(trader.user_id, true);
+    let refused = place(&mut ws, json!(2), "Buy", 900, 1).await;
+    assert_eq!(refused["id"], 2);
+    assert_eq!(refused["code"], 403);
+    assert_eq!(refused["error_code"], "TRADING_DISABLED");
+    assert!(exchange.book(TEST_SYMBOL).await.bids.is_empty());
+}